@@ -31,11 +31,18 @@ sealed class TunnelState() : Parcelable {
     @Parcelize
     class Error(val errorState: ErrorState) : TunnelState(), Parcelable
 
+    @Parcelize
+    class Paused(
+        val endpoint: TunnelEndpoint,
+        val location: GeoIpLocation?
+    ) : TunnelState(), Parcelable
+
     fun isSecured(): Boolean {
         return when (this) {
             is Connected,
             is Connecting,
-            is Disconnecting, -> true
+            is Disconnecting,
+            is Paused, -> true
             is Disconnected -> false
             is Error -> this.errorState.isBlocking
         }
@@ -49,6 +56,7 @@ sealed class TunnelState() : Parcelable {
         const val DISCONNECTING = "disconnecting"
         const val BLOCKING = "blocking"
         const val ERROR = "error"
+        const val PAUSED = "paused"
 
         fun fromString(description: String, endpoint: TunnelEndpoint?): TunnelState {
             return when (description) {
@@ -58,6 +66,7 @@ sealed class TunnelState() : Parcelable {
                 RECONNECTING -> TunnelState.Disconnecting(ActionAfterDisconnect.Reconnect)
                 DISCONNECTING -> TunnelState.Disconnecting(ActionAfterDisconnect.Nothing)
                 BLOCKING -> TunnelState.Error(ErrorState(ErrorStateCause.StartTunnelError, true))
+                PAUSED -> TunnelState.Paused(endpoint!!, null)
                 ERROR -> {
                     TunnelState.Error(ErrorState(ErrorStateCause.SetFirewallPolicyError, false))
                 }
@@ -84,5 +93,6 @@ sealed class TunnelState() : Parcelable {
                 ERROR
             }
         }
+        is TunnelState.Paused -> PAUSED
     }
 }
@@ -97,6 +97,14 @@ class MullvadDaemon(vpnService: MullvadVpnService) {
         reconnect(daemonInterfaceAddress)
     }
 
+    fun pauseTunnel() {
+        pauseTunnel(daemonInterfaceAddress)
+    }
+
+    fun resumeTunnel() {
+        resumeTunnel(daemonInterfaceAddress)
+    }
+
     fun clearAccountHistory() {
         clearAccountHistory(daemonInterfaceAddress)
     }
@@ -198,6 +206,8 @@ class MullvadDaemon(vpnService: MullvadVpnService) {
     private external fun getState(daemonInterfaceAddress: Long): TunnelState?
     private external fun getVersionInfo(daemonInterfaceAddress: Long): AppVersionInfo?
     private external fun reconnect(daemonInterfaceAddress: Long)
+    private external fun pauseTunnel(daemonInterfaceAddress: Long)
+    private external fun resumeTunnel(daemonInterfaceAddress: Long)
     private external fun clearAccountHistory(daemonInterfaceAddress: Long)
     private external fun loginAccount(
         daemonInterfaceAddress: Long,
@@ -119,6 +119,7 @@ class MullvadTileService : TileService() {
                         Tile.STATE_INACTIVE
                     }
                 }
+                is TunnelState.Paused -> Tile.STATE_ACTIVE
             }
         } else {
             Tile.STATE_INACTIVE
@@ -66,6 +66,7 @@ class LocationInfoCache(private val endpoint: ServiceEndpoint) {
                 }
             }
             is TunnelState.Error -> location = null
+            is TunnelState.Paused -> location = newState.location
         }
     }
 
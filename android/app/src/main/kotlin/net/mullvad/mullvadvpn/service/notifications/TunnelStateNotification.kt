@@ -53,6 +53,7 @@ class TunnelStateNotification(val context: Context) {
                     R.string.critical_error
                 }
             }
+            is TunnelState.Paused -> R.string.paused
         }
 
     private var reconnecting = false
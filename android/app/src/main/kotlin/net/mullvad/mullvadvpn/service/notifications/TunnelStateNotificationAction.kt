@@ -29,6 +29,7 @@ enum class TunnelStateNotificationAction {
                     Dismiss
                 }
             }
+            is TunnelState.Paused -> Disconnect
         }
     }
 
@@ -53,6 +53,7 @@ class ConnectActionButton(val parentView: View) {
                         blockError()
                     }
                 }
+                is TunnelState.Paused -> connected()
             }
 
             field = value
@@ -94,6 +95,7 @@ class ConnectActionButton(val parentView: View) {
                     onCancel?.invoke()
                 }
             }
+            is TunnelState.Paused -> onDisconnect?.invoke()
         }
     }
 
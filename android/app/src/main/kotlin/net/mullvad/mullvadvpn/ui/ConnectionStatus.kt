@@ -28,6 +28,7 @@ class ConnectionStatus(val parentView: View, context: Context) {
             is TunnelState.Connecting -> connecting()
             is TunnelState.Connected -> connected()
             is TunnelState.Error -> errorState(state.errorState.isBlocking)
+            is TunnelState.Paused -> paused()
         }
     }
 
@@ -52,6 +53,13 @@ class ConnectionStatus(val parentView: View, context: Context) {
         text.setText(R.string.secure_connection)
     }
 
+    private fun paused() {
+        spinner.visibility = View.GONE
+
+        text.setTextColor(securedTextColor)
+        text.setText(R.string.paused_connection)
+    }
+
     private fun errorState(isBlocking: Boolean) {
         spinner.visibility = View.GONE
 
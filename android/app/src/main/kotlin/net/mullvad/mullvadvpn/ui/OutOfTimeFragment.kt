@@ -160,6 +160,7 @@ class OutOfTimeFragment : BaseFragment() {
                 state.actionAfterDisconnect != ActionAfterDisconnect.Nothing
             }
             is TunnelState.Error -> state.errorState.isBlocking
+            is TunnelState.Paused -> true
         }
 
         disconnectButton.apply {
@@ -34,6 +34,7 @@ class TunnelStateNotification(
             is TunnelState.Connecting -> show(null)
             is TunnelState.Connected -> hide()
             is TunnelState.Error -> show(state.errorState)
+            is TunnelState.Paused -> show(null)
         }
 
         update()
@@ -62,21 +63,27 @@ class TunnelStateNotification(
                 return context.getString(R.string.invalid_dns_servers, addresses)
             }
             is ErrorStateCause.AuthFailed -> R.string.auth_failed
+            is ErrorStateCause.AccountExpired -> R.string.account_credit_has_expired
             is ErrorStateCause.Ipv6Unavailable -> R.string.ipv6_unavailable
             is ErrorStateCause.SetFirewallPolicyError -> R.string.set_firewall_policy_error
             is ErrorStateCause.SetDnsError -> R.string.set_dns_error
             is ErrorStateCause.StartTunnelError -> R.string.start_tunnel_error
+            is ErrorStateCause.TunnelMonitorStopped -> R.string.tunnel_monitor_stopped
+            is ErrorStateCause.TooManyAttempts -> R.string.too_many_attempts
             is ErrorStateCause.IsOffline -> R.string.is_offline
             is ErrorStateCause.TunnelParameterError -> {
-                when (cause.error) {
-                    ParameterGenerationError.NoMatchingRelay -> R.string.no_matching_relay
-                    ParameterGenerationError.NoMatchingBridgeRelay -> {
+                when (val error = cause.error) {
+                    is ParameterGenerationError.NoMatchingRelay -> R.string.no_matching_relay
+                    is ParameterGenerationError.NoMatchingBridgeRelay -> {
                         R.string.no_matching_bridge_relay
                     }
-                    ParameterGenerationError.NoWireguardKey -> R.string.no_wireguard_key
-                    ParameterGenerationError.CustomTunnelHostResultionError -> {
+                    is ParameterGenerationError.NoWireguardKey -> R.string.no_wireguard_key
+                    is ParameterGenerationError.CustomTunnelHostResultionError -> {
                         R.string.custom_tunnel_host_resolution_error
                     }
+                    is ParameterGenerationError.InvalidTunnelParameters -> {
+                        return context.getString(R.string.invalid_tunnel_parameters, error.reason)
+                    }
                 }
             }
             is ErrorStateCause.VpnPermissionDenied -> R.string.vpn_permission_denied_error
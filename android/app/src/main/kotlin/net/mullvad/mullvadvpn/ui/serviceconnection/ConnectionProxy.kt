@@ -93,6 +93,8 @@ class ConnectionProxy(private val connection: Messenger, eventDispatcher: EventD
                 is TunnelState.Connecting -> true
                 is TunnelState.Connected -> true
                 is TunnelState.Error -> true
+                // The daemon does not reconnect a paused tunnel until it is resumed
+                is TunnelState.Paused -> false
             }
 
             if (willReconnect) {
@@ -48,6 +48,7 @@ class SwitchLocationButton : FrameLayout {
             is TunnelState.Connecting -> showLabel()
             is TunnelState.Connected -> showLabel()
             is TunnelState.Error -> showLocation()
+            is TunnelState.Paused -> showLabel()
         }
     }
 
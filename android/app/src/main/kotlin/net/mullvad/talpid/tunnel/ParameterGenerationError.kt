@@ -1,5 +1,21 @@
 package net.mullvad.talpid.tunnel
 
-enum class ParameterGenerationError {
-    NoMatchingRelay, NoMatchingBridgeRelay, NoWireguardKey, CustomTunnelHostResultionError
+import android.os.Parcelable
+import kotlinx.parcelize.Parcelize
+
+sealed class ParameterGenerationError : Parcelable {
+    @Parcelize
+    object NoMatchingRelay : ParameterGenerationError()
+
+    @Parcelize
+    object NoMatchingBridgeRelay : ParameterGenerationError()
+
+    @Parcelize
+    object NoWireguardKey : ParameterGenerationError()
+
+    @Parcelize
+    object CustomTunnelHostResultionError : ParameterGenerationError()
+
+    @Parcelize
+    class InvalidTunnelParameters(val reason: String) : ParameterGenerationError()
 }
@@ -6,7 +6,7 @@ set -eu
 # Default configurations generated by Visual Studio are "Release" and "Debug".
 CPP_BUILD_MODES=${CPP_BUILD_MODES:-"Debug"}
 # List of target platforms to build for.
-# Common platforms include "x86" and "x64".
+# Common platforms include "x86", "x64" and "ARM64".
 CPP_BUILD_TARGETS=${CPP_BUILD_TARGETS:-"x64"}
 
 IS_RELEASE=${IS_RELEASE:-"false"}
@@ -68,6 +68,7 @@ function get_solution_output_path {
     case $build_target in
         "x86") echo "$solution_root/bin/Win32-$build_mode";;
         "x64") echo "$solution_root/bin/x64-$build_mode";;
+        "ARM64") echo "$solution_root/bin/ARM64-$build_mode";;
         *)
             echo "Unkown build target: $build_target"
             exit 1
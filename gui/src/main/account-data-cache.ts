@@ -74,6 +74,12 @@ export default class AccountDataCache {
     }
   }
 
+  public handleExpiryUpdate(accountToken: AccountToken, expiry: string) {
+    if (accountToken === this.currentAccount) {
+      this.setValue({ expiry });
+    }
+  }
+
   private setValue(accountData: IAccountData) {
     this.validUntil = this.getValidUntil(accountData);
     this.updateHandler(accountData);
@@ -123,6 +123,13 @@ export default class Account {
     }
   }
 
+  public handleAccountExpiry(expiry: string) {
+    const accountToken = this.getAccountToken();
+    if (accountToken) {
+      this.accountDataCache.handleExpiryUpdate(accountToken, expiry);
+    }
+  }
+
   public handleDeviceEvent(deviceEvent: DeviceEvent) {
     this.deviceStateValue = deviceEvent.deviceState;
 
@@ -17,16 +17,20 @@ import {
   DaemonEvent,
   DeviceEvent,
   DeviceState,
+  DisconnectCause,
+  DisconnectedBlockReason,
   EndpointObfuscationType,
   ErrorStateCause,
   FirewallPolicyError,
   IAccountData,
   IAppVersionInfo,
   IBridgeConstraints,
+  ICompetingVpn,
   IDevice,
   IDeviceRemoval,
   IDnsOptions,
   IErrorState,
+  IFirewallConflict,
   ILocation,
   IObfuscationEndpoint,
   IOpenVpnConstraints,
@@ -45,6 +49,7 @@ import {
   ObfuscationSettings,
   ObfuscationType,
   Ownership,
+  ProcessFailure,
   ProxySettings,
   ProxyType,
   RelayEndpointType,
@@ -80,6 +85,15 @@ const invalidErrorStateCause = new Error(
   'VPN_PERMISSION_DENIED is not a valid error state cause on desktop',
 );
 
+export interface IStartupState {
+  accountHistory?: AccountToken;
+  tunnelState: TunnelState;
+  device: DeviceState;
+  settings: ISettings;
+  relayList: IRelayListWithEndpointData;
+  currentVersion: string;
+}
+
 export class ConnectionObserver {
   constructor(
     private openHandler: () => void,
@@ -253,6 +267,8 @@ export class DaemonRpc {
             return { type: 'invalid' };
           case grpc.status.RESOURCE_EXHAUSTED:
             return { type: 'already_used' };
+          case grpc.status.FAILED_PRECONDITION:
+            return { type: 'expired' };
         }
       }
       return { type: 'error' };
@@ -409,6 +425,11 @@ export class DaemonRpc {
           grpcTypes.ObfuscationSettings.SelectedObfuscation.UDP2TCP,
         );
         break;
+      case ObfuscationType.tls:
+        grpcObfuscationSettings.setSelectedObfuscation(
+          grpcTypes.ObfuscationSettings.SelectedObfuscation.TLS,
+        );
+        break;
     }
 
     if (obfuscationSettings.udp2tcpSettings) {
@@ -421,6 +442,12 @@ export class DaemonRpc {
       grpcObfuscationSettings.setUdp2tcp(grpcUdp2tcpSettings);
     }
 
+    if (obfuscationSettings.tlsSettings) {
+      const grpcTlsSettings = new grpcTypes.TlsObfuscationSettings();
+      grpcTlsSettings.setServerName(obfuscationSettings.tlsSettings.serverName ?? '');
+      grpcObfuscationSettings.setTls(grpcTlsSettings);
+    }
+
     await this.call<grpcTypes.ObfuscationSettings, Empty>(
       this.client.setObfuscationSettings,
       grpcObfuscationSettings,
@@ -466,6 +493,18 @@ export class DaemonRpc {
     return convertFromSettings(response)!;
   }
 
+  public async getStartupState(): Promise<IStartupState> {
+    const response = await this.callEmpty<grpcTypes.StartupState>(this.client.getStartupState);
+    return {
+      accountHistory: response.getAccountHistory()?.getToken()?.getValue(),
+      tunnelState: convertFromTunnelState(response.getTunnelState()!)!,
+      device: convertFromDeviceState(response.getDevice()!),
+      settings: convertFromSettings(response.getSettings()!)!,
+      relayList: convertFromRelayList(response.getRelayList()!),
+      currentVersion: response.getCurrentVersion(),
+    };
+  }
+
   public subscribeDaemonEventListener(listener: SubscriptionListener<DaemonEvent>) {
     const call = this.isConnected && this.client.eventsListen(new Empty());
     if (!call) {
@@ -832,8 +871,37 @@ function convertFromTunnelState(tunnelState: grpcTypes.TunnelState): TunnelState
   switch (tunnelState.getStateCase()) {
     case grpcTypes.TunnelState.StateCase.STATE_NOT_SET:
       return undefined;
-    case grpcTypes.TunnelState.StateCase.DISCONNECTED:
-      return { state: 'disconnected' };
+    case grpcTypes.TunnelState.StateCase.DISCONNECTED: {
+      const causeMap: Record<
+        grpcTypes.TunnelState.Disconnected.Cause,
+        DisconnectCause | undefined
+      > = {
+        [grpcTypes.TunnelState.Disconnected.Cause.UNKNOWN]: undefined,
+        [grpcTypes.TunnelState.Disconnected.Cause.USER_INITIATED]: 'user_initiated',
+        [grpcTypes.TunnelState.Disconnected.Cause.RECONNECTING]: 'reconnecting',
+        [grpcTypes.TunnelState.Disconnected.Cause.ERROR]: 'error',
+        [grpcTypes.TunnelState.Disconnected.Cause.REVOKED]: 'revoked',
+        [grpcTypes.TunnelState.Disconnected.Cause.SHUTDOWN]: 'shutdown',
+      };
+      const blockReasonMap: Record<
+        grpcTypes.TunnelState.Disconnected.Security,
+        DisconnectedBlockReason | undefined
+      > = {
+        [grpcTypes.TunnelState.Disconnected.Security.UNSECURED]: undefined,
+        [grpcTypes.TunnelState.Disconnected.Security.BLOCK_WHEN_DISCONNECTED]:
+          'block_when_disconnected',
+        [grpcTypes.TunnelState.Disconnected.Security.PENDING_CONNECT]: 'pending_connect',
+        [grpcTypes.TunnelState.Disconnected.Security.RECONNECT_GRACE_PERIOD]:
+          'reconnect_grace_period',
+      };
+      return {
+        state: 'disconnected',
+        details: tunnelStateObject.disconnected && causeMap[tunnelStateObject.disconnected.cause],
+        blockReason:
+          tunnelStateObject.disconnected &&
+          blockReasonMap[tunnelStateObject.disconnected.security],
+      };
+    }
     case grpcTypes.TunnelState.StateCase.DISCONNECTING: {
       const detailsMap: Record<grpcTypes.AfterDisconnect, AfterDisconnect> = {
         [grpcTypes.AfterDisconnect.NOTHING]: 'nothing',
@@ -861,6 +929,10 @@ function convertFromTunnelState(tunnelState: grpcTypes.TunnelState): TunnelState
           tunnelStateObject.connecting?.relayInfo &&
           convertFromTunnelStateRelayInfo(tunnelStateObject.connecting.relayInfo),
       };
+    case grpcTypes.TunnelState.StateCase.PAUSED:
+      // Only the Android app can pause the tunnel. Like a pending connection, it blocks traffic
+      // until the tunnel is brought back up.
+      return { state: 'disconnected', blockReason: 'pending_connect' };
     case grpcTypes.TunnelState.StateCase.CONNECTED: {
       const relayInfo =
         tunnelStateObject.connected?.relayInfo &&
@@ -915,17 +987,52 @@ function convertFromTunnelStateErrorCause(
         [grpcTypes.ErrorState.GenerationError.NO_WIREGUARD_KEY]: 'no_wireguard_key',
         [grpcTypes.ErrorState.GenerationError.CUSTOM_TUNNEL_HOST_RESOLUTION_ERROR]:
           'custom_tunnel_host_resultion_error',
+        [grpcTypes.ErrorState.GenerationError.INVALID_TUNNEL_PARAMETERS]:
+          'invalid_tunnel_parameters',
       };
       return { reason: 'tunnel_parameter_error', details: parameterErrorMap[state.parameterError] };
     }
     case grpcTypes.ErrorState.Cause.SPLIT_TUNNEL_ERROR:
       return { reason: 'split_tunnel_error' };
+    case grpcTypes.ErrorState.Cause.TUNNEL_MONITOR_STOPPED:
+      return { reason: 'tunnel_monitor_stopped' };
+    case grpcTypes.ErrorState.Cause.FORCED_INTERFACE_UNAVAILABLE:
+      return { reason: 'forced_interface_unavailable' };
+    case grpcTypes.ErrorState.Cause.GATEWAY_UNREACHABLE:
+      return { reason: 'gateway_unreachable' };
+    case grpcTypes.ErrorState.Cause.TOO_MANY_ATTEMPTS:
+      return { reason: 'too_many_attempts' };
+    case grpcTypes.ErrorState.Cause.ROUTE_TAKEN_OVER:
+      return { reason: 'route_taken_over' };
+    case grpcTypes.ErrorState.Cause.TUNNEL_DEVICE_BUSY:
+      return { reason: 'tunnel_device_busy' };
+    case grpcTypes.ErrorState.Cause.ACCOUNT_EXPIRED:
+      return { reason: 'account_expired' };
+    case grpcTypes.ErrorState.Cause.TUNNEL_PROCESS_FAILED:
+      return {
+        reason: 'tunnel_process_failed',
+        details: convertFromProcessFailure(state.processFailure),
+      };
     case grpcTypes.ErrorState.Cause.VPN_PERMISSION_DENIED:
       // VPN_PERMISSION_DENIED is only ever created on Android
       throw invalidErrorStateCause;
   }
 }
 
+function convertFromProcessFailure(
+  failure?: grpcTypes.ErrorState.ProcessFailure.AsObject,
+): ProcessFailure {
+  switch (failure?.kind) {
+    case grpcTypes.ErrorState.ProcessFailure.Kind.SPAWN:
+      return { kind: 'spawn' };
+    case grpcTypes.ErrorState.ProcessFailure.Kind.EXIT_CODE:
+      return { kind: 'exit_code', code: failure!.exitCode };
+    case grpcTypes.ErrorState.ProcessFailure.Kind.CRASH:
+    default:
+      return { kind: 'crash' };
+  }
+}
+
 function convertFromFirewallPolicyError(
   error: grpcTypes.ErrorState.FirewallPolicyError.AsObject,
 ): FirewallPolicyError {
@@ -990,6 +1097,7 @@ function convertFromObfuscationEndpoint(
 ): IObfuscationEndpoint {
   const obfuscationTypes: Record<grpcTypes.ObfuscationType, EndpointObfuscationType> = {
     [grpcTypes.ObfuscationType.UDP2TCP]: 'udp2tcp',
+    [grpcTypes.ObfuscationType.TLS]: 'tls',
   };
 
   return {
@@ -1229,6 +1337,9 @@ function convertFromObfuscationSettings(
     case grpcTypes.ObfuscationSettings.SelectedObfuscation.UDP2TCP:
       selectedObfuscationType = ObfuscationType.udp2tcp;
       break;
+    case grpcTypes.ObfuscationSettings.SelectedObfuscation.TLS:
+      selectedObfuscationType = ObfuscationType.tls;
+      break;
   }
 
   return {
@@ -1237,6 +1348,9 @@ function convertFromObfuscationSettings(
       obfuscationSettings?.udp2tcp && obfuscationSettings.udp2tcp.port !== 0
         ? { port: { only: obfuscationSettings.udp2tcp.port } }
         : { port: 'any' },
+    tlsSettings: obfuscationSettings?.tls?.serverName
+      ? { serverName: obfuscationSettings.tls.serverName }
+      : {},
   };
 }
 
@@ -1271,6 +1385,28 @@ function convertFromDaemonEvent(data: grpcTypes.DaemonEvent): DaemonEvent {
     return { appVersionInfo: versionInfo.toObject() };
   }
 
+  const firewallConflicts = data.getFirewallConflicts();
+  if (firewallConflicts !== undefined) {
+    return {
+      firewallConflicts: firewallConflicts.getConflictsList().map(convertFromFirewallConflict),
+    };
+  }
+
+  const competingVpn = data.getCompetingVpn();
+  if (competingVpn !== undefined) {
+    return { competingVpn: convertFromCompetingVpn(competingVpn) };
+  }
+
+  const settingsChanged = data.getSettingsChanged();
+  if (settingsChanged !== undefined) {
+    return { settingsChanged: settingsChanged.getChangedFieldsList() };
+  }
+
+  const accountExpiry = data.getAccountExpiry();
+  if (accountExpiry !== undefined) {
+    return { accountExpiry: accountExpiry.getExpiry()!.toDate().toISOString() };
+  }
+
   // Handle unknown daemon events
   const keys = Object.entries(data.toObject())
     .filter(([, value]) => value !== undefined)
@@ -1278,6 +1414,27 @@ function convertFromDaemonEvent(data: grpcTypes.DaemonEvent): DaemonEvent {
   throw new Error(`Unknown daemon event received containing ${keys}`);
 }
 
+function convertFromFirewallConflict(conflict: grpcTypes.FirewallConflict): IFirewallConflict {
+  const provider = conflict.getProvider();
+  return {
+    kind:
+      conflict.getKind() === grpcTypes.FirewallConflict.Kind.SUBLAYER
+        ? 'sublayer'
+        : 'hard-permit-filter',
+    name: conflict.getName(),
+    provider: provider === '' ? undefined : provider,
+  };
+}
+
+function convertFromCompetingVpn(competingVpn: grpcTypes.CompetingVpn): ICompetingVpn {
+  const description = competingVpn.getDescription();
+  return {
+    interface: competingVpn.getInterface(),
+    description: description === '' ? undefined : description,
+    prefix: competingVpn.getPrefix(),
+  };
+}
+
 function convertFromOwnership(ownership: grpcTypes.Ownership): Ownership {
   switch (ownership) {
     case grpcTypes.Ownership.ANY:
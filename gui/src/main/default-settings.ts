@@ -65,6 +65,7 @@ export function getDefaultSettings(): ISettings {
       udp2tcpSettings: {
         port: 'any',
       },
+      tlsSettings: {},
     },
   };
 }
@@ -23,7 +23,7 @@ import {
   SHOULD_FORWARD_RENDERER_LOG,
   SHOULD_SHOW_CHANGES,
 } from './command-line-options';
-import { ConnectionObserver, DaemonRpc, SubscriptionListener } from './daemon-rpc';
+import { ConnectionObserver, DaemonRpc, IStartupState, SubscriptionListener } from './daemon-rpc';
 import Expectation from './expectation';
 import { IpcMainEventChannel } from './ipc-event-channel';
 import { findIconPath } from './linux-desktop-entry';
@@ -471,79 +471,40 @@ class ApplicationMain
       }
     }
 
-    // fetch account history
+    // fetch the account history, tunnel state, device, settings, relays and daemon version
+    let startupState: IStartupState;
     try {
-      this.account.setAccountHistory(await this.daemonRpc.getAccountHistory());
+      startupState = await this.daemonRpc.getStartupState();
     } catch (e) {
       const error = e as Error;
-      log.error(`Failed to fetch the account history: ${error.message}`);
+      log.error(`Failed to fetch the initial daemon state: ${error.message}`);
 
       return this.handleBootstrapError(error);
     }
 
-    // fetch the tunnel state
-    try {
-      this.tunnelState.handleNewTunnelState(await this.daemonRpc.getState());
-    } catch (e) {
-      const error = e as Error;
-      log.error(`Failed to fetch the tunnel state: ${error.message}`);
+    this.account.setAccountHistory(startupState.accountHistory);
+    this.tunnelState.handleNewTunnelState(startupState.tunnelState);
 
-      return this.handleBootstrapError(error);
+    const deviceState = startupState.device;
+    this.account.handleDeviceEvent({ type: deviceState.type, deviceState } as DeviceEvent);
+    if (deviceState.type === 'logged in') {
+      void this.daemonRpc
+        .updateDevice()
+        .catch((error: Error) => log.warn(`Failed to update device info: ${error.message}`));
     }
 
-    // fetch device
-    try {
-      const deviceState = await this.daemonRpc.getDevice();
-      this.account.handleDeviceEvent({ type: deviceState.type, deviceState } as DeviceEvent);
-      if (deviceState.type === 'logged in') {
-        void this.daemonRpc
-          .updateDevice()
-          .catch((error: Error) => log.warn(`Failed to update device info: ${error.message}`));
-      }
-    } catch (e) {
-      const error = e as Error;
-      log.error(`Failed to fetch device: ${error.message}`);
-
-      return this.handleBootstrapError(error);
-    }
-
-    // fetch settings
-    try {
-      this.setSettings(await this.daemonRpc.getSettings());
-    } catch (e) {
-      const error = e as Error;
-      log.error(`Failed to fetch settings: ${error.message}`);
-
-      return this.handleBootstrapError(error);
-    }
+    this.setSettings(startupState.settings);
 
     if (this.tunnelStateExpectation) {
       this.tunnelStateExpectation.fulfill();
     }
 
-    // fetch relays
-    try {
-      this.relayList.setRelays(
-        await this.daemonRpc.getRelayLocations(),
-        this.settings.relaySettings,
-        this.settings.bridgeState,
-      );
-    } catch (e) {
-      const error = e as Error;
-      log.error(`Failed to fetch relay locations: ${error.message}`);
-
-      return this.handleBootstrapError(error);
-    }
-
-    // fetch the daemon's version
-    try {
-      this.version.setDaemonVersion(await this.daemonRpc.getCurrentVersion());
-    } catch (e) {
-      const error = e as Error;
-      log.error(`Failed to fetch the daemon's version: ${error.message}`);
-
-      return this.handleBootstrapError(error);
-    }
+    this.relayList.setRelays(
+      startupState.relayList,
+      this.settings.relaySettings,
+      this.settings.bridgeState,
+    );
+    this.version.setDaemonVersion(startupState.currentVersion);
 
     // fetch the latest version info in background
     if (!UPDATE_NOTIFICATION_DISABLED) {
@@ -630,6 +591,25 @@ class ApplicationMain
           this.account.handleDeviceEvent(daemonEvent.device);
         } else if ('deviceRemoval' in daemonEvent) {
           IpcMainEventChannel.account.notifyDevices?.(daemonEvent.deviceRemoval);
+        } else if ('firewallConflicts' in daemonEvent) {
+          daemonEvent.firewallConflicts.forEach((conflict) =>
+            log.warn(
+              `Firewall conflict: ${conflict.kind} "${conflict.name}" from "${
+                conflict.provider ?? 'unknown provider'
+              }"`,
+            ),
+          );
+        } else if ('competingVpn' in daemonEvent) {
+          const { competingVpn } = daemonEvent;
+          log.warn(
+            `Another VPN took over the default route: ${competingVpn.prefix} via ${
+              competingVpn.interface
+            } (${competingVpn.description ?? 'no description'})`,
+          );
+        } else if ('accountExpiry' in daemonEvent) {
+          this.account.handleAccountExpiry(daemonEvent.accountExpiry);
+        } else if ('settingsChanged' in daemonEvent) {
+          log.info(`Settings changed: ${daemonEvent.settingsChanged.join(', ')}`);
         }
       },
       (error: Error) => {
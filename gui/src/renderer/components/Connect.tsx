@@ -63,7 +63,6 @@ export default function Connect() {
   const { connectTunnel, disconnectTunnel, reconnectTunnel } = useAppContext();
 
   const connection = useSelector((state) => state.connection);
-  const blockWhenDisconnected = useSelector((state) => state.settings.blockWhenDisconnected);
   const relaySettings = useSelector((state) => state.settings.relaySettings);
   const relayLocations = useSelector((state) => state.settings.relayLocations);
 
@@ -184,7 +183,6 @@ export default function Connect() {
 
             <TunnelControl
               tunnelState={connection.status}
-              blockWhenDisconnected={blockWhenDisconnected}
               selectedRelayName={selectedRelayName}
               city={connection.city}
               country={connection.country}
@@ -134,7 +134,7 @@ export function DefaultHeaderBar(props: IHeaderBarProps) {
 export function calculateHeaderBarStyle(tunnelState: TunnelState): HeaderBarStyle {
   switch (tunnelState.state) {
     case 'disconnected':
-      return HeaderBarStyle.error;
+      return tunnelState.blockReason ? HeaderBarStyle.success : HeaderBarStyle.error;
     case 'connecting':
     case 'connected':
       return HeaderBarStyle.success;
@@ -184,6 +184,12 @@ export function RedeemVoucherResponse() {
             {messages.pgettext('redeem-voucher-view', 'Voucher code has already been used.')}
           </StyledErrorResponse>
         );
+      case 'expired':
+        return (
+          <StyledErrorResponse>
+            {messages.pgettext('redeem-voucher-view', 'Voucher code has expired.')}
+          </StyledErrorResponse>
+        );
       case 'error':
         return (
           <StyledErrorResponse>
@@ -15,7 +15,6 @@ import SecuredLabel, { SecuredDisplayStyle } from './SecuredLabel';
 
 interface ITunnelControlProps {
   tunnelState: TunnelState;
-  blockWhenDisconnected: boolean;
   selectedRelayName: string;
   city?: string;
   country?: string;
@@ -190,7 +189,7 @@ export default class TunnelControl extends React.Component<ITunnelControlProps>
         );
 
       case 'disconnected': {
-        const displayStyle = this.props.blockWhenDisconnected
+        const displayStyle = this.props.tunnelState.blockReason
           ? SecuredDisplayStyle.blocked
           : SecuredDisplayStyle.unsecured;
         return (
@@ -162,6 +162,7 @@ const initialState: ISettingsReduxState = {
     udp2tcpSettings: {
       port: 'any',
     },
+    tlsSettings: {},
   },
 };
 
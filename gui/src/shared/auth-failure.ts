@@ -4,6 +4,7 @@ export enum AuthFailureKind {
   invalidAccount,
   expiredAccount,
   tooManyConnections,
+  deviceRevoked,
   unknown,
 }
 
@@ -50,6 +51,9 @@ function parseRawFailureKind(failureId: string): AuthFailureKind {
     case 'TOO_MANY_CONNECTIONS':
       return AuthFailureKind.tooManyConnections;
 
+    case 'DEVICE_REVOKED':
+      return AuthFailureKind.deviceRevoked;
+
     default:
       return AuthFailureKind.unknown;
   }
@@ -72,6 +76,12 @@ function messageForFailureKind(kind: AuthFailureKind): string {
         'Too many simultaneous connections on this account. Disconnect another device or try connecting again shortly.',
       );
 
+    case AuthFailureKind.deviceRevoked:
+      return messages.pgettext(
+        'auth-failure',
+        'Blocking internet: this device has been removed from the account. Please log in again.',
+      );
+
     case AuthFailureKind.unknown:
       return messages.pgettext(
         'auth-failure',
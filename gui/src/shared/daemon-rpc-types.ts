@@ -31,7 +31,13 @@ export type TunnelParameterError =
   | 'no_matching_relay'
   | 'no_matching_bridge_relay'
   | 'no_wireguard_key'
-  | 'custom_tunnel_host_resultion_error';
+  | 'custom_tunnel_host_resultion_error'
+  | 'invalid_tunnel_parameters';
+
+export type ProcessFailure =
+  | { kind: 'spawn' }
+  | { kind: 'exit_code'; code: number }
+  | { kind: 'crash' };
 
 export type ErrorStateCause =
   | {
@@ -40,14 +46,29 @@ export type ErrorStateCause =
         | 'set_dns_error'
         | 'start_tunnel_error'
         | 'is_offline'
-        | 'split_tunnel_error';
+        | 'split_tunnel_error'
+        | 'tunnel_monitor_stopped'
+        | 'forced_interface_unavailable'
+        | 'gateway_unreachable'
+        | 'too_many_attempts'
+        | 'route_taken_over'
+        | 'tunnel_device_busy'
+        | 'account_expired';
     }
   | { reason: 'set_firewall_policy_error'; details: FirewallPolicyError }
   | { reason: 'tunnel_parameter_error'; details: TunnelParameterError }
-  | { reason: 'auth_failed'; details?: string };
+  | { reason: 'auth_failed'; details?: string }
+  | { reason: 'tunnel_process_failed'; details: ProcessFailure };
 
 export type AfterDisconnect = 'nothing' | 'block' | 'reconnect';
 
+export type DisconnectCause = 'user_initiated' | 'reconnecting' | 'error' | 'revoked' | 'shutdown';
+
+export type DisconnectedBlockReason =
+  | 'block_when_disconnected'
+  | 'pending_connect'
+  | 'reconnect_grace_period';
+
 export type TunnelType = 'any' | 'wireguard' | 'openvpn';
 export function tunnelTypeToString(tunnel: TunnelType): string {
   switch (tunnel) {
@@ -61,7 +82,7 @@ export function tunnelTypeToString(tunnel: TunnelType): string {
 }
 
 export type RelayProtocol = 'tcp' | 'udp';
-export type EndpointObfuscationType = 'udp2tcp';
+export type EndpointObfuscationType = 'udp2tcp' | 'tls';
 
 export type Constraint<T> = 'any' | { only: T };
 export type LiftedConstraint<T> = 'any' | T;
@@ -122,7 +143,23 @@ export type DaemonEvent =
   | { relayList: IRelayListWithEndpointData }
   | { appVersionInfo: IAppVersionInfo }
   | { device: DeviceEvent }
-  | { deviceRemoval: Array<IDevice> };
+  | { deviceRemoval: Array<IDevice> }
+  | { firewallConflicts: Array<IFirewallConflict> }
+  | { competingVpn: ICompetingVpn }
+  | { settingsChanged: Array<string> }
+  | { accountExpiry: string };
+
+export interface IFirewallConflict {
+  kind: 'sublayer' | 'hard-permit-filter';
+  name: string;
+  provider?: string;
+}
+
+export interface ICompetingVpn {
+  interface: string;
+  description?: string;
+  prefix: string;
+}
 
 export interface ITunnelStateRelayInfo {
   endpoint: ITunnelEndpoint;
@@ -130,7 +167,7 @@ export interface ITunnelStateRelayInfo {
 }
 
 export type TunnelState =
-  | { state: 'disconnected' }
+  | { state: 'disconnected'; details?: DisconnectCause; blockReason?: DisconnectedBlockReason }
   | { state: 'connecting'; details?: ITunnelStateRelayInfo }
   | { state: 'connected'; details: ITunnelStateRelayInfo }
   | { state: 'disconnecting'; details: AfterDisconnect }
@@ -371,15 +408,21 @@ export type Udp2TcpObfuscationSettings = {
   port: Constraint<number>;
 };
 
+export type TlsObfuscationSettings = {
+  serverName?: string;
+};
+
 export enum ObfuscationType {
   auto,
   off,
   udp2tcp,
+  tls,
 }
 
 export type ObfuscationSettings = {
   selectedObfuscation: ObfuscationType;
   udp2tcpSettings: Udp2TcpObfuscationSettings;
+  tlsSettings: TlsObfuscationSettings;
 };
 
 export interface IBridgeConstraints {
@@ -397,7 +440,7 @@ export interface ISocketAddress {
 
 export type VoucherResponse =
   | { type: 'success'; newExpiry: string; secondsAdded: number }
-  | { type: 'invalid' | 'already_used' | 'error' };
+  | { type: 'invalid' | 'already_used' | 'expired' | 'error' };
 
 export function parseSocketAddress(socketAddrStr: string): ISocketAddress {
   const re = new RegExp(/(.+):(\d+)$/);
@@ -152,6 +152,51 @@ function getMessage(errorDetails: IErrorState, accountExpiry?: string): string {
           'notifications',
           'Unable to communicate with Mullvad kernel driver. Try reconnecting or contact support.',
         );
+      case 'tunnel_monitor_stopped':
+        return messages.pgettext(
+          'notifications',
+          'The tunnel connection stopped unexpectedly. Try reconnecting or contact support.',
+        );
+      case 'forced_interface_unavailable':
+        return messages.pgettext(
+          'notifications',
+          'The network interface that the tunnel is set to use is unavailable. Reconnect the interface or change the setting.',
+        );
+      case 'gateway_unreachable':
+        return messages.pgettext(
+          'notifications',
+          'The router of your network is not responding. Reconnect to the network and try again.',
+        );
+      case 'too_many_attempts':
+        return messages.pgettext(
+          'notifications',
+          'Stopped reconnecting after too many failed attempts. Connect again to retry.',
+        );
+      case 'route_taken_over':
+        return messages.pgettext(
+          'notifications',
+          'Another VPN took over your internet traffic. Quit the other VPN and connect again.',
+        );
+      case 'tunnel_device_busy':
+        return messages.pgettext(
+          'notifications',
+          'Another program is using the tunnel device. Quit other VPNs and connect again.',
+        );
+      case 'account_expired':
+        return messages.pgettext(
+          'notifications',
+          'You have no more VPN time left on this account. Add time to connect again.',
+        );
+      case 'tunnel_process_failed':
+        return errorDetails.cause.details.kind === 'spawn'
+          ? messages.pgettext(
+              'notifications',
+              'Unable to start the tunnel process. Try reinstalling the app or contact support.',
+            )
+          : messages.pgettext(
+              'notifications',
+              'The tunnel process stopped unexpectedly. Try reconnecting or contact support.',
+            );
     }
   }
 }
@@ -180,5 +225,10 @@ function getTunnelParameterMessage(err: TunnelParameterError): string {
         'notifications',
         'Unable to resolve host of custom tunnel. Try changing your settings.',
       );
+    case 'invalid_tunnel_parameters':
+      return messages.pgettext(
+        'notifications',
+        'Unable to connect using your current settings. Try changing the MTU or other advanced settings.',
+      );
   }
 }
@@ -61,6 +61,25 @@ test('App should show disconnected tunnel state', async () => {
   expect(buttonColor).toBe(SECURE_COLOR);
 });
 
+test('App should show blocked disconnected tunnel state', async () => {
+  await mockIpcHandle<ILocation>({
+    channel: 'location-get',
+    response: mockLocation,
+  });
+
+  await sendMockIpcResponse<TunnelState>({
+    channel: 'tunnel-',
+    response: { state: 'disconnected', blockReason: 'block_when_disconnected' },
+  });
+
+  const statusLabel = getLabel();
+  await expect(statusLabel).toContainText(/blocked connection/i);
+
+  const header = getHeader();
+  const headerColor = await getBackgroundColor(header);
+  expect(headerColor).toBe(SECURE_COLOR);
+});
+
 /**
  * Connecting state
  */
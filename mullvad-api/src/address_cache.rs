@@ -31,18 +31,22 @@ pub struct AddressCache {
 impl AddressCache {
     /// Initialize cache using the hardcoded address, and write changes to `write_path`.
     pub fn new(write_path: Option<Box<Path>>) -> Result<Self, Error> {
-        Self::new_inner(API.addr, write_path)
+        Self::new_inner(AddressCacheInner::from_address(API.addr), write_path)
     }
 
     /// Initialize cache using `read_path`, and write changes to `write_path`.
     pub async fn from_file(read_path: &Path, write_path: Option<Box<Path>>) -> Result<Self, Error> {
         log::debug!("Loading API addresses from {}", read_path.display());
-        Self::new_inner(read_address_file(read_path).await?, write_path)
+        let addresses = read_address_file(read_path).await?;
+        let cache = AddressCacheInner::from_addresses(&addresses).ok_or(Error::Parse)?;
+        Self::new_inner(cache, write_path)
     }
 
-    fn new_inner(address: SocketAddr, write_path: Option<Box<Path>>) -> Result<Self, Error> {
-        let cache = AddressCacheInner::from_address(address);
-        log::debug!("Using API address: {}", cache.address);
+    fn new_inner(cache: AddressCacheInner, write_path: Option<Box<Path>>) -> Result<Self, Error> {
+        match cache.alternate_address {
+            Some(alternate) => log::debug!("Using API addresses: {}, {}", cache.address, alternate),
+            None => log::debug!("Using API address: {}", cache.address),
+        }
 
         let address_cache = Self {
             inner: Arc::new(Mutex::new(cache)),
@@ -51,30 +55,59 @@ impl AddressCache {
         Ok(address_cache)
     }
 
-    /// Returns the address if the hostname equals `API.host`. Otherwise, returns `None`.
-    pub async fn resolve_hostname(&self, hostname: &str) -> Option<SocketAddr> {
+    /// Returns the addresses if the hostname equals `API.host`. Otherwise, returns `None`.
+    pub async fn resolve_hostname(&self, hostname: &str) -> Option<Vec<SocketAddr>> {
         if hostname.eq_ignore_ascii_case(&API.host) {
-            Some(self.get_address().await)
+            Some(self.get_addresses().await)
         } else {
             None
         }
     }
 
-    /// Returns the currently selected address.
+    /// Returns the hostname of the API if all of `addresses` are API addresses in the cache, as
+    /// opposed to, e.g., the address of a proxy.
+    pub async fn hostname_of(&self, addresses: &[SocketAddr]) -> Option<String> {
+        let api_addresses = self.get_addresses().await;
+        if !addresses.is_empty()
+            && addresses
+                .iter()
+                .all(|address| api_addresses.contains(address))
+        {
+            Some(API.host.clone())
+        } else {
+            None
+        }
+    }
+
+    /// Returns the currently selected address for the primary IP version.
     pub async fn get_address(&self) -> SocketAddr {
         self.inner.lock().await.address
     }
 
-    pub async fn set_address(&self, address: SocketAddr) -> Result<(), Error> {
+    /// Returns all currently selected addresses. There is at most one address per IP version.
+    pub async fn get_addresses(&self) -> Vec<SocketAddr> {
+        let inner = self.inner.lock().await;
+        std::iter::once(inner.address)
+            .chain(inner.alternate_address)
+            .collect()
+    }
+
+    /// Selects the first address of each IP version in `addresses`. Does nothing if `addresses`
+    /// is empty.
+    pub async fn set_addresses(&self, addresses: &[SocketAddr]) -> Result<(), Error> {
+        let new_cache = match AddressCacheInner::from_addresses(addresses) {
+            Some(new_cache) => new_cache,
+            None => return Ok(()),
+        };
         let mut inner = self.inner.lock().await;
-        if address != inner.address {
-            self.save_to_disk(&address).await?;
-            inner.address = address;
+        if new_cache != *inner {
+            self.save_to_disk(&new_cache).await?;
+            *inner = new_cache;
         }
         Ok(())
     }
 
-    async fn save_to_disk(&self, address: &SocketAddr) -> Result<(), Error> {
+    async fn save_to_disk(&self, cache: &AddressCacheInner) -> Result<(), Error> {
         let write_path = match self.write_path.as_ref() {
             Some(write_path) => write_path,
             None => return Ok(()),
@@ -83,8 +116,11 @@ impl AddressCache {
         let mut file = crate::fs::AtomicFile::new(write_path.to_path_buf())
             .await
             .map_err(Error::Open)?;
-        let mut contents = address.to_string();
-        contents += "\n";
+        let mut contents = String::new();
+        for address in std::iter::once(cache.address).chain(cache.alternate_address) {
+            contents += &address.to_string();
+            contents += "\n";
+        }
         file.write_all(contents.as_bytes())
             .await
             .map_err(Error::Write)?;
@@ -95,19 +131,43 @@ impl AddressCache {
 #[derive(Clone, PartialEq, Eq)]
 struct AddressCacheInner {
     address: SocketAddr,
+    /// Address of the other IP version than `address`, if the API has one.
+    alternate_address: Option<SocketAddr>,
 }
 
 impl AddressCacheInner {
     fn from_address(address: SocketAddr) -> Self {
-        Self { address }
+        Self {
+            address,
+            alternate_address: None,
+        }
+    }
+
+    /// Uses the first address in `addresses` and the first address of the other IP version.
+    fn from_addresses(addresses: &[SocketAddr]) -> Option<Self> {
+        let address = *addresses.first()?;
+        let alternate_address = addresses
+            .iter()
+            .find(|alternate| alternate.is_ipv4() != address.is_ipv4())
+            .copied();
+        Some(Self {
+            address,
+            alternate_address,
+        })
     }
 }
 
-async fn read_address_file(path: &Path) -> Result<SocketAddr, Error> {
+/// Reads one address per line. Older versions only stored a single address.
+async fn read_address_file(path: &Path) -> Result<Vec<SocketAddr>, Error> {
     let mut file = fs::File::open(path).await.map_err(Error::Open)?;
-    let mut address = String::new();
-    file.read_to_string(&mut address)
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)
         .await
         .map_err(Error::Read)?;
-    address.trim().parse().map_err(|_| Error::Parse)
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| line.parse().map_err(|_| Error::Parse))
+        .collect()
 }
@@ -0,0 +1,139 @@
+//! Keeps track of whether the system clock differs too much from the clock of the API.
+use chrono::{DateTime, Utc};
+pub use mullvad_types::clock_skew::ClockSkew;
+use std::{
+    mem,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::broadcast;
+
+const CHANNEL_CAPACITY: usize = 10;
+
+/// Largest difference between the local clock and the clock of the API that is not reported.
+const MAX_CLOCK_SKEW: Duration = Duration::from_secs(10 * 60);
+
+/// Tracks the most recent clock problem that was detected by any request service.
+pub struct ClockSkewMonitor {
+    skew: Arc<Mutex<Option<ClockSkew>>>,
+    tx: broadcast::Sender<Option<ClockSkew>>,
+}
+
+impl ClockSkewMonitor {
+    pub fn new() -> Self {
+        let (tx, _rx) = broadcast::channel(CHANNEL_CAPACITY);
+        ClockSkewMonitor {
+            skew: Arc::new(Mutex::new(None)),
+            tx,
+        }
+    }
+
+    pub fn handle(&self) -> ClockSkewHandle {
+        ClockSkewHandle {
+            skew: self.skew.clone(),
+            tx: self.tx.clone(),
+        }
+    }
+}
+
+impl Default for ClockSkewMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ClockSkewHandle {
+    skew: Arc<Mutex<Option<ClockSkew>>>,
+    tx: broadcast::Sender<Option<ClockSkew>>,
+}
+
+impl ClockSkewHandle {
+    /// Returns the current clock problem, if there is one.
+    pub fn get(&self) -> Option<ClockSkew> {
+        *self.skew.lock().unwrap()
+    }
+
+    /// Returns a receiver that is notified whenever a clock problem is detected, changes kind,
+    /// or is no longer present.
+    pub fn subscribe(&self) -> broadcast::Receiver<Option<ClockSkew>> {
+        self.tx.subscribe()
+    }
+
+    /// Updates the clock skew using the time reported by the API in a response that was received
+    /// at `now`.
+    pub(crate) fn report_server_time(&self, server_time: DateTime<Utc>, now: DateTime<Utc>) {
+        let skew = now.signed_duration_since(server_time);
+        if skew.num_seconds().unsigned_abs() > MAX_CLOCK_SKEW.as_secs() {
+            self.set(Some(ClockSkew::Measured(skew)));
+        } else {
+            self.set(None);
+        }
+    }
+
+    /// Reports that the API certificate was rejected because of the system time.
+    pub(crate) fn report_certificate_not_valid(&self) {
+        self.set(Some(ClockSkew::CertificateNotValid));
+    }
+
+    fn set(&self, new_skew: Option<ClockSkew>) {
+        let mut skew = self.skew.lock().unwrap();
+        let changed_kind = skew.map(|skew| mem::discriminant(&skew))
+            != new_skew.map(|skew| mem::discriminant(&skew));
+        *skew = new_skew;
+        if changed_kind {
+            match new_skew {
+                Some(ClockSkew::Measured(skew)) => log::warn!(
+                    "The system clock differs from the clock of the API by {} seconds. \
+                     Connections may fail unless the clock is corrected.",
+                    skew.num_seconds()
+                ),
+                Some(ClockSkew::CertificateNotValid) => {
+                    log::warn!("The API certificate was rejected because of the system time")
+                }
+                None => log::info!("The system clock is no longer too far off"),
+            }
+            let _ = self.tx.send(new_skew);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_report_server_time() {
+        let monitor = ClockSkewMonitor::new();
+        let handle = monitor.handle();
+        let mut rx = handle.subscribe();
+        let server_time = DateTime::parse_from_rfc3339("1994-11-06T08:49:37Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        handle.report_server_time(server_time, server_time + chrono::Duration::minutes(5));
+        assert_eq!(handle.get(), None);
+        assert!(rx.try_recv().is_err());
+
+        handle.report_server_time(server_time, server_time - chrono::Duration::hours(2));
+        assert_eq!(
+            handle.get(),
+            Some(ClockSkew::Measured(chrono::Duration::hours(-2)))
+        );
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            Some(ClockSkew::Measured(chrono::Duration::hours(-2)))
+        );
+
+        // Only changes of the kind of problem are broadcast
+        handle.report_server_time(server_time, server_time - chrono::Duration::hours(3));
+        assert!(rx.try_recv().is_err());
+
+        handle.report_certificate_not_valid();
+        assert_eq!(rx.try_recv().unwrap(), Some(ClockSkew::CertificateNotValid));
+
+        handle.report_server_time(server_time, server_time);
+        assert_eq!(handle.get(), None);
+        assert_eq!(rx.try_recv().unwrap(), None);
+    }
+}
@@ -0,0 +1,261 @@
+//! Racing of IPv4 and IPv6 connections to the API, as described in RFC 8305 ("Happy Eyeballs").
+//!
+//! Connection attempts are started one at a time, alternating between the IP versions, without
+//! waiting for the previous attempt to fail. The first attempt that succeeds is used, and the IP
+//! version that won is tried first the next time a connection is made on the same network. This
+//! avoids waiting for a full connect timeout on networks where one of the IP versions is broken.
+
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::{
+    collections::HashMap,
+    future::Future,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use talpid_types::net::IpVersion;
+
+/// Time to wait for a connection attempt before starting the next one in parallel.
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// IP version that is tried first on networks where no connection race has been won yet.
+const DEFAULT_PREFERENCE: IpVersion = IpVersion::V6;
+
+/// Identifies the network that API connections are made on.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct NetworkId {
+    /// Interface that API connections are bound to, if any.
+    interface: Option<String>,
+    /// Local address that connections to the API are routed from. An IPv4 address is used if
+    /// there is one, since IPv6 addresses tend to change over time on the same network.
+    local_addr: Option<IpAddr>,
+}
+
+impl NetworkId {
+    /// Returns the network that connections to `addrs` are currently made on.
+    pub fn new(interface: Option<String>, addrs: &[SocketAddr]) -> Self {
+        let find_local_addr = |ip_version| {
+            addrs
+                .iter()
+                .filter(|addr| self::ip_version(addr) == ip_version)
+                .find_map(|addr| local_addr_for(*addr))
+        };
+        Self {
+            interface,
+            local_addr: find_local_addr(IpVersion::V4).or_else(|| find_local_addr(IpVersion::V6)),
+        }
+    }
+}
+
+/// Returns the local address that packets to `addr` would be sent from. Nothing is sent, since
+/// connecting a UDP socket only looks up the route.
+fn local_addr_for(addr: SocketAddr) -> Option<IpAddr> {
+    let unspecified = match addr {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    let socket = UdpSocket::bind(SocketAddr::new(unspecified, 0)).ok()?;
+    socket.connect(addr).ok()?;
+    Some(socket.local_addr().ok()?.ip())
+}
+
+/// Remembers which IP version most recently won a connection race on each network. Clones share
+/// the same state.
+#[derive(Clone, Default)]
+pub(crate) struct IpVersionPreference {
+    winners: Arc<Mutex<HashMap<NetworkId, IpVersion>>>,
+}
+
+impl IpVersionPreference {
+    /// Returns the IP version that should be tried first on `network`.
+    pub fn get(&self, network: &NetworkId) -> IpVersion {
+        self.winners
+            .lock()
+            .unwrap()
+            .get(network)
+            .copied()
+            .unwrap_or(DEFAULT_PREFERENCE)
+    }
+
+    /// Records that a connection using `ip_version` won the race on `network`.
+    pub fn set(&self, network: NetworkId, ip_version: IpVersion) {
+        let previous = self.winners.lock().unwrap().insert(network, ip_version);
+        if previous != Some(ip_version) {
+            log::debug!("Preferring {:?} for API connections", ip_version);
+        }
+    }
+}
+
+/// Orders `addrs` so that the IP versions alternate, starting with `preferred`.
+pub(crate) fn sort_addresses(addrs: Vec<SocketAddr>, preferred: IpVersion) -> Vec<SocketAddr> {
+    let (first, second): (Vec<_>, Vec<_>) = addrs
+        .into_iter()
+        .partition(|addr| ip_version(addr) == preferred);
+    let mut first = first.into_iter();
+    let mut second = second.into_iter();
+
+    let mut sorted = Vec::with_capacity(first.len() + second.len());
+    loop {
+        match (first.next(), second.next()) {
+            (None, None) => break,
+            (a, b) => sorted.extend(a.into_iter().chain(b)),
+        }
+    }
+    sorted
+}
+
+/// Connects to each address in `addrs` in order, using `connect`, and returns the first
+/// connection that succeeds along with its address. A new attempt is started whenever the
+/// previous one fails or has not completed within [`CONNECTION_ATTEMPT_DELAY`]. Attempts that are
+/// still in progress when one succeeds are dropped.
+pub(crate) async fn race<T, F, Fut>(
+    addrs: Vec<SocketAddr>,
+    connect: F,
+) -> io::Result<(T, SocketAddr)>
+where
+    F: Fn(SocketAddr) -> Fut,
+    Fut: Future<Output = io::Result<T>>,
+{
+    let attempt = |addr| {
+        let connection = connect(addr);
+        async move { (addr, connection.await) }
+    };
+
+    let mut candidates = addrs.into_iter().peekable();
+    let mut attempts = FuturesUnordered::new();
+    let mut last_error = None;
+
+    loop {
+        if attempts.is_empty() {
+            match candidates.next() {
+                Some(addr) => attempts.push(attempt(addr)),
+                None => {
+                    return Err(last_error.unwrap_or_else(|| {
+                        io::Error::new(io::ErrorKind::InvalidInput, "No addresses to connect to")
+                    }))
+                }
+            }
+        }
+
+        let delay = tokio::time::sleep(CONNECTION_ATTEMPT_DELAY);
+        tokio::select! {
+            Some((addr, result)) = attempts.next() => match result {
+                Ok(connection) => return Ok((connection, addr)),
+                Err(error) => {
+                    log::debug!("Failed to connect to {}: {}", addr, error);
+                    last_error = Some(error);
+                    if let Some(addr) = candidates.next() {
+                        attempts.push(attempt(addr));
+                    }
+                }
+            },
+            _ = delay, if candidates.peek().is_some() => {
+                if let Some(addr) = candidates.next() {
+                    attempts.push(attempt(addr));
+                }
+            }
+        }
+    }
+}
+
+pub(crate) fn ip_version(addr: &SocketAddr) -> IpVersion {
+    match addr {
+        SocketAddr::V4(_) => IpVersion::V4,
+        SocketAddr::V6(_) => IpVersion::V6,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sort_addresses() {
+        let v4_1: SocketAddr = "1.1.1.1:443".parse().unwrap();
+        let v4_2: SocketAddr = "2.2.2.2:443".parse().unwrap();
+        let v6_1: SocketAddr = "[::1]:443".parse().unwrap();
+
+        assert_eq!(
+            sort_addresses(vec![v4_1, v4_2, v6_1], IpVersion::V6),
+            vec![v6_1, v4_1, v4_2]
+        );
+        assert_eq!(
+            sort_addresses(vec![v6_1, v4_1, v4_2], IpVersion::V4),
+            vec![v4_1, v6_1, v4_2]
+        );
+        assert_eq!(sort_addresses(vec![v4_1], IpVersion::V6), vec![v4_1]);
+    }
+
+    #[test]
+    fn test_preference_per_network() {
+        let preference = IpVersionPreference::default();
+        let home = NetworkId {
+            interface: None,
+            local_addr: Some("192.168.1.10".parse().unwrap()),
+        };
+        let office = NetworkId {
+            interface: None,
+            local_addr: Some("10.0.0.10".parse().unwrap()),
+        };
+
+        assert_eq!(preference.get(&home), DEFAULT_PREFERENCE);
+        preference.set(home.clone(), IpVersion::V4);
+        assert_eq!(preference.get(&home), IpVersion::V4);
+        assert_eq!(preference.get(&office), DEFAULT_PREFERENCE);
+    }
+
+    #[test]
+    fn test_network_id_uses_local_addr() {
+        let loopback: SocketAddr = "127.0.0.1:443".parse().unwrap();
+        let network = NetworkId::new(None, &[loopback]);
+        assert_eq!(network.local_addr, Some(loopback.ip()));
+        assert_eq!(network.interface, None);
+    }
+
+    #[tokio::test]
+    async fn test_race_skips_failed_attempts() {
+        let broken: SocketAddr = "[::1]:443".parse().unwrap();
+        let working: SocketAddr = "1.1.1.1:443".parse().unwrap();
+
+        let (_, addr) = race(vec![broken, working], |addr| async move {
+            if addr == broken {
+                Err(io::Error::new(io::ErrorKind::Other, "unreachable"))
+            } else {
+                Ok(())
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(addr, working);
+    }
+
+    #[tokio::test]
+    async fn test_race_does_not_wait_for_stalled_attempts() {
+        let stalled: SocketAddr = "[::1]:443".parse().unwrap();
+        let working: SocketAddr = "1.1.1.1:443".parse().unwrap();
+
+        let result = tokio::time::timeout(
+            Duration::from_secs(5),
+            race(vec![stalled, working], |addr| async move {
+                if addr == stalled {
+                    futures::future::pending::<()>().await;
+                }
+                Ok(())
+            }),
+        )
+        .await
+        .expect("race waited for the stalled attempt");
+        assert_eq!(result.unwrap().1, working);
+    }
+
+    #[tokio::test]
+    async fn test_race_returns_last_error() {
+        let addr: SocketAddr = "1.1.1.1:443".parse().unwrap();
+        let result = race(vec![addr], |_| async {
+            Err::<(), _>(io::Error::new(io::ErrorKind::ConnectionRefused, "refused"))
+        })
+        .await;
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::ConnectionRefused);
+    }
+}
@@ -1,8 +1,9 @@
 use crate::{
     abortable_stream::{AbortableStream, AbortableStreamHandle},
+    happy_eyeballs::{self, IpVersionPreference, NetworkId},
     proxy::{ApiConnection, ApiConnectionMode, ProxyConfig},
     tls_stream::TlsStream,
-    AddressCache,
+    AddressCache, InterfaceBinding,
 };
 use futures::{channel::mpsc, future, pin_mut, StreamExt};
 #[cfg(target_os = "android")]
@@ -117,6 +118,8 @@ pub struct HttpsConnectorWithSni {
     inner: Arc<Mutex<HttpsConnectorWithSniInner>>,
     sni_hostname: Option<String>,
     address_cache: AddressCache,
+    interface_binding: InterfaceBinding,
+    ip_version_preference: IpVersionPreference,
     abort_notify: Arc<tokio::sync::Notify>,
     proxy_context: SharedContext,
     #[cfg(target_os = "android")]
@@ -135,6 +138,7 @@ impl HttpsConnectorWithSni {
     pub fn new(
         sni_hostname: Option<String>,
         address_cache: AddressCache,
+        interface_binding: InterfaceBinding,
         #[cfg(target_os = "android")] socket_bypass_tx: Option<mpsc::Sender<SocketBypassRequest>>,
     ) -> (Self, HttpsConnectorWithSniHandle) {
         let (tx, mut rx) = mpsc::unbounded();
@@ -182,6 +186,8 @@ impl HttpsConnectorWithSni {
                 inner,
                 sni_hostname,
                 address_cache,
+                interface_binding,
+                ip_version_preference: IpVersionPreference::default(),
                 abort_notify,
                 proxy_context: SsContext::new_shared(ServerType::Local),
                 #[cfg(target_os = "android")]
@@ -193,6 +199,7 @@ impl HttpsConnectorWithSni {
 
     async fn open_socket(
         addr: SocketAddr,
+        interface_binding: &InterfaceBinding,
         #[cfg(target_os = "android")] socket_bypass_tx: Option<mpsc::Sender<SocketBypassRequest>>,
     ) -> std::io::Result<TcpStream> {
         let socket = match addr {
@@ -200,6 +207,8 @@ impl HttpsConnectorWithSni {
             SocketAddr::V6(_) => TcpSocket::new_v6()?,
         };
 
+        interface_binding.apply(&socket, &addr);
+
         #[cfg(target_os = "android")]
         if let Some(mut tx) = socket_bypass_tx {
             let (done_tx, done_rx) = oneshot::channel();
@@ -214,34 +223,67 @@ impl HttpsConnectorWithSni {
             .map_err(|err| io::Error::new(io::ErrorKind::TimedOut, err))?
     }
 
-    async fn resolve_address(address_cache: AddressCache, uri: Uri) -> io::Result<SocketAddr> {
+    /// Connects directly to the first address in `addrs` that accepts a connection, racing the
+    /// IP versions. The version that wins is preferred for subsequent connections on the same
+    /// network.
+    async fn open_socket_racing(
+        addrs: Vec<SocketAddr>,
+        interface_binding: &InterfaceBinding,
+        ip_version_preference: &IpVersionPreference,
+        #[cfg(target_os = "android")] socket_bypass_tx: Option<mpsc::Sender<SocketBypassRequest>>,
+    ) -> std::io::Result<TcpStream> {
+        let network = NetworkId::new(interface_binding.interface_name(), &addrs);
+        let addrs = happy_eyeballs::sort_addresses(addrs, ip_version_preference.get(&network));
+        let (socket, addr) = happy_eyeballs::race(addrs, |addr| {
+            Self::open_socket(
+                addr,
+                interface_binding,
+                #[cfg(target_os = "android")]
+                socket_bypass_tx.clone(),
+            )
+        })
+        .await?;
+        ip_version_preference.set(network, happy_eyeballs::ip_version(&addr));
+        Ok(socket)
+    }
+
+    /// Returns the addresses of the host in `uri`. The first address is the preferred one.
+    async fn resolve_addresses(
+        address_cache: AddressCache,
+        uri: Uri,
+    ) -> io::Result<Vec<SocketAddr>> {
         let hostname = uri.host().ok_or_else(|| {
             io::Error::new(io::ErrorKind::InvalidInput, "invalid url, missing host")
         })?;
         let port = uri.port_u16().unwrap_or(443);
         if let Ok(addr) = hostname.parse::<IpAddr>() {
-            return Ok(SocketAddr::new(addr, port));
+            return Ok(vec![SocketAddr::new(addr, port)]);
         }
 
-        // Preferentially, use cached address.
+        // Preferentially, use cached addresses.
         //
-        if let Some(addr) = address_cache.resolve_hostname(hostname).await {
-            return Ok(SocketAddr::new(addr.ip(), port));
+        if let Some(addrs) = address_cache.resolve_hostname(hostname).await {
+            return Ok(addrs
+                .into_iter()
+                .map(|addr| SocketAddr::new(addr.ip(), port))
+                .collect());
         }
 
         // Use getaddrinfo as a fallback
         //
-        let mut addrs = GaiResolver::new()
+        let addrs: Vec<_> = GaiResolver::new()
             .call(
                 Name::from_str(hostname)
                     .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?,
             )
             .await
-            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
-        let addr = addrs
-            .next()
-            .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "Empty DNS response"))?;
-        Ok(SocketAddr::new(addr.ip(), port))
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?
+            .map(|addr| SocketAddr::new(addr.ip(), port))
+            .collect();
+        if addrs.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::Other, "Empty DNS response"));
+        }
+        Ok(addrs)
     }
 }
 
@@ -277,6 +319,8 @@ impl Service<Uri> for HttpsConnectorWithSni {
         #[cfg(target_os = "android")]
         let socket_bypass_tx = self.socket_bypass_tx.clone();
         let address_cache = self.address_cache.clone();
+        let interface_binding = self.interface_binding.clone();
+        let ip_version_preference = self.ip_version_preference.clone();
 
         let fut = async move {
             if uri.scheme() != Some(&Scheme::HTTPS) {
@@ -287,7 +331,7 @@ impl Service<Uri> for HttpsConnectorWithSni {
             }
 
             let hostname = sni_hostname?;
-            let addr = Self::resolve_address(address_cache, uri).await?;
+            let addrs = Self::resolve_addresses(address_cache, uri).await?;
 
             // Loop until we have established a connection. This starts over if a new endpoint
             // is selected while connecting.
@@ -297,8 +341,10 @@ impl Service<Uri> for HttpsConnectorWithSni {
                 let stream_fut = async {
                     match config {
                         InnerConnectionMode::Direct => {
-                            let socket = Self::open_socket(
-                                addr,
+                            let socket = Self::open_socket_racing(
+                                addrs.clone(),
+                                &interface_binding,
+                                &ip_version_preference,
                                 #[cfg(target_os = "android")]
                                 socket_bypass_tx.clone(),
                             )
@@ -309,6 +355,7 @@ impl Service<Uri> for HttpsConnectorWithSni {
                         InnerConnectionMode::Proxied(proxy_config) => {
                             let socket = Self::open_socket(
                                 proxy_config.peer,
+                                &interface_binding,
                                 #[cfg(target_os = "android")]
                                 socket_bypass_tx.clone(),
                             )
@@ -317,7 +364,7 @@ impl Service<Uri> for HttpsConnectorWithSni {
                                 proxy_context.clone(),
                                 socket,
                                 &ServerConfig::from(proxy_config),
-                                addr,
+                                addrs[0],
                             );
                             let tls_stream = TlsStream::connect_https(proxy, &hostname).await?;
                             Ok(ApiConnection::Proxied(Box::new(tls_stream)))
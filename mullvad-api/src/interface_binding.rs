@@ -0,0 +1,133 @@
+use std::{
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+use talpid_types::{net::PhysicalInterface, ErrorExt};
+use tokio::net::TcpSocket;
+
+/// Shared handle to the physical interface that sockets opened by the API client should be bound
+/// to. Sockets are not bound to any interface while it is unset.
+#[derive(Clone, Default)]
+pub struct InterfaceBinding {
+    interface: Arc<Mutex<Option<PhysicalInterface>>>,
+}
+
+impl InterfaceBinding {
+    /// Set the interface to bind new sockets to. Existing connections are not affected.
+    pub fn set(&self, interface: Option<PhysicalInterface>) {
+        *self.interface.lock().unwrap() = interface;
+    }
+
+    /// Returns the name of the current interface, if there is one.
+    pub(crate) fn interface_name(&self) -> Option<String> {
+        self.interface
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|interface| interface.name.clone())
+    }
+
+    /// Bind `socket` to the current interface, if there is one. Failures are logged, and the
+    /// socket is left unbound.
+    pub(crate) fn apply(&self, socket: &TcpSocket, addr: &SocketAddr) {
+        let interface = match &*self.interface.lock().unwrap() {
+            Some(interface) => interface.clone(),
+            None => return,
+        };
+        if let Err(error) = bind_to_interface(socket, &interface, addr.is_ipv6()) {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg(&format!(
+                    "Failed to bind API socket to interface {}",
+                    interface
+                ))
+            );
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn bind_to_interface(
+    socket: &TcpSocket,
+    interface: &PhysicalInterface,
+    _ipv6: bool,
+) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let name = interface.name.as_bytes();
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_BINDTODEVICE,
+            name.as_ptr() as *const libc::c_void,
+            name.len() as libc::socklen_t,
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn bind_to_interface(
+    socket: &TcpSocket,
+    interface: &PhysicalInterface,
+    ipv6: bool,
+) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let (level, option) = if ipv6 {
+        (libc::IPPROTO_IPV6, libc::IPV6_BOUND_IF)
+    } else {
+        (libc::IPPROTO_IP, libc::IP_BOUND_IF)
+    };
+    let index = interface.index as libc::c_int;
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            option,
+            &index as *const _ as *const libc::c_void,
+            std::mem::size_of_val(&index) as libc::socklen_t,
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn bind_to_interface(
+    socket: &TcpSocket,
+    interface: &PhysicalInterface,
+    ipv6: bool,
+) -> io::Result<()> {
+    use std::os::windows::io::AsRawSocket;
+    use windows_sys::Win32::Networking::WinSock::{
+        setsockopt, IPPROTO_IP, IPPROTO_IPV6, IPV6_UNICAST_IF, IP_UNICAST_IF, SOCKET_ERROR,
+    };
+
+    // `IP_UNICAST_IF` expects the index in network byte order, but `IPV6_UNICAST_IF` does not.
+    let (level, option, index) = if ipv6 {
+        (IPPROTO_IPV6, IPV6_UNICAST_IF, interface.index)
+    } else {
+        (IPPROTO_IP, IP_UNICAST_IF, interface.index.to_be())
+    };
+    let result = unsafe {
+        setsockopt(
+            socket.as_raw_socket() as usize,
+            level,
+            option,
+            &index as *const u32 as *const u8,
+            std::mem::size_of_val(&index) as i32,
+        )
+    };
+    if result == SOCKET_ERROR {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
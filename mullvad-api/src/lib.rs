@@ -20,9 +20,12 @@ use talpid_types::ErrorExt;
 
 pub mod availability;
 use availability::{ApiAvailability, ApiAvailabilityHandle};
+pub mod clock_skew;
+use clock_skew::{ClockSkewHandle, ClockSkewMonitor};
 pub mod rest;
 
 mod abortable_stream;
+mod happy_eyeballs;
 mod https_client_with_sni;
 pub mod proxy;
 mod tls_stream;
@@ -33,10 +36,13 @@ mod access;
 mod address_cache;
 pub mod device;
 mod fs;
+mod interface_binding;
 mod relay_list;
+mod scheduler;
 pub use address_cache::AddressCache;
 pub use device::DevicesProxy;
 pub use hyper::StatusCode;
+pub use interface_binding::InterfaceBinding;
 pub use relay_list::RelayListProxy;
 
 /// Error code returned by the Mullvad API if the voucher has alreaby been used.
@@ -45,6 +51,9 @@ pub const VOUCHER_USED: &str = "VOUCHER_USED";
 /// Error code returned by the Mullvad API if the voucher code is invalid.
 pub const INVALID_VOUCHER: &str = "INVALID_VOUCHER";
 
+/// Error code returned by the Mullvad API if the voucher can no longer be redeemed.
+pub const VOUCHER_EXPIRED: &str = "VOUCHER_EXPIRED";
+
 /// Error code returned by the Mullvad API if the account token is invalid.
 pub const INVALID_ACCOUNT: &str = "INVALID_ACCOUNT";
 
@@ -129,6 +138,8 @@ pub struct Runtime {
     handle: tokio::runtime::Handle,
     pub address_cache: AddressCache,
     api_availability: availability::ApiAvailability,
+    clock_skew: ClockSkewMonitor,
+    interface_binding: InterfaceBinding,
     #[cfg(target_os = "android")]
     socket_bypass_tx: Option<mpsc::Sender<SocketBypassRequest>>,
 }
@@ -145,15 +156,17 @@ pub enum Error {
     ApiCheckError(#[error(source)] availability::Error),
 }
 
-/// Closure that receives the next API (real or proxy) endpoint to use for `api.mullvad.net`.
-/// It should return a future that determines whether to reject the new endpoint or not.
-pub trait ApiEndpointUpdateCallback: Fn(SocketAddr) -> Self::AcceptedNewEndpoint {
+/// Closure that receives the next API (real or proxy) endpoints to use for `api.mullvad.net`.
+/// There is more than one endpoint when connecting directly to an API that has both IPv4 and
+/// IPv6 addresses, since connections to both are raced.
+/// It should return a future that determines whether to reject the new endpoints or not.
+pub trait ApiEndpointUpdateCallback: Fn(Vec<SocketAddr>) -> Self::AcceptedNewEndpoint {
     type AcceptedNewEndpoint: Future<Output = bool> + Send;
 }
 
 impl<U, T: Future<Output = bool> + Send> ApiEndpointUpdateCallback for U
 where
-    U: Fn(SocketAddr) -> T,
+    U: Fn(Vec<SocketAddr>) -> T,
 {
     type AcceptedNewEndpoint = T;
 }
@@ -176,6 +189,8 @@ impl Runtime {
             handle,
             address_cache: AddressCache::new(None)?,
             api_availability: ApiAvailability::new(availability::State::default()),
+            clock_skew: ClockSkewMonitor::new(),
+            interface_binding: InterfaceBinding::default(),
             #[cfg(target_os = "android")]
             socket_bypass_tx,
         })
@@ -223,6 +238,8 @@ impl Runtime {
             handle,
             address_cache,
             api_availability: ApiAvailability::new(availability::State::default()),
+            clock_skew: ClockSkewMonitor::new(),
+            interface_binding: InterfaceBinding::default(),
             #[cfg(target_os = "android")]
             socket_bypass_tx,
         })
@@ -239,9 +256,11 @@ impl Runtime {
         rest::RequestService::spawn(
             sni_hostname,
             self.api_availability.handle(),
+            self.clock_skew.handle(),
             self.address_cache.clone(),
             proxy_provider,
             new_address_callback,
+            self.interface_binding.clone(),
             #[cfg(target_os = "android")]
             socket_bypass_tx,
         )
@@ -294,6 +313,17 @@ impl Runtime {
     pub fn availability_handle(&self) -> ApiAvailabilityHandle {
         self.api_availability.handle()
     }
+
+    /// Returns a handle for observing problems with the system clock that are detected by the
+    /// request services.
+    pub fn clock_skew_handle(&self) -> ClockSkewHandle {
+        self.clock_skew.handle()
+    }
+
+    /// Returns a handle for setting the interface that API connections are bound to.
+    pub fn interface_binding(&self) -> InterfaceBinding {
+        self.interface_binding.clone()
+    }
 }
 
 #[derive(Clone)]
@@ -4,7 +4,7 @@ use crate::rest;
 
 use hyper::{header, Method, StatusCode};
 use mullvad_types::{location, relay_list};
-use talpid_types::net::wireguard;
+use talpid_types::net::{obfuscation::CertificateFingerprint, wireguard};
 
 use std::{
     collections::BTreeMap,
@@ -226,7 +226,7 @@ struct Relay {
     owned: bool,
     location: String,
     provider: String,
-    ipv4_addr_in: Ipv4Addr,
+    ipv4_addr_in: Option<Ipv4Addr>,
     ipv6_addr_in: Option<Ipv6Addr>,
     weight: u64,
     include_in_country: bool,
@@ -311,6 +311,10 @@ struct WireGuardRelay {
     #[serde(flatten)]
     relay: Relay,
     public_key: wireguard::PublicKey,
+    /// Fingerprint of the certificate presented to TLS obfuscation clients, if the relay
+    /// supports it.
+    #[serde(default)]
+    tls_certificate_sha256: Option<CertificateFingerprint>,
 }
 
 impl WireGuardRelay {
@@ -320,6 +324,7 @@ impl WireGuardRelay {
             location,
             relay_list::RelayEndpointData::Wireguard(relay_list::WireguardRelayEndpointData {
                 public_key: self.public_key,
+                tls_certificate: self.tls_certificate_sha256,
             }),
         )
     }
@@ -4,9 +4,13 @@ use crate::{
     access::AccessTokenProxy,
     address_cache::AddressCache,
     availability::ApiAvailabilityHandle,
+    clock_skew::ClockSkewHandle,
     https_client_with_sni::{HttpsConnectorWithSni, HttpsConnectorWithSniHandle},
     proxy::ApiConnectionMode,
+    scheduler::RequestScheduler,
+    InterfaceBinding,
 };
+use chrono::{DateTime, Utc};
 use futures::{
     channel::{mpsc, oneshot},
     stream::StreamExt,
@@ -74,6 +78,13 @@ pub enum Error {
     /// The string given was not a valid URI.
     #[error(display = "Not a valid URI")]
     UriError(#[error(source)] http::uri::InvalidUri),
+
+    /// The API certificate was rejected as expired or not yet valid. This almost always means
+    /// that the system clock is wrong.
+    #[error(
+        display = "The API certificate is not valid at the current time, check the system clock"
+    )]
+    ClockSkewError(#[error(source)] hyper::Error),
 }
 
 impl Error {
@@ -104,6 +115,69 @@ impl Error {
         }
         self
     }
+
+    /// Returns a new instance for which certificate validity errors are mapped to
+    /// `Self::ClockSkewError`.
+    fn map_clock_skew(self) -> Self {
+        match self {
+            Error::HyperError(error) if is_clock_skew_error(&error) => Self::ClockSkewError(error),
+            error => error,
+        }
+    }
+}
+
+/// Returns whether `error` was caused by the API certificate being rejected because the local
+/// time is outside of its validity period.
+fn is_clock_skew_error(error: &hyper::Error) -> bool {
+    use std::error::Error;
+    let mut source = error.source();
+    while let Some(error) = source {
+        let io_error: Option<&std::io::Error> = error.downcast_ref();
+        if let Some(io_error) = io_error {
+            let time_error: Option<&crate::tls_stream::CertificateTimeError> =
+                io_error.get_ref().and_then(|inner| inner.downcast_ref());
+            if time_error.is_some() {
+                return true;
+            }
+        }
+        source = error.source();
+    }
+    false
+}
+
+/// Returns the time of the API according to the `Date` header of `response`.
+fn server_time(response: &Response) -> Option<DateTime<Utc>> {
+    let date = response.headers().get(header::DATE)?.to_str().ok()?;
+    let server_time = DateTime::parse_from_rfc2822(date).ok()?;
+    Some(server_time.with_timezone(&Utc))
+}
+
+/// Returns how long the API asked clients to wait before sending more requests, according to the
+/// `Retry-After` header of `response`. The header contains either a number of seconds or a date.
+fn retry_after(response: &Response, now: DateTime<Utc>) -> Option<Duration> {
+    let value = response.headers().get(header::RETRY_AFTER)?.to_str().ok()?;
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let retry_time = DateTime::parse_from_rfc2822(value).ok()?;
+    retry_time.signed_duration_since(now).to_std().ok()
+}
+
+/// Reports the outcome of a request to `scheduler`. Only network errors and responses indicating
+/// that the API is overloaded or failing count as failures.
+fn register_outcome(scheduler: &RequestScheduler, response: &Result<Response>) {
+    match response {
+        Ok(response)
+            if response.status() == StatusCode::TOO_MANY_REQUESTS
+                || response.status().is_server_error() =>
+        {
+            log::warn!("The API responded with status {}", response.status());
+            scheduler.register_failure(retry_after(response, Utc::now()));
+        }
+        Ok(_) => scheduler.register_success(),
+        Err(error) if error.is_network_error() => scheduler.register_failure(None),
+        Err(_) => (),
+    }
 }
 
 use super::ApiEndpointUpdateCallback;
@@ -122,6 +196,8 @@ pub(crate) struct RequestService<
     new_address_callback: F,
     address_cache: AddressCache,
     api_availability: ApiAvailabilityHandle,
+    clock_skew: ClockSkewHandle,
+    scheduler: RequestScheduler,
 }
 
 impl<
@@ -133,14 +209,17 @@ impl<
     pub async fn spawn(
         sni_hostname: Option<String>,
         api_availability: ApiAvailabilityHandle,
+        clock_skew: ClockSkewHandle,
         address_cache: AddressCache,
         mut proxy_config_provider: T,
         new_address_callback: F,
+        interface_binding: InterfaceBinding,
         #[cfg(target_os = "android")] socket_bypass_tx: Option<mpsc::Sender<SocketBypassRequest>>,
     ) -> RequestServiceHandle {
         let (connector, connector_handle) = HttpsConnectorWithSni::new(
             sni_hostname,
             address_cache.clone(),
+            interface_binding,
             #[cfg(target_os = "android")]
             socket_bypass_tx.clone(),
         );
@@ -163,6 +242,8 @@ impl<
             new_address_callback,
             address_cache,
             api_availability,
+            clock_skew,
+            scheduler: RequestScheduler::new(),
         };
         let handle = RequestServiceHandle { tx: command_tx };
         tokio::spawn(service.into_future());
@@ -178,6 +259,8 @@ impl<
                 let hyper_request = request.into_request();
 
                 let api_availability = self.api_availability.clone();
+                let clock_skew = self.clock_skew.clone();
+                let scheduler = self.scheduler.clone();
                 let suspend_fut = api_availability.wait_for_unsuspend();
                 let request_fut = self.client.request(hyper_request).map_err(Error::from);
 
@@ -187,11 +270,31 @@ impl<
                 };
 
                 let future = async move {
+                    // Wait for the backoff outside of the timeout, since it may be longer
+                    let _permit = scheduler.wait_for_turn().await;
                     let response = tokio::time::timeout(timeout, request_future)
                         .await
                         .map_err(Error::TimeoutError);
 
-                    let response = flatten_result(response).map_err(|error| error.map_aborted());
+                    let response = flatten_result(response)
+                        .map_err(|error| error.map_aborted().map_clock_skew());
+
+                    match &response {
+                        Ok(response) => {
+                            if let Some(server_time) = server_time(response) {
+                                clock_skew.report_server_time(server_time, Utc::now());
+                            }
+                        }
+                        Err(error @ Error::ClockSkewError(_)) => {
+                            log::error!("{}", error.display_chain_with_msg("HTTP request failed"));
+                            clock_skew.report_certificate_not_valid();
+                        }
+                        _ => (),
+                    }
+
+                    if !api_availability.get_state().is_offline() {
+                        register_outcome(&scheduler, &response);
+                    }
 
                     if let Err(err) = &response {
                         if err.is_network_error() && !api_availability.get_state().is_offline() {
@@ -212,15 +315,17 @@ impl<
             }
             RequestCommand::Reset => {
                 self.connector_handle.reset();
+                self.scheduler.reset();
             }
             RequestCommand::NextApiConfig => {
                 if let Some(new_config) = self.proxy_config_provider.next().await {
-                    let endpoint = match new_config.get_endpoint() {
-                        Some(endpoint) => endpoint,
-                        None => self.address_cache.get_address().await,
+                    // Direct connections race all API addresses, so all of them must be allowed
+                    let endpoints = match new_config.get_endpoint() {
+                        Some(endpoint) => vec![endpoint],
+                        None => self.address_cache.get_addresses().await,
                     };
                     // Switch to new connection mode unless rejected by address change callback
-                    if (self.new_address_callback)(endpoint).await {
+                    if (self.new_address_callback)(endpoints).await {
                         self.connector_handle.set_connection_mode(new_config);
                     }
                 }
@@ -642,14 +747,14 @@ impl MullvadRestHandle {
                 }
                 match api_proxy.clone().get_api_addrs().await {
                     Ok(new_addrs) => {
-                        if let Some(addr) = new_addrs.get(0) {
+                        if !new_addrs.is_empty() {
                             log::debug!(
-                                "Fetched new API address {:?}. Fetching again in {} hours",
-                                addr,
+                                "Fetched new API addresses {:?}. Fetching again in {} hours",
+                                new_addrs,
                                 API_IP_CHECK_INTERVAL.as_secs() / (60 * 60)
                             );
-                            if let Err(err) = address_cache.set_address(*addr).await {
-                                log::error!("Failed to save newly updated API address: {}", err);
+                            if let Err(err) = address_cache.set_addresses(&new_addrs).await {
+                                log::error!("Failed to save newly updated API addresses: {}", err);
                             }
                         } else {
                             log::error!("API returned no API addresses");
@@ -688,3 +793,47 @@ fn flatten_result<T, E>(
         Err(err) => Err(err),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_server_time() {
+        let response = hyper::Response::builder()
+            .header(header::DATE, "Sun, 06 Nov 1994 08:49:37 GMT")
+            .body(hyper::Body::empty())
+            .unwrap();
+        let expected = DateTime::parse_from_rfc3339("1994-11-06T08:49:37Z").unwrap();
+        assert_eq!(server_time(&response), Some(expected.with_timezone(&Utc)));
+
+        let response = hyper::Response::new(hyper::Body::empty());
+        assert_eq!(server_time(&response), None);
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let response = hyper::Response::builder()
+            .header(header::RETRY_AFTER, "120")
+            .body(hyper::Body::empty())
+            .unwrap();
+        assert_eq!(
+            retry_after(&response, Utc::now()),
+            Some(Duration::from_secs(120))
+        );
+
+        let response = hyper::Response::builder()
+            .header(header::RETRY_AFTER, "Sun, 06 Nov 1994 08:49:37 GMT")
+            .body(hyper::Body::empty())
+            .unwrap();
+        let now = DateTime::parse_from_rfc3339("1994-11-06T08:48:37Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(retry_after(&response, now), Some(Duration::from_secs(60)));
+        // A date in the past does not delay requests
+        assert_eq!(
+            retry_after(&response, now + chrono::Duration::hours(1)),
+            None
+        );
+    }
+}
@@ -0,0 +1,157 @@
+//! Scheduling of requests sent by the `RequestService`.
+//!
+//! All requests to the API, whether they fetch the relay list, check for updates, rotate keys or
+//! refresh the account expiry, go through a single scheduler. It limits how many requests may be
+//! in flight at once, and backs off exponentially while the API is failing. The backoff is
+//! jittered, so that installations that lost access to the API at the same time, e.g. during an
+//! outage, do not all come back at the same time. If the API asks clients to wait using the
+//! `Retry-After` header, no requests are sent until that time has passed.
+
+use rand::Rng;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time::Instant,
+};
+
+/// Maximum number of requests that may be in flight at the same time.
+const MAX_CONCURRENT_REQUESTS: usize = 4;
+/// Backoff after the first failed request.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// Largest backoff caused by consecutive failures.
+const MAX_BACKOFF: Duration = Duration::from_secs(2 * 60);
+/// Largest delay that the API may request using `Retry-After`.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60 * 60);
+
+/// Decides when requests may be sent. Clones share the same state.
+#[derive(Clone)]
+pub(crate) struct RequestScheduler {
+    permits: Arc<Semaphore>,
+    backoff: Arc<Mutex<Backoff>>,
+}
+
+#[derive(Default)]
+struct Backoff {
+    consecutive_failures: u32,
+    /// No requests are sent before this time.
+    not_before: Option<Instant>,
+}
+
+impl RequestScheduler {
+    pub fn new() -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(MAX_CONCURRENT_REQUESTS)),
+            backoff: Arc::new(Mutex::new(Backoff::default())),
+        }
+    }
+
+    /// Waits until a request may be sent. The request should be sent while holding the returned
+    /// permit, and its outcome reported using [`Self::register_success`] or
+    /// [`Self::register_failure`].
+    pub async fn wait_for_turn(&self) -> OwnedSemaphorePermit {
+        loop {
+            let permit = self
+                .permits
+                .clone()
+                .acquire_owned()
+                .await
+                .expect("request scheduler semaphore is never closed");
+
+            // The backoff may have changed while waiting for a permit
+            let not_before = self.backoff.lock().unwrap().not_before;
+            match not_before {
+                Some(not_before) if not_before > Instant::now() => {
+                    drop(permit);
+                    tokio::time::sleep_until(not_before).await;
+                }
+                _ => return permit,
+            }
+        }
+    }
+
+    /// Registers that the API responded successfully, ending any backoff caused by failures.
+    pub fn register_success(&self) {
+        let mut backoff = self.backoff.lock().unwrap();
+        if backoff.consecutive_failures > 0 {
+            log::debug!(
+                "API request succeeded after {} failures",
+                backoff.consecutive_failures
+            );
+        }
+        backoff.consecutive_failures = 0;
+    }
+
+    /// Registers that a request failed because the API was unreachable or overloaded. Subsequent
+    /// requests are delayed by a jittered exponential backoff, or by `retry_after` if the API
+    /// asked for it and it is longer.
+    pub fn register_failure(&self, retry_after: Option<Duration>) {
+        let mut backoff = self.backoff.lock().unwrap();
+        backoff.consecutive_failures = backoff.consecutive_failures.saturating_add(1);
+
+        let mut delay = jitter(
+            backoff_delay(backoff.consecutive_failures),
+            rand::thread_rng().gen(),
+        );
+        if let Some(retry_after) = retry_after {
+            delay = delay.max(retry_after.min(MAX_RETRY_AFTER));
+        }
+
+        let not_before = Instant::now() + delay;
+        if backoff
+            .not_before
+            .map(|current| current < not_before)
+            .unwrap_or(true)
+        {
+            log::debug!(
+                "Delaying API requests by {} ms after {} consecutive failures",
+                delay.as_millis(),
+                backoff.consecutive_failures
+            );
+            backoff.not_before = Some(not_before);
+        }
+    }
+
+    /// Forgets all failures, e.g. because requests will be sent over another network path.
+    /// Delays requested by the API are kept.
+    pub fn reset(&self) {
+        self.backoff.lock().unwrap().consecutive_failures = 0;
+    }
+}
+
+/// Returns the backoff after `failures` consecutive failures, before applying jitter.
+fn backoff_delay(failures: u32) -> Duration {
+    let exponent = failures.saturating_sub(1).min(16);
+    INITIAL_BACKOFF
+        .saturating_mul(1 << exponent)
+        .min(MAX_BACKOFF)
+}
+
+/// Returns a delay between half of `delay` and all of it, depending on `random`, which should be
+/// in the range `[0, 1)`.
+fn jitter(delay: Duration, random: f64) -> Duration {
+    delay / 2 + delay.mul_f64(random / 2.0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_backoff_delay() {
+        assert_eq!(backoff_delay(1), INITIAL_BACKOFF);
+        assert_eq!(backoff_delay(2), INITIAL_BACKOFF * 2);
+        assert_eq!(backoff_delay(3), INITIAL_BACKOFF * 4);
+        assert_eq!(backoff_delay(100), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn test_jitter() {
+        let delay = Duration::from_secs(10);
+        assert_eq!(jitter(delay, 0.0), Duration::from_secs(5));
+        assert_eq!(jitter(delay, 0.5), Duration::from_millis(7500));
+        assert!(jitter(delay, 0.999) <= delay);
+    }
+}
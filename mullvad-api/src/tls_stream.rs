@@ -1,20 +1,59 @@
 //! Provides a TLS 1.3 stream with SNI and LE root cert only.
 use std::{
+    fmt,
     io::{self, ErrorKind},
     pin::Pin,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     task::{self, Poll},
+    time::SystemTime,
 };
 
 use hyper::client::connect::{Connected, Connection};
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use tokio_rustls::{
-    rustls::{self, ClientConfig, ServerName},
+    rustls::{
+        self,
+        client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier},
+        Certificate, ClientConfig, ServerName,
+    },
     TlsConnector,
 };
 
 const LE_ROOT_CERT: &[u8] = include_bytes!("../le_root_cert.pem");
 
+/// Signature algorithms accepted when checking why a certificate chain was rejected. These are the
+/// algorithms that rustls accepts.
+static SUPPORTED_SIG_ALGS: &[&webpki::SignatureAlgorithm] = &[
+    &webpki::ECDSA_P256_SHA256,
+    &webpki::ECDSA_P256_SHA384,
+    &webpki::ECDSA_P384_SHA256,
+    &webpki::ECDSA_P384_SHA384,
+    &webpki::ED25519,
+    &webpki::RSA_PSS_2048_8192_SHA256_LEGACY_KEY,
+    &webpki::RSA_PSS_2048_8192_SHA384_LEGACY_KEY,
+    &webpki::RSA_PSS_2048_8192_SHA512_LEGACY_KEY,
+    &webpki::RSA_PKCS1_2048_8192_SHA256,
+    &webpki::RSA_PKCS1_2048_8192_SHA384,
+    &webpki::RSA_PKCS1_2048_8192_SHA512,
+    &webpki::RSA_PKCS1_3072_8192_SHA384,
+];
+
+/// Returned when the server certificate was rejected because the current time is outside of the
+/// validity period of a certificate in its chain.
+#[derive(Debug)]
+pub struct CertificateTimeError(());
+
+impl fmt::Display for CertificateTimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The certificate is not valid at the current time")
+    }
+}
+
+impl std::error::Error for CertificateTimeError {}
+
 pub struct TlsStream<S: AsyncRead + AsyncWrite + Unpin> {
     stream: tokio_rustls::client::TlsStream<S>,
 }
@@ -24,20 +63,18 @@ where
     S: AsyncRead + AsyncWrite + Unpin,
 {
     pub async fn connect_https(stream: S, domain: &str) -> io::Result<TlsStream<S>> {
-        lazy_static::lazy_static! {
-            static ref TLS_CONFIG: Arc<ClientConfig> = {
-                let config = ClientConfig::builder()
-                    .with_safe_default_cipher_suites()
-                    .with_safe_default_kx_groups()
-                    .with_protocol_versions(&[&rustls::version::TLS13])
-                    .unwrap()
-                    .with_root_certificates(read_cert_store())
-                    .with_no_client_auth();
-                Arc::new(config)
-            };
-        }
-
-        let connector = TlsConnector::from(TLS_CONFIG.clone());
+        // The verifier is created for each connection, so that it can tell whether this
+        // particular handshake failed because of the time.
+        let verifier = Arc::new(Verifier::new());
+        let config = ClientConfig::builder()
+            .with_safe_default_cipher_suites()
+            .with_safe_default_kx_groups()
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .unwrap()
+            .with_custom_certificate_verifier(verifier.clone())
+            .with_no_client_auth();
+
+        let connector = TlsConnector::from(Arc::new(config));
 
         let host = match ServerName::try_from(domain) {
             Ok(n) => n,
@@ -49,18 +86,30 @@ where
             }
         };
 
-        let stream = connector.connect(host, stream).await?;
+        let stream = connector.connect(host, stream).await.map_err(|error| {
+            if verifier.rejected_time.load(Ordering::SeqCst) {
+                io::Error::new(ErrorKind::InvalidData, CertificateTimeError(()))
+            } else {
+                error
+            }
+        })?;
 
         Ok(TlsStream { stream })
     }
 }
 
+lazy_static::lazy_static! {
+    static ref ROOT_CERTS: Vec<Vec<u8>> = rustls_pemfile::certs(
+        &mut std::io::BufReader::new(LE_ROOT_CERT)
+    )
+    .expect("Failed to parse pem file");
+    static ref CERT_STORE: rustls::RootCertStore = read_cert_store();
+}
+
 fn read_cert_store() -> rustls::RootCertStore {
     let mut cert_store = rustls::RootCertStore::empty();
 
-    let certs = rustls_pemfile::certs(&mut std::io::BufReader::new(LE_ROOT_CERT))
-        .expect("Failed to parse pem file");
-    let (num_certs_added, num_failures) = cert_store.add_parsable_certificates(&certs);
+    let (num_certs_added, num_failures) = cert_store.add_parsable_certificates(&ROOT_CERTS);
     if num_failures > 0 || num_certs_added != 1 {
         panic!("Failed to add root cert");
     }
@@ -68,6 +117,76 @@ fn read_cert_store() -> rustls::RootCertStore {
     cert_store
 }
 
+/// Verifies certificates like the default verifier of rustls. If verification fails, it also
+/// records whether that was because of the current time, since rustls does not say so in a
+/// structured way.
+struct Verifier {
+    inner: WebPkiVerifier,
+    rejected_time: AtomicBool,
+}
+
+impl Verifier {
+    fn new() -> Self {
+        Verifier {
+            inner: WebPkiVerifier::new(CERT_STORE.clone(), None),
+            rejected_time: AtomicBool::new(false),
+        }
+    }
+}
+
+impl ServerCertVerifier for Verifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let result = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        );
+        if result.is_err() && is_time_error(end_entity, intermediates, now) {
+            self.rejected_time.store(true, Ordering::SeqCst);
+        }
+        result
+    }
+}
+
+/// Returns whether the chain is rejected by webpki because `now` is outside of the validity
+/// period of one of its certificates.
+fn is_time_error(end_entity: &Certificate, intermediates: &[Certificate], now: SystemTime) -> bool {
+    let time = match webpki::Time::try_from(now) {
+        Ok(time) => time,
+        Err(_) => return false,
+    };
+    let cert = match webpki::EndEntityCert::try_from(end_entity.0.as_ref()) {
+        Ok(cert) => cert,
+        Err(_) => return false,
+    };
+    let trust_anchors: Vec<_> = ROOT_CERTS
+        .iter()
+        .filter_map(|cert| webpki::TrustAnchor::try_from_cert_der(cert).ok())
+        .collect();
+    let intermediates: Vec<&[u8]> = intermediates.iter().map(|cert| cert.0.as_ref()).collect();
+
+    matches!(
+        cert.verify_is_valid_tls_server_cert(
+            SUPPORTED_SIG_ALGS,
+            &webpki::TlsServerTrustAnchors(&trust_anchors),
+            &intermediates,
+            time,
+        ),
+        Err(webpki::Error::CertExpired) | Err(webpki::Error::CertNotValidYet)
+    )
+}
+
 impl<S> AsyncRead for TlsStream<S>
 where
     S: AsyncRead + AsyncWrite + Unpin,
@@ -202,9 +202,13 @@ impl Account {
             .sort_unstable_by_key(|dev| dev.created.as_ref().map(|dt| dt.seconds).unwrap_or(0));
         for device in device_list.devices {
             let device = Device::try_from(device.clone()).unwrap();
+            let is_current = device.id == device_list.current_device_id;
             if verbose {
                 println!();
                 println!("Name      : {}", device.pretty_name());
+                if is_current {
+                    println!("Current   : yes");
+                }
                 println!("Id        : {}", device.id);
                 println!("Public key: {}", device.pubkey);
                 println!(
@@ -214,6 +218,8 @@ impl Account {
                 for port in device.ports {
                     println!("Port      : {}", port);
                 }
+            } else if is_current {
+                println!("{} (current)", device.pretty_name());
             } else {
                 println!("{}", device.pretty_name());
             }
@@ -293,7 +299,7 @@ impl Account {
             }
             Err(err) => {
                 match err.code() {
-                    Code::NotFound | Code::ResourceExhausted => {
+                    Code::NotFound | Code::ResourceExhausted | Code::FailedPrecondition => {
                         eprintln!("Failed to submit voucher: {}", err.message());
                     }
                     _ => return Err(Error::RpcFailed(err)),
@@ -0,0 +1,81 @@
+use crate::{new_rpc_client, Command, Result};
+use mullvad_management_interface::types;
+
+pub struct BlockedProtocols;
+
+#[mullvad_management_interface::async_trait]
+impl Command for BlockedProtocols {
+    fn name(&self) -> &'static str {
+        "blocked-protocols"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static> {
+        clap::App::new(self.name())
+            .about(
+                "Block protocols inside the tunnel while connected. Traffic to the relay is \
+                 never blocked",
+            )
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::App::new("set")
+                    .about("Set the protocols to block. Protocols that are not given are allowed")
+                    .arg(
+                        clap::Arg::new("ipv6")
+                            .long("ipv6")
+                            .help("Block all IPv6 traffic, including DNS"),
+                    )
+                    .arg(
+                        clap::Arg::new("udp")
+                            .long("udp")
+                            .help("Block all UDP traffic, including DNS"),
+                    )
+                    .arg(
+                        clap::Arg::new("smb")
+                            .long("smb")
+                            .help("Block SMB traffic (TCP ports 139 and 445)"),
+                    ),
+            )
+            .subcommand(clap::App::new("clear").about("Stop blocking any protocols"))
+            .subcommand(clap::App::new("get").about("Display the blocked protocols"))
+    }
+
+    async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
+        match matches.subcommand() {
+            Some(("set", matches)) => {
+                self.set(types::BlockedTunnelProtocols {
+                    ipv6: matches.is_present("ipv6"),
+                    udp: matches.is_present("udp"),
+                    smb: matches.is_present("smb"),
+                })
+                .await
+            }
+            Some(("clear", _)) => self.set(types::BlockedTunnelProtocols::default()).await,
+            Some(("get", _)) => self.get().await,
+            _ => unreachable!("No blocked-protocols command given"),
+        }
+    }
+}
+
+impl BlockedProtocols {
+    async fn set(&self, blocked_protocols: types::BlockedTunnelProtocols) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_blocked_tunnel_protocols(blocked_protocols).await?;
+        println!("Changed blocked protocols");
+        Ok(())
+    }
+
+    async fn get(&self) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let blocked_protocols = rpc
+            .get_settings(())
+            .await?
+            .into_inner()
+            .blocked_tunnel_protocols
+            .unwrap_or_default();
+        println!(
+            "Blocked in tunnel: {}",
+            talpid_types::net::BlockedTunnelProtocols::from(blocked_protocols)
+        );
+        Ok(())
+    }
+}
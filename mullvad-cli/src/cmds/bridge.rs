@@ -472,9 +472,14 @@ impl Bridge {
                     } else {
                         "rented"
                     };
+                    let address = if relay.ipv4_addr_in.is_empty() {
+                        &relay.ipv6_addr_in
+                    } else {
+                        &relay.ipv4_addr_in
+                    };
                     println!(
                         "\t\t{} ({}) - hosted by {} ({ownership})",
-                        relay.hostname, relay.ipv4_addr_in, relay.provider
+                        relay.hostname, address, relay.provider
                     );
                 }
             }
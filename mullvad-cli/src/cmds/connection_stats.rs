@@ -0,0 +1,60 @@
+use crate::{new_rpc_client, Command, Result};
+
+pub struct ConnectionStats;
+
+#[mullvad_management_interface::async_trait]
+impl Command for ConnectionStats {
+    fn name(&self) -> &'static str {
+        "connection-stats"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static> {
+        clap::App::new(self.name())
+            .about(
+                "Control whether anonymous statistics about connection attempts are kept \
+                 locally. They can be attached to problem reports using \
+                 `mullvad-problem-report collect --connection-stats`",
+            )
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::App::new("set")
+                    .about("Change the setting. Turning it off removes the statistics")
+                    .arg(
+                        clap::Arg::new("policy")
+                            .required(true)
+                            .possible_values(["on", "off"]),
+                    ),
+            )
+            .subcommand(clap::App::new("get").about("Display the current setting"))
+    }
+
+    async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
+        match matches.subcommand() {
+            Some(("set", matches)) => {
+                let connection_stats = matches.value_of("policy").expect("missing policy");
+                self.set(connection_stats == "on").await
+            }
+            Some(("get", _)) => self.get().await,
+            _ => unreachable!("No connection-stats command given"),
+        }
+    }
+}
+
+impl ConnectionStats {
+    async fn set(&self, connection_stats: bool) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_connection_stats(connection_stats).await?;
+        println!("Changed connection statistics setting");
+        Ok(())
+    }
+
+    async fn get(&self) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let connection_stats = rpc.get_settings(()).await?.into_inner().connection_stats;
+        println!(
+            "Connection statistics: {}",
+            if connection_stats { "on" } else { "off" }
+        );
+        Ok(())
+    }
+}
@@ -1,6 +1,6 @@
 use crate::{format, new_rpc_client, state, Command, Error, Result};
 use futures::StreamExt;
-use mullvad_management_interface::types::tunnel_state::State::Disconnected;
+use mullvad_management_interface::types::{self, tunnel_state::State::Disconnected};
 
 pub struct Disconnect;
 
@@ -19,6 +19,16 @@ impl Command for Disconnect {
                     .short('w')
                     .help("Wait until disconnected before exiting"),
             )
+            .arg(
+                clap::Arg::new("for")
+                    .long("for")
+                    .takes_value(true)
+                    .value_name("MINUTES")
+                    .help(
+                        "Reconnect automatically after this many minutes. Traffic is not blocked \
+                         in the meantime, even if \"block when disconnected\" is enabled",
+                    ),
+            )
     }
 
     async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
@@ -30,7 +40,33 @@ impl Command for Disconnect {
             None
         };
 
-        if rpc.disconnect_tunnel(()).await?.into_inner() {
+        let disconnect_issued = if matches.is_present("for") {
+            let minutes = matches.value_of_t_or_exit::<u32>("for");
+            let resume_at = rpc
+                .pause_protection(types::Duration {
+                    seconds: i64::from(minutes) * 60,
+                    nanos: 0,
+                })
+                .await
+                .map_err(|error| Error::RpcFailedExt("Failed to pause protection", error))?
+                .into_inner();
+            let ndt =
+                chrono::NaiveDateTime::from_timestamp(resume_at.seconds, resume_at.nanos as u32);
+            let utc = chrono::DateTime::<chrono::Utc>::from_utc(ndt, chrono::Utc);
+            println!(
+                "Reconnecting automatically at {}",
+                utc.with_timezone(&chrono::Local)
+            );
+            // The tunnel may already be disconnected, in which case there is nothing to wait for
+            !matches!(
+                rpc.get_tunnel_state(()).await?.into_inner().state,
+                Some(Disconnected(_))
+            )
+        } else {
+            rpc.disconnect_tunnel(()).await?.into_inner()
+        };
+
+        if disconnect_issued {
             if let Some(mut receiver) = receiver_option {
                 while let Some(state) = receiver.next().await {
                     let state = state?;
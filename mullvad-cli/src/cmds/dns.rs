@@ -1,4 +1,4 @@
-use crate::{new_rpc_client, Command, Result};
+use crate::{format, new_rpc_client, Command, Result};
 use mullvad_management_interface::types;
 use mullvad_types::settings::{DnsOptions, DnsState};
 use std::{convert::TryInto, net::IpAddr};
@@ -65,6 +65,15 @@ impl Command for Dns {
                             ),
                     ),
             )
+            .subcommand(
+                clap::App::new("flush-cache")
+                    .about("Flush the system DNS cache after changing DNS settings")
+                    .arg(
+                        clap::Arg::new("policy")
+                            .required(true)
+                            .possible_values(["on", "off"]),
+                    ),
+            )
     }
 
     async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
@@ -92,6 +101,10 @@ impl Command for Dns {
                 }
                 _ => unreachable!("No custom-dns server command given"),
             },
+            Some(("flush-cache", matches)) => {
+                let policy = matches.value_of("policy").expect("missing policy");
+                self.set_flush_cache(policy == "on").await
+            }
             Some(("get", _)) => self.get().await,
             _ => unreachable!("No custom-dns command given"),
         }
@@ -109,47 +122,58 @@ impl Dns {
     ) -> Result<()> {
         let mut rpc = new_rpc_client().await?;
         let settings = rpc.get_settings(()).await?.into_inner();
-        rpc.set_dns_options(types::DnsOptions {
-            state: types::dns_options::DnsState::Default as i32,
-            default_options: Some(types::DefaultDnsOptions {
-                block_ads,
-                block_trackers,
-                block_malware,
-                block_adult_content,
-                block_gambling,
-            }),
-            ..settings.tunnel_options.unwrap().dns_options.unwrap()
-        })
-        .await?;
+        let apply_plan = rpc
+            .set_dns_options(types::DnsOptions {
+                state: types::dns_options::DnsState::Default as i32,
+                default_options: Some(types::DefaultDnsOptions {
+                    block_ads,
+                    block_trackers,
+                    block_malware,
+                    block_adult_content,
+                    block_gambling,
+                }),
+                ..settings.tunnel_options.unwrap().dns_options.unwrap()
+            })
+            .await?
+            .into_inner();
         println!("Updated DNS settings");
+        format::print_apply_plan(&apply_plan);
         Ok(())
     }
 
     async fn set_custom(&self, servers: Option<Vec<IpAddr>>) -> Result<()> {
         let mut rpc = new_rpc_client().await?;
         let settings = rpc.get_settings(()).await?.into_inner();
-        rpc.set_dns_options(types::DnsOptions {
-            state: types::dns_options::DnsState::Custom as i32,
-            custom_options: Some(types::CustomDnsOptions {
-                addresses: servers
-                    .unwrap_or_default()
-                    .into_iter()
-                    .map(|a| a.to_string())
-                    .collect(),
-            }),
-            ..settings.tunnel_options.unwrap().dns_options.unwrap()
-        })
-        .await?;
+        let apply_plan = rpc
+            .set_dns_options(types::DnsOptions {
+                state: types::dns_options::DnsState::Custom as i32,
+                custom_options: Some(types::CustomDnsOptions {
+                    addresses: servers
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|a| a.to_string())
+                        .collect(),
+                }),
+                ..settings.tunnel_options.unwrap().dns_options.unwrap()
+            })
+            .await?
+            .into_inner();
         println!("Updated DNS settings");
+        format::print_apply_plan(&apply_plan);
+        Ok(())
+    }
+
+    async fn set_flush_cache(&self, flush_cache: bool) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_flush_dns_cache(flush_cache).await?;
+        println!("Changed DNS cache flushing setting");
         Ok(())
     }
 
     async fn get(&self) -> Result<()> {
         let mut rpc = new_rpc_client().await?;
-        let options: DnsOptions = rpc
-            .get_settings(())
-            .await?
-            .into_inner()
+        let settings = rpc.get_settings(()).await?.into_inner();
+        let options: DnsOptions = settings
             .tunnel_options
             .unwrap()
             .dns_options
@@ -176,6 +200,14 @@ impl Dns {
                 }
             }
         }
+        println!(
+            "Flush DNS cache: {}",
+            if settings.flush_dns_cache {
+                "on"
+            } else {
+                "off"
+            }
+        );
 
         Ok(())
     }
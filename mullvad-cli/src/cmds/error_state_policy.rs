@@ -0,0 +1,65 @@
+use crate::{new_rpc_client, Command, Result};
+use mullvad_management_interface::types;
+use std::convert::TryFrom;
+use talpid_types::tunnel::ErrorStatePolicy as Policy;
+
+pub struct ErrorStatePolicy;
+
+#[mullvad_management_interface::async_trait]
+impl Command for ErrorStatePolicy {
+    fn name(&self) -> &'static str {
+        "error-state-policy"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static> {
+        clap::App::new(self.name())
+            .about("Control which traffic is blocked when the daemon is in the error state")
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::App::new("set").about("Change the policy").arg(
+                    clap::Arg::new("policy")
+                        .required(true)
+                        .possible_values(["allow-lan", "block-all"])
+                        .help(
+                            "allow-lan: allow LAN traffic if LAN access is allowed. block-all: \
+                             block LAN traffic as well",
+                        ),
+                ),
+            )
+            .subcommand(clap::App::new("get").about("Display the current policy"))
+    }
+
+    async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
+        match matches.subcommand() {
+            Some(("set", matches)) => {
+                let policy = matches.value_of_t_or_exit::<Policy>("policy");
+                self.set(policy).await
+            }
+            Some(("get", _)) => self.get().await,
+            _ => unreachable!("No error-state-policy command given"),
+        }
+    }
+}
+
+impl ErrorStatePolicy {
+    async fn set(&self, policy: Policy) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_error_state_policy(types::ErrorStatePolicy::from(policy))
+            .await?;
+        println!("Changed error state policy");
+        Ok(())
+    }
+
+    async fn get(&self) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let policy = rpc
+            .get_settings(())
+            .await?
+            .into_inner()
+            .error_state_policy
+            .unwrap_or_default();
+        let policy = Policy::try_from(policy).unwrap();
+        println!("Error state policy: {}", policy);
+        Ok(())
+    }
+}
@@ -0,0 +1,105 @@
+use crate::{new_rpc_client, Command, Error, Result};
+use mullvad_management_interface::types::physical_interface::Kind;
+
+pub struct ForcedInterface;
+
+#[mullvad_management_interface::async_trait]
+impl Command for ForcedInterface {
+    fn name(&self) -> &'static str {
+        "forced-interface"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static> {
+        clap::App::new(self.name())
+            .about(
+                "Force WireGuard tunnel traffic through a specific network interface, such as \
+                 Ethernet instead of Wi-Fi",
+            )
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::App::new("set").about("Set the interface to use").arg(
+                    clap::Arg::new("interface")
+                        .required(true)
+                        .help("The name of the interface, e.g. eth0, en0 or Ethernet"),
+                ),
+            )
+            .subcommand(
+                clap::App::new("unset").about("Use the interface of the default route again"),
+            )
+            .subcommand(clap::App::new("get").about("Display the forced interface"))
+            .subcommand(
+                clap::App::new("list")
+                    .about("List the interfaces that can be used, and which one is in use"),
+            )
+    }
+
+    async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
+        match matches.subcommand() {
+            Some(("set", matches)) => {
+                let interface = matches.value_of("interface").expect("missing interface");
+                self.set(interface.to_owned()).await
+            }
+            Some(("unset", _)) => self.set(String::new()).await,
+            Some(("get", _)) => self.get().await,
+            Some(("list", _)) => self.list().await,
+            _ => unreachable!("No forced-interface command given"),
+        }
+    }
+}
+
+impl ForcedInterface {
+    async fn set(&self, interface: String) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_forced_interface(interface).await?;
+        println!("Changed forced interface setting");
+        Ok(())
+    }
+
+    async fn get(&self) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let interface = rpc.get_settings(()).await?.into_inner().forced_interface;
+        if interface.is_empty() {
+            println!("Forced interface: none");
+        } else {
+            println!("Forced interface: {}", interface);
+        }
+        Ok(())
+    }
+
+    async fn list(&self) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let forced_interface = rpc.get_settings(()).await?.into_inner().forced_interface;
+        let interfaces = rpc
+            .get_physical_interfaces(())
+            .await
+            .map_err(|error| Error::RpcFailedExt("Failed to list interfaces", error))?
+            .into_inner()
+            .interfaces;
+        if interfaces.is_empty() {
+            println!("No physical interfaces found");
+        }
+        for interface in interfaces {
+            let kind = match Kind::from_i32(interface.kind).unwrap_or(Kind::Other) {
+                Kind::Ethernet => "Ethernet",
+                Kind::Wireless => "Wi-Fi",
+                Kind::Cellular => "cellular",
+                Kind::Other => "other",
+            };
+            let mut line = format!("{} ({}, index {})", interface.name, kind, interface.index);
+            if interface.is_default_route {
+                line.push_str(", default route");
+            }
+            if interface.name == forced_interface {
+                line.push_str(", forced");
+            }
+            println!("{}", line);
+            if interface.mtu != 0 {
+                println!("    MTU: {}", interface.mtu);
+            }
+            if !interface.addresses.is_empty() {
+                println!("    Addresses: {}", interface.addresses.join(", "));
+            }
+        }
+        Ok(())
+    }
+}
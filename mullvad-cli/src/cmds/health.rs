@@ -0,0 +1,62 @@
+use crate::{new_rpc_client, Command, Error, Result};
+use mullvad_management_interface::types::health_check::Status;
+
+pub struct Health;
+
+#[mullvad_management_interface::async_trait]
+impl Command for Health {
+    fn name(&self) -> &'static str {
+        "health"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static> {
+        clap::App::new(self.name())
+            .about(
+                "Check that the internal components of the daemon are working. Exits with an \
+                 error if any check fails",
+            )
+            .arg(clap::Arg::new("preflight").long("preflight").help(
+                "Show the results of the checks for missing drivers, disabled system \
+                         services and other common problems that were run when the daemon started",
+            ))
+    }
+
+    async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let report = if matches.is_present("preflight") {
+            rpc.get_preflight_report(())
+                .await
+                .map_err(|error| Error::RpcFailedExt("Failed to get preflight report", error))?
+        } else {
+            rpc.health_check(())
+                .await
+                .map_err(|error| Error::RpcFailedExt("Failed to run health check", error))?
+        }
+        .into_inner();
+
+        let mut failed = false;
+        for check in &report.checks {
+            let status = match Status::from_i32(check.status) {
+                Some(Status::Pass) => "pass",
+                Some(Status::Warn) => "warn",
+                Some(Status::Fail) | None => {
+                    failed = true;
+                    "fail"
+                }
+            };
+            if check.details.is_empty() {
+                println!("{:21}: {}", check.name, status);
+            } else {
+                println!("{:21}: {} ({})", check.name, status, check.details);
+            }
+            if !check.remediation.is_empty() {
+                println!("{:21}  hint: {}", "", check.remediation);
+            }
+        }
+
+        if failed {
+            return Err(Error::CommandFailed("One or more health checks failed"));
+        }
+        Ok(())
+    }
+}
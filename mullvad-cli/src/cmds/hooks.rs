@@ -0,0 +1,130 @@
+use crate::{new_rpc_client, Command, Error, Result};
+use mullvad_management_interface::types;
+use mullvad_types::hooks::{Hook, HookEvent};
+use std::{convert::TryFrom, path::Path};
+
+pub struct Hooks;
+
+#[mullvad_management_interface::async_trait]
+impl Command for Hooks {
+    fn name(&self) -> &'static str {
+        "hooks"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static> {
+        let event_arg = clap::Arg::new("event")
+            .required(true)
+            .possible_values(["connected", "disconnected", "error"])
+            .help("Tunnel state that the program is run for");
+        let program_arg = clap::Arg::new("program").required(true).help(
+            "Program to run. It is run as the user that owns it, and must not be writable by \
+             anyone else",
+        );
+
+        clap::App::new(self.name())
+            .about(
+                "Manage programs that are run when the tunnel state changes. The new state is \
+                 described by MULLVAD_* environment variables",
+            )
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::App::new("add")
+                    .about("Run a program whenever a tunnel state is entered")
+                    .arg(event_arg.clone())
+                    .arg(program_arg.clone()),
+            )
+            .subcommand(
+                clap::App::new("remove")
+                    .about("Remove a hook")
+                    .arg(event_arg)
+                    .arg(program_arg),
+            )
+            .subcommand(clap::App::new("clear").about("Remove all hooks"))
+            .subcommand(clap::App::new("list").about("Display all hooks"))
+    }
+
+    async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
+        match matches.subcommand() {
+            Some(("add", matches)) => {
+                let hook = hook_from_matches(matches, true)?;
+                let mut hooks = self.get().await?;
+                if !hooks.contains(&hook) {
+                    hooks.push(hook);
+                }
+                self.set(hooks).await?;
+                println!("Added hook");
+                Ok(())
+            }
+            Some(("remove", matches)) => {
+                let hook = hook_from_matches(matches, false)?;
+                let mut hooks = self.get().await?;
+                let num_hooks = hooks.len();
+                hooks.retain(|existing| *existing != hook);
+                if hooks.len() == num_hooks {
+                    return Err(Error::InvalidCommand("No such hook exists"));
+                }
+                self.set(hooks).await?;
+                println!("Removed hook");
+                Ok(())
+            }
+            Some(("clear", _)) => {
+                self.set(vec![]).await?;
+                println!("Removed all hooks");
+                Ok(())
+            }
+            Some(("list", _)) => {
+                let hooks = self.get().await?;
+                if hooks.is_empty() {
+                    println!("No hooks");
+                }
+                for hook in hooks {
+                    println!("{:<12} {}", hook.event, hook.program.display());
+                }
+                Ok(())
+            }
+            _ => unreachable!("No hooks command given"),
+        }
+    }
+}
+
+impl Hooks {
+    async fn set(&self, hooks: Vec<Hook>) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_hooks(types::Hooks::from(&hooks[..])).await?;
+        Ok(())
+    }
+
+    async fn get(&self) -> Result<Vec<Hook>> {
+        let mut rpc = new_rpc_client().await?;
+        let hooks = rpc
+            .get_settings(())
+            .await?
+            .into_inner()
+            .hooks
+            .unwrap_or_default();
+        Ok(Vec::<Hook>::try_from(hooks).unwrap())
+    }
+}
+
+/// Returns the hook given on the command line. Unless `must_exist` is set, a program that no
+/// longer exists is accepted as is, so that its hook can be removed.
+fn hook_from_matches(matches: &clap::ArgMatches, must_exist: bool) -> Result<Hook> {
+    let event = match matches.value_of("event").unwrap() {
+        "connected" => HookEvent::Connected,
+        "disconnected" => HookEvent::Disconnected,
+        "error" => HookEvent::Error,
+        _ => unreachable!("invalid hook event"),
+    };
+    // The daemon does not share the working directory of the CLI
+    let program = Path::new(matches.value_of("program").unwrap());
+    let program = match program.canonicalize() {
+        Ok(program) => program,
+        Err(_) if !must_exist => program.to_path_buf(),
+        Err(_) => return Err(Error::InvalidCommand("The program does not exist")),
+    };
+    Ok(Hook {
+        event,
+        program,
+        added_by: None,
+    })
+}
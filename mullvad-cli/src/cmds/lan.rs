@@ -1,4 +1,4 @@
-use crate::{new_rpc_client, Command, Result};
+use crate::{format, new_rpc_client, Command, Result};
 
 pub struct Lan;
 
@@ -39,8 +39,9 @@ impl Command for Lan {
 impl Lan {
     async fn set(&self, allow_lan: bool) -> Result<()> {
         let mut rpc = new_rpc_client().await?;
-        rpc.set_allow_lan(allow_lan).await?;
+        let apply_plan = rpc.set_allow_lan(allow_lan).await?.into_inner();
         println!("Changed local network sharing setting");
+        format::print_apply_plan(&apply_plan);
         Ok(())
     }
 
@@ -0,0 +1,105 @@
+use crate::{new_rpc_client, Command, Result};
+use mullvad_management_interface::types::{log_line::Level, LogsSubscription};
+
+pub struct Logs;
+
+#[mullvad_management_interface::async_trait]
+impl Command for Logs {
+    fn name(&self) -> &'static str {
+        "logs"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static> {
+        clap::App::new(self.name())
+            .about("Show where the daemon logs are stored, or follow them live")
+            .arg(
+                clap::Arg::new("follow")
+                    .long("follow")
+                    .short('f')
+                    .help("Print log lines from the daemon as they are logged"),
+            )
+            .arg(
+                clap::Arg::new("level")
+                    .long("level")
+                    .takes_value(true)
+                    .default_value("info")
+                    .possible_values(["error", "warn", "info", "debug", "trace"])
+                    .help(
+                        "Least severe level to print. Lines below the log level of the daemon \
+                         are never printed",
+                    ),
+            )
+            .arg(
+                clap::Arg::new("subsystem")
+                    .long("subsystem")
+                    .takes_value(true)
+                    .multiple_occurrences(true)
+                    .help(
+                        "Only print lines from this module and its submodules, e.g. \
+                         talpid_core::firewall. Can be given more than once",
+                    ),
+            )
+    }
+
+    async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
+        if !matches.is_present("follow") {
+            match mullvad_paths::get_log_dir() {
+                Ok(log_dir) => println!("Daemon logs are stored in {}", log_dir.display()),
+                Err(_) => println!("Unable to find the daemon log directory"),
+            }
+            println!("Use --follow to print log lines as they are logged");
+            return Ok(());
+        }
+
+        let min_level = match matches.value_of("level").unwrap() {
+            "error" => Level::Error,
+            "warn" => Level::Warn,
+            "info" => Level::Info,
+            "debug" => Level::Debug,
+            _ => Level::Trace,
+        };
+        let subsystems = matches
+            .values_of("subsystem")
+            .map(|subsystems| subsystems.map(str::to_owned).collect())
+            .unwrap_or_default();
+
+        let mut rpc = new_rpc_client().await?;
+        let mut lines = rpc
+            .logs_subscribe(LogsSubscription {
+                min_level: i32::from(min_level),
+                subsystems,
+            })
+            .await?
+            .into_inner();
+        while let Some(line) = lines.message().await? {
+            let time = line
+                .time
+                .and_then(|time| {
+                    chrono::NaiveDateTime::from_timestamp_opt(time.seconds, time.nanos as u32)
+                })
+                .map(|time| {
+                    chrono::DateTime::<chrono::Utc>::from_utc(time, chrono::Utc)
+                        .with_timezone(&chrono::Local)
+                        .format("%Y-%m-%d %H:%M:%S%.3f")
+                        .to_string()
+                })
+                .unwrap_or_default();
+            let level = match Level::from_i32(line.level) {
+                Some(Level::Error) => "ERROR",
+                Some(Level::Warn) => "WARN",
+                Some(Level::Info) => "INFO",
+                Some(Level::Debug) => "DEBUG",
+                Some(Level::Trace) | None => "TRACE",
+            };
+            if line.connection_attempt.is_empty() {
+                println!("[{}][{}][{}] {}", time, line.subsystem, level, line.message);
+            } else {
+                println!(
+                    "[{}][{}][{}][attempt {}] {}",
+                    time, line.subsystem, level, line.connection_attempt, line.message
+                );
+            }
+        }
+        Ok(())
+    }
+}
@@ -10,6 +10,9 @@ pub use self::auto_connect::AutoConnect;
 mod beta_program;
 pub use self::beta_program::BetaProgram;
 
+mod blocked_protocols;
+pub use self::blocked_protocols::BlockedProtocols;
+
 mod block_when_disconnected;
 pub use self::block_when_disconnected::BlockWhenDisconnected;
 
@@ -19,27 +22,65 @@ pub use self::bridge::Bridge;
 mod connect;
 pub use self::connect::Connect;
 
+mod connection_stats;
+pub use self::connection_stats::ConnectionStats;
+
 mod disconnect;
 pub use self::disconnect::Disconnect;
 
 mod dns;
 pub use self::dns::Dns;
 
+mod error_state_policy;
+pub use self::error_state_policy::ErrorStatePolicy;
+
+mod forced_interface;
+pub use self::forced_interface::ForcedInterface;
+
+mod health;
+pub use self::health::Health;
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod hooks;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub use self::hooks::Hooks;
+
 mod lan;
 pub use self::lan::Lan;
 
+mod logs;
+pub use self::logs::Logs;
+
 mod obfuscation;
 pub use self::obfuscation::Obfuscation;
 
 mod reconnect;
 pub use self::reconnect::Reconnect;
 
+mod reconnect_limits;
+pub use self::reconnect_limits::ReconnectLimits;
+
+mod recovery_allowlist;
+pub use self::recovery_allowlist::RecoveryAllowlist;
+
 mod relay;
 pub use self::relay::Relay;
 
+mod profile;
+pub use self::profile::Profile;
+
+mod query_traffic;
+pub use self::query_traffic::QueryTraffic;
+
 mod reset;
 pub use self::reset::Reset;
 
+mod route_takeover;
+pub use self::route_takeover::RouteTakeover;
+
+mod runtime_config;
+pub use self::runtime_config::RuntimeConfig;
+
 #[cfg(any(target_os = "linux", windows))]
 mod split_tunnel;
 #[cfg(any(target_os = "linux", windows))]
@@ -48,12 +89,18 @@ pub use self::split_tunnel::SplitTunnel;
 mod status;
 pub use self::status::Status;
 
+mod system_state;
+pub use self::system_state::SystemState;
+
 mod tunnel;
 pub use self::tunnel::Tunnel;
 
 mod version;
 pub use self::version::Version;
 
+mod vpn_coexistence;
+pub use self::vpn_coexistence::VpnCoexistence;
+
 /// Returns a map of all available subcommands with their name as key.
 pub fn get_commands() -> HashMap<&'static str, Box<dyn Command>> {
     let commands: Vec<Box<dyn Command>> = vec![
@@ -61,20 +108,36 @@ pub fn get_commands() -> HashMap<&'static str, Box<dyn Command>> {
         Box::new(AutoConnect),
         Box::new(BetaProgram),
         Box::new(BlockWhenDisconnected),
+        Box::new(BlockedProtocols),
         Box::new(Bridge),
         Box::new(Connect),
+        Box::new(ConnectionStats),
         Box::new(Disconnect),
         Box::new(Dns),
+        Box::new(ErrorStatePolicy),
+        Box::new(ForcedInterface),
+        Box::new(Health),
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        Box::new(Hooks),
         Box::new(Reconnect),
+        Box::new(ReconnectLimits),
+        Box::new(RecoveryAllowlist),
         Box::new(Lan),
+        Box::new(Logs),
         Box::new(Obfuscation),
+        Box::new(Profile),
+        Box::new(QueryTraffic),
         Box::new(Relay),
         Box::new(Reset),
+        Box::new(RouteTakeover),
+        Box::new(RuntimeConfig),
         #[cfg(any(target_os = "linux", windows))]
         Box::new(SplitTunnel),
         Box::new(Status),
+        Box::new(SystemState),
         Box::new(Tunnel),
         Box::new(Version),
+        Box::new(VpnCoexistence),
     ];
     let mut map = HashMap::new();
     for cmd in commands {
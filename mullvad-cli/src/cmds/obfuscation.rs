@@ -1,4 +1,4 @@
-use crate::{new_rpc_client, Command, Result};
+use crate::{format, new_rpc_client, Command, Result};
 
 use mullvad_management_interface::{types as grpc_types, ManagementServiceClient};
 
@@ -46,6 +46,7 @@ impl Obfuscation {
                     "auto" => SelectedObfuscation::Auto,
                     "off" => SelectedObfuscation::Off,
                     "udp2tcp" => SelectedObfuscation::Udp2Tcp,
+                    "tls" => SelectedObfuscation::Tls,
                     _ => unreachable!("Unhandled obfuscator mode"),
                 };
                 Self::set_obfuscation_settings(&mut rpc, &settings).await?;
@@ -63,6 +64,17 @@ impl Obfuscation {
                 };
                 Self::set_obfuscation_settings(&mut rpc, &settings).await?;
             }
+            Some(("tls", settings_matches)) => {
+                let server_name: String = settings_matches.value_of_t_or_exit("server-name");
+                let mut rpc = new_rpc_client().await?;
+                let mut settings = Self::get_obfuscation_settings(&mut rpc).await?;
+                settings.tls.server_name = if server_name == "default" {
+                    None
+                } else {
+                    Some(server_name)
+                };
+                Self::set_obfuscation_settings(&mut rpc, &settings).await?;
+            }
             _ => unreachable!("unhandled command"),
         }
         Ok(())
@@ -76,6 +88,7 @@ impl Obfuscation {
             obfuscation_settings.selected_obfuscation
         );
         println!("udp2tcp settings: {}", obfuscation_settings.udp2tcp);
+        println!("TLS settings: {}", obfuscation_settings.tls);
         Ok(())
     }
 
@@ -98,7 +111,11 @@ impl Obfuscation {
         settings: &ObfuscationSettings,
     ) -> Result<()> {
         let grpc_settings: grpc_types::ObfuscationSettings = settings.into();
-        let _ = rpc.set_obfuscation_settings(grpc_settings).await?;
+        let apply_plan = rpc
+            .set_obfuscation_settings(grpc_settings)
+            .await?
+            .into_inner();
+        format::print_apply_plan(&apply_plan);
         Ok(())
     }
 }
@@ -116,7 +133,7 @@ fn create_obfuscation_set_subcommand() -> clap::App<'static> {
                     )
                     .required(true)
                     .index(1)
-                    .possible_values(["auto", "off", "udp2tcp"]),
+                    .possible_values(["auto", "off", "udp2tcp", "tls"]),
             ),
         )
         .subcommand(
@@ -130,6 +147,20 @@ fn create_obfuscation_set_subcommand() -> clap::App<'static> {
                         .takes_value(true),
                 ),
         )
+        .subcommand(
+            clap::App::new("tls")
+                .about("Specifies the config for the TLS obfuscator")
+                .setting(clap::AppSettings::ArgRequiredElseHelp)
+                .arg(
+                    clap::Arg::new("server-name")
+                        .help(
+                            "Server name to send in the TLS handshake. Either 'default' or \
+                            a domain name",
+                        )
+                        .long("server-name")
+                        .takes_value(true),
+                ),
+        )
 }
 
 fn create_obfuscation_get_subcommand() -> clap::App<'static> {
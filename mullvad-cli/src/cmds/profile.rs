@@ -0,0 +1,93 @@
+use crate::{new_rpc_client, Command, Result};
+use mullvad_types::relay_constraints::RelaySettings;
+use std::convert::TryFrom;
+
+pub struct Profile;
+
+#[mullvad_management_interface::async_trait]
+impl Command for Profile {
+    fn name(&self) -> &'static str {
+        "profile"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static> {
+        clap::App::new(self.name())
+            .about(
+                "Save the relay, obfuscation, DNS and split tunneling settings as named profiles, \
+                 and switch between them with a single reconnect at most",
+            )
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::App::new("save")
+                    .about("Save the current settings as a profile, replacing any with that name")
+                    .arg(clap::Arg::new("name").required(true)),
+            )
+            .subcommand(
+                clap::App::new("apply")
+                    .about("Apply the settings of a profile")
+                    .arg(clap::Arg::new("name").required(true)),
+            )
+            .subcommand(
+                clap::App::new("remove")
+                    .about("Remove a profile")
+                    .arg(clap::Arg::new("name").required(true)),
+            )
+            .subcommand(clap::App::new("list").about("List the saved profiles"))
+    }
+
+    async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
+        match matches.subcommand() {
+            Some(("save", matches)) => self.save(name_arg(matches)).await,
+            Some(("apply", matches)) => self.apply(name_arg(matches)).await,
+            Some(("remove", matches)) => self.remove(name_arg(matches)).await,
+            Some(("list", _)) => self.list().await,
+            _ => unreachable!("No profile command given"),
+        }
+    }
+}
+
+fn name_arg(matches: &clap::ArgMatches) -> String {
+    matches.value_of("name").expect("missing name").to_owned()
+}
+
+impl Profile {
+    async fn save(&self, name: String) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.save_profile(name.clone()).await?;
+        println!("Saved the current settings as profile \"{}\"", name);
+        Ok(())
+    }
+
+    async fn apply(&self, name: String) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.apply_profile(name.clone()).await?;
+        println!("Applied profile \"{}\"", name);
+        Ok(())
+    }
+
+    async fn remove(&self, name: String) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.remove_profile(name.clone()).await?;
+        println!("Removed profile \"{}\"", name);
+        Ok(())
+    }
+
+    async fn list(&self) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let profiles = rpc.list_profiles(()).await?.into_inner().profiles;
+        if profiles.is_empty() {
+            println!("No profiles have been saved");
+            return Ok(());
+        }
+        for profile in profiles {
+            println!("{}", profile.name);
+            if let Some(relay_settings) = profile
+                .relay_settings
+                .and_then(|settings| RelaySettings::try_from(settings).ok())
+            {
+                println!("\tRelay constraints: {}", relay_settings);
+            }
+        }
+        Ok(())
+    }
+}
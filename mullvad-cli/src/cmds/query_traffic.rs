@@ -0,0 +1,86 @@
+use crate::{new_rpc_client, Command, Error, Result};
+use mullvad_management_interface::types::{self, traffic_verdict};
+use std::net::IpAddr;
+
+pub struct QueryTraffic;
+
+#[mullvad_management_interface::async_trait]
+impl Command for QueryTraffic {
+    fn name(&self) -> &'static str {
+        "query-traffic"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static> {
+        clap::App::new(self.name())
+            .about(
+                "Show whether traffic to a destination would go through the tunnel, be blocked, \
+                 or bypass the tunnel",
+            )
+            .arg(
+                clap::Arg::new("destination")
+                    .help("The IP address of the destination")
+                    .required(true),
+            )
+            .arg(
+                clap::Arg::new("port")
+                    .help("The destination port")
+                    .required(true),
+            )
+            .arg(
+                clap::Arg::new("protocol")
+                    .long("protocol")
+                    .takes_value(true)
+                    .default_value("tcp")
+                    .possible_values(["tcp", "udp"]),
+            )
+            .arg(clap::Arg::new("app").long("app").takes_value(true).help(
+                "The app that sends the traffic. This is the path of the executable on \
+                         Windows and the process ID on Linux",
+            ))
+    }
+
+    async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
+        let destination = matches.value_of_t_or_exit::<IpAddr>("destination");
+        let port = matches.value_of_t_or_exit::<u16>("port");
+        let protocol = match matches.value_of("protocol").unwrap() {
+            "udp" => types::TransportProtocol::Udp,
+            _ => types::TransportProtocol::Tcp,
+        };
+        let query = types::TrafficQuery {
+            destination: destination.to_string(),
+            port: u32::from(port),
+            protocol: protocol as i32,
+            app: matches.value_of("app").unwrap_or_default().to_owned(),
+        };
+
+        let mut rpc = new_rpc_client().await?;
+        let verdict = rpc
+            .query_traffic(query)
+            .await
+            .map_err(|error| Error::RpcFailedExt("Failed to query traffic", error))?
+            .into_inner();
+
+        let outcome = match traffic_verdict::Outcome::from_i32(verdict.outcome) {
+            Some(traffic_verdict::Outcome::Tunneled) => "tunneled",
+            Some(traffic_verdict::Outcome::Blocked) => "blocked",
+            Some(traffic_verdict::Outcome::Bypassed) => "bypassed",
+            None => "unknown",
+        };
+        println!("Outcome: {}", outcome);
+        println!("Reason: {}", verdict.explanation);
+        if let Some(route) = verdict.route {
+            let mut via = Vec::new();
+            if !route.gateway.is_empty() {
+                via.push(route.gateway);
+            }
+            if !route.interface.is_empty() {
+                via.push(format!("dev {}", route.interface));
+            }
+            if route.follows_default_route {
+                via.push("default route".to_owned());
+            }
+            println!("Route: {} via {}", route.destination, via.join(" "));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+use crate::{new_rpc_client, Command, Result};
+use mullvad_management_interface::types;
+
+pub struct ReconnectLimits;
+
+#[mullvad_management_interface::async_trait]
+impl Command for ReconnectLimits {
+    fn name(&self) -> &'static str {
+        "reconnect-limits"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static> {
+        clap::App::new(self.name())
+            .about(
+                "Limit how many times in a row to reconnect after failed attempts. Once a limit \
+                 is reached, traffic is blocked until you connect again or the network changes",
+            )
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::App::new("set")
+                    .about("Set the limits. Limits that are not given are unlimited")
+                    .arg(
+                        clap::Arg::new("start-failures")
+                            .long("start-failures")
+                            .takes_value(true)
+                            .help("Attempts after the tunnel failed to start"),
+                    )
+                    .arg(
+                        clap::Arg::new("timeouts")
+                            .long("timeouts")
+                            .takes_value(true)
+                            .help("Attempts after the tunnel was not established in time"),
+                    )
+                    .arg(
+                        clap::Arg::new("bridge-failures")
+                            .long("bridge-failures")
+                            .takes_value(true)
+                            .help("Attempts after the bridge stopped working"),
+                    ),
+            )
+            .subcommand(clap::App::new("clear").about("Reconnect without any limits"))
+            .subcommand(clap::App::new("get").about("Display the reconnect limits"))
+    }
+
+    async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
+        match matches.subcommand() {
+            Some(("set", matches)) => {
+                let limit = |name| {
+                    if matches.is_present(name) {
+                        Some(matches.value_of_t_or_exit::<u32>(name))
+                    } else {
+                        None
+                    }
+                };
+                self.set(types::ReconnectLimits {
+                    start_failures: limit("start-failures"),
+                    timeouts: limit("timeouts"),
+                    bridge_failures: limit("bridge-failures"),
+                })
+                .await
+            }
+            Some(("clear", _)) => self.set(types::ReconnectLimits::default()).await,
+            Some(("get", _)) => self.get().await,
+            _ => unreachable!("No reconnect-limits command given"),
+        }
+    }
+}
+
+impl ReconnectLimits {
+    async fn set(&self, reconnect_limits: types::ReconnectLimits) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_reconnect_limits(reconnect_limits).await?;
+        println!("Changed reconnect limits");
+        Ok(())
+    }
+
+    async fn get(&self) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let reconnect_limits = rpc
+            .get_settings(())
+            .await?
+            .into_inner()
+            .reconnect_limits
+            .unwrap_or_default();
+        println!(
+            "Reconnect limits: {}",
+            talpid_types::tunnel::ReconnectLimits::from(reconnect_limits)
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+use crate::{new_rpc_client, Command, Result};
+use ipnetwork::IpNetwork;
+use mullvad_management_interface::types;
+
+pub struct RecoveryAllowlist;
+
+#[mullvad_management_interface::async_trait]
+impl Command for RecoveryAllowlist {
+    fn name(&self) -> &'static str {
+        "recovery-allowlist"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static> {
+        clap::App::new(self.name())
+            .about(
+                "Control which networks can be reached while the app is blocking traffic due to \
+                 an error. This weakens the protection offered by the error state",
+            )
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::App::new("set")
+                    .about("Set the networks that are allowed in the error state")
+                    .arg(
+                        clap::Arg::new("networks")
+                            .multiple_occurrences(true)
+                            .help("One or more networks, e.g. 192.168.1.0/24 or 2001:db8::/32")
+                            .required(true),
+                    ),
+            )
+            .subcommand(
+                clap::App::new("clear").about("Block all networks in the error state again"),
+            )
+            .subcommand(
+                clap::App::new("get")
+                    .about("Display the networks that are allowed in the error state"),
+            )
+    }
+
+    async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
+        match matches.subcommand() {
+            Some(("set", matches)) => {
+                let networks = matches
+                    .values_of_t::<IpNetwork>("networks")
+                    .unwrap_or_else(|e| e.exit());
+                self.set(networks).await
+            }
+            Some(("clear", _)) => self.set(vec![]).await,
+            Some(("get", _)) => self.get().await,
+            _ => unreachable!("No recovery-allowlist command given"),
+        }
+    }
+}
+
+impl RecoveryAllowlist {
+    async fn set(&self, networks: Vec<IpNetwork>) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_recovery_allowlist(types::RecoveryAllowlist::from(&networks[..]))
+            .await?;
+        println!("Changed recovery allowlist");
+        Ok(())
+    }
+
+    async fn get(&self) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let networks = rpc
+            .get_settings(())
+            .await?
+            .into_inner()
+            .recovery_allowlist
+            .map(|allowlist| allowlist.networks)
+            .unwrap_or_default();
+        if networks.is_empty() {
+            println!("All networks are blocked in the error state");
+        } else {
+            println!("Networks allowed in the error state:");
+            for network in networks {
+                println!("{}", network);
+            }
+        }
+        Ok(())
+    }
+}
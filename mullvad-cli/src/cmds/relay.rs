@@ -66,6 +66,14 @@ impl Command for Relay {
                                         .long("v6-gateway")
                                         .takes_value(true),
                                 )
+                                .arg(
+                                    clap::Arg::new("preshared-key")
+                                        .help("Read a base64 encoded preshared key from standard \
+                                               input, after the private key. The daemon does not \
+                                               save it, so it must be set again after the daemon \
+                                               restarts")
+                                        .long("preshared-key"),
+                                )
                             )
                             .subcommand(clap::App::new("openvpn")
                                 .arg(
@@ -95,6 +103,23 @@ impl Command for Relay {
                                         .default_value("udp")
                                         .possible_values(["udp", "tcp"]),
                                 )
+                                .arg(
+                                    clap::Arg::new("client-cert")
+                                        .help("Path to a PEM encoded client certificate. The \
+                                               daemon does not save it, so it must be set again \
+                                               after the daemon restarts")
+                                        .long("client-cert")
+                                        .takes_value(true)
+                                        .requires("client-key"),
+                                )
+                                .arg(
+                                    clap::Arg::new("client-key")
+                                        .help("Path to the PEM encoded private key of the client \
+                                               certificate")
+                                        .long("client-key")
+                                        .takes_value(true)
+                                        .requires("client-cert"),
+                                )
                             )
                     )
                     .subcommand(
@@ -201,6 +226,51 @@ impl Command for Relay {
                 clap::App::new("update")
                     .about("Update the list of available countries and cities"),
             )
+            .subcommand(
+                clap::App::new("query")
+                    .about("List the relays that match the given filters, optionally with the \
+                           estimated latency to some of them")
+                    .arg(
+                        clap::Arg::new("country")
+                            .help("Two letter country code of the relays")
+                            .long("country")
+                            .takes_value(true)
+                            .validator(location::country_code_validator),
+                    )
+                    .arg(
+                        clap::Arg::new("city")
+                            .help("Three letter city code of the relays")
+                            .long("city")
+                            .takes_value(true)
+                            .requires("country")
+                            .validator(location::city_code_validator),
+                    )
+                    .arg(
+                        clap::Arg::new("ownership")
+                            .help("Ownership of the relays")
+                            .long("ownership")
+                            .takes_value(true)
+                            .default_value("any")
+                            .possible_values(["any", "owned", "rented"]),
+                    )
+                    .arg(
+                        clap::Arg::new("tunnel protocol")
+                            .help("Tunnel protocol that the relays must support")
+                            .long("tunnel-protocol")
+                            .takes_value(true)
+                            .default_value("any")
+                            .possible_values(["any", "wireguard", "openvpn"]),
+                    )
+                    .arg(
+                        clap::Arg::new("probe")
+                            .help("Number of matching relays to estimate the latency to. \
+                                  At most 10 relays are probed")
+                            .long("probe")
+                            .takes_value(true)
+                            .default_value("0")
+                            .validator(str::parse::<u32>),
+                    ),
+            )
     }
 
     async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
@@ -212,6 +282,8 @@ impl Command for Relay {
             self.list().await
         } else if matches.subcommand_matches("update").is_some() {
             self.update().await
+        } else if let Some(query_matches) = matches.subcommand_matches("query") {
+            self.query(query_matches).await
         } else {
             unreachable!("No relay command given");
         }
@@ -275,6 +347,13 @@ impl Relay {
         let protocol: String = matches.value_of_t_or_exit("protocol");
 
         let protocol = Self::validate_transport_protocol(&protocol);
+        let client_certificate = matches.value_of("client-cert").map(|cert_path| {
+            let key_path = matches.value_of("client-key").unwrap();
+            types::connection_config::openvpn_config::ClientCertificate {
+                certificate: Self::read_pem_file(cert_path),
+                private_key: Self::read_pem_file(key_path),
+            }
+        });
 
         types::CustomRelaySettings {
             host,
@@ -286,6 +365,7 @@ impl Relay {
                         protocol: protocol as i32,
                         username,
                         password,
+                        client_certificate,
                     },
                 )),
             }),
@@ -315,6 +395,20 @@ impl Relay {
         }
         let private_key = Self::validate_wireguard_key(&private_key_str);
         let peer_public_key = Self::validate_wireguard_key(&peer_key_str);
+        let preshared_key = if matches.is_present("preshared-key") {
+            let mut preshared_key_str = String::new();
+            println!("Reading preshared key from standard input");
+            if let Err(error) = io::stdin().lock().read_line(&mut preshared_key_str) {
+                eprintln!(
+                    "Failed to read preshared key from standard input: {}",
+                    error
+                );
+                std::process::exit(1);
+            }
+            Self::validate_wireguard_key(&preshared_key_str).to_vec()
+        } else {
+            vec![]
+        };
 
         types::CustomRelaySettings {
             host,
@@ -336,18 +430,27 @@ impl Relay {
                                 .collect(),
                             endpoint: SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port)
                                 .to_string(),
+                            preshared_key,
                         }),
                         ipv4_gateway: ipv4_gateway.to_string(),
                         ipv6_gateway: ipv6_gateway
                             .as_ref()
                             .map(|addr| addr.to_string())
                             .unwrap_or_default(),
+                        exit_peer: None,
                     },
                 )),
             }),
         }
     }
 
+    fn read_pem_file(path: &str) -> String {
+        std::fs::read_to_string(path).unwrap_or_else(|e| {
+            eprintln!("Failed to read {}: {}", path, e);
+            std::process::exit(1);
+        })
+    }
+
     fn validate_wireguard_key(key_str: &str) -> [u8; 32] {
         let key_bytes = base64::decode(key_str.trim()).unwrap_or_else(|e| {
             eprintln!("Failed to decode wireguard key: {}", e);
@@ -710,16 +813,13 @@ impl Relay {
                     } else {
                         "rented"
                     };
-                    let mut addresses = vec![&relay.ipv4_addr_in];
-                    if !relay.ipv6_addr_in.is_empty() {
-                        addresses.push(&relay.ipv6_addr_in);
-                    }
+                    let addresses = [&relay.ipv4_addr_in, &relay.ipv6_addr_in]
+                        .into_iter()
+                        .filter(|address| !address.is_empty())
+                        .join(", ");
                     println!(
                         "\t\t{} ({}) - {}, hosted by {} ({ownership})",
-                        relay.hostname,
-                        addresses.iter().join(", "),
-                        support_msg,
-                        relay.provider
+                        relay.hostname, addresses, support_msg, relay.provider
                     );
                 }
             }
@@ -734,6 +834,71 @@ impl Relay {
         Ok(())
     }
 
+    async fn query(&self, matches: &clap::ArgMatches) -> Result<()> {
+        let location = types::RelayLocation {
+            country: matches
+                .value_of("country")
+                .filter(|country| *country != "any")
+                .unwrap_or_default()
+                .to_lowercase(),
+            city: matches.value_of("city").unwrap_or_default().to_lowercase(),
+            ..Default::default()
+        };
+        let ownership = parse_ownership_constraint(matches.value_of("ownership").unwrap());
+        let tunnel_type = match matches.value_of("tunnel protocol").unwrap() {
+            "wireguard" => Some(types::TunnelType::Wireguard),
+            "openvpn" => Some(types::TunnelType::Openvpn),
+            "any" => None,
+            _ => unreachable!(),
+        };
+        let probe_count: u32 = matches.value_of_t_or_exit("probe");
+
+        let mut rpc = new_rpc_client().await?;
+        let result = rpc
+            .query_relays(types::RelayQuery {
+                location: Some(location),
+                ownership: ownership as i32,
+                tunnel_type: tunnel_type.map(|tunnel_type| types::TunnelTypeConstraint {
+                    tunnel_type: tunnel_type as i32,
+                }),
+                probe_count,
+            })
+            .await
+            .map_err(|error| Error::RpcFailedExt("Failed to query relays", error))?
+            .into_inner();
+
+        if result.relays.is_empty() {
+            println!("No matching relays");
+        }
+        for relay_match in result.relays {
+            let relay = relay_match.relay.unwrap();
+            let support_msg = match relay.endpoint_type {
+                i if i == i32::from(types::relay::RelayType::Openvpn) => "OpenVPN",
+                i if i == i32::from(types::relay::RelayType::Wireguard) => "WireGuard",
+                _ => "bridge",
+            };
+            let ownership = if relay.owned {
+                "Mullvad-owned"
+            } else {
+                "rented"
+            };
+            let location = relay
+                .location
+                .map(|location| format!("{}, {}", location.city, location.country))
+                .unwrap_or_default();
+            let status = match relay_match.latency_ms {
+                _ if !relay.active => " - inactive".to_string(),
+                Some(latency_ms) => format!(" - {} ms", latency_ms),
+                None => String::new(),
+            };
+            println!(
+                "{} ({}) - {}, hosted by {} ({ownership}){}",
+                relay.hostname, location, support_msg, relay.provider, status
+            );
+        }
+        Ok(())
+    }
+
     async fn get_filtered_relays() -> Result<Vec<types::RelayListCountry>> {
         let mut rpc = new_rpc_client().await?;
         let relay_list = rpc
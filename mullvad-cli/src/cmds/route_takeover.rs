@@ -0,0 +1,69 @@
+use crate::{new_rpc_client, Command, Result};
+use mullvad_management_interface::types;
+use std::convert::TryFrom;
+use talpid_types::net::route_takeover::RouteTakeoverPolicy;
+
+pub struct RouteTakeover;
+
+#[mullvad_management_interface::async_trait]
+impl Command for RouteTakeover {
+    fn name(&self) -> &'static str {
+        "route-takeover"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static> {
+        clap::App::new(self.name())
+            .about(
+                "Control what happens when another VPN takes over the default route while \
+                 connected",
+            )
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::App::new("set").about("Change the policy").arg(
+                    clap::Arg::new("policy")
+                        .required(true)
+                        .possible_values(["warn", "reclaim", "yield"])
+                        .help(
+                            "warn: only report the other VPN. reclaim: reconnect so that the \
+                             routes of the tunnel take precedence. yield: block all traffic \
+                             until you connect again",
+                        ),
+                ),
+            )
+            .subcommand(clap::App::new("get").about("Display the current policy"))
+    }
+
+    async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
+        match matches.subcommand() {
+            Some(("set", matches)) => {
+                let policy = matches.value_of_t_or_exit::<RouteTakeoverPolicy>("policy");
+                self.set(policy).await
+            }
+            Some(("get", _)) => self.get().await,
+            _ => unreachable!("No route-takeover command given"),
+        }
+    }
+}
+
+impl RouteTakeover {
+    async fn set(&self, policy: RouteTakeoverPolicy) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_route_takeover_policy(types::RouteTakeoverPolicy::from(policy))
+            .await?;
+        println!("Changed route takeover policy");
+        Ok(())
+    }
+
+    async fn get(&self) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let policy = rpc
+            .get_settings(())
+            .await?
+            .into_inner()
+            .route_takeover_policy
+            .unwrap_or_default();
+        let policy = RouteTakeoverPolicy::try_from(policy).unwrap();
+        println!("Route takeover policy: {}", policy);
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+use crate::{new_rpc_client, Command, Error, Result};
+use mullvad_management_interface::types::RuntimeConfig as RuntimeConfigProto;
+
+pub struct RuntimeConfig;
+
+#[mullvad_management_interface::async_trait]
+impl Command for RuntimeConfig {
+    fn name(&self) -> &'static str {
+        "runtime-config"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static> {
+        clap::App::new(self.name())
+            .about(
+                "Change log levels and experimental features without restarting the daemon. \
+                 Changes are lost when the daemon restarts",
+            )
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(clap::App::new("get").about("Display the current configuration"))
+            .subcommand(
+                clap::App::new("set-log-filter")
+                    .about("Override the log level of specific modules")
+                    .arg(clap::Arg::new("filter").required(true).help(
+                        "Comma separated `module=level` directives, where a directive without \
+                         a module applies to all modules, e.g. `info,talpid_core::firewall=trace`",
+                    )),
+            )
+            .subcommand(
+                clap::App::new("clear-log-filter")
+                    .about("Log at the level that the daemon was started with"),
+            )
+            .subcommand(
+                clap::App::new("enable")
+                    .about("Enable an experimental feature")
+                    .arg(clap::Arg::new("feature").required(true)),
+            )
+            .subcommand(
+                clap::App::new("disable")
+                    .about("Disable an experimental feature")
+                    .arg(clap::Arg::new("feature").required(true)),
+            )
+    }
+
+    async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
+        match matches.subcommand() {
+            Some(("get", _)) => self.get().await,
+            Some(("set-log-filter", matches)) => {
+                let filter = matches.value_of("filter").unwrap().to_owned();
+                self.update(|config| config.log_filter = filter).await
+            }
+            Some(("clear-log-filter", _)) => self.update(|config| config.log_filter.clear()).await,
+            Some(("enable", matches)) => {
+                let feature = feature_arg(matches);
+                self.update(|config| {
+                    if !config.enabled_features.contains(&feature) {
+                        config.enabled_features.push(feature);
+                    }
+                })
+                .await
+            }
+            Some(("disable", matches)) => {
+                let feature = feature_arg(matches);
+                self.update(|config| config.enabled_features.retain(|f| *f != feature))
+                    .await
+            }
+            _ => unreachable!("No runtime-config command given"),
+        }
+    }
+}
+
+fn feature_arg(matches: &clap::ArgMatches) -> String {
+    matches
+        .value_of("feature")
+        .expect("missing feature")
+        .to_owned()
+}
+
+impl RuntimeConfig {
+    async fn get(&self) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let config = rpc.get_runtime_config(()).await?.into_inner();
+        if config.log_filter.is_empty() {
+            println!("Log filter: none");
+        } else {
+            println!("Log filter: {}", config.log_filter);
+        }
+        println!("Features:");
+        for feature in &config.available_features {
+            let state = if config.enabled_features.contains(feature) {
+                "enabled"
+            } else {
+                "disabled"
+            };
+            println!("\t{:26}: {}", feature, state);
+        }
+        Ok(())
+    }
+
+    async fn update(&self, modify: impl FnOnce(&mut RuntimeConfigProto)) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let mut config = rpc.get_runtime_config(()).await?.into_inner();
+        modify(&mut config);
+        rpc.set_runtime_config(config).await.map_err(|error| {
+            Error::RpcFailedExt("Failed to update runtime configuration", error)
+        })?;
+        println!("Updated runtime configuration");
+        Ok(())
+    }
+}
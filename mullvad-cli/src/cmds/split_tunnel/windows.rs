@@ -26,6 +26,10 @@ impl Command for SplitTunnel {
             )
             .subcommand(clap::App::new("get").about("Display the split tunnel status"))
             .subcommand(create_pid_subcommand())
+            .subcommand(clap::App::new("reinstall-driver").about(
+                "Reinstall the split tunnel driver. Use this if split tunneling fails because \
+                     the driver is incompatible with the app",
+            ))
     }
 
     async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
@@ -33,6 +37,7 @@ impl Command for SplitTunnel {
             Some(("app", matches)) => Self::handle_app_subcommand(matches).await,
             Some(("pid", matches)) => Self::handle_pid_subcommand(matches).await,
             Some(("get", _)) => self.get().await,
+            Some(("reinstall-driver", _)) => self.reinstall_driver().await,
             Some(("set", matches)) => {
                 let enabled = matches.value_of("policy").expect("missing policy");
                 self.set(enabled == "on").await
@@ -49,7 +54,11 @@ fn create_app_subcommand() -> clap::App<'static> {
         .about("Manage applications to exclude from the tunnel")
         .setting(clap::AppSettings::SubcommandRequiredElseHelp)
         .subcommand(clap::App::new("list"))
-        .subcommand(clap::App::new("add").arg(clap::Arg::new("path").required(true)))
+        .subcommand(
+            clap::App::new("add")
+                .about("Exclude an application, all applications in a directory, or all applications matching a glob pattern")
+                .arg(clap::Arg::new("path").required(true)),
+        )
         .subcommand(clap::App::new("remove").arg(clap::Arg::new("path").required(true)))
         .subcommand(clap::App::new("clear"))
 }
@@ -139,6 +148,13 @@ impl SplitTunnel {
         Ok(())
     }
 
+    async fn reinstall_driver(&self) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.reinstall_split_tunnel_driver(()).await?;
+        println!("Reinstalled split tunnel driver");
+        Ok(())
+    }
+
     async fn get(&self) -> Result<()> {
         let mut rpc = new_rpc_client().await?;
         let enabled = rpc
@@ -1,7 +1,9 @@
 use crate::{format, new_rpc_client, Command, Error, Result};
 use mullvad_management_interface::{
-    types::daemon_event::Event as EventType, ManagementServiceClient,
+    types::{daemon_event::Event as EventType, Timestamp},
+    ManagementServiceClient,
 };
+use mullvad_types::states::ProtectionGapReport;
 
 pub struct Status;
 
@@ -31,7 +33,18 @@ impl Command for Status {
                     .global(true)
                     .help("Enables debug output"),
             )
-            .subcommand(clap::App::new("listen").about("Listen for VPN tunnel state changes"))
+            .subcommand(
+                clap::App::new("listen")
+                    .about("Listen for VPN tunnel state changes")
+                    .arg(clap::Arg::new("json").long("json").help(
+                        "Print each tunnel state change as a JSON document on its own line, in \
+                         a versioned format that is meant for scripts",
+                    )),
+            )
+            .subcommand(clap::App::new("protection-gap").about(
+                "Show whether the network may have been unprotected because the daemon or the \
+                 system stopped while the tunnel was secured",
+            ))
     }
 
     async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
@@ -40,6 +53,16 @@ impl Command for Status {
         let show_full_location = matches.is_present("location");
 
         let mut rpc = new_rpc_client().await?;
+
+        if matches.subcommand_matches("protection-gap").is_some() {
+            return print_protection_gap(&mut rpc).await;
+        }
+        if let Some(listen_matches) = matches.subcommand_matches("listen") {
+            if listen_matches.is_present("json") {
+                return print_transitions_json(&mut rpc).await;
+            }
+        }
+
         let state = rpc.get_tunnel_state(()).await?.into_inner();
 
         if debug {
@@ -47,6 +70,7 @@ impl Command for Status {
         } else {
             format::print_state(&state, verbose);
         }
+        print_protection_pause(&mut rpc).await?;
 
         if show_full_location {
             print_location(&mut rpc).await?;
@@ -99,6 +123,45 @@ impl Command for Status {
                             println!("Remove device event: {:#?}", device);
                         }
                     }
+                    EventType::FirewallConflicts(conflicts) => {
+                        if debug {
+                            println!("Firewall conflicts: {:#?}", conflicts);
+                        } else {
+                            format::print_firewall_conflicts(&conflicts);
+                        }
+                    }
+                    EventType::CompetingVpn(competing_vpn) => {
+                        if debug {
+                            println!("Competing VPN: {:#?}", competing_vpn);
+                        } else {
+                            format::print_competing_vpn(&competing_vpn);
+                        }
+                    }
+                    EventType::PerformanceWarning(warning) => {
+                        if debug {
+                            println!("Performance warning: {:#?}", warning);
+                        } else {
+                            format::print_performance_warning(&warning);
+                        }
+                    }
+                    EventType::ClockSkew(skew) => {
+                        if debug {
+                            println!("Clock skew: {:#?}", skew);
+                        } else {
+                            format::print_clock_skew(&skew);
+                        }
+                    }
+                    EventType::AccountExpiry(account_data) => {
+                        if debug {
+                            println!("Account expiry: {:#?}", account_data);
+                        }
+                    }
+                    EventType::SettingsChanged(changed) => {
+                        println!("Settings changed: {}", changed.changed_fields.join(", "));
+                        if let Some(apply_plan) = &changed.apply_plan {
+                            format::print_apply_plan(apply_plan);
+                        }
+                    }
                 }
             }
         }
@@ -107,6 +170,56 @@ impl Command for Status {
     }
 }
 
+async fn print_transitions_json(rpc: &mut ManagementServiceClient) -> Result<()> {
+    let mut transitions = rpc
+        .tunnel_state_transitions_listen(())
+        .await
+        .map_err(|error| Error::RpcFailedExt("Failed to listen for tunnel states", error))?
+        .into_inner();
+    while let Some(transition) = transitions.message().await? {
+        println!("{}", transition);
+    }
+    Ok(())
+}
+
+async fn print_protection_gap(rpc: &mut ManagementServiceClient) -> Result<()> {
+    let report = match rpc.get_protection_gap_report(()).await {
+        Ok(response) => response.into_inner(),
+        Err(status) => {
+            if status.code() == mullvad_management_interface::Code::NotFound {
+                println!("The network was not left unprotected when the daemon last stopped");
+                return Ok(());
+            } else {
+                return Err(Error::RpcFailed(status));
+            }
+        }
+    };
+
+    let report = ProtectionGapReport::try_from(report).unwrap();
+    println!("{}", report);
+    Ok(())
+}
+
+async fn print_protection_pause(rpc: &mut ManagementServiceClient) -> Result<()> {
+    match rpc.get_protection_pause(()).await {
+        Ok(resume_at) => {
+            println!(
+                "Protection is paused until {}",
+                format_timestamp(&resume_at.into_inner())
+            );
+            Ok(())
+        }
+        Err(status) if status.code() == mullvad_management_interface::Code::NotFound => Ok(()),
+        Err(status) => Err(Error::RpcFailed(status)),
+    }
+}
+
+fn format_timestamp(timestamp: &Timestamp) -> String {
+    let ndt = chrono::NaiveDateTime::from_timestamp(timestamp.seconds, timestamp.nanos as u32);
+    let utc = chrono::DateTime::<chrono::Utc>::from_utc(ndt, chrono::Utc);
+    utc.with_timezone(&chrono::Local).to_string()
+}
+
 async fn print_location(rpc: &mut ManagementServiceClient) -> Result<()> {
     let location = rpc.get_current_location(()).await;
     let location = match location {
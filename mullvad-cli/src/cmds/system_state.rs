@@ -0,0 +1,28 @@
+use crate::{new_rpc_client, Command, Error, Result};
+
+pub struct SystemState;
+
+#[mullvad_management_interface::async_trait]
+impl Command for SystemState {
+    fn name(&self) -> &'static str {
+        "system-state"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static> {
+        clap::App::new(self.name()).about(
+            "Print the firewall policy, routes and DNS settings owned by the daemon as a JSON \
+             document",
+        )
+    }
+
+    async fn run(&self, _: &clap::ArgMatches) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let state = rpc
+            .get_system_state(())
+            .await
+            .map_err(|error| Error::RpcFailedExt("Failed to get system state", error))?
+            .into_inner();
+        println!("{}", state);
+        Ok(())
+    }
+}
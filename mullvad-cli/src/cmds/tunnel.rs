@@ -1,4 +1,5 @@
 use crate::{new_rpc_client, Command, Error, Result};
+use ipnetwork::IpNetwork;
 use mullvad_management_interface::types::{self, Timestamp, TunnelOptions};
 use mullvad_types::wireguard::DEFAULT_ROTATION_INTERVAL;
 use std::{convert::TryFrom, time::Duration};
@@ -18,6 +19,7 @@ impl Command for Tunnel {
             .subcommand(create_openvpn_subcommand())
             .subcommand(create_wireguard_subcommand())
             .subcommand(create_ipv6_subcommand())
+            .subcommand(create_ipv4_subcommand())
     }
 
     async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
@@ -25,6 +27,7 @@ impl Command for Tunnel {
             Some(("openvpn", openvpn_matches)) => Self::handle_openvpn_cmd(openvpn_matches).await,
             Some(("wireguard", wg_matches)) => Self::handle_wireguard_cmd(wg_matches).await,
             Some(("ipv6", ipv6_matches)) => Self::handle_ipv6_cmd(ipv6_matches).await,
+            Some(("ipv4", ipv4_matches)) => Self::handle_ipv4_cmd(ipv4_matches).await,
             _ => {
                 unreachable!("unhandled comand");
             }
@@ -38,6 +41,9 @@ fn create_wireguard_subcommand() -> clap::App<'static> {
         .setting(clap::AppSettings::SubcommandRequiredElseHelp)
         .subcommand(create_wireguard_mtu_subcommand())
         .subcommand(create_wireguard_quantum_resistant_tunnel_subcommand())
+        .subcommand(create_wireguard_traffic_shaping_subcommand())
+        .subcommand(create_wireguard_bandwidth_limit_subcommand())
+        .subcommand(create_wireguard_allowed_ips_subcommand())
         .subcommand(create_wireguard_keys_subcommand());
     #[cfg(windows)]
     {
@@ -66,6 +72,76 @@ fn create_wireguard_quantum_resistant_tunnel_subcommand() -> clap::App<'static>
         .subcommand(clap::App::new("set").arg(clap::Arg::new("policy").required(true)))
 }
 
+fn create_wireguard_traffic_shaping_subcommand() -> clap::App<'static> {
+    clap::App::new("traffic-shaping")
+        .about("EXPERIMENTAL: Pad packets and send dummy traffic to resist traffic analysis")
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(clap::App::new("get"))
+        .subcommand(
+            clap::App::new("set")
+                .arg(
+                    clap::Arg::new("padding")
+                        .long("padding")
+                        .takes_value(true)
+                        .possible_values(&["on", "off"]),
+                )
+                .arg(
+                    clap::Arg::new("dummy traffic")
+                        .long("dummy-traffic")
+                        .takes_value(true)
+                        .possible_values(&["on", "off"]),
+                )
+                .arg(
+                    clap::Arg::new("on failure")
+                        .long("on-failure")
+                        .help("Whether to connect without traffic shaping or block if it cannot be set up")
+                        .takes_value(true)
+                        .possible_values(&["fallback", "block"]),
+                ),
+        )
+}
+
+fn create_wireguard_bandwidth_limit_subcommand() -> clap::App<'static> {
+    clap::App::new("bandwidth-limit")
+        .about("Limit the throughput of WireGuard tunnels")
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(clap::App::new("get"))
+        .subcommand(
+            clap::App::new("set")
+                .arg(
+                    clap::Arg::new("upload")
+                        .long("upload")
+                        .help("Limit for sent traffic in kbit/s, or 'unlimited'")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::new("download")
+                        .long("download")
+                        .help("Limit for received traffic in kbit/s, or 'unlimited'")
+                        .takes_value(true),
+                ),
+        )
+}
+
+fn create_wireguard_allowed_ips_subcommand() -> clap::App<'static> {
+    clap::App::new("allowed-ips")
+        .about(
+            "Route only traffic to some networks through WireGuard tunnels. All other traffic \
+             bypasses the tunnel",
+        )
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(clap::App::new("get"))
+        .subcommand(
+            clap::App::new("set").arg(
+                clap::Arg::new("networks")
+                    .multiple_occurrences(true)
+                    .help("One or more networks, e.g. 10.0.0.0/8 or 2001:db8::/32")
+                    .required(true),
+            ),
+        )
+        .subcommand(clap::App::new("unset").about("Route all traffic through the tunnel"))
+}
+
 fn create_wireguard_keys_subcommand() -> clap::App<'static> {
     clap::App::new("key")
         .about("Manage your wireguard key")
@@ -130,6 +206,21 @@ fn create_ipv6_subcommand() -> clap::App<'static> {
         )
 }
 
+fn create_ipv4_subcommand() -> clap::App<'static> {
+    clap::App::new("ipv4")
+        .about("Forward IPv4 traffic through the tunnel. If off, IPv4 traffic is blocked")
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(clap::App::new("get"))
+        .subcommand(
+            clap::App::new("set").arg(
+                clap::Arg::new("policy")
+                    .required(true)
+                    .takes_value(true)
+                    .possible_values(["on", "off"]),
+            ),
+        )
+}
+
 impl Tunnel {
     async fn handle_openvpn_cmd(matches: &clap::ArgMatches) -> Result<()> {
         match matches.subcommand() {
@@ -180,6 +271,29 @@ impl Tunnel {
                 _ => unreachable!("unhandled command"),
             },
 
+            Some(("traffic-shaping", matches)) => match matches.subcommand() {
+                Some(("get", _)) => Self::process_wireguard_traffic_shaping_get().await,
+                Some(("set", matches)) => {
+                    Self::process_wireguard_traffic_shaping_set(matches).await
+                }
+                _ => unreachable!("unhandled command"),
+            },
+
+            Some(("bandwidth-limit", matches)) => match matches.subcommand() {
+                Some(("get", _)) => Self::process_wireguard_bandwidth_limit_get().await,
+                Some(("set", matches)) => {
+                    Self::process_wireguard_bandwidth_limit_set(matches).await
+                }
+                _ => unreachable!("unhandled command"),
+            },
+
+            Some(("allowed-ips", matches)) => match matches.subcommand() {
+                Some(("get", _)) => Self::process_wireguard_allowed_ips_get().await,
+                Some(("set", matches)) => Self::process_wireguard_allowed_ips_set(matches).await,
+                Some(("unset", _)) => Self::process_wireguard_allowed_ips_unset().await,
+                _ => unreachable!("unhandled command"),
+            },
+
             #[cfg(windows)]
             Some(("use-wireguard-nt", matches)) => match matches.subcommand() {
                 Some(("get", _)) => Self::process_wireguard_use_wg_nt_get().await,
@@ -261,6 +375,127 @@ impl Tunnel {
         Ok(())
     }
 
+    async fn process_wireguard_traffic_shaping_get() -> Result<()> {
+        let tunnel_options = Self::get_tunnel_options().await?;
+        let options = tunnel_options
+            .wireguard
+            .unwrap()
+            .traffic_shaping
+            .unwrap_or_default();
+        let on_off = |enabled| if enabled { "on" } else { "off" };
+        println!("Padding      : {}", on_off(options.padding));
+        println!("Dummy traffic: {}", on_off(options.dummy_traffic));
+        let on_failure =
+            match types::traffic_shaping_options::FailurePolicy::from_i32(options.on_failure) {
+                Some(types::traffic_shaping_options::FailurePolicy::Block) => "block",
+                _ => "fallback",
+            };
+        println!("On failure   : {}", on_failure);
+        Ok(())
+    }
+
+    async fn process_wireguard_traffic_shaping_set(matches: &clap::ArgMatches) -> Result<()> {
+        let mut options = Self::get_tunnel_options()
+            .await?
+            .wireguard
+            .unwrap()
+            .traffic_shaping
+            .unwrap_or_default();
+        if let Some(padding) = matches.value_of("padding") {
+            options.padding = padding == "on";
+        }
+        if let Some(dummy_traffic) = matches.value_of("dummy traffic") {
+            options.dummy_traffic = dummy_traffic == "on";
+        }
+        if let Some(on_failure) = matches.value_of("on failure") {
+            let policy = if on_failure == "block" {
+                types::traffic_shaping_options::FailurePolicy::Block
+            } else {
+                types::traffic_shaping_options::FailurePolicy::Fallback
+            };
+            options.on_failure = i32::from(policy);
+        }
+
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_wireguard_traffic_shaping(options).await?;
+        println!("Updated traffic shaping setting");
+        Ok(())
+    }
+
+    async fn process_wireguard_bandwidth_limit_get() -> Result<()> {
+        let tunnel_options = Self::get_tunnel_options().await?;
+        let limit = tunnel_options
+            .wireguard
+            .unwrap()
+            .bandwidth_limit
+            .unwrap_or_default();
+        let format_limit = |limit: Option<u32>| match limit {
+            Some(kbps) => format!("{} kbit/s", kbps),
+            None => "unlimited".to_owned(),
+        };
+        println!("Upload  : {}", format_limit(limit.upload_kbps));
+        println!("Download: {}", format_limit(limit.download_kbps));
+        Ok(())
+    }
+
+    async fn process_wireguard_bandwidth_limit_set(matches: &clap::ArgMatches) -> Result<()> {
+        let mut limit = Self::get_tunnel_options()
+            .await?
+            .wireguard
+            .unwrap()
+            .bandwidth_limit
+            .unwrap_or_default();
+        if let Some(upload) = matches.value_of("upload") {
+            limit.upload_kbps = parse_bandwidth_limit(upload)?;
+        }
+        if let Some(download) = matches.value_of("download") {
+            limit.download_kbps = parse_bandwidth_limit(download)?;
+        }
+
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_wireguard_bandwidth_limit(limit).await?;
+        println!("Updated bandwidth limit");
+        Ok(())
+    }
+
+    async fn process_wireguard_allowed_ips_get() -> Result<()> {
+        let tunnel_options = Self::get_tunnel_options().await?;
+        let networks = tunnel_options
+            .wireguard
+            .unwrap()
+            .allowed_ips
+            .map(|allowed_ips| allowed_ips.networks)
+            .unwrap_or_default();
+        if networks.is_empty() {
+            println!("All traffic is routed through the tunnel");
+        } else {
+            println!("Networks routed through the tunnel:");
+            for network in networks {
+                println!("{}", network);
+            }
+        }
+        Ok(())
+    }
+
+    async fn process_wireguard_allowed_ips_set(matches: &clap::ArgMatches) -> Result<()> {
+        let networks = matches
+            .values_of_t::<IpNetwork>("networks")
+            .unwrap_or_else(|e| e.exit());
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_wireguard_allowed_ips(types::WireguardAllowedIps::from(&networks[..]))
+            .await?;
+        println!("Updated allowed IPs setting");
+        Ok(())
+    }
+
+    async fn process_wireguard_allowed_ips_unset() -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_wireguard_allowed_ips(types::WireguardAllowedIps::default())
+            .await?;
+        println!("All traffic is routed through the tunnel again");
+        Ok(())
+    }
+
     #[cfg(windows)]
     async fn process_wireguard_use_wg_nt_get() -> Result<()> {
         let tunnel_options = Self::get_tunnel_options().await?;
@@ -361,6 +596,16 @@ impl Tunnel {
         }
     }
 
+    async fn handle_ipv4_cmd(matches: &clap::ArgMatches) -> Result<()> {
+        if matches.subcommand_matches("get").is_some() {
+            Self::process_ipv4_get().await
+        } else if let Some(m) = matches.subcommand_matches("set") {
+            Self::process_ipv4_set(m).await
+        } else {
+            unreachable!("unhandled command");
+        }
+    }
+
     async fn process_openvpn_mssfix_get() -> Result<()> {
         let tunnel_options = Self::get_tunnel_options().await?;
         let mssfix = tunnel_options.openvpn.unwrap().mssfix;
@@ -426,6 +671,32 @@ impl Tunnel {
         Ok(())
     }
 
+    async fn process_ipv4_get() -> Result<()> {
+        let tunnel_options = Self::get_tunnel_options().await?;
+        println!(
+            "IPv4: {}",
+            if tunnel_options.generic.unwrap().enable_ipv4 {
+                "on"
+            } else {
+                "off"
+            }
+        );
+        Ok(())
+    }
+
+    async fn process_ipv4_set(matches: &clap::ArgMatches) -> Result<()> {
+        let enabled = matches.value_of("policy").unwrap() == "on";
+
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_enable_ipv4(enabled).await?;
+        if enabled {
+            println!("Enabled IPv4");
+        } else {
+            println!("Disabled IPv4. Only IPv6 traffic will go through WireGuard tunnels");
+        }
+        Ok(())
+    }
+
     fn format_key_timestamp(timestamp: &Timestamp) -> String {
         let ndt = chrono::NaiveDateTime::from_timestamp(timestamp.seconds, timestamp.nanos as u32);
         let utc = chrono::DateTime::<chrono::Utc>::from_utc(ndt, chrono::Utc);
@@ -436,3 +707,16 @@ impl Tunnel {
 fn duration_hours(duration: &Duration) -> u64 {
     duration.as_secs() / 60 / 60
 }
+
+/// Parses a limit in kbit/s, where `unlimited` means no limit.
+fn parse_bandwidth_limit(limit: &str) -> Result<Option<u32>> {
+    if limit == "unlimited" {
+        return Ok(None);
+    }
+    match limit.parse() {
+        Ok(0) | Err(_) => Err(Error::InvalidCommand(
+            "A bandwidth limit must be a positive number of kbit/s or 'unlimited'",
+        )),
+        Ok(kbps) => Ok(Some(kbps)),
+    }
+}
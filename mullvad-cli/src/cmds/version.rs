@@ -49,6 +49,20 @@ impl Command for Version {
             println!("{:21}: {}", "Latest beta version", version_info.latest_beta);
         };
 
+        let capabilities = mullvad_management_interface::get_capabilities(&mut rpc)
+            .await
+            .map_err(|error| Error::RpcFailedExt("Failed to obtain capabilities", error))?;
+        println!(
+            "{:21}: {} (client: {})",
+            "API version",
+            capabilities.api_version,
+            mullvad_management_interface::API_VERSION
+        );
+        if capabilities.api_version < mullvad_management_interface::API_VERSION {
+            println!("The daemon is older than this client. Some commands may not be available");
+        }
+        println!("{:21}: {}", "Features", capabilities.features.join(", "));
+
         Ok(())
     }
 }
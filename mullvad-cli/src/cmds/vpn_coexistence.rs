@@ -0,0 +1,102 @@
+use crate::{new_rpc_client, Command, Result};
+use ipnetwork::IpNetwork;
+use mullvad_management_interface::types;
+
+pub struct VpnCoexistence;
+
+#[mullvad_management_interface::async_trait]
+impl Command for VpnCoexistence {
+    fn name(&self) -> &'static str {
+        "vpn-coexistence"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static> {
+        clap::App::new(self.name())
+            .about(
+                "Exempt the traffic of another VPN, such as a WireGuard network of local \
+                 servers, from the tunnel and from blocking",
+            )
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::App::new("set")
+                    .about("Set the networks and UDP ports used by the other VPN")
+                    .arg(
+                        clap::Arg::new("network")
+                            .long("network")
+                            .takes_value(true)
+                            .multiple_occurrences(true)
+                            .help("A network used by the other VPN, e.g. 10.99.0.0/24"),
+                    )
+                    .arg(
+                        clap::Arg::new("udp port")
+                            .long("udp-port")
+                            .takes_value(true)
+                            .multiple_occurrences(true)
+                            .help("A local UDP port the other VPN listens on, e.g. 51821"),
+                    ),
+            )
+            .subcommand(clap::App::new("clear").about("Remove all exemptions"))
+            .subcommand(clap::App::new("get").about("Display the current exemptions"))
+    }
+
+    async fn run(&self, matches: &clap::ArgMatches) -> Result<()> {
+        match matches.subcommand() {
+            Some(("set", matches)) => {
+                let networks = if matches.is_present("network") {
+                    matches
+                        .values_of_t::<IpNetwork>("network")
+                        .unwrap_or_else(|e| e.exit())
+                } else {
+                    vec![]
+                };
+                let udp_ports = if matches.is_present("udp port") {
+                    matches
+                        .values_of_t::<u16>("udp port")
+                        .unwrap_or_else(|e| e.exit())
+                } else {
+                    vec![]
+                };
+                self.set(types::VpnCoexistence {
+                    networks: networks.iter().map(|net| net.to_string()).collect(),
+                    udp_ports: udp_ports.into_iter().map(u32::from).collect(),
+                })
+                .await
+            }
+            Some(("clear", _)) => self.set(types::VpnCoexistence::default()).await,
+            Some(("get", _)) => self.get().await,
+            _ => unreachable!("No vpn-coexistence command given"),
+        }
+    }
+}
+
+impl VpnCoexistence {
+    async fn set(&self, vpn_coexistence: types::VpnCoexistence) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        rpc.set_vpn_coexistence(vpn_coexistence).await?;
+        println!("Changed VPN coexistence settings");
+        Ok(())
+    }
+
+    async fn get(&self) -> Result<()> {
+        let mut rpc = new_rpc_client().await?;
+        let vpn_coexistence = rpc
+            .get_settings(())
+            .await?
+            .into_inner()
+            .vpn_coexistence
+            .unwrap_or_default();
+        if vpn_coexistence.networks.is_empty() && vpn_coexistence.udp_ports.is_empty() {
+            println!("No traffic is exempted for other VPNs");
+            return Ok(());
+        }
+        println!("Exempted networks:");
+        for network in vpn_coexistence.networks {
+            println!("{}", network);
+        }
+        println!("Exempted UDP ports:");
+        for port in vpn_coexistence.udp_ports {
+            println!("{}", port);
+        }
+        Ok(())
+    }
+}
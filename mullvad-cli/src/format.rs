@@ -1,12 +1,22 @@
 use mullvad_management_interface::types::{
+    clock_skew,
+    competing_vpn::Kind as CompetingVpnKind,
     error_state::{
-        firewall_policy_error::ErrorType as FirewallPolicyErrorType, Cause as ErrorStateCause,
-        FirewallPolicyError, GenerationError,
+        firewall_policy_error::ErrorType as FirewallPolicyErrorType,
+        process_failure::Kind as ProcessFailureKind, Cause as ErrorStateCause, FirewallPolicyError,
+        GenerationError, ProcessFailure,
     },
+    firewall_conflict::Kind as FirewallConflictKind,
+    performance_warning::Operation as PerformanceOperation,
+    settings_apply_plan::Operation as ApplyOperation,
+    tunnel_endpoint::WireguardBackend,
     tunnel_state,
-    tunnel_state::State::*,
-    ErrorState, ObfuscationType, ProxyType, TransportProtocol, TunnelState, TunnelStateRelayInfo,
-    TunnelType,
+    tunnel_state::{
+        disconnected::{Cause as DisconnectCause, Security as DisconnectedSecurity},
+        State::*,
+    },
+    ClockSkew, CompetingVpn, ErrorState, FirewallConflicts, ObfuscationType, PerformanceWarning,
+    ProxyType, SettingsApplyPlan, TransportProtocol, TunnelState, TunnelStateRelayInfo, TunnelType,
 };
 use mullvad_types::auth_failed::AuthFailed;
 use std::borrow::Cow;
@@ -27,8 +37,44 @@ pub fn print_state(state: &TunnelState, verbose: bool) {
                 format_relay_connection(relay_info.as_ref().unwrap(), verbose)
             );
         }
-        Disconnected(_) => println!("Disconnected"),
+        Disconnected(disconnected) => {
+            let security = format_disconnected_security(disconnected);
+            match format_disconnect_cause(disconnected) {
+                Some(cause) if verbose => println!("Disconnected ({cause}){security}"),
+                _ => println!("Disconnected{security}"),
+            }
+        }
         Disconnecting(_) => println!("Disconnecting..."),
+        Paused(tunnel_state::Paused { relay_info }) => {
+            println!(
+                "Paused connection to {}",
+                format_relay_connection(relay_info.as_ref().unwrap(), verbose)
+            );
+        }
+    }
+}
+
+fn format_disconnect_cause(disconnected: &tunnel_state::Disconnected) -> Option<&'static str> {
+    match disconnected.cause() {
+        DisconnectCause::Unknown => None,
+        DisconnectCause::UserInitiated => Some("by user"),
+        DisconnectCause::Reconnecting => Some("reconnect cancelled"),
+        DisconnectCause::Error => Some("error resolved"),
+        DisconnectCause::Revoked => Some("device revoked"),
+        DisconnectCause::Shutdown => Some("shutting down"),
+    }
+}
+
+fn format_disconnected_security(disconnected: &tunnel_state::Disconnected) -> &'static str {
+    match disconnected.security() {
+        DisconnectedSecurity::Unsecured => "",
+        DisconnectedSecurity::BlockWhenDisconnected => {
+            ", blocking internet access (always require VPN)"
+        }
+        DisconnectedSecurity::PendingConnect => ", blocking internet access until connected",
+        DisconnectedSecurity::ReconnectGracePeriod => {
+            ", blocking internet access briefly in case of a reconnect"
+        }
     }
 }
 
@@ -134,6 +180,7 @@ fn format_relay_connection(relay_info: &TunnelStateRelayInfo, verbose: bool) ->
 
     let mut bridge_type = String::new();
     let mut obfuscator_type = String::new();
+    let mut wireguard_backend = String::new();
     if verbose {
         if let Some(bridge) = endpoint.proxy.as_ref() {
             let bridge = match ProxyType::from_i32(bridge.proxy_type).expect("invalid proxy type") {
@@ -146,10 +193,21 @@ fn format_relay_connection(relay_info: &TunnelStateRelayInfo, verbose: bool) ->
             let obfuscation = convert_obfuscator_type(obfuscator.obfuscation_type);
             obfuscator_type = format!("\nObfuscator: {obfuscation}");
         }
+        let backend = match WireguardBackend::from_i32(endpoint.wireguard_backend)
+            .expect("invalid WireGuard backend")
+        {
+            WireguardBackend::Unknown => None,
+            WireguardBackend::Kernel => Some("kernel"),
+            WireguardBackend::WireguardNt => Some("WireGuardNT"),
+            WireguardBackend::WireguardGo => Some("wireguard-go"),
+        };
+        if let Some(backend) = backend {
+            wireguard_backend = format!("\nWireGuard backend: {backend}");
+        }
     }
 
     format!(
-        "{exit_endpoint}{first_hop}{bridge}{obfuscator}{tunnel_type}{quantum_resistant}{bridge_type}{obfuscator_type}",
+        "{exit_endpoint}{first_hop}{bridge}{obfuscator}{tunnel_type}{quantum_resistant}{wireguard_backend}{bridge_type}{obfuscator_type}",
         first_hop = first_hop.unwrap_or_default(),
         bridge = bridge.unwrap_or_default(),
         obfuscator = obfuscator.unwrap_or_default(),
@@ -159,6 +217,7 @@ fn format_relay_connection(relay_info: &TunnelStateRelayInfo, verbose: bool) ->
 fn convert_obfuscator_type(obfuscator: i32) -> &'static str {
     match ObfuscationType::from_i32(obfuscator).expect("invalid obfuscator type") {
         ObfuscationType::Udp2tcp => "Udp2Tcp",
+        ObfuscationType::Tls => "TLS",
     }
 }
 
@@ -200,6 +259,121 @@ fn print_error_state(error_state: &ErrorState) {
         }
         _ => println!("Blocked: {}", error_state_to_string(error_state)),
     }
+
+    if let Some(allowlist) = &error_state.recovery_allowlist {
+        if !allowlist.networks.is_empty() {
+            println!(
+                "Traffic to and from these networks is not blocked: {}",
+                allowlist.networks.join(", ")
+            );
+        }
+    }
+}
+
+pub fn print_firewall_conflicts(conflicts: &FirewallConflicts) {
+    if conflicts.conflicts.is_empty() {
+        println!("No conflicting firewall products detected");
+        return;
+    }
+    eprintln!("Warning: other products may override the firewall rules of the app:");
+    for conflict in &conflicts.conflicts {
+        let kind = match FirewallConflictKind::from_i32(conflict.kind) {
+            Some(FirewallConflictKind::Sublayer) => "sublayer",
+            Some(FirewallConflictKind::HardPermitFilter) => "filter",
+            None => "object",
+        };
+        if conflict.provider.is_empty() {
+            eprintln!("  {} \"{}\"", kind, conflict.name);
+        } else {
+            eprintln!(
+                "  {} \"{}\" from \"{}\"",
+                kind, conflict.name, conflict.provider
+            );
+        }
+    }
+}
+
+pub fn print_competing_vpn(competing_vpn: &CompetingVpn) {
+    let kind = match CompetingVpnKind::from_i32(competing_vpn.kind) {
+        Some(CompetingVpnKind::Wireguard) => "WireGuard",
+        Some(CompetingVpnKind::Openvpn) => "OpenVPN",
+        Some(CompetingVpnKind::CiscoAnyconnect) => "Cisco AnyConnect",
+        Some(CompetingVpnKind::Globalprotect) => "GlobalProtect",
+        Some(CompetingVpnKind::Forticlient) => "FortiClient",
+        Some(CompetingVpnKind::Tailscale) => "Tailscale",
+        Some(CompetingVpnKind::Zerotier) => "ZeroTier",
+        Some(CompetingVpnKind::Ipsec) => "IPsec",
+        Some(CompetingVpnKind::Ppp) => "PPP",
+        Some(CompetingVpnKind::Unknown) | None => "unknown VPN",
+    };
+    eprintln!(
+        "Warning: another VPN ({}) took over the default route: {} via {}",
+        kind, competing_vpn.prefix, competing_vpn.interface
+    );
+    if !competing_vpn.description.is_empty() {
+        eprintln!("  Interface description: {}", competing_vpn.description);
+    }
+}
+
+pub fn print_performance_warning(warning: &PerformanceWarning) {
+    let operation = match PerformanceOperation::from_i32(warning.operation) {
+        Some(PerformanceOperation::ApplyFirewallPolicy) => "applying the firewall policy",
+        Some(PerformanceOperation::ResetFirewallPolicy) => "resetting the firewall policy",
+        Some(PerformanceOperation::SetDns) => "setting DNS",
+        Some(PerformanceOperation::ResetDns) => "resetting DNS",
+        Some(PerformanceOperation::ClearRoutes) => "clearing routes",
+        None => "an unknown operation",
+    };
+    let duration = warning
+        .duration
+        .clone()
+        .and_then(|duration| std::time::Duration::try_from(duration).ok())
+        .unwrap_or_default();
+    eprintln!("Warning: {} took {} ms", operation, duration.as_millis());
+}
+
+/// Prints how changed settings were applied to the tunnel, unless it was unaffected.
+pub fn print_apply_plan(apply_plan: &SettingsApplyPlan) {
+    if apply_plan.operations.is_empty() {
+        return;
+    }
+    let operations: Vec<&str> = apply_plan
+        .operations
+        .iter()
+        .map(|operation| match ApplyOperation::from_i32(*operation) {
+            Some(ApplyOperation::FirewallUpdate) => "firewall update",
+            Some(ApplyOperation::DnsUpdate) => "DNS update",
+            Some(ApplyOperation::PeerUpdate) => "in-place peer update",
+            Some(ApplyOperation::Reconnect) => "reconnect",
+            None => "unknown",
+        })
+        .collect();
+    println!("Applied using: {}", operations.join(", "));
+}
+
+pub fn print_clock_skew(skew: &ClockSkew) {
+    match &skew.skew {
+        Some(clock_skew::Skew::Measured(skew)) => {
+            let (direction, seconds) = if skew.seconds < 0 {
+                ("behind", skew.seconds.unsigned_abs())
+            } else {
+                ("ahead of", skew.seconds.unsigned_abs())
+            };
+            eprintln!(
+                "Warning: The system clock is {} minutes {} the clock of the API. Correct it to \
+                 avoid connection problems",
+                seconds / 60,
+                direction
+            );
+        }
+        Some(clock_skew::Skew::CertificateNotValid(())) => {
+            eprintln!(
+                "Warning: The API cannot be reached because the system clock is wrong. Correct it \
+                 to restore access"
+            );
+        }
+        None => println!("The system clock is no longer too far off"),
+    }
 }
 
 fn error_state_to_string(error_state: &ErrorState) -> String {
@@ -224,16 +398,34 @@ fn error_state_to_string(error_state: &ErrorState) -> String {
         SetDnsError => "Failed to set system DNS server",
         StartTunnelError => "Failed to start connection to remote server",
         TunnelParameterError => {
-            return format!(
-                "Failure to generate tunnel parameters: {}",
-                tunnel_parameter_error_to_string(error_state.parameter_error)
-            );
+            let reason = tunnel_parameter_error_to_string(error_state.parameter_error);
+            return if error_state.invalid_parameters_reason.is_empty() {
+                format!("Failure to generate tunnel parameters: {reason}")
+            } else {
+                format!(
+                    "Failure to generate tunnel parameters: {reason}: {}",
+                    error_state.invalid_parameters_reason
+                )
+            };
         }
         IsOffline => "This device is offline, no tunnels can be established",
         #[cfg(target_os = "android")]
         VpnPermissionDenied => "The Android VPN permission was denied when creating the tunnel",
         #[cfg(target_os = "windows")]
         SplitTunnelError => "The split tunneling module reported an error",
+        TunnelMonitorStopped => "The tunnel monitor stopped unexpectedly",
+        ForcedInterfaceUnavailable => "The forced tunnel interface is unavailable",
+        GatewayUnreachable => "The gateway of the network is unreachable",
+        TooManyAttempts => "Gave up reconnecting after too many failed attempts",
+        RouteTakenOver => "Another VPN took over the default route",
+        TunnelProcessFailed => {
+            return match error_state.process_failure.as_ref() {
+                Some(failure) => process_failure_to_string(failure),
+                None => "The tunnel process failed".to_string(),
+            };
+        }
+        TunnelDeviceBusy => "The tunnel device is in use by another program",
+        AccountExpired => "The account is out of time",
         #[cfg(not(target_os = "android"))]
         _ => unreachable!("unknown error cause"),
     };
@@ -249,6 +441,7 @@ fn tunnel_parameter_error_to_string(parameter_error: i32) -> &'static str {
         GenerationError::CustomTunnelHostResolutionError => {
             "Can't resolve hostname for custom tunnel host"
         }
+        GenerationError::InvalidTunnelParameters => "Invalid tunnel parameters",
     }
 }
 
@@ -265,6 +458,16 @@ fn policy_error_to_string(policy_error: &FirewallPolicyError) -> String {
     format!("Failed to set firewall policy: {}", cause)
 }
 
+fn process_failure_to_string(failure: &ProcessFailure) -> String {
+    match ProcessFailureKind::from_i32(failure.kind).expect("unknown process failure") {
+        ProcessFailureKind::Spawn => "The tunnel process could not be started".to_string(),
+        ProcessFailureKind::ExitCode => {
+            format!("The tunnel process exited with code {}", failure.exit_code)
+        }
+        ProcessFailureKind::Crash => "The tunnel process crashed".to_string(),
+    }
+}
+
 fn format_protocol(protocol: TransportProtocol) -> &'static str {
     match protocol {
         TransportProtocol::Udp => "UDP",
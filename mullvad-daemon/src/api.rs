@@ -4,10 +4,13 @@ use futures::{
     channel::{mpsc, oneshot},
     Future, Stream, StreamExt,
 };
+#[cfg(not(target_os = "android"))]
+use mullvad_api::InterfaceBinding;
 use mullvad_api::{
     availability::ApiAvailabilityHandle,
     proxy::{ApiConnectionMode, ProxyConfig},
-    ApiEndpointUpdateCallback,
+    rest::MullvadRestHandle,
+    AddressCache, ApiEndpointUpdateCallback, ApiProxy,
 };
 use mullvad_relay_selector::RelaySelector;
 use std::{
@@ -16,12 +19,15 @@ use std::{
     pin::Pin,
     sync::{Arc, Mutex, Weak},
     task::Poll,
+    time::Duration,
 };
 #[cfg(target_os = "android")]
 use talpid_core::mpsc::Sender;
 use talpid_core::tunnel_state_machine::TunnelCommand;
+#[cfg(not(target_os = "android"))]
+use talpid_types::net::PhysicalInterface;
 use talpid_types::{
-    net::{openvpn::ProxySettings, AllowedEndpoint, Endpoint, TransportProtocol},
+    net::{openvpn::ProxySettings, AllowedEndpoint, Endpoint, EndpointRange, TransportProtocol},
     ErrorExt,
 };
 
@@ -120,12 +126,14 @@ impl ApiConnectionModeProvider {
 /// be passed to the `mullvad-api` runtime.
 pub(super) struct ApiEndpointUpdaterHandle {
     tunnel_cmd_tx: Arc<Mutex<Option<Weak<mpsc::UnboundedSender<TunnelCommand>>>>>,
+    address_cache: AddressCache,
 }
 
 impl ApiEndpointUpdaterHandle {
-    pub fn new() -> Self {
+    pub fn new(address_cache: AddressCache) -> Self {
         Self {
             tunnel_cmd_tx: Arc::new(Mutex::new(None)),
+            address_cache,
         }
     }
 
@@ -135,8 +143,10 @@ impl ApiEndpointUpdaterHandle {
 
     pub fn callback(&self) -> impl ApiEndpointUpdateCallback {
         let tunnel_tx = self.tunnel_cmd_tx.clone();
-        move |address: SocketAddr| {
+        let address_cache = self.address_cache.clone();
+        move |addresses: Vec<SocketAddr>| {
             let inner_tx = tunnel_tx.clone();
+            let address_cache = address_cache.clone();
             async move {
                 let tunnel_tx = if let Some(Some(tunnel_tx)) = { inner_tx.lock().unwrap().as_ref() }
                     .map(|tx: &Weak<mpsc::UnboundedSender<TunnelCommand>>| tx.upgrade())
@@ -146,23 +156,28 @@ impl ApiEndpointUpdaterHandle {
                     log::error!("Rejecting allowed endpoint: Tunnel state machine is not running");
                     return false;
                 };
+                let hostname = address_cache.hostname_of(&addresses).await;
                 let (result_tx, result_rx) = oneshot::channel();
                 let _ = tunnel_tx.unbounded_send(TunnelCommand::AllowEndpoint(
-                    get_allowed_endpoint(address),
+                    get_allowed_endpoint(&addresses, hostname),
                     result_tx,
                 ));
                 // Wait for the firewall policy to be updated.
                 let _ = result_rx.await;
-                log::debug!("API endpoint: {}", address);
+                log::debug!("API endpoints: {:?}", addresses);
                 true
             }
         }
     }
 }
 
-pub(super) fn get_allowed_endpoint(api_address: SocketAddr) -> AllowedEndpoint {
-    let endpoint = Endpoint::from_socket_address(api_address, TransportProtocol::Tcp);
-
+/// Returns the endpoints that must be reachable for the API to be reached at `api_addresses`.
+/// Connections to all of them are raced, so they are all allowed at once. `hostname` is the
+/// hostname of the API, unless the addresses belong to a proxy.
+pub(super) fn get_allowed_endpoint(
+    api_addresses: &[SocketAddr],
+    hostname: Option<String>,
+) -> AllowedEndpoint {
     #[cfg(windows)]
     let daemon_exe = std::env::current_exe().expect("failed to obtain executable path");
     #[cfg(windows)]
@@ -177,13 +192,86 @@ pub(super) fn get_allowed_endpoint(api_address: SocketAddr) -> AllowedEndpoint {
     AllowedEndpoint {
         #[cfg(windows)]
         clients,
-        endpoint,
+        endpoints: api_addresses
+            .iter()
+            .map(|address| {
+                EndpointRange::from(Endpoint::from_socket_address(
+                    *address,
+                    TransportProtocol::Tcp,
+                ))
+            })
+            .collect(),
+        hostname,
     }
 }
 
+/// Number of times to probe the API after a network change before giving up. A failed probe
+/// causes `mullvad-api` to move on to the next connection mode, so this bounds the number of
+/// connection modes that are tried.
+const MAX_REACHABILITY_PROBES: usize = 3;
+
+/// How long to wait for the network to settle before probing the API.
+const NETWORK_CHANGE_DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// Probes whether the API is reachable using the current connection mode every time a message is
+/// received on the returned channel, which should happen whenever the network changes.
+///
+/// The allowed API endpoint may be blocked on some networks, in which case the daemon would be
+/// unable to talk to the API while in a blocking state. Since network errors cause `mullvad-api`
+/// to rotate to the next connection mode, which in turn updates the allowed endpoint, probing
+/// ensures that a working connection mode is found without waiting for some other request to
+/// fail first.
+pub(crate) fn spawn_reachability_monitor(
+    api_handle: MullvadRestHandle,
+) -> mpsc::UnboundedSender<()> {
+    let (network_change_tx, mut network_change_rx) = mpsc::unbounded();
+    let api_availability = api_handle.availability.clone();
+    let api_proxy = ApiProxy::new(api_handle);
+
+    tokio::spawn(async move {
+        while network_change_rx.next().await.is_some() {
+            tokio::time::sleep(NETWORK_CHANGE_DEBOUNCE).await;
+            // Coalesce changes that occurred while waiting
+            while let Ok(Some(())) = network_change_rx.try_next() {}
+
+            for attempt in 1..=MAX_REACHABILITY_PROBES {
+                if api_availability.get_state().is_offline() {
+                    break;
+                }
+                match api_proxy.get_api_addrs().await {
+                    Ok(_) => {
+                        log::debug!("The API is reachable after a network change");
+                        break;
+                    }
+                    Err(error) if error.is_network_error() => {
+                        log::warn!(
+                            "{}",
+                            error.display_chain_with_msg(&format!(
+                                "The API is unreachable after a network change (attempt {}/{})",
+                                attempt, MAX_REACHABILITY_PROBES
+                            ))
+                        );
+                    }
+                    Err(error) => {
+                        // The API responded, so the endpoint is reachable
+                        log::debug!(
+                            "{}",
+                            error.display_chain_with_msg("Reachability probe failed")
+                        );
+                        break;
+                    }
+                }
+            }
+        }
+    });
+
+    network_change_tx
+}
+
 pub(crate) fn forward_offline_state(
     api_availability: ApiAvailabilityHandle,
     mut offline_state_rx: mpsc::UnboundedReceiver<bool>,
+    network_change_tx: mpsc::UnboundedSender<()>,
 ) {
     tokio::spawn(async move {
         let initial_state = offline_state_rx
@@ -191,8 +279,36 @@ pub(crate) fn forward_offline_state(
             .await
             .expect("missing initial offline state");
         api_availability.set_offline(initial_state);
+        let mut was_offline = initial_state;
         while let Some(is_offline) = offline_state_rx.next().await {
             api_availability.set_offline(is_offline);
+            if was_offline && !is_offline {
+                let _ = network_change_tx.unbounded_send(());
+            }
+            was_offline = is_offline;
+        }
+    });
+}
+
+/// Binds API connections to the physical interface published by the tunnel state machine.
+#[cfg(not(target_os = "android"))]
+pub(crate) fn forward_physical_interface(
+    interface_binding: InterfaceBinding,
+    mut physical_interface_rx: mpsc::UnboundedReceiver<Option<PhysicalInterface>>,
+    network_change_tx: mpsc::UnboundedSender<()>,
+) {
+    tokio::spawn(async move {
+        let mut current_interface = None;
+        while let Some(interface) = physical_interface_rx.next().await {
+            match &interface {
+                Some(interface) => log::debug!("Binding API connections to {}", interface),
+                None => log::debug!("Not binding API connections to any interface"),
+            }
+            interface_binding.set(interface.clone());
+            if interface.is_some() && interface != current_interface {
+                let _ = network_change_tx.unbounded_send(());
+            }
+            current_interface = interface;
         }
     });
 }
@@ -9,6 +9,8 @@ pub struct Config {
     pub register_service: bool,
     #[cfg(target_os = "linux")]
     pub initialize_firewall_and_exit: bool,
+    #[cfg(target_os = "linux")]
+    pub run_firewall_watchdog: bool,
 }
 
 pub fn get_config() -> &'static Config {
@@ -33,12 +35,16 @@ pub fn create_config() -> Config {
     #[cfg(target_os = "linux")]
     let initialize_firewall_and_exit =
         cfg!(target_os = "linux") && matches.is_present("initialize-early-boot-firewall");
+    #[cfg(target_os = "linux")]
+    let run_firewall_watchdog = matches.is_present("firewall-watchdog");
     let run_as_service = cfg!(windows) && matches.is_present("run_as_service");
     let register_service = cfg!(windows) && matches.is_present("register_service");
 
     Config {
         #[cfg(target_os = "linux")]
         initialize_firewall_and_exit,
+        #[cfg(target_os = "linux")]
+        run_firewall_watchdog,
         log_level,
         log_to_file,
         log_stdout_timestamps,
@@ -105,11 +111,18 @@ fn create_app() -> App<'static> {
     }
 
     if cfg!(target_os = "linux") {
-        app = app.arg(
-            Arg::new("initialize-early-boot-firewall")
-                .long("initialize-early-boot-firewall")
-                .help("Initialize firewall to be used during early boot and exit"),
-        )
+        app = app
+            .arg(
+                Arg::new("initialize-early-boot-firewall")
+                    .long("initialize-early-boot-firewall")
+                    .help("Initialize firewall to be used during early boot and exit"),
+            )
+            .arg(
+                Arg::new("firewall-watchdog")
+                    .long("firewall-watchdog")
+                    .hide(true)
+                    .help("Block traffic if the parent daemon dies while traffic is blocked"),
+            )
     }
     app
 }
@@ -0,0 +1,25 @@
+//! Forwards problems with the system clock that are detected by the API client to the daemon, so
+//! that they can be shown to the user.
+use crate::DaemonEventSender;
+use mullvad_api::clock_skew::{ClockSkew, ClockSkewHandle};
+use talpid_core::mpsc::Sender;
+use tokio::sync::broadcast::error::RecvError;
+
+/// Sent to the daemon when a clock problem is detected, changes kind, or is no longer present.
+pub struct ClockSkewChanged(pub Option<ClockSkew>);
+
+pub fn spawn_monitor(handle: ClockSkewHandle, event_tx: DaemonEventSender<ClockSkewChanged>) {
+    let mut skew_rx = handle.subscribe();
+    tokio::spawn(async move {
+        loop {
+            let skew = match skew_rx.recv().await {
+                Ok(skew) => skew,
+                Err(RecvError::Lagged(_)) => handle.get(),
+                Err(RecvError::Closed) => break,
+            };
+            if event_tx.send(ClockSkewChanged(skew)).is_err() {
+                break;
+            }
+        }
+    });
+}
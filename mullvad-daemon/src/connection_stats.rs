@@ -0,0 +1,299 @@
+//! Opt-in, locally aggregated statistics about the outcomes of connection attempts.
+//!
+//! Attempts are grouped by how the relay was reached: tunnel protocol, transport protocol, port,
+//! obfuscation and bridge type. No relays, addresses or account details are recorded. The
+//! statistics are only kept while the user has opted in, and are never sent anywhere unless the
+//! user attaches them to a problem report.
+//!
+//! The number of changes that the route manager made to the routing table is counted as well, to
+//! show how often routes have to be refreshed, e.g. because the default route changed.
+
+use mullvad_types::states::TunnelState;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use talpid_core::routing::events::{self as route_events, RouteMetrics};
+use talpid_types::{
+    net::{proxy::ProxyType, ObfuscationType, TransportProtocol, TunnelEndpoint, TunnelType},
+    ErrorExt,
+};
+use tokio::{fs, io};
+
+const CONNECTION_STATS_FILE: &str = "connection-stats.json";
+
+/// Upper bounds of the buckets that the time it took to connect is sorted into. The last bucket
+/// holds everything that took longer.
+const CONNECT_TIME_BUCKETS: [Duration; 5] = [
+    Duration::from_secs(1),
+    Duration::from_secs(2),
+    Duration::from_secs(5),
+    Duration::from_secs(10),
+    Duration::from_secs(30),
+];
+
+/// How a relay was reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct ConnectionKind {
+    tunnel_type: TunnelType,
+    transport_protocol: TransportProtocol,
+    port: u16,
+    obfuscation: Option<ObfuscationType>,
+    bridge: Option<ProxyType>,
+}
+
+impl From<&TunnelEndpoint> for ConnectionKind {
+    fn from(endpoint: &TunnelEndpoint) -> Self {
+        ConnectionKind {
+            tunnel_type: endpoint.tunnel_type,
+            transport_protocol: endpoint.endpoint.protocol,
+            port: endpoint.endpoint.address.port(),
+            obfuscation: endpoint
+                .obfuscation
+                .as_ref()
+                .map(|obfuscation| obfuscation.obfuscation_type),
+            bridge: endpoint.proxy.as_ref().map(|proxy| proxy.proxy_type),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Outcomes {
+    /// Attempts that resulted in a working tunnel.
+    succeeded: u32,
+    /// Attempts that were abandoned for a new attempt, e.g. because they timed out.
+    retried: u32,
+    /// Attempts that ended in the error state.
+    failed: u32,
+    /// Number of successful attempts per bucket in [`CONNECT_TIME_BUCKETS`].
+    connect_time_histogram: [u32; CONNECT_TIME_BUCKETS.len() + 1],
+}
+
+impl Outcomes {
+    fn add_success(&mut self, connect_time: Duration) {
+        self.succeeded = self.succeeded.saturating_add(1);
+        let bucket = CONNECT_TIME_BUCKETS
+            .iter()
+            .position(|bound| connect_time < *bound)
+            .unwrap_or(CONNECT_TIME_BUCKETS.len());
+        self.connect_time_histogram[bucket] = self.connect_time_histogram[bucket].saturating_add(1);
+    }
+}
+
+/// Number of changes of each kind that the route manager made to the routing table.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct RouteChanges {
+    added: u32,
+    replaced: u32,
+    repaired: u32,
+    deleted: u32,
+}
+
+impl RouteChanges {
+    /// Adds `metrics` to the counts. Returns whether anything changed.
+    fn add(&mut self, metrics: &RouteMetrics) -> bool {
+        let counts = [
+            (&mut self.added, metrics.added),
+            (&mut self.replaced, metrics.replaced),
+            (&mut self.repaired, metrics.repaired),
+            (&mut self.deleted, metrics.deleted),
+        ];
+        let mut changed = false;
+        for (count, new) in counts {
+            let new = u32::try_from(new).unwrap_or(u32::MAX);
+            changed |= new > 0;
+            *count = count.saturating_add(new);
+        }
+        changed
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct ConnectionStats {
+    entries: Vec<(ConnectionKind, Outcomes)>,
+    #[serde(default)]
+    route_changes: RouteChanges,
+}
+
+impl ConnectionStats {
+    fn outcomes(&mut self, kind: ConnectionKind) -> &mut Outcomes {
+        let index = match self.entries.iter().position(|(entry, _)| *entry == kind) {
+            Some(index) => index,
+            None => {
+                self.entries.push((kind, Outcomes::default()));
+                self.entries.len() - 1
+            }
+        };
+        &mut self.entries[index].1
+    }
+}
+
+/// Records the outcome of every connection attempt while enabled.
+pub struct ConnectionStatsTracker {
+    stats: Option<ConnectionStats>,
+    cache_path: PathBuf,
+    /// The attempt in progress and when it started.
+    pending_attempt: Option<(ConnectionKind, Instant)>,
+    /// Route events that have already been counted.
+    route_metrics: RouteMetrics,
+}
+
+impl ConnectionStatsTracker {
+    /// Loads the statistics gathered so far if `enabled` is set. Otherwise, any statistics left
+    /// behind are removed.
+    pub async fn new(cache_dir: &Path, enabled: bool) -> Self {
+        let cache_path = cache_dir.join(CONNECTION_STATS_FILE);
+        let stats = if enabled {
+            Some(load(&cache_path).await.unwrap_or_default())
+        } else {
+            remove(&cache_path).await;
+            None
+        };
+        ConnectionStatsTracker {
+            stats,
+            cache_path,
+            pending_attempt: None,
+            route_metrics: route_events::metrics(),
+        }
+    }
+
+    /// Starts or stops gathering statistics. Stopping removes everything gathered so far.
+    pub async fn set_enabled(&mut self, enabled: bool) {
+        match (enabled, self.stats.is_some()) {
+            (true, false) => {
+                self.stats = Some(ConnectionStats::default());
+                self.route_metrics = route_events::metrics();
+                save(&self.cache_path, &ConnectionStats::default()).await;
+            }
+            (false, true) => {
+                self.stats = None;
+                self.pending_attempt = None;
+                remove(&self.cache_path).await;
+            }
+            _ => (),
+        }
+    }
+
+    pub async fn set_tunnel_state(&mut self, tunnel_state: &TunnelState) {
+        let stats = match self.stats.as_mut() {
+            Some(stats) => stats,
+            None => return,
+        };
+        let route_metrics = route_events::metrics();
+        let routes_changed = stats
+            .route_changes
+            .add(&route_metrics.since(&self.route_metrics));
+        self.route_metrics = route_metrics;
+
+        let pending_attempt = self.pending_attempt.take();
+        let outcome_recorded = match (tunnel_state, pending_attempt) {
+            (TunnelState::Connecting { endpoint, .. }, pending_attempt) => {
+                self.pending_attempt = Some((ConnectionKind::from(endpoint), Instant::now()));
+                match pending_attempt {
+                    Some((kind, _)) => {
+                        let outcomes = stats.outcomes(kind);
+                        outcomes.retried = outcomes.retried.saturating_add(1);
+                        true
+                    }
+                    None => false,
+                }
+            }
+            (TunnelState::Connected { .. }, Some((kind, started))) => {
+                stats.outcomes(kind).add_success(started.elapsed());
+                true
+            }
+            (TunnelState::Error(_), Some((kind, _))) => {
+                let outcomes = stats.outcomes(kind);
+                outcomes.failed = outcomes.failed.saturating_add(1);
+                true
+            }
+            // Attempts that the user cancelled say nothing about the network
+            _ => false,
+        };
+        if outcome_recorded || routes_changed {
+            save(&self.cache_path, stats).await;
+        }
+    }
+}
+
+async fn load(cache_path: &Path) -> Option<ConnectionStats> {
+    match fs::read_to_string(cache_path).await {
+        Ok(content) => serde_json::from_str(&content)
+            .map_err(|error| {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to parse connection statistics")
+                );
+            })
+            .ok(),
+        Err(error) => {
+            if error.kind() != io::ErrorKind::NotFound {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to read connection statistics")
+                );
+            }
+            None
+        }
+    }
+}
+
+async fn save(cache_path: &Path, stats: &ConnectionStats) {
+    match serde_json::to_string_pretty(stats) {
+        Ok(data) => {
+            if let Err(error) = fs::write(cache_path, data).await {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to write connection statistics")
+                );
+            }
+        }
+        Err(error) => {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to serialize connection statistics")
+            );
+        }
+    }
+}
+
+async fn remove(cache_path: &Path) {
+    if let Err(error) = fs::remove_file(cache_path).await {
+        if error.kind() != io::ErrorKind::NotFound {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to remove connection statistics")
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_connect_time_histogram() {
+        let mut outcomes = Outcomes::default();
+        outcomes.add_success(Duration::from_millis(500));
+        outcomes.add_success(Duration::from_secs(3));
+        outcomes.add_success(Duration::from_secs(60));
+        assert_eq!(outcomes.succeeded, 3);
+        assert_eq!(outcomes.connect_time_histogram, [1, 0, 1, 0, 0, 1]);
+    }
+
+    #[test]
+    fn test_route_changes() {
+        let mut changes = RouteChanges::default();
+        assert!(!changes.add(&RouteMetrics::default()));
+        assert!(changes.add(&RouteMetrics {
+            repaired: 2,
+            deleted: 1,
+            ..RouteMetrics::default()
+        }));
+        assert_eq!(changes.repaired, 2);
+        assert_eq!(changes.deleted, 1);
+        assert_eq!(changes.added, 0);
+    }
+}
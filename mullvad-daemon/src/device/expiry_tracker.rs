@@ -0,0 +1,119 @@
+//! Keeps the account expiry up to date by refreshing it in the background. The expiry is checked
+//! more often as it approaches, and once more when it is reached, so that the daemon can block the
+//! tunnel as soon as the account runs out of time. Results are broadcast by the account manager as
+//! [`super::AccountEvent::Expiry`].
+
+use super::{AccountManagerHandle, Error};
+use chrono::{DateTime, Utc};
+use futures::{channel::mpsc, future::FutureExt, stream::StreamExt};
+use std::time::Duration;
+use talpid_types::ErrorExt;
+
+/// How often to refresh the expiry when plenty of time is left on the account.
+const DEFAULT_CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Time left on the account below which the expiry is considered to be approaching.
+const CLOSE_TO_EXPIRY: Duration = Duration::from_secs(3 * 24 * 60 * 60);
+
+/// How often to refresh the expiry when it is approaching.
+const CLOSE_TO_EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How often to refresh the expiry once the account is out of time, to notice when time is added
+/// outside of the app.
+const EXPIRED_CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// How long to wait before trying again after failing to fetch the expiry.
+const RETRY_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// Handle to the expiry tracker. The tracker stops when this is dropped.
+pub(crate) struct ExpiryTracker {
+    refresh_tx: mpsc::UnboundedSender<()>,
+}
+
+impl ExpiryTracker {
+    pub fn spawn(manager: AccountManagerHandle) -> Self {
+        let (refresh_tx, refresh_rx) = mpsc::unbounded();
+        tokio::spawn(Self::run(manager, refresh_rx));
+        Self { refresh_tx }
+    }
+
+    /// Refresh the expiry immediately, e.g. after logging in.
+    pub fn refresh(&self) {
+        let _ = self.refresh_tx.unbounded_send(());
+    }
+
+    async fn run(manager: AccountManagerHandle, mut refresh_rx: mpsc::UnboundedReceiver<()>) {
+        loop {
+            let delay = match manager.check_expiry().await {
+                Ok(expiry) => Some(next_check_delay(expiry, Utc::now())),
+                // Wait until a device is added
+                Err(Error::NoDevice) => None,
+                Err(Error::AccountManagerDown) => break,
+                Err(error) => {
+                    log::error!(
+                        "{}",
+                        error.display_chain_with_msg("Failed to refresh account expiry")
+                    );
+                    Some(RETRY_INTERVAL)
+                }
+            };
+
+            let mut next_check = match delay {
+                Some(delay) => talpid_time::sleep(delay).boxed().fuse(),
+                None => futures::future::pending::<()>().boxed().fuse(),
+            };
+
+            futures::select! {
+                _ = next_check => (),
+                refresh = refresh_rx.next() => {
+                    if refresh.is_none() {
+                        break;
+                    }
+                }
+            }
+        }
+        log::debug!("Account expiry tracker has stopped");
+    }
+}
+
+/// Returns how long to wait before refreshing an account that expires at `expiry`.
+fn next_check_delay(expiry: DateTime<Utc>, now: DateTime<Utc>) -> Duration {
+    let time_left = match (expiry - now).to_std() {
+        Ok(time_left) if !time_left.is_zero() => time_left,
+        _ => return EXPIRED_CHECK_INTERVAL,
+    };
+
+    if time_left <= CLOSE_TO_EXPIRY {
+        time_left.min(CLOSE_TO_EXPIRY_CHECK_INTERVAL)
+    } else {
+        time_left.min(DEFAULT_CHECK_INTERVAL)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_next_check_delay() {
+        let now = Utc::now();
+
+        assert_eq!(
+            next_check_delay(now - chrono::Duration::hours(1), now),
+            EXPIRED_CHECK_INTERVAL
+        );
+        assert_eq!(next_check_delay(now, now), EXPIRED_CHECK_INTERVAL);
+        assert_eq!(
+            next_check_delay(now + chrono::Duration::minutes(10), now),
+            Duration::from_secs(10 * 60)
+        );
+        assert_eq!(
+            next_check_delay(now + chrono::Duration::days(1), now),
+            CLOSE_TO_EXPIRY_CHECK_INTERVAL
+        );
+        assert_eq!(
+            next_check_delay(now + chrono::Duration::days(30), now),
+            DEFAULT_CHECK_INTERVAL
+        );
+    }
+}
@@ -30,7 +30,9 @@ use tokio::{
 };
 
 mod api;
+mod expiry_tracker;
 mod service;
+pub(crate) use expiry_tracker::ExpiryTracker;
 pub(crate) use service::{AccountService, DeviceService};
 
 /// File that used to store account and device data.
@@ -60,6 +62,8 @@ pub enum Error {
     InvalidVoucher,
     #[error(display = "The voucher has already been used")]
     UsedVoucher,
+    #[error(display = "The voucher has expired")]
+    ExpiredVoucher,
     #[error(display = "Failed to read or write device cache")]
     DeviceIoError(#[error(source)] io::Error),
     #[error(display = "Failed parse device cache")]
@@ -1161,7 +1165,7 @@ impl TunnelStateChangeHandler {
             }
             TunnelStateTransition::Error(_)
             | TunnelStateTransition::Connected(_)
-            | TunnelStateTransition::Disconnected => {
+            | TunnelStateTransition::Disconnected { .. } => {
                 self.check_validity.store(true, Ordering::SeqCst);
                 self.wg_retry_attempt = 0;
             }
@@ -428,6 +428,7 @@ fn map_rest_error(error: rest::Error) -> Error {
             mullvad_api::MAX_DEVICES_REACHED => Error::MaxDevicesReached,
             mullvad_api::INVALID_VOUCHER => Error::InvalidVoucher,
             mullvad_api::VOUCHER_USED => Error::UsedVoucher,
+            mullvad_api::VOUCHER_EXPIRED => Error::ExpiredVoucher,
             _ => Error::OtherRestError(error),
         },
         error => Error::OtherRestError(error),
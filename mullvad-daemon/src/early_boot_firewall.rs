@@ -1,5 +1,6 @@
 use mullvad_daemon::settings::{self, SettingsPersister};
 use talpid_core::firewall::{self, Firewall, FirewallPolicy};
+use talpid_types::net::VpnCoexistence;
 
 #[derive(err_derive::Error, Debug)]
 pub enum Error {
@@ -15,24 +16,26 @@ pub enum Error {
 
 pub async fn initialize_firewall() -> Result<(), Error> {
     let mut firewall = Firewall::new()?;
-    let allow_lan = get_allow_lan().await.unwrap_or_else(|err| {
+    let (allow_lan, vpn_coexistence) = get_settings().await.unwrap_or_else(|err| {
         log::info!(
             "Not allowing LAN traffic due to failing to read settings: {}",
             err
         );
-        false
+        (false, VpnCoexistence::default())
     });
     let policy = FirewallPolicy::Blocked {
         allow_lan,
         allowed_endpoint: None,
+        recovery_allowlist: vec![],
+        vpn_coexistence,
     };
     log::info!("Applying firewall policy {policy}");
     firewall.apply_policy(policy)?;
     Ok(())
 }
 
-async fn get_allow_lan() -> Result<bool, Error> {
+async fn get_settings() -> Result<(bool, VpnCoexistence), Error> {
     let path = mullvad_paths::settings_dir()?;
     let settings = SettingsPersister::load(&path).await;
-    Ok(settings.allow_lan)
+    Ok((settings.allow_lan, settings.vpn_coexistence.clone()))
 }
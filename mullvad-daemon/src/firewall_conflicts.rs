@@ -0,0 +1,48 @@
+use crate::DaemonEventSender;
+use std::time::Duration;
+use talpid_core::{firewall::wfp_conflicts, mpsc::Sender};
+use talpid_types::ErrorExt;
+
+pub use wfp_conflicts::{Conflict, ConflictKind};
+
+/// How often to check for conflicting WFP objects after the initial check at startup.
+const CHECK_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// Result of a check for conflicting WFP objects.
+pub(crate) struct FirewallConflictsEvent(pub Vec<Conflict>);
+
+/// Periodically enumerates WFP sublayers and filters owned by other products and sends the
+/// result to the daemon. The first check is performed immediately.
+pub(crate) fn spawn_monitor(tx: DaemonEventSender<FirewallConflictsEvent>) {
+    tokio::spawn(async move {
+        loop {
+            match tokio::task::spawn_blocking(wfp_conflicts::find_conflicts).await {
+                Ok(Ok(conflicts)) => {
+                    if tx.send(FirewallConflictsEvent(conflicts)).is_err() {
+                        return;
+                    }
+                }
+                Ok(Err(error)) => {
+                    log::error!(
+                        "{}",
+                        error.display_chain_with_msg("Failed to check for WFP conflicts")
+                    );
+                }
+                Err(_) => log::error!("WFP conflict check panicked"),
+            }
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    });
+}
+
+/// Formats a report of conflicting objects for the log.
+pub(crate) fn format_report(conflicts: &[Conflict]) -> String {
+    let mut report = String::from(
+        "Found WFP objects from other products that may override the firewall policy:",
+    );
+    for conflict in conflicts {
+        report.push_str("\n  ");
+        report.push_str(&conflict.to_string());
+    }
+    report
+}
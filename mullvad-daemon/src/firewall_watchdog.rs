@@ -0,0 +1,252 @@
+//! Companion process that blocks all traffic if the daemon dies while the firewall is supposed to
+//! be blocking, e.g. because it crashed or was killed.
+//!
+//! The daemon spawns the watchdog as a child process and tells it over its standard input whether
+//! traffic is currently protected. If the pipe is closed without a [`Message::Shutdown`], the
+//! daemon is gone, and the watchdog replaces whatever rules it left behind with a blocking policy
+//! if traffic was protected. The rules remain after the watchdog exits, until the next daemon
+//! reclaims the firewall when it starts.
+
+use mullvad_types::states::TunnelState;
+use std::{
+    env,
+    io::{self, Read},
+    process::Stdio,
+    time::Duration,
+};
+use talpid_types::ErrorExt;
+use tokio::{
+    io::AsyncWriteExt,
+    process::{Child, ChildStdin, Command},
+    sync::mpsc,
+};
+
+/// Argument that makes the daemon binary run as the watchdog.
+pub const WATCHDOG_ARG: &str = "--firewall-watchdog";
+/// How long to wait before restarting a watchdog that exited unexpectedly.
+const RESPAWN_DELAY: Duration = Duration::from_secs(5);
+
+/// Message sent from the daemon to the watchdog, encoded as a single byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Message {
+    /// Traffic outside the tunnel is allowed.
+    Unprotected = b'0',
+    /// Traffic outside the tunnel is blocked, or the tunnel is up.
+    Protected = b'1',
+    /// The daemon is shutting down cleanly.
+    Shutdown = b'q',
+}
+
+impl Message {
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            b'0' => Some(Message::Unprotected),
+            b'1' => Some(Message::Protected),
+            b'q' => Some(Message::Shutdown),
+            _ => None,
+        }
+    }
+}
+
+/// Handle to the supervised watchdog process.
+pub(crate) struct FirewallWatchdog {
+    tx: mpsc::UnboundedSender<Message>,
+    supervisor: tokio::task::JoinHandle<()>,
+}
+
+impl FirewallWatchdog {
+    /// Spawns the watchdog and restarts it whenever it exits unexpectedly.
+    pub fn spawn(tunnel_state: &TunnelState) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let supervisor = tokio::spawn(supervise(rx, protection_message(tunnel_state)));
+        FirewallWatchdog { tx, supervisor }
+    }
+
+    pub fn set_tunnel_state(&self, tunnel_state: &TunnelState) {
+        let _ = self.tx.send(protection_message(tunnel_state));
+    }
+
+    /// Tells the watchdog that the daemon is shutting down cleanly and waits for it to exit.
+    pub async fn shutdown(self) {
+        let _ = self.tx.send(Message::Shutdown);
+        let _ = self.supervisor.await;
+    }
+}
+
+fn protection_message(tunnel_state: &TunnelState) -> Message {
+    if tunnel_state.is_protected() {
+        Message::Protected
+    } else {
+        Message::Unprotected
+    }
+}
+
+async fn supervise(mut rx: mpsc::UnboundedReceiver<Message>, mut protection: Message) {
+    loop {
+        let (mut child, mut stdin) = match spawn_process() {
+            Ok(process) => process,
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to start firewall watchdog")
+                );
+                return;
+            }
+        };
+        // Writes fail if the process exited, which is noticed when waiting for it below
+        let _ = stdin.write_all(&[protection as u8]).await;
+
+        loop {
+            tokio::select! {
+                message = rx.recv() => {
+                    let message = message.unwrap_or(Message::Shutdown);
+                    let _ = stdin.write_all(&[message as u8]).await;
+                    if message == Message::Shutdown {
+                        drop(stdin);
+                        let _ = child.wait().await;
+                        return;
+                    }
+                    protection = message;
+                }
+                status = child.wait() => {
+                    match status {
+                        Ok(status) => log::error!("Firewall watchdog exited unexpectedly: {}", status),
+                        Err(error) => log::error!(
+                            "{}",
+                            error.display_chain_with_msg("Failed to wait for firewall watchdog")
+                        ),
+                    }
+                    break;
+                }
+            }
+        }
+        tokio::time::sleep(RESPAWN_DELAY).await;
+    }
+}
+
+fn spawn_process() -> io::Result<(Child, ChildStdin)> {
+    let mut child = Command::new(env::current_exe()?)
+        .arg(WATCHDOG_ARG)
+        .stdin(Stdio::piped())
+        .spawn()?;
+    let stdin = child.stdin.take().expect("stdin is piped");
+    log::debug!("Started firewall watchdog");
+    Ok((child, stdin))
+}
+
+/// Runs in the watchdog process. Returns `true` once the daemon has died while traffic was
+/// protected, or `false` if it shut down cleanly.
+pub fn wait_for_daemon() -> bool {
+    // The daemon decides when the watchdog exits, and it must outlive the daemon if it is killed
+    // along with it, e.g. by the service manager
+    for signal in [
+        nix::sys::signal::Signal::SIGINT,
+        nix::sys::signal::Signal::SIGTERM,
+        nix::sys::signal::Signal::SIGHUP,
+    ] {
+        if let Err(error) =
+            unsafe { nix::sys::signal::signal(signal, nix::sys::signal::SigHandler::SigIgn) }
+        {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg(&format!("Failed to ignore {}", signal))
+            );
+        }
+    }
+
+    should_block(io::stdin())
+}
+
+/// Reads messages from the daemon until it goes away. Returns whether traffic should be blocked,
+/// which is the case if the input ends without a [`Message::Shutdown`] while traffic is protected.
+fn should_block(mut input: impl Read) -> bool {
+    let mut protection = Message::Unprotected;
+    let mut byte = [0u8];
+    loop {
+        match input.read(&mut byte) {
+            Ok(0) => return protection == Message::Protected,
+            Ok(_) => match Message::from_byte(byte[0]) {
+                Some(Message::Shutdown) => return false,
+                Some(message) => protection = message,
+                None => log::error!("Unknown message from the daemon: {}", byte[0]),
+            },
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => (),
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to read from the daemon")
+                );
+                return protection == Message::Protected;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::VecDeque;
+
+    /// Returns the result of one read at a time, and then the end of the input.
+    struct Input(VecDeque<Result<u8, io::ErrorKind>>);
+
+    impl Input {
+        fn new(reads: impl IntoIterator<Item = Result<u8, io::ErrorKind>>) -> Self {
+            Input(reads.into_iter().collect())
+        }
+    }
+
+    impl Read for Input {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.0.pop_front() {
+                Some(Ok(byte)) => {
+                    buf[0] = byte;
+                    Ok(1)
+                }
+                Some(Err(kind)) => Err(kind.into()),
+                None => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn test_message_from_byte() {
+        for message in [Message::Unprotected, Message::Protected, Message::Shutdown] {
+            assert_eq!(Message::from_byte(message as u8), Some(message));
+        }
+        assert_eq!(Message::from_byte(b'x'), None);
+        assert_eq!(Message::from_byte(0), None);
+    }
+
+    #[test]
+    fn test_should_block() {
+        // The daemon went away while traffic was protected
+        assert!(should_block(&b"01"[..]));
+        assert!(should_block(&b"1x"[..]));
+        assert!(should_block(Input::new([
+            Ok(b'1'),
+            Err(io::ErrorKind::BrokenPipe),
+            Ok(b'q'),
+        ])));
+
+        // The daemon went away while traffic was not protected
+        assert!(!should_block(&b""[..]));
+        assert!(!should_block(&b"10"[..]));
+
+        // The daemon shut down cleanly
+        assert!(!should_block(&b"1q"[..]));
+        assert!(!should_block(&b"1q1"[..]));
+
+        // Interrupted reads are retried
+        assert!(should_block(Input::new([
+            Ok(b'1'),
+            Err(io::ErrorKind::Interrupted),
+        ])));
+        assert!(!should_block(Input::new([
+            Ok(b'1'),
+            Err(io::ErrorKind::Interrupted),
+            Ok(b'q'),
+        ])));
+    }
+}
@@ -1,14 +1,157 @@
-use futures::join;
+use crate::DaemonEventSender;
+use futures::{
+    future::{abortable, AbortHandle},
+    join, Future,
+};
 use mullvad_api::{
     self,
     rest::{Error, RequestServiceHandle},
 };
 use mullvad_types::location::{AmIMullvad, GeoIpLocation};
+use std::time::Duration;
+use talpid_core::{
+    future_retry::{retry_future_n, ExponentialBackoff, Jittered},
+    mpsc::Sender,
+};
 use talpid_types::ErrorExt;
 
 const URI_V4: &str = "https://ipv4.am.i.mullvad.net/json";
 const URI_V6: &str = "https://ipv6.am.i.mullvad.net/json";
 
+/// Lookups made right after the tunnel comes up may fail while the tunnel is still settling, so
+/// they are retried a few times.
+const LOCATION_RETRY_INITIAL_DELAY: Duration = Duration::from_secs(1);
+const LOCATION_RETRY_MAX_DELAY: Duration = Duration::from_secs(8);
+const LOCATION_RETRY_MAX_RETRIES: usize = 4;
+
+/// Result of a location lookup started by [`GeoIpHandler`].
+pub(crate) struct LocationEvent {
+    request_id: usize,
+    location: GeoIpLocation,
+}
+
+/// Caches the location of the current exit IP.
+///
+/// The cache is invalidated whenever the tunnel state changes. Lookups that complete after
+/// the cache was invalidated are discarded, so a result can never be attributed to the wrong
+/// tunnel state.
+pub(crate) struct GeoIpHandler {
+    request_id: usize,
+    abort_handle: Option<AbortHandle>,
+    location: Option<GeoIpLocation>,
+    tx: DaemonEventSender<LocationEvent>,
+}
+
+impl GeoIpHandler {
+    pub fn new(tx: DaemonEventSender<LocationEvent>) -> Self {
+        Self {
+            request_id: 0,
+            abort_handle: None,
+            location: None,
+            tx,
+        }
+    }
+
+    /// Returns the cached location, if there is one.
+    pub fn location(&self) -> Option<&GeoIpLocation> {
+        self.location.as_ref()
+    }
+
+    /// Drops the cached location and cancels any in-flight lookup.
+    pub fn invalidate(&mut self) {
+        self.abort_request();
+        self.request_id = self.request_id.wrapping_add(1);
+        self.location = None;
+    }
+
+    /// Starts a lookup in the background. The lookup is retried a few times if it fails, and
+    /// the result is sent to the daemon as a [`LocationEvent`].
+    pub fn send_request(&mut self, rest_service: RequestServiceHandle, use_ipv6: bool) {
+        self.abort_request();
+
+        let request_id = self.request_id;
+        let tx = self.tx.clone();
+        let (request, abort_handle) = abortable(async move {
+            let delays = Jittered::jitter(
+                ExponentialBackoff::new(LOCATION_RETRY_INITIAL_DELAY, 2)
+                    .max_delay(LOCATION_RETRY_MAX_DELAY),
+            );
+            let result = retry_future_n(
+                move || send_location_request(rest_service.clone(), use_ipv6),
+                |result| result.is_err(),
+                delays,
+                LOCATION_RETRY_MAX_RETRIES,
+            )
+            .await;
+            match result {
+                Ok(location) => {
+                    let _ = tx.send(LocationEvent {
+                        request_id,
+                        location,
+                    });
+                }
+                Err(error) => {
+                    log::warn!(
+                        "{}",
+                        error.display_chain_with_msg("Unable to fetch GeoIP location")
+                    );
+                }
+            }
+        });
+        tokio::spawn(request);
+        self.abort_handle = Some(abort_handle);
+    }
+
+    /// Returns a future that performs a single lookup. The result is also stored in the cache,
+    /// unless the cache has been invalidated before the lookup completes.
+    pub fn lookup(
+        &self,
+        rest_service: RequestServiceHandle,
+        use_ipv6: bool,
+    ) -> impl Future<Output = Result<GeoIpLocation, Error>> {
+        let request_id = self.request_id;
+        let tx = self.tx.clone();
+        async move {
+            let location = send_location_request(rest_service, use_ipv6).await?;
+            let _ = tx.send(LocationEvent {
+                request_id,
+                location: location.clone(),
+            });
+            Ok(location)
+        }
+    }
+
+    /// Stores the result of a lookup. Returns the new location, or `None` if the lookup was
+    /// started before the cache was last invalidated.
+    pub fn handle_event(&mut self, event: LocationEvent) -> Option<&GeoIpLocation> {
+        if event.request_id != self.request_id {
+            log::trace!("Discarding stale GeoIP location");
+            return None;
+        }
+        self.abort_handle = None;
+        self.location = Some(event.location);
+        self.location.as_ref()
+    }
+
+    fn abort_request(&mut self) {
+        if let Some(abort_handle) = self.abort_handle.take() {
+            abort_handle.abort();
+        }
+    }
+}
+
+/// Combines the exit IPs of a fetched location with the location of the relay, if it is known.
+pub fn merge_relay_location(
+    relay_location: Option<GeoIpLocation>,
+    fetched_location: GeoIpLocation,
+) -> GeoIpLocation {
+    GeoIpLocation {
+        ipv4: fetched_location.ipv4,
+        ipv6: fetched_location.ipv6,
+        ..relay_location.unwrap_or(fetched_location)
+    }
+}
+
 pub async fn send_location_request(
     request_sender: RequestServiceHandle,
     use_ipv6: bool,
@@ -0,0 +1,104 @@
+//! Quick checks of the internal components of the daemon.
+//!
+//! The checks are cheap enough to run on demand, e.g. from a troubleshooting view or from the
+//! watchdog of an init system.
+
+use std::{future::Future, io, path::Path, time::Duration};
+use talpid_core::tunnel_state_machine::CommandError;
+use talpid_types::health::{HealthCheck, HealthReport};
+
+/// Name of the check that fails if the tunnel state machine does not respond.
+pub(crate) const STATE_MACHINE_CHECK: &str = "tunnel state machine";
+/// How long to wait for the tunnel state machine to respond.
+const STATE_MACHINE_TIMEOUT: Duration = Duration::from_secs(2);
+/// Logs may stop being written if less disk space than this is available.
+const MIN_LOG_DISK_SPACE: u64 = 10 * 1024 * 1024;
+/// Having less disk space than this available for logs is reported as a warning.
+const LOW_LOG_DISK_SPACE: u64 = 100 * 1024 * 1024;
+
+/// Collects the results of all checks. `state_machine_checks` completes with the checks performed
+/// by the tunnel state machine.
+pub(crate) async fn check(
+    state_machine_checks: impl Future<Output = Result<Vec<HealthCheck>, CommandError>>,
+    log_dir: Option<&Path>,
+) -> HealthReport {
+    let mut checks = vec![];
+
+    match tokio::time::timeout(STATE_MACHINE_TIMEOUT, state_machine_checks).await {
+        Ok(Ok(state_machine_checks)) => {
+            checks.push(HealthCheck::pass(STATE_MACHINE_CHECK));
+            checks.extend(state_machine_checks);
+        }
+        Ok(Err(CommandError::NoResponse)) => checks.push(HealthCheck::fail(
+            STATE_MACHINE_CHECK,
+            "the state machine dropped the request",
+        )),
+        Ok(Err(CommandError::ShutDown)) => checks.push(HealthCheck::fail(
+            STATE_MACHINE_CHECK,
+            "the command channel is closed",
+        )),
+        Err(_) => checks.push(HealthCheck::fail(
+            STATE_MACHINE_CHECK,
+            format!("no response within {} s", STATE_MACHINE_TIMEOUT.as_secs()),
+        )),
+    }
+
+    if let Some(log_dir) = log_dir {
+        checks.push(check_log_disk_space(log_dir));
+    }
+
+    HealthReport { checks }
+}
+
+fn check_log_disk_space(log_dir: &Path) -> HealthCheck {
+    match available_disk_space(log_dir) {
+        Ok(available) if available < MIN_LOG_DISK_SPACE => HealthCheck::fail(
+            "log disk space",
+            format!("{} MiB available", available / (1024 * 1024)),
+        ),
+        Ok(available) if available < LOW_LOG_DISK_SPACE => HealthCheck::warn(
+            "log disk space",
+            format!("{} MiB available", available / (1024 * 1024)),
+        ),
+        Ok(_) => HealthCheck::pass("log disk space"),
+        Err(error) => HealthCheck::warn(
+            "log disk space",
+            format!("failed to get available disk space: {}", error),
+        ),
+    }
+}
+
+#[cfg(unix)]
+fn available_disk_space(path: &Path) -> io::Result<u64> {
+    let stat = nix::sys::statvfs::statvfs(path)
+        .map_err(|errno| io::Error::from_raw_os_error(errno as i32))?;
+    #[allow(clippy::useless_conversion)]
+    Ok(u64::from(stat.blocks_available()) * u64::from(stat.fragment_size()))
+}
+
+#[cfg(windows)]
+fn available_disk_space(path: &Path) -> io::Result<u64> {
+    use std::{iter, os::windows::ffi::OsStrExt};
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    let path: Vec<u16> = path
+        .as_os_str()
+        .encode_wide()
+        .chain(iter::once(0u16))
+        .collect();
+    let mut available = 0u64;
+    // SAFETY: `path` is a null-terminated wide string, and the other arguments are either valid
+    // pointers or null.
+    let result = unsafe {
+        GetDiskFreeSpaceExW(
+            path.as_ptr(),
+            &mut available,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    };
+    if result == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(available)
+}
@@ -0,0 +1,348 @@
+//! Runs the programs configured in [`Settings::hooks`](mullvad_types::settings::Settings) when
+//! the tunnel state changes.
+//!
+//! Hooks are run one at a time, in the order that the states were entered. A hook is never run as
+//! root. Instead, it is run as the user that owns the program, which must not be writable by
+//! anyone else. Unless the hook was added by root, the owner must also be the user that added it,
+//! so that users cannot have programs of other users run on their behalf. The program is run directly, without a shell, in an empty environment except for
+//! a few basic variables and the `MULLVAD_*` variables that describe the new tunnel state. Hooks
+//! that do not finish within [`HOOK_TIMEOUT`] are killed.
+
+use mullvad_types::{
+    hooks::{Hook, HookEvent},
+    states::TunnelState,
+};
+use nix::unistd::{Uid, User};
+use std::{
+    fs, io,
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    path::Path,
+    process::{ExitStatus, Stdio},
+    time::Duration,
+};
+use talpid_types::{
+    net::{TransportProtocol, TunnelType},
+    ErrorExt,
+};
+use tokio::{process::Command, sync::mpsc};
+
+/// How long a hook may run before it is killed.
+const HOOK_TIMEOUT: Duration = Duration::from_secs(30);
+/// `PATH` of the environment that hooks are run in.
+const HOOK_PATH: &str = "/usr/local/bin:/usr/bin:/bin";
+
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    /// Failed to read the metadata of the program
+    #[error(display = "Failed to access the program")]
+    Metadata(#[error(source)] io::Error),
+
+    /// The program is not a regular file
+    #[error(display = "The program is not a file")]
+    NotAFile,
+
+    /// Hooks are never run as root
+    #[error(display = "The program is owned by root")]
+    OwnedByRoot,
+
+    /// Users other than the owner could change what the hook does
+    #[error(display = "The program is writable by users other than its owner")]
+    WritableByOthers,
+
+    /// The hook was added by a user other than the owner of the program
+    #[error(display = "The program is not owned by the user that added the hook")]
+    NotOwnedByCaller,
+
+    /// The owner of the program could not be looked up
+    #[error(display = "Failed to look up the owner of the program")]
+    LookUpOwner(#[error(source)] nix::Error),
+
+    /// The owner of the program does not exist
+    #[error(display = "The owner of the program does not exist: {}", _0)]
+    UnknownOwner(u32),
+
+    /// Failed to start the program
+    #[error(display = "Failed to start the program")]
+    Spawn(#[error(source)] io::Error),
+
+    /// Failed to wait for the program to exit
+    #[error(display = "Failed to wait for the program")]
+    Wait(#[error(source)] io::Error),
+
+    /// The program did not exit in time and was killed
+    #[error(display = "The program timed out")]
+    Timeout,
+
+    /// The program exited unsuccessfully
+    #[error(display = "The program failed ({}): {}", _0, _1)]
+    Failed(ExitStatus, String),
+}
+
+/// Queues hooks to be run for each new tunnel state.
+pub struct HookRunner {
+    tx: mpsc::UnboundedSender<(Vec<Hook>, Vec<(&'static str, String)>)>,
+}
+
+impl HookRunner {
+    pub fn new() -> Self {
+        let (tx, mut rx) = mpsc::unbounded_channel::<(Vec<Hook>, Vec<_>)>();
+        tokio::spawn(async move {
+            while let Some((hooks, env)) = rx.recv().await {
+                for hook in hooks {
+                    log::debug!("Running {} hook {}", hook.event, hook.program.display());
+                    if let Err(error) = run(&hook, &env).await {
+                        log::error!(
+                            "{}",
+                            error.display_chain_with_msg(&format!(
+                                "Hook {} failed",
+                                hook.program.display()
+                            ))
+                        );
+                    }
+                }
+            }
+        });
+        HookRunner { tx }
+    }
+
+    /// Runs the hooks among `hooks` that are registered for `tunnel_state`, if any.
+    pub fn set_tunnel_state(&self, hooks: &[Hook], tunnel_state: &TunnelState) {
+        let event = match tunnel_state {
+            TunnelState::Connected { .. } => HookEvent::Connected,
+            TunnelState::Disconnected { .. } => HookEvent::Disconnected,
+            TunnelState::Error(_) => HookEvent::Error,
+            _ => return,
+        };
+        let hooks: Vec<_> = hooks
+            .iter()
+            .filter(|hook| hook.event == event)
+            .cloned()
+            .collect();
+        if hooks.is_empty() {
+            return;
+        }
+        let _ = self.tx.send((hooks, state_env(event, tunnel_state)));
+    }
+}
+
+/// Returns the variables that describe `tunnel_state` to hooks.
+fn state_env(event: HookEvent, tunnel_state: &TunnelState) -> Vec<(&'static str, String)> {
+    let mut env = vec![("MULLVAD_EVENT", event.to_string())];
+    match tunnel_state {
+        TunnelState::Connected { endpoint, location } => {
+            let tunnel_type = match endpoint.tunnel_type {
+                TunnelType::Wireguard => "wireguard",
+                TunnelType::OpenVpn => "openvpn",
+            };
+            let protocol = match endpoint.endpoint.protocol {
+                TransportProtocol::Udp => "udp",
+                TransportProtocol::Tcp => "tcp",
+            };
+            env.push(("MULLVAD_TUNNEL_TYPE", tunnel_type.to_owned()));
+            env.push(("MULLVAD_ENDPOINT", endpoint.endpoint.address.to_string()));
+            env.push(("MULLVAD_TRANSPORT_PROTOCOL", protocol.to_owned()));
+            if let Some(location) = location {
+                env.push(("MULLVAD_COUNTRY", location.country.clone()));
+                if let Some(city) = &location.city {
+                    env.push(("MULLVAD_CITY", city.clone()));
+                }
+                if let Some(hostname) = &location.hostname {
+                    env.push(("MULLVAD_HOSTNAME", hostname.clone()));
+                }
+            }
+        }
+        TunnelState::Error(error_state) => {
+            env.push(("MULLVAD_ERROR_CAUSE", error_state.cause().to_string()));
+            env.push(("MULLVAD_BLOCKING", error_state.is_blocking().to_string()));
+        }
+        _ => (),
+    }
+    env
+}
+
+async fn run(hook: &Hook, env: &[(&'static str, String)]) -> Result<(), Error> {
+    let owner = program_owner(&hook.program, hook.added_by)?;
+
+    let mut command = Command::new(&hook.program);
+    command
+        .env_clear()
+        .env("PATH", HOOK_PATH)
+        .env("HOME", &owner.dir)
+        .env("USER", &owner.name)
+        .env("LOGNAME", &owner.name)
+        .envs(env.iter().map(|(key, value)| (*key, value)))
+        .current_dir("/")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        // Supplementary groups of the daemon are dropped when the user is changed
+        .uid(owner.uid.as_raw())
+        .gid(owner.gid.as_raw())
+        .kill_on_drop(true);
+    let child = command.spawn().map_err(Error::Spawn)?;
+
+    let output = tokio::time::timeout(HOOK_TIMEOUT, child.wait_with_output())
+        .await
+        .map_err(|_| Error::Timeout)?
+        .map_err(Error::Wait)?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+        return Err(Error::Failed(output.status, stderr));
+    }
+    Ok(())
+}
+
+/// Checks that a user other than root may add a hook that runs `program`.
+pub fn check_caller_owns(program: &Path, caller_uid: u32) -> Result<(), Error> {
+    program_owner(program, Some(caller_uid)).map(|_| ())
+}
+
+/// Returns the user that `program` is run as, after checking that it may be run by a hook that
+/// was added by `added_by`.
+fn program_owner(program: &Path, added_by: Option<u32>) -> Result<User, Error> {
+    let metadata = fs::metadata(program).map_err(Error::Metadata)?;
+    let uid = check_program(
+        metadata.is_file(),
+        metadata.uid(),
+        metadata.permissions().mode(),
+        added_by,
+    )?;
+    User::from_uid(uid)
+        .map_err(Error::LookUpOwner)?
+        .ok_or_else(|| Error::UnknownOwner(uid.as_raw()))
+}
+
+/// Returns the user that a program with the given properties is run as.
+fn check_program(
+    is_file: bool,
+    owner: u32,
+    mode: u32,
+    added_by: Option<u32>,
+) -> Result<Uid, Error> {
+    if !is_file {
+        return Err(Error::NotAFile);
+    }
+    let uid = Uid::from_raw(owner);
+    if uid.is_root() {
+        return Err(Error::OwnedByRoot);
+    }
+    if mode & 0o022 != 0 {
+        return Err(Error::WritableByOthers);
+    }
+    match added_by {
+        Some(caller) if caller != 0 && caller != owner => Err(Error::NotOwnedByCaller),
+        _ => Ok(uid),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mullvad_types::location::GeoIpLocation;
+    use talpid_types::{
+        net::{Endpoint, TunnelEndpoint},
+        tunnel::{DisconnectedSecurity, ErrorState, ErrorStateCause},
+    };
+
+    #[test]
+    fn test_check_program() {
+        assert_eq!(
+            check_program(true, 1000, 0o755, None).unwrap(),
+            Uid::from_raw(1000)
+        );
+        assert_eq!(
+            check_program(true, 1000, 0o700, Some(0)).unwrap(),
+            Uid::from_raw(1000)
+        );
+        assert_eq!(
+            check_program(true, 1000, 0o755, Some(1000)).unwrap(),
+            Uid::from_raw(1000)
+        );
+
+        assert!(matches!(
+            check_program(false, 1000, 0o755, None),
+            Err(Error::NotAFile)
+        ));
+        assert!(matches!(
+            check_program(true, 0, 0o755, None),
+            Err(Error::OwnedByRoot)
+        ));
+        assert!(matches!(
+            check_program(true, 1000, 0o775, None),
+            Err(Error::WritableByOthers)
+        ));
+        assert!(matches!(
+            check_program(true, 1000, 0o757, None),
+            Err(Error::WritableByOthers)
+        ));
+        // Users may not have programs of other users run
+        assert!(matches!(
+            check_program(true, 1000, 0o755, Some(1001)),
+            Err(Error::NotOwnedByCaller)
+        ));
+    }
+
+    #[test]
+    fn test_state_env() {
+        let tunnel_state = TunnelState::Connected {
+            endpoint: TunnelEndpoint {
+                endpoint: Endpoint::new([10, 0, 0, 1], 51820, TransportProtocol::Udp),
+                tunnel_type: TunnelType::Wireguard,
+                quantum_resistant: false,
+                proxy: None,
+                obfuscation: None,
+                entry_endpoint: None,
+                wireguard_backend: None,
+            },
+            location: Some(GeoIpLocation {
+                ipv4: None,
+                ipv6: None,
+                country: "Sweden".to_owned(),
+                city: Some("Gothenburg".to_owned()),
+                latitude: 0.0,
+                longitude: 0.0,
+                mullvad_exit_ip: true,
+                hostname: Some("se-got-wg-001".to_owned()),
+                bridge_hostname: None,
+                entry_hostname: None,
+                obfuscator_hostname: None,
+            }),
+        };
+        assert_eq!(
+            state_env(HookEvent::Connected, &tunnel_state),
+            vec![
+                ("MULLVAD_EVENT", "connected".to_owned()),
+                ("MULLVAD_TUNNEL_TYPE", "wireguard".to_owned()),
+                ("MULLVAD_ENDPOINT", "10.0.0.1:51820".to_owned()),
+                ("MULLVAD_TRANSPORT_PROTOCOL", "udp".to_owned()),
+                ("MULLVAD_COUNTRY", "Sweden".to_owned()),
+                ("MULLVAD_CITY", "Gothenburg".to_owned()),
+                ("MULLVAD_HOSTNAME", "se-got-wg-001".to_owned()),
+            ]
+        );
+
+        let tunnel_state =
+            TunnelState::Error(ErrorState::new(ErrorStateCause::IsOffline, None, vec![]));
+        assert_eq!(
+            state_env(HookEvent::Error, &tunnel_state),
+            vec![
+                ("MULLVAD_EVENT", "error".to_owned()),
+                (
+                    "MULLVAD_ERROR_CAUSE",
+                    ErrorStateCause::IsOffline.to_string()
+                ),
+                ("MULLVAD_BLOCKING", "true".to_owned()),
+            ]
+        );
+
+        let tunnel_state = TunnelState::Disconnected {
+            cause: None,
+            security: DisconnectedSecurity::Unsecured,
+        };
+        assert_eq!(
+            state_env(HookEvent::Disconnected, &tunnel_state),
+            vec![("MULLVAD_EVENT", "disconnected".to_owned())]
+        );
+    }
+}
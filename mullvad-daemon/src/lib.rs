@@ -8,49 +8,83 @@ pub mod account_history;
 mod api;
 #[cfg(not(target_os = "android"))]
 mod cleanup;
+mod clock_skew;
+mod connection_stats;
 pub mod device;
 mod dns;
 pub mod exception_logging;
+#[cfg(windows)]
+pub mod firewall_conflicts;
+#[cfg(target_os = "linux")]
+pub mod firewall_watchdog;
 mod geoip;
+mod health;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod hooks;
 pub mod logging;
 #[cfg(target_os = "macos")]
 mod macos;
 #[cfg(not(target_os = "android"))]
 pub mod management_interface;
 mod migrations;
+mod preflight;
+mod protection;
+mod protection_pause;
+mod relay_probe;
 #[cfg(not(target_os = "android"))]
 pub mod rpc_uniqueness_check;
 pub mod runtime;
+#[cfg(windows)]
+pub mod service_control;
 pub mod settings;
+pub mod settings_plan;
 pub mod shutdown;
+#[cfg(target_os = "linux")]
+pub mod socket_bypass;
+#[cfg(target_os = "linux")]
+mod systemd;
 mod target_state;
 mod tunnel;
 pub mod version;
 mod version_check;
 
-use crate::target_state::PersistentTargetState;
+use crate::{
+    clock_skew::ClockSkewChanged,
+    connection_stats::ConnectionStatsTracker,
+    protection::ProtectionTracker,
+    protection_pause::{ProtectionPause, ProtectionPauseExpired},
+    target_state::PersistentTargetState,
+};
+use chrono::{DateTime, Utc};
 use device::{AccountEvent, PrivateAccountAndDevice, PrivateDeviceEvent};
 use futures::{
     channel::{mpsc, oneshot},
     future::{abortable, AbortHandle, Future, LocalBoxFuture},
     StreamExt,
 };
+use ipnetwork::IpNetwork;
 use mullvad_relay_selector::{
     updater::{RelayListUpdater, RelayListUpdaterHandle},
     RelaySelector, SelectorConfig,
 };
 use mullvad_types::{
     account::{AccountData, AccountToken, VoucherSubmission},
+    auth_failed,
+    clock_skew::ClockSkew,
     device::{Device, DeviceEvent, DeviceEventCause, DeviceId, DeviceState, RemoveDeviceEvent},
+    hooks::Hook,
     location::GeoIpLocation,
-    relay_constraints::{BridgeSettings, BridgeState, ObfuscationSettings, RelaySettingsUpdate},
-    relay_list::RelayList,
-    settings::{DnsOptions, Settings},
-    states::{TargetState, TunnelState},
+    relay_constraints::{
+        BridgeSettings, BridgeState, ObfuscationSettings, RelayQuery, RelaySettingsUpdate,
+    },
+    relay_list::{RelayList, RelayQueryMatch},
+    settings::{DnsOptions, Profile, Settings, MAX_PROFILES},
+    states::{ProtectionGapReport, TargetState, TunnelState},
     version::{AppVersion, AppVersionInfo},
     wireguard::{PublicKey, RotationInterval},
 };
 use settings::SettingsPersister;
+use settings_plan::{ApplyOperation, ApplyPlan, CurrentTunnel};
 #[cfg(target_os = "android")]
 use std::os::unix::io::RawFd;
 #[cfg(target_os = "windows")]
@@ -63,6 +97,8 @@ use std::{
     sync::{Arc, Weak},
     time::Duration,
 };
+#[cfg(not(target_os = "android"))]
+use talpid_core::routing;
 #[cfg(any(target_os = "linux", windows))]
 use talpid_core::split_tunnel;
 use talpid_core::{
@@ -71,18 +107,38 @@ use talpid_core::{
 };
 #[cfg(target_os = "android")]
 use talpid_types::android::AndroidContext;
+#[cfg(not(target_os = "android"))]
+use talpid_types::net::{route_takeover::CompetingVpn, PhysicalInterfaceDetails};
 use talpid_types::{
-    net::{TunnelEndpoint, TunnelType},
-    tunnel::{ErrorStateCause, TunnelStateTransition},
+    health::{HealthReport, HealthStatus},
+    net::{
+        route_takeover::RouteTakeoverPolicy,
+        wireguard::{BandwidthLimit, TrafficShapingOptions},
+        BlockedTunnelProtocols, TunnelEndpoint, TunnelType, VpnCoexistence,
+    },
+    performance::PerformanceWarning,
+    system_state::SystemState,
+    traffic_query::{TrafficQuery, TrafficVerdict},
+    tunnel::{
+        DisconnectCause, ErrorStateCause, ErrorStatePolicy, ReconnectLimits, TunnelStateTransition,
+        VersionedTunnelStateTransition,
+    },
     ErrorExt,
 };
 #[cfg(any(target_os = "macos", target_os = "linux"))]
 use tokio::fs;
 use tokio::io;
 
+/// Directory in the settings directory that signed firewall policy plugins are loaded from
+const FIREWALL_PLUGIN_DIR: &str = "firewall-plugins";
+
 /// Delay between generating a new WireGuard key and reconnecting
 const WG_RECONNECT_DELAY: Duration = Duration::from_secs(4 * 60);
 
+/// The tunnel state machine only stops when the daemon shuts down, after which no commands are
+/// sent to it.
+const TUNNEL_STATE_MACHINE_STOPPED: &str = "Tunnel state machine has stopped";
+
 pub type ResponseTx<T, E> = oneshot::Sender<Result<T, E>>;
 
 #[derive(err_derive::Error, Debug)]
@@ -130,10 +186,17 @@ pub enum Error {
     #[error(display = "Failed to submit voucher")]
     VoucherSubmission(#[error(source)] device::Error),
 
+    #[error(display = "Failed to fetch payment status")]
+    PaymentStatus(#[error(source)] device::Error),
+
     #[cfg(target_os = "linux")]
     #[error(display = "Unable to initialize split tunneling")]
     InitSplitTunneling(#[error(source)] split_tunnel::Error),
 
+    #[cfg(target_os = "linux")]
+    #[error(display = "The container does not grant the daemon what it requires")]
+    ContainerRequirement(#[error(source)] talpid_core::container::RequirementError),
+
     #[cfg(windows)]
     #[error(display = "Split tunneling error")]
     SplitTunnelError(#[error(source)] split_tunnel::Error),
@@ -147,6 +210,18 @@ pub enum Error {
     #[error(display = "No account history available for the token")]
     NoAccountTokenHistory,
 
+    #[error(display = "No profile is named \"{}\"", _0)]
+    ProfileNotFound(String),
+
+    #[error(display = "No more than {} profiles can be saved", MAX_PROFILES)]
+    TooManyProfiles,
+
+    #[error(
+        display = "Protection can only be paused for between one second and {} hours",
+        protection_pause::MAX_PAUSE_HOURS
+    )]
+    InvalidPauseDuration,
+
     #[error(display = "Settings error")]
     SettingsError(#[error(source)] settings::Error),
 
@@ -171,6 +246,16 @@ pub enum DaemonCommand {
     SetTargetState(oneshot::Sender<bool>, TargetState),
     /// Reconnect the tunnel, if one is connecting/connected.
     Reconnect(oneshot::Sender<bool>),
+    /// Close the tunnel, if one is connecting/connected, while still blocking traffic outside of
+    /// it. Returns whether the tunnel is being paused.
+    PauseTunnel(oneshot::Sender<bool>),
+    /// Reopen a paused tunnel. Returns whether the tunnel was paused.
+    ResumeTunnel(oneshot::Sender<bool>),
+    /// Disconnect without blocking traffic until the given duration has passed, and then
+    /// reconnect. Returns when protection will be restored.
+    PauseProtection(ResponseTx<DateTime<Utc>, Error>, Duration),
+    /// Return when protection will be restored, if it is paused.
+    GetProtectionPause(oneshot::Sender<Option<DateTime<Utc>>>),
     /// Request the current state.
     GetState(oneshot::Sender<TunnelState>),
     /// Get the current geographical location.
@@ -185,6 +270,8 @@ pub enum DaemonCommand {
     GetWwwAuthToken(ResponseTx<String, Error>),
     /// Submit voucher to add time to the current account. Returns time added in seconds
     SubmitVoucher(ResponseTx<VoucherSubmission, Error>, String),
+    /// Request the current expiry of the logged in account
+    GetPaymentStatus(ResponseTx<AccountData, Error>),
     /// Request account history
     GetAccountHistory(oneshot::Sender<Option<AccountToken>>),
     /// Remove the last used account, if there is one
@@ -194,6 +281,8 @@ pub enum DaemonCommand {
     /// Trigger an asynchronous relay list update. This returns before the relay list is actually
     /// updated.
     UpdateRelayLocations,
+    /// Return the relays that match a query, with the latency to some of them estimated.
+    QueryRelays(oneshot::Sender<Vec<RelayQueryMatch>>, RelayQuery),
     /// Log in with a given account and create a new device.
     LoginAccount(ResponseTx<(), Error>, AccountToken),
     /// Log out of the current account and remove the device, if they exist.
@@ -209,32 +298,77 @@ pub enum DaemonCommand {
     /// Place constraints on the type of tunnel and relay
     UpdateRelaySettings(ResponseTx<(), settings::Error>, RelaySettingsUpdate),
     /// Set the allow LAN setting.
-    SetAllowLan(ResponseTx<(), settings::Error>, bool),
+    SetAllowLan(ResponseTx<ApplyPlan, settings::Error>, bool),
     /// Set the beta program setting.
     SetShowBetaReleases(ResponseTx<(), settings::Error>, bool),
     /// Set the block_when_disconnected setting.
-    SetBlockWhenDisconnected(ResponseTx<(), settings::Error>, bool),
+    SetBlockWhenDisconnected(ResponseTx<ApplyPlan, settings::Error>, bool),
+    /// Set whether to flush the system DNS cache after changing DNS settings.
+    SetFlushDnsCache(ResponseTx<ApplyPlan, settings::Error>, bool),
+    /// Set networks that are allowed in the error state.
+    SetRecoveryAllowlist(ResponseTx<ApplyPlan, settings::Error>, Vec<IpNetwork>),
+    /// Set the traffic of another VPN that is exempt from blocking and from the tunnel.
+    SetVpnCoexistence(ResponseTx<ApplyPlan, settings::Error>, VpnCoexistence),
+    /// Set the protocols to block inside the tunnel while connected.
+    SetBlockedTunnelProtocols(
+        ResponseTx<ApplyPlan, settings::Error>,
+        BlockedTunnelProtocols,
+    ),
+    /// Set the physical interface that traffic to the relay must leave through.
+    SetForcedInterface(ResponseTx<(), settings::Error>, Option<String>),
+    /// Set the maximum number of consecutive reconnect attempts for each cause.
+    SetReconnectLimits(ResponseTx<ApplyPlan, settings::Error>, ReconnectLimits),
+    /// Set what to do when another VPN takes over the default route while connected.
+    SetRouteTakeoverPolicy(ResponseTx<ApplyPlan, settings::Error>, RouteTakeoverPolicy),
+    /// Set which traffic to block in the error state
+    SetErrorStatePolicy(ResponseTx<ApplyPlan, settings::Error>, ErrorStatePolicy),
     /// Set the auto-connect setting.
     SetAutoConnect(ResponseTx<(), settings::Error>, bool),
+    /// Set whether to keep statistics about the outcomes of connection attempts
+    SetConnectionStats(ResponseTx<(), settings::Error>, bool),
+    /// Set the programs to run when the tunnel state changes
+    SetHooks(ResponseTx<(), settings::Error>, Vec<Hook>),
     /// Set the mssfix argument for OpenVPN
-    SetOpenVpnMssfix(ResponseTx<(), settings::Error>, Option<u16>),
+    SetOpenVpnMssfix(ResponseTx<ApplyPlan, settings::Error>, Option<u16>),
     /// Set proxy details for OpenVPN
-    SetBridgeSettings(ResponseTx<(), settings::Error>, BridgeSettings),
+    SetBridgeSettings(ResponseTx<ApplyPlan, settings::Error>, BridgeSettings),
     /// Set proxy state
-    SetBridgeState(ResponseTx<(), settings::Error>, BridgeState),
+    SetBridgeState(ResponseTx<ApplyPlan, settings::Error>, BridgeState),
     /// Set if IPv6 should be enabled in the tunnel
-    SetEnableIpv6(ResponseTx<(), settings::Error>, bool),
+    SetEnableIpv6(ResponseTx<ApplyPlan, settings::Error>, bool),
+    /// Set if IPv4 should be enabled in the tunnel
+    SetEnableIpv4(ResponseTx<ApplyPlan, settings::Error>, bool),
     /// Set whether to enable PQ PSK exchange in the tunnel
-    SetQuantumResistantTunnel(ResponseTx<(), settings::Error>, bool),
+    SetQuantumResistantTunnel(ResponseTx<ApplyPlan, settings::Error>, bool),
+    /// Set traffic shaping options for WireGuard tunnels
+    SetWireguardTrafficShaping(
+        ResponseTx<ApplyPlan, settings::Error>,
+        TrafficShapingOptions,
+    ),
+    /// Set the maximum throughput of WireGuard tunnels
+    SetWireguardBandwidthLimit(ResponseTx<ApplyPlan, settings::Error>, BandwidthLimit),
+    /// Set the networks to route through WireGuard tunnels, or `None` to route all traffic
+    SetWireguardAllowedIps(
+        ResponseTx<ApplyPlan, settings::Error>,
+        Option<Vec<IpNetwork>>,
+    ),
     /// Set DNS options or servers to use
-    SetDnsOptions(ResponseTx<(), settings::Error>, DnsOptions),
+    SetDnsOptions(ResponseTx<ApplyPlan, settings::Error>, DnsOptions),
     /// Toggle macOS network check leak
     /// Set MTU for wireguard tunnels
-    SetWireguardMtu(ResponseTx<(), settings::Error>, Option<u16>),
+    SetWireguardMtu(ResponseTx<ApplyPlan, settings::Error>, Option<u16>),
     /// Set automatic key rotation interval for wireguard tunnels
     SetWireguardRotationInterval(ResponseTx<(), settings::Error>, Option<RotationInterval>),
     /// Get the daemon settings
     GetSettings(oneshot::Sender<Settings>),
+    /// Get the saved profiles
+    GetProfiles(oneshot::Sender<Vec<Profile>>),
+    /// Save the current settings as a profile, replacing any profile with the same name
+    SaveProfile(ResponseTx<(), Error>, String),
+    /// Remove a saved profile
+    RemoveProfile(ResponseTx<(), Error>, String),
+    /// Apply the settings of a saved profile
+    ApplyProfile(ResponseTx<ApplyPlan, Error>, String),
     /// Generate new wireguard key
     RotateWireguardKey(ResponseTx<(), Error>),
     /// Return a public key of the currently set wireguard private key, if there is one
@@ -245,6 +379,20 @@ pub enum DaemonCommand {
     IsPerformingPostUpgrade(oneshot::Sender<bool>),
     /// Get current version of the app
     GetCurrentVersion(oneshot::Sender<AppVersion>),
+    /// Run quick checks of the internal components of the daemon
+    HealthCheck(oneshot::Sender<HealthReport>),
+    /// Return the results of the checks for common problems that were run at startup
+    GetPreflightReport(oneshot::Sender<HealthReport>),
+    /// Describe the firewall policy, routes and DNS settings that are currently applied
+    GetSystemState(oneshot::Sender<SystemState>),
+    /// Return the period during which the network may have been unprotected because the
+    /// previous instance of the daemon or the system stopped while secured, if there was one
+    GetProtectionGapReport(oneshot::Sender<Option<ProtectionGapReport>>),
+    /// Evaluate whether traffic would be tunneled, blocked or bypass the tunnel
+    QueryTraffic(oneshot::Sender<TrafficVerdict>, TrafficQuery),
+    /// List the network interfaces that are backed by hardware
+    #[cfg(not(target_os = "android"))]
+    GetPhysicalInterfaces(ResponseTx<Vec<PhysicalInterfaceDetails>, routing::Error>),
     /// Remove settings and clear the cache
     #[cfg(not(target_os = "android"))]
     FactoryReset(ResponseTx<(), Error>),
@@ -281,8 +429,11 @@ pub enum DaemonCommand {
     /// Notify the split tunnel monitor that a volume was mounted or dismounted
     #[cfg(target_os = "windows")]
     CheckVolumes(ResponseTx<(), Error>),
+    /// Reinstall the split tunnel driver
+    #[cfg(target_os = "windows")]
+    ReinstallSplitTunnelDriver(ResponseTx<(), Error>),
     /// Register settings for WireGuard obfuscator
-    SetObfuscationSettings(ResponseTx<(), settings::Error>, ObfuscationSettings),
+    SetObfuscationSettings(ResponseTx<ApplyPlan, settings::Error>, ObfuscationSettings),
     /// Saves the target tunnel state and enters a blocking state. The state is restored
     /// upon restart.
     PrepareRestart,
@@ -290,12 +441,27 @@ pub enum DaemonCommand {
     /// to bypass the tunnel in blocking states.
     #[cfg(target_os = "android")]
     BypassSocket(RawFd, oneshot::Sender<()>),
+    /// Inject a simulated event into the tunnel state machine.
+    #[cfg(feature = "qa-tools")]
+    InjectSimulatedEvent(
+        ResponseTx<(), tunnel_state_machine::SimulationError>,
+        tunnel_state_machine::SimulatedEvent,
+    ),
+    /// Start capturing packets on the tunnel interface. Returns the path to the capture file.
+    #[cfg(all(feature = "packet-capture", target_os = "linux"))]
+    StartPacketCapture(
+        ResponseTx<PathBuf, talpid_core::packet_capture::Error>,
+        talpid_core::packet_capture::CaptureLimits,
+    ),
+    /// Stop the running packet capture. Returns whether one was running.
+    #[cfg(all(feature = "packet-capture", target_os = "linux"))]
+    StopPacketCapture(oneshot::Sender<bool>),
 }
 
 /// All events that can happen in the daemon. Sent from various threads and exposed interfaces.
 pub(crate) enum InternalDaemonEvent {
     /// Tunnel has changed state.
-    TunnelStateTransition(TunnelStateTransition),
+    TunnelStateTransition(VersionedTunnelStateTransition),
     /// A command sent to the daemon.
     Command(DaemonCommand),
     /// Daemon shutdown triggered by a signal, ctrl-c or similar.
@@ -307,9 +473,23 @@ pub(crate) enum InternalDaemonEvent {
     DeviceEvent(AccountEvent),
     /// Handles updates from versions without devices.
     DeviceMigrationEvent(Result<PrivateAccountAndDevice, device::Error>),
+    /// A GeoIP location lookup completed.
+    LocationEvent(geoip::LocationEvent),
     /// The split tunnel paths or state were updated.
     #[cfg(target_os = "windows")]
     ExcludedPathsEvent(ExcludedPathsUpdate, oneshot::Sender<Result<(), Error>>),
+    /// A check for WFP objects that may override the firewall policy completed.
+    #[cfg(windows)]
+    FirewallConflicts(firewall_conflicts::FirewallConflictsEvent),
+    /// Another VPN added a route that competes with the routes of the tunnel.
+    #[cfg(not(target_os = "android"))]
+    RouteTakeover(CompetingVpn),
+    /// A firewall, DNS or routing operation was slow to complete.
+    PerformanceWarning(PerformanceWarning),
+    /// The time for which protection was paused has passed.
+    ProtectionPauseExpired(ProtectionPauseExpired),
+    /// The system clock was found to be too far off, or no longer is.
+    ClockSkewChanged(ClockSkewChanged),
 }
 
 #[cfg(target_os = "windows")]
@@ -318,8 +498,8 @@ pub(crate) enum ExcludedPathsUpdate {
     SetPaths(HashSet<PathBuf>),
 }
 
-impl From<TunnelStateTransition> for InternalDaemonEvent {
-    fn from(tunnel_state_transition: TunnelStateTransition) -> Self {
+impl From<VersionedTunnelStateTransition> for InternalDaemonEvent {
+    fn from(tunnel_state_transition: VersionedTunnelStateTransition) -> Self {
         InternalDaemonEvent::TunnelStateTransition(tunnel_state_transition)
     }
 }
@@ -342,6 +522,44 @@ impl From<AccountEvent> for InternalDaemonEvent {
     }
 }
 
+impl From<geoip::LocationEvent> for InternalDaemonEvent {
+    fn from(event: geoip::LocationEvent) -> Self {
+        InternalDaemonEvent::LocationEvent(event)
+    }
+}
+
+#[cfg(windows)]
+impl From<firewall_conflicts::FirewallConflictsEvent> for InternalDaemonEvent {
+    fn from(event: firewall_conflicts::FirewallConflictsEvent) -> Self {
+        InternalDaemonEvent::FirewallConflicts(event)
+    }
+}
+
+#[cfg(not(target_os = "android"))]
+impl From<CompetingVpn> for InternalDaemonEvent {
+    fn from(competing_vpn: CompetingVpn) -> Self {
+        InternalDaemonEvent::RouteTakeover(competing_vpn)
+    }
+}
+
+impl From<PerformanceWarning> for InternalDaemonEvent {
+    fn from(warning: PerformanceWarning) -> Self {
+        InternalDaemonEvent::PerformanceWarning(warning)
+    }
+}
+
+impl From<ProtectionPauseExpired> for InternalDaemonEvent {
+    fn from(event: ProtectionPauseExpired) -> Self {
+        InternalDaemonEvent::ProtectionPauseExpired(event)
+    }
+}
+
+impl From<ClockSkewChanged> for InternalDaemonEvent {
+    fn from(event: ClockSkewChanged) -> Self {
+        InternalDaemonEvent::ClockSkewChanged(event)
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 enum DaemonExecutionState {
     Running,
@@ -356,7 +574,7 @@ impl DaemonExecutionState {
         match self {
             Running => {
                 match tunnel_state {
-                    TunnelState::Disconnected => mem::replace(self, Finished),
+                    TunnelState::Disconnected { .. } => mem::replace(self, Finished),
                     _ => mem::replace(self, Exiting),
                 };
             }
@@ -509,8 +727,19 @@ pub trait EventListener {
     /// Notify that the tunnel state changed.
     fn notify_new_state(&self, new_state: TunnelState);
 
-    /// Notify that the settings changed.
-    fn notify_settings(&self, settings: Settings);
+    /// Notify that the tunnel state machine entered a new state, in the versioned form that is
+    /// streamed to external tools.
+    fn notify_tunnel_state_transition(&self, transition: &VersionedTunnelStateTransition);
+
+    /// Notify that the settings changed. `changed_fields` contains the paths of the fields that
+    /// changed since the previous notification, e.g. `tunnel_options.dns_options`, and
+    /// `apply_plan` how they are applied to the tunnel.
+    fn notify_settings(
+        &self,
+        settings: Settings,
+        changed_fields: Vec<String>,
+        apply_plan: &ApplyPlan,
+    );
 
     /// Notify that the relay list changed.
     fn notify_relay_list(&self, relay_list: RelayList);
@@ -524,14 +753,40 @@ pub trait EventListener {
 
     /// Notify that a device was revoked using `RemoveDevice`.
     fn notify_remove_device_event(&self, event: RemoveDeviceEvent);
+
+    /// Notify that the account expiry was refreshed.
+    fn notify_account_expiry(&self, expiry: DateTime<Utc>);
+
+    /// Notify that WFP objects owned by other products that may override the firewall policy
+    /// were found, or that they are no longer present.
+    #[cfg(windows)]
+    fn notify_firewall_conflicts(&self, conflicts: Vec<firewall_conflicts::Conflict>);
+
+    /// Notify that another VPN took over the default route while connected.
+    #[cfg(not(target_os = "android"))]
+    fn notify_competing_vpn(&self, competing_vpn: CompetingVpn);
+
+    /// Notify that a firewall, DNS or routing operation was slow to complete.
+    fn notify_performance_warning(&self, warning: PerformanceWarning);
+
+    /// Notify that the system clock was found to be too far off, or that it no longer is.
+    fn notify_clock_skew(&self, skew: Option<ClockSkew>);
 }
 
 pub struct Daemon<L: EventListener> {
     tunnel_state: TunnelState,
     target_state: PersistentTargetState,
+    protection_tracker: ProtectionTracker,
+    protection_pause: ProtectionPause,
+    connection_stats: ConnectionStatsTracker,
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    hook_runner: hooks::HookRunner,
+    #[cfg(target_os = "linux")]
+    firewall_watchdog: Option<firewall_watchdog::FirewallWatchdog>,
+    preflight_report: HealthReport,
     state: DaemonExecutionState,
     #[cfg(target_os = "linux")]
-    exclude_pids: split_tunnel::PidManager,
+    exclude_pids: Option<split_tunnel::PidManager>,
     rx: mpsc::UnboundedReceiver<InternalDaemonEvent>,
     tx: DaemonEventSender,
     reconnection_job: Option<AbortHandle>,
@@ -540,18 +795,26 @@ pub struct Daemon<L: EventListener> {
     settings: SettingsPersister,
     account_history: account_history::AccountHistory,
     device_checker: device::TunnelStateChangeHandler,
+    expiry_tracker: device::ExpiryTracker,
     account_manager: device::AccountManagerHandle,
     api_runtime: mullvad_api::Runtime,
     api_handle: mullvad_api::rest::MullvadRestHandle,
     version_updater_handle: version_check::VersionUpdaterHandle,
     relay_selector: RelaySelector,
     relay_list_updater: RelayListUpdaterHandle,
+    relay_prober: relay_probe::RelayProber,
     parameters_generator: tunnel::ParametersGenerator,
+    location_handler: geoip::GeoIpHandler,
     app_version_info: Option<AppVersionInfo>,
     shutdown_tasks: Vec<Pin<Box<dyn Future<Output = ()>>>>,
     tunnel_state_machine_handle: TunnelStateMachineHandle,
+    log_dir: Option<PathBuf>,
     #[cfg(target_os = "windows")]
     volume_update_tx: mpsc::UnboundedSender<()>,
+    #[cfg(target_os = "windows")]
+    _volume_change_listener: talpid_core::windows::window::VolumeChangeListener,
+    #[cfg(windows)]
+    firewall_conflicts: Vec<firewall_conflicts::Conflict>,
 }
 
 impl<L> Daemon<L>
@@ -573,6 +836,13 @@ where
             macos::set_exclusion_gid().map_err(Error::GroupIdError)?
         };
 
+        #[cfg(target_os = "linux")]
+        if talpid_core::container::is_enabled() {
+            talpid_core::container::check_requirements().map_err(Error::ContainerRequirement)?;
+        }
+
+        let preflight_report = preflight::run(&resource_dir);
+
         mullvad_api::proxy::ApiConnectionMode::try_delete_cache(&cache_dir).await;
 
         let (internal_event_tx, internal_event_rx) = command_channel.destructure();
@@ -589,7 +859,13 @@ where
         let api_availability = api_runtime.availability_handle();
         api_availability.suspend();
 
-        let endpoint_updater = api::ApiEndpointUpdaterHandle::new();
+        clock_skew::spawn_monitor(
+            api_runtime.clock_skew_handle(),
+            internal_event_tx.to_specialized_sender(),
+        );
+
+        let endpoint_updater =
+            api::ApiEndpointUpdaterHandle::new(api_runtime.address_cache.clone());
 
         let migration_data = migrations::migrate_all(&cache_dir, &settings_dir)
             .await
@@ -642,12 +918,22 @@ where
         .await
         .map_err(Error::LoadAccountHistory)?;
 
-        let target_state = if settings.auto_connect {
+        let protection_pause =
+            ProtectionPause::new(&cache_dir, internal_event_tx.to_specialized_sender()).await;
+        let target_state = if protection_pause.resume_at().is_some() {
+            PersistentTargetState::force(&cache_dir, TargetState::Unsecured).await
+        } else if protection_pause.ended_while_stopped() {
+            log::info!("Connecting since the protection pause has ended");
+            PersistentTargetState::force(&cache_dir, TargetState::Secured).await
+        } else if settings.auto_connect {
             log::info!("Automatically connecting since auto-connect is turned on");
             PersistentTargetState::force(&cache_dir, TargetState::Secured).await
         } else {
             PersistentTargetState::new(&cache_dir).await
         };
+        let protection_tracker = ProtectionTracker::new(&cache_dir, *target_state).await;
+        let connection_stats =
+            ConnectionStatsTracker::new(&cache_dir, settings.connection_stats).await;
 
         #[cfg(windows)]
         let exclude_paths = if settings.split_tunnel.enable_exclusions {
@@ -661,31 +947,72 @@ where
             vec![]
         };
 
-        let initial_api_endpoint =
-            api::get_allowed_endpoint(api_runtime.address_cache.get_address().await);
+        let api_addresses = api_runtime.address_cache.get_addresses().await;
+        let initial_api_endpoint = api::get_allowed_endpoint(
+            &api_addresses,
+            api_runtime.address_cache.hostname_of(&api_addresses).await,
+        );
         let parameters_generator = tunnel::ParametersGenerator::new(
             account_manager.clone(),
             relay_selector.clone(),
             settings.tunnel_options.clone(),
         );
         let (offline_state_tx, offline_state_rx) = mpsc::unbounded();
+        #[cfg(not(target_os = "android"))]
+        let (physical_interface_tx, physical_interface_rx) = mpsc::unbounded();
         #[cfg(target_os = "windows")]
         let (volume_update_tx, volume_update_rx) = mpsc::unbounded();
+        #[cfg(target_os = "windows")]
+        let volume_change_listener = {
+            let volume_update_tx = volume_update_tx.clone();
+            talpid_core::windows::window::VolumeChangeListener::new(move || {
+                let _ = volume_update_tx.unbounded_send(());
+            })
+        };
+        let initial_tunnel_state = tunnel_state_machine::InitialTunnelState {
+            allow_lan: settings.allow_lan,
+            block_when_disconnected: settings.block_when_disconnected
+                && protection_pause.resume_at().is_none(),
+            dns_servers: dns::addresses_from_options(&settings.tunnel_options.dns_options),
+            allowed_endpoint: initial_api_endpoint,
+            reset_firewall: *target_state != TargetState::Secured,
+            flush_dns_cache: settings.flush_dns_cache,
+            recovery_allowlist: settings.recovery_allowlist.clone(),
+            vpn_coexistence: settings.vpn_coexistence.clone(),
+            blocked_tunnel_protocols: settings.blocked_tunnel_protocols,
+            forced_interface: settings.forced_interface.clone(),
+            reconnect_limits: settings.reconnect_limits,
+            route_takeover_policy: settings.route_takeover_policy,
+            error_state_policy: settings.error_state_policy,
+            #[cfg(windows)]
+            exclude_paths,
+            wireguard_tunnel_provider: None,
+            firewall_plugins: talpid_core::firewall::plugin::load_plugins(
+                &settings_dir.join(FIREWALL_PLUGIN_DIR),
+                &resource_dir.join(talpid_core::firewall::plugin::TRUSTED_KEYS_FILENAME),
+            ),
+        };
+        let initial_security = initial_tunnel_state.disconnected_security();
+        // Find out early whether the driver works, so that connecting does not have to fail first
+        #[cfg(windows)]
+        if settings.tunnel_options.wireguard.options.use_wireguard_nt {
+            tokio::spawn(talpid_core::tunnel::wireguard::probe_wireguard_nt(
+                resource_dir.clone(),
+            ));
+        }
         let tunnel_state_machine_handle = tunnel_state_machine::spawn(
-            tunnel_state_machine::InitialTunnelState {
-                allow_lan: settings.allow_lan,
-                block_when_disconnected: settings.block_when_disconnected,
-                dns_servers: dns::addresses_from_options(&settings.tunnel_options.dns_options),
-                allowed_endpoint: initial_api_endpoint,
-                reset_firewall: *target_state != TargetState::Secured,
-                #[cfg(windows)]
-                exclude_paths,
-            },
+            initial_tunnel_state,
             parameters_generator.clone(),
-            log_dir,
+            log_dir.clone(),
             resource_dir.clone(),
+            cache_dir.clone(),
             internal_event_tx.to_specialized_sender(),
             offline_state_tx,
+            #[cfg(not(target_os = "android"))]
+            physical_interface_tx,
+            #[cfg(not(target_os = "android"))]
+            internal_event_tx.to_specialized_sender(),
+            internal_event_tx.to_specialized_sender(),
             #[cfg(target_os = "windows")]
             volume_update_rx,
             #[cfg(target_os = "macos")]
@@ -699,7 +1026,18 @@ where
         endpoint_updater
             .set_tunnel_command_tx(Arc::downgrade(tunnel_state_machine_handle.command_tx()));
 
-        api::forward_offline_state(api_availability.clone(), offline_state_rx);
+        let network_change_tx = api::spawn_reachability_monitor(api_handle.clone());
+        api::forward_offline_state(
+            api_availability.clone(),
+            offline_state_rx,
+            network_change_tx.clone(),
+        );
+        #[cfg(not(target_os = "android"))]
+        api::forward_physical_interface(
+            api_runtime.interface_binding(),
+            physical_interface_rx,
+            network_change_tx,
+        );
 
         let relay_list_listener = event_listener.clone();
         let on_relay_list_update = move |relay_list: &RelayList| {
@@ -723,15 +1061,29 @@ where
         );
         tokio::spawn(version_updater.run());
 
+        #[cfg(windows)]
+        firewall_conflicts::spawn_monitor(internal_event_tx.to_specialized_sender());
+
         // Attempt to download a fresh relay list
         relay_list_updater.update().await;
 
         let daemon = Daemon {
-            tunnel_state: TunnelState::Disconnected,
+            tunnel_state: TunnelState::Disconnected {
+                cause: None,
+                security: initial_security,
+            },
             target_state,
+            protection_tracker,
+            protection_pause,
+            connection_stats,
+            #[cfg(any(target_os = "linux", target_os = "macos"))]
+            hook_runner: hooks::HookRunner::new(),
+            #[cfg(target_os = "linux")]
+            firewall_watchdog: None,
+            preflight_report,
             state: DaemonExecutionState::Running,
             #[cfg(target_os = "linux")]
-            exclude_pids: split_tunnel::PidManager::new().map_err(Error::InitSplitTunneling)?,
+            exclude_pids: Self::init_split_tunneling()?,
             rx: internal_event_rx,
             tx: internal_event_tx,
             reconnection_job: None,
@@ -740,18 +1092,26 @@ where
             settings,
             account_history,
             device_checker: device::TunnelStateChangeHandler::new(account_manager.clone()),
+            expiry_tracker: device::ExpiryTracker::spawn(account_manager.clone()),
             account_manager,
             api_runtime,
             api_handle,
             version_updater_handle,
             relay_selector,
             relay_list_updater,
+            relay_prober: relay_probe::RelayProber::new(),
             parameters_generator,
+            location_handler: geoip::GeoIpHandler::new(internal_event_tx.to_specialized_sender()),
             app_version_info,
             shutdown_tasks: vec![],
             tunnel_state_machine_handle,
+            log_dir,
             #[cfg(target_os = "windows")]
             volume_update_tx,
+            #[cfg(target_os = "windows")]
+            _volume_change_listener: volume_change_listener,
+            #[cfg(windows)]
+            firewall_conflicts: vec![],
         };
 
         api_availability.unsuspend();
@@ -762,6 +1122,15 @@ where
     /// Consume the `Daemon` and run the main event loop. Blocks until an error happens or a
     /// shutdown event is received.
     pub async fn run(mut self) -> Result<(), Error> {
+        #[cfg(target_os = "linux")]
+        {
+            systemd::notify_ready();
+            systemd::spawn_watchdog(self.tx.to_specialized_sender());
+            self.firewall_watchdog = Some(firewall_watchdog::FirewallWatchdog::spawn(
+                &self.tunnel_state,
+            ));
+        }
+
         if *self.target_state == TargetState::Secured {
             self.connect_tunnel();
         }
@@ -777,7 +1146,9 @@ where
         Ok(())
     }
 
-    async fn finalize(self) {
+    async fn finalize(mut self) {
+        #[cfg(target_os = "linux")]
+        let firewall_watchdog = self.firewall_watchdog.take();
         let (event_listener, shutdown_tasks, api_runtime, tunnel_state_machine_handle) =
             self.shutdown();
         for future in shutdown_tasks {
@@ -785,6 +1156,11 @@ where
         }
 
         tunnel_state_machine_handle.try_join().await;
+        // The firewall is left as it should be once the tunnel state machine has stopped
+        #[cfg(target_os = "linux")]
+        if let Some(firewall_watchdog) = firewall_watchdog {
+            firewall_watchdog.shutdown().await;
+        }
 
         drop(event_listener);
         drop(api_runtime);
@@ -813,11 +1189,13 @@ where
             api_runtime,
             tunnel_state_machine_handle,
             target_state,
+            protection_tracker,
             account_manager,
             ..
         } = self;
 
         shutdown_tasks.push(Box::pin(target_state.finalize()));
+        shutdown_tasks.push(Box::pin(protection_tracker.finalize()));
         shutdown_tasks.push(Box::pin(account_manager.shutdown()));
 
         (
@@ -841,22 +1219,36 @@ where
             }
             DeviceEvent(event) => self.handle_device_event(event).await,
             DeviceMigrationEvent(event) => self.handle_device_migration_event(event).await,
+            LocationEvent(event) => self.handle_location_event(event),
             #[cfg(windows)]
             ExcludedPathsEvent(update, tx) => self.handle_new_excluded_paths(update, tx).await,
+            #[cfg(windows)]
+            FirewallConflicts(event) => self.handle_firewall_conflicts(event.0),
+            #[cfg(not(target_os = "android"))]
+            RouteTakeover(competing_vpn) => self.handle_route_takeover(competing_vpn),
+            PerformanceWarning(warning) => self.handle_performance_warning(warning),
+            ProtectionPauseExpired(event) => self.handle_protection_pause_expired(event).await,
+            ClockSkewChanged(event) => self.handle_clock_skew_changed(event),
         }
     }
 
     async fn handle_tunnel_state_transition(
         &mut self,
-        tunnel_state_transition: TunnelStateTransition,
+        versioned_transition: VersionedTunnelStateTransition,
     ) {
+        self.event_listener
+            .notify_tunnel_state_transition(&versioned_transition);
+        let tunnel_state_transition = versioned_transition.transition;
+
         self.reset_rpc_sockets_on_tunnel_state_transition(&tunnel_state_transition)
             .await;
         self.device_checker
             .handle_state_transition(&tunnel_state_transition);
 
         let tunnel_state = match tunnel_state_transition {
-            TunnelStateTransition::Disconnected => TunnelState::Disconnected,
+            TunnelStateTransition::Disconnected { cause, security } => {
+                TunnelState::Disconnected { cause, security }
+            }
             TunnelStateTransition::Connecting(endpoint) => TunnelState::Connecting {
                 endpoint,
                 location: self.parameters_generator.get_last_location().await,
@@ -869,6 +1261,10 @@ where
                 TunnelState::Disconnecting(after_disconnect)
             }
             TunnelStateTransition::Error(error_state) => TunnelState::Error(error_state),
+            TunnelStateTransition::Paused(endpoint) => TunnelState::Paused {
+                endpoint,
+                location: self.parameters_generator.get_last_location().await,
+            },
         };
 
         if !tunnel_state.is_connected() {
@@ -881,7 +1277,7 @@ where
         log::debug!("New tunnel state: {:?}", tunnel_state);
 
         match tunnel_state {
-            TunnelState::Disconnected => {
+            TunnelState::Disconnected { .. } => {
                 self.api_handle.availability.reset_inactivity_timer();
             }
             _ => {
@@ -890,7 +1286,7 @@ where
         }
 
         match tunnel_state {
-            TunnelState::Disconnected => self.state.disconnected(),
+            TunnelState::Disconnected { .. } => self.state.disconnected(),
             TunnelState::Error(ref error_state) => {
                 if error_state.is_blocking() {
                     log::info!(
@@ -904,19 +1300,64 @@ where
                     );
                 }
 
-                if let ErrorStateCause::AuthFailed(_) = error_state.cause() {
-                    // If time is added outside of the app, no notifications
-                    // are received. So we must continually try to reconnect.
-                    self.schedule_reconnect(Duration::from_secs(60))
+                match error_state.cause() {
+                    // A revoked device cannot be used again until the user logs in.
+                    ErrorStateCause::AuthFailed(Some(reason))
+                        if reason == auth_failed::DEVICE_REVOKED_REASON => {}
+                    ErrorStateCause::AuthFailed(_) => {
+                        // If time is added outside of the app, no notifications
+                        // are received. So we must continually try to reconnect.
+                        self.schedule_reconnect(Duration::from_secs(60))
+                    }
+                    _ => (),
                 }
             }
             _ => {}
         }
 
+        self.location_handler.invalidate();
+        if tunnel_state.is_connected() {
+            let rest_service = self.api_runtime.rest_handle().await;
+            self.location_handler.send_request(
+                rest_service,
+                self.settings.tunnel_options.generic.enable_ipv6,
+            );
+        }
+
+        #[cfg(target_os = "linux")]
+        systemd::notify_tunnel_state(&tunnel_state);
+
+        self.protection_tracker
+            .set_tunnel_state(&tunnel_state)
+            .await;
+        self.connection_stats.set_tunnel_state(&tunnel_state).await;
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        self.hook_runner
+            .set_tunnel_state(&self.settings.hooks, &tunnel_state);
+        #[cfg(target_os = "linux")]
+        if let Some(firewall_watchdog) = &self.firewall_watchdog {
+            firewall_watchdog.set_tunnel_state(&tunnel_state);
+        }
+
         self.tunnel_state = tunnel_state.clone();
         self.event_listener.notify_new_state(tunnel_state);
     }
 
+    fn handle_location_event(&mut self, event: geoip::LocationEvent) {
+        let fetched_location = match self.location_handler.handle_event(event) {
+            Some(location) => location.clone(),
+            None => return,
+        };
+        if let TunnelState::Connected { location, .. } = &mut self.tunnel_state {
+            *location = Some(geoip::merge_relay_location(
+                location.take(),
+                fetched_location,
+            ));
+            self.event_listener
+                .notify_new_state(self.tunnel_state.clone());
+        }
+    }
+
     async fn reset_rpc_sockets_on_tunnel_state_transition(
         &mut self,
         tunnel_state_transition: &TunnelStateTransition,
@@ -966,15 +1407,21 @@ where
 
         match command {
             SetTargetState(tx, state) => self.on_set_target_state(tx, state).await,
+            PauseProtection(tx, duration) => self.on_pause_protection(tx, duration).await,
+            GetProtectionPause(tx) => self.on_get_protection_pause(tx),
             Reconnect(tx) => self.on_reconnect(tx),
+            PauseTunnel(tx) => self.on_pause_tunnel(tx),
+            ResumeTunnel(tx) => self.on_resume_tunnel(tx),
             GetState(tx) => self.on_get_state(tx),
             GetCurrentLocation(tx) => self.on_get_current_location(tx).await,
             CreateNewAccount(tx) => self.on_create_new_account(tx).await,
             GetAccountData(tx, account_token) => self.on_get_account_data(tx, account_token).await,
             GetWwwAuthToken(tx) => self.on_get_www_auth_token(tx).await,
             SubmitVoucher(tx, voucher) => self.on_submit_voucher(tx, voucher).await,
+            GetPaymentStatus(tx) => self.on_get_payment_status(tx),
             GetRelayLocations(tx) => self.on_get_relay_locations(tx),
             UpdateRelayLocations => self.on_update_relay_locations().await,
+            QueryRelays(tx, query) => self.on_query_relays(tx, query),
             LoginAccount(tx, account_token) => self.on_login_account(tx, account_token),
             LogoutAccount(tx) => self.on_logout_account(tx),
             GetDevice(tx) => self.on_get_device(tx).await,
@@ -992,27 +1439,78 @@ where
                 self.on_set_block_when_disconnected(tx, block_when_disconnected)
                     .await
             }
+            SetFlushDnsCache(tx, flush_dns_cache) => {
+                self.on_set_flush_dns_cache(tx, flush_dns_cache).await
+            }
+            SetRecoveryAllowlist(tx, recovery_allowlist) => {
+                self.on_set_recovery_allowlist(tx, recovery_allowlist).await
+            }
+            SetVpnCoexistence(tx, vpn_coexistence) => {
+                self.on_set_vpn_coexistence(tx, vpn_coexistence).await
+            }
+            SetBlockedTunnelProtocols(tx, blocked_tunnel_protocols) => {
+                self.on_set_blocked_tunnel_protocols(tx, blocked_tunnel_protocols)
+                    .await
+            }
+            SetForcedInterface(tx, forced_interface) => {
+                self.on_set_forced_interface(tx, forced_interface).await
+            }
+            SetReconnectLimits(tx, reconnect_limits) => {
+                self.on_set_reconnect_limits(tx, reconnect_limits).await
+            }
+            SetRouteTakeoverPolicy(tx, route_takeover_policy) => {
+                self.on_set_route_takeover_policy(tx, route_takeover_policy)
+                    .await
+            }
+            SetErrorStatePolicy(tx, error_state_policy) => {
+                self.on_set_error_state_policy(tx, error_state_policy).await
+            }
             SetAutoConnect(tx, auto_connect) => self.on_set_auto_connect(tx, auto_connect).await,
+            SetConnectionStats(tx, connection_stats) => {
+                self.on_set_connection_stats(tx, connection_stats).await
+            }
+            SetHooks(tx, hooks) => self.on_set_hooks(tx, hooks).await,
             SetOpenVpnMssfix(tx, mssfix_arg) => self.on_set_openvpn_mssfix(tx, mssfix_arg).await,
             SetBridgeSettings(tx, bridge_settings) => {
                 self.on_set_bridge_settings(tx, bridge_settings).await
             }
             SetBridgeState(tx, bridge_state) => self.on_set_bridge_state(tx, bridge_state).await,
             SetEnableIpv6(tx, enable_ipv6) => self.on_set_enable_ipv6(tx, enable_ipv6).await,
+            SetEnableIpv4(tx, enable_ipv4) => self.on_set_enable_ipv4(tx, enable_ipv4).await,
             SetQuantumResistantTunnel(tx, enable_pq) => {
                 self.on_set_quantum_resistant_tunnel(tx, enable_pq).await
             }
+            SetWireguardTrafficShaping(tx, options) => {
+                self.on_set_wireguard_traffic_shaping(tx, options).await
+            }
+            SetWireguardBandwidthLimit(tx, limit) => {
+                self.on_set_wireguard_bandwidth_limit(tx, limit).await
+            }
+            SetWireguardAllowedIps(tx, allowed_ips) => {
+                self.on_set_wireguard_allowed_ips(tx, allowed_ips).await
+            }
             SetDnsOptions(tx, dns_servers) => self.on_set_dns_options(tx, dns_servers).await,
             SetWireguardMtu(tx, mtu) => self.on_set_wireguard_mtu(tx, mtu).await,
             SetWireguardRotationInterval(tx, interval) => {
                 self.on_set_wireguard_rotation_interval(tx, interval).await
             }
             GetSettings(tx) => self.on_get_settings(tx),
+            GetProfiles(tx) => self.on_get_profiles(tx),
+            SaveProfile(tx, name) => self.on_save_profile(tx, name).await,
+            RemoveProfile(tx, name) => self.on_remove_profile(tx, name).await,
+            ApplyProfile(tx, name) => self.on_apply_profile(tx, name).await,
             RotateWireguardKey(tx) => self.on_rotate_wireguard_key(tx).await,
             GetWireguardKey(tx) => self.on_get_wireguard_key(tx).await,
             GetVersionInfo(tx) => self.on_get_version_info(tx).await,
             IsPerformingPostUpgrade(tx) => self.on_is_performing_post_upgrade(tx).await,
             GetCurrentVersion(tx) => self.on_get_current_version(tx),
+            HealthCheck(tx) => self.on_health_check(tx),
+            GetPreflightReport(tx) => self.on_get_preflight_report(tx),
+            GetSystemState(tx) => self.on_get_system_state(tx),
+            GetProtectionGapReport(tx) => self.on_get_protection_gap_report(tx),
+            QueryTraffic(tx, query) => self.on_query_traffic(tx, query),
+            #[cfg(not(target_os = "android"))]
+            GetPhysicalInterfaces(tx) => self.on_get_physical_interfaces(tx),
             #[cfg(not(target_os = "android"))]
             FactoryReset(tx) => self.on_factory_reset(tx).await,
             #[cfg(target_os = "linux")]
@@ -1037,12 +1535,20 @@ where
             UseWireGuardNt(tx, state) => self.on_use_wireguard_nt(tx, state).await,
             #[cfg(target_os = "windows")]
             CheckVolumes(tx) => self.on_check_volumes(tx).await,
+            #[cfg(target_os = "windows")]
+            ReinstallSplitTunnelDriver(tx) => self.on_reinstall_split_tunnel_driver(tx),
             SetObfuscationSettings(tx, settings) => {
                 self.on_set_obfuscation_settings(tx, settings).await
             }
             PrepareRestart => self.on_prepare_restart(),
             #[cfg(target_os = "android")]
             BypassSocket(fd, tx) => self.on_bypass_socket(fd, tx),
+            #[cfg(feature = "qa-tools")]
+            InjectSimulatedEvent(tx, event) => self.on_inject_simulated_event(tx, event),
+            #[cfg(all(feature = "packet-capture", target_os = "linux"))]
+            StartPacketCapture(tx, limits) => self.on_start_packet_capture(tx, limits),
+            #[cfg(all(feature = "packet-capture", target_os = "linux"))]
+            StopPacketCapture(tx) => self.on_stop_packet_capture(tx),
         }
     }
 
@@ -1053,9 +1559,37 @@ where
         self.event_listener.notify_app_version(app_version_info);
     }
 
+    #[cfg(windows)]
+    fn handle_firewall_conflicts(&mut self, conflicts: Vec<firewall_conflicts::Conflict>) {
+        if conflicts == self.firewall_conflicts {
+            return;
+        }
+        if conflicts.is_empty() {
+            log::info!("Conflicting WFP objects are no longer present");
+        } else {
+            log::warn!("{}", firewall_conflicts::format_report(&conflicts));
+        }
+        self.firewall_conflicts = conflicts.clone();
+        self.event_listener.notify_firewall_conflicts(conflicts);
+    }
+
+    #[cfg(not(target_os = "android"))]
+    fn handle_route_takeover(&mut self, competing_vpn: CompetingVpn) {
+        self.event_listener.notify_competing_vpn(competing_vpn);
+    }
+
+    fn handle_performance_warning(&mut self, warning: PerformanceWarning) {
+        self.event_listener.notify_performance_warning(warning);
+    }
+
+    fn handle_clock_skew_changed(&mut self, event: ClockSkewChanged) {
+        self.event_listener.notify_clock_skew(event.0);
+    }
+
     async fn handle_device_event(&mut self, event: AccountEvent) {
         match &event {
             AccountEvent::Device(PrivateDeviceEvent::Login(device)) => {
+                self.expiry_tracker.refresh();
                 if let Err(error) = self.account_history.set(device.account_token.clone()).await {
                     log::error!(
                         "{}",
@@ -1069,13 +1603,22 @@ where
             }
             AccountEvent::Device(PrivateDeviceEvent::Logout) => {
                 log::info!("Disconnecting because account token was cleared");
-                self.set_target_state(TargetState::Unsecured).await;
+                let cause = if self.is_blocked_by_revoked_device() {
+                    DisconnectCause::Revoked
+                } else {
+                    DisconnectCause::UserInitiated
+                };
+                self.set_target_state(TargetState::Unsecured, cause).await;
             }
             AccountEvent::Device(PrivateDeviceEvent::Revoked) => {
-                // If we're currently in a secured state, reconnect to make sure we immediately
-                // enter the error state.
+                // If we're currently in a secured state, block until the user logs in again.
                 if *self.target_state == TargetState::Secured {
-                    self.connect_tunnel();
+                    log::debug!("Entering blocking state since the device was revoked");
+                    self.tunnel_state_machine_handle
+                        .block(ErrorStateCause::AuthFailed(Some(
+                            auth_failed::DEVICE_REVOKED_REASON.to_owned(),
+                        )))
+                        .expect(TUNNEL_STATE_MACHINE_STOPPED);
                 }
             }
             AccountEvent::Device(PrivateDeviceEvent::RotatedKey(_)) => {
@@ -1083,19 +1626,28 @@ where
                     self.schedule_reconnect(WG_RECONNECT_DELAY);
                 }
             }
-            AccountEvent::Expiry(expiry) if *self.target_state == TargetState::Secured => {
-                if expiry >= &chrono::Utc::now() {
-                    if let TunnelState::Error(ref state) = self.tunnel_state {
-                        if matches!(state.cause(), ErrorStateCause::AuthFailed(_)) {
-                            log::debug!("Reconnecting since the account has time on it");
-                            self.connect_tunnel();
+            AccountEvent::Expiry(expiry) => {
+                self.event_listener.notify_account_expiry(*expiry);
+
+                if *self.target_state == TargetState::Secured {
+                    if expiry >= &Utc::now() {
+                        if let TunnelState::Error(ref state) = self.tunnel_state {
+                            if matches!(
+                                state.cause(),
+                                ErrorStateCause::AuthFailed(_) | ErrorStateCause::AccountExpired
+                            ) {
+                                log::debug!("Reconnecting since the account has time on it");
+                                self.connect_tunnel();
+                            }
                         }
+                    } else if !self.is_blocked_due_to_expiry() {
+                        // Only the API remains reachable, so the account can still be topped
+                        // up, e.g. using a voucher. The tracker reconnects once it has time.
+                        log::debug!("Entering blocking state since the account is out of time");
+                        self.tunnel_state_machine_handle
+                            .block(ErrorStateCause::AccountExpired)
+                            .expect(TUNNEL_STATE_MACHINE_STOPPED);
                     }
-                } else if self.get_target_tunnel_type() == Some(TunnelType::Wireguard) {
-                    log::debug!("Entering blocking state since the account is out of time");
-                    self.send_tunnel_command(TunnelCommand::Block(ErrorStateCause::AuthFailed(
-                        None,
-                    )))
                 }
             }
             _ => (),
@@ -1106,6 +1658,13 @@ where
         }
     }
 
+    fn is_blocked_due_to_expiry(&self) -> bool {
+        match &self.tunnel_state {
+            TunnelState::Error(state) => matches!(state.cause(), ErrorStateCause::AccountExpired),
+            _ => false,
+        }
+    }
+
     async fn handle_device_migration_event(
         &mut self,
         result: Result<PrivateAccountAndDevice, device::Error>,
@@ -1166,8 +1725,7 @@ where
         let changed = *save_result.as_ref().unwrap_or(&false);
         let _ = tx.send(save_result.map(|_| ()));
         if changed {
-            self.event_listener
-                .notify_settings(self.settings.to_settings());
+            self.notify_settings_changed();
         }
     }
 
@@ -1177,13 +1735,71 @@ where
         new_target_state: TargetState,
     ) {
         if self.state.is_running() {
-            let state_change_initated = self.set_target_state(new_target_state).await;
+            self.end_protection_pause().await;
+            let state_change_initated = self
+                .set_target_state(new_target_state, DisconnectCause::UserInitiated)
+                .await;
             Self::oneshot_send(tx, state_change_initated, "state change initiated");
         } else {
             log::warn!("Ignoring target state change request due to shutdown");
         }
     }
 
+    async fn on_pause_protection(
+        &mut self,
+        tx: ResponseTx<DateTime<Utc>, Error>,
+        duration: Duration,
+    ) {
+        if !self.state.is_running() {
+            log::warn!("Ignoring protection pause request due to shutdown");
+            return;
+        }
+        if duration < Duration::from_secs(1) || duration > protection_pause::MAX_PAUSE_DURATION {
+            Self::oneshot_send(
+                tx,
+                Err(Error::InvalidPauseDuration),
+                "pause_protection response",
+            );
+            return;
+        }
+
+        let resume_at = self.protection_pause.start(duration).await;
+        log::info!("Pausing protection until {}", resume_at);
+        if self.settings.block_when_disconnected {
+            self.send_tunnel_command(TunnelCommand::BlockWhenDisconnected(false));
+        }
+        self.set_target_state(TargetState::Unsecured, DisconnectCause::UserInitiated)
+            .await;
+        Self::oneshot_send(tx, Ok(resume_at), "pause_protection response");
+    }
+
+    fn on_get_protection_pause(&self, tx: oneshot::Sender<Option<DateTime<Utc>>>) {
+        Self::oneshot_send(
+            tx,
+            self.protection_pause.resume_at(),
+            "get_protection_pause response",
+        );
+    }
+
+    async fn handle_protection_pause_expired(&mut self, event: ProtectionPauseExpired) {
+        // The pause may have been replaced or ended after the event was sent
+        if self.protection_pause.resume_at() != Some(event.0) || !self.state.is_running() {
+            return;
+        }
+        log::info!("Protection pause ended. Reconnecting");
+        self.end_protection_pause().await;
+        self.set_target_state(TargetState::Secured, DisconnectCause::UserInitiated)
+            .await;
+    }
+
+    /// Ends the protection pause, if there is one, and blocks traffic again if "block when
+    /// disconnected" is enabled.
+    async fn end_protection_pause(&mut self) {
+        if self.protection_pause.end().await && self.settings.block_when_disconnected {
+            self.send_tunnel_command(TunnelCommand::BlockWhenDisconnected(true));
+        }
+    }
+
     fn on_reconnect(&mut self, tx: oneshot::Sender<bool>) {
         if *self.target_state == TargetState::Secured || self.tunnel_state.is_in_error_state() {
             self.connect_tunnel();
@@ -1194,6 +1810,34 @@ where
         }
     }
 
+    fn on_pause_tunnel(&mut self, tx: oneshot::Sender<bool>) {
+        let can_pause = matches!(
+            self.tunnel_state,
+            TunnelState::Connecting { .. } | TunnelState::Connected { .. }
+        );
+        if *self.target_state == TargetState::Secured && can_pause {
+            self.tunnel_state_machine_handle
+                .pause()
+                .expect(TUNNEL_STATE_MACHINE_STOPPED);
+            Self::oneshot_send(tx, true, "pause issued");
+        } else {
+            log::debug!("Ignoring pause command. There is no tunnel to pause");
+            Self::oneshot_send(tx, false, "pause issued");
+        }
+    }
+
+    fn on_resume_tunnel(&mut self, tx: oneshot::Sender<bool>) {
+        if self.tunnel_state.is_paused() {
+            self.tunnel_state_machine_handle
+                .resume()
+                .expect(TUNNEL_STATE_MACHINE_STOPPED);
+            Self::oneshot_send(tx, true, "resume issued");
+        } else {
+            log::debug!("Ignoring resume command. The tunnel is not paused");
+            Self::oneshot_send(tx, false, "resume issued");
+        }
+    }
+
     fn on_get_state(&self, tx: oneshot::Sender<TunnelState>) {
         Self::oneshot_send(tx, self.tunnel_state.clone(), "current state");
     }
@@ -1207,7 +1851,11 @@ where
         use self::TunnelState::*;
 
         match &self.tunnel_state {
-            Disconnected => {
+            Disconnected { .. } => {
+                if let Some(location) = self.location_handler.location() {
+                    Self::oneshot_send(tx, Some(location.clone()), "current location");
+                    return;
+                }
                 let location = self.get_geo_location().await;
                 tokio::spawn(async {
                     Self::oneshot_send(tx, location.await.ok(), "current location");
@@ -1216,22 +1864,31 @@ where
             Connecting { location, .. } => {
                 Self::oneshot_send(tx, location.clone(), "current location")
             }
-            Disconnecting(..) => Self::oneshot_send(
+            Disconnecting(..) | Paused { .. } => Self::oneshot_send(
                 tx,
                 self.parameters_generator.get_last_location().await,
                 "current location",
             ),
             Connected { location, .. } => {
                 let relay_location = location.clone();
+                if let Some(fetched_location) = self.location_handler.location() {
+                    Self::oneshot_send(
+                        tx,
+                        Some(geoip::merge_relay_location(
+                            relay_location,
+                            fetched_location.clone(),
+                        )),
+                        "current location",
+                    );
+                    return;
+                }
                 let location_future = self.get_geo_location().await;
                 tokio::spawn(async {
                     let location = location_future.await;
                     Self::oneshot_send(
                         tx,
-                        location.ok().map(|fetched_location| GeoIpLocation {
-                            ipv4: fetched_location.ipv4,
-                            ipv6: fetched_location.ipv6,
-                            ..relay_location.unwrap_or(fetched_location)
+                        location.ok().map(|fetched_location| {
+                            geoip::merge_relay_location(relay_location, fetched_location)
                         }),
                         "current location",
                     );
@@ -1247,12 +1904,11 @@ where
     async fn get_geo_location(&mut self) -> impl Future<Output = Result<GeoIpLocation, ()>> {
         let rest_service = self.api_runtime.rest_handle().await;
         let use_ipv6 = self.settings.tunnel_options.generic.enable_ipv6;
+        let lookup = self.location_handler.lookup(rest_service, use_ipv6);
         async move {
-            geoip::send_location_request(rest_service, use_ipv6)
-                .await
-                .map_err(|e| {
-                    log::warn!("Unable to fetch GeoIP location: {}", e.display_chain());
-                })
+            lookup.await.map_err(|e| {
+                log::warn!("Unable to fetch GeoIP location: {}", e.display_chain());
+            })
         }
     }
 
@@ -1342,6 +1998,21 @@ where
         });
     }
 
+    fn on_get_payment_status(&mut self, tx: ResponseTx<AccountData, Error>) {
+        let manager = self.account_manager.clone();
+        tokio::spawn(async move {
+            Self::oneshot_send(
+                tx,
+                manager
+                    .check_expiry()
+                    .await
+                    .map(|expiry| AccountData { expiry })
+                    .map_err(Error::PaymentStatus),
+                "get_payment_status response",
+            );
+        });
+    }
+
     fn on_get_relay_locations(&mut self, tx: oneshot::Sender<RelayList>) {
         Self::oneshot_send(tx, self.relay_selector.get_locations(), "relay locations");
     }
@@ -1350,6 +2021,36 @@ where
         self.relay_list_updater.update().await;
     }
 
+    fn on_query_relays(&mut self, tx: oneshot::Sender<Vec<RelayQueryMatch>>, query: RelayQuery) {
+        let relays = self.relay_selector.query_relays(&query);
+
+        // Only the first few active relays are probed, to limit the number of connections made.
+        let mut remaining_probes = usize::try_from(query.probe_count)
+            .unwrap_or(usize::MAX)
+            .min(relay_probe::MAX_PROBED_RELAYS);
+        let endpoints = relays
+            .iter()
+            .map(|relay| {
+                if remaining_probes == 0 || !relay.active {
+                    return None;
+                }
+                remaining_probes -= 1;
+                self.relay_selector.get_probe_endpoint(relay)
+            })
+            .collect();
+
+        let relay_prober = self.relay_prober.clone();
+        tokio::spawn(async move {
+            let latencies = relay_prober.probe(endpoints).await;
+            let matches = relays
+                .into_iter()
+                .zip(latencies)
+                .map(|(relay, latency)| RelayQueryMatch { relay, latency })
+                .collect();
+            Self::oneshot_send(tx, matches, "relay query");
+        });
+    }
+
     fn on_login_account(&mut self, tx: ResponseTx<(), Error>, account_token: String) {
         let account_manager = self.account_manager.clone();
         tokio::spawn(async move {
@@ -1503,35 +2204,137 @@ where
         );
     }
 
-    #[cfg(not(target_os = "android"))]
-    async fn on_factory_reset(&mut self, tx: ResponseTx<(), Error>) {
-        let mut last_error = Ok(());
-
-        if let Err(error) = self.account_manager.logout().await {
-            log::error!(
-                "{}",
-                error.display_chain_with_msg("Failed to clear device cache")
-            );
-        }
+    fn on_health_check(&mut self, tx: oneshot::Sender<HealthReport>) {
+        let state_machine_checks = self.tunnel_state_machine_handle.health_check();
+        let log_dir = self.log_dir.clone();
+        tokio::spawn(async move {
+            let report = health::check(state_machine_checks, log_dir.as_deref()).await;
+            for check in report
+                .checks
+                .iter()
+                .filter(|check| check.status != HealthStatus::Pass)
+            {
+                log::debug!("Health check: {}", check);
+            }
+            Self::oneshot_send(tx, report, "health_check response");
+        });
+    }
 
-        if let Err(error) = self.account_history.clear().await {
-            log::error!(
-                "{}",
-                error.display_chain_with_msg("Failed to clear account history")
-            );
-            last_error = Err(Error::FactoryResetError("Failed to clear account history"));
-        }
+    fn on_get_preflight_report(&mut self, tx: oneshot::Sender<HealthReport>) {
+        Self::oneshot_send(
+            tx,
+            self.preflight_report.clone(),
+            "get_preflight_report response",
+        );
+    }
 
-        if let Err(e) = self.settings.reset().await {
-            log::error!("Failed to reset settings: {}", e);
-            last_error = Err(Error::FactoryResetError("Failed to reset settings"));
-        }
+    fn on_get_system_state(&mut self, tx: oneshot::Sender<SystemState>) {
+        self.send_tunnel_command(TunnelCommand::GetSystemState(tx));
+    }
 
-        // Shut the daemon down.
-        self.trigger_shutdown_event(false);
+    fn on_get_protection_gap_report(&mut self, tx: oneshot::Sender<Option<ProtectionGapReport>>) {
+        Self::oneshot_send(
+            tx,
+            self.protection_tracker.gap_report(),
+            "get_protection_gap_report response",
+        );
+    }
 
-        self.shutdown_tasks.push(Box::pin(async move {
-            if let Err(e) = cleanup::clear_directories().await {
+    #[cfg(not(target_os = "android"))]
+    fn on_get_physical_interfaces(
+        &mut self,
+        tx: ResponseTx<Vec<PhysicalInterfaceDetails>, routing::Error>,
+    ) {
+        tokio::spawn(async move {
+            let result = routing::get_physical_interfaces().await;
+            if let Err(error) = &result {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to list physical interfaces")
+                );
+            }
+            Self::oneshot_send(tx, result, "get_physical_interfaces response");
+        });
+    }
+
+    fn on_query_traffic(&mut self, tx: oneshot::Sender<TrafficVerdict>, query: TrafficQuery) {
+        let excluded_apps = self.excluded_apps();
+        let state = self.tunnel_state_machine_handle.get_system_state();
+        tokio::spawn(async move {
+            if let Ok(state) = state.await {
+                let verdict = talpid_core::traffic_query::resolve(&state, &query, &excluded_apps);
+                if tx.send(verdict).is_err() {
+                    log::warn!(
+                        "Unable to send query_traffic response to the daemon command sender"
+                    );
+                }
+            }
+        });
+    }
+
+    /// Returns the apps that are excluded from the tunnel, in the form expected by
+    /// [`TrafficQuery::app`].
+    fn excluded_apps(&self) -> Vec<String> {
+        #[cfg(target_os = "linux")]
+        {
+            match self.exclude_pids().and_then(|pids| pids.list()) {
+                Ok(pids) => pids.into_iter().map(|pid| pid.to_string()).collect(),
+                Err(error) => {
+                    log::error!(
+                        "{}",
+                        error.display_chain_with_msg("Unable to obtain PIDs being split")
+                    );
+                    vec![]
+                }
+            }
+        }
+        #[cfg(windows)]
+        {
+            let split_tunnel = &self.settings.split_tunnel;
+            if !split_tunnel.enable_exclusions {
+                return vec![];
+            }
+            split_tunnel
+                .apps
+                .iter()
+                .map(|app| app.to_string_lossy().into_owned())
+                .collect()
+        }
+        #[cfg(not(any(target_os = "linux", windows)))]
+        {
+            vec![]
+        }
+    }
+
+    #[cfg(not(target_os = "android"))]
+    async fn on_factory_reset(&mut self, tx: ResponseTx<(), Error>) {
+        let mut last_error = Ok(());
+
+        if let Err(error) = self.account_manager.logout().await {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to clear device cache")
+            );
+        }
+
+        if let Err(error) = self.account_history.clear().await {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to clear account history")
+            );
+            last_error = Err(Error::FactoryResetError("Failed to clear account history"));
+        }
+
+        if let Err(e) = self.settings.reset().await {
+            log::error!("Failed to reset settings: {}", e);
+            last_error = Err(Error::FactoryResetError("Failed to reset settings"));
+        }
+
+        // Shut the daemon down.
+        self.trigger_shutdown_event(false);
+
+        self.shutdown_tasks.push(Box::pin(async move {
+            if let Err(e) = cleanup::clear_directories().await {
                 log::error!(
                     "{}",
                     e.display_chain_with_msg("Failed to clear cache and log directories")
@@ -1544,21 +2347,52 @@ where
         }));
     }
 
+    /// Cgroups cannot be mounted in most containers. Split tunneling is then unavailable rather
+    /// than preventing the daemon from starting.
+    #[cfg(target_os = "linux")]
+    fn init_split_tunneling() -> Result<Option<split_tunnel::PidManager>, Error> {
+        match split_tunnel::PidManager::new() {
+            Ok(pid_manager) => Ok(Some(pid_manager)),
+            Err(error) if talpid_core::container::is_enabled() => {
+                log::warn!(
+                    "{}",
+                    error
+                        .display_chain_with_msg("Split tunneling is unavailable in this container")
+                );
+                Ok(None)
+            }
+            Err(error) => Err(Error::InitSplitTunneling(error)),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn exclude_pids(&self) -> Result<&split_tunnel::PidManager, split_tunnel::Error> {
+        self.exclude_pids
+            .as_ref()
+            .ok_or(split_tunnel::Error::Unavailable)
+    }
+
     #[cfg(target_os = "linux")]
     fn on_get_split_tunnel_processes(&mut self, tx: ResponseTx<Vec<i32>, split_tunnel::Error>) {
-        let result = self.exclude_pids.list().map_err(|error| {
-            log::error!("{}", error.display_chain_with_msg("Unable to obtain PIDs"));
-            error
-        });
+        let result = self
+            .exclude_pids()
+            .and_then(|pids| pids.list())
+            .map_err(|error| {
+                log::error!("{}", error.display_chain_with_msg("Unable to obtain PIDs"));
+                error
+            });
         Self::oneshot_send(tx, result, "get_split_tunnel_processes response");
     }
 
     #[cfg(target_os = "linux")]
     fn on_add_split_tunnel_process(&mut self, tx: ResponseTx<(), split_tunnel::Error>, pid: i32) {
-        let result = self.exclude_pids.add(pid).map_err(|error| {
-            log::error!("{}", error.display_chain_with_msg("Unable to add PID"));
-            error
-        });
+        let result = self
+            .exclude_pids()
+            .and_then(|pids| pids.add(pid))
+            .map_err(|error| {
+                log::error!("{}", error.display_chain_with_msg("Unable to add PID"));
+                error
+            });
         Self::oneshot_send(tx, result, "add_split_tunnel_process response");
     }
 
@@ -1568,19 +2402,25 @@ where
         tx: ResponseTx<(), split_tunnel::Error>,
         pid: i32,
     ) {
-        let result = self.exclude_pids.remove(pid).map_err(|error| {
-            log::error!("{}", error.display_chain_with_msg("Unable to remove PID"));
-            error
-        });
+        let result = self
+            .exclude_pids()
+            .and_then(|pids| pids.remove(pid))
+            .map_err(|error| {
+                log::error!("{}", error.display_chain_with_msg("Unable to remove PID"));
+                error
+            });
         Self::oneshot_send(tx, result, "remove_split_tunnel_process response");
     }
 
     #[cfg(target_os = "linux")]
     fn on_clear_split_tunnel_processes(&mut self, tx: ResponseTx<(), split_tunnel::Error>) {
-        let result = self.exclude_pids.clear().map_err(|error| {
-            log::error!("{}", error.display_chain_with_msg("Unable to clear PIDs"));
-            error
-        });
+        let result = self
+            .exclude_pids()
+            .and_then(|pids| pids.clear())
+            .map_err(|error| {
+                log::error!("{}", error.display_chain_with_msg("Unable to clear PIDs"));
+                error
+            });
         Self::oneshot_send(tx, result, "clear_split_tunnel_processes response");
     }
 
@@ -1736,8 +2576,7 @@ where
                     self.parameters_generator
                         .set_tunnel_options(&self.settings.tunnel_options)
                         .await;
-                    self.event_listener
-                        .notify_settings(self.settings.to_settings());
+                    self.notify_settings_changed();
                     if let Some(TunnelType::Wireguard) = self.get_target_tunnel_type() {
                         log::info!("Initiating tunnel restart");
                         self.reconnect_tunnel();
@@ -1761,6 +2600,29 @@ where
         }
     }
 
+    #[cfg(windows)]
+    fn on_reinstall_split_tunnel_driver(&mut self, tx: ResponseTx<(), Error>) {
+        let (result_tx, result_rx) = oneshot::channel();
+        self.send_tunnel_command(TunnelCommand::ReinstallSplitTunnelDriver(result_tx));
+
+        tokio::spawn(async move {
+            let result = match result_rx.await {
+                Ok(result) => result.map_err(|error| {
+                    log::error!(
+                        "{}",
+                        error.display_chain_with_msg("Failed to reinstall split tunnel driver")
+                    );
+                    Error::SplitTunnelError(error)
+                }),
+                Err(_) => {
+                    log::error!("The tunnel failed to return a result");
+                    return;
+                }
+            };
+            Self::oneshot_send(tx, result, "reinstall_split_tunnel_driver response");
+        });
+    }
+
     async fn on_update_relay_settings(
         &mut self,
         tx: ResponseTx<(), settings::Error>,
@@ -1771,8 +2633,7 @@ where
             Ok(settings_changed) => {
                 Self::oneshot_send(tx, Ok(()), "update_relay_settings response");
                 if settings_changed {
-                    self.event_listener
-                        .notify_settings(self.settings.to_settings());
+                    self.notify_settings_changed();
                     self.relay_selector
                         .set_config(new_selector_config(&self.settings, &self.app_version_info));
                     log::info!("Initiating tunnel restart because the relay settings changed");
@@ -1786,16 +2647,20 @@ where
         }
     }
 
-    async fn on_set_allow_lan(&mut self, tx: ResponseTx<(), settings::Error>, allow_lan: bool) {
+    async fn on_set_allow_lan(
+        &mut self,
+        tx: ResponseTx<ApplyPlan, settings::Error>,
+        allow_lan: bool,
+    ) {
         let save_result = self.settings.set_allow_lan(allow_lan).await;
         match save_result {
             Ok(settings_changed) => {
-                Self::oneshot_send(tx, Ok(()), "set_allow_lan response");
-                if settings_changed {
-                    self.event_listener
-                        .notify_settings(self.settings.to_settings());
-                    self.send_tunnel_command(TunnelCommand::AllowLan(allow_lan));
-                }
+                let apply_plan = if settings_changed {
+                    self.apply_settings_changes(Some(TunnelCommand::AllowLan(allow_lan)))
+                } else {
+                    ApplyPlan::default()
+                };
+                Self::oneshot_send(tx, Ok(apply_plan), "set_allow_lan response");
             }
             Err(e) => {
                 log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
@@ -1814,8 +2679,7 @@ where
             Ok(settings_changed) => {
                 Self::oneshot_send(tx, Ok(()), "set_show_beta_releases response");
                 if settings_changed {
-                    self.event_listener
-                        .notify_settings(self.settings.to_settings());
+                    self.notify_settings_changed();
                     let mut handle = self.version_updater_handle.clone();
                     handle.set_show_beta_releases(enabled).await;
                 }
@@ -1829,7 +2693,7 @@ where
 
     async fn on_set_block_when_disconnected(
         &mut self,
-        tx: ResponseTx<(), settings::Error>,
+        tx: ResponseTx<ApplyPlan, settings::Error>,
         block_when_disconnected: bool,
     ) {
         let save_result = self
@@ -1838,18 +2702,224 @@ where
             .await;
         match save_result {
             Ok(settings_changed) => {
-                Self::oneshot_send(tx, Ok(()), "set_block_when_disconnected response");
+                let apply_plan = if settings_changed {
+                    // Traffic is not blocked again until the protection pause ends
+                    self.apply_settings_changes(Some(TunnelCommand::BlockWhenDisconnected(
+                        block_when_disconnected && self.protection_pause.resume_at().is_none(),
+                    )))
+                } else {
+                    ApplyPlan::default()
+                };
+                Self::oneshot_send(tx, Ok(apply_plan), "set_block_when_disconnected response");
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_block_when_disconnected response");
+            }
+        }
+    }
+
+    async fn on_set_flush_dns_cache(
+        &mut self,
+        tx: ResponseTx<ApplyPlan, settings::Error>,
+        flush_dns_cache: bool,
+    ) {
+        let save_result = self.settings.set_flush_dns_cache(flush_dns_cache).await;
+        match save_result {
+            Ok(settings_changed) => {
+                let apply_plan = if settings_changed {
+                    self.apply_settings_changes(Some(TunnelCommand::FlushDnsCache(flush_dns_cache)))
+                } else {
+                    ApplyPlan::default()
+                };
+                Self::oneshot_send(tx, Ok(apply_plan), "set_flush_dns_cache response");
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_flush_dns_cache response");
+            }
+        }
+    }
+
+    async fn on_set_recovery_allowlist(
+        &mut self,
+        tx: ResponseTx<ApplyPlan, settings::Error>,
+        recovery_allowlist: Vec<IpNetwork>,
+    ) {
+        let save_result = self
+            .settings
+            .set_recovery_allowlist(recovery_allowlist.clone())
+            .await;
+        match save_result {
+            Ok(settings_changed) => {
+                let apply_plan = if settings_changed {
+                    self.apply_settings_changes(Some(TunnelCommand::RecoveryAllowlist(
+                        recovery_allowlist,
+                    )))
+                } else {
+                    ApplyPlan::default()
+                };
+                Self::oneshot_send(tx, Ok(apply_plan), "set_recovery_allowlist response");
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_recovery_allowlist response");
+            }
+        }
+    }
+
+    async fn on_set_vpn_coexistence(
+        &mut self,
+        tx: ResponseTx<ApplyPlan, settings::Error>,
+        vpn_coexistence: VpnCoexistence,
+    ) {
+        let save_result = self
+            .settings
+            .set_vpn_coexistence(vpn_coexistence.clone())
+            .await;
+        match save_result {
+            Ok(settings_changed) => {
+                let apply_plan = if settings_changed {
+                    self.apply_settings_changes(Some(TunnelCommand::VpnCoexistence(
+                        vpn_coexistence,
+                    )))
+                } else {
+                    ApplyPlan::default()
+                };
+                Self::oneshot_send(tx, Ok(apply_plan), "set_vpn_coexistence response");
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_vpn_coexistence response");
+            }
+        }
+    }
+
+    async fn on_set_blocked_tunnel_protocols(
+        &mut self,
+        tx: ResponseTx<ApplyPlan, settings::Error>,
+        blocked_tunnel_protocols: BlockedTunnelProtocols,
+    ) {
+        let save_result = self
+            .settings
+            .set_blocked_tunnel_protocols(blocked_tunnel_protocols)
+            .await;
+        match save_result {
+            Ok(settings_changed) => {
+                let apply_plan = if settings_changed {
+                    self.apply_settings_changes(Some(TunnelCommand::BlockedTunnelProtocols(
+                        blocked_tunnel_protocols,
+                    )))
+                } else {
+                    ApplyPlan::default()
+                };
+                Self::oneshot_send(tx, Ok(apply_plan), "set_blocked_tunnel_protocols response");
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_blocked_tunnel_protocols response");
+            }
+        }
+    }
+
+    async fn on_set_forced_interface(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        forced_interface: Option<String>,
+    ) {
+        let save_result = self
+            .settings
+            .set_forced_interface(forced_interface.clone())
+            .await;
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set_forced_interface response");
                 if settings_changed {
-                    self.event_listener
-                        .notify_settings(self.settings.to_settings());
-                    self.send_tunnel_command(TunnelCommand::BlockWhenDisconnected(
-                        block_when_disconnected,
-                    ));
+                    self.notify_settings_changed();
+                    self.send_tunnel_command(TunnelCommand::SetForcedInterface(forced_interface));
                 }
             }
             Err(e) => {
                 log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
-                Self::oneshot_send(tx, Err(e), "set_block_when_disconnected response");
+                Self::oneshot_send(tx, Err(e), "set_forced_interface response");
+            }
+        }
+    }
+
+    async fn on_set_reconnect_limits(
+        &mut self,
+        tx: ResponseTx<ApplyPlan, settings::Error>,
+        reconnect_limits: ReconnectLimits,
+    ) {
+        let save_result = self.settings.set_reconnect_limits(reconnect_limits).await;
+        match save_result {
+            Ok(settings_changed) => {
+                let apply_plan = if settings_changed {
+                    self.apply_settings_changes(Some(TunnelCommand::ReconnectLimits(
+                        reconnect_limits,
+                    )))
+                } else {
+                    ApplyPlan::default()
+                };
+                Self::oneshot_send(tx, Ok(apply_plan), "set_reconnect_limits response");
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_reconnect_limits response");
+            }
+        }
+    }
+
+    async fn on_set_route_takeover_policy(
+        &mut self,
+        tx: ResponseTx<ApplyPlan, settings::Error>,
+        route_takeover_policy: RouteTakeoverPolicy,
+    ) {
+        let save_result = self
+            .settings
+            .set_route_takeover_policy(route_takeover_policy)
+            .await;
+        match save_result {
+            Ok(settings_changed) => {
+                let apply_plan = if settings_changed {
+                    self.apply_settings_changes(Some(TunnelCommand::RouteTakeoverPolicy(
+                        route_takeover_policy,
+                    )))
+                } else {
+                    ApplyPlan::default()
+                };
+                Self::oneshot_send(tx, Ok(apply_plan), "set_route_takeover_policy response");
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_route_takeover_policy response");
+            }
+        }
+    }
+
+    async fn on_set_error_state_policy(
+        &mut self,
+        tx: ResponseTx<ApplyPlan, settings::Error>,
+        error_state_policy: ErrorStatePolicy,
+    ) {
+        let save_result = self
+            .settings
+            .set_error_state_policy(error_state_policy)
+            .await;
+        match save_result {
+            Ok(settings_changed) => {
+                let apply_plan = if settings_changed {
+                    self.apply_settings_changes(Some(TunnelCommand::ErrorStatePolicy(
+                        error_state_policy,
+                    )))
+                } else {
+                    ApplyPlan::default()
+                };
+                Self::oneshot_send(tx, Ok(apply_plan), "set_error_state_policy response");
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_error_state_policy response");
             }
         }
     }
@@ -1864,8 +2934,7 @@ where
             Ok(settings_changed) => {
                 Self::oneshot_send(tx, Ok(()), "set auto-connect response");
                 if settings_changed {
-                    self.event_listener
-                        .notify_settings(self.settings.to_settings());
+                    self.notify_settings_changed();
                 }
             }
             Err(e) => {
@@ -1875,28 +2944,60 @@ where
         }
     }
 
-    async fn on_set_openvpn_mssfix(
+    async fn on_set_connection_stats(
         &mut self,
         tx: ResponseTx<(), settings::Error>,
+        connection_stats: bool,
+    ) {
+        let save_result = self.settings.set_connection_stats(connection_stats).await;
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set_connection_stats response");
+                if settings_changed {
+                    self.connection_stats.set_enabled(connection_stats).await;
+                    self.notify_settings_changed();
+                }
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_connection_stats response");
+            }
+        }
+    }
+
+    async fn on_set_hooks(&mut self, tx: ResponseTx<(), settings::Error>, hooks: Vec<Hook>) {
+        let save_result = self.settings.set_hooks(hooks).await;
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set_hooks response");
+                if settings_changed {
+                    self.notify_settings_changed();
+                }
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_hooks response");
+            }
+        }
+    }
+
+    async fn on_set_openvpn_mssfix(
+        &mut self,
+        tx: ResponseTx<ApplyPlan, settings::Error>,
         mssfix_arg: Option<u16>,
     ) {
         let save_result = self.settings.set_openvpn_mssfix(mssfix_arg).await;
         match save_result {
             Ok(settings_changed) => {
-                Self::oneshot_send(tx, Ok(()), "set_openvpn_mssfix response");
-                if settings_changed {
+                let apply_plan = if settings_changed {
                     self.parameters_generator
                         .set_tunnel_options(&self.settings.tunnel_options)
                         .await;
-                    self.event_listener
-                        .notify_settings(self.settings.to_settings());
-                    if self.get_target_tunnel_type() == Some(TunnelType::OpenVpn) {
-                        log::info!(
-                            "Initiating tunnel restart because the OpenVPN mssfix setting changed"
-                        );
-                        self.reconnect_tunnel();
-                    }
-                }
+                    self.apply_settings_changes(None)
+                } else {
+                    ApplyPlan::default()
+                };
+                Self::oneshot_send(tx, Ok(apply_plan), "set_openvpn_mssfix response");
             }
             Err(e) => {
                 log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
@@ -1907,22 +3008,22 @@ where
 
     async fn on_set_bridge_settings(
         &mut self,
-        tx: ResponseTx<(), settings::Error>,
+        tx: ResponseTx<ApplyPlan, settings::Error>,
         new_settings: BridgeSettings,
     ) {
         match self.settings.set_bridge_settings(new_settings).await {
             Ok(settings_changes) => {
-                if settings_changes {
-                    self.event_listener
-                        .notify_settings(self.settings.to_settings());
+                let apply_plan = if settings_changes {
                     self.relay_selector
                         .set_config(new_selector_config(&self.settings, &self.app_version_info));
                     if let Err(error) = self.api_handle.service().next_api_endpoint().await {
                         log::error!("Failed to rotate API endpoint: {}", error);
                     }
-                    self.reconnect_tunnel();
+                    self.apply_settings_changes(None)
+                } else {
+                    ApplyPlan::default()
                 };
-                Self::oneshot_send(tx, Ok(()), "set_bridge_settings");
+                Self::oneshot_send(tx, Ok(apply_plan), "set_bridge_settings");
             }
 
             Err(e) => {
@@ -1937,19 +3038,19 @@ where
 
     async fn on_set_obfuscation_settings(
         &mut self,
-        tx: ResponseTx<(), settings::Error>,
+        tx: ResponseTx<ApplyPlan, settings::Error>,
         new_settings: ObfuscationSettings,
     ) {
         match self.settings.set_obfuscation_settings(new_settings).await {
             Ok(settings_changed) => {
-                if settings_changed {
-                    self.event_listener
-                        .notify_settings(self.settings.to_settings());
+                let apply_plan = if settings_changed {
                     self.relay_selector
                         .set_config(new_selector_config(&self.settings, &self.app_version_info));
-                    self.reconnect_tunnel();
-                }
-                Self::oneshot_send(tx, Ok(()), "set_obfuscation_settings");
+                    self.apply_settings_changes(None)
+                } else {
+                    ApplyPlan::default()
+                };
+                Self::oneshot_send(tx, Ok(apply_plan), "set_obfuscation_settings");
             }
             Err(err) => {
                 log::error!(
@@ -1963,20 +3064,18 @@ where
 
     async fn on_set_bridge_state(
         &mut self,
-        tx: ResponseTx<(), settings::Error>,
+        tx: ResponseTx<ApplyPlan, settings::Error>,
         bridge_state: BridgeState,
     ) {
         let result = match self.settings.set_bridge_state(bridge_state).await {
             Ok(settings_changed) => {
                 if settings_changed {
-                    self.event_listener
-                        .notify_settings(self.settings.to_settings());
                     self.relay_selector
                         .set_config(new_selector_config(&self.settings, &self.app_version_info));
-                    log::info!("Initiating tunnel restart because bridge state changed");
-                    self.reconnect_tunnel();
+                    Ok(self.apply_settings_changes(None))
+                } else {
+                    Ok(ApplyPlan::default())
                 }
-                Ok(())
             }
             Err(error) => {
                 log::error!(
@@ -1989,20 +3088,23 @@ where
         Self::oneshot_send(tx, result, "on_set_bridge_state response");
     }
 
-    async fn on_set_enable_ipv6(&mut self, tx: ResponseTx<(), settings::Error>, enable_ipv6: bool) {
+    async fn on_set_enable_ipv6(
+        &mut self,
+        tx: ResponseTx<ApplyPlan, settings::Error>,
+        enable_ipv6: bool,
+    ) {
         let save_result = self.settings.set_enable_ipv6(enable_ipv6).await;
         match save_result {
             Ok(settings_changed) => {
-                Self::oneshot_send(tx, Ok(()), "set_enable_ipv6 response");
-                if settings_changed {
+                let apply_plan = if settings_changed {
                     self.parameters_generator
                         .set_tunnel_options(&self.settings.tunnel_options)
                         .await;
-                    self.event_listener
-                        .notify_settings(self.settings.to_settings());
-                    log::info!("Initiating tunnel restart because the enable IPv6 setting changed");
-                    self.reconnect_tunnel();
-                }
+                    self.apply_settings_changes(None)
+                } else {
+                    ApplyPlan::default()
+                };
+                Self::oneshot_send(tx, Ok(apply_plan), "set_enable_ipv6 response");
             }
             Err(e) => {
                 log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
@@ -2011,9 +3113,34 @@ where
         }
     }
 
+    async fn on_set_enable_ipv4(
+        &mut self,
+        tx: ResponseTx<ApplyPlan, settings::Error>,
+        enable_ipv4: bool,
+    ) {
+        let save_result = self.settings.set_enable_ipv4(enable_ipv4).await;
+        match save_result {
+            Ok(settings_changed) => {
+                let apply_plan = if settings_changed {
+                    self.parameters_generator
+                        .set_tunnel_options(&self.settings.tunnel_options)
+                        .await;
+                    self.apply_settings_changes(None)
+                } else {
+                    ApplyPlan::default()
+                };
+                Self::oneshot_send(tx, Ok(apply_plan), "set_enable_ipv4 response");
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_enable_ipv4 response");
+            }
+        }
+    }
+
     async fn on_set_quantum_resistant_tunnel(
         &mut self,
-        tx: ResponseTx<(), settings::Error>,
+        tx: ResponseTx<ApplyPlan, settings::Error>,
         use_pq_safe_psk: bool,
     ) {
         let save_result = self
@@ -2022,18 +3149,15 @@ where
             .await;
         match save_result {
             Ok(settings_changed) => {
-                Self::oneshot_send(tx, Ok(()), "set_quantum_resistant_tunnel response");
-                if settings_changed {
+                let apply_plan = if settings_changed {
                     self.parameters_generator
                         .set_tunnel_options(&self.settings.tunnel_options)
                         .await;
-                    self.event_listener
-                        .notify_settings(self.settings.to_settings());
-                    if self.get_target_tunnel_type() == Some(TunnelType::Wireguard) {
-                        log::info!("Reconnecting because the PQ safety setting changed");
-                        self.reconnect_tunnel();
-                    }
-                }
+                    self.apply_settings_changes(None)
+                } else {
+                    ApplyPlan::default()
+                };
+                Self::oneshot_send(tx, Ok(apply_plan), "set_quantum_resistant_tunnel response");
             }
             Err(e) => {
                 log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
@@ -2042,25 +3166,101 @@ where
         }
     }
 
+    async fn on_set_wireguard_traffic_shaping(
+        &mut self,
+        tx: ResponseTx<ApplyPlan, settings::Error>,
+        options: TrafficShapingOptions,
+    ) {
+        let save_result = self.settings.set_wireguard_traffic_shaping(options).await;
+        match save_result {
+            Ok(settings_changed) => {
+                let apply_plan = if settings_changed {
+                    self.parameters_generator
+                        .set_tunnel_options(&self.settings.tunnel_options)
+                        .await;
+                    self.apply_settings_changes(Some(TunnelCommand::TrafficShaping(options)))
+                } else {
+                    ApplyPlan::default()
+                };
+                Self::oneshot_send(tx, Ok(apply_plan), "set_wireguard_traffic_shaping response");
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_wireguard_traffic_shaping response");
+            }
+        }
+    }
+
+    async fn on_set_wireguard_bandwidth_limit(
+        &mut self,
+        tx: ResponseTx<ApplyPlan, settings::Error>,
+        limit: BandwidthLimit,
+    ) {
+        let save_result = self.settings.set_wireguard_bandwidth_limit(limit).await;
+        match save_result {
+            Ok(settings_changed) => {
+                let apply_plan = if settings_changed {
+                    self.parameters_generator
+                        .set_tunnel_options(&self.settings.tunnel_options)
+                        .await;
+                    self.apply_settings_changes(None)
+                } else {
+                    ApplyPlan::default()
+                };
+                Self::oneshot_send(tx, Ok(apply_plan), "set_wireguard_bandwidth_limit response");
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_wireguard_bandwidth_limit response");
+            }
+        }
+    }
+
+    async fn on_set_wireguard_allowed_ips(
+        &mut self,
+        tx: ResponseTx<ApplyPlan, settings::Error>,
+        allowed_ips: Option<Vec<IpNetwork>>,
+    ) {
+        let save_result = self.settings.set_wireguard_allowed_ips(allowed_ips).await;
+        match save_result {
+            Ok(settings_changed) => {
+                let apply_plan = if settings_changed {
+                    self.parameters_generator
+                        .set_tunnel_options(&self.settings.tunnel_options)
+                        .await;
+                    self.apply_settings_changes(None)
+                } else {
+                    ApplyPlan::default()
+                };
+                Self::oneshot_send(tx, Ok(apply_plan), "set_wireguard_allowed_ips response");
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_wireguard_allowed_ips response");
+            }
+        }
+    }
+
     async fn on_set_dns_options(
         &mut self,
-        tx: ResponseTx<(), settings::Error>,
+        tx: ResponseTx<ApplyPlan, settings::Error>,
         dns_options: DnsOptions,
     ) {
         let save_result = self.settings.set_dns_options(dns_options.clone()).await;
         match save_result {
             Ok(settings_changed) => {
-                Self::oneshot_send(tx, Ok(()), "set_dns_options response");
-                if settings_changed {
+                let apply_plan = if settings_changed {
                     let settings = self.settings.to_settings();
                     let resolvers =
                         dns::addresses_from_options(&settings.tunnel_options.dns_options);
                     self.parameters_generator
                         .set_tunnel_options(&settings.tunnel_options)
                         .await;
-                    self.event_listener.notify_settings(settings);
-                    self.send_tunnel_command(TunnelCommand::Dns(resolvers));
-                }
+                    self.apply_settings_changes(Some(TunnelCommand::Dns(resolvers)))
+                } else {
+                    ApplyPlan::default()
+                };
+                Self::oneshot_send(tx, Ok(apply_plan), "set_dns_options response");
             }
             Err(e) => {
                 log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
@@ -2071,26 +3271,21 @@ where
 
     async fn on_set_wireguard_mtu(
         &mut self,
-        tx: ResponseTx<(), settings::Error>,
+        tx: ResponseTx<ApplyPlan, settings::Error>,
         mtu: Option<u16>,
     ) {
         let save_result = self.settings.set_wireguard_mtu(mtu).await;
         match save_result {
             Ok(settings_changed) => {
-                Self::oneshot_send(tx, Ok(()), "set_wireguard_mtu response");
-                if settings_changed {
+                let apply_plan = if settings_changed {
                     self.parameters_generator
                         .set_tunnel_options(&self.settings.tunnel_options)
                         .await;
-                    self.event_listener
-                        .notify_settings(self.settings.to_settings());
-                    if let Some(TunnelType::Wireguard) = self.get_connected_tunnel_type() {
-                        log::info!(
-                            "Initiating tunnel restart because the WireGuard MTU setting changed"
-                        );
-                        self.reconnect_tunnel();
-                    }
-                }
+                    self.apply_settings_changes(None)
+                } else {
+                    ApplyPlan::default()
+                };
+                Self::oneshot_send(tx, Ok(apply_plan), "set_wireguard_mtu response");
             }
             Err(e) => {
                 log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
@@ -2125,8 +3320,7 @@ where
                     self.parameters_generator
                         .set_tunnel_options(&self.settings.tunnel_options)
                         .await;
-                    self.event_listener
-                        .notify_settings(self.settings.to_settings());
+                    self.notify_settings_changed();
                 }
             }
             Err(e) => {
@@ -2162,6 +3356,122 @@ where
         Self::oneshot_send(tx, self.settings.to_settings(), "get_settings response");
     }
 
+    fn on_get_profiles(&self, tx: oneshot::Sender<Vec<Profile>>) {
+        Self::oneshot_send(tx, self.settings.profiles.clone(), "get_profiles response");
+    }
+
+    async fn on_save_profile(&mut self, tx: ResponseTx<(), Error>, name: String) {
+        if self.settings.get_profile(&name).is_none()
+            && self.settings.profiles.len() >= MAX_PROFILES
+        {
+            Self::oneshot_send(tx, Err(Error::TooManyProfiles), "save_profile response");
+            return;
+        }
+        let profile = Profile::from_settings(name, &self.settings);
+        match self.settings.save_profile(profile).await {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "save_profile response");
+                if settings_changed {
+                    self.notify_settings_changed();
+                }
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(Error::SettingsError(e)), "save_profile response");
+            }
+        }
+    }
+
+    async fn on_remove_profile(&mut self, tx: ResponseTx<(), Error>, name: String) {
+        match self.settings.remove_profile(&name).await {
+            Ok(true) => {
+                Self::oneshot_send(tx, Ok(()), "remove_profile response");
+                self.notify_settings_changed();
+            }
+            Ok(false) => Self::oneshot_send(
+                tx,
+                Err(Error::ProfileNotFound(name)),
+                "remove_profile response",
+            ),
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(Error::SettingsError(e)), "remove_profile response");
+            }
+        }
+    }
+
+    /// Applies all settings of a profile at once, so that switching profiles reconnects at most
+    /// once, and not at all if only settings that can be applied in place differ.
+    async fn on_apply_profile(&mut self, tx: ResponseTx<ApplyPlan, Error>, name: String) {
+        let profile = match self.settings.get_profile(&name) {
+            Some(profile) => profile.clone(),
+            None => {
+                Self::oneshot_send(
+                    tx,
+                    Err(Error::ProfileNotFound(name)),
+                    "apply_profile response",
+                );
+                return;
+            }
+        };
+        let old_settings = self.settings.to_settings();
+        match self.settings.apply_profile(&profile).await {
+            Ok(settings_changed) => {
+                if !settings_changed {
+                    Self::oneshot_send(tx, Ok(ApplyPlan::default()), "apply_profile response");
+                    return;
+                }
+                log::info!("Applied profile \"{}\"", profile.name);
+                self.relay_selector
+                    .set_config(new_selector_config(&self.settings, &self.app_version_info));
+                self.parameters_generator
+                    .set_tunnel_options(&self.settings.tunnel_options)
+                    .await;
+                #[cfg(windows)]
+                if old_settings.split_tunnel != self.settings.split_tunnel {
+                    self.apply_split_tunnel_settings();
+                }
+                let dns_command = if old_settings.tunnel_options.dns_options
+                    != self.settings.tunnel_options.dns_options
+                {
+                    Some(TunnelCommand::Dns(dns::addresses_from_options(
+                        &self.settings.tunnel_options.dns_options,
+                    )))
+                } else {
+                    None
+                };
+                let apply_plan = self.apply_settings_changes(dns_command);
+                Self::oneshot_send(tx, Ok(apply_plan), "apply_profile response");
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(Error::SettingsError(e)), "apply_profile response");
+            }
+        }
+    }
+
+    /// Sends the excluded apps in the split tunnel settings to the tunnel state machine. Failures
+    /// are only logged.
+    #[cfg(windows)]
+    fn apply_split_tunnel_settings(&mut self) {
+        let split_tunnel = &self.settings.split_tunnel;
+        let apps = if split_tunnel.enable_exclusions {
+            split_tunnel.apps.iter().map(OsString::from).collect()
+        } else {
+            vec![]
+        };
+        let (result_tx, result_rx) = oneshot::channel();
+        self.send_tunnel_command(TunnelCommand::SetExcludedApps(result_tx, apps));
+        tokio::spawn(async move {
+            if let Ok(Err(error)) = result_rx.await {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to set excluded apps list")
+                );
+            }
+        });
+    }
+
     fn oneshot_send<T>(tx: oneshot::Sender<T>, t: T, msg: &'static str) {
         if tx.send(t).is_err() {
             log::warn!("Unable to send {} to the daemon command sender", msg);
@@ -2169,6 +3479,9 @@ where
     }
 
     fn trigger_shutdown_event(&mut self, user_init_shutdown: bool) {
+        #[cfg(target_os = "linux")]
+        systemd::notify_stopping();
+
         // Block all traffic before shutting down to ensure that no traffic can leak on boot or
         // shutdown.
         if !user_init_shutdown
@@ -2179,7 +3492,43 @@ where
         }
 
         self.state.shutdown(&self.tunnel_state);
-        self.disconnect_tunnel();
+        self.disconnect_tunnel(DisconnectCause::Shutdown);
+    }
+
+    /// Broadcasts the new settings, along with the fields that changed and how the changes are
+    /// applied to the tunnel. Returns the plan for applying the changes.
+    fn notify_settings_changed(&mut self) -> ApplyPlan {
+        let changed_fields = self.settings.take_changed_fields();
+        let apply_plan = ApplyPlan::new(&changed_fields, self.current_tunnel());
+        if !changed_fields.is_empty() {
+            log::info!(
+                "Settings changed: {} ({})",
+                changed_fields.join(", "),
+                apply_plan
+            );
+        }
+        self.event_listener.notify_settings(
+            self.settings.to_settings(),
+            changed_fields,
+            &apply_plan,
+        );
+        apply_plan
+    }
+
+    /// Applies changed settings to the tunnel using the least disruptive operations, and returns
+    /// the plan that was followed. `update_command` is sent to the tunnel state machine to apply
+    /// the change in place, if that is possible. The tunnel is only reconnected if the change
+    /// cannot be applied otherwise.
+    fn apply_settings_changes(&mut self, update_command: Option<TunnelCommand>) -> ApplyPlan {
+        let apply_plan = self.notify_settings_changed();
+        if let Some(command) = update_command {
+            self.send_tunnel_command(command);
+        }
+        if apply_plan.contains(ApplyOperation::Reconnect) {
+            log::info!("Reconnecting to apply the changed settings");
+            self.reconnect_tunnel();
+        }
+        apply_plan
     }
 
     fn on_prepare_restart(&mut self) {
@@ -2192,6 +3541,52 @@ where
         self.target_state.lock();
     }
 
+    #[cfg(feature = "qa-tools")]
+    fn on_inject_simulated_event(
+        &mut self,
+        tx: ResponseTx<(), tunnel_state_machine::SimulationError>,
+        event: tunnel_state_machine::SimulatedEvent,
+    ) {
+        let result = self
+            .tunnel_state_machine_handle
+            .simulation()
+            .inject(event)
+            .map_err(|error| {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to inject simulated event")
+                );
+                error
+            });
+        Self::oneshot_send(tx, result, "inject_simulated_event response");
+    }
+
+    #[cfg(all(feature = "packet-capture", target_os = "linux"))]
+    fn on_start_packet_capture(
+        &mut self,
+        tx: ResponseTx<PathBuf, talpid_core::packet_capture::Error>,
+        limits: talpid_core::packet_capture::CaptureLimits,
+    ) {
+        let result = self
+            .tunnel_state_machine_handle
+            .packet_capture()
+            .start(limits)
+            .map_err(|error| {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to start packet capture")
+                );
+                error
+            });
+        Self::oneshot_send(tx, result, "start_packet_capture response");
+    }
+
+    #[cfg(all(feature = "packet-capture", target_os = "linux"))]
+    fn on_stop_packet_capture(&mut self, tx: oneshot::Sender<bool>) {
+        let was_running = self.tunnel_state_machine_handle.packet_capture().stop();
+        Self::oneshot_send(tx, was_running, "stop_packet_capture response");
+    }
+
     #[cfg(target_os = "android")]
     fn on_bypass_socket(&mut self, fd: RawFd, tx: oneshot::Sender<()>) {
         match self.tunnel_state {
@@ -2208,16 +3603,22 @@ where
 
     /// Set the target state of the client. If it changed trigger the operations needed to
     /// progress towards that state.
+    /// `disconnect_cause` is reported to listeners if this disconnects the tunnel.
     /// Returns a bool representing whether or not a state change was initiated.
-    async fn set_target_state(&mut self, new_state: TargetState) -> bool {
+    async fn set_target_state(
+        &mut self,
+        new_state: TargetState,
+        disconnect_cause: DisconnectCause,
+    ) -> bool {
         if new_state != *self.target_state || self.tunnel_state.is_in_error_state() {
             log::debug!("Target state {:?} => {:?}", *self.target_state, new_state);
 
             self.target_state.set(new_state).await;
+            self.protection_tracker.set_target_state(new_state).await;
 
             match *self.target_state {
                 TargetState::Secured => self.connect_tunnel(),
-                TargetState::Unsecured => self.disconnect_tunnel(),
+                TargetState::Unsecured => self.disconnect_tunnel(disconnect_cause),
             }
             true
         } else {
@@ -2227,11 +3628,27 @@ where
 
     fn connect_tunnel(&mut self) {
         self.api_runtime.availability_handle().resume_background();
-        self.send_tunnel_command(TunnelCommand::Connect);
+        self.tunnel_state_machine_handle
+            .connect()
+            .expect(TUNNEL_STATE_MACHINE_STOPPED);
+    }
+
+    fn disconnect_tunnel(&mut self, cause: DisconnectCause) {
+        self.tunnel_state_machine_handle
+            .disconnect(cause)
+            .expect(TUNNEL_STATE_MACHINE_STOPPED);
     }
 
-    fn disconnect_tunnel(&mut self) {
-        self.send_tunnel_command(TunnelCommand::Disconnect);
+    /// Returns whether the tunnel is blocked because this device was revoked.
+    fn is_blocked_by_revoked_device(&self) -> bool {
+        match &self.tunnel_state {
+            TunnelState::Error(error_state) => matches!(
+                error_state.cause(),
+                ErrorStateCause::AuthFailed(Some(reason))
+                    if reason == auth_failed::DEVICE_REVOKED_REASON
+            ),
+            _ => false,
+        }
     }
 
     fn reconnect_tunnel(&mut self) {
@@ -2240,15 +3657,22 @@ where
         }
     }
 
-    fn get_connected_tunnel_type(&self) -> Option<TunnelType> {
-        if let TunnelState::Connected {
-            endpoint: TunnelEndpoint { tunnel_type, .. },
-            ..
-        } = self.tunnel_state
-        {
-            Some(tunnel_type)
-        } else {
-            None
+    /// Returns the tunnel that changed settings are applied to.
+    fn current_tunnel(&self) -> CurrentTunnel {
+        match self.tunnel_state {
+            TunnelState::Connected {
+                endpoint: TunnelEndpoint { tunnel_type, .. },
+                ..
+            } => CurrentTunnel::Connected(tunnel_type),
+            TunnelState::Connecting {
+                endpoint: TunnelEndpoint { tunnel_type, .. },
+                ..
+            }
+            | TunnelState::Paused {
+                endpoint: TunnelEndpoint { tunnel_type, .. },
+                ..
+            } => CurrentTunnel::Connecting(tunnel_type),
+            _ => CurrentTunnel::None,
         }
     }
 
@@ -2261,6 +3685,10 @@ where
             | TunnelState::Connecting {
                 endpoint: TunnelEndpoint { tunnel_type, .. },
                 ..
+            }
+            | TunnelState::Paused {
+                endpoint: TunnelEndpoint { tunnel_type, .. },
+                ..
             } => Some(tunnel_type),
             _ => None,
         }
@@ -2268,9 +3696,8 @@ where
 
     fn send_tunnel_command(&self, command: TunnelCommand) {
         self.tunnel_state_machine_handle
-            .command_tx()
-            .unbounded_send(command)
-            .expect("Tunnel state machine has stopped");
+            .send(command)
+            .expect(TUNNEL_STATE_MACHINE_STOPPED);
     }
 
     pub fn shutdown_handle(&self) -> DaemonShutdownHandle {
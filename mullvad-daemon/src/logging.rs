@@ -2,8 +2,15 @@ use fern::{
     colors::{Color, ColoredLevelConfig},
     Output,
 };
-use std::{fmt, io, path::PathBuf};
+use parking_lot::{Mutex, RwLock};
+use std::{
+    collections::HashMap,
+    fmt, io,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 use talpid_core::logging::rotate_log;
+use tokio::sync::mpsc;
 
 #[derive(err_derive::Error, Debug)]
 pub enum Error {
@@ -62,22 +69,42 @@ const LINE_SEPARATOR: &str = "\r\n";
 
 const DATE_TIME_FORMAT_STR: &str = "[%Y-%m-%d %H:%M:%S%.3f]";
 
+/// Longest message, in bytes, that is pushed to log subscribers. Longer messages are truncated.
+const MAX_PUSHED_MESSAGE_LEN: usize = 4096;
+/// Number of lines from a single subsystem that a subscriber receives per [`RATE_LIMIT_WINDOW`].
+/// Further lines are dropped, and the number of dropped lines is reported afterwards.
+const RATE_LIMIT_LINES: u32 = 100;
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(1);
+/// Number of lines buffered for each subscriber. Lines are dropped while the buffer is full.
+const SUBSCRIBER_BUFFER_SIZE: usize = 1024;
+
+lazy_static::lazy_static! {
+    static ref LOG_SUBSCRIBERS: Mutex<Vec<LogSubscriber>> = Mutex::new(vec![]);
+    /// Log level that the daemon was started with.
+    static ref BASE_LOG_LEVEL: Mutex<log::LevelFilter> = Mutex::new(log::LevelFilter::Info);
+    /// Levels that targets are logged at, so that the runtime log filter does not have to be
+    /// consulted for every record.
+    static ref LEVEL_CACHE: RwLock<LevelCache> = RwLock::new(LevelCache::default());
+}
+
+#[derive(Default)]
+struct LevelCache {
+    /// Increased whenever the cache is cleared, so that levels that were looked up in a previous
+    /// configuration are not inserted afterwards.
+    generation: u64,
+    levels: HashMap<String, log::LevelFilter>,
+}
+
 pub fn init_logger(
     log_level: log::LevelFilter,
     log_file: Option<&PathBuf>,
     output_timestamp: bool,
 ) -> Result<(), Error> {
-    let mut top_dispatcher = fern::Dispatch::new().level(log_level);
-    for silenced_crate in WARNING_SILENCED_CRATES {
-        top_dispatcher = top_dispatcher.level_for(*silenced_crate, log::LevelFilter::Error);
-    }
-    for silenced_crate in SILENCED_CRATES {
-        top_dispatcher = top_dispatcher.level_for(*silenced_crate, log::LevelFilter::Warn);
-    }
-    for silenced_crate in SLIGHTLY_SILENCED_CRATES {
-        top_dispatcher = top_dispatcher.level_for(*silenced_crate, one_level_quieter(log_level));
-    }
-
+    // Levels are checked by the filter rather than by `level_for`, since the runtime log filter
+    // may change while the daemon is running
+    let mut top_dispatcher = fern::Dispatch::new()
+        .level(log::LevelFilter::Trace)
+        .filter(move |metadata| metadata.level() <= cached_level_for(metadata.target(), log_level));
     let stdout_formatter = Formatter {
         output_timestamp,
         output_color: true,
@@ -102,6 +129,8 @@ pub fn init_logger(
             .chain(Output::file(f, LINE_SEPARATOR));
         top_dispatcher = top_dispatcher.chain(file_dispatcher);
     }
+    let broadcaster: Box<dyn log::Log> = Box::new(LogBroadcaster);
+    top_dispatcher = top_dispatcher.chain(broadcaster);
     #[cfg(all(target_os = "android", debug_assertions))]
     {
         use android_logger::{AndroidLogger, Config};
@@ -111,9 +140,59 @@ pub fn init_logger(
         top_dispatcher = top_dispatcher.chain(logger);
     }
     top_dispatcher.apply().map_err(Error::SetLoggerError)?;
+    *BASE_LOG_LEVEL.lock() = log_level;
+    apply_runtime_log_filter();
     Ok(())
 }
 
+/// Updates the maximum log level after the runtime log filter has changed, so that records that
+/// the filter enables are not discarded before they reach the logger.
+pub fn apply_runtime_log_filter() {
+    {
+        let mut cache = LEVEL_CACHE.write();
+        cache.generation += 1;
+        cache.levels.clear();
+    }
+    let base_level = *BASE_LOG_LEVEL.lock();
+    let runtime_level = talpid_core::runtime_config::get()
+        .log_filter
+        .max_level()
+        .unwrap_or(log::LevelFilter::Off);
+    log::set_max_level(std::cmp::max(base_level, runtime_level));
+}
+
+/// Returns the level that `target` is logged at.
+fn cached_level_for(target: &str, log_level: log::LevelFilter) -> log::LevelFilter {
+    let generation = {
+        let cache = LEVEL_CACHE.read();
+        if let Some(level) = cache.levels.get(target) {
+            return *level;
+        }
+        cache.generation
+    };
+    let level = talpid_core::runtime_config::log_level_for(target)
+        .unwrap_or_else(|| static_level_for(target, log_level));
+    let mut cache = LEVEL_CACHE.write();
+    if cache.generation == generation {
+        cache.levels.insert(target.to_owned(), level);
+    }
+    level
+}
+
+/// Returns the level that `target` is logged at unless the runtime log filter overrides it.
+fn static_level_for(target: &str, log_level: log::LevelFilter) -> log::LevelFilter {
+    let crate_name = target.split("::").next().unwrap_or(target);
+    if WARNING_SILENCED_CRATES.contains(&crate_name) {
+        log::LevelFilter::Error
+    } else if SILENCED_CRATES.contains(&crate_name) {
+        log::LevelFilter::Warn
+    } else if SLIGHTLY_SILENCED_CRATES.contains(&crate_name) {
+        one_level_quieter(log_level)
+    } else {
+        log_level
+    }
+}
+
 fn one_level_quieter(level: log::LevelFilter) -> log::LevelFilter {
     use log::LevelFilter::*;
     match level {
@@ -156,17 +235,150 @@ impl Formatter {
         record: &log::Record<'_>,
     ) {
         let message = escape_newlines(format!("{}", message));
+        let attempt = talpid_core::logging::connection_attempt_for(record.target())
+            .map(|attempt| format!("[attempt {}]", attempt))
+            .unwrap_or_default();
 
         out.finish(format_args!(
-            "{}[{}][{}] {}",
+            "{}[{}][{}]{} {}",
             chrono::Local::now().format(self.get_timetsamp_fmt()),
             record.target(),
             self.get_record_level(record.level()),
+            attempt,
             message,
         ))
     }
 }
 
+/// A log line pushed to subscribers.
+#[derive(Debug, Clone)]
+pub struct LogLine {
+    pub time: chrono::DateTime<chrono::Utc>,
+    pub level: log::Level,
+    /// Module that logged the line.
+    pub subsystem: String,
+    pub message: String,
+    /// Connection attempt that the line belongs to, if any.
+    pub connection_attempt: Option<talpid_types::tunnel::ConnectionAttemptId>,
+}
+
+/// Returns a channel that receives the log lines of `min_level` or more severe, from the given
+/// subsystems and their submodules, or from all subsystems if `subsystems` is empty. Lines below
+/// the log level of the daemon are never received. Dropping the receiver ends the subscription.
+pub fn subscribe(min_level: log::Level, subsystems: Vec<String>) -> mpsc::Receiver<LogLine> {
+    let (tx, rx) = mpsc::channel(SUBSCRIBER_BUFFER_SIZE);
+    LOG_SUBSCRIBERS.lock().push(LogSubscriber {
+        min_level,
+        subsystems,
+        tx,
+        rate_limits: HashMap::new(),
+    });
+    rx
+}
+
+struct LogSubscriber {
+    min_level: log::Level,
+    subsystems: Vec<String>,
+    tx: mpsc::Sender<LogLine>,
+    rate_limits: HashMap<String, RateLimit>,
+}
+
+struct RateLimit {
+    window_start: Instant,
+    lines: u32,
+    dropped: u32,
+}
+
+impl LogSubscriber {
+    fn wants(&self, subsystem: &str) -> bool {
+        self.subsystems.is_empty()
+            || self.subsystems.iter().any(|wanted| {
+                subsystem == wanted
+                    || (subsystem.starts_with(wanted.as_str())
+                        && subsystem[wanted.len()..].starts_with("::"))
+            })
+    }
+
+    fn push(&mut self, record: &log::Record<'_>, now: Instant) {
+        let subsystem = record.target();
+        if record.level() > self.min_level || !self.wants(subsystem) {
+            return;
+        }
+
+        let rate_limit = self
+            .rate_limits
+            .entry(subsystem.to_owned())
+            .or_insert(RateLimit {
+                window_start: now,
+                lines: 0,
+                dropped: 0,
+            });
+        if now.duration_since(rate_limit.window_start) >= RATE_LIMIT_WINDOW {
+            let dropped = rate_limit.dropped;
+            *rate_limit = RateLimit {
+                window_start: now,
+                lines: 0,
+                dropped: 0,
+            };
+            if dropped > 0 {
+                let _ = self.tx.try_send(LogLine {
+                    time: chrono::Utc::now(),
+                    level: log::Level::Warn,
+                    subsystem: subsystem.to_owned(),
+                    message: format!("{} lines were dropped due to rate limiting", dropped),
+                    connection_attempt: None,
+                });
+            }
+        }
+        if rate_limit.lines >= RATE_LIMIT_LINES {
+            rate_limit.dropped += 1;
+            return;
+        }
+        rate_limit.lines += 1;
+
+        let mut message = record.args().to_string();
+        if message.len() > MAX_PUSHED_MESSAGE_LEN {
+            let mut end = MAX_PUSHED_MESSAGE_LEN;
+            while !message.is_char_boundary(end) {
+                end -= 1;
+            }
+            message.truncate(end);
+            message.push_str(" [truncated]");
+        }
+        let _ = self.tx.try_send(LogLine {
+            time: chrono::Utc::now(),
+            level: record.level(),
+            subsystem: subsystem.to_owned(),
+            message,
+            connection_attempt: talpid_core::logging::connection_attempt_for(subsystem),
+        });
+    }
+}
+
+/// Pushes log records to the subscribers created by [`subscribe`]. This must never log anything
+/// itself.
+struct LogBroadcaster;
+
+impl log::Log for LogBroadcaster {
+    fn enabled(&self, _metadata: &log::Metadata<'_>) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record<'_>) {
+        let mut subscribers = LOG_SUBSCRIBERS.lock();
+        if subscribers.is_empty() {
+            return;
+        }
+        subscribers.retain(|subscriber| !subscriber.tx.is_closed());
+        let now = Instant::now();
+        for subscriber in subscribers.iter_mut() {
+            subscriber.push(record, now);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
 #[cfg(not(windows))]
 fn escape_newlines(text: String) -> String {
     text
@@ -176,3 +388,23 @@ fn escape_newlines(text: String) -> String {
 fn escape_newlines(text: String) -> String {
     text.replace("\n", LINE_SEPARATOR)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_subsystem_filter() {
+        let (tx, _rx) = mpsc::channel(1);
+        let subscriber = LogSubscriber {
+            min_level: log::Level::Info,
+            subsystems: vec!["talpid_core::firewall".to_owned()],
+            tx,
+            rate_limits: HashMap::new(),
+        };
+        assert!(subscriber.wants("talpid_core::firewall"));
+        assert!(subscriber.wants("talpid_core::firewall::linux"));
+        assert!(!subscriber.wants("talpid_core::firewall_conflicts"));
+        assert!(!subscriber.wants("mullvad_daemon"));
+    }
+}
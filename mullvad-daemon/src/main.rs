@@ -20,6 +20,8 @@ mod system_service;
 const DAEMON_LOG_FILENAME: &str = "daemon.log";
 #[cfg(target_os = "linux")]
 const EARLY_BOOT_LOG_FILENAME: &str = "early-boot-fw.log";
+#[cfg(target_os = "linux")]
+const FIREWALL_WATCHDOG_LOG_FILENAME: &str = "firewall-watchdog.log";
 
 fn main() {
     let config = cli::get_config();
@@ -72,6 +74,15 @@ fn init_logging(config: &cli::Config) -> Result<Option<PathBuf>, String> {
         return Ok(None);
     }
 
+    #[cfg(target_os = "linux")]
+    if config.run_firewall_watchdog {
+        if initialize_logging(log_path(FIREWALL_WATCHDOG_LOG_FILENAME)).is_err() {
+            let _ = initialize_logging(None);
+        }
+
+        return Ok(None);
+    }
+
     initialize_logging(log_path(DAEMON_LOG_FILENAME))?;
 
     if let Some(ref log_dir) = log_dir {
@@ -114,9 +125,31 @@ async fn run_platform(config: &cli::Config, log_dir: Option<PathBuf>) -> Result<
             .await
             .map_err(|err| format!("{}", err));
     }
+    if config.run_firewall_watchdog {
+        return run_firewall_watchdog().await;
+    }
     run_standalone(log_dir).await
 }
 
+/// Waits for the daemon that spawned this process to exit, and blocks all traffic if it died while
+/// traffic was protected.
+#[cfg(target_os = "linux")]
+async fn run_firewall_watchdog() -> Result<(), String> {
+    let daemon_died =
+        tokio::task::spawn_blocking(mullvad_daemon::firewall_watchdog::wait_for_daemon)
+            .await
+            .map_err(|err| format!("{}", err))?;
+    if !daemon_died {
+        return Ok(());
+    }
+    log::error!(
+        "The daemon stopped unexpectedly while traffic was protected. Blocking all traffic"
+    );
+    crate::early_boot_firewall::initialize_firewall()
+        .await
+        .map_err(|err| format!("{}", err))
+}
+
 #[cfg(not(any(windows, target_os = "linux")))]
 async fn run_platform(_config: &cli::Config, log_dir: Option<PathBuf>) -> Result<(), String> {
     run_standalone(log_dir).await
@@ -193,6 +226,15 @@ async fn spawn_management_interface(
 
     log::info!("Management interface listening on {}", socket_path);
 
+    #[cfg(target_os = "linux")]
+    match mullvad_daemon::socket_bypass::spawn_server() {
+        Ok(path) => log::info!("Socket bypass server listening on {}", path.display()),
+        Err(error) => log::error!(
+            "{}",
+            error.display_chain_with_msg("Unable to start socket bypass server")
+        ),
+    }
+
     Ok(event_broadcaster)
 }
 
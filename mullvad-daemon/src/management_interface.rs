@@ -1,19 +1,34 @@
-use crate::{account_history, device, settings, DaemonCommand, DaemonCommandSender, EventListener};
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use crate::hooks;
+use crate::{
+    account_history, device, logging, settings,
+    settings_plan::{ApplyOperation, ApplyPlan},
+    DaemonCommand, DaemonCommandSender, EventListener,
+};
 use futures::{
     channel::{mpsc, oneshot},
     StreamExt,
 };
+use ipnetwork::IpNetwork;
 use mullvad_api::{rest::Error as RestError, StatusCode};
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use mullvad_management_interface::PeerCredentials;
+#[cfg(feature = "qa-tools")]
+use mullvad_management_interface::QaService;
 use mullvad_management_interface::{
     types::{self, daemon_event, management_service_server::ManagementService},
     Code, Request, Response, Status,
 };
 use mullvad_paths;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use mullvad_types::hooks::Hook;
 #[cfg(not(target_os = "android"))]
 use mullvad_types::settings::DnsOptions;
 use mullvad_types::{
     account::AccountToken,
-    relay_constraints::{BridgeSettings, BridgeState, ObfuscationSettings, RelaySettingsUpdate},
+    relay_constraints::{
+        BridgeSettings, BridgeState, ObfuscationSettings, RelayQuery, RelaySettingsUpdate,
+    },
     relay_list::RelayList,
     settings::Settings,
     states::{TargetState, TunnelState},
@@ -28,8 +43,17 @@ use std::{
     sync::Arc,
     time::Duration,
 };
-use talpid_types::ErrorExt;
-use tokio_stream::wrappers::UnboundedReceiverStream;
+use talpid_core::runtime_config::{self, FeatureFlag, RuntimeConfig};
+use talpid_types::{
+    net::{
+        route_takeover::RouteTakeoverPolicy,
+        wireguard::{BandwidthLimit, TrafficShapingOptions},
+        BlockedTunnelProtocols, VpnCoexistence,
+    },
+    tunnel::{ErrorStatePolicy, ReconnectLimits, VersionedTunnelStateTransition},
+    ErrorExt,
+};
+use tokio_stream::wrappers::{ReceiverStream, UnboundedReceiverStream};
 
 #[derive(err_derive::Error, Debug)]
 #[error(no_from)]
@@ -42,19 +66,35 @@ pub enum Error {
 struct ManagementServiceImpl {
     daemon_tx: DaemonCommandSender,
     subscriptions: Arc<RwLock<Vec<EventsListenerSender>>>,
+    transition_subscriptions: Arc<RwLock<Vec<TransitionsListenerSender>>>,
 }
 
 pub type ServiceResult<T> = std::result::Result<Response<T>, Status>;
 type EventsListenerReceiver = UnboundedReceiverStream<Result<types::DaemonEvent, Status>>;
 type EventsListenerSender = tokio::sync::mpsc::UnboundedSender<Result<types::DaemonEvent, Status>>;
+type TransitionsListenerReceiver = UnboundedReceiverStream<Result<String, Status>>;
+type TransitionsListenerSender = tokio::sync::mpsc::UnboundedSender<Result<String, Status>>;
+type LogsSubscribeStream = futures::stream::Map<
+    ReceiverStream<logging::LogLine>,
+    fn(logging::LogLine) -> Result<types::LogLine, Status>,
+>;
 
 const INVALID_VOUCHER_MESSAGE: &str = "This voucher code is invalid";
 const USED_VOUCHER_MESSAGE: &str = "This voucher code has already been used";
+const EXPIRED_VOUCHER_MESSAGE: &str = "This voucher code has expired";
+
+/// Longest interface name that is accepted. Windows interface aliases are the longest.
+const MAX_INTERFACE_NAME_LEN: usize = 256;
+
+/// Longest profile name that is accepted.
+const MAX_PROFILE_NAME_LEN: usize = 64;
 
 #[mullvad_management_interface::async_trait]
 impl ManagementService for ManagementServiceImpl {
     type GetSplitTunnelProcessesStream = UnboundedReceiverStream<Result<i32, Status>>;
     type EventsListenStream = EventsListenerReceiver;
+    type TunnelStateTransitionsListenStream = TransitionsListenerReceiver;
+    type LogsSubscribeStream = LogsSubscribeStream;
 
     // Control and get the tunnel state
     //
@@ -85,6 +125,35 @@ impl ManagementService for ManagementServiceImpl {
         Ok(Response::new(reconnect_issued))
     }
 
+    async fn pause_protection(
+        &self,
+        request: Request<types::Duration>,
+    ) -> ServiceResult<types::Timestamp> {
+        let duration = Duration::try_from(request.into_inner())
+            .map_err(|_| Status::invalid_argument("unexpected negative pause duration"))?;
+        log::debug!("pause_protection({:?})", duration);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::PauseProtection(tx, duration))?;
+        let resume_at = self.wait_for_result(rx).await?.map_err(map_daemon_error)?;
+        Ok(Response::new(types::Timestamp {
+            seconds: resume_at.timestamp(),
+            nanos: 0,
+        }))
+    }
+
+    async fn get_protection_pause(&self, _: Request<()>) -> ServiceResult<types::Timestamp> {
+        log::debug!("get_protection_pause");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetProtectionPause(tx))?;
+        match self.wait_for_result(rx).await? {
+            Some(resume_at) => Ok(Response::new(types::Timestamp {
+                seconds: resume_at.timestamp(),
+                nanos: 0,
+            })),
+            None => Err(Status::not_found("protection is not paused")),
+        }
+    }
+
     async fn get_tunnel_state(&self, _: Request<()>) -> ServiceResult<types::TunnelState> {
         log::debug!("get_tunnel_state");
         let (tx, rx) = oneshot::channel();
@@ -105,6 +174,58 @@ impl ManagementService for ManagementServiceImpl {
         Ok(Response::new(UnboundedReceiverStream::new(rx)))
     }
 
+    async fn tunnel_state_transitions_listen(
+        &self,
+        _: Request<()>,
+    ) -> ServiceResult<Self::TunnelStateTransitionsListenStream> {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let mut subscriptions = self.transition_subscriptions.write();
+        subscriptions.push(tx);
+
+        Ok(Response::new(UnboundedReceiverStream::new(rx)))
+    }
+
+    async fn logs_subscribe(
+        &self,
+        request: Request<types::LogsSubscription>,
+    ) -> ServiceResult<Self::LogsSubscribeStream> {
+        let subscription = request.into_inner();
+        let min_level = log_level_from_proto(subscription.min_level)?;
+        log::debug!(
+            "logs_subscribe({}, {:?})",
+            min_level,
+            subscription.subsystems
+        );
+        let lines = logging::subscribe(min_level, subscription.subsystems);
+        Ok(Response::new(ReceiverStream::new(lines).map(
+            log_line_to_proto as fn(logging::LogLine) -> Result<types::LogLine, Status>,
+        )))
+    }
+
+    async fn get_runtime_config(&self, _: Request<()>) -> ServiceResult<types::RuntimeConfig> {
+        log::debug!("get_runtime_config");
+        Ok(Response::new(
+            runtime_config_to_proto(runtime_config::get()),
+        ))
+    }
+
+    async fn set_runtime_config(
+        &self,
+        request: Request<types::RuntimeConfig>,
+    ) -> ServiceResult<()> {
+        log::debug!("set_runtime_config");
+        if !mullvad_management_interface::is_privileged(&request) {
+            return Err(Status::permission_denied(
+                "only root or members of the management group may change the runtime configuration",
+            ));
+        }
+        let config = runtime_config_from_proto(request.into_inner())?;
+        runtime_config::set(config);
+        logging::apply_runtime_log_filter();
+        Ok(Response::new(()))
+    }
+
     async fn prepare_restart(&self, _: Request<()>) -> ServiceResult<()> {
         log::debug!("prepare_restart");
         self.send_command_to_daemon(DaemonCommand::PrepareRestart)?;
@@ -128,6 +249,39 @@ impl ManagementService for ManagementServiceImpl {
         }
     }
 
+    async fn get_startup_state(&self, _: Request<()>) -> ServiceResult<types::StartupState> {
+        log::debug!("get_startup_state");
+        // The daemon handles commands in order, so this is equivalent to issuing the calls
+        // separately, minus the round trips.
+        let account_history = self.get_account_history(Request::new(())).await?;
+        let tunnel_state = self.get_tunnel_state(Request::new(())).await?;
+        let device = self.get_device(Request::new(())).await?;
+        let settings = self.get_settings(Request::new(())).await?;
+        let relay_list = self.get_relay_locations(Request::new(())).await?;
+        let current_version = self.get_current_version(Request::new(())).await?;
+
+        Ok(Response::new(types::StartupState {
+            account_history: Some(account_history.into_inner()),
+            tunnel_state: Some(tunnel_state.into_inner()),
+            device: Some(device.into_inner()),
+            settings: Some(settings.into_inner()),
+            relay_list: Some(relay_list.into_inner()),
+            current_version: current_version.into_inner(),
+        }))
+    }
+
+    async fn get_capabilities(&self, _: Request<()>) -> ServiceResult<types::Capabilities> {
+        log::debug!("get_capabilities");
+        Ok(Response::new(types::Capabilities {
+            api_version: mullvad_management_interface::API_VERSION,
+            min_supported_api_version: mullvad_management_interface::MIN_SUPPORTED_API_VERSION,
+            features: supported_capabilities()
+                .iter()
+                .map(|feature| feature.to_string())
+                .collect(),
+        }))
+    }
+
     async fn get_current_version(&self, _: Request<()>) -> ServiceResult<String> {
         log::debug!("get_current_version");
         let (tx, rx) = oneshot::channel();
@@ -136,6 +290,86 @@ impl ManagementService for ManagementServiceImpl {
         Ok(Response::new(version))
     }
 
+    async fn health_check(&self, _: Request<()>) -> ServiceResult<types::HealthReport> {
+        log::debug!("health_check");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::HealthCheck(tx))?;
+        let report = self.wait_for_result(rx).await?;
+        Ok(Response::new(types::HealthReport::from(report)))
+    }
+
+    async fn get_preflight_report(&self, _: Request<()>) -> ServiceResult<types::HealthReport> {
+        log::debug!("get_preflight_report");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetPreflightReport(tx))?;
+        let report = self.wait_for_result(rx).await?;
+        Ok(Response::new(types::HealthReport::from(report)))
+    }
+
+    async fn get_protection_gap_report(
+        &self,
+        _: Request<()>,
+    ) -> ServiceResult<types::ProtectionGapReport> {
+        log::debug!("get_protection_gap_report");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetProtectionGapReport(tx))?;
+        match self.wait_for_result(rx).await? {
+            Some(report) => Ok(Response::new(types::ProtectionGapReport::from(report))),
+            None => Err(Status::not_found("the network was not left unprotected")),
+        }
+    }
+
+    async fn get_system_state(&self, _: Request<()>) -> ServiceResult<String> {
+        log::debug!("get_system_state");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetSystemState(tx))?;
+        let state = self.wait_for_result(rx).await?;
+        serde_json::to_string(&state)
+            .map(Response::new)
+            .map_err(|error| Status::internal(error.to_string()))
+    }
+
+    async fn get_physical_interfaces(
+        &self,
+        _: Request<()>,
+    ) -> ServiceResult<types::PhysicalInterfaceList> {
+        log::debug!("get_physical_interfaces");
+        #[cfg(not(target_os = "android"))]
+        {
+            let (tx, rx) = oneshot::channel();
+            self.send_command_to_daemon(DaemonCommand::GetPhysicalInterfaces(tx))?;
+            let interfaces = self
+                .wait_for_result(rx)
+                .await?
+                .map_err(|error| Status::internal(error.display_chain()))?;
+            Ok(Response::new(types::PhysicalInterfaceList {
+                interfaces: interfaces
+                    .into_iter()
+                    .map(types::PhysicalInterface::from)
+                    .collect(),
+            }))
+        }
+        #[cfg(target_os = "android")]
+        {
+            Err(Status::unimplemented(
+                "physical interfaces cannot be listed on Android",
+            ))
+        }
+    }
+
+    async fn query_traffic(
+        &self,
+        request: Request<types::TrafficQuery>,
+    ) -> ServiceResult<types::TrafficVerdict> {
+        log::debug!("query_traffic");
+        let query = talpid_types::traffic_query::TrafficQuery::try_from(request.into_inner())
+            .map_err(map_protobuf_type_err)?;
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::QueryTraffic(tx, query))?;
+        let verdict = self.wait_for_result(rx).await?;
+        Ok(Response::new(types::TrafficVerdict::from(verdict)))
+    }
+
     async fn get_version_info(&self, _: Request<()>) -> ServiceResult<types::AppVersionInfo> {
         log::debug!("get_version_info");
 
@@ -191,6 +425,24 @@ impl ManagementService for ManagementServiceImpl {
             .map(|relays| Response::new(types::RelayList::from(relays)))
     }
 
+    async fn query_relays(
+        &self,
+        request: Request<types::RelayQuery>,
+    ) -> ServiceResult<types::RelayQueryResult> {
+        let query = RelayQuery::try_from(request.into_inner()).map_err(map_protobuf_type_err)?;
+        log::debug!("query_relays({:?})", query);
+
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::QueryRelays(tx, query))?;
+        let matches = self.wait_for_result(rx).await?;
+        Ok(Response::new(types::RelayQueryResult {
+            relays: matches
+                .into_iter()
+                .map(types::RelayQueryMatch::from)
+                .collect(),
+        }))
+    }
+
     async fn get_current_location(&self, _: Request<()>) -> ServiceResult<types::GeoIpLocation> {
         log::debug!("get_current_location");
         let (tx, rx) = oneshot::channel();
@@ -205,7 +457,7 @@ impl ManagementService for ManagementServiceImpl {
     async fn set_bridge_settings(
         &self,
         request: Request<types::BridgeSettings>,
-    ) -> ServiceResult<()> {
+    ) -> ServiceResult<types::SettingsApplyPlan> {
         let settings =
             BridgeSettings::try_from(request.into_inner()).map_err(map_protobuf_type_err)?;
 
@@ -215,14 +467,14 @@ impl ManagementService for ManagementServiceImpl {
         self.send_command_to_daemon(DaemonCommand::SetBridgeSettings(tx, settings))?;
         let settings_result = self.wait_for_result(rx).await?;
         settings_result
-            .map(Response::new)
+            .map(|apply_plan| Response::new(apply_plan_to_proto(&apply_plan)))
             .map_err(map_settings_error)
     }
 
     async fn set_obfuscation_settings(
         &self,
         request: Request<types::ObfuscationSettings>,
-    ) -> ServiceResult<()> {
+    ) -> ServiceResult<types::SettingsApplyPlan> {
         let settings =
             ObfuscationSettings::try_from(request.into_inner()).map_err(map_protobuf_type_err)?;
         log::debug!("set_obfuscation_settings({:?})", settings);
@@ -230,11 +482,14 @@ impl ManagementService for ManagementServiceImpl {
         self.send_command_to_daemon(DaemonCommand::SetObfuscationSettings(tx, settings))?;
         let settings_result = self.wait_for_result(rx).await?;
         settings_result
-            .map(Response::new)
+            .map(|apply_plan| Response::new(apply_plan_to_proto(&apply_plan)))
             .map_err(map_settings_error)
     }
 
-    async fn set_bridge_state(&self, request: Request<types::BridgeState>) -> ServiceResult<()> {
+    async fn set_bridge_state(
+        &self,
+        request: Request<types::BridgeState>,
+    ) -> ServiceResult<types::SettingsApplyPlan> {
         let bridge_state =
             BridgeState::try_from(request.into_inner()).map_err(map_protobuf_type_err)?;
 
@@ -243,7 +498,7 @@ impl ManagementService for ManagementServiceImpl {
         self.send_command_to_daemon(DaemonCommand::SetBridgeState(tx, bridge_state))?;
         let settings_result = self.wait_for_result(rx).await?;
         settings_result
-            .map(Response::new)
+            .map(|apply_plan| Response::new(apply_plan_to_proto(&apply_plan)))
             .map_err(map_settings_error)
     }
 
@@ -259,14 +514,71 @@ impl ManagementService for ManagementServiceImpl {
             .map(|settings| Response::new(types::Settings::from(&settings)))
     }
 
-    async fn set_allow_lan(&self, request: Request<bool>) -> ServiceResult<()> {
+    async fn list_profiles(&self, _: Request<()>) -> ServiceResult<types::ProfileList> {
+        log::debug!("list_profiles");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetProfiles(tx))?;
+        self.wait_for_result(rx).await.map(|profiles| {
+            Response::new(types::ProfileList {
+                profiles: profiles.iter().map(types::Profile::from).collect(),
+            })
+        })
+    }
+
+    async fn save_profile(&self, request: Request<String>) -> ServiceResult<()> {
+        let name = request.into_inner();
+        log::debug!("save_profile({:?})", name);
+        if name.is_empty()
+            || name.len() > MAX_PROFILE_NAME_LEN
+            || name.trim() != name
+            || name.chars().any(char::is_control)
+        {
+            return Err(Status::invalid_argument("invalid profile name"));
+        }
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SaveProfile(tx, name))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
+    async fn remove_profile(&self, request: Request<String>) -> ServiceResult<()> {
+        let name = request.into_inner();
+        log::debug!("remove_profile({:?})", name);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::RemoveProfile(tx, name))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
+    async fn apply_profile(
+        &self,
+        request: Request<String>,
+    ) -> ServiceResult<types::SettingsApplyPlan> {
+        let name = request.into_inner();
+        log::debug!("apply_profile({:?})", name);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::ApplyProfile(tx, name))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(|apply_plan| Response::new(apply_plan_to_proto(&apply_plan)))
+            .map_err(map_daemon_error)
+    }
+
+    async fn set_allow_lan(
+        &self,
+        request: Request<bool>,
+    ) -> ServiceResult<types::SettingsApplyPlan> {
         let allow_lan = request.into_inner();
         log::debug!("set_allow_lan({})", allow_lan);
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::SetAllowLan(tx, allow_lan))?;
         self.wait_for_result(rx)
             .await?
-            .map(Response::new)
+            .map(|apply_plan| Response::new(apply_plan_to_proto(&apply_plan)))
             .map_err(map_settings_error)
     }
 
@@ -281,7 +593,10 @@ impl ManagementService for ManagementServiceImpl {
             .map_err(map_settings_error)
     }
 
-    async fn set_block_when_disconnected(&self, request: Request<bool>) -> ServiceResult<()> {
+    async fn set_block_when_disconnected(
+        &self,
+        request: Request<bool>,
+    ) -> ServiceResult<types::SettingsApplyPlan> {
         let block_when_disconnected = request.into_inner();
         log::debug!("set_block_when_disconnected({})", block_when_disconnected);
         let (tx, rx) = oneshot::channel();
@@ -289,12 +604,197 @@ impl ManagementService for ManagementServiceImpl {
             tx,
             block_when_disconnected,
         ))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(|apply_plan| Response::new(apply_plan_to_proto(&apply_plan)))
+            .map_err(map_settings_error)
+    }
+
+    async fn set_flush_dns_cache(
+        &self,
+        request: Request<bool>,
+    ) -> ServiceResult<types::SettingsApplyPlan> {
+        let flush_dns_cache = request.into_inner();
+        log::debug!("set_flush_dns_cache({})", flush_dns_cache);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetFlushDnsCache(tx, flush_dns_cache))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(|apply_plan| Response::new(apply_plan_to_proto(&apply_plan)))
+            .map_err(map_settings_error)
+    }
+
+    async fn set_recovery_allowlist(
+        &self,
+        request: Request<types::RecoveryAllowlist>,
+    ) -> ServiceResult<types::SettingsApplyPlan> {
+        let recovery_allowlist =
+            Vec::<IpNetwork>::try_from(request.into_inner()).map_err(map_protobuf_type_err)?;
+        log::debug!("set_recovery_allowlist({:?})", recovery_allowlist);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetRecoveryAllowlist(tx, recovery_allowlist))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(|apply_plan| Response::new(apply_plan_to_proto(&apply_plan)))
+            .map_err(map_settings_error)
+    }
+
+    async fn set_vpn_coexistence(
+        &self,
+        request: Request<types::VpnCoexistence>,
+    ) -> ServiceResult<types::SettingsApplyPlan> {
+        let vpn_coexistence =
+            VpnCoexistence::try_from(request.into_inner()).map_err(map_protobuf_type_err)?;
+        log::debug!("set_vpn_coexistence({})", vpn_coexistence);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetVpnCoexistence(tx, vpn_coexistence))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(|apply_plan| Response::new(apply_plan_to_proto(&apply_plan)))
+            .map_err(map_settings_error)
+    }
+
+    async fn set_blocked_tunnel_protocols(
+        &self,
+        request: Request<types::BlockedTunnelProtocols>,
+    ) -> ServiceResult<types::SettingsApplyPlan> {
+        let blocked_tunnel_protocols = BlockedTunnelProtocols::from(request.into_inner());
+        log::debug!("set_blocked_tunnel_protocols({})", blocked_tunnel_protocols);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetBlockedTunnelProtocols(
+            tx,
+            blocked_tunnel_protocols,
+        ))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(|apply_plan| Response::new(apply_plan_to_proto(&apply_plan)))
+            .map_err(map_settings_error)
+    }
+
+    async fn set_forced_interface(&self, request: Request<String>) -> ServiceResult<()> {
+        let interface = request.into_inner();
+        log::debug!("set_forced_interface({:?})", interface);
+        let forced_interface = if interface.is_empty() {
+            None
+        } else {
+            if interface.len() > MAX_INTERFACE_NAME_LEN
+                || interface.trim() != interface
+                || interface.chars().any(char::is_control)
+            {
+                return Err(Status::invalid_argument("invalid interface name"));
+            }
+            Some(interface)
+        };
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetForcedInterface(tx, forced_interface))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_settings_error)
+    }
+
+    async fn set_reconnect_limits(
+        &self,
+        request: Request<types::ReconnectLimits>,
+    ) -> ServiceResult<types::SettingsApplyPlan> {
+        let reconnect_limits = ReconnectLimits::from(request.into_inner());
+        log::debug!("set_reconnect_limits({})", reconnect_limits);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetReconnectLimits(tx, reconnect_limits))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(|apply_plan| Response::new(apply_plan_to_proto(&apply_plan)))
+            .map_err(map_settings_error)
+    }
+
+    async fn set_route_takeover_policy(
+        &self,
+        request: Request<types::RouteTakeoverPolicy>,
+    ) -> ServiceResult<types::SettingsApplyPlan> {
+        let policy =
+            RouteTakeoverPolicy::try_from(request.into_inner()).map_err(map_protobuf_type_err)?;
+        log::debug!("set_route_takeover_policy({})", policy);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetRouteTakeoverPolicy(tx, policy))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(|apply_plan| Response::new(apply_plan_to_proto(&apply_plan)))
+            .map_err(map_settings_error)
+    }
+
+    async fn set_error_state_policy(
+        &self,
+        request: Request<types::ErrorStatePolicy>,
+    ) -> ServiceResult<types::SettingsApplyPlan> {
+        let policy =
+            ErrorStatePolicy::try_from(request.into_inner()).map_err(map_protobuf_type_err)?;
+        log::debug!("set_error_state_policy({})", policy);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetErrorStatePolicy(tx, policy))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(|apply_plan| Response::new(apply_plan_to_proto(&apply_plan)))
+            .map_err(map_settings_error)
+    }
+
+    async fn set_connection_stats(&self, request: Request<bool>) -> ServiceResult<()> {
+        let connection_stats = request.into_inner();
+        log::debug!("set_connection_stats({})", connection_stats);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetConnectionStats(tx, connection_stats))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_settings_error)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    async fn set_hooks(&self, request: Request<types::Hooks>) -> ServiceResult<()> {
+        let caller = PeerCredentials::from_request(&request)
+            .ok_or_else(|| Status::permission_denied("the identity of the client is unknown"))?;
+        let mut hooks =
+            Vec::<Hook>::try_from(request.into_inner()).map_err(map_protobuf_type_err)?;
+        log::debug!("set_hooks({:?})", hooks);
+
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetSettings(tx))?;
+        let current_hooks = self.wait_for_result(rx).await?.hooks;
+        for hook in &mut hooks {
+            let current_hook = current_hooks
+                .iter()
+                .find(|current| current.event == hook.event && current.program == hook.program);
+            hook.added_by = match current_hook {
+                // Hooks that are kept retain the user that added them
+                Some(current_hook) => current_hook.added_by,
+                None => {
+                    if caller.uid != 0 {
+                        hooks::check_caller_owns(&hook.program, caller.uid).map_err(|error| {
+                            Status::permission_denied(error.display_chain_with_msg(&format!(
+                                "Cannot add hook {}",
+                                hook.program.display()
+                            )))
+                        })?;
+                    }
+                    Some(caller.uid)
+                }
+            };
+        }
+
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetHooks(tx, hooks))?;
         self.wait_for_result(rx)
             .await?
             .map(Response::new)
             .map_err(map_settings_error)
     }
 
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    async fn set_hooks(&self, _: Request<types::Hooks>) -> ServiceResult<()> {
+        Err(Status::unimplemented(
+            "Hooks are only supported on Linux and macOS",
+        ))
+    }
+
     async fn set_auto_connect(&self, request: Request<bool>) -> ServiceResult<()> {
         let auto_connect = request.into_inner();
         log::debug!("set_auto_connect({})", auto_connect);
@@ -306,7 +806,10 @@ impl ManagementService for ManagementServiceImpl {
             .map_err(map_settings_error)
     }
 
-    async fn set_openvpn_mssfix(&self, request: Request<u32>) -> ServiceResult<()> {
+    async fn set_openvpn_mssfix(
+        &self,
+        request: Request<u32>,
+    ) -> ServiceResult<types::SettingsApplyPlan> {
         let mssfix = request.into_inner();
         let mssfix = if mssfix != 0 {
             Some(mssfix as u16)
@@ -318,11 +821,14 @@ impl ManagementService for ManagementServiceImpl {
         self.send_command_to_daemon(DaemonCommand::SetOpenVpnMssfix(tx, mssfix))?;
         self.wait_for_result(rx)
             .await?
-            .map(Response::new)
+            .map(|apply_plan| Response::new(apply_plan_to_proto(&apply_plan)))
             .map_err(map_settings_error)
     }
 
-    async fn set_wireguard_mtu(&self, request: Request<u32>) -> ServiceResult<()> {
+    async fn set_wireguard_mtu(
+        &self,
+        request: Request<u32>,
+    ) -> ServiceResult<types::SettingsApplyPlan> {
         let mtu = request.into_inner();
         let mtu = if mtu != 0 { Some(mtu as u16) } else { None };
         log::debug!("set_wireguard_mtu({:?})", mtu);
@@ -330,34 +836,102 @@ impl ManagementService for ManagementServiceImpl {
         self.send_command_to_daemon(DaemonCommand::SetWireguardMtu(tx, mtu))?;
         self.wait_for_result(rx)
             .await?
-            .map(Response::new)
+            .map(|apply_plan| Response::new(apply_plan_to_proto(&apply_plan)))
             .map_err(map_settings_error)
     }
 
-    async fn set_enable_ipv6(&self, request: Request<bool>) -> ServiceResult<()> {
+    async fn set_enable_ipv6(
+        &self,
+        request: Request<bool>,
+    ) -> ServiceResult<types::SettingsApplyPlan> {
         let enable_ipv6 = request.into_inner();
         log::debug!("set_enable_ipv6({})", enable_ipv6);
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::SetEnableIpv6(tx, enable_ipv6))?;
         self.wait_for_result(rx)
             .await?
-            .map(Response::new)
+            .map(|apply_plan| Response::new(apply_plan_to_proto(&apply_plan)))
             .map_err(map_settings_error)
     }
 
-    async fn set_quantum_resistant_tunnel(&self, request: Request<bool>) -> ServiceResult<()> {
+    async fn set_enable_ipv4(
+        &self,
+        request: Request<bool>,
+    ) -> ServiceResult<types::SettingsApplyPlan> {
+        let enable_ipv4 = request.into_inner();
+        log::debug!("set_enable_ipv4({})", enable_ipv4);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetEnableIpv4(tx, enable_ipv4))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(|apply_plan| Response::new(apply_plan_to_proto(&apply_plan)))
+            .map_err(map_settings_error)
+    }
+
+    async fn set_quantum_resistant_tunnel(
+        &self,
+        request: Request<bool>,
+    ) -> ServiceResult<types::SettingsApplyPlan> {
         let enable = request.into_inner();
         log::debug!("set_quantum_resistant_tunnel({})", enable);
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::SetQuantumResistantTunnel(tx, enable))?;
         self.wait_for_result(rx)
             .await?
-            .map(Response::new)
+            .map(|apply_plan| Response::new(apply_plan_to_proto(&apply_plan)))
+            .map_err(map_settings_error)
+    }
+
+    async fn set_wireguard_traffic_shaping(
+        &self,
+        request: Request<types::TrafficShapingOptions>,
+    ) -> ServiceResult<types::SettingsApplyPlan> {
+        let options =
+            TrafficShapingOptions::try_from(request.into_inner()).map_err(map_protobuf_type_err)?;
+        log::debug!("set_wireguard_traffic_shaping({:?})", options);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetWireguardTrafficShaping(tx, options))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(|apply_plan| Response::new(apply_plan_to_proto(&apply_plan)))
+            .map_err(map_settings_error)
+    }
+
+    async fn set_wireguard_bandwidth_limit(
+        &self,
+        request: Request<types::BandwidthLimit>,
+    ) -> ServiceResult<types::SettingsApplyPlan> {
+        let limit =
+            BandwidthLimit::try_from(request.into_inner()).map_err(map_protobuf_type_err)?;
+        log::debug!("set_wireguard_bandwidth_limit({})", limit);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetWireguardBandwidthLimit(tx, limit))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(|apply_plan| Response::new(apply_plan_to_proto(&apply_plan)))
+            .map_err(map_settings_error)
+    }
+
+    async fn set_wireguard_allowed_ips(
+        &self,
+        request: Request<types::WireguardAllowedIps>,
+    ) -> ServiceResult<types::SettingsApplyPlan> {
+        let allowed_ips = Option::<Vec<IpNetwork>>::try_from(request.into_inner())
+            .map_err(map_protobuf_type_err)?;
+        log::debug!("set_wireguard_allowed_ips({:?})", allowed_ips);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetWireguardAllowedIps(tx, allowed_ips))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(|apply_plan| Response::new(apply_plan_to_proto(&apply_plan)))
             .map_err(map_settings_error)
     }
 
     #[cfg(not(target_os = "android"))]
-    async fn set_dns_options(&self, request: Request<types::DnsOptions>) -> ServiceResult<()> {
+    async fn set_dns_options(
+        &self,
+        request: Request<types::DnsOptions>,
+    ) -> ServiceResult<types::SettingsApplyPlan> {
         let options = DnsOptions::try_from(request.into_inner()).map_err(map_protobuf_type_err)?;
         log::debug!("set_dns_options({:?})", options);
 
@@ -365,7 +939,7 @@ impl ManagementService for ManagementServiceImpl {
         self.send_command_to_daemon(DaemonCommand::SetDnsOptions(tx, options))?;
         self.wait_for_result(rx)
             .await?
-            .map(Response::new)
+            .map(|apply_plan| Response::new(apply_plan_to_proto(&apply_plan)))
             .map_err(map_settings_error)
     }
 
@@ -490,6 +1064,20 @@ impl ManagementService for ManagementServiceImpl {
             .map_err(map_daemon_error)
     }
 
+    async fn get_payment_status(&self, _: Request<()>) -> ServiceResult<types::PaymentStatus> {
+        log::debug!("get_payment_status");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetPaymentStatus(tx))?;
+        let account_data = self.wait_for_result(rx).await?.map_err(map_daemon_error)?;
+        Ok(Response::new(types::PaymentStatus {
+            expiry: Some(types::Timestamp {
+                seconds: account_data.expiry.timestamp(),
+                nanos: 0,
+            }),
+            expired: account_data.is_expired(),
+        }))
+    }
+
     // Device management
     async fn get_device(&self, _: Request<()>) -> ServiceResult<types::DeviceState> {
         log::debug!("get_device");
@@ -514,11 +1102,26 @@ impl ManagementService for ManagementServiceImpl {
         request: Request<AccountToken>,
     ) -> ServiceResult<types::DeviceList> {
         log::debug!("list_devices");
-        let (tx, rx) = oneshot::channel();
         let token = request.into_inner();
+
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetDevice(tx))?;
+        let current_device = self
+            .wait_for_result(rx)
+            .await?
+            .map_err(map_daemon_error)?
+            .into_device()
+            .filter(|device| device.account_token == token);
+
+        let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::ListDevices(tx, token))?;
-        let device = self.wait_for_result(rx).await?.map_err(map_daemon_error)?;
-        Ok(Response::new(types::DeviceList::from(device)))
+        let devices = self.wait_for_result(rx).await?.map_err(map_daemon_error)?;
+
+        let mut device_list = types::DeviceList::from(devices);
+        if let Some(current_device) = current_device {
+            device_list.current_device_id = current_device.device.id;
+        }
+        Ok(Response::new(device_list))
     }
 
     async fn remove_device(&self, request: Request<types::DeviceRemoval>) -> ServiceResult<()> {
@@ -803,6 +1406,72 @@ impl ManagementService for ManagementServiceImpl {
     async fn check_volumes(&self, _: Request<()>) -> ServiceResult<()> {
         Ok(Response::new(()))
     }
+
+    #[cfg(windows)]
+    async fn reinstall_split_tunnel_driver(&self, _: Request<()>) -> ServiceResult<()> {
+        log::debug!("reinstall_split_tunnel_driver");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::ReinstallSplitTunnelDriver(tx))?;
+        self.wait_for_result(rx)
+            .await?
+            .map_err(map_daemon_error)
+            .map(Response::new)
+    }
+
+    #[cfg(not(windows))]
+    async fn reinstall_split_tunnel_driver(&self, _: Request<()>) -> ServiceResult<()> {
+        Err(Status::unimplemented(
+            "The split tunnel driver is only used on Windows",
+        ))
+    }
+
+    #[cfg(all(feature = "packet-capture", target_os = "linux"))]
+    async fn start_packet_capture(
+        &self,
+        request: Request<types::PacketCaptureLimits>,
+    ) -> ServiceResult<String> {
+        let request = request.into_inner();
+        let max_size = Some(request.max_size).filter(|size| *size > 0);
+        let max_duration = request
+            .max_duration
+            .map(Duration::try_from)
+            .transpose()
+            .map_err(|_| Status::invalid_argument("unexpected negative capture duration"))?;
+        let limits = talpid_core::packet_capture::CaptureLimits::new(max_size, max_duration);
+
+        log::debug!("start_packet_capture({:?})", limits);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::StartPacketCapture(tx, limits))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(|path| Response::new(path.to_string_lossy().into_owned()))
+            .map_err(map_packet_capture_error)
+    }
+
+    #[cfg(not(all(feature = "packet-capture", target_os = "linux")))]
+    async fn start_packet_capture(
+        &self,
+        _: Request<types::PacketCaptureLimits>,
+    ) -> ServiceResult<String> {
+        Err(Status::unimplemented(
+            "the daemon was built without support for packet capture",
+        ))
+    }
+
+    #[cfg(all(feature = "packet-capture", target_os = "linux"))]
+    async fn stop_packet_capture(&self, _: Request<()>) -> ServiceResult<bool> {
+        log::debug!("stop_packet_capture");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::StopPacketCapture(tx))?;
+        Ok(Response::new(self.wait_for_result(rx).await?))
+    }
+
+    #[cfg(not(all(feature = "packet-capture", target_os = "linux")))]
+    async fn stop_packet_capture(&self, _: Request<()>) -> ServiceResult<bool> {
+        Err(Status::unimplemented(
+            "the daemon was built without support for packet capture",
+        ))
+    }
 }
 
 impl ManagementServiceImpl {
@@ -818,6 +1487,31 @@ impl ManagementServiceImpl {
     }
 }
 
+#[cfg(feature = "qa-tools")]
+struct QaServiceImpl {
+    daemon_tx: DaemonCommandSender,
+}
+
+#[cfg(feature = "qa-tools")]
+#[mullvad_management_interface::async_trait]
+impl QaService for QaServiceImpl {
+    async fn inject_simulated_event(
+        &self,
+        request: Request<types::SimulatedEvent>,
+    ) -> ServiceResult<()> {
+        let event = simulated_event_from_proto(request.into_inner())?;
+        log::debug!("inject_simulated_event({:?})", event);
+        let (tx, rx) = oneshot::channel();
+        self.daemon_tx
+            .send(DaemonCommand::InjectSimulatedEvent(tx, event))
+            .map_err(|_| Status::internal("the daemon channel receiver has been dropped"))?;
+        rx.await
+            .map_err(|_| Status::internal("sender was dropped"))?
+            .map(Response::new)
+            .map_err(map_simulation_error)
+    }
+}
+
 pub struct ManagementInterfaceServer(());
 
 impl ManagementInterfaceServer {
@@ -825,19 +1519,30 @@ impl ManagementInterfaceServer {
         tunnel_tx: DaemonCommandSender,
     ) -> Result<(String, ManagementInterfaceEventBroadcaster), Error> {
         let subscriptions = Arc::<RwLock<Vec<EventsListenerSender>>>::default();
+        let transition_subscriptions = Arc::<RwLock<Vec<TransitionsListenerSender>>>::default();
 
         let socket_path = mullvad_paths::get_rpc_socket_path()
             .to_string_lossy()
             .to_string();
 
         let (server_abort_tx, server_abort_rx) = mpsc::channel(0);
+        #[cfg(feature = "qa-tools")]
+        let qa_server = QaServiceImpl {
+            daemon_tx: tunnel_tx.clone(),
+        };
         let server = ManagementServiceImpl {
             daemon_tx: tunnel_tx,
             subscriptions: subscriptions.clone(),
+            transition_subscriptions: transition_subscriptions.clone(),
         };
-        let join_handle = mullvad_management_interface::spawn_rpc_server(server, async move {
-            server_abort_rx.into_future().await;
-        })
+        let join_handle = mullvad_management_interface::spawn_rpc_server(
+            server,
+            #[cfg(feature = "qa-tools")]
+            qa_server,
+            async move {
+                server_abort_rx.into_future().await;
+            },
+        )
         .await
         .map_err(Error::SetupError)?;
 
@@ -852,6 +1557,7 @@ impl ManagementInterfaceServer {
             socket_path,
             ManagementInterfaceEventBroadcaster {
                 subscriptions,
+                transition_subscriptions,
                 _close_handle: server_abort_tx,
             },
         ))
@@ -862,6 +1568,7 @@ impl ManagementInterfaceServer {
 #[derive(Clone)]
 pub struct ManagementInterfaceEventBroadcaster {
     subscriptions: Arc<RwLock<Vec<EventsListenerSender>>>,
+    transition_subscriptions: Arc<RwLock<Vec<TransitionsListenerSender>>>,
     _close_handle: mpsc::Sender<()>,
 }
 
@@ -875,14 +1582,46 @@ impl EventListener for ManagementInterfaceEventBroadcaster {
         })
     }
 
-    /// Sends settings to all `settings` subscribers of the management interface.
-    fn notify_settings(&self, settings: Settings) {
+    /// Sends the versioned transition as JSON to all `tunnel_state_transitions_listen`
+    /// subscribers of the management interface.
+    fn notify_tunnel_state_transition(&self, transition: &VersionedTunnelStateTransition) {
+        let mut subscriptions = self.transition_subscriptions.write();
+        if subscriptions.is_empty() {
+            return;
+        }
+        match serde_json::to_string(transition) {
+            Ok(json) => subscriptions.retain(|tx| tx.send(Ok(json.clone())).is_ok()),
+            Err(error) => log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to serialize tunnel state transition")
+            ),
+        }
+    }
+
+    /// Sends settings to all `settings` subscribers of the management interface, followed by the
+    /// fields that changed and how they were applied, if any.
+    fn notify_settings(
+        &self,
+        settings: Settings,
+        changed_fields: Vec<String>,
+        apply_plan: &ApplyPlan,
+    ) {
         log::debug!("Broadcasting new settings");
         self.notify(types::DaemonEvent {
             event: Some(daemon_event::Event::Settings(types::Settings::from(
                 &settings,
             ))),
-        })
+        });
+        if !changed_fields.is_empty() {
+            self.notify(types::DaemonEvent {
+                event: Some(daemon_event::Event::SettingsChanged(
+                    types::SettingsChanged {
+                        changed_fields,
+                        apply_plan: Some(apply_plan_to_proto(apply_plan)),
+                    },
+                )),
+            })
+        }
     }
 
     /// Sends relays to all subscribers of the management interface.
@@ -921,6 +1660,72 @@ impl EventListener for ManagementInterfaceEventBroadcaster {
             )),
         })
     }
+
+    fn notify_account_expiry(&self, expiry: chrono::DateTime<chrono::Utc>) {
+        log::debug!("Broadcasting account expiry");
+        self.notify(types::DaemonEvent {
+            event: Some(daemon_event::Event::AccountExpiry(types::AccountData {
+                expiry: Some(types::Timestamp {
+                    seconds: expiry.timestamp(),
+                    nanos: 0,
+                }),
+            })),
+        })
+    }
+
+    #[cfg(windows)]
+    fn notify_firewall_conflicts(&self, conflicts: Vec<crate::firewall_conflicts::Conflict>) {
+        use crate::firewall_conflicts::ConflictKind;
+        use types::firewall_conflict::Kind;
+
+        log::debug!("Broadcasting firewall conflicts");
+        let conflicts = conflicts
+            .into_iter()
+            .map(|conflict| {
+                let (kind, weight) = match conflict.kind {
+                    ConflictKind::Sublayer { weight } => (Kind::Sublayer, u32::from(weight)),
+                    ConflictKind::HardPermitFilter => (Kind::HardPermitFilter, 0),
+                };
+                types::FirewallConflict {
+                    kind: i32::from(kind),
+                    name: conflict.name,
+                    provider: conflict.provider.unwrap_or_default(),
+                    weight,
+                }
+            })
+            .collect();
+        self.notify(types::DaemonEvent {
+            event: Some(daemon_event::Event::FirewallConflicts(
+                types::FirewallConflicts { conflicts },
+            )),
+        })
+    }
+
+    #[cfg(not(target_os = "android"))]
+    fn notify_competing_vpn(&self, competing_vpn: talpid_types::net::route_takeover::CompetingVpn) {
+        log::debug!("Broadcasting competing VPN");
+        self.notify(types::DaemonEvent {
+            event: Some(daemon_event::Event::CompetingVpn(
+                types::CompetingVpn::from(competing_vpn),
+            )),
+        })
+    }
+
+    fn notify_performance_warning(&self, warning: talpid_types::performance::PerformanceWarning) {
+        log::debug!("Broadcasting performance warning");
+        self.notify(types::DaemonEvent {
+            event: Some(daemon_event::Event::PerformanceWarning(
+                types::PerformanceWarning::from(warning),
+            )),
+        })
+    }
+
+    fn notify_clock_skew(&self, skew: Option<mullvad_types::clock_skew::ClockSkew>) {
+        log::debug!("Broadcasting clock skew");
+        self.notify(types::DaemonEvent {
+            event: Some(daemon_event::Event::ClockSkew(types::ClockSkew::from(skew))),
+        })
+    }
 }
 
 impl ManagementInterfaceEventBroadcaster {
@@ -931,6 +1736,105 @@ impl ManagementInterfaceEventBroadcaster {
     }
 }
 
+fn apply_plan_to_proto(apply_plan: &ApplyPlan) -> types::SettingsApplyPlan {
+    use types::settings_apply_plan::Operation;
+    types::SettingsApplyPlan {
+        operations: apply_plan
+            .operations()
+            .map(|operation| {
+                i32::from(match operation {
+                    ApplyOperation::FirewallUpdate => Operation::FirewallUpdate,
+                    ApplyOperation::DnsUpdate => Operation::DnsUpdate,
+                    ApplyOperation::PeerUpdate => Operation::PeerUpdate,
+                    ApplyOperation::Reconnect => Operation::Reconnect,
+                })
+            })
+            .collect(),
+    }
+}
+
+fn log_line_to_proto(line: logging::LogLine) -> Result<types::LogLine, Status> {
+    use types::log_line::Level;
+    let level = match line.level {
+        log::Level::Error => Level::Error,
+        log::Level::Warn => Level::Warn,
+        log::Level::Info => Level::Info,
+        log::Level::Debug => Level::Debug,
+        log::Level::Trace => Level::Trace,
+    };
+    Ok(types::LogLine {
+        time: Some(types::Timestamp {
+            seconds: line.time.timestamp(),
+            nanos: line.time.timestamp_subsec_nanos() as i32,
+        }),
+        level: i32::from(level),
+        subsystem: line.subsystem,
+        message: line.message,
+        connection_attempt: line
+            .connection_attempt
+            .map(|attempt| attempt.to_string())
+            .unwrap_or_default(),
+    })
+}
+
+fn log_level_from_proto(level: i32) -> Result<log::Level, Status> {
+    use types::log_line::Level;
+    match Level::from_i32(level) {
+        Some(Level::Error) => Ok(log::Level::Error),
+        Some(Level::Warn) => Ok(log::Level::Warn),
+        Some(Level::Info) => Ok(log::Level::Info),
+        Some(Level::Debug) => Ok(log::Level::Debug),
+        Some(Level::Trace) => Ok(log::Level::Trace),
+        None => Err(Status::invalid_argument("invalid log level")),
+    }
+}
+
+/// Returns the optional features that this build supports on this platform.
+fn supported_capabilities() -> Vec<&'static str> {
+    use mullvad_management_interface::capabilities;
+
+    #[allow(unused_mut)]
+    let mut features = vec![capabilities::OBFUSCATION_UDP2TCP];
+    #[cfg(any(target_os = "linux", windows))]
+    features.push(capabilities::SPLIT_TUNNEL);
+    #[cfg(not(target_os = "android"))]
+    features.extend([capabilities::OPENVPN, capabilities::BRIDGES]);
+    #[cfg(all(feature = "packet-capture", target_os = "linux"))]
+    features.push(capabilities::PACKET_CAPTURE);
+    #[cfg(feature = "qa-tools")]
+    features.push(capabilities::SIMULATION);
+    features
+}
+
+fn runtime_config_to_proto(config: RuntimeConfig) -> types::RuntimeConfig {
+    types::RuntimeConfig {
+        log_filter: config.log_filter.to_string(),
+        enabled_features: config
+            .enabled_features
+            .iter()
+            .map(|feature| feature.to_string())
+            .collect(),
+        available_features: FeatureFlag::ALL
+            .iter()
+            .map(|feature| feature.to_string())
+            .collect(),
+    }
+}
+
+fn runtime_config_from_proto(config: types::RuntimeConfig) -> Result<RuntimeConfig, Status> {
+    let invalid_argument =
+        |error: runtime_config::Error| Status::invalid_argument(error.to_string());
+    Ok(RuntimeConfig {
+        log_filter: config.log_filter.parse().map_err(invalid_argument)?,
+        enabled_features: config
+            .enabled_features
+            .iter()
+            .map(|feature| feature.parse())
+            .collect::<Result<_, _>>()
+            .map_err(invalid_argument)?,
+    })
+}
+
 /// Converts [`mullvad_daemon::Error`] into a tonic status.
 fn map_daemon_error(error: crate::Error) -> Status {
     use crate::Error as DaemonError;
@@ -946,9 +1850,13 @@ fn map_daemon_error(error: crate::Error) -> Status {
         DaemonError::RemoveDeviceError(error) => map_device_error(&error),
         DaemonError::UpdateDeviceError(error) => map_device_error(&error),
         DaemonError::VoucherSubmission(error) => map_device_error(&error),
+        DaemonError::PaymentStatus(error) => map_device_error(&error),
         #[cfg(windows)]
         DaemonError::SplitTunnelError(error) => map_split_tunnel_error(error),
         DaemonError::AccountHistory(error) => map_account_history_error(error),
+        DaemonError::ProfileNotFound(_) => Status::not_found(error.to_string()),
+        DaemonError::TooManyProfiles => Status::resource_exhausted(error.to_string()),
+        DaemonError::InvalidPauseDuration => Status::invalid_argument(error.to_string()),
         DaemonError::NoAccountToken | DaemonError::NoAccountTokenHistory => {
             Status::unauthenticated(error.to_string())
         }
@@ -969,10 +1877,83 @@ fn map_split_tunnel_error(error: talpid_core::split_tunnel::Error) -> Status {
                 Status::unknown(error.to_string())
             }
         }
+        Error::CannotResetEngaged => Status::failed_precondition(error.to_string()),
         _ => Status::unknown(error.to_string()),
     }
 }
 
+#[cfg(feature = "qa-tools")]
+fn simulated_event_from_proto(
+    event: types::SimulatedEvent,
+) -> Result<talpid_core::tunnel_state_machine::SimulatedEvent, Status> {
+    use talpid_core::tunnel_state_machine::SimulatedEvent;
+    use talpid_types::{net::PhysicalInterface, tunnel::ParameterGenerationError};
+    use types::{error_state::GenerationError, simulated_event::Event};
+
+    match event.event {
+        Some(Event::Offline(is_offline)) => Ok(SimulatedEvent::Offline(is_offline)),
+        Some(Event::DefaultRoute(route)) => {
+            let interface = if route.interface_name.is_empty() {
+                None
+            } else {
+                Some(PhysicalInterface {
+                    name: route.interface_name,
+                    index: route.interface_index,
+                })
+            };
+            Ok(SimulatedEvent::DefaultRouteChanged(interface))
+        }
+        Some(Event::DropTunnelEvents(count)) => Ok(SimulatedEvent::DropTunnelEvents(count)),
+        Some(Event::ParameterError(error)) => {
+            let error = match GenerationError::from_i32(error) {
+                Some(GenerationError::NoMatchingRelay) => ParameterGenerationError::NoMatchingRelay,
+                Some(GenerationError::NoMatchingBridgeRelay) => {
+                    ParameterGenerationError::NoMatchingBridgeRelay
+                }
+                Some(GenerationError::NoWireguardKey) => ParameterGenerationError::NoWireguardKey,
+                Some(GenerationError::CustomTunnelHostResolutionError) => {
+                    ParameterGenerationError::CustomTunnelHostResultionError
+                }
+                Some(GenerationError::InvalidTunnelParameters) => {
+                    return Err(Status::invalid_argument(
+                        "invalid tunnel parameters are detected by connecting with them",
+                    ))
+                }
+                None => {
+                    return Err(Status::invalid_argument(
+                        "invalid parameter generation error",
+                    ))
+                }
+            };
+            Ok(SimulatedEvent::ParameterGenerationFailure(error))
+        }
+        None => Err(Status::invalid_argument("missing simulated event")),
+    }
+}
+
+#[cfg(feature = "qa-tools")]
+/// Converts [`talpid_core::tunnel_state_machine::SimulationError`] into a tonic status.
+fn map_simulation_error(error: talpid_core::tunnel_state_machine::SimulationError) -> Status {
+    use talpid_core::tunnel_state_machine::SimulationError;
+
+    match error {
+        SimulationError::StateMachineDown => Status::unavailable(error.to_string()),
+        SimulationError::Unsupported => Status::unimplemented(error.to_string()),
+    }
+}
+
+#[cfg(all(feature = "packet-capture", target_os = "linux"))]
+/// Converts [`talpid_core::packet_capture::Error`] into a tonic status.
+fn map_packet_capture_error(error: talpid_core::packet_capture::Error) -> Status {
+    use talpid_core::packet_capture::Error;
+
+    match error {
+        Error::AlreadyRunning => Status::already_exists(error.to_string()),
+        Error::NoLogDirectory => Status::failed_precondition(error.to_string()),
+        Error::CreateFile(_) | Error::OpenInterface(_) => Status::internal(error.display_chain()),
+    }
+}
+
 /// Converts a REST API error into a tonic status.
 fn map_rest_error(error: &RestError) -> Status {
     match error {
@@ -983,6 +1964,9 @@ fn map_rest_error(error: &RestError) -> Status {
         }
         RestError::TimeoutError(_elapsed) => Status::deadline_exceeded("API request timed out"),
         RestError::HyperError(_) => Status::unavailable("Cannot reach the API"),
+        RestError::ClockSkewError(_) => Status::failed_precondition(
+            "Cannot verify the identity of the API. Make sure that the system clock is correct",
+        ),
         error => Status::unknown(format!("REST error: {}", error)),
     }
 }
@@ -1012,6 +1996,9 @@ fn map_device_error(error: &device::Error) -> Status {
         }
         device::Error::InvalidVoucher => Status::new(Code::NotFound, INVALID_VOUCHER_MESSAGE),
         device::Error::UsedVoucher => Status::new(Code::ResourceExhausted, USED_VOUCHER_MESSAGE),
+        device::Error::ExpiredVoucher => {
+            Status::new(Code::FailedPrecondition, EXPIRED_VOUCHER_MESSAGE)
+        }
         device::Error::DeviceIoError(ref _error) => {
             Status::new(Code::Unavailable, error.to_string())
         }
@@ -0,0 +1,284 @@
+//! Checks for common problems that prevent the daemon from connecting, run once at startup.
+//!
+//! A missing driver or a disabled system service otherwise only shows up as an obscure error
+//! when the user tries to connect. The checks are run before the tunnel state machine is started,
+//! and their results, including hints about how to fix each problem, can be retrieved by clients.
+
+use std::path::Path;
+use talpid_types::health::{HealthCheck, HealthReport, HealthStatus};
+
+/// Runs all checks for the current platform.
+#[cfg_attr(not(windows), allow(unused_variables))]
+pub(crate) fn run(resource_dir: &Path) -> HealthReport {
+    #[allow(unused_mut)]
+    let mut checks = vec![];
+
+    #[cfg(target_os = "linux")]
+    {
+        checks.push(linux::check_tun_device());
+        checks.push(linux::check_security_module());
+        checks.push(linux::check_conflicting_processes());
+    }
+    #[cfg(windows)]
+    {
+        checks.push(windows::check_driver_library(
+            "WireGuard driver",
+            &resource_dir.join("mullvad-wireguard.dll"),
+        ));
+        checks.push(windows::check_driver_library(
+            "Wintun driver",
+            &resource_dir.join("wintun.dll"),
+        ));
+        checks.push(windows::check_base_filtering_engine());
+        checks.push(windows::check_conflicting_services());
+    }
+    #[cfg(target_os = "macos")]
+    checks.push(macos::check_packet_filter());
+
+    for check in checks
+        .iter()
+        .filter(|check| check.status != HealthStatus::Pass)
+    {
+        match &check.remediation {
+            Some(remediation) => log::warn!("Preflight check: {}. {}", check, remediation),
+            None => log::warn!("Preflight check: {}", check),
+        }
+    }
+    HealthReport { checks }
+}
+
+/// Advice given when another VPN client is running.
+#[cfg(any(target_os = "linux", windows))]
+const CONFLICTING_VPN_REMEDIATION: &str =
+    "Stop or uninstall the other VPN client, since its firewall rules and routes may conflict \
+     with those of this app";
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::CONFLICTING_VPN_REMEDIATION;
+    use std::{collections::BTreeSet, fs, io};
+    use talpid_types::health::HealthCheck;
+
+    const TUN_DEVICE_PATH: &str = "/dev/net/tun";
+
+    /// Names of the daemons of other VPN clients that manage the firewall or routes.
+    const CONFLICTING_PROCESSES: &[&str] = &["nordvpnd", "expressvpnd", "pia-daemon", "warp-svc"];
+
+    pub fn check_tun_device() -> HealthCheck {
+        const NAME: &str = "tun device";
+        match fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(TUN_DEVICE_PATH)
+        {
+            Ok(_) => HealthCheck::pass(NAME),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                HealthCheck::fail(NAME, format!("{} does not exist", TUN_DEVICE_PATH))
+                    .with_remediation("Load the tun kernel module, e.g. with `modprobe tun`")
+            }
+            Err(error) => HealthCheck::fail(
+                NAME,
+                format!("failed to open {}: {}", TUN_DEVICE_PATH, error),
+            )
+            .with_remediation(
+                "Make sure that the daemon runs as root and that no security policy denies it \
+                 access to the device",
+            ),
+        }
+    }
+
+    /// Mandatory access control that the daemon is confined by.
+    #[derive(Debug, PartialEq, Eq)]
+    pub enum Confinement {
+        /// Name of the enforced AppArmor profile.
+        AppArmor(String),
+        /// SELinux type of the daemon.
+        Selinux(String),
+    }
+
+    pub fn check_security_module() -> HealthCheck {
+        const NAME: &str = "security module";
+        let label = fs::read_to_string("/proc/self/attr/apparmor/current")
+            .or_else(|_| fs::read_to_string("/proc/self/attr/current"))
+            .unwrap_or_default();
+        let selinux_enforcing = fs::read_to_string("/sys/fs/selinux/enforce")
+            .map(|enforce| enforce.trim() == "1")
+            .unwrap_or(false);
+
+        match confinement(label.trim_end_matches('\0').trim(), selinux_enforcing) {
+            Some(Confinement::AppArmor(profile)) => HealthCheck::warn(
+                NAME,
+                format!("confined by the AppArmor profile {}", profile),
+            )
+            .with_remediation(
+                "If connecting fails, look for denials with `journalctl -k | grep apparmor` and \
+                 adjust the profile",
+            ),
+            Some(Confinement::Selinux(domain)) => HealthCheck::warn(
+                NAME,
+                format!("confined by SELinux in the domain {}", domain),
+            )
+            .with_remediation(
+                "If connecting fails, look for denials with `ausearch -m avc -ts recent` and \
+                 adjust the policy",
+            ),
+            None => HealthCheck::pass(NAME),
+        }
+    }
+
+    /// Determines how the daemon is confined, given its security label as read from
+    /// `/proc/self/attr`.
+    pub fn confinement(label: &str, selinux_enforcing: bool) -> Option<Confinement> {
+        if let Some(profile) = label.strip_suffix(" (enforce)") {
+            return Some(Confinement::AppArmor(profile.to_owned()));
+        }
+        if selinux_enforcing {
+            let domain = label.split(':').nth(2)?;
+            if !domain.contains("unconfined") {
+                return Some(Confinement::Selinux(domain.to_owned()));
+            }
+        }
+        None
+    }
+
+    pub fn check_conflicting_processes() -> HealthCheck {
+        const NAME: &str = "conflicting services";
+        let running = match running_process_names() {
+            Ok(running) => running,
+            Err(error) => {
+                return HealthCheck::warn(NAME, format!("failed to list processes: {}", error))
+            }
+        };
+        let conflicting: Vec<_> = CONFLICTING_PROCESSES
+            .iter()
+            .filter(|name| running.contains(**name))
+            .copied()
+            .collect();
+        if conflicting.is_empty() {
+            HealthCheck::pass(NAME)
+        } else {
+            HealthCheck::warn(NAME, format!("running: {}", conflicting.join(", ")))
+                .with_remediation(CONFLICTING_VPN_REMEDIATION)
+        }
+    }
+
+    fn running_process_names() -> io::Result<BTreeSet<String>> {
+        let mut names = BTreeSet::new();
+        for entry in fs::read_dir("/proc")? {
+            let path = entry?.path();
+            // Processes may exit while they are listed
+            if let Ok(name) = fs::read_to_string(path.join("comm")) {
+                names.insert(name.trim().to_owned());
+            }
+        }
+        Ok(names)
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use super::CONFLICTING_VPN_REMEDIATION;
+    use std::path::Path;
+    use talpid_types::health::HealthCheck;
+    use windows_service::{
+        service::{ServiceAccess, ServiceState},
+        service_manager::{ServiceManager, ServiceManagerAccess},
+    };
+
+    /// Names of the services of other VPN clients that manage the firewall or routes.
+    const CONFLICTING_SERVICES: &[&str] = &[
+        "nordvpn-service",
+        "ExpressVpnService",
+        "PrivateInternetAccessService",
+        "CloudflareWARP",
+    ];
+
+    pub fn check_driver_library(name: &'static str, path: &Path) -> HealthCheck {
+        if path.exists() {
+            HealthCheck::pass(name)
+        } else {
+            HealthCheck::fail(name, format!("{} is missing", path.display()))
+                .with_remediation("Reinstall the app")
+        }
+    }
+
+    pub fn check_base_filtering_engine() -> HealthCheck {
+        const NAME: &str = "base filtering engine";
+        match service_state("BFE") {
+            Ok(ServiceState::Running) => HealthCheck::pass(NAME),
+            Ok(state) => HealthCheck::fail(NAME, format!("the BFE service is {:?}", state))
+                .with_remediation(
+                    "Start the Base Filtering Engine service, e.g. with `sc start BFE`, and set \
+                     it to start automatically. The firewall of the app depends on it",
+                ),
+            Err(error) => {
+                HealthCheck::warn(NAME, format!("failed to query the BFE service: {}", error))
+            }
+        }
+    }
+
+    pub fn check_conflicting_services() -> HealthCheck {
+        const NAME: &str = "conflicting services";
+        let conflicting: Vec<_> = CONFLICTING_SERVICES
+            .iter()
+            .filter(|service| matches!(service_state(service), Ok(ServiceState::Running)))
+            .copied()
+            .collect();
+        if conflicting.is_empty() {
+            HealthCheck::pass(NAME)
+        } else {
+            HealthCheck::warn(NAME, format!("running: {}", conflicting.join(", ")))
+                .with_remediation(CONFLICTING_VPN_REMEDIATION)
+        }
+    }
+
+    fn service_state(name: &str) -> windows_service::Result<ServiceState> {
+        let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+        let service = manager.open_service(name, ServiceAccess::QUERY_STATUS)?;
+        Ok(service.query_status()?.current_state)
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+    use std::path::Path;
+    use talpid_types::health::HealthCheck;
+
+    const PFCTL_PATH: &str = "/sbin/pfctl";
+
+    pub fn check_packet_filter() -> HealthCheck {
+        const NAME: &str = "packet filter";
+        if Path::new(PFCTL_PATH).exists() {
+            HealthCheck::pass(NAME)
+        } else {
+            HealthCheck::fail(NAME, format!("{} is missing", PFCTL_PATH)).with_remediation(
+                "The firewall of the app requires pfctl, which is part of macOS. Reinstall macOS \
+                 if it has been removed",
+            )
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod test {
+    use super::linux::{confinement, Confinement};
+
+    #[test]
+    fn test_confinement() {
+        assert_eq!(confinement("unconfined", false), None);
+        assert_eq!(
+            confinement("mullvad-daemon (enforce)", false),
+            Some(Confinement::AppArmor("mullvad-daemon".to_owned()))
+        );
+        assert_eq!(confinement("mullvad-daemon (complain)", false), None);
+        assert_eq!(
+            confinement("system_u:system_r:unconfined_service_t:s0", true),
+            None
+        );
+        assert_eq!(
+            confinement("system_u:system_r:mullvad_t:s0", true),
+            Some(Confinement::Selinux("mullvad_t".to_owned()))
+        );
+        assert_eq!(confinement("system_u:system_r:mullvad_t:s0", false), None);
+    }
+}
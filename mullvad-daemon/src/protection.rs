@@ -0,0 +1,296 @@
+//! Persistent record of the target state and of whether the network was protected, which is used
+//! to tell whether the network may have been left unprotected while the daemon or the system was
+//! not running.
+
+use chrono::{DateTime, Utc};
+use mullvad_types::states::{ProtectionGapReport, TargetState, TunnelState};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use talpid_types::ErrorExt;
+use tokio::{fs, io, sync::Mutex};
+
+const PROTECTION_RECORD_FILE: &str = "protection-record.json";
+
+/// How often the record is rewritten while nothing changes. This bounds how far off the time
+/// at which an unclean shutdown happened can be.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ProtectionRecord {
+    /// Last commanded target state.
+    target_state: TargetState,
+    target_state_since: DateTime<Utc>,
+    /// Whether the last achieved tunnel state protected the network.
+    protected: bool,
+    protected_since: DateTime<Utc>,
+    /// Last time that the record was known to be accurate.
+    last_seen: DateTime<Utc>,
+    /// Set when the daemon is shut down cleanly.
+    stopped_at: Option<DateTime<Utc>>,
+    /// Identifies the current boot of the system, if supported on the platform.
+    boot_id: Option<String>,
+}
+
+/// Keeps the protection record up to date, and reconciles it with the record left behind by the
+/// previous instance of the daemon.
+pub struct ProtectionTracker {
+    record: Arc<Mutex<ProtectionRecord>>,
+    cache_path: PathBuf,
+    gap_report: Option<ProtectionGapReport>,
+    heartbeat: tokio::task::JoinHandle<()>,
+}
+
+impl ProtectionTracker {
+    /// Loads the record of the previous instance, if there is one, and starts a new record using
+    /// `target_state`.
+    pub async fn new(cache_dir: &Path, target_state: TargetState) -> Self {
+        let cache_path = cache_dir.join(PROTECTION_RECORD_FILE);
+        let now = Utc::now();
+        let boot_id = boot_id().await;
+
+        let previous = load(&cache_path).await;
+        let gap_report = previous.and_then(|previous| gap_report(previous, boot_id.as_deref()));
+        if let Some(report) = &gap_report {
+            log::warn!(
+                "The network may have been unprotected since {}. System rebooted: {}",
+                report.unprotected_since,
+                report
+                    .system_rebooted
+                    .map(|rebooted| rebooted.to_string())
+                    .unwrap_or_else(|| "unknown".to_owned())
+            );
+        }
+
+        let record = ProtectionRecord {
+            target_state,
+            target_state_since: now,
+            protected: false,
+            protected_since: now,
+            last_seen: now,
+            stopped_at: None,
+            boot_id,
+        };
+        save(&cache_path, &record).await;
+        let record = Arc::new(Mutex::new(record));
+
+        let heartbeat = tokio::spawn(heartbeat(record.clone(), cache_path.clone()));
+
+        ProtectionTracker {
+            record,
+            cache_path,
+            gap_report,
+            heartbeat,
+        }
+    }
+
+    /// Returns the protection gap caused by the previous instance stopping while secured, if
+    /// there was one.
+    pub fn gap_report(&self) -> Option<ProtectionGapReport> {
+        self.gap_report.clone()
+    }
+
+    pub async fn set_target_state(&mut self, target_state: TargetState) {
+        let mut record = self.record.lock().await;
+        if record.target_state != target_state {
+            let now = Utc::now();
+            record.target_state = target_state;
+            record.target_state_since = now;
+            record.last_seen = now;
+            save(&self.cache_path, &record).await;
+        }
+    }
+
+    pub async fn set_tunnel_state(&mut self, tunnel_state: &TunnelState) {
+        let protected = tunnel_state.is_protected();
+        let mut record = self.record.lock().await;
+        if record.protected == protected {
+            return;
+        }
+        let now = Utc::now();
+        record.protected = protected;
+        record.protected_since = now;
+        record.last_seen = now;
+        save(&self.cache_path, &record).await;
+
+        if let Some(report) = self.gap_report.as_mut() {
+            if protected && report.protected_again_at.is_none() {
+                report.protected_again_at = Some(now);
+                log::info!("{}", report);
+            }
+        }
+    }
+
+    /// Records that the daemon was shut down cleanly.
+    pub async fn finalize(self) {
+        self.heartbeat.abort();
+        let mut record = self.record.lock().await;
+        let now = Utc::now();
+        record.last_seen = now;
+        record.stopped_at = Some(now);
+        save(&self.cache_path, &record).await;
+    }
+}
+
+/// Returns the gap left by the instance that wrote `previous`, if it was secured when it stopped.
+fn gap_report(previous: ProtectionRecord, boot_id: Option<&str>) -> Option<ProtectionGapReport> {
+    if previous.target_state != TargetState::Secured {
+        return None;
+    }
+    let unprotected_since = if previous.protected {
+        previous.stopped_at.unwrap_or(previous.last_seen)
+    } else {
+        previous.protected_since
+    };
+    let system_rebooted = match (previous.boot_id.as_deref(), boot_id) {
+        (Some(previous_boot_id), Some(boot_id)) => Some(previous_boot_id != boot_id),
+        _ => None,
+    };
+    Some(ProtectionGapReport {
+        unclean_shutdown: previous.stopped_at.is_none(),
+        system_rebooted,
+        unprotected_since,
+        protected_again_at: None,
+    })
+}
+
+async fn heartbeat(record: Arc<Mutex<ProtectionRecord>>, cache_path: PathBuf) {
+    loop {
+        tokio::time::sleep(HEARTBEAT_INTERVAL).await;
+        let mut record = record.lock().await;
+        record.last_seen = Utc::now();
+        save(&cache_path, &record).await;
+    }
+}
+
+async fn load(cache_path: &Path) -> Option<ProtectionRecord> {
+    match fs::read_to_string(cache_path).await {
+        Ok(content) => serde_json::from_str(&content)
+            .map_err(|error| {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to parse protection record")
+                );
+            })
+            .ok(),
+        Err(error) => {
+            if error.kind() != io::ErrorKind::NotFound {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to read protection record")
+                );
+            }
+            None
+        }
+    }
+}
+
+/// Replaces the record at `cache_path`. The record is written to a temporary file first, so that
+/// a crash while writing it does not leave a partial record behind.
+async fn save(cache_path: &Path, record: &ProtectionRecord) {
+    match serde_json::to_string(record) {
+        Ok(data) => {
+            let temp_path = cache_path.with_extension("json.tmp");
+            let result = async {
+                fs::write(&temp_path, data).await?;
+                fs::rename(&temp_path, cache_path).await
+            };
+            if let Err(error) = result.await {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to write protection record")
+                );
+            }
+        }
+        Err(error) => {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to serialize protection record")
+            );
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+async fn boot_id() -> Option<String> {
+    fs::read_to_string("/proc/sys/kernel/random/boot_id")
+        .await
+        .map(|boot_id| boot_id.trim().to_owned())
+        .ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn boot_id() -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(target_state: TargetState, protected: bool) -> ProtectionRecord {
+        let start = DateTime::parse_from_rfc3339("2022-01-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        ProtectionRecord {
+            target_state,
+            target_state_since: start,
+            protected,
+            protected_since: start + chrono::Duration::seconds(10),
+            last_seen: start + chrono::Duration::seconds(60),
+            stopped_at: None,
+            boot_id: Some("a".to_owned()),
+        }
+    }
+
+    #[test]
+    fn test_no_gap_when_unsecured() {
+        assert_eq!(
+            gap_report(record(TargetState::Unsecured, false), Some("b")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_gap_after_crash_while_protected() {
+        let previous = record(TargetState::Secured, true);
+        let report = gap_report(previous.clone(), Some("b")).unwrap();
+        assert!(report.unclean_shutdown);
+        assert_eq!(report.system_rebooted, Some(true));
+        assert_eq!(report.unprotected_since, previous.last_seen);
+        assert_eq!(report.duration(), None);
+    }
+
+    #[tokio::test]
+    async fn test_save_replaces_record() {
+        let cache_dir = tempfile::tempdir().unwrap();
+        let cache_path = cache_dir.path().join(PROTECTION_RECORD_FILE);
+
+        save(&cache_path, &record(TargetState::Unsecured, false)).await;
+        let mut updated = record(TargetState::Secured, true);
+        updated.stopped_at = Some(updated.last_seen);
+        save(&cache_path, &updated).await;
+
+        let loaded = load(&cache_path).await.unwrap();
+        assert_eq!(loaded.target_state, TargetState::Secured);
+        assert_eq!(loaded.stopped_at, updated.stopped_at);
+        assert_eq!(
+            std::fs::read_dir(cache_dir.path()).unwrap().count(),
+            1,
+            "temporary file was left behind"
+        );
+    }
+
+    #[test]
+    fn test_gap_while_unprotected() {
+        let mut previous = record(TargetState::Secured, false);
+        previous.stopped_at = Some(previous.last_seen);
+        let report = gap_report(previous.clone(), None).unwrap();
+        assert!(!report.unclean_shutdown);
+        assert_eq!(report.system_rebooted, None);
+        assert_eq!(report.unprotected_since, previous.protected_since);
+    }
+}
@@ -0,0 +1,171 @@
+//! Keeps track of a temporary pause of the protection, during which the tunnel is disconnected
+//! and traffic is not blocked. The daemon reconnects by itself once the pause ends, so that
+//! protection is restored even if the front-end that paused it is no longer running.
+
+use crate::DaemonEventSender;
+use chrono::{DateTime, Utc};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use talpid_core::mpsc::Sender;
+use talpid_types::ErrorExt;
+use tokio::{fs, io};
+
+const PROTECTION_PAUSE_FILE: &str = "protection-pause.json";
+
+/// Protection cannot be paused for longer than this many hours.
+pub const MAX_PAUSE_HOURS: u64 = 24;
+pub const MAX_PAUSE_DURATION: Duration = Duration::from_secs(MAX_PAUSE_HOURS * 60 * 60);
+
+/// How often the wall clock is checked while paused. The monotonic clock may not advance while
+/// the system is suspended, so it cannot be relied upon alone to end the pause on time.
+const CLOCK_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Sent to the daemon when the pause that ends at the contained time is over.
+pub struct ProtectionPauseExpired(pub DateTime<Utc>);
+
+/// The current pause, if there is one. The end of the pause is saved to the cache directory so
+/// that it is respected across restarts of the daemon.
+pub struct ProtectionPause {
+    cache_path: PathBuf,
+    resume_at: Option<DateTime<Utc>>,
+    ended_while_stopped: bool,
+    timer: Option<tokio::task::JoinHandle<()>>,
+    event_tx: DaemonEventSender<ProtectionPauseExpired>,
+}
+
+impl ProtectionPause {
+    /// Loads the pause that was in effect when the previous instance of the daemon stopped, if
+    /// there was one.
+    pub async fn new(
+        cache_dir: &Path,
+        event_tx: DaemonEventSender<ProtectionPauseExpired>,
+    ) -> Self {
+        let cache_path = cache_dir.join(PROTECTION_PAUSE_FILE);
+        let mut pause = ProtectionPause {
+            cache_path,
+            resume_at: None,
+            ended_while_stopped: false,
+            timer: None,
+            event_tx,
+        };
+
+        if let Some(resume_at) = load(&pause.cache_path).await {
+            if resume_at <= Utc::now() {
+                log::info!(
+                    "Protection pause ended at {} while the daemon was stopped",
+                    resume_at
+                );
+                pause.ended_while_stopped = true;
+                remove(&pause.cache_path).await;
+            } else {
+                log::info!("Protection is paused until {}", resume_at);
+                pause.set_timer(resume_at);
+            }
+        }
+        pause
+    }
+
+    /// Returns when the current pause ends, if protection is paused.
+    pub fn resume_at(&self) -> Option<DateTime<Utc>> {
+        self.resume_at
+    }
+
+    /// Returns whether the pause of the previous instance of the daemon ended while it was not
+    /// running.
+    pub fn ended_while_stopped(&self) -> bool {
+        self.ended_while_stopped
+    }
+
+    /// Pauses protection for `duration`, replacing the current pause if there is one. Returns
+    /// when the pause ends.
+    pub async fn start(&mut self, duration: Duration) -> DateTime<Utc> {
+        let resume_at = Utc::now()
+            + chrono::Duration::from_std(duration)
+                .unwrap_or_else(|_| chrono::Duration::max_value());
+        save(&self.cache_path, resume_at).await;
+        self.set_timer(resume_at);
+        resume_at
+    }
+
+    /// Ends the current pause. Returns whether protection was paused.
+    pub async fn end(&mut self) -> bool {
+        if let Some(timer) = self.timer.take() {
+            timer.abort();
+        }
+        if self.resume_at.take().is_some() {
+            remove(&self.cache_path).await;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn set_timer(&mut self, resume_at: DateTime<Utc>) {
+        if let Some(timer) = self.timer.take() {
+            timer.abort();
+        }
+        self.resume_at = Some(resume_at);
+        self.timer = Some(tokio::spawn(wait_until(resume_at, self.event_tx.clone())));
+    }
+}
+
+async fn wait_until(resume_at: DateTime<Utc>, event_tx: DaemonEventSender<ProtectionPauseExpired>) {
+    while let Ok(remaining) = (resume_at - Utc::now()).to_std() {
+        tokio::time::sleep(remaining.min(CLOCK_CHECK_INTERVAL)).await;
+    }
+    let _ = event_tx.send(ProtectionPauseExpired(resume_at));
+}
+
+async fn load(cache_path: &Path) -> Option<DateTime<Utc>> {
+    match fs::read_to_string(cache_path).await {
+        Ok(content) => serde_json::from_str(&content)
+            .map_err(|error| {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to parse protection pause")
+                );
+            })
+            .ok(),
+        Err(error) => {
+            if error.kind() != io::ErrorKind::NotFound {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to read protection pause")
+                );
+            }
+            None
+        }
+    }
+}
+
+async fn save(cache_path: &Path, resume_at: DateTime<Utc>) {
+    match serde_json::to_string(&resume_at) {
+        Ok(data) => {
+            if let Err(error) = fs::write(cache_path, data).await {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to write protection pause")
+                );
+            }
+        }
+        Err(error) => {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to serialize protection pause")
+            );
+        }
+    }
+}
+
+async fn remove(cache_path: &Path) {
+    if let Err(error) = fs::remove_file(cache_path).await {
+        if error.kind() != io::ErrorKind::NotFound {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to remove protection pause")
+            );
+        }
+    }
+}
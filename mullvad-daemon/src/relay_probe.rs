@@ -0,0 +1,86 @@
+//! Estimates the latency to relays by timing a TCP handshake with them. Results are cached for a
+//! while, so that repeated relay queries do not cause a burst of connections to the same relays.
+
+use futures::{stream, StreamExt};
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::net::TcpStream;
+
+/// Maximum number of relays that are probed for a single query.
+pub const MAX_PROBED_RELAYS: usize = 10;
+
+/// An endpoint is probed at most once during this interval. Queries in between reuse the result.
+const MIN_PROBE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Endpoints that do not complete the handshake within this time are assumed to be unreachable.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+const MAX_CONCURRENT_PROBES: usize = 5;
+
+struct ProbeResult {
+    probed_at: Instant,
+    latency: Option<Duration>,
+}
+
+#[derive(Clone, Default)]
+pub struct RelayProber {
+    results: Arc<Mutex<HashMap<SocketAddr, ProbeResult>>>,
+}
+
+impl RelayProber {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Estimates the latency to each endpoint. The result for `None` is always `None`.
+    pub async fn probe(&self, endpoints: Vec<Option<SocketAddr>>) -> Vec<Option<Duration>> {
+        stream::iter(endpoints)
+            .map(|endpoint| async move {
+                match endpoint {
+                    Some(endpoint) => self.probe_endpoint(endpoint).await,
+                    None => None,
+                }
+            })
+            .buffered(MAX_CONCURRENT_PROBES)
+            .collect()
+            .await
+    }
+
+    async fn probe_endpoint(&self, endpoint: SocketAddr) -> Option<Duration> {
+        if let Some(result) = self.results.lock().get(&endpoint) {
+            if result.probed_at.elapsed() < MIN_PROBE_INTERVAL {
+                return result.latency;
+            }
+        }
+
+        let latency = measure_handshake(endpoint).await;
+        self.results.lock().insert(
+            endpoint,
+            ProbeResult {
+                probed_at: Instant::now(),
+                latency,
+            },
+        );
+        latency
+    }
+}
+
+async fn measure_handshake(endpoint: SocketAddr) -> Option<Duration> {
+    let start = Instant::now();
+    match tokio::time::timeout(PROBE_TIMEOUT, TcpStream::connect(endpoint)).await {
+        Ok(Ok(_stream)) => Some(start.elapsed()),
+        Ok(Err(error)) => {
+            log::debug!("Failed to probe relay at {}: {}", endpoint, error);
+            None
+        }
+        Err(_) => {
+            log::debug!("Timed out probing relay at {}", endpoint);
+            None
+        }
+    }
+}
@@ -0,0 +1,178 @@
+#![cfg(windows)]
+
+//! How the daemon is stopped when it runs as a Windows service.
+//!
+//! This does not depend on the service control manager (SCM), so the behavior can be exercised
+//! without installing the service. The service only has to translate its control events into
+//! calls to [`ServiceStopHandle::stop`], and report the status that it is given.
+
+use crate::DaemonShutdownHandle;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+/// How long the SCM should wait for the next status update while the daemon is stopping.
+pub const STOP_WAIT_HINT: Duration = Duration::from_secs(10);
+/// How often to tell the SCM that the daemon is still stopping. This must be shorter than
+/// [`STOP_WAIT_HINT`].
+const STOP_PENDING_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Reports the status of the service to the SCM.
+pub trait StatusReporter: Send + 'static {
+    /// Tells the SCM that the service is stopping, and that the next update will be sent within
+    /// `wait_hint`.
+    fn report_stop_pending(&mut self, wait_hint: Duration);
+}
+
+/// Why the service is being stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The service was stopped by a user or another program.
+    Stop,
+    /// The system is shutting down. If the daemon should keep blocking traffic, the firewall is
+    /// switched to persistent blocking so that traffic remains blocked while the system shuts
+    /// down and boots again.
+    Preshutdown,
+    /// The daemon should be restarted by the SCM, e.g. after resuming from hibernation. Traffic
+    /// is blocked the same way as when the system shuts down.
+    Restart,
+}
+
+/// Stops the daemon and keeps the SCM informed until it has stopped. Clones share the same
+/// state.
+pub struct ServiceStopHandle<R: StatusReporter> {
+    reporter: Arc<Mutex<R>>,
+    shutdown_handle: DaemonShutdownHandle,
+    should_restart: Arc<AtomicBool>,
+    stopping: Arc<AtomicBool>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl<R: StatusReporter> Clone for ServiceStopHandle<R> {
+    fn clone(&self) -> Self {
+        Self {
+            reporter: self.reporter.clone(),
+            shutdown_handle: self.shutdown_handle.clone(),
+            should_restart: self.should_restart.clone(),
+            stopping: self.stopping.clone(),
+            stopped: self.stopped.clone(),
+        }
+    }
+}
+
+impl<R: StatusReporter> ServiceStopHandle<R> {
+    /// Creates a handle that stops the daemon using `shutdown_handle`. By default, the service
+    /// should be restarted if the daemon exits without being stopped by this handle.
+    pub fn new(reporter: R, shutdown_handle: DaemonShutdownHandle) -> Self {
+        Self {
+            reporter: Arc::new(Mutex::new(reporter)),
+            shutdown_handle,
+            should_restart: Arc::new(AtomicBool::new(true)),
+            stopping: Arc::new(AtomicBool::new(false)),
+            stopped: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Asks the daemon to stop. Until [`Self::set_stopped`] is called, the SCM is periodically
+    /// told that the service is still stopping, so that it waits for the tunnel state machine to
+    /// exit rather than considering the service hung.
+    pub fn stop(&self, reason: StopReason) {
+        log::debug!("Stopping service: {:?}", reason);
+
+        self.should_restart
+            .store(reason == StopReason::Restart, Ordering::Release);
+        self.reporter
+            .lock()
+            .unwrap()
+            .report_stop_pending(STOP_WAIT_HINT);
+        if !self.stopping.swap(true, Ordering::AcqRel) {
+            self.spawn_stop_pending_reporter();
+        }
+
+        let user_init_shutdown = reason == StopReason::Stop;
+        self.shutdown_handle.shutdown(user_init_shutdown);
+    }
+
+    fn spawn_stop_pending_reporter(&self) {
+        let reporter = self.reporter.clone();
+        let stopped = self.stopped.clone();
+        thread::spawn(move || loop {
+            thread::sleep(STOP_PENDING_INTERVAL);
+            if stopped.load(Ordering::Acquire) {
+                break;
+            }
+            reporter.lock().unwrap().report_stop_pending(STOP_WAIT_HINT);
+        });
+    }
+
+    /// Must be called once the daemon has exited, before reporting that the service has stopped.
+    /// Returns whether the SCM should restart the service.
+    pub fn set_stopped(&self) -> bool {
+        self.stopped.store(true, Ordering::Release);
+        // Hold the lock so that an update cannot be sent after the service has stopped
+        let _reporter = self.reporter.lock().unwrap();
+        self.should_restart.load(Ordering::Acquire)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{DaemonEventSender, InternalDaemonEvent};
+    use futures::channel::mpsc;
+
+    #[derive(Clone, Default)]
+    struct TestReporter(Arc<Mutex<Vec<Duration>>>);
+
+    impl StatusReporter for TestReporter {
+        fn report_stop_pending(&mut self, wait_hint: Duration) {
+            self.0.lock().unwrap().push(wait_hint);
+        }
+    }
+
+    fn stop_and_receive(reason: StopReason) -> (bool, bool, usize) {
+        let (tx, mut rx) = mpsc::unbounded();
+        let tx = Arc::new(tx);
+        let shutdown_handle = DaemonShutdownHandle {
+            tx: DaemonEventSender::new(Arc::downgrade(&tx)),
+        };
+        let reporter = TestReporter::default();
+        let handle = ServiceStopHandle::new(reporter.clone(), shutdown_handle);
+
+        handle.stop(reason);
+        let user_init_shutdown = match rx.try_next() {
+            Ok(Some(InternalDaemonEvent::TriggerShutdown(user_init_shutdown))) => {
+                user_init_shutdown
+            }
+            _ => panic!("expected shutdown event"),
+        };
+        let should_restart = handle.set_stopped();
+        let updates = reporter.0.lock().unwrap().len();
+        (user_init_shutdown, should_restart, updates)
+    }
+
+    #[test]
+    fn test_preshutdown_keeps_blocking() {
+        let (user_init_shutdown, should_restart, updates) =
+            stop_and_receive(StopReason::Preshutdown);
+        assert!(!user_init_shutdown);
+        assert!(!should_restart);
+        assert_eq!(updates, 1);
+    }
+
+    #[test]
+    fn test_stop_and_restart() {
+        let (user_init_shutdown, should_restart, _) = stop_and_receive(StopReason::Stop);
+        assert!(user_init_shutdown);
+        assert!(!should_restart);
+
+        let (user_init_shutdown, should_restart, _) = stop_and_receive(StopReason::Restart);
+        assert!(!user_init_shutdown);
+        assert!(should_restart);
+    }
+}
@@ -1,8 +1,10 @@
 #[cfg(not(target_os = "android"))]
 use futures::TryFutureExt;
+use ipnetwork::IpNetwork;
 use mullvad_types::{
+    hooks::Hook,
     relay_constraints::{BridgeSettings, BridgeState, ObfuscationSettings, RelaySettingsUpdate},
-    settings::{DnsOptions, Settings},
+    settings::{DnsOptions, Profile, Settings},
     wireguard::RotationInterval,
 };
 use rand::Rng;
@@ -12,7 +14,15 @@ use std::{
     ops::Deref,
     path::{Path, PathBuf},
 };
-use talpid_types::ErrorExt;
+use talpid_types::{
+    net::{
+        route_takeover::RouteTakeoverPolicy,
+        wireguard::{BandwidthLimit, TrafficShapingOptions},
+        BlockedTunnelProtocols, VpnCoexistence,
+    },
+    tunnel::{ErrorStatePolicy, ReconnectLimits},
+    ErrorExt,
+};
 use tokio::{
     fs,
     io::{self, AsyncWriteExt},
@@ -47,6 +57,8 @@ pub enum Error {
 pub struct SettingsPersister {
     settings: Settings,
     path: PathBuf,
+    /// Serialized settings as of the last call to `take_changed_fields`.
+    reported_settings: serde_json::Value,
 }
 
 impl SettingsPersister {
@@ -88,7 +100,12 @@ impl SettingsPersister {
             should_save |= Self::update_field(&mut settings.show_beta_releases, true);
         }
 
-        let mut persister = SettingsPersister { settings, path };
+        let reported_settings = Self::serialize_for_diff(&settings);
+        let mut persister = SettingsPersister {
+            settings,
+            path,
+            reported_settings,
+        };
 
         if should_save {
             if let Err(error) = persister.save().await {
@@ -226,11 +243,90 @@ impl SettingsPersister {
         self.update(should_save).await
     }
 
+    pub async fn set_flush_dns_cache(&mut self, flush_dns_cache: bool) -> Result<bool, Error> {
+        let should_save = Self::update_field(&mut self.settings.flush_dns_cache, flush_dns_cache);
+        self.update(should_save).await
+    }
+
+    pub async fn set_recovery_allowlist(
+        &mut self,
+        recovery_allowlist: Vec<IpNetwork>,
+    ) -> Result<bool, Error> {
+        let should_save =
+            Self::update_field(&mut self.settings.recovery_allowlist, recovery_allowlist);
+        self.update(should_save).await
+    }
+
+    pub async fn set_vpn_coexistence(
+        &mut self,
+        vpn_coexistence: VpnCoexistence,
+    ) -> Result<bool, Error> {
+        let should_save = Self::update_field(&mut self.settings.vpn_coexistence, vpn_coexistence);
+        self.update(should_save).await
+    }
+
+    pub async fn set_blocked_tunnel_protocols(
+        &mut self,
+        blocked_tunnel_protocols: BlockedTunnelProtocols,
+    ) -> Result<bool, Error> {
+        let should_save = Self::update_field(
+            &mut self.settings.blocked_tunnel_protocols,
+            blocked_tunnel_protocols,
+        );
+        self.update(should_save).await
+    }
+
+    pub async fn set_reconnect_limits(
+        &mut self,
+        reconnect_limits: ReconnectLimits,
+    ) -> Result<bool, Error> {
+        let should_save = Self::update_field(&mut self.settings.reconnect_limits, reconnect_limits);
+        self.update(should_save).await
+    }
+
+    pub async fn set_route_takeover_policy(
+        &mut self,
+        route_takeover_policy: RouteTakeoverPolicy,
+    ) -> Result<bool, Error> {
+        let should_save = Self::update_field(
+            &mut self.settings.route_takeover_policy,
+            route_takeover_policy,
+        );
+        self.update(should_save).await
+    }
+
+    pub async fn set_error_state_policy(
+        &mut self,
+        error_state_policy: ErrorStatePolicy,
+    ) -> Result<bool, Error> {
+        let should_save =
+            Self::update_field(&mut self.settings.error_state_policy, error_state_policy);
+        self.update(should_save).await
+    }
+
+    pub async fn set_forced_interface(
+        &mut self,
+        forced_interface: Option<String>,
+    ) -> Result<bool, Error> {
+        let should_save = Self::update_field(&mut self.settings.forced_interface, forced_interface);
+        self.update(should_save).await
+    }
+
     pub async fn set_auto_connect(&mut self, auto_connect: bool) -> Result<bool, Error> {
         let should_save = Self::update_field(&mut self.settings.auto_connect, auto_connect);
         self.update(should_save).await
     }
 
+    pub async fn set_connection_stats(&mut self, connection_stats: bool) -> Result<bool, Error> {
+        let should_save = Self::update_field(&mut self.settings.connection_stats, connection_stats);
+        self.update(should_save).await
+    }
+
+    pub async fn set_hooks(&mut self, hooks: Vec<Hook>) -> Result<bool, Error> {
+        let should_save = Self::update_field(&mut self.settings.hooks, hooks);
+        self.update(should_save).await
+    }
+
     pub async fn set_openvpn_mssfix(&mut self, openvpn_mssfix: Option<u16>) -> Result<bool, Error> {
         let should_save = Self::update_field(
             &mut self.settings.tunnel_options.openvpn.mssfix,
@@ -247,6 +343,14 @@ impl SettingsPersister {
         self.update(should_save).await
     }
 
+    pub async fn set_enable_ipv4(&mut self, enable_ipv4: bool) -> Result<bool, Error> {
+        let should_save = Self::update_field(
+            &mut self.settings.tunnel_options.generic.enable_ipv4,
+            enable_ipv4,
+        );
+        self.update(should_save).await
+    }
+
     pub async fn set_quantum_resistant_tunnel(
         &mut self,
         use_pq_safe_psk: bool,
@@ -263,6 +367,49 @@ impl SettingsPersister {
         self.update(should_save).await
     }
 
+    pub async fn set_wireguard_traffic_shaping(
+        &mut self,
+        options: TrafficShapingOptions,
+    ) -> Result<bool, Error> {
+        let should_save = Self::update_field(
+            &mut self
+                .settings
+                .tunnel_options
+                .wireguard
+                .options
+                .traffic_shaping,
+            options,
+        );
+        self.update(should_save).await
+    }
+
+    pub async fn set_wireguard_bandwidth_limit(
+        &mut self,
+        limit: BandwidthLimit,
+    ) -> Result<bool, Error> {
+        let should_save = Self::update_field(
+            &mut self
+                .settings
+                .tunnel_options
+                .wireguard
+                .options
+                .bandwidth_limit,
+            limit,
+        );
+        self.update(should_save).await
+    }
+
+    pub async fn set_wireguard_allowed_ips(
+        &mut self,
+        allowed_ips: Option<Vec<IpNetwork>>,
+    ) -> Result<bool, Error> {
+        let should_save = Self::update_field(
+            &mut self.settings.tunnel_options.wireguard.options.allowed_ips,
+            allowed_ips,
+        );
+        self.update(should_save).await
+    }
+
     pub async fn set_dns_options(&mut self, options: DnsOptions) -> Result<bool, Error> {
         let should_save =
             Self::update_field(&mut self.settings.tunnel_options.dns_options, options);
@@ -338,6 +485,41 @@ impl SettingsPersister {
         self.update(should_save).await
     }
 
+    pub async fn save_profile(&mut self, profile: Profile) -> Result<bool, Error> {
+        let should_save = self.settings.save_profile(profile);
+        self.update(should_save).await
+    }
+
+    pub async fn remove_profile(&mut self, name: &str) -> Result<bool, Error> {
+        let should_save = self.settings.remove_profile(name);
+        self.update(should_save).await
+    }
+
+    pub async fn apply_profile(&mut self, profile: &Profile) -> Result<bool, Error> {
+        let should_save = self.settings.apply_profile(profile);
+        self.update(should_save).await
+    }
+
+    /// Returns the paths of all fields that have changed since the last time this function was
+    /// called, e.g. `tunnel_options.dns_options`.
+    pub fn take_changed_fields(&mut self) -> Vec<String> {
+        let current = Self::serialize_for_diff(&self.settings);
+        let mut changed_fields = vec![];
+        changed_fields_between(&self.reported_settings, &current, "", &mut changed_fields);
+        self.reported_settings = current;
+        changed_fields
+    }
+
+    fn serialize_for_diff(settings: &Settings) -> serde_json::Value {
+        serde_json::to_value(settings).unwrap_or_else(|error| {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to serialize settings")
+            );
+            serde_json::Value::Null
+        })
+    }
+
     fn update_field<T: Eq>(field: &mut T, new_value: T) -> bool {
         if *field != new_value {
             *field = new_value;
@@ -368,6 +550,41 @@ impl SettingsPersister {
     }
 }
 
+/// Appends the paths of all leaf values that differ between `old` and `new` to `changed_fields`.
+/// Objects are compared key by key, while any other values are compared as a whole.
+fn changed_fields_between(
+    old: &serde_json::Value,
+    new: &serde_json::Value,
+    path: &str,
+    changed_fields: &mut Vec<String>,
+) {
+    use serde_json::Value;
+
+    match (old, new) {
+        (Value::Object(old_fields), Value::Object(new_fields)) => {
+            let mut keys: Vec<&String> = old_fields.keys().chain(new_fields.keys()).collect();
+            keys.sort();
+            keys.dedup();
+
+            for key in keys {
+                let field_path = if path.is_empty() {
+                    key.to_owned()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                changed_fields_between(
+                    old_fields.get(key).unwrap_or(&Value::Null),
+                    new_fields.get(key).unwrap_or(&Value::Null),
+                    &field_path,
+                    changed_fields,
+                );
+            }
+        }
+        (old, new) if old != new => changed_fields.push(path.to_owned()),
+        _ => (),
+    }
+}
+
 impl Deref for SettingsPersister {
     type Target = Settings;
 
@@ -378,7 +595,7 @@ impl Deref for SettingsPersister {
 
 #[cfg(test)]
 mod test {
-    use super::SettingsPersister;
+    use super::{changed_fields_between, SettingsPersister};
     use mullvad_types::settings::SettingsVersion;
     use serde_json;
 
@@ -457,4 +674,39 @@ mod test {
 
         let _ = SettingsPersister::load_from_bytes(settings).unwrap();
     }
+
+    #[test]
+    fn test_changed_fields() {
+        let old = serde_json::json!({
+            "allow_lan": false,
+            "tunnel_options": {
+                "wireguard": { "mtu": null },
+                "generic": { "enable_ipv6": true },
+            },
+            "recovery_allowlist": ["10.0.0.0/8"],
+        });
+        let new = serde_json::json!({
+            "allow_lan": true,
+            "tunnel_options": {
+                "wireguard": { "mtu": 1380 },
+                "generic": { "enable_ipv6": true },
+            },
+            "recovery_allowlist": ["10.0.0.0/8", "192.168.0.0/16"],
+        });
+
+        let mut changed_fields = vec![];
+        changed_fields_between(&old, &new, "", &mut changed_fields);
+        assert_eq!(
+            changed_fields,
+            vec![
+                "allow_lan",
+                "recovery_allowlist",
+                "tunnel_options.wireguard.mtu"
+            ]
+        );
+
+        changed_fields.clear();
+        changed_fields_between(&new, &new, "", &mut changed_fields);
+        assert!(changed_fields.is_empty());
+    }
 }
@@ -0,0 +1,246 @@
+//! Planning of how settings changes are applied to the tunnel.
+//!
+//! Most settings can be applied without closing the tunnel, and some only matter for one tunnel
+//! type. Given the paths of the fields that changed, as returned by
+//! `SettingsPersister::take_changed_fields`, an [`ApplyPlan`] contains the least disruptive
+//! operations that apply them.
+
+use std::{collections::BTreeSet, fmt};
+use talpid_types::net::TunnelType;
+
+/// An operation that applies changed settings to the tunnel state machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ApplyOperation {
+    /// Update the firewall policy of the current state.
+    FirewallUpdate,
+    /// Update the DNS configuration of the current state.
+    DnsUpdate,
+    /// Update the running WireGuard tunnel in place, without a new handshake with the relay.
+    PeerUpdate,
+    /// Close the tunnel, if any, and establish a new one.
+    Reconnect,
+}
+
+impl fmt::Display for ApplyOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            ApplyOperation::FirewallUpdate => "firewall update",
+            ApplyOperation::DnsUpdate => "DNS update",
+            ApplyOperation::PeerUpdate => "in-place peer update",
+            ApplyOperation::Reconnect => "reconnect",
+        };
+        f.write_str(description)
+    }
+}
+
+/// The tunnel that changed settings are applied to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CurrentTunnel {
+    /// No tunnel is being established or used.
+    None,
+    /// A tunnel of the given type is being established, or protection is paused and a tunnel of
+    /// the given type is established when it resumes.
+    Connecting(TunnelType),
+    /// A tunnel of the given type is up.
+    Connected(TunnelType),
+}
+
+impl CurrentTunnel {
+    /// Returns the type of the tunnel that is being established or used, if any.
+    fn tunnel_type(self) -> Option<TunnelType> {
+        match self {
+            CurrentTunnel::None => None,
+            CurrentTunnel::Connecting(tunnel_type) | CurrentTunnel::Connected(tunnel_type) => {
+                Some(tunnel_type)
+            }
+        }
+    }
+
+    /// Returns the type of the tunnel if it is up.
+    fn connected_tunnel_type(self) -> Option<TunnelType> {
+        match self {
+            CurrentTunnel::Connected(tunnel_type) => Some(tunnel_type),
+            _ => None,
+        }
+    }
+}
+
+/// The operations needed to apply a set of changed settings. An empty plan means that the
+/// changes do not affect the tunnel.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ApplyPlan {
+    operations: BTreeSet<ApplyOperation>,
+}
+
+impl ApplyPlan {
+    /// Plans how to apply the fields in `changed_fields` to `tunnel`. Changes that only concern
+    /// another tunnel type do not require a reconnect.
+    pub fn new(changed_fields: &[String], tunnel: CurrentTunnel) -> Self {
+        Self {
+            operations: changed_fields
+                .iter()
+                .filter_map(|field| operation_for_field(field, tunnel))
+                .collect(),
+        }
+    }
+
+    /// Returns the operations in the order that they should be performed.
+    pub fn operations(&self) -> impl Iterator<Item = ApplyOperation> + '_ {
+        self.operations.iter().copied()
+    }
+
+    /// Returns whether `operation` is part of the plan.
+    pub fn contains(&self, operation: ApplyOperation) -> bool {
+        self.operations.contains(&operation)
+    }
+
+    /// Returns whether the plan is empty.
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+}
+
+impl fmt::Display for ApplyPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return f.write_str("no tunnel changes");
+        }
+        let operations: Vec<_> = self.operations().map(|op| op.to_string()).collect();
+        f.write_str(&operations.join(", "))
+    }
+}
+
+fn operation_for_field(field: &str, tunnel: CurrentTunnel) -> Option<ApplyOperation> {
+    let tunnel_type = tunnel.tunnel_type();
+    let is_field = |name: &str| field == name || field.starts_with(&format!("{}.", name));
+    // Changes that only concern one tunnel type require a reconnect unless another tunnel type
+    // is known to be used
+    let reconnect_if = |affected: bool| {
+        if affected {
+            Some(ApplyOperation::Reconnect)
+        } else {
+            None
+        }
+    };
+
+    if is_field("allow_lan")
+        || is_field("block_when_disconnected")
+        || is_field("recovery_allowlist")
+        || is_field("vpn_coexistence")
+        || is_field("blocked_tunnel_protocols")
+        || is_field("error_state_policy")
+    {
+        Some(ApplyOperation::FirewallUpdate)
+    } else if is_field("tunnel_options.dns_options") || is_field("flush_dns_cache") {
+        Some(ApplyOperation::DnsUpdate)
+    } else if is_field("relay_settings")
+        || is_field("tunnel_options.generic")
+        || is_field("forced_interface")
+    {
+        Some(ApplyOperation::Reconnect)
+    } else if is_field("obfuscation_settings") {
+        reconnect_if(tunnel_type != Some(TunnelType::OpenVpn))
+    } else if is_field("bridge_settings") || is_field("bridge_state") {
+        reconnect_if(tunnel_type != Some(TunnelType::Wireguard))
+    } else if is_field("tunnel_options.openvpn") {
+        reconnect_if(tunnel_type == Some(TunnelType::OpenVpn))
+    } else if is_field("tunnel_options.wireguard.rotation_interval") {
+        None
+    } else if is_field("tunnel_options.wireguard.traffic_shaping") {
+        // A tunnel that is still being established may already have been configured
+        match tunnel {
+            CurrentTunnel::Connected(TunnelType::Wireguard) => Some(ApplyOperation::PeerUpdate),
+            CurrentTunnel::Connecting(TunnelType::Wireguard) => Some(ApplyOperation::Reconnect),
+            _ => None,
+        }
+    } else if is_field("tunnel_options.wireguard.mtu") {
+        reconnect_if(tunnel.connected_tunnel_type() == Some(TunnelType::Wireguard))
+    } else if is_field("tunnel_options.wireguard") {
+        reconnect_if(tunnel_type == Some(TunnelType::Wireguard))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn plan(fields: &[&str], tunnel: CurrentTunnel) -> ApplyPlan {
+        let fields: Vec<String> = fields.iter().map(|field| field.to_string()).collect();
+        ApplyPlan::new(&fields, tunnel)
+    }
+
+    #[test]
+    fn test_firewall_and_dns_changes_do_not_reconnect() {
+        let plan = plan(
+            &["allow_lan", "tunnel_options.dns_options.state"],
+            CurrentTunnel::Connected(TunnelType::Wireguard),
+        );
+        assert!(plan.contains(ApplyOperation::FirewallUpdate));
+        assert!(plan.contains(ApplyOperation::DnsUpdate));
+        assert!(!plan.contains(ApplyOperation::Reconnect));
+    }
+
+    #[test]
+    fn test_changes_for_other_tunnel_type() {
+        let wireguard = CurrentTunnel::Connecting(TunnelType::Wireguard);
+        let openvpn = CurrentTunnel::Connecting(TunnelType::OpenVpn);
+
+        assert!(plan(&["bridge_state"], wireguard).is_empty());
+        assert!(plan(&["bridge_state"], openvpn).contains(ApplyOperation::Reconnect));
+
+        let obfuscation = ["obfuscation_settings.selected_obfuscation"];
+        assert!(plan(&obfuscation, openvpn).is_empty());
+        assert!(plan(&obfuscation, wireguard).contains(ApplyOperation::Reconnect));
+        assert!(plan(&obfuscation, CurrentTunnel::None).contains(ApplyOperation::Reconnect));
+    }
+
+    #[test]
+    fn test_traffic_shaping_is_updated_in_place() {
+        let traffic_shaping = ["tunnel_options.wireguard.traffic_shaping.padding"];
+
+        let connected = plan(
+            &traffic_shaping,
+            CurrentTunnel::Connected(TunnelType::Wireguard),
+        );
+        assert!(connected.contains(ApplyOperation::PeerUpdate));
+        assert!(!connected.contains(ApplyOperation::Reconnect));
+
+        let connecting = plan(
+            &traffic_shaping,
+            CurrentTunnel::Connecting(TunnelType::Wireguard),
+        );
+        assert!(connecting.contains(ApplyOperation::Reconnect));
+        assert!(!connecting.contains(ApplyOperation::PeerUpdate));
+
+        assert!(plan(
+            &traffic_shaping,
+            CurrentTunnel::Connected(TunnelType::OpenVpn)
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_mtu_only_reconnects_connected_wireguard_tunnels() {
+        let mtu = ["tunnel_options.wireguard.mtu"];
+        assert!(plan(&mtu, CurrentTunnel::Connected(TunnelType::Wireguard))
+            .contains(ApplyOperation::Reconnect));
+        assert!(plan(&mtu, CurrentTunnel::Connecting(TunnelType::Wireguard)).is_empty());
+        assert!(plan(&mtu, CurrentTunnel::Connected(TunnelType::OpenVpn)).is_empty());
+    }
+
+    #[test]
+    fn test_unrelated_changes() {
+        let plan = plan(
+            &[
+                "auto_connect",
+                "show_beta_releases",
+                "tunnel_options.wireguard.rotation_interval",
+            ],
+            CurrentTunnel::Connected(TunnelType::Wireguard),
+        );
+        assert!(plan.is_empty());
+        assert_eq!(plan.to_string(), "no tunnel changes");
+    }
+}
@@ -0,0 +1,266 @@
+//! Excludes sockets of cooperating local processes from the tunnel. See
+//! [`mullvad_management_interface::socket_bypass`] for the protocol.
+//!
+//! Only processes running as root, or as a member of the group named by
+//! `MULLVAD_SOCKET_BYPASS_GROUP`, are allowed to exclude sockets.
+
+use mullvad_management_interface::socket_bypass::{self, Response};
+use nix::{
+    sys::socket::{getsockopt, sockopt::PeerCredentials, UnixCredentials},
+    unistd::{Gid, Group, Uid, User},
+};
+use std::{
+    env, fs, io,
+    os::unix::{fs::PermissionsExt, io::AsRawFd, net::UnixStream},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+use talpid_core::split_tunnel;
+use talpid_types::ErrorExt;
+use tokio::{net::UnixListener, sync::Semaphore};
+
+/// Environment variable that names the group whose members may exclude sockets.
+const SOCKET_BYPASS_GROUP_ENV_VAR: &str = "MULLVAD_SOCKET_BYPASS_GROUP";
+/// How long to wait for a client to send its socket.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Maximum number of clients that are handled at the same time. Further clients are not accepted
+/// until one of them is done, so that slow clients cannot exhaust the blocking thread pool.
+const MAX_CONCURRENT_CLIENTS: usize = 8;
+
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    /// Failed to remove the socket of a previous daemon instance
+    #[error(display = "Failed to remove old socket bypass socket")]
+    RemoveOldSocket(#[error(source)] io::Error),
+
+    /// Failed to create the listening socket
+    #[error(display = "Failed to bind socket bypass socket")]
+    Bind(#[error(source)] io::Error),
+
+    /// Failed to make the socket accessible to unprivileged processes
+    #[error(display = "Unable to set permissions for socket bypass socket")]
+    Permissions(#[error(source)] io::Error),
+
+    /// Failed to obtain the credentials of the client
+    #[error(display = "Failed to obtain peer credentials")]
+    PeerCredentials(#[error(source)] nix::Error),
+
+    /// Failed to receive the socket of the client
+    #[error(display = "Failed to receive socket")]
+    Receive(#[error(source)] io::Error),
+
+    /// Failed to send the response to the client
+    #[error(display = "Failed to send response")]
+    Respond(#[error(source)] io::Error),
+}
+
+/// Starts accepting sockets to exclude from the tunnel. Returns the path of the listening socket.
+pub fn spawn_server() -> Result<PathBuf, Error> {
+    let path = mullvad_paths::get_socket_bypass_path();
+    if let Err(error) = fs::remove_file(&path) {
+        if error.kind() != io::ErrorKind::NotFound {
+            return Err(Error::RemoveOldSocket(error));
+        }
+    }
+
+    let listener = UnixListener::bind(&path).map_err(Error::Bind)?;
+    // Clients are authorized by their credentials rather than by file permissions
+    fs::set_permissions(&path, PermissionsExt::from_mode(0o766)).map_err(Error::Permissions)?;
+
+    let client_slots = Arc::new(Semaphore::new(MAX_CONCURRENT_CLIENTS));
+    tokio::spawn(async move {
+        loop {
+            // The semaphore is never closed
+            let client_slot = match client_slots.clone().acquire_owned().await {
+                Ok(permit) => permit,
+                Err(_) => break,
+            };
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(error) => {
+                    log::error!(
+                        "{}",
+                        error.display_chain_with_msg("Failed to accept socket bypass client")
+                    );
+                    continue;
+                }
+            };
+            let stream = match stream.into_std().and_then(|stream| {
+                stream.set_nonblocking(false)?;
+                stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+                Ok(stream)
+            }) {
+                Ok(stream) => stream,
+                Err(error) => {
+                    log::error!(
+                        "{}",
+                        error.display_chain_with_msg("Failed to set up socket bypass client")
+                    );
+                    continue;
+                }
+            };
+            tokio::task::spawn_blocking(move || {
+                if let Err(error) = handle_client(&stream) {
+                    log::error!(
+                        "{}",
+                        error.display_chain_with_msg("Failed to handle socket bypass request")
+                    );
+                }
+                drop(client_slot);
+            });
+        }
+    });
+
+    Ok(path)
+}
+
+fn handle_client(stream: &UnixStream) -> Result<(), Error> {
+    let credentials =
+        getsockopt(stream.as_raw_fd(), PeerCredentials).map_err(Error::PeerCredentials)?;
+
+    let fd = match socket_bypass::receive_socket(stream).map_err(Error::Receive)? {
+        Some(fd) => fd,
+        None => {
+            log::warn!(
+                "Socket bypass client {} did not send a socket",
+                credentials.pid()
+            );
+            return socket_bypass::send_response(stream, Response::Failed).map_err(Error::Respond);
+        }
+    };
+
+    let response = if !is_authorized(&credentials) {
+        log::warn!(
+            "Denied process {} (uid {}) to exclude a socket from the tunnel",
+            credentials.pid(),
+            credentials.uid()
+        );
+        Response::PermissionDenied
+    } else {
+        match split_tunnel::exclude_socket(fd) {
+            Ok(()) => {
+                log::debug!(
+                    "Excluded socket of process {} from the tunnel",
+                    credentials.pid()
+                );
+                Response::Excluded
+            }
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to exclude socket from the tunnel")
+                );
+                Response::Failed
+            }
+        }
+    };
+    let _ = nix::unistd::close(fd);
+
+    socket_bypass::send_response(stream, response).map_err(Error::Respond)
+}
+
+fn is_authorized(credentials: &UnixCredentials) -> bool {
+    let uid = Uid::from_raw(credentials.uid());
+    let gid = Gid::from_raw(credentials.gid());
+    let group = if uid.is_root() { None } else { bypass_group() };
+    is_authorized_peer(uid, gid, group.as_ref(), |uid| {
+        User::from_uid(uid).ok().flatten().map(|user| user.name)
+    })
+}
+
+/// Returns the group named by `MULLVAD_SOCKET_BYPASS_GROUP`, if it is set and exists.
+fn bypass_group() -> Option<Group> {
+    let group_name = env::var(SOCKET_BYPASS_GROUP_ENV_VAR).ok()?;
+    match Group::from_name(&group_name) {
+        Ok(Some(group)) => Some(group),
+        Ok(None) => {
+            log::error!("Socket bypass group \"{}\" does not exist", group_name);
+            None
+        }
+        Err(error) => {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to look up socket bypass group")
+            );
+            None
+        }
+    }
+}
+
+/// Returns whether a process running as `uid` and `gid` may exclude sockets. `user_name` looks up
+/// the name of a user, which is needed to check whether it is a supplementary member of `group`.
+fn is_authorized_peer(
+    uid: Uid,
+    gid: Gid,
+    group: Option<&Group>,
+    user_name: impl FnOnce(Uid) -> Option<String>,
+) -> bool {
+    if uid.is_root() {
+        return true;
+    }
+    let group = match group {
+        Some(group) => group,
+        None => return false,
+    };
+    if gid == group.gid {
+        return true;
+    }
+    match user_name(uid) {
+        Some(name) => group.mem.contains(&name),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::ffi::CString;
+
+    fn test_group() -> Group {
+        Group {
+            name: "mullvad-bypass".to_owned(),
+            passwd: CString::new("x").unwrap(),
+            gid: Gid::from_raw(900),
+            mem: vec!["alice".to_owned()],
+        }
+    }
+
+    #[test]
+    fn test_is_authorized_peer() {
+        let group = test_group();
+        let user_name = |uid: Uid| match uid.as_raw() {
+            1000 => Some("alice".to_owned()),
+            1001 => Some("bob".to_owned()),
+            _ => None,
+        };
+        let user = Uid::from_raw(1001);
+        let user_gid = Gid::from_raw(1001);
+
+        // Root is always allowed
+        assert!(is_authorized_peer(
+            Uid::from_raw(0),
+            user_gid,
+            None,
+            user_name
+        ));
+
+        // Others must belong to the group, if there is one
+        assert!(!is_authorized_peer(user, user_gid, None, user_name));
+        assert!(!is_authorized_peer(user, user_gid, Some(&group), user_name));
+        assert!(is_authorized_peer(user, group.gid, Some(&group), user_name));
+        assert!(is_authorized_peer(
+            Uid::from_raw(1000),
+            Gid::from_raw(1000),
+            Some(&group),
+            user_name
+        ));
+        assert!(!is_authorized_peer(
+            Uid::from_raw(1002),
+            Gid::from_raw(1002),
+            Some(&group),
+            user_name
+        ));
+    }
+}
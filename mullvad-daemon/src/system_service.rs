@@ -1,12 +1,15 @@
 use crate::cli;
 use libc::c_void;
-use mullvad_daemon::{runtime::new_runtime_builder, DaemonShutdownHandle};
+use mullvad_daemon::{
+    runtime::new_runtime_builder,
+    service_control::{ServiceStopHandle, StatusReporter, StopReason},
+};
 use std::{
     env,
     ffi::OsString,
     mem, ptr, slice,
     sync::{
-        atomic::{AtomicBool, AtomicUsize, Ordering},
+        atomic::{AtomicUsize, Ordering},
         mpsc, Arc,
     },
     thread,
@@ -94,8 +97,6 @@ pub fn handle_service_main(_arguments: Vec<OsString>) {
         .set_pending_start(Duration::from_secs(1))
         .unwrap();
 
-    let should_restart = Arc::new(AtomicBool::new(true));
-
     let log_dir = crate::get_log_dir(cli::get_config()).expect("Log dir should be available here");
 
     let runtime = new_runtime_builder().build();
@@ -111,31 +112,30 @@ pub fn handle_service_main(_arguments: Vec<OsString>) {
     };
 
     let result = runtime.block_on(crate::create_daemon(log_dir));
-    let result = if let Ok(daemon) = result {
-        let shutdown_handle = daemon.shutdown_handle();
+    let (result, should_restart) = if let Ok(daemon) = result {
+        let stop_handle =
+            ServiceStopHandle::new(persistent_service_status.clone(), daemon.shutdown_handle());
 
         // Register monitor that translates `ServiceControl` to Daemon events
-        start_event_monitor(
-            persistent_service_status.clone(),
-            shutdown_handle,
-            event_rx,
-            should_restart.clone(),
-        );
+        start_event_monitor(stop_handle.clone(), event_rx);
 
         persistent_service_status.set_running().unwrap();
 
-        runtime
+        // Stop pending updates are sent until the daemon, including the tunnel state machine, has
+        // exited
+        let result = runtime
             .block_on(daemon.run())
-            .map_err(|e| e.display_chain())
+            .map_err(|e| e.display_chain());
+        (result, stop_handle.set_stopped())
     } else {
-        result.map(|_| ())
+        (result.map(|_| ()), true)
     };
 
     let exit_code = match result {
         Ok(()) => {
             log::info!("Stopping service");
             // check if shutdown signal was sent from the system
-            if !should_restart.load(Ordering::Acquire) {
+            if !should_restart {
                 ServiceExitCode::default()
             } else {
                 // otherwise return a non-zero code so that the daemon gets restarted
@@ -154,25 +154,17 @@ pub fn handle_service_main(_arguments: Vec<OsString>) {
 /// Start event monitor thread that polls for `ServiceControl` and translates them into calls to
 /// Daemon.
 fn start_event_monitor(
-    persistent_service_status: PersistentServiceStatus,
-    shutdown_handle: DaemonShutdownHandle,
+    stop_handle: ServiceStopHandle<PersistentServiceStatus>,
     event_rx: mpsc::Receiver<ServiceControl>,
-    should_restart: Arc<AtomicBool>,
 ) -> thread::JoinHandle<()> {
     thread::spawn(move || {
-        let mut shutdown_handle = ServiceShutdownHandle {
-            persistent_service_status,
-            shutdown_handle,
-            should_restart,
-        };
-        let mut hibernation_detector = HibernationDetector::new(shutdown_handle.clone());
+        let mut hibernation_detector = HibernationDetector::new(stop_handle.clone());
         for event in event_rx {
             match event {
-                ServiceControl::Stop | ServiceControl::Preshutdown => {
-                    // If the daemon is closing due to the system shutting down,
-                    // keep blocking traffic after the daemon exits.
-                    shutdown_handle.shutdown(false, event == ServiceControl::Preshutdown);
-                }
+                ServiceControl::Stop => stop_handle.stop(StopReason::Stop),
+                // If the daemon is closing due to the system shutting down,
+                // keep blocking traffic after the daemon exits.
+                ServiceControl::Preshutdown => stop_handle.stop(StopReason::Preshutdown),
                 ServiceControl::PowerEvent(details) => match details {
                     PowerEventParam::Suspend => {
                         hibernation_detector.register_suspend();
@@ -193,26 +185,6 @@ fn start_event_monitor(
     })
 }
 
-#[derive(Clone)]
-struct ServiceShutdownHandle {
-    persistent_service_status: PersistentServiceStatus,
-    shutdown_handle: DaemonShutdownHandle,
-    /// If true, the service will be restarted by the SCM when
-    /// the daemon has exited.
-    should_restart: Arc<AtomicBool>,
-}
-
-impl ServiceShutdownHandle {
-    fn shutdown(&mut self, should_restart: bool, is_system_shutdown: bool) {
-        self.persistent_service_status
-            .set_pending_stop(Duration::from_secs(10))
-            .unwrap();
-
-        self.should_restart.store(should_restart, Ordering::Release);
-        self.shutdown_handle.shutdown(!is_system_shutdown);
-    }
-}
-
 /// Service status helper with persistent checkpoint counter.
 #[derive(Debug, Clone)]
 struct PersistentServiceStatus {
@@ -301,6 +273,17 @@ impl PersistentServiceStatus {
     }
 }
 
+impl StatusReporter for PersistentServiceStatus {
+    fn report_stop_pending(&mut self, wait_hint: Duration) {
+        if let Err(error) = self.set_pending_stop(wait_hint) {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to report stop pending status")
+            );
+        }
+    }
+}
+
 /// Returns the list of accepted service events at each stage of the service lifecycle.
 fn accepted_controls_by_state(state: ServiceState) -> ServiceControlAccept {
     let always_accepted = ServiceControlAccept::POWER_EVENT | ServiceControlAccept::SESSION_CHANGE;
@@ -411,17 +394,17 @@ fn get_service_info() -> ServiceInfo {
 struct HibernationDetector {
     logoff_time: Option<Instant>,
     should_restart: bool,
-    shutdown_handle: ServiceShutdownHandle,
+    stop_handle: ServiceStopHandle<PersistentServiceStatus>,
 }
 
 const SECURITY_LOGON_TYPE_INTERACTIVE: u32 = 2;
 
 impl HibernationDetector {
-    fn new(shutdown_handle: ServiceShutdownHandle) -> Self {
+    fn new(stop_handle: ServiceStopHandle<PersistentServiceStatus>) -> Self {
         Self {
             logoff_time: None,
             should_restart: false,
-            shutdown_handle,
+            stop_handle,
         }
     }
 
@@ -486,7 +469,7 @@ impl HibernationDetector {
             log::info!("System is being restored from hibernation. Restarting daemon service");
 
             // Perform a non-clean shutdown. This will cause the daemon to restart itself.
-            self.shutdown_handle.shutdown(true, true);
+            self.stop_handle.stop(StopReason::Restart);
         }
     }
 }
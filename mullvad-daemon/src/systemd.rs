@@ -0,0 +1,153 @@
+#![cfg(target_os = "linux")]
+
+//! Integration with systemd using the `sd_notify` protocol.
+//!
+//! When the daemon is started by systemd as a `Type=notify` service, it reports when it is ready
+//! and what the current tunnel state is. If a watchdog is enabled using `WatchdogSec`, keepalives
+//! are only sent while the daemon and the tunnel state machine respond to health checks, so that
+//! systemd restarts a daemon that has hung.
+
+use crate::{health, DaemonCommand, DaemonEventSender};
+use futures::channel::oneshot;
+use mullvad_types::states::TunnelState;
+use nix::{
+    sys::socket::{self, AddressFamily, MsgFlags, SockAddr, SockFlag, SockType, UnixAddr},
+    unistd,
+};
+use std::{env, ffi::OsStr, io, os::unix::ffi::OsStrExt, time::Duration};
+use talpid_core::mpsc::Sender;
+use talpid_types::{health::HealthStatus, ErrorExt};
+
+const NOTIFY_SOCKET_VAR: &str = "NOTIFY_SOCKET";
+const WATCHDOG_USEC_VAR: &str = "WATCHDOG_USEC";
+const WATCHDOG_PID_VAR: &str = "WATCHDOG_PID";
+
+/// How long to wait for a health check to complete. A keepalive that is not sent in time counts
+/// as a missed keepalive.
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Sends `state` to the service manager. Does nothing if the daemon was not started by systemd.
+fn notify(state: &str) {
+    let socket_path = match env::var_os(NOTIFY_SOCKET_VAR) {
+        Some(path) => path,
+        None => return,
+    };
+    if let Err(error) = send_to_socket(&socket_path, state) {
+        log::error!(
+            "{}",
+            error.display_chain_with_msg("Failed to send notification to systemd")
+        );
+    }
+}
+
+fn send_to_socket(socket_path: &OsStr, state: &str) -> io::Result<()> {
+    let to_io_error = |errno: nix::Error| io::Error::from_raw_os_error(errno as i32);
+
+    // Paths starting with `@` refer to the abstract namespace
+    let path = socket_path.as_bytes();
+    let addr = match path.strip_prefix(b"@") {
+        Some(name) => UnixAddr::new_abstract(name),
+        None => UnixAddr::new(path),
+    }
+    .map_err(to_io_error)?;
+
+    let fd = socket::socket(
+        AddressFamily::Unix,
+        SockType::Datagram,
+        SockFlag::SOCK_CLOEXEC,
+        None,
+    )
+    .map_err(to_io_error)?;
+    let result = socket::sendto(
+        fd,
+        state.as_bytes(),
+        &SockAddr::Unix(addr),
+        MsgFlags::empty(),
+    );
+    let _ = unistd::close(fd);
+    result.map(|_| ()).map_err(to_io_error)
+}
+
+/// Tells systemd that the daemon has finished starting up.
+pub(crate) fn notify_ready() {
+    notify("READY=1");
+}
+
+/// Tells systemd that the daemon is shutting down.
+pub(crate) fn notify_stopping() {
+    notify("STOPPING=1");
+}
+
+/// Sets the status of the unit, as shown by `systemctl status`, to the tunnel state.
+pub(crate) fn notify_tunnel_state(tunnel_state: &TunnelState) {
+    let status = match tunnel_state {
+        TunnelState::Disconnected { .. } => "Disconnected".to_owned(),
+        TunnelState::Connecting { endpoint, .. } => format!("Connecting to {}", endpoint),
+        TunnelState::Connected { endpoint, .. } => format!("Connected to {}", endpoint),
+        TunnelState::Disconnecting(_) => "Disconnecting".to_owned(),
+        TunnelState::Error(error_state) if error_state.is_blocking() => {
+            format!("Blocking: {}", error_state.cause())
+        }
+        TunnelState::Error(error_state) => format!("Failed to block: {}", error_state.cause()),
+        TunnelState::Paused { .. } => "Paused".to_owned(),
+    };
+    notify(&format!("STATUS={}", status.replace('\n', " ")));
+}
+
+/// Returns how often keepalives must be sent, if the watchdog is enabled for this process.
+fn watchdog_timeout() -> Option<Duration> {
+    if let Some(pid) = env::var_os(WATCHDOG_PID_VAR) {
+        if pid.to_string_lossy().parse::<u32>().ok() != Some(std::process::id()) {
+            return None;
+        }
+    }
+    let usec = env::var(WATCHDOG_USEC_VAR).ok()?.parse::<u64>().ok()?;
+    if usec == 0 {
+        return None;
+    }
+    Some(Duration::from_micros(usec))
+}
+
+/// Sends keepalives to the systemd watchdog, if it is enabled, for as long as the daemon responds
+/// to health checks and the tunnel state machine is responsive.
+pub(crate) fn spawn_watchdog(daemon_tx: DaemonEventSender<DaemonCommand>) {
+    let timeout = match watchdog_timeout() {
+        Some(timeout) => timeout,
+        None => return,
+    };
+    // Send keepalives twice as often as required, so that one slow health check does not cause a
+    // restart
+    let interval = timeout / 2;
+    log::debug!(
+        "Sending systemd watchdog keepalives every {} ms",
+        interval.as_millis()
+    );
+
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+
+            let (tx, rx) = oneshot::channel();
+            if daemon_tx.send(DaemonCommand::HealthCheck(tx)).is_err() {
+                return;
+            }
+            match tokio::time::timeout(HEALTH_CHECK_TIMEOUT.min(interval), rx).await {
+                Ok(Ok(report)) => {
+                    let state_machine_responsive = report.checks.iter().any(|check| {
+                        check.name == health::STATE_MACHINE_CHECK
+                            && check.status != HealthStatus::Fail
+                    });
+                    if state_machine_responsive {
+                        notify("WATCHDOG=1");
+                    } else {
+                        log::error!("Tunnel state machine is unresponsive. Not notifying watchdog");
+                    }
+                }
+                Ok(Err(_)) => return,
+                Err(_) => {
+                    log::error!("Daemon did not respond to health check. Not notifying watchdog");
+                }
+            }
+        }
+    });
+}
@@ -45,6 +45,7 @@ pub const CLASSES: &[&str] = &[
     "net/mullvad/mullvadvpn/model/TunnelState$Connecting",
     "net/mullvad/mullvadvpn/model/TunnelState$Disconnected",
     "net/mullvad/mullvadvpn/model/TunnelState$Disconnecting",
+    "net/mullvad/mullvadvpn/model/TunnelState$Paused",
     "net/mullvad/mullvadvpn/model/VoucherSubmission",
     "net/mullvad/mullvadvpn/model/VoucherSubmissionResult",
     "net/mullvad/mullvadvpn/model/LoginResult",
@@ -59,6 +60,7 @@ pub const CLASSES: &[&str] = &[
     "net/mullvad/talpid/tunnel/ActionAfterDisconnect",
     "net/mullvad/talpid/tunnel/ErrorState",
     "net/mullvad/talpid/tunnel/ErrorStateCause$AuthFailed",
+    "net/mullvad/talpid/tunnel/ErrorStateCause$AccountExpired",
     "net/mullvad/talpid/tunnel/ErrorStateCause$Ipv6Unavailable",
     "net/mullvad/talpid/tunnel/ErrorStateCause$SetFirewallPolicyError",
     "net/mullvad/talpid/tunnel/ErrorStateCause$SetDnsError",
@@ -67,7 +69,13 @@ pub const CLASSES: &[&str] = &[
     "net/mullvad/talpid/tunnel/ErrorStateCause$IsOffline",
     "net/mullvad/talpid/tunnel/ErrorStateCause$InvalidDnsServers",
     "net/mullvad/talpid/tunnel/ErrorStateCause$VpnPermissionDenied",
-    "net/mullvad/talpid/tunnel/ParameterGenerationError",
+    "net/mullvad/talpid/tunnel/ErrorStateCause$TunnelMonitorStopped",
+    "net/mullvad/talpid/tunnel/ErrorStateCause$TooManyAttempts",
+    "net/mullvad/talpid/tunnel/ParameterGenerationError$NoMatchingRelay",
+    "net/mullvad/talpid/tunnel/ParameterGenerationError$NoMatchingBridgeRelay",
+    "net/mullvad/talpid/tunnel/ParameterGenerationError$NoWireguardKey",
+    "net/mullvad/talpid/tunnel/ParameterGenerationError$CustomTunnelHostResultionError",
+    "net/mullvad/talpid/tunnel/ParameterGenerationError$InvalidTunnelParameters",
     "net/mullvad/talpid/ConnectivityListener",
     "net/mullvad/talpid/CreateTunResult$Success",
     "net/mullvad/talpid/CreateTunResult$InvalidDnsServers",
@@ -172,6 +172,22 @@ impl DaemonInterface {
         Ok(())
     }
 
+    pub fn pause_tunnel(&self) -> Result<()> {
+        let (tx, _) = oneshot::channel();
+
+        self.send_command(DaemonCommand::PauseTunnel(tx))?;
+
+        Ok(())
+    }
+
+    pub fn resume_tunnel(&self) -> Result<()> {
+        let (tx, _) = oneshot::channel();
+
+        self.send_command(DaemonCommand::ResumeTunnel(tx))?;
+
+        Ok(())
+    }
+
     pub fn clear_account_history(&self) -> Result<()> {
         let (tx, rx) = oneshot::channel();
 
@@ -259,6 +275,7 @@ impl DaemonInterface {
 
         block_on(rx)
             .map_err(|_| Error::NoResponse)?
+            .map(|_apply_plan| ())
             .map_err(|_| Error::SettingsError)
     }
 
@@ -279,6 +296,7 @@ impl DaemonInterface {
 
         block_on(rx)
             .map_err(|_| Error::NoResponse)?
+            .map(|_apply_plan| ())
             .map_err(|_| Error::SettingsError)
     }
 
@@ -289,6 +307,7 @@ impl DaemonInterface {
 
         block_on(rx)
             .map_err(|_| Error::NoResponse)?
+            .map(|_apply_plan| ())
             .map_err(|_| Error::SettingsError)
     }
 
@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc};
 use jnix::{
     jni::{
         objects::{GlobalRef, JMethodID, JObject, JValue},
@@ -5,8 +6,9 @@ use jnix::{
     },
     IntoJava, JnixEnv,
 };
-use mullvad_daemon::EventListener;
+use mullvad_daemon::{settings_plan::ApplyPlan, EventListener};
 use mullvad_types::{
+    clock_skew::ClockSkew,
     device::{DeviceEvent, RemoveDeviceEvent},
     relay_list::RelayList,
     settings::Settings,
@@ -14,7 +16,9 @@ use mullvad_types::{
     version::AppVersionInfo,
 };
 use std::{sync::mpsc, thread};
-use talpid_types::ErrorExt;
+use talpid_types::{
+    performance::PerformanceWarning, tunnel::VersionedTunnelStateTransition, ErrorExt,
+};
 
 #[derive(Debug, err_derive::Error)]
 #[error(no_from)]
@@ -52,7 +56,16 @@ impl EventListener for JniEventListener {
         let _ = self.0.send(Event::Tunnel(state));
     }
 
-    fn notify_settings(&self, settings: Settings) {
+    fn notify_tunnel_state_transition(&self, _transition: &VersionedTunnelStateTransition) {
+        // The app only uses the tunnel state
+    }
+
+    fn notify_settings(
+        &self,
+        settings: Settings,
+        _changed_fields: Vec<String>,
+        _apply_plan: &ApplyPlan,
+    ) {
         let _ = self.0.send(Event::Settings(settings));
     }
 
@@ -71,6 +84,18 @@ impl EventListener for JniEventListener {
     fn notify_remove_device_event(&self, event: RemoveDeviceEvent) {
         let _ = self.0.send(Event::RemoveDeviceEvent(event));
     }
+
+    fn notify_account_expiry(&self, _expiry: DateTime<Utc>) {
+        // The app fetches the account expiry on its own
+    }
+
+    fn notify_performance_warning(&self, _warning: PerformanceWarning) {
+        // Performance warnings are only logged on Android
+    }
+
+    fn notify_clock_skew(&self, _skew: Option<ClockSkew>) {
+        // Clock problems are only logged on Android
+    }
 }
 
 struct JniEventHandler<'env> {
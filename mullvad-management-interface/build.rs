@@ -1,5 +1,16 @@
 fn main() {
+    const PROTO_DIR: &str = "proto";
     const PROTO_FILE: &str = "proto/management_interface.proto";
-    tonic_build::compile_protos(PROTO_FILE).unwrap();
+    const QA_TOOLS_PROTO_FILE: &str = "proto/qa_tools.proto";
+
+    let mut protos = vec![PROTO_FILE];
+    if std::env::var_os("CARGO_FEATURE_QA_TOOLS").is_some() {
+        protos.push(QA_TOOLS_PROTO_FILE);
+    }
+    tonic_build::configure()
+        .compile(&protos, &[PROTO_DIR])
+        .unwrap();
+
     println!("cargo:rerun-if-changed={}", PROTO_FILE);
+    println!("cargo:rerun-if-changed={}", QA_TOOLS_PROTO_FILE);
 }
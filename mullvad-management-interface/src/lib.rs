@@ -1,24 +1,75 @@
 pub mod types;
 
+#[cfg(target_os = "linux")]
+pub mod socket_bypass;
+
 use parity_tokio_ipc::Endpoint as IpcEndpoint;
 #[cfg(unix)]
-use std::{env, fs, os::unix::fs::PermissionsExt};
+use std::{env, fs, os::unix::fs::PermissionsExt, path::Path};
 use std::{
     future::Future,
     io,
     pin::Pin,
     task::{Context, Poll},
+    time::Duration,
 };
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
-use tonic::transport::{server::Connected, Endpoint, Server, Uri};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use tonic::{
+    codegen::{http, Bytes, InterceptedService, StdError},
+    metadata::MetadataValue,
+    transport::{server::Connected, Body, Endpoint, Server, Uri},
+};
 use tower::service_fn;
 
 pub use tonic::{async_trait, transport::Channel, Code, Request, Response, Status};
 
-pub type ManagementServiceClient =
-    types::management_service_client::ManagementServiceClient<Channel>;
+pub type ManagementServiceClient = types::management_service_client::ManagementServiceClient<
+    InterceptedService<Channel, ApiVersionInterceptor>,
+>;
 pub use types::management_service_server::{ManagementService, ManagementServiceServer};
 
+#[cfg(feature = "qa-tools")]
+pub use types::qa_service_server::{QaService, QaServiceServer};
+
+/// Version of the management interface. It is increased whenever RPCs, fields or enum values are
+/// added, so that clients can tell which of them the daemon knows about.
+pub const API_VERSION: u32 = 1;
+/// Oldest client API version that the daemon works with. It is increased when a change breaks
+/// older clients, e.g. when an RPC is removed.
+pub const MIN_SUPPORTED_API_VERSION: u32 = 1;
+/// Request metadata in which clients send the API version that they implement. Clients that do
+/// not send it are assumed to be compatible.
+pub const API_VERSION_METADATA_KEY: &str = "mullvad-api-version";
+
+/// Names of the optional features that the daemon reports in [`types::Capabilities`].
+pub mod capabilities {
+    /// Excluding applications from the tunnel.
+    pub const SPLIT_TUNNEL: &str = "split-tunnel";
+    /// Tunneling WireGuard traffic over TCP using udp2tcp.
+    pub const OBFUSCATION_UDP2TCP: &str = "obfuscation-udp2tcp";
+    /// Connecting to OpenVPN relays.
+    pub const OPENVPN: &str = "openvpn";
+    /// Connecting to OpenVPN relays through bridges.
+    pub const BRIDGES: &str = "bridges";
+    /// Capturing packets on the tunnel interface.
+    pub const PACKET_CAPTURE: &str = "packet-capture";
+    /// Injecting simulated events into the tunnel state machine.
+    pub const SIMULATION: &str = "simulation";
+}
+
+type ApiVersionInterceptor = fn(Request<()>) -> Result<Request<()>, Status>;
+
+/// How long to wait for the daemon to accept a connection before giving up.
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Maximum number of concurrent requests and streams that a single client may have open.
+const MAX_CONCURRENT_STREAMS: u32 = 64;
+
+/// Default maximum size of a request sent to the management interface, in bytes.
+const DEFAULT_MAX_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+
 #[cfg(unix)]
 lazy_static::lazy_static! {
     static ref MULLVAD_MANAGEMENT_SOCKET_GROUP: Option<String> = env::var("MULLVAD_MANAGEMENT_SOCKET_GROUP")
@@ -31,6 +82,9 @@ pub enum Error {
     #[error(display = "Management RPC server or client error")]
     GrpcTransportError(#[error(source)] tonic::transport::Error),
 
+    #[error(display = "Timed out while connecting to the management interface")]
+    ConnectTimeout,
+
     #[error(display = "Failed to start IPC pipe/socket")]
     StartServerError(#[error(source)] io::Error),
 
@@ -53,39 +107,162 @@ pub enum Error {
     SetGidError(#[error(source)] nix::Error),
 }
 
+/// Connects to the management interface, failing with [`Error::ConnectTimeout`] if the daemon
+/// does not accept the connection within [`CONNECT_TIMEOUT`].
 pub async fn new_rpc_client() -> Result<ManagementServiceClient, Error> {
+    new_rpc_client_with_timeout(CONNECT_TIMEOUT).await
+}
+
+/// Same as [`new_rpc_client`], but with a custom connect timeout.
+pub async fn new_rpc_client_with_timeout(
+    connect_timeout: Duration,
+) -> Result<ManagementServiceClient, Error> {
     let ipc_path = mullvad_paths::get_rpc_socket_path();
 
     // The URI will be ignored
-    let channel = Endpoint::from_static("lttp://[::]:50051")
-        .connect_with_connector(service_fn(move |_: Uri| {
-            IpcEndpoint::connect(ipc_path.clone())
-        }))
+    let connect = Endpoint::from_static("lttp://[::]:50051").connect_with_connector(service_fn(
+        move |_: Uri| IpcEndpoint::connect(ipc_path.clone()),
+    ));
+    let channel = tokio::time::timeout(connect_timeout, connect)
         .await
+        .map_err(|_| Error::ConnectTimeout)?
         .map_err(Error::GrpcTransportError)?;
 
-    Ok(ManagementServiceClient::new(channel))
+    Ok(
+        types::management_service_client::ManagementServiceClient::with_interceptor(
+            channel,
+            add_api_version as ApiVersionInterceptor,
+        ),
+    )
+}
+
+fn add_api_version(mut request: Request<()>) -> Result<Request<()>, Status> {
+    request
+        .metadata_mut()
+        .insert(API_VERSION_METADATA_KEY, MetadataValue::from(API_VERSION));
+    Ok(request)
+}
+
+/// Rejects requests from clients that are too old to work with this version of the daemon.
+fn check_api_version(request: Request<()>) -> Result<Request<()>, Status> {
+    let version = match request.metadata().get(API_VERSION_METADATA_KEY) {
+        Some(version) => version,
+        None => return Ok(request),
+    };
+    let version: u32 = version
+        .to_str()
+        .ok()
+        .and_then(|version| version.parse().ok())
+        .ok_or_else(|| Status::invalid_argument("invalid management interface API version"))?;
+    if version < MIN_SUPPORTED_API_VERSION {
+        return Err(Status::failed_precondition(format!(
+            "management interface API version {} is no longer supported, the oldest supported \
+             version is {}. Update the client",
+            version, MIN_SUPPORTED_API_VERSION
+        )));
+    }
+    Ok(request)
+}
+
+/// Returns the capabilities of the daemon. Daemons that predate the `GetCapabilities` RPC are
+/// reported as API version 0 without any optional features.
+pub async fn get_capabilities(
+    rpc: &mut ManagementServiceClient,
+) -> Result<types::Capabilities, Status> {
+    match rpc.get_capabilities(()).await {
+        Ok(capabilities) => Ok(capabilities.into_inner()),
+        Err(status) if status.code() == Code::Unimplemented => Ok(types::Capabilities::default()),
+        Err(status) => Err(status),
+    }
+}
+
+/// Waits at most `timeout` for `call` to complete. If it does not, the call is cancelled and
+/// a [`Code::DeadlineExceeded`] status is returned.
+///
+/// Calls can also be cancelled at any point by dropping the returned future.
+pub async fn call_with_timeout<T>(
+    timeout: Duration,
+    call: impl Future<Output = Result<Response<T>, Status>>,
+) -> Result<Response<T>, Status> {
+    tokio::time::timeout(timeout, call)
+        .await
+        .unwrap_or_else(|_| {
+            Err(Status::deadline_exceeded(
+                "the daemon did not respond in time",
+            ))
+        })
 }
 
 pub type ServerJoinHandle = tokio::task::JoinHandle<Result<(), Error>>;
 
+/// Credentials of a process that is connected to the management interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerCredentials {
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl PeerCredentials {
+    /// Returns the credentials of the client that sent `request`. They are only known on Unix.
+    pub fn from_request<T>(request: &Request<T>) -> Option<Self> {
+        request
+            .extensions()
+            .get::<Option<PeerCredentials>>()
+            .copied()
+            .flatten()
+    }
+}
+
+/// Returns whether the client that sent `request` may change how the daemon itself operates,
+/// rather than only the settings of the VPN.
+pub fn is_privileged<T>(request: &Request<T>) -> bool {
+    #[cfg(unix)]
+    {
+        is_privileged_peer(
+            PeerCredentials::from_request(request),
+            MULLVAD_MANAGEMENT_SOCKET_GROUP.is_some(),
+        )
+    }
+    #[cfg(windows)]
+    {
+        let _ = request;
+        false
+    }
+}
+
+/// Root is always privileged. If the socket is restricted to a management group, every client is
+/// privileged, since only root and members of that group can connect.
+#[cfg(unix)]
+fn is_privileged_peer(credentials: Option<PeerCredentials>, restricted_to_group: bool) -> bool {
+    restricted_to_group || credentials.map(|peer| peer.uid == 0).unwrap_or(false)
+}
+
 pub async fn spawn_rpc_server<T: ManagementService, F: Future<Output = ()> + Send + 'static>(
     service: T,
+    #[cfg(feature = "qa-tools")] qa_service: impl QaService,
     abort_rx: F,
 ) -> std::result::Result<ServerJoinHandle, Error> {
-    use futures::stream::TryStreamExt;
-    use parity_tokio_ipc::SecurityAttributes;
-
     let socket_path = mullvad_paths::get_rpc_socket_path();
 
-    let mut endpoint = IpcEndpoint::new(socket_path.to_string_lossy().to_string());
-    endpoint.set_security_attributes(
-        SecurityAttributes::allow_everyone_create()
-            .map_err(Error::SecurityAttributes)?
-            .set_mode(0o766)
-            .map_err(Error::SecurityAttributes)?,
-    );
-    let incoming = endpoint.incoming().map_err(Error::StartServerError)?;
+    #[cfg(unix)]
+    let incoming = bind_unix_socket(&socket_path)?;
+    #[cfg(windows)]
+    let incoming = {
+        use futures::stream::TryStreamExt;
+        use parity_tokio_ipc::SecurityAttributes;
+
+        let mut endpoint = IpcEndpoint::new(socket_path.to_string_lossy().to_string());
+        endpoint.set_security_attributes(
+            SecurityAttributes::allow_everyone_create()
+                .map_err(Error::SecurityAttributes)?
+                .set_mode(0o766)
+                .map_err(Error::SecurityAttributes)?,
+        );
+        endpoint
+            .incoming()
+            .map_err(Error::StartServerError)?
+            .map_ok(StreamBox)
+    };
 
     #[cfg(unix)]
     if let Some(group_name) = &*MULLVAD_MANAGEMENT_SOCKET_GROUP {
@@ -97,19 +274,126 @@ pub async fn spawn_rpc_server<T: ManagementService, F: Future<Output = ()> + Sen
             .map_err(Error::PermissionsError)?;
     }
 
+    let max_message_size = max_message_size();
+    let router = Server::builder()
+        .layer(tower::layer::layer_fn(move |inner| MaxMessageSize {
+            inner,
+            max_size: max_message_size,
+        }))
+        .max_concurrent_streams(Some(MAX_CONCURRENT_STREAMS))
+        .concurrency_limit_per_connection(MAX_CONCURRENT_STREAMS as usize)
+        .add_service(ManagementServiceServer::with_interceptor(
+            service,
+            check_api_version,
+        ));
+    #[cfg(feature = "qa-tools")]
+    let router = router.add_service(QaServiceServer::with_interceptor(
+        qa_service,
+        check_api_version,
+    ));
+
     Ok(tokio::spawn(async move {
-        Server::builder()
-            .add_service(ManagementServiceServer::new(service))
-            .serve_with_incoming_shutdown(incoming.map_ok(StreamBox), abort_rx)
+        router
+            .serve_with_incoming_shutdown(incoming, abort_rx)
             .await
             .map_err(Error::GrpcTransportError)
     }))
 }
 
+/// Listens on a Unix socket that every user may connect to. Unlike the IPC endpoint used on
+/// Windows, this lets connections report the credentials of the peer.
+#[cfg(unix)]
+fn bind_unix_socket(
+    socket_path: &Path,
+) -> Result<impl futures::Stream<Item = io::Result<StreamBox<UnixStream>>>, Error> {
+    let listener = UnixListener::bind(socket_path).map_err(Error::StartServerError)?;
+    fs::set_permissions(socket_path, PermissionsExt::from_mode(0o766))
+        .map_err(Error::PermissionsError)?;
+    Ok(Box::pin(futures::stream::unfold(
+        listener,
+        |listener| async move {
+            let connection = listener.accept().await.map(|(stream, _)| StreamBox(stream));
+            Some((connection, listener))
+        },
+    )))
+}
+
+/// Returns the maximum size of a request, which can be overridden using the
+/// `MULLVAD_MANAGEMENT_MAX_MESSAGE_SIZE` environment variable.
+fn max_message_size() -> usize {
+    match std::env::var("MULLVAD_MANAGEMENT_MAX_MESSAGE_SIZE") {
+        Ok(size) => size.parse().unwrap_or_else(|_| {
+            log::error!(
+                "Invalid MULLVAD_MANAGEMENT_MAX_MESSAGE_SIZE: {}. Using the default limit",
+                size
+            );
+            DEFAULT_MAX_MESSAGE_SIZE
+        }),
+        Err(_) => DEFAULT_MAX_MESSAGE_SIZE,
+    }
+}
+
+/// Fails requests whose body exceeds `max_size` bytes with `RESOURCE_EXHAUSTED`. No RPC streams
+/// requests, so the body of a request contains a single message.
+#[derive(Clone)]
+struct MaxMessageSize<S> {
+    inner: S,
+    max_size: usize,
+}
+
+impl<S> tower::Service<http::Request<Body>> for MaxMessageSize<S>
+where
+    S: tower::Service<http::Request<Body>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<Body>) -> Self::Future {
+        let max_size = self.max_size;
+        let request = request.map(|body| Body::wrap_stream(limit_body(body, max_size)));
+        self.inner.call(request)
+    }
+}
+
+fn limit_body(body: Body, max_size: usize) -> impl futures::Stream<Item = Result<Bytes, StdError>> {
+    use futures::StreamExt;
+
+    let mut received = 0usize;
+    body.map(move |chunk| {
+        let chunk = chunk?;
+        received = received.saturating_add(chunk.len());
+        if received > max_size {
+            return Err(Status::resource_exhausted(format!(
+                "request exceeds the maximum message size of {} bytes",
+                max_size
+            ))
+            .into());
+        }
+        Ok(chunk)
+    })
+}
+
 #[derive(Debug)]
 struct StreamBox<T: AsyncRead + AsyncWrite>(pub T);
+#[cfg(unix)]
+impl Connected for StreamBox<UnixStream> {
+    type ConnectInfo = Option<PeerCredentials>;
+
+    fn connect_info(&self) -> Self::ConnectInfo {
+        self.0.peer_cred().ok().map(|credentials| PeerCredentials {
+            uid: credentials.uid(),
+            gid: credentials.gid(),
+        })
+    }
+}
+#[cfg(windows)]
 impl<T: AsyncRead + AsyncWrite> Connected for StreamBox<T> {
-    type ConnectInfo = Option<()>;
+    type ConnectInfo = Option<PeerCredentials>;
 
     fn connect_info(&self) -> Self::ConnectInfo {
         None
@@ -141,3 +425,37 @@ impl<T: AsyncRead + AsyncWrite + Unpin> AsyncWrite for StreamBox<T> {
         Pin::new(&mut self.0).poll_shutdown(cx)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::{executor::block_on, TryStreamExt};
+
+    #[cfg(unix)]
+    #[test]
+    fn test_is_privileged_peer() {
+        let root = PeerCredentials { uid: 0, gid: 0 };
+        let user = PeerCredentials {
+            uid: 1000,
+            gid: 1000,
+        };
+
+        assert!(is_privileged_peer(Some(root), false));
+        assert!(!is_privileged_peer(Some(user), false));
+        assert!(!is_privileged_peer(None, false));
+
+        assert!(is_privileged_peer(Some(user), true));
+    }
+
+    #[test]
+    fn test_limit_body() {
+        let body = limit_body(Body::from(vec![0u8; 16]), 16);
+        let received: Vec<Bytes> = block_on(body.try_collect()).unwrap();
+        assert_eq!(received.iter().map(Bytes::len).sum::<usize>(), 16);
+
+        let body = limit_body(Body::from(vec![0u8; 17]), 16);
+        let error = block_on(body.try_collect::<Vec<_>>()).unwrap_err();
+        let status = error.downcast_ref::<Status>().expect("expected a status");
+        assert_eq!(status.code(), Code::ResourceExhausted);
+    }
+}
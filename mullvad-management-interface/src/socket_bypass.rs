@@ -0,0 +1,164 @@
+//! Lets local processes exclude sockets from the tunnel on Linux, similar to how sockets are
+//! protected on Android.
+//!
+//! A process connects to the socket at [`mullvad_paths::get_socket_bypass_path`] and sends a
+//! single byte along with the socket to exclude as `SCM_RIGHTS` ancillary data. The daemon checks
+//! that the process is allowed to do so, sets the firewall mark of the socket and replies with a
+//! single [`Response`] byte. The socket must be excluded before it is connected, since the route
+//! of a connected socket may already have been chosen.
+
+use nix::sys::{
+    socket::{recvmsg, sendmsg, ControlMessage, ControlMessageOwned, MsgFlags},
+    uio::IoVec,
+};
+use std::{
+    io::{self, Read, Write},
+    os::unix::{
+        io::{AsRawFd, RawFd},
+        net::UnixStream,
+    },
+    time::Duration,
+};
+
+/// How long to wait for the daemon to respond.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    /// Failed to connect to the daemon
+    #[error(display = "Failed to connect to the socket bypass server")]
+    Connect(#[error(source)] io::Error),
+
+    /// Failed to send the socket to the daemon
+    #[error(display = "Failed to send the socket to the daemon")]
+    Send(#[error(source)] nix::Error),
+
+    /// The daemon did not respond
+    #[error(display = "Failed to receive a response from the daemon")]
+    Receive(#[error(source)] io::Error),
+
+    /// The process is not allowed to exclude sockets from the tunnel
+    #[error(display = "Not allowed to exclude sockets from the tunnel")]
+    PermissionDenied,
+
+    /// The daemon failed to exclude the socket, e.g. because it is not a socket
+    #[error(display = "The daemon failed to exclude the socket from the tunnel")]
+    Failed,
+
+    /// The daemon sent an unknown response
+    #[error(display = "Unknown response from the daemon: {}", _0)]
+    UnknownResponse(u8),
+}
+
+/// Result of a request to exclude a socket, as sent by the daemon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Response {
+    /// The socket was excluded from the tunnel.
+    Excluded = 0,
+    /// The process is not allowed to exclude sockets from the tunnel.
+    PermissionDenied = 1,
+    /// The socket could not be excluded.
+    Failed = 2,
+}
+
+fn response_to_result(response: u8) -> Result<(), Error> {
+    match response {
+        r if r == Response::Excluded as u8 => Ok(()),
+        r if r == Response::PermissionDenied as u8 => Err(Error::PermissionDenied),
+        r if r == Response::Failed as u8 => Err(Error::Failed),
+        r => Err(Error::UnknownResponse(r)),
+    }
+}
+
+/// Asks the daemon to exclude `socket` from the tunnel. Traffic of the socket is then neither
+/// routed through the tunnel nor blocked by the firewall, also in blocking states.
+pub fn bypass_socket(socket: &impl AsRawFd) -> Result<(), Error> {
+    let mut stream =
+        UnixStream::connect(mullvad_paths::get_socket_bypass_path()).map_err(Error::Connect)?;
+    stream
+        .set_read_timeout(Some(RESPONSE_TIMEOUT))
+        .map_err(Error::Connect)?;
+    send_socket(&stream, socket.as_raw_fd())?;
+
+    let mut response = [0u8];
+    stream.read_exact(&mut response).map_err(Error::Receive)?;
+    response_to_result(response[0])
+}
+
+fn send_socket(stream: &UnixStream, fd: RawFd) -> Result<(), Error> {
+    // Ancillary data can only be sent along with regular data
+    let iov = [IoVec::from_slice(&[0u8])];
+    sendmsg(
+        stream.as_raw_fd(),
+        &iov,
+        &[ControlMessage::ScmRights(&[fd])],
+        MsgFlags::empty(),
+        None,
+    )
+    .map_err(Error::Send)?;
+    Ok(())
+}
+
+/// Receives a socket sent by [`bypass_socket`]. Returns `None` if the client did not send exactly
+/// one file descriptor. The caller is responsible for closing the returned file descriptor.
+pub fn receive_socket(stream: &UnixStream) -> io::Result<Option<RawFd>> {
+    let mut buffer = [0u8];
+    let iov = [IoVec::from_mut_slice(&mut buffer)];
+    let mut cmsg_buffer = nix::cmsg_space!([RawFd; 1]);
+    let message = recvmsg(
+        stream.as_raw_fd(),
+        &iov,
+        Some(&mut cmsg_buffer),
+        MsgFlags::MSG_CMSG_CLOEXEC,
+    )
+    .map_err(io::Error::from)?;
+
+    let mut fds = vec![];
+    for cmsg in message.cmsgs() {
+        if let ControlMessageOwned::ScmRights(received) = cmsg {
+            fds.extend(received);
+        }
+    }
+    if fds.len() == 1 {
+        Ok(Some(fds[0]))
+    } else {
+        for fd in fds {
+            let _ = nix::unistd::close(fd);
+        }
+        Ok(None)
+    }
+}
+
+/// Sends the result of a request to the client.
+pub fn send_response(mut stream: &UnixStream, response: Response) -> io::Result<()> {
+    stream.write_all(&[response as u8])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{net::UdpSocket, os::unix::io::FromRawFd};
+
+    #[test]
+    fn test_send_socket() {
+        let (client, server) = UnixStream::pair().unwrap();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        send_socket(&client, socket.as_raw_fd()).unwrap();
+        let fd = receive_socket(&server)
+            .unwrap()
+            .expect("no socket was received");
+        let received = unsafe { UdpSocket::from_raw_fd(fd) };
+        assert_eq!(received.local_addr().unwrap(), socket.local_addr().unwrap());
+
+        send_response(&server, Response::PermissionDenied).unwrap();
+        let mut response = [0u8];
+        (&client).read_exact(&mut response).unwrap();
+        assert!(matches!(
+            response_to_result(response[0]),
+            Err(Error::PermissionDenied)
+        ));
+    }
+}
@@ -2,7 +2,14 @@ pub use prost_types::{Duration, Timestamp};
 
 use mullvad_types::relay_constraints::Constraint;
 use std::convert::TryFrom;
-use talpid_types::{net::wireguard, ErrorExt};
+use talpid_types::{
+    net::{obfuscation::CertificateFingerprint, wireguard},
+    ErrorExt,
+};
+
+/// Maximum number of entries accepted in a list that is stored in the settings. This prevents
+/// clients from making the daemon persist arbitrarily large settings.
+pub const MAX_SETTINGS_LIST_LEN: usize = 256;
 
 #[allow(clippy::derive_partial_eq_without_eq)]
 mod proto {
@@ -32,6 +39,7 @@ impl From<mullvad_types::location::GeoIpLocation> for GeoIpLocation {
 impl From<talpid_types::net::TunnelEndpoint> for TunnelEndpoint {
     fn from(endpoint: talpid_types::net::TunnelEndpoint) -> Self {
         use talpid_types::net;
+        use tunnel_endpoint::WireguardBackend;
 
         TunnelEndpoint {
             address: endpoint.endpoint.address.to_string(),
@@ -59,12 +67,23 @@ impl From<talpid_types::net::TunnelEndpoint> for TunnelEndpoint {
                     )),
                     obfuscation_type: match obfuscation_endpoint.obfuscation_type {
                         net::ObfuscationType::Udp2Tcp => i32::from(ObfuscationType::Udp2tcp),
+                        net::ObfuscationType::Tls => i32::from(ObfuscationType::Tls),
                     },
                 }),
             entry_endpoint: endpoint.entry_endpoint.map(|entry| Endpoint {
                 address: entry.address.to_string(),
                 protocol: i32::from(TransportProtocol::from(entry.protocol)),
             }),
+            wireguard_backend: i32::from(match endpoint.wireguard_backend {
+                Some(net::wireguard::WireguardBackend::Kernel) => WireguardBackend::Kernel,
+                Some(net::wireguard::WireguardBackend::WireguardNt) => {
+                    WireguardBackend::WireguardNt
+                }
+                Some(net::wireguard::WireguardBackend::WireguardGo) => {
+                    WireguardBackend::WireguardGo
+                }
+                None => WireguardBackend::Unknown,
+            }),
         }
     }
 }
@@ -75,7 +94,10 @@ impl From<mullvad_types::states::TunnelState> for TunnelState {
             firewall_policy_error::ErrorType as PolicyErrorType, Cause, FirewallPolicyError,
             GenerationError,
         };
+        #[cfg(not(target_os = "android"))]
+        use error_state::{process_failure::Kind as ProcessFailureKind, ProcessFailure};
         use mullvad_types::states::TunnelState as MullvadTunnelState;
+        use tunnel_state::disconnected::{Cause as DisconnectCause, Security};
 
         use talpid_types::tunnel as talpid_tunnel;
 
@@ -100,9 +122,81 @@ impl From<mullvad_types::states::TunnelState> for TunnelState {
                 }
             };
 
+        let map_error_cause = |cause: &talpid_tunnel::ErrorStateCause| match cause {
+            talpid_tunnel::ErrorStateCause::AuthFailed(_) => i32::from(Cause::AuthFailed),
+            talpid_tunnel::ErrorStateCause::AccountExpired => i32::from(Cause::AccountExpired),
+            talpid_tunnel::ErrorStateCause::Ipv6Unavailable => i32::from(Cause::Ipv6Unavailable),
+            talpid_tunnel::ErrorStateCause::SetFirewallPolicyError(_) => {
+                i32::from(Cause::SetFirewallPolicyError)
+            }
+            talpid_tunnel::ErrorStateCause::SetDnsError => i32::from(Cause::SetDnsError),
+            talpid_tunnel::ErrorStateCause::StartTunnelError => i32::from(Cause::StartTunnelError),
+            talpid_tunnel::ErrorStateCause::TunnelParameterError(_) => {
+                i32::from(Cause::TunnelParameterError)
+            }
+            talpid_tunnel::ErrorStateCause::IsOffline => i32::from(Cause::IsOffline),
+            #[cfg(target_os = "android")]
+            talpid_tunnel::ErrorStateCause::VpnPermissionDenied => {
+                i32::from(Cause::VpnPermissionDenied)
+            }
+            #[cfg(target_os = "windows")]
+            talpid_tunnel::ErrorStateCause::SplitTunnelError => i32::from(Cause::SplitTunnelError),
+            talpid_tunnel::ErrorStateCause::TunnelMonitorStopped => {
+                i32::from(Cause::TunnelMonitorStopped)
+            }
+            #[cfg(not(target_os = "android"))]
+            talpid_tunnel::ErrorStateCause::ForcedInterfaceUnavailable => {
+                i32::from(Cause::ForcedInterfaceUnavailable)
+            }
+            #[cfg(target_os = "linux")]
+            talpid_tunnel::ErrorStateCause::GatewayUnreachable => {
+                i32::from(Cause::GatewayUnreachable)
+            }
+            talpid_tunnel::ErrorStateCause::TooManyAttempts => i32::from(Cause::TooManyAttempts),
+            #[cfg(not(target_os = "android"))]
+            talpid_tunnel::ErrorStateCause::RouteTakenOver => i32::from(Cause::RouteTakenOver),
+            #[cfg(not(target_os = "android"))]
+            talpid_tunnel::ErrorStateCause::TunnelProcessFailed(_) => {
+                i32::from(Cause::TunnelProcessFailed)
+            }
+            #[cfg(any(target_os = "linux", target_os = "macos"))]
+            talpid_tunnel::ErrorStateCause::TunnelDeviceBusy => i32::from(Cause::TunnelDeviceBusy),
+        };
+
         let state = match state {
-            MullvadTunnelState::Disconnected => {
-                tunnel_state::State::Disconnected(tunnel_state::Disconnected {})
+            MullvadTunnelState::Disconnected { cause, security } => {
+                tunnel_state::State::Disconnected(tunnel_state::Disconnected {
+                    cause: i32::from(match cause {
+                        None => DisconnectCause::Unknown,
+                        Some(talpid_tunnel::DisconnectCause::UserInitiated) => {
+                            DisconnectCause::UserInitiated
+                        }
+                        Some(talpid_tunnel::DisconnectCause::Reconnecting) => {
+                            DisconnectCause::Reconnecting
+                        }
+                        Some(talpid_tunnel::DisconnectCause::Error(_)) => DisconnectCause::Error,
+                        Some(talpid_tunnel::DisconnectCause::Revoked) => DisconnectCause::Revoked,
+                        Some(talpid_tunnel::DisconnectCause::Shutdown) => DisconnectCause::Shutdown,
+                    }),
+                    resolved_error: match cause {
+                        Some(talpid_tunnel::DisconnectCause::Error(ref error_cause)) => {
+                            map_error_cause(error_cause)
+                        }
+                        _ => 0,
+                    },
+                    security: i32::from(match security {
+                        talpid_tunnel::DisconnectedSecurity::Unsecured => Security::Unsecured,
+                        talpid_tunnel::DisconnectedSecurity::Blocked(
+                            talpid_tunnel::DisconnectedBlockReason::BlockWhenDisconnected,
+                        ) => Security::BlockWhenDisconnected,
+                        talpid_tunnel::DisconnectedSecurity::Blocked(
+                            talpid_tunnel::DisconnectedBlockReason::PendingConnect,
+                        ) => Security::PendingConnect,
+                        talpid_tunnel::DisconnectedSecurity::Blocked(
+                            talpid_tunnel::DisconnectedBlockReason::ReconnectGracePeriod,
+                        ) => Security::ReconnectGracePeriod,
+                    }),
+                })
             }
             MullvadTunnelState::Connecting { endpoint, location } => {
                 tunnel_state::State::Connecting(tunnel_state::Connecting {
@@ -120,6 +214,14 @@ impl From<mullvad_types::states::TunnelState> for TunnelState {
                     }),
                 })
             }
+            MullvadTunnelState::Paused { endpoint, location } => {
+                tunnel_state::State::Paused(tunnel_state::Paused {
+                    relay_info: Some(TunnelStateRelayInfo {
+                        tunnel_endpoint: Some(TunnelEndpoint::from(endpoint)),
+                        location: location.map(GeoIpLocation::from),
+                    }),
+                })
+            }
             MullvadTunnelState::Disconnecting(after_disconnect) => {
                 tunnel_state::State::Disconnecting(tunnel_state::Disconnecting {
                     after_disconnect: match after_disconnect {
@@ -138,37 +240,7 @@ impl From<mullvad_types::states::TunnelState> for TunnelState {
             MullvadTunnelState::Error(error_state) => {
                 tunnel_state::State::Error(tunnel_state::Error {
                     error_state: Some(ErrorState {
-                        cause: match error_state.cause() {
-                            talpid_tunnel::ErrorStateCause::AuthFailed(_) => {
-                                i32::from(Cause::AuthFailed)
-                            }
-                            talpid_tunnel::ErrorStateCause::Ipv6Unavailable => {
-                                i32::from(Cause::Ipv6Unavailable)
-                            }
-                            talpid_tunnel::ErrorStateCause::SetFirewallPolicyError(_) => {
-                                i32::from(Cause::SetFirewallPolicyError)
-                            }
-                            talpid_tunnel::ErrorStateCause::SetDnsError => {
-                                i32::from(Cause::SetDnsError)
-                            }
-                            talpid_tunnel::ErrorStateCause::StartTunnelError => {
-                                i32::from(Cause::StartTunnelError)
-                            }
-                            talpid_tunnel::ErrorStateCause::TunnelParameterError(_) => {
-                                i32::from(Cause::TunnelParameterError)
-                            }
-                            talpid_tunnel::ErrorStateCause::IsOffline => {
-                                i32::from(Cause::IsOffline)
-                            }
-                            #[cfg(target_os = "android")]
-                            talpid_tunnel::ErrorStateCause::VpnPermissionDenied => {
-                                i32::from(Cause::VpnPermissionDenied)
-                            }
-                            #[cfg(target_os = "windows")]
-                            talpid_tunnel::ErrorStateCause::SplitTunnelError => {
-                                i32::from(Cause::SplitTunnelError)
-                            }
-                        },
+                        cause: map_error_cause(error_state.cause()),
                         blocking_error: error_state.block_failure().map(map_firewall_error),
                         auth_fail_reason: if let talpid_tunnel::ErrorStateCause::AuthFailed(
                             reason,
@@ -195,6 +267,9 @@ impl From<mullvad_types::states::TunnelState> for TunnelState {
                             talpid_tunnel::ParameterGenerationError::CustomTunnelHostResultionError => {
                                 i32::from(GenerationError::CustomTunnelHostResolutionError)
                             }
+                            talpid_tunnel::ParameterGenerationError::InvalidTunnelParameters(_) => {
+                                i32::from(GenerationError::InvalidTunnelParameters)
+                            }
                         }
                             } else {
                                 0
@@ -207,6 +282,46 @@ impl From<mullvad_types::states::TunnelState> for TunnelState {
                             } else {
                                 None
                             },
+                        recovery_allowlist: Some(RecoveryAllowlist::from(
+                            error_state.recovery_allowlist(),
+                        )),
+                        invalid_parameters_reason:
+                            if let talpid_tunnel::ErrorStateCause::TunnelParameterError(
+                                talpid_tunnel::ParameterGenerationError::InvalidTunnelParameters(
+                                    reason,
+                                ),
+                            ) = error_state.cause()
+                            {
+                                reason.to_string()
+                            } else {
+                                "".to_string()
+                            },
+                        #[cfg(not(target_os = "android"))]
+                        process_failure:
+                            if let talpid_tunnel::ErrorStateCause::TunnelProcessFailed(failure) =
+                                error_state.cause()
+                            {
+                                Some(match failure {
+                                    talpid_tunnel::ProcessFailure::Spawn => ProcessFailure {
+                                        kind: i32::from(ProcessFailureKind::Spawn),
+                                        exit_code: 0,
+                                    },
+                                    talpid_tunnel::ProcessFailure::ExitCode(exit_code) => {
+                                        ProcessFailure {
+                                            kind: i32::from(ProcessFailureKind::ExitCode),
+                                            exit_code: *exit_code,
+                                        }
+                                    }
+                                    talpid_tunnel::ProcessFailure::Crash => ProcessFailure {
+                                        kind: i32::from(ProcessFailureKind::Crash),
+                                        exit_code: 0,
+                                    },
+                                })
+                            } else {
+                                None
+                            },
+                        #[cfg(target_os = "android")]
+                        process_failure: None,
                     }),
                 })
             }
@@ -305,6 +420,7 @@ impl From<Vec<mullvad_types::device::Device>> for DeviceList {
     fn from(devices: Vec<mullvad_types::device::Device>) -> Self {
         DeviceList {
             devices: devices.into_iter().map(Device::from).collect(),
+            current_device_id: String::new(),
         }
     }
 }
@@ -342,6 +458,7 @@ impl From<mullvad_types::ConnectionConfig> for ConnectionConfig {
                         protocol: i32::from(TransportProtocol::from(config.endpoint.protocol)),
                         username: config.username,
                         password: config.password,
+                        client_certificate: None,
                     })
                 }
                 mullvad_types::ConnectionConfig::Wireguard(config) => {
@@ -355,22 +472,14 @@ impl From<mullvad_types::ConnectionConfig> for ConnectionConfig {
                                 .map(|address| address.to_string())
                                 .collect(),
                         }),
-                        peer: Some(connection_config::wireguard_config::PeerConfig {
-                            public_key: config.peer.public_key.as_bytes().to_vec(),
-                            allowed_ips: config
-                                .peer
-                                .allowed_ips
-                                .iter()
-                                .map(|address| address.to_string())
-                                .collect(),
-                            endpoint: config.peer.endpoint.to_string(),
-                        }),
+                        peer: Some(peer_config_to_proto(&config.peer)),
                         ipv4_gateway: config.ipv4_gateway.to_string(),
                         ipv6_gateway: config
                             .ipv6_gateway
                             .as_ref()
                             .map(|address| address.to_string())
                             .unwrap_or_default(),
+                        exit_peer: config.exit_peer.as_ref().map(peer_config_to_proto),
                     })
                 }
             }),
@@ -378,6 +487,22 @@ impl From<mullvad_types::ConnectionConfig> for ConnectionConfig {
     }
 }
 
+/// The preshared key is left out, since it must never leave the daemon.
+fn peer_config_to_proto(
+    peer: &wireguard::PeerConfig,
+) -> connection_config::wireguard_config::PeerConfig {
+    connection_config::wireguard_config::PeerConfig {
+        public_key: peer.public_key.as_bytes().to_vec(),
+        allowed_ips: peer
+            .allowed_ips
+            .iter()
+            .map(|address| address.to_string())
+            .collect(),
+        endpoint: peer.endpoint.to_string(),
+        preshared_key: vec![],
+    }
+}
+
 impl From<talpid_types::net::TransportProtocol> for TransportProtocol {
     fn from(protocol: talpid_types::net::TransportProtocol) -> Self {
         match protocol {
@@ -458,22 +583,7 @@ impl From<mullvad_types::relay_constraints::LocationConstraint> for RelayLocatio
 impl From<&mullvad_types::settings::Settings> for Settings {
     fn from(settings: &mullvad_types::settings::Settings) -> Self {
         #[cfg(windows)]
-        let split_tunnel = {
-            let mut converted_list = vec![];
-            for path in settings.split_tunnel.apps.clone().iter() {
-                match path.as_path().as_os_str().to_str() {
-                    Some(path) => converted_list.push(path.to_string()),
-                    None => {
-                        log::error!("failed to convert OS string: {:?}", path);
-                    }
-                }
-            }
-
-            Some(SplitTunnelSettings {
-                enable_exclusions: settings.split_tunnel.enable_exclusions,
-                apps: converted_list,
-            })
-        };
+        let split_tunnel = Some(SplitTunnelSettings::from(&settings.split_tunnel));
         #[cfg(not(windows))]
         let split_tunnel = None;
 
@@ -488,6 +598,56 @@ impl From<&mullvad_types::settings::Settings> for Settings {
             show_beta_releases: settings.show_beta_releases,
             obfuscation_settings: Some(ObfuscationSettings::from(&settings.obfuscation_settings)),
             split_tunnel,
+            flush_dns_cache: settings.flush_dns_cache,
+            recovery_allowlist: Some(RecoveryAllowlist::from(&settings.recovery_allowlist[..])),
+            vpn_coexistence: Some(VpnCoexistence::from(&settings.vpn_coexistence)),
+            blocked_tunnel_protocols: Some(BlockedTunnelProtocols::from(
+                settings.blocked_tunnel_protocols,
+            )),
+            forced_interface: settings.forced_interface.clone().unwrap_or_default(),
+            reconnect_limits: Some(ReconnectLimits::from(settings.reconnect_limits)),
+            route_takeover_policy: Some(RouteTakeoverPolicy::from(settings.route_takeover_policy)),
+            connection_stats: settings.connection_stats,
+            error_state_policy: Some(ErrorStatePolicy::from(settings.error_state_policy)),
+            hooks: Some(Hooks::from(&settings.hooks[..])),
+        }
+    }
+}
+
+#[cfg(windows)]
+impl From<&mullvad_types::settings::SplitTunnelSettings> for SplitTunnelSettings {
+    fn from(settings: &mullvad_types::settings::SplitTunnelSettings) -> Self {
+        let mut converted_list = vec![];
+        for path in settings.apps.iter() {
+            match path.as_path().as_os_str().to_str() {
+                Some(path) => converted_list.push(path.to_string()),
+                None => {
+                    log::error!("failed to convert OS string: {:?}", path);
+                }
+            }
+        }
+
+        SplitTunnelSettings {
+            enable_exclusions: settings.enable_exclusions,
+            apps: converted_list,
+        }
+    }
+}
+
+impl From<&mullvad_types::settings::Profile> for Profile {
+    fn from(profile: &mullvad_types::settings::Profile) -> Self {
+        Self {
+            name: profile.name.clone(),
+            relay_settings: Some(RelaySettings::from(profile.relay_settings.clone())),
+            obfuscation_settings: Some(ObfuscationSettings::from(&profile.obfuscation_settings)),
+            #[cfg(not(target_os = "android"))]
+            dns_options: Some(DnsOptions::from(&profile.dns_options)),
+            #[cfg(target_os = "android")]
+            dns_options: None,
+            #[cfg(windows)]
+            split_tunnel: Some(SplitTunnelSettings::from(&profile.split_tunnel)),
+            #[cfg(not(windows))]
+            split_tunnel: None,
         }
     }
 }
@@ -512,10 +672,12 @@ impl From<&mullvad_types::relay_constraints::ObfuscationSettings> for Obfuscatio
             SelectedObfuscation::Auto => obfuscation_settings::SelectedObfuscation::Auto,
             SelectedObfuscation::Off => obfuscation_settings::SelectedObfuscation::Off,
             SelectedObfuscation::Udp2Tcp => obfuscation_settings::SelectedObfuscation::Udp2tcp,
+            SelectedObfuscation::Tls => obfuscation_settings::SelectedObfuscation::Tls,
         });
         Self {
             selected_obfuscation,
             udp2tcp: Some(Udp2TcpObfuscationSettings::from(&settings.udp2tcp)),
+            tls: Some(TlsObfuscationSettings::from(&settings.tls)),
         }
     }
 }
@@ -530,6 +692,14 @@ impl From<&mullvad_types::relay_constraints::Udp2TcpObfuscationSettings>
     }
 }
 
+impl From<&mullvad_types::relay_constraints::TlsObfuscationSettings> for TlsObfuscationSettings {
+    fn from(settings: &mullvad_types::relay_constraints::TlsObfuscationSettings) -> Self {
+        Self {
+            server_name: settings.server_name.clone().unwrap_or_default(),
+        }
+    }
+}
+
 impl From<mullvad_types::relay_constraints::BridgeSettings> for BridgeSettings {
     fn from(settings: mullvad_types::relay_constraints::BridgeSettings) -> Self {
         use mullvad_types::relay_constraints::BridgeSettings as MullvadBridgeSettings;
@@ -687,9 +857,22 @@ impl From<&mullvad_types::settings::TunnelOptions> for TunnelOptions {
                 #[cfg(not(windows))]
                 use_wireguard_nt: false,
                 use_pq_safe_psk: options.wireguard.options.use_pq_safe_psk,
+                traffic_shaping: Some(TrafficShapingOptions::from(
+                    options.wireguard.options.traffic_shaping,
+                )),
+                bandwidth_limit: Some(BandwidthLimit::from(
+                    options.wireguard.options.bandwidth_limit,
+                )),
+                allowed_ips: options
+                    .wireguard
+                    .options
+                    .allowed_ips
+                    .as_deref()
+                    .map(WireguardAllowedIps::from),
             }),
             generic: Some(tunnel_options::GenericOptions {
                 enable_ipv6: options.generic.enable_ipv6,
+                enable_ipv4: options.generic.enable_ipv4,
             }),
             #[cfg(not(target_os = "android"))]
             dns_options: Some(DnsOptions::from(&options.dns_options)),
@@ -794,7 +977,10 @@ impl From<mullvad_types::relay_list::Relay> for Relay {
 
         Self {
             hostname: relay.hostname,
-            ipv4_addr_in: relay.ipv4_addr_in.to_string(),
+            ipv4_addr_in: relay
+                .ipv4_addr_in
+                .map(|addr| addr.to_string())
+                .unwrap_or_default(),
             ipv6_addr_in: relay
                 .ipv6_addr_in
                 .map(|addr| addr.to_string())
@@ -814,6 +1000,10 @@ impl From<mullvad_types::relay_list::Relay> for Relay {
                     "mullvad_daemon.management_interface/WireguardRelayEndpointData",
                     WireguardRelayEndpointData {
                         public_key: data.public_key.as_bytes().to_vec(),
+                        tls_certificate_sha256: data
+                            .tls_certificate
+                            .map(|fingerprint| fingerprint.as_bytes().to_vec())
+                            .unwrap_or_default(),
                     },
                 )),
                 _ => None,
@@ -830,6 +1020,37 @@ impl From<mullvad_types::relay_list::Relay> for Relay {
     }
 }
 
+impl From<mullvad_types::relay_list::RelayQueryMatch> for RelayQueryMatch {
+    fn from(relay_match: mullvad_types::relay_list::RelayQueryMatch) -> Self {
+        Self {
+            relay: Some(Relay::from(relay_match.relay)),
+            latency_ms: relay_match
+                .latency
+                .map(|latency| u32::try_from(latency.as_millis()).unwrap_or(u32::MAX)),
+        }
+    }
+}
+
+impl TryFrom<RelayQuery> for mullvad_types::relay_constraints::RelayQuery {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(query: RelayQuery) -> Result<Self, Self::Error> {
+        Ok(Self {
+            location: query
+                .location
+                .map(Constraint::from)
+                .unwrap_or(Constraint::Any),
+            ownership: try_ownership_constraint_from_i32(query.ownership)?,
+            tunnel_protocol: query
+                .tunnel_type
+                .map(Constraint::try_from)
+                .transpose()?
+                .unwrap_or(Constraint::Any),
+            probe_count: query.probe_count,
+        })
+    }
+}
+
 impl TryFrom<Relay> for mullvad_types::relay_list::Relay {
     type Error = FromProtobufTypeError;
 
@@ -858,6 +1079,9 @@ impl TryFrom<Relay> for mullvad_types::relay_list::Relay {
                 MullvadEndpointData::Wireguard(
                     mullvad_types::relay_list::WireguardRelayEndpointData {
                         public_key: bytes_to_pubkey(&data.public_key)?,
+                        tls_certificate: bytes_to_certificate_fingerprint(
+                            &data.tls_certificate_sha256,
+                        )?,
                     },
                 )
             }
@@ -868,19 +1092,24 @@ impl TryFrom<Relay> for mullvad_types::relay_list::Relay {
             }
         };
 
-        let ipv6_addr_in = if relay.ipv6_addr_in.is_empty() {
+        let ipv4_addr_in = if relay.ipv4_addr_in.is_empty() {
             None
         } else {
             Some(relay.ipv4_addr_in.parse().map_err(|_err| {
+                FromProtobufTypeError::InvalidArgument("invalid relay IPv4 address")
+            })?)
+        };
+        let ipv6_addr_in = if relay.ipv6_addr_in.is_empty() {
+            None
+        } else {
+            Some(relay.ipv6_addr_in.parse().map_err(|_err| {
                 FromProtobufTypeError::InvalidArgument("invalid relay IPv6 address")
             })?)
         };
 
         Ok(MullvadRelay {
             hostname: relay.hostname,
-            ipv4_addr_in: relay.ipv4_addr_in.parse().map_err(|_err| {
-                FromProtobufTypeError::InvalidArgument("invalid relay IPv4 address")
-            })?,
+            ipv4_addr_in,
             ipv6_addr_in,
             include_in_country: relay.include_in_country,
             active: relay.active,
@@ -1218,6 +1447,10 @@ impl TryFrom<ConnectionConfig> for mullvad_types::ConnectionConfig {
                         },
                         username: config.username,
                         password: config.password,
+                        client_certificate: config
+                            .client_certificate
+                            .map(client_certificate_from_proto)
+                            .transpose()?,
                     },
                 ))
             }
@@ -1241,7 +1474,8 @@ impl TryFrom<ConnectionConfig> for mullvad_types::ConnectionConfig {
                     "missing peer config",
                 ))?;
 
-                let public_key = bytes_to_pubkey(&peer.public_key)?;
+                let peer = peer_config_from_proto(peer)?;
+                let exit_peer = config.exit_peer.map(peer_config_from_proto).transpose()?;
 
                 let ipv4_gateway = match config.ipv4_gateway.parse() {
                     Ok(address) => address,
@@ -1265,15 +1499,6 @@ impl TryFrom<ConnectionConfig> for mullvad_types::ConnectionConfig {
                     None
                 };
 
-                let endpoint = match peer.endpoint.parse() {
-                    Ok(address) => address,
-                    Err(_) => {
-                        return Err(FromProtobufTypeError::InvalidArgument(
-                            "invalid peer address",
-                        ))
-                    }
-                };
-
                 let mut tunnel_addresses = Vec::new();
                 for address in tunnel.addresses {
                     let address = address
@@ -1282,27 +1507,14 @@ impl TryFrom<ConnectionConfig> for mullvad_types::ConnectionConfig {
                     tunnel_addresses.push(address);
                 }
 
-                let mut allowed_ips = Vec::new();
-                for address in peer.allowed_ips {
-                    let address = address
-                        .parse()
-                        .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid address"))?;
-                    allowed_ips.push(address);
-                }
-
                 Ok(mullvad_types::ConnectionConfig::Wireguard(
                     wireguard::ConnectionConfig {
                         tunnel: wireguard::TunnelConfig {
                             private_key: wireguard::PrivateKey::from(private_key),
                             addresses: tunnel_addresses,
                         },
-                        peer: wireguard::PeerConfig {
-                            public_key,
-                            allowed_ips,
-                            endpoint,
-                            psk: None,
-                        },
-                        exit_peer: None,
+                        peer,
+                        exit_peer,
                         ipv4_gateway,
                         ipv6_gateway,
                     },
@@ -1312,6 +1524,64 @@ impl TryFrom<ConnectionConfig> for mullvad_types::ConnectionConfig {
     }
 }
 
+fn peer_config_from_proto(
+    peer: connection_config::wireguard_config::PeerConfig,
+) -> Result<wireguard::PeerConfig, FromProtobufTypeError> {
+    let public_key = bytes_to_pubkey(&peer.public_key)?;
+
+    let endpoint = peer
+        .endpoint
+        .parse()
+        .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid peer address"))?;
+
+    let mut allowed_ips = Vec::new();
+    for address in peer.allowed_ips {
+        let address = address
+            .parse()
+            .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid address"))?;
+        allowed_ips.push(address);
+    }
+
+    let psk = bytes_to_psk(&peer.preshared_key)?;
+
+    Ok(wireguard::PeerConfig {
+        public_key,
+        allowed_ips,
+        endpoint,
+        psk_required: psk.is_some(),
+        psk,
+    })
+}
+
+fn client_certificate_from_proto(
+    client_certificate: connection_config::openvpn_config::ClientCertificate,
+) -> Result<talpid_types::net::openvpn::ClientCertificate, FromProtobufTypeError> {
+    if client_certificate.certificate.is_empty() || client_certificate.private_key.is_empty() {
+        return Err(FromProtobufTypeError::InvalidArgument(
+            "invalid client certificate",
+        ));
+    }
+    Ok(talpid_types::net::openvpn::ClientCertificate {
+        certificate: client_certificate.certificate,
+        private_key: client_certificate.private_key,
+    })
+}
+
+/// Returns `None` if `bytes` is empty, which means that no preshared key is used.
+fn bytes_to_psk(bytes: &[u8]) -> Result<Option<wireguard::PresharedKey>, FromProtobufTypeError> {
+    if bytes.is_empty() {
+        return Ok(None);
+    }
+    if bytes.len() != 32 {
+        return Err(FromProtobufTypeError::InvalidArgument(
+            "invalid preshared key",
+        ));
+    }
+    let mut psk = Box::new([0; 32]);
+    psk.copy_from_slice(bytes);
+    Ok(Some(wireguard::PresharedKey::from(psk)))
+}
+
 fn bytes_to_pubkey(bytes: &[u8]) -> Result<wireguard::PublicKey, FromProtobufTypeError> {
     if bytes.len() != 32 {
         return Err(FromProtobufTypeError::InvalidArgument("invalid public key"));
@@ -1321,6 +1591,19 @@ fn bytes_to_pubkey(bytes: &[u8]) -> Result<wireguard::PublicKey, FromProtobufTyp
     Ok(wireguard::PublicKey::from(public_key))
 }
 
+/// Returns `None` if `bytes` is empty, which means that the relay does not support TLS
+/// obfuscation.
+fn bytes_to_certificate_fingerprint(
+    bytes: &[u8],
+) -> Result<Option<CertificateFingerprint>, FromProtobufTypeError> {
+    if bytes.is_empty() {
+        return Ok(None);
+    }
+    CertificateFingerprint::try_from(bytes)
+        .map(Some)
+        .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid certificate fingerprint"))
+}
+
 impl From<RelayLocation> for Constraint<mullvad_types::relay_constraints::LocationConstraint> {
     fn from(location: RelayLocation) -> Self {
         use mullvad_types::relay_constraints::LocationConstraint;
@@ -1426,6 +1709,7 @@ impl TryFrom<ObfuscationSettings> for mullvad_types::relay_constraints::Obfuscat
                 Some(IpcSelectedObfuscation::Auto) => SelectedObfuscation::Auto,
                 Some(IpcSelectedObfuscation::Off) => SelectedObfuscation::Off,
                 Some(IpcSelectedObfuscation::Udp2tcp) => SelectedObfuscation::Udp2Tcp,
+                Some(IpcSelectedObfuscation::Tls) => SelectedObfuscation::Tls,
                 None => {
                     return Err(FromProtobufTypeError::InvalidArgument(
                         "invalid selected obfuscator",
@@ -1444,13 +1728,32 @@ impl TryFrom<ObfuscationSettings> for mullvad_types::relay_constraints::Obfuscat
             }
         };
 
+        // Clients that predate TLS obfuscation do not send its settings
+        let tls = settings
+            .tls
+            .map(mullvad_types::relay_constraints::TlsObfuscationSettings::from)
+            .unwrap_or_default();
+
         Ok(Self {
             selected_obfuscation,
             udp2tcp,
+            tls,
         })
     }
 }
 
+impl From<TlsObfuscationSettings> for mullvad_types::relay_constraints::TlsObfuscationSettings {
+    fn from(settings: TlsObfuscationSettings) -> Self {
+        Self {
+            server_name: if settings.server_name.is_empty() {
+                None
+            } else {
+                Some(settings.server_name)
+            },
+        }
+    }
+}
+
 impl TryFrom<&Udp2TcpObfuscationSettings>
     for mullvad_types::relay_constraints::Udp2TcpObfuscationSettings
 {
@@ -1529,6 +1832,21 @@ impl TryFrom<TunnelOptions> for mullvad_types::settings::TunnelOptions {
                         None
                     },
                     use_pq_safe_psk: wireguard_options.use_pq_safe_psk,
+                    traffic_shaping: wireguard_options
+                        .traffic_shaping
+                        .map(net::wireguard::TrafficShapingOptions::try_from)
+                        .transpose()?
+                        .unwrap_or_default(),
+                    bandwidth_limit: wireguard_options
+                        .bandwidth_limit
+                        .map(net::wireguard::BandwidthLimit::try_from)
+                        .transpose()?
+                        .unwrap_or_default(),
+                    allowed_ips: wireguard_options
+                        .allowed_ips
+                        .map(Option::<Vec<ipnetwork::IpNetwork>>::try_from)
+                        .transpose()?
+                        .flatten(),
                     #[cfg(windows)]
                     use_wireguard_nt: wireguard_options.use_wireguard_nt,
                 },
@@ -1549,6 +1867,7 @@ impl TryFrom<TunnelOptions> for mullvad_types::settings::TunnelOptions {
             },
             generic: net::GenericTunnelOptions {
                 enable_ipv6: generic_options.enable_ipv6,
+                enable_ipv4: generic_options.enable_ipv4,
             },
             #[cfg(not(target_os = "android"))]
             dns_options: mullvad_types::settings::DnsOptions::try_from(dns_options)?,
@@ -1556,6 +1875,77 @@ impl TryFrom<TunnelOptions> for mullvad_types::settings::TunnelOptions {
     }
 }
 
+impl From<talpid_types::net::wireguard::BandwidthLimit> for BandwidthLimit {
+    fn from(limit: talpid_types::net::wireguard::BandwidthLimit) -> Self {
+        Self {
+            upload_kbps: limit.upload_kbps,
+            download_kbps: limit.download_kbps,
+        }
+    }
+}
+
+impl TryFrom<BandwidthLimit> for talpid_types::net::wireguard::BandwidthLimit {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(limit: BandwidthLimit) -> Result<Self, Self::Error> {
+        if limit.upload_kbps == Some(0) || limit.download_kbps == Some(0) {
+            return Err(FromProtobufTypeError::InvalidArgument(
+                "bandwidth limit must be greater than zero",
+            ));
+        }
+        Ok(Self {
+            upload_kbps: limit.upload_kbps,
+            download_kbps: limit.download_kbps,
+        })
+    }
+}
+
+impl From<talpid_types::net::wireguard::TrafficShapingOptions> for TrafficShapingOptions {
+    fn from(options: talpid_types::net::wireguard::TrafficShapingOptions) -> Self {
+        use talpid_types::net::wireguard::TrafficShapingFailurePolicy;
+
+        let on_failure = match options.on_failure {
+            TrafficShapingFailurePolicy::Fallback => {
+                traffic_shaping_options::FailurePolicy::Fallback
+            }
+            TrafficShapingFailurePolicy::Block => traffic_shaping_options::FailurePolicy::Block,
+        };
+        Self {
+            padding: options.padding,
+            dummy_traffic: options.dummy_traffic,
+            on_failure: i32::from(on_failure),
+        }
+    }
+}
+
+impl TryFrom<TrafficShapingOptions> for talpid_types::net::wireguard::TrafficShapingOptions {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(options: TrafficShapingOptions) -> Result<Self, Self::Error> {
+        use talpid_types::net::wireguard::TrafficShapingFailurePolicy;
+
+        let on_failure = match traffic_shaping_options::FailurePolicy::from_i32(options.on_failure)
+        {
+            Some(traffic_shaping_options::FailurePolicy::Fallback) => {
+                TrafficShapingFailurePolicy::Fallback
+            }
+            Some(traffic_shaping_options::FailurePolicy::Block) => {
+                TrafficShapingFailurePolicy::Block
+            }
+            None => {
+                return Err(FromProtobufTypeError::InvalidArgument(
+                    "invalid traffic shaping failure policy",
+                ))
+            }
+        };
+        Ok(Self {
+            padding: options.padding,
+            dummy_traffic: options.dummy_traffic,
+            on_failure,
+        })
+    }
+}
+
 impl TryFrom<DnsOptions> for mullvad_types::settings::DnsOptions {
     type Error = FromProtobufTypeError;
 
@@ -1589,6 +1979,12 @@ impl TryFrom<DnsOptions> for mullvad_types::settings::DnsOptions {
                     "missing default DNS options",
                 ))?;
 
+        if custom_options.addresses.len() > MAX_SETTINGS_LIST_LEN {
+            return Err(FromProtobufTypeError::InvalidArgument(
+                "too many custom DNS servers",
+            ));
+        }
+
         Ok(MullvadDnsOptions {
             state,
             default_options: MullvadDefaultDnsOptions {
@@ -1613,6 +2009,487 @@ impl TryFrom<DnsOptions> for mullvad_types::settings::DnsOptions {
     }
 }
 
+impl From<&[ipnetwork::IpNetwork]> for RecoveryAllowlist {
+    fn from(networks: &[ipnetwork::IpNetwork]) -> Self {
+        RecoveryAllowlist {
+            networks: networks.iter().map(|network| network.to_string()).collect(),
+        }
+    }
+}
+
+impl TryFrom<RecoveryAllowlist> for Vec<ipnetwork::IpNetwork> {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(allowlist: RecoveryAllowlist) -> Result<Self, Self::Error> {
+        if allowlist.networks.len() > MAX_SETTINGS_LIST_LEN {
+            return Err(FromProtobufTypeError::InvalidArgument(
+                "too many networks in recovery allowlist",
+            ));
+        }
+        networks_from_strings(allowlist.networks)
+    }
+}
+
+impl From<&[ipnetwork::IpNetwork]> for WireguardAllowedIps {
+    fn from(networks: &[ipnetwork::IpNetwork]) -> Self {
+        WireguardAllowedIps {
+            networks: networks.iter().map(|network| network.to_string()).collect(),
+        }
+    }
+}
+
+impl TryFrom<WireguardAllowedIps> for Option<Vec<ipnetwork::IpNetwork>> {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(allowed_ips: WireguardAllowedIps) -> Result<Self, Self::Error> {
+        if allowed_ips.networks.len() > MAX_SETTINGS_LIST_LEN {
+            return Err(FromProtobufTypeError::InvalidArgument(
+                "too many networks in WireGuard allowed IPs",
+            ));
+        }
+        if allowed_ips.networks.is_empty() {
+            return Ok(None);
+        }
+        networks_from_strings(allowed_ips.networks).map(Some)
+    }
+}
+
+fn networks_from_strings(
+    networks: Vec<String>,
+) -> Result<Vec<ipnetwork::IpNetwork>, FromProtobufTypeError> {
+    networks
+        .into_iter()
+        .map(|network| {
+            let network: ipnetwork::IpNetwork = network
+                .parse()
+                .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid IP network"))?;
+            // Clear any host bits, since the firewall matches on the network address
+            Ok(
+                ipnetwork::IpNetwork::new(network.network(), network.prefix())
+                    .expect("prefix is valid for the address family"),
+            )
+        })
+        .collect()
+}
+
+impl From<mullvad_types::states::ProtectionGapReport> for ProtectionGapReport {
+    fn from(report: mullvad_types::states::ProtectionGapReport) -> Self {
+        let to_timestamp = |time: chrono::DateTime<chrono::Utc>| Timestamp {
+            seconds: time.timestamp(),
+            nanos: 0,
+        };
+        ProtectionGapReport {
+            unclean_shutdown: report.unclean_shutdown,
+            system_rebooted: report.system_rebooted,
+            unprotected_since: Some(to_timestamp(report.unprotected_since)),
+            protected_again_at: report.protected_again_at.map(to_timestamp),
+        }
+    }
+}
+
+impl TryFrom<ProtectionGapReport> for mullvad_types::states::ProtectionGapReport {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(report: ProtectionGapReport) -> Result<Self, Self::Error> {
+        let from_timestamp = |timestamp: Timestamp| {
+            chrono::DateTime::from_utc(
+                chrono::NaiveDateTime::from_timestamp(timestamp.seconds, 0),
+                chrono::Utc,
+            )
+        };
+        Ok(mullvad_types::states::ProtectionGapReport {
+            unclean_shutdown: report.unclean_shutdown,
+            system_rebooted: report.system_rebooted,
+            unprotected_since: from_timestamp(report.unprotected_since.ok_or(
+                FromProtobufTypeError::InvalidArgument("missing 'unprotected_since' field"),
+            )?),
+            protected_again_at: report.protected_again_at.map(from_timestamp),
+        })
+    }
+}
+
+impl TryFrom<TrafficQuery> for talpid_types::traffic_query::TrafficQuery {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(query: TrafficQuery) -> Result<Self, Self::Error> {
+        let destination = query
+            .destination
+            .parse()
+            .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid destination address"))?;
+        let port = u16::try_from(query.port)
+            .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid port"))?;
+        let protocol = TransportProtocol::from_i32(query.protocol).ok_or(
+            FromProtobufTypeError::InvalidArgument("invalid transport protocol"),
+        )?;
+        Ok(talpid_types::traffic_query::TrafficQuery {
+            destination,
+            port,
+            protocol: talpid_types::net::TransportProtocol::from(protocol),
+            app: if query.app.is_empty() {
+                None
+            } else {
+                Some(query.app)
+            },
+        })
+    }
+}
+
+impl From<talpid_types::traffic_query::TrafficVerdict> for TrafficVerdict {
+    fn from(verdict: talpid_types::traffic_query::TrafficVerdict) -> Self {
+        use talpid_types::traffic_query::{TrafficOutcome, TrafficReason};
+        use traffic_verdict::{Outcome, Reason};
+
+        let outcome = match verdict.outcome {
+            TrafficOutcome::Tunneled => Outcome::Tunneled,
+            TrafficOutcome::Blocked => Outcome::Blocked,
+            TrafficOutcome::Bypassed => Outcome::Bypassed,
+        };
+        let reason = match verdict.reason {
+            TrafficReason::NoFirewallPolicy => Reason::NoFirewallPolicy,
+            TrafficReason::RelayEndpoint => Reason::RelayEndpoint,
+            TrafficReason::AllowedEndpoint => Reason::AllowedEndpoint,
+            TrafficReason::LocalNetwork => Reason::LocalNetwork,
+            TrafficReason::RecoveryAllowlist => Reason::RecoveryAllowlist,
+            TrafficReason::VpnCoexistence => Reason::VpnCoexistence,
+            TrafficReason::ExcludedApp => Reason::ExcludedApp,
+            TrafficReason::AllowedTunnelTraffic => Reason::AllowedTunnelTraffic,
+            TrafficReason::BlockedTunnelProtocol => Reason::BlockedTunnelProtocol,
+            TrafficReason::DnsServerNotAllowed => Reason::DnsServerNotAllowed,
+            TrafficReason::TunnelRoute => Reason::TunnelRoute,
+            TrafficReason::NoTunnelRoute => Reason::NoTunnelRoute,
+            TrafficReason::NotAllowed => Reason::NotAllowed,
+        };
+        TrafficVerdict {
+            outcome: i32::from(outcome),
+            reason: i32::from(reason),
+            route: verdict.route.map(|route| traffic_verdict::Route {
+                destination: route.destination.to_string(),
+                gateway: route
+                    .gateway
+                    .map(|gateway| gateway.to_string())
+                    .unwrap_or_default(),
+                interface: route.interface.unwrap_or_default(),
+                follows_default_route: route.follows_default_route,
+            }),
+            explanation: verdict.reason.to_string(),
+        }
+    }
+}
+
+impl From<talpid_types::net::PhysicalInterfaceDetails> for PhysicalInterface {
+    fn from(interface: talpid_types::net::PhysicalInterfaceDetails) -> Self {
+        use talpid_types::net::PhysicalInterfaceKind;
+
+        let kind = match interface.kind {
+            PhysicalInterfaceKind::Ethernet => physical_interface::Kind::Ethernet,
+            PhysicalInterfaceKind::Wireless => physical_interface::Kind::Wireless,
+            PhysicalInterfaceKind::Cellular => physical_interface::Kind::Cellular,
+            PhysicalInterfaceKind::Other => physical_interface::Kind::Other,
+        };
+        PhysicalInterface {
+            name: interface.name,
+            index: interface.index,
+            kind: i32::from(kind),
+            addresses: interface
+                .addresses
+                .iter()
+                .map(|address| address.to_string())
+                .collect(),
+            is_default_route: interface.is_default_route,
+            mtu: interface.mtu.unwrap_or(0),
+        }
+    }
+}
+
+impl From<talpid_types::health::HealthReport> for HealthReport {
+    fn from(report: talpid_types::health::HealthReport) -> Self {
+        HealthReport {
+            checks: report.checks.into_iter().map(HealthCheck::from).collect(),
+        }
+    }
+}
+
+impl From<talpid_types::health::HealthCheck> for HealthCheck {
+    fn from(check: talpid_types::health::HealthCheck) -> Self {
+        use talpid_types::health::HealthStatus;
+
+        let status = match check.status {
+            HealthStatus::Pass => health_check::Status::Pass,
+            HealthStatus::Warn => health_check::Status::Warn,
+            HealthStatus::Fail => health_check::Status::Fail,
+        };
+        HealthCheck {
+            name: check.name.to_owned(),
+            status: i32::from(status),
+            details: check.details.unwrap_or_default(),
+            remediation: check.remediation.unwrap_or_default(),
+        }
+    }
+}
+
+impl From<&talpid_types::net::VpnCoexistence> for VpnCoexistence {
+    fn from(vpn_coexistence: &talpid_types::net::VpnCoexistence) -> Self {
+        VpnCoexistence {
+            networks: vpn_coexistence
+                .networks
+                .iter()
+                .map(|network| network.to_string())
+                .collect(),
+            udp_ports: vpn_coexistence
+                .udp_ports
+                .iter()
+                .map(|port| u32::from(*port))
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<VpnCoexistence> for talpid_types::net::VpnCoexistence {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(vpn_coexistence: VpnCoexistence) -> Result<Self, Self::Error> {
+        if vpn_coexistence.networks.len() > MAX_SETTINGS_LIST_LEN
+            || vpn_coexistence.udp_ports.len() > MAX_SETTINGS_LIST_LEN
+        {
+            return Err(FromProtobufTypeError::InvalidArgument(
+                "too many networks or ports for VPN coexistence",
+            ));
+        }
+        let networks = networks_from_strings(vpn_coexistence.networks)?;
+        let udp_ports = vpn_coexistence
+            .udp_ports
+            .into_iter()
+            .map(|port| {
+                u16::try_from(port)
+                    .ok()
+                    .filter(|port| *port != 0)
+                    .ok_or(FromProtobufTypeError::InvalidArgument("invalid UDP port"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(talpid_types::net::VpnCoexistence {
+            networks,
+            udp_ports,
+        })
+    }
+}
+
+impl From<talpid_types::net::BlockedTunnelProtocols> for BlockedTunnelProtocols {
+    fn from(blocked: talpid_types::net::BlockedTunnelProtocols) -> Self {
+        BlockedTunnelProtocols {
+            ipv6: blocked.ipv6,
+            udp: blocked.udp,
+            smb: blocked.smb,
+        }
+    }
+}
+
+impl From<BlockedTunnelProtocols> for talpid_types::net::BlockedTunnelProtocols {
+    fn from(blocked: BlockedTunnelProtocols) -> Self {
+        talpid_types::net::BlockedTunnelProtocols {
+            ipv6: blocked.ipv6,
+            udp: blocked.udp,
+            smb: blocked.smb,
+        }
+    }
+}
+
+impl From<talpid_types::tunnel::ReconnectLimits> for ReconnectLimits {
+    fn from(limits: talpid_types::tunnel::ReconnectLimits) -> Self {
+        ReconnectLimits {
+            start_failures: limits.start_failures,
+            timeouts: limits.timeouts,
+            bridge_failures: limits.bridge_failures,
+        }
+    }
+}
+
+impl From<ReconnectLimits> for talpid_types::tunnel::ReconnectLimits {
+    fn from(limits: ReconnectLimits) -> Self {
+        talpid_types::tunnel::ReconnectLimits {
+            start_failures: limits.start_failures,
+            timeouts: limits.timeouts,
+            bridge_failures: limits.bridge_failures,
+        }
+    }
+}
+
+impl From<talpid_types::net::route_takeover::RouteTakeoverPolicy> for RouteTakeoverPolicy {
+    fn from(policy: talpid_types::net::route_takeover::RouteTakeoverPolicy) -> Self {
+        use talpid_types::net::route_takeover::RouteTakeoverPolicy;
+        Self {
+            policy: i32::from(match policy {
+                RouteTakeoverPolicy::Warn => route_takeover_policy::Policy::Warn,
+                RouteTakeoverPolicy::Reclaim => route_takeover_policy::Policy::Reclaim,
+                RouteTakeoverPolicy::Yield => route_takeover_policy::Policy::Yield,
+            }),
+        }
+    }
+}
+
+impl TryFrom<RouteTakeoverPolicy> for talpid_types::net::route_takeover::RouteTakeoverPolicy {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(policy: RouteTakeoverPolicy) -> Result<Self, Self::Error> {
+        use talpid_types::net::route_takeover::RouteTakeoverPolicy;
+        match route_takeover_policy::Policy::from_i32(policy.policy) {
+            Some(route_takeover_policy::Policy::Warn) => Ok(RouteTakeoverPolicy::Warn),
+            Some(route_takeover_policy::Policy::Reclaim) => Ok(RouteTakeoverPolicy::Reclaim),
+            Some(route_takeover_policy::Policy::Yield) => Ok(RouteTakeoverPolicy::Yield),
+            None => Err(FromProtobufTypeError::InvalidArgument(
+                "invalid route takeover policy",
+            )),
+        }
+    }
+}
+
+impl From<talpid_types::tunnel::ErrorStatePolicy> for ErrorStatePolicy {
+    fn from(policy: talpid_types::tunnel::ErrorStatePolicy) -> Self {
+        use talpid_types::tunnel::ErrorStatePolicy;
+        Self {
+            policy: i32::from(match policy {
+                ErrorStatePolicy::AllowLan => error_state_policy::Policy::AllowLan,
+                ErrorStatePolicy::BlockAll => error_state_policy::Policy::BlockAll,
+            }),
+        }
+    }
+}
+
+impl TryFrom<ErrorStatePolicy> for talpid_types::tunnel::ErrorStatePolicy {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(policy: ErrorStatePolicy) -> Result<Self, Self::Error> {
+        use talpid_types::tunnel::ErrorStatePolicy;
+        match error_state_policy::Policy::from_i32(policy.policy) {
+            Some(error_state_policy::Policy::AllowLan) => Ok(ErrorStatePolicy::AllowLan),
+            Some(error_state_policy::Policy::BlockAll) => Ok(ErrorStatePolicy::BlockAll),
+            None => Err(FromProtobufTypeError::InvalidArgument(
+                "invalid error state policy",
+            )),
+        }
+    }
+}
+
+impl From<&mullvad_types::hooks::Hook> for Hook {
+    fn from(hook: &mullvad_types::hooks::Hook) -> Self {
+        use mullvad_types::hooks::HookEvent;
+        Self {
+            event: i32::from(match hook.event {
+                HookEvent::Connected => hook::Event::Connected,
+                HookEvent::Disconnected => hook::Event::Disconnected,
+                HookEvent::Error => hook::Event::Error,
+            }),
+            program: hook.program.to_string_lossy().into_owned(),
+        }
+    }
+}
+
+impl TryFrom<Hook> for mullvad_types::hooks::Hook {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(hook: Hook) -> Result<Self, Self::Error> {
+        use mullvad_types::hooks::HookEvent;
+        let event = match hook::Event::from_i32(hook.event) {
+            Some(hook::Event::Connected) => HookEvent::Connected,
+            Some(hook::Event::Disconnected) => HookEvent::Disconnected,
+            Some(hook::Event::Error) => HookEvent::Error,
+            None => return Err(FromProtobufTypeError::InvalidArgument("invalid hook event")),
+        };
+        let program = std::path::PathBuf::from(hook.program);
+        if !program.is_absolute() {
+            return Err(FromProtobufTypeError::InvalidArgument(
+                "hook programs must be absolute paths",
+            ));
+        }
+        // Set by the daemon, since only it knows who the client is
+        Ok(mullvad_types::hooks::Hook {
+            event,
+            program,
+            added_by: None,
+        })
+    }
+}
+
+impl From<&[mullvad_types::hooks::Hook]> for Hooks {
+    fn from(hooks: &[mullvad_types::hooks::Hook]) -> Self {
+        Hooks {
+            hooks: hooks.iter().map(Hook::from).collect(),
+        }
+    }
+}
+
+impl TryFrom<Hooks> for Vec<mullvad_types::hooks::Hook> {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(hooks: Hooks) -> Result<Self, Self::Error> {
+        if hooks.hooks.len() > MAX_SETTINGS_LIST_LEN {
+            return Err(FromProtobufTypeError::InvalidArgument("too many hooks"));
+        }
+        hooks
+            .hooks
+            .into_iter()
+            .map(mullvad_types::hooks::Hook::try_from)
+            .collect()
+    }
+}
+
+impl From<talpid_types::net::route_takeover::CompetingVpn> for CompetingVpn {
+    fn from(competing_vpn: talpid_types::net::route_takeover::CompetingVpn) -> Self {
+        use talpid_types::net::route_takeover::CompetingVpnKind;
+        let kind = match competing_vpn.kind {
+            CompetingVpnKind::WireGuard => competing_vpn::Kind::Wireguard,
+            CompetingVpnKind::OpenVpn => competing_vpn::Kind::Openvpn,
+            CompetingVpnKind::CiscoAnyConnect => competing_vpn::Kind::CiscoAnyconnect,
+            CompetingVpnKind::GlobalProtect => competing_vpn::Kind::Globalprotect,
+            CompetingVpnKind::FortiClient => competing_vpn::Kind::Forticlient,
+            CompetingVpnKind::Tailscale => competing_vpn::Kind::Tailscale,
+            CompetingVpnKind::ZeroTier => competing_vpn::Kind::Zerotier,
+            CompetingVpnKind::Ipsec => competing_vpn::Kind::Ipsec,
+            CompetingVpnKind::Ppp => competing_vpn::Kind::Ppp,
+            CompetingVpnKind::Unknown => competing_vpn::Kind::Unknown,
+        };
+        Self {
+            interface: competing_vpn.interface,
+            description: competing_vpn.description.unwrap_or_default(),
+            kind: i32::from(kind),
+            prefix: competing_vpn.prefix.to_string(),
+        }
+    }
+}
+
+impl From<talpid_types::performance::PerformanceWarning> for PerformanceWarning {
+    fn from(warning: talpid_types::performance::PerformanceWarning) -> Self {
+        use talpid_types::performance::Operation;
+        let operation = match warning.operation {
+            Operation::ApplyFirewallPolicy => performance_warning::Operation::ApplyFirewallPolicy,
+            Operation::ResetFirewallPolicy => performance_warning::Operation::ResetFirewallPolicy,
+            Operation::SetDns => performance_warning::Operation::SetDns,
+            Operation::ResetDns => performance_warning::Operation::ResetDns,
+            Operation::ClearRoutes => performance_warning::Operation::ClearRoutes,
+        };
+        Self {
+            operation: i32::from(operation),
+            duration: Some(
+                prost_types::Duration::try_from(warning.duration)
+                    .expect("Failed to convert std::time::Duration to prost_types::Duration"),
+            ),
+        }
+    }
+}
+
+impl From<Option<mullvad_types::clock_skew::ClockSkew>> for ClockSkew {
+    fn from(skew: Option<mullvad_types::clock_skew::ClockSkew>) -> Self {
+        use mullvad_types::clock_skew::ClockSkew as MullvadClockSkew;
+        let skew = skew.map(|skew| match skew {
+            MullvadClockSkew::Measured(skew) => clock_skew::Skew::Measured(prost_types::Duration {
+                seconds: skew.num_seconds(),
+                nanos: 0,
+            }),
+            MullvadClockSkew::CertificateNotValid => clock_skew::Skew::CertificateNotValid(()),
+        });
+        Self { skew }
+    }
+}
+
 impl TryFrom<TransportPort> for mullvad_types::relay_constraints::TransportPort {
     type Error = FromProtobufTypeError;
 
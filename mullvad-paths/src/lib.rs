@@ -65,6 +65,8 @@ pub mod resources;
 pub use crate::resources::{get_default_resource_dir, get_resource_dir};
 
 mod rpc_socket;
+#[cfg(target_os = "linux")]
+pub use crate::rpc_socket::get_socket_bypass_path;
 pub use crate::rpc_socket::{get_default_rpc_socket_path, get_rpc_socket_path};
 
 mod settings;
@@ -7,6 +7,15 @@ pub fn get_rpc_socket_path() -> PathBuf {
     }
 }
 
+/// Returns the path of the socket that processes send sockets to in order to exclude them from the
+/// tunnel. It is next to the RPC socket.
+#[cfg(target_os = "linux")]
+pub fn get_socket_bypass_path() -> PathBuf {
+    let mut path = get_rpc_socket_path().into_os_string();
+    path.push("-socket-bypass");
+    PathBuf::from(path)
+}
+
 pub fn get_default_rpc_socket_path() -> PathBuf {
     #[cfg(any(target_os = "linux", target_os = "macos"))]
     {
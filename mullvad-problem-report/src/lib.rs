@@ -34,6 +34,10 @@ const LINE_SEPARATOR: &str = "\r\n";
 
 const MAX_SEND_ATTEMPTS: usize = 3;
 
+/// File in the cache directory where the daemon aggregates the outcomes of connection attempts,
+/// if the user has opted in to it.
+const CONNECTION_STATS_FILE: &str = "connection-stats.json";
+
 /// Custom macro to write a line to an output formatter that uses platform-specific newline
 /// character sequences.
 macro_rules! write_line {
@@ -177,6 +181,14 @@ pub fn collect_report(
     })
 }
 
+/// Returns the path of the connection statistics kept by the daemon. The file only exists if the
+/// user has opted in to gathering them.
+pub fn connection_stats_path() -> Result<PathBuf, Error> {
+    mullvad_paths::get_cache_dir()
+        .map(|cache_dir| cache_dir.join(CONNECTION_STATS_FILE))
+        .map_err(Error::ObtainCacheDirectory)
+}
+
 /// Returns an iterator over all files in the given directory that has the `.log` extension.
 fn list_logs(
     log_dir: PathBuf,
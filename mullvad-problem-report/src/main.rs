@@ -1,7 +1,7 @@
 #![deny(rust_2018_idioms)]
 
 use clap::{crate_authors, crate_name};
-use mullvad_problem_report::{collect_report, Error};
+use mullvad_problem_report::{collect_report, connection_stats_path, Error};
 use std::{env, path::Path, process};
 use talpid_types::ErrorExt;
 
@@ -47,6 +47,14 @@ fn run() -> Result<(), Error> {
                         .takes_value(true)
                         .required(false),
                 )
+                .arg(
+                    clap::Arg::new("connection_stats")
+                        .help(
+                            "Include the statistics about connection attempts that the daemon \
+                             gathers when enabled with `mullvad connection-stats set on`.",
+                        )
+                        .long("connection-stats"),
+                )
                 .arg(
                     clap::Arg::new("redact")
                         .help("List of words and expressions to remove from the report")
@@ -93,10 +101,22 @@ fn run() -> Result<(), Error> {
         let redact_custom_strings = collect_matches
             .values_of_t("redact")
             .unwrap_or_else(|_| vec![]);
-        let extra_logs = collect_matches
+        let mut extra_logs = collect_matches
             .values_of_os("extra_logs")
             .map(|os_values| os_values.map(Path::new).collect())
             .unwrap_or_else(Vec::new);
+        let connection_stats = if collect_matches.is_present("connection_stats") {
+            let path = connection_stats_path()?;
+            if path.exists() {
+                Some(path)
+            } else {
+                eprintln!("No connection statistics have been gathered");
+                None
+            }
+        } else {
+            None
+        };
+        extra_logs.extend(connection_stats.as_deref());
         let output_path = Path::new(collect_matches.value_of_os("output").unwrap());
         collect_report(&extra_logs, output_path, redact_custom_strings)?;
 
@@ -9,8 +9,8 @@ use mullvad_types::{
     relay_constraints::{
         BridgeSettings, BridgeState, Constraint, InternalBridgeConstraints, LocationConstraint,
         Match, ObfuscationSettings, OpenVpnConstraints, Ownership, Providers, RelayConstraints,
-        RelaySettings, SelectedObfuscation, Set, TransportPort, Udp2TcpObfuscationSettings,
-        WireguardConstraints,
+        RelayQuery, RelaySettings, SelectedObfuscation, Set, TlsObfuscationSettings, TransportPort,
+        Udp2TcpObfuscationSettings, WireguardConstraints,
     },
     relay_list::{BridgeEndpointData, Relay, RelayEndpointData, RelayList},
     CustomTunnelEndpoint,
@@ -19,7 +19,7 @@ use parking_lot::{Mutex, MutexGuard};
 use rand::{seq::SliceRandom, Rng};
 use std::{
     io,
-    net::{IpAddr, SocketAddr},
+    net::SocketAddr,
     path::Path,
     sync::Arc,
     time::{self, SystemTime},
@@ -44,6 +44,12 @@ const WIREGUARD_EXIT_PORT: Constraint<u16> = Constraint::Only(51820);
 const WIREGUARD_EXIT_IP_VERSION: Constraint<IpVersion> = Constraint::Only(IpVersion::V4);
 
 const UDP2TCP_PORTS: [u16; 3] = [80, 443, 5001];
+const TLS_PORT: u16 = 443;
+/// Domain of the server name sent to relays that use TLS obfuscation, unless a server name is
+/// configured.
+const TLS_SERVER_NAME_DOMAIN: &str = "relays.mullvad.net";
+/// Auto obfuscation retry attempt on which TLS obfuscation is used, if the relay supports it.
+const TLS_AUTO_RETRY_ATTEMPT: u32 = 1;
 
 /// Minimum number of bridges to keep for selection when filtering by distance.
 const MIN_BRIDGE_COUNT: usize = 5;
@@ -215,6 +221,41 @@ impl RelaySelector {
         self.parsed_relays.lock().locations().clone()
     }
 
+    /// Returns all relays that match `query`, in the order that they appear in the relay list.
+    pub fn query_relays(&self, query: &RelayQuery) -> Vec<Relay> {
+        self.parsed_relays
+            .lock()
+            .relays()
+            .iter()
+            .filter(|relay| query.matches(*relay))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns a TCP endpoint that `relay` listens on, which can be used to estimate the latency
+    /// to the relay without setting up a tunnel to it.
+    pub fn get_probe_endpoint(&self, relay: &Relay) -> Option<SocketAddr> {
+        let parsed_relays = self.parsed_relays.lock();
+        let relay_list = parsed_relays.locations();
+        let port = match relay.endpoint_data {
+            RelayEndpointData::Openvpn => relay_list
+                .openvpn
+                .ports
+                .iter()
+                .find(|endpoint| endpoint.protocol == TransportProtocol::Tcp)
+                .map(|endpoint| endpoint.port),
+            RelayEndpointData::Bridge => relay_list
+                .bridge
+                .shadowsocks
+                .iter()
+                .find(|endpoint| endpoint.protocol == TransportProtocol::Tcp)
+                .map(|endpoint| endpoint.port),
+            RelayEndpointData::Wireguard(_) => relay_list.wireguard.udp2tcp_ports.first().copied(),
+        }?;
+        let address = relay.addr_in(Constraint::Any)?;
+        Some(SocketAddr::new(address, port))
+    }
+
     /// Returns a random relay and relay endpoint matching the current constraints.
     pub fn get_relay(
         &self,
@@ -908,6 +949,10 @@ impl RelaySelector {
                 )
                 .ok_or(Error::NoObfuscator)?,
             )),
+            SelectedObfuscation::Tls => Ok(Some(
+                self.get_tls_obfuscator(&config.obfuscation_settings.tls, relay, endpoint)
+                    .ok_or(Error::NoObfuscator)?,
+            )),
         }
     }
 
@@ -918,8 +963,15 @@ impl RelaySelector {
         endpoint: &MullvadWireguardEndpoint,
         retry_attempt: u32,
     ) -> Option<SelectedObfuscator> {
-        if !self.should_use_auto_obfuscator(retry_attempt) {
-            return None;
+        let auto_retry_attempt = self.get_auto_obfuscator_retry_attempt(retry_attempt)?;
+        // Escalate to TLS, which is harder to tell apart from other traffic, once udp2tcp has
+        // been tried.
+        if auto_retry_attempt == TLS_AUTO_RETRY_ATTEMPT {
+            if let Some(obfuscator) =
+                self.get_tls_obfuscator(&obfuscation_settings.tls, relay, endpoint)
+            {
+                return Some(obfuscator);
+            }
         }
         // TODO FIX: The third obfuscator entry will never be chosen
         // Because get_auto_obfuscator_retry_attempt() returns [0, 1]
@@ -928,16 +980,10 @@ impl RelaySelector {
             &obfuscation_settings.udp2tcp,
             relay,
             endpoint,
-            self.get_auto_obfuscator_retry_attempt(retry_attempt)
-                .unwrap(),
+            auto_retry_attempt,
         )
     }
 
-    fn should_use_auto_obfuscator(&self, retry_attempt: u32) -> bool {
-        self.get_auto_obfuscator_retry_attempt(retry_attempt)
-            .is_some()
-    }
-
     fn get_auto_obfuscator_retry_attempt(&self, retry_attempt: u32) -> Option<u32> {
         match retry_attempt % 4 {
             0 | 1 => None,
@@ -970,6 +1016,31 @@ impl RelaySelector {
             })
     }
 
+    /// Returns a TLS obfuscator for `relay`, or `None` if the relay does not support it.
+    fn get_tls_obfuscator(
+        &self,
+        obfuscation_settings: &TlsObfuscationSettings,
+        relay: &Relay,
+        endpoint: &MullvadWireguardEndpoint,
+    ) -> Option<SelectedObfuscator> {
+        let certificate = match &relay.endpoint_data {
+            RelayEndpointData::Wireguard(data) => data.tls_certificate?,
+            _ => return None,
+        };
+        let server_name = obfuscation_settings
+            .server_name
+            .clone()
+            .unwrap_or_else(|| format!("{}.{}", relay.hostname, TLS_SERVER_NAME_DOMAIN));
+        Some(SelectedObfuscator {
+            config: ObfuscatorConfig::Tls {
+                endpoint: SocketAddr::new(endpoint.peer.endpoint.ip(), TLS_PORT),
+                server_name,
+                certificate,
+            },
+            relay: relay.clone(),
+        })
+    }
+
     /// Returns preferred constraints
     #[allow(unused_variables)]
     fn preferred_tunnel_constraints(
@@ -1083,13 +1154,13 @@ impl RelaySelector {
 
         self.pick_random_relay(&matching_relays)
             .and_then(|selected_relay| {
-                let endpoint = matcher.mullvad_endpoint(selected_relay);
-                let addr_in = endpoint
-                    .as_ref()
-                    .map(|endpoint| endpoint.to_endpoint().address.ip())
-                    .unwrap_or_else(|| IpAddr::from(selected_relay.ipv4_addr_in));
-                log::info!("Selected relay {} at {}", selected_relay.hostname, addr_in);
-                endpoint.map(|endpoint| NormalSelectedRelay::new(endpoint, selected_relay.clone()))
+                let endpoint = matcher.mullvad_endpoint(selected_relay)?;
+                log::info!(
+                    "Selected relay {} at {}",
+                    selected_relay.hostname,
+                    endpoint.to_endpoint().address.ip()
+                );
+                Some(NormalSelectedRelay::new(endpoint, selected_relay.clone()))
             })
             .ok_or(Error::NoRelay)
     }
@@ -1145,17 +1216,18 @@ impl RelaySelector {
         if relay.endpoint_data != RelayEndpointData::Bridge {
             return None;
         }
+        let address = relay.addr_in(Constraint::Any)?;
         data.shadowsocks
             .choose(&mut rand::thread_rng())
             .map(|shadowsocks_endpoint| {
                 log::info!(
                     "Selected Shadowsocks bridge {} at {}:{}/{}",
                     relay.hostname,
-                    relay.ipv4_addr_in,
+                    address,
                     shadowsocks_endpoint.port,
                     shadowsocks_endpoint.protocol
                 );
-                shadowsocks_endpoint.to_proxy_settings(relay.ipv4_addr_in.into())
+                shadowsocks_endpoint.to_proxy_settings(address)
             })
     }
 
@@ -1258,7 +1330,7 @@ mod test {
             WireguardEndpointData, WireguardRelayEndpointData,
         },
     };
-    use talpid_types::net::wireguard::PublicKey;
+    use talpid_types::net::{obfuscation::CertificateFingerprint, wireguard::PublicKey};
 
     lazy_static::lazy_static! {
         static ref RELAYS: RelayList = RelayList {
@@ -1276,7 +1348,7 @@ mod test {
                             relays: vec![
                                 Relay {
                                     hostname: "se9-wireguard".to_string(),
-                                    ipv4_addr_in: "185.213.154.68".parse().unwrap(),
+                                    ipv4_addr_in: Some("185.213.154.68".parse().unwrap()),
                                     ipv6_addr_in: Some("2a03:1b20:5:f011::a09f".parse().unwrap()),
                                     include_in_country: true,
                                     active: true,
@@ -1285,12 +1357,13 @@ mod test {
                                     weight: 1,
                                     endpoint_data: RelayEndpointData::Wireguard(WireguardRelayEndpointData {
                                         public_key: PublicKey::from_base64("BLNHNoGO88LjV/wDBa7CUUwUzPq/fO2UwcGLy56hKy4=").unwrap(),
+                                        tls_certificate: Some(CertificateFingerprint::from_bytes([1; 32])),
                                     }),
                                     location: None,
                                 },
                                 Relay {
                                     hostname: "se10-wireguard".to_string(),
-                                    ipv4_addr_in: "185.213.154.69".parse().unwrap(),
+                                    ipv4_addr_in: Some("185.213.154.69".parse().unwrap()),
                                     ipv6_addr_in: Some("2a03:1b20:5:f011::a10f".parse().unwrap()),
                                     include_in_country: true,
                                     active: true,
@@ -1299,12 +1372,13 @@ mod test {
                                     weight: 1,
                                     endpoint_data: RelayEndpointData::Wireguard(WireguardRelayEndpointData {
                                         public_key: PublicKey::from_base64("BLNHNoGO88LjV/wDBa7CUUwUzPq/fO2UwcGLy56hKy4=").unwrap(),
+                                        tls_certificate: None,
                                     }),
                                     location: None,
                                 },
                                 Relay {
                                     hostname: "se-got-001".to_string(),
-                                    ipv4_addr_in: "185.213.154.131".parse().unwrap(),
+                                    ipv4_addr_in: Some("185.213.154.131".parse().unwrap()),
                                     ipv6_addr_in: None,
                                     include_in_country: true,
                                     active: true,
@@ -1568,14 +1642,38 @@ mod test {
 
         let endpoint = endpoint.unwrap_wireguard();
         assert_eq!(
-            exit_relay.ipv4_addr_in,
-            endpoint.exit_peer.as_ref().unwrap().endpoint.ip()
+            exit_relay.addr_in(Constraint::Any),
+            Some(endpoint.exit_peer.as_ref().unwrap().endpoint.ip())
+        );
+        assert_ne!(
+            exit_relay.addr_in(Constraint::Any),
+            Some(endpoint.peer.endpoint.ip())
         );
-        assert_ne!(exit_relay.ipv4_addr_in, endpoint.peer.endpoint.ip());
 
         Ok(())
     }
 
+    #[test]
+    fn test_ipv6_only_relay() {
+        let mut relay = RELAYS.countries[0].cities[0].relays[0].clone();
+        relay.ipv4_addr_in = None;
+
+        let mut matcher = WireguardMatcher::from_endpoint(RELAYS.wireguard.clone());
+        matcher.ip_version = Constraint::Only(IpVersion::V4);
+        assert!(matcher.filter_matching_endpoints(&relay).is_none());
+
+        matcher.ip_version = Constraint::Any;
+        assert!(matcher.filter_matching_endpoints(&relay).is_some());
+        let endpoint = matcher
+            .mullvad_endpoint(&relay)
+            .expect("IPv6 endpoint should be used");
+        let endpoint = endpoint.unwrap_wireguard();
+        assert_eq!(
+            Some(endpoint.peer.endpoint.ip()),
+            relay.ipv6_addr_in.map(std::net::IpAddr::from)
+        );
+    }
+
     #[test]
     fn test_bridge_constraints() -> Result<(), String> {
         let relay_selector = new_relay_selector();
@@ -1859,11 +1957,73 @@ mod test {
                 }
             ));
 
-            let SelectedObfuscator {
-                config: ObfuscatorConfig::Udp2Tcp { endpoint },
+            match obfs_config.config {
+                ObfuscatorConfig::Udp2Tcp { endpoint } => {
+                    assert!(TCP2UDP_PORTS.contains(&endpoint.port()))
+                }
+                config => panic!("Unexpected obfuscator: {:?}", config),
+            }
+        }
+    }
+
+    #[test]
+    fn test_selecting_wg_endpoint_with_tls_obfuscation() {
+        let relay_selector = new_relay_selector();
+
+        let result = relay_selector
+            .get_tunnel_endpoint(
+                &WIREGUARD_SINGLEHOP_CONSTRAINTS,
+                BridgeState::Off,
+                0,
+                TunnelType::Wireguard,
+            )
+            .expect("Failed to select a WireGuard relay");
+        let endpoint = result.endpoint.unwrap_wireguard();
+        let relays = &RELAYS.countries[0].cities[0].relays;
+        let (tls_relay, udp2tcp_relay) = (&relays[0], &relays[1]);
+
+        relay_selector.config.lock().obfuscation_settings = ObfuscationSettings {
+            selected_obfuscation: SelectedObfuscation::Tls,
+            ..ObfuscationSettings::default()
+        };
+
+        let obfs_config = relay_selector
+            .get_obfuscator(tls_relay, endpoint, 0)
+            .unwrap()
+            .expect("Failed to get TLS endpoint");
+        match obfs_config.config {
+            ObfuscatorConfig::Tls {
+                endpoint: tls_endpoint,
+                server_name,
                 ..
-            } = obfs_config;
-            assert!(TCP2UDP_PORTS.contains(&endpoint.port()));
+            } => {
+                assert_eq!(
+                    tls_endpoint,
+                    SocketAddr::new(endpoint.peer.endpoint.ip(), TLS_PORT)
+                );
+                assert_eq!(server_name, "se9-wireguard.relays.mullvad.net");
+            }
+            config => panic!("Unexpected obfuscator: {:?}", config),
+        }
+        assert!(matches!(
+            relay_selector.get_obfuscator(udp2tcp_relay, endpoint, 0),
+            Err(Error::NoObfuscator)
+        ));
+
+        // Auto obfuscation falls back on udp2tcp if the relay does not support TLS
+        relay_selector.config.lock().obfuscation_settings = ObfuscationSettings {
+            selected_obfuscation: SelectedObfuscation::Auto,
+            ..ObfuscationSettings::default()
+        };
+        for (relay, expect_tls) in [(tls_relay, true), (udp2tcp_relay, false)] {
+            let obfs_config = relay_selector
+                .get_obfuscator(relay, endpoint, 3)
+                .unwrap()
+                .expect("Failed to get obfuscator");
+            assert_eq!(
+                matches!(obfs_config.config, ObfuscatorConfig::Tls { .. }),
+                expect_tls
+            );
         }
     }
 
@@ -1897,4 +2057,34 @@ mod test {
             ));
         }
     }
+
+    #[test]
+    fn test_query_relays() {
+        let relay_selector = new_relay_selector();
+
+        let query = RelayQuery {
+            location: Constraint::Only(LocationConstraint::City("se".to_owned(), "got".to_owned())),
+            ownership: Constraint::Only(Ownership::MullvadOwned),
+            tunnel_protocol: Constraint::Only(TunnelType::Wireguard),
+            probe_count: 0,
+        };
+        let relays = relay_selector.query_relays(&query);
+        assert_eq!(relays.len(), 1);
+        assert_eq!(relays[0].hostname, "se9-wireguard");
+        assert_eq!(
+            relay_selector.get_probe_endpoint(&relays[0]),
+            Some("185.213.154.68:80".parse().unwrap())
+        );
+
+        let query = RelayQuery {
+            tunnel_protocol: Constraint::Only(TunnelType::OpenVpn),
+            ..Default::default()
+        };
+        let relays = relay_selector.query_relays(&query);
+        assert_eq!(relays.len(), 1);
+        assert_eq!(
+            relay_selector.get_probe_endpoint(&relays[0]),
+            Some("185.213.154.131:443".parse().unwrap())
+        );
+    }
 }
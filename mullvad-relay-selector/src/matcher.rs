@@ -64,6 +64,7 @@ impl<T: TunnelMatcher> RelayMatcher<T> {
         if !self.location.matches(relay)
             || !self.providers.matches(relay)
             || !self.ownership.matches(relay)
+            || relay.addr_in(Constraint::Any).is_none()
         {
             return None;
         }
@@ -97,12 +98,9 @@ impl TunnelMatcher for OpenVpnMatcher {
     }
 
     fn mullvad_endpoint(&self, relay: &Relay) -> Option<MullvadEndpoint> {
+        let address = relay.addr_in(Constraint::Any)?;
         self.get_transport_port().map(|endpoint| {
-            MullvadEndpoint::OpenVpn(Endpoint::new(
-                relay.ipv4_addr_in,
-                endpoint.port,
-                endpoint.protocol,
-            ))
+            MullvadEndpoint::OpenVpn(Endpoint::new(address, endpoint.port, endpoint.protocol))
         })
     }
 }
@@ -243,6 +241,7 @@ impl WireguardMatcher {
             endpoint: SocketAddr::new(host, port),
             allowed_ips: all_of_the_internet(),
             psk: None,
+            psk_required: false,
         };
         Some(MullvadEndpoint::Wireguard(MullvadWireguardEndpoint {
             peer: peer_config,
@@ -253,10 +252,7 @@ impl WireguardMatcher {
     }
 
     fn get_address_for_wireguard_relay(&self, relay: &Relay) -> Option<IpAddr> {
-        match self.ip_version {
-            Constraint::Any | Constraint::Only(IpVersion::V4) => Some(relay.ipv4_addr_in.into()),
-            Constraint::Only(IpVersion::V6) => relay.ipv6_addr_in.map(|addr| addr.into()),
-        }
+        relay.addr_in(self.ip_version)
     }
 
     fn get_port_for_wireguard_relay(&self, data: &WireguardEndpointData) -> Option<u16> {
@@ -310,6 +306,8 @@ impl TunnelMatcher for WireguardMatcher {
         if !matches!(relay.endpoint_data, RelayEndpointData::Wireguard(..)) {
             return None;
         }
+        // Exclude relays that cannot be reached using the IP version
+        self.get_address_for_wireguard_relay(relay)?;
         Some(relay.clone())
     }
 
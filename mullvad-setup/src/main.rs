@@ -1,6 +1,6 @@
 use clap::{crate_authors, crate_description, crate_name, App};
 use mullvad_api::{self, proxy::ApiConnectionMode};
-use mullvad_management_interface::new_rpc_client;
+use mullvad_management_interface::{call_with_timeout, new_rpc_client};
 use mullvad_types::version::ParsedAppVersion;
 use std::{path::PathBuf, process, str::FromStr, time::Duration};
 use talpid_core::{
@@ -17,6 +17,9 @@ lazy_static::lazy_static! {
 const KEY_RETRY_INTERVAL: Duration = Duration::ZERO;
 const KEY_RETRY_MAX_RETRIES: usize = 4;
 
+/// How long to wait for the daemon to prepare for a restart.
+const PREPARE_RESTART_TIMEOUT: Duration = Duration::from_secs(10);
+
 #[repr(i32)]
 enum ExitStatus {
     Ok = 0,
@@ -142,7 +145,7 @@ async fn is_older_version(old_version: &str) -> Result<ExitStatus, Error> {
 
 async fn prepare_restart() -> Result<(), Error> {
     let mut rpc = new_rpc_client().await.map_err(Error::RpcConnectionError)?;
-    rpc.prepare_restart(())
+    call_with_timeout(PREPARE_RESTART_TIMEOUT, rpc.prepare_restart(()))
         .await
         .map_err(Error::DaemonRpcError)?;
     Ok(())
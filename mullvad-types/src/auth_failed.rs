@@ -15,13 +15,20 @@ enum AuthFailedInner {
     InvalidAccount,
     ExpiredAccount,
     TooManyConnectons,
+    DeviceRevoked,
     Unknown(String, String),
 }
 
+/// Reason used by the daemon when it blocks the tunnel because the device was removed from the
+/// account, e.g. from another device.
+pub const DEVICE_REVOKED_REASON: &str = "[DEVICE_REVOKED] The device has been revoked";
+
 // These strings should match up with gui/packages/desktop/src/renderer/lib/auth-failure.js
 const INVALID_ACCOUNT_MSG: &str = "You've logged in with an account number that is not valid. Please log out and try another one.";
 const EXPIRED_ACCOUNT_MSG: &str = "You have no more VPN time left on this account. Please log in on our website to buy more credit.";
 const TOO_MANY_CONNECTIONS_MSG: &str = "This account has too many simultaneous connections. Disconnect another device or try connecting again shortly.";
+const DEVICE_REVOKED_MSG: &str =
+    "This device has been removed from the account. Please log in again to keep using the app.";
 
 impl<'a> From<&'a str> for AuthFailedInner {
     fn from(reason: &'a str) -> AuthFailedInner {
@@ -30,6 +37,7 @@ impl<'a> From<&'a str> for AuthFailedInner {
             Some(("INVALID_ACCOUNT", _)) => InvalidAccount,
             Some(("EXPIRED_ACCOUNT", _)) => ExpiredAccount,
             Some(("TOO_MANY_CONNECTIONS", _)) => TooManyConnectons,
+            Some(("DEVICE_REVOKED", _)) => DeviceRevoked,
             Some((unknown_reason, message)) => {
                 log::warn!(
                     "Received AUTH_FAILED message with unknown reason: {}",
@@ -60,6 +68,7 @@ impl fmt::Display for AuthFailed {
             InvalidAccount => write!(f, "{}", INVALID_ACCOUNT_MSG),
             ExpiredAccount => write!(f, "{}", EXPIRED_ACCOUNT_MSG),
             TooManyConnectons => write!(f, "{}", TOO_MANY_CONNECTIONS_MSG),
+            DeviceRevoked => write!(f, "{}", DEVICE_REVOKED_MSG),
             Unknown(_, ref reason) => write!(f, "{}", reason),
         }
     }
@@ -0,0 +1,12 @@
+use chrono::Duration;
+
+/// A problem with the system clock that was detected while communicating with the API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockSkew {
+    /// The system clock is this far ahead of the clock of the API, according to the `Date` header
+    /// of its responses. A negative value means that the system clock is behind.
+    Measured(Duration),
+    /// The API certificate was rejected because the system time is outside of its validity
+    /// period. Connections to the API fail until the clock is corrected.
+    CertificateNotValid,
+}
@@ -0,0 +1,38 @@
+//! Programs that the daemon runs when the tunnel state changes, e.g. to remount network shares
+//! once the tunnel is up.
+
+use serde::{Deserialize, Serialize};
+use std::{fmt, path::PathBuf};
+
+/// Tunnel state that a hook is run for. The hook is run every time the state is entered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookEvent {
+    Connected,
+    Disconnected,
+    Error,
+}
+
+impl fmt::Display for HookEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let event = match self {
+            HookEvent::Connected => "connected",
+            HookEvent::Disconnected => "disconnected",
+            HookEvent::Error => "error",
+        };
+        f.write_str(event)
+    }
+}
+
+/// A program to run when the tunnel state changes.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct Hook {
+    pub event: HookEvent,
+    /// Absolute path of the program. It is run directly rather than through a shell, and as the
+    /// user that owns it.
+    pub program: PathBuf,
+    /// User ID of the client that added the hook. Unless this is root, the program is only run if
+    /// it is owned by this user. It is `None` for hooks that were added to the settings directly.
+    #[serde(default)]
+    pub added_by: Option<u32>,
+}
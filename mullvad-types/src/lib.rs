@@ -2,8 +2,10 @@
 
 pub mod account;
 pub mod auth_failed;
+pub mod clock_skew;
 pub mod device;
 pub mod endpoint;
+pub mod hooks;
 pub mod location;
 pub mod relay_constraints;
 pub mod relay_list;
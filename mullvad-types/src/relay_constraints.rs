@@ -3,7 +3,7 @@
 
 use crate::{
     location::{CityCode, CountryCode, Hostname},
-    relay_list::Relay,
+    relay_list::{Relay, RelayEndpointData},
     CustomTunnelEndpoint,
 };
 #[cfg(target_os = "android")]
@@ -172,6 +172,25 @@ impl RelaySettings {
             }),
         }
     }
+
+    /// Returns false if the relay settings explicitly do not allow for bridging (i.e. use UDP
+    /// instead of TCP)
+    pub fn supports_bridge(&self) -> bool {
+        match self {
+            RelaySettings::CustomTunnelEndpoint(endpoint) => {
+                endpoint.endpoint().protocol == TransportProtocol::Tcp
+            }
+            RelaySettings::Normal(constraints) => !matches!(
+                constraints.openvpn_constraints,
+                OpenVpnConstraints {
+                    port: Constraint::Only(TransportPort {
+                        protocol: TransportProtocol::Udp,
+                        ..
+                    })
+                }
+            ),
+        }
+    }
 }
 
 /// Limits the set of [`crate::relay_list::Relay`]s that a `RelaySelector` may select.
@@ -401,6 +420,34 @@ impl fmt::Display for Providers {
     }
 }
 
+/// Filters used to look up relays in the relay list, e.g. so that the user can pick one
+/// manually.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct RelayQuery {
+    pub location: Constraint<LocationConstraint>,
+    pub ownership: Constraint<Ownership>,
+    /// Only relays that can be used with this tunnel protocol match. Bridges never match a
+    /// specific tunnel protocol.
+    pub tunnel_protocol: Constraint<TunnelType>,
+    /// Number of the matching relays to estimate the latency to.
+    pub probe_count: u32,
+}
+
+impl Match<Relay> for RelayQuery {
+    fn matches(&self, relay: &Relay) -> bool {
+        let supports_tunnel_protocol = match self.tunnel_protocol {
+            Constraint::Any => true,
+            Constraint::Only(TunnelType::OpenVpn) => {
+                relay.endpoint_data == RelayEndpointData::Openvpn
+            }
+            Constraint::Only(TunnelType::Wireguard) => {
+                matches!(relay.endpoint_data, RelayEndpointData::Wireguard(_))
+            }
+        };
+        supports_tunnel_protocol && self.location.matches(relay) && self.ownership.matches(relay)
+    }
+}
+
 impl fmt::Display for LocationConstraint {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         match self {
@@ -489,6 +536,7 @@ pub enum SelectedObfuscation {
     #[default]
     Off,
     Udp2Tcp,
+    Tls,
 }
 
 impl fmt::Display for SelectedObfuscation {
@@ -497,6 +545,7 @@ impl fmt::Display for SelectedObfuscation {
             SelectedObfuscation::Auto => "auto".fmt(f),
             SelectedObfuscation::Off => "off".fmt(f),
             SelectedObfuscation::Udp2Tcp => "udp2tcp".fmt(f),
+            SelectedObfuscation::Tls => "tls".fmt(f),
         }
     }
 }
@@ -516,6 +565,24 @@ impl fmt::Display for Udp2TcpObfuscationSettings {
     }
 }
 
+#[derive(Default, Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[serde(default)]
+pub struct TlsObfuscationSettings {
+    /// Server name to send in the TLS handshake. A name derived from the hostname of the relay is
+    /// used if this is not set.
+    pub server_name: Option<String>,
+}
+
+impl fmt::Display for TlsObfuscationSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.server_name {
+            Some(ref server_name) => write!(f, "server name {}", server_name),
+            None => write!(f, "default server name"),
+        }
+    }
+}
+
 /// Contains obfuscation settings
 #[derive(Default, Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
@@ -523,6 +590,7 @@ impl fmt::Display for Udp2TcpObfuscationSettings {
 pub struct ObfuscationSettings {
     pub selected_obfuscation: SelectedObfuscation,
     pub udp2tcp: Udp2TcpObfuscationSettings,
+    pub tls: TlsObfuscationSettings,
 }
 
 /// Limits the set of bridge servers to use in `mullvad-daemon`.
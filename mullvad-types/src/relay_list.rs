@@ -1,11 +1,18 @@
-use crate::location::{CityCode, CountryCode, Location};
+use crate::{
+    location::{CityCode, CountryCode, Location},
+    relay_constraints::Constraint,
+};
 #[cfg(target_os = "android")]
 use jnix::IntoJava;
 use serde::{Deserialize, Serialize};
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
 use talpid_types::net::{
+    obfuscation::CertificateFingerprint,
     openvpn::{ProxySettings, ShadowsocksProxySettings},
-    wireguard, TransportProtocol,
+    wireguard, IpVersion, TransportProtocol,
 };
 
 /// Stores a list of relays for each country obtained from the API using
@@ -63,8 +70,9 @@ pub struct RelayListCity {
 #[cfg_attr(target_os = "android", jnix(package = "net.mullvad.mullvadvpn.model"))]
 pub struct Relay {
     pub hostname: String,
+    /// The IPv4 address of the relay. Relays that can only be reached over IPv6 do not have one.
     #[cfg_attr(target_os = "android", jnix(skip))]
-    pub ipv4_addr_in: Ipv4Addr,
+    pub ipv4_addr_in: Option<Ipv4Addr>,
     #[cfg_attr(target_os = "android", jnix(skip))]
     pub ipv6_addr_in: Option<Ipv6Addr>,
     #[cfg_attr(target_os = "android", jnix(skip))]
@@ -81,6 +89,29 @@ pub struct Relay {
     pub location: Option<Location>,
 }
 
+impl Relay {
+    /// Returns the address to connect to the relay on using the given IP version. If any IP
+    /// version is allowed, IPv4 is preferred.
+    pub fn addr_in(&self, ip_version: Constraint<IpVersion>) -> Option<IpAddr> {
+        let ipv4 = self.ipv4_addr_in.map(IpAddr::from);
+        let ipv6 = self.ipv6_addr_in.map(IpAddr::from);
+        match ip_version {
+            Constraint::Any => ipv4.or(ipv6),
+            Constraint::Only(IpVersion::V4) => ipv4,
+            Constraint::Only(IpVersion::V6) => ipv6,
+        }
+    }
+}
+
+/// A relay that matched a [`crate::relay_constraints::RelayQuery`].
+#[derive(Debug, Clone)]
+pub struct RelayQueryMatch {
+    pub relay: Relay,
+    /// Estimated round-trip time to the relay. `None` if the relay was not probed or did not
+    /// respond in time.
+    pub latency: Option<Duration>,
+}
+
 /// Specifies the type of a relay or relay-specific endpoint data.
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
@@ -147,6 +178,10 @@ impl Default for WireguardEndpointData {
 pub struct WireguardRelayEndpointData {
     /// Public key used by the relay peer
     pub public_key: wireguard::PublicKey,
+    /// Fingerprint of the certificate that the relay presents to TLS obfuscation clients. Only
+    /// relays that have one support TLS obfuscation.
+    #[serde(default)]
+    pub tls_certificate: Option<CertificateFingerprint>,
 }
 
 #[derive(Debug, Default, Clone, Deserialize, Serialize)]
@@ -1,4 +1,5 @@
 use crate::{
+    hooks::Hook,
     relay_constraints::{
         BridgeConstraints, BridgeSettings, BridgeState, Constraint, LocationConstraint,
         ObfuscationSettings, RelayConstraints, RelaySettings, RelaySettingsUpdate,
@@ -6,15 +7,23 @@ use crate::{
     },
     wireguard,
 };
+use ipnetwork::IpNetwork;
 #[cfg(target_os = "android")]
 use jnix::IntoJava;
 use rand::Rng;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 #[cfg(target_os = "windows")]
 use std::{collections::HashSet, path::PathBuf};
-use talpid_types::net::{self, openvpn, GenericTunnelOptions};
+use talpid_types::{
+    net::{
+        self, openvpn, route_takeover::RouteTakeoverPolicy, BlockedTunnelProtocols,
+        GenericTunnelOptions, VpnCoexistence,
+    },
+    tunnel::{ErrorStatePolicy, ReconnectLimits},
+};
 
 mod dns;
+mod profile;
 
 /// The version used by the current version of the code. Should always be the
 /// latest version that exists in `SettingsVersion`.
@@ -79,6 +88,48 @@ pub struct Settings {
     /// the firewall to not allow any traffic in or out.
     #[cfg_attr(target_os = "android", jnix(skip))]
     pub block_when_disconnected: bool,
+    /// Flush the system DNS cache after the DNS configuration has been changed or reset. On
+    /// Windows, the cache is always flushed.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub flush_dns_cache: bool,
+    /// Networks that traffic is allowed to and from while in the error state. This can be used to
+    /// keep a remote machine reachable, at the cost of not blocking all traffic in that state.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub recovery_allowlist: Vec<IpNetwork>,
+    /// Traffic of another VPN running alongside this one, such as a WireGuard mesh, that is not
+    /// blocked and is kept out of the tunnel. This is narrower than disabling the kill switch.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub vpn_coexistence: VpnCoexistence,
+    /// Protocols that are blocked inside the tunnel while connected, for restricting what may
+    /// leave through the VPN.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub blocked_tunnel_protocols: BlockedTunnelProtocols,
+    /// Physical interface, such as an Ethernet adapter, that traffic to the relay must leave
+    /// through. If unset, the interface of the best default route is used. Only WireGuard
+    /// tunnels are affected.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub forced_interface: Option<String>,
+    /// Maximum number of consecutive attempts to reconnect for each cause of a failed attempt,
+    /// after which traffic is blocked until the user connects again or the network changes.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub reconnect_limits: ReconnectLimits,
+    /// What to do when another VPN takes over the default route while connected.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub route_takeover_policy: RouteTakeoverPolicy,
+    /// Which traffic to block in the error state. Blocking LAN traffic is not supported on
+    /// Android.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub error_state_policy: ErrorStatePolicy,
+    /// Whether to keep statistics about the outcomes of connection attempts, which can be
+    /// attached to problem reports. Nothing that identifies the user or the relays is recorded.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub connection_stats: bool,
+    /// Programs to run when the tunnel state changes. Hooks are not run on Windows or Android.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub hooks: Vec<Hook>,
+    /// Saved sets of settings that can be applied at once.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub profiles: Vec<Profile>,
     /// If the daemon should connect the VPN tunnel directly on start or not.
     pub auto_connect: bool,
     /// Options that should be applied to tunnels of a specific type regardless of where the relays
@@ -107,7 +158,7 @@ fn out_of_range_wg_migration_rand_num() -> f32 {
 }
 
 #[cfg(windows)]
-#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
 pub struct SplitTunnelSettings {
     /// Toggles split tunneling on or off
     pub enable_exclusions: bool,
@@ -134,6 +185,17 @@ impl Default for Settings {
             bridge_state: BridgeState::Auto,
             allow_lan: false,
             block_when_disconnected: false,
+            flush_dns_cache: false,
+            recovery_allowlist: vec![],
+            vpn_coexistence: VpnCoexistence::default(),
+            blocked_tunnel_protocols: BlockedTunnelProtocols::default(),
+            forced_interface: None,
+            reconnect_limits: ReconnectLimits::default(),
+            route_takeover_policy: RouteTakeoverPolicy::default(),
+            error_state_policy: ErrorStatePolicy::default(),
+            connection_stats: false,
+            hooks: vec![],
+            profiles: vec![],
             auto_connect: false,
             tunnel_options: TunnelOptions::default(),
             show_beta_releases: false,
@@ -206,6 +268,8 @@ pub struct TunnelOptions {
     pub dns_options: DnsOptions,
 }
 
+pub use profile::{Profile, MAX_PROFILES};
+
 pub use dns::{CustomDnsOptions, DefaultDnsOptions, DnsOptions, DnsState};
 
 #[cfg(target_os = "android")]
@@ -222,6 +286,7 @@ impl Default for TunnelOptions {
             generic: GenericTunnelOptions {
                 // Enable IPv6 be default on Android
                 enable_ipv6: cfg!(target_os = "android"),
+                enable_ipv4: true,
             },
             dns_options: DnsOptions::default(),
         }
@@ -0,0 +1,138 @@
+#[cfg(windows)]
+use super::SplitTunnelSettings;
+use super::{DnsOptions, Settings};
+use crate::relay_constraints::{BridgeState, ObfuscationSettings, RelaySettings};
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of profiles that can be saved.
+pub const MAX_PROFILES: usize = 64;
+
+/// A named set of settings that can be applied at once, e.g. to switch between relay locations
+/// and DNS servers for different tasks without changing each setting separately.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Profile {
+    /// Name that identifies the profile.
+    pub name: String,
+    pub relay_settings: RelaySettings,
+    pub obfuscation_settings: ObfuscationSettings,
+    pub dns_options: DnsOptions,
+    #[cfg(windows)]
+    pub split_tunnel: SplitTunnelSettings,
+}
+
+impl Profile {
+    /// Creates a profile from the current values of `settings`.
+    pub fn from_settings(name: String, settings: &Settings) -> Self {
+        Profile {
+            name,
+            relay_settings: settings.relay_settings.clone(),
+            obfuscation_settings: settings.obfuscation_settings.clone(),
+            dns_options: settings.tunnel_options.dns_options.clone(),
+            #[cfg(windows)]
+            split_tunnel: settings.split_tunnel.clone(),
+        }
+    }
+}
+
+impl Settings {
+    /// Returns the saved profile named `name`, if there is one.
+    pub fn get_profile(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+
+    /// Saves `profile`, replacing any profile with the same name. Returns whether the saved
+    /// profiles changed.
+    pub fn save_profile(&mut self, profile: Profile) -> bool {
+        match self
+            .profiles
+            .iter_mut()
+            .find(|existing| existing.name == profile.name)
+        {
+            Some(existing) if *existing == profile => false,
+            Some(existing) => {
+                *existing = profile;
+                true
+            }
+            None => {
+                self.profiles.push(profile);
+                true
+            }
+        }
+    }
+
+    /// Removes the profile named `name`. Returns whether it existed.
+    pub fn remove_profile(&mut self, name: &str) -> bool {
+        let num_profiles = self.profiles.len();
+        self.profiles.retain(|profile| profile.name != name);
+        self.profiles.len() != num_profiles
+    }
+
+    /// Replaces the settings that are part of `profile` with its values. Returns whether any
+    /// setting changed.
+    pub fn apply_profile(&mut self, profile: &Profile) -> bool {
+        let before = Profile::from_settings(profile.name.clone(), self);
+        if before == *profile {
+            return false;
+        }
+
+        if self.relay_settings != profile.relay_settings {
+            log::debug!(
+                "Changing relay settings:\n\tfrom: {}\n\tto: {}",
+                self.relay_settings,
+                profile.relay_settings
+            );
+            self.relay_settings = profile.relay_settings.clone();
+            if !self.relay_settings.supports_bridge() && self.bridge_state == BridgeState::On {
+                self.bridge_state = BridgeState::Auto;
+            }
+        }
+        self.obfuscation_settings = profile.obfuscation_settings.clone();
+        self.tunnel_options.dns_options = profile.dns_options.clone();
+        #[cfg(windows)]
+        {
+            self.split_tunnel = profile.split_tunnel.clone();
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::relay_constraints::{
+        Constraint, LocationConstraint, RelayConstraints, SelectedObfuscation,
+    };
+
+    #[test]
+    fn test_apply_profile() {
+        let mut settings = Settings::default();
+        let mut profile = Profile::from_settings("work".to_owned(), &settings);
+        assert!(!settings.apply_profile(&profile));
+
+        profile.relay_settings = RelaySettings::Normal(RelayConstraints {
+            location: Constraint::Only(LocationConstraint::Country("de".to_owned())),
+            ..Default::default()
+        });
+        profile.obfuscation_settings.selected_obfuscation = SelectedObfuscation::Udp2Tcp;
+        assert!(settings.apply_profile(&profile));
+        assert_eq!(settings.get_relay_settings(), profile.relay_settings);
+        assert_eq!(
+            settings.obfuscation_settings.selected_obfuscation,
+            SelectedObfuscation::Udp2Tcp
+        );
+        assert!(!settings.apply_profile(&profile));
+    }
+
+    #[test]
+    fn test_save_and_remove_profile() {
+        let mut settings = Settings::default();
+        let profile = Profile::from_settings("home".to_owned(), &settings);
+        assert!(settings.save_profile(profile.clone()));
+        assert!(!settings.save_profile(profile.clone()));
+        assert_eq!(settings.get_profile("home"), Some(&profile));
+
+        assert!(settings.remove_profile("home"));
+        assert!(!settings.remove_profile("home"));
+        assert!(settings.profiles.is_empty());
+    }
+}
@@ -1,11 +1,12 @@
 use crate::location::GeoIpLocation;
+use chrono::{DateTime, Utc};
 #[cfg(target_os = "android")]
 use jnix::IntoJava;
 use serde::{Deserialize, Serialize};
 use std::fmt;
 use talpid_types::{
     net::TunnelEndpoint,
-    tunnel::{ActionAfterDisconnect, ErrorState},
+    tunnel::{ActionAfterDisconnect, DisconnectCause, DisconnectedSecurity, ErrorState},
 };
 
 /// Represents the state the client strives towards.
@@ -34,7 +35,12 @@ impl fmt::Display for TargetState {
 #[cfg_attr(target_os = "android", derive(IntoJava))]
 #[cfg_attr(target_os = "android", jnix(package = "net.mullvad.mullvadvpn.model"))]
 pub enum TunnelState {
-    Disconnected,
+    Disconnected {
+        #[cfg_attr(target_os = "android", jnix(skip))]
+        cause: Option<DisconnectCause>,
+        #[cfg_attr(target_os = "android", jnix(skip))]
+        security: DisconnectedSecurity,
+    },
     Connecting {
         endpoint: TunnelEndpoint,
         location: Option<GeoIpLocation>,
@@ -45,6 +51,10 @@ pub enum TunnelState {
     },
     Disconnecting(ActionAfterDisconnect),
     Error(ErrorState),
+    Paused {
+        endpoint: TunnelEndpoint,
+        location: Option<GeoIpLocation>,
+    },
 }
 
 impl TunnelState {
@@ -60,6 +70,71 @@ impl TunnelState {
 
     /// Returns true if the tunnel state is in the disconnected state.
     pub fn is_disconnected(&self) -> bool {
-        matches!(self, TunnelState::Disconnected)
+        matches!(self, TunnelState::Disconnected { .. })
+    }
+
+    /// Returns true if the tunnel state is in the paused state.
+    pub fn is_paused(&self) -> bool {
+        matches!(self, TunnelState::Paused { .. })
+    }
+
+    /// Returns true if traffic outside of the tunnel is blocked, or the tunnel is up.
+    pub fn is_protected(&self) -> bool {
+        match self {
+            TunnelState::Disconnected { security, .. } => security.is_blocked(),
+            TunnelState::Error(error_state) => error_state.is_blocking(),
+            TunnelState::Connecting { .. }
+            | TunnelState::Connected { .. }
+            | TunnelState::Disconnecting(..)
+            | TunnelState::Paused { .. } => true,
+        }
+    }
+}
+
+/// Describes a period during which the network may have been unprotected even though the tunnel
+/// was supposed to be secured, because the daemon or the system stopped.
+///
+/// The firewall may remain in place while the daemon is not running, e.g. if it was stopped
+/// cleanly while secured, so the period is an upper bound.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProtectionGapReport {
+    /// Whether the daemon stopped without shutting down cleanly, e.g. because it crashed or the
+    /// system lost power.
+    pub unclean_shutdown: bool,
+    /// Whether the system was restarted in between. `None` if this is unknown on the platform.
+    pub system_rebooted: Option<bool>,
+    /// When the network may have become unprotected.
+    pub unprotected_since: DateTime<Utc>,
+    /// When the network was protected again, or `None` if it has not been protected since.
+    pub protected_again_at: Option<DateTime<Utc>>,
+}
+
+impl ProtectionGapReport {
+    /// Returns how long the network may have been unprotected, or `None` if it still is.
+    pub fn duration(&self) -> Option<chrono::Duration> {
+        self.protected_again_at
+            .map(|protected_again_at| protected_again_at - self.unprotected_since)
+    }
+}
+
+impl fmt::Display for ProtectionGapReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.system_rebooted, self.unclean_shutdown) {
+            (Some(true), _) => write!(f, "System rebooted while secured")?,
+            (_, true) => write!(f, "Daemon stopped unexpectedly while secured")?,
+            (_, false) => write!(f, "Daemon stopped while secured")?,
+        }
+        match self.duration() {
+            Some(duration) => write!(
+                f,
+                ", network unprotected for {} seconds until reconnect",
+                duration.num_seconds().max(0)
+            ),
+            None => write!(
+                f,
+                ", network unprotected since {}",
+                self.unprotected_since.to_rfc3339()
+            ),
+        }
     }
 }
@@ -18,6 +18,7 @@ mod win {
         let target_dir = match target.as_str() {
             "i686-pc-windows-msvc" => format!("Win32-{}", get_build_mode()),
             "x86_64-pc-windows-msvc" => format!("x64-{}", get_build_mode()),
+            "aarch64-pc-windows-msvc" => format!("ARM64-{}", get_build_mode()),
             _ => panic!("uncrecognized target: {}", target),
         };
         target_dir.into()
@@ -52,7 +53,8 @@ fn main() {
     const WINNET_DIR_VAR: &str = "WINNET_LIB_DIR";
     declare_library(WINFW_DIR_VAR, WINFW_BUILD_DIR, "winfw");
     declare_library(WINNET_DIR_VAR, WINNET_BUILD_DIR, "winnet");
-    let lib_dir = manifest_dir().join("../build/lib/x86_64-pc-windows-msvc");
+    let target = env::var("TARGET").expect("TARGET env var not set");
+    let lib_dir = manifest_dir().join("../build/lib").join(target);
     println!("cargo:rustc-link-search={}", &lib_dir.display());
     println!("cargo:rustc-link-lib=dylib=libwg");
 }
@@ -0,0 +1,205 @@
+//! Runs the tunnel state machine with a custom WireGuard tunnel provider instead of the built-in
+//! backends. The mock tunnel does not forward any traffic, but reports traffic counters that keep
+//! increasing, which is what the connectivity check looks at.
+//!
+//! The state machine still applies firewall rules, routes and DNS settings, so this must be run
+//! as root, preferably in a throwaway VM or network namespace:
+//!
+//! ```text
+//! cargo run -p talpid-core --example mock_tunnel
+//! ```
+
+#[cfg(target_os = "linux")]
+mod mock {
+    use futures::{channel::mpsc, StreamExt};
+    use std::{
+        future::Future,
+        net::{Ipv4Addr, SocketAddr},
+        pin::Pin,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+    use talpid_core::{
+        firewall::plugin::PolicyFragment,
+        tunnel::wireguard::{config::Config, Stats, StatsMap, Tunnel, TunnelError, TunnelProvider},
+        tunnel_state_machine::{self, InitialTunnelState, TunnelParametersGenerator},
+    };
+    use talpid_types::{
+        net::{
+            route_takeover::RouteTakeoverPolicy,
+            wireguard::{
+                self, ConnectionConfig, PeerConfig, PrivateKey, TunnelConfig, TunnelOptions,
+            },
+            AllowedEndpoint, BlockedTunnelProtocols, GenericTunnelOptions, TunnelParameters,
+            VpnCoexistence,
+        },
+        tunnel::{DisconnectCause, ErrorStatePolicy, ParameterGenerationError, ReconnectLimits},
+    };
+
+    /// Tunnel that drops all traffic, but reports ever increasing traffic counters so that the
+    /// connectivity check succeeds.
+    struct MockTunnel {
+        peers: Vec<[u8; 32]>,
+        counter: AtomicU64,
+    }
+
+    impl Tunnel for MockTunnel {
+        fn get_interface_name(&self) -> String {
+            "lo".to_owned()
+        }
+
+        fn stop(self: Box<Self>) -> Result<(), TunnelError> {
+            println!("Mock tunnel stopped");
+            Ok(())
+        }
+
+        fn get_tunnel_stats(&self) -> Result<StatsMap, TunnelError> {
+            let bytes = self.counter.fetch_add(1024, Ordering::Relaxed);
+            Ok(self
+                .peers
+                .iter()
+                .map(|peer| {
+                    let stats = Stats {
+                        tx_bytes: bytes,
+                        rx_bytes: bytes,
+                    };
+                    (*peer, stats)
+                })
+                .collect())
+        }
+
+        fn set_config(
+            &self,
+            _config: Config,
+        ) -> Pin<Box<dyn Future<Output = Result<(), TunnelError>> + Send>> {
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    struct MockTunnelProvider;
+
+    impl TunnelProvider for MockTunnelProvider {
+        fn open_tunnel(&self, config: &Config) -> Result<Box<dyn Tunnel>, TunnelError> {
+            println!("Opening mock tunnel to {}", config.peers[0].endpoint);
+            Ok(Box::new(MockTunnel {
+                peers: config
+                    .peers
+                    .iter()
+                    .map(|peer| *peer.public_key.as_bytes())
+                    .collect(),
+                counter: AtomicU64::new(0),
+            }))
+        }
+    }
+
+    /// Always connects to the same made up relay.
+    struct StaticParametersGenerator;
+
+    impl TunnelParametersGenerator for StaticParametersGenerator {
+        fn generate(
+            &mut self,
+            _retry_attempt: u32,
+        ) -> Pin<Box<dyn Future<Output = Result<TunnelParameters, ParameterGenerationError>>>>
+        {
+            let parameters = TunnelParameters::Wireguard(wireguard::TunnelParameters {
+                connection: ConnectionConfig {
+                    tunnel: TunnelConfig {
+                        private_key: PrivateKey::new_from_random(),
+                        addresses: vec![Ipv4Addr::new(10, 64, 0, 2).into()],
+                    },
+                    peer: PeerConfig {
+                        public_key: PrivateKey::new_from_random().public_key(),
+                        allowed_ips: vec!["0.0.0.0/0".parse().unwrap()],
+                        endpoint: SocketAddr::new(Ipv4Addr::new(192, 0, 2, 1).into(), 51820),
+                        psk: None,
+                        psk_required: false,
+                    },
+                    exit_peer: None,
+                    ipv4_gateway: Ipv4Addr::new(10, 64, 0, 1),
+                    ipv6_gateway: None,
+                },
+                options: TunnelOptions::default(),
+                generic_options: GenericTunnelOptions {
+                    enable_ipv6: false,
+                    enable_ipv4: true,
+                },
+                obfuscation: None,
+            });
+            Box::pin(async move { Ok(parameters) })
+        }
+    }
+
+    pub fn run() {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("Failed to create runtime");
+
+        runtime.block_on(async {
+            let (state_tx, mut state_rx) = mpsc::unbounded();
+            let (offline_tx, _offline_rx) = mpsc::unbounded();
+            let (physical_interface_tx, _physical_interface_rx) = mpsc::unbounded();
+            let (route_takeover_tx, _route_takeover_rx) = mpsc::unbounded();
+            let (performance_warning_tx, _performance_warning_rx) = mpsc::unbounded();
+
+            let handle = tunnel_state_machine::spawn(
+                InitialTunnelState {
+                    allow_lan: false,
+                    block_when_disconnected: false,
+                    dns_servers: None,
+                    allowed_endpoint: AllowedEndpoint {
+                        endpoints: vec![],
+                        hostname: None,
+                    },
+                    reset_firewall: true,
+                    flush_dns_cache: false,
+                    recovery_allowlist: vec![],
+                    vpn_coexistence: VpnCoexistence::default(),
+                    blocked_tunnel_protocols: BlockedTunnelProtocols::default(),
+                    forced_interface: None,
+                    reconnect_limits: ReconnectLimits::default(),
+                    route_takeover_policy: RouteTakeoverPolicy::default(),
+                    error_state_policy: ErrorStatePolicy::default(),
+                    wireguard_tunnel_provider: Some(Arc::new(MockTunnelProvider)),
+                    firewall_plugins: PolicyFragment::default(),
+                },
+                StaticParametersGenerator,
+                None,
+                std::env::temp_dir(),
+                std::env::temp_dir(),
+                state_tx,
+                offline_tx,
+                physical_interface_tx,
+                route_takeover_tx,
+                performance_warning_tx,
+            )
+            .await
+            .expect("Failed to start the tunnel state machine");
+
+            handle.connect().unwrap();
+
+            let print_transitions = async {
+                while let Some(transition) = state_rx.next().await {
+                    println!("Tunnel state: {:?}", transition);
+                }
+            };
+            let _ = tokio::time::timeout(Duration::from_secs(30), print_transitions).await;
+
+            handle.disconnect(DisconnectCause::UserInitiated).unwrap();
+            handle.try_join().await;
+        });
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn main() {
+    mock::run();
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("This example only runs on Linux");
+}
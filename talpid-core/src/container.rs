@@ -0,0 +1,211 @@
+//! Support for running the daemon inside a container, such as Docker or Podman.
+//!
+//! A container usually runs in its own network namespace with a reduced set of privileges. The
+//! firewall rules and routes that the daemon adds then only affect that namespace, which is the
+//! point of running the tunnel in a container. Some things that the daemon manages on the host
+//! are unavailable, however: there is typically no D-Bus to reach a DNS manager over, `/proc/sys`
+//! is read-only and cgroup filesystems cannot be mounted. Container mode adapts to this:
+//!
+//! * DNS is configured by writing `/etc/resolv.conf` directly, unless `TALPID_DNS_MODULE` says
+//!   otherwise.
+//! * Failing to set sysctls is expected and not reported as an error.
+//! * Split tunneling is unavailable instead of preventing the daemon from starting.
+//!
+//! The capabilities the daemon cannot do without are checked at startup by
+//! [`check_requirements`], so that a missing `--cap-add` or `--device` flag results in an error
+//! that says what to add.
+
+use std::{
+    env, fmt, fs,
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+
+/// Environment variable that selects whether container mode is used. `"1"` forces it on, `"0"`
+/// forces it off. By default, container mode is used if a container runtime is detected.
+const CONTAINER_MODE_ENV_VAR: &str = "TALPID_CONTAINER_MODE";
+
+const TUN_DEVICE_PATH: &str = "/dev/net/tun";
+
+/// Bit of `CAP_NET_ADMIN` in the capability sets in `/proc/<pid>/status`.
+const CAP_NET_ADMIN: u32 = 12;
+
+lazy_static::lazy_static! {
+    static ref CONTAINER_RUNTIME: Option<ContainerRuntime> = {
+        let runtime = match env::var(CONTAINER_MODE_ENV_VAR).as_deref() {
+            Ok("0") => None,
+            Ok("1") => Some(detect_runtime().unwrap_or(ContainerRuntime::Unknown)),
+            _ => detect_runtime(),
+        };
+        if let Some(runtime) = &runtime {
+            log::info!("Running in container mode ({})", runtime);
+        }
+        runtime
+    };
+}
+
+/// Errors caused by the container lacking something that the daemon requires.
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum RequirementError {
+    /// The process does not have the `CAP_NET_ADMIN` capability.
+    #[error(
+        display = "The container lacks the NET_ADMIN capability. Start it with --cap-add=NET_ADMIN"
+    )]
+    MissingNetAdmin,
+
+    /// The capabilities of the process could not be determined.
+    #[error(display = "Failed to read the capabilities of the daemon")]
+    ReadCapabilities(#[error(source)] io::Error),
+
+    /// The TUN device node is missing.
+    #[error(
+        display = "{} does not exist in the container. Start it with --device={}",
+        TUN_DEVICE_PATH,
+        TUN_DEVICE_PATH
+    )]
+    MissingTunDevice,
+
+    /// The TUN device node exists but cannot be opened.
+    #[error(
+        display = "{} cannot be opened. Make sure that the device cgroup of the container allows it",
+        TUN_DEVICE_PATH
+    )]
+    TunDeviceUnavailable(#[error(source)] io::Error),
+}
+
+/// Container runtimes that can be detected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContainerRuntime {
+    /// Docker or another runtime that creates `/.dockerenv`.
+    Docker,
+    /// Podman.
+    Podman,
+    /// Kubernetes.
+    Kubernetes,
+    /// A runtime that was named in the `container` environment variable.
+    Other(String),
+    /// Container mode was forced on, but no runtime was detected.
+    Unknown,
+}
+
+impl fmt::Display for ContainerRuntime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContainerRuntime::Docker => f.write_str("Docker"),
+            ContainerRuntime::Podman => f.write_str("Podman"),
+            ContainerRuntime::Kubernetes => f.write_str("Kubernetes"),
+            ContainerRuntime::Other(name) => f.write_str(name),
+            ContainerRuntime::Unknown => f.write_str("unknown runtime"),
+        }
+    }
+}
+
+/// Returns the container runtime that the daemon is running in, if container mode is used.
+pub fn runtime() -> Option<&'static ContainerRuntime> {
+    CONTAINER_RUNTIME.as_ref()
+}
+
+/// Returns whether container mode is used.
+pub fn is_enabled() -> bool {
+    runtime().is_some()
+}
+
+/// Checks that the container grants the daemon what it needs to create a tunnel and manage the
+/// firewall.
+pub fn check_requirements() -> Result<(), RequirementError> {
+    let status =
+        fs::read_to_string("/proc/self/status").map_err(RequirementError::ReadCapabilities)?;
+    if !has_effective_capability(&status, CAP_NET_ADMIN) {
+        return Err(RequirementError::MissingNetAdmin);
+    }
+
+    match fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(TUN_DEVICE_PATH)
+    {
+        Ok(_) => Ok(()),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            Err(RequirementError::MissingTunDevice)
+        }
+        Err(error) => Err(RequirementError::TunDeviceUnavailable(error)),
+    }
+}
+
+fn detect_runtime() -> Option<ContainerRuntime> {
+    // Set by Podman, systemd-nspawn and LXC, among others
+    if let Ok(name) = env::var("container") {
+        return Some(match name.as_str() {
+            "podman" => ContainerRuntime::Podman,
+            "docker" => ContainerRuntime::Docker,
+            _ => ContainerRuntime::Other(name),
+        });
+    }
+    if Path::new("/run/.containerenv").exists() {
+        return Some(ContainerRuntime::Podman);
+    }
+    if Path::new("/.dockerenv").exists() {
+        return Some(ContainerRuntime::Docker);
+    }
+    let cgroups = fs::File::open("/proc/1/cgroup").ok()?;
+    BufReader::new(cgroups)
+        .lines()
+        .filter_map(|line| line.ok())
+        .find_map(|line| runtime_from_cgroup(&line))
+}
+
+fn runtime_from_cgroup(line: &str) -> Option<ContainerRuntime> {
+    let path = line.splitn(3, ':').nth(2)?;
+    if path.contains("kubepods") {
+        Some(ContainerRuntime::Kubernetes)
+    } else if path.contains("libpod") {
+        Some(ContainerRuntime::Podman)
+    } else if path.contains("docker") {
+        Some(ContainerRuntime::Docker)
+    } else {
+        None
+    }
+}
+
+/// Returns whether `capability` is in the effective set listed in `status`, the contents of
+/// `/proc/<pid>/status`.
+fn has_effective_capability(status: &str, capability: u32) -> bool {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))
+        .and_then(|mask| u64::from_str_radix(mask.trim(), 16).ok())
+        .map(|mask| mask & (1 << capability) != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_effective_capability() {
+        let status = "Name:\tmullvad-daemon\nCapInh:\t0000000000000000\n\
+                      CapPrm:\t00000000a80435fb\nCapEff:\t00000000a80435fb\n";
+        assert!(has_effective_capability(status, CAP_NET_ADMIN));
+
+        // Default capabilities granted by Docker
+        let status = "CapEff:\t00000000a80425fb\n";
+        assert!(!has_effective_capability(status, CAP_NET_ADMIN));
+
+        assert!(!has_effective_capability("Name:\tfoo\n", CAP_NET_ADMIN));
+    }
+
+    #[test]
+    fn test_runtime_from_cgroup() {
+        assert_eq!(
+            runtime_from_cgroup("12:cpu,cpuacct:/docker/3f2a1b"),
+            Some(ContainerRuntime::Docker)
+        );
+        assert_eq!(
+            runtime_from_cgroup("0::/machine.slice/libpod-3f2a1b.scope"),
+            Some(ContainerRuntime::Podman)
+        );
+        assert_eq!(runtime_from_cgroup("0::/init.scope"), None);
+    }
+}
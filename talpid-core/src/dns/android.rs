@@ -21,4 +21,8 @@ impl super::DnsMonitorT for DnsMonitor {
     fn reset(&mut self) -> Result<(), Self::Error> {
         Ok(())
     }
+
+    fn flush_cache(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
 }
@@ -70,6 +70,16 @@ impl super::DnsMonitorT for DnsMonitor {
         }
         Ok(())
     }
+
+    fn flush_cache(&mut self) -> Result<()> {
+        // Of the supported DNS managers, only systemd-resolved maintains a cache.
+        let systemd_resolved = match SystemdResolved::new() {
+            Ok(systemd_resolved) => systemd_resolved,
+            Err(_) => return Ok(()),
+        };
+        self.handle.block_on(systemd_resolved.flush_caches())?;
+        Ok(())
+    }
 }
 
 pub enum DnsMonitorHolder {
@@ -103,6 +113,10 @@ impl DnsMonitorHolder {
             Some("resolvconf") => DnsMonitorHolder::Resolvconf(Resolvconf::new()?),
             Some("systemd") => DnsMonitorHolder::SystemdResolved(SystemdResolved::new()?),
             Some("network-manager") => DnsMonitorHolder::NetworkManager(NetworkManager::new()?),
+            Some(_) | None if crate::container::is_enabled() => {
+                // There is usually no DNS manager to talk to in a container
+                DnsMonitorHolder::StaticResolvConf(handle.block_on(StaticResolvConf::new())?)
+            }
             Some(_) | None => Self::with_detected_dns_manager(handle)?,
         };
         log::debug!("Managing DNS via {}", manager);
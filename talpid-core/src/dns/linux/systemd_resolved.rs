@@ -65,6 +65,11 @@ impl SystemdResolved {
         Ok(())
     }
 
+    pub async fn flush_caches(&self) -> Result<()> {
+        self.dbus_interface.flush_caches().await?;
+        Ok(())
+    }
+
     pub async fn reset(&mut self) -> Result<()> {
         if let Err(error) = self
             .dbus_interface
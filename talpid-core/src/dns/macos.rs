@@ -53,6 +53,10 @@ pub enum Error {
     /// Failed to load DNS config
     #[error(display = "Failed to load DNS config at path {}", _0)]
     LoadDnsConfigError(String),
+
+    /// Failed to run a command that flushes the DNS cache
+    #[error(display = "Failed to run {}", _0)]
+    FlushCacheError(&'static str, #[error(source)] std::io::Error),
 }
 
 const STATE_PATH_PATTERN: &str = "State:/Network/Service/.*/DNS";
@@ -361,6 +365,26 @@ impl super::DnsMonitorT for DnsMonitor {
     fn reset(&mut self) -> Result<()> {
         self.state.lock().reset(&self.store)
     }
+
+    fn flush_cache(&mut self) -> Result<()> {
+        run_flush_command("dscacheutil", &["-flushcache"])?;
+        // mDNSResponder drops its cache when it receives SIGHUP
+        run_flush_command("killall", &["-HUP", "mDNSResponder"])
+    }
+}
+
+fn run_flush_command(program: &'static str, args: &[&str]) -> Result<()> {
+    let status = std::process::Command::new(program)
+        .args(args)
+        .status()
+        .map_err(|error| Error::FlushCacheError(program, error))?;
+    if !status.success() {
+        return Err(Error::FlushCacheError(
+            program,
+            std::io::Error::new(std::io::ErrorKind::Other, format!("exited with {}", status)),
+        ));
+    }
+    Ok(())
 }
 
 impl DnsMonitor {
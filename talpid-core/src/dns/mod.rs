@@ -1,6 +1,8 @@
+use crate::restore_journal::{JournalEntry, RestoreJournal};
 #[cfg(target_os = "linux")]
 use crate::routing::RouteManagerHandle;
 use std::net::IpAddr;
+use talpid_types::{system_state::DnsState, ErrorExt};
 
 #[cfg(target_os = "macos")]
 use {
@@ -32,6 +34,10 @@ pub use self::imp::Error;
 /// Sets and monitors system DNS settings. Makes sure the desired DNS servers are being used.
 pub struct DnsMonitor {
     inner: imp::DnsMonitor,
+    flush_cache: bool,
+    /// DNS servers that have been set and not yet reset by this instance.
+    current: Option<DnsState>,
+    journal: Option<RestoreJournal>,
 }
 
 impl DnsMonitor {
@@ -50,9 +56,24 @@ impl DnsMonitor {
                 #[cfg(target_os = "macos")]
                 tx,
             )?,
+            flush_cache: false,
+            current: None,
+            journal: None,
         })
     }
 
+    /// Records changes to the DNS configuration in `journal`, so that they can be undone if the
+    /// process crashes.
+    pub fn set_journal(&mut self, journal: RestoreJournal) {
+        self.journal = Some(journal);
+    }
+
+    /// Enable or disable flushing the system DNS cache after the DNS settings have been changed,
+    /// so that answers resolved through the previous path are not reused.
+    pub fn set_flush_cache(&mut self, flush_cache: bool) {
+        self.flush_cache = flush_cache;
+    }
+
     /// Returns a map of interfaces and respective list of resolvers that don't contain our
     /// changes.
     #[cfg(target_os = "macos")]
@@ -60,6 +81,16 @@ impl DnsMonitor {
         self.inner.get_system_config()
     }
 
+    /// Returns whether DNS servers have been set and not yet reset by this instance.
+    pub fn is_set(&self) -> bool {
+        self.current.is_some()
+    }
+
+    /// Returns the DNS servers that have been set and not yet reset by this instance.
+    pub fn current(&self) -> Option<&DnsState> {
+        self.current.as_ref()
+    }
+
     /// Set DNS to the given servers. And start monitoring the system for changes.
     pub fn set(&mut self, interface: &str, servers: &[IpAddr]) -> Result<(), Error> {
         log::info!(
@@ -70,14 +101,46 @@ impl DnsMonitor {
                 .collect::<Vec<String>>()
                 .join(", ")
         );
-        self.inner.set(interface, servers)
+        self.inner.set(interface, servers)?;
+        self.current = Some(DnsState {
+            interface: interface.to_owned(),
+            servers: servers.to_vec(),
+        });
+        if let Some(journal) = &self.journal {
+            journal.remove(|entry| matches!(entry, JournalEntry::Dns { .. }));
+            journal.record(JournalEntry::Dns {
+                interface: interface.to_owned(),
+            });
+        }
+        self.flush_cache_if_enabled();
+        Ok(())
     }
 
     /// Reset system DNS settings to what it was before being set by this instance.
     /// This succeeds if the interface does not exist.
     pub fn reset(&mut self) -> Result<(), Error> {
         log::info!("Resetting DNS");
-        self.inner.reset()
+        self.inner.reset()?;
+        if let Some(journal) = &self.journal {
+            journal.remove(|entry| matches!(entry, JournalEntry::Dns { .. }));
+        }
+        if self.current.take().is_some() {
+            self.flush_cache_if_enabled();
+        }
+        Ok(())
+    }
+
+    fn flush_cache_if_enabled(&mut self) {
+        if !self.flush_cache {
+            return;
+        }
+        log::debug!("Flushing DNS cache");
+        if let Err(error) = self.inner.flush_cache() {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to flush DNS cache")
+            );
+        }
     }
 }
 
@@ -93,4 +156,6 @@ trait DnsMonitorT: Sized {
     fn set(&mut self, interface: &str, servers: &[IpAddr]) -> Result<(), Self::Error>;
 
     fn reset(&mut self) -> Result<(), Self::Error>;
+
+    fn flush_cache(&mut self) -> Result<(), Self::Error>;
 }
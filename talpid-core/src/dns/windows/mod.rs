@@ -57,6 +57,12 @@ impl super::DnsMonitorT for DnsMonitor {
         }
         Ok(())
     }
+
+    fn flush_cache(&mut self) -> Result<(), Error> {
+        // The cache is always flushed after changing the DNS servers, since Windows does not
+        // otherwise pick up the new servers reliably.
+        Ok(())
+    }
 }
 
 fn set_dns(interface: &GUID, servers: &[IpAddr]) -> Result<(), Error> {
@@ -1,4 +1,4 @@
-use super::{FirewallArguments, FirewallPolicy};
+use super::{plugin::PolicyFragment, FirewallArguments, FirewallPolicy};
 
 /// Stub error type for Firewall errors on Android.
 #[derive(Debug, err_derive::Error)]
@@ -17,11 +17,19 @@ impl Firewall {
         Ok(Firewall)
     }
 
-    pub fn apply_policy(&mut self, _policy: FirewallPolicy) -> Result<(), Error> {
+    pub fn apply_policy(
+        &mut self,
+        _policy: FirewallPolicy,
+        _plugins: &PolicyFragment,
+    ) -> Result<(), Error> {
         Ok(())
     }
 
     pub fn reset_policy(&mut self) -> Result<(), Error> {
         Ok(())
     }
+
+    pub fn rule_count(&self) -> Option<usize> {
+        None
+    }
 }
@@ -0,0 +1,325 @@
+//! Firewall for FreeBSD and OpenBSD, based on pf.
+//!
+//! The rules are generated by [`super::pf`], like on macOS, but they are written in `pf.conf`
+//! syntax and loaded into the anchor with `pfctl`, since the ioctl interface differs between the
+//! BSDs. The main ruleset is never modified, so it must contain the line `anchor "mullvad"`,
+//! preferably before any other `quick` rules.
+
+use super::{
+    pf::{
+        Action, AddrFamily, Direction, Icmp6Type, Port, Proto, Rule, RuleGenerator, Target,
+        ANCHOR_NAME,
+    },
+    plugin::PolicyFragment,
+    FirewallArguments, FirewallPolicy,
+};
+use ipnetwork::IpNetwork;
+use std::{fmt, io};
+
+type Result<T> = std::result::Result<T, Error>;
+
+const PFCTL_PATH: &str = "/sbin/pfctl";
+
+/// Errors that can happen when configuring pf.
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    /// Unable to run pfctl.
+    #[error(display = "Unable to run pfctl")]
+    RunPfctl(#[error(source)] io::Error),
+
+    /// pfctl exited with an error.
+    #[error(display = "pfctl failed: {}", stderr)]
+    PfctlFailed { stderr: String },
+
+    /// The main ruleset does not evaluate the anchor, so the rules in it would have no effect.
+    #[error(
+        display = "The main pf ruleset does not contain 'anchor \"{}\"'",
+        ANCHOR_NAME
+    )]
+    AnchorNotReferenced,
+}
+
+pub struct Firewall {
+    pf_was_enabled: Option<bool>,
+    rules: RuleGenerator,
+    rule_count: Option<usize>,
+}
+
+impl Firewall {
+    pub fn from_args(_args: FirewallArguments) -> Result<Self> {
+        Self::new()
+    }
+
+    pub fn new() -> Result<Self> {
+        Ok(Firewall {
+            pf_was_enabled: None,
+            rules: RuleGenerator::from_env(),
+            rule_count: None,
+        })
+    }
+
+    pub fn apply_policy(&mut self, policy: FirewallPolicy, plugins: &PolicyFragment) -> Result<()> {
+        self.enable()?;
+        Self::verify_anchor()?;
+        self.set_rules(policy, plugins)
+    }
+
+    pub fn reset_policy(&mut self) -> Result<()> {
+        self.rule_count = None;
+        // Always restore the state of pf, even if the rules could not be removed.
+        self.remove_rules().and(self.restore_state())
+    }
+
+    /// Returns the number of rules added to the anchor by the last applied policy.
+    pub fn rule_count(&self) -> Option<usize> {
+        self.rule_count
+    }
+
+    fn set_rules(&mut self, policy: FirewallPolicy, plugins: &PolicyFragment) -> Result<()> {
+        let rules = self.rules.filter_rules(&policy, plugins);
+        let ruleset = rules
+            .iter()
+            .map(|rule| format!("{}\n", rule))
+            .collect::<String>();
+        pfctl(&["-a", ANCHOR_NAME, "-f", "-"], Some(ruleset))?;
+        self.rule_count = Some(rules.len());
+        Ok(())
+    }
+
+    fn remove_rules(&mut self) -> Result<()> {
+        pfctl(&["-a", ANCHOR_NAME, "-F", "rules"], None).map(|_| ())
+    }
+
+    fn enable(&mut self) -> Result<()> {
+        let is_enabled = Self::is_enabled()?;
+        if self.pf_was_enabled.is_none() {
+            self.pf_was_enabled = Some(is_enabled);
+        }
+        if !is_enabled {
+            pfctl(&["-e"], None)?;
+        }
+        Ok(())
+    }
+
+    fn is_enabled() -> Result<bool> {
+        let output = pfctl(&["-s", "info"], None)?;
+        Ok(output.contains("Status: Enabled"))
+    }
+
+    fn restore_state(&mut self) -> Result<()> {
+        match self.pf_was_enabled.take() {
+            Some(false) => pfctl(&["-d"], None).map(|_| ()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails if the main ruleset does not contain the anchor. Unlike on macOS, the anchor is not
+    /// added automatically, since that would require replacing the main ruleset.
+    fn verify_anchor() -> Result<()> {
+        let main_rules = pfctl(&["-s", "rules"], None)?;
+        let anchor_rule = format!("anchor \"{}\"", ANCHOR_NAME);
+        if main_rules
+            .lines()
+            .any(|line| line.trim_start().starts_with(&anchor_rule))
+        {
+            Ok(())
+        } else {
+            Err(Error::AnchorNotReferenced)
+        }
+    }
+}
+
+/// Runs pfctl with the given arguments and input, and returns its output.
+fn pfctl(args: &[&str], stdin: Option<String>) -> Result<String> {
+    let mut cmd = duct::cmd(PFCTL_PATH, args)
+        .stdout_capture()
+        .stderr_capture()
+        .unchecked();
+    if let Some(stdin) = stdin {
+        cmd = cmd.stdin_bytes(stdin);
+    }
+    let output = cmd.run().map_err(Error::RunPfctl)?;
+    if !output.status.success() {
+        return Err(Error::PfctlFailed {
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+impl fmt::Display for Port {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Port::Single(port) => write!(f, "port {}", port),
+            Port::Range(start, end) => write!(f, "port {}:{}", start, end),
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.address {
+            Some(address) if address.prefix() == max_prefix(&address) => {
+                write!(f, "{}", address.ip())?
+            }
+            Some(address) => write!(f, "{}", address)?,
+            None => f.write_str("any")?,
+        }
+        if let Some(port) = self.port {
+            write!(f, " {}", port)?;
+        }
+        Ok(())
+    }
+}
+
+fn is_any(target: &Target) -> bool {
+    target.address.is_none() && target.port.is_none()
+}
+
+fn max_prefix(network: &IpNetwork) -> u8 {
+    match network {
+        IpNetwork::V4(_) => 32,
+        IpNetwork::V6(_) => 128,
+    }
+}
+
+/// Writes the rule in `pf.conf` syntax.
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self.action {
+            Action::Pass => "pass",
+            Action::Return => "block return",
+            Action::Drop => "block drop",
+        })?;
+        match self.direction {
+            Some(Direction::In) => f.write_str(" in")?,
+            Some(Direction::Out) => f.write_str(" out")?,
+            None => (),
+        }
+        if self.log {
+            f.write_str(" log")?;
+        }
+        if self.quick {
+            f.write_str(" quick")?;
+        }
+        if let Some(interface) = &self.interface {
+            write!(f, " on {}", interface)?;
+        }
+        match self.af {
+            Some(AddrFamily::Inet) => f.write_str(" inet")?,
+            Some(AddrFamily::Inet6) => f.write_str(" inet6")?,
+            None => (),
+        }
+        match self.proto {
+            Some(Proto::Tcp) => f.write_str(" proto tcp")?,
+            Some(Proto::Udp) => f.write_str(" proto udp")?,
+            Some(Proto::Icmp6) => f.write_str(" proto icmp6")?,
+            None => (),
+        }
+        if is_any(&self.from) && is_any(&self.to) {
+            f.write_str(" all")?;
+        } else {
+            write!(f, " from {} to {}", self.from, self.to)?;
+        }
+        if let Some(uid) = self.user {
+            write!(f, " user {}", uid)?;
+        }
+        if let Some(icmp6_type) = self.icmp6_type {
+            write!(f, " icmp6-type {}", icmp6_type_name(icmp6_type))?;
+        }
+        // Pass rules keep state with `flags S/SA` unless told otherwise
+        if self.action == Action::Pass {
+            if !self.keep_state {
+                f.write_str(" no state")?;
+            } else {
+                if matches!(self.proto, None | Some(Proto::Tcp)) {
+                    f.write_str(if self.tcp_flags {
+                        " flags S/SA"
+                    } else {
+                        " flags any"
+                    })?;
+                }
+                f.write_str(" keep state")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn icmp6_type_name(icmp6_type: Icmp6Type) -> &'static str {
+    match icmp6_type {
+        Icmp6Type::RouterSol => "routersol",
+        Icmp6Type::RouterAdv => "routeradv",
+        Icmp6Type::Redir => "redir",
+        Icmp6Type::NeighbrSol => "neighbrsol",
+        Icmp6Type::NeighbrAdv => "neighbradv",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::IpAddr;
+
+    #[test]
+    fn test_rule_syntax() {
+        let mut rule = Rule::new(Action::Pass, false);
+        rule.direction(Direction::Out)
+            .quick()
+            .proto(Proto::Udp)
+            .to(Target::endpoint(
+                "185.65.134.1".parse::<IpAddr>().unwrap(),
+                Port::Single(51820),
+            ))
+            .user(0)
+            .keep_state()
+            .tcp_flags();
+        assert_eq!(
+            rule.to_string(),
+            "pass out quick proto udp from any to 185.65.134.1 port 51820 user 0 keep state"
+        );
+
+        let mut rule = Rule::new(Action::Pass, false);
+        rule.quick().interface("wg0").keep_state().tcp_flags();
+        assert_eq!(
+            rule.to_string(),
+            "pass quick on wg0 all flags S/SA keep state"
+        );
+
+        let mut rule = Rule::new(Action::Pass, false);
+        rule.direction(Direction::Out)
+            .quick()
+            .to(Target::network("10.0.0.0/8".parse().unwrap()));
+        assert_eq!(
+            rule.to_string(),
+            "pass out quick from any to 10.0.0.0/8 no state"
+        );
+
+        let mut rule = Rule::new(Action::Pass, false);
+        rule.direction(Direction::In)
+            .quick()
+            .af(AddrFamily::Inet6)
+            .proto(Proto::Icmp6)
+            .icmp6_type(Icmp6Type::NeighbrAdv);
+        assert_eq!(
+            rule.to_string(),
+            "pass in quick inet6 proto icmp6 all icmp6-type neighbradv no state"
+        );
+
+        let mut rule = Rule::new(Action::Return, true);
+        rule.direction(Direction::Out)
+            .quick()
+            .interface("wg0")
+            .proto(Proto::Tcp)
+            .to(Target::port(Port::Single(445)));
+        assert_eq!(
+            rule.to_string(),
+            "block return out log quick on wg0 proto tcp from any to any port 445"
+        );
+
+        let mut rule = Rule::new(Action::Drop, false);
+        rule.quick();
+        assert_eq!(rule.to_string(), "block drop quick all");
+    }
+}
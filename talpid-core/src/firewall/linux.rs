@@ -1,5 +1,11 @@
-use super::{FirewallArguments, FirewallPolicy};
-use crate::{split_tunnel, tunnel};
+use super::{
+    plugin::{PluginRule, PolicyFragment},
+    FirewallArguments, FirewallPolicy, TunnelProtocolBlock,
+};
+use crate::{
+    runtime_config::{self, FeatureFlag},
+    split_tunnel, tunnel,
+};
 use ipnetwork::IpNetwork;
 use lazy_static::lazy_static;
 use libc;
@@ -14,7 +20,10 @@ use std::{
     io,
     net::{IpAddr, Ipv4Addr},
 };
-use talpid_types::net::{AllowedTunnelTraffic, Endpoint, TransportProtocol};
+use talpid_types::net::{
+    AllowedEndpoint, AllowedTunnelTraffic, BlockedTunnelProtocols, Endpoint, EndpointRange,
+    PortRange, TransportProtocol, VpnCoexistence,
+};
 
 /// Priority for rules that tag split tunneling packets. Equals NF_IP_PRI_MANGLE.
 const MANGLE_CHAIN_PRIORITY: i32 = libc::NF_IP_PRI_MANGLE;
@@ -72,17 +81,16 @@ lazy_static! {
     static ref MANGLE_CHAIN_NAME: CString = CString::new("mangle").unwrap();
     static ref NAT_CHAIN_NAME: CString = CString::new("nat").unwrap();
 
-    /// Allows controlling whether firewall rules should have packet counters or not from an env
-    /// variable. Useful for debugging the rules.
-    static ref ADD_COUNTERS: bool = env::var("TALPID_FIREWALL_DEBUG")
-        .map(|v| v != "0")
-        .unwrap_or(false);
-
     static ref DONT_SET_SRC_VALID_MARK: bool = env::var("TALPID_FIREWALL_DONT_SET_SRC_VALID_MARK")
         .map(|v| v != "0")
         .unwrap_or(false);
 }
 
+/// Returns whether firewall rules should have packet counters. Useful for debugging the rules.
+fn add_counters() -> bool {
+    runtime_config::is_enabled(FeatureFlag::FirewallCounters)
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 enum Direction {
     In,
@@ -96,7 +104,9 @@ enum End {
 }
 
 /// The Linux implementation for the firewall and DNS.
-pub struct Firewall(());
+pub struct Firewall {
+    rule_count: Option<usize>,
+}
 
 struct FirewallTables {
     main: Table,
@@ -106,26 +116,28 @@ struct FirewallTables {
 
 impl Firewall {
     pub fn from_args(_args: FirewallArguments) -> Result<Self> {
-        Ok(Firewall(()))
+        Ok(Firewall { rule_count: None })
     }
 
     pub fn new() -> Result<Self> {
-        Ok(Firewall(()))
+        Ok(Firewall { rule_count: None })
     }
 
-    pub fn apply_policy(&mut self, policy: FirewallPolicy) -> Result<()> {
+    pub fn apply_policy(&mut self, policy: FirewallPolicy, plugins: &PolicyFragment) -> Result<()> {
         let tables = FirewallTables {
             main: Table::new(&*TABLE_NAME, ProtoFamily::Inet),
             mangle_v4: Table::new(&*MANGLE_TABLE_NAME_V4, ProtoFamily::Ipv4),
             mangle_v6: Table::new(&*MANGLE_TABLE_NAME_V6, ProtoFamily::Ipv6),
         };
-        let batch = PolicyBatch::new(&tables).finalize(&policy)?;
+        let (batch, rule_count) = PolicyBatch::new(&tables).finalize(&policy, plugins)?;
         Self::send_and_process(&batch)?;
+        self.rule_count = Some(rule_count);
         Self::apply_kernel_config(&policy);
         self.verify_tables(&[&TABLE_NAME, &MANGLE_TABLE_NAME_V4, &MANGLE_TABLE_NAME_V6])
     }
 
     pub fn reset_policy(&mut self) -> Result<()> {
+        self.rule_count = None;
         let tables = [
             Table::new(&*TABLE_NAME, ProtoFamily::Inet),
             Table::new(&*MANGLE_TABLE_NAME_V4, ProtoFamily::Ipv4),
@@ -145,6 +157,11 @@ impl Firewall {
         Ok(())
     }
 
+    /// Returns the number of rules added by the last applied policy.
+    pub fn rule_count(&self) -> Option<usize> {
+        self.rule_count
+    }
+
     fn apply_kernel_config(policy: &FirewallPolicy) {
         if *DONT_SET_SRC_VALID_MARK {
             log::debug!("Not setting src_valid_mark");
@@ -153,7 +170,12 @@ impl Firewall {
 
         if let FirewallPolicy::Connecting { .. } = policy {
             if let Err(err) = crate::linux::set_src_valid_mark_sysctl() {
-                log::error!("Failed to apply src_valid_mark: {}", err);
+                if crate::container::is_enabled() {
+                    // `/proc/sys` is read-only in most containers
+                    log::debug!("Failed to apply src_valid_mark: {}", err);
+                } else {
+                    log::error!("Failed to apply src_valid_mark: {}", err);
+                }
             }
         }
     }
@@ -226,8 +248,21 @@ impl Firewall {
     }
 }
 
-struct PolicyBatch<'a> {
+/// Message batch that keeps track of how many rules have been added to it.
+struct RuleBatch {
     batch: Batch,
+    rule_count: usize,
+}
+
+impl RuleBatch {
+    fn add_rule(&mut self, rule: &Rule<'_>) {
+        self.batch.add(rule, nftnl::MsgType::Add);
+        self.rule_count += 1;
+    }
+}
+
+struct PolicyBatch<'a> {
+    batch: RuleBatch,
     in_chain: Chain<'a>,
     out_chain: Chain<'a>,
     forward_chain: Chain<'a>,
@@ -293,7 +328,10 @@ impl<'a> PolicyBatch<'a> {
         let nat_chain_v6 = add_nat_chain(&tables.mangle_v6);
 
         PolicyBatch {
-            batch,
+            batch: RuleBatch {
+                batch,
+                rule_count: 0,
+            },
             in_chain,
             out_chain,
             forward_chain,
@@ -313,15 +351,22 @@ impl<'a> PolicyBatch<'a> {
     }
 
     /// Finalize the nftnl message batch by adding every firewall rule needed to satisfy the given
-    /// policy.
-    pub fn finalize(mut self, policy: &FirewallPolicy) -> Result<FinalizedBatch> {
+    /// policy and plugin rules.
+    pub fn finalize(
+        mut self,
+        policy: &FirewallPolicy,
+        plugins: &PolicyFragment,
+    ) -> Result<(FinalizedBatch, usize)> {
         self.add_loopback_rules()?;
+        // Plugin rules must come before the split tunneling rules, so that they apply to excluded
+        // processes as well.
+        self.add_plugin_rules(plugins);
         self.add_split_tunneling_rules(policy)?;
         self.add_dhcp_client_rules();
         self.add_ndp_rules();
         self.add_policy_specific_rules(policy)?;
 
-        Ok(self.batch.finalize())
+        Ok((self.batch.batch.finalize(), self.batch.rule_count))
     }
 
     fn add_split_tunneling_rules(&mut self, policy: &FirewallPolicy) -> Result<()> {
@@ -347,14 +392,14 @@ impl<'a> PolicyBatch<'a> {
                     TransportProtocol::Udp,
                     *server,
                 )?;
-                self.batch.add(&allow_rule, nftnl::MsgType::Add);
+                self.batch.add_rule(&allow_rule);
                 let allow_rule = allow_tunnel_dns_rule(
                     chain,
                     &tunnel.interface,
                     TransportProtocol::Tcp,
                     *server,
                 )?;
-                self.batch.add(&allow_rule, nftnl::MsgType::Add);
+                self.batch.add_rule(&allow_rule);
             }
         }
 
@@ -367,7 +412,17 @@ impl<'a> PolicyBatch<'a> {
             rule.add_expr(&nft_expr!(ct mark set));
             rule.add_expr(&nft_expr!(immediate data crate::linux::TUNNEL_FW_MARK));
             rule.add_expr(&nft_expr!(meta mark set));
-            self.batch.add(&rule, nftnl::MsgType::Add);
+            self.batch.add_rule(&rule);
+
+            // Sockets excluded through the daemon are marked instead of being in the cgroup
+            let mut rule = Rule::new(chain);
+            rule.add_expr(&nft_expr!(meta mark));
+            rule.add_expr(&nft_expr!(cmp == split_tunnel::SOCKET_BYPASS_MARK));
+            rule.add_expr(&nft_expr!(immediate data split_tunnel::MARK));
+            rule.add_expr(&nft_expr!(ct mark set));
+            rule.add_expr(&nft_expr!(immediate data crate::linux::TUNNEL_FW_MARK));
+            rule.add_expr(&nft_expr!(meta mark set));
+            self.batch.add_rule(&rule);
         }
 
         for chain in &[&self.in_chain, &self.out_chain] {
@@ -375,7 +430,7 @@ impl<'a> PolicyBatch<'a> {
             rule.add_expr(&nft_expr!(ct mark));
             rule.add_expr(&nft_expr!(cmp == split_tunnel::MARK));
             add_verdict(&mut rule, &Verdict::Accept);
-            self.batch.add(&rule, nftnl::MsgType::Add);
+            self.batch.add_rule(&rule);
         }
 
         let nat_chains = [&self.nat_chain_v4, &self.nat_chain_v6];
@@ -387,7 +442,7 @@ impl<'a> PolicyBatch<'a> {
                 block_tunnel_rule.add_expr(&nft_expr!(ct mark));
                 block_tunnel_rule.add_expr(&nft_expr!(cmp == split_tunnel::MARK));
                 add_verdict(&mut block_tunnel_rule, &Verdict::Drop);
-                self.batch.add(&block_tunnel_rule, nftnl::MsgType::Add);
+                self.batch.add_rule(&block_tunnel_rule);
             }
 
             // Replace source IP address in rerouted packets.
@@ -403,10 +458,10 @@ impl<'a> PolicyBatch<'a> {
             rule.add_expr(&nft_expr!(cmp == split_tunnel::MARK));
 
             rule.add_expr(&nft_expr!(masquerade));
-            if *ADD_COUNTERS {
+            if add_counters() {
                 rule.add_expr(&nft_expr!(counter));
             }
-            self.batch.add(&rule, nftnl::MsgType::Add);
+            self.batch.add_rule(&rule);
         }
 
         // Route incoming traffic correctly to prevent strict rpf from rejecting packets
@@ -418,10 +473,10 @@ impl<'a> PolicyBatch<'a> {
             prerouting_rule.add_expr(&nft_expr!(cmp == split_tunnel::MARK));
             prerouting_rule.add_expr(&nft_expr!(immediate data crate::linux::TUNNEL_FW_MARK));
             prerouting_rule.add_expr(&nft_expr!(meta mark set));
-            if *ADD_COUNTERS {
+            if add_counters() {
                 prerouting_rule.add_expr(&nft_expr!(counter));
             }
-            self.batch.add(&prerouting_rule, nftnl::MsgType::Add);
+            self.batch.add_rule(&prerouting_rule);
         }
 
         Ok(())
@@ -429,14 +484,16 @@ impl<'a> PolicyBatch<'a> {
 
     fn add_loopback_rules(&mut self) -> Result<()> {
         const LOOPBACK_IFACE_NAME: &str = "lo";
-        self.batch.add(
-            &allow_interface_rule(&self.out_chain, Direction::Out, LOOPBACK_IFACE_NAME)?,
-            nftnl::MsgType::Add,
-        );
-        self.batch.add(
-            &allow_interface_rule(&self.in_chain, Direction::In, LOOPBACK_IFACE_NAME)?,
-            nftnl::MsgType::Add,
-        );
+        self.batch.add_rule(&allow_interface_rule(
+            &self.out_chain,
+            Direction::Out,
+            LOOPBACK_IFACE_NAME,
+        )?);
+        self.batch.add_rule(&allow_interface_rule(
+            &self.in_chain,
+            Direction::In,
+            LOOPBACK_IFACE_NAME,
+        )?);
         Ok(())
     }
 
@@ -449,7 +506,7 @@ impl<'a> PolicyBatch<'a> {
             check_ip(&mut out_v4, End::Dst, IpAddr::V4(Ipv4Addr::BROADCAST));
             check_port(&mut out_v4, Udp, End::Dst, super::DHCPV4_SERVER_PORT);
             add_verdict(&mut out_v4, &Verdict::Accept);
-            self.batch.add(&out_v4, nftnl::MsgType::Add);
+            self.batch.add_rule(&out_v4);
         }
         // Incoming DHCPv4 response
         for chain in &[&self.in_chain, &self.forward_chain] {
@@ -457,7 +514,7 @@ impl<'a> PolicyBatch<'a> {
             check_port(&mut in_v4, Udp, End::Src, super::DHCPV4_SERVER_PORT);
             check_port(&mut in_v4, Udp, End::Dst, super::DHCPV4_CLIENT_PORT);
             add_verdict(&mut in_v4, &Verdict::Accept);
-            self.batch.add(&in_v4, nftnl::MsgType::Add);
+            self.batch.add_rule(&in_v4);
         }
 
         for chain in &[&self.out_chain, &self.forward_chain] {
@@ -468,7 +525,7 @@ impl<'a> PolicyBatch<'a> {
                 check_ip(&mut out_v6, End::Dst, *dhcpv6_server);
                 check_port(&mut out_v6, Udp, End::Dst, super::DHCPV6_SERVER_PORT);
                 add_verdict(&mut out_v6, &Verdict::Accept);
-                self.batch.add(&out_v6, nftnl::MsgType::Add);
+                self.batch.add_rule(&out_v6);
             }
         }
         for chain in &[&self.in_chain, &self.forward_chain] {
@@ -478,7 +535,7 @@ impl<'a> PolicyBatch<'a> {
             check_net(&mut in_v6, End::Dst, *super::IPV6_LINK_LOCAL);
             check_port(&mut in_v6, Udp, End::Dst, super::DHCPV6_CLIENT_PORT);
             add_verdict(&mut in_v6, &Verdict::Accept);
-            self.batch.add(&in_v6, nftnl::MsgType::Add);
+            self.batch.add_rule(&in_v6);
         }
     }
 
@@ -493,7 +550,7 @@ impl<'a> PolicyBatch<'a> {
             );
             check_icmpv6(&mut rule, 133, 0);
             add_verdict(&mut rule, &Verdict::Accept);
-            self.batch.add(&rule, nftnl::MsgType::Add);
+            self.batch.add_rule(&rule);
         }
         // Incoming Router advertisement (part of NDP)
         for chain in &[&self.in_chain, &self.forward_chain] {
@@ -501,7 +558,7 @@ impl<'a> PolicyBatch<'a> {
             check_net(&mut rule, End::Src, *super::IPV6_LINK_LOCAL);
             check_icmpv6(&mut rule, 134, 0);
             add_verdict(&mut rule, &Verdict::Accept);
-            self.batch.add(&rule, nftnl::MsgType::Add);
+            self.batch.add_rule(&rule);
         }
         // Incoming Redirect (part of NDP)
         for chain in &[&self.in_chain, &self.forward_chain] {
@@ -509,7 +566,7 @@ impl<'a> PolicyBatch<'a> {
             check_net(&mut rule, End::Src, *super::IPV6_LINK_LOCAL);
             check_icmpv6(&mut rule, 137, 0);
             add_verdict(&mut rule, &Verdict::Accept);
-            self.batch.add(&rule, nftnl::MsgType::Add);
+            self.batch.add_rule(&rule);
         }
         // Outgoing Neighbor solicitation (part of NDP)
         for chain in &[&self.out_chain, &self.forward_chain] {
@@ -517,14 +574,14 @@ impl<'a> PolicyBatch<'a> {
             check_net(&mut rule, End::Dst, *super::SOLICITED_NODE_MULTICAST);
             check_icmpv6(&mut rule, 135, 0);
             add_verdict(&mut rule, &Verdict::Accept);
-            self.batch.add(&rule, nftnl::MsgType::Add);
+            self.batch.add_rule(&rule);
         }
         for chain in &[&self.out_chain, &self.forward_chain] {
             let mut rule = Rule::new(chain);
             check_net(&mut rule, End::Dst, *super::IPV6_LINK_LOCAL);
             check_icmpv6(&mut rule, 135, 0);
             add_verdict(&mut rule, &Verdict::Accept);
-            self.batch.add(&rule, nftnl::MsgType::Add);
+            self.batch.add_rule(&rule);
         }
         // Incoming Neighbor solicitation (part of NDP)
         for chain in &[&self.in_chain, &self.forward_chain] {
@@ -532,7 +589,7 @@ impl<'a> PolicyBatch<'a> {
             check_net(&mut rule, End::Src, *super::IPV6_LINK_LOCAL);
             check_icmpv6(&mut rule, 135, 0);
             add_verdict(&mut rule, &Verdict::Accept);
-            self.batch.add(&rule, nftnl::MsgType::Add);
+            self.batch.add_rule(&rule);
         }
         // Outgoing Neighbor advertisement (part of NDP)
         for chain in &[&self.out_chain, &self.forward_chain] {
@@ -540,14 +597,14 @@ impl<'a> PolicyBatch<'a> {
             check_net(&mut rule, End::Dst, *super::IPV6_LINK_LOCAL);
             check_icmpv6(&mut rule, 136, 0);
             add_verdict(&mut rule, &Verdict::Accept);
-            self.batch.add(&rule, nftnl::MsgType::Add);
+            self.batch.add_rule(&rule);
         }
         // Incoming Neighbor advertisement (part of NDP)
         for chain in &[&self.in_chain, &self.forward_chain] {
             let mut rule = Rule::new(chain);
             check_icmpv6(&mut rule, 136, 0);
             add_verdict(&mut rule, &Verdict::Accept);
-            self.batch.add(&rule, nftnl::MsgType::Add);
+            self.batch.add_rule(&rule);
         }
     }
 
@@ -559,9 +616,21 @@ impl<'a> PolicyBatch<'a> {
                 allow_lan,
                 allowed_endpoint,
                 allowed_tunnel_traffic,
+                ..
             } => {
                 self.add_allow_tunnel_endpoint_rules(peer_endpoint);
-                self.add_allow_endpoint_rules(&allowed_endpoint.endpoint);
+                self.add_allow_endpoint_rules(allowed_endpoint);
+
+                // DNS to the given servers must be allowed before all other DNS is blocked
+                if let (Some(tunnel), AllowedTunnelTraffic::Dns(servers)) =
+                    (tunnel, allowed_tunnel_traffic)
+                {
+                    for server in servers {
+                        for protocol in [TransportProtocol::Udp, TransportProtocol::Tcp] {
+                            self.add_allow_tunnel_dns_rule(&tunnel.interface, protocol, *server)?;
+                        }
+                    }
+                }
 
                 // Important to block DNS after allow relay rule (so the relay can operate
                 // over port 53) but before allow LAN (so DNS does not leak to the LAN)
@@ -572,10 +641,23 @@ impl<'a> PolicyBatch<'a> {
                         AllowedTunnelTraffic::All => {
                             self.add_allow_tunnel_rules(&tunnel.interface)?;
                         }
-                        AllowedTunnelTraffic::None => (),
+                        AllowedTunnelTraffic::None | AllowedTunnelTraffic::Dns(_) => (),
                         AllowedTunnelTraffic::Only(endpoint) => {
                             self.add_allow_in_tunnel_endpoint_rules(&tunnel.interface, endpoint)?;
                         }
+                        AllowedTunnelTraffic::Endpoints {
+                            addresses,
+                            port,
+                            protocol,
+                        } => {
+                            for address in addresses {
+                                let endpoint = Endpoint::new(*address, *port, *protocol);
+                                self.add_allow_in_tunnel_endpoint_rules(
+                                    &tunnel.interface,
+                                    &endpoint,
+                                )?;
+                            }
+                        }
                     }
                     if *allow_lan {
                         self.add_block_cve_2019_14899(tunnel);
@@ -588,8 +670,12 @@ impl<'a> PolicyBatch<'a> {
                 tunnel,
                 allow_lan,
                 dns_servers,
+                blocked_tunnel_protocols,
+                ..
             } => {
                 self.add_allow_tunnel_endpoint_rules(peer_endpoint);
+                // Must come before the DNS rules, so that DNS can be blocked as well.
+                self.add_block_tunnel_protocol_rules(&tunnel.interface, blocked_tunnel_protocols)?;
                 self.add_allow_dns_rules(tunnel, dns_servers, TransportProtocol::Udp)?;
                 self.add_allow_dns_rules(tunnel, dns_servers, TransportProtocol::Tcp)?;
                 // Important to block DNS *before* we allow the tunnel and allow LAN. So DNS
@@ -604,17 +690,22 @@ impl<'a> PolicyBatch<'a> {
             FirewallPolicy::Blocked {
                 allow_lan,
                 allowed_endpoint,
+                recovery_allowlist,
+                ..
             } => {
                 if let Some(endpoint) = allowed_endpoint {
-                    self.add_allow_endpoint_rules(&endpoint.endpoint);
+                    self.add_allow_endpoint_rules(endpoint);
                 }
 
                 // Important to drop DNS before allowing LAN (to stop DNS leaking to the LAN)
                 self.add_drop_dns_rule();
+                self.add_allow_recovery_rules(recovery_allowlist);
                 *allow_lan
             }
         };
 
+        self.add_vpn_coexistence_rules(policy.vpn_coexistence());
+
         if allow_lan {
             self.add_allow_lan_rules();
         }
@@ -626,7 +717,7 @@ impl<'a> PolicyBatch<'a> {
                 &mut reject_rule,
                 &Verdict::Reject(RejectionType::Icmp(IcmpCode::PortUnreach)),
             );
-            self.batch.add(&reject_rule, nftnl::MsgType::Add);
+            self.batch.add_rule(&reject_rule);
         }
 
         Ok(())
@@ -638,11 +729,11 @@ impl<'a> PolicyBatch<'a> {
         prerouting_rule.add_expr(&nft_expr!(immediate data crate::linux::TUNNEL_FW_MARK));
         prerouting_rule.add_expr(&nft_expr!(meta mark set));
 
-        if *ADD_COUNTERS {
+        if add_counters() {
             prerouting_rule.add_expr(&nft_expr!(counter));
         }
 
-        self.batch.add(&prerouting_rule, nftnl::MsgType::Add);
+        self.batch.add_rule(&prerouting_rule);
 
         let mut in_rule = Rule::new(&self.in_chain);
         check_endpoint(&mut in_rule, End::Src, endpoint);
@@ -653,7 +744,7 @@ impl<'a> PolicyBatch<'a> {
         in_rule.add_expr(&nft_expr!(cmp != 0u32));
         add_verdict(&mut in_rule, &Verdict::Accept);
 
-        self.batch.add(&in_rule, nftnl::MsgType::Add);
+        self.batch.add_rule(&in_rule);
 
         let mut out_rule = Rule::new(&self.out_chain);
         check_endpoint(&mut out_rule, End::Dst, endpoint);
@@ -661,14 +752,26 @@ impl<'a> PolicyBatch<'a> {
         out_rule.add_expr(&nft_expr!(cmp == crate::linux::TUNNEL_FW_MARK));
         add_verdict(&mut out_rule, &Verdict::Accept);
 
-        self.batch.add(&out_rule, nftnl::MsgType::Add);
+        self.batch.add_rule(&out_rule);
     }
 
     /// Adds firewall rules allow traffic to flow to the API. Allows the app to reach the API in
     /// blocked states.
-    fn add_allow_endpoint_rules(&mut self, endpoint: &Endpoint) {
+    fn add_allow_endpoint_rules(&mut self, allowed_endpoint: &AllowedEndpoint) {
+        for range in &allowed_endpoint.endpoints {
+            for protocol in range.protocols() {
+                self.add_allow_endpoint_range_rules(range, *protocol);
+            }
+        }
+    }
+
+    fn add_allow_endpoint_range_rules(
+        &mut self,
+        range: &EndpointRange,
+        protocol: TransportProtocol,
+    ) {
         let mut in_rule = Rule::new(&self.in_chain);
-        check_endpoint(&mut in_rule, End::Src, endpoint);
+        check_endpoint_range(&mut in_rule, End::Src, range, protocol);
         let allowed_states = nftnl::expr::ct::States::ESTABLISHED.bits();
         in_rule.add_expr(&nft_expr!(ct state));
         in_rule.add_expr(&nft_expr!(bitwise mask allowed_states, xor 0u32));
@@ -678,15 +781,15 @@ impl<'a> PolicyBatch<'a> {
 
         add_verdict(&mut in_rule, &Verdict::Accept);
 
-        self.batch.add(&in_rule, nftnl::MsgType::Add);
+        self.batch.add_rule(&in_rule);
 
         let mut out_rule = Rule::new(&self.out_chain);
-        check_endpoint(&mut out_rule, End::Dst, endpoint);
+        check_endpoint_range(&mut out_rule, End::Dst, range, protocol);
         out_rule.add_expr(&nft_expr!(meta skuid));
         out_rule.add_expr(&nft_expr!(cmp == super::ROOT_UID));
         add_verdict(&mut out_rule, &Verdict::Accept);
 
-        self.batch.add(&out_rule, nftnl::MsgType::Add);
+        self.batch.add_rule(&out_rule);
     }
 
     fn add_allow_dns_rules(
@@ -718,7 +821,7 @@ impl<'a> PolicyBatch<'a> {
     ) -> Result<()> {
         for chain in &[&self.out_chain, &self.forward_chain] {
             let allow_rule = allow_tunnel_dns_rule(chain, interface, protocol, host)?;
-            self.batch.add(&allow_rule, nftnl::MsgType::Add);
+            self.batch.add_rule(&allow_rule);
         }
         Ok(())
     }
@@ -758,7 +861,7 @@ impl<'a> PolicyBatch<'a> {
             allow_rule.add_expr(&nft_expr!(cmp == host));
             add_verdict(&mut allow_rule, &Verdict::Accept);
 
-            self.batch.add(&allow_rule, nftnl::MsgType::Add);
+            self.batch.add_rule(&allow_rule);
         }
 
         Ok(())
@@ -773,12 +876,12 @@ impl<'a> PolicyBatch<'a> {
                 &mut block_udp_rule,
                 &Verdict::Reject(RejectionType::Icmp(IcmpCode::PortUnreach)),
             );
-            self.batch.add(&block_udp_rule, nftnl::MsgType::Add);
+            self.batch.add_rule(&block_udp_rule);
 
             let mut block_tcp_rule = Rule::new(chain);
             check_port(&mut block_tcp_rule, TransportProtocol::Tcp, End::Dst, 53);
             add_verdict(&mut block_tcp_rule, &Verdict::Reject(RejectionType::TcpRst));
-            self.batch.add(&block_tcp_rule, nftnl::MsgType::Add);
+            self.batch.add_rule(&block_tcp_rule);
         }
     }
 
@@ -796,24 +899,59 @@ impl<'a> PolicyBatch<'a> {
             check_ip(&mut rule, end, endpoint.address.ip());
             check_port(&mut rule, endpoint.protocol, end, endpoint.address.port());
             add_verdict(&mut rule, &Verdict::Accept);
-            self.batch.add(&rule, nftnl::MsgType::Add);
+            self.batch.add_rule(&rule);
+        }
+        Ok(())
+    }
+
+    /// Rejects outgoing traffic in the tunnel that uses any of the blocked protocols.
+    fn add_block_tunnel_protocol_rules(
+        &mut self,
+        tunnel_interface: &str,
+        blocked: &BlockedTunnelProtocols,
+    ) -> Result<()> {
+        for chain in &[&self.out_chain, &self.forward_chain] {
+            for block in super::tunnel_protocol_blocks(blocked) {
+                let mut rule = Rule::new(chain);
+                check_iface(&mut rule, Direction::Out, tunnel_interface)?;
+                let rejection = match block {
+                    TunnelProtocolBlock::Ipv6 => {
+                        rule.add_expr(&nft_expr!(meta nfproto));
+                        rule.add_expr(&nft_expr!(cmp == libc::NFPROTO_IPV6 as u8));
+                        RejectionType::Icmp(IcmpCode::AdminProhibited)
+                    }
+                    TunnelProtocolBlock::Udp => {
+                        check_l4proto(&mut rule, TransportProtocol::Udp);
+                        RejectionType::Icmp(IcmpCode::PortUnreach)
+                    }
+                    TunnelProtocolBlock::TcpPort(port) => {
+                        check_port(&mut rule, TransportProtocol::Tcp, End::Dst, port);
+                        RejectionType::TcpRst
+                    }
+                };
+                add_verdict(&mut rule, &Verdict::Reject(rejection));
+                self.batch.add_rule(&rule);
+            }
         }
         Ok(())
     }
 
     fn add_allow_tunnel_rules(&mut self, tunnel_interface: &str) -> Result<()> {
-        self.batch.add(
-            &allow_interface_rule(&self.out_chain, Direction::Out, tunnel_interface)?,
-            nftnl::MsgType::Add,
-        );
-        self.batch.add(
-            &allow_interface_rule(&self.forward_chain, Direction::Out, tunnel_interface)?,
-            nftnl::MsgType::Add,
-        );
-        self.batch.add(
-            &allow_interface_rule(&self.in_chain, Direction::In, tunnel_interface)?,
-            nftnl::MsgType::Add,
-        );
+        self.batch.add_rule(&allow_interface_rule(
+            &self.out_chain,
+            Direction::Out,
+            tunnel_interface,
+        )?);
+        self.batch.add_rule(&allow_interface_rule(
+            &self.forward_chain,
+            Direction::Out,
+            tunnel_interface,
+        )?);
+        self.batch.add_rule(&allow_interface_rule(
+            &self.in_chain,
+            Direction::In,
+            tunnel_interface,
+        )?);
 
         let mut interface_rule = Rule::new(&self.forward_chain);
         check_iface(&mut interface_rule, Direction::In, tunnel_interface)?;
@@ -822,7 +960,7 @@ impl<'a> PolicyBatch<'a> {
         interface_rule.add_expr(&nft_expr!(bitwise mask allowed_states, xor 0u32));
         interface_rule.add_expr(&nft_expr!(cmp != 0u32));
         add_verdict(&mut interface_rule, &Verdict::Accept);
-        self.batch.add(&interface_rule, nftnl::MsgType::Add);
+        self.batch.add_rule(&interface_rule);
 
         Ok(())
     }
@@ -837,7 +975,7 @@ impl<'a> PolicyBatch<'a> {
             let mut rule = Rule::new(&self.in_chain);
             check_ip(&mut rule, End::Dst, *tunnel_ip);
             add_verdict(&mut rule, &Verdict::Drop);
-            self.batch.add(&rule, nftnl::MsgType::Add);
+            self.batch.add_rule(&rule);
         }
     }
 
@@ -849,7 +987,7 @@ impl<'a> PolicyBatch<'a> {
                 let mut out_rule = Rule::new(chain);
                 check_net(&mut out_rule, End::Dst, *net);
                 add_verdict(&mut out_rule, &Verdict::Accept);
-                self.batch.add(&out_rule, nftnl::MsgType::Add);
+                self.batch.add_rule(&out_rule);
             }
 
             // LAN -> Multicast
@@ -857,7 +995,7 @@ impl<'a> PolicyBatch<'a> {
                 let mut rule = Rule::new(chain);
                 check_net(&mut rule, End::Dst, *net);
                 add_verdict(&mut rule, &Verdict::Accept);
-                self.batch.add(&rule, nftnl::MsgType::Add);
+                self.batch.add_rule(&rule);
             }
         }
 
@@ -867,11 +1005,117 @@ impl<'a> PolicyBatch<'a> {
             let mut in_rule = Rule::new(&self.in_chain);
             check_net(&mut in_rule, End::Src, *net);
             add_verdict(&mut in_rule, &Verdict::Accept);
-            self.batch.add(&in_rule, nftnl::MsgType::Add);
+            self.batch.add_rule(&in_rule);
         }
         self.add_dhcp_server_rules();
     }
 
+    /// Adds firewall rules that allow traffic to and from the given networks, so that the
+    /// machine can be reached while blocking.
+    fn add_allow_recovery_rules(&mut self, networks: &[IpNetwork]) {
+        for net in networks {
+            let mut out_rule = Rule::new(&self.out_chain);
+            check_net(&mut out_rule, End::Dst, *net);
+            add_verdict(&mut out_rule, &Verdict::Accept);
+            self.batch.add_rule(&out_rule);
+
+            let mut in_rule = Rule::new(&self.in_chain);
+            check_net(&mut in_rule, End::Src, *net);
+            add_verdict(&mut in_rule, &Verdict::Accept);
+            self.batch.add_rule(&in_rule);
+        }
+    }
+
+    /// Adds the rules of policy plugins. Block rules are added first, so that they take precedence
+    /// over the allow rules.
+    fn add_plugin_rules(&mut self, plugins: &PolicyFragment) {
+        for rule in plugins.block_rules() {
+            self.add_plugin_rule(rule, &Verdict::Drop);
+        }
+        for rule in plugins.allow_rules() {
+            self.add_plugin_rule(rule, &Verdict::Accept);
+        }
+    }
+
+    fn add_plugin_rule(&mut self, plugin_rule: &PluginRule, verdict: &Verdict) {
+        let mut out_rule = Rule::new(&self.out_chain);
+        check_net(&mut out_rule, End::Dst, plugin_rule.network);
+        check_plugin_rule_port(&mut out_rule, plugin_rule, End::Dst);
+        add_verdict(&mut out_rule, verdict);
+        self.batch.add_rule(&out_rule);
+
+        let mut in_rule = Rule::new(&self.in_chain);
+        check_net(&mut in_rule, End::Src, plugin_rule.network);
+        check_plugin_rule_port(&mut in_rule, plugin_rule, End::Src);
+        add_verdict(&mut in_rule, verdict);
+        self.batch.add_rule(&in_rule);
+    }
+
+    /// Adds firewall rules that allow the traffic of another VPN. The traffic is also marked so
+    /// that it skips the tunnel routing table, which leaves it to the routes and rules of the
+    /// other VPN.
+    fn add_vpn_coexistence_rules(&mut self, vpn_coexistence: &VpnCoexistence) {
+        for net in &vpn_coexistence.networks {
+            let mangle_chain = match net {
+                IpNetwork::V4(_) => &self.mangle_chain_v4,
+                IpNetwork::V6(_) => &self.mangle_chain_v6,
+            };
+            let mut mangle_rule = Rule::new(mangle_chain);
+            check_net(&mut mangle_rule, End::Dst, *net);
+            mangle_rule.add_expr(&nft_expr!(immediate data crate::linux::TUNNEL_FW_MARK));
+            mangle_rule.add_expr(&nft_expr!(meta mark set));
+            self.batch.add_rule(&mangle_rule);
+
+            // Prevent strict rpf from rejecting incoming packets
+            let mut prerouting_rule = Rule::new(&self.prerouting_chain);
+            check_net(&mut prerouting_rule, End::Src, *net);
+            prerouting_rule.add_expr(&nft_expr!(immediate data crate::linux::TUNNEL_FW_MARK));
+            prerouting_rule.add_expr(&nft_expr!(meta mark set));
+            self.batch.add_rule(&prerouting_rule);
+
+            let mut out_rule = Rule::new(&self.out_chain);
+            check_net(&mut out_rule, End::Dst, *net);
+            add_verdict(&mut out_rule, &Verdict::Accept);
+            self.batch.add_rule(&out_rule);
+
+            let mut in_rule = Rule::new(&self.in_chain);
+            check_net(&mut in_rule, End::Src, *net);
+            add_verdict(&mut in_rule, &Verdict::Accept);
+            self.batch.add_rule(&in_rule);
+        }
+
+        for port in &vpn_coexistence.udp_ports {
+            for chain in &[&self.mangle_chain_v4, &self.mangle_chain_v6] {
+                let mut mangle_rule = Rule::new(chain);
+                check_port(&mut mangle_rule, TransportProtocol::Udp, End::Src, *port);
+                mangle_rule.add_expr(&nft_expr!(immediate data crate::linux::TUNNEL_FW_MARK));
+                mangle_rule.add_expr(&nft_expr!(meta mark set));
+                self.batch.add_rule(&mangle_rule);
+            }
+
+            let mut prerouting_rule = Rule::new(&self.prerouting_chain);
+            check_port(
+                &mut prerouting_rule,
+                TransportProtocol::Udp,
+                End::Dst,
+                *port,
+            );
+            prerouting_rule.add_expr(&nft_expr!(immediate data crate::linux::TUNNEL_FW_MARK));
+            prerouting_rule.add_expr(&nft_expr!(meta mark set));
+            self.batch.add_rule(&prerouting_rule);
+
+            let mut out_rule = Rule::new(&self.out_chain);
+            check_port(&mut out_rule, TransportProtocol::Udp, End::Src, *port);
+            add_verdict(&mut out_rule, &Verdict::Accept);
+            self.batch.add_rule(&out_rule);
+
+            let mut in_rule = Rule::new(&self.in_chain);
+            check_port(&mut in_rule, TransportProtocol::Udp, End::Dst, *port);
+            add_verdict(&mut in_rule, &Verdict::Accept);
+            self.batch.add_rule(&in_rule);
+        }
+    }
+
     fn add_dhcp_server_rules(&mut self) {
         use TransportProtocol::Udp;
         // Outgoing DHCPv4 response
@@ -880,7 +1124,7 @@ impl<'a> PolicyBatch<'a> {
             check_port(&mut out_v4, Udp, End::Src, super::DHCPV4_SERVER_PORT);
             check_port(&mut out_v4, Udp, End::Dst, super::DHCPV4_CLIENT_PORT);
             add_verdict(&mut out_v4, &Verdict::Accept);
-            self.batch.add(&out_v4, nftnl::MsgType::Add);
+            self.batch.add_rule(&out_v4);
         }
         // Incoming DHCPv4 request
         {
@@ -892,7 +1136,7 @@ impl<'a> PolicyBatch<'a> {
                 &Endpoint::new(Ipv4Addr::BROADCAST, super::DHCPV4_SERVER_PORT, Udp),
             );
             add_verdict(&mut in_v4, &Verdict::Accept);
-            self.batch.add(&in_v4, nftnl::MsgType::Add);
+            self.batch.add_rule(&in_v4);
         }
     }
 }
@@ -999,6 +1243,16 @@ fn check_endpoint(rule: &mut Rule<'_>, end: End, endpoint: &Endpoint) {
     check_port(rule, endpoint.protocol, end, endpoint.address.port());
 }
 
+fn check_endpoint_range(
+    rule: &mut Rule<'_>,
+    end: End,
+    range: &EndpointRange,
+    protocol: TransportProtocol,
+) {
+    check_ip(rule, end, range.address);
+    check_port_range(rule, protocol, end, range.ports);
+}
+
 fn check_ip(rule: &mut Rule<'_>, end: End, ip: impl Into<IpAddr>) {
     let ip = ip.into();
     // Must check network layer protocol before loading network layer payload
@@ -1029,6 +1283,34 @@ fn check_port(rule: &mut Rule<'_>, protocol: TransportProtocol, end: End, port:
     rule.add_expr(&nft_expr!(cmp == port.to_be()));
 }
 
+fn check_port_range(rule: &mut Rule<'_>, protocol: TransportProtocol, end: End, ports: PortRange) {
+    if ports.is_single() {
+        return check_port(rule, protocol, end, ports.start());
+    }
+
+    check_l4proto(rule, protocol);
+
+    rule.add_expr(&match (protocol, end) {
+        (TransportProtocol::Udp, End::Src) => nft_expr!(payload udp sport),
+        (TransportProtocol::Udp, End::Dst) => nft_expr!(payload udp dport),
+        (TransportProtocol::Tcp, End::Src) => nft_expr!(payload tcp sport),
+        (TransportProtocol::Tcp, End::Dst) => nft_expr!(payload tcp dport),
+    });
+    // Ports are compared in network byte order, which preserves their ordering.
+    rule.add_expr(&expr::Cmp::new(expr::CmpOp::Gte, ports.start().to_be()));
+    rule.add_expr(&expr::Cmp::new(expr::CmpOp::Lte, ports.end().to_be()));
+}
+
+/// Matches the protocol and remote port of a plugin rule, if it has any. `remote_end` is the end
+/// of the packet that the remote host is on.
+fn check_plugin_rule_port(rule: &mut Rule<'_>, plugin_rule: &PluginRule, remote_end: End) {
+    match (plugin_rule.protocol, plugin_rule.port) {
+        (Some(protocol), Some(port)) => check_port(rule, protocol, remote_end, port),
+        (Some(protocol), None) => check_l4proto(rule, protocol),
+        (None, _) => (),
+    }
+}
+
 fn check_l3proto(rule: &mut Rule<'_>, ip: IpAddr) {
     rule.add_expr(&nft_expr!(meta nfproto));
     rule.add_expr(&nft_expr!(cmp == l3proto(ip)));
@@ -1054,7 +1336,7 @@ fn l4proto(protocol: TransportProtocol) -> u8 {
 }
 
 fn add_verdict(rule: &mut Rule<'_>, verdict: &expr::Verdict) {
-    if *ADD_COUNTERS {
+    if add_counters() {
         rule.add_expr(&nft_expr!(counter));
     }
     rule.add_expr(verdict);
@@ -1,25 +1,20 @@
-use super::{FirewallArguments, FirewallPolicy};
-use ipnetwork::IpNetwork;
-use pfctl::{DropAction, FilterRuleAction, Uid};
-use std::{
-    env,
-    net::{IpAddr, Ipv4Addr},
+use super::{
+    pf::{self, RuleGenerator, ANCHOR_NAME},
+    plugin::PolicyFragment,
+    FirewallArguments, FirewallPolicy,
 };
+use pfctl::{DropAction, FilterRuleAction, Uid};
 use subslice::SubsliceExt;
-use talpid_types::net::{self, AllowedTunnelTraffic};
 
 pub use pfctl::Error;
 
 type Result<T> = std::result::Result<T, Error>;
 
-/// TODO(linus): This crate is not supposed to be Mullvad-aware. So at some point this should be
-/// replaced by allowing the anchor name to be configured from the public API of this crate.
-const ANCHOR_NAME: &'static str = "mullvad";
-
 pub struct Firewall {
     pf: pfctl::PfCtl,
     pf_was_enabled: Option<bool>,
-    rule_logging: RuleLogging,
+    rules: RuleGenerator,
+    rule_count: Option<usize>,
 }
 
 impl Firewall {
@@ -28,31 +23,22 @@ impl Firewall {
     }
 
     pub fn new() -> Result<Self> {
-        // Allows controlling whether firewall rules should log to pflog0. Useful for debugging the
-        // rules.
-        let firewall_debugging = env::var("TALPID_FIREWALL_DEBUG");
-        let rule_logging = match firewall_debugging.as_ref().map(String::as_str) {
-            Ok("pass") => RuleLogging::Pass,
-            Ok("drop") => RuleLogging::Drop,
-            Ok("all") => RuleLogging::All,
-            Ok(_) | Err(_) => RuleLogging::None,
-        };
-        log::trace!("Firewall debug log policy: {:?}", rule_logging);
-
         Ok(Firewall {
             pf: pfctl::PfCtl::new()?,
             pf_was_enabled: None,
-            rule_logging,
+            rules: RuleGenerator::from_env(),
+            rule_count: None,
         })
     }
 
-    pub fn apply_policy(&mut self, policy: FirewallPolicy) -> Result<()> {
+    pub fn apply_policy(&mut self, policy: FirewallPolicy, plugins: &PolicyFragment) -> Result<()> {
         self.enable()?;
         self.add_anchor()?;
-        self.set_rules(policy)
+        self.set_rules(policy, plugins)
     }
 
     pub fn reset_policy(&mut self) -> Result<()> {
+        self.rule_count = None;
         // Implemented this way to not early return on an error.
         // We always want all three methods to run, and then return
         // the first error it encounterd, if any.
@@ -61,31 +47,28 @@ impl Firewall {
             .and(self.restore_state())
     }
 
-    fn set_rules(&mut self, policy: FirewallPolicy) -> Result<()> {
-        let mut new_filter_rules = vec![];
-
-        new_filter_rules.append(&mut self.get_allow_loopback_rules()?);
-        new_filter_rules.append(&mut self.get_allow_dhcp_client_rules()?);
-        new_filter_rules.append(&mut self.get_allow_ndp_rules()?);
-        new_filter_rules.append(&mut self.get_policy_specific_rules(&policy)?);
+    /// Returns the number of rules added to the anchor by the last applied policy.
+    pub fn rule_count(&self) -> Option<usize> {
+        self.rule_count
+    }
 
-        let return_out_rule = self
-            .create_rule_builder(FilterRuleAction::Drop(DropAction::Return))
-            .direction(pfctl::Direction::Out)
-            .quick(true)
-            .build()?;
-        new_filter_rules.push(return_out_rule);
+    fn set_rules(&mut self, policy: FirewallPolicy, plugins: &PolicyFragment) -> Result<()> {
+        let new_filter_rules = self
+            .rules
+            .filter_rules(&policy, plugins)
+            .iter()
+            .map(as_pfctl_rule)
+            .collect::<Result<Vec<_>>>()?;
 
-        let drop_all_rule = self
-            .create_rule_builder(FilterRuleAction::Drop(DropAction::Drop))
-            .quick(true)
-            .build()?;
-        new_filter_rules.push(drop_all_rule);
+        let redirect_rules = self.get_dns_redirect_rules(&policy)?;
+        let rule_count = new_filter_rules.len() + redirect_rules.len();
 
         let mut anchor_change = pfctl::AnchorChange::new();
         anchor_change.set_filter_rules(new_filter_rules);
-        anchor_change.set_redirect_rules(self.get_dns_redirect_rules(&policy)?);
-        Ok(self.pf.set_rules(ANCHOR_NAME, anchor_change)?)
+        anchor_change.set_redirect_rules(redirect_rules);
+        self.pf.set_rules(ANCHOR_NAME, anchor_change)?;
+        self.rule_count = Some(rule_count);
+        Ok(())
     }
 
     fn get_dns_redirect_rules(
@@ -109,489 +92,6 @@ impl Firewall {
         Ok(redirect_rules)
     }
 
-    fn get_policy_specific_rules(
-        &mut self,
-        policy: &FirewallPolicy,
-    ) -> Result<Vec<pfctl::FilterRule>> {
-        match policy {
-            FirewallPolicy::Connecting {
-                peer_endpoint,
-                tunnel,
-                allow_lan,
-                allowed_endpoint,
-                allowed_tunnel_traffic,
-            } => {
-                let mut rules = vec![self.get_allow_relay_rule(*peer_endpoint)?];
-                rules.push(self.get_allowed_endpoint_rule(allowed_endpoint.endpoint)?);
-
-                // Important to block DNS after allow relay rule (so the relay can operate
-                // over port 53) but before allow LAN (so DNS does not leak to the LAN)
-                rules.append(&mut self.get_block_dns_rules()?);
-
-                if let Some(tunnel) = tunnel {
-                    rules.extend(
-                        self.get_allow_tunnel_rule(&tunnel.interface, allowed_tunnel_traffic)?
-                            .into_iter(),
-                    );
-                }
-
-                if *allow_lan {
-                    rules.append(&mut self.get_allow_lan_rules()?);
-                }
-                Ok(rules)
-            }
-            FirewallPolicy::Connected {
-                peer_endpoint,
-                tunnel,
-                allow_lan,
-                dns_servers,
-            } => {
-                let mut rules = vec![];
-
-                for server in dns_servers.iter() {
-                    rules.append(&mut self.get_allow_dns_rules_when_connected(&tunnel, *server)?);
-                }
-
-                rules.push(self.get_allow_relay_rule(*peer_endpoint)?);
-
-                // Important to block DNS *before* we allow the tunnel and allow LAN. So DNS
-                // can't leak to the wrong IPs in the tunnel or on the LAN.
-                rules.append(&mut self.get_block_dns_rules()?);
-
-                rules.extend(
-                    self.get_allow_tunnel_rule(
-                        tunnel.interface.as_str(),
-                        &AllowedTunnelTraffic::All,
-                    )?
-                    .into_iter(),
-                );
-
-                if *allow_lan {
-                    rules.append(&mut self.get_allow_lan_rules()?);
-                }
-
-                Ok(rules)
-            }
-            FirewallPolicy::Blocked {
-                allow_lan,
-                allowed_endpoint,
-                ..
-            } => {
-                let mut rules = Vec::new();
-                if let Some(allowed_endpoint) = allowed_endpoint {
-                    rules.push(self.get_allowed_endpoint_rule(allowed_endpoint.endpoint)?);
-                }
-
-                if *allow_lan {
-                    // Important to block DNS before allow LAN (so DNS does not leak to the LAN)
-                    rules.append(&mut self.get_block_dns_rules()?);
-                    rules.append(&mut self.get_allow_lan_rules()?);
-                }
-
-                Ok(rules)
-            }
-        }
-    }
-
-    fn get_allow_dns_rules_when_connected(
-        &self,
-        tunnel: &crate::tunnel::TunnelMetadata,
-        server: IpAddr,
-    ) -> Result<Vec<pfctl::FilterRule>> {
-        let mut rules = Vec::with_capacity(4);
-
-        let is_local = super::is_local_address(&server)
-            && server != tunnel.ipv4_gateway
-            && !tunnel
-                .ipv6_gateway
-                .map(|ref gateway| &server == gateway)
-                .unwrap_or(false);
-
-        if is_local {
-            // Block requests on the tunnel interface
-            let block_tunnel_tcp = self
-                .create_rule_builder(FilterRuleAction::Drop(DropAction::Return))
-                .direction(pfctl::Direction::Out)
-                .quick(true)
-                .interface(&tunnel.interface)
-                .proto(pfctl::Proto::Tcp)
-                .keep_state(pfctl::StatePolicy::None)
-                .to(pfctl::Endpoint::new(server, 53))
-                .build()?;
-            rules.push(block_tunnel_tcp);
-            let block_tunnel_udp = self
-                .create_rule_builder(FilterRuleAction::Drop(DropAction::Return))
-                .direction(pfctl::Direction::Out)
-                .quick(true)
-                .interface(&tunnel.interface)
-                .proto(pfctl::Proto::Udp)
-                .keep_state(pfctl::StatePolicy::None)
-                .to(pfctl::Endpoint::new(server, 53))
-                .build()?;
-            rules.push(block_tunnel_udp);
-
-            // Allow requests on other interfaces
-            let allow_nontunnel_tcp = self
-                .create_rule_builder(FilterRuleAction::Pass)
-                .direction(pfctl::Direction::Out)
-                .quick(true)
-                .proto(pfctl::Proto::Tcp)
-                .keep_state(pfctl::StatePolicy::Keep)
-                .tcp_flags(Self::get_tcp_flags())
-                .to(pfctl::Endpoint::new(server, 53))
-                .build()?;
-            rules.push(allow_nontunnel_tcp);
-            let allow_nontunnel_udp = self
-                .create_rule_builder(FilterRuleAction::Pass)
-                .direction(pfctl::Direction::Out)
-                .quick(true)
-                .proto(pfctl::Proto::Udp)
-                .keep_state(pfctl::StatePolicy::Keep)
-                .to(pfctl::Endpoint::new(server, 53))
-                .build()?;
-            rules.push(allow_nontunnel_udp);
-        } else {
-            // Allow outgoing requests on the tunnel interface only
-            let allow_tunnel_tcp = self
-                .create_rule_builder(FilterRuleAction::Pass)
-                .direction(pfctl::Direction::Out)
-                .quick(true)
-                .interface(&tunnel.interface)
-                .proto(pfctl::Proto::Tcp)
-                .keep_state(pfctl::StatePolicy::Keep)
-                .tcp_flags(Self::get_tcp_flags())
-                .to(pfctl::Endpoint::new(server, 53))
-                .build()?;
-            rules.push(allow_tunnel_tcp);
-            let allow_tunnel_udp = self
-                .create_rule_builder(FilterRuleAction::Pass)
-                .direction(pfctl::Direction::Out)
-                .quick(true)
-                .interface(&tunnel.interface)
-                .proto(pfctl::Proto::Udp)
-                .to(pfctl::Endpoint::new(server, 53))
-                .build()?;
-            rules.push(allow_tunnel_udp);
-        };
-
-        Ok(rules)
-    }
-
-    fn get_allow_relay_rule(&self, relay_endpoint: net::Endpoint) -> Result<pfctl::FilterRule> {
-        let pfctl_proto = as_pfctl_proto(relay_endpoint.protocol);
-
-        Ok(self
-            .create_rule_builder(FilterRuleAction::Pass)
-            .direction(pfctl::Direction::Out)
-            .to(relay_endpoint.address)
-            .proto(pfctl_proto)
-            .keep_state(pfctl::StatePolicy::Keep)
-            .tcp_flags(Self::get_tcp_flags())
-            .user(Uid::from(super::ROOT_UID))
-            .quick(true)
-            .build()?)
-    }
-
-    /// Produces a rule that allows traffic to flow to the API. Allows the app to reach the API in
-    /// blocked states.
-    fn get_allowed_endpoint_rule(
-        &self,
-        allowed_endpoint: net::Endpoint,
-    ) -> Result<pfctl::FilterRule> {
-        let pfctl_proto = as_pfctl_proto(allowed_endpoint.protocol);
-
-        Ok(self
-            .create_rule_builder(FilterRuleAction::Pass)
-            .direction(pfctl::Direction::Out)
-            .to(allowed_endpoint.address)
-            .proto(pfctl_proto)
-            .keep_state(pfctl::StatePolicy::Keep)
-            .user(Uid::from(super::ROOT_UID))
-            .quick(true)
-            .build()?)
-    }
-
-    fn get_block_dns_rules(&self) -> Result<Vec<pfctl::FilterRule>> {
-        let block_tcp_dns_rule = self
-            .create_rule_builder(FilterRuleAction::Drop(DropAction::Return))
-            .direction(pfctl::Direction::Out)
-            .quick(true)
-            .proto(pfctl::Proto::Tcp)
-            .to(pfctl::Port::from(53))
-            .build()?;
-        let block_udp_dns_rule = self
-            .create_rule_builder(FilterRuleAction::Drop(DropAction::Return))
-            .direction(pfctl::Direction::Out)
-            .quick(true)
-            .proto(pfctl::Proto::Udp)
-            .to(pfctl::Port::from(53))
-            .build()?;
-
-        Ok(vec![block_tcp_dns_rule, block_udp_dns_rule])
-    }
-
-    fn get_allow_tunnel_rule(
-        &self,
-        tunnel_interface: &str,
-        allowed_traffic: &AllowedTunnelTraffic,
-    ) -> Result<Option<pfctl::FilterRule>> {
-        let mut rule_builder = self.create_rule_builder(FilterRuleAction::Pass);
-        let mut base_rule = rule_builder
-            .quick(true)
-            .interface(tunnel_interface)
-            .keep_state(pfctl::StatePolicy::Keep)
-            .tcp_flags(Self::get_tcp_flags());
-        match allowed_traffic {
-            AllowedTunnelTraffic::Only(endpoint) => {
-                let pfctl_proto = as_pfctl_proto(endpoint.protocol);
-                base_rule = base_rule.to(endpoint.address).proto(pfctl_proto);
-            }
-            AllowedTunnelTraffic::All => {}
-            AllowedTunnelTraffic::None => {
-                return Ok(None);
-            }
-        };
-        Ok(Some(base_rule.build()?))
-    }
-
-    fn get_allow_loopback_rules(&self) -> Result<Vec<pfctl::FilterRule>> {
-        let lo0_rule = self
-            .create_rule_builder(FilterRuleAction::Pass)
-            .quick(true)
-            .interface("lo0")
-            .keep_state(pfctl::StatePolicy::Keep)
-            .build()?;
-        Ok(vec![lo0_rule])
-    }
-
-    fn get_allow_lan_rules(&self) -> Result<Vec<pfctl::FilterRule>> {
-        let mut rules = vec![];
-        for net in &*super::ALLOWED_LAN_NETS {
-            let mut rule_builder = self.create_rule_builder(FilterRuleAction::Pass);
-            rule_builder.quick(true);
-            let allow_out = rule_builder
-                .direction(pfctl::Direction::Out)
-                .from(pfctl::Ip::Any)
-                .to(pfctl::Ip::from(*net))
-                .build()?;
-            let allow_in = rule_builder
-                .direction(pfctl::Direction::In)
-                .from(pfctl::Ip::from(*net))
-                .to(pfctl::Ip::Any)
-                .build()?;
-            rules.push(allow_out);
-            rules.push(allow_in);
-        }
-        for multicast_net in &*super::ALLOWED_LAN_MULTICAST_NETS {
-            let allow_multicast_out = self
-                .create_rule_builder(FilterRuleAction::Pass)
-                .quick(true)
-                .direction(pfctl::Direction::Out)
-                .to(pfctl::Ip::from(*multicast_net))
-                .build()?;
-            rules.push(allow_multicast_out);
-        }
-
-        let dhcpv4_out = self
-            .create_rule_builder(FilterRuleAction::Pass)
-            .quick(true)
-            .direction(pfctl::Direction::Out)
-            .af(pfctl::AddrFamily::Ipv4)
-            .proto(pfctl::Proto::Udp)
-            .from(pfctl::Port::from(super::DHCPV4_SERVER_PORT))
-            .to(pfctl::Port::from(super::DHCPV4_CLIENT_PORT))
-            .build()?;
-        let dhcpv4_in = self
-            .create_rule_builder(FilterRuleAction::Pass)
-            .quick(true)
-            .direction(pfctl::Direction::In)
-            .proto(pfctl::Proto::Udp)
-            .from(pfctl::Port::from(super::DHCPV4_CLIENT_PORT))
-            .to(pfctl::Endpoint::new(
-                Ipv4Addr::BROADCAST,
-                pfctl::Port::from(super::DHCPV4_SERVER_PORT),
-            ))
-            .build()?;
-        rules.push(dhcpv4_out);
-        rules.push(dhcpv4_in);
-
-        Ok(rules)
-    }
-
-    fn get_allow_dhcp_client_rules(&self) -> Result<Vec<pfctl::FilterRule>> {
-        let mut dhcp_rule_builder = self.create_rule_builder(FilterRuleAction::Pass);
-        dhcp_rule_builder.quick(true).proto(pfctl::Proto::Udp);
-
-        let mut rules = Vec::new();
-
-        // DHCPv4
-        dhcp_rule_builder.af(pfctl::AddrFamily::Ipv4);
-        let allow_outgoing_dhcp_v4 = dhcp_rule_builder
-            .direction(pfctl::Direction::Out)
-            .from(pfctl::Port::from(super::DHCPV4_CLIENT_PORT))
-            .to(pfctl::Endpoint::new(
-                Ipv4Addr::BROADCAST,
-                pfctl::Port::from(super::DHCPV4_SERVER_PORT),
-            ))
-            .build()?;
-        let allow_incoming_dhcp_v4 = dhcp_rule_builder
-            .direction(pfctl::Direction::In)
-            .from(pfctl::Port::from(super::DHCPV4_SERVER_PORT))
-            .to(pfctl::Port::from(super::DHCPV4_CLIENT_PORT))
-            .build()?;
-        rules.push(allow_outgoing_dhcp_v4);
-        rules.push(allow_incoming_dhcp_v4);
-
-        // DHCPv6
-        dhcp_rule_builder.af(pfctl::AddrFamily::Ipv6);
-        for dhcpv6_server in &*super::DHCPV6_SERVER_ADDRS {
-            let allow_outgoing_dhcp_v6 = dhcp_rule_builder
-                .direction(pfctl::Direction::Out)
-                .from(pfctl::Endpoint::new(
-                    IpNetwork::V6(*super::IPV6_LINK_LOCAL),
-                    pfctl::Port::from(super::DHCPV6_CLIENT_PORT),
-                ))
-                .to(pfctl::Endpoint::new(
-                    *dhcpv6_server,
-                    pfctl::Port::from(super::DHCPV6_SERVER_PORT),
-                ))
-                .build()?;
-            rules.push(allow_outgoing_dhcp_v6);
-        }
-        let allow_incoming_dhcp_v6 = dhcp_rule_builder
-            .direction(pfctl::Direction::In)
-            .from(pfctl::Endpoint::new(
-                pfctl::Ip::from(IpNetwork::V6(*super::IPV6_LINK_LOCAL)),
-                pfctl::Port::from(super::DHCPV6_SERVER_PORT),
-            ))
-            .to(pfctl::Endpoint::new(
-                pfctl::Ip::from(IpNetwork::V6(*super::IPV6_LINK_LOCAL)),
-                pfctl::Port::from(super::DHCPV6_CLIENT_PORT),
-            ))
-            .build()?;
-        rules.push(allow_incoming_dhcp_v6);
-
-        Ok(rules)
-    }
-
-    fn get_allow_ndp_rules(&self) -> Result<Vec<pfctl::FilterRule>> {
-        let mut ndp_rule_builder = self.create_rule_builder(FilterRuleAction::Pass);
-        ndp_rule_builder
-            .quick(true)
-            .af(pfctl::AddrFamily::Ipv6)
-            .proto(pfctl::Proto::IcmpV6);
-
-        let mut rules = Vec::new();
-
-        // Outgoing router solicitation to `ff02::2`
-        rules.push(
-            ndp_rule_builder
-                .clone()
-                .direction(pfctl::Direction::Out)
-                .icmp_type(pfctl::IcmpType::Icmp6(pfctl::Icmp6Type::RouterSol))
-                .to(*super::ROUTER_SOLICITATION_OUT_DST_ADDR)
-                .build()?,
-        );
-
-        // Incoming router advertisement from `fe80::/10`
-        rules.push(
-            ndp_rule_builder
-                .clone()
-                .direction(pfctl::Direction::In)
-                .icmp_type(pfctl::IcmpType::Icmp6(pfctl::Icmp6Type::RouterAdv))
-                .from(pfctl::Ip::from(IpNetwork::V6(*super::IPV6_LINK_LOCAL)))
-                .build()?,
-        );
-
-        // Incoming Redirect from `fe80::/10`
-        rules.push(
-            ndp_rule_builder
-                .clone()
-                .direction(pfctl::Direction::In)
-                .icmp_type(pfctl::IcmpType::Icmp6(pfctl::Icmp6Type::Redir))
-                .from(pfctl::Ip::from(IpNetwork::V6(*super::IPV6_LINK_LOCAL)))
-                .build()?,
-        );
-
-        // Outgoing neighbor solicitation to `ff02::1:ff00:0/104` and `fe80::/10`
-        rules.push(
-            ndp_rule_builder
-                .clone()
-                .direction(pfctl::Direction::Out)
-                .icmp_type(pfctl::IcmpType::Icmp6(pfctl::Icmp6Type::NeighbrSol))
-                .to(pfctl::Ip::from(IpNetwork::V6(
-                    *super::SOLICITED_NODE_MULTICAST,
-                )))
-                .build()?,
-        );
-        rules.push(
-            ndp_rule_builder
-                .clone()
-                .direction(pfctl::Direction::Out)
-                .icmp_type(pfctl::IcmpType::Icmp6(pfctl::Icmp6Type::NeighbrSol))
-                .to(pfctl::Ip::from(IpNetwork::V6(*super::IPV6_LINK_LOCAL)))
-                .build()?,
-        );
-
-        // Incoming neighbor solicitation from `fe80::/10`
-        rules.push(
-            ndp_rule_builder
-                .clone()
-                .direction(pfctl::Direction::In)
-                .icmp_type(pfctl::IcmpType::Icmp6(pfctl::Icmp6Type::NeighbrSol))
-                .from(pfctl::Ip::from(IpNetwork::V6(*super::IPV6_LINK_LOCAL)))
-                .build()?,
-        );
-
-        // Outgoing neigbor advertisement to fe80::/10`
-        rules.push(
-            ndp_rule_builder
-                .clone()
-                .direction(pfctl::Direction::Out)
-                .icmp_type(pfctl::IcmpType::Icmp6(pfctl::Icmp6Type::NeighbrAdv))
-                .to(pfctl::Ip::from(IpNetwork::V6(*super::IPV6_LINK_LOCAL)))
-                .build()?,
-        );
-
-        // Incoming neigbor advertisement from anywhere
-        rules.push(
-            ndp_rule_builder
-                .clone()
-                .direction(pfctl::Direction::In)
-                .icmp_type(pfctl::IcmpType::Icmp6(pfctl::Icmp6Type::NeighbrAdv))
-                .build()?,
-        );
-
-        Ok(rules)
-    }
-
-    fn create_rule_builder(&self, action: FilterRuleAction) -> pfctl::FilterRuleBuilder {
-        let mut builder = pfctl::FilterRuleBuilder::default();
-        builder.action(action);
-        let rule_log = pfctl::RuleLog::IncludeMatchingState;
-        let do_log = match action {
-            FilterRuleAction::Pass => match self.rule_logging {
-                RuleLogging::All | RuleLogging::Pass => true,
-                _ => false,
-            },
-            FilterRuleAction::Drop(..) => match self.rule_logging {
-                RuleLogging::All | RuleLogging::Drop => true,
-                _ => false,
-            },
-        };
-        if do_log {
-            builder.log(rule_log);
-        }
-        builder
-    }
-
-    fn get_tcp_flags() -> pfctl::TcpFlags {
-        pfctl::TcpFlags::new(
-            &[pfctl::TcpFlag::Syn],
-            &[pfctl::TcpFlag::Syn, pfctl::TcpFlag::Ack],
-        )
-    }
-
     fn remove_rules(&mut self) -> Result<()> {
         // remove_anchor() does not deactivate active rules
         self.pf
@@ -648,17 +148,289 @@ impl Firewall {
     }
 }
 
-fn as_pfctl_proto(protocol: net::TransportProtocol) -> pfctl::Proto {
-    match protocol {
-        net::TransportProtocol::Udp => pfctl::Proto::Udp,
-        net::TransportProtocol::Tcp => pfctl::Proto::Tcp,
+/// Converts a rule that is shared with the other platforms that use pf to a rule that can be
+/// added through the ioctl interface.
+fn as_pfctl_rule(rule: &pf::Rule) -> Result<pfctl::FilterRule> {
+    let mut builder = pfctl::FilterRuleBuilder::default();
+    builder.action(match rule.action {
+        pf::Action::Pass => FilterRuleAction::Pass,
+        pf::Action::Return => FilterRuleAction::Drop(DropAction::Return),
+        pf::Action::Drop => FilterRuleAction::Drop(DropAction::Drop),
+    });
+    if let Some(direction) = rule.direction {
+        builder.direction(match direction {
+            pf::Direction::In => pfctl::Direction::In,
+            pf::Direction::Out => pfctl::Direction::Out,
+        });
+    }
+    if rule.log {
+        builder.log(pfctl::RuleLog::IncludeMatchingState);
+    }
+    builder.quick(rule.quick);
+    if let Some(interface) = &rule.interface {
+        builder.interface(interface);
     }
+    if let Some(af) = rule.af {
+        builder.af(match af {
+            pf::AddrFamily::Inet => pfctl::AddrFamily::Ipv4,
+            pf::AddrFamily::Inet6 => pfctl::AddrFamily::Ipv6,
+        });
+    }
+    if let Some(proto) = rule.proto {
+        builder.proto(match proto {
+            pf::Proto::Tcp => pfctl::Proto::Tcp,
+            pf::Proto::Udp => pfctl::Proto::Udp,
+            pf::Proto::Icmp6 => pfctl::Proto::IcmpV6,
+        });
+    }
+    builder
+        .from(as_pfctl_endpoint(&rule.from))
+        .to(as_pfctl_endpoint(&rule.to));
+    if let Some(uid) = rule.user {
+        builder.user(Uid::from(uid));
+    }
+    if let Some(icmp6_type) = rule.icmp6_type {
+        builder.icmp_type(pfctl::IcmpType::Icmp6(match icmp6_type {
+            pf::Icmp6Type::RouterSol => pfctl::Icmp6Type::RouterSol,
+            pf::Icmp6Type::RouterAdv => pfctl::Icmp6Type::RouterAdv,
+            pf::Icmp6Type::Redir => pfctl::Icmp6Type::Redir,
+            pf::Icmp6Type::NeighbrSol => pfctl::Icmp6Type::NeighbrSol,
+            pf::Icmp6Type::NeighbrAdv => pfctl::Icmp6Type::NeighbrAdv,
+        }));
+    }
+    if rule.keep_state {
+        builder.keep_state(pfctl::StatePolicy::Keep);
+    }
+    if rule.tcp_flags {
+        builder.tcp_flags(pfctl::TcpFlags::new(
+            &[pfctl::TcpFlag::Syn],
+            &[pfctl::TcpFlag::Syn, pfctl::TcpFlag::Ack],
+        ));
+    }
+    Ok(builder.build()?)
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
-enum RuleLogging {
-    None,
-    Pass,
-    Drop,
-    All,
+fn as_pfctl_endpoint(target: &pf::Target) -> pfctl::Endpoint {
+    let ip = target
+        .address
+        .map(pfctl::Ip::from)
+        .unwrap_or(pfctl::Ip::Any);
+    let port = match target.port {
+        Some(pf::Port::Single(port)) => pfctl::Port::from(port),
+        Some(pf::Port::Range(start, end)) => {
+            pfctl::Port::Range(start, end, pfctl::PortRangeModifier::Inclusive)
+        }
+        None => pfctl::Port::Any,
+    };
+    pfctl::Endpoint::new(ip, port)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        super::{
+            macos_legacy_rules::LegacyRules,
+            pf::RuleLogging,
+            plugin::{PluginAction, PluginRule},
+        },
+        *,
+    };
+    use crate::tunnel::TunnelMetadata;
+    use std::net::IpAddr;
+    use talpid_types::net::{
+        AllowedEndpoint, AllowedTunnelTraffic, BlockedTunnelProtocols, Endpoint, EndpointRange,
+        PortRange, TransportProtocol, VpnCoexistence,
+    };
+
+    /// Checks that the shared rules are loaded exactly like the rules that the macOS firewall
+    /// generated itself, with and without plugin rules and with every kind of rule logging.
+    fn assert_same_rules_as_before(policy: &FirewallPolicy) {
+        let plugins = [
+            PolicyFragment::default(),
+            PolicyFragment::from_rules(vec![
+                PluginRule {
+                    action: PluginAction::Block,
+                    network: "10.0.0.0/8".parse().unwrap(),
+                    protocol: Some(TransportProtocol::Tcp),
+                    port: Some(22),
+                },
+                PluginRule {
+                    action: PluginAction::Allow,
+                    network: "192.0.2.0/24".parse().unwrap(),
+                    protocol: None,
+                    port: None,
+                },
+            ]),
+        ];
+        for plugins in &plugins {
+            for rule_logging in [
+                RuleLogging::None,
+                RuleLogging::Pass,
+                RuleLogging::Drop,
+                RuleLogging::All,
+            ] {
+                let rules: Vec<String> = RuleGenerator::new(rule_logging)
+                    .filter_rules(policy, plugins)
+                    .iter()
+                    .map(|rule| as_pfctl_rule(rule).map(|rule| format!("{:?}", rule)))
+                    .collect::<Result<_>>()
+                    .unwrap();
+                let legacy_rules: Vec<String> = LegacyRules { rule_logging }
+                    .filter_rules(policy, plugins)
+                    .unwrap()
+                    .iter()
+                    .map(|rule| format!("{:?}", rule))
+                    .collect();
+                assert_eq!(
+                    rules, legacy_rules,
+                    "{} with {:?} logging and plugins {:?}",
+                    policy, rule_logging, plugins
+                );
+            }
+        }
+    }
+
+    fn peer_endpoint() -> Endpoint {
+        Endpoint::new(
+            "185.65.134.1".parse::<IpAddr>().unwrap(),
+            51820,
+            TransportProtocol::Udp,
+        )
+    }
+
+    fn tunnel() -> TunnelMetadata {
+        TunnelMetadata {
+            interface: "utun4".to_owned(),
+            ips: vec!["10.64.0.2".parse().unwrap()],
+            ipv4_gateway: "10.64.0.1".parse().unwrap(),
+            ipv6_gateway: Some("fc00:bbbb:bbbb:bb01::1".parse().unwrap()),
+            wireguard_backend: None,
+        }
+    }
+
+    fn allowed_endpoint() -> AllowedEndpoint {
+        AllowedEndpoint {
+            endpoints: vec![
+                EndpointRange::from(Endpoint::new(
+                    "45.83.223.196".parse::<IpAddr>().unwrap(),
+                    443,
+                    TransportProtocol::Tcp,
+                )),
+                EndpointRange {
+                    address: "45.83.223.197".parse().unwrap(),
+                    ports: PortRange::new(3000, 3010).unwrap(),
+                    protocol: None,
+                },
+            ],
+            hostname: None,
+        }
+    }
+
+    fn vpn_coexistence_options() -> [VpnCoexistence; 2] {
+        [
+            VpnCoexistence::default(),
+            VpnCoexistence {
+                networks: vec!["10.8.0.0/24".parse().unwrap()],
+                udp_ports: vec![51821],
+            },
+        ]
+    }
+
+    #[test]
+    fn test_connecting_rules() {
+        let allowed_tunnel_traffic = [
+            AllowedTunnelTraffic::None,
+            AllowedTunnelTraffic::All,
+            AllowedTunnelTraffic::Only(Endpoint::new(
+                "10.64.0.1".parse::<IpAddr>().unwrap(),
+                1337,
+                TransportProtocol::Tcp,
+            )),
+            AllowedTunnelTraffic::Dns(vec![
+                "10.64.0.1".parse().unwrap(),
+                "fc00:bbbb:bbbb:bb01::1".parse().unwrap(),
+            ]),
+            AllowedTunnelTraffic::Endpoints {
+                addresses: vec![
+                    "10.64.0.1".parse().unwrap(),
+                    "fc00:bbbb:bbbb:bb01::1".parse().unwrap(),
+                ],
+                port: 1337,
+                protocol: TransportProtocol::Udp,
+            },
+        ];
+        for tunnel in [None, Some(tunnel())] {
+            for allowed_tunnel_traffic in &allowed_tunnel_traffic {
+                for allow_lan in [false, true] {
+                    for vpn_coexistence in vpn_coexistence_options() {
+                        assert_same_rules_as_before(&FirewallPolicy::Connecting {
+                            peer_endpoint: peer_endpoint(),
+                            tunnel: tunnel.clone(),
+                            allow_lan,
+                            allowed_endpoint: allowed_endpoint(),
+                            allowed_tunnel_traffic: allowed_tunnel_traffic.clone(),
+                            vpn_coexistence,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_connected_rules() {
+        let dns_servers: [Vec<IpAddr>; 3] = [
+            vec![],
+            // The tunnel gateways, which are not treated as local servers
+            vec![
+                "10.64.0.1".parse().unwrap(),
+                "fc00:bbbb:bbbb:bb01::1".parse().unwrap(),
+            ],
+            // A local and a public server
+            vec!["192.168.1.1".parse().unwrap(), "8.8.8.8".parse().unwrap()],
+        ];
+        let blocked_tunnel_protocols = [
+            BlockedTunnelProtocols::default(),
+            BlockedTunnelProtocols {
+                ipv6: true,
+                udp: true,
+                smb: true,
+            },
+        ];
+        for dns_servers in &dns_servers {
+            for blocked_tunnel_protocols in blocked_tunnel_protocols {
+                for allow_lan in [false, true] {
+                    for vpn_coexistence in vpn_coexistence_options() {
+                        assert_same_rules_as_before(&FirewallPolicy::Connected {
+                            peer_endpoint: peer_endpoint(),
+                            tunnel: tunnel(),
+                            allow_lan,
+                            dns_servers: dns_servers.clone(),
+                            vpn_coexistence,
+                            blocked_tunnel_protocols,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_blocked_rules() {
+        for allowed_endpoint in [None, Some(allowed_endpoint())] {
+            for recovery_allowlist in [vec![], vec!["203.0.113.0/24".parse().unwrap()]] {
+                for allow_lan in [false, true] {
+                    for vpn_coexistence in vpn_coexistence_options() {
+                        assert_same_rules_as_before(&FirewallPolicy::Blocked {
+                            allow_lan,
+                            allowed_endpoint: allowed_endpoint.clone(),
+                            recovery_allowlist: recovery_allowlist.clone(),
+                            vpn_coexistence,
+                            dns_redirect_port: 1053,
+                        });
+                    }
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,736 @@
+//! The filter rules that the macOS firewall generated before the rules were shared with the
+//! other platforms that use pf. Only used to test that the shared rules are loaded unchanged.
+
+use super::{
+    pf::RuleLogging,
+    plugin::{PluginRule, PolicyFragment},
+    FirewallPolicy, TunnelProtocolBlock,
+};
+use ipnetwork::IpNetwork;
+use pfctl::{DropAction, FilterRuleAction, Uid};
+use std::net::{IpAddr, Ipv4Addr};
+use talpid_types::net::{self, AllowedTunnelTraffic};
+
+type Result<T> = std::result::Result<T, pfctl::Error>;
+
+pub struct LegacyRules {
+    pub rule_logging: RuleLogging,
+}
+
+impl LegacyRules {
+    pub fn filter_rules(
+        &self,
+        policy: &FirewallPolicy,
+        plugins: &PolicyFragment,
+    ) -> Result<Vec<pfctl::FilterRule>> {
+        let mut new_filter_rules = vec![];
+
+        new_filter_rules.append(&mut self.get_allow_loopback_rules()?);
+        new_filter_rules.append(&mut self.get_plugin_rules(plugins)?);
+        new_filter_rules.append(&mut self.get_allow_dhcp_client_rules()?);
+        new_filter_rules.append(&mut self.get_allow_ndp_rules()?);
+        new_filter_rules.append(&mut self.get_policy_specific_rules(policy)?);
+
+        let return_out_rule = self
+            .create_rule_builder(FilterRuleAction::Drop(DropAction::Return))
+            .direction(pfctl::Direction::Out)
+            .quick(true)
+            .build()?;
+        new_filter_rules.push(return_out_rule);
+
+        let drop_all_rule = self
+            .create_rule_builder(FilterRuleAction::Drop(DropAction::Drop))
+            .quick(true)
+            .build()?;
+        new_filter_rules.push(drop_all_rule);
+
+        Ok(new_filter_rules)
+    }
+
+    fn get_policy_specific_rules(&self, policy: &FirewallPolicy) -> Result<Vec<pfctl::FilterRule>> {
+        match policy {
+            FirewallPolicy::Connecting {
+                peer_endpoint,
+                tunnel,
+                allow_lan,
+                allowed_endpoint,
+                allowed_tunnel_traffic,
+                vpn_coexistence,
+            } => {
+                let mut rules = vec![self.get_allow_relay_rule(*peer_endpoint)?];
+                rules.append(&mut self.get_allowed_endpoint_rules(allowed_endpoint)?);
+
+                let mut tunnel_rules = match tunnel {
+                    Some(tunnel) => {
+                        self.get_allow_tunnel_rules(&tunnel.interface, allowed_tunnel_traffic)?
+                    }
+                    None => vec![],
+                };
+
+                // DNS to the given servers must be allowed before all other DNS is blocked
+                if let AllowedTunnelTraffic::Dns(_) = allowed_tunnel_traffic {
+                    rules.append(&mut tunnel_rules);
+                }
+
+                // Important to block DNS after allow relay rule (so the relay can operate
+                // over port 53) but before allow LAN (so DNS does not leak to the LAN)
+                rules.append(&mut self.get_block_dns_rules()?);
+
+                rules.append(&mut tunnel_rules);
+                rules.append(&mut self.get_vpn_coexistence_rules(vpn_coexistence)?);
+
+                if *allow_lan {
+                    rules.append(&mut self.get_allow_lan_rules()?);
+                }
+                Ok(rules)
+            }
+            FirewallPolicy::Connected {
+                peer_endpoint,
+                tunnel,
+                allow_lan,
+                dns_servers,
+                vpn_coexistence,
+                blocked_tunnel_protocols,
+            } => {
+                // Must come before the DNS rules, so that DNS can be blocked as well.
+                let mut rules = self
+                    .get_block_tunnel_protocol_rules(&tunnel.interface, blocked_tunnel_protocols)?;
+
+                for server in dns_servers.iter() {
+                    rules.append(&mut self.get_allow_dns_rules_when_connected(tunnel, *server)?);
+                }
+
+                rules.push(self.get_allow_relay_rule(*peer_endpoint)?);
+
+                // Important to block DNS *before* we allow the tunnel and allow LAN. So DNS
+                // can't leak to the wrong IPs in the tunnel or on the LAN.
+                rules.append(&mut self.get_block_dns_rules()?);
+
+                rules.append(&mut self.get_allow_tunnel_rules(
+                    tunnel.interface.as_str(),
+                    &AllowedTunnelTraffic::All,
+                )?);
+                rules.append(&mut self.get_vpn_coexistence_rules(vpn_coexistence)?);
+
+                if *allow_lan {
+                    rules.append(&mut self.get_allow_lan_rules()?);
+                }
+
+                Ok(rules)
+            }
+            FirewallPolicy::Blocked {
+                allow_lan,
+                allowed_endpoint,
+                recovery_allowlist,
+                vpn_coexistence,
+                ..
+            } => {
+                let mut rules = Vec::new();
+                if let Some(allowed_endpoint) = allowed_endpoint {
+                    rules.append(&mut self.get_allowed_endpoint_rules(allowed_endpoint)?);
+                }
+
+                if *allow_lan || !recovery_allowlist.is_empty() || !vpn_coexistence.is_empty() {
+                    // Important to block DNS before allow LAN (so DNS does not leak to the LAN)
+                    rules.append(&mut self.get_block_dns_rules()?);
+                }
+                if *allow_lan {
+                    rules.append(&mut self.get_allow_lan_rules()?);
+                }
+                rules.append(&mut self.get_allow_recovery_rules(recovery_allowlist)?);
+                rules.append(&mut self.get_vpn_coexistence_rules(vpn_coexistence)?);
+
+                Ok(rules)
+            }
+        }
+    }
+
+    fn get_allow_dns_rules_when_connected(
+        &self,
+        tunnel: &crate::tunnel::TunnelMetadata,
+        server: IpAddr,
+    ) -> Result<Vec<pfctl::FilterRule>> {
+        let mut rules = Vec::with_capacity(4);
+
+        let is_local = super::is_local_address(&server)
+            && server != tunnel.ipv4_gateway
+            && !tunnel
+                .ipv6_gateway
+                .map(|ref gateway| &server == gateway)
+                .unwrap_or(false);
+
+        if is_local {
+            // Block requests on the tunnel interface
+            let block_tunnel_tcp = self
+                .create_rule_builder(FilterRuleAction::Drop(DropAction::Return))
+                .direction(pfctl::Direction::Out)
+                .quick(true)
+                .interface(&tunnel.interface)
+                .proto(pfctl::Proto::Tcp)
+                .keep_state(pfctl::StatePolicy::None)
+                .to(pfctl::Endpoint::new(server, 53))
+                .build()?;
+            rules.push(block_tunnel_tcp);
+            let block_tunnel_udp = self
+                .create_rule_builder(FilterRuleAction::Drop(DropAction::Return))
+                .direction(pfctl::Direction::Out)
+                .quick(true)
+                .interface(&tunnel.interface)
+                .proto(pfctl::Proto::Udp)
+                .keep_state(pfctl::StatePolicy::None)
+                .to(pfctl::Endpoint::new(server, 53))
+                .build()?;
+            rules.push(block_tunnel_udp);
+
+            // Allow requests on other interfaces
+            let allow_nontunnel_tcp = self
+                .create_rule_builder(FilterRuleAction::Pass)
+                .direction(pfctl::Direction::Out)
+                .quick(true)
+                .proto(pfctl::Proto::Tcp)
+                .keep_state(pfctl::StatePolicy::Keep)
+                .tcp_flags(Self::get_tcp_flags())
+                .to(pfctl::Endpoint::new(server, 53))
+                .build()?;
+            rules.push(allow_nontunnel_tcp);
+            let allow_nontunnel_udp = self
+                .create_rule_builder(FilterRuleAction::Pass)
+                .direction(pfctl::Direction::Out)
+                .quick(true)
+                .proto(pfctl::Proto::Udp)
+                .keep_state(pfctl::StatePolicy::Keep)
+                .to(pfctl::Endpoint::new(server, 53))
+                .build()?;
+            rules.push(allow_nontunnel_udp);
+        } else {
+            // Allow outgoing requests on the tunnel interface only
+            let allow_tunnel_tcp = self
+                .create_rule_builder(FilterRuleAction::Pass)
+                .direction(pfctl::Direction::Out)
+                .quick(true)
+                .interface(&tunnel.interface)
+                .proto(pfctl::Proto::Tcp)
+                .keep_state(pfctl::StatePolicy::Keep)
+                .tcp_flags(Self::get_tcp_flags())
+                .to(pfctl::Endpoint::new(server, 53))
+                .build()?;
+            rules.push(allow_tunnel_tcp);
+            let allow_tunnel_udp = self
+                .create_rule_builder(FilterRuleAction::Pass)
+                .direction(pfctl::Direction::Out)
+                .quick(true)
+                .interface(&tunnel.interface)
+                .proto(pfctl::Proto::Udp)
+                .to(pfctl::Endpoint::new(server, 53))
+                .build()?;
+            rules.push(allow_tunnel_udp);
+        };
+
+        Ok(rules)
+    }
+
+    fn get_allow_relay_rule(&self, relay_endpoint: net::Endpoint) -> Result<pfctl::FilterRule> {
+        let pfctl_proto = as_pfctl_proto(relay_endpoint.protocol);
+
+        Ok(self
+            .create_rule_builder(FilterRuleAction::Pass)
+            .direction(pfctl::Direction::Out)
+            .to(relay_endpoint.address)
+            .proto(pfctl_proto)
+            .keep_state(pfctl::StatePolicy::Keep)
+            .tcp_flags(Self::get_tcp_flags())
+            .user(Uid::from(super::ROOT_UID))
+            .quick(true)
+            .build()?)
+    }
+
+    /// Produces rules that allow traffic to flow to the API. Allows the app to reach the API in
+    /// blocked states.
+    fn get_allowed_endpoint_rules(
+        &self,
+        allowed_endpoint: &net::AllowedEndpoint,
+    ) -> Result<Vec<pfctl::FilterRule>> {
+        let mut rules = vec![];
+        for range in &allowed_endpoint.endpoints {
+            let port = if range.ports.is_single() {
+                pfctl::Port::from(range.ports.start())
+            } else {
+                pfctl::Port::Range(
+                    range.ports.start(),
+                    range.ports.end(),
+                    pfctl::PortRangeModifier::Inclusive,
+                )
+            };
+            for protocol in range.protocols() {
+                rules.push(
+                    self.create_rule_builder(FilterRuleAction::Pass)
+                        .direction(pfctl::Direction::Out)
+                        .to(pfctl::Endpoint::new(range.address, port))
+                        .proto(as_pfctl_proto(*protocol))
+                        .keep_state(pfctl::StatePolicy::Keep)
+                        .user(Uid::from(super::ROOT_UID))
+                        .quick(true)
+                        .build()?,
+                );
+            }
+        }
+        Ok(rules)
+    }
+
+    fn get_block_dns_rules(&self) -> Result<Vec<pfctl::FilterRule>> {
+        let block_tcp_dns_rule = self
+            .create_rule_builder(FilterRuleAction::Drop(DropAction::Return))
+            .direction(pfctl::Direction::Out)
+            .quick(true)
+            .proto(pfctl::Proto::Tcp)
+            .to(pfctl::Port::from(53))
+            .build()?;
+        let block_udp_dns_rule = self
+            .create_rule_builder(FilterRuleAction::Drop(DropAction::Return))
+            .direction(pfctl::Direction::Out)
+            .quick(true)
+            .proto(pfctl::Proto::Udp)
+            .to(pfctl::Port::from(53))
+            .build()?;
+
+        Ok(vec![block_tcp_dns_rule, block_udp_dns_rule])
+    }
+
+    /// Returns rules that reject outgoing traffic in the tunnel that uses any of the blocked
+    /// protocols.
+    fn get_block_tunnel_protocol_rules(
+        &self,
+        tunnel_interface: &str,
+        blocked: &net::BlockedTunnelProtocols,
+    ) -> Result<Vec<pfctl::FilterRule>> {
+        let mut rules = vec![];
+        for block in super::tunnel_protocol_blocks(blocked) {
+            let mut rule = self.create_rule_builder(FilterRuleAction::Drop(DropAction::Return));
+            rule.direction(pfctl::Direction::Out)
+                .quick(true)
+                .interface(tunnel_interface);
+            match block {
+                TunnelProtocolBlock::Ipv6 => {
+                    rule.af(pfctl::AddrFamily::Ipv6);
+                }
+                TunnelProtocolBlock::Udp => {
+                    rule.proto(pfctl::Proto::Udp);
+                }
+                TunnelProtocolBlock::TcpPort(port) => {
+                    rule.proto(pfctl::Proto::Tcp).to(pfctl::Port::from(port));
+                }
+            }
+            rules.push(rule.build()?);
+        }
+        Ok(rules)
+    }
+
+    fn get_allow_tunnel_rules(
+        &self,
+        tunnel_interface: &str,
+        allowed_traffic: &AllowedTunnelTraffic,
+    ) -> Result<Vec<pfctl::FilterRule>> {
+        let allow_rule = |to: Option<(pfctl::Endpoint, pfctl::Proto)>| -> Result<_> {
+            let mut rule_builder = self.create_rule_builder(FilterRuleAction::Pass);
+            rule_builder
+                .quick(true)
+                .interface(tunnel_interface)
+                .keep_state(pfctl::StatePolicy::Keep)
+                .tcp_flags(Self::get_tcp_flags());
+            if let Some((endpoint, proto)) = to {
+                rule_builder.to(endpoint).proto(proto);
+            }
+            Ok(rule_builder.build()?)
+        };
+
+        match allowed_traffic {
+            AllowedTunnelTraffic::None => Ok(vec![]),
+            AllowedTunnelTraffic::All => Ok(vec![allow_rule(None)?]),
+            AllowedTunnelTraffic::Only(endpoint) => Ok(vec![allow_rule(Some((
+                pfctl::Endpoint::from(endpoint.address),
+                as_pfctl_proto(endpoint.protocol),
+            )))?]),
+            AllowedTunnelTraffic::Dns(servers) => {
+                let mut rules = Vec::with_capacity(servers.len() * 2);
+                for server in servers {
+                    for proto in [pfctl::Proto::Udp, pfctl::Proto::Tcp] {
+                        rules.push(allow_rule(Some((
+                            pfctl::Endpoint::new(*server, 53),
+                            proto,
+                        )))?);
+                    }
+                }
+                Ok(rules)
+            }
+            AllowedTunnelTraffic::Endpoints {
+                addresses,
+                port,
+                protocol,
+            } => addresses
+                .iter()
+                .map(|address| {
+                    allow_rule(Some((
+                        pfctl::Endpoint::new(*address, *port),
+                        as_pfctl_proto(*protocol),
+                    )))
+                })
+                .collect(),
+        }
+    }
+
+    fn get_allow_loopback_rules(&self) -> Result<Vec<pfctl::FilterRule>> {
+        let lo0_rule = self
+            .create_rule_builder(FilterRuleAction::Pass)
+            .quick(true)
+            .interface("lo0")
+            .keep_state(pfctl::StatePolicy::Keep)
+            .build()?;
+        Ok(vec![lo0_rule])
+    }
+
+    fn get_allow_recovery_rules(&self, networks: &[IpNetwork]) -> Result<Vec<pfctl::FilterRule>> {
+        let mut rules = vec![];
+        for net in networks {
+            let mut rule_builder = self.create_rule_builder(FilterRuleAction::Pass);
+            rule_builder.quick(true);
+            let allow_out = rule_builder
+                .direction(pfctl::Direction::Out)
+                .from(pfctl::Ip::Any)
+                .to(pfctl::Ip::from(*net))
+                .build()?;
+            let allow_in = rule_builder
+                .direction(pfctl::Direction::In)
+                .from(pfctl::Ip::from(*net))
+                .to(pfctl::Ip::Any)
+                .build()?;
+            rules.push(allow_out);
+            rules.push(allow_in);
+        }
+        Ok(rules)
+    }
+
+    /// Returns the rules of policy plugins. Block rules come first, so that they take precedence
+    /// over the allow rules.
+    fn get_plugin_rules(&self, plugins: &PolicyFragment) -> Result<Vec<pfctl::FilterRule>> {
+        let mut rules = vec![];
+        for rule in plugins.block_rules() {
+            rules
+                .append(&mut self.get_plugin_rule(rule, FilterRuleAction::Drop(DropAction::Drop))?);
+        }
+        for rule in plugins.allow_rules() {
+            rules.append(&mut self.get_plugin_rule(rule, FilterRuleAction::Pass)?);
+        }
+        Ok(rules)
+    }
+
+    fn get_plugin_rule(
+        &self,
+        plugin_rule: &PluginRule,
+        action: FilterRuleAction,
+    ) -> Result<Vec<pfctl::FilterRule>> {
+        let remote = || {
+            pfctl::Endpoint::new(
+                pfctl::Ip::from(plugin_rule.network),
+                plugin_rule
+                    .port
+                    .map(pfctl::Port::from)
+                    .unwrap_or(pfctl::Port::Any),
+            )
+        };
+        let mut rule_builder = self.create_rule_builder(action);
+        rule_builder.quick(true);
+        if let Some(protocol) = plugin_rule.protocol {
+            rule_builder.proto(as_pfctl_proto(protocol));
+        }
+        let out_rule = rule_builder
+            .direction(pfctl::Direction::Out)
+            .from(pfctl::Ip::Any)
+            .to(remote())
+            .build()?;
+        let in_rule = rule_builder
+            .direction(pfctl::Direction::In)
+            .from(remote())
+            .to(pfctl::Ip::Any)
+            .build()?;
+        Ok(vec![out_rule, in_rule])
+    }
+
+    /// Returns rules that allow the traffic of another VPN. Routing is left to the other VPN,
+    /// whose routes are more specific than the ones for the tunnel.
+    fn get_vpn_coexistence_rules(
+        &self,
+        vpn_coexistence: &net::VpnCoexistence,
+    ) -> Result<Vec<pfctl::FilterRule>> {
+        let mut rules = vec![];
+        for net in &vpn_coexistence.networks {
+            let mut rule_builder = self.create_rule_builder(FilterRuleAction::Pass);
+            rule_builder.quick(true);
+            let allow_out = rule_builder
+                .direction(pfctl::Direction::Out)
+                .from(pfctl::Ip::Any)
+                .to(pfctl::Ip::from(*net))
+                .build()?;
+            let allow_in = rule_builder
+                .direction(pfctl::Direction::In)
+                .from(pfctl::Ip::from(*net))
+                .to(pfctl::Ip::Any)
+                .build()?;
+            rules.push(allow_out);
+            rules.push(allow_in);
+        }
+        for port in &vpn_coexistence.udp_ports {
+            let mut rule_builder = self.create_rule_builder(FilterRuleAction::Pass);
+            rule_builder.quick(true).proto(pfctl::Proto::Udp);
+            let allow_out = rule_builder
+                .direction(pfctl::Direction::Out)
+                .from(pfctl::Port::from(*port))
+                .to(pfctl::Ip::Any)
+                .keep_state(pfctl::StatePolicy::Keep)
+                .build()?;
+            let allow_in = rule_builder
+                .direction(pfctl::Direction::In)
+                .from(pfctl::Ip::Any)
+                .to(pfctl::Port::from(*port))
+                .keep_state(pfctl::StatePolicy::Keep)
+                .build()?;
+            rules.push(allow_out);
+            rules.push(allow_in);
+        }
+        Ok(rules)
+    }
+
+    fn get_allow_lan_rules(&self) -> Result<Vec<pfctl::FilterRule>> {
+        let mut rules = vec![];
+        for net in &*super::ALLOWED_LAN_NETS {
+            let mut rule_builder = self.create_rule_builder(FilterRuleAction::Pass);
+            rule_builder.quick(true);
+            let allow_out = rule_builder
+                .direction(pfctl::Direction::Out)
+                .from(pfctl::Ip::Any)
+                .to(pfctl::Ip::from(*net))
+                .build()?;
+            let allow_in = rule_builder
+                .direction(pfctl::Direction::In)
+                .from(pfctl::Ip::from(*net))
+                .to(pfctl::Ip::Any)
+                .build()?;
+            rules.push(allow_out);
+            rules.push(allow_in);
+        }
+        for multicast_net in &*super::ALLOWED_LAN_MULTICAST_NETS {
+            let allow_multicast_out = self
+                .create_rule_builder(FilterRuleAction::Pass)
+                .quick(true)
+                .direction(pfctl::Direction::Out)
+                .to(pfctl::Ip::from(*multicast_net))
+                .build()?;
+            rules.push(allow_multicast_out);
+        }
+
+        let dhcpv4_out = self
+            .create_rule_builder(FilterRuleAction::Pass)
+            .quick(true)
+            .direction(pfctl::Direction::Out)
+            .af(pfctl::AddrFamily::Ipv4)
+            .proto(pfctl::Proto::Udp)
+            .from(pfctl::Port::from(super::DHCPV4_SERVER_PORT))
+            .to(pfctl::Port::from(super::DHCPV4_CLIENT_PORT))
+            .build()?;
+        let dhcpv4_in = self
+            .create_rule_builder(FilterRuleAction::Pass)
+            .quick(true)
+            .direction(pfctl::Direction::In)
+            .proto(pfctl::Proto::Udp)
+            .from(pfctl::Port::from(super::DHCPV4_CLIENT_PORT))
+            .to(pfctl::Endpoint::new(
+                Ipv4Addr::BROADCAST,
+                pfctl::Port::from(super::DHCPV4_SERVER_PORT),
+            ))
+            .build()?;
+        rules.push(dhcpv4_out);
+        rules.push(dhcpv4_in);
+
+        Ok(rules)
+    }
+
+    fn get_allow_dhcp_client_rules(&self) -> Result<Vec<pfctl::FilterRule>> {
+        let mut dhcp_rule_builder = self.create_rule_builder(FilterRuleAction::Pass);
+        dhcp_rule_builder.quick(true).proto(pfctl::Proto::Udp);
+
+        let mut rules = Vec::new();
+
+        // DHCPv4
+        dhcp_rule_builder.af(pfctl::AddrFamily::Ipv4);
+        let allow_outgoing_dhcp_v4 = dhcp_rule_builder
+            .direction(pfctl::Direction::Out)
+            .from(pfctl::Port::from(super::DHCPV4_CLIENT_PORT))
+            .to(pfctl::Endpoint::new(
+                Ipv4Addr::BROADCAST,
+                pfctl::Port::from(super::DHCPV4_SERVER_PORT),
+            ))
+            .build()?;
+        let allow_incoming_dhcp_v4 = dhcp_rule_builder
+            .direction(pfctl::Direction::In)
+            .from(pfctl::Port::from(super::DHCPV4_SERVER_PORT))
+            .to(pfctl::Port::from(super::DHCPV4_CLIENT_PORT))
+            .build()?;
+        rules.push(allow_outgoing_dhcp_v4);
+        rules.push(allow_incoming_dhcp_v4);
+
+        // DHCPv6
+        dhcp_rule_builder.af(pfctl::AddrFamily::Ipv6);
+        for dhcpv6_server in &*super::DHCPV6_SERVER_ADDRS {
+            let allow_outgoing_dhcp_v6 = dhcp_rule_builder
+                .direction(pfctl::Direction::Out)
+                .from(pfctl::Endpoint::new(
+                    IpNetwork::V6(*super::IPV6_LINK_LOCAL),
+                    pfctl::Port::from(super::DHCPV6_CLIENT_PORT),
+                ))
+                .to(pfctl::Endpoint::new(
+                    *dhcpv6_server,
+                    pfctl::Port::from(super::DHCPV6_SERVER_PORT),
+                ))
+                .build()?;
+            rules.push(allow_outgoing_dhcp_v6);
+        }
+        let allow_incoming_dhcp_v6 = dhcp_rule_builder
+            .direction(pfctl::Direction::In)
+            .from(pfctl::Endpoint::new(
+                pfctl::Ip::from(IpNetwork::V6(*super::IPV6_LINK_LOCAL)),
+                pfctl::Port::from(super::DHCPV6_SERVER_PORT),
+            ))
+            .to(pfctl::Endpoint::new(
+                pfctl::Ip::from(IpNetwork::V6(*super::IPV6_LINK_LOCAL)),
+                pfctl::Port::from(super::DHCPV6_CLIENT_PORT),
+            ))
+            .build()?;
+        rules.push(allow_incoming_dhcp_v6);
+
+        Ok(rules)
+    }
+
+    fn get_allow_ndp_rules(&self) -> Result<Vec<pfctl::FilterRule>> {
+        let mut ndp_rule_builder = self.create_rule_builder(FilterRuleAction::Pass);
+        ndp_rule_builder
+            .quick(true)
+            .af(pfctl::AddrFamily::Ipv6)
+            .proto(pfctl::Proto::IcmpV6);
+
+        let mut rules = Vec::new();
+
+        // Outgoing router solicitation to `ff02::2`
+        rules.push(
+            ndp_rule_builder
+                .clone()
+                .direction(pfctl::Direction::Out)
+                .icmp_type(pfctl::IcmpType::Icmp6(pfctl::Icmp6Type::RouterSol))
+                .to(*super::ROUTER_SOLICITATION_OUT_DST_ADDR)
+                .build()?,
+        );
+
+        // Incoming router advertisement from `fe80::/10`
+        rules.push(
+            ndp_rule_builder
+                .clone()
+                .direction(pfctl::Direction::In)
+                .icmp_type(pfctl::IcmpType::Icmp6(pfctl::Icmp6Type::RouterAdv))
+                .from(pfctl::Ip::from(IpNetwork::V6(*super::IPV6_LINK_LOCAL)))
+                .build()?,
+        );
+
+        // Incoming Redirect from `fe80::/10`
+        rules.push(
+            ndp_rule_builder
+                .clone()
+                .direction(pfctl::Direction::In)
+                .icmp_type(pfctl::IcmpType::Icmp6(pfctl::Icmp6Type::Redir))
+                .from(pfctl::Ip::from(IpNetwork::V6(*super::IPV6_LINK_LOCAL)))
+                .build()?,
+        );
+
+        // Outgoing neighbor solicitation to `ff02::1:ff00:0/104` and `fe80::/10`
+        rules.push(
+            ndp_rule_builder
+                .clone()
+                .direction(pfctl::Direction::Out)
+                .icmp_type(pfctl::IcmpType::Icmp6(pfctl::Icmp6Type::NeighbrSol))
+                .to(pfctl::Ip::from(IpNetwork::V6(
+                    *super::SOLICITED_NODE_MULTICAST,
+                )))
+                .build()?,
+        );
+        rules.push(
+            ndp_rule_builder
+                .clone()
+                .direction(pfctl::Direction::Out)
+                .icmp_type(pfctl::IcmpType::Icmp6(pfctl::Icmp6Type::NeighbrSol))
+                .to(pfctl::Ip::from(IpNetwork::V6(*super::IPV6_LINK_LOCAL)))
+                .build()?,
+        );
+
+        // Incoming neighbor solicitation from `fe80::/10`
+        rules.push(
+            ndp_rule_builder
+                .clone()
+                .direction(pfctl::Direction::In)
+                .icmp_type(pfctl::IcmpType::Icmp6(pfctl::Icmp6Type::NeighbrSol))
+                .from(pfctl::Ip::from(IpNetwork::V6(*super::IPV6_LINK_LOCAL)))
+                .build()?,
+        );
+
+        // Outgoing neigbor advertisement to fe80::/10`
+        rules.push(
+            ndp_rule_builder
+                .clone()
+                .direction(pfctl::Direction::Out)
+                .icmp_type(pfctl::IcmpType::Icmp6(pfctl::Icmp6Type::NeighbrAdv))
+                .to(pfctl::Ip::from(IpNetwork::V6(*super::IPV6_LINK_LOCAL)))
+                .build()?,
+        );
+
+        // Incoming neigbor advertisement from anywhere
+        rules.push(
+            ndp_rule_builder
+                .clone()
+                .direction(pfctl::Direction::In)
+                .icmp_type(pfctl::IcmpType::Icmp6(pfctl::Icmp6Type::NeighbrAdv))
+                .build()?,
+        );
+
+        Ok(rules)
+    }
+
+    fn create_rule_builder(&self, action: FilterRuleAction) -> pfctl::FilterRuleBuilder {
+        let mut builder = pfctl::FilterRuleBuilder::default();
+        builder.action(action);
+        let rule_log = pfctl::RuleLog::IncludeMatchingState;
+        let do_log = match action {
+            FilterRuleAction::Pass => match self.rule_logging {
+                RuleLogging::All | RuleLogging::Pass => true,
+                _ => false,
+            },
+            FilterRuleAction::Drop(..) => match self.rule_logging {
+                RuleLogging::All | RuleLogging::Drop => true,
+                _ => false,
+            },
+        };
+        if do_log {
+            builder.log(rule_log);
+        }
+        builder
+    }
+
+    fn get_tcp_flags() -> pfctl::TcpFlags {
+        pfctl::TcpFlags::new(
+            &[pfctl::TcpFlag::Syn],
+            &[pfctl::TcpFlag::Syn, pfctl::TcpFlag::Ack],
+        )
+    }
+}
+
+fn as_pfctl_proto(protocol: net::TransportProtocol) -> pfctl::Proto {
+    match protocol {
+        net::TransportProtocol::Udp => pfctl::Proto::Udp,
+        net::TransportProtocol::Tcp => pfctl::Proto::Tcp,
+    }
+}
@@ -1,3 +1,5 @@
+use self::plugin::PolicyFragment;
+use crate::restore_journal::{JournalEntry, RestoreJournal};
 use ipnetwork::{IpNetwork, Ipv4Network, Ipv6Network};
 use lazy_static::lazy_static;
 #[cfg(not(target_os = "android"))]
@@ -7,8 +9,14 @@ use std::path::PathBuf;
 use std::{
     fmt,
     net::{Ipv4Addr, Ipv6Addr},
+    time::{Duration, Instant},
+};
+use talpid_types::{
+    net::{
+        AllowedEndpoint, AllowedTunnelTraffic, BlockedTunnelProtocols, Endpoint, VpnCoexistence,
+    },
+    system_state::{AllowedTunnelTrafficState, FirewallState},
 };
-use talpid_types::net::{AllowedEndpoint, AllowedTunnelTraffic, Endpoint};
 
 #[cfg(target_os = "macos")]
 #[path = "macos.rs"]
@@ -26,6 +34,27 @@ mod imp;
 #[path = "android.rs"]
 mod imp;
 
+#[cfg(all(feature = "bsd-pf", any(target_os = "freebsd", target_os = "openbsd")))]
+#[path = "bsd.rs"]
+mod imp;
+
+/// Rules that are shared by the platforms that use pf.
+#[cfg(any(
+    target_os = "macos",
+    all(feature = "bsd-pf", any(target_os = "freebsd", target_os = "openbsd"))
+))]
+mod pf;
+
+/// The macOS rules from before they were shared, to test that they did not change.
+#[cfg(all(test, target_os = "macos"))]
+mod macos_legacy_rules;
+
+/// Detection of other WFP providers that may interfere with the firewall.
+#[cfg(windows)]
+pub mod wfp_conflicts;
+
+pub mod plugin;
+
 pub use self::imp::Error;
 
 lazy_static! {
@@ -78,6 +107,43 @@ const DHCPV4_CLIENT_PORT: u16 = 68;
 const DHCPV6_SERVER_PORT: u16 = 547;
 #[cfg(all(unix, not(target_os = "android")))]
 const DHCPV6_CLIENT_PORT: u16 = 546;
+/// TCP ports used by SMB, directly and over NetBIOS.
+#[cfg(all(unix, not(target_os = "android")))]
+const SMB_PORTS: [u16; 2] = [445, 139];
+
+/// Outgoing traffic that is rejected on the tunnel interface when some protocols are blocked.
+/// The traffic of the WireGuard tunnel itself never passes through the tunnel interface, so it is
+/// not affected.
+#[cfg(all(unix, not(target_os = "android")))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TunnelProtocolBlock {
+    /// All IPv6 traffic.
+    Ipv6,
+    /// All UDP traffic, including DNS.
+    Udp,
+    /// TCP traffic to the given port.
+    TcpPort(u16),
+}
+
+/// Returns what to reject on the tunnel interface for the `blocked` protocols.
+#[cfg(all(unix, not(target_os = "android")))]
+fn tunnel_protocol_blocks(blocked: &BlockedTunnelProtocols) -> Vec<TunnelProtocolBlock> {
+    let mut blocks = vec![];
+    if blocked.ipv6 {
+        blocks.push(TunnelProtocolBlock::Ipv6);
+    }
+    if blocked.udp {
+        blocks.push(TunnelProtocolBlock::Udp);
+    }
+    if blocked.smb {
+        blocks.extend(
+            SMB_PORTS
+                .iter()
+                .map(|port| TunnelProtocolBlock::TcpPort(*port)),
+        );
+    }
+    blocks
+}
 #[cfg(all(unix, not(target_os = "android")))]
 const ROOT_UID: u32 = 0;
 
@@ -111,6 +177,8 @@ pub enum FirewallPolicy {
         allowed_endpoint: AllowedEndpoint,
         /// Networks for which to permit in-tunnel traffic.
         allowed_tunnel_traffic: AllowedTunnelTraffic,
+        /// Traffic of another VPN that should not be blocked or routed through the tunnel.
+        vpn_coexistence: VpnCoexistence,
         /// A process that is allowed to send packets to the relay.
         #[cfg(windows)]
         relay_client: PathBuf,
@@ -127,6 +195,10 @@ pub enum FirewallPolicy {
         /// Servers that are allowed to respond to DNS requests.
         #[cfg(not(target_os = "android"))]
         dns_servers: Vec<IpAddr>,
+        /// Traffic of another VPN that should not be blocked or routed through the tunnel.
+        vpn_coexistence: VpnCoexistence,
+        /// Protocols that should be blocked inside the tunnel.
+        blocked_tunnel_protocols: BlockedTunnelProtocols,
         /// A process that is allowed to send packets to the relay.
         #[cfg(windows)]
         relay_client: PathBuf,
@@ -138,6 +210,11 @@ pub enum FirewallPolicy {
         allow_lan: bool,
         /// Host that should be reachable while in the blocked state.
         allowed_endpoint: Option<AllowedEndpoint>,
+        /// Networks that traffic is allowed to and from while in the blocked state, regardless of
+        /// `allow_lan`. This lets a remote machine be reached for recovery.
+        recovery_allowlist: Vec<IpNetwork>,
+        /// Traffic of another VPN that should not be blocked or routed through the tunnel.
+        vpn_coexistence: VpnCoexistence,
         /// Desination port for DNS traffic redirection. Traffic destined to `127.0.0.1:53` will be
         /// redirected to `127.0.0.1:$dns_redirect_port`.
         #[cfg(target_os = "macos")]
@@ -145,6 +222,23 @@ pub enum FirewallPolicy {
     },
 }
 
+impl FirewallPolicy {
+    /// Returns the traffic of another VPN that is exempt under this policy.
+    pub fn vpn_coexistence(&self) -> &VpnCoexistence {
+        match self {
+            FirewallPolicy::Connecting {
+                vpn_coexistence, ..
+            }
+            | FirewallPolicy::Connected {
+                vpn_coexistence, ..
+            }
+            | FirewallPolicy::Blocked {
+                vpn_coexistence, ..
+            } => vpn_coexistence,
+        }
+    }
+}
+
 impl fmt::Display for FirewallPolicy {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -188,35 +282,138 @@ impl fmt::Display for FirewallPolicy {
                 peer_endpoint,
                 tunnel,
                 allow_lan,
+                blocked_tunnel_protocols,
                 ..
-            } => write!(
-                f,
-                "Connected to {} over \"{}\" (ip: {}, v4 gw: {}, v6 gw: {:?}), {} LAN",
+            } => {
+                write!(
+                    f,
+                    "Connected to {} over \"{}\" (ip: {}, v4 gw: {}, v6 gw: {:?}), {} LAN",
+                    peer_endpoint,
+                    tunnel.interface,
+                    tunnel
+                        .ips
+                        .iter()
+                        .map(|ip| ip.to_string())
+                        .collect::<Vec<_>>()
+                        .join(","),
+                    tunnel.ipv4_gateway,
+                    tunnel.ipv6_gateway,
+                    if *allow_lan { "Allowing" } else { "Blocking" }
+                )?;
+                if !blocked_tunnel_protocols.is_empty() {
+                    write!(f, ". Blocking in tunnel: {}", blocked_tunnel_protocols)?;
+                }
+                Ok(())
+            }
+            FirewallPolicy::Blocked {
+                allow_lan,
+                allowed_endpoint,
+                recovery_allowlist,
+                ..
+            } => {
+                write!(
+                    f,
+                    "Blocked. {} LAN. Allowing endpoint: {}",
+                    if *allow_lan { "Allowing" } else { "Blocking" },
+                    allowed_endpoint
+                        .as_ref()
+                        .map(|endpoint| -> &dyn std::fmt::Display { endpoint })
+                        .unwrap_or(&"none"),
+                )?;
+                if !recovery_allowlist.is_empty() {
+                    write!(
+                        f,
+                        ". Allowing recovery networks: {}",
+                        recovery_allowlist
+                            .iter()
+                            .map(|network| network.to_string())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )?;
+                }
+                Ok(())
+            }
+        }?;
+        let vpn_coexistence = self.vpn_coexistence();
+        if !vpn_coexistence.is_empty() {
+            write!(f, ". Exempting other VPN ({})", vpn_coexistence)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<&FirewallPolicy> for FirewallState {
+    fn from(policy: &FirewallPolicy) -> Self {
+        match policy {
+            FirewallPolicy::Connecting {
+                peer_endpoint,
+                tunnel,
+                allow_lan,
+                allowed_endpoint,
+                allowed_tunnel_traffic,
+                vpn_coexistence,
+                ..
+            } => FirewallState::Connecting {
+                peer_endpoint: *peer_endpoint,
+                tunnel_interface: tunnel.as_ref().map(|tunnel| tunnel.interface.clone()),
+                allow_lan: *allow_lan,
+                allowed_endpoints: allowed_endpoint.endpoints.clone(),
+                allowed_tunnel_traffic: match allowed_tunnel_traffic {
+                    AllowedTunnelTraffic::None => AllowedTunnelTrafficState::None,
+                    AllowedTunnelTraffic::All => AllowedTunnelTrafficState::All,
+                    AllowedTunnelTraffic::Only(endpoint) => {
+                        AllowedTunnelTrafficState::Only(*endpoint)
+                    }
+                    AllowedTunnelTraffic::Dns(servers) => {
+                        AllowedTunnelTrafficState::Dns(servers.clone())
+                    }
+                    AllowedTunnelTraffic::Endpoints {
+                        addresses,
+                        port,
+                        protocol,
+                    } => AllowedTunnelTrafficState::Endpoints {
+                        addresses: addresses.clone(),
+                        port: *port,
+                        protocol: *protocol,
+                    },
+                },
+                vpn_coexistence: vpn_coexistence.clone(),
+            },
+            FirewallPolicy::Connected {
                 peer_endpoint,
-                tunnel.interface,
-                tunnel
-                    .ips
-                    .iter()
-                    .map(|ip| ip.to_string())
-                    .collect::<Vec<_>>()
-                    .join(","),
-                tunnel.ipv4_gateway,
-                tunnel.ipv6_gateway,
-                if *allow_lan { "Allowing" } else { "Blocking" }
-            ),
+                tunnel,
+                allow_lan,
+                #[cfg(not(target_os = "android"))]
+                dns_servers,
+                vpn_coexistence,
+                blocked_tunnel_protocols,
+                ..
+            } => FirewallState::Connected {
+                peer_endpoint: *peer_endpoint,
+                tunnel_interface: tunnel.interface.clone(),
+                allow_lan: *allow_lan,
+                #[cfg(not(target_os = "android"))]
+                dns_servers: dns_servers.clone(),
+                #[cfg(target_os = "android")]
+                dns_servers: vec![],
+                vpn_coexistence: vpn_coexistence.clone(),
+                blocked_tunnel_protocols: *blocked_tunnel_protocols,
+            },
             FirewallPolicy::Blocked {
                 allow_lan,
                 allowed_endpoint,
+                recovery_allowlist,
+                vpn_coexistence,
                 ..
-            } => write!(
-                f,
-                "Blocked. {} LAN. Allowing endpoint: {}",
-                if *allow_lan { "Allowing" } else { "Blocking" },
-                allowed_endpoint
+            } => FirewallState::Blocked {
+                allow_lan: *allow_lan,
+                allowed_endpoints: allowed_endpoint
                     .as_ref()
-                    .map(|endpoint| -> &dyn std::fmt::Display { endpoint })
-                    .unwrap_or(&"none"),
-            ),
+                    .map(|allowed_endpoint| allowed_endpoint.endpoints.clone())
+                    .unwrap_or_default(),
+                recovery_allowlist: recovery_allowlist.clone(),
+                vpn_coexistence: vpn_coexistence.clone(),
+            },
         }
     }
 }
@@ -225,6 +422,69 @@ impl fmt::Display for FirewallPolicy {
 /// by manipulating the OS firewall and DNS settings.
 pub struct Firewall {
     inner: imp::Firewall,
+    /// Rules from policy plugins that are added to every policy.
+    plugins: PolicyFragment,
+    metrics: FirewallMetrics,
+    journal: Option<RestoreJournal>,
+    policy: AppliedPolicy,
+}
+
+/// Measurements of how firewall policies have been applied, for each kind of policy.
+#[derive(Debug, Default, Clone)]
+pub struct FirewallMetrics {
+    /// Metrics for [`FirewallPolicy::Connecting`].
+    pub connecting: PolicyMetrics,
+    /// Metrics for [`FirewallPolicy::Connected`].
+    pub connected: PolicyMetrics,
+    /// Metrics for [`FirewallPolicy::Blocked`].
+    pub blocked: PolicyMetrics,
+}
+
+impl FirewallMetrics {
+    fn policy_metrics(&mut self, policy: &FirewallPolicy) -> &mut PolicyMetrics {
+        match policy {
+            FirewallPolicy::Connecting { .. } => &mut self.connecting,
+            FirewallPolicy::Connected { .. } => &mut self.connected,
+            FirewallPolicy::Blocked { .. } => &mut self.blocked,
+        }
+    }
+}
+
+/// Measurements of how one kind of firewall policy has been applied.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PolicyMetrics {
+    /// Number of times the policy has been applied successfully.
+    pub apply_count: u64,
+    /// Number of rules generated the last time the policy was applied. This is `None` if the
+    /// backend cannot tell.
+    pub last_rule_count: Option<usize>,
+    /// Time it took to apply the policy the last time.
+    pub last_apply_duration: Duration,
+    /// Longest time it has taken to apply the policy.
+    pub max_apply_duration: Duration,
+    /// Total time spent applying the policy.
+    pub total_apply_duration: Duration,
+    /// Number of times applying the policy was skipped because it was already in place.
+    pub skip_count: u64,
+}
+
+impl PolicyMetrics {
+    fn record(&mut self, rule_count: Option<usize>, duration: Duration) {
+        self.apply_count += 1;
+        self.last_rule_count = rule_count;
+        self.last_apply_duration = duration;
+        self.max_apply_duration = self.max_apply_duration.max(duration);
+        self.total_apply_duration += duration;
+    }
+
+    /// Average time it has taken to apply the policy.
+    pub fn average_apply_duration(&self) -> Duration {
+        match u32::try_from(self.apply_count) {
+            Ok(0) => Duration::ZERO,
+            Ok(count) => self.total_apply_duration / count,
+            Err(_) => Duration::ZERO,
+        }
+    }
 }
 
 /// Arguments required when first initializing the firewall.
@@ -233,6 +493,10 @@ pub struct FirewallArguments {
     pub initial_state: InitialFirewallState,
     /// This argument is required for the blocked state to configure the firewall correctly.
     pub allow_lan: bool,
+    /// VPN applications and networks that are allowed in the blocked state.
+    pub vpn_coexistence: VpnCoexistence,
+    /// Rules from policy plugins that are added to every policy, including the initial one.
+    pub plugins: PolicyFragment,
 }
 
 /// State to enter during firewall init.
@@ -246,8 +510,13 @@ pub enum InitialFirewallState {
 impl Firewall {
     /// Creates a firewall instance with the given arguments.
     pub fn from_args(args: FirewallArguments) -> Result<Self, Error> {
+        let plugins = args.plugins.clone();
         Ok(Firewall {
             inner: imp::Firewall::from_args(args)?,
+            plugins,
+            metrics: FirewallMetrics::default(),
+            journal: None,
+            policy: AppliedPolicy::default(),
         })
     }
 
@@ -255,20 +524,239 @@ impl Firewall {
     pub fn new() -> Result<Self, Error> {
         Ok(Firewall {
             inner: imp::Firewall::new()?,
+            plugins: PolicyFragment::default(),
+            metrics: FirewallMetrics::default(),
+            journal: None,
+            policy: AppliedPolicy::default(),
         })
     }
 
+    /// Records whether a policy is applied in `journal`, so that it can be removed if the process
+    /// crashes.
+    pub fn set_journal(&mut self, journal: RestoreJournal) {
+        self.journal = Some(journal);
+    }
+
     /// Applies and starts enforcing the given `FirewallPolicy` Makes sure it is being kept in place
     /// until this method is called again with another policy, or until `reset_policy` is called.
     pub fn apply_policy(&mut self, policy: FirewallPolicy) -> Result<(), Error> {
-        log::info!("Applying firewall policy: {}", policy);
-        self.inner.apply_policy(policy)
+        let metrics = self.metrics.policy_metrics(&policy);
+        let inner = &mut self.inner;
+        let plugins = &self.plugins;
+
+        let start = Instant::now();
+        let applied = self.policy.apply_with(policy, |policy| {
+            log::info!("Applying firewall policy: {}", policy);
+            inner.apply_policy(policy.clone(), plugins)
+        })?;
+        let duration = start.elapsed();
+
+        if !applied {
+            if let Some(policy) = self.policy.get() {
+                log::debug!("Firewall policy is already applied: {}", policy);
+            }
+            metrics.skip_count += 1;
+            return Ok(());
+        }
+
+        if let Some(journal) = &self.journal {
+            journal.record(JournalEntry::Firewall);
+        }
+
+        let rule_count = self.inner.rule_count();
+        metrics.record(rule_count, duration);
+        log::debug!(
+            "Applied firewall policy in {} ms ({} rules). Average: {} ms, max: {} ms",
+            duration.as_millis(),
+            rule_count
+                .map(|count| count.to_string())
+                .unwrap_or_else(|| "unknown number of".to_owned()),
+            metrics.average_apply_duration().as_millis(),
+            metrics.max_apply_duration.as_millis(),
+        );
+        Ok(())
     }
 
     /// Resets/removes any currently enforced `FirewallPolicy`. Returns the system to the same state
     /// it had before any policy was applied through this `Firewall` instance.
     pub fn reset_policy(&mut self) -> Result<(), Error> {
         log::info!("Resetting firewall policy");
-        self.inner.reset_policy()
+        let inner = &mut self.inner;
+        self.policy.reset_with(|| inner.reset_policy())?;
+        if let Some(journal) = &self.journal {
+            journal.remove(|entry| entry == &JournalEntry::Firewall);
+        }
+        Ok(())
+    }
+
+    /// Returns the policy that is fully in place. This is `None` if no policy has been applied,
+    /// if it has been reset, or if the last attempt to apply or reset a policy failed.
+    pub fn policy(&self) -> Option<&FirewallPolicy> {
+        self.policy.get()
+    }
+
+    /// Returns measurements of how long it has taken to apply each kind of policy, and how many
+    /// rules they generated.
+    pub fn metrics(&self) -> &FirewallMetrics {
+        &self.metrics
+    }
+}
+
+/// Keeps track of the policy that is in place, so that applying the same policy again can be
+/// skipped.
+#[derive(Debug, Default)]
+struct AppliedPolicy(Option<FirewallPolicy>);
+
+impl AppliedPolicy {
+    fn get(&self) -> Option<&FirewallPolicy> {
+        self.0.as_ref()
+    }
+
+    /// Applies `policy` using `apply`, unless it is already in place. Returns whether `apply` was
+    /// called.
+    fn apply_with<E>(
+        &mut self,
+        policy: FirewallPolicy,
+        apply: impl FnOnce(&FirewallPolicy) -> Result<(), E>,
+    ) -> Result<bool, E> {
+        if self.0.as_ref() == Some(&policy) {
+            return Ok(false);
+        }
+        // The previous policy may be partially replaced if this fails
+        self.0 = None;
+        apply(&policy)?;
+        self.0 = Some(policy);
+        Ok(true)
+    }
+
+    fn reset_with<E>(&mut self, reset: impl FnOnce() -> Result<(), E>) -> Result<(), E> {
+        // The previous policy may be partially removed if this fails
+        self.0 = None;
+        reset()
+    }
+}
+
+#[cfg(all(test, unix, not(target_os = "android")))]
+mod test {
+    use super::*;
+
+    fn blocked_policy(allow_lan: bool) -> FirewallPolicy {
+        FirewallPolicy::Blocked {
+            allow_lan,
+            allowed_endpoint: None,
+            recovery_allowlist: vec![],
+            vpn_coexistence: VpnCoexistence::default(),
+            #[cfg(target_os = "macos")]
+            dns_redirect_port: 0,
+        }
+    }
+
+    #[test]
+    fn test_identical_policy_is_skipped() {
+        let mut applied = AppliedPolicy::default();
+        let mut apply_count = 0;
+        let mut apply = |_: &FirewallPolicy| -> Result<(), ()> {
+            apply_count += 1;
+            Ok(())
+        };
+
+        assert_eq!(
+            applied.apply_with(blocked_policy(false), &mut apply),
+            Ok(true)
+        );
+        assert_eq!(
+            applied.apply_with(blocked_policy(false), &mut apply),
+            Ok(false)
+        );
+        assert_eq!(
+            applied.apply_with(blocked_policy(true), &mut apply),
+            Ok(true)
+        );
+        assert_eq!(
+            applied.apply_with(blocked_policy(false), &mut apply),
+            Ok(true)
+        );
+        assert_eq!(apply_count, 3);
+        assert_eq!(applied.get(), Some(&blocked_policy(false)));
+    }
+
+    #[test]
+    fn test_failed_policy_is_not_skipped() {
+        let mut applied = AppliedPolicy::default();
+
+        assert_eq!(
+            applied.apply_with(blocked_policy(false), |_| Ok::<_, ()>(())),
+            Ok(true)
+        );
+        assert_eq!(
+            applied.apply_with(blocked_policy(true), |_| Err(())),
+            Err(())
+        );
+        assert_eq!(applied.get(), None);
+
+        // Neither the failed policy nor the one it partially replaced is in place
+        assert_eq!(
+            applied.apply_with(blocked_policy(true), |_| Ok::<_, ()>(())),
+            Ok(true)
+        );
+        assert_eq!(
+            applied.apply_with(blocked_policy(false), |_| Ok::<_, ()>(())),
+            Ok(true)
+        );
+    }
+
+    #[test]
+    fn test_reset_policy_is_not_skipped() {
+        let mut applied = AppliedPolicy::default();
+
+        assert_eq!(
+            applied.apply_with(blocked_policy(false), |_| Ok::<_, ()>(())),
+            Ok(true)
+        );
+        assert_eq!(applied.reset_with(|| Ok::<_, ()>(())), Ok(()));
+        assert_eq!(applied.get(), None);
+        assert_eq!(
+            applied.apply_with(blocked_policy(false), |_| Ok::<_, ()>(())),
+            Ok(true)
+        );
+
+        assert_eq!(applied.reset_with(|| Err(())), Err(()));
+        assert_eq!(
+            applied.apply_with(blocked_policy(false), |_| Ok::<_, ()>(())),
+            Ok(true)
+        );
+    }
+
+    #[test]
+    fn test_tunnel_protocol_blocks() {
+        assert_eq!(
+            tunnel_protocol_blocks(&BlockedTunnelProtocols::default()),
+            vec![]
+        );
+
+        // UDP is blocked entirely, DNS included
+        let blocked = BlockedTunnelProtocols {
+            udp: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            tunnel_protocol_blocks(&blocked),
+            vec![TunnelProtocolBlock::Udp]
+        );
+
+        let blocked = BlockedTunnelProtocols {
+            ipv6: true,
+            udp: true,
+            smb: true,
+        };
+        assert_eq!(
+            tunnel_protocol_blocks(&blocked),
+            vec![
+                TunnelProtocolBlock::Ipv6,
+                TunnelProtocolBlock::Udp,
+                TunnelProtocolBlock::TcpPort(445),
+                TunnelProtocolBlock::TcpPort(139),
+            ]
+        );
     }
 }
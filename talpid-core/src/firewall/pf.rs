@@ -0,0 +1,856 @@
+//! Rules for the pf firewall, which is used on macOS and on the BSDs.
+//!
+//! The rules are generated here, in the same order on every platform, and each backend only
+//! loads them into the anchor in its own way: through the ioctl interface on macOS, and as
+//! `pf.conf` syntax passed to `pfctl` on the BSDs.
+
+use super::{
+    plugin::{PluginRule, PolicyFragment},
+    FirewallPolicy, TunnelProtocolBlock,
+};
+use ipnetwork::IpNetwork;
+use std::{
+    env,
+    net::{IpAddr, Ipv4Addr},
+};
+use talpid_types::net::{self, AllowedTunnelTraffic};
+
+/// TODO(linus): This crate is not supposed to be Mullvad-aware. So at some point this should be
+/// replaced by allowing the anchor name to be configured from the public API of this crate.
+pub(super) const ANCHOR_NAME: &str = "mullvad";
+
+/// Generates the filter rules of a firewall policy.
+pub(super) struct RuleGenerator {
+    rule_logging: RuleLogging,
+}
+
+impl RuleGenerator {
+    pub fn from_env() -> Self {
+        // Allows controlling whether firewall rules should log to pflog0. Useful for debugging the
+        // rules.
+        let firewall_debugging = env::var("TALPID_FIREWALL_DEBUG");
+        let rule_logging = match firewall_debugging.as_ref().map(String::as_str) {
+            Ok("pass") => RuleLogging::Pass,
+            Ok("drop") => RuleLogging::Drop,
+            Ok("all") => RuleLogging::All,
+            Ok(_) | Err(_) => RuleLogging::None,
+        };
+        log::trace!("Firewall debug log policy: {:?}", rule_logging);
+
+        Self::new(rule_logging)
+    }
+
+    pub fn new(rule_logging: RuleLogging) -> Self {
+        RuleGenerator { rule_logging }
+    }
+
+    /// Returns all filter rules of the anchor for the given policy, ending with rules that block
+    /// everything else.
+    pub fn filter_rules(&self, policy: &FirewallPolicy, plugins: &PolicyFragment) -> Vec<Rule> {
+        let mut rules = vec![];
+
+        rules.append(&mut self.get_allow_loopback_rules());
+        rules.append(&mut self.get_plugin_rules(plugins));
+        rules.append(&mut self.get_allow_dhcp_client_rules());
+        rules.append(&mut self.get_allow_ndp_rules());
+        rules.append(&mut self.get_policy_specific_rules(policy));
+
+        let mut return_out_rule = self.rule(Action::Return);
+        return_out_rule.direction(Direction::Out).quick();
+        rules.push(return_out_rule);
+
+        let mut drop_all_rule = self.rule(Action::Drop);
+        drop_all_rule.quick();
+        rules.push(drop_all_rule);
+
+        rules
+    }
+
+    fn get_policy_specific_rules(&self, policy: &FirewallPolicy) -> Vec<Rule> {
+        match policy {
+            FirewallPolicy::Connecting {
+                peer_endpoint,
+                tunnel,
+                allow_lan,
+                allowed_endpoint,
+                allowed_tunnel_traffic,
+                vpn_coexistence,
+            } => {
+                let mut rules = vec![self.get_allow_relay_rule(*peer_endpoint)];
+                rules.append(&mut self.get_allowed_endpoint_rules(allowed_endpoint));
+
+                let mut tunnel_rules = match tunnel {
+                    Some(tunnel) => {
+                        self.get_allow_tunnel_rules(&tunnel.interface, allowed_tunnel_traffic)
+                    }
+                    None => vec![],
+                };
+
+                // DNS to the given servers must be allowed before all other DNS is blocked
+                if let AllowedTunnelTraffic::Dns(_) = allowed_tunnel_traffic {
+                    rules.append(&mut tunnel_rules);
+                }
+
+                // Important to block DNS after allow relay rule (so the relay can operate
+                // over port 53) but before allow LAN (so DNS does not leak to the LAN)
+                rules.append(&mut self.get_block_dns_rules());
+
+                rules.append(&mut tunnel_rules);
+                rules.append(&mut self.get_vpn_coexistence_rules(vpn_coexistence));
+
+                if *allow_lan {
+                    rules.append(&mut self.get_allow_lan_rules());
+                }
+                rules
+            }
+            FirewallPolicy::Connected {
+                peer_endpoint,
+                tunnel,
+                allow_lan,
+                dns_servers,
+                vpn_coexistence,
+                blocked_tunnel_protocols,
+            } => {
+                // Must come before the DNS rules, so that DNS can be blocked as well.
+                let mut rules = self
+                    .get_block_tunnel_protocol_rules(&tunnel.interface, blocked_tunnel_protocols);
+
+                for server in dns_servers.iter() {
+                    rules.append(&mut self.get_allow_dns_rules_when_connected(tunnel, *server));
+                }
+
+                rules.push(self.get_allow_relay_rule(*peer_endpoint));
+
+                // Important to block DNS *before* we allow the tunnel and allow LAN. So DNS
+                // can't leak to the wrong IPs in the tunnel or on the LAN.
+                rules.append(&mut self.get_block_dns_rules());
+
+                rules.append(
+                    &mut self.get_allow_tunnel_rules(&tunnel.interface, &AllowedTunnelTraffic::All),
+                );
+                rules.append(&mut self.get_vpn_coexistence_rules(vpn_coexistence));
+
+                if *allow_lan {
+                    rules.append(&mut self.get_allow_lan_rules());
+                }
+                rules
+            }
+            FirewallPolicy::Blocked {
+                allow_lan,
+                allowed_endpoint,
+                recovery_allowlist,
+                vpn_coexistence,
+                ..
+            } => {
+                let mut rules = Vec::new();
+                if let Some(allowed_endpoint) = allowed_endpoint {
+                    rules.append(&mut self.get_allowed_endpoint_rules(allowed_endpoint));
+                }
+
+                if *allow_lan || !recovery_allowlist.is_empty() || !vpn_coexistence.is_empty() {
+                    // Important to block DNS before allow LAN (so DNS does not leak to the LAN)
+                    rules.append(&mut self.get_block_dns_rules());
+                }
+                if *allow_lan {
+                    rules.append(&mut self.get_allow_lan_rules());
+                }
+                rules.append(&mut self.get_allow_networks_rules(recovery_allowlist));
+                rules.append(&mut self.get_vpn_coexistence_rules(vpn_coexistence));
+
+                rules
+            }
+        }
+    }
+
+    fn get_allow_dns_rules_when_connected(
+        &self,
+        tunnel: &crate::tunnel::TunnelMetadata,
+        server: IpAddr,
+    ) -> Vec<Rule> {
+        let is_local = super::is_local_address(&server)
+            && server != tunnel.ipv4_gateway
+            && !tunnel
+                .ipv6_gateway
+                .map(|ref gateway| &server == gateway)
+                .unwrap_or(false);
+        let dns_server = Target::endpoint(server, Port::Single(53));
+
+        let mut rules = Vec::with_capacity(4);
+        if is_local {
+            // Block requests on the tunnel interface
+            for proto in [Proto::Tcp, Proto::Udp] {
+                let mut block_tunnel = self.rule(Action::Return);
+                block_tunnel
+                    .direction(Direction::Out)
+                    .quick()
+                    .interface(&tunnel.interface)
+                    .proto(proto)
+                    .to(dns_server);
+                rules.push(block_tunnel);
+            }
+
+            // Allow requests on other interfaces
+            let mut allow_nontunnel_tcp = self.rule(Action::Pass);
+            allow_nontunnel_tcp
+                .direction(Direction::Out)
+                .quick()
+                .proto(Proto::Tcp)
+                .keep_state()
+                .tcp_flags()
+                .to(dns_server);
+            rules.push(allow_nontunnel_tcp);
+            let mut allow_nontunnel_udp = self.rule(Action::Pass);
+            allow_nontunnel_udp
+                .direction(Direction::Out)
+                .quick()
+                .proto(Proto::Udp)
+                .keep_state()
+                .to(dns_server);
+            rules.push(allow_nontunnel_udp);
+        } else {
+            // Allow outgoing requests on the tunnel interface only
+            let mut allow_tunnel_tcp = self.rule(Action::Pass);
+            allow_tunnel_tcp
+                .direction(Direction::Out)
+                .quick()
+                .interface(&tunnel.interface)
+                .proto(Proto::Tcp)
+                .keep_state()
+                .tcp_flags()
+                .to(dns_server);
+            rules.push(allow_tunnel_tcp);
+            let mut allow_tunnel_udp = self.rule(Action::Pass);
+            allow_tunnel_udp
+                .direction(Direction::Out)
+                .quick()
+                .interface(&tunnel.interface)
+                .proto(Proto::Udp)
+                .to(dns_server);
+            rules.push(allow_tunnel_udp);
+        }
+        rules
+    }
+
+    fn get_allow_relay_rule(&self, relay_endpoint: net::Endpoint) -> Rule {
+        let mut rule = self.rule(Action::Pass);
+        rule.direction(Direction::Out)
+            .to(Target::endpoint(
+                relay_endpoint.address.ip(),
+                Port::Single(relay_endpoint.address.port()),
+            ))
+            .proto(Proto::from(relay_endpoint.protocol))
+            .keep_state()
+            .tcp_flags()
+            .user(super::ROOT_UID)
+            .quick();
+        rule
+    }
+
+    /// Produces rules that allow traffic to flow to the API. Allows the app to reach the API in
+    /// blocked states.
+    fn get_allowed_endpoint_rules(&self, allowed_endpoint: &net::AllowedEndpoint) -> Vec<Rule> {
+        let mut rules = vec![];
+        for range in &allowed_endpoint.endpoints {
+            let port = if range.ports.is_single() {
+                Port::Single(range.ports.start())
+            } else {
+                Port::Range(range.ports.start(), range.ports.end())
+            };
+            for protocol in range.protocols() {
+                let mut rule = self.rule(Action::Pass);
+                rule.direction(Direction::Out)
+                    .to(Target::endpoint(range.address, port))
+                    .proto(Proto::from(*protocol))
+                    .keep_state()
+                    .user(super::ROOT_UID)
+                    .quick();
+                rules.push(rule);
+            }
+        }
+        rules
+    }
+
+    fn get_block_dns_rules(&self) -> Vec<Rule> {
+        [Proto::Tcp, Proto::Udp]
+            .into_iter()
+            .map(|proto| {
+                let mut rule = self.rule(Action::Return);
+                rule.direction(Direction::Out)
+                    .quick()
+                    .proto(proto)
+                    .to(Target::port(Port::Single(53)));
+                rule
+            })
+            .collect()
+    }
+
+    /// Returns rules that reject outgoing traffic in the tunnel that uses any of the blocked
+    /// protocols.
+    fn get_block_tunnel_protocol_rules(
+        &self,
+        tunnel_interface: &str,
+        blocked: &net::BlockedTunnelProtocols,
+    ) -> Vec<Rule> {
+        super::tunnel_protocol_blocks(blocked)
+            .into_iter()
+            .map(|block| {
+                let mut rule = self.rule(Action::Return);
+                rule.direction(Direction::Out)
+                    .quick()
+                    .interface(tunnel_interface);
+                match block {
+                    TunnelProtocolBlock::Ipv6 => {
+                        rule.af(AddrFamily::Inet6);
+                    }
+                    TunnelProtocolBlock::Udp => {
+                        rule.proto(Proto::Udp);
+                    }
+                    TunnelProtocolBlock::TcpPort(port) => {
+                        rule.proto(Proto::Tcp).to(Target::port(Port::Single(port)));
+                    }
+                }
+                rule
+            })
+            .collect()
+    }
+
+    fn get_allow_tunnel_rules(
+        &self,
+        tunnel_interface: &str,
+        allowed_traffic: &AllowedTunnelTraffic,
+    ) -> Vec<Rule> {
+        let allow_rule = |to: Option<(Target, Proto)>| {
+            let mut rule = self.rule(Action::Pass);
+            rule.quick()
+                .interface(tunnel_interface)
+                .keep_state()
+                .tcp_flags();
+            if let Some((target, proto)) = to {
+                rule.to(target).proto(proto);
+            }
+            rule
+        };
+
+        match allowed_traffic {
+            AllowedTunnelTraffic::None => vec![],
+            AllowedTunnelTraffic::All => vec![allow_rule(None)],
+            AllowedTunnelTraffic::Only(endpoint) => vec![allow_rule(Some((
+                Target::endpoint(endpoint.address.ip(), Port::Single(endpoint.address.port())),
+                Proto::from(endpoint.protocol),
+            )))],
+            AllowedTunnelTraffic::Dns(servers) => servers
+                .iter()
+                .flat_map(|server| {
+                    [Proto::Udp, Proto::Tcp].map(|proto| {
+                        allow_rule(Some((Target::endpoint(*server, Port::Single(53)), proto)))
+                    })
+                })
+                .collect(),
+            AllowedTunnelTraffic::Endpoints {
+                addresses,
+                port,
+                protocol,
+            } => addresses
+                .iter()
+                .map(|address| {
+                    allow_rule(Some((
+                        Target::endpoint(*address, Port::Single(*port)),
+                        Proto::from(*protocol),
+                    )))
+                })
+                .collect(),
+        }
+    }
+
+    fn get_allow_loopback_rules(&self) -> Vec<Rule> {
+        let mut lo0_rule = self.rule(Action::Pass);
+        lo0_rule.quick().interface("lo0").keep_state();
+        vec![lo0_rule]
+    }
+
+    /// Returns rules that allow all traffic to and from the given networks.
+    fn get_allow_networks_rules<'a>(
+        &self,
+        networks: impl IntoIterator<Item = &'a IpNetwork>,
+    ) -> Vec<Rule> {
+        let mut rules = vec![];
+        for net in networks {
+            let mut allow_out = self.rule(Action::Pass);
+            allow_out
+                .quick()
+                .direction(Direction::Out)
+                .to(Target::network(*net));
+            let mut allow_in = self.rule(Action::Pass);
+            allow_in
+                .quick()
+                .direction(Direction::In)
+                .from(Target::network(*net));
+            rules.push(allow_out);
+            rules.push(allow_in);
+        }
+        rules
+    }
+
+    /// Returns the rules of policy plugins. Block rules come first, so that they take precedence
+    /// over the allow rules.
+    fn get_plugin_rules(&self, plugins: &PolicyFragment) -> Vec<Rule> {
+        let mut rules = vec![];
+        for rule in plugins.block_rules() {
+            rules.append(&mut self.get_plugin_rule(rule, Action::Drop));
+        }
+        for rule in plugins.allow_rules() {
+            rules.append(&mut self.get_plugin_rule(rule, Action::Pass));
+        }
+        rules
+    }
+
+    fn get_plugin_rule(&self, plugin_rule: &PluginRule, action: Action) -> Vec<Rule> {
+        let remote = Target {
+            address: Some(plugin_rule.network),
+            port: plugin_rule.port.map(Port::Single),
+        };
+        let mut rule = self.rule(action);
+        rule.quick();
+        if let Some(protocol) = plugin_rule.protocol {
+            rule.proto(Proto::from(protocol));
+        }
+        let mut out_rule = rule.clone();
+        out_rule.direction(Direction::Out).to(remote);
+        let mut in_rule = rule;
+        in_rule.direction(Direction::In).from(remote);
+        vec![out_rule, in_rule]
+    }
+
+    /// Returns rules that allow the traffic of another VPN. Routing is left to the other VPN,
+    /// whose routes are more specific than the ones for the tunnel.
+    fn get_vpn_coexistence_rules(&self, vpn_coexistence: &net::VpnCoexistence) -> Vec<Rule> {
+        let mut rules = self.get_allow_networks_rules(&vpn_coexistence.networks);
+        for port in &vpn_coexistence.udp_ports {
+            let mut allow_out = self.rule(Action::Pass);
+            allow_out
+                .quick()
+                .proto(Proto::Udp)
+                .direction(Direction::Out)
+                .from(Target::port(Port::Single(*port)))
+                .keep_state();
+            let mut allow_in = self.rule(Action::Pass);
+            allow_in
+                .quick()
+                .proto(Proto::Udp)
+                .direction(Direction::In)
+                .to(Target::port(Port::Single(*port)))
+                .keep_state();
+            rules.push(allow_out);
+            rules.push(allow_in);
+        }
+        rules
+    }
+
+    fn get_allow_lan_rules(&self) -> Vec<Rule> {
+        let mut rules = self.get_allow_networks_rules(&*super::ALLOWED_LAN_NETS);
+        for multicast_net in &*super::ALLOWED_LAN_MULTICAST_NETS {
+            let mut allow_multicast_out = self.rule(Action::Pass);
+            allow_multicast_out
+                .quick()
+                .direction(Direction::Out)
+                .to(Target::network(*multicast_net));
+            rules.push(allow_multicast_out);
+        }
+
+        let mut dhcpv4_out = self.rule(Action::Pass);
+        dhcpv4_out
+            .quick()
+            .direction(Direction::Out)
+            .af(AddrFamily::Inet)
+            .proto(Proto::Udp)
+            .from(Target::port(Port::Single(super::DHCPV4_SERVER_PORT)))
+            .to(Target::port(Port::Single(super::DHCPV4_CLIENT_PORT)));
+        let mut dhcpv4_in = self.rule(Action::Pass);
+        dhcpv4_in
+            .quick()
+            .direction(Direction::In)
+            .proto(Proto::Udp)
+            .from(Target::port(Port::Single(super::DHCPV4_CLIENT_PORT)))
+            .to(Target::endpoint(
+                Ipv4Addr::BROADCAST,
+                Port::Single(super::DHCPV4_SERVER_PORT),
+            ));
+        rules.push(dhcpv4_out);
+        rules.push(dhcpv4_in);
+
+        rules
+    }
+
+    fn get_allow_dhcp_client_rules(&self) -> Vec<Rule> {
+        let mut dhcp_rule = self.rule(Action::Pass);
+        dhcp_rule.quick().proto(Proto::Udp);
+
+        let mut rules = Vec::new();
+
+        // DHCPv4
+        let mut dhcpv4_rule = dhcp_rule.clone();
+        dhcpv4_rule.af(AddrFamily::Inet);
+        let mut allow_outgoing_dhcp_v4 = dhcpv4_rule.clone();
+        allow_outgoing_dhcp_v4
+            .direction(Direction::Out)
+            .from(Target::port(Port::Single(super::DHCPV4_CLIENT_PORT)))
+            .to(Target::endpoint(
+                Ipv4Addr::BROADCAST,
+                Port::Single(super::DHCPV4_SERVER_PORT),
+            ));
+        let mut allow_incoming_dhcp_v4 = dhcpv4_rule;
+        allow_incoming_dhcp_v4
+            .direction(Direction::In)
+            .from(Target::port(Port::Single(super::DHCPV4_SERVER_PORT)))
+            .to(Target::port(Port::Single(super::DHCPV4_CLIENT_PORT)));
+        rules.push(allow_outgoing_dhcp_v4);
+        rules.push(allow_incoming_dhcp_v4);
+
+        // DHCPv6
+        let link_local = IpNetwork::V6(*super::IPV6_LINK_LOCAL);
+        let mut dhcpv6_rule = dhcp_rule;
+        dhcpv6_rule.af(AddrFamily::Inet6);
+        for dhcpv6_server in &*super::DHCPV6_SERVER_ADDRS {
+            let mut allow_outgoing_dhcp_v6 = dhcpv6_rule.clone();
+            allow_outgoing_dhcp_v6
+                .direction(Direction::Out)
+                .from(Target::endpoint(
+                    link_local,
+                    Port::Single(super::DHCPV6_CLIENT_PORT),
+                ))
+                .to(Target::endpoint(
+                    *dhcpv6_server,
+                    Port::Single(super::DHCPV6_SERVER_PORT),
+                ));
+            rules.push(allow_outgoing_dhcp_v6);
+        }
+        let mut allow_incoming_dhcp_v6 = dhcpv6_rule;
+        allow_incoming_dhcp_v6
+            .direction(Direction::In)
+            .from(Target::endpoint(
+                link_local,
+                Port::Single(super::DHCPV6_SERVER_PORT),
+            ))
+            .to(Target::endpoint(
+                link_local,
+                Port::Single(super::DHCPV6_CLIENT_PORT),
+            ));
+        rules.push(allow_incoming_dhcp_v6);
+
+        rules
+    }
+
+    fn get_allow_ndp_rules(&self) -> Vec<Rule> {
+        let link_local = IpNetwork::V6(*super::IPV6_LINK_LOCAL);
+        let ndp_rule = |direction, icmp6_type| {
+            let mut rule = self.rule(Action::Pass);
+            rule.quick()
+                .af(AddrFamily::Inet6)
+                .proto(Proto::Icmp6)
+                .direction(direction)
+                .icmp6_type(icmp6_type);
+            rule
+        };
+
+        let mut rules = Vec::new();
+
+        // Outgoing router solicitation to `ff02::2`
+        let mut rule = ndp_rule(Direction::Out, Icmp6Type::RouterSol);
+        rule.to(Target::address(IpAddr::V6(
+            *super::ROUTER_SOLICITATION_OUT_DST_ADDR,
+        )));
+        rules.push(rule);
+
+        // Incoming router advertisement and redirect from `fe80::/10`
+        for icmp6_type in [Icmp6Type::RouterAdv, Icmp6Type::Redir] {
+            let mut rule = ndp_rule(Direction::In, icmp6_type);
+            rule.from(Target::network(link_local));
+            rules.push(rule);
+        }
+
+        // Outgoing neighbor solicitation to `ff02::1:ff00:0/104` and `fe80::/10`
+        for net in [IpNetwork::V6(*super::SOLICITED_NODE_MULTICAST), link_local] {
+            let mut rule = ndp_rule(Direction::Out, Icmp6Type::NeighbrSol);
+            rule.to(Target::network(net));
+            rules.push(rule);
+        }
+
+        // Incoming neighbor solicitation from `fe80::/10`
+        let mut rule = ndp_rule(Direction::In, Icmp6Type::NeighbrSol);
+        rule.from(Target::network(link_local));
+        rules.push(rule);
+
+        // Outgoing neigbor advertisement to fe80::/10`
+        let mut rule = ndp_rule(Direction::Out, Icmp6Type::NeighbrAdv);
+        rule.to(Target::network(link_local));
+        rules.push(rule);
+
+        // Incoming neigbor advertisement from anywhere
+        rules.push(ndp_rule(Direction::In, Icmp6Type::NeighbrAdv));
+
+        rules
+    }
+
+    fn rule(&self, action: Action) -> Rule {
+        let log = match action {
+            Action::Pass => matches!(self.rule_logging, RuleLogging::All | RuleLogging::Pass),
+            Action::Return | Action::Drop => {
+                matches!(self.rule_logging, RuleLogging::All | RuleLogging::Drop)
+            }
+        };
+        Rule::new(action, log)
+    }
+}
+
+/// Which rules log the packets they match to pflog0.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub(super) enum RuleLogging {
+    None,
+    Pass,
+    Drop,
+    All,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(super) enum Action {
+    Pass,
+    /// Block, and reply with a TCP RST or ICMP unreachable.
+    Return,
+    Drop,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(super) enum Direction {
+    In,
+    Out,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(super) enum AddrFamily {
+    Inet,
+    Inet6,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(super) enum Proto {
+    Tcp,
+    Udp,
+    Icmp6,
+}
+
+impl From<net::TransportProtocol> for Proto {
+    fn from(protocol: net::TransportProtocol) -> Self {
+        match protocol {
+            net::TransportProtocol::Udp => Proto::Udp,
+            net::TransportProtocol::Tcp => Proto::Tcp,
+        }
+    }
+}
+
+/// ICMPv6 types used by the neighbor discovery protocol.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(super) enum Icmp6Type {
+    RouterSol,
+    RouterAdv,
+    Redir,
+    NeighbrSol,
+    NeighbrAdv,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(super) enum Port {
+    Single(u16),
+    /// Inclusive range of ports.
+    Range(u16, u16),
+}
+
+/// Source or destination of a rule. Missing parts match anything.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub(super) struct Target {
+    pub address: Option<IpNetwork>,
+    pub port: Option<Port>,
+}
+
+impl Target {
+    pub fn address(address: impl Into<IpAddr>) -> Self {
+        Self::network(IpNetwork::from(address.into()))
+    }
+
+    pub fn network(network: IpNetwork) -> Self {
+        Target {
+            address: Some(network),
+            port: None,
+        }
+    }
+
+    pub fn port(port: Port) -> Self {
+        Target {
+            address: None,
+            port: Some(port),
+        }
+    }
+
+    pub fn endpoint(address: impl Into<IpNetwork>, port: Port) -> Self {
+        Target {
+            address: Some(address.into()),
+            port: Some(port),
+        }
+    }
+}
+
+/// A filter rule. Matches traffic in both directions and of any protocol unless restricted.
+#[derive(Debug, Clone)]
+pub(super) struct Rule {
+    pub action: Action,
+    pub direction: Option<Direction>,
+    pub log: bool,
+    pub quick: bool,
+    pub interface: Option<String>,
+    pub af: Option<AddrFamily>,
+    pub proto: Option<Proto>,
+    pub from: Target,
+    pub to: Target,
+    pub user: Option<u32>,
+    pub icmp6_type: Option<Icmp6Type>,
+    /// Whether state is created, so that replies are let through. Pass rules without state only
+    /// match the packets that they describe.
+    pub keep_state: bool,
+    /// Whether only the first packet of a TCP connection, with SYN set and ACK unset, creates
+    /// state.
+    pub tcp_flags: bool,
+}
+
+impl Rule {
+    pub fn new(action: Action, log: bool) -> Self {
+        Rule {
+            action,
+            direction: None,
+            log,
+            quick: false,
+            interface: None,
+            af: None,
+            proto: None,
+            from: Target::default(),
+            to: Target::default(),
+            user: None,
+            icmp6_type: None,
+            keep_state: false,
+            tcp_flags: false,
+        }
+    }
+
+    pub fn direction(&mut self, direction: Direction) -> &mut Self {
+        self.direction = Some(direction);
+        self
+    }
+
+    pub fn quick(&mut self) -> &mut Self {
+        self.quick = true;
+        self
+    }
+
+    pub fn interface(&mut self, interface: &str) -> &mut Self {
+        self.interface = Some(interface.to_owned());
+        self
+    }
+
+    pub fn af(&mut self, af: AddrFamily) -> &mut Self {
+        self.af = Some(af);
+        self
+    }
+
+    pub fn proto(&mut self, proto: Proto) -> &mut Self {
+        self.proto = Some(proto);
+        self
+    }
+
+    pub fn from(&mut self, from: Target) -> &mut Self {
+        self.from = from;
+        self
+    }
+
+    pub fn to(&mut self, to: Target) -> &mut Self {
+        self.to = to;
+        self
+    }
+
+    pub fn user(&mut self, uid: u32) -> &mut Self {
+        self.user = Some(uid);
+        self
+    }
+
+    pub fn icmp6_type(&mut self, icmp6_type: Icmp6Type) -> &mut Self {
+        self.icmp6_type = Some(icmp6_type);
+        self
+    }
+
+    pub fn keep_state(&mut self) -> &mut Self {
+        self.keep_state = true;
+        self
+    }
+
+    pub fn tcp_flags(&mut self) -> &mut Self {
+        self.tcp_flags = true;
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use talpid_types::net::{
+        AllowedEndpoint, Endpoint, EndpointRange, TransportProtocol, VpnCoexistence,
+    };
+
+    #[test]
+    fn test_blocked_rules() {
+        let generator = RuleGenerator {
+            rule_logging: RuleLogging::None,
+        };
+        let api_endpoint = Endpoint::new(
+            "45.83.223.196".parse::<IpAddr>().unwrap(),
+            443,
+            TransportProtocol::Tcp,
+        );
+        let policy = FirewallPolicy::Blocked {
+            allow_lan: false,
+            allowed_endpoint: Some(AllowedEndpoint {
+                endpoints: vec![EndpointRange::from(api_endpoint)],
+                hostname: None,
+            }),
+            recovery_allowlist: vec![],
+            vpn_coexistence: VpnCoexistence::default(),
+            #[cfg(target_os = "macos")]
+            dns_redirect_port: 1053,
+        };
+
+        let rules = generator.filter_rules(&policy, &PolicyFragment::default());
+
+        let api_rules: Vec<_> = rules.iter().filter(|rule| rule.user.is_some()).collect();
+        assert_eq!(api_rules.len(), 1);
+        assert_eq!(api_rules[0].user, Some(super::super::ROOT_UID));
+        assert_eq!(api_rules[0].proto, Some(Proto::Tcp));
+        assert_eq!(
+            api_rules[0].to,
+            Target::endpoint(api_endpoint.address.ip(), Port::Single(443))
+        );
+        assert!(api_rules[0].keep_state);
+
+        // DNS is only blocked explicitly when something else is allowed
+        assert!(!rules
+            .iter()
+            .any(|rule| rule.to == Target::port(Port::Single(53))));
+
+        let last_rules: Vec<_> = rules[rules.len() - 2..]
+            .iter()
+            .map(|rule| (rule.action, rule.direction, rule.quick))
+            .collect();
+        assert_eq!(
+            last_rules,
+            [
+                (Action::Return, Some(Direction::Out), true),
+                (Action::Drop, None, true)
+            ]
+        );
+    }
+}
@@ -0,0 +1,438 @@
+//! Signed policy plugins, which let an administrator add rules to every firewall policy.
+//!
+//! A plugin is a file with the extension `.policy` in the plugin directory, next to a detached
+//! ed25519 signature of its contents in a file with the same name and the extension `.sig`. The
+//! signature is hex encoded, and must be made by one of the hex encoded public keys listed in the
+//! file `trusted-keys` in the resource directory. The keys are kept apart from the plugins, so
+//! that being able to add a plugin is not enough to have it trusted. Plugins that are not signed
+//! by a trusted key, that contain invalid rules, or that would make the rules too many to enforce,
+//! are ignored.
+//!
+//! Each line of a plugin is empty, a comment starting with `#`, or a rule of the form
+//!
+//! ```text
+//! allow|block <network> [tcp|udp [port <port>]]
+//! ```
+//!
+//! A rule matches traffic to and from `<network>`, and `<port>` is the port on the remote end.
+//! Block rules take precedence over allow rules, and both take precedence over the rules of the
+//! policy itself.
+
+use ipnetwork::IpNetwork;
+use std::{
+    ffi::OsStr,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+use talpid_types::{net::TransportProtocol, ErrorExt};
+
+/// Name of the file in the resource directory that lists the keys that may sign plugins.
+pub const TRUSTED_KEYS_FILENAME: &str = "trusted-keys";
+const POLICY_EXTENSION: &str = "policy";
+const SIGNATURE_EXTENSION: &str = "sig";
+/// Maximum number of rules that a single plugin may contain.
+const MAX_RULES: usize = 256;
+/// Maximum number of rule groups across all plugins. Rules that only differ in their network form
+/// a group, and the Windows firewall has a fixed number of filters for each group.
+const MAX_RULE_GROUPS: usize = 64;
+const ED25519_PUBLIC_KEY_LEN: usize = 32;
+
+/// Errors that can happen when loading a policy plugin.
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    /// Failed to read a file in the plugin directory.
+    #[error(display = "Failed to read {}", _0)]
+    ReadFile(String, #[error(source)] io::Error),
+
+    /// A trusted key is not a hex encoded ed25519 public key.
+    #[error(display = "Invalid trusted key on line {}", _0)]
+    InvalidTrustedKey(usize),
+
+    /// The signature is not hex encoded.
+    #[error(display = "The signature is not hex encoded")]
+    InvalidSignatureEncoding,
+
+    /// The plugin is not signed by any of the trusted keys.
+    #[error(display = "The plugin is not signed by a trusted key")]
+    UntrustedSignature,
+
+    /// A line of the plugin is not a valid rule.
+    #[error(display = "Invalid rule on line {}: {}", _0, _1)]
+    InvalidRule(usize, &'static str),
+
+    /// The plugin contains too many rules.
+    #[error(display = "The plugin contains more than {} rules", MAX_RULES)]
+    TooManyRules,
+
+    /// The plugin would make the rules of all plugins form too many groups.
+    #[error(
+        display = "The rules of all plugins would form more than {} groups",
+        MAX_RULE_GROUPS
+    )]
+    TooManyRuleGroups,
+
+    /// The trusted keys file may be modified by users other than root.
+    #[error(display = "The trusted keys file must be owned by root and only writable by root")]
+    InsecureTrustedKeys,
+}
+
+/// Whether a plugin rule allows or blocks the traffic it matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PluginAction {
+    Allow,
+    Block,
+}
+
+/// A rule from a policy plugin.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PluginRule {
+    pub action: PluginAction,
+    /// Remote network that traffic is matched against, in both directions.
+    pub network: IpNetwork,
+    /// Transport protocol to match. Traffic of any protocol is matched if this is `None`.
+    pub protocol: Option<TransportProtocol>,
+    /// Remote port to match. Only set if `protocol` is.
+    pub port: Option<u16>,
+}
+
+impl fmt::Display for PluginRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.action {
+            PluginAction::Allow => write!(f, "allow {}", self.network)?,
+            PluginAction::Block => write!(f, "block {}", self.network)?,
+        }
+        match self.protocol {
+            Some(TransportProtocol::Tcp) => write!(f, " tcp")?,
+            Some(TransportProtocol::Udp) => write!(f, " udp")?,
+            None => (),
+        }
+        if let Some(port) = self.port {
+            write!(f, " port {}", port)?;
+        }
+        Ok(())
+    }
+}
+
+/// The rules of all valid plugins, which are added to every firewall policy.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct PolicyFragment {
+    rules: Vec<PluginRule>,
+}
+
+impl PolicyFragment {
+    /// Returns a fragment with the given rules.
+    #[cfg(test)]
+    pub(super) fn from_rules(rules: Vec<PluginRule>) -> Self {
+        PolicyFragment { rules }
+    }
+
+    /// Returns whether there are no plugin rules.
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Returns the rules that block traffic.
+    pub fn block_rules(&self) -> impl Iterator<Item = &PluginRule> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.action == PluginAction::Block)
+    }
+
+    /// Returns the rules that allow traffic.
+    pub fn allow_rules(&self) -> impl Iterator<Item = &PluginRule> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.action == PluginAction::Allow)
+    }
+}
+
+/// Loads and merges the rules of all valid plugins in `dir`, which must be signed by one of the
+/// keys in `trusted_keys_path`. Plugins that fail to load are logged and ignored, since a broken
+/// plugin must not prevent the firewall from working.
+pub fn load_plugins(dir: &Path, trusted_keys_path: &Path) -> PolicyFragment {
+    let mut fragment = PolicyFragment::default();
+
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return fragment,
+        Err(error) => {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to read the firewall plugin directory")
+            );
+            return fragment;
+        }
+    };
+    let mut plugin_paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension() == Some(OsStr::new(POLICY_EXTENSION)))
+        .collect();
+    if plugin_paths.is_empty() {
+        return fragment;
+    }
+    plugin_paths.sort();
+
+    let trusted_keys = match read_trusted_keys(trusted_keys_path) {
+        Ok(keys) => keys,
+        Err(error) => {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Ignoring all firewall plugins")
+            );
+            return fragment;
+        }
+    };
+
+    for path in plugin_paths {
+        let result = load_plugin(&path, &trusted_keys).and_then(|rules| {
+            if count_rule_groups(fragment.rules.iter().chain(&rules)) > MAX_RULE_GROUPS {
+                return Err(Error::TooManyRuleGroups);
+            }
+            Ok(rules)
+        });
+        match result {
+            Ok(mut rules) => {
+                log::info!(
+                    "Loaded firewall plugin {} with {} rules",
+                    path.display(),
+                    rules.len()
+                );
+                fragment.rules.append(&mut rules);
+            }
+            Err(error) => log::error!(
+                "{}",
+                error.display_chain_with_msg(&format!(
+                    "Ignoring firewall plugin {}",
+                    path.display()
+                ))
+            ),
+        }
+    }
+    fragment
+}
+
+fn load_plugin(path: &Path, trusted_keys: &[Vec<u8>]) -> Result<Vec<PluginRule>, Error> {
+    let contents = read_file(path)?;
+    let mut signature_path = path.as_os_str().to_owned();
+    signature_path.push(".");
+    signature_path.push(SIGNATURE_EXTENSION);
+    let signature = read_file(Path::new(&signature_path))?;
+    let signature = hex::decode(signature.trim()).map_err(|_| Error::InvalidSignatureEncoding)?;
+
+    verify_signature(contents.as_bytes(), &signature, trusted_keys)?;
+    parse_rules(&contents)
+}
+
+fn read_file(path: &Path) -> Result<String, Error> {
+    fs::read_to_string(path).map_err(|error| Error::ReadFile(path.display().to_string(), error))
+}
+
+fn read_trusted_keys(path: &Path) -> Result<Vec<Vec<u8>>, Error> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+        let metadata = fs::metadata(path)
+            .map_err(|error| Error::ReadFile(path.display().to_string(), error))?;
+        if metadata.uid() != 0 || metadata.permissions().mode() & 0o022 != 0 {
+            return Err(Error::InsecureTrustedKeys);
+        }
+    }
+    parse_trusted_keys(&read_file(path)?)
+}
+
+fn parse_trusted_keys(contents: &str) -> Result<Vec<Vec<u8>>, Error> {
+    relevant_lines(contents)
+        .map(|(line_number, line)| match hex::decode(line) {
+            Ok(key) if key.len() == ED25519_PUBLIC_KEY_LEN => Ok(key),
+            _ => Err(Error::InvalidTrustedKey(line_number)),
+        })
+        .collect()
+}
+
+fn verify_signature(data: &[u8], signature: &[u8], trusted_keys: &[Vec<u8>]) -> Result<(), Error> {
+    let trusted = trusted_keys.iter().any(|key| {
+        ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, key)
+            .verify(data, signature)
+            .is_ok()
+    });
+    if trusted {
+        Ok(())
+    } else {
+        Err(Error::UntrustedSignature)
+    }
+}
+
+/// Returns the number of groups that `rules` form. Rules are in the same group if they only differ
+/// in their network, and the network is of the same IP version.
+fn count_rule_groups<'a>(rules: impl Iterator<Item = &'a PluginRule>) -> usize {
+    let mut groups = Vec::new();
+    for rule in rules {
+        let group = (
+            rule.action,
+            rule.network.is_ipv4(),
+            rule.protocol,
+            rule.port,
+        );
+        if !groups.contains(&group) {
+            groups.push(group);
+        }
+    }
+    groups.len()
+}
+
+/// Parses the rules of a plugin.
+pub fn parse_rules(contents: &str) -> Result<Vec<PluginRule>, Error> {
+    let rules = relevant_lines(contents)
+        .map(|(line_number, line)| {
+            parse_rule(line).map_err(|reason| Error::InvalidRule(line_number, reason))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if rules.len() > MAX_RULES {
+        return Err(Error::TooManyRules);
+    }
+    Ok(rules)
+}
+
+fn parse_rule(line: &str) -> Result<PluginRule, &'static str> {
+    let mut tokens = line.split_whitespace();
+
+    let action = match tokens.next() {
+        Some("allow") => PluginAction::Allow,
+        Some("block") => PluginAction::Block,
+        _ => return Err("expected \"allow\" or \"block\""),
+    };
+    let network: IpNetwork = tokens
+        .next()
+        .ok_or("missing network")?
+        .parse()
+        .map_err(|_| "invalid network")?;
+    if action == PluginAction::Allow && network.prefix() == 0 {
+        return Err("allowing all addresses would disable the firewall");
+    }
+
+    let protocol = match tokens.next() {
+        None => None,
+        Some("tcp") => Some(TransportProtocol::Tcp),
+        Some("udp") => Some(TransportProtocol::Udp),
+        Some(_) => return Err("expected \"tcp\" or \"udp\""),
+    };
+    let port = match tokens.next() {
+        None => None,
+        Some("port") => Some(
+            tokens
+                .next()
+                .ok_or("missing port")?
+                .parse()
+                .map_err(|_| "invalid port")?,
+        ),
+        Some(_) => return Err("expected \"port\""),
+    };
+    if tokens.next().is_some() {
+        return Err("unexpected trailing input");
+    }
+
+    Ok(PluginRule {
+        action,
+        network,
+        protocol,
+        port,
+    })
+}
+
+/// Returns the lines that are neither empty nor comments, along with their line numbers.
+fn relevant_lines(contents: &str) -> impl Iterator<Item = (usize, &str)> {
+    contents
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ring::signature::KeyPair;
+
+    #[test]
+    fn test_parse_rules() {
+        let rules = parse_rules(
+            "# Management agent\n\
+             allow 10.20.0.0/16 tcp port 443\n\
+             \n\
+             block 203.0.113.7\n",
+        )
+        .unwrap();
+        assert_eq!(
+            rules,
+            vec![
+                PluginRule {
+                    action: PluginAction::Allow,
+                    network: "10.20.0.0/16".parse().unwrap(),
+                    protocol: Some(TransportProtocol::Tcp),
+                    port: Some(443),
+                },
+                PluginRule {
+                    action: PluginAction::Block,
+                    network: "203.0.113.7/32".parse().unwrap(),
+                    protocol: None,
+                    port: None,
+                },
+            ]
+        );
+
+        assert!(matches!(
+            parse_rules("allow 0.0.0.0/0"),
+            Err(Error::InvalidRule(1, _))
+        ));
+        assert!(matches!(
+            parse_rules("\nallow 10.0.0.0/8 port 22"),
+            Err(Error::InvalidRule(2, _))
+        ));
+        assert!(matches!(
+            parse_rules("allow 10.0.0.0/8 udp port 53 extra"),
+            Err(Error::InvalidRule(1, _))
+        ));
+    }
+
+    #[test]
+    fn test_count_rule_groups() {
+        let rules = parse_rules(
+            "allow 10.0.0.0/8 tcp port 443\n\
+             allow 192.168.0.0/16 tcp port 443\n\
+             allow fd00::/8 tcp port 443\n\
+             allow 10.0.0.0/8 tcp port 22\n\
+             block 10.0.0.0/8 tcp port 443\n",
+        )
+        .unwrap();
+        assert_eq!(count_rule_groups(rules.iter()), 4);
+
+        let rules: Vec<_> = (0..=MAX_RULE_GROUPS)
+            .map(|port| format!("allow 10.0.0.0/8 udp port {}", port + 1))
+            .collect();
+        let rules = parse_rules(&rules.join("\n")).unwrap();
+        assert_eq!(count_rule_groups(rules.iter()), MAX_RULE_GROUPS + 1);
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let rng = ring::rand::SystemRandom::new();
+        let pkcs8 = ring::signature::Ed25519KeyPair::generate_pkcs8(&rng).unwrap();
+        let key_pair = ring::signature::Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let trusted_keys =
+            parse_trusted_keys(&hex::encode(key_pair.public_key().as_ref())).unwrap();
+
+        let policy = b"allow 192.0.2.0/24";
+        let signature = key_pair.sign(policy);
+        assert!(verify_signature(policy, signature.as_ref(), &trusted_keys).is_ok());
+        assert!(matches!(
+            verify_signature(b"allow 198.51.100.0/24", signature.as_ref(), &trusted_keys),
+            Err(Error::UntrustedSignature)
+        ));
+        assert!(matches!(
+            parse_trusted_keys("# admin\nabcd"),
+            Err(Error::InvalidTrustedKey(2))
+        ));
+    }
+}
@@ -0,0 +1,306 @@
+//! Detection of WFP objects owned by other products that may override the policy applied by
+//! WinFw. Antivirus products and third-party firewalls commonly add sublayers with maximum
+//! weight, and hard permit filters in such sublayers can let traffic through that our policy
+//! intends to block.
+
+use std::{fmt, io, ptr};
+use widestring::WideCStr;
+use windows_sys::{
+    core::{GUID, PWSTR},
+    Win32::{
+        Foundation::{ERROR_SUCCESS, HANDLE},
+        NetworkManagement::WindowsFilteringPlatform::{
+            FwpmEngineClose0, FwpmEngineOpen0, FwpmFilterCreateEnumHandle0,
+            FwpmFilterDestroyEnumHandle0, FwpmFilterEnum0, FwpmFreeMemory0, FwpmProviderGetByKey0,
+            FwpmSubLayerCreateEnumHandle0, FwpmSubLayerDestroyEnumHandle0, FwpmSubLayerEnum0,
+            FWPM_FILTER0, FWPM_FILTER_FLAG_CLEAR_ACTION_RIGHT, FWPM_PROVIDER0, FWPM_SUBLAYER0,
+            FWP_ACTION_PERMIT,
+        },
+        System::Rpc::RPC_C_AUTHN_WINNT,
+    },
+};
+
+/// Provider used by WinFw. Must match `MullvadGuids::Provider()`.
+const MULLVAD_PROVIDER: GUID = GUID {
+    data1: 0x21e1dab8,
+    data2: 0xb9db,
+    data3: 0x43c0,
+    data4: [0xb3, 0x43, 0xeb, 0x93, 0x65, 0xc7, 0xbd, 0xd2],
+};
+
+/// Provider used by WinFw for persistent objects. Must match `MullvadGuids::ProviderPersistent()`.
+const MULLVAD_PROVIDER_PERSISTENT: GUID = GUID {
+    data1: 0x2bc5bc63,
+    data2: 0x80b0,
+    data3: 0x4119,
+    data4: [0x86, 0xd3, 0x6a, 0xfe, 0x0d, 0xff, 0x2a, 0x26],
+};
+
+/// Lowest weight of the sublayers added by WinFw. Sublayers with at least this weight may be
+/// evaluated before ours.
+const MULLVAD_MIN_SUBLAYER_WEIGHT: u16 = u16::MAX - 1;
+
+/// Number of objects to request per enumeration call.
+const ENUM_BATCH_SIZE: u32 = 100;
+
+/// Errors that can occur while enumerating WFP objects.
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    /// Failed to open a session to the filter engine.
+    #[error(display = "Failed to open a session to the filter engine")]
+    OpenEngine(#[error(source)] io::Error),
+
+    /// Failed to enumerate sublayers.
+    #[error(display = "Failed to enumerate WFP sublayers")]
+    EnumerateSublayers(#[error(source)] io::Error),
+
+    /// Failed to enumerate filters.
+    #[error(display = "Failed to enumerate WFP filters")]
+    EnumerateFilters(#[error(source)] io::Error),
+}
+
+/// An object owned by another provider that may interfere with our firewall policy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    /// Display name of the sublayer or filter.
+    pub name: String,
+    /// Display name of the provider that owns the object, if any.
+    pub provider: Option<String>,
+    /// What kind of object this is.
+    pub kind: ConflictKind,
+}
+
+/// Kind of a conflicting WFP object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictKind {
+    /// A sublayer whose weight is at least that of our sublayers.
+    Sublayer { weight: u16 },
+    /// A filter that permits traffic and cannot be overridden by other sublayers.
+    HardPermitFilter,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            ConflictKind::Sublayer { weight } => {
+                write!(f, "sublayer \"{}\" (weight {})", self.name, weight)?
+            }
+            ConflictKind::HardPermitFilter => write!(f, "hard permit filter \"{}\"", self.name)?,
+        }
+        match &self.provider {
+            Some(provider) => write!(f, " from provider \"{}\"", provider),
+            None => write!(f, " without a provider"),
+        }
+    }
+}
+
+/// Returns the number of filters owned by our providers.
+pub fn count_own_filters() -> Result<usize, Error> {
+    let engine = Engine::open()?;
+    let mut count = 0;
+    engine.for_each_filter(|filter| {
+        if is_mullvad_provider(filter.providerKey) {
+            count += 1;
+        }
+    })?;
+    Ok(count)
+}
+
+/// Enumerates sublayers and filters owned by other providers that may override the policy
+/// applied by WinFw.
+pub fn find_conflicts() -> Result<Vec<Conflict>, Error> {
+    let engine = Engine::open()?;
+    let mut conflicts = vec![];
+    let mut conflicting_sublayers = vec![];
+
+    engine.for_each_sublayer(|sublayer| {
+        if sublayer.weight < MULLVAD_MIN_SUBLAYER_WEIGHT
+            || is_mullvad_provider(sublayer.providerKey)
+        {
+            return;
+        }
+        conflicting_sublayers.push(sublayer.subLayerKey);
+        conflicts.push(Conflict {
+            name: string_from_pwstr(sublayer.displayData.name),
+            provider: engine.provider_name(sublayer.providerKey),
+            kind: ConflictKind::Sublayer {
+                weight: sublayer.weight,
+            },
+        });
+    })?;
+
+    if conflicting_sublayers.is_empty() {
+        return Ok(conflicts);
+    }
+
+    engine.for_each_filter(|filter| {
+        let is_hard_permit = filter.action.r#type == FWP_ACTION_PERMIT
+            && (filter.flags & FWPM_FILTER_FLAG_CLEAR_ACTION_RIGHT) != 0;
+        if !is_hard_permit
+            || is_mullvad_provider(filter.providerKey)
+            || !conflicting_sublayers
+                .iter()
+                .any(|sublayer| guid_eq(sublayer, &filter.subLayerKey))
+        {
+            return;
+        }
+        conflicts.push(Conflict {
+            name: string_from_pwstr(filter.displayData.name),
+            provider: engine.provider_name(filter.providerKey),
+            kind: ConflictKind::HardPermitFilter,
+        });
+    })?;
+
+    Ok(conflicts)
+}
+
+struct Engine(HANDLE);
+
+impl Engine {
+    fn open() -> Result<Self, Error> {
+        let mut handle = 0;
+        let status = unsafe {
+            FwpmEngineOpen0(
+                ptr::null(),
+                RPC_C_AUTHN_WINNT,
+                ptr::null(),
+                ptr::null(),
+                &mut handle,
+            )
+        };
+        check_status(status).map_err(Error::OpenEngine)?;
+        Ok(Engine(handle))
+    }
+
+    fn for_each_sublayer(&self, mut f: impl FnMut(&FWPM_SUBLAYER0)) -> Result<(), Error> {
+        let mut enum_handle = 0;
+        check_status(unsafe {
+            FwpmSubLayerCreateEnumHandle0(self.0, ptr::null(), &mut enum_handle)
+        })
+        .map_err(Error::EnumerateSublayers)?;
+
+        let result = loop {
+            let mut entries: *mut *mut FWPM_SUBLAYER0 = ptr::null_mut();
+            let mut num_entries = 0;
+            let status = unsafe {
+                FwpmSubLayerEnum0(
+                    self.0,
+                    enum_handle,
+                    ENUM_BATCH_SIZE,
+                    &mut entries,
+                    &mut num_entries,
+                )
+            };
+            if let Err(error) = check_status(status) {
+                break Err(Error::EnumerateSublayers(error));
+            }
+            for i in 0..num_entries as usize {
+                f(unsafe { &**entries.add(i) });
+            }
+            unsafe { FwpmFreeMemory0(&mut entries as *mut _ as *mut *mut _) };
+            if num_entries < ENUM_BATCH_SIZE {
+                break Ok(());
+            }
+        };
+
+        unsafe { FwpmSubLayerDestroyEnumHandle0(self.0, enum_handle) };
+        result
+    }
+
+    fn for_each_filter(&self, mut f: impl FnMut(&FWPM_FILTER0)) -> Result<(), Error> {
+        let mut enum_handle = 0;
+        check_status(unsafe { FwpmFilterCreateEnumHandle0(self.0, ptr::null(), &mut enum_handle) })
+            .map_err(Error::EnumerateFilters)?;
+
+        let result = loop {
+            let mut entries: *mut *mut FWPM_FILTER0 = ptr::null_mut();
+            let mut num_entries = 0;
+            let status = unsafe {
+                FwpmFilterEnum0(
+                    self.0,
+                    enum_handle,
+                    ENUM_BATCH_SIZE,
+                    &mut entries,
+                    &mut num_entries,
+                )
+            };
+            if let Err(error) = check_status(status) {
+                break Err(Error::EnumerateFilters(error));
+            }
+            for i in 0..num_entries as usize {
+                f(unsafe { &**entries.add(i) });
+            }
+            unsafe { FwpmFreeMemory0(&mut entries as *mut _ as *mut *mut _) };
+            if num_entries < ENUM_BATCH_SIZE {
+                break Ok(());
+            }
+        };
+
+        unsafe { FwpmFilterDestroyEnumHandle0(self.0, enum_handle) };
+        result
+    }
+
+    fn provider_name(&self, key: *const GUID) -> Option<String> {
+        if key.is_null() {
+            return None;
+        }
+        let mut provider: *mut FWPM_PROVIDER0 = ptr::null_mut();
+        if check_status(unsafe { FwpmProviderGetByKey0(self.0, key, &mut provider) }).is_err() {
+            return Some(format!("{{{}}}", format_guid(unsafe { &*key })));
+        }
+        let name = string_from_pwstr(unsafe { (*provider).displayData.name });
+        unsafe { FwpmFreeMemory0(&mut provider as *mut _ as *mut *mut _) };
+        Some(name)
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        unsafe { FwpmEngineClose0(self.0) };
+    }
+}
+
+fn check_status(status: u32) -> io::Result<()> {
+    if status == ERROR_SUCCESS {
+        Ok(())
+    } else {
+        Err(io::Error::from_raw_os_error(status as i32))
+    }
+}
+
+fn is_mullvad_provider(key: *const GUID) -> bool {
+    if key.is_null() {
+        return false;
+    }
+    let key = unsafe { &*key };
+    guid_eq(key, &MULLVAD_PROVIDER) || guid_eq(key, &MULLVAD_PROVIDER_PERSISTENT)
+}
+
+fn guid_eq(a: &GUID, b: &GUID) -> bool {
+    a.data1 == b.data1 && a.data2 == b.data2 && a.data3 == b.data3 && a.data4 == b.data4
+}
+
+fn format_guid(guid: &GUID) -> String {
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:02x}{:02x}-{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}",
+        guid.data1,
+        guid.data2,
+        guid.data3,
+        guid.data4[0],
+        guid.data4[1],
+        guid.data4[2],
+        guid.data4[3],
+        guid.data4[4],
+        guid.data4[5],
+        guid.data4[6],
+        guid.data4[7],
+    )
+}
+
+fn string_from_pwstr(string: PWSTR) -> String {
+    if string.is_null() {
+        return String::from("<unnamed>");
+    }
+    unsafe { WideCStr::from_ptr_str(string) }.to_string_lossy()
+}
@@ -1,12 +1,20 @@
 use crate::{logging::windows::log_sink, tunnel::TunnelMetadata};
 
+use ipnetwork::IpNetwork;
 use std::{net::IpAddr, path::Path, ptr};
 
 use self::winfw::*;
-use super::{FirewallArguments, FirewallPolicy, InitialFirewallState};
+use super::{
+    plugin::{PluginAction, PolicyFragment},
+    FirewallArguments, FirewallPolicy, InitialFirewallState,
+};
 use talpid_types::{
-    net::{AllowedEndpoint, AllowedTunnelTraffic, Endpoint},
+    net::{
+        AllowedEndpoint, AllowedTunnelTraffic, BlockedTunnelProtocols, Endpoint, TransportProtocol,
+        VpnCoexistence,
+    },
     tunnel::FirewallPolicyError,
+    ErrorExt,
 };
 use widestring::WideCString;
 
@@ -37,6 +45,10 @@ pub enum Error {
     /// Failure to reset firewall policies
     #[error(display = "Failed to reset firewall policies")]
     ResettingPolicy(#[error(source)] FirewallPolicyError),
+
+    /// WinFw does not support allowing the given set of endpoints
+    #[error(display = "Unsupported allowed endpoint: {}", _0)]
+    UnsupportedAllowedEndpoint(AllowedEndpoint),
 }
 
 /// Timeout for acquiring the WFP transaction lock
@@ -50,7 +62,12 @@ pub struct Firewall(());
 impl Firewall {
     pub fn from_args(args: FirewallArguments) -> Result<Self, Error> {
         if let InitialFirewallState::Blocked(allowed_endpoint) = args.initial_state {
-            Self::initialize_blocked(allowed_endpoint, args.allow_lan)
+            Self::initialize_blocked(
+                allowed_endpoint,
+                args.allow_lan,
+                &args.vpn_coexistence,
+                &args.plugins,
+            )
         } else {
             Self::new()
         }
@@ -73,9 +90,13 @@ impl Firewall {
     fn initialize_blocked(
         allowed_endpoint: AllowedEndpoint,
         allow_lan: bool,
+        vpn_coexistence: &VpnCoexistence,
+        plugins: &PolicyFragment,
     ) -> Result<Self, Error> {
-        let cfg = &WinFwSettings::new(allow_lan);
-        let allowed_endpoint = WinFwAllowedEndpointContainer::from(allowed_endpoint);
+        let vpn_coexistence = WinFwVpnCoexistenceContainer::from(vpn_coexistence);
+        let plugin_rules = WinFwPluginRuleContainer::from(plugins);
+        let cfg = &WinFwSettings::new(allow_lan, &vpn_coexistence, &plugin_rules);
+        let allowed_endpoint = WinFwAllowedEndpointContainer::try_from(allowed_endpoint)?;
         unsafe {
             WinFw_InitializeBlocked(
                 WINFW_TIMEOUT_SECONDS,
@@ -90,7 +111,12 @@ impl Firewall {
         Ok(Firewall(()))
     }
 
-    pub fn apply_policy(&mut self, policy: FirewallPolicy) -> Result<(), Error> {
+    pub fn apply_policy(
+        &mut self,
+        policy: FirewallPolicy,
+        plugins: &PolicyFragment,
+    ) -> Result<(), Error> {
+        let plugin_rules = WinFwPluginRuleContainer::from(plugins);
         match policy {
             FirewallPolicy::Connecting {
                 peer_endpoint,
@@ -98,15 +124,17 @@ impl Firewall {
                 allow_lan,
                 allowed_endpoint,
                 allowed_tunnel_traffic,
+                vpn_coexistence,
                 relay_client,
             } => {
-                let cfg = &WinFwSettings::new(allow_lan);
+                let vpn_coexistence = WinFwVpnCoexistenceContainer::from(&vpn_coexistence);
+                let cfg = &WinFwSettings::new(allow_lan, &vpn_coexistence, &plugin_rules);
 
                 self.set_connecting_state(
                     &peer_endpoint,
                     &cfg,
                     &tunnel,
-                    &WinFwAllowedEndpointContainer::from(allowed_endpoint).as_endpoint(),
+                    &WinFwAllowedEndpointContainer::try_from(allowed_endpoint)?.as_endpoint(),
                     &allowed_tunnel_traffic,
                     &relay_client,
                 )
@@ -116,20 +144,33 @@ impl Firewall {
                 tunnel,
                 allow_lan,
                 dns_servers,
+                vpn_coexistence,
+                blocked_tunnel_protocols,
                 relay_client,
             } => {
-                let cfg = &WinFwSettings::new(allow_lan);
-                self.set_connected_state(&peer_endpoint, &cfg, &tunnel, &dns_servers, &relay_client)
+                let vpn_coexistence = WinFwVpnCoexistenceContainer::from(&vpn_coexistence);
+                let cfg = &WinFwSettings::new(allow_lan, &vpn_coexistence, &plugin_rules);
+                self.set_connected_state(
+                    &peer_endpoint,
+                    &cfg,
+                    &tunnel,
+                    &dns_servers,
+                    &blocked_tunnel_protocols,
+                    &relay_client,
+                )
             }
             FirewallPolicy::Blocked {
                 allow_lan,
                 allowed_endpoint,
+                recovery_allowlist,
+                vpn_coexistence,
             } => {
-                let cfg = &WinFwSettings::new(allow_lan);
-                self.set_blocked_state(
-                    &cfg,
-                    allowed_endpoint.map(|endpoint| WinFwAllowedEndpointContainer::from(endpoint)),
-                )
+                let vpn_coexistence = WinFwVpnCoexistenceContainer::from(&vpn_coexistence);
+                let cfg = &WinFwSettings::new(allow_lan, &vpn_coexistence, &plugin_rules);
+                let allowed_endpoint = allowed_endpoint
+                    .map(WinFwAllowedEndpointContainer::try_from)
+                    .transpose()?;
+                self.set_blocked_state(&cfg, allowed_endpoint, &recovery_allowlist)
             }
         }
     }
@@ -139,10 +180,25 @@ impl Firewall {
         Ok(())
     }
 
+    /// Returns the number of WFP filters currently added by WinFw. The filters are created by
+    /// WinFw itself, so they are counted by enumerating the filter engine.
+    pub fn rule_count(&self) -> Option<usize> {
+        match super::wfp_conflicts::count_own_filters() {
+            Ok(count) => Some(count),
+            Err(error) => {
+                log::debug!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to count WFP filters")
+                );
+                None
+            }
+        }
+    }
+
     fn set_connecting_state(
         &mut self,
         endpoint: &Endpoint,
-        winfw_settings: &WinFwSettings,
+        winfw_settings: &WinFwSettings<'_>,
         tunnel_metadata: &Option<TunnelMetadata>,
         allowed_endpoint: &WinFwAllowedEndpoint<'_>,
         allowed_tunnel_traffic: &AllowedTunnelTraffic,
@@ -179,12 +235,28 @@ impl Firewall {
             } else {
                 None
             };
+        let (addresses, port, protocol) = match allowed_tunnel_traffic {
+            AllowedTunnelTraffic::Dns(servers) => (&servers[..], 0, TransportProtocol::Udp),
+            AllowedTunnelTraffic::Endpoints {
+                addresses,
+                port,
+                protocol,
+            } => (&addresses[..], *port, *protocol),
+            _ => (&[][..], 0, TransportProtocol::Udp),
+        };
+        let addresses: Vec<WideCString> = addresses.iter().cloned().map(widestring_ip).collect();
+        let addresses: Vec<*const u16> = addresses.iter().map(|ip| ip.as_ptr()).collect();
+
         let allowed_tunnel_traffic = WinFwAllowedTunnelTraffic {
             type_: WinFwAllowedTunnelTrafficType::from(allowed_tunnel_traffic),
             endpoint: allowed_tunnel_endpoint
                 .as_ref()
                 .map(|ep| ep as *const _)
                 .unwrap_or(ptr::null()),
+            addresses: addresses.as_ptr(),
+            num_addresses: addresses.len(),
+            port,
+            protocol: WinFwProt::from(protocol),
         };
 
         unsafe {
@@ -204,9 +276,10 @@ impl Firewall {
     fn set_connected_state(
         &mut self,
         endpoint: &Endpoint,
-        winfw_settings: &WinFwSettings,
+        winfw_settings: &WinFwSettings<'_>,
         tunnel_metadata: &TunnelMetadata,
         dns_servers: &[IpAddr],
+        blocked_tunnel_protocols: &BlockedTunnelProtocols,
         relay_client: &Path,
     ) -> Result<(), Error> {
         log::trace!("Applying 'connected' firewall policy");
@@ -236,6 +309,8 @@ impl Firewall {
             dns_servers.iter().cloned().map(widestring_ip).collect();
         let dns_servers: Vec<*const u16> = dns_servers.iter().map(|ip| ip.as_ptr()).collect();
 
+        let blocked_tunnel_protocols = WinFwBlockedTunnelProtocols::from(blocked_tunnel_protocols);
+
         unsafe {
             WinFw_ApplyPolicyConnected(
                 winfw_settings,
@@ -246,6 +321,7 @@ impl Firewall {
                 v6_gateway_ptr,
                 dns_servers.as_ptr(),
                 dns_servers.len(),
+                &blocked_tunnel_protocols,
             )
             .into_result()
             .map_err(Error::ApplyingConnectedPolicy)
@@ -254,14 +330,28 @@ impl Firewall {
 
     fn set_blocked_state(
         &mut self,
-        winfw_settings: &WinFwSettings,
+        winfw_settings: &WinFwSettings<'_>,
         allowed_endpoint: Option<WinFwAllowedEndpointContainer>,
+        recovery_allowlist: &[IpNetwork],
     ) -> Result<(), Error> {
         log::trace!("Applying 'blocked' firewall policy");
         let endpoint = allowed_endpoint
             .as_ref()
             .map(WinFwAllowedEndpointContainer::as_endpoint);
 
+        let recovery_addresses: Vec<WideCString> = recovery_allowlist
+            .iter()
+            .map(|network| widestring_ip(network.network()))
+            .collect();
+        let recovery_networks: Vec<WinFwNetwork> = recovery_allowlist
+            .iter()
+            .zip(&recovery_addresses)
+            .map(|(network, address)| WinFwNetwork {
+                address: address.as_ptr(),
+                prefix: network.prefix(),
+            })
+            .collect();
+
         unsafe {
             WinFw_ApplyPolicyBlocked(
                 winfw_settings,
@@ -269,6 +359,8 @@ impl Firewall {
                     .as_ref()
                     .map(|container| container as *const _)
                     .unwrap_or(ptr::null()),
+                recovery_networks.as_ptr(),
+                recovery_networks.len(),
             )
             .into_result()
             .map_err(Error::ApplyingBlockedPolicy)
@@ -296,7 +388,10 @@ fn widestring_ip(ip: IpAddr) -> WideCString {
 
 #[allow(non_snake_case)]
 mod winfw {
-    use super::{widestring_ip, AllowedEndpoint, AllowedTunnelTraffic, Error, WideCString};
+    use super::{
+        widestring_ip, AllowedEndpoint, AllowedTunnelTraffic, BlockedTunnelProtocols, Error,
+        PluginAction, PolicyFragment, VpnCoexistence, WideCString,
+    };
     use crate::logging::windows::LogSink;
     use libc;
     use talpid_types::net::TransportProtocol;
@@ -304,13 +399,20 @@ mod winfw {
     pub struct WinFwAllowedEndpointContainer {
         _clients: Box<[WideCString]>,
         clients_ptrs: Box<[*const u16]>,
-        ip: WideCString,
-        port: u16,
-        protocol: WinFwProt,
+        _ips: Box<[WideCString]>,
+        endpoints: Box<[WinFwEndpointRange]>,
     }
 
-    impl From<AllowedEndpoint> for WinFwAllowedEndpointContainer {
-        fn from(endpoint: AllowedEndpoint) -> Self {
+    impl TryFrom<AllowedEndpoint> for WinFwAllowedEndpointContainer {
+        type Error = Error;
+
+        fn try_from(endpoint: AllowedEndpoint) -> Result<Self, Error> {
+            if endpoint.endpoints.is_empty()
+                || endpoint.endpoints.len() > MAX_ALLOWED_ENDPOINT_RANGES
+            {
+                return Err(Error::UnsupportedAllowedEndpoint(endpoint));
+            }
+
             let clients = endpoint
                 .clients
                 .iter()
@@ -320,15 +422,33 @@ mod winfw {
                 .iter()
                 .map(|client| client.as_ptr())
                 .collect::<Box<_>>();
-            let ip = widestring_ip(endpoint.endpoint.address.ip());
+            let ips = endpoint
+                .endpoints
+                .iter()
+                .map(|range| widestring_ip(range.address))
+                .collect::<Box<_>>();
+            let endpoints = endpoint
+                .endpoints
+                .iter()
+                .zip(ips.iter())
+                .map(|(range, ip)| WinFwEndpointRange {
+                    ip: ip.as_ptr(),
+                    port_start: range.ports.start(),
+                    port_end: range.ports.end(),
+                    match_protocol: range.protocol.is_some(),
+                    protocol: range
+                        .protocol
+                        .map(WinFwProt::from)
+                        .unwrap_or(WinFwProt::Tcp),
+                })
+                .collect::<Box<_>>();
 
-            WinFwAllowedEndpointContainer {
+            Ok(WinFwAllowedEndpointContainer {
                 _clients: clients,
                 clients_ptrs,
-                ip,
-                port: endpoint.endpoint.address.port(),
-                protocol: WinFwProt::from(endpoint.endpoint.protocol),
-            }
+                _ips: ips,
+                endpoints,
+            })
         }
     }
 
@@ -337,30 +457,53 @@ mod winfw {
             WinFwAllowedEndpoint {
                 num_clients: self.clients_ptrs.len() as u32,
                 clients: self.clients_ptrs.as_ptr(),
-                endpoint: WinFwEndpoint {
-                    ip: self.ip.as_ptr(),
-                    port: self.port,
-                    protocol: self.protocol,
-                },
+                num_endpoints: self.endpoints.len() as u32,
+                endpoints: self.endpoints.as_ptr(),
 
                 _phantom: std::marker::PhantomData,
             }
         }
     }
 
+    /// Must not exceed `MullvadGuids::NumPermitEndpointFilters` in winfw.
+    const MAX_ALLOWED_ENDPOINT_RANGES: usize = 16;
+
+    #[repr(C)]
+    pub struct WinFwEndpointRange {
+        ip: *const libc::wchar_t,
+        port_start: u16,
+        port_end: u16,
+        match_protocol: bool,
+        protocol: WinFwProt,
+    }
+
     #[repr(C)]
     pub struct WinFwAllowedEndpoint<'a> {
         num_clients: u32,
         clients: *const *const libc::wchar_t,
-        endpoint: WinFwEndpoint,
+        num_endpoints: u32,
+        endpoints: *const WinFwEndpointRange,
 
         _phantom: std::marker::PhantomData<&'a WinFwAllowedEndpointContainer>,
     }
 
+    #[repr(C)]
+    pub struct WinFwNetwork {
+        pub address: *const libc::wchar_t,
+        pub prefix: u8,
+    }
+
     #[repr(C)]
     pub struct WinFwAllowedTunnelTraffic {
         pub type_: WinFwAllowedTunnelTrafficType,
+        /// Only used by `Only`.
         pub endpoint: *const WinFwEndpoint,
+        /// Only used by `Dns` and `Endpoints`.
+        pub addresses: *const *const libc::wchar_t,
+        pub num_addresses: usize,
+        /// Only used by `Endpoints`.
+        pub port: u16,
+        pub protocol: WinFwProt,
     }
 
     #[repr(u8)]
@@ -369,6 +512,8 @@ mod winfw {
         None,
         All,
         Only,
+        Dns,
+        Endpoints,
     }
 
     impl From<&AllowedTunnelTraffic> for WinFwAllowedTunnelTrafficType {
@@ -377,6 +522,25 @@ mod winfw {
                 AllowedTunnelTraffic::None => WinFwAllowedTunnelTrafficType::None,
                 AllowedTunnelTraffic::All => WinFwAllowedTunnelTrafficType::All,
                 AllowedTunnelTraffic::Only(..) => WinFwAllowedTunnelTrafficType::Only,
+                AllowedTunnelTraffic::Dns(..) => WinFwAllowedTunnelTrafficType::Dns,
+                AllowedTunnelTraffic::Endpoints { .. } => WinFwAllowedTunnelTrafficType::Endpoints,
+            }
+        }
+    }
+
+    #[repr(C)]
+    pub struct WinFwBlockedTunnelProtocols {
+        pub ipv6: bool,
+        pub udp: bool,
+        pub smb: bool,
+    }
+
+    impl From<&BlockedTunnelProtocols> for WinFwBlockedTunnelProtocols {
+        fn from(blocked: &BlockedTunnelProtocols) -> Self {
+            WinFwBlockedTunnelProtocols {
+                ipv6: blocked.ipv6,
+                udp: blocked.udp,
+                smb: blocked.smb,
             }
         }
     }
@@ -404,17 +568,128 @@ mod winfw {
         }
     }
 
+    /// Owns the data that [`WinFwSettings`] points to for the traffic of another VPN.
+    #[derive(Default)]
+    pub struct WinFwVpnCoexistenceContainer {
+        _addresses: Box<[WideCString]>,
+        networks: Box<[WinFwNetwork]>,
+        udp_ports: Box<[u16]>,
+    }
+
+    impl From<&VpnCoexistence> for WinFwVpnCoexistenceContainer {
+        fn from(vpn_coexistence: &VpnCoexistence) -> Self {
+            let addresses = vpn_coexistence
+                .networks
+                .iter()
+                .map(|network| widestring_ip(network.network()))
+                .collect::<Box<_>>();
+            let networks = vpn_coexistence
+                .networks
+                .iter()
+                .zip(addresses.iter())
+                .map(|(network, address)| WinFwNetwork {
+                    address: address.as_ptr(),
+                    prefix: network.prefix(),
+                })
+                .collect::<Box<_>>();
+
+            WinFwVpnCoexistenceContainer {
+                _addresses: addresses,
+                networks,
+                udp_ports: vpn_coexistence.udp_ports.iter().cloned().collect(),
+            }
+        }
+    }
+
+    #[repr(u8)]
+    #[derive(Clone, Copy)]
+    pub enum WinFwPluginAction {
+        Permit = 0u8,
+        Block = 1u8,
+    }
+
+    #[repr(C)]
+    pub struct WinFwPluginRule {
+        pub action: WinFwPluginAction,
+        pub network: WinFwNetwork,
+        pub match_protocol: bool,
+        pub protocol: WinFwProt,
+        /// Zero matches any port.
+        pub port: u16,
+    }
+
+    /// Owns the data that [`WinFwSettings`] points to for the rules of policy plugins.
+    pub struct WinFwPluginRuleContainer {
+        _addresses: Box<[WideCString]>,
+        rules: Box<[WinFwPluginRule]>,
+    }
+
+    impl From<&PolicyFragment> for WinFwPluginRuleContainer {
+        fn from(plugins: &PolicyFragment) -> Self {
+            let plugin_rules: Vec<_> = plugins.block_rules().chain(plugins.allow_rules()).collect();
+            let addresses = plugin_rules
+                .iter()
+                .map(|rule| widestring_ip(rule.network.network()))
+                .collect::<Box<_>>();
+            let rules = plugin_rules
+                .iter()
+                .zip(addresses.iter())
+                .map(|(rule, address)| WinFwPluginRule {
+                    action: match rule.action {
+                        PluginAction::Allow => WinFwPluginAction::Permit,
+                        PluginAction::Block => WinFwPluginAction::Block,
+                    },
+                    network: WinFwNetwork {
+                        address: address.as_ptr(),
+                        prefix: rule.network.prefix(),
+                    },
+                    match_protocol: rule.protocol.is_some(),
+                    protocol: WinFwProt::from(rule.protocol.unwrap_or(TransportProtocol::Tcp)),
+                    port: rule.port.unwrap_or(0),
+                })
+                .collect::<Box<_>>();
+
+            WinFwPluginRuleContainer {
+                _addresses: addresses,
+                rules,
+            }
+        }
+    }
+
     #[repr(C)]
-    pub struct WinFwSettings {
+    pub struct WinFwSettings<'a> {
         permitDhcp: bool,
         permitLan: bool,
+        coexistenceNetworks: *const WinFwNetwork,
+        numCoexistenceNetworks: usize,
+        coexistenceUdpPorts: *const u16,
+        numCoexistenceUdpPorts: usize,
+        pluginRules: *const WinFwPluginRule,
+        numPluginRules: usize,
+
+        _phantom: std::marker::PhantomData<(
+            &'a WinFwVpnCoexistenceContainer,
+            &'a WinFwPluginRuleContainer,
+        )>,
     }
 
-    impl WinFwSettings {
-        pub fn new(permit_lan: bool) -> WinFwSettings {
+    impl<'a> WinFwSettings<'a> {
+        pub fn new(
+            permit_lan: bool,
+            vpn_coexistence: &'a WinFwVpnCoexistenceContainer,
+            plugin_rules: &'a WinFwPluginRuleContainer,
+        ) -> WinFwSettings<'a> {
             WinFwSettings {
                 permitDhcp: true,
                 permitLan: permit_lan,
+                coexistenceNetworks: vpn_coexistence.networks.as_ptr(),
+                numCoexistenceNetworks: vpn_coexistence.networks.len(),
+                coexistenceUdpPorts: vpn_coexistence.udp_ports.as_ptr(),
+                numCoexistenceUdpPorts: vpn_coexistence.udp_ports.len(),
+                pluginRules: plugin_rules.rules.as_ptr(),
+                numPluginRules: plugin_rules.rules.len(),
+
+                _phantom: std::marker::PhantomData,
             }
         }
     }
@@ -469,7 +744,7 @@ mod winfw {
         #[link_name = "WinFw_InitializeBlocked"]
         pub fn WinFw_InitializeBlocked(
             timeout: libc::c_uint,
-            settings: &WinFwSettings,
+            settings: &WinFwSettings<'_>,
             allowed_endpoint: *const WinFwAllowedEndpoint<'_>,
             sink: Option<LogSink>,
             sink_context: *const u8,
@@ -480,7 +755,7 @@ mod winfw {
 
         #[link_name = "WinFw_ApplyPolicyConnecting"]
         pub fn WinFw_ApplyPolicyConnecting(
-            settings: &WinFwSettings,
+            settings: &WinFwSettings<'_>,
             relay: &WinFwEndpoint,
             relayClient: *const libc::wchar_t,
             tunnelIfaceAlias: *const libc::wchar_t,
@@ -490,7 +765,7 @@ mod winfw {
 
         #[link_name = "WinFw_ApplyPolicyConnected"]
         pub fn WinFw_ApplyPolicyConnected(
-            settings: &WinFwSettings,
+            settings: &WinFwSettings<'_>,
             relay: &WinFwEndpoint,
             relayClient: *const libc::wchar_t,
             tunnelIfaceAlias: *const libc::wchar_t,
@@ -498,15 +773,154 @@ mod winfw {
             v6Gateway: *const libc::wchar_t,
             dnsServers: *const *const libc::wchar_t,
             numDnsServers: usize,
+            blockedProtocols: &WinFwBlockedTunnelProtocols,
         ) -> WinFwPolicyStatus;
 
         #[link_name = "WinFw_ApplyPolicyBlocked"]
         pub fn WinFw_ApplyPolicyBlocked(
-            settings: &WinFwSettings,
+            settings: &WinFwSettings<'_>,
             allowed_endpoint: *const WinFwAllowedEndpoint<'_>,
+            recovery_networks: *const WinFwNetwork,
+            num_recovery_networks: usize,
         ) -> WinFwPolicyStatus;
 
         #[link_name = "WinFw_Reset"]
         pub fn WinFw_Reset() -> WinFwPolicyStatus;
     }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+        use std::path::PathBuf;
+        use talpid_types::net::{Endpoint, EndpointRange, PortRange};
+        use widestring::WideCStr;
+
+        fn allowed_endpoint(endpoints: Vec<EndpointRange>) -> AllowedEndpoint {
+            AllowedEndpoint {
+                clients: vec![PathBuf::from(r"C:\mullvad-daemon.exe")],
+                endpoints,
+                hostname: None,
+            }
+        }
+
+        fn ranges(
+            container: &WinFwAllowedEndpointContainer,
+        ) -> Vec<(String, u16, u16, Option<u8>)> {
+            let endpoint = container.as_endpoint();
+            let ranges = unsafe {
+                std::slice::from_raw_parts(endpoint.endpoints, endpoint.num_endpoints as usize)
+            };
+            ranges
+                .iter()
+                .map(|range| {
+                    let ip = unsafe { WideCStr::from_ptr_str(range.ip) }.to_string_lossy();
+                    let protocol = if range.match_protocol {
+                        Some(range.protocol as u8)
+                    } else {
+                        None
+                    };
+                    (ip, range.port_start, range.port_end, protocol)
+                })
+                .collect()
+        }
+
+        #[test]
+        fn test_single_endpoint() {
+            let endpoint = Endpoint::new([192, 0, 2, 1], 443, TransportProtocol::Tcp);
+            let container =
+                WinFwAllowedEndpointContainer::try_from(allowed_endpoint(vec![endpoint.into()]))
+                    .unwrap();
+
+            assert_eq!(container.as_endpoint().num_clients, 1);
+            assert_eq!(
+                ranges(&container),
+                vec![("192.0.2.1".to_owned(), 443, 443, Some(WinFwProt::Tcp as u8))]
+            );
+        }
+
+        #[test]
+        fn test_endpoint_set() {
+            let endpoints = vec![
+                EndpointRange {
+                    address: "192.0.2.1".parse().unwrap(),
+                    ports: PortRange::new(1000, 2000).unwrap(),
+                    protocol: None,
+                },
+                EndpointRange {
+                    address: "2001:db8::1".parse().unwrap(),
+                    ports: PortRange::single(443),
+                    protocol: Some(TransportProtocol::Udp),
+                },
+            ];
+            let container =
+                WinFwAllowedEndpointContainer::try_from(allowed_endpoint(endpoints)).unwrap();
+
+            assert_eq!(
+                ranges(&container),
+                vec![
+                    ("192.0.2.1".to_owned(), 1000, 2000, None),
+                    (
+                        "2001:db8::1".to_owned(),
+                        443,
+                        443,
+                        Some(WinFwProt::Udp as u8)
+                    ),
+                ]
+            );
+        }
+
+        #[test]
+        fn test_vpn_coexistence() {
+            let vpn_coexistence = VpnCoexistence {
+                networks: vec![
+                    "10.8.0.0/24".parse().unwrap(),
+                    "fd00:1234::/64".parse().unwrap(),
+                ],
+                udp_ports: vec![51820],
+            };
+            let container = WinFwVpnCoexistenceContainer::from(&vpn_coexistence);
+
+            let networks = container
+                .networks
+                .iter()
+                .map(|network| {
+                    let ip = unsafe { WideCStr::from_ptr_str(network.address) }.to_string_lossy();
+                    (ip, network.prefix)
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                networks,
+                vec![("10.8.0.0".to_owned(), 24), ("fd00:1234::".to_owned(), 64)]
+            );
+            assert_eq!(&*container.udp_ports, &[51820]);
+        }
+
+        #[test]
+        fn test_blocked_tunnel_protocols() {
+            let blocked = WinFwBlockedTunnelProtocols::from(&BlockedTunnelProtocols {
+                ipv6: false,
+                udp: true,
+                smb: true,
+            });
+            assert!(!blocked.ipv6);
+            assert!(blocked.udp);
+            assert!(blocked.smb);
+        }
+
+        #[test]
+        fn test_unsupported_endpoint_sets() {
+            assert!(matches!(
+                WinFwAllowedEndpointContainer::try_from(allowed_endpoint(vec![])),
+                Err(Error::UnsupportedAllowedEndpoint(_))
+            ));
+
+            let too_many = (0..=MAX_ALLOWED_ENDPOINT_RANGES as u16)
+                .map(|port| Endpoint::new([192, 0, 2, 1], port, TransportProtocol::Tcp).into())
+                .collect();
+            assert!(matches!(
+                WinFwAllowedEndpointContainer::try_from(allowed_endpoint(too_many)),
+                Err(Error::UnsupportedAllowedEndpoint(_))
+            ));
+        }
+    }
 }
@@ -46,12 +46,25 @@ pub mod firewall;
 /// Abstractions over operating system DNS settings.
 pub mod dns;
 
+/// Capture of packets on the tunnel interface, for debugging.
+#[cfg(all(feature = "packet-capture", target_os = "linux"))]
+pub mod packet_capture;
+
+/// Journal of changes to the system configuration, used to clean up after a crash.
+pub mod restore_journal;
+
 /// State machine to handle tunnel configuration.
 pub mod tunnel_state_machine;
 
+/// Evaluation of whether traffic would be tunneled, blocked or bypass the tunnel.
+pub mod traffic_query;
+
 /// Future utilities
 pub mod future_retry;
 
+/// Configuration that can be changed without restarting the daemon.
+pub mod runtime_config;
+
 #[cfg(not(target_os = "android"))]
 /// Internal code for managing bundled proxy software.
 mod proxy;
@@ -63,6 +76,14 @@ mod mktemp;
 #[cfg(target_os = "linux")]
 mod linux;
 
+/// Running the tunnel inside a packet tunnel provider of a macOS Network Extension.
+#[cfg(target_os = "macos")]
+pub mod network_extension;
+
+/// Detection of and adaptations for running inside a container.
+#[cfg(target_os = "linux")]
+pub mod container;
+
 /// A pair of functions to monitor and establish connectivity with ICMP
 pub mod ping_monitor;
 
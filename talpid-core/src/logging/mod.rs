@@ -1,4 +1,9 @@
-use std::{fs, io, path::Path};
+use std::{
+    fs, io,
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use talpid_types::tunnel::ConnectionAttemptId;
 
 /// Types/implementations for logging through a callback.
 #[cfg(windows)]
@@ -27,3 +32,72 @@ pub fn rotate_log(file: &Path) -> Result<(), RotateLogError> {
 
     fs::File::create(file).map(|_| ()).map_err(RotateLogError)
 }
+
+/// Modules whose log lines belong to the current connection attempt, including their submodules.
+pub const CONNECTION_ATTEMPT_SUBSYSTEMS: &[&str] = &[
+    "talpid_core::dns",
+    "talpid_core::firewall",
+    "talpid_core::resolver",
+    "talpid_core::routing",
+    "talpid_core::split_tunnel",
+    "talpid_core::tunnel",
+    "talpid_core::tunnel_state_machine",
+];
+
+/// The current connection attempt, stored with [`ATTEMPT_SET_FLAG`] set, or 0 if there is none.
+static CURRENT_CONNECTION_ATTEMPT: AtomicU64 = AtomicU64::new(0);
+const ATTEMPT_SET_FLAG: u64 = 1 << 32;
+
+/// Returns the connection attempt that is in progress, if any.
+pub fn current_connection_attempt() -> Option<ConnectionAttemptId> {
+    let value = CURRENT_CONNECTION_ATTEMPT.load(Ordering::Relaxed);
+    if value & ATTEMPT_SET_FLAG == 0 {
+        return None;
+    }
+    Some(ConnectionAttemptId::from_u32(value as u32))
+}
+
+/// Returns the connection attempt that a log line from `target` belongs to, if any.
+pub fn connection_attempt_for(target: &str) -> Option<ConnectionAttemptId> {
+    let is_attempt_subsystem = CONNECTION_ATTEMPT_SUBSYSTEMS.iter().any(|subsystem| {
+        target == *subsystem
+            || (target.starts_with(subsystem) && target[subsystem.len()..].starts_with("::"))
+    });
+    if is_attempt_subsystem {
+        current_connection_attempt()
+    } else {
+        None
+    }
+}
+
+pub(crate) fn set_current_connection_attempt(attempt: Option<ConnectionAttemptId>) {
+    let value = attempt
+        .map(|attempt| u64::from(attempt.as_u32()) | ATTEMPT_SET_FLAG)
+        .unwrap_or(0);
+    CURRENT_CONNECTION_ATTEMPT.store(value, Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_connection_attempt_for() {
+        let attempt = ConnectionAttemptId::from_u32(0);
+        set_current_connection_attempt(Some(attempt));
+        assert_eq!(current_connection_attempt(), Some(attempt));
+        assert_eq!(
+            connection_attempt_for("talpid_core::firewall::linux"),
+            Some(attempt)
+        );
+        assert_eq!(
+            connection_attempt_for("talpid_core::tunnel_state_machine"),
+            Some(attempt)
+        );
+        assert_eq!(connection_attempt_for("talpid_core::tunnels"), None);
+        assert_eq!(connection_attempt_for("mullvad_api::rest"), None);
+
+        set_current_connection_attempt(None);
+        assert_eq!(connection_attempt_for("talpid_core::firewall"), None);
+    }
+}
@@ -14,7 +14,15 @@ impl TempFile {
     /// Create a new unique `TempFile`. The file will not exist after this.
     pub fn new() -> Self {
         TempFile {
-            path: generate_path(),
+            path: generate_path(&env::temp_dir()),
+        }
+    }
+
+    /// Create a new unique `TempFile` for storing secrets. On Linux, it is placed in a tmpfs if
+    /// one is available, so that the contents never reach persistent storage.
+    pub fn new_secret() -> Self {
+        TempFile {
+            path: generate_path(&secret_dir()),
         }
     }
 
@@ -43,6 +51,21 @@ impl Drop for TempFile {
     }
 }
 
-fn generate_path() -> PathBuf {
-    env::temp_dir().join(Uuid::new_v4().to_string())
+fn generate_path(dir: &Path) -> PathBuf {
+    dir.join(Uuid::new_v4().to_string())
+}
+
+#[cfg(target_os = "linux")]
+fn secret_dir() -> PathBuf {
+    let shm_dir = Path::new("/dev/shm");
+    if shm_dir.is_dir() {
+        shm_dir.to_path_buf()
+    } else {
+        env::temp_dir()
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn secret_dir() -> PathBuf {
+    env::temp_dir()
 }
@@ -0,0 +1,267 @@
+//! Running the tunnel inside a packet tunnel provider of a macOS Network Extension.
+//!
+//! A Network Extension cannot create utun devices, change the routing table or load pf rules.
+//! Instead, the packet tunnel provider applies a set of network settings, and the system creates
+//! the tunnel device, the routes and the DNS configuration from them. While the provider is
+//! running, the system can also block all traffic that does not go through the tunnel.
+//! [`PacketTunnelSettings`] expresses the firewall, DNS and routing behavior of the daemon in
+//! those terms, so that the same policy applies whether the tunnel runs in the daemon or in an
+//! extension. The data path, i.e. wireguard-go and the connectivity monitor, is the same in both.
+//!
+//! Only WireGuard tunnels are supported, since OpenVPN requires a separate process.
+
+use crate::{
+    firewall::{ALLOWED_LAN_MULTICAST_NETS, ALLOWED_LAN_NETS},
+    routing::{self, RouteManager},
+    tunnel::{
+        self,
+        tun_provider::{TunConfig, TunProvider},
+        TunnelArgs, TunnelEvent, TunnelMonitor,
+    },
+};
+use futures::{channel::oneshot, future::BoxFuture};
+use ipnetwork::IpNetwork;
+use serde::Serialize;
+use std::{
+    net::IpAddr,
+    os::unix::io::RawFd,
+    path::PathBuf,
+    sync::{mpsc as sync_mpsc, Arc, Mutex},
+    thread,
+};
+use talpid_types::{net::TunnelParameters, BoxedError, ErrorExt};
+
+/// Errors that can occur while running a tunnel in a packet tunnel provider.
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    /// Only WireGuard tunnels can run in a packet tunnel provider.
+    #[error(display = "Only WireGuard tunnels are supported in a Network Extension")]
+    UnsupportedTunnelType,
+
+    /// Failed to create the async runtime.
+    #[error(display = "Failed to create the async runtime")]
+    CreateRuntime(#[error(source)] std::io::Error),
+
+    /// Failed to obtain a handle to the route manager.
+    #[error(display = "Failed to obtain a route manager handle")]
+    RouteManager(#[error(source)] routing::Error),
+
+    /// Failed to start the tunnel.
+    #[error(display = "Failed to start the tunnel")]
+    StartTunnel(#[error(source)] tunnel::Error),
+
+    /// The tunnel thread stopped before the tunnel was started.
+    #[error(display = "The tunnel thread stopped unexpectedly")]
+    TunnelThreadStopped,
+}
+
+/// The packet tunnel provider that the tunnel runs in.
+pub trait PacketTunnelHost: Send + Sync {
+    /// Applies `settings` to the tunnel and returns the file descriptor of the utun device that
+    /// the system created for it. The file descriptor remains owned by the provider.
+    fn apply_settings(&self, settings: &PacketTunnelSettings) -> Result<RawFd, BoxedError>;
+
+    /// Called when the state of the tunnel changes.
+    fn on_tunnel_event(&self, event: &TunnelEvent);
+}
+
+/// Settings of the tunnel state machine that the packet tunnel provider should enforce.
+#[derive(Debug, Clone, Default)]
+pub struct PacketTunnelPolicy {
+    /// Allow traffic to and from local networks outside the tunnel.
+    pub allow_lan: bool,
+    /// DNS servers to use. If `None`, the tunnel gateway is used.
+    pub dns_servers: Option<Vec<IpAddr>>,
+}
+
+/// Network settings for a packet tunnel provider. These map directly onto
+/// `NEPacketTunnelNetworkSettings` and the protocol configuration of the tunnel.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PacketTunnelSettings {
+    /// Addresses of the tunnel interface.
+    pub addresses: Vec<IpAddr>,
+    /// DNS servers that all queries are sent to.
+    pub dns_servers: Vec<IpAddr>,
+    /// Networks that are routed through the tunnel.
+    pub included_routes: Vec<IpNetwork>,
+    /// Networks that are excluded from the tunnel.
+    pub excluded_routes: Vec<IpNetwork>,
+    /// Maximum transmission unit of the tunnel interface.
+    pub mtu: u16,
+    /// Block all traffic outside the tunnel while the provider is running. This corresponds to
+    /// the firewall rules that the daemon applies.
+    pub include_all_networks: bool,
+    /// Exempt local networks from `include_all_networks`.
+    pub exclude_local_networks: bool,
+}
+
+impl PacketTunnelSettings {
+    /// Creates the settings for a tunnel device configured with `config`, enforcing `policy`.
+    pub(crate) fn new(config: &TunConfig, policy: &PacketTunnelPolicy) -> Self {
+        let excluded_routes = if policy.allow_lan {
+            ALLOWED_LAN_NETS
+                .iter()
+                .chain(ALLOWED_LAN_MULTICAST_NETS.iter())
+                .cloned()
+                .collect()
+        } else {
+            vec![]
+        };
+        PacketTunnelSettings {
+            addresses: config.addresses.clone(),
+            dns_servers: policy
+                .dns_servers
+                .clone()
+                .unwrap_or_else(|| config.dns_servers.clone()),
+            included_routes: config.routes.clone(),
+            excluded_routes,
+            mtu: config.mtu,
+            include_all_networks: true,
+            exclude_local_networks: policy.allow_lan,
+        }
+    }
+}
+
+/// A tunnel running in a packet tunnel provider. The tunnel is closed when this is dropped.
+pub struct PacketTunnel {
+    close_tx: Option<oneshot::Sender<()>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl PacketTunnel {
+    /// Starts a tunnel using `parameters`, obtaining its tunnel device from `host`. Returns once
+    /// the tunnel device has been set up.
+    pub fn start(
+        mut parameters: TunnelParameters,
+        resource_dir: PathBuf,
+        log_dir: Option<PathBuf>,
+        policy: PacketTunnelPolicy,
+        host: Arc<dyn PacketTunnelHost>,
+    ) -> Result<Self, Error> {
+        if !matches!(parameters, TunnelParameters::Wireguard(_)) {
+            return Err(Error::UnsupportedTunnelType);
+        }
+
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .map_err(Error::CreateRuntime)?;
+        let (close_tx, close_rx) = oneshot::channel();
+        let (start_tx, start_rx) = sync_mpsc::channel();
+
+        let thread = thread::spawn(move || {
+            let route_manager = runtime.block_on(async { RouteManager::new_detached() });
+            let route_manager_handle = match route_manager.handle() {
+                Ok(handle) => handle,
+                Err(error) => {
+                    let _ = start_tx.send(Err(Error::RouteManager(error)));
+                    return;
+                }
+            };
+
+            let event_host = host.clone();
+            let on_event = move |event: TunnelEvent| -> BoxFuture<'static, ()> {
+                event_host.on_tunnel_event(&event);
+                Box::pin(async {})
+            };
+            let args = TunnelArgs {
+                runtime: runtime.handle().clone(),
+                resource_dir: &resource_dir,
+                on_event,
+                tunnel_close_rx: close_rx,
+                tun_provider: Arc::new(Mutex::new(TunProvider::with_packet_tunnel_host(
+                    host, policy,
+                ))),
+                retry_attempt: 0,
+                route_manager: route_manager_handle,
+                wireguard_tunnel_provider: None,
+                traffic_shaping: Default::default(),
+                forced_interface: None,
+            };
+
+            match TunnelMonitor::start(&mut parameters, &log_dir, args) {
+                Ok(monitor) => {
+                    let _ = start_tx.send(Ok(()));
+                    if let Err(error) = monitor.wait() {
+                        log::error!("{}", error.display_chain_with_msg("Tunnel exited"));
+                    }
+                }
+                Err(error) => {
+                    let _ = start_tx.send(Err(Error::StartTunnel(error)));
+                }
+            }
+        });
+
+        match start_rx.recv() {
+            Ok(Ok(())) => Ok(PacketTunnel {
+                close_tx: Some(close_tx),
+                thread: Some(thread),
+            }),
+            Ok(Err(error)) => {
+                let _ = thread.join();
+                Err(error)
+            }
+            Err(_) => {
+                let _ = thread.join();
+                Err(Error::TunnelThreadStopped)
+            }
+        }
+    }
+
+    /// Closes the tunnel and waits for it to exit.
+    pub fn stop(mut self) {
+        self.stop_inner();
+    }
+
+    fn stop_inner(&mut self) {
+        if let Some(close_tx) = self.close_tx.take() {
+            let _ = close_tx.send(());
+        }
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::error!("The tunnel thread panicked");
+            }
+        }
+    }
+}
+
+impl Drop for PacketTunnel {
+    fn drop(&mut self) {
+        self.stop_inner();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tun_config() -> TunConfig {
+        TunConfig {
+            addresses: vec!["10.64.0.2".parse().unwrap()],
+            dns_servers: vec!["10.64.0.1".parse().unwrap()],
+            routes: vec!["0.0.0.0/0".parse().unwrap(), "::/0".parse().unwrap()],
+            mtu: 1380,
+        }
+    }
+
+    #[test]
+    fn test_settings_follow_policy() {
+        let settings = PacketTunnelSettings::new(&tun_config(), &PacketTunnelPolicy::default());
+        assert!(settings.include_all_networks);
+        assert!(!settings.exclude_local_networks);
+        assert!(settings.excluded_routes.is_empty());
+        assert_eq!(settings.dns_servers, tun_config().dns_servers);
+
+        let policy = PacketTunnelPolicy {
+            allow_lan: true,
+            dns_servers: Some(vec!["192.168.1.1".parse().unwrap()]),
+        };
+        let settings = PacketTunnelSettings::new(&tun_config(), &policy);
+        assert!(settings.exclude_local_networks);
+        assert!(settings
+            .excluded_routes
+            .contains(&"192.168.0.0/16".parse().unwrap()));
+        assert_eq!(settings.dns_servers, policy.dns_servers.unwrap());
+    }
+}
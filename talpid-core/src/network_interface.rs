@@ -22,6 +22,10 @@ pub enum Error {
     #[error(display = "Unable to open a tunnel device")]
     CreateDeviceError(#[error(source)] tun::Error),
 
+    /// The tunnel device is in use by another program
+    #[error(display = "The tunnel device is in use by another program")]
+    DeviceBusy,
+
     /// Failed to apply async flags to tunnel device
     #[error(display = "Failed to apply async flags to tunnel device")]
     SetDeviceAsyncError(#[error(source)] nix::Error),
@@ -70,7 +74,12 @@ impl TunnelDevice {
         config.platform(|config| {
             config.packet_information(true);
         });
-        let mut dev = platform::create(&config).map_err(Error::CreateDeviceError)?;
+        let mut dev = platform::create(&config).map_err(|error| match error {
+            tun::Error::Io(ref io_error) if io_error.raw_os_error() == Some(libc::EBUSY) => {
+                Error::DeviceBusy
+            }
+            error => Error::CreateDeviceError(error),
+        })?;
         apply_async_flags(dev.as_raw_fd()).map_err(Error::SetDeviceAsyncError)?;
         Ok(Self { dev })
     }
@@ -113,6 +113,10 @@ impl MonitorHandle {
         }
     }
 
+    /// Connectivity changes are pushed by the system, so there is nothing to throttle in
+    /// low-power mode.
+    pub fn set_low_power(&self, _low_power: bool) {}
+
     fn get_is_connected(&self) -> Result<bool, Error> {
         let result = self.call_method(
             "isConnected",
@@ -1,10 +1,17 @@
-use crate::routing::{self, RouteManagerHandle};
-use futures::{channel::mpsc::UnboundedSender, StreamExt};
+use crate::routing::{self, CallbackMessage, RouteManagerHandle};
+use futures::{
+    channel::mpsc::{self, UnboundedSender},
+    Stream, StreamExt,
+};
 use std::{
+    fs,
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
-    sync::Arc,
+    pin::Pin,
+    sync::{Arc, Weak},
+    time::Duration,
 };
 use talpid_types::ErrorExt;
+use tokio::time::Sleep;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -17,9 +24,18 @@ pub enum Error {
 
 pub struct MonitorHandle {
     route_manager: RouteManagerHandle,
+    low_power_tx: UnboundedSender<bool>,
     _notify_tx: Arc<UnboundedSender<bool>>,
 }
 
+/// Time to wait for a default route to return while a physical link is up, before the host is
+/// considered to be offline. DHCP clients may remove an address, and with it the default route,
+/// while renewing a lease, and add it again shortly after.
+const ROUTE_LOSS_GRACE_PERIOD: Duration = Duration::from_secs(10);
+const SYSFS_NET_PATH: &str = "/sys/class/net";
+/// Minimum time between checks of the offline state in low-power mode.
+const LOW_POWER_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
 const PUBLIC_INTERNET_ADDRESS_V4: IpAddr = IpAddr::V4(Ipv4Addr::new(193, 138, 218, 78));
 const PUBLIC_INTERNET_ADDRESS_V6: IpAddr =
     IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0x1, 0x2, 0x3, 0x4, 0x5, 0x6));
@@ -37,50 +53,176 @@ impl MonitorHandle {
             }
         }
     }
+
+    /// Enables or disables low-power mode. In low-power mode, route changes are not checked as
+    /// they happen, but at most once every 30 seconds.
+    pub fn set_low_power(&self, low_power: bool) {
+        let _ = self.low_power_tx.unbounded_send(low_power);
+    }
 }
 
 pub async fn spawn_monitor(
     notify_tx: UnboundedSender<bool>,
     route_manager: RouteManagerHandle,
 ) -> Result<MonitorHandle> {
-    let mut is_offline = public_ip_unreachable(&route_manager).await?;
+    let is_offline = public_ip_unreachable(&route_manager).await?;
 
-    let mut listener = route_manager
+    let listener = route_manager
         .change_listener()
         .await
         .map_err(Error::RouteManagerError)?;
 
     let notify_tx = Arc::new(notify_tx);
-    let sender = Arc::downgrade(&notify_tx);
-    let monitor_handle = MonitorHandle {
+    let (low_power_tx, low_power_rx) = mpsc::unbounded();
+    let monitor = OfflineMonitor {
         route_manager: route_manager.clone(),
-        _notify_tx: notify_tx,
+        sender: Arc::downgrade(&notify_tx),
+        is_offline,
+        grace_period: None,
+        low_power: false,
+        deferred_check: None,
     };
+    tokio::spawn(monitor.run(listener, low_power_rx));
+
+    Ok(MonitorHandle {
+        route_manager,
+        low_power_tx,
+        _notify_tx: notify_tx,
+    })
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Wakeup {
+    Change,
+    LowPower(bool),
+    GracePeriodExpired,
+    DeferredCheck,
+}
 
-    tokio::spawn(async move {
-        while let Some(_event) = listener.next().await {
-            match sender.upgrade() {
-                Some(sender) => {
-                    let new_offline_state = public_ip_unreachable(&route_manager)
-                        .await
-                        .unwrap_or_else(|err| {
-                            log::error!(
-                                "{}",
-                                err.display_chain_with_msg("Failed to infer offline state")
-                            );
-                            false
-                        });
-                    if new_offline_state != is_offline {
-                        is_offline = new_offline_state;
-                        let _ = sender.unbounded_send(is_offline);
+struct OfflineMonitor {
+    route_manager: RouteManagerHandle,
+    sender: Weak<UnboundedSender<bool>>,
+    is_offline: bool,
+    /// Set while the default route is missing but a physical link is up.
+    grace_period: Option<Pin<Box<Sleep>>>,
+    low_power: bool,
+    /// Set in low-power mode while a check of the offline state is pending.
+    deferred_check: Option<Pin<Box<Sleep>>>,
+}
+
+impl OfflineMonitor {
+    async fn run(
+        mut self,
+        mut listener: impl Stream<Item = CallbackMessage> + Unpin,
+        mut low_power_rx: mpsc::UnboundedReceiver<bool>,
+    ) {
+        loop {
+            let wakeup = {
+                let grace_period = wait_for(&mut self.grace_period);
+                let deferred_check = wait_for(&mut self.deferred_check);
+                tokio::select! {
+                    event = listener.next() => match event {
+                        Some(_) => Wakeup::Change,
+                        None => return,
+                    },
+                    Some(low_power) = low_power_rx.next() => Wakeup::LowPower(low_power),
+                    _ = grace_period => Wakeup::GracePeriodExpired,
+                    _ = deferred_check => Wakeup::DeferredCheck,
+                }
+            };
+
+            match wakeup {
+                Wakeup::Change if self.low_power => {
+                    if self.deferred_check.is_none() {
+                        self.deferred_check =
+                            Some(Box::pin(tokio::time::sleep(LOW_POWER_CHECK_INTERVAL)));
                     }
+                    continue;
                 }
-                None => return,
+                Wakeup::LowPower(low_power) => {
+                    self.low_power = low_power;
+                    // Changes that were deferred are checked right away when leaving low-power
+                    // mode
+                    if low_power || self.deferred_check.take().is_none() {
+                        continue;
+                    }
+                }
+                Wakeup::DeferredCheck => self.deferred_check = None,
+                Wakeup::Change | Wakeup::GracePeriodExpired => (),
+            }
+
+            if self.sender.upgrade().is_none() {
+                return;
             }
+            self.check(wakeup == Wakeup::GracePeriodExpired).await;
         }
-    });
+    }
+
+    async fn check(&mut self, grace_period_expired: bool) {
+        let route_unreachable = public_ip_unreachable(&self.route_manager)
+            .await
+            .unwrap_or_else(|err| {
+                log::error!(
+                    "{}",
+                    err.display_chain_with_msg("Failed to infer offline state")
+                );
+                false
+            });
+        let new_offline_state = if !route_unreachable {
+            self.grace_period = None;
+            false
+        } else if grace_period_expired || !physical_link_up() {
+            self.grace_period = None;
+            true
+        } else {
+            if !self.is_offline && self.grace_period.is_none() {
+                log::debug!(
+                    "No default route, but a physical link is up. Waiting {}s before assuming \
+                     that the host is offline",
+                    ROUTE_LOSS_GRACE_PERIOD.as_secs()
+                );
+                self.grace_period = Some(Box::pin(tokio::time::sleep(ROUTE_LOSS_GRACE_PERIOD)));
+            }
+            self.is_offline
+        };
+
+        if new_offline_state != self.is_offline {
+            self.is_offline = new_offline_state;
+            if let Some(sender) = self.sender.upgrade() {
+                let _ = sender.unbounded_send(new_offline_state);
+            }
+        }
+    }
+}
 
-    Ok(monitor_handle)
+/// Completes when `timer` expires. Never completes if there is no timer.
+async fn wait_for(timer: &mut Option<Pin<Box<Sleep>>>) {
+    match timer {
+        Some(timer) => timer.as_mut().await,
+        None => futures::future::pending().await,
+    }
+}
+
+/// Returns whether any network interface that is backed by a device has carrier.
+fn physical_link_up() -> bool {
+    let interfaces = match fs::read_dir(SYSFS_NET_PATH) {
+        Ok(interfaces) => interfaces,
+        Err(error) => {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to list network interfaces")
+            );
+            return false;
+        }
+    };
+    interfaces.filter_map(|entry| entry.ok()).any(|entry| {
+        let path = entry.path();
+        // Reading the carrier of an interface that is down fails
+        path.join("device").exists()
+            && fs::read_to_string(path.join("carrier"))
+                .map(|carrier| carrier.trim() == "1")
+                .unwrap_or(false)
+    })
 }
 
 async fn public_ip_unreachable(handle: &RouteManagerHandle) -> Result<bool> {
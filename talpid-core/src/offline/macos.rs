@@ -20,9 +20,20 @@
 //!
 //! [`SCNetworkReachability`]: https://developer.apple.com/documentation/systemconfiguration/scnetworkreachability-g7d
 //! [`NWPathMonitor`]: https://developer.apple.com/documentation/network/nwpathmonitor
-use futures::{channel::mpsc::UnboundedSender, Future, StreamExt};
-use std::sync::{Arc, Weak};
+use futures::{
+    channel::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    Future, StreamExt,
+};
+use std::{
+    pin::Pin,
+    sync::{Arc, Weak},
+    time::Duration,
+};
 use talpid_types::ErrorExt;
+use tokio::time::Sleep;
+
+/// Minimum time between checks of the offline state in low-power mode.
+const LOW_POWER_CHECK_INTERVAL: Duration = Duration::from_secs(30);
 
 #[derive(err_derive::Error, Debug)]
 pub enum Error {
@@ -31,6 +42,7 @@ pub enum Error {
 }
 
 pub struct MonitorHandle {
+    low_power_tx: UnboundedSender<bool>,
     _notify_tx: Arc<UnboundedSender<bool>>,
 }
 
@@ -40,6 +52,12 @@ impl MonitorHandle {
     pub async fn host_is_offline(&self) -> bool {
         !exists_non_tunnel_default_route().await
     }
+
+    /// Enables or disables low-power mode. In low-power mode, the default routes are not checked
+    /// on every route change, but at most once every 30 seconds.
+    pub fn set_low_power(&self, low_power: bool) {
+        let _ = self.low_power_tx.unbounded_send(low_power);
+    }
 }
 
 async fn exists_non_tunnel_default_route() -> bool {
@@ -75,21 +93,59 @@ pub async fn spawn_monitor(notify_tx: UnboundedSender<bool>) -> Result<MonitorHa
         is_offline: !exists_non_tunnel_default_route().await,
     };
 
-    let route_monitor = watch_route_monitor(context)?;
+    let (low_power_tx, low_power_rx) = mpsc::unbounded();
+    let route_monitor = watch_route_monitor(context, low_power_rx)?;
     tokio::spawn(route_monitor);
     Ok(MonitorHandle {
+        low_power_tx,
         _notify_tx: notify_tx,
     })
 }
 
 fn watch_route_monitor(
     mut context: OfflineStateContext,
+    mut low_power_rx: UnboundedReceiver<bool>,
 ) -> Result<impl Future<Output = ()>, Error> {
     let mut monitor = crate::routing::listen_for_default_route_changes()?;
 
     Ok(async move {
-        while let Some(_route_change) = monitor.next().await {
-            context.new_state(!exists_non_tunnel_default_route().await);
+        let mut low_power = false;
+        // Set in low-power mode while a check of the default routes is pending
+        let mut deferred_check: Option<Pin<Box<Sleep>>> = None;
+        loop {
+            // Whether to check the default routes now, or later if in low-power mode
+            let check = {
+                let deferred = async {
+                    match deferred_check.as_mut() {
+                        Some(deferred) => deferred.as_mut().await,
+                        None => futures::future::pending().await,
+                    }
+                };
+                tokio::select! {
+                    route_change = monitor.next() => match route_change {
+                        Some(_) => Some(!low_power),
+                        None => break,
+                    },
+                    Some(new_low_power) = low_power_rx.next() => {
+                        low_power = new_low_power;
+                        // Changes that were deferred are checked right away when leaving
+                        // low-power mode
+                        if low_power { None } else { Some(true) }
+                    }
+                    _ = deferred => Some(true),
+                }
+            };
+
+            match check {
+                Some(true) => {
+                    deferred_check = None;
+                    context.new_state(!exists_non_tunnel_default_route().await);
+                }
+                Some(false) if deferred_check.is_none() => {
+                    deferred_check = Some(Box::pin(tokio::time::sleep(LOW_POWER_CHECK_INTERVAL)));
+                }
+                _ => (),
+            }
             if context.should_shut_down() {
                 break;
             }
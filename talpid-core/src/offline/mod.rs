@@ -40,6 +40,14 @@ impl MonitorHandle {
             None => false,
         }
     }
+
+    /// Enables or disables low-power mode, which is used when no protection is active. In
+    /// low-power mode, changes to the connectivity may be reported later than they happen.
+    pub fn set_low_power(&self, low_power: bool) {
+        if let Some(monitor) = self.0.as_ref() {
+            monitor.set_low_power(low_power);
+        }
+    }
 }
 
 pub async fn spawn_monitor(
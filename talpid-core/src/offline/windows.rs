@@ -2,7 +2,7 @@ use crate::{
     windows::window::{PowerManagementEvent, PowerManagementListener},
     winnet,
 };
-use futures::channel::mpsc::UnboundedSender;
+use futures::channel::{mpsc::UnboundedSender, oneshot};
 use parking_lot::Mutex;
 use std::{
     ffi::c_void,
@@ -12,6 +12,14 @@ use std::{
 };
 use talpid_types::ErrorExt;
 
+/// Minimum time to wait after resuming before the host is considered online again. The tunnel
+/// device is unavailable for approximately 2 seconds on a healthy machine.
+const MIN_RESUME_DELAY: Duration = Duration::from_secs(5);
+
+/// Maximum time to wait for the default route to be updated after resuming. The connectivity is
+/// checked again after this even if no route change has been seen.
+const RESUME_NETWORK_TIMEOUT: Duration = Duration::from_secs(15);
+
 #[derive(err_derive::Error, Debug)]
 pub enum Error {
     #[error(display = "Unable to create listener thread")]
@@ -34,11 +42,15 @@ impl BroadcastListener {
         mut power_mgmt_rx: PowerManagementListener,
     ) -> Result<Self, Error> {
         let notify_tx = Arc::new(notify_tx);
-        let (v4_connectivity, v6_connectivity) = Self::check_initial_connectivity();
+        let (v4_connectivity, v6_connectivity) = Self::check_connectivity();
+        let is_online = v4_connectivity || v6_connectivity;
+        log::info!("Initial connectivity: {}", is_offline_str(!is_online));
+
         let system_state = Arc::new(Mutex::new(SystemState {
             v4_connectivity,
             v6_connectivity,
             suspended: false,
+            resume_tx: None,
             notify_tx: Arc::downgrade(&notify_tx),
         }));
 
@@ -48,17 +60,15 @@ impl BroadcastListener {
                 match event {
                     PowerManagementEvent::Suspend => {
                         log::debug!("Machine is preparing to enter sleep mode");
-                        apply_system_state_change(state.clone(), StateChange::Suspended(true));
+                        let mut state = state.lock();
+                        // Cancel waiting for a previous resume to complete
+                        state.resume_tx = None;
+                        state.apply_change(StateChange::Suspended(true));
                     }
                     PowerManagementEvent::ResumeAutomatic => {
-                        let state_copy = state.clone();
-                        tokio::spawn(async move {
-                            // Tunnel will be unavailable for approximately 2 seconds on a healthy
-                            // machine.
-                            tokio::time::sleep(Duration::from_secs(5)).await;
-                            log::debug!("Tunnel device is presumed to have been re-initialized");
-                            apply_system_state_change(state_copy, StateChange::Suspended(false));
-                        });
+                        let (resume_tx, resume_rx) = oneshot::channel();
+                        state.lock().resume_tx = Some(resume_tx);
+                        tokio::spawn(Self::complete_resume(state.clone(), resume_rx));
                     }
                     _ => (),
                 }
@@ -75,29 +85,62 @@ impl BroadcastListener {
         })
     }
 
-    fn check_initial_connectivity() -> (bool, bool) {
-        let v4_connectivity = winnet::get_best_default_route(winnet::WinNetAddrFamily::IPV4)
+    /// Keeps the host offline after resuming until the default route has been updated, since the
+    /// routes and adapter state from before the machine went to sleep are often stale, e.g. while
+    /// DHCP has not completed. Then the connectivity is checked again from scratch.
+    async fn complete_resume(state: Arc<Mutex<SystemState>>, resume_rx: oneshot::Receiver<()>) {
+        log::debug!("Machine resumed. Waiting for the default route to be updated");
+
+        let wait_for_network = async {
+            match tokio::time::timeout(RESUME_NETWORK_TIMEOUT, resume_rx).await {
+                Ok(Ok(())) => {
+                    log::debug!("Default route was updated after resuming");
+                    true
+                }
+                // Suspended again, or resumed again, before the network came back
+                Ok(Err(_)) => false,
+                Err(_) => {
+                    log::warn!(
+                        "Default route was not updated within {} seconds of resuming",
+                        RESUME_NETWORK_TIMEOUT.as_secs()
+                    );
+                    true
+                }
+            }
+        };
+        let (_, completed) = tokio::join!(tokio::time::sleep(MIN_RESUME_DELAY), wait_for_network);
+        if !completed {
+            return;
+        }
+
+        let (v4_connectivity, v6_connectivity) = Self::check_connectivity();
+
+        let mut state = state.lock();
+        state.apply_change(StateChange::NetworkV4Connectivity(v4_connectivity));
+        state.apply_change(StateChange::NetworkV6Connectivity(v6_connectivity));
+        state.apply_change(StateChange::Suspended(false));
+    }
+
+    fn check_connectivity() -> (bool, bool) {
+        let v4_connectivity = winnet::get_best_default_route(winnet::WinNetAddrFamily::IPV4, &[])
             .map(|route| route.is_some())
             .unwrap_or_else(|error| {
                 log::error!(
                     "{}",
-                    error.display_chain_with_msg("Failed to check initial IPv4 connectivity")
+                    error.display_chain_with_msg("Failed to check IPv4 connectivity")
                 );
                 true
             });
-        let v6_connectivity = winnet::get_best_default_route(winnet::WinNetAddrFamily::IPV6)
+        let v6_connectivity = winnet::get_best_default_route(winnet::WinNetAddrFamily::IPV6, &[])
             .map(|route| route.is_some())
             .unwrap_or_else(|error| {
                 log::error!(
                     "{}",
-                    error.display_chain_with_msg("Failed to check initial IPv6 connectivity")
+                    error.display_chain_with_msg("Failed to check IPv6 connectivity")
                 );
                 true
             });
 
-        let is_online = v4_connectivity || v6_connectivity;
-        log::info!("Initial connectivity: {}", is_offline_str(!is_online));
-
         (v4_connectivity, v6_connectivity)
     }
 
@@ -116,23 +159,30 @@ impl BroadcastListener {
     unsafe extern "system" fn connectivity_callback(
         event_type: winnet::WinNetDefaultRouteChangeEventType,
         family: winnet::WinNetAddrFamily,
-        _default_route: winnet::WinNetDefaultRoute,
+        _change: &winnet::WinNetDefaultRouteChange,
         ctx: *mut c_void,
     ) {
         use winnet::WinNetDefaultRouteChangeEventType::*;
 
+        let state_lock: &mut Arc<Mutex<SystemState>> = &mut *(ctx as *mut _);
+        let mut state = state_lock.lock();
+
+        // Any change, including new addresses from DHCP, means that the network has been
+        // reinitialized after resuming
+        if let Some(resume_tx) = state.resume_tx.take() {
+            let _ = resume_tx.send(());
+        }
+
         if event_type == DefaultRouteUpdatedDetails {
             // ignore changes that don't affect the route
             return;
         }
 
-        let state_lock: &mut Arc<Mutex<SystemState>> = &mut *(ctx as *mut _);
         let connectivity = event_type != DefaultRouteRemoved;
         let change = match family {
             winnet::WinNetAddrFamily::IPV4 => StateChange::NetworkV4Connectivity(connectivity),
             winnet::WinNetAddrFamily::IPV6 => StateChange::NetworkV6Connectivity(connectivity),
         };
-        let mut state = state_lock.lock();
         state.apply_change(change);
     }
 
@@ -140,6 +190,10 @@ impl BroadcastListener {
         let state = self.system_state.lock();
         state.is_offline_currently()
     }
+
+    /// Default route changes are pushed by the system, so there is nothing to throttle in
+    /// low-power mode.
+    pub fn set_low_power(&self, _low_power: bool) {}
 }
 
 #[derive(Debug)]
@@ -153,6 +207,8 @@ struct SystemState {
     v4_connectivity: bool,
     v6_connectivity: bool,
     suspended: bool,
+    /// Set while waiting for the default route to be updated after resuming.
+    resume_tx: Option<oneshot::Sender<()>>,
     notify_tx: Weak<UnboundedSender<bool>>,
 }
 
@@ -206,8 +262,3 @@ pub async fn spawn_monitor(
 ) -> Result<MonitorHandle, Error> {
     BroadcastListener::start(sender, power_mgmt_rx)
 }
-
-fn apply_system_state_change(state: Arc<Mutex<SystemState>>, change: StateChange) {
-    let mut state = state.lock();
-    state.apply_change(change);
-}
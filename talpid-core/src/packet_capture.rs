@@ -0,0 +1,622 @@
+//! Capture of the packets that enter and leave the tunnel interface, for debugging MTU,
+//! fragmentation and routing issues on machines where Wireshark is unavailable. This is only
+//! included in builds with the `packet-capture` feature.
+//!
+//! Packets are written to a pcap file in the log directory. Only the IP and transport headers of
+//! each packet are kept, so the payload of the user's traffic is never written to disk. A capture
+//! stops when the size or time limit is reached, when it is stopped, or when the tunnel goes down.
+//! If it is started while there is no tunnel, it starts once the tunnel is up.
+
+use std::{
+    ffi::CString,
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+    mem,
+    os::unix::{fs::OpenOptionsExt, io::RawFd},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use talpid_types::ErrorExt;
+
+/// Name of the capture file in the log directory. It is replaced by each new capture.
+const CAPTURE_FILENAME: &str = "tunnel.pcap";
+
+/// Size limit used when none is given.
+pub const DEFAULT_MAX_SIZE: u64 = 10 * 1024 * 1024;
+/// Largest size limit that may be set.
+pub const MAX_SIZE: u64 = 100 * 1024 * 1024;
+/// Time limit used when none is given.
+pub const DEFAULT_MAX_DURATION: Duration = Duration::from_secs(5 * 60);
+/// Longest time limit that may be set.
+pub const MAX_DURATION: Duration = Duration::from_secs(60 * 60);
+
+/// How often the capture thread checks whether it should stop, when no packets are received.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Size of the receive buffer. Larger packets are counted in full but truncated anyway.
+const RECEIVE_BUFFER_SIZE: usize = 65535;
+
+/// `LINKTYPE_RAW`: packets begin with an IPv4 or IPv6 header.
+const LINKTYPE_RAW: u32 = 101;
+const PCAP_MAGIC: u32 = 0xa1b2c3d4;
+const PCAP_HEADER_SIZE: u64 = 24;
+const PCAP_RECORD_HEADER_SIZE: u64 = 16;
+
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+const IPPROTO_ICMP: u8 = 1;
+const IPPROTO_ICMPV6: u8 = 58;
+const IPPROTO_IPV6_FRAGMENT: u8 = 44;
+const IPV6_HEADER_SIZE: usize = 40;
+const IPV6_FRAGMENT_HEADER_SIZE: usize = 8;
+const UDP_HEADER_SIZE: usize = 8;
+const ICMP_HEADER_SIZE: usize = 8;
+
+/// Errors that can occur when starting a capture.
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    /// A capture is already running or waiting for the tunnel.
+    #[error(display = "A packet capture is already running")]
+    AlreadyRunning,
+
+    /// There is nowhere to write the capture.
+    #[error(display = "No log directory to write the packet capture to")]
+    NoLogDirectory,
+
+    /// The capture file could not be created.
+    #[error(display = "Failed to create the packet capture file")]
+    CreateFile(#[error(source)] io::Error),
+
+    /// The tunnel interface could not be opened for capturing.
+    #[error(display = "Failed to open the tunnel interface for capturing")]
+    OpenInterface(#[error(source)] io::Error),
+}
+
+/// Limits of a single capture.
+#[derive(Debug, Clone, Copy)]
+pub struct CaptureLimits {
+    /// Maximum size of the capture file, in bytes.
+    pub max_size: u64,
+    /// Maximum time to capture for, counted from when the tunnel interface is opened.
+    pub max_duration: Duration,
+}
+
+impl CaptureLimits {
+    /// Uses the defaults for limits that are not given, and caps the limits that are too large.
+    pub fn new(max_size: Option<u64>, max_duration: Option<Duration>) -> Self {
+        Self {
+            max_size: max_size.unwrap_or(DEFAULT_MAX_SIZE).min(MAX_SIZE),
+            max_duration: max_duration
+                .unwrap_or(DEFAULT_MAX_DURATION)
+                .min(MAX_DURATION),
+        }
+    }
+}
+
+#[derive(Default)]
+struct CaptureState {
+    /// Tunnel interface, if the tunnel is up.
+    interface: Option<String>,
+    /// Capture that is waiting for the tunnel to come up.
+    pending: Option<(File, CaptureLimits)>,
+    /// Set to stop the running capture. It is also set by the capture itself when it ends.
+    stop: Option<Arc<AtomicBool>>,
+}
+
+/// Handle used to start and stop packet captures.
+#[derive(Clone)]
+pub struct PacketCaptureHandle {
+    log_dir: Option<PathBuf>,
+    state: Arc<Mutex<CaptureState>>,
+}
+
+impl PacketCaptureHandle {
+    pub(crate) fn new(log_dir: Option<PathBuf>) -> Self {
+        Self {
+            log_dir,
+            state: Arc::new(Mutex::new(CaptureState::default())),
+        }
+    }
+
+    /// Starts capturing packets on the tunnel interface, or as soon as it comes up. Returns the
+    /// path to the capture file.
+    pub fn start(&self, limits: CaptureLimits) -> Result<PathBuf, Error> {
+        let mut state = self.state.lock().unwrap();
+        let running = state
+            .stop
+            .as_ref()
+            .map(|stop| !stop.load(Ordering::Acquire))
+            .unwrap_or(false);
+        if running || state.pending.is_some() {
+            return Err(Error::AlreadyRunning);
+        }
+
+        let path = self
+            .log_dir
+            .as_ref()
+            .ok_or(Error::NoLogDirectory)?
+            .join(CAPTURE_FILENAME);
+        let file = create_capture_file(&path).map_err(Error::CreateFile)?;
+
+        log::info!(
+            "Starting packet capture to {}. Limits: {} bytes, {} s",
+            path.display(),
+            limits.max_size,
+            limits.max_duration.as_secs()
+        );
+        match state.interface.clone() {
+            Some(interface) => {
+                state.stop = Some(spawn_capture(&interface, file, limits)?);
+            }
+            None => {
+                log::info!("Packet capture will start when the tunnel is up");
+                state.pending = Some((file, limits));
+            }
+        }
+        Ok(path)
+    }
+
+    /// Stops the running capture, if any. Returns whether there was one.
+    pub fn stop(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let was_pending = state.pending.take().is_some();
+        let was_running = state
+            .stop
+            .take()
+            .map(|stop| !stop.swap(true, Ordering::AcqRel))
+            .unwrap_or(false);
+        if was_pending || was_running {
+            log::info!("Stopping packet capture");
+        }
+        was_pending || was_running
+    }
+
+    /// Notifies the capture of the current tunnel interface. A pending capture is started when
+    /// the tunnel comes up, and a running capture is stopped when it goes down.
+    pub(crate) fn set_interface(&self, interface: Option<&str>) {
+        let mut state = self.state.lock().unwrap();
+        state.interface = interface.map(str::to_owned);
+
+        match interface {
+            Some(interface) => {
+                if let Some((file, limits)) = state.pending.take() {
+                    match spawn_capture(interface, file, limits) {
+                        Ok(stop) => state.stop = Some(stop),
+                        Err(error) => log::error!(
+                            "{}",
+                            error.display_chain_with_msg("Failed to start packet capture")
+                        ),
+                    }
+                }
+            }
+            None => {
+                if let Some(stop) = state.stop.take() {
+                    stop.store(true, Ordering::Release);
+                }
+            }
+        }
+    }
+}
+
+fn create_capture_file(path: &Path) -> io::Result<File> {
+    // The headers reveal which hosts are contacted, so only root may read the capture
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+}
+
+fn spawn_capture(
+    interface: &str,
+    file: File,
+    limits: CaptureLimits,
+) -> Result<Arc<AtomicBool>, Error> {
+    let socket = PacketSocket::open(interface).map_err(Error::OpenInterface)?;
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread_stop = stop.clone();
+
+    thread::spawn(move || {
+        match run_capture(socket, file, limits, &thread_stop) {
+            Ok(reason) => log::info!("Packet capture stopped: {}", reason),
+            Err(error) => log::error!(
+                "{}",
+                error.display_chain_with_msg("Packet capture stopped due to an error")
+            ),
+        }
+        thread_stop.store(true, Ordering::Release);
+    });
+
+    Ok(stop)
+}
+
+fn run_capture(
+    socket: PacketSocket,
+    file: File,
+    limits: CaptureLimits,
+    stop: &AtomicBool,
+) -> io::Result<&'static str> {
+    let mut writer = PcapWriter::new(BufWriter::new(file))?;
+    let mut buffer = vec![0u8; RECEIVE_BUFFER_SIZE];
+    let start = Instant::now();
+
+    let reason = loop {
+        if stop.load(Ordering::Acquire) {
+            break "stopped";
+        }
+        if start.elapsed() >= limits.max_duration {
+            break "time limit reached";
+        }
+        let (received_len, original_len) = match socket.recv(&mut buffer) {
+            Ok(Some(received)) => received,
+            Ok(None) => continue,
+            // The interface was removed
+            Err(error) if error.raw_os_error() == Some(libc::ENETDOWN) => {
+                break "tunnel interface is down"
+            }
+            Err(error) => return Err(error),
+        };
+        let packet = &buffer[..received_len];
+        let record = &packet[..redacted_len(packet)];
+        if writer.written() + PCAP_RECORD_HEADER_SIZE + record.len() as u64 > limits.max_size {
+            break "size limit reached";
+        }
+        writer.write_packet(SystemTime::now(), record, original_len)?;
+    };
+
+    writer.flush()?;
+    Ok(reason)
+}
+
+/// `AF_PACKET` socket that receives the IP packets entering and leaving an interface.
+struct PacketSocket(RawFd);
+
+impl PacketSocket {
+    fn open(interface: &str) -> io::Result<Self> {
+        let interface = CString::new(interface)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+        // SAFETY: `interface` is a valid null-terminated string.
+        let index = unsafe { libc::if_nametoindex(interface.as_ptr()) };
+        if index == 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let protocol = (libc::ETH_P_ALL as u16).to_be();
+        // Tunnel interfaces have no link-layer header, so datagram sockets receive IP packets
+        // SAFETY: Trivially safe.
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_PACKET,
+                libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+                i32::from(protocol),
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let socket = Self(fd);
+
+        // SAFETY: `sockaddr_ll` is valid when zeroed.
+        let mut address: libc::sockaddr_ll = unsafe { mem::zeroed() };
+        address.sll_family = libc::AF_PACKET as u16;
+        address.sll_protocol = protocol;
+        address.sll_ifindex = index as i32;
+        // SAFETY: `address` is a valid `sockaddr_ll` of the given size.
+        let result = unsafe {
+            libc::bind(
+                socket.0,
+                &address as *const libc::sockaddr_ll as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+            )
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let timeout = libc::timeval {
+            tv_sec: 0,
+            tv_usec: POLL_INTERVAL.as_micros() as libc::suseconds_t,
+        };
+        // SAFETY: `timeout` is a valid `timeval` of the given size.
+        let result = unsafe {
+            libc::setsockopt(
+                socket.0,
+                libc::SOL_SOCKET,
+                libc::SO_RCVTIMEO,
+                &timeout as *const libc::timeval as *const libc::c_void,
+                mem::size_of::<libc::timeval>() as libc::socklen_t,
+            )
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(socket)
+    }
+
+    /// Receives a packet into `buffer`. Returns the number of bytes received and the original
+    /// length of the packet, or `None` if the timeout expired.
+    fn recv(&self, buffer: &mut [u8]) -> io::Result<Option<(usize, u32)>> {
+        // SAFETY: `buffer` is valid for writes of its length.
+        let result = unsafe {
+            libc::recv(
+                self.0,
+                buffer.as_mut_ptr() as *mut libc::c_void,
+                buffer.len(),
+                libc::MSG_TRUNC,
+            )
+        };
+        if result < 0 {
+            let error = io::Error::last_os_error();
+            return match error.kind() {
+                io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted => Ok(None),
+                _ => Err(error),
+            };
+        }
+        // With `MSG_TRUNC`, the full length is returned even if the buffer is too small
+        let original_len = result as usize;
+        Ok(Some((original_len.min(buffer.len()), original_len as u32)))
+    }
+}
+
+impl Drop for PacketSocket {
+    fn drop(&mut self) {
+        // SAFETY: The socket is owned by this struct.
+        unsafe { libc::close(self.0) };
+    }
+}
+
+/// Returns the length of the IP and transport headers of `packet`. Everything after them is
+/// payload, which must not be captured. Unknown protocols are truncated after the IP header.
+/// Only the first fragment of a fragmented packet has a transport header, so any other fragment is
+/// truncated after the IP header, including the IPv6 Fragment header.
+fn redacted_len(packet: &[u8]) -> usize {
+    let (ip_header_len, protocol) = match packet.first().map(|byte| byte >> 4) {
+        Some(4) => {
+            let header_len = usize::from(packet[0] & 0x0f) * 4;
+            let protocol = match packet.get(9) {
+                Some(protocol) if header_len >= 20 => *protocol,
+                _ => return 0,
+            };
+            let fragment_offset = u16::from_be_bytes([packet[6], packet[7]]) & 0x1fff;
+            if fragment_offset != 0 {
+                return header_len.min(packet.len());
+            }
+            (header_len, protocol)
+        }
+        Some(6) => match packet.get(6) {
+            Some(&IPPROTO_IPV6_FRAGMENT) => {
+                let header_len = IPV6_HEADER_SIZE + IPV6_FRAGMENT_HEADER_SIZE;
+                let fragment = match packet.get(IPV6_HEADER_SIZE..header_len) {
+                    Some(fragment) => fragment,
+                    None => return packet.len(),
+                };
+                let fragment_offset = u16::from_be_bytes([fragment[2], fragment[3]]) >> 3;
+                if fragment_offset != 0 {
+                    return header_len;
+                }
+                (header_len, fragment[0])
+            }
+            Some(next_header) => (IPV6_HEADER_SIZE, *next_header),
+            None => return 0,
+        },
+        _ => return 0,
+    };
+
+    let transport = match packet.get(ip_header_len..) {
+        Some(transport) => transport,
+        None => return packet.len(),
+    };
+    let transport_header_len = match protocol {
+        IPPROTO_TCP => transport
+            .get(12)
+            .map(|data_offset| usize::from(data_offset >> 4) * 4)
+            .unwrap_or(0),
+        IPPROTO_UDP => UDP_HEADER_SIZE,
+        IPPROTO_ICMP | IPPROTO_ICMPV6 => ICMP_HEADER_SIZE,
+        _ => 0,
+    };
+    (ip_header_len + transport_header_len).min(packet.len())
+}
+
+/// Writes packets in the pcap file format.
+struct PcapWriter<W: Write> {
+    writer: W,
+    written: u64,
+}
+
+impl<W: Write> PcapWriter<W> {
+    fn new(mut writer: W) -> io::Result<Self> {
+        let mut header = Vec::with_capacity(PCAP_HEADER_SIZE as usize);
+        header.extend_from_slice(&PCAP_MAGIC.to_ne_bytes());
+        // Version 2.4
+        header.extend_from_slice(&2u16.to_ne_bytes());
+        header.extend_from_slice(&4u16.to_ne_bytes());
+        // Time zone offset and timestamp accuracy
+        header.extend_from_slice(&0i32.to_ne_bytes());
+        header.extend_from_slice(&0u32.to_ne_bytes());
+        header.extend_from_slice(&(RECEIVE_BUFFER_SIZE as u32).to_ne_bytes());
+        header.extend_from_slice(&LINKTYPE_RAW.to_ne_bytes());
+        writer.write_all(&header)?;
+
+        Ok(Self {
+            writer,
+            written: PCAP_HEADER_SIZE,
+        })
+    }
+
+    /// Number of bytes written so far, including the file header.
+    fn written(&self) -> u64 {
+        self.written
+    }
+
+    fn write_packet(
+        &mut self,
+        timestamp: SystemTime,
+        data: &[u8],
+        original_len: u32,
+    ) -> io::Result<()> {
+        let timestamp = timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut record = Vec::with_capacity(PCAP_RECORD_HEADER_SIZE as usize + data.len());
+        record.extend_from_slice(&(timestamp.as_secs() as u32).to_ne_bytes());
+        record.extend_from_slice(&timestamp.subsec_micros().to_ne_bytes());
+        record.extend_from_slice(&(data.len() as u32).to_ne_bytes());
+        record.extend_from_slice(&original_len.to_ne_bytes());
+        record.extend_from_slice(data);
+        self.writer.write_all(&record)?;
+        self.written += record.len() as u64;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ipv4_packet(protocol: u8, transport: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0u8; 20];
+        packet[0] = 0x45;
+        packet[9] = protocol;
+        packet.extend_from_slice(transport);
+        packet
+    }
+
+    #[test]
+    fn test_redact_tcp_payload() {
+        let mut tcp = vec![0u8; 32];
+        // Data offset of 8 words, i.e. 32 bytes with options
+        tcp[12] = 0x80;
+        tcp.extend_from_slice(b"secret payload");
+        let packet = ipv4_packet(IPPROTO_TCP, &tcp);
+
+        assert_eq!(redacted_len(&packet), 20 + 32);
+    }
+
+    #[test]
+    fn test_redact_udp_payload() {
+        let mut udp = vec![0u8; 8];
+        udp.extend_from_slice(b"secret payload");
+        let packet = ipv4_packet(IPPROTO_UDP, &udp);
+        assert_eq!(redacted_len(&packet), 20 + 8);
+
+        let mut packet = vec![0u8; IPV6_HEADER_SIZE];
+        packet[0] = 0x60;
+        packet[6] = IPPROTO_UDP;
+        packet.extend_from_slice(&udp);
+        assert_eq!(redacted_len(&packet), IPV6_HEADER_SIZE + 8);
+    }
+
+    #[test]
+    fn test_redact_ipv4_fragments() {
+        let mut udp = vec![0u8; 8];
+        udp.extend_from_slice(b"secret payload");
+
+        // The first fragment has the UDP header
+        let mut packet = ipv4_packet(IPPROTO_UDP, &udp);
+        // More fragments flag
+        packet[6] = 0x20;
+        assert_eq!(redacted_len(&packet), 20 + 8);
+
+        // Later fragments only contain payload
+        let mut packet = ipv4_packet(IPPROTO_UDP, &udp);
+        packet[6] = 0x20;
+        packet[7] = 0xb9;
+        assert_eq!(redacted_len(&packet), 20);
+
+        // Last fragment
+        let mut packet = ipv4_packet(IPPROTO_TCP, b"secret payload");
+        packet[6] = 0x01;
+        assert_eq!(redacted_len(&packet), 20);
+    }
+
+    #[test]
+    fn test_redact_ipv6_fragments() {
+        fn ipv6_fragment(offset: u16, transport: &[u8]) -> Vec<u8> {
+            let mut packet = vec![0u8; IPV6_HEADER_SIZE];
+            packet[0] = 0x60;
+            packet[6] = IPPROTO_IPV6_FRAGMENT;
+            packet.push(IPPROTO_UDP);
+            packet.push(0);
+            // Fragment offset in 8-byte units, followed by the more fragments flag
+            packet.extend_from_slice(&((offset << 3) | 1).to_be_bytes());
+            packet.extend_from_slice(&[0u8; 4]);
+            packet.extend_from_slice(transport);
+            packet
+        }
+
+        let mut udp = vec![0u8; 8];
+        udp.extend_from_slice(b"secret payload");
+
+        let packet = ipv6_fragment(0, &udp);
+        assert_eq!(
+            redacted_len(&packet),
+            IPV6_HEADER_SIZE + IPV6_FRAGMENT_HEADER_SIZE + 8
+        );
+
+        let packet = ipv6_fragment(185, &udp);
+        assert_eq!(
+            redacted_len(&packet),
+            IPV6_HEADER_SIZE + IPV6_FRAGMENT_HEADER_SIZE
+        );
+
+        // Truncated Fragment header
+        let mut packet = ipv6_fragment(0, &[]);
+        packet.truncate(IPV6_HEADER_SIZE + 4);
+        assert_eq!(redacted_len(&packet), packet.len());
+    }
+
+    #[test]
+    fn test_redact_unknown_and_malformed() {
+        let packet = ipv4_packet(50, b"encrypted");
+        assert_eq!(redacted_len(&packet), 20);
+
+        // Truncated TCP header
+        let packet = ipv4_packet(IPPROTO_TCP, &[0u8; 4]);
+        assert_eq!(redacted_len(&packet), 20);
+
+        // Truncated IP header
+        let mut packet = ipv4_packet(IPPROTO_UDP, &[]);
+        packet[0] = 0x46;
+        assert_eq!(redacted_len(&packet), packet.len());
+
+        assert_eq!(redacted_len(&[]), 0);
+        assert_eq!(redacted_len(&[0x10, 0, 0]), 0);
+    }
+
+    #[test]
+    fn test_pcap_writer() {
+        let mut writer = PcapWriter::new(Vec::new()).unwrap();
+        writer
+            .write_packet(
+                UNIX_EPOCH + Duration::from_micros(1_500_000),
+                &[1, 2, 3],
+                100,
+            )
+            .unwrap();
+        assert_eq!(
+            writer.written(),
+            PCAP_HEADER_SIZE + PCAP_RECORD_HEADER_SIZE + 3
+        );
+
+        let output = writer.writer;
+        assert_eq!(output.len() as u64, writer.written);
+        assert_eq!(output[0..4], PCAP_MAGIC.to_ne_bytes());
+        assert_eq!(output[20..24], LINKTYPE_RAW.to_ne_bytes());
+        let record = &output[PCAP_HEADER_SIZE as usize..];
+        assert_eq!(record[0..4], 1u32.to_ne_bytes());
+        assert_eq!(record[4..8], 500_000u32.to_ne_bytes());
+        assert_eq!(record[8..12], 3u32.to_ne_bytes());
+        assert_eq!(record[12..16], 100u32.to_ne_bytes());
+        assert_eq!(record[16..], [1, 2, 3]);
+    }
+}
@@ -2,5 +2,6 @@
 #[cfg(not(target_os = "android"))]
 pub mod openvpn;
 
-/// A trait for stopping subprocesses gracefully.
-pub mod stoppable_process;
+/// Supervision of external helper processes, with log forwarding and graceful termination.
+#[cfg(not(target_os = "android"))]
+pub mod supervisor;
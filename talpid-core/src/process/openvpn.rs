@@ -1,16 +1,13 @@
-use duct;
-
-use super::stoppable_process::StoppableProcess;
-use atty;
-use os_pipe::{pipe, PipeWriter};
-use parking_lot::Mutex;
+use super::supervisor::SupervisedProcess;
 use shell_escape;
 use std::{
     ffi::{OsStr, OsString},
     fmt, io,
     path::{Path, PathBuf},
+    process::ExitStatus,
+    time::Duration,
 };
-use talpid_types::{net, ErrorExt};
+use talpid_types::net;
 
 static BASE_ARGUMENTS: &[&[&str]] = &[
     &["--client"],
@@ -65,6 +62,7 @@ pub struct OpenVpnCommand {
     proxy_auth_path: Option<PathBuf>,
     ca: Option<PathBuf>,
     crl: Option<PathBuf>,
+    client_certificate: Option<(PathBuf, PathBuf)>,
     iproute_bin: Option<OsString>,
     plugin: Option<(PathBuf, Vec<String>)>,
     log: Option<PathBuf>,
@@ -87,6 +85,7 @@ impl OpenVpnCommand {
             proxy_auth_path: None,
             ca: None,
             crl: None,
+            client_certificate: None,
             iproute_bin: None,
             plugin: None,
             log: None,
@@ -136,6 +135,19 @@ impl OpenVpnCommand {
         self
     }
 
+    /// Sets the paths to the client certificate and its private key, both PEM encoded.
+    pub fn client_certificate(
+        &mut self,
+        cert_path: impl AsRef<Path>,
+        key_path: impl AsRef<Path>,
+    ) -> &mut Self {
+        self.client_certificate = Some((
+            cert_path.as_ref().to_path_buf(),
+            key_path.as_ref().to_path_buf(),
+        ));
+        self
+    }
+
     /// Sets the path to the ip route command.
     pub fn iproute_bin(&mut self, iproute_bin: impl Into<OsString>) -> &mut Self {
         self.iproute_bin = Some(iproute_bin.into());
@@ -186,10 +198,12 @@ impl OpenVpnCommand {
         self
     }
 
-    /// Build a runnable expression from the current state of the command.
-    pub fn build(&self) -> duct::Expression {
-        log::debug!("Building expression: {}", &self);
-        duct::cmd(&self.openvpn_bin, self.get_arguments()).unchecked()
+    /// Build a runnable command from the current state of the command.
+    pub fn build(&self) -> tokio::process::Command {
+        log::debug!("Building command: {}", &self);
+        let mut cmd = tokio::process::Command::new(&self.openvpn_bin);
+        cmd.args(self.get_arguments());
+        cmd
     }
 
     /// Returns all arguments that the subprocess would be spawned with.
@@ -300,6 +314,12 @@ impl OpenVpnCommand {
             args.push(OsString::from("--auth-user-pass"));
             args.push(OsString::from(user_pass_path));
         }
+        if let Some((ref cert_path, ref key_path)) = self.client_certificate {
+            args.push(OsString::from("--cert"));
+            args.push(OsString::from(cert_path));
+            args.push(OsString::from("--key"));
+            args.push(OsString::from(key_path));
+        }
         args
     }
 
@@ -369,76 +389,26 @@ impl fmt::Display for OpenVpnCommand {
 
 /// Proc handle for an openvpn process
 pub struct OpenVpnProcHandle {
-    /// Duct handle
-    pub inner: duct::Handle,
-    /// Standard input handle
-    pub stdin: Mutex<Option<PipeWriter>>,
+    process: SupervisedProcess,
 }
 
 /// Impl for proc handle
 impl OpenVpnProcHandle {
-    /// Constructor for a new openvpn proc handle
-    pub fn new(mut cmd: duct::Expression) -> io::Result<Self> {
-        if !atty::is(atty::Stream::Stdout) {
-            cmd = cmd.stdout_null();
-        }
-
-        if !atty::is(atty::Stream::Stderr) {
-            cmd = cmd.stderr_null();
-        }
-
-        let (reader, writer) = pipe()?;
-        let proc_handle = cmd.stdin_file(reader).start()?;
-
+    /// Spawns OpenVPN. When stopped, OpenVPN is killed unless it exits within `stop_timeout`.
+    pub fn new(cmd: tokio::process::Command, stop_timeout: Duration) -> io::Result<Self> {
         Ok(Self {
-            inner: proc_handle,
-            stdin: Mutex::new(Some(writer)),
+            process: SupervisedProcess::spawn("openvpn", cmd, stop_timeout)?,
         })
     }
-}
 
-impl StoppableProcess for OpenVpnProcHandle {
-    /// Closes STDIN to stop the openvpn process
-    fn stop(&self) {
-        // Dropping our stdin handle so that it is closed once. Closing the handle should
-        // gracefully stop our openvpn child process.
-        if self.stdin.lock().take().is_none() {
-            log::warn!("Tried to close OpenVPN stdin handle twice, this is a bug");
-        }
+    /// Closes STDIN to stop the openvpn process, and kills it if it does not exit in time.
+    pub fn stop(&self) {
+        self.process.stop();
     }
 
-    fn kill(&self) -> io::Result<()> {
-        log::warn!("Killing OpenVPN process");
-        self.inner.kill()?;
-        log::debug!("OpenVPN forcefully killed");
-        Ok(())
-    }
-
-    fn has_stopped(&self) -> io::Result<bool> {
-        match self.inner.try_wait() {
-            Ok(None) => Ok(false),
-            Ok(Some(_)) => Ok(true),
-            Err(e) => Err(e),
-        }
-    }
-}
-
-impl Drop for OpenVpnProcHandle {
-    fn drop(&mut self) {
-        let result = match self.has_stopped() {
-            Ok(false) => self.kill(),
-            Err(e) => {
-                log::error!(
-                    "{}",
-                    e.display_chain_with_msg("Failed to check if OpenVPN is running")
-                );
-                self.kill()
-            }
-            _ => Ok(()),
-        };
-        if let Err(error) = result {
-            log::error!("{}", error.display_chain_with_msg("Failed to kill OpenVPN"));
-        }
+    /// Waits for the openvpn process to exit.
+    pub async fn wait(&self) -> io::Result<ExitStatus> {
+        self.process.wait().await
     }
 }
 
@@ -473,4 +443,21 @@ mod tests {
         assert!(testee_args.contains(&OsString::from("123")));
         assert!(testee_args.contains(&OsString::from("cde")));
     }
+
+    #[test]
+    fn passes_client_certificate() {
+        let testee_args = OpenVpnCommand::new("")
+            .client_certificate("./client.crt", "./client.key")
+            .get_arguments();
+        let cert_index = testee_args
+            .iter()
+            .position(|arg| arg == "--cert")
+            .expect("missing --cert");
+        assert_eq!(testee_args[cert_index + 1], OsString::from("./client.crt"));
+        let key_index = testee_args
+            .iter()
+            .position(|arg| arg == "--key")
+            .expect("missing --key");
+        assert_eq!(testee_args[key_index + 1], OsString::from("./client.key"));
+    }
 }
@@ -0,0 +1,205 @@
+//! Supervision of external helper processes.
+//!
+//! A [`SupervisedProcess`] owns a child process for its entire lifetime. Its output is forwarded
+//! line by line to the logger, and stopping it first closes its stdin, which asks the process to
+//! exit, and kills it if it has not exited within a deadline.
+
+use std::{
+    io,
+    process::{ExitStatus, Stdio},
+    sync::Arc,
+    time::Duration,
+};
+use talpid_types::{tunnel::ProcessFailure, ErrorExt};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    process::{Child, ChildStdin, Command},
+    sync::{mpsc, watch},
+};
+
+type ExitResult = Option<Result<ExitStatus, Arc<io::Error>>>;
+
+/// Handle to a supervised child process. The process is stopped when the handle is dropped.
+pub struct SupervisedProcess {
+    name: &'static str,
+    stop_tx: mpsc::UnboundedSender<()>,
+    exit_rx: watch::Receiver<ExitResult>,
+}
+
+impl SupervisedProcess {
+    /// Spawns `cmd` and supervises it. `name` is used to identify the process in the logs.
+    /// When stopped, the process is given `stop_timeout` to exit after its stdin is closed,
+    /// before it is killed.
+    ///
+    /// This must be called from within a tokio runtime.
+    pub fn spawn(name: &'static str, mut cmd: Command, stop_timeout: Duration) -> io::Result<Self> {
+        let mut child = cmd
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        if let Some(stdout) = child.stdout.take() {
+            tokio::spawn(forward_output(name, stdout, log::Level::Info));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            tokio::spawn(forward_output(name, stderr, log::Level::Warn));
+        }
+        let stdin = child.stdin.take();
+
+        let (stop_tx, stop_rx) = mpsc::unbounded_channel();
+        let (exit_tx, exit_rx) = watch::channel(None);
+        tokio::spawn(async move {
+            let result = supervise(name, child, stdin, stop_rx, stop_timeout).await;
+            match &result {
+                Ok(status) => log::debug!("{} exited with status: {}", name, status),
+                Err(error) => log::error!(
+                    "{}",
+                    error.display_chain_with_msg(&format!("Failed to wait for {}", name))
+                ),
+            }
+            let _ = exit_tx.send(Some(result.map_err(Arc::new)));
+        });
+
+        Ok(Self {
+            name,
+            stop_tx,
+            exit_rx,
+        })
+    }
+
+    /// Asks the process to stop, and kills it if it does not exit in time. This does not block.
+    /// Use [`SupervisedProcess::wait`] to wait for the process to exit.
+    pub fn stop(&self) {
+        if self.stop_tx.send(()).is_err() {
+            log::trace!("{} has already exited", self.name);
+        }
+    }
+
+    /// Waits for the process to exit.
+    pub async fn wait(&self) -> io::Result<ExitStatus> {
+        let mut exit_rx = self.exit_rx.clone();
+        loop {
+            let exit = exit_rx.borrow().clone();
+            if let Some(result) = exit {
+                return result.map_err(|error| io::Error::new(error.kind(), error.to_string()));
+            }
+            if exit_rx.changed().await.is_err() {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "The process supervisor stopped unexpectedly",
+                ));
+            }
+        }
+    }
+}
+
+async fn supervise(
+    name: &'static str,
+    mut child: Child,
+    stdin: Option<ChildStdin>,
+    mut stop_rx: mpsc::UnboundedReceiver<()>,
+    stop_timeout: Duration,
+) -> io::Result<ExitStatus> {
+    // A closed channel means that the handle was dropped, which also stops the process.
+    let exited = tokio::select! {
+        result = child.wait() => Some(result),
+        _ = stop_rx.recv() => None,
+    };
+    if let Some(result) = exited {
+        return result;
+    }
+
+    log::debug!("Stopping {}", name);
+    drop(stdin);
+    match tokio::time::timeout(stop_timeout, child.wait()).await {
+        Ok(result) => result,
+        Err(_) => {
+            log::warn!(
+                "{} did not exit within {} ms, killing it",
+                name,
+                stop_timeout.as_millis()
+            );
+            child.start_kill()?;
+            child.wait().await
+        }
+    }
+}
+
+async fn forward_output(name: &'static str, output: impl AsyncRead + Unpin, level: log::Level) {
+    let mut lines = BufReader::new(output).lines();
+    loop {
+        match lines.next_line().await {
+            Ok(Some(line)) => log::log!(level, "[{}] {}", name, line),
+            Ok(None) => break,
+            Err(error) => {
+                log::debug!(
+                    "{}",
+                    error.display_chain_with_msg(&format!("Failed to read output of {}", name))
+                );
+                break;
+            }
+        }
+    }
+}
+
+/// Classifies how a process exited, given its exit status. Returns `None` if the process exited
+/// successfully.
+pub fn classify_exit(status: ExitStatus) -> Option<ProcessFailure> {
+    if status.success() {
+        return None;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if status.signal().is_some() {
+            return Some(ProcessFailure::Crash);
+        }
+    }
+    match status.code() {
+        // Unhandled exceptions are reported as NTSTATUS error codes, such as
+        // `STATUS_ACCESS_VIOLATION`.
+        #[cfg(windows)]
+        Some(code) if (code as u32) >= 0xC000_0000 => Some(ProcessFailure::Crash),
+        Some(code) => Some(ProcessFailure::ExitCode(code)),
+        None => Some(ProcessFailure::Crash),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_stop_closes_stdin() {
+        let process =
+            SupervisedProcess::spawn("cat", Command::new("cat"), Duration::from_secs(10)).unwrap();
+        process.stop();
+        let status = process.wait().await.unwrap();
+        assert!(status.success());
+        assert_eq!(classify_exit(status), None);
+    }
+
+    #[tokio::test]
+    async fn test_stop_kills_after_timeout() {
+        let mut cmd = Command::new("sleep");
+        cmd.arg("60");
+        let process = SupervisedProcess::spawn("sleep", cmd, Duration::from_millis(100)).unwrap();
+        process.stop();
+        let status = tokio::time::timeout(Duration::from_secs(10), process.wait())
+            .await
+            .expect("process was not killed")
+            .unwrap();
+        assert_eq!(classify_exit(status), Some(ProcessFailure::Crash));
+    }
+
+    #[tokio::test]
+    async fn test_exit_code() {
+        let mut cmd = Command::new("sh");
+        cmd.args(&["-c", "exit 3"]);
+        let process = SupervisedProcess::spawn("sh", cmd, Duration::from_secs(10)).unwrap();
+        let status = process.wait().await.unwrap();
+        assert_eq!(classify_exit(status), Some(ProcessFailure::ExitCode(3)));
+    }
+}
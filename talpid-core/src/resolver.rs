@@ -1,8 +1,11 @@
 use std::{
     io,
-    net::{Ipv4Addr, SocketAddr},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
     str::FromStr,
-    sync::{Arc, Weak},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Weak,
+    },
 };
 
 use std::time::{Duration, Instant};
@@ -11,6 +14,8 @@ use futures::{
     channel::{mpsc, oneshot},
     SinkExt, StreamExt,
 };
+pub use talpid_types::system_state::ResolverStats;
+use talpid_types::{net::AllowedEndpoint, ErrorExt};
 
 use trust_dns_server::{
     authority::{
@@ -21,10 +26,15 @@ use trust_dns_server::{
         rr::{LowerName, RecordType},
     },
     proto::{
-        op::{header::MessageType, op_code::OpCode, Header},
+        op::{header::MessageType, op_code::OpCode, Header, ResponseCode},
         rr::{domain::Name, record_data::RData, Record},
     },
-    resolver::lookup::Lookup,
+    resolver::{
+        config::{NameServerConfigGroup, ResolverConfig as ForwarderConfig, ResolverOpts},
+        error::{ResolveError, ResolveErrorKind},
+        lookup::Lookup,
+        TokioAsyncResolver,
+    },
     server::{Request, RequestHandler, ResponseHandler, ResponseInfo},
     ServerFuture,
 };
@@ -35,6 +45,10 @@ const TTL_SECONDS: u32 = 3;
 /// An IP address to be used in the DNS response to the captive domain query. The address itself
 /// belongs to the documentation range so should never be reachable.
 const RESOLVED_ADDR: Ipv4Addr = Ipv4Addr::new(198, 51, 100, 1);
+/// Port of the upstream resolvers that queries are forwarded to.
+const UPSTREAM_PORT: u16 = 53;
+/// Maximum number of answers from upstream resolvers to cache.
+const FORWARDER_CACHE_SIZE: usize = 1024;
 
 /// Starts a resolver. Returns a cloneable handle, which can activate, deactivate and shut down the
 /// resolver. When all instances of a handle are dropped, the server will stop.
@@ -57,30 +71,114 @@ pub enum Error {
     GetSocketAddrError(#[error(source)] io::Error),
 }
 
-/// A filtering resolver. Listens on a specified port for DNS queries and responds queries for
-/// `catpive.apple.com`. Can be toggled to unbind, be bound but not respond or bound and responding
-/// to some queries.
+/// How the resolver answers queries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ResolverConfig {
+    /// Only captive portal checks and queries for the allowed host are answered. All other
+    /// queries are refused.
+    Blocking { allowed_host: Option<AllowedHost> },
+    /// Queries are forwarded to the resolvers at `upstreams`, and their answers are cached.
+    Forwarding { upstreams: Vec<IpAddr> },
+}
+
+impl Default for ResolverConfig {
+    fn default() -> Self {
+        ResolverConfig::Blocking { allowed_host: None }
+    }
+}
+
+impl ResolverConfig {
+    /// Returns the configuration for blocking states, in which only `allowed_endpoint` can be
+    /// reached. Its hostname, if known, is resolved to the addresses of its endpoints.
+    pub fn blocking(allowed_endpoint: &AllowedEndpoint) -> Self {
+        let allowed_host = allowed_endpoint.hostname.as_ref().and_then(|hostname| {
+            match Name::from_str(hostname) {
+                Ok(name) => Some(AllowedHost {
+                    name: LowerName::from(name),
+                    addresses: allowed_endpoint
+                        .endpoints
+                        .iter()
+                        .map(|endpoint| endpoint.address)
+                        .collect(),
+                }),
+                Err(error) => {
+                    log::error!(
+                        "Invalid hostname of allowed endpoint {}: {}",
+                        hostname,
+                        error
+                    );
+                    None
+                }
+            }
+        });
+        ResolverConfig::Blocking { allowed_host }
+    }
+}
+
+/// A host that is resolved in blocking states.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct AllowedHost {
+    name: LowerName,
+    addresses: Vec<IpAddr>,
+}
+
+#[derive(Default)]
+struct Counters {
+    served: AtomicU64,
+    blocked: AtomicU64,
+}
+
+impl Counters {
+    fn get(&self) -> ResolverStats {
+        ResolverStats {
+            served: self.served.load(Ordering::Relaxed),
+            blocked: self.blocked.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A filtering resolver. Listens on a specified port for DNS queries and answers them according
+/// to its [`ResolverConfig`]. In blocking states, it responds to queries for `captive.apple.com`
+/// and the allowed host, and in the connected state it can forward queries to the tunnel DNS.
 struct FilteringResolver {
     rx: mpsc::Receiver<ResolverMessage>,
     dns_server: Option<(tokio::task::JoinHandle<()>, oneshot::Receiver<()>)>,
+    config: ResolverConfig,
+    /// Resolver that queries are forwarded to in the forwarding configuration.
+    forwarder: Option<TokioAsyncResolver>,
+    counters: Arc<Counters>,
 }
 
+/// The answer to a query, or the response code to send if there is none.
+type LookupResult = Result<Box<dyn LookupObject>, ResponseCode>;
+
 /// The `FilteringResolver` is an actor responding to DNS queries.
-type ResolverMessage = (LowerQuery, oneshot::Sender<Box<dyn LookupObject>>);
+enum ResolverMessage {
+    /// A query to answer.
+    Query(LowerQuery, oneshot::Sender<LookupResult>),
+    /// Changes how queries are answered.
+    SetConfig(ResolverConfig),
+}
 
 /// A handle to control a filtering resolver. When all resolver handles are dropped, custom
 /// resolver will stop.
 #[derive(Clone)]
 pub(crate) struct ResolverHandle {
-    _tx: Arc<mpsc::Sender<ResolverMessage>>,
+    tx: Arc<mpsc::Sender<ResolverMessage>>,
     listening_port: u16,
+    counters: Arc<Counters>,
 }
 
 impl ResolverHandle {
-    fn new(tx: Arc<mpsc::Sender<ResolverMessage>>, listening_port: u16) -> Self {
+    fn new(
+        tx: Arc<mpsc::Sender<ResolverMessage>>,
+        listening_port: u16,
+        counters: Arc<Counters>,
+    ) -> Self {
         Self {
-            _tx: tx,
+            tx,
             listening_port,
+            counters,
         }
     }
 
@@ -88,6 +186,21 @@ impl ResolverHandle {
     pub fn listening_port(&self) -> u16 {
         self.listening_port
     }
+
+    /// Changes how the resolver answers queries. Queries that have already been received may still
+    /// be answered according to the old configuration.
+    pub fn set_config(&self, config: ResolverConfig) {
+        // Every sender has a guaranteed slot in the channel, so sending on a new sender only fails
+        // if the resolver has stopped
+        let _ = (*self.tx)
+            .clone()
+            .try_send(ResolverMessage::SetConfig(config));
+    }
+
+    /// Returns the number of queries that have been answered so far.
+    pub fn stats(&self) -> ResolverStats {
+        self.counters.get()
+    }
 }
 
 impl FilteringResolver {
@@ -118,20 +231,27 @@ impl FilteringResolver {
 
             let _ = server_done_tx.send(());
         });
+        let counters = Arc::new(Counters::default());
         let resolver = Self {
             rx,
             dns_server: Some((server_handle, server_done_rx)),
+            config: ResolverConfig::default(),
+            forwarder: None,
+            counters: counters.clone(),
         };
 
-        Ok((resolver, ResolverHandle::new(command_tx, port)))
+        Ok((resolver, ResolverHandle::new(command_tx, port, counters)))
     }
 
     /// Runs the filtering resolver as an actor, listening for new queries instances.  When all
     /// related [ResolverHandle] instances are dropped, this function will return, closing the DNS
     /// server.
     async fn run(mut self) {
-        while let Some((query, tx)) = self.rx.next().await {
-            self.resolve(query, tx);
+        while let Some(message) = self.rx.next().await {
+            match message {
+                ResolverMessage::Query(query, tx) => self.resolve(query, tx),
+                ResolverMessage::SetConfig(config) => self.set_config(config),
+            }
         }
 
         if let Some((server_handle, done_rx)) = self.dns_server.take() {
@@ -140,35 +260,143 @@ impl FilteringResolver {
         }
     }
 
-    /// Resolvers a query to nothing or a documentation address
-    fn resolve(&mut self, query: LowerQuery, tx: oneshot::Sender<Box<dyn LookupObject>>) {
-        if !self.allow_query(&query) {
-            let _ = tx.send(Box::new(EmptyLookup) as Box<dyn LookupObject>);
+    fn set_config(&mut self, config: ResolverConfig) {
+        if config == self.config {
             return;
         }
-
-        let return_query = query.original().clone();
-        let mut return_record = Record::with(
-            return_query.name().clone(),
-            return_query.query_type(),
-            TTL_SECONDS,
+        let stats = self.counters.get();
+        log::debug!(
+            "Filtering resolver has served {} and blocked {} queries",
+            stats.served,
+            stats.blocked
         );
-        return_record.set_data(Some(RData::A(RESOLVED_ADDR)));
 
-        let lookup = Lookup::new_with_deadline(
-            return_query,
-            Arc::new([return_record]),
-            Instant::now() + Duration::from_secs(3),
+        self.forwarder = match &config {
+            ResolverConfig::Forwarding { upstreams } => match Self::create_forwarder(upstreams) {
+                Ok(forwarder) => Some(forwarder),
+                Err(error) => {
+                    log::error!(
+                        "{}",
+                        error.display_chain_with_msg("Failed to create forwarding resolver")
+                    );
+                    None
+                }
+            },
+            ResolverConfig::Blocking { .. } => None,
+        };
+        self.config = config;
+    }
+
+    fn create_forwarder(upstreams: &[IpAddr]) -> Result<TokioAsyncResolver, ResolveError> {
+        let config = ForwarderConfig::from_parts(
+            None,
+            vec![],
+            NameServerConfigGroup::from_ips_clear(upstreams, UPSTREAM_PORT, true),
         );
-        let _ = tx.send(Box::new(ForwardLookup(lookup)));
+        let mut options = ResolverOpts::default();
+        options.cache_size = FORWARDER_CACHE_SIZE;
+        TokioAsyncResolver::tokio(config, options)
+    }
+
+    /// Answers a query according to the current configuration.
+    fn resolve(&mut self, query: LowerQuery, tx: oneshot::Sender<LookupResult>) {
+        let allowed_host = match &self.config {
+            ResolverConfig::Blocking { allowed_host } => allowed_host.as_ref(),
+            ResolverConfig::Forwarding { .. } => {
+                self.counters.served.fetch_add(1, Ordering::Relaxed);
+                self.forward(query, tx);
+                return;
+            }
+        };
+
+        let result = Self::resolve_blocked(&query, allowed_host);
+        let counter = if result.is_ok() {
+            &self.counters.served
+        } else {
+            &self.counters.blocked
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        let _ = tx.send(result);
     }
 
-    /// Determines whether a DNS query is allowable. Currently, this implies that the query is
-    /// either a `A`, `AAAA` or a `CNAME` query for `captive.apple.com`.
-    fn allow_query(&self, query: &LowerQuery) -> bool {
+    /// Forwards a query to the upstream resolvers without waiting for the answer.
+    fn forward(&self, query: LowerQuery, tx: oneshot::Sender<LookupResult>) {
+        let forwarder = match &self.forwarder {
+            Some(forwarder) => forwarder.clone(),
+            None => {
+                let _ = tx.send(Err(ResponseCode::ServFail));
+                return;
+            }
+        };
+        tokio::spawn(async move {
+            let result = forwarder
+                .lookup(query.name().clone(), query.query_type(), Default::default())
+                .await
+                .map(|lookup| Box::new(ForwardLookup(lookup)) as Box<dyn LookupObject>)
+                .map_err(|error| match error.kind() {
+                    ResolveErrorKind::NoRecordsFound { response_code, .. } => *response_code,
+                    _ => {
+                        log::debug!("Failed to forward DNS query: {}", error);
+                        ResponseCode::ServFail
+                    }
+                });
+            let _ = tx.send(result);
+        });
+    }
+
+    /// Answers a query in a blocking state. A query for `captive.apple.com` is resolved to a
+    /// documentation address and a query for the allowed host is resolved to its addresses. All
+    /// other queries are refused.
+    fn resolve_blocked(query: &LowerQuery, allowed_host: Option<&AllowedHost>) -> LookupResult {
+        if !ALLOWED_RECORD_TYPES.contains(&query.query_type()) {
+            return Err(ResponseCode::Refused);
+        }
+
         let captive_apple_com: LowerName =
             LowerName::from(Name::from_str(CAPTIVE_PORTAL_DOMAIN).unwrap());
-        ALLOWED_RECORD_TYPES.contains(&query.query_type()) && query.name() == &captive_apple_com
+        if query.name() == &captive_apple_com {
+            return Ok(Self::answer(query, &[RData::A(RESOLVED_ADDR)]));
+        }
+
+        match allowed_host {
+            Some(host) if query.name() == &host.name => {
+                let data: Vec<_> = host
+                    .addresses
+                    .iter()
+                    .filter_map(|address| match (address, query.query_type()) {
+                        (IpAddr::V4(address), RecordType::A) => Some(RData::A(*address)),
+                        (IpAddr::V6(address), RecordType::AAAA) => Some(RData::AAAA(*address)),
+                        _ => None,
+                    })
+                    .collect();
+                Ok(Self::answer(query, &data))
+            }
+            _ => Err(ResponseCode::Refused),
+        }
+    }
+
+    /// Returns an answer to `query` with a record for each item in `data`.
+    fn answer(query: &LowerQuery, data: &[RData]) -> Box<dyn LookupObject> {
+        let return_query = query.original().clone();
+        let records: Vec<_> = data
+            .iter()
+            .map(|data| {
+                let mut record = Record::with(
+                    return_query.name().clone(),
+                    return_query.query_type(),
+                    TTL_SECONDS,
+                );
+                record.set_data(Some(data.clone()));
+                record
+            })
+            .collect();
+
+        let lookup = Lookup::new_with_deadline(
+            return_query,
+            Arc::from(records),
+            Instant::now() + Duration::from_secs(3),
+        );
+        Box::new(ForwardLookup(lookup))
     }
 }
 
@@ -182,6 +410,7 @@ impl ResolverImpl {
     fn build_response<'a>(
         message: &'a MessageRequest,
         lookup: &'a mut Box<dyn LookupObject>,
+        response_code: ResponseCode,
     ) -> MessageResponse<
         'a,
         'a,
@@ -195,6 +424,7 @@ impl ResolverImpl {
         response_header.set_op_code(OpCode::Query);
         response_header.set_message_type(MessageType::Response);
         response_header.set_authoritative(false);
+        response_header.set_response_code(response_code);
 
         MessageResponseBuilder::from_message_request(message).build(
             response_header,
@@ -211,11 +441,21 @@ impl ResolverImpl {
             let mut tx = (&*tx_ref).clone();
             let query = message.query();
             let (lookup_tx, lookup_rx) = oneshot::channel();
-            let _ = tx.send((query.clone(), lookup_tx)).await;
-            let mut lookup_result: Box<dyn LookupObject> = lookup_rx
-                .await
-                .unwrap_or_else(|_| Box::new(EmptyLookup) as Box<dyn LookupObject>);
-            let response = Self::build_response(&message, &mut lookup_result);
+            let _ = tx
+                .send(ResolverMessage::Query(query.clone(), lookup_tx))
+                .await;
+            let (mut lookup, response_code) = match lookup_rx.await {
+                Ok(Ok(lookup)) => (lookup, ResponseCode::NoError),
+                Ok(Err(response_code)) => (
+                    Box::new(EmptyLookup) as Box<dyn LookupObject>,
+                    response_code,
+                ),
+                Err(_) => (
+                    Box::new(EmptyLookup) as Box<dyn LookupObject>,
+                    ResponseCode::ServFail,
+                ),
+            };
+            let response = Self::build_response(&message, &mut lookup, response_code);
 
             if let Err(err) = response_handler.send_response(response).await {
                 log::error!("Failed to send response: {}", err);
@@ -325,6 +565,37 @@ mod test {
         )
     }
 
+    #[test]
+    fn test_allowed_host_lookup() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let handle = rt.block_on(start_resolver());
+        let allowed_address = Ipv4Addr::new(192, 0, 2, 1);
+        handle.set_config(super::ResolverConfig::blocking(&AllowedEndpoint {
+            hostname: Some("api.example.com".to_owned()),
+            endpoints: vec![talpid_types::net::EndpointRange::from(
+                talpid_types::net::Endpoint::new(
+                    allowed_address,
+                    443,
+                    talpid_types::net::TransportProtocol::Tcp,
+                ),
+            )],
+        }));
+        let test_resolver = rt.block_on(get_test_resolver(handle.listening_port()));
+
+        let lookup = rt
+            .block_on(test_resolver.ipv4_lookup("api.example.com."))
+            .expect("Failed to resolve allowed host");
+        assert_eq!(lookup.iter().collect::<Vec<_>>(), vec![&allowed_address]);
+
+        assert!(rt
+            .block_on(test_resolver.ipv4_lookup("example.com."))
+            .is_err());
+        // Refused queries may be retried by the test resolver
+        let stats = handle.stats();
+        assert_eq!(stats.served, 1);
+        assert!(stats.blocked >= 1);
+    }
+
     #[test]
     fn test_shutdown() {
         let rt = tokio::runtime::Runtime::new().unwrap();
@@ -0,0 +1,206 @@
+use crate::routing::RequiredRoute;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use talpid_types::ErrorExt;
+
+/// Name of the journal file in the cache directory.
+pub const JOURNAL_FILENAME: &str = "restore-journal.json";
+
+/// Errors that can happen when reading or writing the journal.
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    /// Failed to read the journal file.
+    #[error(display = "Failed to read restore journal")]
+    Read(#[error(source)] io::Error),
+
+    /// The journal file is not valid.
+    #[error(display = "Failed to parse restore journal")]
+    Parse(#[error(source)] serde_json::Error),
+
+    /// Failed to serialize the journal entries.
+    #[error(display = "Failed to serialize restore journal")]
+    Serialize(#[error(source)] serde_json::Error),
+
+    /// Failed to write the journal file.
+    #[error(display = "Failed to write restore journal")]
+    Write(#[error(source)] io::Error),
+}
+
+/// A change to the system configuration that must be undone if the daemon stops without cleaning
+/// up after itself.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum JournalEntry {
+    /// DNS servers were set for the interface.
+    Dns {
+        /// Name of the interface.
+        interface: String,
+    },
+    /// A route was added to the routing table.
+    Route {
+        /// The route that was added.
+        route: RequiredRoute,
+    },
+    /// A firewall policy was applied.
+    Firewall,
+}
+
+/// On-disk record of what talpid-core has changed in the system configuration. Entries are added
+/// when a change is made and removed when it is undone, so any entries that remain when the journal
+/// is opened were left behind by an instance that crashed.
+///
+/// Failing to update the journal is logged but is otherwise not treated as an error, since it must
+/// never prevent the tunnel from working.
+#[derive(Clone)]
+pub struct RestoreJournal {
+    inner: Arc<Mutex<JournalState>>,
+}
+
+struct JournalState {
+    path: PathBuf,
+    entries: Vec<JournalEntry>,
+}
+
+impl RestoreJournal {
+    /// Opens the journal at `path`. Returns an empty journal along with any entries that were left
+    /// behind by a previous instance. The stale entries are removed from the file on the next
+    /// write, so they must be cleaned up by the caller.
+    pub fn open(path: PathBuf) -> (Self, Vec<JournalEntry>) {
+        let stale_entries = match read_entries(&path) {
+            Ok(entries) => entries,
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Ignoring unreadable restore journal")
+                );
+                vec![]
+            }
+        };
+        let journal = RestoreJournal {
+            inner: Arc::new(Mutex::new(JournalState {
+                path,
+                entries: vec![],
+            })),
+        };
+        (journal, stale_entries)
+    }
+
+    /// Records that a change has been made, unless an identical entry already exists.
+    pub fn record(&self, entry: JournalEntry) {
+        self.record_all(std::iter::once(entry));
+    }
+
+    /// Records several changes at once, skipping entries that already exist.
+    pub fn record_all(&self, entries: impl IntoIterator<Item = JournalEntry>) {
+        let mut state = self.inner.lock();
+        let num_entries = state.entries.len();
+        for entry in entries {
+            if !state.entries.contains(&entry) {
+                state.entries.push(entry);
+            }
+        }
+        if state.entries.len() != num_entries {
+            state.persist();
+        }
+    }
+
+    /// Removes all entries for which `predicate` returns true.
+    pub fn remove(&self, predicate: impl Fn(&JournalEntry) -> bool) {
+        let mut state = self.inner.lock();
+        let num_entries = state.entries.len();
+        state.entries.retain(|entry| !predicate(entry));
+        if state.entries.len() != num_entries {
+            state.persist();
+        }
+    }
+
+    /// Writes the current entries to disk, replacing any stale entries.
+    pub fn flush(&self) {
+        self.inner.lock().persist();
+    }
+
+    /// Returns the entries that are currently recorded.
+    pub fn entries(&self) -> Vec<JournalEntry> {
+        self.inner.lock().entries.clone()
+    }
+}
+
+impl JournalState {
+    fn persist(&self) {
+        if let Err(error) = write_entries(&self.path, &self.entries) {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to update restore journal")
+            );
+        }
+    }
+}
+
+fn read_entries(path: &Path) -> Result<Vec<JournalEntry>, Error> {
+    match fs::read(path) {
+        Ok(contents) => serde_json::from_slice(&contents).map_err(Error::Parse),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+        Err(error) => Err(Error::Read(error)),
+    }
+}
+
+fn write_entries(path: &Path, entries: &[JournalEntry]) -> Result<(), Error> {
+    if entries.is_empty() {
+        return match fs::remove_file(path) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => Err(Error::Write(error)),
+            _ => Ok(()),
+        };
+    }
+
+    // Write to a temporary file first, so that a crash never leaves a partially written journal
+    let temp_path = path.with_extension("tmp");
+    let contents = serde_json::to_vec_pretty(entries).map_err(Error::Serialize)?;
+    fs::write(&temp_path, contents).map_err(Error::Write)?;
+    fs::rename(&temp_path, path).map_err(Error::Write)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_stale_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(JOURNAL_FILENAME);
+
+        let (journal, stale_entries) = RestoreJournal::open(path.clone());
+        assert!(stale_entries.is_empty());
+
+        let dns_entry = JournalEntry::Dns {
+            interface: "wg-mullvad".to_owned(),
+        };
+        journal.record(dns_entry.clone());
+        journal.record(dns_entry.clone());
+        journal.record(JournalEntry::Firewall);
+        journal.remove(|entry| entry == &JournalEntry::Firewall);
+        drop(journal);
+
+        let (journal, stale_entries) = RestoreJournal::open(path.clone());
+        assert_eq!(stale_entries, vec![dns_entry]);
+        assert!(journal.entries().is_empty());
+
+        journal.flush();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_unreadable_journal() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(JOURNAL_FILENAME);
+        fs::write(&path, "not a journal").unwrap();
+
+        let (_journal, stale_entries) = RestoreJournal::open(path);
+        assert!(stale_entries.is_empty());
+    }
+}
@@ -0,0 +1,357 @@
+//! Aggregation of required routes, so that fewer routes have to be added and removed when a large
+//! number of overlapping prefixes are routed through the same node.
+
+use super::{NetNode, RequiredRoute, RouteKind};
+use ipnetwork::{IpNetwork, Ipv4Network, Ipv6Network};
+use std::{
+    collections::{HashMap, HashSet},
+    net::{Ipv4Addr, Ipv6Addr},
+};
+
+/// Returns a set of routes that routes traffic the same way as `routes`, but where adjacent
+/// prefixes with the same node have been merged into a single prefix. In routing tables that only
+/// contain routes added by the route manager, prefixes contained in another prefix with the same
+/// node are also removed.
+///
+/// Elsewhere, routes that are not managed by us must be taken into account. A route for a prefix
+/// in between a contained prefix and the containing one would take precedence if the contained
+/// prefix was removed, and the default route would compete with a merged default route, which is
+/// why `0.0.0.0/1` and `128.0.0.0/1` are used instead.
+///
+/// A prefix is only removed or merged if that does not change which of the given routes is the
+/// most specific match for any destination.
+pub fn aggregate_routes(routes: HashSet<RequiredRoute>) -> HashSet<RequiredRoute> {
+    let num_routes = routes.len();
+    let mut groups: HashMap<RouteGroup, Vec<Prefix>> = HashMap::new();
+    for route in routes {
+        groups
+            .entry(RouteGroup::from(&route))
+            .or_default()
+            .push(Prefix::from(route.prefix));
+    }
+
+    let mut aggregated = HashSet::with_capacity(num_routes);
+    for (group, prefixes) in &groups {
+        let other_prefixes: Vec<Prefix> = groups
+            .iter()
+            .filter(|(other_group, _)| *other_group != group)
+            .flat_map(|(_, prefixes)| prefixes.iter().copied())
+            .collect();
+
+        for prefix in aggregate_prefixes(prefixes.clone(), &other_prefixes, group.is_exclusive()) {
+            aggregated.insert(group.route(prefix.into()));
+        }
+    }
+
+    if aggregated.len() < num_routes {
+        log::debug!(
+            "Aggregated {} required routes into {}",
+            num_routes,
+            aggregated.len()
+        );
+    }
+    aggregated
+}
+
+/// Routes that differ only in their prefix.
+#[derive(Debug, Hash, Eq, PartialEq)]
+struct RouteGroup {
+    node: NetNode,
+    kind: RouteKind,
+    #[cfg(target_os = "linux")]
+    table_id: u32,
+}
+
+impl RouteGroup {
+    /// Returns whether the route manager owns every route in the routing table of the group.
+    fn is_exclusive(&self) -> bool {
+        #[cfg(target_os = "linux")]
+        {
+            self.table_id == crate::linux::TUNNEL_TABLE_ID
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            false
+        }
+    }
+
+    fn route(&self, prefix: IpNetwork) -> RequiredRoute {
+        RequiredRoute {
+            prefix,
+            node: self.node.clone(),
+            kind: self.kind,
+            #[cfg(target_os = "linux")]
+            table_id: self.table_id,
+        }
+    }
+}
+
+impl From<&RequiredRoute> for RouteGroup {
+    fn from(route: &RequiredRoute) -> Self {
+        Self {
+            node: route.node.clone(),
+            kind: route.kind,
+            #[cfg(target_os = "linux")]
+            table_id: route.table_id,
+        }
+    }
+}
+
+fn aggregate_prefixes(
+    mut prefixes: Vec<Prefix>,
+    other_prefixes: &[Prefix],
+    exclusive: bool,
+) -> Vec<Prefix> {
+    prefixes.sort_unstable();
+    prefixes.dedup();
+    loop {
+        let num_prefixes = prefixes.len();
+        if exclusive {
+            remove_contained_prefixes(&mut prefixes, other_prefixes);
+        }
+        merge_adjacent_prefixes(&mut prefixes, other_prefixes, exclusive);
+        if prefixes.len() == num_prefixes {
+            return prefixes;
+        }
+    }
+}
+
+/// Removes prefixes that are contained in a less specific prefix, unless a prefix of another
+/// group lies in between them.
+fn remove_contained_prefixes(prefixes: &mut Vec<Prefix>, other_prefixes: &[Prefix]) {
+    // Less specific prefixes come first
+    prefixes.sort_unstable_by_key(|prefix| (prefix.len, prefix.is_ipv6, prefix.bits));
+
+    let mut kept: Vec<Prefix> = Vec::with_capacity(prefixes.len());
+    for prefix in prefixes.drain(..) {
+        let covered = kept.iter().any(|outer| {
+            outer.contains(&prefix)
+                && !other_prefixes
+                    .iter()
+                    .any(|other| other.len > outer.len && other.contains(&prefix))
+        });
+        if !covered {
+            kept.push(prefix);
+        }
+    }
+    *prefixes = kept;
+}
+
+/// Replaces pairs of prefixes that together make up a less specific prefix with that prefix,
+/// unless another group routes the less specific prefix. Default routes are only created if
+/// `allow_default` is true.
+fn merge_adjacent_prefixes(
+    prefixes: &mut Vec<Prefix>,
+    other_prefixes: &[Prefix],
+    allow_default: bool,
+) {
+    let mut remaining: HashSet<Prefix> = prefixes.iter().copied().collect();
+    let mut merged = Vec::new();
+
+    for prefix in prefixes.iter() {
+        if !remaining.contains(prefix) {
+            continue;
+        }
+        let (sibling, parent) = match (prefix.sibling(), prefix.parent()) {
+            (Some(sibling), Some(parent)) => (sibling, parent),
+            _ => continue,
+        };
+        if (parent.len == 0 && !allow_default) || other_prefixes.contains(&parent) {
+            continue;
+        }
+        if remaining.contains(&sibling) {
+            remaining.remove(prefix);
+            remaining.remove(&sibling);
+            merged.push(parent);
+        }
+    }
+
+    *prefixes = remaining.into_iter().chain(merged).collect();
+    prefixes.sort_unstable();
+}
+
+/// An IPv4 or IPv6 prefix without any host bits set.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Ord, PartialOrd)]
+struct Prefix {
+    is_ipv6: bool,
+    bits: u128,
+    len: u8,
+}
+
+impl Prefix {
+    fn max_len(&self) -> u8 {
+        if self.is_ipv6 {
+            128
+        } else {
+            32
+        }
+    }
+
+    fn mask(&self, len: u8) -> u128 {
+        if len == 0 {
+            0
+        } else {
+            (u128::MAX << (128 - len)) >> (128 - self.max_len())
+        }
+    }
+
+    fn contains(&self, other: &Prefix) -> bool {
+        self.is_ipv6 == other.is_ipv6
+            && self.len <= other.len
+            && other.bits & self.mask(self.len) == self.bits
+    }
+
+    /// Returns the prefix that together with this one makes up the parent prefix.
+    fn sibling(&self) -> Option<Prefix> {
+        if self.len == 0 {
+            return None;
+        }
+        Some(Prefix {
+            bits: self.bits ^ (1 << (self.max_len() - self.len)),
+            ..*self
+        })
+    }
+
+    /// Returns the prefix that is one bit shorter than this one.
+    fn parent(&self) -> Option<Prefix> {
+        let len = self.len.checked_sub(1)?;
+        Some(Prefix {
+            bits: self.bits & self.mask(len),
+            len,
+            ..*self
+        })
+    }
+}
+
+impl From<IpNetwork> for Prefix {
+    fn from(network: IpNetwork) -> Self {
+        let (is_ipv6, bits) = match network.network() {
+            std::net::IpAddr::V4(addr) => (false, u128::from(u32::from(addr))),
+            std::net::IpAddr::V6(addr) => (true, u128::from(addr)),
+        };
+        Prefix {
+            is_ipv6,
+            bits,
+            len: network.prefix(),
+        }
+    }
+}
+
+impl From<Prefix> for IpNetwork {
+    fn from(prefix: Prefix) -> Self {
+        if prefix.is_ipv6 {
+            Ipv6Network::new(Ipv6Addr::from(prefix.bits), prefix.len)
+                .expect("prefix length is valid")
+                .into()
+        } else {
+            Ipv4Network::new(Ipv4Addr::from(prefix.bits as u32), prefix.len)
+                .expect("prefix length is valid")
+                .into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::routing::Node;
+
+    fn route(prefix: &str, node: &Node) -> RequiredRoute {
+        RequiredRoute::new(prefix.parse().unwrap(), node.clone())
+    }
+
+    fn routes(prefixes: &[&str], node: &Node) -> HashSet<RequiredRoute> {
+        prefixes.iter().map(|prefix| route(prefix, node)).collect()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn in_main_table(routes: HashSet<RequiredRoute>) -> HashSet<RequiredRoute> {
+        use netlink_packet_route::rtnl::constants::RT_TABLE_MAIN;
+        routes
+            .into_iter()
+            .map(|route| route.table(u32::from(RT_TABLE_MAIN)))
+            .collect()
+    }
+
+    fn tunnel_node() -> Node {
+        Node::device("tun0".to_owned())
+    }
+
+    fn lan_node() -> Node {
+        Node::address("192.168.1.1".parse().unwrap())
+    }
+
+    #[test]
+    fn test_merge_adjacent() {
+        let node = tunnel_node();
+        let input = routes(
+            &[
+                "10.0.0.0/26",
+                "10.0.0.64/26",
+                "10.0.0.128/25",
+                "fd00::/65",
+                "fd00::8000:0:0:0/65",
+            ],
+            &node,
+        );
+        assert_eq!(
+            aggregate_routes(input),
+            routes(&["10.0.0.0/24", "fd00::/64"], &node)
+        );
+    }
+
+    #[test]
+    fn test_keep_non_siblings() {
+        let node = tunnel_node();
+        // Adjacent, but not two halves of the same prefix
+        let input = routes(&["10.0.0.128/25", "10.0.1.0/25", "10.0.3.0/24"], &node);
+        assert_eq!(aggregate_routes(input.clone()), input);
+    }
+
+    #[test]
+    fn test_do_not_merge_into_other_route() {
+        let mut input = routes(&["10.0.0.0/25", "10.0.0.128/25"], &tunnel_node());
+        input.insert(route("10.0.0.0/24", &lan_node()));
+        assert_eq!(aggregate_routes(input.clone()), input);
+    }
+
+    #[test]
+    fn test_merge_default_route() {
+        let input = routes(&["0.0.0.0/1", "128.0.0.0/1"], &tunnel_node());
+        #[cfg(target_os = "linux")]
+        {
+            // Nothing else is routed in the tunnel table
+            let expected = routes(&["0.0.0.0/0"], &tunnel_node());
+            assert_eq!(aggregate_routes(input.clone()), expected);
+
+            let input = in_main_table(input);
+            assert_eq!(aggregate_routes(input.clone()), input);
+        }
+        #[cfg(not(target_os = "linux"))]
+        assert_eq!(aggregate_routes(input.clone()), input);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_remove_contained() {
+        let node = tunnel_node();
+        let input = routes(&["10.0.0.0/8", "10.1.0.0/16", "10.1.2.3/32"], &node);
+        assert_eq!(
+            aggregate_routes(input.clone()),
+            routes(&["10.0.0.0/8"], &node)
+        );
+
+        // Routes in the main table may be shadowed by routes that we do not manage
+        let input = in_main_table(input);
+        assert_eq!(aggregate_routes(input.clone()), input);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_keep_contained_shadowing_other_route() {
+        // 10.1.2.0/24 must stay, or it would be routed via the LAN node
+        let mut input = routes(&["10.0.0.0/8", "10.1.2.0/24"], &tunnel_node());
+        input.insert(route("10.1.0.0/16", &lan_node()));
+        assert_eq!(aggregate_routes(input.clone()), input);
+    }
+}
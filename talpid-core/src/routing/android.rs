@@ -27,7 +27,8 @@ impl RouteManagerImpl {
                     tx.send(()).map_err(|()| Error)?;
                     break;
                 }
-                RouteManagerCommand::AddRoutes(_routes, tx) => {
+                RouteManagerCommand::AddRoutes(_routes, tx)
+                | RouteManagerCommand::RemoveRoutes(_routes, tx) => {
                     let _ = tx.send(Ok(()));
                 }
                 RouteManagerCommand::ClearRoutes => (),
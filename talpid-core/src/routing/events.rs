@@ -0,0 +1,158 @@
+//! Structured events for the changes that the route manager makes to the routing table.
+//!
+//! Every event is logged, counted and sent to the subscribers returned by [`subscribe`]. Events
+//! are tagged with the connection attempt that was in progress when the change was made, so that
+//! churn can be attributed to the attempt that caused it.
+
+use super::Route;
+use crate::logging;
+use futures::channel::mpsc;
+use ipnetwork::IpNetwork;
+use parking_lot::Mutex;
+use std::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use talpid_types::tunnel::ConnectionAttemptId;
+
+/// What the route manager did to a route.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouteEventKind {
+    /// A route was added.
+    Added,
+    /// A route replaced another route to the same destination that the route manager had added.
+    Replaced,
+    /// A route through the default node was added again because the default route changed.
+    Repaired,
+    /// A route was deleted.
+    Deleted,
+}
+
+impl fmt::Display for RouteEventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            RouteEventKind::Added => "added",
+            RouteEventKind::Replaced => "replaced",
+            RouteEventKind::Repaired => "repaired",
+            RouteEventKind::Deleted => "deleted",
+        };
+        f.write_str(kind)
+    }
+}
+
+/// A change made to the routing table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteEvent {
+    pub kind: RouteEventKind,
+    /// Destination of the route.
+    pub prefix: IpNetwork,
+    /// Node that the route goes through. `None` if only the destination is known, e.g. when a
+    /// route is deleted by its destination.
+    pub node: Option<super::Node>,
+    /// Connection attempt that was in progress when the change was made, if any.
+    pub connection_attempt: Option<ConnectionAttemptId>,
+}
+
+impl fmt::Display for RouteEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Route {}: {}", self.kind, self.prefix)?;
+        if let Some(node) = &self.node {
+            write!(f, " via {}", node)?;
+        }
+        Ok(())
+    }
+}
+
+/// Number of route events of each kind since the process started.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RouteMetrics {
+    pub added: u64,
+    pub replaced: u64,
+    pub repaired: u64,
+    pub deleted: u64,
+}
+
+impl RouteMetrics {
+    /// Returns the number of events that happened since `earlier` was taken.
+    pub fn since(&self, earlier: &RouteMetrics) -> RouteMetrics {
+        RouteMetrics {
+            added: self.added.saturating_sub(earlier.added),
+            replaced: self.replaced.saturating_sub(earlier.replaced),
+            repaired: self.repaired.saturating_sub(earlier.repaired),
+            deleted: self.deleted.saturating_sub(earlier.deleted),
+        }
+    }
+}
+
+static ADDED: AtomicU64 = AtomicU64::new(0);
+static REPLACED: AtomicU64 = AtomicU64::new(0);
+static REPAIRED: AtomicU64 = AtomicU64::new(0);
+static DELETED: AtomicU64 = AtomicU64::new(0);
+
+lazy_static::lazy_static! {
+    static ref SUBSCRIBERS: Mutex<Vec<mpsc::UnboundedSender<RouteEvent>>> = Mutex::new(vec![]);
+}
+
+/// Returns a channel that receives every route event from now on. Dropping the receiver ends the
+/// subscription.
+pub fn subscribe() -> mpsc::UnboundedReceiver<RouteEvent> {
+    let (tx, rx) = mpsc::unbounded();
+    SUBSCRIBERS.lock().push(tx);
+    rx
+}
+
+/// Returns the number of route events of each kind so far.
+pub fn metrics() -> RouteMetrics {
+    RouteMetrics {
+        added: ADDED.load(Ordering::Relaxed),
+        replaced: REPLACED.load(Ordering::Relaxed),
+        repaired: REPAIRED.load(Ordering::Relaxed),
+        deleted: DELETED.load(Ordering::Relaxed),
+    }
+}
+
+/// Records a change made to `route`.
+pub(crate) fn record_route(kind: RouteEventKind, route: &Route) {
+    record(kind, route.prefix, Some(route.node.clone()));
+}
+
+/// Records a change made to a route to `prefix` through `node`, if it is known.
+pub(crate) fn record(kind: RouteEventKind, prefix: IpNetwork, node: Option<super::Node>) {
+    let counter = match kind {
+        RouteEventKind::Added => &ADDED,
+        RouteEventKind::Replaced => &REPLACED,
+        RouteEventKind::Repaired => &REPAIRED,
+        RouteEventKind::Deleted => &DELETED,
+    };
+    counter.fetch_add(1, Ordering::Relaxed);
+
+    let event = RouteEvent {
+        kind,
+        prefix,
+        node,
+        connection_attempt: logging::current_connection_attempt(),
+    };
+    log::debug!("{}", event);
+    SUBSCRIBERS
+        .lock()
+        .retain(|subscriber| subscriber.unbounded_send(event.clone()).is_ok());
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_record_route_event() {
+        let mut events = subscribe();
+        let before = metrics();
+
+        let prefix = "10.0.0.0/8".parse().unwrap();
+        record(RouteEventKind::Repaired, prefix, None);
+
+        assert_eq!(metrics().since(&before).repaired, 1);
+        let event = events.try_next().unwrap().expect("no event was received");
+        assert_eq!(event.kind, RouteEventKind::Repaired);
+        assert_eq!(event.prefix, prefix);
+    }
+}
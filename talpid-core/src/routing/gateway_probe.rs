@@ -0,0 +1,199 @@
+//! Checks whether a gateway responds to ARP or NDP.
+//!
+//! A network with stale DHCP information may leave a default route through a gateway that is no
+//! longer there. Connecting through it fails in a way that looks like a problem with the relay,
+//! so the gateway can be probed before connecting. The kernel is asked to resolve the gateway, and
+//! its entry in the neighbour table is then watched until it is either resolved or has failed.
+//! Entries that the kernel has resolved before and not yet found to be dead, i.e. stale entries,
+//! are considered reachable, so that connecting is not delayed by the kernel's probe delay.
+
+use super::Node;
+use futures::{future::abortable, StreamExt};
+use netlink_packet_core::constants::*;
+use netlink_packet_route::{
+    rtnl::{
+        constants::{
+            NTF_USE, NUD_DELAY, NUD_FAILED, NUD_NOARP, NUD_PERMANENT, NUD_PROBE, NUD_REACHABLE,
+            NUD_STALE,
+        },
+        neighbour::nlas::Nla as NeighbourNla,
+        NeighbourMessage, RtnlMessage,
+    },
+    NetlinkMessage, NetlinkPayload,
+};
+use std::{
+    io,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+/// How long to wait for the gateway to be resolved. The kernel gives up after three probes sent
+/// a second apart by default.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often to check the state of the neighbour table entry.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Neighbour states in which the gateway is considered reachable.
+const REACHABLE_STATES: u16 =
+    NUD_REACHABLE | NUD_STALE | NUD_DELAY | NUD_PROBE | NUD_PERMANENT | NUD_NOARP;
+
+/// Errors that can happen while probing a gateway.
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    /// Failed to obtain the index of the interface of the gateway.
+    #[error(display = "Failed to obtain the index of interface {}", _0)]
+    InterfaceIndex(String, #[error(source)] nix::Error),
+
+    /// Failed to open a netlink connection.
+    #[error(display = "Failed to connect to netlink")]
+    Connect(#[error(source)] io::Error),
+
+    /// A netlink request failed.
+    #[error(display = "Netlink request failed")]
+    Netlink(#[error(source)] rtnetlink::Error),
+}
+
+/// Result of probing the gateway of a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GatewayReachability {
+    /// The gateway responded.
+    Reachable(IpAddr),
+    /// The gateway did not respond.
+    Unreachable(IpAddr),
+    /// The node is directly connected, so there is no gateway to probe.
+    NoGateway,
+}
+
+/// Probes the gateway of `node`, if it has one.
+pub async fn probe(node: &Node) -> Result<GatewayReachability, Error> {
+    let (gateway, device) = match (node.get_address(), node.get_device()) {
+        (Some(gateway), Some(device)) => (gateway, device),
+        _ => return Ok(GatewayReachability::NoGateway),
+    };
+    let index = nix::net::if_::if_nametoindex(device)
+        .map_err(|error| Error::InterfaceIndex(device.to_owned(), error))?;
+
+    let (connection, mut handle, _) = rtnetlink::new_connection().map_err(Error::Connect)?;
+    let (connection, abort_handle) = abortable(connection);
+    tokio::spawn(connection);
+
+    let result = probe_neighbour(&mut handle, index, gateway).await;
+    abort_handle.abort();
+
+    if result? {
+        Ok(GatewayReachability::Reachable(gateway))
+    } else {
+        Ok(GatewayReachability::Unreachable(gateway))
+    }
+}
+
+async fn probe_neighbour(
+    handle: &mut rtnetlink::Handle,
+    index: u32,
+    gateway: IpAddr,
+) -> Result<bool, Error> {
+    // Ask the kernel to resolve the gateway, as if a packet was sent to it. Sending an actual
+    // packet would not work, since the firewall may block it before it is routed.
+    let mut use_message = neighbour_message(index, gateway);
+    use_message.header.flags = NTF_USE;
+    request(
+        handle,
+        RtnlMessage::NewNeighbour(use_message),
+        NLM_F_REQUEST | NLM_F_ACK | NLM_F_CREATE,
+    )
+    .await?;
+
+    let deadline = Instant::now() + PROBE_TIMEOUT;
+    loop {
+        match neighbour_state(handle, index, gateway).await? {
+            Some(state) if state & REACHABLE_STATES != 0 => return Ok(true),
+            Some(state) if state & NUD_FAILED != 0 => return Ok(false),
+            // The gateway is still being resolved
+            _ => (),
+        }
+        if Instant::now() >= deadline {
+            return Ok(false);
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Returns the state of the neighbour table entry of `gateway`, if there is one.
+async fn neighbour_state(
+    handle: &mut rtnetlink::Handle,
+    index: u32,
+    gateway: IpAddr,
+) -> Result<Option<u16>, Error> {
+    let mut dump_message = NeighbourMessage::default();
+    dump_message.header.family = address_family(gateway);
+    let messages = request(
+        handle,
+        RtnlMessage::GetNeighbour(dump_message),
+        NLM_F_REQUEST | NLM_F_DUMP,
+    )
+    .await?;
+
+    let destination = ip_to_bytes(gateway);
+    Ok(messages.into_iter().find_map(|message| match message {
+        RtnlMessage::NewNeighbour(neighbour)
+            if neighbour.header.ifindex == index && has_destination(&neighbour, &destination) =>
+        {
+            Some(neighbour.header.state)
+        }
+        _ => None,
+    }))
+}
+
+fn has_destination(neighbour: &NeighbourMessage, destination: &[u8]) -> bool {
+    neighbour.nlas.iter().any(|nla| match nla {
+        NeighbourNla::Destination(address) => address.as_slice() == destination,
+        _ => false,
+    })
+}
+
+fn neighbour_message(index: u32, gateway: IpAddr) -> NeighbourMessage {
+    let mut message = NeighbourMessage::default();
+    message.header.family = address_family(gateway);
+    message.header.ifindex = index;
+    message
+        .nlas
+        .push(NeighbourNla::Destination(ip_to_bytes(gateway)));
+    message
+}
+
+async fn request(
+    handle: &mut rtnetlink::Handle,
+    message: RtnlMessage,
+    flags: u16,
+) -> Result<Vec<RtnlMessage>, Error> {
+    let mut request = NetlinkMessage::from(message);
+    request.header.flags = flags;
+    let mut response = handle.request(request).map_err(Error::Netlink)?;
+
+    let mut messages = vec![];
+    while let Some(message) = response.next().await {
+        match message.payload {
+            NetlinkPayload::InnerMessage(message) => messages.push(message),
+            NetlinkPayload::Error(error) => {
+                return Err(Error::Netlink(rtnetlink::Error::NetlinkError(error)))
+            }
+            _ => (),
+        }
+    }
+    Ok(messages)
+}
+
+fn address_family(address: IpAddr) -> u8 {
+    match address {
+        IpAddr::V4(_) => libc::AF_INET as u8,
+        IpAddr::V6(_) => libc::AF_INET6 as u8,
+    }
+}
+
+fn ip_to_bytes(address: IpAddr) -> Vec<u8> {
+    match address {
+        IpAddr::V4(address) => address.octets().to_vec(),
+        IpAddr::V6(address) => address.octets().to_vec(),
+    }
+}
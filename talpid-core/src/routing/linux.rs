@@ -1,14 +1,19 @@
 use crate::routing::{
+    events::{self, RouteEventKind},
     imp::{CallbackMessage, RouteManagerCommand},
     NetNode, Node, RequiredRoute, Route,
 };
 use netlink_sys::AsyncSocket;
 use std::{
     collections::{BTreeMap, HashSet},
-    io,
+    fs, io,
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    path::Path,
+};
+use talpid_types::{
+    net::{PhysicalInterfaceDetails, PhysicalInterfaceKind},
+    ErrorExt,
 };
-use talpid_types::ErrorExt;
 
 use futures::{
     channel::mpsc::{UnboundedReceiver, UnboundedSender},
@@ -18,7 +23,8 @@ use futures::{
 use ipnetwork::IpNetwork;
 use lazy_static::lazy_static;
 use netlink_packet_route::{
-    constants::{ARPHRD_LOOPBACK, FIB_RULE_INVERT, FR_ACT_TO_TBL, NLM_F_REQUEST},
+    address::{nlas::Nla as AddressNla, AddressMessage},
+    constants::{ARPHRD_ETHER, ARPHRD_LOOPBACK, FIB_RULE_INVERT, FR_ACT_TO_TBL, NLM_F_REQUEST},
     link::{nlas::Nla as LinkNla, LinkMessage},
     route::{nlas::Nla as RouteNla, RouteHeader, RouteMessage},
     rtnl::{
@@ -32,7 +38,10 @@ use netlink_packet_route::{
     NetlinkMessage, NetlinkPayload, RtnlMessage,
 };
 use rtnetlink::{
-    constants::{RTMGRP_IPV4_ROUTE, RTMGRP_IPV6_ROUTE, RTMGRP_LINK, RTMGRP_NOTIFY},
+    constants::{
+        RTMGRP_IPV4_IFADDR, RTMGRP_IPV4_ROUTE, RTMGRP_IPV6_IFADDR, RTMGRP_IPV6_ROUTE, RTMGRP_LINK,
+        RTMGRP_NOTIFY,
+    },
     sys::SocketAddr,
     Handle, IpVersion,
 };
@@ -81,6 +90,11 @@ lazy_static! {
     ];
 }
 
+/// Destinations used to look up the default routes. These are reserved for documentation, so
+/// they are routed through the default routes.
+const DEFAULT_ROUTE_PROBE_V4: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+const DEFAULT_ROUTE_PROBE_V6: IpAddr = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 /// Errors that can happen in the Linux routing integration
@@ -143,7 +157,12 @@ impl RouteManagerImpl {
         let (mut connection, handle, messages) =
             rtnetlink::new_connection().map_err(Error::Connect)?;
 
-        let mgroup_flags = RTMGRP_IPV4_ROUTE | RTMGRP_IPV6_ROUTE | RTMGRP_LINK | RTMGRP_NOTIFY;
+        let mgroup_flags = RTMGRP_IPV4_ROUTE
+            | RTMGRP_IPV6_ROUTE
+            | RTMGRP_LINK
+            | RTMGRP_IPV4_IFADDR
+            | RTMGRP_IPV6_IFADDR
+            | RTMGRP_NOTIFY;
         let addr = SocketAddr::new(0, mgroup_flags);
         connection
             .socket_mut()
@@ -293,6 +312,23 @@ impl RouteManagerImpl {
         Ok(())
     }
 
+    async fn remove_required_routes(
+        &mut self,
+        required_routes: HashSet<RequiredRoute>,
+    ) -> Result<()> {
+        for route in required_routes {
+            match route.node {
+                NetNode::RealNode(node) => {
+                    let route = Route::new(node, route.prefix).table(route.table_id);
+                    self.delete_route_if_exists(&route).await?;
+                    self.added_routes.remove(&route);
+                    events::record_route(RouteEventKind::Deleted, &route);
+                }
+            }
+        }
+        Ok(())
+    }
+
     async fn initialize_link_map(
         handle: &rtnetlink::Handle,
     ) -> Result<BTreeMap<u32, NetworkInterface>> {
@@ -321,8 +357,9 @@ impl RouteManagerImpl {
 
     async fn cleanup_routes(&mut self) {
         for route in self.added_routes.drain().collect::<Vec<_>>().iter() {
-            if let Err(e) = self.delete_route_if_exists(route).await {
-                log::error!("Failed to remove route: {}: {}", route, e);
+            match self.delete_route_if_exists(route).await {
+                Ok(()) => events::record_route(RouteEventKind::Deleted, route),
+                Err(e) => log::error!("Failed to remove route: {}: {}", route, e),
             }
         }
     }
@@ -359,6 +396,10 @@ impl RouteManagerImpl {
                 log::debug!("Adding routes: {:?}", routes);
                 let _ = result_tx.send(self.add_required_routes(routes.clone()).await);
             }
+            RouteManagerCommand::RemoveRoutes(routes, result_tx) => {
+                log::debug!("Removing routes: {:?}", routes);
+                let _ = result_tx.send(self.remove_required_routes(routes).await);
+            }
             RouteManagerCommand::CreateRoutingRules(enable_ipv6, result_tx) => {
                 let _ = result_tx.send(self.create_routing_rules(enable_ipv6).await);
             }
@@ -374,6 +415,9 @@ impl RouteManagerImpl {
             RouteManagerCommand::GetMtuForRoute(ip, result_tx) => {
                 let _ = result_tx.send(self.get_mtu_for_route(ip).await);
             }
+            RouteManagerCommand::GetInterfaceNode(interface, destination, result_tx) => {
+                let _ = result_tx.send(self.get_interface_node(&interface, destination).await);
+            }
             RouteManagerCommand::ClearRoutes => {
                 log::debug!("Clearing routes");
                 self.cleanup_routes().await;
@@ -385,14 +429,22 @@ impl RouteManagerImpl {
     async fn process_netlink_message(&mut self, msg: NetlinkMessage<RtnlMessage>) -> Result<()> {
         match msg.payload {
             NetlinkPayload::InnerMessage(RtnlMessage::NewLink(new_link)) => {
+                let index = new_link.header.index;
                 if let Some((idx, name)) = Self::map_interface(new_link) {
                     self.iface_map.insert(idx, name);
                 }
+                self.notify_change_listeners(CallbackMessage::LinkChange(index));
             }
             NetlinkPayload::InnerMessage(RtnlMessage::DelLink(old_link)) => {
+                let index = old_link.header.index;
                 if let Some((idx, _)) = Self::map_interface(old_link) {
                     self.iface_map.remove(&idx);
                 }
+                self.notify_change_listeners(CallbackMessage::LinkChange(index));
+            }
+            NetlinkPayload::InnerMessage(RtnlMessage::NewAddress(address))
+            | NetlinkPayload::InnerMessage(RtnlMessage::DelAddress(address)) => {
+                self.notify_change_listeners(CallbackMessage::AddressChange(address.header.index));
             }
             NetlinkPayload::InnerMessage(RtnlMessage::NewRoute(new_route)) => {
                 if let Some(addition) = self.parse_route_message(new_route)? {
@@ -707,6 +759,18 @@ impl RouteManagerImpl {
 
     async fn add_route(&mut self, route: Route) -> Result<()> {
         self.add_route_direct(route.clone()).await?;
+        // Routes are added with `NLM_F_REPLACE`, so a route to the same destination in the same
+        // table is replaced
+        let kind = if self
+            .added_routes
+            .iter()
+            .any(|added| added.prefix == route.prefix && added.table_id == route.table_id)
+        {
+            RouteEventKind::Replaced
+        } else {
+            RouteEventKind::Added
+        };
+        events::record_route(kind, &route);
         self.added_routes.insert(route);
         Ok(())
     }
@@ -794,21 +858,32 @@ impl RouteManagerImpl {
         destination: &IpAddr,
         set_mark: bool,
     ) -> Result<Option<Route>> {
-        let mut request = self.handle.route().get(get_ip_version(destination));
-        let octets = match destination {
-            IpAddr::V4(address) => address.octets().to_vec(),
-            IpAddr::V6(address) => address.octets().to_vec(),
+        self.get_route(destination, set_mark, None).await
+    }
+
+    /// Looks up the route that marked traffic to `destination` would take if it was forced out
+    /// through `interface`.
+    async fn get_interface_node(
+        &self,
+        interface: &str,
+        destination: IpAddr,
+    ) -> Result<Option<Node>> {
+        let iface_idx = match self.find_iface_idx(interface) {
+            Some(idx) => idx,
+            None => return Ok(None),
         };
-        let message = request.message_mut();
-        if set_mark {
-            message
-                .nlas
-                .push(RouteNla::Mark(crate::linux::TUNNEL_FW_MARK));
-        }
-        message.header.destination_prefix_length = 8u8 * (octets.len() as u8);
-        message.header.flags = RouteFlags::RTM_F_FIB_MATCH;
-        message.nlas.push(RouteNla::Destination(octets));
-        let mut stream = execute_route_get_request(self.handle.clone(), message.clone());
+        let route = self.get_route(&destination, true, Some(iface_idx)).await?;
+        Ok(route.map(|route| route.get_node().clone()))
+    }
+
+    async fn get_route(
+        &self,
+        destination: &IpAddr,
+        set_mark: bool,
+        output_iface: Option<u32>,
+    ) -> Result<Option<Route>> {
+        let message = route_get_message(&self.handle, destination, set_mark, output_iface);
+        let mut stream = execute_route_get_request(self.handle.clone(), message);
         match stream.try_next().await {
             Ok(Some(route_msg)) => self.parse_route_message(route_msg),
             Ok(None) => Err(Error::NoRoute),
@@ -820,6 +895,136 @@ impl RouteManagerImpl {
     }
 }
 
+/// Lists the network interfaces that are backed by hardware. A separate netlink connection is
+/// used, so this does not require a route manager.
+pub async fn get_physical_interfaces() -> Result<Vec<PhysicalInterfaceDetails>> {
+    let (connection, handle, _) = rtnetlink::new_connection().map_err(Error::Connect)?;
+    tokio::spawn(connection);
+
+    let mut interfaces = BTreeMap::new();
+    let mut link_request = handle.link().get().execute();
+    while let Some(link) = link_request.try_next().await.map_err(Error::Netlink)? {
+        if let Some(interface) = physical_interface_from_link(link) {
+            interfaces.insert(interface.index, interface);
+        }
+    }
+
+    let mut address_request = handle.address().get().execute();
+    while let Some(address) = address_request.try_next().await.map_err(Error::Netlink)? {
+        if let Some(interface) = interfaces.get_mut(&address.header.index) {
+            interface.addresses.extend(network_from_address(address));
+        }
+    }
+
+    for destination in [DEFAULT_ROUTE_PROBE_V4, DEFAULT_ROUTE_PROBE_V6] {
+        if let Some(index) = get_default_route_interface(&handle, destination).await? {
+            if let Some(interface) = interfaces.get_mut(&index) {
+                interface.is_default_route = true;
+            }
+        }
+    }
+
+    Ok(interfaces.into_values().collect())
+}
+
+/// Returns the interface if it is backed by a device, i.e. if it is not virtual.
+fn physical_interface_from_link(link: LinkMessage) -> Option<PhysicalInterfaceDetails> {
+    let mut name = None;
+    let mut mtu = None;
+    for nla in link.nlas {
+        match nla {
+            LinkNla::IfName(link_name) => name = Some(link_name),
+            LinkNla::Mtu(link_mtu) => mtu = Some(link_mtu),
+            _ => (),
+        }
+    }
+    let name = name?;
+
+    let sysfs_path = Path::new("/sys/class/net").join(&name);
+    if !sysfs_path.join("device").exists() {
+        return None;
+    }
+    let is_wwan = fs::read_to_string(sysfs_path.join("uevent"))
+        .map(|uevent| uevent.lines().any(|line| line == "DEVTYPE=wwan"))
+        .unwrap_or(false);
+    let kind = if sysfs_path.join("wireless").exists() || sysfs_path.join("phy80211").exists() {
+        PhysicalInterfaceKind::Wireless
+    } else if is_wwan {
+        PhysicalInterfaceKind::Cellular
+    } else if link.header.link_layer_type == ARPHRD_ETHER {
+        PhysicalInterfaceKind::Ethernet
+    } else {
+        PhysicalInterfaceKind::Other
+    };
+
+    Some(PhysicalInterfaceDetails {
+        name,
+        index: link.header.index,
+        kind,
+        addresses: vec![],
+        is_default_route: false,
+        mtu,
+    })
+}
+
+fn network_from_address(address: AddressMessage) -> Option<IpNetwork> {
+    let mut local = None;
+    let mut peer = None;
+    for nla in address.nlas {
+        match nla {
+            AddressNla::Local(bytes) => local = Some(bytes),
+            AddressNla::Address(bytes) => peer = Some(bytes),
+            _ => (),
+        }
+    }
+    // For point-to-point links, `IFA_ADDRESS` is the address of the peer
+    let ip = RouteManagerImpl::parse_ip(&local.or(peer)?).ok()?;
+    IpNetwork::new(ip, address.header.prefix_len).ok()
+}
+
+/// Returns the index of the interface that the default route for `destination` leaves through.
+/// The lookup is done as if the traffic was marked, so that routes through the tunnel are
+/// ignored.
+async fn get_default_route_interface(handle: &Handle, destination: IpAddr) -> Result<Option<u32>> {
+    let message = route_get_message(handle, &destination, true, None);
+    let mut stream = execute_route_get_request(handle.clone(), message);
+    match stream.try_next().await {
+        Ok(Some(route_msg)) => Ok(route_msg.nlas.iter().find_map(|nla| match nla {
+            RouteNla::Oif(index) => Some(*index),
+            _ => None,
+        })),
+        Ok(None) => Ok(None),
+        Err(rtnetlink::Error::NetlinkError(nl_err)) if nl_err.code == -libc::ENETUNREACH => {
+            Ok(None)
+        }
+        Err(err) => Err(Error::GetRoute(err)),
+    }
+}
+
+/// Creates a request for the route that traffic to `destination` would take.
+fn route_get_message(
+    handle: &Handle,
+    destination: &IpAddr,
+    set_mark: bool,
+    output_iface: Option<u32>,
+) -> RouteMessage {
+    let mut request = handle.route().get(get_ip_version(destination));
+    let octets = ip_to_bytes(*destination);
+    let message = request.message_mut();
+    if set_mark {
+        message
+            .nlas
+            .push(RouteNla::Mark(crate::linux::TUNNEL_FW_MARK));
+    }
+    if let Some(iface_idx) = output_iface {
+        message.nlas.push(RouteNla::Oif(iface_idx));
+    }
+    message.header.destination_prefix_length = 8u8 * (octets.len() as u8);
+    message.header.flags = RouteFlags::RTM_F_FIB_MATCH;
+    message.nlas.push(RouteNla::Destination(octets));
+    message.clone()
+}
+
 fn ip_to_bytes(addr: IpAddr) -> Vec<u8> {
     match addr {
         IpAddr::V4(addr) => addr.octets().to_vec(),
@@ -1,4 +1,8 @@
-use crate::routing::{imp::RouteManagerCommand, NetNode, Node, RequiredRoute, Route};
+use crate::routing::{
+    events::{self, RouteEventKind},
+    imp::RouteManagerCommand,
+    NetNode, Node, RequiredRoute, Route,
+};
 
 use futures::{
     channel::mpsc,
@@ -7,12 +11,15 @@ use futures::{
 };
 use ipnetwork::IpNetwork;
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
+    ffi::{CStr, CString},
     io,
-    net::IpAddr,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
     process::{ExitStatus, Stdio},
+    ptr,
 };
-use talpid_types::net::IpVersion;
+use system_configuration::network_configuration::{self, SCNetworkInterfaceType};
+use talpid_types::net::{IpVersion, PhysicalInterfaceDetails, PhysicalInterfaceKind};
 use tokio::{io::AsyncBufReadExt, process::Command};
 use tokio_stream::wrappers::LinesStream;
 
@@ -41,6 +48,10 @@ pub enum Error {
     /// Unexpected output from netstat
     #[error(display = "Unexpected output from netstat")]
     BadOutputFromNetstat,
+
+    /// Failed to obtain the addresses of the network interfaces.
+    #[error(display = "Failed to obtain interface addresses")]
+    GetInterfaceAddresses(#[error(source)] io::Error),
 }
 
 /// Route manager can be in 1 of 4 states -
@@ -101,9 +112,21 @@ impl RouteManagerImpl {
                             let result = self.add_required_routes(routes).await;
                             let _ = result_tx.send(result);
                         },
+                        Some(RouteManagerCommand::RemoveRoutes(routes, result_tx)) => {
+                            let result = Self::remove_required_routes(routes).await;
+                            let _ = result_tx.send(result);
+                        },
                         Some(RouteManagerCommand::ClearRoutes) => {
                             self.cleanup_routes().await;
                         },
+                        Some(RouteManagerCommand::GetInterfaceNode(
+                            interface,
+                            destination,
+                            result_tx,
+                        )) => {
+                            let result = Self::get_interface_node(&interface, destination).await;
+                            let _ = result_tx.send(result);
+                        },
                         None => {
                             break;
                         }
@@ -147,7 +170,9 @@ impl RouteManagerImpl {
         }
 
         for route in routes_to_apply {
-            Self::add_route(&route).await?;
+            if Self::add_route(&route).await?.success() {
+                events::record_route(RouteEventKind::Added, &route);
+            }
             self.applied_routes.insert(route);
         }
 
@@ -155,7 +180,9 @@ impl RouteManagerImpl {
             match (&self.v4_gateway, &self.v6_gateway, destination.is_ipv4()) {
                 (Some(gateway), _, true) | (_, Some(gateway), false) => {
                     let route = Route::new(gateway.clone(), *destination);
-                    Self::add_route(&route).await?;
+                    if Self::add_route(&route).await?.success() {
+                        events::record_route(RouteEventKind::Added, &route);
+                    }
                     self.applied_routes.insert(route);
                 }
                 _ => (),
@@ -167,6 +194,17 @@ impl RouteManagerImpl {
         Ok(())
     }
 
+    async fn remove_required_routes(required_routes: HashSet<RequiredRoute>) -> Result<()> {
+        for route in required_routes {
+            if Self::delete_route(route.prefix).await?.success() {
+                events::record(RouteEventKind::Deleted, route.prefix, None);
+            } else {
+                log::debug!("Route to {} was not present", route.prefix);
+            }
+        }
+        Ok(())
+    }
+
     // Retrieves the node that's currently used to reach 0.0.0.0/0
     pub(crate) async fn get_default_node(ip_version: IpVersion) -> Result<Option<Node>> {
         let ip_version_arg = match ip_version {
@@ -175,7 +213,43 @@ impl RouteManagerImpl {
         };
         let mut cmd = Command::new("route");
         cmd.arg("-n").arg("get").arg(ip_version_arg).arg("default");
+        Self::get_route_node(cmd).await
+    }
+
+    // Retrieves the node of the route to exactly `prefix`, if there is one
+    pub(crate) async fn get_prefix_node(prefix: IpNetwork) -> Result<Option<Node>> {
+        let mut cmd = Command::new("route");
+        cmd.arg("-n")
+            .arg("get")
+            .arg(ip_vers(prefix))
+            .arg("-net")
+            .arg(prefix.to_string())
+            .stderr(Stdio::null());
+        Self::get_route_node(cmd).await
+    }
+
+    // Retrieves the node used to reach `destination` when scoped to the given interface
+    async fn get_interface_node(interface: &str, destination: IpAddr) -> Result<Option<Node>> {
+        let ip_version_arg = if destination.is_ipv4() {
+            "-inet"
+        } else {
+            "-inet6"
+        };
+        let mut cmd = Command::new("route");
+        cmd.arg("-n")
+            .arg("get")
+            .arg("-ifscope")
+            .arg(interface)
+            .arg(ip_version_arg)
+            .arg(destination.to_string())
+            .stderr(Stdio::null());
+
+        let node = Self::get_route_node(cmd).await?;
+        // Without a matching scoped route, `route` may report the route of another interface
+        Ok(node.filter(|node| node.get_device() == Some(interface)))
+    }
 
+    async fn get_route_node(mut cmd: Command) -> Result<Option<Node>> {
         let output = cmd.output().await.map_err(Error::FailedToRunRoute)?;
         let output = String::from_utf8(output.stdout).map_err(|e| {
             log::error!("Failed to parse utf-8 bytes from output of netstat: {}", e);
@@ -260,7 +334,9 @@ impl RouteManagerImpl {
         for destination in destinations_to_remove {
             match Self::delete_route(*destination).await {
                 Ok(status) => {
-                    if !status.success() {
+                    if status.success() {
+                        events::record(RouteEventKind::Deleted, *destination, None);
+                    } else {
                         log::debug!("Failed to remove route during shutdown");
                     }
                 }
@@ -272,18 +348,26 @@ impl RouteManagerImpl {
     async fn apply_new_default_route(&self, new_node: &Option<Node>, v4: bool) {
         for destination in self.default_destinations.iter() {
             if destination.is_ipv4() == v4 {
-                let _ = Self::delete_route(*destination).await;
+                let deleted = matches!(
+                    Self::delete_route(*destination).await,
+                    Ok(status) if status.success()
+                );
 
                 if let Some(node) = new_node {
                     log::error!("Resetting default route for {}", destination);
-                    match Self::add_route(&Route::new(node.clone(), *destination)).await {
+                    let route = Route::new(node.clone(), *destination);
+                    match Self::add_route(&route).await {
                         Ok(status) => {
-                            if !status.success() {
+                            if status.success() {
+                                events::record_route(RouteEventKind::Repaired, &route);
+                            } else {
                                 log::error!("Failed to reapply route");
                             }
                         }
                         Err(e) => log::error!("Failed to reset route: {}", e),
                     }
+                } else if deleted {
+                    events::record(RouteEventKind::Deleted, *destination, None);
                 }
             }
         }
@@ -298,10 +382,176 @@ fn ip_vers(prefix: IpNetwork) -> &'static str {
     }
 }
 
+/// Lists the network interfaces that are backed by hardware. These are the interfaces that the
+/// system configuration framework knows of.
+pub async fn get_physical_interfaces() -> Result<Vec<PhysicalInterfaceDetails>> {
+    let mut addresses = get_interface_addresses().map_err(Error::GetInterfaceAddresses)?;
+    let (v4_default, v6_default) = futures::try_join!(
+        RouteManagerImpl::get_default_node(IpVersion::V4),
+        RouteManagerImpl::get_default_node(IpVersion::V6)
+    )?;
+    let default_interfaces: Vec<String> = [v4_default, v6_default]
+        .iter()
+        .flatten()
+        .filter_map(|node| node.get_device().map(str::to_owned))
+        .collect();
+
+    let mut interfaces = vec![];
+    for interface in network_configuration::get_interfaces().iter() {
+        let name = match interface.bsd_name() {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        let kind = match interface.interface_type() {
+            Some(SCNetworkInterfaceType::Ethernet) => PhysicalInterfaceKind::Ethernet,
+            Some(SCNetworkInterfaceType::IEEE80211) => PhysicalInterfaceKind::Wireless,
+            Some(SCNetworkInterfaceType::WWAN) => PhysicalInterfaceKind::Cellular,
+            _ => PhysicalInterfaceKind::Other,
+        };
+        let c_name = match CString::new(name.as_str()) {
+            Ok(c_name) => c_name,
+            Err(_) => continue,
+        };
+        // SAFETY: `c_name` is a valid, null-terminated string.
+        let index = unsafe { libc::if_nametoindex(c_name.as_ptr()) };
+        if index == 0 {
+            // The interface is not present, e.g. a disconnected USB adapter
+            continue;
+        }
+        let InterfaceAddresses { networks, mtu } = addresses.remove(&name).unwrap_or_default();
+        interfaces.push(PhysicalInterfaceDetails {
+            is_default_route: default_interfaces.contains(&name),
+            name,
+            index,
+            kind,
+            addresses: networks,
+            mtu,
+        });
+    }
+    interfaces.sort_by_key(|interface| interface.index);
+    Ok(interfaces)
+}
+
+#[derive(Default)]
+struct InterfaceAddresses {
+    networks: Vec<IpNetwork>,
+    mtu: Option<u32>,
+}
+
+/// Returns the IP addresses and MTU of all interfaces, keyed by interface name.
+fn get_interface_addresses() -> io::Result<HashMap<String, InterfaceAddresses>> {
+    let mut ifaddrs: *mut libc::ifaddrs = ptr::null_mut();
+    // SAFETY: `ifaddrs` is freed below.
+    if unsafe { libc::getifaddrs(&mut ifaddrs) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut interfaces: HashMap<String, InterfaceAddresses> = HashMap::new();
+    let mut current = ifaddrs;
+    while !current.is_null() {
+        // SAFETY: `current` is a valid entry in the list returned by `getifaddrs`.
+        let entry = unsafe { &*current };
+        current = entry.ifa_next;
+        if entry.ifa_addr.is_null() {
+            continue;
+        }
+        // SAFETY: The name is a valid, null-terminated string.
+        let name = unsafe { CStr::from_ptr(entry.ifa_name) }
+            .to_string_lossy()
+            .into_owned();
+        let interface = interfaces.entry(name).or_default();
+
+        // SAFETY: `ifa_addr` is not null, and `ifa_data` points to an `if_data` for link-layer
+        // entries.
+        match i32::from(unsafe { (*entry.ifa_addr).sa_family }) {
+            libc::AF_LINK if !entry.ifa_data.is_null() => {
+                interface.mtu =
+                    Some(unsafe { (*(entry.ifa_data as *const libc::if_data)).ifi_mtu });
+            }
+            libc::AF_INET | libc::AF_INET6 => {
+                if let Some(network) = unsafe { network_from_ifaddr(entry) } {
+                    interface.networks.push(network);
+                }
+            }
+            _ => (),
+        }
+    }
+    // SAFETY: `ifaddrs` was returned by `getifaddrs` and is not used after this.
+    unsafe { libc::freeifaddrs(ifaddrs) };
+
+    Ok(interfaces)
+}
+
+/// Converts the address and netmask of an `AF_INET` or `AF_INET6` entry to a network.
+///
+/// # Safety
+///
+/// `entry.ifa_addr` must point to a `sockaddr_in` or `sockaddr_in6`, matching its family.
+/// `entry.ifa_netmask` must be null or point to a netmask of the same family.
+unsafe fn network_from_ifaddr(entry: &libc::ifaddrs) -> Option<IpNetwork> {
+    // The family of the netmask is not always set, so the family of the address is used for both
+    let (address, prefix) = match i32::from((*entry.ifa_addr).sa_family) {
+        libc::AF_INET => {
+            let address = &*(entry.ifa_addr as *const libc::sockaddr_in);
+            let prefix = if entry.ifa_netmask.is_null() {
+                32
+            } else {
+                let netmask = &*(entry.ifa_netmask as *const libc::sockaddr_in);
+                netmask.sin_addr.s_addr.count_ones()
+            };
+            (
+                IpAddr::V4(Ipv4Addr::from(u32::from_be(address.sin_addr.s_addr))),
+                prefix,
+            )
+        }
+        libc::AF_INET6 => {
+            let address = &*(entry.ifa_addr as *const libc::sockaddr_in6);
+            let prefix = if entry.ifa_netmask.is_null() {
+                128
+            } else {
+                let netmask = &*(entry.ifa_netmask as *const libc::sockaddr_in6);
+                netmask
+                    .sin6_addr
+                    .s6_addr
+                    .iter()
+                    .map(|byte| byte.count_ones())
+                    .sum()
+            };
+            (
+                IpAddr::V6(Ipv6Addr::from(address.sin6_addr.s6_addr)),
+                prefix,
+            )
+        }
+        _ => return None,
+    };
+    IpNetwork::new(address, prefix as u8).ok()
+}
+
 /// Returns a stream that produces an item whenever a default route is either added or deleted from
 /// the routing table.
 pub(crate) fn listen_for_default_route_changes() -> Result<impl Stream<Item = std::io::Result<()>>>
 {
+    listen_for_route_changes(|line| line.contains("default"))
+}
+
+/// Returns a stream that produces an item whenever a default route or one of the `/1` routes that
+/// together cover the default route is either added or deleted from the routing table.
+pub(crate) fn listen_for_default_and_half_route_changes(
+) -> Result<impl Stream<Item = std::io::Result<()>>> {
+    listen_for_route_changes(is_default_or_half_route_line)
+}
+
+/// Checks whether the attribute line of a `route -n monitor` message refers to a default route or
+/// to a `/1` route. `0.0.0.0/1` is printed as `default`, and `128.0.0.0/1` as its destination and
+/// netmask, which are both `128.0.0.0`. The same applies to IPv6 with `::` and `8000::`.
+fn is_default_or_half_route_line(line: &str) -> bool {
+    line.split_whitespace()
+        .any(|token| matches!(token, "default" | "::" | "128.0.0.0" | "8000::"))
+}
+
+fn listen_for_route_changes(
+    mut matches: impl FnMut(&str) -> bool + Send + 'static,
+) -> Result<impl Stream<Item = std::io::Result<()>>> {
     let mut cmd = Command::new("route");
     cmd.arg("-n")
         .arg("monitor")
@@ -326,21 +576,21 @@ pub(crate) fn listen_for_default_route_changes() -> Result<impl Stream<Item = st
     // On the second line of the message, the message type is specified. Only messages with the
     // type 'RTM_ADD' or 'RTM_DELETE' are considered. On the 6th line, message attribute values are
     // shown. To detect a change for a default route in the routing table, check whether this line
-    // contains 'default'.  Whenever an empty line is encountered, the message has been sent, so
-    // the state can be reset.
+    // contains 'default', or whatever else `matches` accepts. Whenever an empty line is
+    // encountered, the message has been sent, so the state can be reset.
 
     let mut add_or_delete_message = false;
-    let mut contains_default = false;
+    let mut is_match = false;
 
     let monitor = LinesStream::new(lines).try_filter_map(move |line| {
         if add_or_delete_message {
-            if line.contains("default") {
-                contains_default = true;
+            if matches(&line) {
+                is_match = true;
             }
             if line.trim().is_empty() {
                 add_or_delete_message = false;
-                if contains_default {
-                    contains_default = false;
+                if is_match {
+                    is_match = false;
                     return future::ready(Ok(Some(())));
                 }
             }
@@ -352,3 +602,25 @@ pub(crate) fn listen_for_default_route_changes() -> Result<impl Stream<Item = st
 
     Ok(monitor)
 }
+
+#[cfg(test)]
+mod test {
+    use super::is_default_or_half_route_line;
+
+    #[test]
+    fn test_default_or_half_route_line() {
+        assert!(is_default_or_half_route_line(
+            " default 192.168.44.1 default  192.168.44.90"
+        ));
+        assert!(is_default_or_half_route_line(
+            " 128.0.0.0 10.8.0.1 128.0.0.0 utun4"
+        ));
+        assert!(is_default_or_half_route_line(
+            " 8000:: fe80::1 8000:: utun4"
+        ));
+        assert!(!is_default_or_half_route_line(
+            " 10.8.0.0 10.8.0.1 255.255.255.0 utun4"
+        ));
+        assert!(!is_default_or_half_route_line(" 128.0.0.1 link#4"));
+    }
+}
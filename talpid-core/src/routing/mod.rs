@@ -1,8 +1,16 @@
 #![cfg_attr(target_os = "android", allow(dead_code))]
 #![cfg_attr(target_os = "windows", allow(dead_code))]
 
+use crate::restore_journal::{JournalEntry, RestoreJournal};
 use ipnetwork::IpNetwork;
-use std::{fmt, net::IpAddr};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fmt,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+};
+use talpid_types::system_state::RouteState;
 
 #[cfg(target_os = "windows")]
 #[path = "windows.rs"]
@@ -12,16 +20,50 @@ mod imp;
 #[path = "unix.rs"]
 mod imp;
 
+mod aggregate;
+use aggregate::aggregate_routes;
+
+/// Structured events for the changes that the route manager makes.
+pub mod events;
+
+/// Probing of gateways using ARP and NDP.
+#[cfg(target_os = "linux")]
+pub mod gateway_probe;
+
 #[cfg(target_os = "linux")]
 use netlink_packet_route::rtnl::constants::RT_TABLE_MAIN;
 
 #[cfg(target_os = "macos")]
-pub(crate) use imp::{get_default_routes, listen_for_default_route_changes, PlatformError};
+pub(crate) use imp::{
+    get_default_routes, get_half_default_routes, listen_for_default_and_half_route_changes,
+    listen_for_default_route_changes, PlatformError,
+};
 
 pub use imp::{Error, RouteManager};
 
 pub use imp::RouteManagerHandle;
 
+#[cfg(not(target_os = "android"))]
+pub use imp::get_physical_interfaces;
+
+#[cfg(target_os = "linux")]
+pub use imp::CallbackMessage;
+
+/// Returns the index of the interface named `name`, or `None` if there is no such interface. On
+/// Windows, `name` is the alias of the interface.
+#[cfg(not(target_os = "android"))]
+pub fn interface_index(name: &str) -> Option<u32> {
+    #[cfg(unix)]
+    {
+        nix::net::if_::if_nametoindex(name).ok()
+    }
+    #[cfg(windows)]
+    {
+        let luid = crate::windows::luid_from_alias(name).ok()?;
+        crate::windows::index_from_luid(&luid).ok()
+    }
+}
+
 /// A network route with a specific network node, destinaiton and an optional metric.
 #[derive(Debug, Hash, Eq, PartialEq, Clone)]
 pub struct Route {
@@ -53,6 +95,17 @@ impl Route {
     pub fn get_node(&self) -> &Node {
         &self.node
     }
+
+    /// Returns the destination of the route.
+    pub fn get_prefix(&self) -> IpNetwork {
+        self.prefix
+    }
+
+    /// Returns the ID of the routing table that the route belongs to.
+    #[cfg(target_os = "linux")]
+    pub fn get_table_id(&self) -> u32 {
+        self.table_id
+    }
 }
 
 impl fmt::Display for Route {
@@ -70,11 +123,13 @@ impl fmt::Display for Route {
 /// A network route that should be applied by the RouteManager.
 /// It can either be routed through a specific network node or it can be routed through the current
 /// default route.
-#[derive(Debug, Hash, Eq, PartialEq, Clone)]
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Serialize, Deserialize)]
 pub struct RequiredRoute {
     /// Route's prefix
     pub prefix: IpNetwork,
     node: NetNode,
+    #[serde(default)]
+    kind: RouteKind,
     #[cfg(target_os = "linux")]
     table_id: u32,
 }
@@ -85,11 +140,45 @@ impl RequiredRoute {
         Self {
             node: node.into(),
             prefix,
+            kind: RouteKind::Custom,
             #[cfg(target_os = "linux")]
             table_id: crate::linux::TUNNEL_TABLE_ID,
         }
     }
 
+    /// Constructs a host route to a relay, which must bypass the tunnel.
+    pub fn relay_host(address: IpAddr, node: impl Into<NetNode>) -> Self {
+        Self::new(IpNetwork::from(address), node).kind(RouteKind::RelayHost)
+    }
+
+    /// Constructs a route that sends the traffic of `prefix`, which must be a default prefix or
+    /// half of one, into the tunnel through `node`.
+    pub fn tunnel_default(prefix: IpNetwork, node: Node) -> Self {
+        Self::new(prefix, node).kind(RouteKind::TunnelDefault)
+    }
+
+    /// Constructs a route that keeps traffic to a local network out of the tunnel.
+    pub fn lan_exemption(prefix: IpNetwork, node: impl Into<NetNode>) -> Self {
+        Self::new(prefix, node).kind(RouteKind::LanExemption)
+    }
+
+    /// Constructs a route that keeps traffic to a network of another VPN out of the tunnel.
+    pub fn vpn_coexistence(prefix: IpNetwork, node: impl Into<NetNode>) -> Self {
+        Self::new(prefix, node).kind(RouteKind::VpnCoexistence)
+    }
+
+    /// Sets the purpose of the route, which determines what the route is checked against before
+    /// it is added.
+    pub fn kind(mut self, kind: RouteKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Returns the purpose of the route.
+    pub fn get_kind(&self) -> RouteKind {
+        self.kind
+    }
+
     /// Sets the routing table ID of the route.
     #[cfg(target_os = "linux")]
     pub fn table(mut self, new_id: u32) -> Self {
@@ -98,9 +187,204 @@ impl RequiredRoute {
     }
 }
 
+impl From<&RequiredRoute> for RouteState {
+    fn from(route: &RequiredRoute) -> Self {
+        let (node, follows_default_route) = match &route.node {
+            NetNode::RealNode(node) => (Some(node), false),
+            #[cfg(not(target_os = "linux"))]
+            NetNode::DefaultNode => (None, true),
+        };
+        RouteState {
+            destination: route.prefix,
+            gateway: node.and_then(Node::get_address),
+            interface: node.and_then(|node| node.get_device().map(str::to_owned)),
+            follows_default_route,
+        }
+    }
+}
+
+/// The purpose of a [`RequiredRoute`]. Routes of every kind but [`RouteKind::Custom`] are checked
+/// by the route manager before they are added, and combinations that could leak traffic or break
+/// the tunnel are rejected.
+#[derive(Debug, Default, Clone, Copy, Hash, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RouteKind {
+    /// A host route to a relay, bridge or obfuscation server. It must be a `/32` or `/128` route
+    /// and must never lead into the tunnel, or tunnel traffic would be routed through itself.
+    RelayHost,
+    /// A default route, or one of the `/1` routes replacing it, that leads into the tunnel.
+    TunnelDefault,
+    /// A route to a local network that must not lead into the tunnel.
+    LanExemption,
+    /// A route to a network of another VPN that must not lead into the tunnel.
+    VpnCoexistence,
+    /// Any other route. These are not checked.
+    #[default]
+    Custom,
+}
+
+impl fmt::Display for RouteKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouteKind::RelayHost => "relay host".fmt(f),
+            RouteKind::TunnelDefault => "tunnel default".fmt(f),
+            RouteKind::LanExemption => "LAN exemption".fmt(f),
+            RouteKind::VpnCoexistence => "VPN coexistence".fmt(f),
+            RouteKind::Custom => "custom".fmt(f),
+        }
+    }
+}
+
+/// A route that was rejected because it does not fit its [`RouteKind`].
+#[derive(err_derive::Error, Debug, Clone, PartialEq, Eq)]
+#[error(display = "Refusing to add {} route to {}: {}", kind, prefix, reason)]
+pub struct InvalidRouteError {
+    /// The kind of the rejected route.
+    pub kind: RouteKind,
+    /// The destination of the rejected route.
+    pub prefix: IpNetwork,
+    /// Why the route was rejected.
+    pub reason: InvalidRouteReason,
+}
+
+/// Why a route was rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidRouteReason {
+    /// A relay host route does not cover exactly one address.
+    NotAHostRoute,
+    /// A route that must bypass the tunnel leads into it.
+    ThroughTunnel,
+    /// A tunnel default route is not a default route or half of one.
+    NotADefaultRoute,
+    /// A tunnel default route does not name the tunnel interface.
+    NoTunnelInterface,
+    /// A LAN exemption route leads to a network that is not a local network.
+    NotALocalNetwork,
+}
+
+impl fmt::Display for InvalidRouteReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            InvalidRouteReason::NotAHostRoute => "the prefix must contain a single address",
+            InvalidRouteReason::ThroughTunnel => "the route must not go through the tunnel",
+            InvalidRouteReason::NotADefaultRoute => {
+                "the prefix must be a default prefix or half of one"
+            }
+            InvalidRouteReason::NoTunnelInterface => "the route must name the tunnel interface",
+            InvalidRouteReason::NotALocalNetwork => "the prefix is not a local network",
+        };
+        reason.fmt(f)
+    }
+}
+
+/// Checks required routes against their [`RouteKind`] before they are handed to the platform
+/// specific route manager. Tunnel interfaces are learned from [`RouteKind::TunnelDefault`] routes
+/// and remembered until the routes are cleared, since the routes to the relay are usually added
+/// before the tunnel routes.
+#[derive(Debug, Default, Clone)]
+struct RouteValidator {
+    tunnel_interfaces: Arc<Mutex<HashSet<String>>>,
+}
+
+impl RouteValidator {
+    fn validate(&self, routes: &HashSet<RequiredRoute>) -> Result<(), InvalidRouteError> {
+        let mut tunnel_interfaces = self.tunnel_interfaces.lock().unwrap();
+        let mut new_tunnel_interfaces = tunnel_interfaces.clone();
+        for route in routes
+            .iter()
+            .filter(|route| route.kind == RouteKind::TunnelDefault)
+        {
+            check_tunnel_default(route)?;
+            if let Some(device) = route.device() {
+                new_tunnel_interfaces.insert(device.to_owned());
+            }
+        }
+        for route in routes {
+            check_route(route, &new_tunnel_interfaces)?;
+        }
+        *tunnel_interfaces = new_tunnel_interfaces;
+        Ok(())
+    }
+
+    /// Forgets the tunnel interfaces once the routes through them have been removed.
+    fn clear(&self) {
+        self.tunnel_interfaces.lock().unwrap().clear();
+    }
+}
+
+impl RequiredRoute {
+    fn device(&self) -> Option<&str> {
+        match &self.node {
+            NetNode::RealNode(node) => node.get_device(),
+            #[cfg(not(target_os = "linux"))]
+            NetNode::DefaultNode => None,
+        }
+    }
+
+    fn invalid(&self, reason: InvalidRouteReason) -> InvalidRouteError {
+        InvalidRouteError {
+            kind: self.kind,
+            prefix: self.prefix,
+            reason,
+        }
+    }
+}
+
+fn check_tunnel_default(route: &RequiredRoute) -> Result<(), InvalidRouteError> {
+    if route.prefix.prefix() > 1 || route.prefix.network() != route.prefix.ip() {
+        return Err(route.invalid(InvalidRouteReason::NotADefaultRoute));
+    }
+    if route.device().is_none() {
+        return Err(route.invalid(InvalidRouteReason::NoTunnelInterface));
+    }
+    Ok(())
+}
+
+fn check_route(
+    route: &RequiredRoute,
+    tunnel_interfaces: &HashSet<String>,
+) -> Result<(), InvalidRouteError> {
+    let through_tunnel = route
+        .device()
+        .map(|device| tunnel_interfaces.contains(device))
+        .unwrap_or(false);
+    match route.kind {
+        RouteKind::RelayHost => {
+            let host_prefix = match route.prefix {
+                IpNetwork::V4(_) => 32,
+                IpNetwork::V6(_) => 128,
+            };
+            if route.prefix.prefix() != host_prefix {
+                return Err(route.invalid(InvalidRouteReason::NotAHostRoute));
+            }
+            if through_tunnel {
+                return Err(route.invalid(InvalidRouteReason::ThroughTunnel));
+            }
+        }
+        RouteKind::LanExemption => {
+            let is_local = crate::firewall::ALLOWED_LAN_NETS.iter().any(|net| {
+                net.contains(route.prefix.network()) && net.prefix() <= route.prefix.prefix()
+            });
+            if !is_local {
+                return Err(route.invalid(InvalidRouteReason::NotALocalNetwork));
+            }
+            if through_tunnel {
+                return Err(route.invalid(InvalidRouteReason::ThroughTunnel));
+            }
+        }
+        RouteKind::VpnCoexistence => {
+            if through_tunnel {
+                return Err(route.invalid(InvalidRouteReason::ThroughTunnel));
+            }
+        }
+        RouteKind::TunnelDefault | RouteKind::Custom => (),
+    }
+    Ok(())
+}
+
 /// A NetNode represents a network node - either a real one or a symbolic default one.
 /// A route with a symbolic default node will be changed whenever a new default route is created.
-#[derive(Debug, Hash, Eq, PartialEq, Clone)]
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Serialize, Deserialize)]
 pub enum NetNode {
     /// A real node will be used to set a regular route that will remain unchanged for the lifetime
     /// of the RouteManager
@@ -119,7 +403,7 @@ impl From<Node> for NetNode {
 
 /// Node represents a real network node - it can be identified by a network interface name, an IP
 /// address or both.
-#[derive(Debug, Hash, Eq, PartialEq, Clone)]
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Node {
     ip: Option<IpAddr>,
     device: Option<String>,
@@ -173,3 +457,147 @@ impl fmt::Display for Node {
         Ok(())
     }
 }
+
+/// Returns journal entries for `routes`, or nothing if there is no journal.
+fn journal_entries(
+    journal: &Option<RestoreJournal>,
+    routes: &HashSet<RequiredRoute>,
+) -> Vec<JournalEntry> {
+    if journal.is_none() {
+        return vec![];
+    }
+    routes
+        .iter()
+        .map(|route| JournalEntry::Route {
+            route: route.clone(),
+        })
+        .collect()
+}
+
+/// Records routes that were successfully added.
+fn record_routes(journal: &Option<RestoreJournal>, entries: Vec<JournalEntry>) {
+    if let Some(journal) = journal {
+        journal.record_all(entries);
+    }
+}
+
+/// Removes all routes from the journal.
+fn remove_routes(journal: &Option<RestoreJournal>) {
+    if let Some(journal) = journal {
+        journal.remove(|entry| matches!(entry, JournalEntry::Route { .. }));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn routes(routes: impl IntoIterator<Item = RequiredRoute>) -> HashSet<RequiredRoute> {
+        routes.into_iter().collect()
+    }
+
+    #[test]
+    fn test_relay_host_must_be_host_route() {
+        let validator = RouteValidator::default();
+        let gateway = Node::new("192.168.1.1".parse().unwrap(), "eth0".to_owned());
+
+        let host = RequiredRoute::relay_host("1.2.3.4".parse().unwrap(), gateway.clone());
+        assert!(validator.validate(&routes([host])).is_ok());
+
+        let network =
+            RequiredRoute::new("1.2.3.0/24".parse().unwrap(), gateway).kind(RouteKind::RelayHost);
+        assert_eq!(
+            validator.validate(&routes([network])).unwrap_err().reason,
+            InvalidRouteReason::NotAHostRoute
+        );
+    }
+
+    #[test]
+    fn test_relay_host_must_bypass_tunnel() {
+        let validator = RouteValidator::default();
+        let tunnel = Node::device("wg0-mullvad".to_owned());
+
+        let default = RequiredRoute::tunnel_default("0.0.0.0/0".parse().unwrap(), tunnel.clone());
+        assert!(validator.validate(&routes([default])).is_ok());
+
+        // The tunnel interface is remembered across calls
+        let relay = RequiredRoute::relay_host("1.2.3.4".parse().unwrap(), tunnel);
+        assert_eq!(
+            validator
+                .validate(&routes([relay.clone()]))
+                .unwrap_err()
+                .reason,
+            InvalidRouteReason::ThroughTunnel
+        );
+
+        validator.clear();
+        assert!(validator.validate(&routes([relay])).is_ok());
+    }
+
+    #[test]
+    fn test_tunnel_default_prefixes() {
+        let validator = RouteValidator::default();
+        let tunnel = Node::device("wg0-mullvad".to_owned());
+
+        let halves = ["0.0.0.0/1", "128.0.0.0/1", "::/1", "8000::/1"]
+            .map(|prefix| RequiredRoute::tunnel_default(prefix.parse().unwrap(), tunnel.clone()));
+        assert!(validator.validate(&routes(halves)).is_ok());
+
+        let narrow = RequiredRoute::tunnel_default("10.0.0.0/8".parse().unwrap(), tunnel);
+        assert_eq!(
+            validator.validate(&routes([narrow])).unwrap_err().reason,
+            InvalidRouteReason::NotADefaultRoute
+        );
+    }
+
+    #[test]
+    fn test_lan_exemption_must_be_local() {
+        let validator = RouteValidator::default();
+        let gateway = Node::device("eth0".to_owned());
+
+        let lan = RequiredRoute::lan_exemption("192.168.1.0/24".parse().unwrap(), gateway.clone());
+        assert!(validator.validate(&routes([lan])).is_ok());
+
+        let public = RequiredRoute::lan_exemption("8.8.0.0/16".parse().unwrap(), gateway.clone());
+        assert_eq!(
+            validator.validate(&routes([public])).unwrap_err().reason,
+            InvalidRouteReason::NotALocalNetwork
+        );
+
+        let too_wide = RequiredRoute::lan_exemption("192.0.0.0/8".parse().unwrap(), gateway);
+        assert!(validator.validate(&routes([too_wide])).is_err());
+    }
+
+    #[test]
+    fn test_vpn_coexistence_must_bypass_tunnel() {
+        let validator = RouteValidator::default();
+        let tunnel = Node::device("wg0-mullvad".to_owned());
+
+        let default = RequiredRoute::tunnel_default("0.0.0.0/0".parse().unwrap(), tunnel.clone());
+        assert!(validator.validate(&routes([default])).is_ok());
+
+        // Unlike LAN exemptions, the networks of other VPNs need not be local
+        let other_vpn = RequiredRoute::vpn_coexistence(
+            "100.64.0.0/10".parse().unwrap(),
+            Node::device("utun4".to_owned()),
+        );
+        assert!(validator.validate(&routes([other_vpn])).is_ok());
+
+        let through_tunnel =
+            RequiredRoute::vpn_coexistence("100.64.0.0/10".parse().unwrap(), tunnel);
+        assert_eq!(
+            validator
+                .validate(&routes([through_tunnel]))
+                .unwrap_err()
+                .reason,
+            InvalidRouteReason::ThroughTunnel
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_interface_index() {
+        assert!(interface_index("lo").is_some());
+        assert_eq!(interface_index("mullvad-bogus0"), None);
+    }
+}
@@ -1,9 +1,18 @@
 #![cfg_attr(target_os = "android", allow(dead_code))]
 #![cfg_attr(target_os = "windows", allow(dead_code))]
 // TODO: remove the allow(dead_code) for android once it's up to scratch.
-use super::RequiredRoute;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use super::Node;
 #[cfg(target_os = "linux")]
 use super::Route;
+use super::{
+    aggregate_routes, journal_entries, record_routes, remove_routes, InvalidRouteError,
+    RequiredRoute, RouteValidator,
+};
+use crate::restore_journal::RestoreJournal;
+
+#[cfg(target_os = "macos")]
+use ipnetwork::IpNetwork;
 
 use futures::channel::{
     mpsc::{self, UnboundedSender},
@@ -12,11 +21,13 @@ use futures::channel::{
 use std::{collections::HashSet, io};
 #[cfg(target_os = "macos")]
 use talpid_types::net::IpVersion;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use talpid_types::net::PhysicalInterfaceDetails;
 
 #[cfg(target_os = "linux")]
 use futures::stream::Stream;
 
-#[cfg(target_os = "linux")]
+#[cfg(any(target_os = "linux", target_os = "macos"))]
 use std::net::IpAddr;
 
 #[allow(clippy::module_inception)]
@@ -24,7 +35,7 @@ use std::net::IpAddr;
 #[path = "macos.rs"]
 mod imp;
 #[cfg(target_os = "macos")]
-pub(crate) use imp::listen_for_default_route_changes;
+pub(crate) use imp::{listen_for_default_and_half_route_changes, listen_for_default_route_changes};
 
 #[allow(clippy::module_inception)]
 #[cfg(target_os = "linux")]
@@ -56,17 +67,27 @@ pub enum Error {
     /// Attempt to use route manager that has been dropped
     #[error(display = "Cannot send message to route manager since it is down")]
     RouteManagerDown,
+    /// A route does not fit its kind
+    #[error(display = "Invalid route")]
+    InvalidRoute(#[error(source)] InvalidRouteError),
 }
 
 /// Handle to a route manager.
 #[derive(Clone)]
 pub struct RouteManagerHandle {
     tx: UnboundedSender<RouteManagerCommand>,
+    journal: Option<RestoreJournal>,
+    validator: RouteValidator,
 }
 
 impl RouteManagerHandle {
     /// Applies the given routes while the route manager is running.
     pub async fn add_routes(&self, routes: HashSet<RequiredRoute>) -> Result<(), Error> {
+        self.validator
+            .validate(&routes)
+            .map_err(Error::InvalidRoute)?;
+        let routes = aggregate_routes(routes);
+        let journal_entries = journal_entries(&self.journal, &routes);
         let (response_tx, response_rx) = oneshot::channel();
         self.tx
             .unbounded_send(RouteManagerCommand::AddRoutes(routes, response_tx))
@@ -74,7 +95,9 @@ impl RouteManagerHandle {
         response_rx
             .await
             .map_err(|_| Error::ManagerChannelDown)?
-            .map_err(Error::PlatformError)
+            .map_err(Error::PlatformError)?;
+        record_routes(&self.journal, journal_entries);
+        Ok(())
     }
 
     /// Ensure that packets are routed using the correct tables.
@@ -149,6 +172,29 @@ impl RouteManagerHandle {
             .map_err(|_| Error::ManagerChannelDown)?
             .map_err(Error::PlatformError)
     }
+
+    /// Returns the node that traffic to `destination` would be routed through if it had to leave
+    /// through the interface named `interface`. Returns `None` if the interface does not exist or
+    /// has no route to the destination.
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    pub async fn get_interface_node(
+        &self,
+        interface: String,
+        destination: IpAddr,
+    ) -> Result<Option<Node>, Error> {
+        let (response_tx, response_rx) = oneshot::channel();
+        self.tx
+            .unbounded_send(RouteManagerCommand::GetInterfaceNode(
+                interface,
+                destination,
+                response_tx,
+            ))
+            .map_err(|_| Error::RouteManagerDown)?;
+        response_rx
+            .await
+            .map_err(|_| Error::ManagerChannelDown)?
+            .map_err(Error::PlatformError)
+    }
 }
 
 /// Commands for the underlying route manager object.
@@ -158,6 +204,10 @@ pub(crate) enum RouteManagerCommand {
         HashSet<RequiredRoute>,
         oneshot::Sender<Result<(), PlatformError>>,
     ),
+    RemoveRoutes(
+        HashSet<RequiredRoute>,
+        oneshot::Sender<Result<(), PlatformError>>,
+    ),
     ClearRoutes,
     Shutdown(oneshot::Sender<()>),
     #[cfg(target_os = "linux")]
@@ -174,6 +224,12 @@ pub(crate) enum RouteManagerCommand {
         bool,
         oneshot::Sender<Result<Option<Route>, PlatformError>>,
     ),
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    GetInterfaceNode(
+        String,
+        IpAddr,
+        oneshot::Sender<Result<Option<Node>, PlatformError>>,
+    ),
 }
 
 #[cfg(target_os = "linux")]
@@ -181,6 +237,11 @@ pub(crate) enum RouteManagerCommand {
 pub enum CallbackMessage {
     NewRoute(Route),
     DelRoute(Route),
+    /// The interface with the given index was added, removed, or changed state, e.g. because it
+    /// lost carrier.
+    LinkChange(u32),
+    /// An address was added to or removed from the interface with the given index.
+    AddressChange(u32),
 }
 
 /// RouteManager applies a set of routes to the route table.
@@ -189,6 +250,8 @@ pub enum CallbackMessage {
 pub struct RouteManager {
     manage_tx: Option<UnboundedSender<RouteManagerCommand>>,
     runtime: tokio::runtime::Handle,
+    journal: Option<RestoreJournal>,
+    validator: RouteValidator,
 }
 
 impl RouteManager {
@@ -196,16 +259,44 @@ impl RouteManager {
     /// Takes a set of network destinations and network nodes as an argument, and applies said
     /// routes.
     pub async fn new(required_routes: HashSet<RequiredRoute>) -> Result<Self, Error> {
+        let validator = RouteValidator::default();
+        validator
+            .validate(&required_routes)
+            .map_err(Error::InvalidRoute)?;
         let (manage_tx, manage_rx) = mpsc::unbounded();
-        let manager = imp::RouteManagerImpl::new(required_routes).await?;
+        let manager = imp::RouteManagerImpl::new(aggregate_routes(required_routes)).await?;
         tokio::spawn(manager.run(manage_rx));
 
         Ok(Self {
             runtime: tokio::runtime::Handle::current(),
             manage_tx: Some(manage_tx),
+            journal: None,
+            validator,
         })
     }
 
+    /// Constructs a RouteManager that accepts routes without applying them. This is used when
+    /// the system applies the routes of the tunnel, as it does for a packet tunnel provider in a
+    /// Network Extension.
+    #[cfg(target_os = "macos")]
+    pub fn new_detached() -> Self {
+        let (manage_tx, manage_rx) = mpsc::unbounded();
+        tokio::spawn(run_detached(manage_rx));
+
+        Self {
+            runtime: tokio::runtime::Handle::current(),
+            manage_tx: Some(manage_tx),
+            journal: None,
+            validator: RouteValidator::default(),
+        }
+    }
+
+    /// Records routes added from now on in `journal`, so that they can be removed if the process
+    /// crashes. This also applies to handles created after this call.
+    pub fn set_journal(&mut self, journal: RestoreJournal) {
+        self.journal = Some(journal);
+    }
+
     /// Stops RouteManager and removes all of the applied routes.
     pub async fn stop(&mut self) {
         if let Some(tx) = self.manage_tx.take() {
@@ -222,12 +313,18 @@ impl RouteManager {
             if wait_rx.await.is_err() {
                 log::error!("{}", Error::ManagerChannelDown);
             }
+            remove_routes(&self.journal);
         }
     }
 
     /// Applies the given routes until [`RouteManager::stop`] is called.
     pub async fn add_routes(&mut self, routes: HashSet<RequiredRoute>) -> Result<(), Error> {
         if let Some(tx) = &self.manage_tx {
+            self.validator
+                .validate(&routes)
+                .map_err(Error::InvalidRoute)?;
+            let routes = aggregate_routes(routes);
+            let journal_entries = journal_entries(&self.journal, &routes);
             let (result_tx, result_rx) = oneshot::channel();
             if tx
                 .unbounded_send(RouteManagerCommand::AddRoutes(routes, result_tx))
@@ -236,6 +333,28 @@ impl RouteManager {
                 return Err(Error::RouteManagerDown);
             }
 
+            result_rx
+                .await
+                .map_err(|_| Error::ManagerChannelDown)?
+                .map_err(Error::PlatformError)?;
+            record_routes(&self.journal, journal_entries);
+            Ok(())
+        } else {
+            Err(Error::RouteManagerDown)
+        }
+    }
+
+    /// Removes the given routes from the routing table, even though they were not applied by this
+    /// route manager. This is used to remove routes that were left behind by a previous instance.
+    pub async fn remove_routes(&mut self, routes: HashSet<RequiredRoute>) -> Result<(), Error> {
+        if let Some(tx) = &self.manage_tx {
+            let (result_tx, result_rx) = oneshot::channel();
+            if tx
+                .unbounded_send(RouteManagerCommand::RemoveRoutes(routes, result_tx))
+                .is_err()
+            {
+                return Err(Error::RouteManagerDown);
+            }
             result_rx
                 .await
                 .map_err(|_| Error::ManagerChannelDown)?
@@ -252,6 +371,8 @@ impl RouteManager {
             if tx.unbounded_send(RouteManagerCommand::ClearRoutes).is_err() {
                 return Err(Error::RouteManagerDown);
             }
+            remove_routes(&self.journal);
+            self.validator.clear();
             Ok(())
         } else {
             Err(Error::RouteManagerDown)
@@ -270,10 +391,22 @@ impl RouteManager {
         self.handle()?.clear_routing_rules().await
     }
 
+    /// Returns whether the route manager is still running and accepting commands.
+    pub fn is_running(&self) -> bool {
+        self.manage_tx
+            .as_ref()
+            .map(|tx| !tx.is_closed())
+            .unwrap_or(false)
+    }
+
     /// Retrieve a sender directly to the command channel.
     pub fn handle(&self) -> Result<RouteManagerHandle, Error> {
         if let Some(tx) = &self.manage_tx {
-            Ok(RouteManagerHandle { tx: tx.clone() })
+            Ok(RouteManagerHandle {
+                tx: tx.clone(),
+                journal: self.journal.clone(),
+                validator: self.validator.clone(),
+            })
         } else {
             Err(Error::RouteManagerDown)
         }
@@ -286,6 +419,37 @@ impl Drop for RouteManager {
     }
 }
 
+#[cfg(target_os = "macos")]
+async fn run_detached(manage_rx: mpsc::UnboundedReceiver<RouteManagerCommand>) {
+    use futures::StreamExt;
+
+    let mut manage_rx = manage_rx;
+    while let Some(command) = manage_rx.next().await {
+        match command {
+            RouteManagerCommand::AddRoutes(_routes, tx)
+            | RouteManagerCommand::RemoveRoutes(_routes, tx) => {
+                let _ = tx.send(Ok(()));
+            }
+            RouteManagerCommand::ClearRoutes => (),
+            RouteManagerCommand::GetInterfaceNode(_interface, _destination, tx) => {
+                let _ = tx.send(Ok(None));
+            }
+            RouteManagerCommand::Shutdown(tx) => {
+                let _ = tx.send(());
+                break;
+            }
+        }
+    }
+}
+
+/// Lists the network interfaces that are backed by hardware, such as Ethernet and Wi-Fi adapters.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub async fn get_physical_interfaces() -> Result<Vec<PhysicalInterfaceDetails>, Error> {
+    imp::get_physical_interfaces()
+        .await
+        .map_err(Error::PlatformError)
+}
+
 /// Returns a tuple containing a IPv4 and IPv6 default route nodes.
 #[cfg(target_os = "macos")]
 pub(crate) async fn get_default_routes() -> Result<(Option<super::Node>, Option<super::Node>), Error>
@@ -296,3 +460,17 @@ pub(crate) async fn get_default_routes() -> Result<(Option<super::Node>, Option<
         imp::RouteManagerImpl::get_default_node(IpVersion::V6).map_err(Into::into)
     )
 }
+
+/// Returns the nodes of the `/1` routes that together cover the default route, for each of
+/// `0.0.0.0/1`, `128.0.0.0/1`, `::/1` and `8000::/1` that exists.
+#[cfg(target_os = "macos")]
+pub(crate) async fn get_half_default_routes() -> Result<Vec<(IpNetwork, Node)>, Error> {
+    let mut routes = vec![];
+    for prefix in ["0.0.0.0/1", "128.0.0.0/1", "::/1", "8000::/1"] {
+        let prefix: IpNetwork = prefix.parse().expect("valid /1 prefix");
+        if let Some(node) = imp::RouteManagerImpl::get_prefix_node(prefix).await? {
+            routes.push((prefix, node));
+        }
+    }
+    Ok(routes)
+}
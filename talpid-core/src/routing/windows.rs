@@ -1,5 +1,8 @@
-use super::NetNode;
-use crate::{routing::RequiredRoute, winnet};
+use super::{
+    aggregate_routes, journal_entries, record_routes, remove_routes, InvalidRouteError, NetNode,
+    Node, RouteValidator,
+};
+use crate::{restore_journal::RestoreJournal, routing::RequiredRoute, winnet};
 use futures::{
     channel::{
         mpsc::{self, UnboundedReceiver, UnboundedSender},
@@ -7,10 +10,23 @@ use futures::{
     },
     StreamExt,
 };
-use std::{collections::HashSet, net::IpAddr};
-use windows_sys::Win32::NetworkManagement::Ndis::NET_LUID_LH;
+use ipnetwork::IpNetwork;
+use std::{collections::HashSet, io, net::IpAddr};
+use talpid_types::net::{PhysicalInterfaceDetails, PhysicalInterfaceKind};
+use windows_sys::Win32::NetworkManagement::{
+    IpHelper::MibParameterNotification, Ndis::NET_LUID_LH,
+};
 use winnet::WinNetAddrFamily;
 
+/// Metric assigned to the tunnel interface, so that it is preferred over other interfaces.
+const TUNNEL_INTERFACE_METRIC: u32 = 1;
+
+/// Interface types from `ipifcons.h`.
+const IF_TYPE_ETHERNET_CSMACD: u32 = 6;
+const IF_TYPE_IEEE80211: u32 = 71;
+const IF_TYPE_WWANPP: u32 = 243;
+const IF_TYPE_WWANPP2: u32 = 244;
+
 /// Windows routing errors.
 #[derive(err_derive::Error, Debug)]
 pub enum Error {
@@ -26,6 +42,9 @@ pub enum Error {
     /// Failure to clear routes
     #[error(display = "Failed to clear applied routes")]
     ClearRoutesFailed,
+    /// Failure to remove routes
+    #[error(display = "Failed to remove routes")]
+    RemoveRoutesFailed,
     /// WinNet returned an error while adding default route callback
     #[error(display = "Failed to set callback for default route")]
     FailedToAddDefaultRouteCallback,
@@ -35,6 +54,21 @@ pub enum Error {
     /// Something went wrong when getting the mtu of the interface
     #[error(display = "Could not get the mtu of the interface")]
     GetMtu,
+    /// Failed to set or monitor the metric of the tunnel interface
+    #[error(display = "Failed to set the metric of the tunnel interface")]
+    SetInterfaceMetric(#[error(source)] io::Error),
+    /// Failed to look up the route through a specific interface
+    #[error(display = "Failed to obtain the route through an interface")]
+    GetInterfaceRoute(#[error(source)] io::Error),
+    /// A route does not fit its kind
+    #[error(display = "Invalid route")]
+    InvalidRoute(#[error(source)] InvalidRouteError),
+    /// Failed to obtain the interfaces or their addresses
+    #[error(display = "Failed to obtain the network interfaces")]
+    GetInterfaces(#[error(source)] io::Error),
+    /// Failed to obtain the best default route
+    #[error(display = "Failed to obtain the default route")]
+    GetDefaultRoute(#[error(source)] winnet::Error),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -42,22 +76,33 @@ pub type Result<T> = std::result::Result<T, Error>;
 /// Manages routes by calling into WinNet
 pub struct RouteManager {
     manage_tx: Option<UnboundedSender<RouteManagerCommand>>,
+    journal: Option<RestoreJournal>,
+    validator: RouteValidator,
 }
 
 /// Handle to a route manager.
 #[derive(Clone)]
 pub struct RouteManagerHandle {
     tx: UnboundedSender<RouteManagerCommand>,
+    journal: Option<RestoreJournal>,
+    validator: RouteValidator,
 }
 
 impl RouteManagerHandle {
     /// Applies the given routes while the route manager is running.
     pub async fn add_routes(&self, routes: HashSet<RequiredRoute>) -> Result<()> {
+        self.validator
+            .validate(&routes)
+            .map_err(Error::InvalidRoute)?;
+        let routes = aggregate_routes(routes);
+        let journal_entries = journal_entries(&self.journal, &routes);
         let (response_tx, response_rx) = oneshot::channel();
         self.tx
             .unbounded_send(RouteManagerCommand::AddRoutes(routes, response_tx))
             .map_err(|_| Error::RouteManagerDown)?;
-        response_rx.await.map_err(|_| Error::ManagerChannelDown)?
+        response_rx.await.map_err(|_| Error::ManagerChannelDown)??;
+        record_routes(&self.journal, journal_entries);
+        Ok(())
     }
 
     /// Applies the given routes while the route manager is running.
@@ -68,12 +113,40 @@ impl RouteManagerHandle {
             .map_err(|_| Error::RouteManagerDown)?;
         response_rx.await.map_err(|_| Error::ManagerChannelDown)?
     }
+
+    /// Returns the node that traffic to `destination` would be routed through if it had to leave
+    /// through the interface with the alias `interface`. Returns `None` if the interface does not
+    /// exist or has no route to the destination.
+    pub async fn get_interface_node(
+        &self,
+        interface: String,
+        destination: IpAddr,
+    ) -> Result<Option<Node>> {
+        let luid = match crate::windows::luid_from_alias(&interface) {
+            Ok(luid) => luid,
+            Err(error) => {
+                log::debug!("Failed to find interface {}: {}", interface, error);
+                return Ok(None);
+            }
+        };
+        let next_hop = crate::windows::get_best_route_next_hop(&luid, destination)
+            .map_err(Error::GetInterfaceRoute)?;
+        Ok(next_hop.map(|next_hop| {
+            if next_hop.is_unspecified() {
+                Node::device(interface)
+            } else {
+                Node::new(next_hop, interface)
+            }
+        }))
+    }
 }
 
 #[derive(Debug)]
 pub enum RouteManagerCommand {
     AddRoutes(HashSet<RequiredRoute>, oneshot::Sender<Result<()>>),
     GetMtuForRoute(IpAddr, oneshot::Sender<Result<u16>>),
+    SetTunnelInterfaceMetric(u64, oneshot::Sender<Result<()>>),
+    ClearTunnelInterfaceMetric,
     Shutdown,
 }
 
@@ -87,6 +160,8 @@ impl RouteManager {
         let (manage_tx, manage_rx) = mpsc::unbounded();
         let manager = Self {
             manage_tx: Some(manage_tx),
+            journal: None,
+            validator: RouteValidator::default(),
         };
         tokio::spawn(RouteManager::listen(manage_rx));
         manager.add_routes(required_routes).await?;
@@ -94,35 +169,40 @@ impl RouteManager {
         Ok(manager)
     }
 
+    /// Records routes added from now on in `journal`, so that they can be removed if the process
+    /// crashes. This also applies to handles created after this call.
+    pub fn set_journal(&mut self, journal: RestoreJournal) {
+        self.journal = Some(journal);
+    }
+
+    /// Returns whether the route manager is still running and accepting commands.
+    pub fn is_running(&self) -> bool {
+        self.manage_tx
+            .as_ref()
+            .map(|tx| !tx.is_closed())
+            .unwrap_or(false)
+    }
+
     /// Retrieve a sender directly to the command channel.
     pub fn handle(&self) -> Result<RouteManagerHandle> {
         if let Some(tx) = &self.manage_tx {
-            Ok(RouteManagerHandle { tx: tx.clone() })
+            Ok(RouteManagerHandle {
+                tx: tx.clone(),
+                journal: self.journal.clone(),
+                validator: self.validator.clone(),
+            })
         } else {
             Err(Error::RouteManagerDown)
         }
     }
 
     async fn listen(mut manage_rx: UnboundedReceiver<RouteManagerCommand>) {
+        let mut metric_monitor = None;
+
         while let Some(command) = manage_rx.next().await {
             match command {
                 RouteManagerCommand::AddRoutes(routes, tx) => {
-                    let routes: Vec<_> = routes
-                        .iter()
-                        .map(|route| {
-                            let destination = winnet::WinNetIpNetwork::from(route.prefix);
-                            match &route.node {
-                                NetNode::DefaultNode => {
-                                    winnet::WinNetRoute::through_default_node(destination)
-                                }
-                                NetNode::RealNode(node) => winnet::WinNetRoute::new(
-                                    winnet::WinNetNode::from(node),
-                                    destination,
-                                ),
-                            }
-                        })
-                        .collect();
-
+                    let routes = winnet_routes(&routes);
                     let _ = tx.send(
                         winnet::routing_manager_add_routes(&routes).map_err(Error::AddRoutesFailed),
                     );
@@ -140,6 +220,17 @@ impl RouteManager {
                     };
                     let _ = tx.send(res);
                 }
+                RouteManagerCommand::SetTunnelInterfaceMetric(luid, tx) => {
+                    // Drop any previous monitor before the new one starts
+                    metric_monitor = None;
+                    let result = InterfaceMetricMonitor::start(NET_LUID_LH { Value: luid })
+                        .map(|monitor| metric_monitor = Some(monitor))
+                        .map_err(Error::SetInterfaceMetric);
+                    let _ = tx.send(result);
+                }
+                RouteManagerCommand::ClearTunnelInterfaceMetric => {
+                    metric_monitor = None;
+                }
                 RouteManagerCommand::Shutdown => {
                     break;
                 }
@@ -156,12 +247,18 @@ impl RouteManager {
             }
 
             winnet::deactivate_routing_manager();
+            remove_routes(&self.journal);
         }
     }
 
     /// Applies the given routes until [`RouteManager::stop`] is called.
     pub async fn add_routes(&self, routes: HashSet<RequiredRoute>) -> Result<()> {
         if let Some(tx) = &self.manage_tx {
+            self.validator
+                .validate(&routes)
+                .map_err(Error::InvalidRoute)?;
+            let routes = aggregate_routes(routes);
+            let journal_entries = journal_entries(&self.journal, &routes);
             let (result_tx, result_rx) = oneshot::channel();
             if tx
                 .unbounded_send(RouteManagerCommand::AddRoutes(routes, result_tx))
@@ -169,6 +266,40 @@ impl RouteManager {
             {
                 return Err(Error::RouteManagerDown);
             }
+            result_rx.await.map_err(|_| Error::ManagerChannelDown)??;
+            record_routes(&self.journal, journal_entries);
+            Ok(())
+        } else {
+            Err(Error::RouteManagerDown)
+        }
+    }
+
+    /// Removes the given routes from the routing table, even though they were not applied by this
+    /// route manager. This is used to remove routes that were left behind by a previous instance.
+    pub async fn remove_routes(&mut self, routes: HashSet<RequiredRoute>) -> Result<()> {
+        if self.manage_tx.is_none() {
+            return Err(Error::RouteManagerDown);
+        }
+        if winnet::routing_manager_delete_unowned_routes(&winnet_routes(&routes)) {
+            Ok(())
+        } else {
+            Err(Error::RemoveRoutesFailed)
+        }
+    }
+
+    /// Sets a low metric on the tunnel interface identified by `luid`, and re-applies it whenever
+    /// it is changed, until [`RouteManager::clear_routes`] is called.
+    pub async fn set_tunnel_interface_metric(&self, luid: NET_LUID_LH) -> Result<()> {
+        if let Some(tx) = &self.manage_tx {
+            let (result_tx, result_rx) = oneshot::channel();
+            if tx
+                .unbounded_send(RouteManagerCommand::SetTunnelInterfaceMetric(
+                    luid.Value, result_tx,
+                ))
+                .is_err()
+            {
+                return Err(Error::RouteManagerDown);
+            }
             result_rx.await.map_err(|_| Error::ManagerChannelDown)?
         } else {
             Err(Error::RouteManagerDown)
@@ -176,9 +307,14 @@ impl RouteManager {
     }
 
     /// Removes all routes previously applied in [`RouteManager::new`] or
-    /// [`RouteManager::add_routes`].
+    /// [`RouteManager::add_routes`]. This also stops enforcing the tunnel interface metric.
     pub fn clear_routes(&self) -> Result<()> {
+        if let Some(tx) = &self.manage_tx {
+            let _ = tx.unbounded_send(RouteManagerCommand::ClearTunnelInterfaceMetric);
+        }
         if winnet::routing_manager_delete_applied_routes() {
+            remove_routes(&self.journal);
+            self.validator.clear();
             Ok(())
         } else {
             Err(Error::ClearRoutesFailed)
@@ -186,9 +322,77 @@ impl RouteManager {
     }
 }
 
+fn winnet_routes(routes: &HashSet<RequiredRoute>) -> Vec<winnet::WinNetRoute> {
+    routes
+        .iter()
+        .map(|route| {
+            let destination = winnet::WinNetIpNetwork::from(route.prefix);
+            match &route.node {
+                NetNode::DefaultNode => winnet::WinNetRoute::through_default_node(destination),
+                NetNode::RealNode(node) => {
+                    winnet::WinNetRoute::new(winnet::WinNetNode::from(node), destination)
+                }
+            }
+        })
+        .collect()
+}
+
+/// Lists the network interfaces that are backed by hardware, such as Ethernet and Wi-Fi adapters.
+pub async fn get_physical_interfaces() -> Result<Vec<PhysicalInterfaceDetails>> {
+    let unicast_rows = crate::windows::get_unicast_table(None).map_err(Error::GetInterfaces)?;
+    let mut default_luids = vec![];
+    for family in [WinNetAddrFamily::IPV4, WinNetAddrFamily::IPV6] {
+        if let Some(route) =
+            winnet::get_best_default_route(family, &[]).map_err(Error::GetDefaultRoute)?
+        {
+            default_luids.push(route.interface_luid);
+        }
+    }
+
+    let mut interfaces = vec![];
+    for row in crate::windows::get_if_table().map_err(Error::GetInterfaces)? {
+        // The lowest bit of the flags is `HardwareInterface`
+        if row.InterfaceAndOperStatusFlags._bitfield & 1 == 0 {
+            continue;
+        }
+        let luid = unsafe { row.InterfaceLuid.Value };
+        let kind = match row.Type {
+            IF_TYPE_ETHERNET_CSMACD => PhysicalInterfaceKind::Ethernet,
+            IF_TYPE_IEEE80211 => PhysicalInterfaceKind::Wireless,
+            IF_TYPE_WWANPP | IF_TYPE_WWANPP2 => PhysicalInterfaceKind::Cellular,
+            _ => PhysicalInterfaceKind::Other,
+        };
+        let alias_len = row
+            .Alias
+            .iter()
+            .position(|&c| c == 0u16)
+            .unwrap_or(row.Alias.len());
+        let addresses = unicast_rows
+            .iter()
+            .filter(|address| unsafe { address.InterfaceLuid.Value } == luid)
+            .filter_map(|address| {
+                let ip = crate::windows::try_socketaddr_from_inet_sockaddr(address.Address)
+                    .ok()?
+                    .ip();
+                IpNetwork::new(ip, address.OnLinkPrefixLength).ok()
+            })
+            .collect();
+
+        interfaces.push(PhysicalInterfaceDetails {
+            name: String::from_utf16_lossy(&row.Alias[0..alias_len]),
+            index: row.InterfaceIndex,
+            kind,
+            addresses,
+            is_default_route: default_luids.contains(&luid),
+            mtu: Some(row.Mtu),
+        });
+    }
+    Ok(interfaces)
+}
+
 fn get_mtu_for_route(addr_family: WinNetAddrFamily) -> Result<Option<u16>> {
     use crate::windows::AddressFamily;
-    match winnet::get_best_default_route(addr_family) {
+    match winnet::get_best_default_route(addr_family, &[]) {
         Ok(Some(route)) => {
             let addr_family = match addr_family {
                 WinNetAddrFamily::IPV4 => AddressFamily::Ipv4,
@@ -214,6 +418,88 @@ fn get_mtu_for_route(addr_family: WinNetAddrFamily) -> Result<Option<u16>> {
     }
 }
 
+/// Keeps the metric of the tunnel interface at [`TUNNEL_INTERFACE_METRIC`]. Windows may reset
+/// the metric, e.g. when the network profile of the interface changes, which would cause traffic
+/// to be routed through other interfaces.
+struct InterfaceMetricMonitor {
+    _notifier: Box<crate::windows::IpNotifierHandle<'static>>,
+}
+
+impl InterfaceMetricMonitor {
+    fn start(luid: NET_LUID_LH) -> io::Result<Self> {
+        use crate::windows::AddressFamily;
+
+        for family in [AddressFamily::Ipv4, AddressFamily::Ipv6] {
+            match enforce_interface_metric(family, luid) {
+                Ok(_) => (),
+                // The interface may lack an IPv6 interface
+                Err(error) if matches!(family, AddressFamily::Ipv6) => {
+                    log::debug!("Not setting IPv6 metric of tunnel interface: {}", error);
+                }
+                Err(error) => return Err(error),
+            }
+        }
+
+        // Updating the interface from within the callback could cause it to be invoked recursively,
+        // so the metric is re-applied by a separate task.
+        let (change_tx, mut change_rx) = mpsc::unbounded();
+        let notifier = crate::windows::notify_ip_interface_change(
+            move |row, notification_type| {
+                if notification_type != MibParameterNotification {
+                    return;
+                }
+                if unsafe { row.InterfaceLuid.Value != luid.Value } {
+                    return;
+                }
+                if let Ok(family) = AddressFamily::try_from_af_family(row.Family) {
+                    let _ = change_tx.unbounded_send(family);
+                }
+            },
+            None,
+        )?;
+
+        tokio::spawn(async move {
+            while let Some(family) = change_rx.next().await {
+                match enforce_interface_metric(family, luid) {
+                    Ok(true) => log::warn!(
+                        "The {} metric of the tunnel interface was changed. Restored it to {}",
+                        family,
+                        TUNNEL_INTERFACE_METRIC
+                    ),
+                    Ok(false) => (),
+                    Err(error) => log::error!(
+                        "Failed to restore the {} metric of the tunnel interface: {}",
+                        family,
+                        error
+                    ),
+                }
+            }
+        });
+
+        Ok(Self {
+            _notifier: notifier,
+        })
+    }
+}
+
+/// Sets the metric of the interface to [`TUNNEL_INTERFACE_METRIC`] unless it already has that
+/// metric. Returns whether the metric was changed.
+fn enforce_interface_metric(
+    family: crate::windows::AddressFamily,
+    luid: NET_LUID_LH,
+) -> io::Result<bool> {
+    let mut row = crate::windows::get_ip_interface_entry(family, &luid)?;
+    if row.UseAutomaticMetric == 0 && row.Metric == TUNNEL_INTERFACE_METRIC {
+        return Ok(false);
+    }
+    row.Metric = TUNNEL_INTERFACE_METRIC;
+    row.UseAutomaticMetric = 0;
+    // `SitePrefixLength` must be zero when updating the entry
+    row.SitePrefixLength = 0;
+    crate::windows::set_ip_interface_entry(&mut row)?;
+    Ok(true)
+}
+
 impl Drop for RouteManager {
     fn drop(&mut self) {
         self.stop();
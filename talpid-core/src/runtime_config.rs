@@ -0,0 +1,273 @@
+//! Configuration that can be changed while the daemon is running, e.g. to log a subsystem more
+//! verbosely or to try an experimental feature during a support session. It is not persisted, so
+//! a restart restores the defaults.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env, fmt,
+    str::FromStr,
+};
+use tokio::sync::watch;
+
+lazy_static::lazy_static! {
+    static ref CONFIG: (watch::Sender<RuntimeConfig>, watch::Receiver<RuntimeConfig>) =
+        watch::channel(RuntimeConfig::from_env());
+    /// Serializes updates, so that concurrent updates are not lost.
+    static ref UPDATE_LOCK: parking_lot::Mutex<()> = parking_lot::Mutex::new(());
+}
+
+/// Errors that can happen when parsing runtime configuration.
+#[derive(err_derive::Error, Debug, Clone, PartialEq, Eq)]
+#[error(no_from)]
+pub enum Error {
+    /// A log filter directive is not of the form `[module=]level`.
+    #[error(display = "Invalid log filter directive: {}", _0)]
+    InvalidLogFilter(String),
+
+    /// No feature has the given name.
+    #[error(display = "Unknown feature: {}", _0)]
+    UnknownFeature(String),
+}
+
+/// Experimental and debugging features that can be toggled at runtime. A feature is initially
+/// enabled if its environment variable is set to a value other than `0`. Changes take effect the
+/// next time the feature is used, e.g. on the next connection attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FeatureFlag {
+    /// Measure whether traffic escapes the tunnel while reconnecting.
+    LeakCanary,
+    /// Use wireguard-go instead of the WireGuard kernel module.
+    #[cfg(target_os = "linux")]
+    ForceUserspaceWireguard,
+    /// Add counters to the firewall rules.
+    #[cfg(target_os = "linux")]
+    FirewallCounters,
+    /// Check that the gateway responds to ARP or NDP before connecting through it.
+    #[cfg(target_os = "linux")]
+    GatewayProbe,
+}
+
+impl FeatureFlag {
+    /// All features that are available on this platform.
+    pub const ALL: &'static [FeatureFlag] = &[
+        FeatureFlag::LeakCanary,
+        #[cfg(target_os = "linux")]
+        FeatureFlag::ForceUserspaceWireguard,
+        #[cfg(target_os = "linux")]
+        FeatureFlag::FirewallCounters,
+        #[cfg(target_os = "linux")]
+        FeatureFlag::GatewayProbe,
+    ];
+
+    /// Returns the name that identifies the feature.
+    pub fn name(&self) -> &'static str {
+        match self {
+            FeatureFlag::LeakCanary => "leak-canary",
+            #[cfg(target_os = "linux")]
+            FeatureFlag::ForceUserspaceWireguard => "force-userspace-wireguard",
+            #[cfg(target_os = "linux")]
+            FeatureFlag::FirewallCounters => "firewall-counters",
+            #[cfg(target_os = "linux")]
+            FeatureFlag::GatewayProbe => "gateway-probe",
+        }
+    }
+
+    fn env_var(&self) -> &'static str {
+        match self {
+            FeatureFlag::LeakCanary => "TALPID_LEAK_CANARY",
+            #[cfg(target_os = "linux")]
+            FeatureFlag::ForceUserspaceWireguard => "TALPID_FORCE_USERSPACE_WIREGUARD",
+            #[cfg(target_os = "linux")]
+            FeatureFlag::FirewallCounters => "TALPID_FIREWALL_DEBUG",
+            #[cfg(target_os = "linux")]
+            FeatureFlag::GatewayProbe => "TALPID_GATEWAY_PROBE",
+        }
+    }
+}
+
+impl fmt::Display for FeatureFlag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for FeatureFlag {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        FeatureFlag::ALL
+            .iter()
+            .find(|feature| feature.name() == name)
+            .copied()
+            .ok_or_else(|| Error::UnknownFeature(name.to_owned()))
+    }
+}
+
+/// Log levels for specific modules and their submodules, which take precedence over the log level
+/// that the daemon was started with. Written as comma separated `module=level` directives, where
+/// a directive without a module applies to all modules, e.g. `info,talpid_core::firewall=trace`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogFilter {
+    /// Levels by module. The empty module matches all modules.
+    directives: BTreeMap<String, log::LevelFilter>,
+}
+
+impl LogFilter {
+    /// Returns whether the filter has no directives.
+    pub fn is_empty(&self) -> bool {
+        self.directives.is_empty()
+    }
+
+    /// Returns the level of the most specific directive that applies to `target`, if any.
+    pub fn level_for(&self, target: &str) -> Option<log::LevelFilter> {
+        self.directives
+            .iter()
+            .rev()
+            .find(|(module, _)| {
+                module.is_empty()
+                    || target == module.as_str()
+                    || (target.starts_with(module.as_str())
+                        && target[module.len()..].starts_with("::"))
+            })
+            .map(|(_, level)| *level)
+    }
+
+    /// Returns the most verbose level of any directive.
+    pub fn max_level(&self) -> Option<log::LevelFilter> {
+        self.directives.values().max().copied()
+    }
+}
+
+impl FromStr for LogFilter {
+    type Err = Error;
+
+    fn from_str(filter: &str) -> Result<Self, Self::Err> {
+        let mut directives = BTreeMap::new();
+        for directive in filter.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            let (module, level) = match directive.split_once('=') {
+                Some((module, level)) => (module.trim(), level.trim()),
+                None => ("", directive),
+            };
+            let level = log::LevelFilter::from_str(level)
+                .map_err(|_| Error::InvalidLogFilter(directive.to_owned()))?;
+            directives.insert(module.to_owned(), level);
+        }
+        Ok(LogFilter { directives })
+    }
+}
+
+impl fmt::Display for LogFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let directives: Vec<_> = self
+            .directives
+            .iter()
+            .map(|(module, level)| {
+                let level = level.to_string().to_lowercase();
+                if module.is_empty() {
+                    level
+                } else {
+                    format!("{}={}", module, level)
+                }
+            })
+            .collect();
+        f.write_str(&directives.join(","))
+    }
+}
+
+/// Configuration that can be changed without restarting the daemon.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RuntimeConfig {
+    /// Log levels that override the log level of the daemon.
+    pub log_filter: LogFilter,
+    /// Features that are enabled.
+    pub enabled_features: BTreeSet<FeatureFlag>,
+}
+
+impl RuntimeConfig {
+    fn from_env() -> Self {
+        let enabled_features = FeatureFlag::ALL
+            .iter()
+            .filter(|feature| {
+                env::var(feature.env_var())
+                    .map(|v| v != "0")
+                    .unwrap_or(false)
+            })
+            .copied()
+            .collect();
+        RuntimeConfig {
+            log_filter: LogFilter::default(),
+            enabled_features,
+        }
+    }
+}
+
+/// Returns the current configuration.
+pub fn get() -> RuntimeConfig {
+    CONFIG.1.borrow().clone()
+}
+
+/// Replaces the current configuration and notifies subscribers if it changed.
+pub fn set(config: RuntimeConfig) {
+    let _lock = UPDATE_LOCK.lock();
+    if *CONFIG.1.borrow() == config {
+        return;
+    }
+    log::info!(
+        "Runtime configuration changed: log filter \"{}\", enabled features: {:?}",
+        config.log_filter,
+        config.enabled_features
+    );
+    // The static receiver is never dropped, so this cannot fail
+    let _ = CONFIG.0.send(config);
+}
+
+/// Returns a receiver that is notified whenever the configuration changes.
+pub fn subscribe() -> watch::Receiver<RuntimeConfig> {
+    CONFIG.1.clone()
+}
+
+/// Returns whether `feature` is enabled.
+pub fn is_enabled(feature: FeatureFlag) -> bool {
+    CONFIG.1.borrow().enabled_features.contains(&feature)
+}
+
+/// Returns the level of the most specific directive in the log filter that applies to `target`.
+pub fn log_level_for(target: &str) -> Option<log::LevelFilter> {
+    CONFIG.1.borrow().log_filter.level_for(target)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_log_filter() {
+        let filter: LogFilter = "warn, talpid_core::firewall=trace,talpid_core=debug"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            filter.level_for("talpid_core::firewall::linux"),
+            Some(log::LevelFilter::Trace)
+        );
+        assert_eq!(
+            filter.level_for("talpid_core::firewall"),
+            Some(log::LevelFilter::Trace)
+        );
+        assert_eq!(
+            filter.level_for("talpid_core::firewalls"),
+            Some(log::LevelFilter::Debug)
+        );
+        assert_eq!(
+            filter.level_for("mullvad_daemon"),
+            Some(log::LevelFilter::Warn)
+        );
+        assert_eq!(filter.max_level(), Some(log::LevelFilter::Trace));
+        assert_eq!(
+            filter.to_string(),
+            "warn,talpid_core=debug,talpid_core::firewall=trace"
+        );
+
+        assert_eq!(LogFilter::from_str("").unwrap(), LogFilter::default());
+        assert!(LogFilter::from_str("talpid_core=loud").is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use std::{
     env, fs,
     io::{self, BufRead, BufReader, Write},
+    os::unix::io::RawFd,
     path::PathBuf,
 };
 use talpid_types::cgroup::{find_net_cls_mount, SPLIT_TUNNEL_CGROUP_NAME};
@@ -14,6 +15,10 @@ pub const NET_CLS_CLASSID: u32 = 0x4d9f41;
 /// Value used to mark packets and associated connections.
 /// This should be an arbitrary but unique integer.
 pub const MARK: i32 = 0xf41;
+/// Firewall mark of sockets that are excluded from the tunnel. Their traffic is treated like the
+/// traffic of excluded processes.
+/// This should be an arbitrary but unique integer.
+pub const SOCKET_BYPASS_MARK: u32 = 0xf42;
 
 /// Errors related to split tunneling.
 #[derive(err_derive::Error, Debug)]
@@ -46,6 +51,17 @@ pub enum Error {
     /// Unable to read /proc/mounts
     #[error(display = "Failed to read /proc/mounts")]
     ListMounts(#[error(source)] io::Error),
+
+    /// Split tunneling could not be initialized when the daemon started.
+    #[error(display = "Split tunneling is unavailable")]
+    Unavailable,
+}
+
+/// Excludes a socket from the tunnel by setting its firewall mark. This requires
+/// `CAP_NET_ADMIN`, and fails if `fd` is not a socket.
+pub fn exclude_socket(fd: RawFd) -> io::Result<()> {
+    nix::sys::socket::setsockopt(fd, nix::sys::socket::sockopt::Mark, &SOCKET_BYPASS_MARK)
+        .map_err(io::Error::from)
 }
 
 /// Manages PIDs in the Linux Cgroup excluded from the VPN tunnel.
@@ -2,13 +2,17 @@ use super::windows::{
     get_device_path, get_process_creation_time, get_process_device_path, open_process, Event,
     Overlapped, ProcessAccess, ProcessSnapshot,
 };
-use crate::windows::as_uninit_byte_slice;
+use crate::windows::{
+    arch::{expect_size, LayoutMismatch},
+    as_uninit_byte_slice,
+};
 use bitflags::bitflags;
 use memoffset::offset_of;
 use std::{
     cell::RefCell,
     collections::HashMap,
     ffi::{OsStr, OsString},
+    fmt,
     fs::{self, OpenOptions},
     io,
     mem::{self, size_of, MaybeUninit},
@@ -25,8 +29,9 @@ use std::{
 use talpid_types::ErrorExt;
 use windows_sys::Win32::{
     Foundation::{
-        ERROR_ACCESS_DENIED, ERROR_FILE_NOT_FOUND, ERROR_INVALID_PARAMETER, ERROR_IO_PENDING,
-        HANDLE, NTSTATUS, WAIT_ABANDONED, WAIT_ABANDONED_0, WAIT_FAILED, WAIT_OBJECT_0,
+        ERROR_ACCESS_DENIED, ERROR_FILE_NOT_FOUND, ERROR_INVALID_FUNCTION, ERROR_INVALID_PARAMETER,
+        ERROR_IO_PENDING, HANDLE, NTSTATUS, WAIT_ABANDONED, WAIT_ABANDONED_0, WAIT_FAILED,
+        WAIT_OBJECT_0,
     },
     Networking::WinSock::{IN6_ADDR, IN_ADDR},
     Storage::FileSystem::FILE_FLAG_OVERLAPPED,
@@ -60,6 +65,33 @@ pub enum DriverIoctlCode {
     GetState = ctl_code(ST_DEVICE_TYPE, 9, METHOD_BUFFERED, FILE_ANY_ACCESS),
     QueryProcess = ctl_code(ST_DEVICE_TYPE, 10, METHOD_BUFFERED, FILE_ANY_ACCESS),
     Reset = ctl_code(ST_DEVICE_TYPE, 11, METHOD_NEITHER, FILE_ANY_ACCESS),
+    GetDriverVersion = ctl_code(ST_DEVICE_TYPE, 12, METHOD_BUFFERED, FILE_ANY_ACCESS),
+}
+
+/// Version of the interface between the driver and this module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DriverVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl DriverVersion {
+    /// The version that this module is written against.
+    pub const EXPECTED: DriverVersion = DriverVersion { major: 1, minor: 0 };
+    /// The version of drivers that do not support `GetDriverVersion`.
+    const LEGACY: DriverVersion = DriverVersion { major: 1, minor: 0 };
+
+    /// Returns whether a driver of this version can be used by a module that expects `expected`.
+    /// Minor versions only add functionality, so a newer minor version is compatible.
+    pub fn is_compatible_with(&self, expected: DriverVersion) -> bool {
+        self.major == expected.major && self.minor >= expected.minor
+    }
+}
+
+impl fmt::Display for DriverVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -188,6 +220,21 @@ pub enum DeviceHandleError {
     #[error(display = "Failed to connect to driver")]
     ConnectionError(#[error(source)] io::Error),
 
+    /// Failed to inquire about driver version
+    #[error(display = "Failed to inquire about driver version")]
+    GetVersionError(#[error(source)] io::Error),
+
+    /// The driver does not implement the expected interface
+    #[error(
+        display = "Incompatible driver version: expected {}, found {}",
+        expected,
+        found
+    )]
+    IncompatibleDriver {
+        expected: DriverVersion,
+        found: DriverVersion,
+    },
+
     /// Failed to inquire about driver state
     #[error(display = "Failed to inquire about driver state")]
     GetStateError(#[error(source)] io::Error),
@@ -212,6 +259,7 @@ pub enum DeviceHandleError {
 impl DeviceHandle {
     pub fn new() -> Result<Self, DeviceHandleError> {
         let device = Self::new_handle_only()?;
+        device.check_version()?;
         device.reinitialize()?;
         Ok(device)
     }
@@ -234,6 +282,22 @@ impl DeviceHandle {
         Ok(Self { handle })
     }
 
+    /// Fails if the driver does not implement the interface that this module expects. This must
+    /// be checked before any other request is sent to the driver.
+    fn check_version(&self) -> Result<(), DeviceHandleError> {
+        let found = self
+            .get_driver_version()
+            .map_err(DeviceHandleError::GetVersionError)?;
+        if !found.is_compatible_with(DriverVersion::EXPECTED) {
+            return Err(DeviceHandleError::IncompatibleDriver {
+                expected: DriverVersion::EXPECTED,
+                found,
+            });
+        }
+        log::debug!("Split tunnel driver version: {}", found);
+        Ok(())
+    }
+
     pub fn reinitialize(&self) -> Result<(), DeviceHandleError> {
         let state = self
             .get_driver_state()
@@ -343,6 +407,35 @@ impl DeviceHandle {
             .map_err(|error| io::Error::new(io::ErrorKind::Other, error))?)
     }
 
+    pub fn get_driver_version(&self) -> io::Result<DriverVersion> {
+        let buffer = match device_io_control(
+            self,
+            DriverIoctlCode::GetDriverVersion as u32,
+            None,
+            size_of::<DriverVersionBuffer>() as u32,
+        ) {
+            Ok(Some(buffer)) => buffer,
+            Ok(None) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "the driver returned no version",
+                ))
+            }
+            Err(error) if error.raw_os_error() == Some(ERROR_INVALID_FUNCTION as i32) => {
+                return Ok(DriverVersion::LEGACY)
+            }
+            Err(error) => return Err(error),
+        };
+
+        let version: DriverVersionBuffer = unsafe {
+            deserialize_buffer(&buffer[0..buffer.len().min(size_of::<DriverVersionBuffer>())])
+        };
+        Ok(DriverVersion {
+            major: version.major,
+            minor: version.minor,
+        })
+    }
+
     pub fn set_config<T: AsRef<OsStr>>(&self, apps: &[T]) -> io::Result<()> {
         let mut device_paths = Vec::with_capacity(apps.len());
         for app in apps.as_ref() {
@@ -397,6 +490,13 @@ impl AsRawHandle for DeviceHandle {
     }
 }
 
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct DriverVersionBuffer {
+    major: u32,
+    minor: u32,
+}
+
 #[derive(Clone, Copy)]
 #[repr(C)]
 struct SplitTunnelAddresses {
@@ -651,6 +751,20 @@ struct ErrorMessageEventHeader {
     error_message_data: [u16; 0],
 }
 
+/// Checks that the structures passed to and from the driver match the layouts used by the driver.
+pub fn check_layouts() -> Result<(), LayoutMismatch> {
+    expect_size::<DriverVersionBuffer>("DriverVersionBuffer", 8, 8)?;
+    expect_size::<SplitTunnelAddresses>("SplitTunnelAddresses", 40, 40)?;
+    expect_size::<ConfigurationHeader>("ConfigurationHeader", 8, 16)?;
+    expect_size::<ConfigurationEntry>("ConfigurationEntry", 8, 16)?;
+    expect_size::<ProcessRegistryHeader>("ProcessRegistryHeader", 8, 16)?;
+    expect_size::<ProcessRegistryEntry>("ProcessRegistryEntry", 16, 32)?;
+    expect_size::<EventHeader>("EventHeader", 8, 16)?;
+    expect_size::<SplittingEventHeader>("SplittingEventHeader", 12, 16)?;
+    expect_size::<SplittingErrorEventHeader>("SplittingErrorEventHeader", 8, 16)?;
+    expect_size::<ErrorMessageEventHeader>("ErrorMessageEventHeader", 8, 8)
+}
+
 /// Parses an event returned by the ST driver.
 ///
 /// # Panics
@@ -968,12 +1082,13 @@ fn buffer_to_osstring(buffer: &[u8]) -> OsString {
     let mut out_buf = Vec::new();
     out_buf.resize((buffer.len() + 1) / mem::size_of::<u16>(), 0u16);
 
+    // The buffer is copied byte by byte, since it is not necessarily aligned for `u16`.
     // SAFETY: `out_buf` contains enough bytes to store all of `buffer`.
     unsafe {
         ptr::copy_nonoverlapping(
-            buffer as *const _ as *const u16,
-            out_buf.as_mut_ptr(),
-            out_buf.len(),
+            buffer.as_ptr(),
+            out_buf.as_mut_ptr() as *mut u8,
+            buffer.len(),
         )
     };
     OsStringExt::from_wide(&out_buf)
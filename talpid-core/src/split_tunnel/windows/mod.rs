@@ -1,14 +1,18 @@
 mod driver;
 mod path_monitor;
+mod path_resolver;
 mod service;
 mod volume_monitor;
 mod windows;
 
+pub use driver::DriverVersion;
+
 use crate::{
     tunnel::TunnelMetadata,
     tunnel_state_machine::TunnelCommand,
     windows::{
-        get_ip_address_for_interface,
+        arch::{self, Architecture, LayoutMismatch},
+        get_ip_address_for_interface, luid_from_alias,
         window::{PowerManagementEvent, PowerManagementListener},
         AddressFamily,
     },
@@ -26,7 +30,7 @@ use std::{
         atomic::{AtomicBool, Ordering},
         mpsc as sync_mpsc, Arc, Mutex, RwLock, Weak,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 use talpid_types::{tunnel::ErrorStateCause, ErrorExt};
 use windows_sys::Win32::{
@@ -36,6 +40,9 @@ use windows_sys::Win32::{
 const DRIVER_EVENT_BUFFER_SIZE: usize = 2048;
 const RESERVED_IP_V4: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 123);
 
+/// Architectures that the split tunnel driver is available for.
+const DRIVER_ARCHITECTURES: &[Architecture] = &[Architecture::X64];
+
 /// Errors that may occur in [`SplitTunnel`].
 #[derive(err_derive::Error, Debug)]
 #[error(no_from)]
@@ -48,6 +55,21 @@ pub enum Error {
     #[error(display = "Failed to initialize driver")]
     InitializationError(#[error(source)] driver::DeviceHandleError),
 
+    /// Failed to remove the driver service before reinstalling it
+    #[error(display = "Failed to remove driver service")]
+    RemoveService(#[error(source)] service::Error),
+
+    /// The loaded driver does not implement the interface that this module expects
+    #[error(
+        display = "Incompatible driver version: expected {}, found {}",
+        expected,
+        found
+    )]
+    IncompatibleDriver {
+        expected: driver::DriverVersion,
+        found: driver::DriverVersion,
+    },
+
     /// Failed to reset the driver
     #[error(display = "Failed to reset driver")]
     ResetError(#[error(source)] io::Error),
@@ -107,6 +129,18 @@ pub enum Error {
     /// Resetting in the engaged state risks leaking into the tunnel
     #[error(display = "Failed to reset driver because it is engaged")]
     CannotResetEngaged,
+
+    /// No driver is loaded, because it is incompatible or failed to be reinstalled
+    #[error(display = "No split tunnel driver is loaded")]
+    NoDriver,
+
+    /// The driver is still in use by another thread, so it cannot be reinstalled
+    #[error(display = "The driver handle is still in use")]
+    DriverInUse,
+
+    /// There is no driver for the architecture of the OS
+    #[error(display = "The split tunnel driver is not available on {} Windows", _0)]
+    DriverUnavailable(Architecture),
 }
 
 /// Manages applications whose traffic to exclude from the tunnel.
@@ -126,12 +160,22 @@ enum Request {
     SetPaths(Vec<OsString>),
     RegisterIps(InterfaceAddresses),
     Restart,
+    ReinstallDriver,
     Stop,
 }
 type RequestResponseTx = sync_mpsc::Sender<Result<(), Error>>;
 type RequestTx = sync_mpsc::Sender<(Request, RequestResponseTx)>;
 
 const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Starting the request thread may involve reinstalling the driver, which takes longer than other
+/// requests.
+const INIT_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long to wait for other threads to release the driver handle before reinstalling it.
+const RELEASE_HANDLE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Connection to the driver that is shared between threads. It is replaced when the driver is
+/// reinstalled, and is `None` while reinstalling or if no compatible driver could be loaded.
+type SharedDeviceHandle = Arc<RwLock<Option<Arc<driver::DeviceHandle>>>>;
 
 #[derive(Default, PartialEq, Clone)]
 struct InterfaceAddresses {
@@ -190,11 +234,41 @@ impl SplitTunnel {
     ) -> Result<Self, Error> {
         let excluded_processes = Arc::new(RwLock::new(HashMap::new()));
 
-        let (request_tx, handle) =
+        let native_arch = arch::native_architecture().unwrap_or_else(|error| {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to determine the OS architecture")
+            );
+            arch::process_architecture()
+        });
+        if !DRIVER_ARCHITECTURES.contains(&native_arch) {
+            log::warn!(
+                "Split tunneling is not available on {} Windows",
+                native_arch
+            );
+            let request_tx = Self::spawn_unavailable_request_thread(native_arch);
+            let power_mgmt_handle =
+                Self::spawn_power_management_monitor(request_tx.clone(), power_mgmt_rx);
+            return Ok(SplitTunnel {
+                runtime,
+                request_tx,
+                event_thread: None,
+                quit_event: Arc::new(
+                    windows::Event::new(true, false).map_err(Error::EventThreadError)?,
+                ),
+                _route_change_callback: None,
+                daemon_tx,
+                async_path_update_in_progress: Arc::new(AtomicBool::new(false)),
+                excluded_processes,
+                power_mgmt_handle,
+            });
+        }
+
+        let (request_tx, shared_handle) =
             Self::spawn_request_thread(resource_dir, volume_update_rx, excluded_processes.clone())?;
 
         let (event_thread, quit_event) =
-            Self::spawn_event_listener(handle, excluded_processes.clone())?;
+            Self::spawn_event_listener(shared_handle, excluded_processes.clone())?;
 
         let power_mgmt_handle =
             Self::spawn_power_management_monitor(request_tx.clone(), power_mgmt_rx);
@@ -214,7 +288,7 @@ impl SplitTunnel {
 
     /// Spawns an event loop thread that processes events from the driver service.
     fn spawn_event_listener(
-        handle: Arc<driver::DeviceHandle>,
+        shared_handle: SharedDeviceHandle,
         excluded_processes: Arc<RwLock<HashMap<usize, ExcludedProcess>>>,
     ) -> Result<(std::thread::JoinHandle<()>, Arc<windows::Event>), Error> {
         let mut event_overlapped = windows::Overlapped::new(Some(
@@ -231,6 +305,24 @@ impl SplitTunnel {
             let mut data_buffer = vec![];
 
             loop {
+                let handle = match shared_handle.read().unwrap().clone() {
+                    Some(handle) => handle,
+                    None => {
+                        // Wait for the driver to be reinstalled, or for the quit event.
+                        if unsafe {
+                            driver::wait_for_single_object(
+                                quit_event.as_handle(),
+                                Some(Duration::from_millis(500)),
+                            )
+                        }
+                        .is_ok()
+                        {
+                            break;
+                        }
+                        continue;
+                    }
+                };
+
                 // Wait until either the next event is received or the quit event is signaled.
                 let (event_id, event_body) = match Self::fetch_next_event(
                     &handle,
@@ -414,10 +506,13 @@ impl SplitTunnel {
         resource_dir: PathBuf,
         volume_update_rx: mpsc::UnboundedReceiver<()>,
         excluded_processes: Arc<RwLock<HashMap<usize, ExcludedProcess>>>,
-    ) -> Result<(RequestTx, Arc<driver::DeviceHandle>), Error> {
+    ) -> Result<(RequestTx, SharedDeviceHandle), Error> {
         let (tx, rx): (RequestTx, _) = sync_mpsc::channel();
         let (init_tx, init_rx) = sync_mpsc::channel();
 
+        let shared_handle: SharedDeviceHandle = Arc::new(RwLock::new(None));
+        let shared_handle_copy = shared_handle.clone();
+
         let monitored_paths = Arc::new(Mutex::new(vec![]));
         let monitored_paths_copy = monitored_paths.clone();
 
@@ -425,6 +520,7 @@ impl SplitTunnel {
 
         let path_monitor = path_monitor::PathMonitor::spawn(monitor_tx.clone())
             .map_err(Error::StartPathMonitor)?;
+        let path_monitor_copy = path_monitor.clone();
         let volume_monitor = volume_monitor::VolumeMonitor::spawn(
             path_monitor.clone(),
             monitor_tx,
@@ -433,44 +529,45 @@ impl SplitTunnel {
         );
 
         std::thread::spawn(move || {
-            let init_fn = || {
-                service::install_driver_if_required(&resource_dir).map_err(Error::ServiceError)?;
-                driver::DeviceHandle::new()
-                    .map(Arc::new)
-                    .map_err(Error::InitializationError)
-            };
-
-            let handle = match init_fn() {
-                Ok(handle) => {
-                    let _ = init_tx.send(Ok(handle.clone()));
-                    handle
+            let mut handle = match open_driver(&resource_dir) {
+                Ok(handle) => Some(Arc::new(handle)),
+                Err(error @ Error::IncompatibleDriver { .. }) => {
+                    // Keep running, so that the driver can be reinstalled later
+                    log::error!(
+                        "{}",
+                        error.display_chain_with_msg("Split tunneling is unavailable")
+                    );
+                    None
                 }
                 Err(error) => {
                     let _ = init_tx.send(Err(error));
                     return;
                 }
             };
+            *shared_handle.write().unwrap() = handle.clone();
+            let _ = init_tx.send(Ok(()));
 
             let mut previous_addresses = InterfaceAddresses::default();
 
             while let Ok((request, response_tx)) = rx.recv() {
                 let response = match request {
                     Request::SetPaths(paths) => {
-                        let mut monitored_paths_guard = monitored_paths.lock().unwrap();
+                        // Resolving may take a while, so don't block the monitor thread meanwhile
+                        let resolved_paths = path_resolver::resolve_paths(&paths);
+                        let directories = path_resolver::watched_directories(&paths);
 
-                        let result = if paths.len() > 0 {
-                            handle.set_config(&paths).map_err(Error::SetConfiguration)
-                        } else {
-                            handle.clear_config().map_err(Error::SetConfiguration)
+                        let mut monitored_paths_guard = monitored_paths.lock().unwrap();
+                        let result = match &handle {
+                            Some(handle) if resolved_paths.len() > 0 => handle
+                                .set_config(&resolved_paths)
+                                .map_err(Error::SetConfiguration),
+                            Some(handle) => handle.clear_config().map_err(Error::SetConfiguration),
+                            None if resolved_paths.len() > 0 => Err(Error::NoDriver),
+                            None => Ok(()),
                         };
 
                         if result.is_ok() {
-                            if let Err(error) = path_monitor.set_paths(&paths) {
-                                log::error!(
-                                    "{}",
-                                    error.display_chain_with_msg("Failed to update path monitor")
-                                );
-                            }
+                            Self::update_path_monitor(&path_monitor, &resolved_paths, &directories);
                             *monitored_paths_guard = paths.to_vec();
                         }
 
@@ -484,14 +581,18 @@ impl SplitTunnel {
                         if previous_addresses == ips {
                             Ok(())
                         } else {
-                            let result = handle
-                                .register_ips(
-                                    ips.tunnel_ipv4,
-                                    ips.tunnel_ipv6,
-                                    ips.internet_ipv4,
-                                    ips.internet_ipv6,
-                                )
-                                .map_err(Error::RegisterIps);
+                            let result = match &handle {
+                                Some(handle) => handle
+                                    .register_ips(
+                                        ips.tunnel_ipv4,
+                                        ips.tunnel_ipv6,
+                                        ips.internet_ipv4,
+                                        ips.internet_ipv6,
+                                    )
+                                    .map_err(Error::RegisterIps),
+                                // The addresses are registered if a driver is loaded later
+                                None => Ok(()),
+                            };
                             if result.is_ok() {
                                 previous_addresses = ips;
                             }
@@ -499,45 +600,50 @@ impl SplitTunnel {
                         }
                     }
                     Request::Restart => {
-                        let monitored_paths_guard = monitored_paths.lock().unwrap();
-                        (|| {
-                            let state = handle.get_driver_state().map_err(Error::GetState)?;
-                            if state == driver::DriverState::Engaged {
-                                // Leaving the engaged state risks leaking traffic into the tunnel,
-                                // so err on the safe side.
-                                log::warn!(
-                                    "Not resetting driver state because it is currently engaged"
-                                );
-                                return Err(Error::CannotResetEngaged);
-                            }
-
-                            handle.reinitialize().map_err(Error::InitializationError)?;
-
-                            {
-                                excluded_processes.write().unwrap().clear();
-                            }
-
-                            handle
-                                .register_ips(
-                                    previous_addresses.tunnel_ipv4,
-                                    previous_addresses.tunnel_ipv6,
-                                    previous_addresses.internet_ipv4,
-                                    previous_addresses.internet_ipv6,
+                        let paths = monitored_paths.lock().unwrap().clone();
+                        match &handle {
+                            Some(handle) => (|| {
+                                let state = handle.get_driver_state().map_err(Error::GetState)?;
+                                if state == driver::DriverState::Engaged {
+                                    // Leaving the engaged state risks leaking traffic into the
+                                    // tunnel, so err on the safe side.
+                                    log::warn!(
+                                        "Not resetting driver state because it is currently engaged"
+                                    );
+                                    return Err(Error::CannotResetEngaged);
+                                }
+
+                                handle.reinitialize().map_err(Error::InitializationError)?;
+
+                                Self::restore_driver_state(
+                                    handle,
+                                    &excluded_processes,
+                                    &previous_addresses,
+                                    &paths,
                                 )
-                                .map_err(Error::RegisterIps)?;
-
-                            if monitored_paths_guard.len() > 0 {
-                                handle
-                                    .set_config(&*monitored_paths_guard)
-                                    .map_err(Error::SetConfiguration)?;
-                            }
-                            Ok(())
-                        })()
+                            })(),
+                            None => Ok(()),
+                        }
+                    }
+                    Request::ReinstallDriver => {
+                        let paths = monitored_paths.lock().unwrap().clone();
+                        Self::reinstall_driver(&mut handle, &shared_handle, &resource_dir).and_then(
+                            |handle| {
+                                Self::restore_driver_state(
+                                    &handle,
+                                    &excluded_processes,
+                                    &previous_addresses,
+                                    &paths,
+                                )
+                            },
+                        )
                     }
                     Request::Stop => {
-                        if let Err(error) = handle.reset().map_err(Error::ResetError) {
-                            let _ = response_tx.send(Err(error));
-                            continue;
+                        if let Some(handle) = &handle {
+                            if let Err(error) = handle.reset().map_err(Error::ResetError) {
+                                let _ = response_tx.send(Err(error));
+                                continue;
+                            }
                         }
 
                         monitored_paths.lock().unwrap().clear();
@@ -562,6 +668,7 @@ impl SplitTunnel {
                 );
             }
 
+            *shared_handle.write().unwrap() = None;
             drop(handle);
 
             log::debug!("Stopping ST service");
@@ -573,31 +680,154 @@ impl SplitTunnel {
             }
         });
 
-        let handle = init_rx
-            .recv_timeout(REQUEST_TIMEOUT)
+        init_rx
+            .recv_timeout(INIT_TIMEOUT)
             .map_err(|_| Error::RequestThreadStuck)??;
 
-        let handle_copy = handle.clone();
+        let monitor_handle = shared_handle_copy.clone();
 
         std::thread::spawn(move || {
             while let Ok(()) = monitor_rx.recv() {
-                let paths = monitored_paths_copy.lock().unwrap();
-                let result = if paths.len() > 0 {
-                    log::debug!("Re-resolving excluded paths");
-                    handle_copy.set_config(&*paths)
-                } else {
+                // Changes tend to come in bursts, e.g. when an application is installed
+                while let Ok(()) = monitor_rx.try_recv() {}
+
+                let handle = match monitor_handle.read().unwrap().clone() {
+                    Some(handle) => handle,
+                    None => continue,
+                };
+                let paths = monitored_paths_copy.lock().unwrap().clone();
+                if paths.is_empty() {
+                    continue;
+                }
+
+                log::debug!("Re-resolving excluded paths");
+                let resolved_paths = path_resolver::resolve_paths(&paths);
+                let directories = path_resolver::watched_directories(&paths);
+
+                let paths_guard = monitored_paths_copy.lock().unwrap();
+                if *paths_guard != paths {
+                    // The paths were replaced while resolving them
                     continue;
+                }
+                let result = if resolved_paths.len() > 0 {
+                    handle.set_config(&resolved_paths)
+                } else {
+                    handle.clear_config()
                 };
-                if let Err(error) = result {
-                    log::error!(
+                match result {
+                    Ok(()) => {
+                        Self::update_path_monitor(&path_monitor_copy, &resolved_paths, &directories)
+                    }
+                    Err(error) => log::error!(
                         "{}",
                         error.display_chain_with_msg("Failed to update excluded paths")
-                    );
+                    ),
                 }
             }
         });
 
-        Ok((tx, handle))
+        Ok((tx, shared_handle_copy))
+    }
+
+    /// Replaces the driver with the one in the resource directory. Fails without doing anything
+    /// if the current driver is engaged.
+    fn reinstall_driver(
+        handle: &mut Option<Arc<driver::DeviceHandle>>,
+        shared_handle: &SharedDeviceHandle,
+        resource_dir: &Path,
+    ) -> Result<Arc<driver::DeviceHandle>, Error> {
+        if let Some(current_handle) = handle {
+            // A driver that cannot report its state is reinstalled anyway, since that is probably
+            // why it is being reinstalled.
+            if let Ok(driver::DriverState::Engaged) = current_handle.get_driver_state() {
+                log::warn!("Not reinstalling driver because it is currently engaged");
+                return Err(Error::CannotResetEngaged);
+            }
+        }
+
+        log::info!("Reinstalling split tunnel driver");
+
+        *shared_handle.write().unwrap() = None;
+        if let Some(old_handle) = handle.take() {
+            // Abort pending requests, so that the event thread releases the handle.
+            if let Err(error) = old_handle.reset() {
+                log::warn!("{}", error.display_chain_with_msg("Failed to reset driver"));
+            }
+            release_handle(old_handle)?;
+        }
+
+        let new_handle = Arc::new(reinstall_and_open_driver(resource_dir)?);
+        *handle = Some(new_handle.clone());
+        *shared_handle.write().unwrap() = Some(new_handle.clone());
+        Ok(new_handle)
+    }
+
+    /// Registers the addresses and excluded paths with a driver that has just been initialized.
+    /// Watches the resolved paths for changes to their links, and `directories` for changes to
+    /// their contents.
+    fn update_path_monitor(
+        path_monitor: &path_monitor::PathMonitorHandle,
+        resolved_paths: &[OsString],
+        directories: &[PathBuf],
+    ) {
+        if let Err(error) = path_monitor
+            .set_paths(resolved_paths)
+            .and_then(|()| path_monitor.set_directories(directories))
+        {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to update path monitor")
+            );
+        }
+    }
+
+    fn restore_driver_state(
+        handle: &driver::DeviceHandle,
+        excluded_processes: &RwLock<HashMap<usize, ExcludedProcess>>,
+        addresses: &InterfaceAddresses,
+        paths: &[OsString],
+    ) -> Result<(), Error> {
+        excluded_processes.write().unwrap().clear();
+
+        handle
+            .register_ips(
+                addresses.tunnel_ipv4,
+                addresses.tunnel_ipv6,
+                addresses.internet_ipv4,
+                addresses.internet_ipv6,
+            )
+            .map_err(Error::RegisterIps)?;
+
+        let resolved_paths = path_resolver::resolve_paths(paths);
+        if resolved_paths.len() > 0 {
+            handle
+                .set_config(&resolved_paths)
+                .map_err(Error::SetConfiguration)?;
+        }
+        Ok(())
+    }
+
+    /// Spawns a request thread that rejects attempts to exclude applications, for when the driver
+    /// is not available on `architecture`.
+    fn spawn_unavailable_request_thread(architecture: Architecture) -> RequestTx {
+        let (tx, rx): (RequestTx, _) = sync_mpsc::channel();
+        std::thread::spawn(move || {
+            while let Ok((request, response_tx)) = rx.recv() {
+                let response = match request {
+                    Request::SetPaths(paths) if !paths.is_empty() => {
+                        Err(Error::DriverUnavailable(architecture))
+                    }
+                    Request::ReinstallDriver => Err(Error::DriverUnavailable(architecture)),
+                    Request::Stop => {
+                        let _ = response_tx.send(Ok(()));
+                        break;
+                    }
+                    _ => Ok(()),
+                };
+                let _ = response_tx.send(response);
+            }
+        });
+        tx
     }
 
     fn send_request(&self, request: Request) -> Result<(), Error> {
@@ -644,7 +874,8 @@ impl SplitTunnel {
         })
     }
 
-    /// Set a list of applications to exclude from the tunnel.
+    /// Set a list of applications to exclude from the tunnel. Each path may also be a directory
+    /// or a glob pattern, in which case all matching executables are excluded.
     pub fn set_paths_sync<T: AsRef<OsStr>>(&self, paths: &[T]) -> Result<(), Error> {
         self.send_request(Request::SetPaths(
             paths
@@ -654,7 +885,8 @@ impl SplitTunnel {
         ))
     }
 
-    /// Set a list of applications to exclude from the tunnel.
+    /// Set a list of applications to exclude from the tunnel. Each path may also be a directory
+    /// or a glob pattern, in which case all matching executables are excluded.
     pub fn set_paths<T: AsRef<OsStr>>(
         &self,
         paths: &[T],
@@ -689,6 +921,22 @@ impl SplitTunnel {
         });
     }
 
+    /// Reinstall the driver from the resource directory, e.g. if the loaded driver is incompatible
+    /// with this version of the app. The excluded applications and registered addresses are
+    /// restored afterwards. This fails if the driver is currently excluding traffic.
+    pub fn reinstall_driver(&self, result_tx: oneshot::Sender<Result<(), Error>>) {
+        let (response_tx, response_rx) = sync_mpsc::channel();
+        let request_tx = self.request_tx.clone();
+
+        self.runtime.spawn_blocking(move || {
+            let result = request_tx
+                .send((Request::ReinstallDriver, response_tx))
+                .map_err(|_| Error::SplitTunnelDown)
+                .and_then(|()| response_rx.recv().map_err(|_| Error::SplitTunnelDown)?);
+            let _ = result_tx.send(result);
+        });
+    }
+
     /// Instructs the driver to redirect traffic from sockets bound to 0.0.0.0, ::, or the
     /// tunnel addresses (if any) to the default route.
     pub fn set_tunnel_addresses(&mut self, metadata: Option<&TunnelMetadata>) -> Result<(), Error> {
@@ -704,6 +952,18 @@ impl SplitTunnel {
             }
         }
 
+        // The tunnel interface must never be mistaken for the internet interface
+        let tunnel_luid = metadata.and_then(|metadata| {
+            luid_from_alias(&metadata.interface)
+                .map_err(|error| {
+                    log::error!(
+                        "{}",
+                        error.display_chain_with_msg("Failed to obtain tunnel interface LUID")
+                    );
+                })
+                .ok()
+        });
+
         let tunnel_ipv4 = Some(tunnel_ipv4.unwrap_or(RESERVED_IP_V4));
         let context_mutex = Arc::new(Mutex::new(
             SplitTunnelDefaultRouteChangeHandlerContext::new(
@@ -711,6 +971,7 @@ impl SplitTunnel {
                 self.daemon_tx.clone(),
                 tunnel_ipv4,
                 tunnel_ipv6,
+                tunnel_luid.into_iter().collect(),
             ),
         ));
 
@@ -744,6 +1005,57 @@ impl SplitTunnel {
     }
 }
 
+/// Installs the driver if needed and connects to it. A driver that is incompatible with this
+/// module, e.g. one that was left running by an older version of the app, is reinstalled.
+fn open_driver(resource_dir: &Path) -> Result<driver::DeviceHandle, Error> {
+    service::install_driver_if_required(resource_dir).map_err(Error::ServiceError)?;
+    match driver::DeviceHandle::new() {
+        Err(driver::DeviceHandleError::IncompatibleDriver { expected, found }) => {
+            log::warn!(
+                "Reinstalling incompatible split tunnel driver. Expected version {}, found {}",
+                expected,
+                found
+            );
+            reinstall_and_open_driver(resource_dir)
+        }
+        result => result.map_err(Error::InitializationError),
+    }
+}
+
+/// Removes the driver service and installs it again. No handles to the driver may be open.
+fn reinstall_and_open_driver(resource_dir: &Path) -> Result<driver::DeviceHandle, Error> {
+    service::remove_driver_service().map_err(Error::RemoveService)?;
+    service::install_driver_if_required(resource_dir).map_err(Error::ServiceError)?;
+    driver::DeviceHandle::new().map_err(|error| match error {
+        driver::DeviceHandleError::IncompatibleDriver { expected, found } => {
+            Error::IncompatibleDriver { expected, found }
+        }
+        error => Error::InitializationError(error),
+    })
+}
+
+/// Waits for other threads to drop their references to `handle`, and closes it.
+fn release_handle(mut handle: Arc<driver::DeviceHandle>) -> Result<(), Error> {
+    let deadline = Instant::now() + RELEASE_HANDLE_TIMEOUT;
+    loop {
+        match Arc::try_unwrap(handle) {
+            Ok(_) => return Ok(()),
+            Err(shared_handle) => {
+                if Instant::now() >= deadline {
+                    return Err(Error::DriverInUse);
+                }
+                handle = shared_handle;
+                std::thread::sleep(Duration::from_millis(100));
+            }
+        }
+    }
+}
+
+/// Checks that the structures passed to the split tunnel driver have the expected layouts.
+pub(crate) fn check_layouts() -> Result<(), LayoutMismatch> {
+    driver::check_layouts()
+}
+
 impl Drop for SplitTunnel {
     fn drop(&mut self) {
         self.power_mgmt_handle.abort();
@@ -771,6 +1083,7 @@ struct SplitTunnelDefaultRouteChangeHandlerContext {
     request_tx: RequestTx,
     pub daemon_tx: Weak<mpsc::UnboundedSender<TunnelCommand>>,
     pub addresses: InterfaceAddresses,
+    excluded_luids: Vec<NET_LUID_LH>,
 }
 
 impl SplitTunnelDefaultRouteChangeHandlerContext {
@@ -779,6 +1092,7 @@ impl SplitTunnelDefaultRouteChangeHandlerContext {
         daemon_tx: Weak<mpsc::UnboundedSender<TunnelCommand>>,
         tunnel_ipv4: Option<Ipv4Addr>,
         tunnel_ipv6: Option<Ipv6Addr>,
+        excluded_luids: Vec<NET_LUID_LH>,
     ) -> Self {
         SplitTunnelDefaultRouteChangeHandlerContext {
             request_tx,
@@ -789,6 +1103,7 @@ impl SplitTunnelDefaultRouteChangeHandlerContext {
                 internet_ipv4: None,
                 internet_ipv6: None,
             },
+            excluded_luids,
         }
     }
 
@@ -801,7 +1116,7 @@ impl SplitTunnelDefaultRouteChangeHandlerContext {
 
     pub fn initialize_internet_addresses(&mut self) -> Result<(), Error> {
         // Identify IP address that gives us Internet access
-        let internet_ipv4 = get_best_default_route(WinNetAddrFamily::IPV4)
+        let internet_ipv4 = get_best_default_route(WinNetAddrFamily::IPV4, &self.excluded_luids)
             .map_err(Error::ObtainDefaultRoute)?
             .map(|route| {
                 get_ip_address_for_interface(
@@ -822,7 +1137,7 @@ impl SplitTunnelDefaultRouteChangeHandlerContext {
             .transpose()
             .map_err(Error::LuidToIp)?
             .flatten();
-        let internet_ipv6 = get_best_default_route(WinNetAddrFamily::IPV6)
+        let internet_ipv6 = get_best_default_route(WinNetAddrFamily::IPV6, &self.excluded_luids)
             .map_err(Error::ObtainDefaultRoute)?
             .map(|route| {
                 get_ip_address_for_interface(
@@ -854,7 +1169,7 @@ impl SplitTunnelDefaultRouteChangeHandlerContext {
 unsafe extern "system" fn split_tunnel_default_route_change_handler(
     event_type: winnet::WinNetDefaultRouteChangeEventType,
     address_family: WinNetAddrFamily,
-    default_route: winnet::WinNetDefaultRoute,
+    change: &winnet::WinNetDefaultRouteChange,
     ctx: *mut libc::c_void,
 ) {
     use winnet::WinNetDefaultRouteChangeEventType::*;
@@ -877,7 +1192,7 @@ unsafe extern "system" fn split_tunnel_default_route_change_handler(
             match get_ip_address_for_interface(
                 translated_family,
                 NET_LUID_LH {
-                    Value: default_route.interface_luid,
+                    Value: change.route().interface_luid,
                 },
             ) {
                 Ok(Some(ip)) => match IpAddr::from(ip) {
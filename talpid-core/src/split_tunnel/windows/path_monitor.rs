@@ -453,6 +453,18 @@ impl PathMonitorHandle {
         self.notify_monitor()
     }
 
+    /// Sets directories whose contents are watched. A change anywhere below any of them is
+    /// reported.
+    pub fn set_directories<P: AsRef<Path>>(&self, directories: &[P]) -> io::Result<()> {
+        let _ = self.tx.send(PathMonitorCommand::SetDirectories(
+            directories
+                .iter()
+                .map(|dir| dir.as_ref().to_path_buf())
+                .collect(),
+        ));
+        self.notify_monitor()
+    }
+
     pub fn refresh(&self) -> io::Result<()> {
         let _ = self.tx.send(PathMonitorCommand::Refresh);
         self.notify_monitor()
@@ -474,15 +486,28 @@ impl PathMonitorHandle {
 
 enum PathMonitorCommand {
     SetPaths(Vec<PathBuf>),
+    SetDirectories(Vec<PathBuf>),
     Refresh,
     Shutdown,
 }
 
+/// What a change notification affected.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Change {
+    /// Nothing that is monitored.
+    None,
+    /// A parent directory of a monitored path, which may be a link.
+    Parent,
+    /// The contents of a watched directory.
+    DirectoryContents,
+}
+
 pub struct PathMonitor {
     port_handle: Arc<CompletionPort>,
     dir_contexts: Vec<DirContext>,
     discarded_contexts: Vec<DirContext>,
     stripped_paths: HashSet<StrippedPath>,
+    stripped_directories: HashSet<StrippedPath>,
 }
 
 impl PathMonitor {
@@ -495,6 +520,7 @@ impl PathMonitor {
             dir_contexts: vec![],
             discarded_contexts: vec![],
             stripped_paths: HashSet::new(),
+            stripped_directories: HashSet::new(),
         };
 
         let (cmd_tx, cmd_rx) = sync_mpsc::channel();
@@ -505,14 +531,15 @@ impl PathMonitor {
                     break;
                 }
                 match monitor.handle_next_completion_packet() {
-                    Ok(true) => match monitor.update_paths(&original_paths, false) {
-                        Ok(true) => {
-                            let _ = update_notify_tx.send(());
+                    Ok(Change::None) => (),
+                    Ok(change) => match monitor.update_paths(&original_paths, false) {
+                        Ok(links_changed) => {
+                            if links_changed || change == Change::DirectoryContents {
+                                let _ = update_notify_tx.send(());
+                            }
                         }
-                        Ok(false) => (),
                         Err(_) => break,
                     },
-                    Ok(false) => (),
                     Err(error) => {
                         log::error!("handle_next_completion_packet failed: {}", error);
                         break;
@@ -544,6 +571,17 @@ impl PathMonitor {
                     *original_paths = new_paths;
                     return !self.update_paths(&original_paths, false).is_err();
                 }
+                PathMonitorCommand::SetDirectories(directories) => {
+                    self.stripped_directories = directories
+                        .iter()
+                        .filter_map(|dir| StrippedPath::new(dir).ok())
+                        .collect();
+                    if let Err(error) = self.update_directory_contexts() {
+                        log::error!("Failed to open new directory handles: {}", error);
+                        return false;
+                    }
+                    return true;
+                }
                 PathMonitorCommand::Refresh => {
                     return !self.update_paths(&original_paths, true).is_err();
                 }
@@ -576,6 +614,7 @@ impl PathMonitor {
             if !self
                 .stripped_paths
                 .iter()
+                .chain(self.stripped_directories.iter())
                 .any(|p| p.prefix == self.dir_contexts[i].path)
             {
                 let mut removed_ctx = self.dir_contexts.remove(i);
@@ -591,7 +630,11 @@ impl PathMonitor {
         }
 
         // Add new paths to monitor
-        for path in &self.stripped_paths {
+        for path in self
+            .stripped_paths
+            .iter()
+            .chain(self.stripped_directories.iter())
+        {
             if self
                 .dir_contexts
                 .iter()
@@ -625,10 +668,10 @@ impl PathMonitor {
         Ok(())
     }
 
-    fn handle_next_completion_packet(&mut self) -> io::Result<bool> {
+    fn handle_next_completion_packet(&mut self) -> io::Result<Change> {
         let result = match self.port_handle.get_queued_completion_status() {
             Ok(result) if result.completion_key == PATH_MONITOR_COMPLETION_KEY_IGNORE => {
-                return Ok(false);
+                return Ok(Change::None);
             }
             Err((error, status)) => {
                 self.free_discarded_context(status.used_overlapped);
@@ -636,13 +679,13 @@ impl PathMonitor {
                     log::error!("GetQueuedCompletionStatus failed: {:?}", error);
                     return Err(error);
                 }
-                return Ok(false);
+                return Ok(Change::None);
             }
             Ok(result) => result,
         };
 
         if self.free_discarded_context(result.used_overlapped) {
-            return Ok(false);
+            return Ok(Change::None);
         }
 
         let ctx_index = self
@@ -654,7 +697,7 @@ impl PathMonitor {
 
         let changed = if result.bytes_returned == 0 {
             log::trace!("Change event buffer is empty");
-            false
+            Change::None
         } else {
             self.process_file_notification(&self.dir_contexts[ctx_index])?
         };
@@ -697,7 +740,8 @@ impl PathMonitor {
         was_discarded
     }
 
-    fn process_file_notification(&self, dir_context: &DirContext) -> io::Result<bool> {
+    fn process_file_notification(&self, dir_context: &DirContext) -> io::Result<Change> {
+        let mut change = Change::None;
         let mut info = dir_context.buffer.as_ptr() as *const FILE_NOTIFY_INFORMATION;
         loop {
             let current_field = unsafe { &*info };
@@ -726,19 +770,29 @@ impl PathMonitor {
                     )
                 };
                 match cmp_status {
-                    CSTR_EQUAL => return Ok(true),
+                    CSTR_EQUAL => change = Change::Parent,
                     0 => log::error!("Bug: CompareStringOrdinal failed"),
                     _ => (),
                 }
             }
 
+            for dir in &self.stripped_directories {
+                if dir.prefix != dir_context.path() {
+                    continue;
+                }
+                let tail = dir.tail.strip_suffix(&[0]).unwrap_or(&dir.tail);
+                if is_below_directory(tail, file_name) {
+                    return Ok(Change::DirectoryContents);
+                }
+            }
+
             if current_field.NextEntryOffset == 0 {
                 break;
             }
             info = unsafe { (info as *mut u8).offset(current_field.NextEntryOffset as isize) }
                 as *const FILE_NOTIFY_INFORMATION;
         }
-        Ok(false)
+        Ok(change)
     }
 
     /// Cancel all requests and give the cancelled operations some time to complete.
@@ -787,6 +841,30 @@ impl PathMonitor {
     }
 }
 
+/// Returns whether the file `file_name` is in the directory `dir`, or is `dir` or one of its
+/// parents, so that a change to it may change the contents of `dir`. Both are relative to the
+/// same volume, and are compared without regard to case.
+fn is_below_directory(dir: &[u16], file_name: &[u16]) -> bool {
+    const SEPARATOR: u16 = b'\\' as u16;
+
+    let len = dir.len().min(file_name.len());
+    if len > 0 {
+        let cmp_status = unsafe {
+            CompareStringOrdinal(dir.as_ptr(), len as i32, file_name.as_ptr(), len as i32, 1)
+        };
+        if cmp_status != CSTR_EQUAL {
+            return false;
+        }
+    }
+    // The common prefix must end at a path component boundary
+    match (dir.get(len), file_name.get(len)) {
+        (None, None) => true,
+        (None, Some(c)) => len == 0 || *c == SEPARATOR,
+        (Some(c), None) => *c == SEPARATOR,
+        (Some(_), Some(_)) => false,
+    }
+}
+
 fn get_full_path_name<T: AsRef<OsStr>>(path: T) -> io::Result<PathBuf> {
     let path_buf_os: Vec<u16> = osstr_to_wide(path);
     let mut full_path_buffer = vec![0u16; 2048 / mem::size_of::<u16>()];
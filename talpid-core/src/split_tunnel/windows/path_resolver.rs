@@ -0,0 +1,320 @@
+//! Expansion of excluded directories and glob patterns into executable paths.
+//!
+//! The driver only matches processes against exact image paths. An excluded entry may instead be
+//! a directory, in which case every executable below it is excluded, or a glob pattern such as
+//! `C:\Games\**\*.exe`. `*` and `?` match within a single path component, and `**` matches any
+//! number of directories. Entries are resolved whenever the configuration is applied, so they
+//! must be resolved again when the file system changes, e.g. when a volume is mounted.
+
+use std::{
+    ffi::{OsStr, OsString},
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+use talpid_types::ErrorExt;
+
+/// Extension of files that are considered executable when excluding a directory.
+const EXECUTABLE_EXTENSION: &str = "exe";
+/// Maximum depth to descend into when resolving an entry.
+const MAX_DEPTH: usize = 32;
+
+/// Resolves all `entries` to a list of executable paths. Entries that are neither a directory
+/// nor a pattern are returned as they are, whether they exist or not.
+pub fn resolve_paths(entries: &[OsString]) -> Vec<OsString> {
+    let mut resolved = vec![];
+    for entry in entries {
+        let path = Path::new(entry);
+        if is_pattern(entry) {
+            resolve_pattern(path, &mut resolved);
+        } else if path.is_dir() {
+            find_executables(path, 0, &mut resolved);
+        } else {
+            resolved.push(entry.clone());
+        }
+    }
+    resolved.sort_by_key(|path| path.to_ascii_lowercase());
+    resolved.dedup_by(|a, b| a.eq_ignore_ascii_case(b));
+    resolved
+}
+
+/// Returns the directories whose contents determine what `entries` resolve to. The result of
+/// [`resolve_paths`] may change whenever anything below one of them changes.
+pub fn watched_directories(entries: &[OsString]) -> Vec<PathBuf> {
+    let mut directories = vec![];
+    for entry in entries {
+        let path = Path::new(entry);
+        if is_pattern(entry) {
+            directories.push(split_pattern(path).0);
+        } else if path.is_dir() {
+            directories.push(path.to_path_buf());
+        }
+    }
+    directories
+}
+
+/// Returns whether the entry contains any wildcards.
+fn is_pattern(entry: &OsStr) -> bool {
+    entry.to_string_lossy().contains(|c| c == '*' || c == '?')
+}
+
+/// Splits a pattern into the longest prefix that does not contain any wildcards, and the
+/// remaining lowercase components.
+fn split_pattern(pattern: &Path) -> (PathBuf, Vec<String>) {
+    let mut base = PathBuf::new();
+    let mut components = pattern.components().peekable();
+    while let Some(component) = components.peek() {
+        if let Component::Normal(name) = component {
+            if is_pattern(name) {
+                break;
+            }
+        }
+        base.push(component);
+        components.next();
+    }
+    let remaining = components
+        .map(|component| component.as_os_str().to_string_lossy().to_lowercase())
+        .collect();
+    (base, remaining)
+}
+
+fn resolve_pattern(pattern: &Path, resolved: &mut Vec<OsString>) {
+    let (base, remaining) = split_pattern(pattern);
+    match_components(&base, &remaining, 0, resolved);
+}
+
+fn match_components(dir: &Path, pattern: &[String], depth: usize, resolved: &mut Vec<OsString>) {
+    let (first, rest) = match pattern.split_first() {
+        Some(split) => split,
+        None => {
+            if dir.is_file() {
+                resolved.push(dir.as_os_str().to_os_string());
+            }
+            return;
+        }
+    };
+    if depth > MAX_DEPTH {
+        return;
+    }
+
+    if first == "**" {
+        // Match zero directories
+        match_components(dir, rest, depth, resolved);
+    }
+
+    for (name, path, is_dir) in read_dir(dir) {
+        if first == "**" {
+            if is_dir {
+                match_components(&path, pattern, depth + 1, resolved);
+            }
+        } else if matches_wildcard(first, &name.to_string_lossy().to_lowercase()) {
+            if rest.is_empty() {
+                if !is_dir {
+                    resolved.push(path.into_os_string());
+                }
+            } else if is_dir {
+                match_components(&path, rest, depth + 1, resolved);
+            }
+        }
+    }
+}
+
+fn find_executables(dir: &Path, depth: usize, resolved: &mut Vec<OsString>) {
+    if depth > MAX_DEPTH {
+        return;
+    }
+    for (_name, path, is_dir) in read_dir(dir) {
+        if is_dir {
+            find_executables(&path, depth + 1, resolved);
+        } else if path
+            .extension()
+            .map(|extension| extension.eq_ignore_ascii_case(EXECUTABLE_EXTENSION))
+            .unwrap_or(false)
+        {
+            resolved.push(path.into_os_string());
+        }
+    }
+}
+
+/// Returns the name, path, and whether it is a directory for each entry in `dir`. Symbolic links
+/// and junctions are not followed, to avoid cycles. Errors are logged and ignored.
+fn read_dir(dir: &Path) -> Vec<(OsString, PathBuf, bool)> {
+    let read_entries = || -> io::Result<Vec<_>> {
+        let mut entries = vec![];
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_symlink() {
+                continue;
+            }
+            entries.push((entry.file_name(), entry.path(), file_type.is_dir()));
+        }
+        Ok(entries)
+    };
+    match read_entries() {
+        Ok(entries) => entries,
+        Err(error) => {
+            if error.kind() != io::ErrorKind::NotFound {
+                log::warn!(
+                    "{}",
+                    error.display_chain_with_msg(&format!(
+                        "Failed to read excluded directory {}",
+                        dir.display()
+                    ))
+                );
+            }
+            vec![]
+        }
+    }
+}
+
+/// Matches `name` against a single path component containing `*` and `?` wildcards.
+fn matches_wildcard(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    let (mut p, mut n) = (0, 0);
+    // Position after the last `*`, and the name position it was matched against
+    let mut backtrack = None;
+
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            p += 1;
+            backtrack = Some((p, n));
+        } else if let Some((star_p, star_n)) = backtrack {
+            p = star_p;
+            n = star_n + 1;
+            backtrack = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn create_files(root: &Path, files: &[&str]) {
+        for file in files {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"").unwrap();
+        }
+    }
+
+    fn sorted(mut paths: Vec<OsString>) -> Vec<OsString> {
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn test_matches_wildcard() {
+        assert!(matches_wildcard("*.exe", "game.exe"));
+        assert!(matches_wildcard("*.exe", ".exe"));
+        assert!(!matches_wildcard("*.exe", "game.exe.bak"));
+        assert!(matches_wildcard("g?me*", "game"));
+        assert!(matches_wildcard("g?me*", "gamelauncher.exe"));
+        assert!(!matches_wildcard("g?me", "gme"));
+        assert!(matches_wildcard("*a*b", "xaxxab"));
+        assert!(!matches_wildcard("*a*b", "xaxxba"));
+        assert!(matches_wildcard("*", ""));
+        assert!(!matches_wildcard("", "a"));
+    }
+
+    #[test]
+    fn test_resolve_pattern() {
+        let root = tempfile::tempdir().unwrap();
+        create_files(
+            root.path(),
+            &[
+                "game.exe",
+                "readme.txt",
+                "bin/launcher.exe",
+                "bin/x64/engine.exe",
+                "bin/x64/engine.dll",
+            ],
+        );
+
+        let mut resolved = vec![];
+        resolve_pattern(&root.path().join("*.exe"), &mut resolved);
+        assert_eq!(
+            resolved,
+            vec![root.path().join("game.exe").into_os_string()]
+        );
+
+        let mut resolved = vec![];
+        resolve_pattern(&root.path().join("**").join("*.exe"), &mut resolved);
+        assert_eq!(
+            sorted(resolved),
+            sorted(vec![
+                root.path().join("game.exe").into_os_string(),
+                root.path()
+                    .join("bin")
+                    .join("launcher.exe")
+                    .into_os_string(),
+                root.path()
+                    .join("bin")
+                    .join("x64")
+                    .join("engine.exe")
+                    .into_os_string(),
+            ])
+        );
+
+        let mut resolved = vec![];
+        resolve_pattern(
+            &root.path().join("b?n").join("*").join("*.dll"),
+            &mut resolved,
+        );
+        assert_eq!(
+            resolved,
+            vec![root
+                .path()
+                .join("bin")
+                .join("x64")
+                .join("engine.dll")
+                .into_os_string()]
+        );
+    }
+
+    #[test]
+    fn test_find_executables() {
+        let root = tempfile::tempdir().unwrap();
+        create_files(
+            root.path(),
+            &["game.EXE", "game.dll", "bin/launcher.exe", "bin/exe"],
+        );
+
+        let mut resolved = vec![];
+        find_executables(root.path(), 0, &mut resolved);
+        assert_eq!(
+            sorted(resolved),
+            sorted(vec![
+                root.path().join("game.EXE").into_os_string(),
+                root.path()
+                    .join("bin")
+                    .join("launcher.exe")
+                    .into_os_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_watched_directories() {
+        let root = tempfile::tempdir().unwrap();
+        create_files(root.path(), &["bin/game.exe"]);
+
+        let entries = vec![
+            root.path().join("bin").into_os_string(),
+            root.path().join("bin").join("game.exe").into_os_string(),
+            root.path().join("**").join("*.exe").into_os_string(),
+        ];
+        assert_eq!(
+            watched_directories(&entries),
+            vec![root.path().join("bin"), root.path().to_path_buf()]
+        );
+    }
+}
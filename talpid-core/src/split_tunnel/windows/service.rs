@@ -95,24 +95,37 @@ pub fn install_driver_if_required(resource_dir: &Path) -> Result<(), Error> {
 }
 
 pub fn stop_driver_service() -> Result<(), Error> {
+    match open_existing_service()? {
+        Some(service) => stop_service(&service),
+        None => Ok(()),
+    }
+}
+
+/// Stops and deletes the driver service, so that the driver is installed again by
+/// [`install_driver_if_required`]. No handles to the driver may be open.
+pub fn remove_driver_service() -> Result<(), Error> {
+    match open_existing_service()? {
+        Some(service) => {
+            log::debug!("Removing ST driver service");
+            remove_device(service)
+        }
+        None => Ok(()),
+    }
+}
+
+fn open_existing_service() -> Result<Option<Service>, Error> {
     let scm = ServiceManager::local_computer(None::<OsString>, ServiceManagerAccess::CONNECT)
         .map_err(Error::OpenServiceControlManager)?;
 
-    let service = match scm.open_service(SPLIT_TUNNEL_SERVICE, ServiceAccess::all()) {
-        Ok(service) => service,
-        Err(error) => {
-            return match error {
-                windows_service::Error::Winapi(io_error)
-                    if io_error.raw_os_error() == Some(ERROR_SERVICE_DOES_NOT_EXIST as i32) =>
-                {
-                    Ok(())
-                }
-                error => Err(Error::OpenServiceHandle(error)),
-            };
+    match scm.open_service(SPLIT_TUNNEL_SERVICE, ServiceAccess::all()) {
+        Ok(service) => Ok(Some(service)),
+        Err(windows_service::Error::Winapi(io_error))
+            if io_error.raw_os_error() == Some(ERROR_SERVICE_DOES_NOT_EXIST as i32) =>
+        {
+            Ok(None)
         }
-    };
-
-    stop_service(&service)
+        Err(error) => Err(Error::OpenServiceHandle(error)),
+    }
 }
 
 fn stop_service(service: &Service) -> Result<(), Error> {
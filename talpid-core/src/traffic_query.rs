@@ -0,0 +1,333 @@
+use crate::firewall::{ALLOWED_LAN_MULTICAST_NETS, ALLOWED_LAN_NETS};
+use std::net::IpAddr;
+use talpid_types::{
+    net::{Endpoint, TransportProtocol},
+    system_state::{AllowedTunnelTrafficState, FirewallState, RouteState, SystemState},
+    traffic_query::{TrafficOutcome, TrafficQuery, TrafficReason, TrafficVerdict},
+};
+
+const DNS_PORT: u16 = 53;
+const SMB_PORTS: [u16; 2] = [139, 445];
+
+/// Evaluates what would happen to the traffic described by `query`, given the firewall policy
+/// and routes in `state`. `excluded_apps` are the applications that are excluded from the tunnel,
+/// in the same form as [`TrafficQuery::app`].
+///
+/// This mirrors the rules that the firewall implements. It does not inspect the actual firewall
+/// or routing table of the system.
+pub fn resolve(
+    state: &SystemState,
+    query: &TrafficQuery,
+    excluded_apps: &[String],
+) -> TrafficVerdict {
+    let route = best_route(&state.routes, query.destination).cloned();
+    let (outcome, reason) = resolve_inner(state, query, excluded_apps, route.as_ref());
+    TrafficVerdict {
+        outcome,
+        reason,
+        route,
+    }
+}
+
+fn resolve_inner(
+    state: &SystemState,
+    query: &TrafficQuery,
+    excluded_apps: &[String],
+    route: Option<&RouteState>,
+) -> (TrafficOutcome, TrafficReason) {
+    use TrafficOutcome::*;
+
+    let endpoint = Endpoint::new(query.destination, query.port, query.protocol);
+    let is_excluded_app = query
+        .app
+        .as_ref()
+        .map(|app| excluded_apps.iter().any(|excluded| same_app(excluded, app)))
+        .unwrap_or(false);
+
+    let firewall = match &state.firewall {
+        Some(firewall) => firewall,
+        None => return (Bypassed, TrafficReason::NoFirewallPolicy),
+    };
+
+    match firewall {
+        FirewallState::Connecting {
+            peer_endpoint,
+            tunnel_interface,
+            allow_lan,
+            allowed_endpoints,
+            allowed_tunnel_traffic,
+            vpn_coexistence,
+        } => {
+            if endpoint == *peer_endpoint {
+                return (Bypassed, TrafficReason::RelayEndpoint);
+            }
+            if allowed_endpoints
+                .iter()
+                .any(|range| range.contains(&endpoint))
+            {
+                return (Bypassed, TrafficReason::AllowedEndpoint);
+            }
+            if is_excluded_app {
+                return (Bypassed, TrafficReason::ExcludedApp);
+            }
+            if *allow_lan && is_local(query.destination) {
+                return (Bypassed, TrafficReason::LocalNetwork);
+            }
+            if contains(&vpn_coexistence.networks, query.destination) {
+                return (Bypassed, TrafficReason::VpnCoexistence);
+            }
+            if tunnel_interface.is_some() && allows_tunnel_traffic(allowed_tunnel_traffic, query) {
+                return (Tunneled, TrafficReason::AllowedTunnelTraffic);
+            }
+            (Blocked, TrafficReason::NotAllowed)
+        }
+        FirewallState::Connected {
+            peer_endpoint,
+            tunnel_interface,
+            allow_lan,
+            dns_servers,
+            vpn_coexistence,
+            blocked_tunnel_protocols,
+        } => {
+            if endpoint == *peer_endpoint {
+                return (Bypassed, TrafficReason::RelayEndpoint);
+            }
+            if is_excluded_app {
+                return (Bypassed, TrafficReason::ExcludedApp);
+            }
+            // Specific routes into the tunnel, such as the one to the tunnel gateway, take
+            // precedence over local networks
+            let specific_tunnel_route = route
+                .map(|route| {
+                    route.destination.prefix() > 1
+                        && route.interface.as_ref() == Some(tunnel_interface)
+                })
+                .unwrap_or(false);
+            if *allow_lan && !specific_tunnel_route && is_local(query.destination) {
+                return (Bypassed, TrafficReason::LocalNetwork);
+            }
+            if contains(&vpn_coexistence.networks, query.destination) {
+                return (Bypassed, TrafficReason::VpnCoexistence);
+            }
+            if query.port == DNS_PORT
+                && !dns_servers.is_empty()
+                && !dns_servers.contains(&query.destination)
+            {
+                return (Blocked, TrafficReason::DnsServerNotAllowed);
+            }
+            let blocked_protocol = (blocked_tunnel_protocols.ipv6 && query.destination.is_ipv6())
+                || (blocked_tunnel_protocols.udp
+                    && query.protocol == TransportProtocol::Udp
+                    && query.port != DNS_PORT)
+                || (blocked_tunnel_protocols.smb
+                    && query.protocol == TransportProtocol::Tcp
+                    && SMB_PORTS.contains(&query.port));
+            if blocked_protocol {
+                return (Blocked, TrafficReason::BlockedTunnelProtocol);
+            }
+            match route.and_then(|route| route.interface.as_ref()) {
+                Some(interface) if interface != tunnel_interface => {
+                    (Blocked, TrafficReason::NoTunnelRoute)
+                }
+                Some(_) => (Tunneled, TrafficReason::TunnelRoute),
+                None => (Blocked, TrafficReason::NoTunnelRoute),
+            }
+        }
+        FirewallState::Blocked {
+            allow_lan,
+            allowed_endpoints,
+            recovery_allowlist,
+            vpn_coexistence,
+        } => {
+            if allowed_endpoints
+                .iter()
+                .any(|range| range.contains(&endpoint))
+            {
+                return (Bypassed, TrafficReason::AllowedEndpoint);
+            }
+            if *allow_lan && is_local(query.destination) {
+                return (Bypassed, TrafficReason::LocalNetwork);
+            }
+            if contains(recovery_allowlist, query.destination) {
+                return (Bypassed, TrafficReason::RecoveryAllowlist);
+            }
+            if contains(&vpn_coexistence.networks, query.destination) {
+                return (Bypassed, TrafficReason::VpnCoexistence);
+            }
+            (Blocked, TrafficReason::NotAllowed)
+        }
+    }
+}
+
+fn allows_tunnel_traffic(allowed: &AllowedTunnelTrafficState, query: &TrafficQuery) -> bool {
+    match allowed {
+        AllowedTunnelTrafficState::None => false,
+        AllowedTunnelTrafficState::All => true,
+        AllowedTunnelTrafficState::Only(endpoint) => {
+            *endpoint == Endpoint::new(query.destination, query.port, query.protocol)
+        }
+        AllowedTunnelTrafficState::Dns(servers) => {
+            query.port == DNS_PORT && servers.contains(&query.destination)
+        }
+        AllowedTunnelTrafficState::Endpoints {
+            addresses,
+            port,
+            protocol,
+        } => {
+            query.port == *port
+                && query.protocol == *protocol
+                && addresses.contains(&query.destination)
+        }
+    }
+}
+
+/// Returns the most specific route that matches `destination`.
+fn best_route(routes: &[RouteState], destination: IpAddr) -> Option<&RouteState> {
+    routes
+        .iter()
+        .filter(|route| route.destination.contains(destination))
+        .max_by_key(|route| route.destination.prefix())
+}
+
+fn is_local(destination: IpAddr) -> bool {
+    contains(&*ALLOWED_LAN_NETS, destination) || contains(&*ALLOWED_LAN_MULTICAST_NETS, destination)
+}
+
+fn contains(networks: &[ipnetwork::IpNetwork], destination: IpAddr) -> bool {
+    networks.iter().any(|network| network.contains(destination))
+}
+
+#[cfg(windows)]
+fn same_app(excluded: &str, app: &str) -> bool {
+    // Paths are case-insensitive on Windows
+    excluded.to_lowercase() == app.to_lowercase()
+}
+
+#[cfg(not(windows))]
+fn same_app(excluded: &str, app: &str) -> bool {
+    excluded == app
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use talpid_types::net::{BlockedTunnelProtocols, VpnCoexistence};
+
+    fn query(destination: &str, port: u16, protocol: TransportProtocol) -> TrafficQuery {
+        TrafficQuery {
+            destination: destination.parse().unwrap(),
+            port,
+            protocol,
+            app: None,
+        }
+    }
+
+    fn connected_state() -> SystemState {
+        let tunnel_route = RouteState {
+            destination: "0.0.0.0/0".parse().unwrap(),
+            gateway: None,
+            interface: Some("wg0-mullvad".to_owned()),
+            follows_default_route: false,
+        };
+        let gateway_route = RouteState {
+            destination: "10.64.0.1/32".parse().unwrap(),
+            ..tunnel_route.clone()
+        };
+        SystemState::new(
+            Some(FirewallState::Connected {
+                peer_endpoint: Endpoint::new(
+                    "1.2.3.4".parse::<IpAddr>().unwrap(),
+                    51820,
+                    TransportProtocol::Udp,
+                ),
+                tunnel_interface: "wg0-mullvad".to_owned(),
+                allow_lan: true,
+                dns_servers: vec!["10.64.0.1".parse().unwrap()],
+                vpn_coexistence: VpnCoexistence::default(),
+                blocked_tunnel_protocols: BlockedTunnelProtocols {
+                    smb: true,
+                    ..Default::default()
+                },
+            }),
+            vec![tunnel_route, gateway_route],
+            None,
+        )
+    }
+
+    #[test]
+    fn test_connected() {
+        let state = connected_state();
+        let resolve = |query| resolve(&state, &query, &["1234".to_owned()]);
+
+        let verdict = resolve(query("8.8.8.8", 443, TransportProtocol::Tcp));
+        assert_eq!(verdict.outcome, TrafficOutcome::Tunneled);
+        assert!(verdict.route.is_some());
+
+        let verdict = resolve(query("1.2.3.4", 51820, TransportProtocol::Udp));
+        assert_eq!(verdict.reason, TrafficReason::RelayEndpoint);
+
+        let verdict = resolve(query("192.168.1.1", 80, TransportProtocol::Tcp));
+        assert_eq!(verdict.reason, TrafficReason::LocalNetwork);
+
+        let verdict = resolve(query("10.64.0.1", 53, TransportProtocol::Udp));
+        assert_eq!(verdict.outcome, TrafficOutcome::Tunneled);
+
+        let verdict = resolve(query("8.8.8.8", 53, TransportProtocol::Udp));
+        assert_eq!(verdict.reason, TrafficReason::DnsServerNotAllowed);
+
+        let verdict = resolve(query("8.8.8.8", 445, TransportProtocol::Tcp));
+        assert_eq!(verdict.reason, TrafficReason::BlockedTunnelProtocol);
+
+        // No route through the tunnel covers IPv6 destinations
+        let verdict = resolve(query("2001:db8::1", 443, TransportProtocol::Tcp));
+        assert_eq!(verdict.outcome, TrafficOutcome::Blocked);
+        assert_eq!(verdict.reason, TrafficReason::NoTunnelRoute);
+    }
+
+    #[test]
+    fn test_excluded_app() {
+        let state = connected_state();
+        let mut query = query("8.8.8.8", 443, TransportProtocol::Tcp);
+        query.app = Some("1234".to_owned());
+
+        let verdict = resolve(&state, &query, &["1234".to_owned()]);
+        assert_eq!(verdict.outcome, TrafficOutcome::Bypassed);
+        assert_eq!(verdict.reason, TrafficReason::ExcludedApp);
+    }
+
+    #[test]
+    fn test_blocked() {
+        let state = SystemState::new(
+            Some(FirewallState::Blocked {
+                allow_lan: false,
+                allowed_endpoints: vec![],
+                recovery_allowlist: vec!["203.0.113.0/24".parse().unwrap()],
+                vpn_coexistence: VpnCoexistence::default(),
+            }),
+            vec![],
+            None,
+        );
+
+        let verdict = resolve(
+            &state,
+            &query("203.0.113.5", 22, TransportProtocol::Tcp),
+            &[],
+        );
+        assert_eq!(verdict.reason, TrafficReason::RecoveryAllowlist);
+
+        let verdict = resolve(
+            &state,
+            &query("192.168.1.1", 80, TransportProtocol::Tcp),
+            &[],
+        );
+        assert_eq!(verdict.outcome, TrafficOutcome::Blocked);
+    }
+
+    #[test]
+    fn test_no_firewall_policy() {
+        let state = SystemState::new(None, vec![], None);
+        let verdict = resolve(&state, &query("8.8.8.8", 443, TransportProtocol::Tcp), &[]);
+        assert_eq!(verdict.outcome, TrafficOutcome::Bypassed);
+        assert_eq!(verdict.reason, TrafficReason::NoFirewallPolicy);
+    }
+}
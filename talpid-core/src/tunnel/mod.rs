@@ -2,7 +2,6 @@ use self::tun_provider::TunProvider;
 use crate::{logging, routing::RouteManagerHandle};
 use futures::{channel::oneshot, future::BoxFuture};
 use std::{
-    net::{IpAddr, Ipv4Addr, Ipv6Addr},
     path::{Path, PathBuf},
     sync::{Arc, Mutex},
 };
@@ -12,6 +11,7 @@ use talpid_types::net::{wireguard as wireguard_types, AllowedTunnelTraffic, Tunn
 
 #[cfg(target_os = "android")]
 pub use self::tun_provider::TunConfig;
+pub use talpid_types::tunnel::TunnelMetadata;
 
 #[cfg(target_os = "windows")]
 mod windows;
@@ -83,19 +83,6 @@ pub enum TunnelEvent {
     Down,
 }
 
-/// Information about a VPN tunnel.
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
-pub struct TunnelMetadata {
-    /// The name of the device which the tunnel is running on.
-    pub interface: String,
-    /// The local IPs on the tunnel interface.
-    pub ips: Vec<IpAddr>,
-    /// The IP to the default gateway on the tunnel interface.
-    pub ipv4_gateway: Ipv4Addr,
-    /// The IP to the IPv6 default gateway on the tunnel interface.
-    pub ipv6_gateway: Option<Ipv6Addr>,
-}
-
 /// Abstraction for monitoring a generic VPN tunnel.
 pub struct TunnelMonitor {
     monitor: InternalTunnelMonitor,
@@ -121,6 +108,14 @@ where
     pub retry_attempt: u32,
     /// Route manager handle.
     pub route_manager: RouteManagerHandle,
+    /// Creates WireGuard tunnels instead of the built-in backends, if set.
+    pub wireguard_tunnel_provider: Option<Arc<dyn wireguard::TunnelProvider>>,
+    /// Set to refer to the WireGuard tunnel once it has been created.
+    pub traffic_shaping: wireguard::TrafficShapingHandle,
+    /// Physical interface that traffic to the relay must leave through, if set. This overrides
+    /// the interface of the best default route.
+    #[cfg(not(target_os = "android"))]
+    pub forced_interface: Option<String>,
 }
 
 // TODO(emilsp) move most of the openvpn tunnel details to OpenVpnTunnelMonitor
@@ -145,15 +140,24 @@ impl TunnelMonitor {
 
         match tunnel_parameters {
             #[cfg(not(target_os = "android"))]
-            TunnelParameters::OpenVpn(config) => args.runtime.block_on(Self::start_openvpn_tunnel(
-                config,
-                log_file,
-                args.resource_dir,
-                args.on_event,
-                args.tunnel_close_rx,
-                #[cfg(target_os = "linux")]
-                args.route_manager,
-            )),
+            TunnelParameters::OpenVpn(config) => {
+                if let Some(interface) = &args.forced_interface {
+                    log::warn!(
+                        "Not forcing OpenVPN traffic through {}, since it is only supported by \
+                         WireGuard",
+                        interface
+                    );
+                }
+                args.runtime.block_on(Self::start_openvpn_tunnel(
+                    config,
+                    log_file,
+                    args.resource_dir,
+                    args.on_event,
+                    args.tunnel_close_rx,
+                    #[cfg(target_os = "linux")]
+                    args.route_manager,
+                ))
+            }
             #[cfg(target_os = "android")]
             TunnelParameters::OpenVpn(_) => Err(Error::UnsupportedPlatform),
 
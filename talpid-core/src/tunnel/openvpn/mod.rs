@@ -5,7 +5,7 @@ use crate::{
     mktemp,
     process::{
         openvpn::{OpenVpnCommand, OpenVpnProcHandle},
-        stoppable_process::StoppableProcess,
+        supervisor::classify_exit,
     },
     proxy::{self, ProxyMonitor, ProxyResourceData},
 };
@@ -28,7 +28,7 @@ use std::{
     thread,
     time::Duration,
 };
-use talpid_types::{net::openvpn, ErrorExt};
+use talpid_types::{net::openvpn, tunnel::ProcessFailure, ErrorExt};
 use tokio::task;
 #[cfg(target_os = "linux")]
 use which;
@@ -54,6 +54,12 @@ const ADAPTER_GUID: GUID = GUID {
     data4: [0x85, 0x36, 0x57, 0x6A, 0xB8, 0x6A, 0xFE, 0x9A],
 };
 
+/// Removes the Wintun adapter that is kept between OpenVPN tunnels.
+#[cfg(windows)]
+pub fn release_wintun_adapter() {
+    wintun::WintunAdapter::release_pooled();
+}
+
 /// Results from fallible operations on the OpenVPN tunnel.
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -87,11 +93,16 @@ pub enum Error {
     #[error(display = "Failed to create Wintun adapter")]
     WintunCreateAdapterError(#[error(source)] io::Error),
 
+    /// Failed to wait for the IP interfaces of the Wintun adapter
+    #[cfg(windows)]
+    #[error(display = "Failed to wait for the IP interfaces of the Wintun adapter")]
+    WaitForInterfacesError(#[error(source)] io::Error),
+
     /// OpenVPN process died unexpectedly
-    #[error(display = "OpenVPN process died unexpectedly")]
-    ChildProcessDied,
+    #[error(display = "OpenVPN process died unexpectedly: {}", _0)]
+    ChildProcessDied(ProcessFailure),
 
-    /// Failed before OpenVPN started
+    /// Failed to spawn the OpenVPN process
     #[error(display = "Failed to start OpenVPN")]
     StartProcessError,
 
@@ -143,6 +154,17 @@ pub enum Error {
     ParseRemoteHost(#[error(source)] std::net::AddrParseError),
 }
 
+impl Error {
+    /// Returns how the OpenVPN process failed, if this error was caused by the process failing.
+    pub fn process_failure(&self) -> Option<ProcessFailure> {
+        match self {
+            Error::StartProcessError => Some(ProcessFailure::Spawn),
+            Error::ChildProcessDied(failure) => Some(*failure),
+            _ => None,
+        }
+    }
+}
+
 #[cfg(unix)]
 static OPENVPN_DIE_TIMEOUT: Duration = Duration::from_secs(4);
 #[cfg(windows)]
@@ -165,7 +187,7 @@ const OPENVPN_BIN_FILENAME: &str = "openvpn.exe";
 pub struct OpenVpnMonitor<C: OpenVpnBuilder = OpenVpnCommand> {
     spawn_task: Option<
         tokio::task::JoinHandle<
-            std::result::Result<io::Result<C::ProcessHandle>, futures::future::Aborted>,
+            std::result::Result<Result<C::ProcessHandle>, futures::future::Aborted>,
         >,
     >,
     abort_spawn: futures::future::AbortHandle,
@@ -177,6 +199,9 @@ pub struct OpenVpnMonitor<C: OpenVpnBuilder = OpenVpnCommand> {
     _user_pass_file: mktemp::TempFile,
     /// Keep the 'TempFile' for the proxy user-pass file in the struct, so it's removed on drop.
     _proxy_auth_file: Option<mktemp::TempFile>,
+    /// Keep the `TempFile`s for the client certificate and key in the struct, so they're removed
+    /// on drop.
+    _client_certificate_files: Option<(mktemp::TempFile, mktemp::TempFile)>,
 
     runtime: tokio::runtime::Handle,
     event_server_abort_tx: triggered::Trigger,
@@ -210,7 +235,7 @@ impl std::fmt::Debug for dyn WintunContext {
 #[cfg(windows)]
 #[derive(Debug)]
 struct WintunContextImpl {
-    adapter: wintun::WintunAdapter,
+    adapter: Arc<wintun::WintunAdapter>,
     wait_v6_interface: bool,
     _logger: wintun::WintunLoggerHandle,
 }
@@ -265,6 +290,13 @@ impl OpenVpnMonitor<OpenVpnCommand> {
                 .map_err(Error::CredentialsWriteError)?;
         let proxy_auth_file =
             Self::create_proxy_auth_file(&params.proxy).map_err(Error::CredentialsWriteError)?;
+        let client_certificate_files = params
+            .config
+            .client_certificate
+            .as_ref()
+            .map(Self::create_client_certificate_files)
+            .transpose()
+            .map_err(Error::CredentialsWriteError)?;
         let user_pass_file_path = user_pass_file.to_path_buf();
         let proxy_auth_file_path = proxy_auth_file.as_ref().map(|file| file.to_path_buf());
 
@@ -289,6 +321,9 @@ impl OpenVpnMonitor<OpenVpnCommand> {
             params,
             user_pass_file.as_ref(),
             proxy_auth_file.as_ref().map(AsRef::as_ref),
+            client_certificate_files
+                .as_ref()
+                .map(|(cert_file, key_file)| (cert_file.as_ref(), key_file.as_ref())),
             resource_dir,
             &proxy_monitor,
             #[cfg(windows)]
@@ -309,6 +344,7 @@ impl OpenVpnMonitor<OpenVpnCommand> {
             log_path,
             user_pass_file,
             proxy_auth_file,
+            client_certificate_files,
             proxy_monitor,
             tunnel_close_rx,
         };
@@ -339,7 +375,7 @@ impl OpenVpnMonitor<OpenVpnCommand> {
         let dll = wintun::WintunDll::instance(resource_dir).map_err(Error::WintunDllError)?;
         let wintun_logger = dll.activate_logging();
 
-        let wintun_adapter = wintun::WintunAdapter::create(
+        let wintun_adapter = wintun::WintunAdapter::get_or_create(
             dll.clone(),
             &*ADAPTER_ALIAS,
             &*ADAPTER_TUNNEL_TYPE,
@@ -361,7 +397,7 @@ fn extract_routes(env: &HashMap<String, String>) -> Result<HashSet<RequiredRoute
     let tun_node = routing::Node::device(tun_interface.to_string());
     let mut routes = HashSet::new();
     for network in &["0.0.0.0/0".parse().unwrap(), "::/0".parse().unwrap()] {
-        routes.insert(RequiredRoute::new(*network, tun_node.clone()));
+        routes.insert(RequiredRoute::tunnel_default(*network, tun_node.clone()));
     }
     Ok(routes)
 }
@@ -373,6 +409,7 @@ struct OpenVpnTunnelInitArgs {
     log_path: Option<PathBuf>,
     user_pass_file: mktemp::TempFile,
     proxy_auth_file: Option<mktemp::TempFile>,
+    client_certificate_files: Option<(mktemp::TempFile, mktemp::TempFile)>,
     proxy_monitor: Option<Box<dyn ProxyMonitor>>,
     tunnel_close_rx: oneshot::Receiver<()>,
 }
@@ -393,6 +430,7 @@ impl<C: OpenVpnBuilder + Send + 'static> OpenVpnMonitor<C> {
         let log_path = init_args.log_path;
         let user_pass_file = init_args.user_pass_file;
         let proxy_auth_file = init_args.proxy_auth_file;
+        let client_certificate_files = init_args.client_certificate_files;
         let proxy_monitor = init_args.proxy_monitor;
         let tunnel_close_rx = init_args.tunnel_close_rx;
 
@@ -420,6 +458,7 @@ impl<C: OpenVpnBuilder + Send + 'static> OpenVpnMonitor<C> {
             closed: Arc::new(AtomicBool::new(false)),
             _user_pass_file: user_pass_file,
             _proxy_auth_file: proxy_auth_file,
+            _client_certificate_files: client_certificate_files,
 
             runtime: tokio::runtime::Handle::current(),
             event_server_abort_tx,
@@ -451,14 +490,23 @@ impl<C: OpenVpnBuilder + Send + 'static> OpenVpnMonitor<C> {
     async fn prepare_process(
         cmd: C,
         #[cfg(windows)] wintun: Arc<Box<dyn WintunContext>>,
-    ) -> io::Result<C::ProcessHandle> {
+    ) -> Result<C::ProcessHandle> {
         #[cfg(windows)]
         {
             log::debug!("Wait for IP interfaces");
-            wintun.wait_for_interfaces().await?;
+            wintun
+                .wait_for_interfaces()
+                .await
+                .map_err(Error::WaitForInterfacesError)?;
             wintun.prepare_interface();
         }
-        cmd.start()
+        cmd.start().map_err(|error| {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to start OpenVPN")
+            );
+            Error::StartProcessError
+        })
     }
 
     /// Creates a handle to this monitor, allowing the tunnel to be closed while some other
@@ -518,28 +566,20 @@ impl<C: OpenVpnBuilder + Send + 'static> OpenVpnMonitor<C> {
     fn wait_tunnel(self) -> Result<()> {
         let result = self.inner_wait_tunnel();
         match result {
-            WaitResult::Preparation(result) => match result {
-                Err(error) => {
-                    log::debug!(
-                        "{}",
-                        error.display_chain_with_msg("Failed to start OpenVPN")
-                    );
-                    Err(Error::StartProcessError)
+            WaitResult::Preparation(result) => result,
+            WaitResult::Child(Ok(exit_status), closed) => match classify_exit(exit_status) {
+                Some(failure) if !closed => {
+                    log::error!("OpenVPN died unexpectedly with status: {}", exit_status);
+                    Err(Error::ChildProcessDied(failure))
                 }
-                _ => Ok(()),
-            },
-            WaitResult::Child(Ok(exit_status), closed) => {
-                if exit_status.success() || closed {
+                _ => {
                     log::debug!(
                         "OpenVPN exited, as expected, with exit status: {}",
                         exit_status
                     );
                     Ok(())
-                } else {
-                    log::error!("OpenVPN died unexpectedly with status: {}", exit_status);
-                    Err(Error::ChildProcessDied)
                 }
-            }
+            },
             WaitResult::Child(Err(e), _) => {
                 log::error!("OpenVPN process wait error: {}", e);
                 Err(Error::ChildProcessError("Error when waiting", e))
@@ -569,6 +609,7 @@ impl<C: OpenVpnBuilder + Send + 'static> OpenVpnMonitor<C> {
 
         if self.closed.load(Ordering::SeqCst) {
             let _ = child.kill();
+            let _ = child.wait();
             return WaitResult::Preparation(Ok(()));
         }
 
@@ -635,7 +676,7 @@ impl<C: OpenVpnBuilder + Send + 'static> OpenVpnMonitor<C> {
     }
 
     fn create_credentials_file(username: &str, password: &str) -> io::Result<mktemp::TempFile> {
-        let temp_file = mktemp::TempFile::new();
+        let temp_file = mktemp::TempFile::new_secret();
         log::debug!("Writing credentials to {}", temp_file.as_ref().display());
         let mut file = fs::File::create(&temp_file)?;
         Self::set_user_pass_file_permissions(&file)?;
@@ -643,6 +684,30 @@ impl<C: OpenVpnBuilder + Send + 'static> OpenVpnMonitor<C> {
         Ok(temp_file)
     }
 
+    /// Writes the client certificate and its private key to separate files. Unlike the
+    /// credentials file, these must remain for as long as the process runs, since OpenVPN reads
+    /// them again when it restarts the connection.
+    fn create_client_certificate_files(
+        client_certificate: &openvpn::ClientCertificate,
+    ) -> io::Result<(mktemp::TempFile, mktemp::TempFile)> {
+        let cert_file = Self::create_secret_file(&client_certificate.certificate)?;
+        let key_file = Self::create_secret_file(&client_certificate.private_key)?;
+        log::debug!(
+            "Wrote client certificate to {} and {}",
+            cert_file.as_ref().display(),
+            key_file.as_ref().display()
+        );
+        Ok((cert_file, key_file))
+    }
+
+    fn create_secret_file(contents: &str) -> io::Result<mktemp::TempFile> {
+        let temp_file = mktemp::TempFile::new_secret();
+        let mut file = fs::File::create(&temp_file)?;
+        Self::set_user_pass_file_permissions(&file)?;
+        file.write_all(contents.as_bytes())?;
+        Ok(temp_file)
+    }
+
     #[cfg(unix)]
     fn set_user_pass_file_permissions(file: &fs::File) -> io::Result<()> {
         use std::os::unix::fs::PermissionsExt;
@@ -669,6 +734,7 @@ impl<C: OpenVpnBuilder + Send + 'static> OpenVpnMonitor<C> {
         params: &openvpn::TunnelParameters,
         user_pass_file: &Path,
         proxy_auth_file: Option<&Path>,
+        client_certificate_files: Option<(&Path, &Path)>,
         resource_dir: &Path,
         proxy_monitor: &Option<Box<dyn ProxyMonitor>>,
         #[cfg(windows)] alias: OsString,
@@ -692,6 +758,9 @@ impl<C: OpenVpnBuilder + Send + 'static> OpenVpnMonitor<C> {
         if let Some(proxy_auth_file) = proxy_auth_file {
             cmd.proxy_auth(proxy_auth_file);
         }
+        if let Some((cert_file, key_file)) = client_certificate_files {
+            cmd.client_certificate(cert_file, key_file);
+        }
         if let Some(proxy) = proxy_monitor {
             cmd.proxy_port(proxy.port());
         }
@@ -728,7 +797,8 @@ pub struct OpenVpnCloseHandle<H: ProcessHandle = OpenVpnProcHandle> {
 }
 
 impl<H: ProcessHandle> OpenVpnCloseHandle<H> {
-    /// Kills the underlying OpenVPN process, making the `OpenVpnMonitor::wait` method return.
+    /// Stops the underlying OpenVPN process, making the `OpenVpnMonitor::wait` method return once
+    /// it has exited.
     pub fn close(self) -> io::Result<()> {
         if !self.closed.swap(true, Ordering::SeqCst) {
             self.abort_spawn.abort();
@@ -746,7 +816,7 @@ impl<H: ProcessHandle> OpenVpnCloseHandle<H> {
 /// Internal enum to differentiate between if the child process or the event dispatcher died first.
 #[derive(Debug)]
 enum WaitResult {
-    Preparation(io::Result<()>),
+    Preparation(Result<()>),
     Child(io::Result<ExitStatus>, bool),
     EventDispatcher,
 }
@@ -771,7 +841,7 @@ pub trait ProcessHandle: Send + Sync + 'static {
     /// Block until the subprocess exits or there is an error in the wait syscall.
     fn wait(&self) -> io::Result<ExitStatus>;
 
-    /// Kill the subprocess.
+    /// Stop the subprocess, killing it if it does not exit in time. This must not block.
     fn kill(&self) -> io::Result<()>;
 }
 
@@ -791,17 +861,18 @@ impl OpenVpnBuilder for OpenVpnCommand {
     }
 
     fn start(&self) -> io::Result<OpenVpnProcHandle> {
-        OpenVpnProcHandle::new(self.build())
+        OpenVpnProcHandle::new(self.build(), OPENVPN_DIE_TIMEOUT)
     }
 }
 
 impl ProcessHandle for OpenVpnProcHandle {
     fn wait(&self) -> io::Result<ExitStatus> {
-        self.inner.wait().map(|output| output.status)
+        futures::executor::block_on(OpenVpnProcHandle::wait(self))
     }
 
     fn kill(&self) -> io::Result<()> {
-        self.nice_kill(OPENVPN_DIE_TIMEOUT)
+        self.stop();
+        Ok(())
     }
 }
 
@@ -989,6 +1060,7 @@ mod event_server {
                 ips,
                 ipv4_gateway,
                 ipv6_gateway,
+                wireguard_backend: None,
             })
         }
     }
@@ -1239,6 +1311,7 @@ mod tests {
             log_path,
             user_pass_file: TempFile::new(),
             proxy_auth_file: None,
+            client_certificate_files: None,
             proxy_monitor: None,
             tunnel_close_rx: close_rx,
         }
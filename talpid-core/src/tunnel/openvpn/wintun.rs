@@ -1,4 +1,4 @@
-use crate::windows::string_from_guid;
+use crate::windows::{alias_from_luid, string_from_guid};
 use lazy_static::lazy_static;
 use std::{
     ffi::CStr,
@@ -31,6 +31,9 @@ use winreg::{
 lazy_static! {
     /// Shared `WintunDll` instance
     static ref WINTUN_DLL: Mutex<Option<Arc<WintunDll>>> = Mutex::new(None);
+
+    /// Adapter that is kept across tunnels, so that it only has to be created once
+    static ref POOLED_ADAPTER: Mutex<Option<Arc<WintunAdapter>>> = Mutex::new(None);
 }
 
 type WintunCreateAdapterFn = unsafe extern "stdcall" fn(
@@ -102,6 +105,77 @@ impl WintunAdapter {
         Ok(adapter)
     }
 
+    /// Returns the adapter that was created by a previous call, if it is still usable. Otherwise,
+    /// a new adapter is created and kept for subsequent calls.
+    ///
+    /// Creating an adapter can take several seconds, and notifies other software of a new
+    /// network device, so the adapter is reused across tunnels.
+    pub fn get_or_create(
+        dll_handle: Arc<WintunDll>,
+        name: &U16CStr,
+        tunnel_type: &U16CStr,
+        requested_guid: Option<GUID>,
+    ) -> io::Result<Arc<Self>> {
+        let mut pooled = POOLED_ADAPTER
+            .lock()
+            .expect("Wintun adapter mutex poisoned");
+        if let Some(adapter) = &*pooled {
+            match adapter.check_health(requested_guid.as_ref()) {
+                Ok(()) => {
+                    log::debug!("Reusing Wintun adapter");
+                    return Ok(adapter.clone());
+                }
+                Err(error) => {
+                    log::warn!(
+                        "{}",
+                        error.display_chain_with_msg("Recreating unusable Wintun adapter")
+                    );
+                }
+            }
+        }
+
+        // Close the old adapter before creating a new one with the same name and GUID
+        *pooled = None;
+
+        let adapter = Arc::new(Self::create(dll_handle, name, tunnel_type, requested_guid)?);
+        *pooled = Some(adapter.clone());
+        Ok(adapter)
+    }
+
+    /// Closes the adapter kept by [`WintunAdapter::get_or_create`]. It is removed once it is no
+    /// longer used by any tunnel.
+    pub fn release_pooled() {
+        if POOLED_ADAPTER
+            .lock()
+            .expect("Wintun adapter mutex poisoned")
+            .take()
+            .is_some()
+        {
+            log::debug!("Releasing Wintun adapter");
+        }
+    }
+
+    /// Checks that the adapter still exists and has not been changed since it was created.
+    fn check_health(&self, requested_guid: Option<&GUID>) -> io::Result<()> {
+        let guid = self.guid()?;
+        if let Some(requested_guid) = requested_guid {
+            if string_from_guid(&guid) != string_from_guid(requested_guid) {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "The adapter GUID has changed",
+                ));
+            }
+        }
+        let alias = alias_from_luid(&self.luid())?;
+        if alias != self.name.to_os_string() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "The adapter has been renamed",
+            ));
+        }
+        Ok(())
+    }
+
     pub fn prepare_interface(&self) {
         if let Err(error) = crate::tunnel::windows::initialize_interfaces(self.luid(), None) {
             log::error!(
@@ -1,6 +1,13 @@
 use super::TunConfig;
+#[cfg(target_os = "macos")]
+use crate::network_extension::{PacketTunnelHost, PacketTunnelPolicy, PacketTunnelSettings};
 use crate::network_interface::{self, NetworkInterface, TunnelDevice};
-use std::{net::IpAddr, ops::Deref};
+#[cfg(target_os = "macos")]
+use std::sync::Arc;
+use std::{
+    net::IpAddr,
+    os::unix::io::{AsRawFd, RawFd},
+};
 
 /// Errors that can occur while setting up a tunnel device.
 #[derive(Debug, err_derive::Error)]
@@ -17,10 +24,24 @@ pub enum Error {
     /// Failure to set the tunnel device as up.
     #[error(display = "Failed to set the tunnel device as up")]
     SetUp(#[cause] network_interface::Error),
+
+    /// The packet tunnel provider failed to apply the network settings.
+    #[cfg(target_os = "macos")]
+    #[error(display = "The packet tunnel provider failed to apply the network settings")]
+    ApplyPacketTunnelSettings(#[cause] talpid_types::BoxedError),
+
+    /// The name of the tunnel device of the packet tunnel provider could not be obtained.
+    #[cfg(target_os = "macos")]
+    #[error(display = "Failed to obtain the name of the packet tunnel device")]
+    PacketTunnelInterfaceName(#[cause] std::io::Error),
 }
 
 /// Factory of tunnel devices on Unix systems.
-pub struct UnixTunProvider;
+pub struct UnixTunProvider {
+    /// Provides the tunnel device instead, if running in a Network Extension.
+    #[cfg(target_os = "macos")]
+    packet_tunnel: Option<(Arc<dyn PacketTunnelHost>, PacketTunnelPolicy)>,
+}
 
 impl Default for UnixTunProvider {
     fn default() -> Self {
@@ -30,10 +51,35 @@ impl Default for UnixTunProvider {
 
 impl UnixTunProvider {
     pub fn new() -> Self {
-        UnixTunProvider
+        UnixTunProvider {
+            #[cfg(target_os = "macos")]
+            packet_tunnel: None,
+        }
+    }
+
+    /// Creates a provider that obtains the tunnel device from a packet tunnel provider, by asking
+    /// `host` to apply the network settings of the tunnel.
+    #[cfg(target_os = "macos")]
+    pub fn with_packet_tunnel_host(
+        host: Arc<dyn PacketTunnelHost>,
+        policy: PacketTunnelPolicy,
+    ) -> Self {
+        UnixTunProvider {
+            packet_tunnel: Some((host, policy)),
+        }
     }
 
     pub fn get_tun(&mut self, config: TunConfig) -> Result<UnixTun, Error> {
+        #[cfg(target_os = "macos")]
+        if let Some((host, policy)) = &self.packet_tunnel {
+            let settings = PacketTunnelSettings::new(&config, policy);
+            let fd = host
+                .apply_settings(&settings)
+                .map_err(Error::ApplyPacketTunnelSettings)?;
+            let name = utun_interface_name(fd).map_err(Error::PacketTunnelInterfaceName)?;
+            return Ok(UnixTun(UnixTunDevice::PacketTunnel { fd, name }));
+        }
+
         let mut tunnel_device = TunnelDevice::new().map_err(Error::CreateTunnelDevice)?;
 
         for ip in config.addresses.iter() {
@@ -44,26 +90,64 @@ impl UnixTunProvider {
 
         tunnel_device.set_up(true).map_err(Error::SetUp)?;
 
-        Ok(UnixTun(tunnel_device))
+        Ok(UnixTun(UnixTunDevice::Device(tunnel_device)))
     }
 }
 
 /// Generic tunnel device.
 ///
 /// Contains the file descriptor representing the device.
-pub struct UnixTun(TunnelDevice);
+pub struct UnixTun(UnixTunDevice);
+
+enum UnixTunDevice {
+    Device(TunnelDevice),
+    /// A utun device that is owned by a packet tunnel provider.
+    #[cfg(target_os = "macos")]
+    PacketTunnel {
+        fd: RawFd,
+        name: String,
+    },
+}
 
 impl UnixTun {
     /// Retrieve the tunnel interface name.
     pub fn interface_name(&self) -> &str {
-        self.get_name()
+        match &self.0 {
+            UnixTunDevice::Device(device) => device.get_name(),
+            #[cfg(target_os = "macos")]
+            UnixTunDevice::PacketTunnel { name, .. } => name,
+        }
     }
 }
 
-impl Deref for UnixTun {
-    type Target = TunnelDevice;
+impl AsRawFd for UnixTun {
+    fn as_raw_fd(&self) -> RawFd {
+        match &self.0 {
+            UnixTunDevice::Device(device) => device.as_raw_fd(),
+            #[cfg(target_os = "macos")]
+            UnixTunDevice::PacketTunnel { fd, .. } => *fd,
+        }
+    }
+}
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+#[cfg(target_os = "macos")]
+fn utun_interface_name(fd: RawFd) -> std::io::Result<String> {
+    let mut buffer = [0u8; libc::IFNAMSIZ];
+    let mut length = buffer.len() as libc::socklen_t;
+    // SAFETY: The buffer is valid for `length` bytes.
+    let result = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SYSPROTO_CONTROL,
+            libc::UTUN_OPT_IFNAME,
+            buffer.as_mut_ptr() as *mut libc::c_void,
+            &mut length,
+        )
+    };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error());
     }
+    let name = &buffer[..length as usize];
+    let name = name.split(|byte| *byte == 0).next().unwrap_or(name);
+    Ok(String::from_utf8_lossy(name).into_owned())
 }
@@ -0,0 +1,113 @@
+//! Limits the throughput of the tunnel interface using the queueing disciplines of the kernel.
+//!
+//! Sent traffic is shaped by a token bucket filter, which delays packets that exceed the limit.
+//! Received traffic cannot be queued, so packets that exceed the limit are dropped by a policer
+//! instead, which makes TCP connections slow down. The queueing disciplines belong to the tunnel
+//! interface, so they are removed along with it.
+
+use talpid_types::net::wireguard::BandwidthLimit;
+
+/// Minimum number of bytes that may be sent in a burst. It must be at least the MTU.
+const MIN_BURST_BYTES: u64 = 16 * 1024;
+/// Maximum time that a packet may wait in the token bucket filter before it is dropped.
+const MAX_LATENCY: &str = "50ms";
+
+/// Errors that can happen while setting a bandwidth limit.
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    /// Failed to run `tc`.
+    #[error(display = "Failed to run tc")]
+    RunTc(#[error(source)] std::io::Error),
+
+    /// `tc` reported an error.
+    #[error(display = "tc failed: {}", _0)]
+    Tc(String),
+}
+
+/// Limits the throughput of `interface` according to `limit`.
+pub fn apply(interface: &str, limit: &BandwidthLimit) -> Result<(), Error> {
+    if let Some(kbps) = limit.upload_kbps {
+        let (rate, burst) = rate_args(kbps);
+        tc(&[
+            "qdisc",
+            "replace",
+            "dev",
+            interface,
+            "root",
+            "tbf",
+            "rate",
+            &rate,
+            "burst",
+            &burst,
+            "latency",
+            MAX_LATENCY,
+        ])?;
+    }
+    if let Some(kbps) = limit.download_kbps {
+        let (rate, burst) = rate_args(kbps);
+        tc(&[
+            "qdisc", "replace", "dev", interface, "handle", "ffff:", "ingress",
+        ])?;
+        tc(&[
+            "filter",
+            "replace",
+            "dev",
+            interface,
+            "parent",
+            "ffff:",
+            "protocol",
+            "all",
+            "prio",
+            "1",
+            "matchall",
+            "action",
+            "police",
+            "rate",
+            &rate,
+            "burst",
+            &burst,
+            "conform-exceed",
+            "drop",
+        ])?;
+    }
+    Ok(())
+}
+
+/// Returns the rate and burst size arguments for a limit of `kbps` kilobits per second. The
+/// burst size allows roughly 100 ms of traffic to be sent at once.
+fn rate_args(kbps: u32) -> (String, String) {
+    let bytes_per_second = u64::from(kbps) * 1000 / 8;
+    let burst = std::cmp::max(bytes_per_second / 10, MIN_BURST_BYTES);
+    (format!("{}kbit", kbps), format!("{}b", burst))
+}
+
+fn tc(args: &[&str]) -> Result<(), Error> {
+    let output = duct::cmd("tc", args)
+        .stderr_capture()
+        .unchecked()
+        .run()
+        .map_err(Error::RunTc)?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+        return Err(Error::Tc(stderr));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rate_args() {
+        assert_eq!(
+            rate_args(1000),
+            ("1000kbit".to_owned(), format!("{}b", MIN_BURST_BYTES))
+        );
+        assert_eq!(
+            rate_args(100_000),
+            ("100000kbit".to_owned(), "1250000b".to_owned())
+        );
+    }
+}
@@ -1,7 +1,7 @@
 use std::{
     borrow::Cow,
     ffi::CString,
-    net::{Ipv4Addr, Ipv6Addr},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
 };
 use talpid_types::net::{obfuscation::ObfuscatorConfig, wireguard, GenericTunnelOptions};
 
@@ -29,6 +29,10 @@ pub struct Config {
     pub use_wireguard_nt: bool,
     /// Obfuscator config to be used for reaching the relay.
     pub obfuscator_config: Option<ObfuscatorConfig>,
+    /// Traffic shaping to apply to the tunnel.
+    pub traffic_shaping: wireguard::TrafficShapingOptions,
+    /// Maximum throughput of the tunnel.
+    pub bandwidth_limit: wireguard::BandwidthLimit,
 }
 
 #[cfg(not(target_os = "android"))]
@@ -86,31 +90,65 @@ impl Config {
             return Err(Error::NoPeersSuppliedError);
         }
         let mtu = wg_options.mtu.unwrap_or(DEFAULT_MTU);
+
+        tunnel
+            .addresses
+            .retain(|ip| ip.is_ipv4() || generic_options.enable_ipv6);
+        if tunnel.addresses.is_empty() {
+            return Err(Error::InvalidTunnelIpError);
+        }
+        // IPv4 traffic cannot be sent through a tunnel that only has IPv6 addresses
+        let has_ipv4 = tunnel.addresses.iter().any(|ip| ip.is_ipv4());
+        // If IPv4 is disabled, only the gateway is reachable over IPv4, for DNS and connectivity
+        // checks. Other IPv4 traffic is not routed into the tunnel, and is blocked by the firewall.
+        let ipv4_gateway_net =
+            ipnetwork::IpNetwork::from(IpAddr::from(connection_config.ipv4_gateway));
+
         for peer in &mut peers {
             peer.allowed_ips = peer
                 .allowed_ips
                 .iter()
                 .cloned()
-                .filter(|ip| ip.is_ipv4() || generic_options.enable_ipv6)
+                .filter(|ip| {
+                    if ip.is_ipv4() {
+                        has_ipv4
+                    } else {
+                        generic_options.enable_ipv6
+                    }
+                })
+                .map(|ip| {
+                    if ip.is_ipv4() && ip.prefix() == 0 && !generic_options.enable_ipv4 {
+                        ipv4_gateway_net
+                    } else {
+                        ip
+                    }
+                })
                 .collect();
             if peer.allowed_ips.is_empty() {
                 return Err(Error::InvalidPeerIpError);
             }
         }
 
-        if tunnel.addresses.is_empty() {
-            return Err(Error::InvalidTunnelIpError);
-        }
-        tunnel
-            .addresses
-            .retain(|ip| ip.is_ipv4() || generic_options.enable_ipv6);
-
         let ipv6_gateway = if generic_options.enable_ipv6 {
             connection_config.ipv6_gateway
         } else {
             None
         };
 
+        if let Some(allowed_ips) = &wg_options.allowed_ips {
+            // Only the exit peer routes traffic to arbitrary destinations
+            let exit_peer = peers.last_mut().expect("there is at least one peer");
+            exit_peer.allowed_ips = narrow_allowed_ips(
+                &exit_peer.allowed_ips,
+                allowed_ips,
+                ipv4_gateway_net,
+                ipv6_gateway,
+            );
+            if exit_peer.allowed_ips.is_empty() {
+                return Err(Error::InvalidPeerIpError);
+            }
+        }
+
         Ok(Config {
             tunnel,
             peers,
@@ -124,6 +162,8 @@ impl Config {
             #[cfg(target_os = "windows")]
             use_wireguard_nt: wg_options.use_wireguard_nt,
             obfuscator_config,
+            traffic_shaping: wg_options.traffic_shaping,
+            bandwidth_limit: wg_options.bandwidth_limit,
         })
     }
 
@@ -159,6 +199,29 @@ impl Config {
     }
 }
 
+/// Returns the networks in `allowed_ips` that are covered by `peer_allowed_ips`. The gateways are
+/// kept so that DNS and connectivity checks still go through the tunnel.
+fn narrow_allowed_ips(
+    peer_allowed_ips: &[ipnetwork::IpNetwork],
+    allowed_ips: &[ipnetwork::IpNetwork],
+    ipv4_gateway_net: ipnetwork::IpNetwork,
+    ipv6_gateway: Option<Ipv6Addr>,
+) -> Vec<ipnetwork::IpNetwork> {
+    let is_covered = |network: &ipnetwork::IpNetwork| {
+        peer_allowed_ips.iter().any(|peer_network| {
+            peer_network.prefix() <= network.prefix() && peer_network.contains(network.ip())
+        })
+    };
+    let mut narrowed: Vec<_> = allowed_ips.iter().cloned().filter(is_covered).collect();
+    if narrowed.is_empty() {
+        return narrowed;
+    }
+    let gateways = std::iter::once(ipv4_gateway_net)
+        .chain(ipv6_gateway.map(|gateway| ipnetwork::IpNetwork::from(IpAddr::from(gateway))));
+    narrowed.extend(gateways.filter(is_covered));
+    narrowed
+}
+
 enum ConfValue<'a> {
     String(&'a str),
     Bytes(&'a [u8]),
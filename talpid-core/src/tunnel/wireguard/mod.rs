@@ -3,15 +3,16 @@ use self::config::Config;
 use super::tun_provider;
 use super::{tun_provider::TunProvider, TunnelArgs, TunnelEvent, TunnelMetadata};
 use crate::routing::{self, RequiredRoute};
+#[cfg(target_os = "linux")]
+use crate::{
+    routing::gateway_probe::{self, GatewayReachability},
+    runtime_config::{self, FeatureFlag},
+};
 use futures::future::{abortable, AbortHandle as FutureAbortHandle, BoxFuture, Future};
 #[cfg(windows)]
 use futures::{channel::mpsc, StreamExt};
 #[cfg(target_os = "linux")]
-use lazy_static::lazy_static;
-#[cfg(target_os = "linux")]
 use netlink_packet_route::rtnl::constants::RT_TABLE_MAIN;
-#[cfg(target_os = "linux")]
-use std::env;
 #[cfg(windows)]
 use std::io;
 use std::{
@@ -20,28 +21,40 @@ use std::{
     net::IpAddr,
     path::Path,
     pin::Pin,
-    sync::{mpsc as sync_mpsc, Arc, Mutex},
+    sync::{mpsc as sync_mpsc, Arc, Mutex, Weak},
     time::Duration,
 };
 #[cfg(windows)]
 use talpid_types::BoxedError;
 use talpid_types::{
     net::{
-        obfuscation::ObfuscatorConfig, wireguard::PublicKey, AllowedTunnelTraffic, Endpoint,
-        TransportProtocol,
+        obfuscation::ObfuscatorConfig,
+        wireguard::{
+            BandwidthLimit, PublicKey, TrafficShapingFailurePolicy, TrafficShapingOptions,
+            TrafficShapingStats, WireguardBackend,
+        },
+        AllowedTunnelTraffic, Endpoint, TransportProtocol,
     },
     ErrorExt,
 };
 use tokio::sync::Mutex as AsyncMutex;
 use tunnel_obfuscation::{
-    create_obfuscator, Error as ObfuscationError, Settings as ObfuscationSettings, Udp2TcpSettings,
+    create_obfuscator, Error as ObfuscationError, Settings as ObfuscationSettings, TlsSettings,
+    Udp2TcpSettings,
 };
 
+#[cfg(target_os = "linux")]
+mod bandwidth_limit;
 /// WireGuard config data-types
 pub mod config;
 mod connectivity_check;
 mod logging;
+#[cfg(target_os = "macos")]
+mod roaming;
+#[cfg(target_os = "linux")]
+mod router_advertisement;
 mod stats;
+pub use stats::{Stats, StatsMap};
 mod wireguard_go;
 #[cfg(target_os = "linux")]
 pub(crate) mod wireguard_kernel;
@@ -85,6 +98,20 @@ pub enum Error {
     #[error(display = "Failed to negotiate PQ PSK")]
     PskNegotiationError(#[error(source)] talpid_tunnel_config_client::Error),
 
+    /// Failed to set up traffic shaping, and the tunnel must not be used without it.
+    #[error(display = "Failed to set up traffic shaping")]
+    TrafficShapingError(#[error(source)] TunnelError),
+
+    /// The interface that the tunnel is forced to use does not exist or cannot reach the relay.
+    #[cfg(not(target_os = "android"))]
+    #[error(display = "The forced interface {} is unavailable", _0)]
+    ForcedInterfaceUnavailable(String),
+
+    /// The gateway that traffic to the relay is routed through does not respond to ARP or NDP.
+    #[cfg(target_os = "linux")]
+    #[error(display = "The gateway {} is unreachable", _0)]
+    GatewayUnreachable(IpAddr),
+
     /// Failed to set up IP interfaces.
     #[cfg(windows)]
     #[error(display = "Failed to set up IP interfaces")]
@@ -108,10 +135,54 @@ pub struct WireguardMonitor {
     obfuscator: Arc<AsyncMutex<Option<ObfuscatorHandle>>>,
 }
 
+/// Reads the traffic shaping counters of the tunnel that is currently running, if there is one,
+/// and changes its traffic shaping in place. Clones refer to the same tunnel.
+#[derive(Clone, Default)]
+pub struct TrafficShapingHandle {
+    tunnel: Arc<Mutex<Weak<Mutex<Option<Box<dyn Tunnel>>>>>>,
+}
+
+impl TrafficShapingHandle {
+    /// Returns the counters of the running tunnel, or `None` if there is no tunnel or traffic
+    /// shaping is not in use.
+    pub fn get(&self) -> Option<TrafficShapingStats> {
+        let tunnel = self.tunnel.lock().unwrap().upgrade()?;
+        let tunnel = tunnel.lock().expect("Tunnel lock poisoned");
+        tunnel.as_ref()?.get_traffic_shaping_stats()
+    }
+
+    /// Applies `options` to the running tunnel without reconnecting. Does nothing if there is no
+    /// tunnel. Fails if the tunnel cannot shape its traffic and `options` enable shaping, in
+    /// which case a new tunnel has to be created.
+    pub fn set(&self, options: &TrafficShapingOptions) -> std::result::Result<(), TunnelError> {
+        let tunnel = match self.tunnel.lock().unwrap().upgrade() {
+            Some(tunnel) => tunnel,
+            None => return Ok(()),
+        };
+        let tunnel = tunnel.lock().expect("Tunnel lock poisoned");
+        let tunnel = match tunnel.as_ref() {
+            Some(tunnel) => tunnel,
+            None => return Ok(()),
+        };
+        match tunnel.set_traffic_shaping(options) {
+            Err(TunnelError::TrafficShapingUnsupported) if !options.is_enabled() => Ok(()),
+            result => result,
+        }
+    }
+
+    fn set_tunnel(&self, tunnel: Weak<Mutex<Option<Box<dyn Tunnel>>>>) {
+        *self.tunnel.lock().unwrap() = tunnel;
+    }
+}
+
 const INITIAL_PSK_EXCHANGE_TIMEOUT: Duration = Duration::from_secs(4);
 const MAX_PSK_EXCHANGE_TIMEOUT: Duration = Duration::from_secs(15);
 const PSK_EXCHANGE_TIMEOUT_MULTIPLIER: u32 = 2;
 
+/// Time to wait for WireGuardNT to create an adapter before giving up on it.
+#[cfg(windows)]
+const WIREGUARD_NT_PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// Simple wrapper that automatically cancels the future which runs an obfuscator.
 struct ObfuscatorHandle {
     abort_handle: FutureAbortHandle,
@@ -133,18 +204,6 @@ impl Drop for ObfuscatorHandle {
     }
 }
 
-#[cfg(target_os = "linux")]
-lazy_static! {
-    /// Overrides the preference for the kernel module for WireGuard.
-    static ref FORCE_USERSPACE_WIREGUARD: bool = env::var("TALPID_FORCE_USERSPACE_WIREGUARD")
-        .map(|v| v != "0")
-        .unwrap_or(false);
-
-    static ref FORCE_NM_WIREGUARD: bool = env::var("TALPID_FORCE_NM_WIREGUARD")
-        .map(|v| v != "0")
-        .unwrap_or(false);
-}
-
 async fn maybe_create_obfuscator(
     config: &mut Config,
     close_msg_sender: sync_mpsc::Sender<CloseMsg>,
@@ -153,42 +212,62 @@ async fn maybe_create_obfuscator(
     // The first one is always the entry relay.
     let mut first_peer = config.peers.get_mut(0).expect("missing peer");
 
-    if let Some(ref obfuscator_config) = config.obfuscator_config {
-        match obfuscator_config {
-            ObfuscatorConfig::Udp2Tcp { endpoint } => {
-                log::trace!("Connecting to Udp2Tcp endpoint {:?}", *endpoint);
-                let settings = Udp2TcpSettings {
-                    peer: *endpoint,
-                    #[cfg(target_os = "linux")]
-                    fwmark: Some(crate::linux::TUNNEL_FW_MARK),
-                };
-                let obfuscator = create_obfuscator(&ObfuscationSettings::Udp2Tcp(settings))
-                    .await
-                    .map_err(Error::CreateObfuscatorError)?;
-                let endpoint = obfuscator.endpoint();
-                log::trace!("Patching first WireGuard peer to become {:?}", endpoint);
-                first_peer.endpoint = endpoint;
-                let (runner, abort_handle) = abortable(async move {
-                    match obfuscator.run().await {
-                        Ok(_) => {
-                            let _ = close_msg_sender.send(CloseMsg::ObfuscatorExpired);
-                        }
-                        Err(error) => {
-                            log::error!(
-                                "{}",
-                                error.display_chain_with_msg("Obfuscation controller failed")
-                            );
-                            let _ = close_msg_sender
-                                .send(CloseMsg::ObfuscatorFailed(Error::ObfuscatorError(error)));
-                        }
-                    }
-                });
-                tokio::spawn(runner);
-                return Ok(Some(ObfuscatorHandle::new(abort_handle)));
+    let obfuscator_config = match config.obfuscator_config {
+        Some(ref obfuscator_config) => obfuscator_config,
+        None => return Ok(None),
+    };
+    let settings = match obfuscator_config {
+        ObfuscatorConfig::Udp2Tcp { endpoint } => {
+            log::trace!("Connecting to Udp2Tcp endpoint {:?}", *endpoint);
+            ObfuscationSettings::Udp2Tcp(Udp2TcpSettings {
+                peer: *endpoint,
+                #[cfg(target_os = "linux")]
+                fwmark: Some(crate::linux::TUNNEL_FW_MARK),
+            })
+        }
+        ObfuscatorConfig::Tls {
+            endpoint,
+            server_name,
+            certificate,
+        } => {
+            log::trace!(
+                "Connecting to TLS endpoint {:?} with server name {}",
+                *endpoint,
+                server_name
+            );
+            ObfuscationSettings::Tls(TlsSettings {
+                peer: *endpoint,
+                server_name: server_name.clone(),
+                certificate_sha256: *certificate.as_bytes(),
+                #[cfg(target_os = "linux")]
+                fwmark: Some(crate::linux::TUNNEL_FW_MARK),
+            })
+        }
+    };
+
+    let obfuscator = create_obfuscator(&settings)
+        .await
+        .map_err(Error::CreateObfuscatorError)?;
+    let endpoint = obfuscator.endpoint();
+    log::trace!("Patching first WireGuard peer to become {:?}", endpoint);
+    first_peer.endpoint = endpoint;
+    let (runner, abort_handle) = abortable(async move {
+        match obfuscator.run().await {
+            Ok(_) => {
+                let _ = close_msg_sender.send(CloseMsg::ObfuscatorExpired);
+            }
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Obfuscation controller failed")
+                );
+                let _ = close_msg_sender
+                    .send(CloseMsg::ObfuscatorFailed(Error::ObfuscatorError(error)));
             }
         }
-    }
-    Ok(None)
+    });
+    tokio::spawn(runner);
+    Ok(Some(ObfuscatorHandle::new(abort_handle)))
 }
 
 impl WireguardMonitor {
@@ -209,6 +288,21 @@ impl WireguardMonitor {
 
         let endpoint_addrs: Vec<IpAddr> =
             config.peers.iter().map(|peer| peer.endpoint.ip()).collect();
+        let endpoint_routes = args.runtime.block_on(Self::get_endpoint_routes(
+            &endpoint_addrs,
+            #[cfg(not(target_os = "android"))]
+            args.forced_interface.as_deref(),
+            #[cfg(not(target_os = "android"))]
+            &args.route_manager,
+        ))?;
+        #[cfg(target_os = "linux")]
+        if runtime_config::is_enabled(FeatureFlag::GatewayProbe) {
+            args.runtime.block_on(Self::probe_gateways(
+                &endpoint_addrs,
+                args.forced_interface.as_deref(),
+                &args.route_manager,
+            ))?;
+        }
         let (close_msg_sender, close_msg_receiver) = sync_mpsc::channel();
 
         let obfuscator = args.runtime.block_on(maybe_create_obfuscator(
@@ -219,16 +313,45 @@ impl WireguardMonitor {
         #[cfg(target_os = "windows")]
         let (setup_done_tx, setup_done_rx) = mpsc::channel(0);
 
-        let tunnel = Self::open_tunnel(
-            args.runtime.clone(),
-            &Self::patch_allowed_ips(&config, psk_negotiation.is_some()),
-            log_path,
-            args.resource_dir,
-            args.tun_provider,
-            #[cfg(target_os = "windows")]
-            setup_done_tx,
-        )?;
+        // The sockets of the tunnel are bound to the forced interface, so that traffic to the
+        // relay cannot leave through another interface even if the routes change
+        #[cfg(not(target_os = "android"))]
+        let bind_interface = match args.forced_interface.as_deref() {
+            Some(interface) => Some(
+                routing::interface_index(interface)
+                    .ok_or_else(|| Error::ForcedInterfaceUnavailable(interface.to_owned()))?,
+            ),
+            None => None,
+        };
+
+        let patched_config = Self::patch_allowed_ips(&config, psk_negotiation.is_some());
+        let tunnel = match args.wireguard_tunnel_provider {
+            Some(ref provider) => {
+                log::debug!("Using custom WireGuard tunnel provider");
+                let tunnel = provider
+                    .open_tunnel(&patched_config)
+                    .map_err(Error::TunnelError)?;
+                // The built-in backends signal this once the interface is ready to be configured
+                #[cfg(target_os = "windows")]
+                let _ = setup_done_tx.clone().try_send(Ok(()));
+                tunnel
+            }
+            None => Self::open_tunnel(
+                args.runtime.clone(),
+                &patched_config,
+                log_path,
+                args.resource_dir,
+                args.tun_provider,
+                #[cfg(not(target_os = "android"))]
+                bind_interface,
+                #[cfg(target_os = "windows")]
+                setup_done_tx,
+            )?,
+        };
+        Self::set_up_traffic_shaping(tunnel.as_ref(), &config.traffic_shaping)?;
+        Self::set_up_bandwidth_limit(tunnel.as_ref(), &config.bandwidth_limit);
         let iface_name = tunnel.get_interface_name();
+        let backend = tunnel.backend();
 
         let event_callback = Box::new(on_event.clone());
         let (pinger_tx, pinger_rx) = sync_mpsc::channel();
@@ -240,6 +363,8 @@ impl WireguardMonitor {
             pinger_stop_sender: pinger_tx,
             obfuscator: Arc::new(AsyncMutex::new(obfuscator)),
         };
+        args.traffic_shaping
+            .set_tunnel(Arc::downgrade(&monitor.tunnel));
 
         let gateway = config.ipv4_gateway;
         let mut connectivity_monitor = connectivity_check::ConnectivityMonitor::new(
@@ -251,8 +376,10 @@ impl WireguardMonitor {
         )
         .map_err(Error::ConnectivityMonitorError)?;
 
-        let metadata = Self::tunnel_metadata(&iface_name, &config);
+        let metadata = Self::tunnel_metadata(&iface_name, &config, backend);
         let tunnel = monitor.tunnel.clone();
+        #[cfg(target_os = "macos")]
+        let roaming_tunnel = Arc::downgrade(&monitor.tunnel);
         let obfs_handle = monitor.obfuscator.clone();
         let obfs_close_sender = close_msg_sender.clone();
 
@@ -281,7 +408,7 @@ impl WireguardMonitor {
                 .map_err(CloseMsg::SetupError)?;
 
             let routes = Self::get_pre_tunnel_routes(&iface_name, &config)
-                .chain(Self::get_endpoint_routes(&endpoint_addrs))
+                .chain(endpoint_routes)
                 .collect();
             args.route_manager
                 .add_routes(routes)
@@ -334,6 +461,23 @@ impl WireguardMonitor {
 
             (on_event)(TunnelEvent::Up(metadata)).await;
 
+            #[cfg(target_os = "macos")]
+            let _roaming_monitor = roaming::RoamingMonitor::start(roaming_tunnel)
+                .map_err(|error| {
+                    log::error!(
+                        "{}",
+                        error.display_chain_with_msg("Failed to start roaming monitor")
+                    );
+                })
+                .ok();
+
+            #[cfg(target_os = "linux")]
+            let _router_advertisement_monitor = Self::start_router_advertisement_monitor(
+                args.runtime.clone(),
+                &iface_name,
+                &config,
+            );
+
             tokio::task::spawn_blocking(move || {
                 if let Err(error) = connectivity_monitor.run() {
                     log::error!(
@@ -365,6 +509,37 @@ impl WireguardMonitor {
         Ok(monitor)
     }
 
+    /// Starts handling router advertisements on the tunnel interface, if IPv6 is enabled.
+    #[cfg(target_os = "linux")]
+    fn start_router_advertisement_monitor(
+        runtime: tokio::runtime::Handle,
+        iface_name: &str,
+        config: &Config,
+    ) -> Option<router_advertisement::RouterAdvertisementMonitor> {
+        let gateway = config.ipv6_gateway?;
+        let static_address = config
+            .tunnel
+            .addresses
+            .iter()
+            .find_map(|address| match address {
+                IpAddr::V6(address) => Some(*address),
+                IpAddr::V4(_) => None,
+            })?;
+        router_advertisement::RouterAdvertisementMonitor::start(
+            runtime,
+            iface_name.to_owned(),
+            static_address,
+            gateway,
+        )
+        .map_err(|error| {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to start router advertisement monitor")
+            );
+        })
+        .ok()
+    }
+
     /// Replace `0.0.0.0/0`/`::/0` with the gateway IPs when `gateway_only` is true.
     /// Used to block traffic to other destinations while connecting on Android.
     fn patch_allowed_ips(config: &Config, gateway_only: bool) -> Cow<'_, Config> {
@@ -507,10 +682,16 @@ impl WireguardMonitor {
         log_path: Option<&Path>,
         resource_dir: &Path,
         tun_provider: Arc<Mutex<TunProvider>>,
+        #[cfg(not(target_os = "android"))] bind_interface: Option<u32>,
         #[cfg(windows)] setup_done_tx: mpsc::Sender<std::result::Result<(), BoxedError>>,
     ) -> Result<Box<dyn Tunnel>> {
+        // Traffic shaping and binding the sockets to an interface are only implemented by the
+        // userspace implementation
         #[cfg(target_os = "linux")]
-        if !*FORCE_USERSPACE_WIREGUARD {
+        if !runtime_config::is_enabled(FeatureFlag::ForceUserspaceWireguard)
+            && !config.traffic_shaping.is_enabled()
+            && bind_interface.is_none()
+        {
             if crate::dns::will_use_nm() {
                 match wireguard_kernel::NetworkManagerTunnel::new(runtime, config) {
                     Ok(tunnel) => {
@@ -532,6 +713,11 @@ impl WireguardMonitor {
                         log::debug!("Using kernel WireGuard implementation");
                         return Ok(Box::new(tunnel));
                     }
+                    // The conflict is with other programs rather than with the kernel module, so
+                    // report it instead of falling back to the userspace implementation
+                    Err(wireguard_kernel::Error::InterfaceNamesUnavailable) => {
+                        return Err(Error::TunnelError(TunnelError::InterfaceNamesUnavailable));
+                    }
                     Err(error) => {
                         log::error!(
                             "{}",
@@ -545,7 +731,11 @@ impl WireguardMonitor {
         }
 
         #[cfg(target_os = "windows")]
-        if config.use_wireguard_nt {
+        if config.use_wireguard_nt
+            && !config.traffic_shaping.is_enabled()
+            && bind_interface.is_none()
+            && wireguard_nt::is_usable()
+        {
             match wireguard_nt::WgNtTunnel::start_tunnel(
                 config,
                 log_path,
@@ -561,6 +751,10 @@ impl WireguardMonitor {
                         "{}",
                         error.display_chain_with_msg("Failed to setup WireGuardNT tunnel")
                     );
+                    // The driver will not become loadable until the daemon is restarted
+                    if matches!(error, wireguard_nt::Error::DllError(_)) {
+                        wireguard_nt::mark_unusable();
+                    }
                 }
             }
         }
@@ -575,6 +769,8 @@ impl WireguardMonitor {
                 tun_provider,
                 #[cfg(not(windows))]
                 Self::get_tunnel_destinations(config).flat_map(Self::replace_default_prefixes),
+                #[cfg(not(target_os = "android"))]
+                bind_interface,
                 #[cfg(windows)]
                 setup_done_tx,
             )
@@ -601,9 +797,54 @@ impl WireguardMonitor {
         wait_result
     }
 
+    /// Enables traffic shaping in `tunnel` if requested. Failing to do so is only an error if
+    /// the failure policy says so.
+    fn set_up_traffic_shaping(tunnel: &dyn Tunnel, options: &TrafficShapingOptions) -> Result<()> {
+        if !options.is_enabled() {
+            return Ok(());
+        }
+        match tunnel.set_traffic_shaping(options) {
+            Ok(()) => Ok(()),
+            Err(error) if options.on_failure == TrafficShapingFailurePolicy::Fallback => {
+                log::warn!(
+                    "{}",
+                    error.display_chain_with_msg(
+                        "Failed to set up traffic shaping. Connecting without it"
+                    )
+                );
+                Ok(())
+            }
+            Err(error) => Err(Error::TrafficShapingError(error)),
+        }
+    }
+
+    /// Limits the throughput of `tunnel` if requested. If this fails, the tunnel is used without a
+    /// limit.
+    fn set_up_bandwidth_limit(tunnel: &dyn Tunnel, limit: &BandwidthLimit) {
+        if !limit.is_enabled() {
+            return;
+        }
+        match tunnel.set_bandwidth_limit(limit) {
+            Ok(()) => log::debug!("Limited tunnel bandwidth: {}", limit),
+            Err(error) => log::warn!(
+                "{}",
+                error.display_chain_with_msg(
+                    "Failed to limit tunnel bandwidth. Connecting without a limit"
+                )
+            ),
+        }
+    }
+
     fn stop_tunnel(&mut self) {
         match self.tunnel.lock().expect("Tunnel lock poisoned").take() {
             Some(tunnel) => {
+                if let Some(stats) = tunnel.get_traffic_shaping_stats() {
+                    log::debug!(
+                        "Traffic shaping padded {} packets and injected {} dummy packets",
+                        stats.padded_packets,
+                        stats.injected_packets
+                    );
+                }
                 if let Err(e) = tunnel.stop() {
                     log::error!("{}", e.display_chain_with_msg("Failed to stop tunnel"));
                 }
@@ -614,21 +855,87 @@ impl WireguardMonitor {
         }
     }
 
-    /// Returns routes to the peer endpoints (through the physical interface).
-    #[cfg_attr(target_os = "linux", allow(unused_variables))]
-    fn get_endpoint_routes(endpoints: &[IpAddr]) -> impl Iterator<Item = RequiredRoute> + '_ {
+    /// Returns routes to the peer endpoints (through the physical interface). If an interface is
+    /// forced, the routes go through the gateway of that interface instead of the default route.
+    async fn get_endpoint_routes(
+        endpoints: &[IpAddr],
+        #[cfg(not(target_os = "android"))] forced_interface: Option<&str>,
+        #[cfg(not(target_os = "android"))] route_manager: &routing::RouteManagerHandle,
+    ) -> Result<Vec<RequiredRoute>> {
+        #[cfg(not(target_os = "android"))]
+        if let Some(interface) = forced_interface {
+            let mut routes = Vec::with_capacity(endpoints.len());
+            for endpoint in endpoints {
+                let node = route_manager
+                    .get_interface_node(interface.to_owned(), *endpoint)
+                    .await
+                    .map_err(Error::SetupRoutingError)?
+                    .ok_or_else(|| Error::ForcedInterfaceUnavailable(interface.to_owned()))?;
+                log::debug!("Routing traffic to {} through {:?}", endpoint, node);
+                let route = RequiredRoute::relay_host(*endpoint, node);
+                // Tunnel traffic is routed using the main table
+                #[cfg(target_os = "linux")]
+                let route = route.table(u32::from(RT_TABLE_MAIN));
+                routes.push(route);
+            }
+            return Ok(routes);
+        }
+
         #[cfg(target_os = "linux")]
         {
             // No need due to policy based routing.
-            std::iter::empty()
+            Ok(vec![])
         }
         #[cfg(not(target_os = "linux"))]
-        endpoints.iter().map(|ip| {
-            RequiredRoute::new(
-                ipnetwork::IpNetwork::from(*ip),
-                routing::NetNode::DefaultNode,
-            )
-        })
+        Ok(endpoints
+            .iter()
+            .map(|ip| RequiredRoute::relay_host(*ip, routing::NetNode::DefaultNode))
+            .collect())
+    }
+
+    /// Checks that the gateways that traffic to the peer endpoints is routed through respond to
+    /// ARP or NDP. Failures to probe are logged, and the gateway is then assumed to be reachable.
+    #[cfg(target_os = "linux")]
+    async fn probe_gateways(
+        endpoints: &[IpAddr],
+        forced_interface: Option<&str>,
+        route_manager: &routing::RouteManagerHandle,
+    ) -> Result<()> {
+        for endpoint in endpoints {
+            let node = match forced_interface {
+                Some(interface) => route_manager
+                    .get_interface_node(interface.to_owned(), *endpoint)
+                    .await
+                    .map_err(Error::SetupRoutingError)?,
+                None => route_manager
+                    .get_destination_route(*endpoint, true)
+                    .await
+                    .map_err(Error::SetupRoutingError)?
+                    .map(|route| route.get_node().clone()),
+            };
+            let node = match node {
+                Some(node) => node,
+                None => continue,
+            };
+            match gateway_probe::probe(&node).await {
+                Ok(GatewayReachability::Reachable(gateway)) => {
+                    log::debug!("Gateway {} to {} is reachable", gateway, endpoint);
+                }
+                Ok(GatewayReachability::Unreachable(gateway)) => {
+                    log::error!("Gateway {} to {} is unreachable", gateway, endpoint);
+                    return Err(Error::GatewayUnreachable(gateway));
+                }
+                Ok(GatewayReachability::NoGateway) => (),
+                Err(error) => log::warn!(
+                    "{}",
+                    error.display_chain_with_msg(&format!(
+                        "Failed to probe the gateway of {}",
+                        node
+                    ))
+                ),
+            }
+        }
+        Ok(())
     }
 
     #[cfg_attr(not(target_os = "windows"), allow(unused_variables))]
@@ -666,10 +973,20 @@ impl WireguardMonitor {
         }));
 
         let (node_v4, node_v6) = Self::get_tunnel_nodes(iface_name, config);
+        // The gateways are allowed IPs if IPv4 is disabled or only some networks are routed
+        // through the tunnel, but they already have routes
+        let ipv4_gateway_net = ipnetwork::IpNetwork::from(IpAddr::from(config.ipv4_gateway));
+        let ipv6_gateway_net = config
+            .ipv6_gateway
+            .map(|gateway| ipnetwork::IpNetwork::from(IpAddr::from(gateway)));
 
         let routes = gateway_routes.chain(
             Self::get_tunnel_destinations(config)
-                .filter(|allowed_ip| allowed_ip.prefix() != 0)
+                .filter(move |allowed_ip| {
+                    allowed_ip.prefix() != 0
+                        && *allowed_ip != ipv4_gateway_net
+                        && Some(*allowed_ip) != ipv6_gateway_net
+                })
                 .map(move |allowed_ip| {
                     if allowed_ip.is_ipv4() {
                         RequiredRoute::new(allowed_ip, node_v4.clone())
@@ -698,9 +1015,9 @@ impl WireguardMonitor {
             .flat_map(Self::replace_default_prefixes)
             .map(move |allowed_ip| {
                 if allowed_ip.is_ipv4() {
-                    RequiredRoute::new(allowed_ip, node_v4.clone())
+                    RequiredRoute::tunnel_default(allowed_ip, node_v4.clone())
                 } else {
-                    RequiredRoute::new(allowed_ip, node_v6.clone())
+                    RequiredRoute::tunnel_default(allowed_ip, node_v6.clone())
                 }
             })
     }
@@ -731,12 +1048,17 @@ impl WireguardMonitor {
         vec![network]
     }
 
-    fn tunnel_metadata(interface_name: &str, config: &Config) -> TunnelMetadata {
+    fn tunnel_metadata(
+        interface_name: &str,
+        config: &Config,
+        backend: Option<WireguardBackend>,
+    ) -> TunnelMetadata {
         TunnelMetadata {
             interface: interface_name.to_string(),
             ips: config.tunnel.addresses.clone(),
             ipv4_gateway: config.ipv4_gateway,
             ipv6_gateway: config.ipv6_gateway,
+            wireguard_backend: backend,
         }
     }
 }
@@ -750,14 +1072,97 @@ enum CloseMsg {
     ObfuscatorFailed(Error),
 }
 
-pub(crate) trait Tunnel: Send {
+/// A running WireGuard tunnel. Implemented by each WireGuard backend, and by custom backends
+/// plugged in through a [`TunnelProvider`].
+pub trait Tunnel: Send {
+    /// Returns the name of the tunnel interface.
     fn get_interface_name(&self) -> String;
+    /// Tears down the tunnel.
     fn stop(self: Box<Self>) -> std::result::Result<(), TunnelError>;
+    /// Returns traffic counters for each peer. These are used to detect whether the tunnel is
+    /// working, so they must increase as traffic flows through the tunnel.
     fn get_tunnel_stats(&self) -> std::result::Result<stats::StatsMap, TunnelError>;
+    /// Replaces the configuration of the running tunnel.
     fn set_config(
         &self,
         _config: Config,
     ) -> Pin<Box<dyn Future<Output = std::result::Result<(), TunnelError>> + Send>>;
+
+    /// Called when the interface of the default route changes, so that the tunnel can keep its
+    /// session by sending from the new interface. Implementations whose sockets are bound to an
+    /// interface or source address should override this. Kernel implementations roam on their
+    /// own.
+    fn rebind_sockets(&self) -> std::result::Result<(), TunnelError> {
+        Ok(())
+    }
+
+    /// Hook for padding packets and injecting dummy traffic. Implementations that have access
+    /// to the packets of the tunnel should override this.
+    fn set_traffic_shaping(
+        &self,
+        _options: &TrafficShapingOptions,
+    ) -> std::result::Result<(), TunnelError> {
+        Err(TunnelError::TrafficShapingUnsupported)
+    }
+
+    /// Limits the throughput of the tunnel. Implementations that have access to the packets of
+    /// the tunnel may override this to limit it in the data path. By default, the limit is
+    /// applied to the tunnel interface using the queueing disciplines of the kernel on Linux, and
+    /// is not supported on other platforms.
+    fn set_bandwidth_limit(&self, limit: &BandwidthLimit) -> std::result::Result<(), TunnelError> {
+        #[cfg(target_os = "linux")]
+        {
+            bandwidth_limit::apply(&self.get_interface_name(), limit)
+                .map_err(TunnelError::BandwidthLimit)
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = limit;
+            Err(TunnelError::BandwidthLimitUnsupported)
+        }
+    }
+
+    /// Returns counters for packets affected by traffic shaping, if it is enabled.
+    fn get_traffic_shaping_stats(&self) -> Option<TrafficShapingStats> {
+        None
+    }
+
+    /// Returns the WireGuard implementation that the tunnel runs on, if it is one of the
+    /// built-in backends.
+    fn backend(&self) -> Option<WireguardBackend> {
+        None
+    }
+}
+
+/// Checks whether WireGuardNT works on this system by loading the driver and creating a temporary
+/// adapter. If it does not, or if the check does not complete within
+/// [`WIREGUARD_NT_PROBE_TIMEOUT`], wireguard-go is used instead until the process is restarted.
+/// Returns whether WireGuardNT can be used.
+#[cfg(windows)]
+pub async fn probe_wireguard_nt(resource_dir: std::path::PathBuf) -> bool {
+    let probe = tokio::task::spawn_blocking(move || wireguard_nt::probe(&resource_dir));
+    let error = match tokio::time::timeout(WIREGUARD_NT_PROBE_TIMEOUT, probe).await {
+        Ok(Ok(Ok(()))) => {
+            log::debug!("WireGuardNT is available");
+            return true;
+        }
+        Ok(Ok(Err(error))) => error.display_chain_with_msg("WireGuardNT is unavailable"),
+        Ok(Err(_)) => "WireGuardNT probe panicked".to_owned(),
+        Err(_) => "Timed out while probing WireGuardNT".to_owned(),
+    };
+    log::warn!("{}. Falling back on wireguard-go", error);
+    wireguard_nt::mark_unusable();
+    false
+}
+
+/// Creates the [`Tunnel`] used by a [`WireguardMonitor`], replacing the built-in WireGuard
+/// backends. This is useful for tests and for custom transports.
+///
+/// The monitor still sets up routes and checks connectivity through the tunnel, so the tunnel
+/// interface returned by [`Tunnel::get_interface_name`] has to exist.
+pub trait TunnelProvider: Send + Sync {
+    /// Creates and starts a tunnel using `config`.
+    fn open_tunnel(&self, config: &Config) -> std::result::Result<Box<dyn Tunnel>, TunnelError>;
 }
 
 /// Errors to be returned from WireGuard implementations, namely implementers of the Tunnel trait
@@ -798,6 +1203,10 @@ pub enum TunnelError {
     #[error(display = "Failed to set config of WireGuard tunnel")]
     SetConfigError,
 
+    /// Failed to rebind the sockets of a WireGuard tunnel to the new default route
+    #[error(display = "Failed to rebind the sockets of the WireGuard tunnel")]
+    RebindSocketsError,
+
     /// Failed to duplicate tunnel file descriptor for wireguard-go
     #[cfg(any(target_os = "linux", target_os = "macos", target_os = "android"))]
     #[error(display = "Failed to duplicate tunnel file descriptor for wireguard-go")]
@@ -822,6 +1231,11 @@ pub enum TunnelError {
     #[error(display = "Invalid tunnel interface name")]
     InterfaceNameError(#[error(source)] std::ffi::NulError),
 
+    /// Every interface name that the tunnel may use is taken by another program.
+    #[cfg(target_os = "linux")]
+    #[error(display = "All tunnel interface names are used by other programs")]
+    InterfaceNamesUnavailable,
+
     /// Failed to convert adapter alias to UTF-8.
     #[cfg(target_os = "windows")]
     #[error(display = "Failed to convert adapter alias")]
@@ -830,4 +1244,22 @@ pub enum TunnelError {
     /// Failure to set up logging
     #[error(display = "Failed to set up logging")]
     LoggingError(#[error(source)] logging::Error),
+
+    /// Failed to enable traffic shaping in the tunnel
+    #[error(display = "Failed to set up traffic shaping in the WireGuard tunnel")]
+    SetTrafficShapingError,
+
+    /// The tunnel implementation does not support traffic shaping.
+    #[error(display = "Traffic shaping is not supported by this WireGuard implementation")]
+    TrafficShapingUnsupported,
+
+    /// Failed to limit the throughput of the tunnel interface.
+    #[cfg(target_os = "linux")]
+    #[error(display = "Failed to set bandwidth limit")]
+    BandwidthLimit(#[error(source)] bandwidth_limit::Error),
+
+    /// Bandwidth limits are not supported on this platform.
+    #[cfg(not(target_os = "linux"))]
+    #[error(display = "Bandwidth limits are not supported by this WireGuard implementation")]
+    BandwidthLimitUnsupported,
 }
@@ -0,0 +1,78 @@
+//! Lets the tunnel roam to a new physical interface when the default route changes, e.g. when
+//! switching from Wi-Fi to Ethernet, instead of reconnecting.
+//!
+//! Whenever the interface of the default route changes, the sockets of the tunnel are reopened
+//! so that WireGuard sends from the new interface. The relay updates the endpoint of the peer once
+//! it receives an authenticated packet from the new address, so the session is kept. The route
+//! manager moves the route to the relay to the new default route by itself, and the firewall does
+//! not tie traffic to the relay to an interface, so neither has to be updated.
+
+use super::Tunnel;
+use crate::routing;
+use futures::{
+    future::{abortable, AbortHandle},
+    StreamExt,
+};
+use std::sync::{Mutex, Weak};
+use talpid_types::ErrorExt;
+
+/// Rebinds the sockets of a tunnel when the default interface changes. Stops when dropped.
+pub struct RoamingMonitor {
+    abort_handle: AbortHandle,
+}
+
+impl RoamingMonitor {
+    /// Starts monitoring the default route for as long as `tunnel` exists.
+    pub fn start(
+        tunnel: Weak<Mutex<Option<Box<dyn Tunnel>>>>,
+    ) -> Result<Self, routing::PlatformError> {
+        let mut route_changes = routing::listen_for_default_route_changes()?;
+        let (task, abort_handle) = abortable(async move {
+            let mut current_interface = default_interface().await;
+            while route_changes.next().await.is_some() {
+                // Keep the sockets while there is no default route, since it may come back on the
+                // same interface
+                let interface = match default_interface().await {
+                    Some(interface) if Some(&interface) != current_interface.as_ref() => interface,
+                    _ => continue,
+                };
+                let tunnel = match tunnel.upgrade() {
+                    Some(tunnel) => tunnel,
+                    None => break,
+                };
+                log::debug!("Default interface changed to {}. Roaming", interface);
+                let result = match tunnel.lock().unwrap().as_ref() {
+                    Some(tunnel) => tunnel.rebind_sockets(),
+                    None => break,
+                };
+                if let Err(error) = result {
+                    log::error!("{}", error.display_chain());
+                }
+                current_interface = Some(interface);
+            }
+        });
+        tokio::spawn(task);
+        Ok(RoamingMonitor { abort_handle })
+    }
+}
+
+impl Drop for RoamingMonitor {
+    fn drop(&mut self) {
+        self.abort_handle.abort();
+    }
+}
+
+/// Returns the interface of the default route, preferring IPv4.
+async fn default_interface() -> Option<String> {
+    let (v4_node, v6_node) = match routing::get_default_routes().await {
+        Ok(nodes) => nodes,
+        Err(error) => {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to obtain the default route")
+            );
+            return None;
+        }
+    };
+    v4_node.or(v6_node)?.get_device().map(str::to_owned)
+}
@@ -0,0 +1,409 @@
+//! Handling of IPv6 router advertisements on the tunnel interface.
+//!
+//! The tunnel interface is configured with the static IPv6 address that the relay assigned to
+//! this device. A relay may also advertise prefixes in router advertisements, e.g. while it is
+//! being renumbered. For every autonomous /64 prefix that is advertised, an address is derived
+//! from the prefix and the interface identifier of the static address, and added with the
+//! lifetimes of the prefix, so that the kernel deprecates and removes it when the prefix
+//! expires. If the static address is not covered by any preferred prefix, it is deprecated, so
+//! that new connections use the new addresses while long-lived connections keep working.
+//!
+//! Only advertisements that arrive through the tunnel are handled, and those are authenticated
+//! by WireGuard, so the usual checks of the hop limit are not needed.
+
+use futures::{
+    future::{abortable, AbortHandle},
+    StreamExt,
+};
+use netlink_packet_core::constants::*;
+use netlink_packet_route::{
+    rtnl::{address::nlas::Nla as AddressNla, AddressMessage, RtnlMessage, RT_SCOPE_UNIVERSE},
+    NetlinkMessage, NetlinkPayload,
+};
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use std::{
+    convert::TryInto,
+    io,
+    mem::MaybeUninit,
+    net::{Ipv6Addr, SocketAddrV6},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use talpid_types::ErrorExt;
+
+const ICMPV6_ROUTER_SOLICITATION: u8 = 133;
+const ICMPV6_ROUTER_ADVERTISEMENT: u8 = 134;
+const OPTION_PREFIX_INFORMATION: u8 = 3;
+const PREFIX_FLAG_AUTONOMOUS: u8 = 0x40;
+/// Length of the fixed part of a router advertisement.
+const ROUTER_ADVERTISEMENT_LEN: usize = 16;
+const PREFIX_INFORMATION_LEN: usize = 32;
+/// Length of the prefixes that addresses can be derived from, since the interface identifier of
+/// the static address is 64 bits.
+const AUTOCONF_PREFIX_LEN: u8 = 64;
+/// Lifetime that never expires.
+const INFINITE_LIFETIME: u32 = u32::MAX;
+
+const ALL_ROUTERS_MULTICAST: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 2);
+/// Number of router solicitations to send when the monitor is started.
+const MAX_ROUTER_SOLICITATIONS: u32 = 3;
+const ROUTER_SOLICITATION_INTERVAL: Duration = Duration::from_secs(4);
+/// How often the monitor checks whether it should stop.
+const RECV_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A prefix advertised by a router.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdvertisedPrefix {
+    pub prefix: Ipv6Addr,
+    pub prefix_len: u8,
+    /// Whether addresses may be derived from the prefix.
+    pub autonomous: bool,
+    /// Lifetime of the prefix in seconds.
+    pub valid_lifetime: u32,
+    /// Time in seconds that addresses derived from the prefix are preferred.
+    pub preferred_lifetime: u32,
+}
+
+impl AdvertisedPrefix {
+    /// Returns the address derived from this prefix and the interface identifier of `address`,
+    /// if addresses may be derived from the prefix.
+    fn derive_address(&self, address: Ipv6Addr) -> Option<Ipv6Addr> {
+        if !self.autonomous || self.prefix_len != AUTOCONF_PREFIX_LEN {
+            return None;
+        }
+        let prefix = u128::from(self.prefix) & !u128::from(u64::MAX);
+        let interface_id = u128::from(address) & u128::from(u64::MAX);
+        Some(Ipv6Addr::from(prefix | interface_id))
+    }
+}
+
+/// Parses an ICMPv6 router advertisement and returns the prefixes that it contains. Returns
+/// `None` if the message is not a valid router advertisement.
+pub fn parse_router_advertisement(message: &[u8]) -> Option<Vec<AdvertisedPrefix>> {
+    if message.len() < ROUTER_ADVERTISEMENT_LEN
+        || message[0] != ICMPV6_ROUTER_ADVERTISEMENT
+        || message[1] != 0
+    {
+        return None;
+    }
+
+    let mut prefixes = vec![];
+    let mut options = &message[ROUTER_ADVERTISEMENT_LEN..];
+    while !options.is_empty() {
+        if options.len() < 2 {
+            return None;
+        }
+        let option_len = usize::from(options[1]) * 8;
+        if option_len == 0 || option_len > options.len() {
+            return None;
+        }
+        let option = &options[..option_len];
+        if option[0] == OPTION_PREFIX_INFORMATION {
+            if option_len != PREFIX_INFORMATION_LEN {
+                return None;
+            }
+            let prefix_bytes: [u8; 16] = option[16..32].try_into().unwrap();
+            let prefix = AdvertisedPrefix {
+                prefix: Ipv6Addr::from(prefix_bytes),
+                prefix_len: option[2],
+                autonomous: option[3] & PREFIX_FLAG_AUTONOMOUS != 0,
+                valid_lifetime: u32::from_be_bytes(option[4..8].try_into().unwrap()),
+                preferred_lifetime: u32::from_be_bytes(option[8..12].try_into().unwrap()),
+            };
+            // A prefix that is preferred for longer than it is valid must be ignored
+            if prefix.preferred_lifetime <= prefix.valid_lifetime {
+                prefixes.push(prefix);
+            }
+        }
+        options = &options[option_len..];
+    }
+    Some(prefixes)
+}
+
+/// Listens for router advertisements on the tunnel interface and adds addresses for the
+/// advertised prefixes, until it is dropped.
+pub struct RouterAdvertisementMonitor {
+    stop: Arc<AtomicBool>,
+    route_abort_handle: AbortHandle,
+}
+
+impl RouterAdvertisementMonitor {
+    /// Starts listening for router advertisements on `interface` in a background thread.
+    /// `static_address` is the address that the interface is configured with, and
+    /// advertisements are only accepted from `gateway` or link-local addresses.
+    pub fn start(
+        runtime: tokio::runtime::Handle,
+        interface: String,
+        static_address: Ipv6Addr,
+        gateway: Ipv6Addr,
+    ) -> io::Result<Self> {
+        let interface_index = crate::linux::iface_index(&interface)
+            .map_err(|error| io::Error::new(io::ErrorKind::NotFound, error))?;
+
+        let socket = Socket::new(Domain::IPV6, Type::RAW, Some(Protocol::ICMPV6))?;
+        socket.bind_device(Some(interface.as_bytes()))?;
+        socket.set_multicast_if_v6(interface_index)?;
+        socket.set_multicast_hops_v6(255)?;
+        socket.set_read_timeout(Some(RECV_TIMEOUT))?;
+
+        let (connection, route_handle, _messages) = rtnetlink::new_connection()?;
+        let (connection, route_abort_handle) = abortable(connection);
+        runtime.spawn(connection);
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let addresses = AddressManager {
+            runtime,
+            route_handle,
+            interface_index,
+            static_address,
+            static_address_deprecated: false,
+        };
+        thread::Builder::new()
+            .name("router-advertisements".to_owned())
+            .spawn(move || listen(socket, gateway, addresses, thread_stop))?;
+
+        Ok(Self {
+            stop,
+            route_abort_handle,
+        })
+    }
+}
+
+impl Drop for RouterAdvertisementMonitor {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        self.route_abort_handle.abort();
+    }
+}
+
+fn listen(socket: Socket, gateway: Ipv6Addr, mut addresses: AddressManager, stop: Arc<AtomicBool>) {
+    let mut solicitations_sent = 0;
+    let mut last_solicitation: Option<Instant> = None;
+    let mut received_advertisement = false;
+    let mut buffer = [MaybeUninit::<u8>::uninit(); 1500];
+
+    while !stop.load(Ordering::Acquire) {
+        if !received_advertisement
+            && solicitations_sent < MAX_ROUTER_SOLICITATIONS
+            && last_solicitation
+                .map(|sent| sent.elapsed() >= ROUTER_SOLICITATION_INTERVAL)
+                .unwrap_or(true)
+        {
+            if let Err(error) = send_router_solicitation(&socket) {
+                log::debug!("Failed to send router solicitation: {}", error);
+            }
+            solicitations_sent += 1;
+            last_solicitation = Some(Instant::now());
+        }
+
+        let (len, source) = match socket.recv_from(&mut buffer) {
+            Ok(result) => result,
+            Err(error)
+                if error.kind() == io::ErrorKind::WouldBlock
+                    || error.kind() == io::ErrorKind::TimedOut
+                    || error.kind() == io::ErrorKind::Interrupted =>
+            {
+                continue
+            }
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to receive router advertisements")
+                );
+                return;
+            }
+        };
+        // SAFETY: `recv_from` initialized the first `len` bytes
+        let message = unsafe { &*(&buffer[..len] as *const [MaybeUninit<u8>] as *const [u8]) };
+
+        let source = match source.as_socket_ipv6() {
+            Some(source) => *source.ip(),
+            None => continue,
+        };
+        if source != gateway && !is_unicast_link_local(source) {
+            continue;
+        }
+        if let Some(prefixes) = parse_router_advertisement(message) {
+            received_advertisement = true;
+            addresses.apply(&prefixes);
+        }
+    }
+}
+
+fn send_router_solicitation(socket: &Socket) -> io::Result<()> {
+    // The kernel computes the checksum of ICMPv6 messages sent on raw sockets
+    let solicitation = [ICMPV6_ROUTER_SOLICITATION, 0, 0, 0, 0, 0, 0, 0];
+    let destination = SockAddr::from(SocketAddrV6::new(ALL_ROUTERS_MULTICAST, 0, 0, 0));
+    socket.send_to(&solicitation, &destination).map(|_| ())
+}
+
+fn is_unicast_link_local(address: Ipv6Addr) -> bool {
+    address.segments()[0] & 0xffc0 == 0xfe80
+}
+
+/// Adds and deprecates addresses on the tunnel interface.
+struct AddressManager {
+    runtime: tokio::runtime::Handle,
+    route_handle: rtnetlink::Handle,
+    interface_index: u32,
+    static_address: Ipv6Addr,
+    static_address_deprecated: bool,
+}
+
+impl AddressManager {
+    fn apply(&mut self, prefixes: &[AdvertisedPrefix]) {
+        // The static address is only deprecated if the router advertises other prefixes
+        let mut static_address_preferred = !prefixes.iter().any(|prefix| {
+            prefix.valid_lifetime > 0 && prefix.derive_address(self.static_address).is_some()
+        });
+
+        for prefix in prefixes {
+            let address = match prefix.derive_address(self.static_address) {
+                Some(address) => address,
+                None => continue,
+            };
+            if address == self.static_address {
+                static_address_preferred |= prefix.preferred_lifetime > 0;
+                continue;
+            }
+            let result = if prefix.valid_lifetime == 0 {
+                log::debug!("Removing tunnel address {} of expired prefix", address);
+                self.delete_address(address)
+            } else {
+                log::debug!(
+                    "Adding tunnel address {} with lifetimes {}s/{}s",
+                    address,
+                    prefix.preferred_lifetime,
+                    prefix.valid_lifetime
+                );
+                self.set_address(address, prefix.preferred_lifetime, prefix.valid_lifetime)
+            };
+            if let Err(error) = result {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg(&format!(
+                        "Failed to update tunnel address {}",
+                        address
+                    ))
+                );
+            }
+        }
+
+        if static_address_preferred == self.static_address_deprecated {
+            let preferred_lifetime = if static_address_preferred {
+                log::debug!("Restoring tunnel address {}", self.static_address);
+                INFINITE_LIFETIME
+            } else {
+                log::info!(
+                    "Deprecating tunnel address {}, since it is no longer advertised",
+                    self.static_address
+                );
+                0
+            };
+            match self.set_address(self.static_address, preferred_lifetime, INFINITE_LIFETIME) {
+                Ok(()) => self.static_address_deprecated = !static_address_preferred,
+                Err(error) => log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to update static tunnel address")
+                ),
+            }
+        }
+    }
+
+    fn set_address(
+        &self,
+        address: Ipv6Addr,
+        preferred_lifetime: u32,
+        valid_lifetime: u32,
+    ) -> Result<(), rtnetlink::Error> {
+        let mut message = self.address_message(address);
+        let mut cache_info = Vec::with_capacity(16);
+        cache_info.extend_from_slice(&preferred_lifetime.to_ne_bytes());
+        cache_info.extend_from_slice(&valid_lifetime.to_ne_bytes());
+        // The creation and update timestamps are set by the kernel
+        cache_info.extend_from_slice(&[0u8; 8]);
+        message.nlas.push(AddressNla::CacheInfo(cache_info));
+
+        let mut request = NetlinkMessage::from(RtnlMessage::NewAddress(message));
+        request.header.flags = NLM_F_REQUEST | NLM_F_ACK | NLM_F_CREATE | NLM_F_REPLACE;
+        self.request(request)
+    }
+
+    fn delete_address(&self, address: Ipv6Addr) -> Result<(), rtnetlink::Error> {
+        let mut request =
+            NetlinkMessage::from(RtnlMessage::DelAddress(self.address_message(address)));
+        request.header.flags = NLM_F_REQUEST | NLM_F_ACK;
+        self.request(request)
+    }
+
+    fn address_message(&self, address: Ipv6Addr) -> AddressMessage {
+        let mut message = AddressMessage::default();
+        message.header.family = libc::AF_INET6 as u8;
+        message.header.prefix_len = 128;
+        message.header.index = self.interface_index;
+        message.header.scope = RT_SCOPE_UNIVERSE;
+        message
+            .nlas
+            .push(AddressNla::Address(address.octets().to_vec()));
+        message
+    }
+
+    fn request(&self, request: NetlinkMessage<RtnlMessage>) -> Result<(), rtnetlink::Error> {
+        let mut route_handle = self.route_handle.clone();
+        self.runtime.block_on(async move {
+            let mut response = route_handle.request(request)?;
+            while let Some(message) = response.next().await {
+                if let NetlinkPayload::Error(error) = message.payload {
+                    return Err(rtnetlink::Error::NetlinkError(error));
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_router_advertisement() {
+        let mut message = vec![ICMPV6_ROUTER_ADVERTISEMENT, 0, 0, 0, 64, 0, 0x07, 0x08];
+        message.extend_from_slice(&[0; 8]);
+        // Prefix information for 2001:db8:1::/64 with lifetimes 1800s/3600s
+        message.extend_from_slice(&[OPTION_PREFIX_INFORMATION, 4, 64, 0xc0]);
+        message.extend_from_slice(&3600u32.to_be_bytes());
+        message.extend_from_slice(&1800u32.to_be_bytes());
+        message.extend_from_slice(&[0; 4]);
+        message.extend_from_slice(&"2001:db8:1::".parse::<Ipv6Addr>().unwrap().octets());
+        // Source link-layer address, which is ignored
+        message.extend_from_slice(&[1, 1, 0, 0, 0, 0, 0, 0]);
+
+        let prefixes = parse_router_advertisement(&message).unwrap();
+        assert_eq!(
+            prefixes,
+            vec![AdvertisedPrefix {
+                prefix: "2001:db8:1::".parse().unwrap(),
+                prefix_len: 64,
+                autonomous: true,
+                valid_lifetime: 3600,
+                preferred_lifetime: 1800,
+            }]
+        );
+        assert_eq!(
+            prefixes[0].derive_address("fc00:bbbb:bbbb:bb01::2:1234".parse().unwrap()),
+            Some("2001:db8:1::2:1234".parse().unwrap())
+        );
+
+        // Truncated option
+        assert_eq!(
+            parse_router_advertisement(&message[..message.len() - 4]),
+            None
+        );
+    }
+}
@@ -19,7 +19,9 @@ pub enum Error {
 /// Contains bytes sent and received through a tunnel
 #[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
 pub struct Stats {
+    /// Number of bytes sent to the peer
     pub tx_bytes: u64,
+    /// Number of bytes received from the peer
     pub rx_bytes: u64,
 }
 
@@ -27,6 +29,7 @@ pub struct Stats {
 pub type StatsMap = std::collections::HashMap<[u8; 32], Stats>;
 
 impl Stats {
+    /// Parses the stats of all peers from a userspace WireGuard config.
     pub fn parse_config_str(config: &str) -> Result<StatsMap, Error> {
         let mut map = StatsMap::new();
 
@@ -91,6 +94,7 @@ impl Stats {
     }
 
     #[cfg(target_os = "linux")]
+    /// Parses the stats of all peers from a netlink device message.
     pub fn parse_device_message(message: &DeviceMessage) -> StatsMap {
         let mut map = StatsMap::new();
 
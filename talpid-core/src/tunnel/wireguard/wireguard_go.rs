@@ -18,6 +18,7 @@ use std::{
     path::Path,
     pin::Pin,
 };
+use talpid_types::net::wireguard::{TrafficShapingOptions, TrafficShapingStats, WireguardBackend};
 #[cfg(windows)]
 use talpid_types::BoxedError;
 use zeroize::Zeroize;
@@ -78,6 +79,7 @@ impl WgGoTunnel {
         log_path: Option<&Path>,
         tun_provider: Arc<Mutex<TunProvider>>,
         routes: impl Iterator<Item = IpNetwork>,
+        #[cfg(not(target_os = "android"))] bind_interface: Option<u32>,
     ) -> Result<Self> {
         #[cfg_attr(not(target_os = "android"), allow(unused_mut))]
         let (mut tunnel_device, tunnel_fd) = Self::get_tunnel(tun_provider, config, routes)?;
@@ -95,6 +97,8 @@ impl WgGoTunnel {
                 mtu,
                 wg_config_str.as_ptr() as *const i8,
                 tunnel_fd,
+                #[cfg(not(target_os = "android"))]
+                bind_interface.unwrap_or(0),
                 Some(wg_go_logging_callback),
                 logging_context.0 as *mut libc::c_void,
             )
@@ -117,18 +121,25 @@ impl WgGoTunnel {
     pub fn start_tunnel(
         config: &Config,
         log_path: Option<&Path>,
+        bind_interface: Option<u32>,
         mut done_tx: futures::channel::mpsc::Sender<std::result::Result<(), BoxedError>>,
     ) -> Result<Self> {
         use talpid_types::ErrorExt;
 
-        let route_callback_handle = winnet::add_default_route_change_callback(
-            Some(WgGoTunnel::default_route_changed_callback),
-            (),
-        )
-        .ok();
-        if route_callback_handle.is_none() {
-            log::warn!("Failed to register default route callback");
-        }
+        // The sockets follow the default route unless they are bound to a forced interface
+        let route_callback_handle = if bind_interface.is_none() {
+            let handle = winnet::add_default_route_change_callback(
+                Some(WgGoTunnel::default_route_changed_callback),
+                (),
+            )
+            .ok();
+            if handle.is_none() {
+                log::warn!("Failed to register default route callback");
+            }
+            handle
+        } else {
+            None
+        };
 
         let wg_config_str = config.to_userspace_format();
         let iface_name: String = "Mullvad".to_string();
@@ -154,6 +165,15 @@ impl WgGoTunnel {
         };
         check_wg_status(handle)?;
 
+        if let Some(iface_idx) = bind_interface {
+            for family in [
+                winnet::WinNetAddrFamily::IPV4,
+                winnet::WinNetAddrFamily::IPV6,
+            ] {
+                unsafe { wgRebindTunnelSocket(family.to_windows_proto_enum(), iface_idx) };
+            }
+        }
+
         let actual_iface_name = {
             let actual_iface_name_c = unsafe { CStr::from_ptr(alias_ptr) };
             let actual_iface_name = actual_iface_name_c
@@ -211,28 +231,16 @@ impl WgGoTunnel {
     pub unsafe extern "system" fn default_route_changed_callback(
         event_type: winnet::WinNetDefaultRouteChangeEventType,
         address_family: winnet::WinNetAddrFamily,
-        default_route: winnet::WinNetDefaultRoute,
+        change: &winnet::WinNetDefaultRouteChange,
         _ctx: *mut libc::c_void,
     ) {
-        use windows_sys::Win32::NetworkManagement::{
-            IpHelper::ConvertInterfaceLuidToIndex, Ndis::NET_LUID_LH,
-        };
         use winnet::WinNetDefaultRouteChangeEventType::*;
 
         let iface_idx: u32 = match event_type {
             DefaultRouteChanged => {
-                let mut iface_idx = 0u32;
-                let iface_luid = NET_LUID_LH {
-                    Value: default_route.interface_luid,
-                };
-                let status =
-                    ConvertInterfaceLuidToIndex(&iface_luid as *const _, &mut iface_idx as *mut _);
-                if status != 0 {
-                    log::error!(
-                        "Failed to convert interface LUID to interface index: {}: {}",
-                        status,
-                        std::io::Error::last_os_error()
-                    );
+                let iface_idx = change.interface_details().index;
+                if iface_idx == 0 {
+                    log::error!("Failed to obtain the index of the default route interface");
                     return;
                 }
                 iface_idx
@@ -343,6 +351,10 @@ impl Tunnel for WgGoTunnel {
         self.interface_name.clone()
     }
 
+    fn backend(&self) -> Option<WireguardBackend> {
+        Some(WireguardBackend::WireguardGo)
+    }
+
     fn get_tunnel_stats(&self) -> Result<StatsMap> {
         let config_str = unsafe {
             let ptr = wgGetConfig(self.handle.unwrap());
@@ -375,6 +387,40 @@ impl Tunnel for WgGoTunnel {
         self.stop_tunnel()
     }
 
+    #[cfg(not(any(target_os = "android", target_os = "windows")))]
+    fn rebind_sockets(&self) -> Result<()> {
+        let status = unsafe { wgRebindTunnelSockets(self.handle.unwrap()) };
+        if status != 0 {
+            return Err(TunnelError::RebindSocketsError);
+        }
+        Ok(())
+    }
+
+    fn set_traffic_shaping(&self, options: &TrafficShapingOptions) -> Result<()> {
+        let status = unsafe {
+            wgSetTrafficShaping(self.handle.unwrap(), options.padding, options.dummy_traffic)
+        };
+        if status != 0 {
+            return Err(TunnelError::SetTrafficShapingError);
+        }
+        Ok(())
+    }
+
+    fn get_traffic_shaping_stats(&self) -> Option<TrafficShapingStats> {
+        let mut stats = TrafficShapingStats::default();
+        let status = unsafe {
+            wgGetTrafficShapingStats(
+                self.handle?,
+                &mut stats.padded_packets,
+                &mut stats.injected_packets,
+            )
+        };
+        if status != 0 {
+            return None;
+        }
+        Some(stats)
+    }
+
     fn set_config(
         &self,
         config: Config,
@@ -426,6 +472,7 @@ extern "C" {
         mtu: isize,
         settings: *const i8,
         fd: Fd,
+        bind_interface: u32,
         logging_callback: Option<LoggingCallback>,
         logging_context: *mut libc::c_void,
     ) -> i32;
@@ -460,6 +507,16 @@ extern "C" {
     // Sets the config of the WireGuard interface.
     fn wgSetConfig(handle: i32, settings: *const i8) -> i32;
 
+    // Pads packets and sends dummy traffic, or stops doing so if both are false.
+    fn wgSetTrafficShaping(handle: i32, padding: bool, dummy_traffic: bool) -> i32;
+
+    // Returns the number of padded and dummy packets. Fails if traffic shaping is not enabled.
+    fn wgGetTrafficShapingStats(
+        handle: i32,
+        padded_packets: *mut u64,
+        injected_packets: *mut u64,
+    ) -> i32;
+
     // Frees a pointer allocated by the go runtime - useful to free return value of wgGetConfig
     fn wgFreePtr(ptr: *mut c_void);
 
@@ -474,4 +531,8 @@ extern "C" {
     // Rebind tunnel socket when network interfaces change
     #[cfg(target_os = "windows")]
     fn wgRebindTunnelSocket(family: u16, interfaceIndex: u32);
+
+    // Reopens the tunnel sockets so that they are bound to the current default route.
+    #[cfg(not(any(target_os = "android", target_os = "windows")))]
+    fn wgRebindTunnelSockets(handle: i32) -> i32;
 }
@@ -1,5 +1,8 @@
 use super::{Config, Tunnel, TunnelError};
-use futures::future::{abortable, AbortHandle};
+use futures::{
+    future::{abortable, AbortHandle},
+    TryStreamExt,
+};
 use netlink_packet_core::{constants::*, NetlinkDeserializable};
 use netlink_packet_route::{
     rtnl::{
@@ -14,7 +17,7 @@ use netlink_proto::{
     sys::{protocols::NETLINK_GENERIC, SocketAddr},
     ConnectionHandle, Error as NetlinkError,
 };
-use std::{ffi::CString, net::IpAddr};
+use std::{collections::HashSet, ffi::CString, net::IpAddr};
 use tokio_stream::StreamExt;
 
 mod parsers;
@@ -80,12 +83,29 @@ pub enum Error {
     #[error(display = "Failed to delete device")]
     DeleteDevice(#[error(source)] rtnetlink::Error),
 
+    #[error(display = "Failed to list network interfaces")]
+    ListLinks(#[error(source)] rtnetlink::Error),
+
+    #[error(display = "Failed to set the alias of the device")]
+    SetAlias(#[error(source)] rtnetlink::Error),
+
+    #[error(display = "All tunnel interface names are used by other programs")]
+    InterfaceNamesUnavailable,
+
     #[error(display = "NetworkManager error")]
     NetworkManager(#[error(source)] nm_tunnel::Error),
 }
 
 pub(crate) const MULLVAD_INTERFACE_NAME: &str = "wg-mullvad";
 
+/// Alias given to every device created by the daemon. Devices with this alias are known to be
+/// ours, so any that exist before a tunnel is created were left behind and can be removed.
+const MULLVAD_INTERFACE_ALIAS: &str = "mullvad-tunnel";
+
+/// Number of alternative names, such as `wg-mullvad1`, to try if another program uses
+/// [`MULLVAD_INTERFACE_NAME`].
+const MAX_ALTERNATIVE_INTERFACE_NAMES: usize = 9;
+
 #[derive(Debug)]
 pub struct Handle {
     pub wg_handle: WireguardConnection,
@@ -153,6 +173,82 @@ impl Handle {
         result
     }
 
+    /// Creates a WireGuard device for the tunnel and returns its index and name. Devices left
+    /// behind by earlier tunnels are removed first. If the preferred name is used by another
+    /// program, an alternative name is picked instead.
+    pub async fn create_mullvad_device(&mut self, mtu: u32) -> Result<(u32, String), Error> {
+        let mut names_in_use = HashSet::new();
+        for link in self.get_links().await? {
+            let name = link.nlas.iter().find_map(|nla| match nla {
+                LinkNla::IfName(name) => Some(name.clone()),
+                _ => None,
+            });
+            let name = match name {
+                Some(name) => name,
+                None => continue,
+            };
+            if is_mullvad_device(&link) {
+                log::warn!("Removing stale tunnel interface {}", name);
+                self.delete_device(link.header.index).await?;
+            } else {
+                names_in_use.insert(name);
+            }
+        }
+
+        let name = std::iter::once(MULLVAD_INTERFACE_NAME.to_string())
+            .chain(
+                (1..=MAX_ALTERNATIVE_INTERFACE_NAMES)
+                    .map(|suffix| format!("{}{}", MULLVAD_INTERFACE_NAME, suffix)),
+            )
+            .find(|name| !names_in_use.contains(name))
+            .ok_or(Error::InterfaceNamesUnavailable)?;
+        if name != MULLVAD_INTERFACE_NAME {
+            log::warn!(
+                "{} is used by another program. Using {} instead",
+                MULLVAD_INTERFACE_NAME,
+                name
+            );
+        }
+
+        let index = self.create_device(name.clone(), mtu).await?;
+        if let Err(error) = self.set_alias(index, MULLVAD_INTERFACE_ALIAS).await {
+            if let Err(delete_error) = self.delete_device(index).await {
+                log::error!("Failed to remove WireGuard device: {}", delete_error);
+            }
+            return Err(error);
+        }
+        Ok((index, name))
+    }
+
+    async fn get_links(&mut self) -> Result<Vec<LinkMessage>, Error> {
+        self.route_handle
+            .link()
+            .get()
+            .execute()
+            .try_collect()
+            .await
+            .map_err(Error::ListLinks)
+    }
+
+    async fn set_alias(&mut self, index: u32, alias: &str) -> Result<(), Error> {
+        let mut message = LinkMessage::default();
+        message.header.index = index;
+        message.nlas.push(LinkNla::IfAlias(alias.to_string()));
+
+        let mut request = NetlinkMessage::from(RtnlMessage::SetLink(message));
+        request.header.flags = NLM_F_REQUEST | NLM_F_ACK;
+
+        let mut response = self
+            .route_handle
+            .request(request)
+            .map_err(Error::SetAlias)?;
+        while let Some(message) = response.next().await {
+            consume_netlink_error(message, Error::SetAlias)?;
+        }
+
+        Ok(())
+    }
+
     // create a wireguard device with the given name.
     pub async fn create_device(&mut self, name: String, mtu: u32) -> Result<u32, Error> {
         let mut message = LinkMessage::default();
@@ -308,6 +404,23 @@ impl WireguardConnection {
     }
 }
 
+/// Returns whether `link` is a WireGuard device that was created by the daemon.
+fn is_mullvad_device(link: &LinkMessage) -> bool {
+    let is_wireguard = link.nlas.iter().any(|nla| match nla {
+        LinkNla::Info(infos) => infos.iter().any(|info| match info {
+            Info::Kind(InfoKind::Wireguard) => true,
+            Info::Kind(InfoKind::Other(kind)) => kind == "wireguard",
+            _ => false,
+        }),
+        _ => false,
+    });
+    let has_alias = link
+        .nlas
+        .iter()
+        .any(|nla| matches!(nla, LinkNla::IfAlias(alias) if alias == MULLVAD_INTERFACE_ALIAS));
+    is_wireguard && has_alias
+}
+
 fn consume_netlink_error<
     I: NetlinkDeserializable + Clone + Eq + std::fmt::Debug,
     F: Fn(rtnetlink::Error) -> Error,
@@ -348,3 +461,34 @@ fn add_ip_addr_message(if_index: u32, addr: IpAddr) -> AddressMessage {
 
     message
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn wireguard_link(alias: Option<&str>) -> LinkMessage {
+        let mut link = LinkMessage::default();
+        link.nlas.push(LinkNla::IfName("wg-mullvad".to_string()));
+        link.nlas
+            .push(LinkNla::Info(vec![Info::Kind(InfoKind::Wireguard)]));
+        if let Some(alias) = alias {
+            link.nlas.push(LinkNla::IfAlias(alias.to_string()));
+        }
+        link
+    }
+
+    #[test]
+    fn test_is_mullvad_device() {
+        assert!(is_mullvad_device(&wireguard_link(Some(
+            MULLVAD_INTERFACE_ALIAS
+        ))));
+        assert!(!is_mullvad_device(&wireguard_link(None)));
+        assert!(!is_mullvad_device(&wireguard_link(Some("wg-quick"))));
+
+        let mut tun_link = LinkMessage::default();
+        tun_link
+            .nlas
+            .push(LinkNla::IfAlias(MULLVAD_INTERFACE_ALIAS.to_string()));
+        assert!(!is_mullvad_device(&tun_link));
+    }
+}
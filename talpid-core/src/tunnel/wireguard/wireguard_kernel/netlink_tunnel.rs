@@ -1,15 +1,17 @@
 use std::pin::Pin;
 
 use futures::Future;
+use talpid_types::net::wireguard::WireguardBackend;
 
 use super::{
     super::stats::{Stats, StatsMap},
     wg_message::DeviceNla,
-    Config, Error, Handle, Tunnel, TunnelError, MULLVAD_INTERFACE_NAME,
+    Config, Error, Handle, Tunnel, TunnelError,
 };
 
 pub struct NetlinkTunnel {
     interface_index: u32,
+    interface_name: String,
     netlink_connections: Handle,
     tokio_handle: tokio::runtime::Handle,
 }
@@ -18,12 +20,13 @@ impl NetlinkTunnel {
     pub fn new(tokio_handle: tokio::runtime::Handle, config: &Config) -> Result<Self, Error> {
         tokio_handle.clone().block_on(async {
             let mut netlink_connections = Handle::connect().await?;
-            let interface_index = netlink_connections
-                .create_device(MULLVAD_INTERFACE_NAME.to_string(), config.mtu as u32)
+            let (interface_index, interface_name) = netlink_connections
+                .create_mullvad_device(config.mtu as u32)
                 .await?;
 
             let mut tunnel = Self {
                 interface_index,
+                interface_name,
                 netlink_connections,
                 tokio_handle,
             };
@@ -78,17 +81,22 @@ impl Tunnel for NetlinkTunnel {
         match result {
             Ok(name) => name.to_string_lossy().to_string(),
             Err(err) => {
-                log::error!("Failed to deduce interface name at runtime, will attempt to use the default name. {}", err);
-                MULLVAD_INTERFACE_NAME.to_string()
+                log::error!("Failed to deduce interface name at runtime, will attempt to use the name it was created with. {}", err);
+                self.interface_name.clone()
             }
         }
     }
 
+    fn backend(&self) -> Option<WireguardBackend> {
+        Some(WireguardBackend::Kernel)
+    }
+
     fn stop(self: Box<Self>) -> std::result::Result<(), TunnelError> {
         let Self {
             mut netlink_connections,
             interface_index,
             tokio_handle,
+            ..
         } = *self;
         tokio_handle.block_on(async move {
             if let Err(err) = netlink_connections.delete_device(interface_index).await {
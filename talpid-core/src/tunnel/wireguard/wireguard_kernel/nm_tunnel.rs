@@ -11,6 +11,7 @@ use talpid_dbus::{
         WireguardTunnel,
     },
 };
+use talpid_types::net::wireguard::WireguardBackend;
 
 #[derive(err_derive::Error, Debug)]
 pub enum Error {
@@ -66,6 +67,10 @@ impl Tunnel for NetworkManagerTunnel {
         self.interface_name.clone()
     }
 
+    fn backend(&self) -> Option<WireguardBackend> {
+        Some(WireguardBackend::Kernel)
+    }
+
     fn stop(mut self: Box<Self>) -> std::result::Result<(), TunnelError> {
         if let Some(tunnel) = self.tunnel.take() {
             if let Err(err) = self.network_manager.remove_tunnel(tunnel) {
@@ -20,9 +20,12 @@ use std::{
     path::Path,
     pin::Pin,
     ptr,
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
 };
-use talpid_types::{BoxedError, ErrorExt};
+use talpid_types::{net::wireguard::WireguardBackend, BoxedError, ErrorExt};
 use widestring::{U16CStr, U16CString};
 use windows_sys::{
     core::GUID,
@@ -42,8 +45,12 @@ lazy_static! {
     static ref WG_NT_DLL: Mutex<Option<Arc<WgNtDll>>> = Mutex::new(None);
     static ref ADAPTER_TYPE: U16CString = U16CString::from_str("Mullvad").unwrap();
     static ref ADAPTER_ALIAS: U16CString = U16CString::from_str("Mullvad").unwrap();
+    static ref PROBE_ADAPTER_ALIAS: U16CString = U16CString::from_str("Mullvad Probe").unwrap();
 }
 
+/// Set once WireGuardNT has been found not to work on this system.
+static UNUSABLE: AtomicBool = AtomicBool::new(false);
+
 const ADAPTER_GUID: GUID = GUID {
     data1: 0x514a3988,
     data2: 0x9716,
@@ -801,6 +808,26 @@ impl Drop for WgNtDll {
     }
 }
 
+/// Returns whether WireGuardNT may be used. This is false once it has failed in a way that will
+/// not resolve itself until the daemon is restarted.
+pub fn is_usable() -> bool {
+    !UNUSABLE.load(Ordering::SeqCst)
+}
+
+/// Makes wireguard-go be used instead of WireGuardNT from now on.
+pub fn mark_unusable() {
+    UNUSABLE.store(true, Ordering::SeqCst);
+}
+
+/// Loads the driver and creates, then removes, a temporary adapter.
+pub fn probe(resource_dir: &Path) -> Result<()> {
+    let dll = load_wg_nt_dll(resource_dir)?;
+    let adapter = WgNtAdapter::create(dll, &*PROBE_ADAPTER_ALIAS, &*ADAPTER_TYPE, None)
+        .map_err(Error::CreateTunnelDeviceError)?;
+    drop(adapter);
+    Ok(())
+}
+
 fn load_wg_nt_dll(resource_dir: &Path) -> Result<Arc<WgNtDll>> {
     let mut dll = (*WG_NT_DLL).lock().expect("WireGuardNT mutex poisoned");
     match &*dll {
@@ -935,6 +962,10 @@ impl Tunnel for WgNtTunnel {
         self.interface_name.clone()
     }
 
+    fn backend(&self) -> Option<WireguardBackend> {
+        Some(WireguardBackend::WireguardNt)
+    }
+
     fn get_tunnel_stats(&self) -> std::result::Result<StatsMap, super::TunnelError> {
         if let Some(ref device) = &*self.device.lock().unwrap() {
             let mut map = StatsMap::new();
@@ -1015,12 +1046,15 @@ mod tests {
                     allowed_ips: vec!["1.3.3.0/24".parse().unwrap()],
                     endpoint: "1.2.3.4:1234".parse().unwrap(),
                     psk: None,
+                    psk_required: false,
                 }],
                 ipv4_gateway: "0.0.0.0".parse().unwrap(),
                 ipv6_gateway: None,
                 mtu: 0,
                 use_wireguard_nt: true,
                 obfuscator_config: None,
+                traffic_shaping: Default::default(),
+                bandwidth_limit: Default::default(),
             }
         };
         static ref WG_STRUCT_CONFIG: Interface = Interface {
@@ -0,0 +1,157 @@
+//! Health checks for the bridge that an OpenVPN tunnel is proxied through.
+//!
+//! OpenVPN only notices that a bridge has stopped forwarding traffic once its own keepalive times
+//! out, which takes a minute or more. Instead, the monitor makes TCP connections to the bridge at
+//! regular intervals, and reports it as dead after a number of consecutive failures. While
+//! connecting, probes are frequent, so that a dead bridge is quickly abandoned in favor of the
+//! next candidate. While connected, probes are sparse to keep the overhead low.
+
+use futures::{
+    channel::oneshot,
+    future::{self, Fuse},
+    FutureExt,
+};
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use std::{io, net::SocketAddr, sync::mpsc, thread, time::Duration};
+use talpid_types::{
+    net::{openvpn, TransportProtocol, TunnelParameters},
+    ErrorExt,
+};
+
+/// How long to wait for a probe connection to be established.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(4);
+
+/// Determines how often the bridge is probed, and how many probes may fail in a row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeSchedule {
+    /// Frequent probes, used while the tunnel is being established.
+    Connecting,
+    /// Infrequent probes, used while the tunnel is up.
+    Connected,
+}
+
+impl ProbeSchedule {
+    fn interval(self) -> Duration {
+        match self {
+            ProbeSchedule::Connecting => Duration::from_secs(2),
+            ProbeSchedule::Connected => Duration::from_secs(30),
+        }
+    }
+
+    fn max_failures(self) -> u32 {
+        match self {
+            ProbeSchedule::Connecting => 2,
+            ProbeSchedule::Connected => 3,
+        }
+    }
+}
+
+/// Probes a bridge in a background thread. The thread stops when the monitor is dropped.
+pub struct BridgeMonitor {
+    _stop_tx: mpsc::Sender<()>,
+    dead_rx: Fuse<oneshot::Receiver<SocketAddr>>,
+}
+
+impl BridgeMonitor {
+    /// Starts probing the bridge used by `params`. Returns `None` if the tunnel does not use a
+    /// bridge that can be probed.
+    pub fn start(params: &TunnelParameters, schedule: ProbeSchedule) -> Option<Self> {
+        let bridge = probe_target(params)?;
+
+        let (stop_tx, stop_rx) = mpsc::channel();
+        let (dead_tx, dead_rx) = oneshot::channel();
+        let result = thread::Builder::new()
+            .name("bridge-monitor".to_owned())
+            .spawn(move || {
+                if run_probes(bridge, schedule, stop_rx) {
+                    let _ = dead_tx.send(bridge);
+                }
+            });
+        if let Err(error) = result {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to start bridge monitor")
+            );
+            return None;
+        }
+
+        Some(Self {
+            _stop_tx: stop_tx,
+            dead_rx: dead_rx.fuse(),
+        })
+    }
+
+    /// Completes when the bridge monitored by `monitor` has been found to be dead. Never
+    /// completes if there is no monitor.
+    pub async fn wait_for_dead_bridge(monitor: &mut Option<Self>) {
+        if let Some(monitor) = monitor {
+            if let Ok(bridge) = (&mut monitor.dead_rx).await {
+                log::warn!("Bridge {} is not responding", bridge);
+                return;
+            }
+        }
+        future::pending::<()>().await
+    }
+}
+
+/// Returns the address of the bridge to probe, if any.
+///
+/// On Windows, the firewall only lets the process that runs the proxy client reach the bridge,
+/// so only bridges whose client runs in the daemon can be probed there.
+fn probe_target(params: &TunnelParameters) -> Option<SocketAddr> {
+    let proxy = match params {
+        TunnelParameters::OpenVpn(params) => params.proxy.as_ref()?,
+        TunnelParameters::Wireguard(_) => return None,
+    };
+    if cfg!(windows) && !matches!(proxy, openvpn::ProxySettings::Shadowsocks(_)) {
+        return None;
+    }
+    let endpoint = proxy.get_endpoint().endpoint;
+    if endpoint.protocol != TransportProtocol::Tcp {
+        return None;
+    }
+    Some(endpoint.address)
+}
+
+/// Probes `bridge` until it has failed too many times in a row, or until the monitor is
+/// dropped. Returns whether the bridge was found to be dead.
+fn run_probes(bridge: SocketAddr, schedule: ProbeSchedule, stop_rx: mpsc::Receiver<()>) -> bool {
+    let mut failures = 0;
+    loop {
+        match probe(bridge) {
+            Ok(()) => failures = 0,
+            Err(error) => {
+                failures += 1;
+                log::debug!(
+                    "{}",
+                    error.display_chain_with_msg(&format!(
+                        "Bridge probe {}/{} to {} failed",
+                        failures,
+                        schedule.max_failures(),
+                        bridge
+                    ))
+                );
+                if failures >= schedule.max_failures() {
+                    return true;
+                }
+            }
+        }
+
+        match stop_rx.recv_timeout(schedule.interval()) {
+            Err(mpsc::RecvTimeoutError::Timeout) => (),
+            _ => return false,
+        }
+    }
+}
+
+fn probe(bridge: SocketAddr) -> io::Result<()> {
+    let socket = Socket::new(
+        Domain::for_address(bridge),
+        Type::STREAM,
+        Some(Protocol::TCP),
+    )?;
+    // Like the traffic of the proxy client, the probe must bypass the tunnel
+    #[cfg(target_os = "linux")]
+    socket.set_mark(crate::linux::TUNNEL_FW_MARK)?;
+    socket.connect_timeout(&SockAddr::from(bridge), PROBE_TIMEOUT)
+}
@@ -1,27 +1,35 @@
 use super::{
-    AfterDisconnect, ConnectingState, DisconnectingState, ErrorState, EventConsequence,
-    EventResult, SharedTunnelStateValues, TunnelCommand, TunnelCommandReceiver, TunnelState,
-    TunnelStateTransition, TunnelStateWrapper,
+    bridge_monitor::{BridgeMonitor, ProbeSchedule},
+    wait_for_tunnel_monitor_exit, AfterDisconnect, ConnectingState, DisconnectingState, ErrorState,
+    EventConsequence, EventResult, SharedTunnelStateValues, TunnelCommand, TunnelCommandReceiver,
+    TunnelState, TunnelStateTransition, TunnelStateWrapper, TUNNEL_MONITOR_EXIT_TIMEOUT,
 };
 use crate::{
     firewall::FirewallPolicy,
-    tunnel::{TunnelEvent, TunnelMetadata},
+    tunnel::{wireguard::TrafficShapingHandle, TunnelEvent, TunnelMetadata},
 };
 use cfg_if::cfg_if;
 use futures::{
     channel::{mpsc, oneshot},
     stream::Fuse,
-    StreamExt,
+    FutureExt, StreamExt,
 };
-use std::net::IpAddr;
+use std::{net::IpAddr, time::Instant};
 use talpid_types::{
-    net::TunnelParameters,
-    tunnel::{ErrorStateCause, FirewallPolicyError},
+    net::{self, AllowedTunnelTraffic, TunnelParameters, VpnCoexistence},
+    performance::Operation,
+    tunnel::{DisconnectCause, ErrorStateCause, FirewallPolicyError},
     BoxedError, ErrorExt,
 };
 
+#[cfg(not(target_os = "android"))]
+use super::route_takeover::RouteTakeoverMonitor;
+#[cfg(not(target_os = "android"))]
+use crate::mpsc::Sender;
 #[cfg(windows)]
 use crate::tunnel::TunnelMonitor;
+#[cfg(not(target_os = "android"))]
+use talpid_types::net::route_takeover::{CompetingVpn, RouteTakeoverPolicy};
 
 use super::connecting_state::TunnelCloseEvent;
 
@@ -34,6 +42,7 @@ pub struct ConnectedStateBootstrap {
     pub tunnel_parameters: TunnelParameters,
     pub tunnel_close_event: TunnelCloseEvent,
     pub tunnel_close_tx: oneshot::Sender<()>,
+    pub traffic_shaping: TrafficShapingHandle,
 }
 
 /// The tunnel is up and working.
@@ -43,24 +52,44 @@ pub struct ConnectedState {
     tunnel_parameters: TunnelParameters,
     tunnel_close_event: TunnelCloseEvent,
     tunnel_close_tx: oneshot::Sender<()>,
+    traffic_shaping: TrafficShapingHandle,
+    bridge_monitor: Option<BridgeMonitor>,
+    /// Set when the tunnel event channel has been closed, to the time when the tunnel monitor is
+    /// given up on unless it has exited.
+    tunnel_monitor_exit_deadline: Option<Instant>,
+    /// Watches for other VPNs that take over the default route.
+    #[cfg(not(target_os = "android"))]
+    route_takeover_monitor: Option<RouteTakeoverMonitor>,
 }
 
 impl ConnectedState {
     fn from(bootstrap: ConnectedStateBootstrap) -> Self {
+        let bridge_monitor =
+            BridgeMonitor::start(&bootstrap.tunnel_parameters, ProbeSchedule::Connected);
         ConnectedState {
             metadata: bootstrap.metadata,
             tunnel_events: bootstrap.tunnel_events,
             tunnel_parameters: bootstrap.tunnel_parameters,
             tunnel_close_event: bootstrap.tunnel_close_event,
             tunnel_close_tx: bootstrap.tunnel_close_tx,
+            traffic_shaping: bootstrap.traffic_shaping,
+            bridge_monitor,
+            tunnel_monitor_exit_deadline: None,
+            #[cfg(not(target_os = "android"))]
+            route_takeover_monitor: None,
         }
     }
 
+    pub(super) fn tunnel_metadata(&self) -> &TunnelMetadata {
+        &self.metadata
+    }
+
     fn set_firewall_policy(
         &self,
         shared_values: &mut SharedTunnelStateValues,
     ) -> Result<(), FirewallPolicyError> {
         let policy = self.get_firewall_policy(shared_values);
+        let _watchdog = shared_values.watchdog(Operation::ApplyFirewallPolicy);
         shared_values
             .firewall
             .apply_policy(policy)
@@ -81,27 +110,68 @@ impl ConnectedState {
             })
     }
 
+    /// Allows only DNS requests to the DNS servers inside the tunnel. This is applied while DNS
+    /// is being configured, so that no other traffic uses the tunnel before DNS has been set, and
+    /// so that nothing has leaked if setting DNS fails.
+    fn set_dns_only_firewall_policy(
+        &self,
+        shared_values: &mut SharedTunnelStateValues,
+    ) -> Result<(), FirewallPolicyError> {
+        let dns_servers = self.get_dns_servers(shared_values);
+        ConnectingState::set_firewall_policy(
+            shared_values,
+            &self.tunnel_parameters,
+            &Some(self.metadata.clone()),
+            AllowedTunnelTraffic::Dns(dns_servers),
+        )
+    }
+
     #[allow(unused_variables)]
     fn get_dns_servers(&self, shared_values: &SharedTunnelStateValues) -> Vec<IpAddr> {
         #[cfg(not(target_os = "android"))]
         if let Some(ref servers) = shared_values.dns_servers {
             servers.clone()
         } else {
-            let mut dns_ips = vec![self.metadata.ipv4_gateway.into()];
-            if let Some(ipv6_gateway) = self.metadata.ipv6_gateway {
-                dns_ips.push(ipv6_gateway.into());
-            };
-            dns_ips
+            self.get_gateway_dns_servers()
         }
         #[cfg(target_os = "android")]
-        {
-            let mut dns_ips = vec![];
+        self.get_gateway_dns_servers()
+    }
+
+    /// The tunnel gateways are used as DNS servers by default. The IPv4 gateway is skipped if the
+    /// tunnel only has IPv6 addresses, since it cannot be reached then.
+    fn get_gateway_dns_servers(&self) -> Vec<IpAddr> {
+        let mut dns_ips = vec![];
+        if self.metadata.ips.iter().any(|ip| ip.is_ipv4()) {
             dns_ips.push(self.metadata.ipv4_gateway.into());
-            if let Some(ipv6_gateway) = self.metadata.ipv6_gateway {
-                dns_ips.push(ipv6_gateway.into());
-            };
-            dns_ips
         }
+        if let Some(ipv6_gateway) = self.metadata.ipv6_gateway {
+            dns_ips.push(ipv6_gateway.into());
+        };
+        dns_ips
+    }
+
+    /// If only some networks are routed through a WireGuard tunnel, all other networks are
+    /// exempt from blocking and kept out of the tunnel, the same way as the networks of another
+    /// VPN.
+    fn get_vpn_coexistence(&self, shared_values: &SharedTunnelStateValues) -> VpnCoexistence {
+        let mut vpn_coexistence = shared_values.vpn_coexistence.clone();
+        let allowed_ips = match &self.tunnel_parameters {
+            TunnelParameters::Wireguard(params) => params.options.allowed_ips.as_ref(),
+            TunnelParameters::OpenVpn(_) => None,
+        };
+        if let Some(allowed_ips) = allowed_ips {
+            let mut tunnel_networks = allowed_ips.clone();
+            tunnel_networks.extend(
+                self.get_gateway_dns_servers()
+                    .into_iter()
+                    .map(ipnetwork::IpNetwork::from),
+            );
+            vpn_coexistence
+                .networks
+                .extend(net::complement_networks(&tunnel_networks));
+        }
+        vpn_coexistence
     }
 
     fn get_firewall_policy(&self, shared_values: &SharedTunnelStateValues) -> FirewallPolicy {
@@ -111,6 +181,8 @@ impl ConnectedState {
             allow_lan: shared_values.allow_lan,
             #[cfg(not(target_os = "android"))]
             dns_servers: self.get_dns_servers(shared_values),
+            vpn_coexistence: self.get_vpn_coexistence(shared_values),
+            blocked_tunnel_protocols: shared_values.blocked_tunnel_protocols,
             #[cfg(windows)]
             relay_client: TunnelMonitor::get_relay_client(
                 &shared_values.resource_dir,
@@ -132,6 +204,16 @@ impl ConnectedState {
             })
             .collect::<Vec<_>>();
 
+        // Queries that still reach the filtering resolver, e.g. because the system has not yet
+        // picked up the new DNS configuration, are answered by the tunnel DNS
+        #[cfg(target_os = "macos")]
+        shared_values
+            .filtering_resolver
+            .set_config(crate::resolver::ResolverConfig::Forwarding {
+                upstreams: dns_ips.clone(),
+            });
+
+        let _watchdog = shared_values.watchdog(Operation::SetDns);
         shared_values
             .dns_monitor
             .set(&self.metadata.interface, &dns_ips)
@@ -140,14 +222,38 @@ impl ConnectedState {
         Ok(())
     }
 
+    #[cfg(windows)]
+    fn set_interface_metric(&self, shared_values: &mut SharedTunnelStateValues) {
+        let result = crate::windows::luid_from_alias(&self.metadata.interface)
+            .map_err(crate::routing::Error::SetInterfaceMetric)
+            .and_then(|luid| {
+                shared_values.runtime.block_on(
+                    shared_values
+                        .route_manager
+                        .set_tunnel_interface_metric(luid),
+                )
+            });
+        if let Err(error) = result {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to set the metric of the tunnel interface")
+            );
+        }
+    }
+
     fn reset_dns(shared_values: &mut SharedTunnelStateValues) {
+        let _watchdog = shared_values.watchdog(Operation::ResetDns);
         if let Err(error) = shared_values.dns_monitor.reset() {
             log::error!("{}", error.display_chain_with_msg("Unable to reset DNS"));
         }
     }
 
     fn reset_routes(shared_values: &mut SharedTunnelStateValues) {
-        if let Err(error) = shared_values.route_manager.clear_routes() {
+        let result = {
+            let _watchdog = shared_values.watchdog(Operation::ClearRoutes);
+            shared_values.route_manager.clear_routes()
+        };
+        if let Err(error) = result {
             log::error!("{}", error.display_chain_with_msg("Failed to clear routes"));
         }
         #[cfg(target_os = "linux")]
@@ -167,6 +273,8 @@ impl ConnectedState {
         shared_values: &mut SharedTunnelStateValues,
         after_disconnect: AfterDisconnect,
     ) -> EventConsequence {
+        #[cfg(all(feature = "packet-capture", target_os = "linux"))]
+        shared_values.packet_capture.set_interface(None);
         Self::reset_dns(shared_values);
         Self::reset_routes(shared_values);
 
@@ -246,6 +354,105 @@ impl ConnectedState {
                 shared_values.block_when_disconnected = block_when_disconnected;
                 SameState(self.into())
             }
+            Some(TunnelCommand::FlushDnsCache(flush_dns_cache)) => {
+                shared_values.dns_monitor.set_flush_cache(flush_dns_cache);
+                SameState(self.into())
+            }
+            Some(TunnelCommand::RecoveryAllowlist(recovery_allowlist)) => {
+                shared_values.recovery_allowlist = recovery_allowlist;
+                SameState(self.into())
+            }
+            Some(TunnelCommand::VpnCoexistence(vpn_coexistence)) => {
+                if shared_values.vpn_coexistence != vpn_coexistence {
+                    #[cfg(any(target_os = "macos", windows))]
+                    if shared_values.vpn_coexistence.networks != vpn_coexistence.networks {
+                        // The routes for the networks are only added when connecting
+                        shared_values.vpn_coexistence = vpn_coexistence;
+                        return self.disconnect(shared_values, AfterDisconnect::Reconnect(0));
+                    }
+                    shared_values.vpn_coexistence = vpn_coexistence;
+                    match self.set_firewall_policy(shared_values) {
+                        Ok(()) => SameState(self.into()),
+                        Err(error) => self.disconnect(
+                            shared_values,
+                            AfterDisconnect::Block(ErrorStateCause::SetFirewallPolicyError(error)),
+                        ),
+                    }
+                } else {
+                    SameState(self.into())
+                }
+            }
+            Some(TunnelCommand::BlockedTunnelProtocols(blocked_tunnel_protocols)) => {
+                if shared_values.blocked_tunnel_protocols != blocked_tunnel_protocols {
+                    shared_values.blocked_tunnel_protocols = blocked_tunnel_protocols;
+                    match self.set_firewall_policy(shared_values) {
+                        Ok(()) => SameState(self.into()),
+                        Err(error) => self.disconnect(
+                            shared_values,
+                            AfterDisconnect::Block(ErrorStateCause::SetFirewallPolicyError(error)),
+                        ),
+                    }
+                } else {
+                    SameState(self.into())
+                }
+            }
+            Some(TunnelCommand::TrafficShaping(options)) => {
+                match self.traffic_shaping.set(&options) {
+                    Ok(()) => SameState(self.into()),
+                    Err(error) => {
+                        log::warn!(
+                            "{}",
+                            error.display_chain_with_msg(
+                                "Failed to change traffic shaping in place. Reconnecting"
+                            )
+                        );
+                        self.disconnect(shared_values, AfterDisconnect::Reconnect(0))
+                    }
+                }
+            }
+            Some(TunnelCommand::ReconnectLimits(reconnect_limits)) => {
+                shared_values.reconnect_budget.set_limits(reconnect_limits);
+                SameState(self.into())
+            }
+            Some(TunnelCommand::RouteTakeoverPolicy(policy)) => {
+                shared_values.route_takeover_policy = policy;
+                SameState(self.into())
+            }
+            Some(TunnelCommand::ErrorStatePolicy(policy)) => {
+                shared_values.error_state_policy = policy;
+                SameState(self.into())
+            }
+            Some(TunnelCommand::SetForcedInterface(forced_interface)) => {
+                if shared_values.set_forced_interface(forced_interface) {
+                    self.disconnect(shared_values, AfterDisconnect::Reconnect(0))
+                } else {
+                    SameState(self.into())
+                }
+            }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::ForcedInterfaceChanged(index)) => {
+                if index.is_some() {
+                    // The interface was recreated, so the sockets are bound to a stale index
+                    self.disconnect(shared_values, AfterDisconnect::Reconnect(0))
+                } else {
+                    self.disconnect(
+                        shared_values,
+                        AfterDisconnect::Block(ErrorStateCause::ForcedInterfaceUnavailable),
+                    )
+                }
+            }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::DefaultInterfaceChanged) => SameState(self.into()),
+            Some(TunnelCommand::HealthCheck(tx)) => {
+                let _ = tx.send(shared_values.health_check(Some(true)));
+                SameState(self.into())
+            }
+            Some(TunnelCommand::GetSystemState(tx)) => {
+                let mut system_state = shared_values.system_state();
+                system_state.traffic_shaping = self.traffic_shaping.get();
+                let _ = tx.send(system_state);
+                SameState(self.into())
+            }
             Some(TunnelCommand::IsOffline(is_offline)) => {
                 shared_values.is_offline = is_offline;
                 if is_offline {
@@ -260,12 +467,21 @@ impl ConnectedState {
             Some(TunnelCommand::Connect) => {
                 self.disconnect(shared_values, AfterDisconnect::Reconnect(0))
             }
-            Some(TunnelCommand::Disconnect) | None => {
-                self.disconnect(shared_values, AfterDisconnect::Nothing)
+            Some(TunnelCommand::Disconnect(cause)) => {
+                self.disconnect(shared_values, AfterDisconnect::Nothing(cause))
             }
+            None => self.disconnect(
+                shared_values,
+                AfterDisconnect::Nothing(DisconnectCause::Shutdown),
+            ),
             Some(TunnelCommand::Block(reason)) => {
                 self.disconnect(shared_values, AfterDisconnect::Block(reason))
             }
+            Some(TunnelCommand::Pause) => {
+                let tunnel_parameters = self.tunnel_parameters.clone();
+                self.disconnect(shared_values, AfterDisconnect::Pause(tunnel_parameters))
+            }
+            Some(TunnelCommand::Resume) => SameState(self.into()),
             #[cfg(target_os = "android")]
             Some(TunnelCommand::BypassSocket(fd, done_tx)) => {
                 shared_values.bypass_socket(fd, done_tx);
@@ -276,21 +492,100 @@ impl ConnectedState {
                 shared_values.split_tunnel.set_paths(&paths, result_tx);
                 SameState(self.into())
             }
+            #[cfg(windows)]
+            Some(TunnelCommand::ReinstallSplitTunnelDriver(result_tx)) => {
+                shared_values.split_tunnel.reinstall_driver(result_tx);
+                SameState(self.into())
+            }
         }
     }
 
     fn handle_tunnel_events(
-        self,
+        mut self,
         event: Option<(TunnelEvent, oneshot::Sender<()>)>,
         shared_values: &mut SharedTunnelStateValues,
     ) -> EventConsequence {
         use self::EventConsequence::*;
 
         match event {
-            Some((TunnelEvent::Down, _)) | None => {
+            Some((TunnelEvent::Down, _)) => {
                 self.disconnect(shared_values, AfterDisconnect::Reconnect(0))
             }
             Some(_) => SameState(self.into()),
+            None => {
+                // The channel is closed when the monitor exits, which is followed by the tunnel
+                // close event. If it is not, the monitor has stopped working.
+                log::debug!("The tunnel event channel was closed");
+                self.tunnel_monitor_exit_deadline
+                    .get_or_insert_with(|| Instant::now() + TUNNEL_MONITOR_EXIT_TIMEOUT);
+                SameState(self.into())
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "android"))]
+    fn start_route_takeover_monitor(&mut self, shared_values: &SharedTunnelStateValues) {
+        #[cfg(target_os = "linux")]
+        let route_manager = match shared_values.route_manager.handle() {
+            Ok(handle) => handle,
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to obtain route manager handle")
+                );
+                return;
+            }
+        };
+        self.route_takeover_monitor = RouteTakeoverMonitor::start(
+            self.metadata.interface.clone(),
+            #[cfg(not(windows))]
+            &shared_values.runtime,
+            #[cfg(target_os = "linux")]
+            route_manager,
+        );
+    }
+
+    #[cfg(not(target_os = "android"))]
+    fn handle_route_takeover(
+        self,
+        competing_vpn: CompetingVpn,
+        shared_values: &mut SharedTunnelStateValues,
+    ) -> EventConsequence {
+        log::warn!("Another VPN took over the default route: {}", competing_vpn);
+        let _ = shared_values.route_takeover_tx.send(competing_vpn);
+
+        match shared_values.route_takeover_policy {
+            RouteTakeoverPolicy::Warn => EventConsequence::SameState(self.into()),
+            RouteTakeoverPolicy::Reclaim if shared_values.register_route_reclaim() => {
+                log::info!("Reconnecting to reclaim the default route");
+                self.disconnect(shared_values, AfterDisconnect::Reconnect(0))
+            }
+            RouteTakeoverPolicy::Reclaim | RouteTakeoverPolicy::Yield => {
+                log::info!("Yielding the default route to the other VPN");
+                self.disconnect(
+                    shared_values,
+                    AfterDisconnect::Block(ErrorStateCause::RouteTakenOver),
+                )
+            }
+        }
+    }
+
+    /// Handles a tunnel monitor that stopped without reporting why. It may still be running, so
+    /// it is asked to close, but it is not waited for.
+    fn handle_tunnel_monitor_failure(
+        self,
+        shared_values: &mut SharedTunnelStateValues,
+    ) -> EventConsequence {
+        let _ = self.tunnel_close_tx.send(());
+        Self::reset_dns(shared_values);
+        Self::reset_routes(shared_values);
+
+        match shared_values.register_tunnel_monitor_failure() {
+            Some(cause) => EventConsequence::NewState(ErrorState::enter(shared_values, cause)),
+            None => {
+                log::info!("Tunnel monitor failed. Reconnecting.");
+                EventConsequence::NewState(ConnectingState::enter(shared_values, 0))
+            }
         }
     }
 
@@ -322,29 +617,48 @@ impl TunnelState for ConnectedState {
         shared_values: &mut SharedTunnelStateValues,
         bootstrap: Self::Bootstrap,
     ) -> (TunnelStateWrapper, TunnelStateTransition) {
-        let connected_state = ConnectedState::from(bootstrap);
-        let tunnel_endpoint = connected_state.tunnel_parameters.get_tunnel_endpoint();
+        shared_values.stop_leak_canary();
 
-        if let Err(error) = connected_state.set_firewall_policy(shared_values) {
-            DisconnectingState::enter(
-                shared_values,
-                (
-                    connected_state.tunnel_close_tx,
-                    connected_state.tunnel_close_event,
-                    AfterDisconnect::Block(ErrorStateCause::SetFirewallPolicyError(error)),
-                ),
-            )
-        } else if let Err(error) = connected_state.set_dns(shared_values) {
-            log::error!("{}", error.display_chain_with_msg("Failed to set DNS"));
+        #[cfg_attr(target_os = "android", allow(unused_mut))]
+        let mut connected_state = ConnectedState::from(bootstrap);
+        let mut tunnel_endpoint = connected_state.tunnel_parameters.get_tunnel_endpoint();
+        tunnel_endpoint.wireguard_backend = connected_state.metadata.wireguard_backend;
+
+        // DNS is set before the tunnel is opened to all traffic
+        let result = connected_state
+            .set_dns_only_firewall_policy(shared_values)
+            .map_err(ErrorStateCause::SetFirewallPolicyError)
+            .and_then(|()| {
+                connected_state.set_dns(shared_values).map_err(|error| {
+                    log::error!("{}", error.display_chain_with_msg("Failed to set DNS"));
+                    ErrorStateCause::SetDnsError
+                })
+            })
+            .and_then(|()| {
+                connected_state
+                    .set_firewall_policy(shared_values)
+                    .map_err(ErrorStateCause::SetFirewallPolicyError)
+            });
+
+        if let Err(cause) = result {
             DisconnectingState::enter(
                 shared_values,
                 (
                     connected_state.tunnel_close_tx,
                     connected_state.tunnel_close_event,
-                    AfterDisconnect::Block(ErrorStateCause::SetDnsError),
+                    AfterDisconnect::Block(cause),
                 ),
             )
         } else {
+            #[cfg(windows)]
+            connected_state.set_interface_metric(shared_values);
+            #[cfg(all(feature = "packet-capture", target_os = "linux"))]
+            shared_values
+                .packet_capture
+                .set_interface(Some(&connected_state.metadata.interface));
+            shared_values.tunnel_monitor_failures = 0;
+            #[cfg(not(target_os = "android"))]
+            connected_state.start_route_takeover_monitor(shared_values);
             (
                 TunnelStateWrapper::from(connected_state),
                 TunnelStateTransition::Connected(tunnel_endpoint),
@@ -359,23 +673,55 @@ impl TunnelState for ConnectedState {
         shared_values: &mut SharedTunnelStateValues,
     ) -> EventConsequence {
         let result = runtime.block_on(async {
+            #[cfg(not(target_os = "android"))]
+            let route_takeover =
+                RouteTakeoverMonitor::wait_for_takeover(&mut self.route_takeover_monitor)
+                    .map(EventResult::RouteTakeover);
+            #[cfg(target_os = "android")]
+            let route_takeover = futures::future::pending();
+
             futures::select! {
                 command = commands.next() => EventResult::Command(command),
                 event = self.tunnel_events.next() => EventResult::Event(event),
                 result = &mut self.tunnel_close_event => EventResult::Close(result),
+                _ = BridgeMonitor::wait_for_dead_bridge(&mut self.bridge_monitor).fuse() => {
+                    EventResult::BridgeDown
+                }
+                _ = wait_for_tunnel_monitor_exit(self.tunnel_monitor_exit_deadline).fuse() => {
+                    EventResult::TunnelMonitorUnresponsive
+                }
+                result = route_takeover.fuse() => result,
             }
         });
 
         match result {
             EventResult::Command(command) => self.handle_commands(command, shared_values),
+            #[cfg(feature = "qa-tools")]
+            EventResult::Event(Some((event, _)))
+                if shared_values.simulation.drop_tunnel_event(&event) =>
+            {
+                EventConsequence::SameState(self.into())
+            }
             EventResult::Event(event) => self.handle_tunnel_events(event, shared_values),
-            EventResult::Close(result) => {
-                if result.is_err() {
-                    log::warn!("Tunnel monitor thread has stopped unexpectedly");
-                }
-                let block_reason = result.unwrap_or(None);
+            EventResult::Close(Ok(block_reason)) => {
                 self.handle_tunnel_close_event(block_reason, shared_values)
             }
+            EventResult::Close(Err(_)) => {
+                log::error!("Tunnel monitor thread has stopped unexpectedly");
+                self.handle_tunnel_monitor_failure(shared_values)
+            }
+            EventResult::TunnelMonitorUnresponsive => {
+                log::error!("Tunnel monitor closed its event channel but did not exit");
+                self.handle_tunnel_monitor_failure(shared_values)
+            }
+            EventResult::BridgeDown => {
+                log::info!("Reconnecting since the bridge stopped responding");
+                self.disconnect(shared_values, AfterDisconnect::Reconnect(0))
+            }
+            #[cfg(not(target_os = "android"))]
+            EventResult::RouteTakeover(competing_vpn) => {
+                self.handle_route_takeover(competing_vpn, shared_values)
+            }
         }
     }
 }
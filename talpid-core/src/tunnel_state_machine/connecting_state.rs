@@ -1,13 +1,20 @@
 use super::{
-    AfterDisconnect, ConnectedState, ConnectedStateBootstrap, DisconnectingState, ErrorState,
-    EventConsequence, EventResult, SharedTunnelStateValues, TunnelCommand, TunnelCommandReceiver,
-    TunnelState, TunnelStateTransition, TunnelStateWrapper,
+    bridge_monitor::{BridgeMonitor, ProbeSchedule},
+    wait_for_tunnel_monitor_exit, AfterDisconnect, ConnectedState, ConnectedStateBootstrap,
+    DisconnectingState, ErrorState, EventConsequence, EventResult, ReconnectCause,
+    SharedTunnelStateValues, TunnelCommand, TunnelCommandReceiver, TunnelState,
+    TunnelStateTransition, TunnelStateWrapper, TUNNEL_MONITOR_EXIT_TIMEOUT,
 };
+#[cfg(any(target_os = "macos", windows))]
+use crate::routing::{NetNode, RequiredRoute};
 use crate::{
     firewall::FirewallPolicy,
     routing::RouteManager,
     tunnel::{
-        self, tun_provider::TunProvider, TunnelArgs, TunnelEvent, TunnelMetadata, TunnelMonitor,
+        self,
+        tun_provider::TunProvider,
+        wireguard::{TrafficShapingHandle, TunnelProvider},
+        TunnelArgs, TunnelEvent, TunnelMetadata, TunnelMonitor,
     },
 };
 use cfg_if::cfg_if;
@@ -22,16 +29,22 @@ use std::{
     thread,
     time::{Duration, Instant},
 };
+#[cfg(not(target_os = "android"))]
+use talpid_types::tunnel::ProcessFailure;
 use talpid_types::{
     net::{AllowedTunnelTraffic, TunnelParameters},
-    tunnel::{ErrorStateCause, FirewallPolicyError},
+    performance::Operation,
+    tunnel::{
+        ConnectionAttemptId, DisconnectCause, ErrorStateCause, FirewallPolicyError,
+        ParameterGenerationError,
+    },
     ErrorExt,
 };
 
 #[cfg(windows)]
 use crate::{routing, winnet};
 
-#[cfg(target_os = "android")]
+#[cfg(any(target_os = "android", target_os = "linux", target_os = "macos"))]
 use crate::tunnel::tun_provider;
 
 use super::connected_state::TunnelEventsReceiver;
@@ -43,6 +56,10 @@ const MAX_ATTEMPTS_WITH_SAME_TUN: u32 = 5;
 const MIN_TUNNEL_ALIVE_TIME: Duration = Duration::from_millis(1000);
 #[cfg(target_os = "windows")]
 const MAX_ADAPTER_FAIL_RETRIES: u32 = 4;
+/// Number of times to reconnect after the tunnel process exits unexpectedly, before entering the
+/// error state.
+#[cfg(not(target_os = "android"))]
+const MAX_PROCESS_FAILURE_RETRIES: u32 = 3;
 
 /// The tunnel has been started, but it is not established/functional.
 pub struct ConnectingState {
@@ -52,11 +69,20 @@ pub struct ConnectingState {
     allowed_tunnel_traffic: AllowedTunnelTraffic,
     tunnel_close_event: TunnelCloseEvent,
     tunnel_close_tx: oneshot::Sender<()>,
+    traffic_shaping: TrafficShapingHandle,
+    bridge_monitor: Option<BridgeMonitor>,
+    /// Set when the tunnel event channel has been closed, to the time when the tunnel monitor is
+    /// given up on unless it has exited.
+    tunnel_monitor_exit_deadline: Option<Instant>,
     retry_attempt: u32,
 }
 
 impl ConnectingState {
-    fn set_firewall_policy(
+    pub(super) fn tunnel_metadata(&self) -> Option<&TunnelMetadata> {
+        self.tunnel_metadata.as_ref()
+    }
+
+    pub(super) fn set_firewall_policy(
         shared_values: &mut SharedTunnelStateValues,
         params: &TunnelParameters,
         tunnel_metadata: &Option<TunnelMetadata>,
@@ -73,9 +99,11 @@ impl ConnectingState {
             allow_lan: shared_values.allow_lan,
             allowed_endpoint: shared_values.allowed_endpoint.clone(),
             allowed_tunnel_traffic,
+            vpn_coexistence: shared_values.vpn_coexistence.clone(),
             #[cfg(windows)]
             relay_client: TunnelMonitor::get_relay_client(&shared_values.resource_dir, &params),
         };
+        let _watchdog = shared_values.watchdog(Operation::ApplyFirewallPolicy);
         shared_values
             .firewall
             .apply_policy(policy)
@@ -100,12 +128,21 @@ impl ConnectingState {
         log_dir: &Option<PathBuf>,
         resource_dir: &Path,
         tun_provider: Arc<Mutex<TunProvider>>,
+        wireguard_tunnel_provider: Option<Arc<dyn TunnelProvider>>,
+        #[cfg(not(target_os = "android"))] forced_interface: Option<String>,
         route_manager: &mut RouteManager,
         retry_attempt: u32,
+        connection_attempt: ConnectionAttemptId,
     ) -> Self {
         let (event_tx, event_rx) = mpsc::unbounded();
         let on_tunnel_event =
             move |event| -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send>> {
+                // Tag the event here, since it may arrive after a newer attempt has started
+                log::debug!(
+                    "Tunnel event in connection attempt {}: {:?}",
+                    connection_attempt,
+                    event
+                );
                 let (tx, rx) = oneshot::channel();
                 let _ = event_tx.unbounded_send((event, tx));
                 Box::pin(async move {
@@ -121,6 +158,8 @@ impl ConnectingState {
         let (tunnel_close_event_tx, tunnel_close_event_rx) = oneshot::channel();
 
         let mut tunnel_parameters = parameters.clone();
+        let traffic_shaping = TrafficShapingHandle::default();
+        let monitor_traffic_shaping = traffic_shaping.clone();
 
         tokio::task::spawn_blocking(move || {
             let start = Instant::now();
@@ -152,12 +191,20 @@ impl ConnectingState {
                 tun_provider,
                 retry_attempt,
                 route_manager: route_manager_handle,
+                wireguard_tunnel_provider,
+                traffic_shaping: monitor_traffic_shaping,
+                #[cfg(not(target_os = "android"))]
+                forced_interface,
             };
 
             let block_reason = match TunnelMonitor::start(&mut tunnel_parameters, &log_dir, args) {
                 Ok(monitor) => {
                     let reason = Self::wait_for_tunnel_monitor(monitor, retry_attempt);
-                    log::debug!("Tunnel monitor exited with block reason: {:?}", reason);
+                    log::debug!(
+                        "Tunnel monitor of connection attempt {} exited with block reason: {:?}",
+                        connection_attempt,
+                        reason
+                    );
                     reason
                 }
                 Err(error) if should_retry(&error, retry_attempt) => {
@@ -189,6 +236,30 @@ impl ConnectingState {
                                 ),
                             ),
                         ) => ErrorStateCause::InvalidDnsServers(addresses),
+                        #[cfg(not(target_os = "android"))]
+                        tunnel::Error::WireguardTunnelMonitoringError(
+                            tunnel::wireguard::Error::ForcedInterfaceUnavailable(_),
+                        ) => ErrorStateCause::ForcedInterfaceUnavailable,
+                        #[cfg(target_os = "linux")]
+                        tunnel::Error::WireguardTunnelMonitoringError(
+                            tunnel::wireguard::Error::GatewayUnreachable(_),
+                        ) => ErrorStateCause::GatewayUnreachable,
+                        #[cfg(target_os = "linux")]
+                        tunnel::Error::WireguardTunnelMonitoringError(
+                            tunnel::wireguard::Error::TunnelError(
+                                tunnel::wireguard::TunnelError::InterfaceNamesUnavailable,
+                            ),
+                        ) => ErrorStateCause::TunnelDeviceBusy,
+                        #[cfg(any(target_os = "linux", target_os = "macos"))]
+                        tunnel::Error::WireguardTunnelMonitoringError(
+                            tunnel::wireguard::Error::TunnelError(
+                                tunnel::wireguard::TunnelError::SetupTunnelDeviceError(
+                                    tun_provider::Error::CreateTunnelDevice(
+                                        crate::network_interface::Error::DeviceBusy,
+                                    ),
+                                ),
+                            ),
+                        ) => ErrorStateCause::TunnelDeviceBusy,
                         _ => ErrorStateCause::StartTunnelError,
                     };
                     Some(block_reason)
@@ -208,6 +279,8 @@ impl ConnectingState {
             log::trace!("Tunnel monitor thread exit");
         });
 
+        let bridge_monitor = BridgeMonitor::start(&parameters, ProbeSchedule::Connecting);
+
         ConnectingState {
             tunnel_events: event_rx.fuse(),
             tunnel_parameters: parameters,
@@ -215,40 +288,137 @@ impl ConnectingState {
             allowed_tunnel_traffic: AllowedTunnelTraffic::None,
             tunnel_close_event: tunnel_close_event_rx.fuse(),
             tunnel_close_tx,
+            traffic_shaping,
+            bridge_monitor,
+            tunnel_monitor_exit_deadline: None,
             retry_attempt,
         }
     }
 
+    /// Starts a tunnel using `tunnel_parameters` rather than generating new ones. This is used to
+    /// quickly bring a paused tunnel back up.
+    pub fn enter_with_parameters(
+        shared_values: &mut SharedTunnelStateValues,
+        tunnel_parameters: TunnelParameters,
+        retry_attempt: u32,
+    ) -> (TunnelStateWrapper, TunnelStateTransition) {
+        if retry_attempt == 0 {
+            // This is the first attempt of a new sequence
+            shared_values.reconnect_budget.reset();
+        }
+        let connection_attempt = shared_values.start_connection_attempt();
+
+        if let Err(error) = tunnel_parameters.validate() {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Refusing to connect using invalid tunnel parameters")
+            );
+            return ErrorState::enter(
+                shared_values,
+                ErrorStateCause::TunnelParameterError(
+                    ParameterGenerationError::InvalidTunnelParameters(error),
+                ),
+            );
+        }
+
+        #[cfg(windows)]
+        if let Err(error) = shared_values.split_tunnel.set_tunnel_addresses(None) {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to reset addresses in split tunnel driver")
+            );
+
+            return ErrorState::enter(shared_values, ErrorStateCause::SplitTunnelError);
+        }
+
+        if let Err(error) = Self::set_firewall_policy(
+            shared_values,
+            &tunnel_parameters,
+            &None,
+            AllowedTunnelTraffic::None,
+        ) {
+            ErrorState::enter(
+                shared_values,
+                ErrorStateCause::SetFirewallPolicyError(error),
+            )
+        } else {
+            #[cfg(target_os = "android")]
+            {
+                if retry_attempt > 0 && retry_attempt % MAX_ATTEMPTS_WITH_SAME_TUN == 0 {
+                    if let Err(error) = { shared_values.tun_provider.lock().unwrap().create_tun() }
+                    {
+                        log::error!(
+                            "{}",
+                            error.display_chain_with_msg("Failed to recreate tun device")
+                        );
+                    }
+                }
+            }
+
+            #[cfg(any(target_os = "macos", windows))]
+            Self::add_vpn_coexistence_routes(shared_values);
+
+            let connecting_state = Self::start_tunnel(
+                shared_values.runtime.clone(),
+                tunnel_parameters,
+                &shared_values.log_dir,
+                &shared_values.resource_dir,
+                shared_values.tun_provider.clone(),
+                shared_values.wireguard_tunnel_provider.clone(),
+                #[cfg(not(target_os = "android"))]
+                shared_values.forced_interface.clone(),
+                &mut shared_values.route_manager,
+                retry_attempt,
+                connection_attempt,
+            );
+            let params = connecting_state.tunnel_parameters.clone();
+            (
+                TunnelStateWrapper::from(connecting_state),
+                TunnelStateTransition::Connecting(params.get_tunnel_endpoint()),
+            )
+        }
+    }
+
     fn wait_for_tunnel_monitor(
         tunnel_monitor: TunnelMonitor,
         retry_attempt: u32,
     ) -> Option<ErrorStateCause> {
         match tunnel_monitor.wait() {
             Ok(_) => None,
-            Err(error) => match error {
-                tunnel::Error::WireguardTunnelMonitoringError(
-                    tunnel::wireguard::Error::TimeoutError,
-                ) => {
-                    log::debug!("WireGuard tunnel timed out");
-                    None
-                }
-                error @ tunnel::Error::WireguardTunnelMonitoringError(..)
-                    if !should_retry(&error, retry_attempt) =>
-                {
+            Err(error) => {
+                #[cfg(not(target_os = "android"))]
+                if let Some(cause) = process_failure_cause(&error, retry_attempt) {
                     log::error!(
                         "{}",
-                        error.display_chain_with_msg("Tunnel has stopped unexpectedly")
+                        error.display_chain_with_msg("The tunnel process failed")
                     );
-                    Some(ErrorStateCause::StartTunnelError)
+                    return Some(cause);
                 }
-                error => {
-                    log::warn!(
-                        "{}",
-                        error.display_chain_with_msg("Tunnel has stopped unexpectedly")
-                    );
-                    None
+                match error {
+                    tunnel::Error::WireguardTunnelMonitoringError(
+                        tunnel::wireguard::Error::TimeoutError,
+                    ) => {
+                        log::debug!("WireGuard tunnel timed out");
+                        None
+                    }
+                    error @ tunnel::Error::WireguardTunnelMonitoringError(..)
+                        if !should_retry(&error, retry_attempt) =>
+                    {
+                        log::error!(
+                            "{}",
+                            error.display_chain_with_msg("Tunnel has stopped unexpectedly")
+                        );
+                        Some(ErrorStateCause::StartTunnelError)
+                    }
+                    error => {
+                        log::warn!(
+                            "{}",
+                            error.display_chain_with_msg("Tunnel has stopped unexpectedly")
+                        );
+                        None
+                    }
                 }
-            },
+            }
         }
     }
 
@@ -259,11 +429,43 @@ impl ConnectingState {
             tunnel_parameters: self.tunnel_parameters,
             tunnel_close_event: self.tunnel_close_event,
             tunnel_close_tx: self.tunnel_close_tx,
+            traffic_shaping: self.traffic_shaping,
+        }
+    }
+
+    /// Routes the networks of other VPNs through the physical default route, so that they are
+    /// not captured by the tunnel routes. The routes are removed with the other routes when the
+    /// tunnel goes down. On Linux, routing rules keep the traffic of other VPNs out of the tunnel
+    /// instead. Routes cannot match source ports, so the UDP ports of other VPNs are only exempt
+    /// in the firewall on these platforms.
+    #[cfg(any(target_os = "macos", windows))]
+    fn add_vpn_coexistence_routes(shared_values: &mut SharedTunnelStateValues) {
+        let routes: std::collections::HashSet<_> = shared_values
+            .vpn_coexistence
+            .networks
+            .iter()
+            .map(|network| RequiredRoute::vpn_coexistence(*network, NetNode::DefaultNode))
+            .collect();
+        if routes.is_empty() {
+            return;
+        }
+        if let Err(error) = shared_values
+            .runtime
+            .block_on(shared_values.route_manager.add_routes(routes))
+        {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to add routes for other VPNs")
+            );
         }
     }
 
     fn reset_routes(shared_values: &mut SharedTunnelStateValues) {
-        if let Err(error) = shared_values.route_manager.clear_routes() {
+        let result = {
+            let _watchdog = shared_values.watchdog(Operation::ClearRoutes);
+            shared_values.route_manager.clear_routes()
+        };
+        if let Err(error) = result {
             log::error!("{}", error.display_chain_with_msg("Failed to clear routes"));
         }
         #[cfg(target_os = "linux")]
@@ -362,6 +564,74 @@ impl ConnectingState {
                 shared_values.block_when_disconnected = block_when_disconnected;
                 SameState(self.into())
             }
+            Some(TunnelCommand::FlushDnsCache(flush_dns_cache)) => {
+                shared_values.dns_monitor.set_flush_cache(flush_dns_cache);
+                SameState(self.into())
+            }
+            Some(TunnelCommand::RecoveryAllowlist(recovery_allowlist)) => {
+                shared_values.recovery_allowlist = recovery_allowlist;
+                SameState(self.into())
+            }
+            Some(TunnelCommand::VpnCoexistence(vpn_coexistence)) => {
+                if shared_values.vpn_coexistence != vpn_coexistence {
+                    #[cfg(any(target_os = "macos", windows))]
+                    if shared_values.vpn_coexistence.networks != vpn_coexistence.networks {
+                        // The routes for the networks are only added when connecting
+                        shared_values.vpn_coexistence = vpn_coexistence;
+                        return self.disconnect(shared_values, AfterDisconnect::Reconnect(0));
+                    }
+                    shared_values.vpn_coexistence = vpn_coexistence;
+                    self.reset_firewall(shared_values)
+                } else {
+                    SameState(self.into())
+                }
+            }
+            Some(TunnelCommand::BlockedTunnelProtocols(blocked_tunnel_protocols)) => {
+                shared_values.blocked_tunnel_protocols = blocked_tunnel_protocols;
+                SameState(self.into())
+            }
+            Some(TunnelCommand::SetForcedInterface(forced_interface)) => {
+                if shared_values.set_forced_interface(forced_interface) {
+                    self.disconnect(shared_values, AfterDisconnect::Reconnect(0))
+                } else {
+                    SameState(self.into())
+                }
+            }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::ForcedInterfaceChanged(index)) => {
+                if index.is_some() {
+                    // The interface was recreated, so the sockets are bound to a stale index
+                    self.disconnect(shared_values, AfterDisconnect::Reconnect(0))
+                } else {
+                    self.disconnect(
+                        shared_values,
+                        AfterDisconnect::Block(ErrorStateCause::ForcedInterfaceUnavailable),
+                    )
+                }
+            }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::DefaultInterfaceChanged) => SameState(self.into()),
+            Some(TunnelCommand::TrafficShaping(_)) => SameState(self.into()),
+            Some(TunnelCommand::ReconnectLimits(reconnect_limits)) => {
+                shared_values.reconnect_budget.set_limits(reconnect_limits);
+                SameState(self.into())
+            }
+            Some(TunnelCommand::RouteTakeoverPolicy(policy)) => {
+                shared_values.route_takeover_policy = policy;
+                SameState(self.into())
+            }
+            Some(TunnelCommand::ErrorStatePolicy(policy)) => {
+                shared_values.error_state_policy = policy;
+                SameState(self.into())
+            }
+            Some(TunnelCommand::HealthCheck(tx)) => {
+                let _ = tx.send(shared_values.health_check(None));
+                SameState(self.into())
+            }
+            Some(TunnelCommand::GetSystemState(tx)) => {
+                let _ = tx.send(shared_values.system_state());
+                SameState(self.into())
+            }
             Some(TunnelCommand::IsOffline(is_offline)) => {
                 shared_values.is_offline = is_offline;
                 if is_offline {
@@ -376,12 +646,21 @@ impl ConnectingState {
             Some(TunnelCommand::Connect) => {
                 self.disconnect(shared_values, AfterDisconnect::Reconnect(0))
             }
-            Some(TunnelCommand::Disconnect) | None => {
-                self.disconnect(shared_values, AfterDisconnect::Nothing)
+            Some(TunnelCommand::Disconnect(cause)) => {
+                self.disconnect(shared_values, AfterDisconnect::Nothing(cause))
             }
+            None => self.disconnect(
+                shared_values,
+                AfterDisconnect::Nothing(DisconnectCause::Shutdown),
+            ),
             Some(TunnelCommand::Block(reason)) => {
                 self.disconnect(shared_values, AfterDisconnect::Block(reason))
             }
+            Some(TunnelCommand::Pause) => {
+                let tunnel_parameters = self.tunnel_parameters.clone();
+                self.disconnect(shared_values, AfterDisconnect::Pause(tunnel_parameters))
+            }
+            Some(TunnelCommand::Resume) => SameState(self.into()),
             #[cfg(target_os = "android")]
             Some(TunnelCommand::BypassSocket(fd, done_tx)) => {
                 shared_values.bypass_socket(fd, done_tx);
@@ -392,6 +671,11 @@ impl ConnectingState {
                 shared_values.split_tunnel.set_paths(&paths, result_tx);
                 SameState(self.into())
             }
+            #[cfg(windows)]
+            Some(TunnelCommand::ReinstallSplitTunnelDriver(result_tx)) => {
+                shared_values.split_tunnel.reinstall_driver(result_tx);
+                SameState(self.into())
+            }
         }
     }
 
@@ -447,10 +731,36 @@ impl ConnectingState {
             )),
             Some((TunnelEvent::Down, _)) => SameState(self.into()),
             None => {
-                // The channel was closed
-                log::debug!("The tunnel disconnected unexpectedly");
-                let retry_attempt = self.retry_attempt + 1;
-                self.disconnect(shared_values, AfterDisconnect::Reconnect(retry_attempt))
+                // The channel is closed when the monitor exits, which is followed by the tunnel
+                // close event. If it is not, the monitor has stopped working.
+                log::debug!("The tunnel event channel was closed");
+                self.tunnel_monitor_exit_deadline
+                    .get_or_insert_with(|| Instant::now() + TUNNEL_MONITOR_EXIT_TIMEOUT);
+                SameState(self.into())
+            }
+        }
+    }
+
+    /// Handles a tunnel monitor that stopped without reporting why. It may still be running, so
+    /// it is asked to close, but it is not waited for.
+    fn handle_tunnel_monitor_failure(
+        self,
+        shared_values: &mut SharedTunnelStateValues,
+    ) -> EventConsequence {
+        let _ = self.tunnel_close_tx.send(());
+        Self::reset_routes(shared_values);
+
+        match shared_values.register_tunnel_monitor_failure() {
+            Some(cause) => EventConsequence::NewState(ErrorState::enter(shared_values, cause)),
+            None => {
+                log::info!(
+                    "Tunnel monitor failed. Reconnecting, attempt {}.",
+                    self.retry_attempt + 1
+                );
+                EventConsequence::NewState(ConnectingState::enter(
+                    shared_values,
+                    self.retry_attempt + 1,
+                ))
             }
         }
     }
@@ -467,11 +777,21 @@ impl ConnectingState {
             return NewState(ErrorState::enter(shared_values, block_reason));
         }
 
+        // The interface is only brought up once the tunnel has been started
+        let cause = if self.tunnel_metadata.is_some() {
+            ReconnectCause::Timeout
+        } else {
+            ReconnectCause::StartFailure
+        };
+        Self::reset_routes(shared_values);
+        if let Some(block_reason) = shared_values.register_reconnect(cause) {
+            return NewState(ErrorState::enter(shared_values, block_reason));
+        }
+
         log::info!(
             "Tunnel closed. Reconnecting, attempt {}.",
             self.retry_attempt + 1
         );
-        Self::reset_routes(shared_values);
         EventConsequence::NewState(ConnectingState::enter(
             shared_values,
             self.retry_attempt + 1,
@@ -479,6 +799,23 @@ impl ConnectingState {
     }
 }
 
+/// Returns the cause of the error state to enter if the tunnel process failed. The process is
+/// restarted a few times if it exited unexpectedly, but not if it could not be started at all.
+#[cfg(not(target_os = "android"))]
+fn process_failure_cause(error: &tunnel::Error, retry_attempt: u32) -> Option<ErrorStateCause> {
+    let failure = match error {
+        tunnel::Error::OpenVpnTunnelMonitoringError(error) => error.process_failure()?,
+        _ => return None,
+    };
+    match failure {
+        ProcessFailure::Spawn => Some(ErrorStateCause::TunnelProcessFailed(failure)),
+        _ if retry_attempt >= MAX_PROCESS_FAILURE_RETRIES => {
+            Some(ErrorStateCause::TunnelProcessFailed(failure))
+        }
+        _ => None,
+    }
+}
+
 #[cfg_attr(not(target_os = "windows"), allow(unused_variables))]
 fn should_retry(error: &tunnel::Error, retry_attempt: u32) -> bool {
     #[cfg(windows)]
@@ -544,6 +881,10 @@ impl TunnelState for ConnectingState {
         if shared_values.is_offline {
             return ErrorState::enter(shared_values, ErrorStateCause::IsOffline);
         }
+        #[cfg(feature = "qa-tools")]
+        if let Some(error) = shared_values.simulation.take_parameter_error() {
+            return ErrorState::enter(shared_values, ErrorStateCause::TunnelParameterError(error));
+        }
         match shared_values.runtime.block_on(
             shared_values
                 .tunnel_parameters_generator
@@ -553,58 +894,7 @@ impl TunnelState for ConnectingState {
                 ErrorState::enter(shared_values, ErrorStateCause::TunnelParameterError(err))
             }
             Ok(tunnel_parameters) => {
-                #[cfg(windows)]
-                if let Err(error) = shared_values.split_tunnel.set_tunnel_addresses(None) {
-                    log::error!(
-                        "{}",
-                        error.display_chain_with_msg(
-                            "Failed to reset addresses in split tunnel driver"
-                        )
-                    );
-
-                    return ErrorState::enter(shared_values, ErrorStateCause::SplitTunnelError);
-                }
-
-                if let Err(error) = Self::set_firewall_policy(
-                    shared_values,
-                    &tunnel_parameters,
-                    &None,
-                    AllowedTunnelTraffic::None,
-                ) {
-                    ErrorState::enter(
-                        shared_values,
-                        ErrorStateCause::SetFirewallPolicyError(error),
-                    )
-                } else {
-                    #[cfg(target_os = "android")]
-                    {
-                        if retry_attempt > 0 && retry_attempt % MAX_ATTEMPTS_WITH_SAME_TUN == 0 {
-                            if let Err(error) =
-                                { shared_values.tun_provider.lock().unwrap().create_tun() }
-                            {
-                                log::error!(
-                                    "{}",
-                                    error.display_chain_with_msg("Failed to recreate tun device")
-                                );
-                            }
-                        }
-                    }
-
-                    let connecting_state = Self::start_tunnel(
-                        shared_values.runtime.clone(),
-                        tunnel_parameters,
-                        &shared_values.log_dir,
-                        &shared_values.resource_dir,
-                        shared_values.tun_provider.clone(),
-                        &mut shared_values.route_manager,
-                        retry_attempt,
-                    );
-                    let params = connecting_state.tunnel_parameters.clone();
-                    (
-                        TunnelStateWrapper::from(connecting_state),
-                        TunnelStateTransition::Connecting(params.get_tunnel_endpoint()),
-                    )
-                }
+                Self::enter_with_parameters(shared_values, tunnel_parameters, retry_attempt)
             }
         }
     }
@@ -620,19 +910,46 @@ impl TunnelState for ConnectingState {
                 command = commands.next() => EventResult::Command(command),
                 event = self.tunnel_events.next() => EventResult::Event(event),
                 result = &mut self.tunnel_close_event => EventResult::Close(result),
+                _ = BridgeMonitor::wait_for_dead_bridge(&mut self.bridge_monitor).fuse() => {
+                    EventResult::BridgeDown
+                }
+                _ = wait_for_tunnel_monitor_exit(self.tunnel_monitor_exit_deadline).fuse() => {
+                    EventResult::TunnelMonitorUnresponsive
+                }
             }
         });
 
         match result {
             EventResult::Command(command) => self.handle_commands(command, shared_values),
+            #[cfg(feature = "qa-tools")]
+            EventResult::Event(Some((event, _)))
+                if shared_values.simulation.drop_tunnel_event(&event) =>
+            {
+                EventConsequence::SameState(self.into())
+            }
             EventResult::Event(event) => self.handle_tunnel_events(event, shared_values),
-            EventResult::Close(result) => {
-                if result.is_err() {
-                    log::warn!("Tunnel monitor thread has stopped unexpectedly");
-                }
-                let block_reason = result.unwrap_or(None);
+            EventResult::Close(Ok(block_reason)) => {
                 self.handle_tunnel_close_event(block_reason, shared_values)
             }
+            EventResult::Close(Err(_)) => {
+                log::error!("Tunnel monitor thread has stopped unexpectedly");
+                self.handle_tunnel_monitor_failure(shared_values)
+            }
+            EventResult::TunnelMonitorUnresponsive => {
+                log::error!("Tunnel monitor closed its event channel but did not exit");
+                self.handle_tunnel_monitor_failure(shared_values)
+            }
+            EventResult::BridgeDown => {
+                if let Some(cause) = shared_values.register_reconnect(ReconnectCause::BridgeDown) {
+                    return self.disconnect(shared_values, AfterDisconnect::Block(cause));
+                }
+                log::info!("Trying the next bridge");
+                let retry_attempt = self.retry_attempt + 1;
+                self.disconnect(shared_values, AfterDisconnect::Reconnect(retry_attempt))
+            }
+            // Routes are only monitored for takeovers while connected.
+            #[cfg(not(target_os = "android"))]
+            EventResult::RouteTakeover(_) => EventConsequence::SameState(self.into()),
         }
     }
 }
@@ -2,48 +2,135 @@ use super::{
     ConnectingState, ErrorState, EventConsequence, SharedTunnelStateValues, TunnelCommand,
     TunnelCommandReceiver, TunnelState, TunnelStateTransition, TunnelStateWrapper,
 };
-#[cfg(target_os = "macos")]
-use crate::dns;
 use crate::firewall::FirewallPolicy;
-use futures::StreamExt;
+#[cfg(target_os = "macos")]
+use crate::{dns, resolver::ResolverConfig};
+use futures::{FutureExt, StreamExt};
 #[cfg(target_os = "macos")]
 use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
 #[cfg(target_os = "macos")]
 use talpid_types::tunnel::ErrorStateCause;
-use talpid_types::ErrorExt;
+use talpid_types::{
+    performance::Operation,
+    tunnel::{DisconnectCause, DisconnectedBlockReason, DisconnectedSecurity},
+    ErrorExt,
+};
+
+/// How long the firewall rules of a tunnel are kept after it has been closed. Reconnecting within
+/// this time replaces the rules directly, instead of removing them and building them up again.
+const FIREWALL_RELEASE_DELAY: Duration = Duration::from_secs(2);
 
 /// No tunnel is running.
-pub struct DisconnectedState;
+pub struct DisconnectedState {
+    cause: Option<DisconnectCause>,
+    security: DisconnectedSecurity,
+    /// When to remove the firewall rules of the tunnel that was just closed, if they are kept.
+    firewall_release: Option<Instant>,
+}
 
 impl DisconnectedState {
+    /// Applies the firewall policy for the disconnected state and returns whether traffic is now
+    /// blocked. `current_security` is returned if the policy is left unchanged.
     fn set_firewall_policy(
         shared_values: &mut SharedTunnelStateValues,
         should_reset_firewall: bool,
-    ) {
-        let result = if shared_values.block_when_disconnected {
+        current_security: DisconnectedSecurity,
+    ) -> DisconnectedSecurity {
+        let (result, security) = if shared_values.block_when_disconnected {
             let policy = FirewallPolicy::Blocked {
                 allow_lan: shared_values.allow_lan,
                 allowed_endpoint: Some(shared_values.allowed_endpoint.clone()),
+                recovery_allowlist: vec![],
+                vpn_coexistence: shared_values.vpn_coexistence.clone(),
                 #[cfg(target_os = "macos")]
                 dns_redirect_port: shared_values.filtering_resolver.listening_port(),
             };
 
-            shared_values.firewall.apply_policy(policy).map_err(|e| {
+            #[cfg(target_os = "macos")]
+            shared_values
+                .filtering_resolver
+                .set_config(ResolverConfig::blocking(&shared_values.allowed_endpoint));
+
+            let _watchdog = shared_values.watchdog(Operation::ApplyFirewallPolicy);
+            let result = shared_values.firewall.apply_policy(policy).map_err(|e| {
                 e.display_chain_with_msg(
                     "Failed to apply blocking firewall policy for disconnected state",
                 )
-            })
+            });
+            let security = if result.is_ok() {
+                DisconnectedSecurity::Blocked(DisconnectedBlockReason::BlockWhenDisconnected)
+            } else {
+                DisconnectedSecurity::Unsecured
+            };
+            (result, security)
         } else if should_reset_firewall {
-            shared_values
+            let _watchdog = shared_values.watchdog(Operation::ResetFirewallPolicy);
+            let result = shared_values
                 .firewall
                 .reset_policy()
-                .map_err(|e| e.display_chain_with_msg("Failed to reset firewall policy"))
+                .map_err(|e| e.display_chain_with_msg("Failed to reset firewall policy"));
+            (result, DisconnectedSecurity::Unsecured)
         } else {
-            Ok(())
+            (Ok(()), current_security)
         };
         if let Err(error_chain) = result {
             log::error!("{}", error_chain);
         }
+        security
+    }
+
+    /// Sets the firewall policy and notifies listeners if this changed whether traffic is
+    /// blocked. The rules of the previous tunnel are removed if they are still kept.
+    fn update_firewall_policy(
+        mut self,
+        shared_values: &mut SharedTunnelStateValues,
+        should_reset_firewall: bool,
+    ) -> EventConsequence {
+        let should_reset_firewall = should_reset_firewall || self.firewall_release.take().is_some();
+        let security =
+            Self::set_firewall_policy(shared_values, should_reset_firewall, self.security);
+        self.with_security(security)
+    }
+
+    /// Returns whether the firewall rules of the tunnel that was just closed should be kept for a
+    /// while, rather than removed right away.
+    fn should_hold_firewall(
+        current_policy: Option<&FirewallPolicy>,
+        block_when_disconnected: bool,
+        should_reset_firewall: bool,
+    ) -> bool {
+        // Android has no firewall rules, only the tunnel device that is closed
+        !cfg!(target_os = "android")
+            && should_reset_firewall
+            && !block_when_disconnected
+            && matches!(
+                current_policy,
+                Some(FirewallPolicy::Connecting { .. }) | Some(FirewallPolicy::Connected { .. })
+            )
+    }
+
+    /// Completes when the firewall rules of the previous tunnel should be removed. Never
+    /// completes if they are not kept.
+    async fn wait_for_firewall_release(deadline: Option<Instant>) {
+        match deadline {
+            Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+            None => futures::future::pending().await,
+        }
+    }
+
+    /// Remains in the disconnected state. A new transition is emitted if `security` differs from
+    /// the current one.
+    fn with_security(mut self, security: DisconnectedSecurity) -> EventConsequence {
+        if security == self.security {
+            return EventConsequence::SameState(self.into());
+        }
+        self.security = security;
+        let transition = TunnelStateTransition::Disconnected {
+            cause: self.cause.clone(),
+            security,
+        };
+        EventConsequence::NewState((self.into(), transition))
     }
 
     #[cfg(windows)]
@@ -72,6 +159,7 @@ impl DisconnectedState {
     }
 
     fn reset_dns(shared_values: &mut SharedTunnelStateValues) {
+        let _watchdog = shared_values.watchdog(Operation::ResetDns);
         if let Err(error) = shared_values.dns_monitor.reset() {
             log::error!("{}", error.display_chain_with_msg("Unable to reset DNS"));
         }
@@ -82,59 +170,20 @@ impl DisconnectedState {
     fn setup_local_dns_config(
         shared_values: &mut SharedTunnelStateValues,
     ) -> Result<(), dns::Error> {
+        let _watchdog = shared_values.watchdog(Operation::SetDns);
         shared_values
             .dns_monitor
             .set("lo", &[Ipv4Addr::LOCALHOST.into()])
     }
-}
 
-impl TunnelState for DisconnectedState {
-    type Bootstrap = bool;
-
-    fn enter(
-        shared_values: &mut SharedTunnelStateValues,
-        should_reset_firewall: Self::Bootstrap,
-    ) -> (TunnelStateWrapper, TunnelStateTransition) {
-        #[cfg(target_os = "macos")]
-        if shared_values.block_when_disconnected {
-            if let Err(err) = Self::setup_local_dns_config(shared_values) {
-                log::error!(
-                    "{}",
-                    err.display_chain_with_msg("Failed to start filtering resolver:")
-                );
-            }
-        } else {
-            if let Err(error) = shared_values.dns_monitor.reset() {
-                log::error!(
-                    "{}",
-                    error.display_chain_with_msg("Unable to disable filtering resolver")
-                );
-            }
-        }
-
-        #[cfg(windows)]
-        Self::register_split_tunnel_addresses(shared_values, should_reset_firewall);
-        Self::set_firewall_policy(shared_values, should_reset_firewall);
-        #[cfg(target_os = "linux")]
-        shared_values.reset_connectivity_check();
-        #[cfg(target_os = "android")]
-        shared_values.tun_provider.lock().unwrap().close_tun();
-
-        (
-            TunnelStateWrapper::from(DisconnectedState),
-            TunnelStateTransition::Disconnected,
-        )
-    }
-
-    fn handle_event(
-        self,
-        runtime: &tokio::runtime::Handle,
-        commands: &mut TunnelCommandReceiver,
+    fn handle_command(
+        mut self,
+        command: Option<TunnelCommand>,
         shared_values: &mut SharedTunnelStateValues,
     ) -> EventConsequence {
         use self::EventConsequence::*;
 
-        match runtime.block_on(commands.next()) {
+        match command {
             Some(TunnelCommand::AllowLan(allow_lan)) => {
                 if shared_values.allow_lan != allow_lan {
                     // The only platform that can fail is Android, but Android doesn't support the
@@ -143,17 +192,20 @@ impl TunnelState for DisconnectedState {
                         .set_allow_lan(allow_lan)
                         .expect("Failed to set allow LAN parameter");
 
-                    Self::set_firewall_policy(shared_values, false);
+                    self.update_firewall_policy(shared_values, false)
+                } else {
+                    SameState(self.into())
                 }
-                SameState(self.into())
             }
             Some(TunnelCommand::AllowEndpoint(endpoint, tx)) => {
-                if shared_values.allowed_endpoint != endpoint {
+                let consequence = if shared_values.allowed_endpoint != endpoint {
                     shared_values.allowed_endpoint = endpoint;
-                    Self::set_firewall_policy(shared_values, false);
-                }
+                    self.update_firewall_policy(shared_values, false)
+                } else {
+                    SameState(self.into())
+                };
                 let _ = tx.send(());
-                SameState(self.into())
+                consequence
             }
             Some(TunnelCommand::Dns(servers)) => {
                 // Same situation as allow LAN above.
@@ -166,7 +218,8 @@ impl TunnelState for DisconnectedState {
             Some(TunnelCommand::BlockWhenDisconnected(block_when_disconnected)) => {
                 if shared_values.block_when_disconnected != block_when_disconnected {
                     shared_values.block_when_disconnected = block_when_disconnected;
-                    Self::set_firewall_policy(shared_values, true);
+                    self.firewall_release = None;
+                    let security = Self::set_firewall_policy(shared_values, true, self.security);
                     #[cfg(windows)]
                     Self::register_split_tunnel_addresses(shared_values, true);
                     #[cfg(target_os = "macos")]
@@ -184,7 +237,58 @@ impl TunnelState for DisconnectedState {
                     } else {
                         Self::reset_dns(shared_values);
                     }
+                    self.with_security(security)
+                } else {
+                    SameState(self.into())
+                }
+            }
+            Some(TunnelCommand::FlushDnsCache(flush_dns_cache)) => {
+                shared_values.dns_monitor.set_flush_cache(flush_dns_cache);
+                SameState(self.into())
+            }
+            Some(TunnelCommand::RecoveryAllowlist(recovery_allowlist)) => {
+                shared_values.recovery_allowlist = recovery_allowlist;
+                SameState(self.into())
+            }
+            Some(TunnelCommand::VpnCoexistence(vpn_coexistence)) => {
+                if shared_values.vpn_coexistence != vpn_coexistence {
+                    shared_values.vpn_coexistence = vpn_coexistence;
+                    self.update_firewall_policy(shared_values, false)
+                } else {
+                    SameState(self.into())
                 }
+            }
+            Some(TunnelCommand::BlockedTunnelProtocols(blocked_tunnel_protocols)) => {
+                shared_values.blocked_tunnel_protocols = blocked_tunnel_protocols;
+                SameState(self.into())
+            }
+            Some(TunnelCommand::TrafficShaping(_)) => SameState(self.into()),
+            Some(TunnelCommand::ReconnectLimits(reconnect_limits)) => {
+                shared_values.reconnect_budget.set_limits(reconnect_limits);
+                SameState(self.into())
+            }
+            Some(TunnelCommand::RouteTakeoverPolicy(policy)) => {
+                shared_values.route_takeover_policy = policy;
+                SameState(self.into())
+            }
+            Some(TunnelCommand::ErrorStatePolicy(policy)) => {
+                shared_values.error_state_policy = policy;
+                SameState(self.into())
+            }
+            Some(TunnelCommand::SetForcedInterface(forced_interface)) => {
+                shared_values.set_forced_interface(forced_interface);
+                SameState(self.into())
+            }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::ForcedInterfaceChanged(_)) => SameState(self.into()),
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::DefaultInterfaceChanged) => SameState(self.into()),
+            Some(TunnelCommand::HealthCheck(tx)) => {
+                let _ = tx.send(shared_values.health_check(Some(false)));
+                SameState(self.into())
+            }
+            Some(TunnelCommand::GetSystemState(tx)) => {
+                let _ = tx.send(shared_values.system_state());
                 SameState(self.into())
             }
             Some(TunnelCommand::IsOffline(is_offline)) => {
@@ -196,6 +300,7 @@ impl TunnelState for DisconnectedState {
                 Self::reset_dns(shared_values);
                 NewState(ErrorState::enter(shared_values, reason))
             }
+            Some(TunnelCommand::Pause) | Some(TunnelCommand::Resume) => SameState(self.into()),
             #[cfg(target_os = "android")]
             Some(TunnelCommand::BypassSocket(fd, done_tx)) => {
                 shared_values.bypass_socket(fd, done_tx);
@@ -206,7 +311,15 @@ impl TunnelState for DisconnectedState {
                 shared_values.split_tunnel.set_paths(&paths, result_tx);
                 SameState(self.into())
             }
+            #[cfg(windows)]
+            Some(TunnelCommand::ReinstallSplitTunnelDriver(result_tx)) => {
+                shared_values.split_tunnel.reinstall_driver(result_tx);
+                SameState(self.into())
+            }
             None => {
+                if self.firewall_release.is_some() {
+                    Self::set_firewall_policy(shared_values, true, self.security);
+                }
                 Self::reset_dns(shared_values);
                 Finished
             }
@@ -214,3 +327,197 @@ impl TunnelState for DisconnectedState {
         }
     }
 }
+
+impl TunnelState for DisconnectedState {
+    type Bootstrap = (bool, Option<DisconnectCause>);
+
+    fn enter(
+        shared_values: &mut SharedTunnelStateValues,
+        (should_reset_firewall, cause): Self::Bootstrap,
+    ) -> (TunnelStateWrapper, TunnelStateTransition) {
+        shared_values.stop_leak_canary();
+
+        #[cfg(target_os = "macos")]
+        if shared_values.block_when_disconnected {
+            if let Err(err) = Self::setup_local_dns_config(shared_values) {
+                log::error!(
+                    "{}",
+                    err.display_chain_with_msg("Failed to start filtering resolver:")
+                );
+            }
+        } else {
+            let result = {
+                let _watchdog = shared_values.watchdog(Operation::ResetDns);
+                shared_values.dns_monitor.reset()
+            };
+            if let Err(error) = result {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Unable to disable filtering resolver")
+                );
+            }
+        }
+
+        #[cfg(windows)]
+        Self::register_split_tunnel_addresses(shared_values, should_reset_firewall);
+        let (security, firewall_release) = if Self::should_hold_firewall(
+            shared_values.firewall.policy(),
+            shared_values.block_when_disconnected,
+            should_reset_firewall,
+        ) {
+            log::debug!(
+                "Keeping the firewall rules of the tunnel for {} ms",
+                FIREWALL_RELEASE_DELAY.as_millis()
+            );
+            (
+                DisconnectedSecurity::Blocked(DisconnectedBlockReason::ReconnectGracePeriod),
+                Some(Instant::now() + FIREWALL_RELEASE_DELAY),
+            )
+        } else {
+            // Unless reset, the blocking policy applied when the state machine started remains
+            let security = Self::set_firewall_policy(
+                shared_values,
+                should_reset_firewall,
+                DisconnectedSecurity::Blocked(DisconnectedBlockReason::PendingConnect),
+            );
+            (security, None)
+        };
+        #[cfg(target_os = "linux")]
+        shared_values.reset_connectivity_check();
+        #[cfg(target_os = "android")]
+        shared_values.tun_provider.lock().unwrap().close_tun();
+        shared_values.end_connection_attempt();
+
+        (
+            TunnelStateWrapper::from(DisconnectedState {
+                cause: cause.clone(),
+                security,
+                firewall_release,
+            }),
+            TunnelStateTransition::Disconnected { cause, security },
+        )
+    }
+
+    fn handle_event(
+        self,
+        runtime: &tokio::runtime::Handle,
+        commands: &mut TunnelCommandReceiver,
+        shared_values: &mut SharedTunnelStateValues,
+    ) -> EventConsequence {
+        let command = runtime.block_on(async {
+            futures::select! {
+                command = commands.next() => Some(command),
+                _ = Self::wait_for_firewall_release(self.firewall_release).fuse() => None,
+            }
+        });
+
+        match command {
+            Some(command) => self.handle_command(command, shared_values),
+            None => {
+                log::debug!("Removing the firewall rules of the closed tunnel");
+                self.update_firewall_policy(shared_values, true)
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(target_os = "android")))]
+mod test {
+    use super::*;
+    use std::net::Ipv4Addr;
+    #[cfg(windows)]
+    use std::path::PathBuf;
+    use talpid_types::net::{
+        AllowedEndpoint, AllowedTunnelTraffic, Endpoint, TransportProtocol, VpnCoexistence,
+    };
+
+    fn connecting_policy() -> FirewallPolicy {
+        FirewallPolicy::Connecting {
+            peer_endpoint: Endpoint::new(Ipv4Addr::new(1, 2, 3, 4), 51820, TransportProtocol::Udp),
+            tunnel: None,
+            allow_lan: false,
+            allowed_endpoint: AllowedEndpoint {
+                #[cfg(windows)]
+                clients: vec![],
+                endpoints: vec![],
+                hostname: None,
+            },
+            allowed_tunnel_traffic: AllowedTunnelTraffic::None,
+            vpn_coexistence: VpnCoexistence::default(),
+            #[cfg(windows)]
+            relay_client: PathBuf::new(),
+        }
+    }
+
+    fn blocked_policy() -> FirewallPolicy {
+        FirewallPolicy::Blocked {
+            allow_lan: false,
+            allowed_endpoint: None,
+            recovery_allowlist: vec![],
+            vpn_coexistence: VpnCoexistence::default(),
+            #[cfg(target_os = "macos")]
+            dns_redirect_port: 0,
+        }
+    }
+
+    #[test]
+    fn test_should_hold_firewall() {
+        let tunnel_policy = connecting_policy();
+        assert!(DisconnectedState::should_hold_firewall(
+            Some(&tunnel_policy),
+            false,
+            true
+        ));
+
+        // The rules are replaced right away when blocking or when they are not being reset
+        assert!(!DisconnectedState::should_hold_firewall(
+            Some(&tunnel_policy),
+            true,
+            true
+        ));
+        assert!(!DisconnectedState::should_hold_firewall(
+            Some(&tunnel_policy),
+            false,
+            false
+        ));
+
+        // Only the rules of a tunnel are kept
+        assert!(!DisconnectedState::should_hold_firewall(
+            Some(&blocked_policy()),
+            false,
+            true
+        ));
+        assert!(!DisconnectedState::should_hold_firewall(None, false, true));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_firewall_release_delay() {
+        let deadline = Instant::now() + FIREWALL_RELEASE_DELAY;
+
+        let early = FIREWALL_RELEASE_DELAY - Duration::from_millis(100);
+        tokio::time::timeout(
+            early,
+            DisconnectedState::wait_for_firewall_release(Some(deadline)),
+        )
+        .await
+        .expect_err("Firewall rules were released too early");
+
+        let late = Duration::from_millis(200);
+        tokio::time::timeout(
+            late,
+            DisconnectedState::wait_for_firewall_release(Some(deadline)),
+        )
+        .await
+        .expect("Firewall rules were not released after the delay");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_firewall_release_without_deadline() {
+        tokio::time::timeout(
+            Duration::from_secs(60 * 60),
+            DisconnectedState::wait_for_firewall_release(None),
+        )
+        .await
+        .expect_err("Firewall rules that are not kept were released");
+    }
+}
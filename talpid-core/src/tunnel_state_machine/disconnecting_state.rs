@@ -1,10 +1,13 @@
 use super::{
     connecting_state::TunnelCloseEvent, ConnectingState, DisconnectedState, ErrorState,
-    EventConsequence, EventResult, SharedTunnelStateValues, TunnelCommand, TunnelCommandReceiver,
-    TunnelState, TunnelStateTransition, TunnelStateWrapper,
+    EventConsequence, EventResult, PausedState, SharedTunnelStateValues, TunnelCommand,
+    TunnelCommandReceiver, TunnelState, TunnelStateTransition, TunnelStateWrapper,
 };
 use futures::{channel::oneshot, future::FusedFuture, StreamExt};
-use talpid_types::tunnel::{ActionAfterDisconnect, ErrorStateCause};
+use talpid_types::{
+    net::TunnelParameters,
+    tunnel::{ActionAfterDisconnect, DisconnectCause, ErrorStateCause},
+};
 
 /// This state is active from when we manually trigger a tunnel kill until the tunnel wait
 /// operation (TunnelExit) returned.
@@ -22,40 +25,94 @@ impl DisconnectingState {
         let after_disconnect = self.after_disconnect;
 
         self.after_disconnect = match after_disconnect {
-            AfterDisconnect::Nothing => match command {
+            AfterDisconnect::Nothing(cause) => match command {
                 Some(TunnelCommand::AllowLan(allow_lan)) => {
                     let _ = shared_values.set_allow_lan(allow_lan);
-                    AfterDisconnect::Nothing
+                    AfterDisconnect::Nothing(cause)
                 }
                 Some(TunnelCommand::AllowEndpoint(endpoint, tx)) => {
                     shared_values.allowed_endpoint = endpoint;
                     let _ = tx.send(());
-                    AfterDisconnect::Nothing
+                    AfterDisconnect::Nothing(cause)
                 }
                 Some(TunnelCommand::Dns(servers)) => {
                     let _ = shared_values.set_dns_servers(servers);
-                    AfterDisconnect::Nothing
+                    AfterDisconnect::Nothing(cause)
                 }
                 Some(TunnelCommand::BlockWhenDisconnected(block_when_disconnected)) => {
                     shared_values.block_when_disconnected = block_when_disconnected;
-                    AfterDisconnect::Nothing
+                    AfterDisconnect::Nothing(cause)
+                }
+                Some(TunnelCommand::FlushDnsCache(flush_dns_cache)) => {
+                    shared_values.dns_monitor.set_flush_cache(flush_dns_cache);
+                    AfterDisconnect::Nothing(cause)
+                }
+                Some(TunnelCommand::RecoveryAllowlist(recovery_allowlist)) => {
+                    shared_values.recovery_allowlist = recovery_allowlist;
+                    AfterDisconnect::Nothing(cause)
+                }
+                Some(TunnelCommand::VpnCoexistence(vpn_coexistence)) => {
+                    shared_values.vpn_coexistence = vpn_coexistence;
+                    AfterDisconnect::Nothing(cause)
+                }
+                Some(TunnelCommand::BlockedTunnelProtocols(blocked_tunnel_protocols)) => {
+                    shared_values.blocked_tunnel_protocols = blocked_tunnel_protocols;
+                    AfterDisconnect::Nothing(cause)
+                }
+                Some(TunnelCommand::TrafficShaping(_)) => AfterDisconnect::Nothing(cause),
+                Some(TunnelCommand::ReconnectLimits(reconnect_limits)) => {
+                    shared_values.reconnect_budget.set_limits(reconnect_limits);
+                    AfterDisconnect::Nothing(cause)
+                }
+                Some(TunnelCommand::RouteTakeoverPolicy(policy)) => {
+                    shared_values.route_takeover_policy = policy;
+                    AfterDisconnect::Nothing(cause)
+                }
+                Some(TunnelCommand::ErrorStatePolicy(policy)) => {
+                    shared_values.error_state_policy = policy;
+                    AfterDisconnect::Nothing(cause)
+                }
+                Some(TunnelCommand::SetForcedInterface(forced_interface)) => {
+                    shared_values.set_forced_interface(forced_interface);
+                    AfterDisconnect::Nothing(cause)
+                }
+                #[cfg(not(target_os = "android"))]
+                Some(TunnelCommand::ForcedInterfaceChanged(_)) => AfterDisconnect::Nothing(cause),
+                #[cfg(not(target_os = "android"))]
+                Some(TunnelCommand::DefaultInterfaceChanged) => AfterDisconnect::Nothing(cause),
+                Some(TunnelCommand::HealthCheck(tx)) => {
+                    let _ = tx.send(shared_values.health_check(None));
+                    AfterDisconnect::Nothing(cause)
+                }
+                Some(TunnelCommand::GetSystemState(tx)) => {
+                    let _ = tx.send(shared_values.system_state());
+                    AfterDisconnect::Nothing(cause)
                 }
                 Some(TunnelCommand::IsOffline(is_offline)) => {
                     shared_values.is_offline = is_offline;
-                    AfterDisconnect::Nothing
+                    AfterDisconnect::Nothing(cause)
                 }
                 Some(TunnelCommand::Connect) => AfterDisconnect::Reconnect(0),
-                Some(TunnelCommand::Disconnect) | None => AfterDisconnect::Nothing,
+                Some(TunnelCommand::Disconnect(new_cause)) => AfterDisconnect::Nothing(new_cause),
+                None => AfterDisconnect::Nothing(DisconnectCause::Shutdown),
                 Some(TunnelCommand::Block(reason)) => AfterDisconnect::Block(reason),
+                Some(TunnelCommand::Pause) | Some(TunnelCommand::Resume) => {
+                    AfterDisconnect::Nothing(cause)
+                }
                 #[cfg(target_os = "android")]
                 Some(TunnelCommand::BypassSocket(fd, done_tx)) => {
                     shared_values.bypass_socket(fd, done_tx);
-                    AfterDisconnect::Nothing
+                    AfterDisconnect::Nothing(cause)
                 }
                 #[cfg(windows)]
                 Some(TunnelCommand::SetExcludedApps(result_tx, paths)) => {
                     shared_values.split_tunnel.set_paths(&paths, result_tx);
-                    AfterDisconnect::Nothing
+                    AfterDisconnect::Nothing(cause)
+                }
+                #[cfg(windows)]
+                Some(TunnelCommand::ReinstallSplitTunnelDriver(result_tx)) => {
+                    shared_values.split_tunnel.reinstall_driver(result_tx);
+                    AfterDisconnect::Nothing(cause)
                 }
             },
             AfterDisconnect::Block(reason) => match command {
@@ -76,6 +133,67 @@ impl DisconnectingState {
                     shared_values.block_when_disconnected = block_when_disconnected;
                     AfterDisconnect::Block(reason)
                 }
+                Some(TunnelCommand::FlushDnsCache(flush_dns_cache)) => {
+                    shared_values.dns_monitor.set_flush_cache(flush_dns_cache);
+                    AfterDisconnect::Block(reason)
+                }
+                Some(TunnelCommand::RecoveryAllowlist(recovery_allowlist)) => {
+                    shared_values.recovery_allowlist = recovery_allowlist;
+                    AfterDisconnect::Block(reason)
+                }
+                Some(TunnelCommand::VpnCoexistence(vpn_coexistence)) => {
+                    shared_values.vpn_coexistence = vpn_coexistence;
+                    AfterDisconnect::Block(reason)
+                }
+                Some(TunnelCommand::BlockedTunnelProtocols(blocked_tunnel_protocols)) => {
+                    shared_values.blocked_tunnel_protocols = blocked_tunnel_protocols;
+                    AfterDisconnect::Block(reason)
+                }
+                Some(TunnelCommand::TrafficShaping(_)) => AfterDisconnect::Block(reason),
+                Some(TunnelCommand::ReconnectLimits(reconnect_limits)) => {
+                    shared_values.reconnect_budget.set_limits(reconnect_limits);
+                    AfterDisconnect::Block(reason)
+                }
+                Some(TunnelCommand::RouteTakeoverPolicy(policy)) => {
+                    shared_values.route_takeover_policy = policy;
+                    AfterDisconnect::Block(reason)
+                }
+                Some(TunnelCommand::ErrorStatePolicy(policy)) => {
+                    shared_values.error_state_policy = policy;
+                    AfterDisconnect::Block(reason)
+                }
+                Some(TunnelCommand::SetForcedInterface(forced_interface)) => {
+                    shared_values.set_forced_interface(forced_interface);
+                    AfterDisconnect::Block(reason)
+                }
+                #[cfg(not(target_os = "android"))]
+                Some(TunnelCommand::ForcedInterfaceChanged(index)) => {
+                    if index.is_some()
+                        && matches!(reason, ErrorStateCause::ForcedInterfaceUnavailable)
+                    {
+                        AfterDisconnect::Reconnect(0)
+                    } else {
+                        AfterDisconnect::Block(reason)
+                    }
+                }
+                #[cfg(not(target_os = "android"))]
+                Some(TunnelCommand::DefaultInterfaceChanged) => {
+                    if matches!(reason, ErrorStateCause::TooManyAttempts)
+                        && !shared_values.is_offline
+                    {
+                        AfterDisconnect::Reconnect(0)
+                    } else {
+                        AfterDisconnect::Block(reason)
+                    }
+                }
+                Some(TunnelCommand::HealthCheck(tx)) => {
+                    let _ = tx.send(shared_values.health_check(None));
+                    AfterDisconnect::Block(reason)
+                }
+                Some(TunnelCommand::GetSystemState(tx)) => {
+                    let _ = tx.send(shared_values.system_state());
+                    AfterDisconnect::Block(reason)
+                }
                 Some(TunnelCommand::IsOffline(is_offline)) => {
                     shared_values.is_offline = is_offline;
                     if !is_offline && matches!(reason, ErrorStateCause::IsOffline) {
@@ -85,8 +203,14 @@ impl DisconnectingState {
                     }
                 }
                 Some(TunnelCommand::Connect) => AfterDisconnect::Reconnect(0),
-                Some(TunnelCommand::Disconnect) => AfterDisconnect::Nothing,
+                Some(TunnelCommand::Disconnect(DisconnectCause::UserInitiated)) => {
+                    AfterDisconnect::Nothing(DisconnectCause::Error(reason))
+                }
+                Some(TunnelCommand::Disconnect(cause)) => AfterDisconnect::Nothing(cause),
                 Some(TunnelCommand::Block(new_reason)) => AfterDisconnect::Block(new_reason),
+                Some(TunnelCommand::Pause) | Some(TunnelCommand::Resume) => {
+                    AfterDisconnect::Block(reason)
+                }
                 #[cfg(target_os = "android")]
                 Some(TunnelCommand::BypassSocket(fd, done_tx)) => {
                     shared_values.bypass_socket(fd, done_tx);
@@ -97,6 +221,11 @@ impl DisconnectingState {
                     shared_values.split_tunnel.set_paths(&paths, result_tx);
                     AfterDisconnect::Block(reason)
                 }
+                #[cfg(windows)]
+                Some(TunnelCommand::ReinstallSplitTunnelDriver(result_tx)) => {
+                    shared_values.split_tunnel.reinstall_driver(result_tx);
+                    AfterDisconnect::Block(reason)
+                }
                 None => AfterDisconnect::Block(reason),
             },
             AfterDisconnect::Reconnect(retry_attempt) => match command {
@@ -117,6 +246,59 @@ impl DisconnectingState {
                     shared_values.block_when_disconnected = block_when_disconnected;
                     AfterDisconnect::Reconnect(retry_attempt)
                 }
+                Some(TunnelCommand::FlushDnsCache(flush_dns_cache)) => {
+                    shared_values.dns_monitor.set_flush_cache(flush_dns_cache);
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
+                Some(TunnelCommand::RecoveryAllowlist(recovery_allowlist)) => {
+                    shared_values.recovery_allowlist = recovery_allowlist;
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
+                Some(TunnelCommand::VpnCoexistence(vpn_coexistence)) => {
+                    shared_values.vpn_coexistence = vpn_coexistence;
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
+                Some(TunnelCommand::BlockedTunnelProtocols(blocked_tunnel_protocols)) => {
+                    shared_values.blocked_tunnel_protocols = blocked_tunnel_protocols;
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
+                Some(TunnelCommand::TrafficShaping(_)) => AfterDisconnect::Reconnect(retry_attempt),
+                Some(TunnelCommand::ReconnectLimits(reconnect_limits)) => {
+                    shared_values.reconnect_budget.set_limits(reconnect_limits);
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
+                Some(TunnelCommand::RouteTakeoverPolicy(policy)) => {
+                    shared_values.route_takeover_policy = policy;
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
+                Some(TunnelCommand::ErrorStatePolicy(policy)) => {
+                    shared_values.error_state_policy = policy;
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
+                Some(TunnelCommand::SetForcedInterface(forced_interface)) => {
+                    shared_values.set_forced_interface(forced_interface);
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
+                #[cfg(not(target_os = "android"))]
+                Some(TunnelCommand::ForcedInterfaceChanged(index)) => {
+                    if index.is_none() {
+                        AfterDisconnect::Block(ErrorStateCause::ForcedInterfaceUnavailable)
+                    } else {
+                        AfterDisconnect::Reconnect(retry_attempt)
+                    }
+                }
+                #[cfg(not(target_os = "android"))]
+                Some(TunnelCommand::DefaultInterfaceChanged) => {
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
+                Some(TunnelCommand::HealthCheck(tx)) => {
+                    let _ = tx.send(shared_values.health_check(None));
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
+                Some(TunnelCommand::GetSystemState(tx)) => {
+                    let _ = tx.send(shared_values.system_state());
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
                 Some(TunnelCommand::IsOffline(is_offline)) => {
                     shared_values.is_offline = is_offline;
                     if is_offline {
@@ -126,8 +308,16 @@ impl DisconnectingState {
                     }
                 }
                 Some(TunnelCommand::Connect) => AfterDisconnect::Reconnect(retry_attempt),
-                Some(TunnelCommand::Disconnect) | None => AfterDisconnect::Nothing,
+                Some(TunnelCommand::Disconnect(DisconnectCause::UserInitiated)) => {
+                    AfterDisconnect::Nothing(DisconnectCause::Reconnecting)
+                }
+                Some(TunnelCommand::Disconnect(cause)) => AfterDisconnect::Nothing(cause),
+                None => AfterDisconnect::Nothing(DisconnectCause::Shutdown),
                 Some(TunnelCommand::Block(reason)) => AfterDisconnect::Block(reason),
+                // The parameters of the closed tunnel are not known here
+                Some(TunnelCommand::Pause) | Some(TunnelCommand::Resume) => {
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
                 #[cfg(target_os = "android")]
                 Some(TunnelCommand::BypassSocket(fd, done_tx)) => {
                     shared_values.bypass_socket(fd, done_tx);
@@ -138,6 +328,105 @@ impl DisconnectingState {
                     shared_values.split_tunnel.set_paths(&paths, result_tx);
                     AfterDisconnect::Reconnect(retry_attempt)
                 }
+                #[cfg(windows)]
+                Some(TunnelCommand::ReinstallSplitTunnelDriver(result_tx)) => {
+                    shared_values.split_tunnel.reinstall_driver(result_tx);
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
+            },
+            AfterDisconnect::Pause(tunnel_parameters) => match command {
+                Some(TunnelCommand::AllowLan(allow_lan)) => {
+                    let _ = shared_values.set_allow_lan(allow_lan);
+                    AfterDisconnect::Pause(tunnel_parameters)
+                }
+                Some(TunnelCommand::AllowEndpoint(endpoint, tx)) => {
+                    shared_values.allowed_endpoint = endpoint;
+                    let _ = tx.send(());
+                    AfterDisconnect::Pause(tunnel_parameters)
+                }
+                Some(TunnelCommand::Dns(servers)) => {
+                    let _ = shared_values.set_dns_servers(servers);
+                    AfterDisconnect::Pause(tunnel_parameters)
+                }
+                Some(TunnelCommand::BlockWhenDisconnected(block_when_disconnected)) => {
+                    shared_values.block_when_disconnected = block_when_disconnected;
+                    AfterDisconnect::Pause(tunnel_parameters)
+                }
+                Some(TunnelCommand::FlushDnsCache(flush_dns_cache)) => {
+                    shared_values.dns_monitor.set_flush_cache(flush_dns_cache);
+                    AfterDisconnect::Pause(tunnel_parameters)
+                }
+                Some(TunnelCommand::RecoveryAllowlist(recovery_allowlist)) => {
+                    shared_values.recovery_allowlist = recovery_allowlist;
+                    AfterDisconnect::Pause(tunnel_parameters)
+                }
+                Some(TunnelCommand::VpnCoexistence(vpn_coexistence)) => {
+                    shared_values.vpn_coexistence = vpn_coexistence;
+                    AfterDisconnect::Pause(tunnel_parameters)
+                }
+                Some(TunnelCommand::BlockedTunnelProtocols(blocked_tunnel_protocols)) => {
+                    shared_values.blocked_tunnel_protocols = blocked_tunnel_protocols;
+                    AfterDisconnect::Pause(tunnel_parameters)
+                }
+                Some(TunnelCommand::TrafficShaping(_)) => AfterDisconnect::Pause(tunnel_parameters),
+                Some(TunnelCommand::ReconnectLimits(reconnect_limits)) => {
+                    shared_values.reconnect_budget.set_limits(reconnect_limits);
+                    AfterDisconnect::Pause(tunnel_parameters)
+                }
+                Some(TunnelCommand::RouteTakeoverPolicy(policy)) => {
+                    shared_values.route_takeover_policy = policy;
+                    AfterDisconnect::Pause(tunnel_parameters)
+                }
+                Some(TunnelCommand::ErrorStatePolicy(policy)) => {
+                    shared_values.error_state_policy = policy;
+                    AfterDisconnect::Pause(tunnel_parameters)
+                }
+                Some(TunnelCommand::SetForcedInterface(forced_interface)) => {
+                    shared_values.set_forced_interface(forced_interface);
+                    AfterDisconnect::Pause(tunnel_parameters)
+                }
+                #[cfg(not(target_os = "android"))]
+                Some(TunnelCommand::ForcedInterfaceChanged(_)) => {
+                    AfterDisconnect::Pause(tunnel_parameters)
+                }
+                #[cfg(not(target_os = "android"))]
+                Some(TunnelCommand::DefaultInterfaceChanged) => {
+                    AfterDisconnect::Pause(tunnel_parameters)
+                }
+                Some(TunnelCommand::HealthCheck(tx)) => {
+                    let _ = tx.send(shared_values.health_check(None));
+                    AfterDisconnect::Pause(tunnel_parameters)
+                }
+                Some(TunnelCommand::GetSystemState(tx)) => {
+                    let _ = tx.send(shared_values.system_state());
+                    AfterDisconnect::Pause(tunnel_parameters)
+                }
+                Some(TunnelCommand::IsOffline(is_offline)) => {
+                    shared_values.is_offline = is_offline;
+                    AfterDisconnect::Pause(tunnel_parameters)
+                }
+                Some(TunnelCommand::Connect) | Some(TunnelCommand::Resume) => {
+                    AfterDisconnect::Reconnect(0)
+                }
+                Some(TunnelCommand::Disconnect(cause)) => AfterDisconnect::Nothing(cause),
+                None => AfterDisconnect::Nothing(DisconnectCause::Shutdown),
+                Some(TunnelCommand::Block(reason)) => AfterDisconnect::Block(reason),
+                Some(TunnelCommand::Pause) => AfterDisconnect::Pause(tunnel_parameters),
+                #[cfg(target_os = "android")]
+                Some(TunnelCommand::BypassSocket(fd, done_tx)) => {
+                    shared_values.bypass_socket(fd, done_tx);
+                    AfterDisconnect::Pause(tunnel_parameters)
+                }
+                #[cfg(windows)]
+                Some(TunnelCommand::SetExcludedApps(result_tx, paths)) => {
+                    shared_values.split_tunnel.set_paths(&paths, result_tx);
+                    AfterDisconnect::Pause(tunnel_parameters)
+                }
+                #[cfg(windows)]
+                Some(TunnelCommand::ReinstallSplitTunnelDriver(result_tx)) => {
+                    shared_values.split_tunnel.reinstall_driver(result_tx);
+                    AfterDisconnect::Pause(tunnel_parameters)
+                }
             },
         };
 
@@ -154,11 +443,16 @@ impl DisconnectingState {
         }
 
         match self.after_disconnect {
-            AfterDisconnect::Nothing => DisconnectedState::enter(shared_values, true),
+            AfterDisconnect::Nothing(cause) => {
+                DisconnectedState::enter(shared_values, (true, Some(cause)))
+            }
             AfterDisconnect::Block(cause) => ErrorState::enter(shared_values, cause),
             AfterDisconnect::Reconnect(retry_attempt) => {
                 ConnectingState::enter(shared_values, retry_attempt)
             }
+            AfterDisconnect::Pause(tunnel_parameters) => {
+                PausedState::enter(shared_values, tunnel_parameters)
+            }
         }
     }
 }
@@ -167,9 +461,12 @@ impl TunnelState for DisconnectingState {
     type Bootstrap = (oneshot::Sender<()>, TunnelCloseEvent, AfterDisconnect);
 
     fn enter(
-        _: &mut SharedTunnelStateValues,
+        shared_values: &mut SharedTunnelStateValues,
         (tunnel_close_tx, tunnel_close_event, after_disconnect): Self::Bootstrap,
     ) -> (TunnelStateWrapper, TunnelStateTransition) {
+        if let AfterDisconnect::Reconnect(_) = after_disconnect {
+            shared_values.start_leak_canary();
+        }
         let _ = tunnel_close_tx.send(());
         let action_after_disconnect = after_disconnect.action();
 
@@ -220,18 +517,21 @@ impl TunnelState for DisconnectingState {
 
 /// Which state should be transitioned to after disconnection is complete.
 pub enum AfterDisconnect {
-    Nothing,
+    Nothing(DisconnectCause),
     Block(ErrorStateCause),
     Reconnect(u32),
+    Pause(TunnelParameters),
 }
 
 impl AfterDisconnect {
     /// Build event representation of the action that will be taken after the disconnection.
     pub fn action(&self) -> ActionAfterDisconnect {
         match self {
-            AfterDisconnect::Nothing => ActionAfterDisconnect::Nothing,
+            AfterDisconnect::Nothing(..) => ActionAfterDisconnect::Nothing,
             AfterDisconnect::Block(..) => ActionAfterDisconnect::Block,
             AfterDisconnect::Reconnect(..) => ActionAfterDisconnect::Reconnect,
+            // Traffic outside the tunnel remains blocked while the tunnel is paused
+            AfterDisconnect::Pause(..) => ActionAfterDisconnect::Block,
         }
     }
 }
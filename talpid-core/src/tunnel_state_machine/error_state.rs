@@ -3,11 +3,15 @@ use super::{
     TunnelCommandReceiver, TunnelState, TunnelStateTransition, TunnelStateWrapper,
 };
 use crate::firewall::FirewallPolicy;
+#[cfg(target_os = "macos")]
+use crate::resolver::ResolverConfig;
 use futures::StreamExt;
 #[cfg(target_os = "macos")]
 use std::net::Ipv4Addr;
 use talpid_types::{
-    tunnel::{self as talpid_tunnel, ErrorStateCause, FirewallPolicyError},
+    net::VpnCoexistence,
+    performance::Operation,
+    tunnel::{self as talpid_tunnel, DisconnectCause, ErrorStateCause, FirewallPolicyError},
     ErrorExt,
 };
 
@@ -19,17 +23,39 @@ pub struct ErrorState {
 impl ErrorState {
     fn set_firewall_policy(
         shared_values: &mut SharedTunnelStateValues,
+        block_reason: &ErrorStateCause,
     ) -> Result<(), FirewallPolicyError> {
-        let policy = FirewallPolicy::Blocked {
-            allow_lan: shared_values.allow_lan,
-            allowed_endpoint: Some(shared_values.allowed_endpoint.clone()),
-            #[cfg(target_os = "macos")]
-            dns_redirect_port: shared_values.filtering_resolver.listening_port(),
+        let policy = if block_reason.allows_only_api() {
+            FirewallPolicy::Blocked {
+                allow_lan: false,
+                allowed_endpoint: Some(shared_values.allowed_endpoint.clone()),
+                recovery_allowlist: vec![],
+                vpn_coexistence: VpnCoexistence::default(),
+                #[cfg(target_os = "macos")]
+                dns_redirect_port: shared_values.filtering_resolver.listening_port(),
+            }
+        } else {
+            FirewallPolicy::Blocked {
+                allow_lan: shared_values
+                    .error_state_policy
+                    .allows_lan(shared_values.allow_lan),
+                allowed_endpoint: Some(shared_values.allowed_endpoint.clone()),
+                recovery_allowlist: shared_values.recovery_allowlist.clone(),
+                vpn_coexistence: shared_values.vpn_coexistence.clone(),
+                #[cfg(target_os = "macos")]
+                dns_redirect_port: shared_values.filtering_resolver.listening_port(),
+            }
         };
 
         #[cfg(target_os = "linux")]
         shared_values.disable_connectivity_check();
 
+        #[cfg(target_os = "macos")]
+        shared_values
+            .filtering_resolver
+            .set_config(ResolverConfig::blocking(&shared_values.allowed_endpoint));
+
+        let _watchdog = shared_values.watchdog(Operation::ApplyFirewallPolicy);
         shared_values
             .firewall
             .apply_policy(policy)
@@ -71,6 +97,7 @@ impl ErrorState {
     }
 
     fn reset_dns(shared_values: &mut SharedTunnelStateValues) {
+        let _watchdog = shared_values.watchdog(Operation::ResetDns);
         if let Err(error) = shared_values.dns_monitor.reset() {
             log::error!("{}", error.display_chain_with_msg("Unable to reset DNS"));
         }
@@ -84,6 +111,8 @@ impl TunnelState for ErrorState {
         shared_values: &mut SharedTunnelStateValues,
         block_reason: Self::Bootstrap,
     ) -> (TunnelStateWrapper, TunnelStateTransition) {
+        shared_values.stop_leak_canary();
+
         #[cfg(windows)]
         if let Err(error) = shared_values.split_tunnel.set_tunnel_addresses(None) {
             log::error!(
@@ -96,10 +125,13 @@ impl TunnelState for ErrorState {
 
         #[cfg(target_os = "macos")]
         if !block_reason.prevents_filtering_resolver() {
-            if let Err(err) = shared_values
-                .dns_monitor
-                .set("lo", &[Ipv4Addr::LOCALHOST.into()])
-            {
+            let result = {
+                let _watchdog = shared_values.watchdog(Operation::SetDns);
+                shared_values
+                    .dns_monitor
+                    .set("lo", &[Ipv4Addr::LOCALHOST.into()])
+            };
+            if let Err(err) = result {
                 log::error!(
                     "{}",
                     err.display_chain_with_msg(
@@ -111,7 +143,13 @@ impl TunnelState for ErrorState {
         };
 
         #[cfg(not(target_os = "android"))]
-        let block_failure = Self::set_firewall_policy(shared_values).err();
+        let block_failure = Self::set_firewall_policy(shared_values, &block_reason).err();
+        #[cfg(not(target_os = "android"))]
+        let recovery_allowlist = if block_reason.allows_only_api() {
+            vec![]
+        } else {
+            shared_values.recovery_allowlist.clone()
+        };
 
         #[cfg(target_os = "android")]
         let block_failure = if !Self::create_blocking_tun(shared_values) {
@@ -119,6 +157,10 @@ impl TunnelState for ErrorState {
         } else {
             None
         };
+        // The allowlist is enforced by the firewall, which is not used on Android.
+        #[cfg(target_os = "android")]
+        let recovery_allowlist = vec![];
+
         (
             TunnelStateWrapper::from(ErrorState {
                 block_reason: block_reason.clone(),
@@ -126,6 +168,7 @@ impl TunnelState for ErrorState {
             TunnelStateTransition::Error(talpid_tunnel::ErrorState::new(
                 block_reason,
                 block_failure,
+                recovery_allowlist,
             )),
         )
     }
@@ -144,14 +187,14 @@ impl TunnelState for ErrorState {
                 if let Err(error_state_cause) = shared_values.set_allow_lan(allow_lan) {
                     NewState(Self::enter(shared_values, error_state_cause))
                 } else {
-                    let _ = Self::set_firewall_policy(shared_values);
+                    let _ = Self::set_firewall_policy(shared_values, &self.block_reason);
                     SameState(self.into())
                 }
             }
             Some(TunnelCommand::AllowEndpoint(endpoint, tx)) => {
                 if shared_values.allowed_endpoint != endpoint {
                     shared_values.allowed_endpoint = endpoint;
-                    let _ = Self::set_firewall_policy(shared_values);
+                    let _ = Self::set_firewall_policy(shared_values, &self.block_reason);
 
                     #[cfg(target_os = "android")]
                     if !Self::create_blocking_tun(shared_values) {
@@ -176,9 +219,108 @@ impl TunnelState for ErrorState {
                 shared_values.block_when_disconnected = block_when_disconnected;
                 SameState(self.into())
             }
+            Some(TunnelCommand::FlushDnsCache(flush_dns_cache)) => {
+                shared_values.dns_monitor.set_flush_cache(flush_dns_cache);
+                SameState(self.into())
+            }
+            Some(TunnelCommand::RecoveryAllowlist(recovery_allowlist)) => {
+                if shared_values.recovery_allowlist != recovery_allowlist {
+                    shared_values.recovery_allowlist = recovery_allowlist;
+                    // Re-enter the state to apply the new firewall policy and to report the
+                    // allowlist in the transition.
+                    NewState(Self::enter(shared_values, self.block_reason))
+                } else {
+                    SameState(self.into())
+                }
+            }
+            Some(TunnelCommand::VpnCoexistence(vpn_coexistence)) => {
+                if shared_values.vpn_coexistence != vpn_coexistence {
+                    shared_values.vpn_coexistence = vpn_coexistence;
+                    let _ = Self::set_firewall_policy(shared_values, &self.block_reason);
+                }
+                SameState(self.into())
+            }
+            Some(TunnelCommand::BlockedTunnelProtocols(blocked_tunnel_protocols)) => {
+                shared_values.blocked_tunnel_protocols = blocked_tunnel_protocols;
+                SameState(self.into())
+            }
+            Some(TunnelCommand::TrafficShaping(_)) => SameState(self.into()),
+            Some(TunnelCommand::ReconnectLimits(reconnect_limits)) => {
+                shared_values.reconnect_budget.set_limits(reconnect_limits);
+                SameState(self.into())
+            }
+            Some(TunnelCommand::RouteTakeoverPolicy(policy)) => {
+                shared_values.route_takeover_policy = policy;
+                SameState(self.into())
+            }
+            Some(TunnelCommand::ErrorStatePolicy(policy)) => {
+                if shared_values.error_state_policy != policy {
+                    shared_values.error_state_policy = policy;
+                    let _ = Self::set_firewall_policy(shared_values, &self.block_reason);
+                }
+                SameState(self.into())
+            }
+            Some(TunnelCommand::SetForcedInterface(forced_interface)) => {
+                #[cfg_attr(target_os = "android", allow(unused_variables))]
+                let changed = shared_values.set_forced_interface(forced_interface);
+                #[cfg(not(target_os = "android"))]
+                if changed
+                    && matches!(
+                        self.block_reason,
+                        ErrorStateCause::ForcedInterfaceUnavailable
+                    )
+                {
+                    // Another interface, or none at all, may be usable now
+                    Self::reset_dns(shared_values);
+                    return NewState(ConnectingState::enter(shared_values, 0));
+                }
+                SameState(self.into())
+            }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::ForcedInterfaceChanged(index)) => {
+                if index.is_some()
+                    && matches!(
+                        self.block_reason,
+                        ErrorStateCause::ForcedInterfaceUnavailable
+                    )
+                {
+                    Self::reset_dns(shared_values);
+                    NewState(ConnectingState::enter(shared_values, 0))
+                } else {
+                    SameState(self.into())
+                }
+            }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::DefaultInterfaceChanged) => {
+                // Joining another network may have fixed the problem
+                if matches!(self.block_reason, ErrorStateCause::TooManyAttempts)
+                    && !shared_values.is_offline
+                {
+                    Self::reset_dns(shared_values);
+                    NewState(ConnectingState::enter(shared_values, 0))
+                } else {
+                    SameState(self.into())
+                }
+            }
+            Some(TunnelCommand::HealthCheck(tx)) => {
+                let _ = tx.send(shared_values.health_check(None));
+                SameState(self.into())
+            }
+            Some(TunnelCommand::GetSystemState(tx)) => {
+                let _ = tx.send(shared_values.system_state());
+                SameState(self.into())
+            }
             Some(TunnelCommand::IsOffline(is_offline)) => {
-                shared_values.is_offline = is_offline;
-                if !is_offline && matches!(self.block_reason, ErrorStateCause::IsOffline) {
+                let was_offline = std::mem::replace(&mut shared_values.is_offline, is_offline);
+                let resume = match self.block_reason {
+                    ErrorStateCause::IsOffline => !is_offline,
+                    // Coming back online is a network change, which may have fixed the problem
+                    ErrorStateCause::TooManyAttempts => was_offline && !is_offline,
+                    #[cfg(target_os = "linux")]
+                    ErrorStateCause::GatewayUnreachable => was_offline && !is_offline,
+                    _ => false,
+                };
+                if resume {
                     Self::reset_dns(shared_values);
                     NewState(ConnectingState::enter(shared_values, 0))
                 } else {
@@ -190,15 +332,29 @@ impl TunnelState for ErrorState {
 
                 NewState(ConnectingState::enter(shared_values, 0))
             }
-            Some(TunnelCommand::Disconnect) | None => {
+            Some(TunnelCommand::Disconnect(cause)) => {
                 #[cfg(target_os = "linux")]
                 shared_values.reset_connectivity_check();
                 Self::reset_dns(shared_values);
-                NewState(DisconnectedState::enter(shared_values, true))
+                let cause = match cause {
+                    DisconnectCause::UserInitiated => DisconnectCause::Error(self.block_reason),
+                    cause => cause,
+                };
+                NewState(DisconnectedState::enter(shared_values, (true, Some(cause))))
+            }
+            None => {
+                #[cfg(target_os = "linux")]
+                shared_values.reset_connectivity_check();
+                Self::reset_dns(shared_values);
+                NewState(DisconnectedState::enter(
+                    shared_values,
+                    (true, Some(DisconnectCause::Shutdown)),
+                ))
             }
             Some(TunnelCommand::Block(reason)) => {
                 NewState(ErrorState::enter(shared_values, reason))
             }
+            Some(TunnelCommand::Pause) | Some(TunnelCommand::Resume) => SameState(self.into()),
             #[cfg(target_os = "android")]
             Some(TunnelCommand::BypassSocket(fd, done_tx)) => {
                 shared_values.bypass_socket(fd, done_tx);
@@ -209,6 +365,11 @@ impl TunnelState for ErrorState {
                 shared_values.split_tunnel.set_paths(&paths, result_tx);
                 SameState(self.into())
             }
+            #[cfg(windows)]
+            Some(TunnelCommand::ReinstallSplitTunnelDriver(result_tx)) => {
+                shared_values.split_tunnel.reinstall_driver(result_tx);
+                SameState(self.into())
+            }
         }
     }
 }
@@ -0,0 +1,140 @@
+//! Watches the physical interface that traffic to the relay is forced to leave through.
+//!
+//! The sockets of the tunnel are bound to the index of the interface, so the tunnel stops working
+//! if the interface disappears, and it does not start working again if the interface is recreated
+//! with a new index. The state machine is notified of both, so that it can block while the
+//! interface is gone and reconnect once it is back.
+
+use super::TunnelCommand;
+use futures::channel::mpsc;
+use std::{sync::Weak, time::Duration};
+
+/// How often the interface is looked up.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Handle to the task that watches the forced interface.
+pub struct ForcedInterfaceMonitor {
+    runtime: tokio::runtime::Handle,
+    command_tx: Weak<mpsc::UnboundedSender<TunnelCommand>>,
+    task: Option<tokio::task::JoinHandle<()>>,
+}
+
+impl ForcedInterfaceMonitor {
+    /// Creates a monitor that sends [`TunnelCommand::ForcedInterfaceChanged`] to `command_tx`.
+    pub fn new(
+        runtime: tokio::runtime::Handle,
+        command_tx: Weak<mpsc::UnboundedSender<TunnelCommand>>,
+        interface: Option<String>,
+    ) -> Self {
+        let mut monitor = ForcedInterfaceMonitor {
+            runtime,
+            command_tx,
+            task: None,
+        };
+        monitor.set_interface(interface);
+        monitor
+    }
+
+    /// Starts watching `interface` instead of the current interface, or stops watching if it is
+    /// `None`.
+    pub fn set_interface(&mut self, interface: Option<String>) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+        if let Some(interface) = interface {
+            self.task = Some(self.runtime.spawn(watch(
+                interface,
+                crate::routing::interface_index,
+                POLL_INTERVAL,
+                self.command_tx.clone(),
+            )));
+        }
+    }
+}
+
+impl Drop for ForcedInterfaceMonitor {
+    fn drop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
+}
+
+/// Looks up the index of `interface` every `interval`, and sends it to `command_tx` whenever it
+/// changes. Returns once the state machine has stopped.
+async fn watch(
+    interface: String,
+    lookup: impl Fn(&str) -> Option<u32>,
+    interval: Duration,
+    command_tx: Weak<mpsc::UnboundedSender<TunnelCommand>>,
+) {
+    let mut index = lookup(&interface);
+    loop {
+        tokio::time::sleep(interval).await;
+        let command_tx = match command_tx.upgrade() {
+            Some(command_tx) => command_tx,
+            None => break,
+        };
+
+        let new_index = lookup(&interface);
+        if new_index == index {
+            continue;
+        }
+        index = new_index;
+        match index {
+            Some(index) => log::info!("Forced interface {} has index {}", interface, index),
+            None => log::warn!("Forced interface {} disappeared", interface),
+        }
+        if command_tx
+            .unbounded_send(TunnelCommand::ForcedInterfaceChanged(index))
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::StreamExt;
+    use std::sync::{Arc, Mutex};
+
+    #[tokio::test(start_paused = true)]
+    async fn test_reports_changes() {
+        let (command_tx, mut command_rx) = mpsc::unbounded();
+        let command_tx = Arc::new(command_tx);
+
+        let indices = Mutex::new(vec![Some(3), Some(3), None, None, Some(5)].into_iter());
+        let lookup = move |name: &str| {
+            assert_eq!(name, "eth1");
+            indices.lock().unwrap().next().unwrap_or(Some(5))
+        };
+        let task = tokio::spawn(watch(
+            "eth1".to_owned(),
+            lookup,
+            POLL_INTERVAL,
+            Arc::downgrade(&command_tx),
+        ));
+
+        assert!(matches!(
+            command_rx.next().await,
+            Some(TunnelCommand::ForcedInterfaceChanged(None))
+        ));
+        assert!(matches!(
+            command_rx.next().await,
+            Some(TunnelCommand::ForcedInterfaceChanged(Some(5)))
+        ));
+
+        // Nothing is sent while the interface stays the same
+        tokio::time::sleep(POLL_INTERVAL * 10).await;
+        assert!(command_rx.try_next().is_err());
+
+        // The monitor stops along with the state machine
+        drop(command_tx);
+        tokio::time::timeout(POLL_INTERVAL * 2, task)
+            .await
+            .expect("Monitor did not stop")
+            .unwrap();
+    }
+}
@@ -0,0 +1,137 @@
+//! Bounded history of the transitions made by the tunnel state machine.
+//!
+//! The history is written to a file in the log directory after every transition, so that it is
+//! included in problem reports. Unlike the daemon log, it is never rotated away, so it shows what
+//! happened leading up to a problem even if the daemon has logged a lot since.
+
+use chrono::{DateTime, Local};
+use std::{
+    collections::VecDeque,
+    fmt, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use talpid_types::{
+    tunnel::{ConnectionAttemptId, TunnelStateTransition},
+    ErrorExt,
+};
+
+/// Maximum number of transitions to remember.
+const MAX_HISTORY_LEN: usize = 50;
+/// Name of the file in the log directory that the history is written to.
+const HISTORY_FILENAME: &str = "state-history.log";
+const DATE_TIME_FORMAT_STR: &str = "%Y-%m-%d %H:%M:%S%.3f";
+
+/// A state that the tunnel state machine entered, and when it did so.
+#[derive(Clone, Debug)]
+pub struct StateHistoryEntry {
+    /// When the state was entered.
+    pub time: DateTime<Local>,
+    /// Name of the state that was entered.
+    pub state: &'static str,
+    /// Why the state was entered, or which endpoint it concerns.
+    pub details: String,
+    /// Connection attempt that led to the state, if any.
+    pub connection_attempt: Option<ConnectionAttemptId>,
+}
+
+impl StateHistoryEntry {
+    fn new(
+        transition: &TunnelStateTransition,
+        connection_attempt: Option<ConnectionAttemptId>,
+    ) -> Self {
+        let (state, details) = match transition {
+            TunnelStateTransition::Disconnected { cause, security } => (
+                "disconnected",
+                format!("cause: {:?}, security: {:?}", cause, security),
+            ),
+            TunnelStateTransition::Connecting(endpoint) => ("connecting", endpoint.to_string()),
+            TunnelStateTransition::Connected(endpoint) => ("connected", endpoint.to_string()),
+            TunnelStateTransition::Disconnecting(action) => {
+                ("disconnecting", format!("after disconnect: {:?}", action))
+            }
+            TunnelStateTransition::Error(error_state) => {
+                let mut details = error_state.cause().to_string();
+                if let Some(block_failure) = error_state.block_failure() {
+                    details.push_str(&format!(" (failed to block: {})", block_failure));
+                }
+                ("error", details)
+            }
+            TunnelStateTransition::Paused(endpoint) => ("paused", endpoint.to_string()),
+        };
+        Self {
+            time: Local::now(),
+            state,
+            details,
+            connection_attempt,
+        }
+    }
+}
+
+impl fmt::Display for StateHistoryEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] {}",
+            self.time.format(DATE_TIME_FORMAT_STR),
+            self.state
+        )?;
+        if let Some(attempt) = self.connection_attempt {
+            write!(f, " (attempt {})", attempt)?;
+        }
+        write!(f, ": {}", self.details)
+    }
+}
+
+/// The last [`MAX_HISTORY_LEN`] state transitions. Clones share the same history.
+#[derive(Clone)]
+pub struct StateHistory {
+    entries: Arc<Mutex<VecDeque<StateHistoryEntry>>>,
+    log_dir: Option<PathBuf>,
+}
+
+impl StateHistory {
+    /// Creates an empty history, which is written to `log_dir` if it is set.
+    pub fn new(log_dir: Option<PathBuf>) -> Self {
+        Self {
+            entries: Arc::new(Mutex::new(VecDeque::with_capacity(MAX_HISTORY_LEN))),
+            log_dir,
+        }
+    }
+
+    /// Adds a transition to the history, dropping the oldest one if the history is full.
+    pub fn record(
+        &self,
+        transition: &TunnelStateTransition,
+        connection_attempt: Option<ConnectionAttemptId>,
+    ) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= MAX_HISTORY_LEN {
+            entries.pop_front();
+        }
+        entries.push_back(StateHistoryEntry::new(transition, connection_attempt));
+
+        if let Some(log_dir) = &self.log_dir {
+            if let Err(error) = write_history(log_dir, &entries) {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to write state transition history")
+                );
+            }
+        }
+    }
+
+    /// Returns the remembered transitions, oldest first.
+    pub fn entries(&self) -> Vec<StateHistoryEntry> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+fn write_history(log_dir: &Path, entries: &VecDeque<StateHistoryEntry>) -> io::Result<()> {
+    let mut file = fs::File::create(log_dir.join(HISTORY_FILENAME))?;
+    for entry in entries {
+        writeln!(file, "{}", entry)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,216 @@
+//! Opt-in instrumentation that measures whether traffic can escape outside the tunnel while
+//! the state machine is reconnecting.
+//!
+//! When enabled, a UDP socket bound to the physical interface repeatedly tries to send probes
+//! from the moment a tunnel is torn down for a reconnect until a new tunnel is up, or until the
+//! state machine settles in a state where no reconnect is pending. A probe that is accepted by
+//! the network stack means that the firewall did not block it. A summary of each reconnect
+//! window is appended to a log file in the log directory, so that it is included in problem
+//! reports.
+
+use crate::runtime_config::{self, FeatureFlag};
+use chrono::Local;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use talpid_types::ErrorExt;
+
+/// Destination of the probes. This is in TEST-NET-1, so any probe that escapes will not reach a
+/// real host.
+const CANARY_DESTINATION: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)), 53);
+/// Interval between probes.
+const PROBE_INTERVAL: Duration = Duration::from_millis(20);
+/// Name of the file that reports are appended to.
+const REPORT_FILENAME: &str = "leak-canary.log";
+const DATE_TIME_FORMAT_STR: &str = "%Y-%m-%d %H:%M:%S%.3f";
+
+/// Returns whether the leak canary is enabled.
+pub fn is_enabled() -> bool {
+    runtime_config::is_enabled(FeatureFlag::LeakCanary)
+}
+
+/// A running leak canary. Call [`LeakCanary::stop`] to stop it and write its report.
+pub struct LeakCanary {
+    stop: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<CanaryReport>>,
+    log_dir: Option<PathBuf>,
+}
+
+impl LeakCanary {
+    /// Starts sending probes in a background thread. If `interface` is given, the socket is
+    /// bound to that interface.
+    pub fn start(interface: Option<String>, log_dir: Option<PathBuf>) -> io::Result<Self> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        if let Some(interface) = &interface {
+            bind_to_interface(&socket, interface)?;
+        }
+        socket.set_nonblocking(true)?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let thread = thread::Builder::new()
+            .name("leak-canary".to_owned())
+            .spawn(move || run_probes(socket, interface, thread_stop))?;
+
+        Ok(Self {
+            stop,
+            thread: Some(thread),
+            log_dir,
+        })
+    }
+
+    /// Stops sending probes and appends a report to the log directory.
+    pub fn stop(mut self) {
+        self.stop.store(true, Ordering::Release);
+        let thread = match self.thread.take() {
+            Some(thread) => thread,
+            None => return,
+        };
+        let report = match thread.join() {
+            Ok(report) => report,
+            Err(_) => {
+                log::error!("Leak canary thread panicked");
+                return;
+            }
+        };
+
+        if report.escaped > 0 {
+            log::warn!("Leak canary: {}", report);
+        } else {
+            log::debug!("Leak canary: {}", report);
+        }
+
+        if let Some(log_dir) = &self.log_dir {
+            if let Err(error) = append_report(log_dir, &report) {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to write leak canary report")
+                );
+            }
+        }
+    }
+}
+
+impl Drop for LeakCanary {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+    }
+}
+
+/// Outcome of a single reconnect window.
+struct CanaryReport {
+    interface: Option<String>,
+    duration: Duration,
+    probes: u32,
+    escaped: u32,
+    first_escape: Option<Duration>,
+    last_error: Option<io::Error>,
+}
+
+impl std::fmt::Display for CanaryReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "reconnect window of {} ms on interface {}: {} of {} probes escaped",
+            self.duration.as_millis(),
+            self.interface.as_deref().unwrap_or("<unbound>"),
+            self.escaped,
+            self.probes,
+        )?;
+        if let Some(first_escape) = self.first_escape {
+            write!(f, ", first after {} ms", first_escape.as_millis())?;
+        }
+        if let Some(error) = &self.last_error {
+            write!(f, ", last send error: {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+fn run_probes(socket: Socket, interface: Option<String>, stop: Arc<AtomicBool>) -> CanaryReport {
+    let destination = CANARY_DESTINATION.into();
+    let start = Instant::now();
+    let mut report = CanaryReport {
+        interface,
+        duration: Duration::ZERO,
+        probes: 0,
+        escaped: 0,
+        first_escape: None,
+        last_error: None,
+    };
+
+    while !stop.load(Ordering::Acquire) {
+        report.probes += 1;
+        match socket.send_to(&report.probes.to_be_bytes(), &destination) {
+            Ok(_) => {
+                report.escaped += 1;
+                report.first_escape.get_or_insert_with(|| start.elapsed());
+            }
+            Err(error) => report.last_error = Some(error),
+        }
+        thread::sleep(PROBE_INTERVAL);
+    }
+
+    report.duration = start.elapsed();
+    report
+}
+
+fn append_report(log_dir: &Path, report: &CanaryReport) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_dir.join(REPORT_FILENAME))?;
+    writeln!(
+        file,
+        "[{}] {}",
+        Local::now().format(DATE_TIME_FORMAT_STR),
+        report
+    )
+}
+
+#[cfg(target_os = "linux")]
+fn bind_to_interface(socket: &Socket, interface: &str) -> io::Result<()> {
+    socket.bind_device(Some(interface.as_bytes()))
+}
+
+#[cfg(target_os = "macos")]
+fn bind_to_interface(socket: &Socket, interface: &str) -> io::Result<()> {
+    use std::{ffi::CString, os::unix::io::AsRawFd};
+
+    let name = CString::new(interface)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid interface name"))?;
+    let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+    if index == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_IP,
+            libc::IP_BOUND_IF,
+            &index as *const _ as *const libc::c_void,
+            std::mem::size_of_val(&index) as libc::socklen_t,
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn bind_to_interface(_socket: &Socket, _interface: &str) -> io::Result<()> {
+    // Routes to the relay are removed during reconnects, so unbound probes are routed through
+    // the default interface.
+    Ok(())
+}
@@ -1,33 +1,60 @@
+mod bridge_monitor;
 mod connected_state;
 mod connecting_state;
 mod disconnected_state;
 mod disconnecting_state;
 mod error_state;
-
+#[cfg(not(target_os = "android"))]
+mod forced_interface;
+mod history;
+#[cfg(not(target_os = "android"))]
+mod leak_canary;
+mod operation_watchdog;
+mod paused_state;
+#[cfg(not(target_os = "android"))]
+mod physical_interface;
+mod reconnect_budget;
+#[cfg(not(target_os = "android"))]
+mod route_takeover;
+#[cfg(feature = "qa-tools")]
+mod simulation;
+
+pub use self::history::StateHistoryEntry;
 use self::{
     connected_state::{ConnectedState, ConnectedStateBootstrap},
     connecting_state::ConnectingState,
     disconnected_state::DisconnectedState,
     disconnecting_state::{AfterDisconnect, DisconnectingState},
     error_state::ErrorState,
+    operation_watchdog::{OperationWatchdog, PerformanceWarningSender},
+    paused_state::PausedState,
+    reconnect_budget::{ReconnectBudget, ReconnectCause},
 };
+#[cfg(all(feature = "packet-capture", target_os = "linux"))]
+use crate::packet_capture::PacketCaptureHandle;
 #[cfg(windows)]
 use crate::split_tunnel;
 use crate::{
     dns::DnsMonitor,
-    firewall::{Firewall, FirewallArguments, InitialFirewallState},
+    firewall::{plugin::PolicyFragment, Firewall, FirewallArguments, InitialFirewallState},
+    logging,
     mpsc::Sender,
     offline,
+    restore_journal::{self, JournalEntry, RestoreJournal},
     routing::RouteManager,
-    tunnel::{tun_provider::TunProvider, TunnelEvent},
+    tunnel::{tun_provider::TunProvider, wireguard::TunnelProvider, TunnelEvent},
 };
 #[cfg(windows)]
 use std::ffi::OsString;
 
+#[cfg(feature = "qa-tools")]
+pub use simulation::{Error as SimulationError, SimulatedEvent, SimulationHandle};
+
 use futures::{
     channel::{mpsc, oneshot},
     stream, StreamExt,
 };
+use ipnetwork::IpNetwork;
 #[cfg(target_os = "android")]
 use std::os::unix::io::RawFd;
 use std::{
@@ -38,17 +65,48 @@ use std::{
     path::PathBuf,
     pin::Pin,
     sync::{Arc, Mutex},
-    time::Duration,
+    time::{Duration, Instant},
 };
 #[cfg(target_os = "android")]
-use talpid_types::{android::AndroidContext, ErrorExt};
+use talpid_types::android::AndroidContext;
+#[cfg(not(target_os = "android"))]
+use talpid_types::net::{route_takeover::CompetingVpn, PhysicalInterface};
 use talpid_types::{
-    net::{AllowedEndpoint, TunnelParameters},
-    tunnel::{ErrorStateCause, ParameterGenerationError, TunnelStateTransition},
+    health::HealthCheck,
+    net::{
+        route_takeover::RouteTakeoverPolicy, wireguard::TrafficShapingOptions, AllowedEndpoint,
+        BlockedTunnelProtocols, TunnelParameters, VpnCoexistence,
+    },
+    performance::{Operation, PerformanceWarning},
+    system_state::{FirewallState, RouteState, SystemState},
+    tunnel::{
+        ConnectionAttemptId, DisconnectCause, DisconnectedBlockReason, DisconnectedSecurity,
+        ErrorStateCause, ErrorStatePolicy, ParameterGenerationError, ReconnectLimits,
+        TunnelMetadata, TunnelStateTransition, VersionedTunnelStateTransition,
+    },
+    ErrorExt,
 };
 
 const TUNNEL_STATE_MACHINE_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
 
+/// How long to wait for the tunnel monitor to exit after it has closed its event channel. A
+/// monitor that is still running at that point can no longer report on the tunnel, so it is
+/// abandoned.
+const TUNNEL_MONITOR_EXIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How many times in a row the tunnel monitor may fail before entering the error state.
+const MAX_TUNNEL_MONITOR_FAILURES: u32 = 3;
+
+/// Applying a firewall policy for longer than this is reported as a health problem.
+const SLOW_FIREWALL_APPLY_DURATION: Duration = Duration::from_secs(2);
+
+/// How many times the tunnel may reconnect to reclaim the default route from another VPN within
+/// [`ROUTE_RECLAIM_WINDOW`] before yielding to it.
+#[cfg(not(target_os = "android"))]
+const MAX_ROUTE_RECLAIMS: usize = 3;
+#[cfg(not(target_os = "android"))]
+const ROUTE_RECLAIM_WINDOW: Duration = Duration::from_secs(5 * 60);
+
 /// Errors that can happen when setting up or using the state machine.
 #[derive(err_derive::Error, Debug)]
 pub enum Error {
@@ -61,6 +119,11 @@ pub enum Error {
     #[error(display = "Failed to initialize split tunneling")]
     InitSplitTunneling(#[error(source)] split_tunnel::Error),
 
+    /// A structure shared with native code has an unexpected layout
+    #[cfg(target_os = "windows")]
+    #[error(display = "Native interop self-test failed")]
+    InteropSelfTest(#[error(source)] crate::windows::arch::LayoutMismatch),
+
     /// Failed to initialize the system firewall integration.
     #[error(display = "Failed to initialize the system firewall integration")]
     InitFirewallError(#[error(source)] crate::firewall::Error),
@@ -73,6 +136,11 @@ pub enum Error {
     #[error(display = "Failed to initialize the route manager")]
     InitRouteManagerError(#[error(source)] crate::routing::Error),
 
+    /// Failed to start monitoring the physical interface.
+    #[cfg(not(target_os = "android"))]
+    #[error(display = "Failed to start monitoring the physical interface")]
+    PhysicalInterfaceMonitorError(#[error(source)] physical_interface::Error),
+
     /// Failed to initialize filtering resolver
     #[cfg(target_os = "macos")]
     #[error(display = "Failed to initialize filtering resolver")]
@@ -87,6 +155,19 @@ pub enum Error {
     SendStateChange,
 }
 
+/// Errors returned when sending a command to the state machine.
+#[derive(err_derive::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error(no_from)]
+pub enum CommandError {
+    /// The state machine has shut down and no longer accepts commands.
+    #[error(display = "The tunnel state machine has shut down")]
+    ShutDown,
+
+    /// The state machine dropped the command without responding.
+    #[error(display = "The tunnel state machine did not respond")]
+    NoResponse,
+}
+
 /// Settings used to initialize the tunnel state machine.
 pub struct InitialTunnelState {
     /// Whether to allow LAN traffic when not in the (non-blocking) disconnected state.
@@ -95,14 +176,48 @@ pub struct InitialTunnelState {
     pub block_when_disconnected: bool,
     /// DNS servers to use. If `None`, the tunnel gateway is used.
     pub dns_servers: Option<Vec<IpAddr>>,
-    /// A single endpoint that is allowed to communicate outside the tunnel, i.e.
-    /// in any of the blocking states.
+    /// Endpoints that are allowed to communicate outside the tunnel, i.e. in any of the blocking
+    /// states.
     pub allowed_endpoint: AllowedEndpoint,
     /// Whether to reset any existing firewall rules when initializing the disconnected state.
     pub reset_firewall: bool,
+    /// Whether to flush the system DNS cache after changing the DNS configuration.
+    pub flush_dns_cache: bool,
+    /// Networks that traffic is allowed to and from in the error state.
+    pub recovery_allowlist: Vec<IpNetwork>,
+    /// Traffic of another VPN that is exempt from blocking and from the tunnel.
+    pub vpn_coexistence: VpnCoexistence,
+    /// Protocols to block inside the tunnel while connected.
+    pub blocked_tunnel_protocols: BlockedTunnelProtocols,
+    /// Physical interface that traffic to the relay must leave through, if set.
+    pub forced_interface: Option<String>,
+    /// Maximum number of consecutive reconnect attempts for each cause.
+    pub reconnect_limits: ReconnectLimits,
+    /// What to do when another VPN takes over the default route while connected.
+    pub route_takeover_policy: RouteTakeoverPolicy,
+    /// Which traffic to block in the error state.
+    pub error_state_policy: ErrorStatePolicy,
     /// Programs to exclude from the tunnel using the split tunnel driver.
     #[cfg(windows)]
     pub exclude_paths: Vec<OsString>,
+    /// Creates WireGuard tunnels instead of the built-in backends, if set.
+    pub wireguard_tunnel_provider: Option<Arc<dyn TunnelProvider>>,
+    /// Rules from signed policy plugins that are added to every firewall policy.
+    pub firewall_plugins: PolicyFragment,
+}
+
+impl InitialTunnelState {
+    /// Returns whether traffic is blocked in the initial disconnected state, provided that the
+    /// firewall policy can be applied.
+    pub fn disconnected_security(&self) -> DisconnectedSecurity {
+        if self.block_when_disconnected {
+            DisconnectedSecurity::Blocked(DisconnectedBlockReason::BlockWhenDisconnected)
+        } else if !self.reset_firewall {
+            DisconnectedSecurity::Blocked(DisconnectedBlockReason::PendingConnect)
+        } else {
+            DisconnectedSecurity::Unsecured
+        }
+    }
 }
 
 /// Spawn the tunnel state machine thread, returning a channel for sending tunnel commands.
@@ -111,8 +226,16 @@ pub async fn spawn(
     tunnel_parameters_generator: impl TunnelParametersGenerator,
     log_dir: Option<PathBuf>,
     resource_dir: PathBuf,
-    state_change_listener: impl Sender<TunnelStateTransition> + Send + 'static,
+    cache_dir: PathBuf,
+    state_change_listener: impl Sender<VersionedTunnelStateTransition> + Send + 'static,
     offline_state_listener: mpsc::UnboundedSender<bool>,
+    #[cfg(not(target_os = "android"))] physical_interface_listener: mpsc::UnboundedSender<
+        Option<PhysicalInterface>,
+    >,
+    #[cfg(not(target_os = "android"))] route_takeover_listener: impl Sender<CompetingVpn>
+        + Send
+        + 'static,
+    performance_warning_listener: impl Sender<PerformanceWarning> + Send + Sync + 'static,
     #[cfg(target_os = "windows")] volume_update_rx: mpsc::UnboundedReceiver<()>,
     #[cfg(target_os = "macos")] exclusion_gid: u32,
     #[cfg(target_os = "android")] android_context: AndroidContext,
@@ -137,10 +260,16 @@ pub async fn spawn(
         settings: initial_settings,
         command_tx: weak_command_tx,
         offline_state_tx: offline_state_listener,
+        #[cfg(not(target_os = "android"))]
+        physical_interface_tx: physical_interface_listener,
+        #[cfg(not(target_os = "android"))]
+        route_takeover_tx: Box::new(route_takeover_listener),
+        performance_warning_tx: Arc::new(performance_warning_listener),
         tunnel_parameters_generator,
         tun_provider,
         log_dir,
         resource_dir,
+        cache_dir,
         commands_rx: command_rx,
         #[cfg(target_os = "windows")]
         volume_update_rx,
@@ -154,6 +283,11 @@ pub async fn spawn(
 
     #[cfg(windows)]
     let split_tunnel = state_machine.shared_values.split_tunnel.handle();
+    #[cfg(feature = "qa-tools")]
+    let simulation = state_machine.shared_values.simulation.clone();
+    #[cfg(all(feature = "packet-capture", target_os = "linux"))]
+    let packet_capture = state_machine.shared_values.packet_capture.clone();
+    let state_history = state_machine.state_history.clone();
 
     tokio::task::spawn_blocking(move || {
         state_machine.run(state_change_listener);
@@ -165,8 +299,13 @@ pub async fn spawn(
     Ok(TunnelStateMachineHandle {
         command_tx,
         shutdown_rx,
+        state_history,
         #[cfg(windows)]
         split_tunnel,
+        #[cfg(feature = "qa-tools")]
+        simulation,
+        #[cfg(all(feature = "packet-capture", target_os = "linux"))]
+        packet_capture,
     })
 }
 
@@ -174,22 +313,59 @@ pub async fn spawn(
 pub enum TunnelCommand {
     /// Enable or disable LAN access in the firewall.
     AllowLan(bool),
-    /// Endpoint that should never be blocked. `()` is sent to the
-    /// channel after attempting to set the firewall policy, regardless
-    /// of whether it succeeded.
+    /// Endpoints that should never be blocked, qualified by port range and protocol. `()` is
+    /// sent to the channel after attempting to set the firewall policy, regardless of whether it
+    /// succeeded.
     AllowEndpoint(AllowedEndpoint, oneshot::Sender<()>),
     /// Set DNS servers to use.
     Dns(Option<Vec<IpAddr>>),
     /// Enable or disable the block_when_disconnected feature.
     BlockWhenDisconnected(bool),
+    /// Enable or disable flushing of the system DNS cache after changing DNS settings.
+    FlushDnsCache(bool),
+    /// Set networks that traffic is allowed to and from in the error state.
+    RecoveryAllowlist(Vec<IpNetwork>),
+    /// Set the traffic of another VPN that is exempt from blocking and from the tunnel.
+    VpnCoexistence(VpnCoexistence),
+    /// Set the protocols to block inside the tunnel while connected.
+    BlockedTunnelProtocols(BlockedTunnelProtocols),
+    /// Set the physical interface that traffic to the relay must leave through, overriding the
+    /// interface of the best default route. A new tunnel is established if the interface changes
+    /// while connecting or connected.
+    SetForcedInterface(Option<String>),
+    /// Sent by the state machine itself when the forced interface disappears, appears, or is
+    /// recreated. Contains the index of the interface, if it exists.
+    #[cfg(not(target_os = "android"))]
+    ForcedInterfaceChanged(Option<u32>),
+    /// Sent by the state machine itself when the default route starts leaving through another
+    /// physical interface, which may mean that the device has joined another network.
+    #[cfg(not(target_os = "android"))]
+    DefaultInterfaceChanged,
+    /// Change the traffic shaping of the WireGuard tunnel while connected, without reconnecting
+    /// unless the tunnel does not support it.
+    TrafficShaping(TrafficShapingOptions),
+    /// Set the maximum number of consecutive reconnect attempts for each cause.
+    ReconnectLimits(ReconnectLimits),
+    /// Set what to do when another VPN takes over the default route while connected.
+    RouteTakeoverPolicy(RouteTakeoverPolicy),
+    /// Set which traffic to block in the error state.
+    ErrorStatePolicy(ErrorStatePolicy),
     /// Notify the state machine of the connectivity of the device.
     IsOffline(bool),
     /// Open tunnel connection.
     Connect,
-    /// Close tunnel connection.
-    Disconnect,
+    /// Close tunnel connection. The cause is reported in the resulting
+    /// [`TunnelStateTransition::Disconnected`].
+    Disconnect(DisconnectCause),
     /// Disconnect any open tunnel and block all network access
     Block(ErrorStateCause),
+    /// Close any open tunnel, but keep blocking traffic outside of it and remember the tunnel
+    /// parameters, so that the same tunnel can be quickly brought up again by
+    /// [`TunnelCommand::Resume`]. This lets the device save power and data while the tunnel is not
+    /// needed.
+    Pause,
+    /// Reopen a paused tunnel using the parameters that were used before pausing.
+    Resume,
     /// Bypass a socket, allowing traffic to flow through outside the tunnel.
     #[cfg(target_os = "android")]
     BypassSocket(RawFd, oneshot::Sender<()>),
@@ -199,6 +375,14 @@ pub enum TunnelCommand {
         oneshot::Sender<Result<(), split_tunnel::Error>>,
         Vec<OsString>,
     ),
+    /// Reinstall the split tunnel driver, e.g. if the loaded driver is incompatible.
+    #[cfg(windows)]
+    ReinstallSplitTunnelDriver(oneshot::Sender<Result<(), split_tunnel::Error>>),
+    /// Check that the firewall, route manager and DNS monitor are working. A response at all
+    /// means that the state machine is not stuck.
+    HealthCheck(oneshot::Sender<Vec<HealthCheck>>),
+    /// Describe the firewall policy, routes and DNS servers that are currently applied.
+    GetSystemState(oneshot::Sender<SystemState>),
 }
 
 type TunnelCommandReceiver = stream::Fuse<mpsc::UnboundedReceiver<TunnelCommand>>;
@@ -207,6 +391,19 @@ enum EventResult {
     Command(Option<TunnelCommand>),
     Event(Option<(TunnelEvent, oneshot::Sender<()>)>),
     Close(Result<Option<ErrorStateCause>, oneshot::Canceled>),
+    BridgeDown,
+    TunnelMonitorUnresponsive,
+    #[cfg(not(target_os = "android"))]
+    RouteTakeover(CompetingVpn),
+}
+
+/// Completes at `deadline`, which is set when the tunnel event channel has been closed. Never
+/// completes if there is no deadline.
+async fn wait_for_tunnel_monitor_exit(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => futures::future::pending().await,
+    }
 }
 
 /// Asynchronous handling of the tunnel state machine.
@@ -219,6 +416,7 @@ struct TunnelStateMachine {
     current_state: Option<TunnelStateWrapper>,
     commands: TunnelCommandReceiver,
     shared_values: SharedTunnelStateValues,
+    state_history: history::StateHistory,
 }
 
 /// Tunnel state machine initialization arguments arguments
@@ -226,10 +424,16 @@ struct TunnelStateMachineInitArgs<G: TunnelParametersGenerator> {
     settings: InitialTunnelState,
     command_tx: std::sync::Weak<mpsc::UnboundedSender<TunnelCommand>>,
     offline_state_tx: mpsc::UnboundedSender<bool>,
+    #[cfg(not(target_os = "android"))]
+    physical_interface_tx: mpsc::UnboundedSender<Option<PhysicalInterface>>,
+    #[cfg(not(target_os = "android"))]
+    route_takeover_tx: Box<dyn Sender<CompetingVpn> + Send>,
+    performance_warning_tx: PerformanceWarningSender,
     tunnel_parameters_generator: G,
     tun_provider: TunProvider,
     log_dir: Option<PathBuf>,
     resource_dir: PathBuf,
+    cache_dir: PathBuf,
     commands_rx: mpsc::UnboundedReceiver<TunnelCommand>,
     #[cfg(target_os = "windows")]
     volume_update_rx: mpsc::UnboundedReceiver<()>,
@@ -255,6 +459,9 @@ impl TunnelStateMachine {
         #[cfg(target_os = "macos")]
         let filtering_resolver = crate::resolver::start_resolver().await?;
 
+        #[cfg(target_os = "windows")]
+        crate::windows::arch::self_test().map_err(Error::InteropSelfTest)?;
+
         #[cfg(target_os = "windows")]
         let power_mgmt_rx = crate::windows::window::PowerManagementListener::new();
 
@@ -268,21 +475,46 @@ impl TunnelStateMachine {
         )
         .map_err(Error::InitSplitTunneling)?;
 
+        let (journal, stale_entries) =
+            RestoreJournal::open(args.cache_dir.join(restore_journal::JOURNAL_FILENAME));
+        if !stale_entries.is_empty() {
+            log::warn!(
+                "Undoing {} changes to the system configuration left behind by a previous instance",
+                stale_entries.len()
+            );
+        }
+
+        let initially_blocked =
+            args.settings.block_when_disconnected || !args.settings.reset_firewall;
         let fw_args = FirewallArguments {
-            initial_state: if args.settings.block_when_disconnected || !args.settings.reset_firewall
-            {
+            initial_state: if initially_blocked {
                 InitialFirewallState::Blocked(args.settings.allowed_endpoint.clone())
             } else {
                 InitialFirewallState::None
             },
             allow_lan: args.settings.allow_lan,
+            vpn_coexistence: args.settings.vpn_coexistence.clone(),
+            plugins: args.settings.firewall_plugins.clone(),
         };
 
         let firewall = Firewall::from_args(fw_args).map_err(Error::InitFirewallError)?;
-        let route_manager = RouteManager::new(HashSet::new())
+        let mut route_manager = RouteManager::new(HashSet::new())
             .await
             .map_err(Error::InitRouteManagerError)?;
-        let dns_monitor = DnsMonitor::new(
+        remove_stale_routes(&mut route_manager, &stale_entries).await;
+        route_manager.set_journal(journal.clone());
+        #[cfg(not(target_os = "android"))]
+        let physical_interface_monitor = physical_interface::PhysicalInterfaceMonitor::spawn(
+            args.physical_interface_tx,
+            args.command_tx.clone(),
+            #[cfg(target_os = "linux")]
+            route_manager
+                .handle()
+                .map_err(Error::InitRouteManagerError)?,
+        )
+        .await
+        .map_err(Error::PhysicalInterfaceMonitorError)?;
+        let mut dns_monitor = DnsMonitor::new(
             #[cfg(target_os = "linux")]
             runtime.clone(),
             #[cfg(target_os = "linux")]
@@ -293,6 +525,7 @@ impl TunnelStateMachine {
             args.command_tx.clone(),
         )
         .map_err(Error::InitDnsMonitorError)?;
+        dns_monitor.set_flush_cache(args.settings.flush_dns_cache);
 
         let (offline_tx, mut offline_rx) = mpsc::unbounded();
         let initial_offline_state_tx = args.offline_state_tx.clone();
@@ -327,6 +560,18 @@ impl TunnelStateMachine {
             .set_paths_sync(&args.settings.exclude_paths)
             .map_err(Error::InitSplitTunneling)?;
 
+        #[cfg(feature = "qa-tools")]
+        let simulation = simulation::SimulationHandle::new(args.command_tx.clone());
+        #[cfg(all(feature = "qa-tools", not(target_os = "android")))]
+        simulation.set_physical_interface_tx(physical_interface_monitor.event_tx());
+
+        #[cfg(not(target_os = "android"))]
+        let forced_interface_monitor = forced_interface::ForcedInterfaceMonitor::new(
+            runtime.clone(),
+            args.command_tx.clone(),
+            args.settings.forced_interface.clone(),
+        );
+
         let mut shared_values = SharedTunnelStateValues {
             #[cfg(windows)]
             split_tunnel,
@@ -334,15 +579,20 @@ impl TunnelStateMachine {
             firewall,
             dns_monitor,
             route_manager,
-            _offline_monitor: offline_monitor,
+            offline_monitor,
             allow_lan: args.settings.allow_lan,
             block_when_disconnected: args.settings.block_when_disconnected,
             is_offline,
             dns_servers: args.settings.dns_servers,
             allowed_endpoint: args.settings.allowed_endpoint,
+            recovery_allowlist: args.settings.recovery_allowlist,
+            vpn_coexistence: args.settings.vpn_coexistence,
+            blocked_tunnel_protocols: args.settings.blocked_tunnel_protocols,
+            forced_interface: args.settings.forced_interface,
             tunnel_parameters_generator: Box::new(args.tunnel_parameters_generator),
             tun_provider: Arc::new(Mutex::new(args.tun_provider)),
-            log_dir: args.log_dir,
+            wireguard_tunnel_provider: args.settings.wireguard_tunnel_provider,
+            log_dir: args.log_dir.clone(),
             resource_dir: args.resource_dir,
             #[cfg(target_os = "linux")]
             connectivity_check_was_enabled: None,
@@ -350,23 +600,63 @@ impl TunnelStateMachine {
             filtering_resolver,
             #[cfg(target_os = "macos")]
             _exclusion_gid: exclusion_gid,
+            #[cfg(not(target_os = "android"))]
+            leak_canary: None,
+            #[cfg(not(target_os = "android"))]
+            physical_interface_monitor,
+            #[cfg(not(target_os = "android"))]
+            forced_interface_monitor,
+            tunnel_monitor_failures: 0,
+            reconnect_budget: ReconnectBudget::new(args.settings.reconnect_limits),
+            route_takeover_policy: args.settings.route_takeover_policy,
+            error_state_policy: args.settings.error_state_policy,
+            #[cfg(not(target_os = "android"))]
+            route_takeover_tx: args.route_takeover_tx,
+            #[cfg(not(target_os = "android"))]
+            route_reclaims: Vec::new(),
+            performance_warning_tx: args.performance_warning_tx,
+            connection_attempt: None,
+            restore_journal: journal.clone(),
+            #[cfg(feature = "qa-tools")]
+            simulation,
+            #[cfg(all(feature = "packet-capture", target_os = "linux"))]
+            packet_capture: PacketCaptureHandle::new(args.log_dir.clone()),
         };
 
         tokio::task::spawn_blocking(move || {
-            let (initial_state, _) =
-                DisconnectedState::enter(&mut shared_values, args.settings.reset_firewall);
+            restore_stale_state(&mut shared_values, &stale_entries, initially_blocked);
+
+            shared_values.firewall.set_journal(journal.clone());
+            shared_values.dns_monitor.set_journal(journal.clone());
+            journal.flush();
+            if initially_blocked {
+                journal.record(JournalEntry::Firewall);
+            }
+
+            let (initial_state, initial_transition) =
+                DisconnectedState::enter(&mut shared_values, (args.settings.reset_firewall, None));
+
+            let state_history = history::StateHistory::new(args.log_dir);
+            state_history.record(&initial_transition, shared_values.connection_attempt);
+            shared_values
+                .offline_monitor
+                .set_low_power(is_low_power_state(&initial_transition));
 
             Ok(TunnelStateMachine {
                 current_state: Some(initial_state),
                 commands: args.commands_rx.fuse(),
                 shared_values,
+                state_history,
             })
         })
         .await
         .unwrap()
     }
 
-    fn run(mut self, change_listener: impl Sender<TunnelStateTransition> + Send + 'static) {
+    fn run(
+        mut self,
+        change_listener: impl Sender<VersionedTunnelStateTransition> + Send + 'static,
+    ) {
         use EventConsequence::*;
 
         let runtime = self.shared_values.runtime.clone();
@@ -375,10 +665,21 @@ impl TunnelStateMachine {
             match state_wrapper.handle_event(&runtime, &mut self.commands, &mut self.shared_values)
             {
                 NewState((state, transition)) => {
+                    let metadata = state.tunnel_metadata();
                     self.current_state = Some(state);
+                    self.state_history
+                        .record(&transition, self.shared_values.connection_attempt);
+
+                    #[cfg(not(target_os = "android"))]
+                    self.shared_values
+                        .physical_interface_monitor
+                        .set_tunnel_up(matches!(transition, TunnelStateTransition::Connected(_)));
+                    self.shared_values
+                        .offline_monitor
+                        .set_low_power(is_low_power_state(&transition));
 
                     if let Err(error) = change_listener
-                        .send(transition)
+                        .send(VersionedTunnelStateTransition::new(transition, metadata))
                         .map_err(|_| Error::SendStateChange)
                     {
                         log::error!("{}", error);
@@ -392,10 +693,106 @@ impl TunnelStateMachine {
             }
         }
 
+        #[cfg(windows)]
+        crate::tunnel::openvpn::release_wintun_adapter();
+
         log::debug!("Exiting tunnel state machine loop");
     }
 }
 
+/// Returns whether monitors can run in low-power mode in the state that was entered, i.e. if the
+/// tunnel is disconnected and traffic is not blocked.
+fn is_low_power_state(transition: &TunnelStateTransition) -> bool {
+    matches!(
+        transition,
+        TunnelStateTransition::Disconnected {
+            security: DisconnectedSecurity::Unsecured,
+            ..
+        }
+    )
+}
+
+/// Removes routes that were left behind by a previous instance.
+async fn remove_stale_routes(route_manager: &mut RouteManager, stale_entries: &[JournalEntry]) {
+    let routes: Vec<_> = stale_entries
+        .iter()
+        .filter_map(|entry| match entry {
+            JournalEntry::Route { route } => Some(route.clone()),
+            _ => None,
+        })
+        .collect();
+    if routes.is_empty() {
+        return;
+    }
+
+    log::info!("Removing {} stale routes", routes.len());
+    for route in routes {
+        // Remove the routes one by one, so that a route that cannot be removed does not prevent
+        // the others from being removed
+        let prefix = route.prefix;
+        if let Err(error) = route_manager
+            .remove_routes(std::iter::once(route).collect())
+            .await
+        {
+            log::warn!(
+                "{}",
+                error
+                    .display_chain_with_msg(&format!("Failed to remove stale route to {}", prefix))
+            );
+        }
+    }
+}
+
+/// Undoes DNS and firewall changes that were left behind by a previous instance. Firewall rules
+/// are kept if the firewall should be blocking anyway.
+fn restore_stale_state(
+    shared_values: &mut SharedTunnelStateValues,
+    stale_entries: &[JournalEntry],
+    initially_blocked: bool,
+) {
+    for entry in stale_entries {
+        match entry {
+            // The DNS monitor on macOS does not persist the original configuration, and clearing
+            // the servers would affect every network service
+            #[cfg(target_os = "macos")]
+            JournalEntry::Dns { interface } => {
+                log::warn!("Cannot restore DNS configuration of {}", interface);
+            }
+            #[cfg(not(target_os = "macos"))]
+            JournalEntry::Dns { interface } => {
+                log::info!("Removing stale DNS servers from {}", interface);
+                let result = {
+                    let _watchdog = shared_values.watchdog(Operation::ResetDns);
+                    shared_values
+                        .dns_monitor
+                        .set(interface, &[])
+                        .and_then(|()| shared_values.dns_monitor.reset())
+                };
+                if let Err(error) = result {
+                    log::error!(
+                        "{}",
+                        error.display_chain_with_msg("Failed to remove stale DNS servers")
+                    );
+                }
+            }
+            JournalEntry::Firewall if !initially_blocked => {
+                log::info!("Removing stale firewall rules");
+                let result = {
+                    let _watchdog = shared_values.watchdog(Operation::ResetFirewallPolicy);
+                    shared_values.firewall.reset_policy()
+                };
+                if let Err(error) = result {
+                    log::error!(
+                        "{}",
+                        error.display_chain_with_msg("Failed to remove stale firewall rules")
+                    );
+                }
+            }
+            JournalEntry::Firewall | JournalEntry::Route { .. } => (),
+        }
+    }
+}
+
 /// Trait for any type that can provide a stream of `TunnelParameters` to the `TunnelStateMachine`.
 pub trait TunnelParametersGenerator: Send + 'static {
     /// Given the number of consecutive failed retry attempts, it should yield a `TunnelParameters`
@@ -418,7 +815,9 @@ struct SharedTunnelStateValues {
     firewall: Firewall,
     dns_monitor: DnsMonitor,
     route_manager: RouteManager,
-    _offline_monitor: offline::MonitorHandle,
+    /// Monitors whether the host is offline. Runs in low-power mode while no protection is
+    /// active.
+    offline_monitor: offline::MonitorHandle,
     /// Should LAN access be allowed outside the tunnel.
     allow_lan: bool,
     /// Should network access be allowed when in the disconnected state.
@@ -429,10 +828,21 @@ struct SharedTunnelStateValues {
     dns_servers: Option<Vec<IpAddr>>,
     /// Endpoint that should not be blocked by the firewall.
     allowed_endpoint: AllowedEndpoint,
+    /// Networks that should not be blocked by the firewall in the error state.
+    recovery_allowlist: Vec<IpNetwork>,
+    /// Traffic of another VPN that should not be blocked by the firewall or routed through the
+    /// tunnel.
+    vpn_coexistence: VpnCoexistence,
+    /// Protocols that should be blocked by the firewall inside the tunnel while connected.
+    blocked_tunnel_protocols: BlockedTunnelProtocols,
+    /// Physical interface that traffic to the relay should leave through.
+    forced_interface: Option<String>,
     /// The generator of new `TunnelParameter`s
     tunnel_parameters_generator: Box<dyn TunnelParametersGenerator>,
     /// The provider of tunnel devices.
     tun_provider: Arc<Mutex<TunProvider>>,
+    /// Custom provider of WireGuard tunnels.
+    wireguard_tunnel_provider: Option<Arc<dyn TunnelProvider>>,
     /// Directory to store tunnel log file.
     log_dir: Option<PathBuf>,
     /// Resource directory path.
@@ -449,9 +859,157 @@ struct SharedTunnelStateValues {
     /// Exclusion GID
     #[cfg(target_os = "macos")]
     _exclusion_gid: u32,
+
+    /// Leak canary that is running during the current reconnect window, if enabled.
+    #[cfg(not(target_os = "android"))]
+    leak_canary: Option<leak_canary::LeakCanary>,
+
+    /// Publishes the interface that connections outside the tunnel should be bound to.
+    #[cfg(not(target_os = "android"))]
+    physical_interface_monitor: physical_interface::PhysicalInterfaceMonitor,
+
+    /// Notifies the state machine when the forced interface disappears or comes back.
+    #[cfg(not(target_os = "android"))]
+    forced_interface_monitor: forced_interface::ForcedInterfaceMonitor,
+
+    /// Number of times in a row that the tunnel monitor has stopped without reporting why.
+    tunnel_monitor_failures: u32,
+
+    /// Consecutive reconnect attempts for each cause, and the limits on them.
+    reconnect_budget: ReconnectBudget,
+
+    /// What to do when another VPN takes over the default route while connected.
+    route_takeover_policy: RouteTakeoverPolicy,
+    /// Which traffic to block in the error state.
+    error_state_policy: ErrorStatePolicy,
+    /// Receives other VPNs that take over the default route while connected.
+    #[cfg(not(target_os = "android"))]
+    route_takeover_tx: Box<dyn Sender<CompetingVpn> + Send>,
+    /// Times at which the tunnel recently reconnected to reclaim the default route.
+    #[cfg(not(target_os = "android"))]
+    route_reclaims: Vec<Instant>,
+
+    /// Receives warnings about firewall, DNS and routing operations that were slow to complete.
+    performance_warning_tx: PerformanceWarningSender,
+
+    /// Connection attempt that is in progress, or that led to the current state.
+    connection_attempt: Option<ConnectionAttemptId>,
+
+    /// Record of the changes made to the system configuration.
+    restore_journal: RestoreJournal,
+
+    /// Source of simulated events injected by QA tooling.
+    #[cfg(feature = "qa-tools")]
+    simulation: simulation::SimulationHandle,
+
+    /// Captures packets on the tunnel interface while it is up, if requested.
+    #[cfg(all(feature = "packet-capture", target_os = "linux"))]
+    packet_capture: PacketCaptureHandle,
 }
 
 impl SharedTunnelStateValues {
+    /// Starts a new connection attempt. Log lines from the subsystems involved in connecting
+    /// include its ID until the next attempt starts or the tunnel is disconnected.
+    pub fn start_connection_attempt(&mut self) -> ConnectionAttemptId {
+        let attempt = ConnectionAttemptId::random();
+        self.connection_attempt = Some(attempt);
+        logging::set_current_connection_attempt(Some(attempt));
+        log::info!("Starting connection attempt {}", attempt);
+        attempt
+    }
+
+    /// Ends the current connection attempt, if any.
+    pub fn end_connection_attempt(&mut self) {
+        if let Some(attempt) = self.connection_attempt.take() {
+            logging::set_current_connection_attempt(None);
+            log::debug!("Connection attempt {} has ended", attempt);
+        }
+    }
+
+    /// Starts timing `operation`, which completes when the returned watchdog is dropped.
+    pub fn watchdog(&self, operation: Operation) -> OperationWatchdog {
+        OperationWatchdog::start(
+            operation,
+            &self.runtime,
+            self.performance_warning_tx.clone(),
+        )
+    }
+
+    /// Checks the components that are shared by all states. `dns_expected` is whether the current
+    /// state should have set the DNS servers, if that is known.
+    pub fn health_check(&self, dns_expected: Option<bool>) -> Vec<HealthCheck> {
+        let metrics = self.firewall.metrics();
+        let slowest_apply = [metrics.connecting, metrics.connected, metrics.blocked]
+            .iter()
+            .map(|policy_metrics| policy_metrics.last_apply_duration)
+            .max()
+            .unwrap_or_default();
+        let firewall = if slowest_apply > SLOW_FIREWALL_APPLY_DURATION {
+            HealthCheck::warn(
+                "firewall",
+                format!(
+                    "applying a policy last took {} ms",
+                    slowest_apply.as_millis()
+                ),
+            )
+        } else {
+            HealthCheck::pass("firewall")
+        };
+
+        let route_manager = if self.route_manager.is_running() {
+            HealthCheck::pass("route manager")
+        } else {
+            HealthCheck::fail("route manager", "the route manager is not running")
+        };
+
+        let dns = match (dns_expected, self.dns_monitor.is_set()) {
+            (Some(true), false) => HealthCheck::fail("dns", "tunnel DNS servers are not set"),
+            (Some(false), true) => {
+                HealthCheck::warn("dns", "tunnel DNS servers have not been reset")
+            }
+            _ => HealthCheck::pass("dns"),
+        };
+
+        vec![firewall, route_manager, dns]
+    }
+
+    /// Describes the firewall policy, routes and DNS servers that are currently applied.
+    pub fn system_state(&self) -> SystemState {
+        let routes = self
+            .restore_journal
+            .entries()
+            .iter()
+            .filter_map(|entry| match entry {
+                JournalEntry::Route { route } => Some(RouteState::from(route)),
+                _ => None,
+            })
+            .collect();
+        #[cfg_attr(not(target_os = "macos"), allow(unused_mut))]
+        let mut state = SystemState::new(
+            self.firewall.policy().map(FirewallState::from),
+            routes,
+            self.dns_monitor.current().cloned(),
+        );
+        #[cfg(target_os = "macos")]
+        {
+            state.filtering_resolver = Some(self.filtering_resolver.stats());
+        }
+        state
+    }
+
+    /// Sets the physical interface that traffic to the relay must leave through, and starts
+    /// watching it. Returns whether the interface changed.
+    pub fn set_forced_interface(&mut self, forced_interface: Option<String>) -> bool {
+        if self.forced_interface == forced_interface {
+            return false;
+        }
+        #[cfg(not(target_os = "android"))]
+        self.forced_interface_monitor
+            .set_interface(forced_interface.clone());
+        self.forced_interface = forced_interface;
+        true
+    }
+
     pub fn set_allow_lan(&mut self, allow_lan: bool) -> Result<(), ErrorStateCause> {
         if self.allow_lan != allow_lan {
             self.allow_lan = allow_lan;
@@ -474,6 +1032,49 @@ impl SharedTunnelStateValues {
         Ok(())
     }
 
+    /// Registers that the tunnel monitor stopped without reporting why, such as when it panics.
+    /// Returns the cause to enter the error state with if this has happened too many times in a
+    /// row, or `None` if another attempt should be made.
+    pub fn register_tunnel_monitor_failure(&mut self) -> Option<ErrorStateCause> {
+        self.tunnel_monitor_failures += 1;
+        if self.tunnel_monitor_failures < MAX_TUNNEL_MONITOR_FAILURES {
+            return None;
+        }
+        self.tunnel_monitor_failures = 0;
+        Some(ErrorStateCause::TunnelMonitorStopped)
+    }
+
+    /// Registers an attempt to reconnect because of `cause`. Returns the cause to enter the error
+    /// state with if the limit for `cause` has been reached, or `None` if the attempt should be
+    /// made.
+    pub fn register_reconnect(&mut self, cause: ReconnectCause) -> Option<ErrorStateCause> {
+        if self.reconnect_budget.register(cause) {
+            return None;
+        }
+        log::warn!(
+            "Giving up reconnecting after too many attempts. Cause: {:?}",
+            cause
+        );
+        self.reconnect_budget.reset();
+        Some(ErrorStateCause::TooManyAttempts)
+    }
+
+    /// Registers a reconnect to reclaim the default route from another VPN. Returns `false` if
+    /// this has happened too many times recently, in which case the other VPN keeps taking the
+    /// route back and the tunnel should yield to it instead.
+    #[cfg(not(target_os = "android"))]
+    pub fn register_route_reclaim(&mut self) -> bool {
+        let now = Instant::now();
+        self.route_reclaims
+            .retain(|reclaim| now.saturating_duration_since(*reclaim) < ROUTE_RECLAIM_WINDOW);
+        if self.route_reclaims.len() >= MAX_ROUTE_RECLAIMS {
+            self.route_reclaims.clear();
+            return false;
+        }
+        self.route_reclaims.push(now);
+        true
+    }
+
     pub fn set_dns_servers(
         &mut self,
         dns_servers: Option<Vec<IpAddr>>,
@@ -538,6 +1139,41 @@ impl SharedTunnelStateValues {
         }
         let _ = tx.send(());
     }
+
+    /// Start the leak canary if it is enabled and not already running.
+    pub fn start_leak_canary(&mut self) {
+        #[cfg(not(target_os = "android"))]
+        {
+            if !leak_canary::is_enabled() || self.leak_canary.is_some() {
+                return;
+            }
+            let interface = self.physical_interface();
+            match leak_canary::LeakCanary::start(interface, self.log_dir.clone()) {
+                Ok(canary) => self.leak_canary = Some(canary),
+                Err(error) => log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to start leak canary")
+                ),
+            }
+        }
+    }
+
+    /// Stop the leak canary, if it is running, and write its report.
+    pub fn stop_leak_canary(&mut self) {
+        #[cfg(not(target_os = "android"))]
+        if let Some(canary) = self.leak_canary.take() {
+            canary.stop();
+        }
+    }
+
+    /// Name of the interface that the default route currently leaves through, bypassing the
+    /// tunnel.
+    #[cfg(not(target_os = "android"))]
+    fn physical_interface(&self) -> Option<String> {
+        self.physical_interface_monitor
+            .default_interface()
+            .map(|interface| interface.name)
+    }
 }
 
 /// Asynchronous result of an attempt to progress a state.
@@ -623,6 +1259,18 @@ state_wrapper! {
         Connected(ConnectedState),
         Disconnecting(DisconnectingState),
         Error(ErrorState),
+        Paused(PausedState),
+    }
+}
+
+impl TunnelStateWrapper {
+    /// Returns information about the tunnel interface, if it is up.
+    fn tunnel_metadata(&self) -> Option<TunnelMetadata> {
+        match self {
+            TunnelStateWrapper::Connecting(state) => state.tunnel_metadata().cloned(),
+            TunnelStateWrapper::Connected(state) => Some(state.tunnel_metadata().clone()),
+            _ => None,
+        }
     }
 }
 
@@ -630,8 +1278,13 @@ state_wrapper! {
 pub struct TunnelStateMachineHandle {
     command_tx: Arc<mpsc::UnboundedSender<TunnelCommand>>,
     shutdown_rx: oneshot::Receiver<()>,
+    state_history: history::StateHistory,
     #[cfg(windows)]
     split_tunnel: split_tunnel::SplitTunnelHandle,
+    #[cfg(feature = "qa-tools")]
+    simulation: simulation::SimulationHandle,
+    #[cfg(all(feature = "packet-capture", target_os = "linux"))]
+    packet_capture: PacketCaptureHandle,
 }
 
 impl TunnelStateMachineHandle {
@@ -646,14 +1299,110 @@ impl TunnelStateMachineHandle {
         }
     }
 
-    /// Returns tunnel command sender.
+    /// Returns tunnel command sender. Prefer the typed methods of the handle, which construct
+    /// the commands. The sender is only needed to send commands from where the handle is not
+    /// available.
     pub fn command_tx(&self) -> &Arc<mpsc::UnboundedSender<TunnelCommand>> {
         &self.command_tx
     }
 
+    /// Sends a command to the state machine.
+    pub fn send(&self, command: TunnelCommand) -> Result<(), CommandError> {
+        self.command_tx
+            .unbounded_send(command)
+            .map_err(|_| CommandError::ShutDown)
+    }
+
+    /// Sends a command that responds on a oneshot channel. The command is sent immediately, so
+    /// that it is ordered before any command sent after this returns, and the returned future
+    /// completes with the response.
+    fn request<T: Send + 'static>(
+        &self,
+        command: impl FnOnce(oneshot::Sender<T>) -> TunnelCommand,
+    ) -> impl Future<Output = Result<T, CommandError>> + Send + 'static {
+        let (tx, rx) = oneshot::channel();
+        let result = self.send(command(tx));
+        async move {
+            result?;
+            rx.await.map_err(|_| CommandError::NoResponse)
+        }
+    }
+
+    /// Opens the tunnel.
+    pub fn connect(&self) -> Result<(), CommandError> {
+        self.send(TunnelCommand::Connect)
+    }
+
+    /// Closes the tunnel. `cause` is reported in the resulting
+    /// [`TunnelStateTransition::Disconnected`].
+    pub fn disconnect(&self, cause: DisconnectCause) -> Result<(), CommandError> {
+        self.send(TunnelCommand::Disconnect(cause))
+    }
+
+    /// Closes any open tunnel and blocks all traffic.
+    pub fn block(&self, cause: ErrorStateCause) -> Result<(), CommandError> {
+        self.send(TunnelCommand::Block(cause))
+    }
+
+    /// Pauses the tunnel. See [`TunnelCommand::Pause`].
+    pub fn pause(&self) -> Result<(), CommandError> {
+        self.send(TunnelCommand::Pause)
+    }
+
+    /// Reopens a paused tunnel.
+    pub fn resume(&self) -> Result<(), CommandError> {
+        self.send(TunnelCommand::Resume)
+    }
+
+    /// Enables or disables LAN access in the firewall.
+    pub fn set_allow_lan(&self, allow_lan: bool) -> Result<(), CommandError> {
+        self.send(TunnelCommand::AllowLan(allow_lan))
+    }
+
+    /// Allows traffic to `endpoint` in all states. The returned future completes once the
+    /// firewall policy has been updated, whether or not that succeeded.
+    pub fn allow_endpoint(
+        &self,
+        endpoint: AllowedEndpoint,
+    ) -> impl Future<Output = Result<(), CommandError>> + Send + 'static {
+        self.request(|tx| TunnelCommand::AllowEndpoint(endpoint, tx))
+    }
+
+    /// Returns the firewall policy, routes and DNS servers that are currently applied.
+    pub fn get_system_state(
+        &self,
+    ) -> impl Future<Output = Result<SystemState, CommandError>> + Send + 'static {
+        self.request(TunnelCommand::GetSystemState)
+    }
+
+    /// Runs the health checks of the state machine.
+    pub fn health_check(
+        &self,
+    ) -> impl Future<Output = Result<Vec<HealthCheck>, CommandError>> + Send + 'static {
+        self.request(TunnelCommand::HealthCheck)
+    }
+
+    /// Returns the most recent state transitions, oldest first. The same history is written to
+    /// the log directory, if there is one, so that it is included in problem reports.
+    pub fn state_history(&self) -> Vec<StateHistoryEntry> {
+        self.state_history.entries()
+    }
+
     /// Returns split tunnel object handle.
     #[cfg(windows)]
     pub fn split_tunnel(&self) -> &split_tunnel::SplitTunnelHandle {
         &self.split_tunnel
     }
+
+    /// Returns a handle for injecting simulated events.
+    #[cfg(feature = "qa-tools")]
+    pub fn simulation(&self) -> &SimulationHandle {
+        &self.simulation
+    }
+
+    /// Returns a handle for capturing packets on the tunnel interface.
+    #[cfg(all(feature = "packet-capture", target_os = "linux"))]
+    pub fn packet_capture(&self) -> &PacketCaptureHandle {
+        &self.packet_capture
+    }
 }
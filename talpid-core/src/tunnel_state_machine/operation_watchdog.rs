@@ -0,0 +1,116 @@
+//! Timing of individual operations on the system configuration.
+//!
+//! When connecting is slow, the cause is often a single firewall, DNS or routing call that hangs
+//! for seconds. A watchdog is started before each such operation. If the operation is still
+//! running after [`SLOW_OPERATION_THRESHOLD`], this is logged right away, so that a hung call can
+//! be identified while it is hung. Once an operation that exceeded the threshold completes, a
+//! [`PerformanceWarning`] is sent with its duration.
+
+use crate::mpsc::Sender;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use talpid_types::performance::{Operation, PerformanceWarning};
+
+/// Operations that take longer than this are reported.
+pub const SLOW_OPERATION_THRESHOLD: Duration = Duration::from_secs(2);
+
+/// Receives warnings about slow operations.
+pub type PerformanceWarningSender = Arc<dyn Sender<PerformanceWarning> + Send + Sync>;
+
+/// Reports the operation if it is not dropped within the threshold.
+pub struct OperationWatchdog {
+    operation: Operation,
+    threshold: Duration,
+    start: Instant,
+    timer: tokio::task::JoinHandle<()>,
+    warning_tx: PerformanceWarningSender,
+}
+
+impl OperationWatchdog {
+    /// Starts timing `operation`. The operation is considered complete when the watchdog is
+    /// dropped.
+    pub fn start(
+        operation: Operation,
+        runtime: &tokio::runtime::Handle,
+        warning_tx: PerformanceWarningSender,
+    ) -> Self {
+        Self::with_threshold(operation, SLOW_OPERATION_THRESHOLD, runtime, warning_tx)
+    }
+
+    fn with_threshold(
+        operation: Operation,
+        threshold: Duration,
+        runtime: &tokio::runtime::Handle,
+        warning_tx: PerformanceWarningSender,
+    ) -> Self {
+        let timer = runtime.spawn(async move {
+            tokio::time::sleep(threshold).await;
+            log::warn!(
+                "{} has not completed after {} ms",
+                operation,
+                threshold.as_millis()
+            );
+        });
+        Self {
+            operation,
+            threshold,
+            start: Instant::now(),
+            timer,
+            warning_tx,
+        }
+    }
+}
+
+impl Drop for OperationWatchdog {
+    fn drop(&mut self) {
+        self.timer.abort();
+
+        let duration = self.start.elapsed();
+        if duration <= self.threshold {
+            return;
+        }
+        let warning = PerformanceWarning {
+            operation: self.operation,
+            duration,
+        };
+        log::warn!("Slow operation: {}", warning);
+        if self.warning_tx.send(warning).is_err() {
+            log::debug!("Failed to send performance warning");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::{channel::mpsc, StreamExt};
+
+    #[tokio::test]
+    async fn test_slow_operation_is_reported() {
+        let (tx, mut rx) = mpsc::unbounded();
+        let runtime = tokio::runtime::Handle::current();
+
+        let watchdog = OperationWatchdog::with_threshold(
+            Operation::SetDns,
+            Duration::from_millis(10),
+            &runtime,
+            Arc::new(tx.clone()),
+        );
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        drop(watchdog);
+
+        let warning = rx.next().await.unwrap();
+        assert_eq!(warning.operation, Operation::SetDns);
+        assert!(warning.duration >= Duration::from_millis(20));
+
+        drop(OperationWatchdog::with_threshold(
+            Operation::ClearRoutes,
+            Duration::from_secs(60),
+            &runtime,
+            Arc::new(tx),
+        ));
+        assert!(rx.next().await.is_none());
+    }
+}
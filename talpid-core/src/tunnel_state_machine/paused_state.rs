@@ -0,0 +1,229 @@
+use super::{
+    ConnectingState, DisconnectedState, ErrorState, EventConsequence, SharedTunnelStateValues,
+    TunnelCommand, TunnelCommandReceiver, TunnelState, TunnelStateTransition, TunnelStateWrapper,
+};
+use futures::StreamExt;
+#[cfg(windows)]
+use talpid_types::ErrorExt;
+use talpid_types::{
+    net::{AllowedTunnelTraffic, TunnelParameters},
+    tunnel::{DisconnectCause, ErrorStateCause, FirewallPolicyError},
+};
+
+/// The tunnel has been closed on request, but traffic outside of it is still blocked. The
+/// parameters of the closed tunnel are kept so that it can be reopened without generating new
+/// ones.
+///
+/// On Android, the tunnel device is kept open, so traffic continues to be captured and dropped.
+pub struct PausedState {
+    tunnel_parameters: TunnelParameters,
+    /// Set when the daemon has asked to reconnect while paused, e.g. because the settings have
+    /// changed. New parameters are then generated when resuming.
+    parameters_outdated: bool,
+}
+
+impl PausedState {
+    /// The firewall policy is the same as when starting to connect, so that resuming does not
+    /// open any gaps.
+    fn set_firewall_policy(
+        shared_values: &mut SharedTunnelStateValues,
+        tunnel_parameters: &TunnelParameters,
+    ) -> Result<(), FirewallPolicyError> {
+        ConnectingState::set_firewall_policy(
+            shared_values,
+            tunnel_parameters,
+            &None,
+            AllowedTunnelTraffic::None,
+        )
+    }
+
+    fn reset_firewall(self, shared_values: &mut SharedTunnelStateValues) -> EventConsequence {
+        match Self::set_firewall_policy(shared_values, &self.tunnel_parameters) {
+            Ok(()) => EventConsequence::SameState(self.into()),
+            Err(error) => EventConsequence::NewState(ErrorState::enter(
+                shared_values,
+                ErrorStateCause::SetFirewallPolicyError(error),
+            )),
+        }
+    }
+
+    fn resume(self, shared_values: &mut SharedTunnelStateValues) -> EventConsequence {
+        if shared_values.is_offline {
+            return EventConsequence::NewState(ErrorState::enter(
+                shared_values,
+                ErrorStateCause::IsOffline,
+            ));
+        }
+        if self.parameters_outdated {
+            log::debug!("Generating new tunnel parameters since the old ones are outdated");
+            return EventConsequence::NewState(ConnectingState::enter(shared_values, 0));
+        }
+        EventConsequence::NewState(ConnectingState::enter_with_parameters(
+            shared_values,
+            self.tunnel_parameters,
+            0,
+        ))
+    }
+}
+
+impl TunnelState for PausedState {
+    type Bootstrap = TunnelParameters;
+
+    fn enter(
+        shared_values: &mut SharedTunnelStateValues,
+        tunnel_parameters: Self::Bootstrap,
+    ) -> (TunnelStateWrapper, TunnelStateTransition) {
+        shared_values.stop_leak_canary();
+
+        #[cfg(windows)]
+        if let Err(error) = shared_values.split_tunnel.set_tunnel_addresses(None) {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to reset addresses in split tunnel driver")
+            );
+        }
+
+        if let Err(error) = Self::set_firewall_policy(shared_values, &tunnel_parameters) {
+            return ErrorState::enter(
+                shared_values,
+                ErrorStateCause::SetFirewallPolicyError(error),
+            );
+        }
+
+        let tunnel_endpoint = tunnel_parameters.get_tunnel_endpoint();
+        (
+            TunnelStateWrapper::from(PausedState {
+                tunnel_parameters,
+                parameters_outdated: false,
+            }),
+            TunnelStateTransition::Paused(tunnel_endpoint),
+        )
+    }
+
+    fn handle_event(
+        mut self,
+        runtime: &tokio::runtime::Handle,
+        commands: &mut TunnelCommandReceiver,
+        shared_values: &mut SharedTunnelStateValues,
+    ) -> EventConsequence {
+        use self::EventConsequence::*;
+
+        match runtime.block_on(commands.next()) {
+            Some(TunnelCommand::AllowLan(allow_lan)) => {
+                if let Err(error_state_cause) = shared_values.set_allow_lan(allow_lan) {
+                    NewState(ErrorState::enter(shared_values, error_state_cause))
+                } else {
+                    self.reset_firewall(shared_values)
+                }
+            }
+            Some(TunnelCommand::AllowEndpoint(endpoint, tx)) => {
+                let consequence = if shared_values.allowed_endpoint != endpoint {
+                    shared_values.allowed_endpoint = endpoint;
+                    self.reset_firewall(shared_values)
+                } else {
+                    SameState(self.into())
+                };
+                let _ = tx.send(());
+                consequence
+            }
+            Some(TunnelCommand::Dns(servers)) => {
+                if let Err(error_state_cause) = shared_values.set_dns_servers(servers) {
+                    NewState(ErrorState::enter(shared_values, error_state_cause))
+                } else {
+                    SameState(self.into())
+                }
+            }
+            Some(TunnelCommand::BlockWhenDisconnected(block_when_disconnected)) => {
+                shared_values.block_when_disconnected = block_when_disconnected;
+                SameState(self.into())
+            }
+            Some(TunnelCommand::FlushDnsCache(flush_dns_cache)) => {
+                shared_values.dns_monitor.set_flush_cache(flush_dns_cache);
+                SameState(self.into())
+            }
+            Some(TunnelCommand::RecoveryAllowlist(recovery_allowlist)) => {
+                shared_values.recovery_allowlist = recovery_allowlist;
+                SameState(self.into())
+            }
+            Some(TunnelCommand::VpnCoexistence(vpn_coexistence)) => {
+                if shared_values.vpn_coexistence != vpn_coexistence {
+                    shared_values.vpn_coexistence = vpn_coexistence;
+                    self.reset_firewall(shared_values)
+                } else {
+                    SameState(self.into())
+                }
+            }
+            Some(TunnelCommand::BlockedTunnelProtocols(blocked_tunnel_protocols)) => {
+                shared_values.blocked_tunnel_protocols = blocked_tunnel_protocols;
+                SameState(self.into())
+            }
+            Some(TunnelCommand::TrafficShaping(_)) => SameState(self.into()),
+            Some(TunnelCommand::ReconnectLimits(reconnect_limits)) => {
+                shared_values.reconnect_budget.set_limits(reconnect_limits);
+                SameState(self.into())
+            }
+            Some(TunnelCommand::RouteTakeoverPolicy(policy)) => {
+                shared_values.route_takeover_policy = policy;
+                SameState(self.into())
+            }
+            Some(TunnelCommand::ErrorStatePolicy(policy)) => {
+                shared_values.error_state_policy = policy;
+                SameState(self.into())
+            }
+            Some(TunnelCommand::SetForcedInterface(forced_interface)) => {
+                shared_values.set_forced_interface(forced_interface);
+                SameState(self.into())
+            }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::ForcedInterfaceChanged(_)) => SameState(self.into()),
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::DefaultInterfaceChanged) => SameState(self.into()),
+            Some(TunnelCommand::HealthCheck(tx)) => {
+                let _ = tx.send(shared_values.health_check(None));
+                SameState(self.into())
+            }
+            Some(TunnelCommand::GetSystemState(tx)) => {
+                let _ = tx.send(shared_values.system_state());
+                SameState(self.into())
+            }
+            Some(TunnelCommand::IsOffline(is_offline)) => {
+                // Connectivity only matters once the tunnel is resumed
+                shared_values.is_offline = is_offline;
+                SameState(self.into())
+            }
+            Some(TunnelCommand::Connect) => {
+                // Reconnecting would defeat the purpose of pausing, so remain paused until asked
+                // to resume, but use fresh parameters then
+                self.parameters_outdated = true;
+                SameState(self.into())
+            }
+            Some(TunnelCommand::Resume) => self.resume(shared_values),
+            Some(TunnelCommand::Pause) => SameState(self.into()),
+            Some(TunnelCommand::Disconnect(cause)) => {
+                NewState(DisconnectedState::enter(shared_values, (true, Some(cause))))
+            }
+            None => NewState(DisconnectedState::enter(
+                shared_values,
+                (true, Some(DisconnectCause::Shutdown)),
+            )),
+            Some(TunnelCommand::Block(reason)) => {
+                NewState(ErrorState::enter(shared_values, reason))
+            }
+            #[cfg(target_os = "android")]
+            Some(TunnelCommand::BypassSocket(fd, done_tx)) => {
+                shared_values.bypass_socket(fd, done_tx);
+                SameState(self.into())
+            }
+            #[cfg(windows)]
+            Some(TunnelCommand::SetExcludedApps(result_tx, paths)) => {
+                shared_values.split_tunnel.set_paths(&paths, result_tx);
+                SameState(self.into())
+            }
+            #[cfg(windows)]
+            Some(TunnelCommand::ReinstallSplitTunnelDriver(result_tx)) => {
+                shared_values.split_tunnel.reinstall_driver(result_tx);
+                SameState(self.into())
+            }
+        }
+    }
+}
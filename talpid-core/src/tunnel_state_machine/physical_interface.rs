@@ -0,0 +1,303 @@
+//! Tracks the physical interface that connections made outside of the tunnel should be bound to,
+//! such as API requests made by the daemon.
+//!
+//! While the tunnel is up, no interface is published, and such connections are routed through
+//! the tunnel. In all other states, the interface that the default route leaves through is
+//! published, and it is republished whenever the default route changes. Binding to it prevents
+//! connections from looping through a tunnel interface that is being set up or torn down.
+//!
+//! The state machine is also told when the default route moves to another interface, since that
+//! is a network change that may let it recover from errors.
+
+use super::TunnelCommand;
+use futures::{channel::mpsc, StreamExt};
+use std::sync::{Arc, Mutex, Weak};
+use talpid_types::net::PhysicalInterface;
+
+#[cfg(target_os = "linux")]
+use crate::routing::RouteManagerHandle;
+#[cfg(windows)]
+use crate::winnet;
+#[cfg(target_os = "linux")]
+use std::net::{IpAddr, Ipv4Addr};
+use talpid_types::ErrorExt;
+
+/// Destination used to look up the route that bypasses the tunnel. This is in TEST-NET-1, so it
+/// is routed through the default route.
+#[cfg(target_os = "linux")]
+const ROUTE_PROBE_DESTINATION: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+
+/// Errors that can occur while starting the monitor.
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    /// Failed to listen for route changes.
+    #[cfg(not(windows))]
+    #[error(display = "Failed to listen for route changes")]
+    ListenForRouteChanges(#[error(source)] crate::routing::Error),
+
+    /// Failed to register a default route callback.
+    #[cfg(windows)]
+    #[error(display = "Failed to register default route callback")]
+    RegisterCallback(#[error(source)] winnet::DefaultRouteCallbackError),
+}
+
+pub(super) enum MonitorEvent {
+    TunnelUp(bool),
+    DefaultInterface(Option<PhysicalInterface>),
+}
+
+/// Handle to the task that publishes the physical interface.
+pub struct PhysicalInterfaceMonitor {
+    event_tx: mpsc::UnboundedSender<MonitorEvent>,
+    default_interface: Arc<Mutex<Option<PhysicalInterface>>>,
+    #[cfg(windows)]
+    _callback_handle: winnet::WinNetCallbackHandle,
+}
+
+impl PhysicalInterfaceMonitor {
+    /// Starts monitoring the default route. `Some` interface is sent to `listener` when
+    /// connections should be bound to it, and `None` when they should not be bound.
+    /// [`TunnelCommand::DefaultInterfaceChanged`] is sent to `command_tx` whenever the default
+    /// route starts leaving through another interface.
+    pub async fn spawn(
+        listener: mpsc::UnboundedSender<Option<PhysicalInterface>>,
+        command_tx: Weak<mpsc::UnboundedSender<TunnelCommand>>,
+        #[cfg(target_os = "linux")] route_manager: RouteManagerHandle,
+    ) -> Result<Self, Error> {
+        let (event_tx, mut event_rx) = mpsc::unbounded();
+
+        #[cfg(target_os = "linux")]
+        let initial_interface = get_default_interface(&route_manager).await;
+        #[cfg(target_os = "macos")]
+        let initial_interface = get_default_interface().await;
+        #[cfg(windows)]
+        let initial_interface = get_default_interface();
+
+        #[cfg(not(windows))]
+        spawn_route_listener(
+            event_tx.clone(),
+            #[cfg(target_os = "linux")]
+            route_manager,
+        )
+        .await?;
+        #[cfg(windows)]
+        let callback_handle = winnet::add_default_route_change_callback(
+            Some(default_route_change_callback),
+            event_tx.clone(),
+        )
+        .map_err(Error::RegisterCallback)?;
+
+        let default_interface = Arc::new(Mutex::new(initial_interface.clone()));
+        let shared_interface = default_interface.clone();
+
+        tokio::spawn(async move {
+            let mut tunnel_up = false;
+            let mut current_interface = initial_interface;
+            let mut published = current_interface.clone();
+            if listener.unbounded_send(published.clone()).is_err() {
+                return;
+            }
+
+            while let Some(event) = event_rx.next().await {
+                match event {
+                    MonitorEvent::TunnelUp(up) => tunnel_up = up,
+                    MonitorEvent::DefaultInterface(interface) => {
+                        *shared_interface.lock().unwrap() = interface.clone();
+                        if interface.is_some() && interface != current_interface {
+                            if let Some(command_tx) = command_tx.upgrade() {
+                                let _ = command_tx
+                                    .unbounded_send(TunnelCommand::DefaultInterfaceChanged);
+                            }
+                        }
+                        current_interface = interface;
+                    }
+                }
+
+                let new_binding = if tunnel_up {
+                    None
+                } else {
+                    current_interface.clone()
+                };
+                if new_binding != published {
+                    published = new_binding;
+                    if listener.unbounded_send(published.clone()).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            event_tx,
+            default_interface,
+            #[cfg(windows)]
+            _callback_handle: callback_handle,
+        })
+    }
+
+    /// Notify the monitor of whether the tunnel is up.
+    pub fn set_tunnel_up(&self, tunnel_up: bool) {
+        let _ = self
+            .event_tx
+            .unbounded_send(MonitorEvent::TunnelUp(tunnel_up));
+    }
+
+    /// Returns a sender that can be used to inject simulated default route changes.
+    #[cfg(feature = "qa-tools")]
+    pub(super) fn event_tx(&self) -> mpsc::UnboundedSender<MonitorEvent> {
+        self.event_tx.clone()
+    }
+
+    /// Returns the interface that the default route currently leaves through, regardless of
+    /// whether the tunnel is up.
+    pub fn default_interface(&self) -> Option<PhysicalInterface> {
+        self.default_interface.lock().unwrap().clone()
+    }
+}
+
+#[cfg(not(windows))]
+async fn spawn_route_listener(
+    event_tx: mpsc::UnboundedSender<MonitorEvent>,
+    #[cfg(target_os = "linux")] route_manager: RouteManagerHandle,
+) -> Result<(), Error> {
+    #[cfg(target_os = "linux")]
+    let mut route_changes = Box::pin(
+        route_manager
+            .change_listener()
+            .await
+            .map_err(Error::ListenForRouteChanges)?,
+    );
+    #[cfg(target_os = "macos")]
+    let mut route_changes = Box::pin(crate::routing::listen_for_default_route_changes().map_err(
+        |error| Error::ListenForRouteChanges(crate::routing::Error::PlatformError(error)),
+    )?);
+
+    tokio::spawn(async move {
+        while route_changes.next().await.is_some() {
+            #[cfg(target_os = "linux")]
+            let interface = get_default_interface(&route_manager).await;
+            #[cfg(target_os = "macos")]
+            let interface = get_default_interface().await;
+
+            if event_tx
+                .unbounded_send(MonitorEvent::DefaultInterface(interface))
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+async fn get_default_interface(route_manager: &RouteManagerHandle) -> Option<PhysicalInterface> {
+    let route = match route_manager
+        .get_destination_route(ROUTE_PROBE_DESTINATION, true)
+        .await
+    {
+        Ok(route) => route?,
+        Err(error) => {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to obtain the default route")
+            );
+            return None;
+        }
+    };
+    let name = route.get_node().get_device()?.to_owned();
+    match crate::linux::iface_index(&name) {
+        Ok(index) => Some(PhysicalInterface { name, index }),
+        Err(error) => {
+            log::error!("{}", error.display_chain());
+            None
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+async fn get_default_interface() -> Option<PhysicalInterface> {
+    let (v4_node, _) = match crate::routing::get_default_routes().await {
+        Ok(nodes) => nodes,
+        Err(error) => {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to obtain the default route")
+            );
+            return None;
+        }
+    };
+    let name = v4_node?.get_device()?.to_owned();
+    match nix::net::if_::if_nametoindex(name.as_str()) {
+        Ok(index) => Some(PhysicalInterface { name, index }),
+        Err(error) => {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to obtain the index of the default interface")
+            );
+            None
+        }
+    }
+}
+
+#[cfg(windows)]
+fn get_default_interface() -> Option<PhysicalInterface> {
+    use windows_sys::Win32::NetworkManagement::{
+        IpHelper::ConvertInterfaceLuidToIndex, Ndis::NET_LUID_LH,
+    };
+
+    let route = match winnet::get_best_default_route(winnet::WinNetAddrFamily::IPV4, &[]) {
+        Ok(route) => route?,
+        Err(error) => {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to obtain the default route")
+            );
+            return None;
+        }
+    };
+    let luid = NET_LUID_LH {
+        Value: route.interface_luid,
+    };
+    let mut index = 0u32;
+    let status = unsafe { ConvertInterfaceLuidToIndex(&luid, &mut index) };
+    if status != 0 {
+        log::error!(
+            "Failed to convert interface LUID to interface index: {}",
+            status
+        );
+        return None;
+    }
+    let name = crate::windows::alias_from_luid(&luid)
+        .ok()?
+        .to_string_lossy()
+        .into_owned();
+    Some(PhysicalInterface { name, index })
+}
+
+#[cfg(windows)]
+unsafe extern "system" fn default_route_change_callback(
+    event_type: winnet::WinNetDefaultRouteChangeEventType,
+    family: winnet::WinNetAddrFamily,
+    change: &winnet::WinNetDefaultRouteChange,
+    ctx: *mut libc::c_void,
+) {
+    use winnet::WinNetDefaultRouteChangeEventType::*;
+
+    if let winnet::WinNetAddrFamily::IPV6 = family {
+        return;
+    }
+    let interface = match event_type {
+        DefaultRouteChanged | DefaultRouteUpdatedDetails => {
+            let details = change.interface_details();
+            Some(PhysicalInterface {
+                name: details.alias(),
+                index: details.index,
+            })
+        }
+        DefaultRouteRemoved => None,
+    };
+    let event_tx = &*(ctx as *const mpsc::UnboundedSender<MonitorEvent>);
+    let _ = event_tx.unbounded_send(MonitorEvent::DefaultInterface(interface));
+}
@@ -0,0 +1,112 @@
+//! Limits on how many times in a row the state machine reconnects for each cause of a failed
+//! connection attempt.
+//!
+//! Without a limit, transient errors that never go away, such as on a laptop that is closed in a
+//! bag, cause the state machine to keep reconnecting forever, draining the battery. Once the limit
+//! for a cause is reached, the error state is entered instead, until the user connects again or
+//! the network changes.
+
+use std::collections::HashMap;
+use talpid_types::tunnel::ReconnectLimits;
+
+/// Reason for making another attempt to connect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReconnectCause {
+    /// The tunnel failed to start with an error that may be transient.
+    StartFailure,
+    /// The tunnel was started, but it was closed before it was established.
+    Timeout,
+    /// The bridge stopped working.
+    BridgeDown,
+}
+
+/// Counts consecutive reconnect attempts for each cause, and tells when the limit set for a
+/// cause has been reached.
+#[derive(Debug, Default)]
+pub struct ReconnectBudget {
+    limits: ReconnectLimits,
+    attempts: HashMap<ReconnectCause, u32>,
+}
+
+impl ReconnectBudget {
+    pub fn new(limits: ReconnectLimits) -> Self {
+        Self {
+            limits,
+            attempts: HashMap::new(),
+        }
+    }
+
+    /// Sets new limits. Attempts that have already been made count towards the new limits.
+    pub fn set_limits(&mut self, limits: ReconnectLimits) {
+        self.limits = limits;
+    }
+
+    /// Registers an attempt to reconnect because of `cause`. Returns `false` if the limit for
+    /// `cause` has already been reached, in which case no attempt should be made.
+    pub fn register(&mut self, cause: ReconnectCause) -> bool {
+        let attempts = self.attempts.entry(cause).or_insert(0);
+        if let Some(limit) = self.limit(cause) {
+            if *attempts >= limit {
+                return false;
+            }
+        }
+        *attempts += 1;
+        true
+    }
+
+    /// Forgets about all attempts, such as when a new sequence of attempts is started.
+    pub fn reset(&mut self) {
+        self.attempts.clear();
+    }
+
+    fn limit(&self, cause: ReconnectCause) -> Option<u32> {
+        match cause {
+            ReconnectCause::StartFailure => self.limits.start_failures,
+            ReconnectCause::Timeout => self.limits.timeouts,
+            ReconnectCause::BridgeDown => self.limits.bridge_failures,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_unlimited_by_default() {
+        let mut budget = ReconnectBudget::default();
+        for _ in 0..1000 {
+            assert!(budget.register(ReconnectCause::Timeout));
+        }
+    }
+
+    #[test]
+    fn test_limit_per_cause() {
+        let mut budget = ReconnectBudget::new(ReconnectLimits {
+            start_failures: Some(2),
+            timeouts: Some(0),
+            bridge_failures: None,
+        });
+
+        assert!(!budget.register(ReconnectCause::Timeout));
+
+        assert!(budget.register(ReconnectCause::StartFailure));
+        assert!(budget.register(ReconnectCause::BridgeDown));
+        assert!(budget.register(ReconnectCause::StartFailure));
+        assert!(!budget.register(ReconnectCause::StartFailure));
+        assert!(budget.register(ReconnectCause::BridgeDown));
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut budget = ReconnectBudget::new(ReconnectLimits {
+            start_failures: Some(1),
+            ..ReconnectLimits::default()
+        });
+
+        assert!(budget.register(ReconnectCause::StartFailure));
+        assert!(!budget.register(ReconnectCause::StartFailure));
+        budget.reset();
+        assert!(budget.register(ReconnectCause::StartFailure));
+    }
+}
@@ -0,0 +1,248 @@
+//! Detection of other VPN clients that take over the default route while the tunnel is up.
+//!
+//! Another VPN that is started while connected typically adds a default route, or a pair of
+//! `/1` routes, through its own interface. Depending on the platform and on route metrics, some
+//! or all traffic may then bypass our tunnel. While connected, the monitor watches for such routes
+//! and reports each competing interface once. What happens next is decided by the
+//! [`RouteTakeoverPolicy`](talpid_types::net::route_takeover::RouteTakeoverPolicy).
+
+#[cfg(not(windows))]
+use futures::future::{abortable, AbortHandle};
+use futures::{channel::mpsc, StreamExt};
+use std::collections::HashSet;
+use talpid_types::net::route_takeover::CompetingVpn;
+
+use talpid_types::ErrorExt;
+
+/// Watches for routes that compete with the routes of the tunnel. Monitoring stops when the
+/// monitor is dropped.
+pub struct RouteTakeoverMonitor {
+    takeover_rx: mpsc::UnboundedReceiver<CompetingVpn>,
+    /// Interfaces that have already been reported.
+    reported: HashSet<String>,
+    #[cfg(not(windows))]
+    abort_handle: AbortHandle,
+    #[cfg(windows)]
+    _notifier_handle: Box<crate::windows::RouteNotifierHandle<'static>>,
+}
+
+impl RouteTakeoverMonitor {
+    /// Starts watching for routes that lead to interfaces other than `tunnel_interface`. Returns
+    /// `None` if route changes cannot be monitored.
+    pub fn start(
+        tunnel_interface: String,
+        #[cfg(not(windows))] runtime: &tokio::runtime::Handle,
+        #[cfg(target_os = "linux")] route_manager: crate::routing::RouteManagerHandle,
+    ) -> Option<Self> {
+        let (takeover_tx, takeover_rx) = mpsc::unbounded();
+
+        #[cfg(not(windows))]
+        {
+            let (task, abort_handle) = abortable(watch_routes(
+                tunnel_interface,
+                takeover_tx,
+                #[cfg(target_os = "linux")]
+                route_manager,
+            ));
+            runtime.spawn(task);
+            Some(Self {
+                takeover_rx,
+                reported: HashSet::new(),
+                abort_handle,
+            })
+        }
+
+        #[cfg(windows)]
+        {
+            let notifier_handle = crate::windows::notify_route_change(
+                move |row, notify_type| {
+                    route_change_callback(&tunnel_interface, &takeover_tx, row, notify_type)
+                },
+                None,
+            )
+            .map_err(|error| {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to register route change callback")
+                )
+            })
+            .ok()?;
+            Some(Self {
+                takeover_rx,
+                reported: HashSet::new(),
+                _notifier_handle: notifier_handle,
+            })
+        }
+    }
+
+    /// Completes when a competing VPN that has not been reported before is found. Never
+    /// completes if there is no monitor.
+    pub async fn wait_for_takeover(monitor: &mut Option<Self>) -> CompetingVpn {
+        if let Some(monitor) = monitor {
+            while let Some(competing_vpn) = monitor.takeover_rx.next().await {
+                if monitor.reported.insert(competing_vpn.interface.clone()) {
+                    return competing_vpn;
+                }
+            }
+        }
+        futures::future::pending().await
+    }
+}
+
+#[cfg(not(windows))]
+impl Drop for RouteTakeoverMonitor {
+    fn drop(&mut self) {
+        self.abort_handle.abort();
+    }
+}
+
+#[cfg(target_os = "linux")]
+async fn watch_routes(
+    tunnel_interface: String,
+    takeover_tx: mpsc::UnboundedSender<CompetingVpn>,
+    route_manager: crate::routing::RouteManagerHandle,
+) {
+    use crate::routing::CallbackMessage;
+    use netlink_packet_route::rtnl::constants::RT_TABLE_MAIN;
+
+    let mut route_changes = match route_manager.change_listener().await {
+        Ok(route_changes) => Box::pin(route_changes),
+        Err(error) => {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to listen for route changes")
+            );
+            return;
+        }
+    };
+    while let Some(change) = route_changes.next().await {
+        let route = match change {
+            CallbackMessage::NewRoute(route) => route,
+            _ => continue,
+        };
+        // Routes in other tables, such as those of Tailscale, are only used by the traffic that
+        // policy rules direct there.
+        if route.get_table_id() != u32::from(RT_TABLE_MAIN) {
+            continue;
+        }
+        let interface = match route.get_node().get_device() {
+            Some(interface) if interface != tunnel_interface => interface,
+            _ => continue,
+        };
+        if let Some(competing_vpn) = CompetingVpn::from_route(route.get_prefix(), interface, None) {
+            if takeover_tx.unbounded_send(competing_vpn).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+async fn watch_routes(tunnel_interface: String, takeover_tx: mpsc::UnboundedSender<CompetingVpn>) {
+    use ipnetwork::IpNetwork;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    let mut route_changes = match crate::routing::listen_for_default_and_half_route_changes() {
+        Ok(route_changes) => Box::pin(route_changes),
+        Err(error) => {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to listen for route changes")
+            );
+            return;
+        }
+    };
+    while route_changes.next().await.is_some() {
+        let (v4_node, v6_node) = match crate::routing::get_default_routes().await {
+            Ok(nodes) => nodes,
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to obtain the default routes")
+                );
+                continue;
+            }
+        };
+        let half_routes = match crate::routing::get_half_default_routes().await {
+            Ok(routes) => routes,
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to obtain the /1 routes")
+                );
+                continue;
+            }
+        };
+        let defaults = [
+            (IpNetwork::new(Ipv4Addr::UNSPECIFIED.into(), 0), v4_node),
+            (IpNetwork::new(Ipv6Addr::UNSPECIFIED.into(), 0), v6_node),
+        ]
+        .into_iter()
+        .map(|(prefix, node)| {
+            (
+                prefix.expect("unspecified address with a zero-length prefix is valid"),
+                node,
+            )
+        });
+        let routes = defaults.chain(
+            half_routes
+                .into_iter()
+                .map(|(prefix, node)| (prefix, Some(node))),
+        );
+        for (prefix, node) in routes {
+            let interface = match node.as_ref().and_then(|node| node.get_device()) {
+                Some(interface) if interface != tunnel_interface => interface,
+                _ => continue,
+            };
+            if let Some(competing_vpn) = CompetingVpn::from_route(prefix, interface, None) {
+                if takeover_tx.unbounded_send(competing_vpn).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+fn route_change_callback(
+    tunnel_interface: &str,
+    takeover_tx: &mpsc::UnboundedSender<CompetingVpn>,
+    row: &windows_sys::Win32::NetworkManagement::IpHelper::MIB_IPFORWARD_ROW2,
+    notify_type: i32,
+) {
+    use ipnetwork::IpNetwork;
+    use windows_sys::Win32::NetworkManagement::IpHelper::{
+        MibAddInstance, MibParameterNotification,
+    };
+
+    if notify_type != MibAddInstance && notify_type != MibParameterNotification {
+        return;
+    }
+    // Only default routes and `/1` routes can take over the default route
+    let prefix_len = row.DestinationPrefix.PrefixLength;
+    if prefix_len > 1 {
+        return;
+    }
+    let prefix =
+        match crate::windows::try_socketaddr_from_inet_sockaddr(row.DestinationPrefix.Prefix)
+            .ok()
+            .and_then(|address| IpNetwork::new(address.ip(), prefix_len).ok())
+        {
+            Some(prefix) => prefix,
+            None => return,
+        };
+    let interface = match crate::windows::alias_from_luid(&row.InterfaceLuid) {
+        Ok(alias) => alias.to_string_lossy().into_owned(),
+        Err(_) => return,
+    };
+    if interface == tunnel_interface {
+        return;
+    }
+    let description = crate::windows::description_from_luid(&row.InterfaceLuid).ok();
+
+    if let Some(competing_vpn) =
+        CompetingVpn::from_route(prefix, &interface, description.as_deref())
+    {
+        let _ = takeover_tx.unbounded_send(competing_vpn);
+    }
+}
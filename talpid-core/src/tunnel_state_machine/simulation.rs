@@ -0,0 +1,125 @@
+//! Injection of simulated events into the tunnel state machine, for reproducing race conditions
+//! on demand. This is only included in builds with the `qa-tools` feature.
+//!
+//! Every injected event is logged with a `[SIMULATED]` prefix, so that it can never be confused
+//! with a real event when reading the logs.
+
+#[cfg(not(target_os = "android"))]
+use super::physical_interface::MonitorEvent;
+use super::TunnelCommand;
+use crate::tunnel::TunnelEvent;
+use futures::channel::mpsc;
+use std::sync::{Arc, Mutex, Weak};
+#[cfg(not(target_os = "android"))]
+use talpid_types::net::PhysicalInterface;
+use talpid_types::tunnel::ParameterGenerationError;
+
+/// Errors that can occur while injecting an event.
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    /// The tunnel state machine is no longer running.
+    #[error(display = "The tunnel state machine is not running")]
+    StateMachineDown,
+
+    /// The event cannot be simulated on this platform.
+    #[error(display = "The event cannot be simulated on this platform")]
+    Unsupported,
+}
+
+/// Synthetic event to inject into the tunnel state machine.
+#[derive(Debug, Clone)]
+pub enum SimulatedEvent {
+    /// Pretend that the device went offline or came back online. The real offline monitor may
+    /// override this the next time connectivity changes.
+    Offline(bool),
+    /// Pretend that the default route changed to go through the given interface. `None` means
+    /// that the default route was removed.
+    #[cfg(not(target_os = "android"))]
+    DefaultRouteChanged(Option<PhysicalInterface>),
+    /// Drop the next `n` events emitted by the tunnel monitor.
+    DropTunnelEvents(u32),
+    /// Fail the next tunnel parameter generation with the given error.
+    ParameterGenerationFailure(ParameterGenerationError),
+}
+
+#[derive(Default)]
+struct SimulationState {
+    dropped_tunnel_events: u32,
+    parameter_error: Option<ParameterGenerationError>,
+    #[cfg(not(target_os = "android"))]
+    physical_interface_tx: Option<mpsc::UnboundedSender<MonitorEvent>>,
+}
+
+/// Handle used to inject simulated events into the tunnel state machine.
+#[derive(Clone)]
+pub struct SimulationHandle {
+    state: Arc<Mutex<SimulationState>>,
+    command_tx: Weak<mpsc::UnboundedSender<TunnelCommand>>,
+}
+
+impl SimulationHandle {
+    pub(super) fn new(command_tx: Weak<mpsc::UnboundedSender<TunnelCommand>>) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(SimulationState::default())),
+            command_tx,
+        }
+    }
+
+    /// Inject `event` into the tunnel state machine.
+    pub fn inject(&self, event: SimulatedEvent) -> Result<(), Error> {
+        log::warn!("[SIMULATED] Injecting event: {:?}", event);
+
+        let mut state = self.state.lock().unwrap();
+        match event {
+            SimulatedEvent::Offline(is_offline) => {
+                let command_tx = self.command_tx.upgrade().ok_or(Error::StateMachineDown)?;
+                command_tx
+                    .unbounded_send(TunnelCommand::IsOffline(is_offline))
+                    .map_err(|_| Error::StateMachineDown)
+            }
+            #[cfg(not(target_os = "android"))]
+            SimulatedEvent::DefaultRouteChanged(interface) => {
+                let tx = state
+                    .physical_interface_tx
+                    .as_ref()
+                    .ok_or(Error::Unsupported)?;
+                tx.unbounded_send(MonitorEvent::DefaultInterface(interface))
+                    .map_err(|_| Error::StateMachineDown)
+            }
+            SimulatedEvent::DropTunnelEvents(count) => {
+                state.dropped_tunnel_events = count;
+                Ok(())
+            }
+            SimulatedEvent::ParameterGenerationFailure(error) => {
+                state.parameter_error = Some(error);
+                Ok(())
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "android"))]
+    pub(super) fn set_physical_interface_tx(&self, tx: mpsc::UnboundedSender<MonitorEvent>) {
+        self.state.lock().unwrap().physical_interface_tx = Some(tx);
+    }
+
+    /// Returns whether `event` should be dropped rather than handled.
+    pub(super) fn drop_tunnel_event(&self, event: &TunnelEvent) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.dropped_tunnel_events == 0 {
+            return false;
+        }
+        state.dropped_tunnel_events -= 1;
+        log::warn!("[SIMULATED] Dropping tunnel event: {:?}", event);
+        true
+    }
+
+    /// Returns the error that the next tunnel parameter generation should fail with, if any.
+    pub(super) fn take_parameter_error(&self) -> Option<ParameterGenerationError> {
+        let error = self.state.lock().unwrap().parameter_error.take();
+        if let Some(error) = &error {
+            log::warn!("[SIMULATED] Failing tunnel parameter generation: {}", error);
+        }
+        error
+    }
+}
@@ -0,0 +1,167 @@
+//! Detection of the processor architecture, and self-tests of the structures that are shared with
+//! native code.
+//!
+//! The daemon is built for x86, x64 and ARM64. The structures passed to `winnet`, `winfw` and the
+//! split tunnel driver must have the same layout as their C counterparts on each of these. The
+//! layouts are checked by [`self_test`] when the daemon starts, so that a mismatch is reported
+//! instead of corrupting memory, whether or not the target is covered by CI.
+
+use std::{fmt, io, mem};
+use windows_sys::Win32::{
+    Foundation::BOOL,
+    System::{
+        SystemInformation::{
+            IMAGE_FILE_MACHINE, IMAGE_FILE_MACHINE_AMD64, IMAGE_FILE_MACHINE_ARM64,
+            IMAGE_FILE_MACHINE_I386, IMAGE_FILE_MACHINE_UNKNOWN,
+        },
+        Threading::{GetCurrentProcess, IsWow64Process2},
+    },
+};
+
+/// Processor architecture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Architecture {
+    X86,
+    X64,
+    Arm64,
+    /// An architecture identified by its `IMAGE_FILE_MACHINE_*` value.
+    Unknown(u16),
+}
+
+impl Architecture {
+    fn from_machine(machine: IMAGE_FILE_MACHINE) -> Self {
+        match machine {
+            IMAGE_FILE_MACHINE_I386 => Architecture::X86,
+            IMAGE_FILE_MACHINE_AMD64 => Architecture::X64,
+            IMAGE_FILE_MACHINE_ARM64 => Architecture::Arm64,
+            other => Architecture::Unknown(other),
+        }
+    }
+}
+
+impl fmt::Display for Architecture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Architecture::X86 => f.write_str("x86"),
+            Architecture::X64 => f.write_str("x64"),
+            Architecture::Arm64 => f.write_str("ARM64"),
+            Architecture::Unknown(machine) => write!(f, "unknown architecture ({:#06x})", machine),
+        }
+    }
+}
+
+/// Returns the architecture that the daemon was built for.
+pub fn process_architecture() -> Architecture {
+    if cfg!(target_arch = "x86") {
+        Architecture::X86
+    } else if cfg!(target_arch = "x86_64") {
+        Architecture::X64
+    } else if cfg!(target_arch = "aarch64") {
+        Architecture::Arm64
+    } else {
+        Architecture::Unknown(IMAGE_FILE_MACHINE_UNKNOWN)
+    }
+}
+
+/// Returns the architecture of the OS. This differs from [`process_architecture`] if the daemon
+/// runs under emulation, e.g. an x64 build on ARM64 Windows.
+pub fn native_architecture() -> io::Result<Architecture> {
+    let mut process_machine: IMAGE_FILE_MACHINE = 0;
+    let mut native_machine: IMAGE_FILE_MACHINE = 0;
+    // SAFETY: The pseudo handle of the current process is always valid, and both out pointers
+    // refer to initialized values.
+    let result: BOOL = unsafe {
+        IsWow64Process2(
+            GetCurrentProcess(),
+            &mut process_machine,
+            &mut native_machine,
+        )
+    };
+    if result == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Architecture::from_machine(native_machine))
+}
+
+/// A structure shared with native code does not have the expected layout.
+#[derive(err_derive::Error, Debug, Clone, PartialEq, Eq)]
+#[error(
+    display = "Unexpected layout of {} on {}: expected {} bytes, found {}",
+    name,
+    architecture,
+    expected,
+    actual
+)]
+pub struct LayoutMismatch {
+    /// Name of the structure.
+    pub name: &'static str,
+    /// Architecture that the daemon was built for.
+    pub architecture: Architecture,
+    /// Size of the C structure.
+    pub expected: usize,
+    /// Size of the Rust structure.
+    pub actual: usize,
+}
+
+/// Checks that `T` is `expected_64` bytes on 64-bit targets, and `expected_32` bytes on 32-bit
+/// targets.
+pub(crate) fn expect_size<T>(
+    name: &'static str,
+    expected_32: usize,
+    expected_64: usize,
+) -> Result<(), LayoutMismatch> {
+    let expected = if cfg!(target_pointer_width = "64") {
+        expected_64
+    } else {
+        expected_32
+    };
+    let actual = mem::size_of::<T>();
+    if actual != expected {
+        return Err(LayoutMismatch {
+            name,
+            architecture: process_architecture(),
+            expected,
+            actual,
+        });
+    }
+    Ok(())
+}
+
+/// Checks the layouts of all structures that are shared with native code, and logs the
+/// architecture that the daemon runs on.
+pub fn self_test() -> Result<(), LayoutMismatch> {
+    let process_arch = process_architecture();
+    match native_architecture() {
+        Ok(native_arch) if native_arch != process_arch => {
+            log::warn!(
+                "Running an {} build on {} Windows under emulation",
+                process_arch,
+                native_arch
+            );
+        }
+        Ok(_) => log::debug!("Running on {} Windows", process_arch),
+        Err(error) => log::warn!("Failed to determine the OS architecture: {}", error),
+    }
+
+    crate::winnet::check_layouts()?;
+    crate::split_tunnel::check_layouts()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_self_test() {
+        self_test().unwrap();
+    }
+
+    #[test]
+    fn test_native_architecture() {
+        let native_arch = native_architecture().unwrap();
+        if process_architecture() == Architecture::Arm64 {
+            assert_eq!(native_arch, Architecture::Arm64);
+        }
+    }
+}
@@ -16,14 +16,16 @@ use winapi::shared::ws2def::SOCKADDR_STORAGE as sockaddr_storage;
 use windows_sys::{
     core::{GUID, PWSTR},
     Win32::{
-        Foundation::{ERROR_NOT_FOUND, HANDLE, NO_ERROR, S_OK},
+        Foundation::{ERROR_NETWORK_UNREACHABLE, ERROR_NOT_FOUND, HANDLE, NO_ERROR, S_OK},
         NetworkManagement::{
             IpHelper::{
                 CancelMibChangeNotify2, ConvertInterfaceAliasToLuid, ConvertInterfaceLuidToAlias,
-                ConvertInterfaceLuidToGuid, CreateUnicastIpAddressEntry, FreeMibTable,
+                ConvertInterfaceLuidToGuid, ConvertInterfaceLuidToIndex,
+                CreateUnicastIpAddressEntry, FreeMibTable, GetBestRoute2, GetIfEntry2, GetIfTable2,
                 GetIpInterfaceEntry, GetUnicastIpAddressEntry, GetUnicastIpAddressTable,
                 InitializeUnicastIpAddressEntry, MibAddInstance, NotifyIpInterfaceChange,
-                SetIpInterfaceEntry, MIB_IPINTERFACE_ROW, MIB_UNICASTIPADDRESS_ROW,
+                NotifyRouteChange2, SetIpInterfaceEntry, MIB_IF_ROW2, MIB_IF_TABLE2,
+                MIB_IPFORWARD_ROW2, MIB_IPINTERFACE_ROW, MIB_UNICASTIPADDRESS_ROW,
                 MIB_UNICASTIPADDRESS_TABLE,
             },
             Ndis::{IF_MAX_STRING_SIZE, NET_LUID_LH},
@@ -38,6 +40,7 @@ use windows_sys::{
     },
 };
 
+pub mod arch;
 pub mod window;
 
 /// Result type for this module.
@@ -174,6 +177,62 @@ pub fn notify_ip_interface_change<'a, T: FnMut(&MIB_IPINTERFACE_ROW, i32) + Send
     }
 }
 
+/// Context for [`notify_route_change`]. When it is dropped, the callback is unregistered.
+pub struct RouteNotifierHandle<'a> {
+    callback: Mutex<Box<dyn FnMut(&MIB_IPFORWARD_ROW2, i32) + Send + 'a>>,
+    handle: HANDLE,
+}
+
+unsafe impl Send for RouteNotifierHandle<'_> {}
+
+impl<'a> Drop for RouteNotifierHandle<'a> {
+    fn drop(&mut self) {
+        unsafe { CancelMibChangeNotify2(self.handle) };
+    }
+}
+
+unsafe extern "system" fn inner_route_callback(
+    context: *const c_void,
+    row: *const MIB_IPFORWARD_ROW2,
+    notify_type: i32,
+) {
+    if row.is_null() {
+        return;
+    }
+    let context = &mut *(context as *mut RouteNotifierHandle<'_>);
+    context
+        .callback
+        .lock()
+        .expect("NotifyRouteChange2 mutex poisoned")(&*row, notify_type);
+}
+
+/// Registers a callback function that is invoked when a route is added, removed, or changed.
+pub fn notify_route_change<'a, T: FnMut(&MIB_IPFORWARD_ROW2, i32) + Send + 'a>(
+    callback: T,
+    family: Option<AddressFamily>,
+) -> io::Result<Box<RouteNotifierHandle<'a>>> {
+    let mut context = Box::new(RouteNotifierHandle {
+        callback: Mutex::new(Box::new(callback)),
+        handle: 0,
+    });
+
+    let status = unsafe {
+        NotifyRouteChange2(
+            af_family_from_family(family),
+            Some(inner_route_callback),
+            &mut *context as *mut _ as *mut _,
+            0,
+            (&mut context.handle) as *mut _,
+        )
+    };
+
+    if status == NO_ERROR as i32 {
+        Ok(context)
+    } else {
+        Err(io::Error::from_raw_os_error(status as i32))
+    }
+}
+
 /// Returns information about a network IP interface.
 pub fn get_ip_interface_entry(
     family: AddressFamily,
@@ -393,6 +452,24 @@ pub fn get_unicast_table(
     Ok(unicast_rows)
 }
 
+/// Returns the table of all interfaces, including virtual and hidden ones.
+pub fn get_if_table() -> io::Result<Vec<MIB_IF_ROW2>> {
+    let mut if_rows = vec![];
+    let mut if_table: *mut MIB_IF_TABLE2 = std::ptr::null_mut();
+
+    let status = unsafe { GetIfTable2(&mut if_table) };
+    if status != NO_ERROR as i32 {
+        return Err(io::Error::from_raw_os_error(status as i32));
+    }
+    let first_row = unsafe { &(*if_table).Table[0] } as *const MIB_IF_ROW2;
+    for i in 0..unsafe { (*if_table).NumEntries } {
+        if_rows.push(unsafe { *(first_row.offset(i as isize)) });
+    }
+    unsafe { FreeMibTable(if_table as *mut _) };
+
+    Ok(if_rows)
+}
+
 /// Obtain a string representation for a GUID object.
 pub fn string_from_guid(guid: &GUID) -> String {
     let mut buffer = [0u16; 40];
@@ -429,6 +506,38 @@ pub fn luid_from_alias<T: AsRef<OsStr>>(alias: T) -> io::Result<NET_LUID_LH> {
     Ok(luid)
 }
 
+/// Returns the next hop of the best route to `destination` that leaves through the interface
+/// identified by `luid`, or `None` if there is no such route. The next hop is an unspecified
+/// address if the destination is on-link.
+pub fn get_best_route_next_hop(
+    luid: &NET_LUID_LH,
+    destination: IpAddr,
+) -> io::Result<Option<IpAddr>> {
+    let destination = inet_sockaddr_from_socketaddr(SocketAddr::new(destination, 0));
+    let mut best_route: MIB_IPFORWARD_ROW2 = unsafe { mem::zeroed() };
+    let mut best_source: SOCKADDR_INET = unsafe { mem::zeroed() };
+    let status = unsafe {
+        GetBestRoute2(
+            luid,
+            0,
+            ptr::null(),
+            &destination,
+            0,
+            &mut best_route,
+            &mut best_source,
+        )
+    };
+    if status == ERROR_NOT_FOUND as i32 || status == ERROR_NETWORK_UNREACHABLE as i32 {
+        return Ok(None);
+    }
+    if status != NO_ERROR as i32 {
+        return Err(io::Error::from_raw_os_error(status as i32));
+    }
+    try_socketaddr_from_inet_sockaddr(best_route.NextHop)
+        .map(|next_hop| Some(next_hop.ip()))
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid next hop address"))
+}
+
 /// Returns the alias of an interface given its LUID.
 pub fn alias_from_luid(luid: &NET_LUID_LH) -> io::Result<OsString> {
     let mut buffer = [0u16; IF_MAX_STRING_SIZE as usize + 1];
@@ -441,6 +550,32 @@ pub fn alias_from_luid(luid: &NET_LUID_LH) -> io::Result<OsString> {
     Ok(OsString::from_wide(&buffer[0..nul]))
 }
 
+/// Returns the index of an interface given its LUID.
+pub fn index_from_luid(luid: &NET_LUID_LH) -> io::Result<u32> {
+    let mut index = 0u32;
+    let status = unsafe { ConvertInterfaceLuidToIndex(luid, &mut index) };
+    if status != NO_ERROR as i32 {
+        return Err(io::Error::from_raw_os_error(status as i32));
+    }
+    Ok(index)
+}
+
+/// Returns the description of an interface given its LUID, such as the name of the adapter.
+pub fn description_from_luid(luid: &NET_LUID_LH) -> io::Result<String> {
+    let mut row: MIB_IF_ROW2 = unsafe { mem::zeroed() };
+    row.InterfaceLuid = *luid;
+    let status = unsafe { GetIfEntry2(&mut row) };
+    if status != NO_ERROR as i32 {
+        return Err(io::Error::from_raw_os_error(status as i32));
+    }
+    let nul = row
+        .Description
+        .iter()
+        .position(|&c| c == 0u16)
+        .unwrap_or(row.Description.len());
+    Ok(String::from_utf16_lossy(&row.Description[0..nul]))
+}
+
 fn af_family_from_family(family: Option<AddressFamily>) -> u16 {
     family
         .map(|family| family as u16)
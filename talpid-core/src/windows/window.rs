@@ -1,17 +1,24 @@
 //! Utilities for windows.
 
-use std::{os::windows::io::AsRawHandle, ptr, sync::Arc, thread};
+use std::{mem, os::windows::io::AsRawHandle, ptr, sync::Arc, thread};
+use talpid_types::ErrorExt;
 use tokio::sync::broadcast;
 use windows_sys::{
     w,
     Win32::{
         Foundation::{HANDLE, HWND, LPARAM, LRESULT, WPARAM},
-        System::{LibraryLoader::GetModuleHandleW, Threading::GetThreadId},
+        System::{
+            Ioctl::GUID_DEVINTERFACE_VOLUME, LibraryLoader::GetModuleHandleW,
+            Threading::GetThreadId,
+        },
         UI::WindowsAndMessaging::{
             CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, GetMessageW,
-            GetWindowLongPtrW, PostQuitMessage, PostThreadMessageW, SetWindowLongPtrW,
-            TranslateMessage, GWLP_USERDATA, GWLP_WNDPROC, PBT_APMRESUMEAUTOMATIC,
-            PBT_APMRESUMESUSPEND, PBT_APMSUSPEND, WM_DESTROY, WM_POWERBROADCAST, WM_USER,
+            GetWindowLongPtrW, PostQuitMessage, PostThreadMessageW, RegisterDeviceNotificationW,
+            SetWindowLongPtrW, TranslateMessage, UnregisterDeviceNotification, DBT_DEVICEARRIVAL,
+            DBT_DEVICEREMOVECOMPLETE, DBT_DEVTYP_DEVICEINTERFACE, DEVICE_NOTIFY_WINDOW_HANDLE,
+            DEV_BROADCAST_DEVICEINTERFACE_W, GWLP_USERDATA, GWLP_WNDPROC, HDEVNOTIFY,
+            PBT_APMRESUMEAUTOMATIC, PBT_APMRESUMESUSPEND, PBT_APMSUSPEND, WM_DESTROY,
+            WM_DEVICECHANGE, WM_POWERBROADCAST, WM_USER,
         },
     },
 };
@@ -40,6 +47,17 @@ impl WindowCloseHandle {
 pub fn create_hidden_window<F: (Fn(HWND, u32, WPARAM, LPARAM) -> LRESULT) + Send + 'static>(
     wnd_proc: F,
 ) -> WindowCloseHandle {
+    create_hidden_window_with_init(wnd_proc, |_window| ())
+}
+
+/// Creates a dummy window whose messages are handled by `wnd_proc`. `init` is called with the
+/// window on the window thread before any messages are handled, and the value that it returns
+/// is dropped when the window has been destroyed.
+pub fn create_hidden_window_with_init<F, I, T>(wnd_proc: F, init: I) -> WindowCloseHandle
+where
+    F: (Fn(HWND, u32, WPARAM, LPARAM) -> LRESULT) + Send + 'static,
+    I: FnOnce(HWND) -> T + Send + 'static,
+{
     let join_handle = thread::spawn(move || {
         let dummy_window = unsafe {
             CreateWindowExW(
@@ -67,6 +85,8 @@ pub fn create_hidden_window<F: (Fn(HWND, u32, WPARAM, LPARAM) -> LRESULT) + Send
             SetWindowLongPtrW(dummy_window, GWLP_WNDPROC, window_procedure::<F> as isize);
         }
 
+        let init_result = init(dummy_window);
+
         let mut msg = unsafe { std::mem::zeroed() };
 
         loop {
@@ -91,6 +111,8 @@ pub fn create_hidden_window<F: (Fn(HWND, u32, WPARAM, LPARAM) -> LRESULT) + Send
             }
         }
 
+        drop(init_result);
+
         // Free callback.
         let _ = unsafe { Box::from_raw(raw_callback) };
     });
@@ -212,3 +234,61 @@ impl Drop for WindowScopedHandle {
         self.0.close();
     }
 }
+
+/// Calls a function whenever a volume is mounted or dismounted. Unlike broadcasts of volume
+/// changes, these notifications are also received by services.
+pub struct VolumeChangeListener {
+    _window: WindowScopedHandle,
+}
+
+impl VolumeChangeListener {
+    /// Starts listening for volume changes, calling `on_change` for every change.
+    pub fn new<F: Fn() + Send + 'static>(on_change: F) -> Self {
+        let device_change_callback = move |window, message, wparam, lparam| {
+            if message == WM_DEVICECHANGE
+                && (wparam as u32 == DBT_DEVICEARRIVAL || wparam as u32 == DBT_DEVICEREMOVECOMPLETE)
+            {
+                on_change();
+            }
+            unsafe { DefWindowProcW(window, message, wparam, lparam) }
+        };
+
+        let window = create_hidden_window_with_init(device_change_callback, |window| {
+            let mut filter: DEV_BROADCAST_DEVICEINTERFACE_W = unsafe { mem::zeroed() };
+            filter.dbcc_size = mem::size_of::<DEV_BROADCAST_DEVICEINTERFACE_W>() as u32;
+            filter.dbcc_devicetype = DBT_DEVTYP_DEVICEINTERFACE;
+            filter.dbcc_classguid = GUID_DEVINTERFACE_VOLUME;
+
+            // SAFETY: `filter` is a valid `DEV_BROADCAST_DEVICEINTERFACE_W` that outlives the call
+            let handle = unsafe {
+                RegisterDeviceNotificationW(
+                    window,
+                    &filter as *const _ as *const _,
+                    DEVICE_NOTIFY_WINDOW_HANDLE,
+                )
+            };
+            if handle.is_null() {
+                log::error!(
+                    "{}",
+                    std::io::Error::last_os_error()
+                        .display_chain_with_msg("Failed to register for volume notifications")
+                );
+            }
+            DeviceNotificationHandle(handle)
+        });
+
+        Self {
+            _window: WindowScopedHandle(window),
+        }
+    }
+}
+
+struct DeviceNotificationHandle(HDEVNOTIFY);
+
+impl Drop for DeviceNotificationHandle {
+    fn drop(&mut self) {
+        if !self.0.is_null() {
+            unsafe { UnregisterDeviceNotification(self.0) };
+        }
+    }
+}
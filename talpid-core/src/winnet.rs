@@ -1,5 +1,9 @@
 use self::api::*;
-use crate::{logging::windows::log_sink, routing::Node};
+use crate::{
+    logging::windows::log_sink,
+    routing::Node,
+    windows::arch::{expect_size, LayoutMismatch},
+};
 use ipnetwork::IpNetwork;
 use libc::c_void;
 use std::{
@@ -7,7 +11,8 @@ use std::{
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
     ptr,
 };
-use widestring::WideCString;
+use widestring::{WideCStr, WideCString};
+use windows_sys::Win32::NetworkManagement::Ndis::NET_LUID_LH;
 
 /// Errors that this module may produce.
 #[derive(err_derive::Error, Debug)]
@@ -68,19 +73,73 @@ impl WinNetAddrFamily {
 }
 
 #[repr(C)]
-#[derive(Default)]
+#[derive(Default, Clone, Copy)]
 pub struct WinNetIp {
     pub addr_family: WinNetAddrFamily,
     pub ip_bytes: [u8; 16],
 }
 
 #[repr(C)]
-#[derive(Default)]
+#[derive(Default, Clone, Copy)]
 pub struct WinNetDefaultRoute {
     pub interface_luid: u64,
     pub gateway: WinNetIp,
 }
 
+/// Details of the interface associated with a best default route.
+#[repr(C)]
+pub struct WinNetInterfaceDetails {
+    /// Interface index. Zero if the details could not be obtained.
+    pub index: u32,
+    /// IP layer MTU for the address family of the event.
+    pub mtu: u32,
+    /// Transmit link speed in bits per second.
+    pub transmit_link_speed: u64,
+    /// Receive link speed in bits per second.
+    pub receive_link_speed: u64,
+    alias: [u16; 257],
+}
+
+impl WinNetInterfaceDetails {
+    /// Returns the alias of the interface.
+    pub fn alias(&self) -> String {
+        WideCStr::from_slice_truncate(&self.alias)
+            .map(|alias| alias.to_string_lossy())
+            .unwrap_or_default()
+    }
+}
+
+/// Payload of a default route change event.
+#[repr(C)]
+pub struct WinNetDefaultRouteChange {
+    has_previous_route: bool,
+    previous_route: WinNetDefaultRoute,
+    route: WinNetDefaultRoute,
+    interface_details: WinNetInterfaceDetails,
+}
+
+impl WinNetDefaultRouteChange {
+    /// Returns the best default route before the event, if there was one.
+    pub fn previous_route(&self) -> Option<&WinNetDefaultRoute> {
+        if self.has_previous_route {
+            Some(&self.previous_route)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the new best default route. Not valid for removal events.
+    pub fn route(&self) -> &WinNetDefaultRoute {
+        &self.route
+    }
+
+    /// Returns details of the interface associated with the new best default route. Not valid
+    /// for removal events.
+    pub fn interface_details(&self) -> &WinNetInterfaceDetails {
+        &self.interface_details
+    }
+}
+
 #[derive(Debug)]
 pub struct WrongIpFamilyError;
 
@@ -281,7 +340,7 @@ pub enum WinNetDefaultRouteChangeEventType {
 pub type DefaultRouteChangedCallback = unsafe extern "system" fn(
     event_type: WinNetDefaultRouteChangeEventType,
     family: WinNetAddrFamily,
-    default_route: WinNetDefaultRoute,
+    change: &WinNetDefaultRouteChange,
     ctx: *mut c_void,
 );
 
@@ -325,17 +384,34 @@ pub fn routing_manager_delete_applied_routes() -> bool {
     unsafe { WinNet_DeleteAppliedRoutes() }
 }
 
+pub fn routing_manager_delete_unowned_routes(routes: &[WinNetRoute]) -> bool {
+    let ptr = routes.as_ptr();
+    let length: u32 = routes.len() as u32;
+    unsafe { WinNet_DeleteUnownedRoutes(ptr, length) }
+}
+
 pub fn deactivate_routing_manager() {
     unsafe { WinNet_DeactivateRouteManager() }
 }
 
+/// Returns the best default route that does not use any of the interfaces in `excluded_luids`,
+/// which should contain the interfaces owned by the caller, such as the tunnel interface. Other
+/// tunnel interfaces are excluded based on their type and description. If several routes have the
+/// lowest metric, the one on the interface with the lowest LUID is returned.
 pub fn get_best_default_route(
     family: WinNetAddrFamily,
+    excluded_luids: &[NET_LUID_LH],
 ) -> Result<Option<WinNetDefaultRoute>, Error> {
+    let excluded_luids: Vec<u64> = excluded_luids
+        .iter()
+        .map(|luid| unsafe { luid.Value })
+        .collect();
     let mut default_route = WinNetDefaultRoute::default();
     match unsafe {
         WinNet_GetBestDefaultRoute(
             family,
+            excluded_luids.as_ptr(),
+            u32::try_from(excluded_luids.len()).expect("too many excluded interfaces"),
             &mut default_route as *mut _,
             Some(log_sink),
             logging_context(),
@@ -347,6 +423,17 @@ pub fn get_best_default_route(
     }
 }
 
+/// Checks that the structures passed to `winnet` match the layouts in `winnet.h`.
+pub(crate) fn check_layouts() -> Result<(), LayoutMismatch> {
+    expect_size::<WinNetIp>("WINNET_IP", 20, 20)?;
+    expect_size::<WinNetIpNetwork>("WINNET_IP_NETWORK", 24, 24)?;
+    expect_size::<WinNetNode>("WINNET_NODE", 8, 16)?;
+    expect_size::<WinNetRoute>("WINNET_ROUTE", 28, 32)?;
+    expect_size::<WinNetDefaultRoute>("WINNET_DEFAULT_ROUTE", 32, 32)?;
+    expect_size::<WinNetInterfaceDetails>("WINNET_INTERFACE_DETAILS", 544, 544)?;
+    expect_size::<WinNetDefaultRouteChange>("WINNET_DEFAULT_ROUTE_CHANGE", 616, 616)
+}
+
 #[allow(non_snake_case)]
 mod api {
     use super::DefaultRouteChangedCallback;
@@ -392,12 +479,20 @@ mod api {
         #[link_name = "WinNet_DeleteAppliedRoutes"]
         pub fn WinNet_DeleteAppliedRoutes() -> bool;
 
+        #[link_name = "WinNet_DeleteUnownedRoutes"]
+        pub fn WinNet_DeleteUnownedRoutes(
+            routes: *const super::WinNetRoute,
+            num_routes: u32,
+        ) -> bool;
+
         #[link_name = "WinNet_DeactivateRouteManager"]
         pub fn WinNet_DeactivateRouteManager();
 
         #[link_name = "WinNet_GetBestDefaultRoute"]
         pub fn WinNet_GetBestDefaultRoute(
             family: super::WinNetAddrFamily,
+            excluded_luids: *const u64,
+            num_excluded_luids: u32,
             default_route: *mut super::WinNetDefaultRoute,
             sink: Option<LogSink>,
             sink_context: *const u8,
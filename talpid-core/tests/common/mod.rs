@@ -0,0 +1,70 @@
+//! Helpers shared by the privileged integration tests.
+
+#[cfg(target_os = "linux")]
+pub mod linux {
+    use nix::{
+        mount::{mount, MsFlags},
+        sched::{unshare, CloneFlags},
+    };
+    use std::{fs, path::Path, sync::Once};
+
+    /// Environment variables are shared by all tests in the binary, which run in parallel. They
+    /// are therefore only set once, before the first test environment is created.
+    static SET_PROCESS_ENV: Once = Once::new();
+
+    /// Scratch environment for a single test. Only the calling thread, and threads spawned by it
+    /// after the environment has been created, are affected by it.
+    ///
+    /// The thread gets its own mount and network namespaces, with a scratch directory containing
+    /// `resolv.conf` mounted over `/etc`. The system bus is hidden, so that services of the host,
+    /// such as NetworkManager, cannot be reconfigured. DNS is managed by the static
+    /// `/etc/resolv.conf` backend, since it is the only one that does not depend on such services.
+    pub struct TestEnvironment {
+        _etc: tempfile::TempDir,
+        _dbus: tempfile::TempDir,
+    }
+
+    impl TestEnvironment {
+        pub fn new(resolv_conf: &str) -> Self {
+            SET_PROCESS_ENV.call_once(|| std::env::set_var("TALPID_DNS_MODULE", "static-file"));
+
+            unshare(CloneFlags::CLONE_NEWNS | CloneFlags::CLONE_NEWNET)
+                .expect("Failed to create namespaces");
+            // Keep the mounts below from propagating to the namespace of the host
+            mount(
+                None::<&str>,
+                "/",
+                None::<&str>,
+                MsFlags::MS_REC | MsFlags::MS_PRIVATE,
+                None::<&str>,
+            )
+            .expect("Failed to make mounts private");
+
+            let etc = tempfile::tempdir().expect("Failed to create temporary directory");
+            fs::write(etc.path().join("resolv.conf"), resolv_conf)
+                .expect("Failed to write resolv.conf");
+            bind_mount(etc.path(), "/etc");
+
+            let dbus = tempfile::tempdir().expect("Failed to create temporary directory");
+            if Path::new("/run/dbus").is_dir() {
+                bind_mount(dbus.path(), "/run/dbus");
+            }
+
+            Self {
+                _etc: etc,
+                _dbus: dbus,
+            }
+        }
+    }
+
+    fn bind_mount(source: &Path, target: &str) {
+        mount(
+            Some(source),
+            target,
+            None::<&str>,
+            MsFlags::MS_BIND,
+            None::<&str>,
+        )
+        .unwrap_or_else(|error| panic!("Failed to mount scratch directory over {target}: {error}"));
+    }
+}
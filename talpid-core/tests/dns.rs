@@ -0,0 +1,225 @@
+//! Integration tests for the DNS monitor. These change the DNS configuration of the host and
+//! must be run as root or administrator.
+//!
+//! On Linux, each test runs in its own mount and network namespace, with a scratch directory
+//! mounted over `/etc`. The real resolver configuration is never touched. See
+//! `common::linux::TestEnvironment`.
+//!
+//! On Windows, the tests configure DNS for an adapter that is not used for anything else, such as
+//! the Microsoft KM-TEST Loopback Adapter. See `windows_routing.rs` for how to set it up.
+//!
+//! macOS is not covered, since the DNS monitor there depends on the dynamic store of the host.
+//!
+//! ```text
+//! cargo test -p talpid-core --features privileged-tests --test dns
+//! ```
+
+#![cfg(all(any(target_os = "linux", windows), feature = "privileged-tests"))]
+
+mod common;
+
+use std::net::{IpAddr, Ipv4Addr};
+
+const TEST_SERVERS: [IpAddr; 2] = [
+    IpAddr::V4(Ipv4Addr::new(10, 64, 0, 1)),
+    IpAddr::V4(Ipv4Addr::new(10, 64, 0, 2)),
+];
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::{common::linux::TestEnvironment, TEST_SERVERS};
+    use std::{collections::HashSet, fs, mem, net::IpAddr, path::Path};
+    use talpid_core::{dns::DnsMonitor, routing::RouteManager};
+
+    const RESOLV_CONF_PATH: &str = "/etc/resolv.conf";
+    const RESOLV_CONF_BACKUP_PATH: &str = "/etc/resolv.conf.mullvadbackup";
+
+    const ORIGINAL_RESOLV_CONF: &str = "nameserver 192.0.2.53\nsearch example.com\n";
+
+    /// DNS monitor together with the runtime and route manager that it depends on. The fields are
+    /// dropped in order, so the runtime outlives the other two.
+    struct TestMonitor {
+        monitor: DnsMonitor,
+        route_manager: RouteManager,
+        runtime: tokio::runtime::Runtime,
+    }
+
+    impl TestMonitor {
+        fn new() -> Self {
+            let runtime = tokio::runtime::Runtime::new().expect("Failed to initialize runtime");
+            let route_manager = runtime
+                .block_on(RouteManager::new(HashSet::new()))
+                .expect("Failed to start route manager");
+            let monitor = DnsMonitor::new(
+                runtime.handle().clone(),
+                route_manager
+                    .handle()
+                    .expect("Failed to obtain route manager handle"),
+            )
+            .expect("Failed to create DNS monitor");
+            Self {
+                monitor,
+                route_manager,
+                runtime,
+            }
+        }
+
+        /// Stops the monitor without letting it clean up after itself, as if the daemon crashed.
+        fn crash(self) {
+            let Self {
+                runtime,
+                route_manager,
+                monitor,
+            } = self;
+            mem::forget(monitor);
+            mem::forget(route_manager);
+            // Stops the watcher task, so that it no longer enforces the leaked configuration
+            runtime.shutdown_background();
+        }
+    }
+
+    fn nameservers() -> Vec<IpAddr> {
+        fs::read_to_string(RESOLV_CONF_PATH)
+            .expect("Failed to read resolv.conf")
+            .lines()
+            .filter_map(|line| line.strip_prefix("nameserver "))
+            .map(|server| server.trim().parse().expect("Invalid nameserver"))
+            .collect()
+    }
+
+    fn assert_original_restored() {
+        assert_eq!(
+            nameservers(),
+            vec!["192.0.2.53".parse::<IpAddr>().unwrap()],
+            "Original nameservers were not restored"
+        );
+        assert!(
+            fs::read_to_string(RESOLV_CONF_PATH)
+                .unwrap()
+                .contains("search example.com"),
+            "Original search domains were not restored"
+        );
+        assert!(
+            !Path::new(RESOLV_CONF_BACKUP_PATH).exists(),
+            "Backup was not removed"
+        );
+    }
+
+    #[test]
+    fn test_set_and_reset() {
+        let _env = TestEnvironment::new(ORIGINAL_RESOLV_CONF);
+        let mut test_monitor = TestMonitor::new();
+
+        test_monitor
+            .monitor
+            .set("lo", &TEST_SERVERS)
+            .expect("Failed to set DNS");
+        assert_eq!(nameservers(), TEST_SERVERS);
+        assert!(
+            Path::new(RESOLV_CONF_BACKUP_PATH).exists(),
+            "No backup was written"
+        );
+
+        test_monitor.monitor.reset().expect("Failed to reset DNS");
+        assert_original_restored();
+    }
+
+    /// The backup left behind by a monitor that was not reset is restored by the next monitor.
+    #[test]
+    fn test_restore_after_crash() {
+        let _env = TestEnvironment::new(ORIGINAL_RESOLV_CONF);
+
+        let mut crashing_monitor = TestMonitor::new();
+        crashing_monitor
+            .monitor
+            .set("lo", &TEST_SERVERS)
+            .expect("Failed to set DNS");
+        crashing_monitor.crash();
+
+        assert_eq!(nameservers(), TEST_SERVERS);
+        assert!(Path::new(RESOLV_CONF_BACKUP_PATH).exists());
+
+        let mut test_monitor = TestMonitor::new();
+        let other_servers: [IpAddr; 1] = ["10.64.0.3".parse().unwrap()];
+        test_monitor
+            .monitor
+            .set("lo", &other_servers)
+            .expect("Failed to set DNS");
+        assert_eq!(nameservers(), other_servers);
+
+        test_monitor.monitor.reset().expect("Failed to reset DNS");
+        assert_original_restored();
+    }
+
+    /// A backup file from an earlier run is restored even if it was not written by this process.
+    #[test]
+    fn test_restore_stale_backup() {
+        let _env = TestEnvironment::new(ORIGINAL_RESOLV_CONF);
+
+        fs::write(RESOLV_CONF_BACKUP_PATH, ORIGINAL_RESOLV_CONF).unwrap();
+        fs::write(RESOLV_CONF_PATH, "nameserver 10.64.0.1\n").unwrap();
+
+        let mut test_monitor = TestMonitor::new();
+        test_monitor
+            .monitor
+            .set("lo", &TEST_SERVERS)
+            .expect("Failed to set DNS");
+        test_monitor.monitor.reset().expect("Failed to reset DNS");
+
+        assert_original_restored();
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use super::TEST_SERVERS;
+    use std::io;
+    use talpid_core::{
+        dns::DnsMonitor,
+        windows::{guid_from_luid, luid_from_alias, string_from_guid},
+    };
+    use winreg::{enums::HKEY_LOCAL_MACHINE, RegKey};
+
+    const DEFAULT_TEST_ADAPTER: &str = "talpid-test-loopback";
+
+    fn test_adapter() -> String {
+        std::env::var("TALPID_TEST_ADAPTER").unwrap_or_else(|_| DEFAULT_TEST_ADAPTER.to_owned())
+    }
+
+    /// Reads the IPv4 nameservers of the adapter directly from the registry.
+    fn nameservers(alias: &str) -> Option<String> {
+        let luid = luid_from_alias(alias)
+            .unwrap_or_else(|error| panic!("Test adapter \"{alias}\" not found: {error}"));
+        let guid = string_from_guid(&guid_from_luid(&luid).expect("Failed to obtain GUID"));
+        let key = RegKey::predef(HKEY_LOCAL_MACHINE)
+            .open_subkey(format!(
+                r#"SYSTEM\CurrentControlSet\Services\Tcpip\Parameters\Interfaces\{guid}"#
+            ))
+            .expect("Failed to open interface key");
+        match key.get_value::<String, _>("NameServer") {
+            Ok(servers) if servers.is_empty() => None,
+            Ok(servers) => Some(servers),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+            Err(error) => panic!("Failed to read nameservers: {error}"),
+        }
+    }
+
+    #[test]
+    fn test_set_and_reset() {
+        let alias = test_adapter();
+        assert_eq!(
+            nameservers(&alias),
+            None,
+            "Test adapter already has nameservers"
+        );
+
+        let mut monitor = DnsMonitor::new().expect("Failed to create DNS monitor");
+        monitor
+            .set(&alias, &TEST_SERVERS)
+            .expect("Failed to set DNS");
+        assert_eq!(nameservers(&alias).as_deref(), Some("10.64.0.1,10.64.0.2"));
+
+        monitor.reset().expect("Failed to reset DNS");
+        assert_eq!(nameservers(&alias), None, "Nameservers were not removed");
+    }
+}
@@ -0,0 +1,440 @@
+//! Integration tests for how the tunnel state machine handles misbehaving tunnel backends. The
+//! state machine applies firewall rules and routes, so these must be run as root. Each test runs
+//! in its own mount and network namespace. See `common::linux::TestEnvironment`.
+//!
+//! ```text
+//! cargo test -p talpid-core --features privileged-tests --test tunnel_state_machine
+//! ```
+
+#![cfg(all(target_os = "linux", feature = "privileged-tests"))]
+
+mod common;
+
+use common::linux::TestEnvironment;
+use futures::{
+    channel::{
+        mpsc::{self, UnboundedReceiver},
+        oneshot,
+    },
+    StreamExt,
+};
+use std::{
+    future::Future,
+    net::{Ipv4Addr, SocketAddr},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Once,
+    },
+    time::Duration,
+};
+use talpid_core::{
+    firewall::plugin::PolicyFragment,
+    tunnel::wireguard::{config::Config, Tunnel, TunnelError, TunnelProvider},
+    tunnel_state_machine::{
+        self, InitialTunnelState, TunnelCommand, TunnelParametersGenerator,
+        TunnelStateMachineHandle,
+    },
+};
+use talpid_types::{
+    net::{
+        route_takeover::RouteTakeoverPolicy,
+        wireguard::{self, ConnectionConfig, PeerConfig, PrivateKey, TunnelConfig, TunnelOptions},
+        AllowedEndpoint, BlockedTunnelProtocols, GenericTunnelOptions, TunnelParameters,
+        VpnCoexistence,
+    },
+    system_state::FirewallState,
+    tunnel::{
+        DisconnectCause, ErrorStateCause, ErrorStatePolicy, ParameterGenerationError,
+        ReconnectLimits, TunnelStateTransition, VersionedTunnelStateTransition,
+    },
+};
+
+/// How long to wait for the state machine to reach the expected state.
+const STATE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Tunnel provider that panics while opening the first `panics` tunnels, as a buggy backend
+/// would. This takes down the tunnel monitor without it reporting why. Once it is done
+/// panicking, it fails to open tunnels normally.
+struct PanickingTunnelProvider {
+    panics: u32,
+    attempts: AtomicU32,
+}
+
+impl PanickingTunnelProvider {
+    fn new(panics: u32) -> Self {
+        Self {
+            panics,
+            attempts: AtomicU32::new(0),
+        }
+    }
+}
+
+impl TunnelProvider for PanickingTunnelProvider {
+    fn open_tunnel(&self, _config: &Config) -> Result<Box<dyn Tunnel>, TunnelError> {
+        let attempt = self.attempts.fetch_add(1, Ordering::SeqCst);
+        if attempt < self.panics {
+            panic!("Misbehaving tunnel provider panicked on attempt {attempt}");
+        }
+        Err(TunnelError::FatalStartWireguardError)
+    }
+}
+
+/// Tunnel provider that fails to open tunnels with an error that may be transient, so that
+/// another attempt is made.
+struct UnreliableTunnelProvider;
+
+impl TunnelProvider for UnreliableTunnelProvider {
+    fn open_tunnel(&self, _config: &Config) -> Result<Box<dyn Tunnel>, TunnelError> {
+        Err(TunnelError::RecoverableStartWireguardError)
+    }
+}
+
+/// Always connects to the same made up relay.
+struct StaticParametersGenerator;
+
+impl TunnelParametersGenerator for StaticParametersGenerator {
+    fn generate(
+        &mut self,
+        _retry_attempt: u32,
+    ) -> Pin<Box<dyn Future<Output = Result<TunnelParameters, ParameterGenerationError>>>> {
+        let parameters = TunnelParameters::Wireguard(wireguard::TunnelParameters {
+            connection: ConnectionConfig {
+                tunnel: TunnelConfig {
+                    private_key: PrivateKey::new_from_random(),
+                    addresses: vec![Ipv4Addr::new(10, 64, 0, 2).into()],
+                },
+                peer: PeerConfig {
+                    public_key: PrivateKey::new_from_random().public_key(),
+                    allowed_ips: vec!["0.0.0.0/0".parse().unwrap()],
+                    endpoint: SocketAddr::new(Ipv4Addr::new(192, 0, 2, 1).into(), 51820),
+                    psk: None,
+                    psk_required: false,
+                },
+                exit_peer: None,
+                ipv4_gateway: Ipv4Addr::new(10, 64, 0, 1),
+                ipv6_gateway: None,
+            },
+            options: TunnelOptions::default(),
+            generic_options: GenericTunnelOptions {
+                enable_ipv6: false,
+                enable_ipv4: true,
+            },
+            obfuscation: None,
+        });
+        Box::pin(async move { Ok(parameters) })
+    }
+}
+
+struct TestStateMachine {
+    handle: TunnelStateMachineHandle,
+    transitions: UnboundedReceiver<VersionedTunnelStateTransition>,
+    _cache_dir: tempfile::TempDir,
+}
+
+impl TestStateMachine {
+    async fn spawn(
+        provider: impl TunnelProvider + 'static,
+        reconnect_limits: ReconnectLimits,
+        forced_interface: Option<String>,
+    ) -> Self {
+        let cache_dir = tempfile::tempdir().expect("Failed to create cache directory");
+        let (state_tx, transitions) = mpsc::unbounded();
+        let (offline_tx, _offline_rx) = mpsc::unbounded();
+        let (physical_interface_tx, _physical_interface_rx) = mpsc::unbounded();
+        let (route_takeover_tx, _route_takeover_rx) = mpsc::unbounded();
+        let (performance_warning_tx, _performance_warning_rx) = mpsc::unbounded();
+
+        let handle = tunnel_state_machine::spawn(
+            InitialTunnelState {
+                allow_lan: false,
+                block_when_disconnected: false,
+                dns_servers: None,
+                allowed_endpoint: AllowedEndpoint {
+                    endpoints: vec![],
+                    hostname: None,
+                },
+                reset_firewall: true,
+                flush_dns_cache: false,
+                recovery_allowlist: vec![],
+                vpn_coexistence: VpnCoexistence::default(),
+                blocked_tunnel_protocols: BlockedTunnelProtocols::default(),
+                forced_interface,
+                reconnect_limits,
+                route_takeover_policy: RouteTakeoverPolicy::default(),
+                error_state_policy: ErrorStatePolicy::default(),
+                wireguard_tunnel_provider: Some(Arc::new(provider)),
+                firewall_plugins: PolicyFragment::default(),
+            },
+            StaticParametersGenerator,
+            None,
+            std::env::temp_dir(),
+            cache_dir.path().to_path_buf(),
+            state_tx,
+            offline_tx,
+            physical_interface_tx,
+            route_takeover_tx,
+            performance_warning_tx,
+        )
+        .await
+        .expect("Failed to start the tunnel state machine");
+
+        Self {
+            handle,
+            transitions,
+            _cache_dir: cache_dir,
+        }
+    }
+
+    fn send(&self, command: TunnelCommand) {
+        self.handle
+            .send(command)
+            .expect("Tunnel state machine is not running");
+    }
+
+    /// Returns all transitions up to and including the next error state.
+    async fn transitions_until_error(&mut self) -> Vec<TunnelStateTransition> {
+        let mut transitions = vec![];
+        let wait_for_error = async {
+            while let Some(versioned) = self.transitions.next().await {
+                let transition = versioned.transition;
+                let is_error = matches!(transition, TunnelStateTransition::Error(_));
+                transitions.push(transition);
+                if is_error {
+                    return;
+                }
+            }
+            panic!("Tunnel state machine stopped");
+        };
+        if tokio::time::timeout(STATE_TIMEOUT, wait_for_error)
+            .await
+            .is_err()
+        {
+            panic!("Timed out waiting for the error state. Transitions: {transitions:?}");
+        }
+        transitions
+    }
+
+    async fn shut_down(self) {
+        self.send(TunnelCommand::Disconnect(DisconnectCause::UserInitiated));
+        self.handle.try_join().await;
+    }
+}
+
+fn count_connecting(transitions: &[TunnelStateTransition]) -> usize {
+    transitions
+        .iter()
+        .filter(|transition| matches!(transition, TunnelStateTransition::Connecting(_)))
+        .count()
+}
+
+fn error_cause(transitions: &[TunnelStateTransition]) -> &ErrorStateCause {
+    match transitions.last() {
+        Some(TunnelStateTransition::Error(error_state)) => error_state.cause(),
+        other => panic!("Expected the error state, got {other:?}"),
+    }
+}
+
+fn run_test<F: Future<Output = ()>>(test: impl FnOnce() -> F) {
+    // The environment is shared by the tests, which run in parallel, so it is only set once
+    static DISABLE_OFFLINE_MONITOR: Once = Once::new();
+    DISABLE_OFFLINE_MONITOR.call_once(|| std::env::set_var("TALPID_DISABLE_OFFLINE_MONITOR", "1"));
+
+    let _env = TestEnvironment::new("nameserver 192.0.2.53\n");
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("Failed to initialize runtime");
+    runtime.block_on(test());
+}
+
+/// A backend that keeps taking down the tunnel monitor eventually puts the state machine in the
+/// error state with a cause that tells it apart from other failures. Connecting again starts
+/// over.
+#[test]
+fn test_repeated_monitor_failures_block() {
+    run_test(|| async {
+        let mut state_machine = TestStateMachine::spawn(
+            PanickingTunnelProvider::new(u32::MAX),
+            Default::default(),
+            None,
+        )
+        .await;
+
+        state_machine.send(TunnelCommand::Connect);
+        let transitions = state_machine.transitions_until_error().await;
+        assert_eq!(count_connecting(&transitions), 3, "{transitions:?}");
+        assert!(
+            matches!(
+                error_cause(&transitions),
+                ErrorStateCause::TunnelMonitorStopped
+            ),
+            "{transitions:?}"
+        );
+
+        state_machine.send(TunnelCommand::Connect);
+        let transitions = state_machine.transitions_until_error().await;
+        assert_eq!(count_connecting(&transitions), 3, "{transitions:?}");
+
+        state_machine.shut_down().await;
+    });
+}
+
+/// A single monitor failure is retried rather than treated as fatal.
+#[test]
+fn test_monitor_failure_is_retried() {
+    run_test(|| async {
+        let mut state_machine =
+            TestStateMachine::spawn(PanickingTunnelProvider::new(1), Default::default(), None)
+                .await;
+
+        state_machine.send(TunnelCommand::Connect);
+        let transitions = state_machine.transitions_until_error().await;
+        assert_eq!(count_connecting(&transitions), 2, "{transitions:?}");
+        assert!(
+            matches!(error_cause(&transitions), ErrorStateCause::StartTunnelError),
+            "{transitions:?}"
+        );
+
+        state_machine.shut_down().await;
+    });
+}
+
+/// Transient failures to start the tunnel are retried until the limit is reached, after which the
+/// state machine blocks instead of retrying forever. Connecting again starts over.
+#[test]
+fn test_reconnect_limit_blocks() {
+    run_test(|| async {
+        let reconnect_limits = ReconnectLimits {
+            start_failures: Some(2),
+            ..ReconnectLimits::default()
+        };
+        let mut state_machine =
+            TestStateMachine::spawn(UnreliableTunnelProvider, reconnect_limits, None).await;
+
+        state_machine.send(TunnelCommand::Connect);
+        let transitions = state_machine.transitions_until_error().await;
+        assert_eq!(count_connecting(&transitions), 3, "{transitions:?}");
+        assert!(
+            matches!(error_cause(&transitions), ErrorStateCause::TooManyAttempts),
+            "{transitions:?}"
+        );
+
+        state_machine.send(TunnelCommand::Connect);
+        let transitions = state_machine.transitions_until_error().await;
+        assert_eq!(count_connecting(&transitions), 3, "{transitions:?}");
+
+        state_machine.shut_down().await;
+    });
+}
+
+/// After giving up, the state machine tries again once the device joins another network.
+#[test]
+fn test_reconnect_limit_resumes_on_network_change() {
+    run_test(|| async {
+        let reconnect_limits = ReconnectLimits {
+            start_failures: Some(1),
+            ..ReconnectLimits::default()
+        };
+        let mut state_machine =
+            TestStateMachine::spawn(UnreliableTunnelProvider, reconnect_limits, None).await;
+
+        state_machine.send(TunnelCommand::Connect);
+        let transitions = state_machine.transitions_until_error().await;
+        assert!(
+            matches!(error_cause(&transitions), ErrorStateCause::TooManyAttempts),
+            "{transitions:?}"
+        );
+
+        state_machine.send(TunnelCommand::DefaultInterfaceChanged);
+        let transitions = state_machine.transitions_until_error().await;
+        assert_eq!(count_connecting(&transitions), 2, "{transitions:?}");
+
+        state_machine.shut_down().await;
+    });
+}
+
+/// The state machine blocks while the forced interface does not exist, and connects again once
+/// it appears.
+#[test]
+fn test_forced_interface_appears() {
+    const INTERFACE: &str = "mullvad-test0";
+
+    run_test(|| async {
+        let mut state_machine = TestStateMachine::spawn(
+            UnreliableTunnelProvider,
+            Default::default(),
+            Some(INTERFACE.to_owned()),
+        )
+        .await;
+
+        state_machine.send(TunnelCommand::Connect);
+        let transitions = state_machine.transitions_until_error().await;
+        assert!(
+            matches!(
+                error_cause(&transitions),
+                ErrorStateCause::ForcedInterfaceUnavailable
+            ),
+            "{transitions:?}"
+        );
+
+        let status = std::process::Command::new("ip")
+            .args(["link", "add", INTERFACE, "type", "dummy"])
+            .status()
+            .expect("Failed to run ip");
+        assert!(status.success(), "Failed to create {INTERFACE}");
+
+        // The interface has no route to the relay, so connecting fails again
+        let transitions = state_machine.transitions_until_error().await;
+        assert!(count_connecting(&transitions) >= 1, "{transitions:?}");
+
+        state_machine.shut_down().await;
+    });
+}
+
+/// When the account is out of time, only the API is reachable, even if the local network and
+/// recovery networks are otherwise allowed in the error state.
+#[test]
+fn test_expired_account_allows_only_api() {
+    run_test(|| async {
+        let mut state_machine =
+            TestStateMachine::spawn(UnreliableTunnelProvider, Default::default(), None).await;
+
+        state_machine.send(TunnelCommand::AllowLan(true));
+        state_machine.send(TunnelCommand::RecoveryAllowlist(vec!["192.0.2.0/24"
+            .parse()
+            .unwrap()]));
+        state_machine.send(TunnelCommand::Block(ErrorStateCause::AccountExpired));
+        let transitions = state_machine.transitions_until_error().await;
+        assert!(
+            matches!(error_cause(&transitions), ErrorStateCause::AccountExpired),
+            "{transitions:?}"
+        );
+        match transitions.last() {
+            Some(TunnelStateTransition::Error(error_state)) => {
+                assert!(error_state.recovery_allowlist().is_empty())
+            }
+            other => panic!("Expected the error state, got {other:?}"),
+        }
+
+        let (tx, rx) = oneshot::channel();
+        state_machine.send(TunnelCommand::GetSystemState(tx));
+        let system_state = rx.await.expect("Tunnel state machine is not running");
+        match system_state.firewall {
+            Some(FirewallState::Blocked {
+                allow_lan,
+                recovery_allowlist,
+                vpn_coexistence,
+                ..
+            }) => {
+                assert!(!allow_lan);
+                assert!(recovery_allowlist.is_empty());
+                assert!(vpn_coexistence.is_empty());
+            }
+            other => panic!("Expected a blocking firewall policy, got {other:?}"),
+        }
+
+        state_machine.shut_down().await;
+    });
+}
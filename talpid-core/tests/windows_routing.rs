@@ -0,0 +1,330 @@
+//! Integration tests for the Windows route manager. These modify the routing table of the host and
+//! must be run as administrator, preferably in a throwaway VM.
+//!
+//! The tests need a network adapter that is not used for anything else, such as the Microsoft
+//! KM-TEST Loopback Adapter. Install it using `hdwwiz.exe` and rename it to
+//! `talpid-test-loopback`, or set `TALPID_TEST_ADAPTER` to the alias of another adapter. The
+//! tests add an IPv4 address and a default route to the adapter while running. `winnet.dll` must
+//! be in the `PATH`.
+//!
+//! ```text
+//! cargo test -p talpid-core --features privileged-tests --test windows_routing
+//! ```
+
+#![cfg(all(windows, feature = "privileged-tests"))]
+
+use ipnetwork::IpNetwork;
+use lazy_static::lazy_static;
+use std::{
+    collections::HashSet,
+    io, mem,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+use talpid_core::{
+    routing::{NetNode, Node, RequiredRoute, RouteManager},
+    windows::{
+        get_ip_interface_entry, inet_sockaddr_from_socketaddr, luid_from_alias,
+        set_ip_interface_entry, try_socketaddr_from_inet_sockaddr, AddressFamily,
+    },
+};
+use windows_sys::Win32::{
+    Foundation::{ERROR_OBJECT_ALREADY_EXISTS, NO_ERROR},
+    NetworkManagement::{
+        IpHelper::{
+            CreateIpForwardEntry2, CreateUnicastIpAddressEntry, DeleteIpForwardEntry2,
+            FreeMibTable, GetIpForwardTable2, InitializeIpForwardEntry,
+            InitializeUnicastIpAddressEntry, MIB_IPFORWARD_ROW2, MIB_IPFORWARD_TABLE2,
+        },
+        Ndis::NET_LUID_LH,
+    },
+    Networking::WinSock::{IpDadStatePreferred, AF_INET},
+};
+
+const DEFAULT_TEST_ADAPTER: &str = "talpid-test-loopback";
+
+/// Address assigned to the test adapter.
+const ADAPTER_ADDRESS: Ipv4Addr = Ipv4Addr::new(10, 213, 0, 2);
+/// On-link gateway used for routes through the test adapter.
+const ADAPTER_GATEWAY: Ipv4Addr = Ipv4Addr::new(10, 213, 0, 1);
+
+/// How long to wait for the route manager to react to changes in the routing table.
+const ROUTE_CHANGE_TIMEOUT: Duration = Duration::from_secs(10);
+
+lazy_static! {
+    /// The route manager in WinNet is global, so the tests must not run concurrently.
+    static ref TEST_LOCK: Mutex<()> = Mutex::new(());
+}
+
+struct TestAdapter {
+    alias: String,
+    luid: NET_LUID_LH,
+}
+
+impl TestAdapter {
+    /// Finds the test adapter and makes sure that it has an address that routes can use.
+    fn get() -> Self {
+        let alias = std::env::var("TALPID_TEST_ADAPTER")
+            .unwrap_or_else(|_| DEFAULT_TEST_ADAPTER.to_owned());
+        let luid = luid_from_alias(&alias)
+            .unwrap_or_else(|error| panic!("Test adapter \"{alias}\" not found: {error}"));
+
+        add_address(luid, ADAPTER_ADDRESS, 24).expect("Failed to add address to test adapter");
+
+        Self { alias, luid }
+    }
+
+    fn node(&self) -> Node {
+        Node::new(IpAddr::V4(ADAPTER_GATEWAY), self.alias.clone())
+    }
+
+    /// Sets a fixed interface metric, so that routes through the adapter can be made preferred.
+    fn set_metric(&self, metric: u32) {
+        let mut row = get_ip_interface_entry(AddressFamily::Ipv4, &self.luid)
+            .expect("Failed to get interface entry");
+        row.UseAutomaticMetric = 0;
+        row.Metric = metric;
+        set_ip_interface_entry(&mut row).expect("Failed to set interface metric");
+    }
+}
+
+/// Route in the system routing table, as read using `GetIpForwardTable2`.
+#[derive(Debug, Clone)]
+struct SystemRoute {
+    destination: IpNetwork,
+    next_hop: IpAddr,
+    luid: u64,
+}
+
+/// Reads the IPv4 routing table independently of the route manager.
+fn ipv4_routes() -> io::Result<Vec<SystemRoute>> {
+    let mut table: *mut MIB_IPFORWARD_TABLE2 = std::ptr::null_mut();
+    let status = unsafe { GetIpForwardTable2(AF_INET as u16, &mut table) };
+    if status != NO_ERROR as i32 {
+        return Err(io::Error::from_raw_os_error(status));
+    }
+
+    let rows = unsafe {
+        std::slice::from_raw_parts((*table).Table.as_ptr(), (*table).NumEntries as usize)
+    };
+    let routes = rows
+        .iter()
+        .filter_map(|row| {
+            let prefix = try_socketaddr_from_inet_sockaddr(row.DestinationPrefix.Prefix).ok()?;
+            let next_hop = try_socketaddr_from_inet_sockaddr(row.NextHop).ok()?;
+            Some(SystemRoute {
+                destination: IpNetwork::new(prefix.ip(), row.DestinationPrefix.PrefixLength)
+                    .ok()?,
+                next_hop: next_hop.ip(),
+                luid: unsafe { row.InterfaceLuid.Value },
+            })
+        })
+        .collect();
+    unsafe { FreeMibTable(table as *mut _) };
+
+    Ok(routes)
+}
+
+fn find_route(destination: IpNetwork) -> Option<SystemRoute> {
+    ipv4_routes()
+        .expect("Failed to read routing table")
+        .into_iter()
+        .find(|route| route.destination == destination)
+}
+
+/// Polls the routing table until `condition` holds for the route to `destination`.
+fn wait_for_route(destination: IpNetwork, condition: impl Fn(Option<&SystemRoute>) -> bool) {
+    let start = Instant::now();
+    loop {
+        let route = find_route(destination);
+        if condition(route.as_ref()) {
+            return;
+        }
+        if start.elapsed() > ROUTE_CHANGE_TIMEOUT {
+            panic!("Timed out waiting for route to {destination}. Current route: {route:?}");
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+fn add_address(luid: NET_LUID_LH, address: Ipv4Addr, prefix_length: u8) -> io::Result<()> {
+    let mut row = unsafe { mem::zeroed() };
+    unsafe { InitializeUnicastIpAddressEntry(&mut row) };
+    row.InterfaceLuid = luid;
+    row.Address = inet_sockaddr_from_socketaddr(SocketAddr::new(address.into(), 0));
+    row.OnLinkPrefixLength = prefix_length;
+    row.DadState = IpDadStatePreferred;
+
+    let status = unsafe { CreateUnicastIpAddressEntry(&row) };
+    if status != NO_ERROR as i32 && status != ERROR_OBJECT_ALREADY_EXISTS as i32 {
+        return Err(io::Error::from_raw_os_error(status));
+    }
+    Ok(())
+}
+
+/// Route added directly to the routing table, bypassing the route manager. It is removed when
+/// dropped.
+struct ExternalRoute(MIB_IPFORWARD_ROW2);
+
+impl ExternalRoute {
+    fn add(luid: NET_LUID_LH, destination: IpNetwork, next_hop: IpAddr) -> io::Result<Self> {
+        let mut row = unsafe { mem::zeroed() };
+        unsafe { InitializeIpForwardEntry(&mut row) };
+        row.InterfaceLuid = luid;
+        row.DestinationPrefix.Prefix =
+            inet_sockaddr_from_socketaddr(SocketAddr::new(destination.ip(), 0));
+        row.DestinationPrefix.PrefixLength = destination.prefix();
+        row.NextHop = inet_sockaddr_from_socketaddr(SocketAddr::new(next_hop, 0));
+        row.Metric = 0;
+
+        let status = unsafe { CreateIpForwardEntry2(&row) };
+        if status != NO_ERROR as i32 {
+            return Err(io::Error::from_raw_os_error(status));
+        }
+        Ok(Self(row))
+    }
+}
+
+impl Drop for ExternalRoute {
+    fn drop(&mut self) {
+        let status = unsafe { DeleteIpForwardEntry2(&self.0) };
+        if status != NO_ERROR as i32 {
+            eprintln!(
+                "Failed to delete route added by test: {}",
+                io::Error::from_raw_os_error(status)
+            );
+        }
+    }
+}
+
+fn run_test(test: impl FnOnce(&tokio::runtime::Runtime, TestAdapter)) {
+    let _lock = TEST_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to initialize runtime");
+    test(&runtime, TestAdapter::get());
+}
+
+fn required_routes(routes: impl IntoIterator<Item = RequiredRoute>) -> HashSet<RequiredRoute> {
+    routes.into_iter().collect()
+}
+
+/// Routes added through the route manager end up in the routing table, and are removed again
+/// by `clear_routes`.
+#[test]
+fn test_add_and_clear_routes() {
+    run_test(|runtime, adapter| {
+        let destination: IpNetwork = "10.214.1.0/24".parse().unwrap();
+
+        let manager = runtime
+            .block_on(RouteManager::new(HashSet::new()))
+            .expect("Failed to start route manager");
+        runtime
+            .block_on(manager.add_routes(required_routes([RequiredRoute::new(
+                destination,
+                adapter.node(),
+            )])))
+            .expect("Failed to add routes");
+
+        let route = find_route(destination).expect("Route was not added");
+        assert_eq!(route.luid, unsafe { adapter.luid.Value });
+        assert_eq!(route.next_hop, IpAddr::V4(ADAPTER_GATEWAY));
+
+        manager.clear_routes().expect("Failed to clear routes");
+        assert!(find_route(destination).is_none(), "Route was not removed");
+    });
+}
+
+/// Stopping the route manager removes the routes that it added.
+#[test]
+fn test_stop_removes_routes() {
+    run_test(|runtime, adapter| {
+        let destination: IpNetwork = "10.214.2.0/24".parse().unwrap();
+
+        let manager = runtime
+            .block_on(RouteManager::new(required_routes([RequiredRoute::new(
+                destination,
+                adapter.node(),
+            )])))
+            .expect("Failed to start route manager");
+        assert!(find_route(destination).is_some(), "Route was not added");
+
+        drop(manager);
+        assert!(find_route(destination).is_none(), "Route was not removed");
+    });
+}
+
+/// If any route in a batch cannot be added, the routes in the batch that were added are rolled
+/// back.
+#[test]
+fn test_failed_add_is_rolled_back() {
+    run_test(|runtime, adapter| {
+        let valid_destination: IpNetwork = "10.214.3.0/24".parse().unwrap();
+        let invalid_destination: IpNetwork = "10.214.4.0/24".parse().unwrap();
+
+        let manager = runtime
+            .block_on(RouteManager::new(HashSet::new()))
+            .expect("Failed to start route manager");
+        let result = runtime.block_on(manager.add_routes(required_routes([
+            RequiredRoute::new(valid_destination, adapter.node()),
+            RequiredRoute::new(
+                invalid_destination,
+                Node::device("talpid-nonexistent-adapter".to_owned()),
+            ),
+        ])));
+
+        assert!(
+            result.is_err(),
+            "Adding a route through a missing adapter succeeded"
+        );
+        assert!(
+            find_route(valid_destination).is_none(),
+            "Route added before the failure was not rolled back"
+        );
+        assert!(find_route(invalid_destination).is_none());
+    });
+}
+
+/// Routes through the default node follow the best default route when it changes.
+#[test]
+fn test_default_route_refresh() {
+    run_test(|runtime, adapter| {
+        let destination: IpNetwork = "10.214.5.0/24".parse().unwrap();
+        let adapter_luid = unsafe { adapter.luid.Value };
+
+        let manager = runtime
+            .block_on(RouteManager::new(required_routes([RequiredRoute::new(
+                destination,
+                NetNode::DefaultNode,
+            )])))
+            .expect("Failed to start route manager");
+
+        // Make a default route through the test adapter the best one
+        adapter.set_metric(1);
+        let default_route = ExternalRoute::add(
+            adapter.luid,
+            "0.0.0.0/0".parse().unwrap(),
+            IpAddr::V4(ADAPTER_GATEWAY),
+        )
+        .expect("Failed to add default route");
+
+        wait_for_route(destination, |route| {
+            route
+                .map(|route| route.luid == adapter_luid)
+                .unwrap_or(false)
+        });
+
+        // When the default route disappears, the route should no longer use the test adapter
+        drop(default_route);
+
+        wait_for_route(destination, |route| {
+            route
+                .map(|route| route.luid != adapter_luid)
+                .unwrap_or(true)
+        });
+
+        drop(manager);
+    });
+}
@@ -85,6 +85,7 @@ const SET_DNS_METHOD: &str = "SetDNS";
 const SET_DNS_OVER_TLS_METHOD: &str = "SetDNSOverTLS";
 const SET_DOMAINS_METHOD: &str = "SetDomains";
 const REVERT_METHOD: &str = "Revert";
+const FLUSH_CACHES_METHOD: &str = "FlushCaches";
 
 #[derive(Clone)]
 pub struct SystemdResolved {
@@ -285,6 +286,13 @@ impl SystemdResolved {
         self.set_link_dns_domains(&link_object_path, domains)
     }
 
+    /// Flush the caches of all links.
+    pub fn flush_caches(&self) -> Result<()> {
+        self.as_manager_object()
+            .method_call(MANAGER_INTERFACE, FLUSH_CACHES_METHOD, ())
+            .map_err(Error::DBusRpcError)
+    }
+
     fn fetch_link(&self, interface_index: u32) -> Result<dbus::Path<'static>> {
         self.as_manager_object()
             .method_call(
@@ -572,6 +580,13 @@ impl AsyncHandle {
             .map_err(Error::AsyncTaskError)?
     }
 
+    pub async fn flush_caches(&self) -> Result<()> {
+        let interface = self.dbus_interface.clone();
+        tokio::task::spawn_blocking(move || interface.flush_caches())
+            .await
+            .map_err(Error::AsyncTaskError)?
+    }
+
     pub async fn revert_link(&self, state: DnsState) -> Result<()> {
         let mut interface = self.dbus_interface.clone();
         tokio::task::spawn_blocking(move || interface.revert_link(&state))
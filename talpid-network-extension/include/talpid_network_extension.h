@@ -0,0 +1,50 @@
+// Interface for running the tunnel inside an NEPacketTunnelProvider. Link against the static
+// library built from the talpid-network-extension crate.
+//
+// Callbacks may be invoked from any thread, and must not call back into this library.
+
+#ifndef TALPID_NETWORK_EXTENSION_H
+#define TALPID_NETWORK_EXTENSION_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+typedef enum {
+    TalpidTunnelEventInterfaceUp = 0,
+    TalpidTunnelEventUp = 1,
+    TalpidTunnelEventDown = 2,
+    TalpidTunnelEventAuthFailed = 3,
+} TalpidTunnelEvent;
+
+typedef struct {
+    // Passed to every callback.
+    void *context;
+    // Applies the network settings, given as a JSON object with the fields `addresses`,
+    // `dns_servers`, `included_routes`, `excluded_routes`, `mtu`, `include_all_networks` and
+    // `exclude_local_networks`. Must block until the settings have been applied, and return the
+    // file descriptor of the utun device of the provider, or a negative value on failure.
+    int32_t (*apply_settings)(void *context, const char *settings_json);
+    // Called when the state of the tunnel changes. `interface_name` is NULL unless the event
+    // concerns a tunnel device.
+    void (*on_event)(void *context, TalpidTunnelEvent event, const char *interface_name);
+} TalpidPacketTunnelHost;
+
+typedef struct PacketTunnel PacketTunnel;
+
+// Starts a WireGuard tunnel. `parameters_json` contains the tunnel parameters as serialized by
+// talpid-types. `log_dir` and `dns_servers` may be NULL. `dns_servers` is a comma-separated list
+// of IP addresses that overrides the tunnel gateway. Returns NULL on failure, including when
+// `resource_dir` is NULL. Log messages are written to packet-tunnel.log in `log_dir`, or to
+// stderr if `log_dir` is NULL.
+PacketTunnel *talpid_packet_tunnel_start(
+    const char *parameters_json,
+    const char *resource_dir,
+    const char *log_dir,
+    bool allow_lan,
+    const char *dns_servers,
+    TalpidPacketTunnelHost host);
+
+// Closes the tunnel and waits for it to exit. `tunnel` must not be used afterwards.
+void talpid_packet_tunnel_stop(PacketTunnel *tunnel);
+
+#endif
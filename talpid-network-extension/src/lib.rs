@@ -0,0 +1,241 @@
+//! C interface for running the tunnel inside an `NEPacketTunnelProvider` on macOS.
+//!
+//! The provider starts a tunnel with [`talpid_packet_tunnel_start`], passing callbacks that apply
+//! network settings and receive tunnel events. All policy, i.e. which routes, DNS servers and
+//! blocking settings to apply, is decided by `talpid-core`. See the C header in `include/`.
+
+#![cfg(target_os = "macos")]
+#![deny(rust_2018_idioms)]
+
+use std::{
+    ffi::{c_void, CStr, CString},
+    net::IpAddr,
+    os::{raw::c_char, unix::io::RawFd},
+    path::{Path, PathBuf},
+    ptr,
+    sync::{Arc, Once},
+};
+use talpid_core::{
+    network_extension::{PacketTunnel, PacketTunnelHost, PacketTunnelPolicy, PacketTunnelSettings},
+    tunnel::TunnelEvent,
+};
+use talpid_types::{net::TunnelParameters, BoxedError, ErrorExt};
+
+/// Name of the log file that is written to the log directory.
+const LOG_FILENAME: &str = "packet-tunnel.log";
+
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+enum Error {
+    #[error(display = "The packet tunnel provider failed to apply the network settings")]
+    ApplySettings,
+
+    #[error(display = "Failed to serialize the network settings")]
+    SerializeSettings(#[error(source)] serde_json::Error),
+}
+
+/// Events sent to [`TalpidPacketTunnelHost::on_event`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub enum TalpidTunnelEvent {
+    /// The tunnel device has been set up, but the tunnel is not ready yet.
+    InterfaceUp = 0,
+    /// The tunnel is up and ready for traffic.
+    Up = 1,
+    /// The tunnel went down.
+    Down = 2,
+    /// The relay rejected the credentials.
+    AuthFailed = 3,
+}
+
+/// Callbacks into the packet tunnel provider.
+#[repr(C)]
+pub struct TalpidPacketTunnelHost {
+    /// Passed to every callback.
+    pub context: *mut c_void,
+    /// Applies the network settings, given as a JSON object, and returns the file descriptor of
+    /// the utun device of the provider. Returns a negative value on failure.
+    pub apply_settings: extern "C" fn(context: *mut c_void, settings_json: *const c_char) -> i32,
+    /// Called when the state of the tunnel changes. `interface_name` is null unless the event
+    /// concerns a tunnel device.
+    pub on_event: extern "C" fn(
+        context: *mut c_void,
+        event: TalpidTunnelEvent,
+        interface_name: *const c_char,
+    ),
+}
+
+// SAFETY: The provider must accept callbacks from any thread, as documented in the header.
+unsafe impl Send for TalpidPacketTunnelHost {}
+// SAFETY: See above.
+unsafe impl Sync for TalpidPacketTunnelHost {}
+
+impl PacketTunnelHost for TalpidPacketTunnelHost {
+    fn apply_settings(&self, settings: &PacketTunnelSettings) -> Result<RawFd, BoxedError> {
+        let settings = serde_json::to_string(settings)
+            .map_err(|error| BoxedError::new(Error::SerializeSettings(error)))?;
+        let settings = CString::new(settings).expect("JSON does not contain null bytes");
+        let fd = (self.apply_settings)(self.context, settings.as_ptr());
+        if fd < 0 {
+            return Err(BoxedError::new(Error::ApplySettings));
+        }
+        Ok(fd)
+    }
+
+    fn on_tunnel_event(&self, event: &TunnelEvent) {
+        let (event, interface) = match event {
+            TunnelEvent::InterfaceUp(metadata, _) => {
+                (TalpidTunnelEvent::InterfaceUp, Some(&metadata.interface))
+            }
+            TunnelEvent::Up(metadata) => (TalpidTunnelEvent::Up, Some(&metadata.interface)),
+            TunnelEvent::Down => (TalpidTunnelEvent::Down, None),
+            TunnelEvent::AuthFailed(_) => (TalpidTunnelEvent::AuthFailed, None),
+        };
+        let interface = interface.and_then(|interface| CString::new(interface.as_str()).ok());
+        (self.on_event)(
+            self.context,
+            event,
+            interface
+                .as_ref()
+                .map(|interface| interface.as_ptr())
+                .unwrap_or(ptr::null()),
+        );
+    }
+}
+
+/// Starts a WireGuard tunnel. Returns null on failure.
+///
+/// # Safety
+///
+/// `parameters_json` must be a valid, null-terminated string. `resource_dir`, `log_dir` and
+/// `dns_servers` must be null or valid, null-terminated strings. `dns_servers` is a
+/// comma-separated list of IP addresses. Null is returned if `resource_dir` is null.
+#[no_mangle]
+pub unsafe extern "C" fn talpid_packet_tunnel_start(
+    parameters_json: *const c_char,
+    resource_dir: *const c_char,
+    log_dir: *const c_char,
+    allow_lan: bool,
+    dns_servers: *const c_char,
+    host: TalpidPacketTunnelHost,
+) -> *mut PacketTunnel {
+    let log_dir = path_from_ptr(log_dir);
+    init_logging(log_dir.as_deref());
+
+    let resource_dir = match path_from_ptr(resource_dir) {
+        Some(resource_dir) => resource_dir,
+        None => {
+            log::error!("No resource directory was given");
+            return ptr::null_mut();
+        }
+    };
+    let parameters: TunnelParameters =
+        match serde_json::from_slice(CStr::from_ptr(parameters_json).to_bytes()) {
+            Ok(parameters) => parameters,
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to parse tunnel parameters")
+                );
+                return ptr::null_mut();
+            }
+        };
+    let dns_servers = match parse_dns_servers(dns_servers) {
+        Ok(dns_servers) => dns_servers,
+        Err(error) => {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to parse DNS servers")
+            );
+            return ptr::null_mut();
+        }
+    };
+    let policy = PacketTunnelPolicy {
+        allow_lan,
+        dns_servers,
+    };
+
+    match PacketTunnel::start(parameters, resource_dir, log_dir, policy, Arc::new(host)) {
+        Ok(tunnel) => Box::into_raw(Box::new(tunnel)),
+        Err(error) => {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to start packet tunnel")
+            );
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Closes a tunnel started by [`talpid_packet_tunnel_start`] and waits for it to exit.
+///
+/// # Safety
+///
+/// `tunnel` must be null or have been returned by [`talpid_packet_tunnel_start`], and must not
+/// be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn talpid_packet_tunnel_stop(tunnel: *mut PacketTunnel) {
+    if !tunnel.is_null() {
+        Box::from_raw(tunnel).stop();
+    }
+}
+
+/// Logs to a file in `log_dir`, or to stderr if there is no log directory. Only the first call has
+/// any effect, since a process can only have one logger.
+fn init_logging(log_dir: Option<&Path>) {
+    static INIT_LOGGING: Once = Once::new();
+    INIT_LOGGING.call_once(|| {
+        let dispatch =
+            fern::Dispatch::new()
+                .level(log::LevelFilter::Debug)
+                .format(|out, message, record| {
+                    out.finish(format_args!(
+                        "{}[{}][{}] {}",
+                        chrono::Local::now().format("[%Y-%m-%d %H:%M:%S%.3f]"),
+                        record.target(),
+                        record.level(),
+                        message,
+                    ))
+                });
+        let dispatch = match log_dir.map(|dir| fern::log_file(dir.join(LOG_FILENAME))) {
+            Some(Ok(file)) => dispatch.chain(file),
+            Some(Err(error)) => {
+                eprintln!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to open the log file")
+                );
+                dispatch.chain(std::io::stderr())
+            }
+            None => dispatch.chain(std::io::stderr()),
+        };
+        if let Err(error) = dispatch.apply() {
+            eprintln!(
+                "{}",
+                error.display_chain_with_msg("Failed to install logger")
+            );
+        }
+    });
+}
+
+unsafe fn path_from_ptr(path: *const c_char) -> Option<PathBuf> {
+    if path.is_null() {
+        return None;
+    }
+    Some(PathBuf::from(
+        CStr::from_ptr(path).to_string_lossy().into_owned(),
+    ))
+}
+
+unsafe fn parse_dns_servers(
+    dns_servers: *const c_char,
+) -> Result<Option<Vec<IpAddr>>, std::net::AddrParseError> {
+    if dns_servers.is_null() {
+        return Ok(None);
+    }
+    CStr::from_ptr(dns_servers)
+        .to_string_lossy()
+        .split(',')
+        .map(|server| server.trim().parse())
+        .collect::<Result<Vec<_>, _>>()
+        .map(Some)
+}
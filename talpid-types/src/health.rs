@@ -0,0 +1,96 @@
+use std::fmt;
+
+/// Outcome of a single health check. Ordered from best to worst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HealthStatus {
+    /// The component works as expected.
+    Pass,
+    /// The component works, but something is likely to cause problems.
+    Warn,
+    /// The component does not work.
+    Fail,
+}
+
+impl fmt::Display for HealthStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HealthStatus::Pass => "pass".fmt(f),
+            HealthStatus::Warn => "warn".fmt(f),
+            HealthStatus::Fail => "fail".fmt(f),
+        }
+    }
+}
+
+/// Result of checking one internal component.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthCheck {
+    /// Name of the component that was checked.
+    pub name: &'static str,
+    pub status: HealthStatus,
+    /// Explanation of why the check did not pass, if it did not.
+    pub details: Option<String>,
+    /// What the user can do to make the check pass.
+    pub remediation: Option<String>,
+}
+
+impl HealthCheck {
+    pub fn pass(name: &'static str) -> Self {
+        Self {
+            name,
+            status: HealthStatus::Pass,
+            details: None,
+            remediation: None,
+        }
+    }
+
+    pub fn warn(name: &'static str, details: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: HealthStatus::Warn,
+            details: Some(details.into()),
+            remediation: None,
+        }
+    }
+
+    pub fn fail(name: &'static str, details: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: HealthStatus::Fail,
+            details: Some(details.into()),
+            remediation: None,
+        }
+    }
+
+    /// Adds a hint about what the user can do to make the check pass.
+    pub fn with_remediation(mut self, remediation: impl Into<String>) -> Self {
+        self.remediation = Some(remediation.into());
+        self
+    }
+}
+
+impl fmt::Display for HealthCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.name, self.status)?;
+        if let Some(details) = &self.details {
+            write!(f, " ({})", details)?;
+        }
+        Ok(())
+    }
+}
+
+/// Results of all health checks.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HealthReport {
+    pub checks: Vec<HealthCheck>,
+}
+
+impl HealthReport {
+    /// Returns the worst status of all checks.
+    pub fn status(&self) -> HealthStatus {
+        self.checks
+            .iter()
+            .map(|check| check.status)
+            .max()
+            .unwrap_or(HealthStatus::Pass)
+    }
+}
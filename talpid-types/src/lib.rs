@@ -4,7 +4,11 @@ use std::{error::Error, fmt, fmt::Write};
 
 #[cfg(target_os = "android")]
 pub mod android;
+pub mod health;
 pub mod net;
+pub mod performance;
+pub mod system_state;
+pub mod traffic_query;
 pub mod tunnel;
 
 #[cfg(target_os = "linux")]
@@ -1,3 +1,4 @@
+use ipnetwork::IpNetwork;
 #[cfg(target_os = "android")]
 use jnix::IntoJava;
 use obfuscation::ObfuscatorConfig;
@@ -13,6 +14,8 @@ use std::{
 pub mod obfuscation;
 pub mod openvpn;
 pub mod proxy;
+pub mod route_takeover;
+mod validation;
 pub mod wireguard;
 
 /// TunnelParameters are used to encapsulate all the data needed to start a tunnel. This is enum
@@ -34,6 +37,7 @@ impl TunnelParameters {
                 proxy: params.proxy.as_ref().map(|proxy| proxy.get_endpoint()),
                 obfuscation: None,
                 entry_endpoint: None,
+                wireguard_backend: None,
             },
             TunnelParameters::Wireguard(params) => TunnelEndpoint {
                 tunnel_type: TunnelType::Wireguard,
@@ -48,6 +52,7 @@ impl TunnelParameters {
                     .connection
                     .get_exit_endpoint()
                     .map(|_| params.connection.get_endpoint()),
+                wireguard_backend: None,
             },
         }
     }
@@ -70,8 +75,8 @@ impl TunnelParameters {
 
     fn get_obfuscator_endpoint(obfuscator: &ObfuscatorConfig) -> Endpoint {
         match obfuscator {
-            ObfuscatorConfig::Udp2Tcp { endpoint } => Endpoint {
-                address: *endpoint,
+            ObfuscatorConfig::Udp2Tcp { .. } | ObfuscatorConfig::Tls { .. } => Endpoint {
+                address: obfuscator.endpoint(),
                 protocol: TransportProtocol::Tcp,
             },
         }
@@ -142,6 +147,9 @@ pub struct TunnelEndpoint {
     pub obfuscation: Option<ObfuscationEndpoint>,
     #[cfg_attr(target_os = "android", jnix(skip))]
     pub entry_endpoint: Option<Endpoint>,
+    /// WireGuard implementation used by the tunnel. This is only known once connected.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub wireguard_backend: Option<wireguard::WireguardBackend>,
 }
 
 impl fmt::Display for TunnelEndpoint {
@@ -164,6 +172,9 @@ impl fmt::Display for TunnelEndpoint {
                 if let Some(ref obfuscation) = self.obfuscation {
                     write!(f, " via {}", obfuscation)?;
                 }
+                if let Some(backend) = self.wireguard_backend {
+                    write!(f, " using {}", backend)?;
+                }
             }
         }
         Ok(())
@@ -175,12 +186,15 @@ impl fmt::Display for TunnelEndpoint {
 pub enum ObfuscationType {
     #[serde(rename = "udp2tcp")]
     Udp2Tcp,
+    #[serde(rename = "tls")]
+    Tls,
 }
 
 impl fmt::Display for ObfuscationType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         let obfuscation = match self {
             ObfuscationType::Udp2Tcp => "Udp2Tcp",
+            ObfuscationType::Tls => "TLS",
         };
         write!(f, "{}", obfuscation)
     }
@@ -203,6 +217,13 @@ impl From<&ObfuscatorConfig> for ObfuscationEndpoint {
                 },
                 ObfuscationType::Udp2Tcp,
             ),
+            ObfuscatorConfig::Tls { endpoint, .. } => (
+                Endpoint {
+                    address: *endpoint,
+                    protocol: TransportProtocol::Tcp,
+                },
+                ObfuscationType::Tls,
+            ),
         };
 
         ObfuscationEndpoint {
@@ -250,23 +271,33 @@ impl fmt::Display for Endpoint {
     }
 }
 
-/// Host that should be reachable in any tunnel state.
+/// Hosts that should be reachable in any tunnel state.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct AllowedEndpoint {
-    /// Paths that should be allowed to communicate with `endpoint`.
+    /// Paths that should be allowed to communicate with `endpoints`.
     #[cfg(windows)]
     pub clients: Vec<PathBuf>,
-    pub endpoint: Endpoint,
+    /// Addresses, ports and protocols that should be reachable.
+    pub endpoints: Vec<EndpointRange>,
+    /// Hostname that resolves to the addresses of `endpoints`, if they belong to a named host.
+    /// It is resolved by the filtering resolver on macOS in blocking states.
+    pub hostname: Option<String>,
 }
 
 impl fmt::Display for AllowedEndpoint {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        #[cfg(not(windows))]
-        write!(f, "{}", self.endpoint)?;
+        for (i, endpoint) in self.endpoints.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", endpoint)?;
+        }
+        if let Some(hostname) = &self.hostname {
+            write!(f, " ({})", hostname)?;
+        }
         #[cfg(windows)]
         {
-            write!(f, "{} for", self.endpoint)?;
-            #[cfg(windows)]
+            write!(f, " for")?;
             for client in &self.clients {
                 write!(
                     f,
@@ -282,19 +313,239 @@ impl fmt::Display for AllowedEndpoint {
     }
 }
 
+/// A host together with a range of ports and the transport protocol(s) used to reach it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct EndpointRange {
+    /// The network layer address of the host.
+    pub address: IpAddr,
+    /// The ports that are covered.
+    pub ports: PortRange,
+    /// The transport protocol that is covered. `None` means both TCP and UDP.
+    pub protocol: Option<TransportProtocol>,
+}
+
+impl EndpointRange {
+    /// Returns the transport protocols that are covered by this range.
+    pub fn protocols(&self) -> &'static [TransportProtocol] {
+        match self.protocol {
+            Some(TransportProtocol::Tcp) => &[TransportProtocol::Tcp],
+            Some(TransportProtocol::Udp) => &[TransportProtocol::Udp],
+            None => &[TransportProtocol::Tcp, TransportProtocol::Udp],
+        }
+    }
+
+    /// Returns whether `endpoint` is covered by this range.
+    pub fn contains(&self, endpoint: &Endpoint) -> bool {
+        self.address == endpoint.address.ip()
+            && self.ports.contains(endpoint.address.port())
+            && self.protocols().contains(&endpoint.protocol)
+    }
+
+    /// Returns the single endpoint that this range covers, if it only covers one.
+    pub fn as_single_endpoint(&self) -> Option<Endpoint> {
+        match (self.ports.is_single(), self.protocol) {
+            (true, Some(protocol)) => {
+                Some(Endpoint::new(self.address, self.ports.start(), protocol))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl From<Endpoint> for EndpointRange {
+    fn from(endpoint: Endpoint) -> Self {
+        EndpointRange {
+            address: endpoint.address.ip(),
+            ports: PortRange::single(endpoint.address.port()),
+            protocol: Some(endpoint.protocol),
+        }
+    }
+}
+
+impl fmt::Display for EndpointRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self.address {
+            IpAddr::V4(address) => write!(f, "{}:{}", address, self.ports)?,
+            IpAddr::V6(address) => write!(f, "[{}]:{}", address, self.ports)?,
+        }
+        match self.protocol {
+            Some(protocol) => write!(f, "/{}", protocol),
+            None => write!(f, "/TCP+UDP"),
+        }
+    }
+}
+
+/// An inclusive range of ports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PortRange {
+    start: u16,
+    end: u16,
+}
+
+impl PortRange {
+    /// Constructs a range from `start` to `end`, inclusive. Returns `None` if `start` is greater
+    /// than `end`.
+    pub fn new(start: u16, end: u16) -> Option<Self> {
+        if start <= end {
+            Some(PortRange { start, end })
+        } else {
+            None
+        }
+    }
+
+    /// Constructs a range that only contains `port`.
+    pub const fn single(port: u16) -> Self {
+        PortRange {
+            start: port,
+            end: port,
+        }
+    }
+
+    pub fn start(&self) -> u16 {
+        self.start
+    }
+
+    pub fn end(&self) -> u16 {
+        self.end
+    }
+
+    pub fn is_single(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn contains(&self, port: u16) -> bool {
+        self.start <= port && port <= self.end
+    }
+}
+
+impl fmt::Display for PortRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        if self.is_single() {
+            write!(f, "{}", self.start)
+        } else {
+            write!(f, "{}-{}", self.start, self.end)
+        }
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum AllowedTunnelTraffic {
     None,
     All,
     Only(Endpoint),
+    /// Only DNS requests, over TCP and UDP, to the given servers.
+    Dns(Vec<IpAddr>),
+    /// Only traffic to `port` over `protocol`, on any of the given addresses.
+    Endpoints {
+        addresses: Vec<IpAddr>,
+        port: u16,
+        protocol: TransportProtocol,
+    },
 }
 
 impl fmt::Display for AllowedTunnelTraffic {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        match *self {
+        match self {
             AllowedTunnelTraffic::None => "None".fmt(f),
             AllowedTunnelTraffic::All => "All".fmt(f),
             AllowedTunnelTraffic::Only(endpoint) => endpoint.fmt(f),
+            AllowedTunnelTraffic::Dns(servers) => {
+                write!(f, "DNS to ")?;
+                fmt_addresses(servers, f)
+            }
+            AllowedTunnelTraffic::Endpoints {
+                addresses,
+                port,
+                protocol,
+            } => {
+                write!(f, "{} port {} on ", protocol, port)?;
+                fmt_addresses(addresses, f)
+            }
+        }
+    }
+}
+
+fn fmt_addresses(addresses: &[IpAddr], f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for (i, address) in addresses.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", address)?;
+    }
+    Ok(())
+}
+
+/// Traffic of another VPN running alongside this one, such as a WireGuard mesh, that should be
+/// exempt from blocking and kept out of the tunnel.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Hash, Deserialize, Serialize)]
+#[serde(default)]
+pub struct VpnCoexistence {
+    /// Networks that are reachable through the other VPN.
+    pub networks: Vec<IpNetwork>,
+    /// Local UDP ports that the other VPN sends and receives its own tunnel traffic on, such as
+    /// the WireGuard listen port.
+    pub udp_ports: Vec<u16>,
+}
+
+impl VpnCoexistence {
+    /// Returns whether no traffic is exempt.
+    pub fn is_empty(&self) -> bool {
+        self.networks.is_empty() && self.udp_ports.is_empty()
+    }
+}
+
+impl fmt::Display for VpnCoexistence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        let networks = self
+            .networks
+            .iter()
+            .map(|network| network.to_string())
+            .collect::<Vec<_>>();
+        let ports = self
+            .udp_ports
+            .iter()
+            .map(|port| port.to_string())
+            .collect::<Vec<_>>();
+        write!(
+            f,
+            "networks: [{}], UDP ports: [{}]",
+            networks.join(", "),
+            ports.join(", ")
+        )
+    }
+}
+
+/// Protocols that are blocked inside the tunnel while connected. Traffic to the relay itself,
+/// such as WireGuard packets, is never affected.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BlockedTunnelProtocols {
+    /// Block all outgoing IPv6 traffic in the tunnel, including DNS.
+    pub ipv6: bool,
+    /// Block all outgoing UDP traffic in the tunnel, including DNS.
+    pub udp: bool,
+    /// Block outgoing SMB traffic (TCP ports 139 and 445) in the tunnel.
+    pub smb: bool,
+}
+
+impl BlockedTunnelProtocols {
+    /// Returns whether no protocols are blocked.
+    pub fn is_empty(&self) -> bool {
+        !self.ipv6 && !self.udp && !self.smb
+    }
+}
+
+impl fmt::Display for BlockedTunnelProtocols {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        let protocols = [("IPv6", self.ipv6), ("UDP", self.udp), ("SMB", self.smb)]
+            .iter()
+            .filter(|(_, blocked)| *blocked)
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>();
+        if protocols.is_empty() {
+            "none".fmt(f)
+        } else {
+            protocols.join(", ").fmt(f)
         }
     }
 }
@@ -361,12 +612,77 @@ impl fmt::Display for TransportProtocolParseError {
     }
 }
 
+/// A non-tunnel network interface that sockets can be bound to, so that their traffic leaves
+/// the host directly rather than through the tunnel.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PhysicalInterface {
+    /// Name of the interface. On Windows, this is the interface alias.
+    pub name: String,
+    /// Index of the interface.
+    pub index: u32,
+}
+
+impl fmt::Display for PhysicalInterface {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (index {})", self.name, self.index)
+    }
+}
+
+/// Description of a network interface that is backed by hardware, such as an Ethernet or Wi-Fi
+/// adapter. Tunnel and other virtual interfaces are not described.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PhysicalInterfaceDetails {
+    /// Name of the interface. This is the name that the forced interface setting accepts.
+    pub name: String,
+    /// Index of the interface.
+    pub index: u32,
+    /// Kind of link that the interface uses.
+    pub kind: PhysicalInterfaceKind,
+    /// IP addresses assigned to the interface, along with the prefix of their subnet.
+    pub addresses: Vec<IpNetwork>,
+    /// Whether the best IPv4 or IPv6 default route leaves through this interface, not counting
+    /// routes through the tunnel.
+    pub is_default_route: bool,
+    /// Maximum transmission unit of the interface, if it is known.
+    pub mtu: Option<u32>,
+}
+
+/// Kind of link of a [`PhysicalInterfaceDetails`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PhysicalInterfaceKind {
+    Ethernet,
+    Wireless,
+    Cellular,
+    Other,
+}
+
+impl fmt::Display for PhysicalInterfaceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PhysicalInterfaceKind::Ethernet => f.write_str("Ethernet"),
+            PhysicalInterfaceKind::Wireless => f.write_str("Wi-Fi"),
+            PhysicalInterfaceKind::Cellular => f.write_str("cellular"),
+            PhysicalInterfaceKind::Other => f.write_str("other"),
+        }
+    }
+}
+
 /// Holds optional settings that can apply to different kinds of tunnels
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
 pub struct GenericTunnelOptions {
     /// Enable configuration of IPv6 on the tunnel interface, allowing IPv6 communication to be
     /// forwarded through the tunnel.
     pub enable_ipv6: bool,
+    /// Forward IPv4 communication through the tunnel. If disabled, IPv4 traffic is blocked, except
+    /// to the tunnel gateway, which is still used for DNS and connectivity checks. Only WireGuard
+    /// tunnels can be used with IPv4 disabled.
+    #[serde(default = "default_enable_ipv4")]
+    pub enable_ipv4: bool,
+}
+
+fn default_enable_ipv4() -> bool {
+    true
 }
 
 /// Returns a vector of IP networks representing all of the internet, 0.0.0.0/0.
@@ -378,3 +694,138 @@ pub fn all_of_the_internet() -> Vec<ipnetwork::IpNetwork> {
         "::0/0".parse().expect("Failed to parse ipv6 network"),
     ]
 }
+
+/// Returns networks that together cover every address that is not in any of `networks`, for both
+/// IPv4 and IPv6.
+pub fn complement_networks(networks: &[IpNetwork]) -> Vec<IpNetwork> {
+    let v4_holes = networks.iter().filter_map(|network| match network {
+        IpNetwork::V4(network) => Some((u128::from(u32::from(network.ip())), network.prefix())),
+        IpNetwork::V6(_) => None,
+    });
+    let v6_holes = networks.iter().filter_map(|network| match network {
+        IpNetwork::V4(_) => None,
+        IpNetwork::V6(network) => Some((u128::from(network.ip()), network.prefix())),
+    });
+
+    let v4 = complement(v4_holes, 32).into_iter().map(|(addr, prefix)| {
+        IpNetwork::V4(
+            ipnetwork::Ipv4Network::new(std::net::Ipv4Addr::from(addr as u32), prefix)
+                .expect("prefix is valid for IPv4"),
+        )
+    });
+    let v6 = complement(v6_holes, 128).into_iter().map(|(addr, prefix)| {
+        IpNetwork::V6(
+            ipnetwork::Ipv6Network::new(std::net::Ipv6Addr::from(addr), prefix)
+                .expect("prefix is valid for IPv6"),
+        )
+    });
+    v4.chain(v6).collect()
+}
+
+/// A network of a single address family, as an address and a prefix length, where the address is
+/// stored in the `width` least significant bits.
+type RawNetwork = (u128, u8);
+
+fn complement(holes: impl Iterator<Item = RawNetwork>, width: u8) -> Vec<RawNetwork> {
+    let mut remaining = vec![(0, 0)];
+    for (addr, prefix) in holes {
+        let hole = (addr & prefix_mask(prefix, width), prefix);
+        remaining = remaining
+            .into_iter()
+            .flat_map(|network| subtract_network(network, hole, width))
+            .collect();
+    }
+    remaining
+}
+
+/// Returns networks that cover `network` except for `hole`.
+fn subtract_network(network: RawNetwork, hole: RawNetwork, width: u8) -> Vec<RawNetwork> {
+    if network_contains(hole, network, width) {
+        return vec![];
+    }
+    if !network_contains(network, hole, width) {
+        // Networks either contain one another or are disjoint
+        return vec![network];
+    }
+    // Split the network in halves, one of which contains the hole
+    let (addr, prefix) = network;
+    let upper_half = addr | (1 << (width - prefix - 1));
+    let mut networks = subtract_network((addr, prefix + 1), hole, width);
+    networks.append(&mut subtract_network((upper_half, prefix + 1), hole, width));
+    networks
+}
+
+fn network_contains(network: RawNetwork, other: RawNetwork, width: u8) -> bool {
+    let mask = prefix_mask(network.1, width);
+    network.1 <= other.1 && network.0 & mask == other.0 & mask
+}
+
+fn prefix_mask(prefix: u8, width: u8) -> u128 {
+    if prefix == 0 {
+        return 0;
+    }
+    let mask = u128::MAX << (width - prefix);
+    if width == 128 {
+        mask
+    } else {
+        mask & ((1 << width) - 1)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn networks(networks: &[&str]) -> Vec<IpNetwork> {
+        networks
+            .iter()
+            .map(|network| network.parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_complement_networks() {
+        assert_eq!(complement_networks(&[]), networks(&["0.0.0.0/0", "::/0"]));
+        assert_eq!(complement_networks(&all_of_the_internet()), vec![]);
+        assert_eq!(
+            complement_networks(&networks(&["128.0.0.0/2", "fc00::/8", "::/1"])),
+            networks(&[
+                "0.0.0.0/1",
+                "192.0.0.0/2",
+                "8000::/2",
+                "c000::/3",
+                "e000::/4",
+                "f000::/5",
+                "f800::/6",
+                "fd00::/8",
+                "fe00::/7"
+            ])
+        );
+    }
+
+    #[test]
+    fn test_complement_covers_other_addresses() {
+        let holes = networks(&["10.0.0.0/8", "10.64.0.1/32", "192.168.1.0/24"]);
+        let complement = complement_networks(&holes);
+        // 7 networks around 10.0.0.0/8, 23 in 128.0.0.0/1 around 192.168.1.0/24, and ::/0
+        assert_eq!(complement.len(), 7 + 23 + 1);
+
+        for addr in [
+            "10.1.2.3",
+            "192.168.1.255",
+            "8.8.8.8",
+            "192.168.2.1",
+            "2001:db8::1",
+        ] {
+            let addr: IpAddr = addr.parse().unwrap();
+            let in_holes = holes.iter().any(|network| network.contains(addr));
+            let in_complement = complement.iter().filter(|network| network.contains(addr));
+            assert_eq!(
+                in_complement.count(),
+                if in_holes { 0 } else { 1 },
+                "{}",
+                addr
+            );
+        }
+    }
+}
@@ -1,7 +1,119 @@
-use serde::{Deserialize, Serialize};
-use std::net::SocketAddr;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{fmt, net::SocketAddr, str::FromStr};
 
 #[derive(Clone, Eq, PartialEq, Deserialize, Serialize, Debug)]
 pub enum ObfuscatorConfig {
-    Udp2Tcp { endpoint: SocketAddr },
+    Udp2Tcp {
+        endpoint: SocketAddr,
+    },
+    /// Tunnels traffic through a TLS connection, so that it resembles HTTPS.
+    Tls {
+        endpoint: SocketAddr,
+        /// Server name to send in the TLS handshake.
+        server_name: String,
+        /// Fingerprint of the certificate that the relay must present.
+        certificate: CertificateFingerprint,
+    },
+}
+
+impl ObfuscatorConfig {
+    /// Returns the address of the relay that the obfuscator connects to.
+    pub fn endpoint(&self) -> SocketAddr {
+        match self {
+            ObfuscatorConfig::Udp2Tcp { endpoint } => *endpoint,
+            ObfuscatorConfig::Tls { endpoint, .. } => *endpoint,
+        }
+    }
+}
+
+/// SHA-256 digest of a DER encoded certificate. It is written as a hex string.
+#[derive(Clone, Copy, Eq, PartialEq, Hash)]
+pub struct CertificateFingerprint([u8; 32]);
+
+/// Returned when parsing a [`CertificateFingerprint`] fails.
+#[derive(err_derive::Error, Debug, Clone, PartialEq, Eq)]
+#[error(display = "A certificate fingerprint must be 64 hex digits")]
+pub struct InvalidCertificateFingerprint;
+
+impl CertificateFingerprint {
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        CertificateFingerprint(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl TryFrom<&[u8]> for CertificateFingerprint {
+    type Error = InvalidCertificateFingerprint;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        <[u8; 32]>::try_from(bytes)
+            .map(CertificateFingerprint)
+            .map_err(|_| InvalidCertificateFingerprint)
+    }
+}
+
+impl FromStr for CertificateFingerprint {
+    type Err = InvalidCertificateFingerprint;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 64 || !s.is_ascii() {
+            return Err(InvalidCertificateFingerprint);
+        }
+        let mut bytes = [0u8; 32];
+        for (byte, digits) in bytes.iter_mut().zip(s.as_bytes().chunks(2)) {
+            // `digits` is ASCII, so it is valid UTF-8
+            let digits = std::str::from_utf8(digits).unwrap();
+            *byte = u8::from_str_radix(digits, 16).map_err(|_| InvalidCertificateFingerprint)?;
+        }
+        Ok(CertificateFingerprint(bytes))
+    }
+}
+
+impl fmt::Display for CertificateFingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in &self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for CertificateFingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl Serialize for CertificateFingerprint {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for CertificateFingerprint {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let fingerprint = String::deserialize(deserializer)?;
+        fingerprint.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_certificate_fingerprint() {
+        let hex = "00ff10ab".repeat(8);
+        let fingerprint: CertificateFingerprint = hex.parse().unwrap();
+        assert_eq!(&fingerprint.as_bytes()[..4], &[0x00, 0xff, 0x10, 0xab]);
+        assert_eq!(fingerprint.to_string(), hex);
+        assert_eq!(hex.to_uppercase().parse(), Ok(fingerprint));
+
+        assert!("00ff".parse::<CertificateFingerprint>().is_err());
+        assert!("zz".repeat(32).parse::<CertificateFingerprint>().is_err());
+        assert!("é".repeat(32).parse::<CertificateFingerprint>().is_err());
+    }
 }
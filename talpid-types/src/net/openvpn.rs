@@ -3,7 +3,7 @@ use crate::net::{
     Endpoint, GenericTunnelOptions, TransportProtocol,
 };
 use serde::{Deserialize, Serialize};
-use std::net::SocketAddr;
+use std::{fmt, net::SocketAddr};
 
 /// Information needed by `OpenVpnMonitor` to establish a tunnel connection.
 /// See [`crate::net::TunnelParameters`].
@@ -21,6 +21,10 @@ pub struct ConnectionConfig {
     pub endpoint: Endpoint,
     pub username: String,
     pub password: String,
+    /// Client certificate supplied at connect time. It is only ever kept in memory, and is
+    /// therefore never serialized.
+    #[serde(skip)]
+    pub client_certificate: Option<ClientCertificate>,
 }
 
 impl ConnectionConfig {
@@ -29,10 +33,27 @@ impl ConnectionConfig {
             endpoint,
             username,
             password,
+            client_certificate: None,
         }
     }
 }
 
+/// PEM encoded client certificate and private key used to authenticate against the server.
+#[derive(Clone, Eq, PartialEq, Hash)]
+pub struct ClientCertificate {
+    pub certificate: String,
+    pub private_key: String,
+}
+
+impl fmt::Debug for ClientCertificate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientCertificate")
+            .field("certificate", &self.certificate)
+            .field("private_key", &"<redacted>")
+            .finish()
+    }
+}
+
 /// `TunnelOptions` contains options for an OpenVPN tunnel that should be applied
 /// irrespective of the relay parameters - i.e. have nothing to do with the particular
 /// OpenVPN server, but do affect the connection.
@@ -0,0 +1,234 @@
+//! Types describing other VPN products that take over the default route while the tunnel is up.
+//!
+//! Other VPN clients typically route all traffic through themselves by adding either a default
+//! route or a pair of `0.0.0.0/1` and `128.0.0.0/1` routes, which are more specific than any
+//! default route. Either way, traffic that should go through our tunnel may end up in theirs.
+
+use ipnetwork::IpNetwork;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// What to do when another VPN takes over the default route while connected.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RouteTakeoverPolicy {
+    /// Only report the competing VPN.
+    #[default]
+    Warn,
+    /// Reconnect, so that the routes of the tunnel are applied again and take precedence.
+    Reclaim,
+    /// Give up the tunnel and block all traffic until the user connects again.
+    Yield,
+}
+
+impl fmt::Display for RouteTakeoverPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouteTakeoverPolicy::Warn => "warn".fmt(f),
+            RouteTakeoverPolicy::Reclaim => "reclaim".fmt(f),
+            RouteTakeoverPolicy::Yield => "yield".fmt(f),
+        }
+    }
+}
+
+impl FromStr for RouteTakeoverPolicy {
+    type Err = ParsePolicyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warn" => Ok(RouteTakeoverPolicy::Warn),
+            "reclaim" => Ok(RouteTakeoverPolicy::Reclaim),
+            "yield" => Ok(RouteTakeoverPolicy::Yield),
+            _ => Err(ParsePolicyError),
+        }
+    }
+}
+
+/// Returned when a string is not a valid [`RouteTakeoverPolicy`].
+#[derive(err_derive::Error, Debug, Clone, PartialEq, Eq)]
+#[error(display = "Expected one of \"warn\", \"reclaim\" or \"yield\"")]
+pub struct ParsePolicyError;
+
+/// Product that a competing VPN interface most likely belongs to.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompetingVpnKind {
+    WireGuard,
+    OpenVpn,
+    CiscoAnyConnect,
+    GlobalProtect,
+    FortiClient,
+    Tailscale,
+    ZeroTier,
+    Ipsec,
+    Ppp,
+    /// The interface does not match any known product.
+    Unknown,
+}
+
+/// Substrings of interface names and descriptions, in lowercase, that identify a product. More
+/// specific patterns come first, since e.g. Tailscale uses Wintun adapters.
+const KIND_PATTERNS: &[(&str, CompetingVpnKind)] = &[
+    ("tailscale", CompetingVpnKind::Tailscale),
+    ("zerotier", CompetingVpnKind::ZeroTier),
+    ("anyconnect", CompetingVpnKind::CiscoAnyConnect),
+    ("cisco", CompetingVpnKind::CiscoAnyConnect),
+    ("cscotun", CompetingVpnKind::CiscoAnyConnect),
+    ("pangp", CompetingVpnKind::GlobalProtect),
+    ("globalprotect", CompetingVpnKind::GlobalProtect),
+    ("fortinet", CompetingVpnKind::FortiClient),
+    ("forticlient", CompetingVpnKind::FortiClient),
+    ("wireguard", CompetingVpnKind::WireGuard),
+    ("wintun", CompetingVpnKind::WireGuard),
+    ("tap-windows", CompetingVpnKind::OpenVpn),
+    ("openvpn", CompetingVpnKind::OpenVpn),
+    ("ipsec", CompetingVpnKind::Ipsec),
+];
+
+/// Prefixes of interface names that identify a product when the interface has no description.
+const NAME_PREFIXES: &[(&str, CompetingVpnKind)] = &[
+    ("wg", CompetingVpnKind::WireGuard),
+    ("tun", CompetingVpnKind::OpenVpn),
+    ("tap", CompetingVpnKind::OpenVpn),
+    ("ppp", CompetingVpnKind::Ppp),
+    ("utun", CompetingVpnKind::Unknown),
+];
+
+impl CompetingVpnKind {
+    /// Guesses the product that created an interface from its name and, where the platform has
+    /// one, its description. Returns `None` if the interface does not look like a VPN interface.
+    pub fn classify(name: &str, description: Option<&str>) -> Option<Self> {
+        let name = name.to_lowercase();
+        let description = description.map(str::to_lowercase).unwrap_or_default();
+        for (pattern, kind) in KIND_PATTERNS {
+            if description.contains(pattern) || name.contains(pattern) {
+                return Some(*kind);
+            }
+        }
+        NAME_PREFIXES
+            .iter()
+            .find(|(prefix, _)| name.starts_with(prefix))
+            .map(|(_, kind)| *kind)
+    }
+}
+
+impl fmt::Display for CompetingVpnKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            CompetingVpnKind::WireGuard => "WireGuard",
+            CompetingVpnKind::OpenVpn => "OpenVPN",
+            CompetingVpnKind::CiscoAnyConnect => "Cisco AnyConnect",
+            CompetingVpnKind::GlobalProtect => "GlobalProtect",
+            CompetingVpnKind::FortiClient => "FortiClient",
+            CompetingVpnKind::Tailscale => "Tailscale",
+            CompetingVpnKind::ZeroTier => "ZeroTier",
+            CompetingVpnKind::Ipsec => "IPsec",
+            CompetingVpnKind::Ppp => "PPP",
+            CompetingVpnKind::Unknown => "unknown VPN",
+        };
+        name.fmt(f)
+    }
+}
+
+/// Another VPN that has added a route competing with the routes of the tunnel.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize)]
+pub struct CompetingVpn {
+    /// Name of the interface that the competing route leads to.
+    pub interface: String,
+    /// Description of the interface, if the platform has one.
+    pub description: Option<String>,
+    /// Product that the interface most likely belongs to.
+    pub kind: CompetingVpnKind,
+    /// Destination of the competing route.
+    pub prefix: IpNetwork,
+}
+
+impl CompetingVpn {
+    /// Returns a competing VPN if a route to `prefix` through the given interface would take
+    /// over the default route. `/1` routes are only ever added to take over the default route, so
+    /// they compete regardless of the interface. Default routes only compete if they lead to an
+    /// interface that looks like a VPN interface, since physical networks add them too.
+    pub fn from_route(
+        prefix: IpNetwork,
+        interface: &str,
+        description: Option<&str>,
+    ) -> Option<Self> {
+        let kind = match (
+            prefix.prefix(),
+            CompetingVpnKind::classify(interface, description),
+        ) {
+            (0, Some(kind)) => kind,
+            (1, kind) => kind.unwrap_or(CompetingVpnKind::Unknown),
+            _ => return None,
+        };
+        Some(CompetingVpn {
+            interface: interface.to_owned(),
+            description: description.map(str::to_owned),
+            kind,
+            prefix,
+        })
+    }
+}
+
+impl fmt::Display for CompetingVpn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} routes {} via {}",
+            self.kind, self.prefix, self.interface
+        )?;
+        if let Some(description) = &self.description {
+            write!(f, " ({})", description)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_classify_by_description() {
+        assert_eq!(
+            CompetingVpnKind::classify("Ethernet 3", Some("PANGP Virtual Ethernet Adapter")),
+            Some(CompetingVpnKind::GlobalProtect)
+        );
+        assert_eq!(
+            CompetingVpnKind::classify("Tailscale", Some("Wintun Userspace Tunnel")),
+            Some(CompetingVpnKind::Tailscale)
+        );
+        assert_eq!(
+            CompetingVpnKind::classify("Ethernet", Some("Intel(R) Ethernet Connection")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_classify_by_name() {
+        assert_eq!(
+            CompetingVpnKind::classify("wg0", None),
+            Some(CompetingVpnKind::WireGuard)
+        );
+        assert_eq!(
+            CompetingVpnKind::classify("utun4", None),
+            Some(CompetingVpnKind::Unknown)
+        );
+        assert_eq!(CompetingVpnKind::classify("eth0", None), None);
+    }
+
+    #[test]
+    fn test_competing_routes() {
+        let default: IpNetwork = "0.0.0.0/0".parse().unwrap();
+        let half: IpNetwork = "128.0.0.0/1".parse().unwrap();
+        let lan: IpNetwork = "192.168.1.0/24".parse().unwrap();
+
+        assert!(CompetingVpn::from_route(default, "eth0", None).is_none());
+        assert!(CompetingVpn::from_route(default, "tun0", None).is_some());
+        assert_eq!(
+            CompetingVpn::from_route(half, "eth0", None).map(|vpn| vpn.kind),
+            Some(CompetingVpnKind::Unknown)
+        );
+        assert!(CompetingVpn::from_route(lan, "tun0", None).is_none());
+    }
+}
@@ -0,0 +1,299 @@
+//! Checks that are run on [`TunnelParameters`] before connecting, so that parameters that
+//! cannot work are rejected with a reason instead of making the tunnel fail in some other way.
+
+use super::{openvpn, wireguard, GenericTunnelOptions, TunnelParameters};
+use crate::tunnel::InvalidTunnelParameters;
+use ipnetwork::IpNetwork;
+use std::net::SocketAddr;
+
+/// The minimum MTU of an IPv4 host.
+const MIN_IPV4_MTU: u16 = 576;
+/// The minimum MTU of an IPv6 link.
+const MIN_IPV6_MTU: u16 = 1280;
+/// The largest MTU for which WireGuard packets fit in 1500 bytes, even over IPv6.
+const MAX_MTU: u16 = 1420;
+
+impl TunnelParameters {
+    /// Returns the first problem found with the parameters, if any.
+    pub fn validate(&self) -> Result<(), InvalidTunnelParameters> {
+        match self {
+            TunnelParameters::OpenVpn(params) => validate_openvpn(params),
+            TunnelParameters::Wireguard(params) => validate_wireguard(params),
+        }
+    }
+}
+
+fn validate_openvpn(params: &openvpn::TunnelParameters) -> Result<(), InvalidTunnelParameters> {
+    validate_ip_versions(&params.generic_options)?;
+    if !params.generic_options.enable_ipv4 {
+        return Err(InvalidTunnelParameters::OpenVpnRequiresIpv4);
+    }
+    validate_port(params.config.endpoint.address)?;
+    if let Some(proxy) = &params.proxy {
+        validate_port(proxy.get_endpoint().endpoint.address)?;
+    }
+    Ok(())
+}
+
+fn validate_wireguard(params: &wireguard::TunnelParameters) -> Result<(), InvalidTunnelParameters> {
+    validate_ip_versions(&params.generic_options)?;
+    validate_addresses(&params.connection, &params.generic_options)?;
+    if let Some(mtu) = params.options.mtu {
+        validate_mtu(mtu, &params.generic_options)?;
+    }
+    if let Some(allowed_ips) = &params.options.allowed_ips {
+        validate_allowed_ips(allowed_ips, &params.generic_options)?;
+    }
+
+    let peers = std::iter::once(&params.connection.peer).chain(&params.connection.exit_peer);
+    for peer in peers {
+        validate_port(peer.endpoint)?;
+        // Keys always have the right length, but an all-zero key is not a valid point
+        if peer.public_key.as_bytes().iter().all(|byte| *byte == 0) {
+            return Err(InvalidTunnelParameters::InvalidPeerKey(
+                peer.public_key.clone(),
+            ));
+        }
+        if peer.psk_required && peer.psk.is_none() {
+            return Err(InvalidTunnelParameters::MissingPresharedKey(
+                peer.public_key.clone(),
+            ));
+        }
+    }
+
+    if let Some(obfuscation) = &params.obfuscation {
+        let endpoint = obfuscation.endpoint();
+        validate_port(endpoint)?;
+        let relay = params.connection.peer.endpoint.ip();
+        if endpoint.ip() != relay {
+            return Err(InvalidTunnelParameters::ObfuscatorEndpointMismatch {
+                obfuscator: endpoint,
+                relay,
+            });
+        }
+    }
+    Ok(())
+}
+
+fn validate_ip_versions(
+    generic_options: &GenericTunnelOptions,
+) -> Result<(), InvalidTunnelParameters> {
+    if !generic_options.enable_ipv4 && !generic_options.enable_ipv6 {
+        return Err(InvalidTunnelParameters::NoIpVersionEnabled);
+    }
+    Ok(())
+}
+
+fn validate_addresses(
+    connection: &wireguard::ConnectionConfig,
+    generic_options: &GenericTunnelOptions,
+) -> Result<(), InvalidTunnelParameters> {
+    let addresses = &connection.tunnel.addresses;
+    if !addresses
+        .iter()
+        .any(|ip| ip.is_ipv4() || generic_options.enable_ipv6)
+    {
+        return Err(InvalidTunnelParameters::NoTunnelAddress);
+    }
+    if generic_options.enable_ipv6
+        && connection.ipv6_gateway.is_some()
+        && !addresses.iter().any(|ip| ip.is_ipv6())
+    {
+        return Err(InvalidTunnelParameters::MissingIpv6Address);
+    }
+    Ok(())
+}
+
+fn validate_mtu(
+    mtu: u16,
+    generic_options: &GenericTunnelOptions,
+) -> Result<(), InvalidTunnelParameters> {
+    let min = if generic_options.enable_ipv6 {
+        MIN_IPV6_MTU
+    } else {
+        MIN_IPV4_MTU
+    };
+    if !(min..=MAX_MTU).contains(&mtu) {
+        return Err(InvalidTunnelParameters::MtuOutOfRange {
+            mtu,
+            min,
+            max: MAX_MTU,
+        });
+    }
+    Ok(())
+}
+
+fn validate_allowed_ips(
+    allowed_ips: &[IpNetwork],
+    generic_options: &GenericTunnelOptions,
+) -> Result<(), InvalidTunnelParameters> {
+    let usable = allowed_ips.iter().any(|network| match network {
+        IpNetwork::V4(_) => generic_options.enable_ipv4,
+        IpNetwork::V6(_) => generic_options.enable_ipv6,
+    });
+    if !usable {
+        return Err(InvalidTunnelParameters::NoAllowedIps);
+    }
+    Ok(())
+}
+
+fn validate_port(endpoint: SocketAddr) -> Result<(), InvalidTunnelParameters> {
+    if endpoint.port() == 0 {
+        return Err(InvalidTunnelParameters::MissingPort(endpoint));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::net::{
+        obfuscation::ObfuscatorConfig,
+        wireguard::{
+            ConnectionConfig, PeerConfig, PresharedKey, PrivateKey, PublicKey, TunnelConfig,
+            TunnelOptions,
+        },
+    };
+    use std::net::Ipv4Addr;
+
+    fn wireguard_params() -> wireguard::TunnelParameters {
+        wireguard::TunnelParameters {
+            connection: ConnectionConfig {
+                tunnel: TunnelConfig {
+                    private_key: PrivateKey::new_from_random(),
+                    addresses: vec!["10.64.0.2".parse().unwrap(), "fc00::2".parse().unwrap()],
+                },
+                peer: PeerConfig {
+                    public_key: PrivateKey::new_from_random().public_key(),
+                    allowed_ips: vec!["0.0.0.0/0".parse().unwrap()],
+                    endpoint: "192.0.2.1:51820".parse().unwrap(),
+                    psk: None,
+                    psk_required: false,
+                },
+                exit_peer: None,
+                ipv4_gateway: Ipv4Addr::new(10, 64, 0, 1),
+                ipv6_gateway: Some("fc00::1".parse().unwrap()),
+            },
+            options: TunnelOptions::default(),
+            generic_options: GenericTunnelOptions {
+                enable_ipv6: true,
+                enable_ipv4: true,
+            },
+            obfuscation: None,
+        }
+    }
+
+    #[test]
+    fn test_valid_parameters() {
+        assert_eq!(validate_wireguard(&wireguard_params()), Ok(()));
+    }
+
+    #[test]
+    fn test_address_family() {
+        let mut params = wireguard_params();
+        params.connection.tunnel.addresses = vec!["10.64.0.2".parse().unwrap()];
+        assert_eq!(
+            validate_wireguard(&params),
+            Err(InvalidTunnelParameters::MissingIpv6Address)
+        );
+
+        params.connection.tunnel.addresses = vec!["fc00::2".parse().unwrap()];
+        params.generic_options.enable_ipv6 = false;
+        assert_eq!(
+            validate_wireguard(&params),
+            Err(InvalidTunnelParameters::NoTunnelAddress)
+        );
+
+        params.generic_options.enable_ipv4 = false;
+        assert_eq!(
+            validate_wireguard(&params),
+            Err(InvalidTunnelParameters::NoIpVersionEnabled)
+        );
+    }
+
+    #[test]
+    fn test_mtu_bounds() {
+        let mut params = wireguard_params();
+        params.options.mtu = Some(1000);
+        assert_eq!(
+            validate_wireguard(&params),
+            Err(InvalidTunnelParameters::MtuOutOfRange {
+                mtu: 1000,
+                min: MIN_IPV6_MTU,
+                max: MAX_MTU,
+            })
+        );
+
+        params.generic_options.enable_ipv6 = false;
+        assert_eq!(validate_wireguard(&params), Ok(()));
+    }
+
+    #[test]
+    fn test_allowed_ips() {
+        let mut params = wireguard_params();
+        params.options.allowed_ips = Some(vec!["fc00:bbbb::/32".parse().unwrap()]);
+        assert_eq!(validate_wireguard(&params), Ok(()));
+
+        params.generic_options.enable_ipv6 = false;
+        assert_eq!(
+            validate_wireguard(&params),
+            Err(InvalidTunnelParameters::NoAllowedIps)
+        );
+
+        params.options.allowed_ips = Some(vec![]);
+        assert_eq!(
+            validate_wireguard(&params),
+            Err(InvalidTunnelParameters::NoAllowedIps)
+        );
+    }
+
+    #[test]
+    fn test_obfuscator_endpoint() {
+        let mut params = wireguard_params();
+        let obfuscator: SocketAddr = "192.0.2.2:443".parse().unwrap();
+        params.obfuscation = Some(ObfuscatorConfig::Udp2Tcp {
+            endpoint: obfuscator,
+        });
+        assert_eq!(
+            validate_wireguard(&params),
+            Err(InvalidTunnelParameters::ObfuscatorEndpointMismatch {
+                obfuscator,
+                relay: params.connection.peer.endpoint.ip(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalid_peer() {
+        let mut params = wireguard_params();
+        params.connection.peer.endpoint.set_port(0);
+        assert_eq!(
+            validate_wireguard(&params),
+            Err(InvalidTunnelParameters::MissingPort(
+                params.connection.peer.endpoint
+            ))
+        );
+
+        let mut params = wireguard_params();
+        params.connection.peer.public_key = PublicKey::from([0; 32]);
+        assert!(matches!(
+            validate_wireguard(&params),
+            Err(InvalidTunnelParameters::InvalidPeerKey(_))
+        ));
+    }
+
+    #[test]
+    fn test_missing_psk() {
+        let mut params = wireguard_params();
+        params.connection.peer.psk_required = true;
+        assert_eq!(
+            validate_wireguard(&params),
+            Err(InvalidTunnelParameters::MissingPresharedKey(
+                params.connection.peer.public_key.clone()
+            ))
+        );
+
+        params.connection.peer.psk = Some(PresharedKey::from(Box::new([1; 32])));
+        assert_eq!(validate_wireguard(&params), Ok(()));
+    }
+}
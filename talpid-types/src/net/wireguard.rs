@@ -57,10 +57,14 @@ pub struct PeerConfig {
     /// IP address of the WireGuard server.
     pub endpoint: SocketAddr,
     /// Preshared key (PSK). The PSK should never be persisted, so it does not serialize
-    /// or deserialize. A PSK is only used with quantum-resistant tunnels and are then
-    /// ephemeral and living in memory only.
+    /// or deserialize. It is either negotiated for quantum-resistant tunnels, in which case it is
+    /// ephemeral, or supplied along with a custom peer. Either way, it lives in memory only.
     #[serde(skip)]
     pub psk: Option<PresharedKey>,
+    /// Whether a PSK was supplied along with a custom peer. This is persisted in place of the PSK,
+    /// so that the peer is not connected to without it once the PSK has been lost.
+    #[serde(default)]
+    pub psk_required: bool,
 }
 
 #[derive(Clone, Eq, PartialEq, Deserialize, Serialize, Debug)]
@@ -87,6 +91,16 @@ pub struct TunnelOptions {
     pub mtu: Option<u16>,
     /// Obtain a PSK using the relay config client.
     pub use_pq_safe_psk: bool,
+    /// Padding and dummy traffic used to make traffic analysis harder.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub traffic_shaping: TrafficShapingOptions,
+    /// Maximum throughput of the tunnel.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub bandwidth_limit: BandwidthLimit,
+    /// If set, only traffic to these networks is sent through the tunnel. All other traffic
+    /// bypasses it.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub allowed_ips: Option<Vec<IpNetwork>>,
     /// Temporary switch for wireguard-nt
     #[cfg(windows)]
     #[serde(default = "default_wgnt_setting")]
@@ -105,12 +119,110 @@ impl Default for TunnelOptions {
         Self {
             mtu: None,
             use_pq_safe_psk: false,
+            traffic_shaping: TrafficShapingOptions::default(),
+            bandwidth_limit: BandwidthLimit::default(),
+            allowed_ips: None,
             #[cfg(windows)]
             use_wireguard_nt: default_wgnt_setting(),
         }
     }
 }
 
+/// Traffic shaping to apply to a WireGuard connection.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrafficShapingOptions {
+    /// Pad packets so that their sizes reveal less about the traffic.
+    pub padding: bool,
+    /// Inject dummy packets to hide when traffic is sent.
+    pub dummy_traffic: bool,
+    /// What to do if traffic shaping cannot be set up for the tunnel.
+    pub on_failure: TrafficShapingFailurePolicy,
+}
+
+impl TrafficShapingOptions {
+    /// Returns whether any kind of traffic shaping is requested.
+    pub fn is_enabled(&self) -> bool {
+        self.padding || self.dummy_traffic
+    }
+}
+
+/// Contains the number of packets affected by traffic shaping in a tunnel
+#[derive(Default, Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct TrafficShapingStats {
+    /// Number of outgoing packets that were padded
+    pub padded_packets: u64,
+    /// Number of dummy packets that were sent
+    pub injected_packets: u64,
+}
+
+/// Determines what happens when traffic shaping is enabled but cannot be set up.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrafficShapingFailurePolicy {
+    /// Connect without traffic shaping.
+    #[default]
+    Fallback,
+    /// Refuse to connect and block all traffic.
+    Block,
+}
+
+/// Maximum throughput of a WireGuard tunnel in each direction, in kilobits per second. `None`
+/// means unlimited.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BandwidthLimit {
+    /// Limit for traffic sent through the tunnel.
+    pub upload_kbps: Option<u32>,
+    /// Limit for traffic received through the tunnel.
+    pub download_kbps: Option<u32>,
+}
+
+impl BandwidthLimit {
+    /// Returns whether any direction is limited.
+    pub fn is_enabled(&self) -> bool {
+        self.upload_kbps.is_some() || self.download_kbps.is_some()
+    }
+}
+
+impl fmt::Display for BandwidthLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let format_limit = |limit: Option<u32>| match limit {
+            Some(kbps) => format!("{} kbit/s", kbps),
+            None => "unlimited".to_owned(),
+        };
+        write!(
+            f,
+            "upload {}, download {}",
+            format_limit(self.upload_kbps),
+            format_limit(self.download_kbps)
+        )
+    }
+}
+
+/// Implementation of WireGuard that a tunnel is running on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WireguardBackend {
+    /// The WireGuard module of the Linux kernel.
+    Kernel,
+    /// The WireGuardNT driver on Windows.
+    WireguardNt,
+    /// The userspace implementation wireguard-go.
+    WireguardGo,
+}
+
+impl fmt::Display for WireguardBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let backend = match self {
+            WireguardBackend::Kernel => "kernel",
+            WireguardBackend::WireguardNt => "WireGuardNT",
+            WireguardBackend::WireguardGo => "wireguard-go",
+        };
+        f.write_str(backend)
+    }
+}
+
 /// Wireguard x25519 private key
 #[derive(Clone)]
 pub struct PrivateKey(x25519_dalek::StaticSecret);
@@ -264,10 +376,18 @@ impl fmt::Display for PublicKey {
     }
 }
 
-/// A WireGuard preshared key (PSK). Used to make the tunnel quantum-resistant.
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Zeroize, ZeroizeOnDrop)]
+/// A WireGuard preshared key (PSK). Used to make the tunnel quantum-resistant, or to add a layer
+/// of symmetric encryption when connecting to custom peers.
+#[derive(Clone, PartialEq, Eq, Hash, Zeroize, ZeroizeOnDrop)]
 pub struct PresharedKey(Box<[u8; 32]>);
 
+/// The key is never printed, since the debug output of tunnel parameters may end up in logs.
+impl fmt::Debug for PresharedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PresharedKey(<redacted>)")
+    }
+}
+
 impl PresharedKey {
     /// Get the PSK as bytes. Try to move or dereference this data as little as possible,
     /// since copying it to more memory locations potentially leaves the secret in more memory
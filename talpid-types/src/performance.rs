@@ -0,0 +1,44 @@
+//! Types for reporting operations on the system configuration that were slow to complete.
+
+use std::{fmt, time::Duration};
+
+/// Operation on the system configuration whose duration is monitored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+    ApplyFirewallPolicy,
+    ResetFirewallPolicy,
+    SetDns,
+    ResetDns,
+    ClearRoutes,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::ApplyFirewallPolicy => "applying the firewall policy".fmt(f),
+            Operation::ResetFirewallPolicy => "resetting the firewall policy".fmt(f),
+            Operation::SetDns => "setting DNS".fmt(f),
+            Operation::ResetDns => "resetting DNS".fmt(f),
+            Operation::ClearRoutes => "clearing routes".fmt(f),
+        }
+    }
+}
+
+/// An operation took longer than expected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PerformanceWarning {
+    pub operation: Operation,
+    /// How long the operation took.
+    pub duration: Duration,
+}
+
+impl fmt::Display for PerformanceWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} took {} ms",
+            self.operation,
+            self.duration.as_millis()
+        )
+    }
+}
@@ -0,0 +1,148 @@
+//! Machine-readable description of the changes that the daemon has made to the firewall, the
+//! routing table and the DNS configuration, for monitoring agents and configuration drift tools.
+//!
+//! The serialized form is versioned. Fields may be added without changing [`VERSION`], so
+//! consumers should ignore fields they do not know. Removing or changing the meaning of a field
+//! requires a new version.
+
+use crate::net::{
+    wireguard::TrafficShapingStats, BlockedTunnelProtocols, Endpoint, EndpointRange,
+    TransportProtocol, VpnCoexistence,
+};
+use ipnetwork::IpNetwork;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+/// Version of the document format.
+pub const VERSION: u32 = 1;
+
+/// Firewall policy, routes and DNS settings that are currently owned by the daemon.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SystemState {
+    /// Version of the document format. Always [`VERSION`] when created by this version.
+    pub version: u32,
+    /// Firewall policy that is currently applied, if any.
+    pub firewall: Option<FirewallState>,
+    /// Routes that have been added to the routing table.
+    pub routes: Vec<RouteState>,
+    /// DNS servers that have been set, if any.
+    pub dns: Option<DnsState>,
+    /// Number of packets that have been padded or injected by traffic shaping in the connected
+    /// tunnel, if it is in use.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub traffic_shaping: Option<TrafficShapingStats>,
+    /// Number of DNS queries that the filtering resolver has answered, if it is used on this
+    /// platform.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filtering_resolver: Option<ResolverStats>,
+}
+
+impl SystemState {
+    pub fn new(
+        firewall: Option<FirewallState>,
+        routes: Vec<RouteState>,
+        dns: Option<DnsState>,
+    ) -> Self {
+        Self {
+            version: VERSION,
+            firewall,
+            routes,
+            dns,
+            traffic_shaping: None,
+            filtering_resolver: None,
+        }
+    }
+}
+
+/// Abstract form of the rules that the firewall enforces. The actual rules depend on the platform.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "policy")]
+pub enum FirewallState {
+    /// Only traffic to the relay, and to the allowed endpoints, is allowed.
+    Connecting {
+        /// Endpoint of the relay.
+        peer_endpoint: Endpoint,
+        /// Name of the tunnel interface, if it has been created.
+        tunnel_interface: Option<String>,
+        /// Whether traffic to and from local networks is allowed.
+        allow_lan: bool,
+        /// Endpoints that are reachable outside the tunnel, such as the API.
+        allowed_endpoints: Vec<EndpointRange>,
+        /// Traffic that is allowed inside the tunnel before it is considered connected.
+        allowed_tunnel_traffic: AllowedTunnelTrafficState,
+        /// Traffic of another VPN that is exempt from blocking.
+        vpn_coexistence: VpnCoexistence,
+    },
+    /// Only traffic to the relay and through the tunnel interface is allowed.
+    Connected {
+        /// Endpoint of the relay.
+        peer_endpoint: Endpoint,
+        /// Name of the tunnel interface.
+        tunnel_interface: String,
+        /// Whether traffic to and from local networks is allowed.
+        allow_lan: bool,
+        /// DNS servers that are allowed to be used. Empty if not enforced on this platform.
+        dns_servers: Vec<IpAddr>,
+        /// Traffic of another VPN that is exempt from blocking.
+        vpn_coexistence: VpnCoexistence,
+        /// Protocols that are blocked inside the tunnel.
+        blocked_tunnel_protocols: BlockedTunnelProtocols,
+    },
+    /// All traffic is blocked, except to the allowed endpoints and networks.
+    Blocked {
+        /// Whether traffic to and from local networks is allowed.
+        allow_lan: bool,
+        /// Endpoints that are reachable, such as the API.
+        allowed_endpoints: Vec<EndpointRange>,
+        /// Networks that are reachable for recovering remote access.
+        recovery_allowlist: Vec<IpNetwork>,
+        /// Traffic of another VPN that is exempt from blocking.
+        vpn_coexistence: VpnCoexistence,
+    },
+}
+
+/// Traffic that is allowed inside the tunnel while connecting.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AllowedTunnelTrafficState {
+    None,
+    All,
+    Only(Endpoint),
+    Dns(Vec<IpAddr>),
+    Endpoints {
+        addresses: Vec<IpAddr>,
+        port: u16,
+        protocol: TransportProtocol,
+    },
+}
+
+/// A route that has been added by the daemon.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RouteState {
+    /// Destination network of the route.
+    pub destination: IpNetwork,
+    /// Gateway of the route, if it is routed through a specific address.
+    pub gateway: Option<IpAddr>,
+    /// Interface of the route, if it is routed through a specific interface.
+    pub interface: Option<String>,
+    /// Whether the route follows the current default route rather than a fixed node.
+    pub follows_default_route: bool,
+}
+
+/// Number of queries that the filtering resolver has answered since it was started.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResolverStats {
+    /// Queries that were answered or forwarded.
+    pub served: u64,
+    /// Queries that were refused.
+    pub blocked: u64,
+}
+
+/// DNS servers that have been set by the daemon.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DnsState {
+    /// Interface that the servers were set for.
+    pub interface: String,
+    /// The DNS servers.
+    pub servers: Vec<IpAddr>,
+}
@@ -0,0 +1,110 @@
+//! Types for asking whether traffic to a destination would go through the tunnel, be blocked, or
+//! bypass the tunnel, given the firewall policy, routes and split tunneling rules in effect.
+
+use crate::{net::TransportProtocol, system_state::RouteState};
+use serde::{Deserialize, Serialize};
+use std::{fmt, net::IpAddr};
+
+/// Traffic to evaluate.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrafficQuery {
+    /// Destination address of the traffic.
+    pub destination: IpAddr,
+    /// Destination port of the traffic.
+    pub port: u16,
+    /// Transport protocol of the traffic.
+    pub protocol: TransportProtocol,
+    /// Application that sends the traffic, if it matters. This is the path of the executable on
+    /// Windows and the process ID on Linux.
+    pub app: Option<String>,
+}
+
+/// What would happen to the traffic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrafficOutcome {
+    /// The traffic would be sent through the tunnel.
+    Tunneled,
+    /// The traffic would be blocked.
+    Blocked,
+    /// The traffic would be sent outside the tunnel.
+    Bypassed,
+}
+
+impl fmt::Display for TrafficOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrafficOutcome::Tunneled => "tunneled".fmt(f),
+            TrafficOutcome::Blocked => "blocked".fmt(f),
+            TrafficOutcome::Bypassed => "bypassed".fmt(f),
+        }
+    }
+}
+
+/// The rule that decided the outcome.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrafficReason {
+    /// No firewall policy is applied, so the traffic follows the routing table of the system.
+    NoFirewallPolicy,
+    /// The destination is the relay that the tunnel is connected to.
+    RelayEndpoint,
+    /// The destination is allowed outside the tunnel, such as the API.
+    AllowedEndpoint,
+    /// The destination is on a local network and local network sharing is enabled.
+    LocalNetwork,
+    /// The destination is in the recovery allowlist.
+    RecoveryAllowlist,
+    /// The destination is reachable through another VPN that is exempt from blocking.
+    VpnCoexistence,
+    /// The application is excluded from the tunnel.
+    ExcludedApp,
+    /// The traffic is allowed inside the tunnel while connecting.
+    AllowedTunnelTraffic,
+    /// The protocol of the traffic is blocked inside the tunnel.
+    BlockedTunnelProtocol,
+    /// The destination is a DNS server other than the ones that are in use.
+    DnsServerNotAllowed,
+    /// The traffic is routed through the tunnel.
+    TunnelRoute,
+    /// The traffic is not routed through the tunnel, and the firewall blocks it elsewhere.
+    NoTunnelRoute,
+    /// The firewall only lets through traffic that is explicitly allowed.
+    NotAllowed,
+}
+
+impl fmt::Display for TrafficReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            TrafficReason::NoFirewallPolicy => "no firewall policy is applied",
+            TrafficReason::RelayEndpoint => "the destination is the relay",
+            TrafficReason::AllowedEndpoint => "the destination is allowed outside the tunnel",
+            TrafficReason::LocalNetwork => "the destination is on a local network",
+            TrafficReason::RecoveryAllowlist => "the destination is in the recovery allowlist",
+            TrafficReason::VpnCoexistence => "the destination is reachable through another VPN",
+            TrafficReason::ExcludedApp => "the app is excluded from the tunnel",
+            TrafficReason::AllowedTunnelTraffic => "the traffic is allowed while connecting",
+            TrafficReason::BlockedTunnelProtocol => "the protocol is blocked inside the tunnel",
+            TrafficReason::DnsServerNotAllowed => "the destination is not an allowed DNS server",
+            TrafficReason::TunnelRoute => "the destination is routed through the tunnel",
+            TrafficReason::NoTunnelRoute => "the destination is not routed through the tunnel",
+            TrafficReason::NotAllowed => "the firewall only allows specific traffic",
+        };
+        reason.fmt(f)
+    }
+}
+
+/// Answer to a [`TrafficQuery`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrafficVerdict {
+    pub outcome: TrafficOutcome,
+    pub reason: TrafficReason,
+    /// The most specific route added by the daemon that matches the destination, if any.
+    pub route: Option<RouteState>,
+}
+
+impl fmt::Display for TrafficVerdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.outcome, self.reason)
+    }
+}
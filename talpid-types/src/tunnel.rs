@@ -1,17 +1,37 @@
-use crate::net::TunnelEndpoint;
+use crate::net::{
+    wireguard::{PublicKey, WireguardBackend},
+    TunnelEndpoint,
+};
+use ipnetwork::IpNetwork;
 #[cfg(target_os = "android")]
 use jnix::IntoJava;
 use serde::{Deserialize, Serialize};
-use std::fmt;
-#[cfg(target_os = "android")]
-use std::net::IpAddr;
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str::FromStr,
+};
+
+/// Version of the serialized form of [`TunnelStateTransition`], [`ErrorStateCause`] and
+/// [`TunnelMetadata`], for tools that parse it. It must be bumped whenever the serialized form of
+/// any of these types, or of the types that they contain, changes. The tests in this module
+/// compare the serialized form with a golden file for the current version.
+pub const TUNNEL_STATE_SCHEMA_VERSION: u32 = 1;
 
 /// Event emitted from the states in `talpid_core::tunnel_state_machine` when the tunnel state
 /// machine enters a new state.
-#[derive(Clone, Debug)]
+///
+/// The serialized form is versioned by [`TUNNEL_STATE_SCHEMA_VERSION`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[serde(tag = "state", content = "details")]
 pub enum TunnelStateTransition {
-    /// No connection is established and network is unsecured.
-    Disconnected,
+    /// No connection is established. Contains the reason for disconnecting, or `None` if the
+    /// state machine just started, and whether traffic is blocked.
+    Disconnected {
+        cause: Option<DisconnectCause>,
+        security: DisconnectedSecurity,
+    },
     /// Network is secured but tunnel is still connecting.
     Connecting(TunnelEndpoint),
     /// Tunnel is connected.
@@ -20,6 +40,75 @@ pub enum TunnelStateTransition {
     Disconnecting(ActionAfterDisconnect),
     /// Tunnel is disconnected but usually secured by blocking all connections.
     Error(ErrorState),
+    /// Tunnel has been closed on request, but connections outside of it are still blocked. It can
+    /// be reopened using the same endpoint.
+    Paused(TunnelEndpoint),
+}
+
+/// A [`TunnelStateTransition`] along with the version of the schema that it is serialized with.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct VersionedTunnelStateTransition {
+    /// Always [`TUNNEL_STATE_SCHEMA_VERSION`] when created by this version of talpid.
+    pub schema_version: u32,
+    pub transition: TunnelStateTransition,
+    /// Information about the tunnel interface, if a tunnel is up.
+    pub metadata: Option<TunnelMetadata>,
+}
+
+impl VersionedTunnelStateTransition {
+    pub fn new(transition: TunnelStateTransition, metadata: Option<TunnelMetadata>) -> Self {
+        Self {
+            schema_version: TUNNEL_STATE_SCHEMA_VERSION,
+            transition,
+            metadata,
+        }
+    }
+}
+
+/// Information about a VPN tunnel.
+///
+/// The serialized form is versioned by [`TUNNEL_STATE_SCHEMA_VERSION`].
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct TunnelMetadata {
+    /// The name of the device which the tunnel is running on.
+    pub interface: String,
+    /// The local IPs on the tunnel interface.
+    pub ips: Vec<IpAddr>,
+    /// The IP to the default gateway on the tunnel interface.
+    pub ipv4_gateway: Ipv4Addr,
+    /// The IP to the IPv6 default gateway on the tunnel interface.
+    pub ipv6_gateway: Option<Ipv6Addr>,
+    /// The WireGuard implementation used, if this is a WireGuard tunnel on a built-in backend.
+    pub wireguard_backend: Option<WireguardBackend>,
+}
+
+/// Identifies a single attempt to connect, from entering the connecting state until the next
+/// attempt or disconnect. It is included in the logs of the subsystems involved in connecting, so
+/// that their log lines can be correlated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ConnectionAttemptId(u32);
+
+impl ConnectionAttemptId {
+    /// Returns a random ID.
+    pub fn random() -> Self {
+        ConnectionAttemptId(rand::random())
+    }
+
+    /// Returns the ID as a number.
+    pub fn as_u32(&self) -> u32 {
+        self.0
+    }
+
+    /// Returns the ID with the given numeric value.
+    pub fn from_u32(id: u32) -> Self {
+        ConnectionAttemptId(id)
+    }
+}
+
+impl fmt::Display for ConnectionAttemptId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:08x}", self.0)
+    }
 }
 
 /// Action that will be taken after disconnection is complete.
@@ -33,6 +122,56 @@ pub enum ActionAfterDisconnect {
     Reconnect,
 }
 
+/// Reason why the tunnel state machine entered the disconnected state.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[serde(tag = "cause", content = "details")]
+pub enum DisconnectCause {
+    /// The user asked to disconnect.
+    UserInitiated,
+    /// The user disconnected while the tunnel was being reconnected, cancelling the reconnect.
+    Reconnecting,
+    /// The user disconnected while in the error state. Contains the error that was resolved by
+    /// disconnecting.
+    Error(ErrorStateCause),
+    /// The device was revoked, so the account had to be logged out.
+    Revoked,
+    /// The tunnel state machine is shutting down.
+    Shutdown,
+}
+
+/// Whether traffic is blocked while in the disconnected state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[serde(tag = "security", content = "reason")]
+pub enum DisconnectedSecurity {
+    /// Traffic is not blocked.
+    Unsecured,
+    /// All traffic is blocked, except to allowed endpoints and, if enabled, the local network.
+    Blocked(DisconnectedBlockReason),
+}
+
+impl DisconnectedSecurity {
+    /// Returns true if traffic is blocked.
+    pub fn is_blocked(&self) -> bool {
+        matches!(self, DisconnectedSecurity::Blocked(_))
+    }
+}
+
+/// Reason why traffic is blocked in the disconnected state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisconnectedBlockReason {
+    /// The "block when disconnected" setting is enabled.
+    BlockWhenDisconnected,
+    /// The blocking rules that were in place when the state machine started are kept, because it
+    /// is expected to connect.
+    PendingConnect,
+    /// The rules of the tunnel that was just closed are kept briefly, in case it is reconnected
+    /// right away.
+    ReconnectGracePeriod,
+}
+
 /// Represents the tunnel state machine entering an error state during a [`TunnelStateTransition`].
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -51,13 +190,23 @@ pub struct ErrorState {
         jnix(map = "|block_failure| block_failure.is_none()")
     )]
     block_failure: Option<FirewallPolicyError>,
+    /// Networks that are exempt from blocking while in the error state. These weaken the
+    /// protection offered by the error state, so they are reported here to make that visible.
+    #[serde(default)]
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    recovery_allowlist: Vec<IpNetwork>,
 }
 
 impl ErrorState {
-    pub fn new(cause: ErrorStateCause, block_failure: Option<FirewallPolicyError>) -> Self {
+    pub fn new(
+        cause: ErrorStateCause,
+        block_failure: Option<FirewallPolicyError>,
+        recovery_allowlist: Vec<IpNetwork>,
+    ) -> Self {
         Self {
             cause,
             block_failure,
+            recovery_allowlist,
         }
     }
 
@@ -72,9 +221,15 @@ impl ErrorState {
     pub fn block_failure(&self) -> Option<&FirewallPolicyError> {
         self.block_failure.as_ref()
     }
+
+    pub fn recovery_allowlist(&self) -> &[IpNetwork] {
+        &self.recovery_allowlist
+    }
 }
 
 /// Reason for the tunnel state machine entering an [`ErrorState`].
+///
+/// The serialized form is versioned by [`TUNNEL_STATE_SCHEMA_VERSION`].
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 #[serde(tag = "reason", content = "details")]
@@ -83,6 +238,8 @@ impl ErrorState {
 pub enum ErrorStateCause {
     /// Authentication with remote server failed.
     AuthFailed(Option<String>),
+    /// The account is out of time. Only the API is reachable, so that time can be added.
+    AccountExpired,
     /// Failed to configure IPv6 because it's disabled in the platform.
     Ipv6Unavailable,
     /// Failed to set firewall policy.
@@ -104,9 +261,35 @@ pub enum ErrorStateCause {
     /// Error reported by split tunnel module.
     #[cfg(target_os = "windows")]
     SplitTunnelError,
+    /// The tunnel monitor repeatedly stopped without reporting why.
+    TunnelMonitorStopped,
+    /// The physical interface that the tunnel is forced to use is unavailable.
+    #[cfg(not(target_os = "android"))]
+    ForcedInterfaceUnavailable,
+    /// The gateway that traffic to the relay is routed through does not respond to ARP or NDP.
+    #[cfg(target_os = "linux")]
+    GatewayUnreachable,
+    /// The limit on consecutive reconnect attempts for some cause was reached.
+    TooManyAttempts,
+    /// Another VPN took over the default route, and the tunnel yielded to it.
+    #[cfg(not(target_os = "android"))]
+    RouteTakenOver,
+    /// The tunnel process could not be started, or kept failing.
+    #[cfg(not(target_os = "android"))]
+    TunnelProcessFailed(ProcessFailure),
+    /// The tunnel device, or every name that the tunnel interface may use, is taken by another
+    /// program.
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    TunnelDeviceBusy,
 }
 
 impl ErrorStateCause {
+    /// Returns whether all traffic except to the API must be blocked, regardless of the settings
+    /// that otherwise let traffic through in the error state, such as "allow LAN".
+    pub fn allows_only_api(&self) -> bool {
+        matches!(self, Self::AccountExpired)
+    }
+
     #[cfg(target_os = "macos")]
     pub fn prevents_filtering_resolver(&self) -> bool {
         match self {
@@ -116,6 +299,115 @@ impl ErrorStateCause {
     }
 }
 
+/// How an external tunnel process, such as OpenVPN, failed.
+#[cfg(not(target_os = "android"))]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessFailure {
+    /// The process could not be started.
+    Spawn,
+    /// The process exited with a non-zero exit code.
+    ExitCode(i32),
+    /// The process was terminated by a signal or an unhandled exception.
+    Crash,
+}
+
+#[cfg(not(target_os = "android"))]
+impl fmt::Display for ProcessFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProcessFailure::Spawn => f.write_str("failed to start"),
+            ProcessFailure::ExitCode(code) => write!(f, "exited with code {}", code),
+            ProcessFailure::Crash => f.write_str("crashed"),
+        }
+    }
+}
+
+/// Maximum number of consecutive attempts to reconnect for each cause of a failed connection
+/// attempt, after which the error state is entered with [`ErrorStateCause::TooManyAttempts`].
+/// `None` means that there is no limit.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ReconnectLimits {
+    /// Attempts after the tunnel failed to start.
+    pub start_failures: Option<u32>,
+    /// Attempts after the tunnel was started but was not established in time.
+    pub timeouts: Option<u32>,
+    /// Attempts after the bridge stopped working.
+    pub bridge_failures: Option<u32>,
+}
+
+impl ReconnectLimits {
+    /// Returns whether reconnect attempts are unlimited for all causes.
+    pub fn is_unlimited(&self) -> bool {
+        self.start_failures.is_none() && self.timeouts.is_none() && self.bridge_failures.is_none()
+    }
+}
+
+impl fmt::Display for ReconnectLimits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let limit = |limit: Option<u32>| match limit {
+            Some(limit) => limit.to_string(),
+            None => "unlimited".to_owned(),
+        };
+        write!(
+            f,
+            "start failures: {}, timeouts: {}, bridge failures: {}",
+            limit(self.start_failures),
+            limit(self.timeouts),
+            limit(self.bridge_failures)
+        )
+    }
+}
+
+/// Which traffic is blocked in the error state. Traffic to the API and to the recovery allowlist
+/// is allowed regardless of the policy.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorStatePolicy {
+    /// Allow traffic to and from the LAN if LAN access is allowed, like in the other states.
+    #[default]
+    AllowLan,
+    /// Block traffic to and from the LAN as well, even if LAN access is allowed.
+    BlockAll,
+}
+
+impl ErrorStatePolicy {
+    /// Returns whether LAN traffic is allowed in the error state, given the LAN access setting.
+    pub fn allows_lan(&self, allow_lan: bool) -> bool {
+        match self {
+            ErrorStatePolicy::AllowLan => allow_lan,
+            ErrorStatePolicy::BlockAll => false,
+        }
+    }
+}
+
+impl fmt::Display for ErrorStatePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorStatePolicy::AllowLan => "allow-lan".fmt(f),
+            ErrorStatePolicy::BlockAll => "block-all".fmt(f),
+        }
+    }
+}
+
+impl FromStr for ErrorStatePolicy {
+    type Err = ParseErrorStatePolicyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow-lan" => Ok(ErrorStatePolicy::AllowLan),
+            "block-all" => Ok(ErrorStatePolicy::BlockAll),
+            _ => Err(ParseErrorStatePolicyError),
+        }
+    }
+}
+
+/// Returned when a string is not a valid [`ErrorStatePolicy`].
+#[derive(err_derive::Error, Debug, Clone, PartialEq, Eq)]
+#[error(display = "Expected \"allow-lan\" or \"block-all\"")]
+pub struct ParseErrorStatePolicyError;
+
 /// Errors that can occur when generating tunnel parameters.
 #[derive(err_derive::Error, Debug, Serialize, Clone, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -134,6 +426,63 @@ pub enum ParameterGenerationError {
     /// Failure to resolve the hostname of a custom tunnel configuration
     #[error(display = "Can't resolve hostname for custom tunnel host")]
     CustomTunnelHostResultionError,
+    /// The generated parameters were rejected before trying to connect with them.
+    #[error(display = "Invalid tunnel parameters: {}", _0)]
+    InvalidTunnelParameters(
+        #[cfg_attr(target_os = "android", jnix(map = "|error| error.to_string()"))]
+        InvalidTunnelParameters,
+    ),
+}
+
+/// Reasons for rejecting tunnel parameters, which are checked before connecting so that
+/// misconfigurations are reported as such rather than as a tunnel that fails to start.
+#[derive(err_derive::Error, Debug, Serialize, Clone, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum InvalidTunnelParameters {
+    /// None of the tunnel addresses can be used, e.g. because they are all IPv6 addresses and
+    /// IPv6 is disabled.
+    #[error(display = "The tunnel has no usable addresses")]
+    NoTunnelAddress,
+    /// Both IPv4 and IPv6 are disabled, so no traffic could go through the tunnel.
+    #[error(display = "Both IPv4 and IPv6 are disabled")]
+    NoIpVersionEnabled,
+    /// IPv4 is disabled, which OpenVPN tunnels do not support.
+    #[error(display = "OpenVPN tunnels cannot be used with IPv4 disabled")]
+    OpenVpnRequiresIpv4,
+    /// IPv6 is enabled and the tunnel has an IPv6 gateway, but no IPv6 address.
+    #[error(display = "The tunnel has an IPv6 gateway but no IPv6 address")]
+    MissingIpv6Address,
+    /// The MTU is outside of the range that works for the tunnel.
+    #[error(
+        display = "The MTU {} is outside of the allowed range {}-{}",
+        mtu,
+        min,
+        max
+    )]
+    MtuOutOfRange { mtu: u16, min: u16, max: u16 },
+    /// An endpoint has port 0.
+    #[error(display = "The endpoint {} has no port", _0)]
+    MissingPort(SocketAddr),
+    /// The obfuscator does not run on the relay that it is used to reach.
+    #[error(
+        display = "The obfuscator endpoint {} is not on the relay {}",
+        obfuscator,
+        relay
+    )]
+    ObfuscatorEndpointMismatch {
+        obfuscator: SocketAddr,
+        relay: IpAddr,
+    },
+    /// A WireGuard peer has a public key that no connection can be made with.
+    #[error(display = "The public key {} of a peer is invalid", _0)]
+    InvalidPeerKey(PublicKey),
+    /// A custom WireGuard peer requires a preshared key that is no longer available, e.g. because
+    /// the daemon was restarted.
+    #[error(display = "The preshared key of the peer {} must be set again", _0)]
+    MissingPresharedKey(PublicKey),
+    /// None of the networks to route through the tunnel belong to an enabled IP version.
+    #[error(display = "None of the networks to route through the tunnel can be used")]
+    NoAllowedIps,
 }
 
 /// Application that prevents setting the firewall policy.
@@ -174,6 +523,7 @@ impl fmt::Display for ErrorStateCause {
                     }
                 );
             }
+            AccountExpired => "The account is out of time",
             Ipv6Unavailable => "Failed to configure IPv6 because it's disabled in the platform",
             SetFirewallPolicyError(ref err) => {
                 return match err {
@@ -206,8 +556,325 @@ impl fmt::Display for ErrorStateCause {
             VpnPermissionDenied => "The Android VPN permission was denied when creating the tunnel",
             #[cfg(target_os = "windows")]
             SplitTunnelError => "The split tunneling module reported an error",
+            TunnelMonitorStopped => "The tunnel monitor stopped unexpectedly",
+            #[cfg(not(target_os = "android"))]
+            ForcedInterfaceUnavailable => "The forced tunnel interface is unavailable",
+            #[cfg(target_os = "linux")]
+            GatewayUnreachable => "The gateway of the network is unreachable",
+            TooManyAttempts => "Gave up reconnecting after too many failed attempts",
+            #[cfg(not(target_os = "android"))]
+            RouteTakenOver => "Another VPN took over the default route",
+            #[cfg(not(target_os = "android"))]
+            TunnelProcessFailed(failure) => {
+                return write!(f, "The tunnel process {}", failure);
+            }
+            #[cfg(any(target_os = "linux", target_os = "macos"))]
+            TunnelDeviceBusy => "The tunnel device is in use by another program",
         };
 
         write!(f, "{}", description)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::net::{
+        proxy::{ProxyEndpoint, ProxyType},
+        Endpoint, ObfuscationEndpoint, ObfuscationType, TransportProtocol, TunnelType,
+    };
+
+    /// Returns the golden file for a version of the schema. Entries in golden files must never be
+    /// changed, only added for types that were not sampled before.
+    /// If the serialized form changes, bump [`TUNNEL_STATE_SCHEMA_VERSION`] and add a new file.
+    fn golden_file(version: u32) -> Option<&'static str> {
+        match version {
+            1 => Some(include_str!("../schema/tunnel-state-v1.json")),
+            _ => None,
+        }
+    }
+
+    fn connecting_endpoint() -> TunnelEndpoint {
+        TunnelEndpoint {
+            endpoint: Endpoint::new(
+                "185.65.134.2".parse::<IpAddr>().unwrap(),
+                1194,
+                TransportProtocol::Udp,
+            ),
+            tunnel_type: TunnelType::OpenVpn,
+            quantum_resistant: false,
+            proxy: Some(ProxyEndpoint {
+                endpoint: Endpoint::new(
+                    "185.65.134.3".parse::<IpAddr>().unwrap(),
+                    443,
+                    TransportProtocol::Tcp,
+                ),
+                proxy_type: ProxyType::Shadowsocks,
+            }),
+            obfuscation: None,
+            entry_endpoint: None,
+            wireguard_backend: None,
+        }
+    }
+
+    fn connected_endpoint() -> TunnelEndpoint {
+        TunnelEndpoint {
+            endpoint: Endpoint::new(
+                "185.65.134.1".parse::<IpAddr>().unwrap(),
+                51820,
+                TransportProtocol::Udp,
+            ),
+            tunnel_type: TunnelType::Wireguard,
+            quantum_resistant: true,
+            proxy: None,
+            obfuscation: Some(ObfuscationEndpoint {
+                endpoint: Endpoint::new(
+                    "185.65.134.4".parse::<IpAddr>().unwrap(),
+                    443,
+                    TransportProtocol::Tcp,
+                ),
+                obfuscation_type: ObfuscationType::Udp2Tcp,
+            }),
+            entry_endpoint: Some(Endpoint::new(
+                "185.65.134.4".parse::<IpAddr>().unwrap(),
+                51820,
+                TransportProtocol::Udp,
+            )),
+            wireguard_backend: Some(WireguardBackend::Kernel),
+        }
+    }
+
+    fn error(cause: ErrorStateCause) -> TunnelStateTransition {
+        TunnelStateTransition::Error(ErrorState::new(cause, None, vec![]))
+    }
+
+    /// Transitions that are compared with the golden file, by name. Transitions that only exist
+    /// on some platforms are only checked on those.
+    fn sample_transitions() -> Vec<(&'static str, TunnelStateTransition)> {
+        let mut samples = vec![
+            (
+                "disconnected_initial",
+                TunnelStateTransition::Disconnected {
+                    cause: None,
+                    security: DisconnectedSecurity::Unsecured,
+                },
+            ),
+            (
+                "disconnected_after_error",
+                TunnelStateTransition::Disconnected {
+                    cause: Some(DisconnectCause::Error(ErrorStateCause::IsOffline)),
+                    security: DisconnectedSecurity::Blocked(
+                        DisconnectedBlockReason::BlockWhenDisconnected,
+                    ),
+                },
+            ),
+            (
+                "connecting",
+                TunnelStateTransition::Connecting(connecting_endpoint()),
+            ),
+            (
+                "connected",
+                TunnelStateTransition::Connected(connected_endpoint()),
+            ),
+            (
+                "disconnecting",
+                TunnelStateTransition::Disconnecting(ActionAfterDisconnect::Reconnect),
+            ),
+            (
+                "error_auth_failed",
+                error(ErrorStateCause::AuthFailed(Some(
+                    "invalid_account".to_owned(),
+                ))),
+            ),
+            (
+                "error_firewall",
+                TunnelStateTransition::Error(ErrorState::new(
+                    ErrorStateCause::SetFirewallPolicyError(FirewallPolicyError::Generic),
+                    Some(FirewallPolicyError::Generic),
+                    vec!["192.168.1.0/24".parse().unwrap()],
+                )),
+            ),
+            (
+                "error_tunnel_parameters",
+                error(ErrorStateCause::TunnelParameterError(
+                    ParameterGenerationError::InvalidTunnelParameters(
+                        InvalidTunnelParameters::MtuOutOfRange {
+                            mtu: 100,
+                            min: 576,
+                            max: 1500,
+                        },
+                    ),
+                )),
+            ),
+            (
+                "paused",
+                TunnelStateTransition::Paused(connected_endpoint()),
+            ),
+        ];
+        samples.extend(
+            sample_error_causes()
+                .into_iter()
+                .map(|(name, cause)| (name, error(cause))),
+        );
+        samples
+    }
+
+    /// One sample per [`ErrorStateCause`] variant that exists on this platform. Variants with
+    /// data that is already covered by another sample use the simplest value.
+    fn sample_error_causes() -> Vec<(&'static str, ErrorStateCause)> {
+        let samples = vec![
+            ("error_account_expired", ErrorStateCause::AccountExpired),
+            ("error_ipv6_unavailable", ErrorStateCause::Ipv6Unavailable),
+            ("error_set_dns", ErrorStateCause::SetDnsError),
+            ("error_start_tunnel", ErrorStateCause::StartTunnelError),
+            ("error_is_offline", ErrorStateCause::IsOffline),
+            (
+                "error_tunnel_monitor_stopped",
+                ErrorStateCause::TunnelMonitorStopped,
+            ),
+            ("error_too_many_attempts", ErrorStateCause::TooManyAttempts),
+            #[cfg(target_os = "android")]
+            (
+                "error_invalid_dns_servers",
+                ErrorStateCause::InvalidDnsServers(vec!["1.1.1.1".parse().unwrap()]),
+            ),
+            #[cfg(target_os = "android")]
+            (
+                "error_vpn_permission_denied",
+                ErrorStateCause::VpnPermissionDenied,
+            ),
+            #[cfg(target_os = "windows")]
+            ("error_split_tunnel", ErrorStateCause::SplitTunnelError),
+            #[cfg(not(target_os = "android"))]
+            (
+                "error_forced_interface_unavailable",
+                ErrorStateCause::ForcedInterfaceUnavailable,
+            ),
+            #[cfg(target_os = "linux")]
+            (
+                "error_gateway_unreachable",
+                ErrorStateCause::GatewayUnreachable,
+            ),
+            #[cfg(not(target_os = "android"))]
+            ("error_route_taken_over", ErrorStateCause::RouteTakenOver),
+            #[cfg(not(target_os = "android"))]
+            (
+                "error_tunnel_process_failed",
+                ErrorStateCause::TunnelProcessFailed(ProcessFailure::ExitCode(1)),
+            ),
+            #[cfg(any(target_os = "linux", target_os = "macos"))]
+            (
+                "error_tunnel_device_busy",
+                ErrorStateCause::TunnelDeviceBusy,
+            ),
+        ];
+        for (_, cause) in &samples {
+            assert_error_cause_is_sampled(cause);
+        }
+        samples
+    }
+
+    /// Fails to compile when a variant is added to [`ErrorStateCause`]. Add a sample for the new
+    /// variant to [`sample_error_causes`] and to the golden file before adding it here.
+    fn assert_error_cause_is_sampled(cause: &ErrorStateCause) {
+        match cause {
+            ErrorStateCause::AuthFailed(_)
+            | ErrorStateCause::AccountExpired
+            | ErrorStateCause::Ipv6Unavailable
+            | ErrorStateCause::SetFirewallPolicyError(_)
+            | ErrorStateCause::SetDnsError
+            | ErrorStateCause::StartTunnelError
+            | ErrorStateCause::TunnelParameterError(_)
+            | ErrorStateCause::IsOffline
+            | ErrorStateCause::TunnelMonitorStopped
+            | ErrorStateCause::TooManyAttempts => (),
+            #[cfg(target_os = "android")]
+            ErrorStateCause::InvalidDnsServers(_) | ErrorStateCause::VpnPermissionDenied => (),
+            #[cfg(target_os = "windows")]
+            ErrorStateCause::SplitTunnelError => (),
+            #[cfg(not(target_os = "android"))]
+            ErrorStateCause::ForcedInterfaceUnavailable
+            | ErrorStateCause::RouteTakenOver
+            | ErrorStateCause::TunnelProcessFailed(_) => (),
+            #[cfg(target_os = "linux")]
+            ErrorStateCause::GatewayUnreachable => (),
+            #[cfg(any(target_os = "linux", target_os = "macos"))]
+            ErrorStateCause::TunnelDeviceBusy => (),
+        }
+    }
+
+    fn sample_versioned() -> VersionedTunnelStateTransition {
+        let metadata = TunnelMetadata {
+            interface: "wg-mullvad".to_owned(),
+            ips: vec![
+                "10.64.0.2".parse().unwrap(),
+                "fc00:bbbb:bbbb:bb01::1:2".parse().unwrap(),
+            ],
+            ipv4_gateway: "10.64.0.1".parse().unwrap(),
+            ipv6_gateway: Some("fc00:bbbb:bbbb:bb01::1".parse().unwrap()),
+            wireguard_backend: Some(WireguardBackend::Kernel),
+        };
+        VersionedTunnelStateTransition::new(
+            TunnelStateTransition::Connected(connected_endpoint()),
+            Some(metadata),
+        )
+    }
+
+    fn load_golden_file() -> serde_json::Map<String, serde_json::Value> {
+        let golden = golden_file(TUNNEL_STATE_SCHEMA_VERSION).unwrap_or_else(|| {
+            panic!(
+                "No golden file for tunnel state schema version {}",
+                TUNNEL_STATE_SCHEMA_VERSION
+            )
+        });
+        serde_json::from_str(golden).expect("Golden file is not a JSON object")
+    }
+
+    fn assert_matches_golden(
+        golden: &serde_json::Map<String, serde_json::Value>,
+        name: &str,
+        value: serde_json::Value,
+    ) {
+        let expected = golden
+            .get(name)
+            .unwrap_or_else(|| panic!("Sample \"{}\" is missing from the golden file", name));
+        assert_eq!(
+            &value, expected,
+            "The serialized form of \"{}\" changed. Bump TUNNEL_STATE_SCHEMA_VERSION and add a \
+             golden file for the new version instead of editing the existing one",
+            name
+        );
+    }
+
+    #[test]
+    fn test_schema_matches_golden_file() {
+        let golden = load_golden_file();
+
+        for (name, transition) in sample_transitions() {
+            assert_matches_golden(&golden, name, serde_json::to_value(&transition).unwrap());
+        }
+        assert_matches_golden(
+            &golden,
+            "versioned",
+            serde_json::to_value(sample_versioned()).unwrap(),
+        );
+    }
+
+    #[test]
+    fn test_golden_file_round_trip() {
+        let golden = load_golden_file();
+
+        for (name, _) in sample_transitions() {
+            let transition: TunnelStateTransition =
+                serde_json::from_value(golden[name].clone()).unwrap();
+            assert_eq!(serde_json::to_value(&transition).unwrap(), golden[name]);
+        }
+        let versioned: VersionedTunnelStateTransition =
+            serde_json::from_value(golden["versioned"].clone()).unwrap();
+        assert_eq!(versioned.schema_version, TUNNEL_STATE_SCHEMA_VERSION);
+        assert_eq!(
+            serde_json::to_value(&versioned).unwrap(),
+            golden["versioned"]
+        );
+    }
+}
@@ -1,7 +1,9 @@
 use async_trait::async_trait;
 use std::net::SocketAddr;
 
+mod tls;
 mod udp2tcp;
+pub use tls::TlsSettings;
 pub use udp2tcp::Udp2TcpSettings;
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -14,6 +16,12 @@ pub enum Error {
 
     #[error(display = "Failed to run Udp2Tcp obfuscator")]
     RunUdp2TcpObfuscator(#[error(source)] udp2tcp::Error),
+
+    #[error(display = "Failed to create TLS obfuscator")]
+    CreateTlsObfuscator(#[error(source)] tls::Error),
+
+    #[error(display = "Failed to run TLS obfuscator")]
+    RunTlsObfuscator(#[error(source)] tls::Error),
 }
 
 #[async_trait]
@@ -24,6 +32,7 @@ pub trait Obfuscator: Send {
 
 pub enum Settings {
     Udp2Tcp(Udp2TcpSettings),
+    Tls(TlsSettings),
 }
 
 pub async fn create_obfuscator(settings: &Settings) -> Result<Box<dyn Obfuscator>> {
@@ -31,5 +40,8 @@ pub async fn create_obfuscator(settings: &Settings) -> Result<Box<dyn Obfuscator
         Settings::Udp2Tcp(s) => udp2tcp::create_obfuscator(s)
             .await
             .map_err(Error::CreateUdp2TcpObfuscator),
+        Settings::Tls(s) => tls::create_obfuscator(s)
+            .await
+            .map_err(Error::CreateTlsObfuscator),
     }
 }
@@ -0,0 +1,251 @@
+//! Tunnels UDP datagrams through a TLS connection, so that the traffic resembles HTTPS.
+//!
+//! The relay presents a certificate that is not issued for the server name sent in the
+//! handshake, so it is authenticated by comparing the SHA-256 digest of its certificate to a
+//! pinned fingerprint instead of by a certificate chain. Once the handshake is done, each datagram
+//! is sent as a big endian `u16` length followed by the datagram itself.
+
+use crate::Obfuscator;
+use async_trait::async_trait;
+use std::{
+    io,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpSocket, TcpStream, UdpSocket},
+};
+use tokio_rustls::{
+    client::TlsStream,
+    rustls::{
+        self,
+        client::{ServerCertVerified, ServerCertVerifier},
+        Certificate, ClientConfig, ServerName,
+    },
+    TlsConnector,
+};
+
+/// How long to wait for the TCP connection and TLS handshake to complete.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Size of the length prefix of each datagram.
+const HEADER_LEN: usize = 2;
+const MAX_DATAGRAM_SIZE: usize = u16::MAX as usize;
+/// ALPN protocols offered by common browsers.
+const ALPN_PROTOCOLS: &[&[u8]] = &[b"h2", b"http/1.1"];
+
+pub struct TlsSettings {
+    pub peer: SocketAddr,
+    /// Server name to send in the handshake.
+    pub server_name: String,
+    /// SHA-256 digest of the DER encoded certificate that the relay must present.
+    pub certificate_sha256: [u8; 32],
+    #[cfg(target_os = "linux")]
+    pub fwmark: Option<u32>,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    /// Failed to bind the local UDP socket
+    #[error(display = "Failed to bind UDP socket")]
+    BindUdpSocket(#[error(source)] io::Error),
+
+    /// The server name is not a valid DNS name
+    #[error(display = "Invalid server name: {}", _0)]
+    InvalidServerName(String),
+
+    /// Failed to set the firewall mark of the TCP socket
+    #[cfg(target_os = "linux")]
+    #[error(display = "Failed to set firewall mark")]
+    SetFwmark(#[error(source)] io::Error),
+
+    /// Failed to connect to the relay
+    #[error(display = "Failed to connect to the relay")]
+    Connect(#[error(source)] io::Error),
+
+    /// The TLS handshake failed. This includes the relay presenting the wrong certificate
+    #[error(display = "TLS handshake failed")]
+    Handshake(#[error(source)] io::Error),
+
+    /// Connecting to the relay took too long
+    #[error(display = "Timed out while connecting to the relay")]
+    Timeout,
+
+    /// Failed to forward traffic
+    #[error(display = "Failed to forward traffic")]
+    Forward(#[error(source)] io::Error),
+}
+
+/// Accepts only a certificate with a specific SHA-256 digest.
+struct PinnedCertificateVerifier {
+    certificate_sha256: [u8; 32],
+}
+
+impl ServerCertVerifier for PinnedCertificateVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        let digest = ring::digest::digest(&ring::digest::SHA256, &end_entity.0);
+        if digest.as_ref() == self.certificate_sha256 {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::InvalidCertificateData(
+                "The certificate does not match the pinned fingerprint".to_owned(),
+            ))
+        }
+    }
+}
+
+struct Tls {
+    local_addr: SocketAddr,
+    udp_socket: UdpSocket,
+    stream: TlsStream<TcpStream>,
+}
+
+impl Tls {
+    pub async fn new(settings: &TlsSettings) -> Result<Self> {
+        let listen_addr = if settings.peer.is_ipv4() {
+            SocketAddr::new("127.0.0.1".parse().unwrap(), 0)
+        } else {
+            SocketAddr::new("::1".parse().unwrap(), 0)
+        };
+        let udp_socket = UdpSocket::bind(listen_addr)
+            .await
+            .map_err(Error::BindUdpSocket)?;
+        let local_addr = udp_socket.local_addr().map_err(Error::BindUdpSocket)?;
+
+        let stream = tokio::time::timeout(HANDSHAKE_TIMEOUT, connect(settings))
+            .await
+            .map_err(|_| Error::Timeout)??;
+
+        Ok(Self {
+            local_addr,
+            udp_socket,
+            stream,
+        })
+    }
+}
+
+async fn connect(settings: &TlsSettings) -> Result<TlsStream<TcpStream>> {
+    let server_name = ServerName::try_from(settings.server_name.as_str())
+        .map_err(|_| Error::InvalidServerName(settings.server_name.clone()))?;
+
+    let socket = if settings.peer.is_ipv4() {
+        TcpSocket::new_v4()
+    } else {
+        TcpSocket::new_v6()
+    }
+    .map_err(Error::Connect)?;
+    #[cfg(target_os = "linux")]
+    if let Some(fwmark) = settings.fwmark {
+        socket2::SockRef::from(&socket)
+            .set_mark(fwmark)
+            .map_err(Error::SetFwmark)?;
+    }
+    let tcp_stream = socket
+        .connect(settings.peer)
+        .await
+        .map_err(Error::Connect)?;
+    tcp_stream.set_nodelay(true).map_err(Error::Connect)?;
+
+    let mut config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(PinnedCertificateVerifier {
+            certificate_sha256: settings.certificate_sha256,
+        }))
+        .with_no_client_auth();
+    config.alpn_protocols = ALPN_PROTOCOLS.iter().map(|p| p.to_vec()).collect();
+
+    TlsConnector::from(Arc::new(config))
+        .connect(server_name, tcp_stream)
+        .await
+        .map_err(Error::Handshake)
+}
+
+#[async_trait]
+impl Obfuscator for Tls {
+    fn endpoint(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    async fn run(self: Box<Self>) -> crate::Result<()> {
+        forward(self.udp_socket, self.stream)
+            .await
+            .map_err(Error::Forward)
+            .map_err(crate::Error::RunTlsObfuscator)
+    }
+}
+
+/// Forwards datagrams between the local UDP socket and the TLS stream until either side fails or
+/// the relay closes the connection.
+async fn forward(udp_socket: UdpSocket, stream: TlsStream<TcpStream>) -> io::Result<()> {
+    let mut buffer = vec![0u8; HEADER_LEN + MAX_DATAGRAM_SIZE];
+
+    // Datagrams from the relay are sent to whoever sent the first datagram
+    let (size, client) = udp_socket.recv_from(&mut buffer[HEADER_LEN..]).await?;
+    udp_socket.connect(client).await?;
+
+    let (tls_reader, mut tls_writer) = tokio::io::split(stream);
+    write_datagram(&mut tls_writer, &mut buffer, size).await?;
+
+    tokio::select! {
+        result = udp_to_tls(&udp_socket, tls_writer, buffer) => result,
+        result = tls_to_udp(tls_reader, &udp_socket) => result,
+    }
+}
+
+async fn udp_to_tls(
+    udp_socket: &UdpSocket,
+    mut tls_writer: impl AsyncWrite + Unpin,
+    mut buffer: Vec<u8>,
+) -> io::Result<()> {
+    loop {
+        let size = udp_socket.recv(&mut buffer[HEADER_LEN..]).await?;
+        write_datagram(&mut tls_writer, &mut buffer, size).await?;
+    }
+}
+
+/// Writes the datagram stored after the header in `buffer`, prefixed by its length.
+async fn write_datagram(
+    tls_writer: &mut (impl AsyncWrite + Unpin),
+    buffer: &mut [u8],
+    size: usize,
+) -> io::Result<()> {
+    // `size` fits, since the buffer has room for at most `MAX_DATAGRAM_SIZE` bytes
+    buffer[..HEADER_LEN].copy_from_slice(&(size as u16).to_be_bytes());
+    tls_writer.write_all(&buffer[..HEADER_LEN + size]).await?;
+    tls_writer.flush().await
+}
+
+async fn tls_to_udp(
+    mut tls_reader: impl AsyncRead + Unpin,
+    udp_socket: &UdpSocket,
+) -> io::Result<()> {
+    let mut buffer = vec![0u8; MAX_DATAGRAM_SIZE];
+    loop {
+        let mut header = [0u8; HEADER_LEN];
+        match tls_reader.read_exact(&mut header).await {
+            Ok(_) => (),
+            // The relay closed the connection
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(error) => return Err(error),
+        }
+        let size = usize::from(u16::from_be_bytes(header));
+        tls_reader.read_exact(&mut buffer[..size]).await?;
+        udp_socket.send(&buffer[..size]).await?;
+    }
+}
+
+pub async fn create_obfuscator(settings: &TlsSettings) -> Result<Box<dyn Obfuscator>> {
+    Ok(Box::new(Tls::new(settings).await?))
+}
@@ -17,6 +17,12 @@ WinFwSettings CreateSettings(const std::wstring &dhcp, const std::wstring &lan)
 
 	s.permitDhcp = (0 == _wcsicmp(dhcp.c_str(), L"yes"));
 	s.permitLan = (0 == _wcsicmp(lan.c_str(), L"yes"));
+	s.coexistenceNetworks = nullptr;
+	s.numCoexistenceNetworks = 0;
+	s.coexistenceUdpPorts = nullptr;
+	s.numCoexistenceUdpPorts = 0;
+	s.pluginRules = nullptr;
+	s.numPluginRules = 0;
 
 	return s;
 }
@@ -143,6 +149,8 @@ void Policy::processConnected(const KeyValuePairs &arguments)
 	const auto v4Gateway = GetArgumentValue(arguments, L"v4Gateway");
 	const auto dnsCstr = v4Gateway.c_str();
 
+	const WinFwBlockedTunnelProtocols blockedProtocols = { false, false, false };
+
 	auto success = WINFW_POLICY_STATUS_SUCCESS == WinFw_ApplyPolicyConnected
 	(
 		&settings,
@@ -152,7 +160,8 @@ void Policy::processConnected(const KeyValuePairs &arguments)
 		dnsCstr,
 		nullptr,
 		&dnsCstr,
-		1
+		1,
+		&blockedProtocols
 	);
 
 	m_messageSink((success
@@ -168,7 +177,7 @@ void Policy::processBlocked(const KeyValuePairs &arguments)
 		GetArgumentValue(arguments, L"lan")
 	);
 
-	auto success = WINFW_POLICY_STATUS_SUCCESS == WinFw_ApplyPolicyBlocked(&settings, nullptr);
+	auto success = WINFW_POLICY_STATUS_SUCCESS == WinFw_ApplyPolicyBlocked(&settings, nullptr, nullptr, 0);
 
 	m_messageSink((success
 		? L"Successfully applied policy."
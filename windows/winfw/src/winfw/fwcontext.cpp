@@ -15,6 +15,10 @@
 #include "rules/baseline/permitvpntunnelservice.h"
 #include "rules/baseline/permitdns.h"
 #include "rules/baseline/permitendpoint.h"
+#include "rules/baseline/permitrecoverynetworks.h"
+#include "rules/baseline/permitvpncoexistence.h"
+#include "rules/baseline/pluginrules.h"
+#include "rules/baseline/blocktunnelprotocols.h"
 #include "rules/dns/blockall.h"
 #include "rules/dns/permittunnel.h"
 #include "rules/dns/permitnontunnel.h"
@@ -64,6 +68,62 @@ void AppendSettingsRules
 		ruleset.emplace_back(baseline::PermitDhcpServer::WithExtent(baseline::PermitDhcpServer::Extent::IPv4Only));
 	}
 
+	if (0 != settings.numCoexistenceNetworks || 0 != settings.numCoexistenceUdpPorts)
+	{
+		if ((nullptr == settings.coexistenceNetworks && 0 != settings.numCoexistenceNetworks)
+			|| (nullptr == settings.coexistenceUdpPorts && 0 != settings.numCoexistenceUdpPorts))
+		{
+			THROW_ERROR("Invalid argument: settings");
+		}
+
+		std::vector<wfp::IpNetwork> ipv4Networks;
+		std::vector<wfp::IpNetwork> ipv6Networks;
+
+		for (size_t i = 0; i < settings.numCoexistenceNetworks; i++)
+		{
+			const auto address = wfp::IpAddress(settings.coexistenceNetworks[i].address);
+			auto &networks = address.type() == wfp::IpAddress::Type::Ipv4 ? ipv4Networks : ipv6Networks;
+			networks.emplace_back(address, settings.coexistenceNetworks[i].prefix);
+		}
+
+		const std::vector<uint16_t> udpPorts(
+			settings.coexistenceUdpPorts,
+			settings.coexistenceUdpPorts + settings.numCoexistenceUdpPorts
+		);
+
+		ruleset.emplace_back(std::make_unique<baseline::PermitVpnCoexistence>(
+			ipv4Networks,
+			ipv6Networks,
+			udpPorts
+		));
+	}
+
+	if (0 != settings.numPluginRules)
+	{
+		if (nullptr == settings.pluginRules)
+		{
+			THROW_ERROR("Invalid argument: settings");
+		}
+
+		std::vector<baseline::PluginRules::Rule> rules;
+
+		for (size_t i = 0; i < settings.numPluginRules; i++)
+		{
+			const auto &rule = settings.pluginRules[i];
+			const auto address = wfp::IpAddress(rule.network.address);
+
+			rules.push_back(baseline::PluginRules::Rule{
+				PluginPermit == rule.action,
+				wfp::IpAddress::Type::Ipv4 == address.type(),
+				wfp::IpNetwork(address, rule.network.prefix),
+				rule.matchProtocol ? std::make_optional(rule.protocol) : std::nullopt,
+				rule.matchProtocol ? rule.port : uint16_t(0)
+			});
+		}
+
+		ruleset.emplace_back(std::make_unique<baseline::PluginRules>(rules));
+	}
+
 	//
 	// DNS management
 	//
@@ -113,11 +173,22 @@ void AppendAllowedEndpointRules
 		clients.push_back(endpoint.clients[i]);
 	}
 
+	std::vector<baseline::PermitEndpoint::Endpoint> endpoints;
+	endpoints.reserve(endpoint.numEndpoints);
+	for (uint32_t i = 0; i < endpoint.numEndpoints; i++) {
+		const auto &range = endpoint.endpoints[i];
+
+		endpoints.push_back(baseline::PermitEndpoint::Endpoint{
+			wfp::IpAddress(range.ip),
+			range.portStart,
+			range.portEnd,
+			range.matchProtocol ? std::make_optional(range.protocol) : std::nullopt
+		});
+	}
+
 	ruleset.emplace_back(std::make_unique<baseline::PermitEndpoint>(
-		wfp::IpAddress(endpoint.endpoint.ip),
-		clients,
-		endpoint.endpoint.port,
-		endpoint.endpoint.protocol
+		endpoints,
+		clients
 	));
 }
 
@@ -202,6 +273,8 @@ bool FwContext::applyPolicyConnecting
 
 	if (tunnelInterfaceAlias.has_value())
 	{
+		std::optional<baseline::PermitVpnTunnel::Endpoints> onlyEndpoints;
+
 		switch (allowedTunnelTraffic.type)
 		{
 			case WinFwAllowedTunnelTrafficType::All:
@@ -218,23 +291,75 @@ bool FwContext::applyPolicyConnecting
 			}
 			case WinFwAllowedTunnelTrafficType::Only:
 			{
-				const auto onlyEndpoint = std::make_optional(baseline::PermitVpnTunnel::Endpoint{
-					wfp::IpAddress(allowedTunnelTraffic.endpoint->ip),
+				onlyEndpoints = baseline::PermitVpnTunnel::Endpoints{
+					{ wfp::IpAddress(allowedTunnelTraffic.endpoint->ip) },
 					allowedTunnelTraffic.endpoint->port,
 					allowedTunnelTraffic.endpoint->protocol
-				});
-				ruleset.emplace_back(std::make_unique<baseline::PermitVpnTunnel>(
-					*tunnelInterfaceAlias,
-					onlyEndpoint
-				));
-				ruleset.emplace_back(std::make_unique<baseline::PermitVpnTunnelService>(
-					*tunnelInterfaceAlias,
-					onlyEndpoint
-				));
+				};
+				break;
+			}
+			case WinFwAllowedTunnelTrafficType::Dns:
+			{
+				std::vector<wfp::IpAddress> servers;
+
+				for (size_t i = 0; i < allowedTunnelTraffic.numAddresses; ++i)
+				{
+					servers.emplace_back(allowedTunnelTraffic.addresses[i]);
+				}
+
+				if (false == servers.empty())
+				{
+					//
+					// DNS is otherwise blocked by the DNS sublayer.
+					//
+					ruleset.emplace_back(std::make_unique<dns::PermitTunnel>(
+						*tunnelInterfaceAlias,
+						servers
+					));
+
+					onlyEndpoints = baseline::PermitVpnTunnel::Endpoints{
+						std::move(servers),
+						DNS_SERVER_PORT,
+						std::nullopt
+					};
+				}
+
+				break;
+			}
+			case WinFwAllowedTunnelTrafficType::Endpoints:
+			{
+				std::vector<wfp::IpAddress> hosts;
+
+				for (size_t i = 0; i < allowedTunnelTraffic.numAddresses; ++i)
+				{
+					hosts.emplace_back(allowedTunnelTraffic.addresses[i]);
+				}
+
+				if (false == hosts.empty())
+				{
+					onlyEndpoints = baseline::PermitVpnTunnel::Endpoints{
+						std::move(hosts),
+						allowedTunnelTraffic.port,
+						allowedTunnelTraffic.protocol
+					};
+				}
+
 				break;
 			}
 			// For the "None" case, do nothing.
 		}
+
+		if (onlyEndpoints.has_value())
+		{
+			ruleset.emplace_back(std::make_unique<baseline::PermitVpnTunnel>(
+				*tunnelInterfaceAlias,
+				onlyEndpoints
+			));
+			ruleset.emplace_back(std::make_unique<baseline::PermitVpnTunnelService>(
+				*tunnelInterfaceAlias,
+				onlyEndpoints
+			));
+		}
 	}
 
 	const auto status = applyRuleset(ruleset);
@@ -254,7 +379,8 @@ bool FwContext::applyPolicyConnected
 	const std::wstring &relayClient,
 	const std::wstring &tunnelInterfaceAlias,
 	const std::vector<wfp::IpAddress> &tunnelDnsServers,
-	const std::vector<wfp::IpAddress> &nonTunnelDnsServers
+	const std::vector<wfp::IpAddress> &nonTunnelDnsServers,
+	const WinFwBlockedTunnelProtocols &blockedProtocols
 )
 {
 	Ruleset ruleset;
@@ -286,6 +412,14 @@ bool FwContext::applyPolicyConnected
 		std::nullopt
 	));
 
+	if (blockedProtocols.ipv6 || blockedProtocols.udp || blockedProtocols.smb)
+	{
+		ruleset.emplace_back(std::make_unique<baseline::BlockTunnelProtocols>(
+			tunnelInterfaceAlias,
+			blockedProtocols
+		));
+	}
+
 	const auto status = applyRuleset(ruleset);
 
 	if (status)
@@ -296,9 +430,15 @@ bool FwContext::applyPolicyConnected
 	return status;
 }
 
-bool FwContext::applyPolicyBlocked(const WinFwSettings &settings, const std::optional<WinFwAllowedEndpoint> &allowedEndpoint)
+bool FwContext::applyPolicyBlocked
+(
+	const WinFwSettings &settings,
+	const std::optional<WinFwAllowedEndpoint> &allowedEndpoint,
+	const std::vector<wfp::IpNetwork> &ipv4RecoveryNetworks,
+	const std::vector<wfp::IpNetwork> &ipv6RecoveryNetworks
+)
 {
-	const auto status = applyRuleset(composePolicyBlocked(settings, allowedEndpoint));
+	const auto status = applyRuleset(composePolicyBlocked(settings, allowedEndpoint, ipv4RecoveryNetworks, ipv6RecoveryNetworks));
 
 	if (status)
 	{
@@ -328,7 +468,13 @@ FwContext::Policy FwContext::activePolicy() const
 	return m_activePolicy;
 }
 
-FwContext::Ruleset FwContext::composePolicyBlocked(const WinFwSettings &settings, const std::optional<WinFwAllowedEndpoint> &allowedEndpoint)
+FwContext::Ruleset FwContext::composePolicyBlocked
+(
+	const WinFwSettings &settings,
+	const std::optional<WinFwAllowedEndpoint> &allowedEndpoint,
+	const std::vector<wfp::IpNetwork> &ipv4RecoveryNetworks,
+	const std::vector<wfp::IpNetwork> &ipv6RecoveryNetworks
+)
 {
 	Ruleset ruleset;
 
@@ -340,6 +486,14 @@ FwContext::Ruleset FwContext::composePolicyBlocked(const WinFwSettings &settings
 		AppendAllowedEndpointRules(ruleset, allowedEndpoint.value());
 	}
 
+	if (false == ipv4RecoveryNetworks.empty() || false == ipv6RecoveryNetworks.empty())
+	{
+		ruleset.emplace_back(std::make_unique<baseline::PermitRecoveryNetworks>(
+			ipv4RecoveryNetworks,
+			ipv6RecoveryNetworks
+		));
+	}
+
 	return ruleset;
 }
 
@@ -367,7 +521,7 @@ bool FwContext::applyBlockedBaseConfiguration(const WinFwSettings &settings, con
 		//
 		checkpoint = controller.peekCheckpoint();
 
-		return applyRulesetDirectly(composePolicyBlocked(settings, allowedEndpoint), controller);
+		return applyRulesetDirectly(composePolicyBlocked(settings, allowedEndpoint, {}, {}), controller);
 	});
 }
 
@@ -4,6 +4,7 @@
 #include "sessioncontroller.h"
 #include "rules/ifirewallrule.h"
 #include "libwfp/ipaddress.h"
+#include "libwfp/ipnetwork.h"
 #include <cstdint>
 #include <memory>
 #include <vector>
@@ -41,12 +42,15 @@ public:
 		const std::wstring &relayClient,
 		const std::wstring &tunnelInterfaceAlias,
 		const std::vector<wfp::IpAddress> &tunnelDnsServers,
-		const std::vector<wfp::IpAddress> &nonTunnelDnsServers
+		const std::vector<wfp::IpAddress> &nonTunnelDnsServers,
+		const WinFwBlockedTunnelProtocols &blockedProtocols
 	);
 
 	bool applyPolicyBlocked(
 		const WinFwSettings &settings,
-		const std::optional<WinFwAllowedEndpoint> &allowedEndpoint
+		const std::optional<WinFwAllowedEndpoint> &allowedEndpoint,
+		const std::vector<wfp::IpNetwork> &ipv4RecoveryNetworks,
+		const std::vector<wfp::IpNetwork> &ipv6RecoveryNetworks
 	);
 
 	bool reset();
@@ -68,7 +72,13 @@ private:
 	FwContext(const FwContext &) = delete;
 	FwContext &operator=(const FwContext &) = delete;
 
-	Ruleset composePolicyBlocked(const WinFwSettings &settings, const std::optional<WinFwAllowedEndpoint> &allowedEndpoint);
+	Ruleset composePolicyBlocked
+	(
+		const WinFwSettings &settings,
+		const std::optional<WinFwAllowedEndpoint> &allowedEndpoint,
+		const std::vector<wfp::IpNetwork> &ipv4RecoveryNetworks,
+		const std::vector<wfp::IpNetwork> &ipv6RecoveryNetworks
+	);
 
 	bool applyBaseConfiguration();
 	bool applyBlockedBaseConfiguration(const WinFwSettings &settings, const std::optional<WinFwAllowedEndpoint> &allowedEndpoint, uint32_t &checkpoint);
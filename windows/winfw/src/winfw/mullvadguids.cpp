@@ -1,5 +1,6 @@
 #include "stdafx.h"
 #include "mullvadguids.h"
+#include <libcommon/error.h>
 #include <algorithm>
 #include <iterator>
 
@@ -118,6 +119,24 @@ MullvadGuids::DetailedIdentityRegistry MullvadGuids::DetailedRegistry(IdentityQu
 	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_PermitLan_Outbound_Multicast_Ipv6()));
 	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_PermitLanService_Inbound_Ipv4()));
 	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_PermitLanService_Inbound_Ipv6()));
+	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_PermitRecoveryNetworks_Outbound_Ipv4()));
+	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_PermitRecoveryNetworks_Inbound_Ipv4()));
+	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_PermitRecoveryNetworks_Outbound_Ipv6()));
+	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_PermitRecoveryNetworks_Inbound_Ipv6()));
+	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_PermitVpnCoexistence_Networks_Outbound_Ipv4()));
+	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_PermitVpnCoexistence_Networks_Inbound_Ipv4()));
+	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_PermitVpnCoexistence_Networks_Outbound_Ipv6()));
+	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_PermitVpnCoexistence_Networks_Inbound_Ipv6()));
+	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_PermitVpnCoexistence_Ports_Outbound_Ipv4()));
+	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_PermitVpnCoexistence_Ports_Inbound_Ipv4()));
+	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_PermitVpnCoexistence_Ports_Outbound_Ipv6()));
+	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_PermitVpnCoexistence_Ports_Inbound_Ipv6()));
+
+	for (size_t i = 0; i < NumPluginRuleFilters; i++)
+	{
+		registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_PluginRule(i)));
+	}
+
 	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_PermitLoopback_Outbound_Ipv4()));
 	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_PermitLoopback_Inbound_Ipv4()));
 	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_PermitLoopback_Outbound_Ipv6()));
@@ -129,11 +148,21 @@ MullvadGuids::DetailedIdentityRegistry MullvadGuids::DetailedRegistry(IdentityQu
 	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_PermitDhcpServer_Inbound_Request_Ipv4()));
 	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_PermitDhcpServer_Outbound_Response_Ipv4()));
 	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_PermitVpnRelay()));
-	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_PermitEndpoint()));
+
+	for (size_t i = 0; i < NumPermitEndpointFilters; i++)
+	{
+		registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_PermitEndpoint(i)));
+	}
+
 	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_PermitVpnTunnel_Outbound_Ipv4()));
 	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_PermitVpnTunnel_Outbound_Ipv6()));
 	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_PermitVpnTunnelService_Ipv4()));
 	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_PermitVpnTunnelService_Ipv6()));
+	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_BlockTunnelProtocols_Ipv6()));
+	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_BlockTunnelProtocols_Udp_Ipv4()));
+	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_BlockTunnelProtocols_Udp_Ipv6()));
+	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_BlockTunnelProtocols_Smb_Ipv4()));
+	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_BlockTunnelProtocols_Smb_Ipv6()));
 	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_PermitNdp_Outbound_Router_Solicitation()));
 	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_PermitNdp_Inbound_Router_Advertisement()));
 	registry.insert(std::make_pair(WfpObjectType::Filter, Filter_Baseline_PermitNdp_Outbound_Neighbor_Solicitation()));
@@ -492,6 +521,195 @@ const GUID &MullvadGuids::Filter_Baseline_PermitLanService_Inbound_Ipv6()
 	return g;
 }
 
+//static
+const GUID &MullvadGuids::Filter_Baseline_PermitRecoveryNetworks_Outbound_Ipv4()
+{
+	static const GUID g =
+	{
+		0x4bf9cd20,
+		0x2add,
+		0x4210,
+		{ 0xb5, 0x92, 0xae, 0x85, 0x80, 0xc1, 0xa9, 0xc1 }
+	};
+
+	return g;
+}
+
+//static
+const GUID &MullvadGuids::Filter_Baseline_PermitRecoveryNetworks_Inbound_Ipv4()
+{
+	static const GUID g =
+	{
+		0x67aead92,
+		0xca10,
+		0x4c33,
+		{ 0x87, 0xb0, 0x7a, 0xf, 0x5a, 0x98, 0x38, 0xd7 }
+	};
+
+	return g;
+}
+
+//static
+const GUID &MullvadGuids::Filter_Baseline_PermitRecoveryNetworks_Outbound_Ipv6()
+{
+	static const GUID g =
+	{
+		0x1c2370e7,
+		0xb08b,
+		0x4098,
+		{ 0xa2, 0xf0, 0xa0, 0x4e, 0x33, 0x9, 0x21, 0xc8 }
+	};
+
+	return g;
+}
+
+//static
+const GUID &MullvadGuids::Filter_Baseline_PermitRecoveryNetworks_Inbound_Ipv6()
+{
+	static const GUID g =
+	{
+		0x2c397601,
+		0x9526,
+		0x4c16,
+		{ 0xbf, 0x82, 0x12, 0xa1, 0x9a, 0x9e, 0xbf, 0x6f }
+	};
+
+	return g;
+}
+
+//static
+const GUID &MullvadGuids::Filter_Baseline_PermitVpnCoexistence_Networks_Outbound_Ipv4()
+{
+	static const GUID g =
+	{
+		0x287a05a7,
+		0xbc0e,
+		0x4649,
+		{ 0xb3, 0x59, 0xea, 0xc, 0xf8, 0xaf, 0xaf, 0x43 }
+	};
+
+	return g;
+}
+
+//static
+const GUID &MullvadGuids::Filter_Baseline_PermitVpnCoexistence_Networks_Inbound_Ipv4()
+{
+	static const GUID g =
+	{
+		0x88ad03ec,
+		0xdc39,
+		0x4f44,
+		{ 0x87, 0xbf, 0x47, 0xff, 0xc7, 0xd9, 0x2d, 0x3b }
+	};
+
+	return g;
+}
+
+//static
+const GUID &MullvadGuids::Filter_Baseline_PermitVpnCoexistence_Networks_Outbound_Ipv6()
+{
+	static const GUID g =
+	{
+		0x0fa917ff,
+		0x0cdc,
+		0x4f0a,
+		{ 0xbe, 0x88, 0xfa, 0x47, 0xf4, 0x97, 0xc0, 0x8e }
+	};
+
+	return g;
+}
+
+//static
+const GUID &MullvadGuids::Filter_Baseline_PermitVpnCoexistence_Networks_Inbound_Ipv6()
+{
+	static const GUID g =
+	{
+		0xc22e73c8,
+		0x8e9e,
+		0x4c63,
+		{ 0xb3, 0x66, 0x38, 0x9c, 0x74, 0x93, 0xc1, 0xe }
+	};
+
+	return g;
+}
+
+//static
+const GUID &MullvadGuids::Filter_Baseline_PermitVpnCoexistence_Ports_Outbound_Ipv4()
+{
+	static const GUID g =
+	{
+		0x1d87a583,
+		0x6b7a,
+		0x4353,
+		{ 0xb2, 0x3a, 0xc3, 0xf0, 0x51, 0xb4, 0xcb, 0xa3 }
+	};
+
+	return g;
+}
+
+//static
+const GUID &MullvadGuids::Filter_Baseline_PermitVpnCoexistence_Ports_Inbound_Ipv4()
+{
+	static const GUID g =
+	{
+		0x721ff15c,
+		0x087d,
+		0x439a,
+		{ 0x82, 0xe8, 0xff, 0x3, 0x5e, 0xbb, 0xd, 0x27 }
+	};
+
+	return g;
+}
+
+//static
+const GUID &MullvadGuids::Filter_Baseline_PermitVpnCoexistence_Ports_Outbound_Ipv6()
+{
+	static const GUID g =
+	{
+		0x42b67572,
+		0x571b,
+		0x4b05,
+		{ 0x82, 0x6f, 0xac, 0xc9, 0x22, 0x1f, 0xc9, 0x83 }
+	};
+
+	return g;
+}
+
+//static
+const GUID &MullvadGuids::Filter_Baseline_PermitVpnCoexistence_Ports_Inbound_Ipv6()
+{
+	static const GUID g =
+	{
+		0x3361f2cd,
+		0x9356,
+		0x42c6,
+		{ 0xa5, 0x18, 0x74, 0xc6, 0xd4, 0x94, 0x8f, 0x5a }
+	};
+
+	return g;
+}
+
+//static
+GUID MullvadGuids::Filter_Baseline_PluginRule(size_t index)
+{
+	if (index >= NumPluginRuleFilters)
+	{
+		THROW_ERROR("Invalid plugin rule filter index");
+	}
+
+	GUID g =
+	{
+		0x5c2f0b91,
+		0x3e7a,
+		0x4d1b,
+		{ 0x9a, 0x64, 0x2b, 0xd8, 0x41, 0x0e, 0x6c, 0x00 }
+	};
+
+	g.Data4[7] = static_cast<uint8_t>(index);
+
+	return g;
+}
+
 //static
 const GUID &MullvadGuids::Filter_Baseline_PermitLoopback_Outbound_Ipv4()
 {
@@ -647,9 +865,14 @@ const GUID &MullvadGuids::Filter_Baseline_PermitVpnRelay()
 }
 
 //static
-const GUID &MullvadGuids::Filter_Baseline_PermitEndpoint()
+GUID MullvadGuids::Filter_Baseline_PermitEndpoint(size_t index)
 {
-	static const GUID g =
+	if (index >= NumPermitEndpointFilters)
+	{
+		THROW_ERROR("Invalid allowed endpoint filter index");
+	}
+
+	GUID g =
 	{
 		0x99dc8dac,
 		0x8520,
@@ -657,6 +880,8 @@ const GUID &MullvadGuids::Filter_Baseline_PermitEndpoint()
 		{ 0xbf, 0xab, 0x0c, 0x9, 0xbf, 0x12, 0xeb, 0 }
 	};
 
+	g.Data4[7] = static_cast<uint8_t>(index);
+
 	return g;
 }
 
@@ -716,6 +941,76 @@ const GUID &MullvadGuids::Filter_Baseline_PermitVpnTunnelService_Ipv6()
 	return g;
 }
 
+//static
+const GUID &MullvadGuids::Filter_Baseline_BlockTunnelProtocols_Ipv6()
+{
+	static const GUID g =
+	{
+		0xe11c06dd,
+		0x3652,
+		0x45a4,
+		{ 0x97, 0x9b, 0x22, 0x9a, 0x40, 0xd1, 0x79, 0xb8 }
+	};
+
+	return g;
+}
+
+//static
+const GUID &MullvadGuids::Filter_Baseline_BlockTunnelProtocols_Udp_Ipv4()
+{
+	static const GUID g =
+	{
+		0x0e9c4ae3,
+		0x30f4,
+		0x4acb,
+		{ 0x9c, 0x2, 0x73, 0xde, 0x4f, 0xcb, 0xa5, 0xcf }
+	};
+
+	return g;
+}
+
+//static
+const GUID &MullvadGuids::Filter_Baseline_BlockTunnelProtocols_Udp_Ipv6()
+{
+	static const GUID g =
+	{
+		0xada55ae0,
+		0xff33,
+		0x4007,
+		{ 0x8b, 0x7d, 0x86, 0xa4, 0xae, 0xc8, 0xf3, 0xa5 }
+	};
+
+	return g;
+}
+
+//static
+const GUID &MullvadGuids::Filter_Baseline_BlockTunnelProtocols_Smb_Ipv4()
+{
+	static const GUID g =
+	{
+		0x9c0575f2,
+		0x0f87,
+		0x408b,
+		{ 0x8b, 0x5e, 0xc6, 0xa1, 0x6e, 0xd0, 0xff, 0x8 }
+	};
+
+	return g;
+}
+
+//static
+const GUID &MullvadGuids::Filter_Baseline_BlockTunnelProtocols_Smb_Ipv6()
+{
+	static const GUID g =
+	{
+		0xff9f2649,
+		0x5abb,
+		0x4f3b,
+		{ 0x8c, 0x3f, 0xc0, 0x5b, 0x16, 0x1d, 0xcf, 0x6c }
+	};
+
+	return g;
+}
+
 //static
 const GUID &MullvadGuids::Filter_Baseline_PermitNdp_Outbound_Router_Solicitation()
 {
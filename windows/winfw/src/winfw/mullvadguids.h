@@ -54,6 +54,27 @@ public:
 	static const GUID &Filter_Baseline_PermitLanService_Inbound_Ipv4();
 	static const GUID &Filter_Baseline_PermitLanService_Inbound_Ipv6();
 
+	static const GUID &Filter_Baseline_PermitRecoveryNetworks_Outbound_Ipv4();
+	static const GUID &Filter_Baseline_PermitRecoveryNetworks_Inbound_Ipv4();
+	static const GUID &Filter_Baseline_PermitRecoveryNetworks_Outbound_Ipv6();
+	static const GUID &Filter_Baseline_PermitRecoveryNetworks_Inbound_Ipv6();
+
+	static const GUID &Filter_Baseline_PermitVpnCoexistence_Networks_Outbound_Ipv4();
+	static const GUID &Filter_Baseline_PermitVpnCoexistence_Networks_Inbound_Ipv4();
+	static const GUID &Filter_Baseline_PermitVpnCoexistence_Networks_Outbound_Ipv6();
+	static const GUID &Filter_Baseline_PermitVpnCoexistence_Networks_Inbound_Ipv6();
+	static const GUID &Filter_Baseline_PermitVpnCoexistence_Ports_Outbound_Ipv4();
+	static const GUID &Filter_Baseline_PermitVpnCoexistence_Ports_Inbound_Ipv4();
+	static const GUID &Filter_Baseline_PermitVpnCoexistence_Ports_Outbound_Ipv6();
+	static const GUID &Filter_Baseline_PermitVpnCoexistence_Ports_Inbound_Ipv6();
+
+	//
+	// The number of filters added for plugin rules varies, so their identifiers are derived
+	// from a common base and the index of the filter.
+	//
+	static const size_t NumPluginRuleFilters = 128;
+	static GUID Filter_Baseline_PluginRule(size_t index);
+
 	static const GUID &Filter_Baseline_PermitLoopback_Outbound_Ipv4();
 	static const GUID &Filter_Baseline_PermitLoopback_Inbound_Ipv4();
 	static const GUID &Filter_Baseline_PermitLoopback_Outbound_Ipv6();
@@ -69,7 +90,8 @@ public:
 
 	static const GUID &Filter_Baseline_PermitVpnRelay();
 
-	static const GUID &Filter_Baseline_PermitEndpoint();
+	static const size_t NumPermitEndpointFilters = 16;
+	static GUID Filter_Baseline_PermitEndpoint(size_t index);
 
 	static const GUID &Filter_Baseline_PermitVpnTunnel_Outbound_Ipv4();
 	static const GUID &Filter_Baseline_PermitVpnTunnel_Outbound_Ipv6();
@@ -77,6 +99,12 @@ public:
 	static const GUID &Filter_Baseline_PermitVpnTunnelService_Ipv4();
 	static const GUID &Filter_Baseline_PermitVpnTunnelService_Ipv6();
 
+	static const GUID &Filter_Baseline_BlockTunnelProtocols_Ipv6();
+	static const GUID &Filter_Baseline_BlockTunnelProtocols_Udp_Ipv4();
+	static const GUID &Filter_Baseline_BlockTunnelProtocols_Udp_Ipv6();
+	static const GUID &Filter_Baseline_BlockTunnelProtocols_Smb_Ipv4();
+	static const GUID &Filter_Baseline_BlockTunnelProtocols_Smb_Ipv6();
+
 	static const GUID &Filter_Baseline_PermitNdp_Outbound_Router_Solicitation();
 	static const GUID &Filter_Baseline_PermitNdp_Inbound_Router_Advertisement();
 	static const GUID &Filter_Baseline_PermitNdp_Outbound_Neighbor_Solicitation();
@@ -0,0 +1,169 @@
+#include "stdafx.h"
+#include "blocktunnelprotocols.h"
+#include <winfw/mullvadguids.h>
+#include <libwfp/filterbuilder.h>
+#include <libwfp/conditionbuilder.h>
+#include <libwfp/conditions/conditioninterface.h>
+#include <libwfp/conditions/conditionport.h>
+#include <libwfp/conditions/conditionprotocol.h>
+
+using namespace wfp::conditions;
+
+namespace rules::baseline
+{
+
+namespace
+{
+
+const uint16_t NETBIOS_SESSION_PORT = 139;
+const uint16_t SMB_PORT = 445;
+
+} // anonymous namespace
+
+BlockTunnelProtocols::BlockTunnelProtocols(
+	const std::wstring &tunnelInterfaceAlias,
+	const WinFwBlockedTunnelProtocols &blockedProtocols
+)
+	: m_tunnelInterfaceAlias(tunnelInterfaceAlias)
+	, m_blockedProtocols(blockedProtocols)
+{
+}
+
+bool BlockTunnelProtocols::apply(IObjectInstaller &objectInstaller)
+{
+	//
+	// These filters use a higher weight than the filters that permit traffic on the
+	// tunnel interface, so that they take precedence within the baseline sublayer.
+	//
+
+	return (!m_blockedProtocols.ipv6 || applyIpv6(objectInstaller))
+		&& (!m_blockedProtocols.udp || applyUdp(objectInstaller))
+		&& (!m_blockedProtocols.smb || applySmb(objectInstaller));
+}
+
+bool BlockTunnelProtocols::applyIpv6(IObjectInstaller &objectInstaller) const
+{
+	wfp::FilterBuilder filterBuilder;
+
+	//
+	// #1 Block outbound connections on tunnel interface, IPv6.
+	//
+
+	filterBuilder
+		.key(MullvadGuids::Filter_Baseline_BlockTunnelProtocols_Ipv6())
+		.name(L"Block outbound IPv6 connections on tunnel interface")
+		.description(L"This filter is part of a rule that blocks selected protocols inside the VPN tunnel")
+		.provider(MullvadGuids::Provider())
+		.layer(FWPM_LAYER_ALE_AUTH_CONNECT_V6)
+		.sublayer(MullvadGuids::SublayerBaseline())
+		.weight(wfp::FilterBuilder::WeightClass::Max)
+		.block();
+
+	wfp::ConditionBuilder conditionBuilder(FWPM_LAYER_ALE_AUTH_CONNECT_V6);
+
+	conditionBuilder.add_condition(ConditionInterface::Alias(m_tunnelInterfaceAlias));
+
+	return objectInstaller.addFilter(filterBuilder, conditionBuilder);
+}
+
+bool BlockTunnelProtocols::applyUdp(IObjectInstaller &objectInstaller) const
+{
+	wfp::FilterBuilder filterBuilder;
+
+	//
+	// #1 Block outbound UDP on tunnel interface, IPv4.
+	//
+
+	filterBuilder
+		.key(MullvadGuids::Filter_Baseline_BlockTunnelProtocols_Udp_Ipv4())
+		.name(L"Block outbound UDP on tunnel interface (IPv4)")
+		.description(L"This filter is part of a rule that blocks selected protocols inside the VPN tunnel")
+		.provider(MullvadGuids::Provider())
+		.layer(FWPM_LAYER_ALE_AUTH_CONNECT_V4)
+		.sublayer(MullvadGuids::SublayerBaseline())
+		.weight(wfp::FilterBuilder::WeightClass::Max)
+		.block();
+
+	{
+		wfp::ConditionBuilder conditionBuilder(FWPM_LAYER_ALE_AUTH_CONNECT_V4);
+
+		conditionBuilder.add_condition(ConditionInterface::Alias(m_tunnelInterfaceAlias));
+		conditionBuilder.add_condition(ConditionProtocol::Udp());
+
+		if (!objectInstaller.addFilter(filterBuilder, conditionBuilder))
+		{
+			return false;
+		}
+	}
+
+	//
+	// #2 Block outbound UDP on tunnel interface, IPv6.
+	//
+
+	filterBuilder
+		.key(MullvadGuids::Filter_Baseline_BlockTunnelProtocols_Udp_Ipv6())
+		.name(L"Block outbound UDP on tunnel interface (IPv6)")
+		.layer(FWPM_LAYER_ALE_AUTH_CONNECT_V6);
+
+	wfp::ConditionBuilder conditionBuilder(FWPM_LAYER_ALE_AUTH_CONNECT_V6);
+
+	conditionBuilder.add_condition(ConditionInterface::Alias(m_tunnelInterfaceAlias));
+	conditionBuilder.add_condition(ConditionProtocol::Udp());
+
+	return objectInstaller.addFilter(filterBuilder, conditionBuilder);
+}
+
+bool BlockTunnelProtocols::applySmb(IObjectInstaller &objectInstaller) const
+{
+	wfp::FilterBuilder filterBuilder;
+
+	//
+	// #1 Block outbound SMB on tunnel interface, IPv4.
+	//
+	// Conditions on the same field are OR'ed, so this matches either port.
+	//
+
+	filterBuilder
+		.key(MullvadGuids::Filter_Baseline_BlockTunnelProtocols_Smb_Ipv4())
+		.name(L"Block outbound SMB on tunnel interface (IPv4)")
+		.description(L"This filter is part of a rule that blocks selected protocols inside the VPN tunnel")
+		.provider(MullvadGuids::Provider())
+		.layer(FWPM_LAYER_ALE_AUTH_CONNECT_V4)
+		.sublayer(MullvadGuids::SublayerBaseline())
+		.weight(wfp::FilterBuilder::WeightClass::Max)
+		.block();
+
+	{
+		wfp::ConditionBuilder conditionBuilder(FWPM_LAYER_ALE_AUTH_CONNECT_V4);
+
+		conditionBuilder.add_condition(ConditionInterface::Alias(m_tunnelInterfaceAlias));
+		conditionBuilder.add_condition(ConditionProtocol::Tcp());
+		conditionBuilder.add_condition(ConditionPort::Remote(SMB_PORT));
+		conditionBuilder.add_condition(ConditionPort::Remote(NETBIOS_SESSION_PORT));
+
+		if (!objectInstaller.addFilter(filterBuilder, conditionBuilder))
+		{
+			return false;
+		}
+	}
+
+	//
+	// #2 Block outbound SMB on tunnel interface, IPv6.
+	//
+
+	filterBuilder
+		.key(MullvadGuids::Filter_Baseline_BlockTunnelProtocols_Smb_Ipv6())
+		.name(L"Block outbound SMB on tunnel interface (IPv6)")
+		.layer(FWPM_LAYER_ALE_AUTH_CONNECT_V6);
+
+	wfp::ConditionBuilder conditionBuilder(FWPM_LAYER_ALE_AUTH_CONNECT_V6);
+
+	conditionBuilder.add_condition(ConditionInterface::Alias(m_tunnelInterfaceAlias));
+	conditionBuilder.add_condition(ConditionProtocol::Tcp());
+	conditionBuilder.add_condition(ConditionPort::Remote(SMB_PORT));
+	conditionBuilder.add_condition(ConditionPort::Remote(NETBIOS_SESSION_PORT));
+
+	return objectInstaller.addFilter(filterBuilder, conditionBuilder);
+}
+
+}
@@ -0,0 +1,31 @@
+#pragma once
+
+#include <winfw/rules/ifirewallrule.h>
+#include <winfw/winfw.h>
+#include <string>
+
+namespace rules::baseline
+{
+
+class BlockTunnelProtocols : public IFirewallRule
+{
+public:
+
+	BlockTunnelProtocols(
+		const std::wstring &tunnelInterfaceAlias,
+		const WinFwBlockedTunnelProtocols &blockedProtocols
+	);
+
+	bool apply(IObjectInstaller &objectInstaller) override;
+
+private:
+
+	bool applyIpv6(IObjectInstaller &objectInstaller) const;
+	bool applyUdp(IObjectInstaller &objectInstaller) const;
+	bool applySmb(IObjectInstaller &objectInstaller) const;
+
+	const std::wstring m_tunnelInterfaceAlias;
+	const WinFwBlockedTunnelProtocols m_blockedProtocols;
+};
+
+}
@@ -35,19 +35,33 @@ const GUID &OutboundLayerFromIp(const wfp::IpAddress &ip)
 
 PermitEndpoint::PermitEndpoint
 (
-	const wfp::IpAddress &address,
-	const std::vector<std::wstring> &clients,
-	uint16_t port,
-	WinFwProtocol protocol
+	const std::vector<Endpoint> &endpoints,
+	const std::vector<std::wstring> &clients
 )
-	: m_address(address)
+	: m_endpoints(endpoints)
 	, m_clients(clients)
-	, m_port(port)
-	, m_protocol(protocol)
 {
 }
 
 bool PermitEndpoint::apply(IObjectInstaller &objectInstaller)
+{
+	if (m_endpoints.size() > MullvadGuids::NumPermitEndpointFilters)
+	{
+		THROW_ERROR("Too many allowed endpoints");
+	}
+
+	for (size_t i = 0; i < m_endpoints.size(); i++)
+	{
+		if (!applyEndpoint(objectInstaller, m_endpoints[i], i))
+		{
+			return false;
+		}
+	}
+
+	return true;
+}
+
+bool PermitEndpoint::applyEndpoint(IObjectInstaller &objectInstaller, const Endpoint &endpoint, size_t filterIndex) const
 {
 	wfp::FilterBuilder filterBuilder;
 
@@ -56,22 +70,36 @@ bool PermitEndpoint::apply(IObjectInstaller &objectInstaller)
 	//
 
 	filterBuilder
-		.key(MullvadGuids::Filter_Baseline_PermitEndpoint())
+		.key(MullvadGuids::Filter_Baseline_PermitEndpoint(filterIndex))
 		.name(L"Permit outbound connections to a given endpoint")
 		.description(L"This filter is part of a rule that permits traffic to a specific endpoint")
 		.provider(MullvadGuids::Provider())
-		.layer(OutboundLayerFromIp(m_address))
+		.layer(OutboundLayerFromIp(endpoint.address))
 		.sublayer(MullvadGuids::SublayerBaseline())
 		.weight(wfp::FilterBuilder::WeightClass::Max)
 		.permit();
 
-	wfp::ConditionBuilder conditionBuilder(OutboundLayerFromIp(m_address));
+	wfp::ConditionBuilder conditionBuilder(OutboundLayerFromIp(endpoint.address));
 
-	conditionBuilder.add_condition(ConditionIp::Remote(m_address));
-	conditionBuilder.add_condition(ConditionPort::Remote(m_port));
-	conditionBuilder.add_condition(CreateProtocolCondition(m_protocol));
+	conditionBuilder.add_condition(ConditionIp::Remote(endpoint.address));
+	conditionBuilder.add_condition(CreateRemotePortCondition(endpoint.portStart, endpoint.portEnd));
 
-	for (const auto client : m_clients) {
+	if (endpoint.protocol.has_value())
+	{
+		conditionBuilder.add_condition(CreateProtocolCondition(endpoint.protocol.value()));
+	}
+	else
+	{
+		//
+		// Conditions on the same field are OR'ed together.
+		//
+
+		conditionBuilder.add_condition(ConditionProtocol::Tcp());
+		conditionBuilder.add_condition(ConditionProtocol::Udp());
+	}
+
+	for (const auto &client : m_clients)
+	{
 		conditionBuilder.add_condition(std::make_unique<ConditionApplication>(client));
 	}
 
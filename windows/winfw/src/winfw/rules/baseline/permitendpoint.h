@@ -3,6 +3,7 @@
 #include <winfw/rules/ifirewallrule.h>
 #include <winfw/winfw.h>
 #include <libwfp/ipaddress.h>
+#include <optional>
 #include <vector>
 #include <string>
 
@@ -13,22 +14,30 @@ class PermitEndpoint : public IFirewallRule
 {
 public:
 
+	struct Endpoint
+	{
+		wfp::IpAddress address;
+		uint16_t portStart;
+		uint16_t portEnd;
+
+		// Both TCP and UDP are permitted if no protocol is specified.
+		std::optional<WinFwProtocol> protocol;
+	};
+
 	PermitEndpoint
 	(
-		const wfp::IpAddress &address,
-		const std::vector<std::wstring> &clients,
-		uint16_t port,
-		WinFwProtocol protocol
+		const std::vector<Endpoint> &endpoints,
+		const std::vector<std::wstring> &clients
 	);
 	
 	bool apply(IObjectInstaller &objectInstaller) override;
 
 private:
 
-	const wfp::IpAddress m_address;
+	bool applyEndpoint(IObjectInstaller &objectInstaller, const Endpoint &endpoint, size_t filterIndex) const;
+
+	const std::vector<Endpoint> m_endpoints;
 	const std::vector<std::wstring> m_clients;
-	const uint16_t m_port;
-	const WinFwProtocol m_protocol;
 };
 
 }
@@ -0,0 +1,140 @@
+#include "stdafx.h"
+#include "permitrecoverynetworks.h"
+#include <winfw/mullvadguids.h>
+#include <libwfp/filterbuilder.h>
+#include <libwfp/conditionbuilder.h>
+#include <libwfp/conditions/conditionip.h>
+
+using namespace wfp::conditions;
+
+namespace rules::baseline
+{
+
+PermitRecoveryNetworks::PermitRecoveryNetworks
+(
+	const std::vector<wfp::IpNetwork> &ipv4Networks,
+	const std::vector<wfp::IpNetwork> &ipv6Networks
+)
+	: m_ipv4Networks(ipv4Networks)
+	, m_ipv6Networks(ipv6Networks)
+{
+}
+
+bool PermitRecoveryNetworks::apply(IObjectInstaller &objectInstaller)
+{
+	return applyIpv4(objectInstaller) && applyIpv6(objectInstaller);
+}
+
+bool PermitRecoveryNetworks::applyIpv4(IObjectInstaller &objectInstaller) const
+{
+	if (m_ipv4Networks.empty())
+	{
+		return true;
+	}
+
+	wfp::FilterBuilder filterBuilder;
+
+	//
+	// #1 Permit outbound connections to recovery networks.
+	//
+
+	filterBuilder
+		.key(MullvadGuids::Filter_Baseline_PermitRecoveryNetworks_Outbound_Ipv4())
+		.name(L"Permit outbound connections to recovery networks (IPv4)")
+		.description(L"This filter is part of a rule that permits traffic to and from user-specified networks in the blocked state")
+		.provider(MullvadGuids::Provider())
+		.layer(FWPM_LAYER_ALE_AUTH_CONNECT_V4)
+		.sublayer(MullvadGuids::SublayerBaseline())
+		.weight(wfp::FilterBuilder::WeightClass::Medium)
+		.permit();
+
+	{
+		wfp::ConditionBuilder conditionBuilder(FWPM_LAYER_ALE_AUTH_CONNECT_V4);
+
+		for (const auto &network : m_ipv4Networks)
+		{
+			conditionBuilder.add_condition(ConditionIp::Remote(network));
+		}
+
+		if (!objectInstaller.addFilter(filterBuilder, conditionBuilder))
+		{
+			return false;
+		}
+	}
+
+	//
+	// #2 Permit inbound connections from recovery networks.
+	//
+
+	filterBuilder
+		.key(MullvadGuids::Filter_Baseline_PermitRecoveryNetworks_Inbound_Ipv4())
+		.name(L"Permit inbound connections from recovery networks (IPv4)")
+		.layer(FWPM_LAYER_ALE_AUTH_RECV_ACCEPT_V4);
+
+	wfp::ConditionBuilder conditionBuilder(FWPM_LAYER_ALE_AUTH_RECV_ACCEPT_V4);
+
+	for (const auto &network : m_ipv4Networks)
+	{
+		conditionBuilder.add_condition(ConditionIp::Remote(network));
+	}
+
+	return objectInstaller.addFilter(filterBuilder, conditionBuilder);
+}
+
+bool PermitRecoveryNetworks::applyIpv6(IObjectInstaller &objectInstaller) const
+{
+	if (m_ipv6Networks.empty())
+	{
+		return true;
+	}
+
+	wfp::FilterBuilder filterBuilder;
+
+	//
+	// #1 Permit outbound connections to recovery networks.
+	//
+
+	filterBuilder
+		.key(MullvadGuids::Filter_Baseline_PermitRecoveryNetworks_Outbound_Ipv6())
+		.name(L"Permit outbound connections to recovery networks (IPv6)")
+		.description(L"This filter is part of a rule that permits traffic to and from user-specified networks in the blocked state")
+		.provider(MullvadGuids::Provider())
+		.layer(FWPM_LAYER_ALE_AUTH_CONNECT_V6)
+		.sublayer(MullvadGuids::SublayerBaseline())
+		.weight(wfp::FilterBuilder::WeightClass::Medium)
+		.permit();
+
+	{
+		wfp::ConditionBuilder conditionBuilder(FWPM_LAYER_ALE_AUTH_CONNECT_V6);
+
+		for (const auto &network : m_ipv6Networks)
+		{
+			conditionBuilder.add_condition(ConditionIp::Remote(network));
+		}
+
+		if (!objectInstaller.addFilter(filterBuilder, conditionBuilder))
+		{
+			return false;
+		}
+	}
+
+	//
+	// #2 Permit inbound connections from recovery networks.
+	//
+
+	filterBuilder
+		.key(MullvadGuids::Filter_Baseline_PermitRecoveryNetworks_Inbound_Ipv6())
+		.name(L"Permit inbound connections from recovery networks (IPv6)")
+		.layer(FWPM_LAYER_ALE_AUTH_RECV_ACCEPT_V6);
+
+	wfp::ConditionBuilder conditionBuilder(FWPM_LAYER_ALE_AUTH_RECV_ACCEPT_V6);
+
+	for (const auto &network : m_ipv6Networks)
+	{
+		conditionBuilder.add_condition(ConditionIp::Remote(network));
+	}
+
+	return objectInstaller.addFilter(filterBuilder, conditionBuilder);
+}
+
+}
@@ -0,0 +1,32 @@
+#pragma once
+
+#include <winfw/rules/ifirewallrule.h>
+#include <libwfp/ipnetwork.h>
+#include <vector>
+
+namespace rules::baseline
+{
+
+class PermitRecoveryNetworks : public IFirewallRule
+{
+public:
+
+	PermitRecoveryNetworks
+	(
+		const std::vector<wfp::IpNetwork> &ipv4Networks,
+		const std::vector<wfp::IpNetwork> &ipv6Networks
+	);
+	~PermitRecoveryNetworks() = default;
+
+	bool apply(IObjectInstaller &objectInstaller) override;
+
+private:
+
+	bool applyIpv4(IObjectInstaller &objectInstaller) const;
+	bool applyIpv6(IObjectInstaller &objectInstaller) const;
+
+	std::vector<wfp::IpNetwork> m_ipv4Networks;
+	std::vector<wfp::IpNetwork> m_ipv6Networks;
+};
+
+}
@@ -0,0 +1,178 @@
+#include "stdafx.h"
+#include "permitvpncoexistence.h"
+#include <winfw/mullvadguids.h>
+#include <libwfp/filterbuilder.h>
+#include <libwfp/conditionbuilder.h>
+#include <libwfp/conditions/conditionip.h>
+#include <libwfp/conditions/conditionport.h>
+#include <libwfp/conditions/conditionprotocol.h>
+
+using namespace wfp::conditions;
+
+namespace rules::baseline
+{
+
+PermitVpnCoexistence::PermitVpnCoexistence
+(
+	const std::vector<wfp::IpNetwork> &ipv4Networks,
+	const std::vector<wfp::IpNetwork> &ipv6Networks,
+	const std::vector<uint16_t> &udpPorts
+)
+	: m_ipv4Networks(ipv4Networks)
+	, m_ipv6Networks(ipv6Networks)
+	, m_udpPorts(udpPorts)
+{
+}
+
+bool PermitVpnCoexistence::apply(IObjectInstaller &objectInstaller)
+{
+	return applyNetworks(objectInstaller, m_ipv4Networks, true)
+		&& applyNetworks(objectInstaller, m_ipv6Networks, false)
+		&& applyPorts(objectInstaller, true)
+		&& applyPorts(objectInstaller, false);
+}
+
+bool PermitVpnCoexistence::applyNetworks
+(
+	IObjectInstaller &objectInstaller,
+	const std::vector<wfp::IpNetwork> &networks,
+	bool ipv4
+) const
+{
+	if (networks.empty())
+	{
+		return true;
+	}
+
+	const auto connectLayer = ipv4 ? FWPM_LAYER_ALE_AUTH_CONNECT_V4 : FWPM_LAYER_ALE_AUTH_CONNECT_V6;
+	const auto acceptLayer = ipv4 ? FWPM_LAYER_ALE_AUTH_RECV_ACCEPT_V4 : FWPM_LAYER_ALE_AUTH_RECV_ACCEPT_V6;
+
+	wfp::FilterBuilder filterBuilder;
+
+	//
+	// #1 Permit outbound connections to networks of the other VPN.
+	//
+
+	filterBuilder
+		.key(ipv4
+			? MullvadGuids::Filter_Baseline_PermitVpnCoexistence_Networks_Outbound_Ipv4()
+			: MullvadGuids::Filter_Baseline_PermitVpnCoexistence_Networks_Outbound_Ipv6())
+		.name(ipv4
+			? L"Permit outbound connections to networks of another VPN (IPv4)"
+			: L"Permit outbound connections to networks of another VPN (IPv6)")
+		.description(L"This filter is part of a rule that permits the traffic of another VPN running alongside this one")
+		.provider(MullvadGuids::Provider())
+		.layer(connectLayer)
+		.sublayer(MullvadGuids::SublayerBaseline())
+		.weight(wfp::FilterBuilder::WeightClass::Medium)
+		.permit();
+
+	{
+		wfp::ConditionBuilder conditionBuilder(connectLayer);
+
+		for (const auto &network : networks)
+		{
+			conditionBuilder.add_condition(ConditionIp::Remote(network));
+		}
+
+		if (!objectInstaller.addFilter(filterBuilder, conditionBuilder))
+		{
+			return false;
+		}
+	}
+
+	//
+	// #2 Permit inbound connections from networks of the other VPN.
+	//
+
+	filterBuilder
+		.key(ipv4
+			? MullvadGuids::Filter_Baseline_PermitVpnCoexistence_Networks_Inbound_Ipv4()
+			: MullvadGuids::Filter_Baseline_PermitVpnCoexistence_Networks_Inbound_Ipv6())
+		.name(ipv4
+			? L"Permit inbound connections from networks of another VPN (IPv4)"
+			: L"Permit inbound connections from networks of another VPN (IPv6)")
+		.layer(acceptLayer);
+
+	wfp::ConditionBuilder conditionBuilder(acceptLayer);
+
+	for (const auto &network : networks)
+	{
+		conditionBuilder.add_condition(ConditionIp::Remote(network));
+	}
+
+	return objectInstaller.addFilter(filterBuilder, conditionBuilder);
+}
+
+bool PermitVpnCoexistence::applyPorts(IObjectInstaller &objectInstaller, bool ipv4) const
+{
+	if (m_udpPorts.empty())
+	{
+		return true;
+	}
+
+	const auto connectLayer = ipv4 ? FWPM_LAYER_ALE_AUTH_CONNECT_V4 : FWPM_LAYER_ALE_AUTH_CONNECT_V6;
+	const auto acceptLayer = ipv4 ? FWPM_LAYER_ALE_AUTH_RECV_ACCEPT_V4 : FWPM_LAYER_ALE_AUTH_RECV_ACCEPT_V6;
+
+	wfp::FilterBuilder filterBuilder;
+
+	//
+	// #1 Permit outbound UDP traffic sent from the ports of the other VPN.
+	//
+
+	filterBuilder
+		.key(ipv4
+			? MullvadGuids::Filter_Baseline_PermitVpnCoexistence_Ports_Outbound_Ipv4()
+			: MullvadGuids::Filter_Baseline_PermitVpnCoexistence_Ports_Outbound_Ipv6())
+		.name(ipv4
+			? L"Permit outbound UDP traffic of another VPN (IPv4)"
+			: L"Permit outbound UDP traffic of another VPN (IPv6)")
+		.description(L"This filter is part of a rule that permits the traffic of another VPN running alongside this one")
+		.provider(MullvadGuids::Provider())
+		.layer(connectLayer)
+		.sublayer(MullvadGuids::SublayerBaseline())
+		.weight(wfp::FilterBuilder::WeightClass::Medium)
+		.permit();
+
+	{
+		wfp::ConditionBuilder conditionBuilder(connectLayer);
+
+		conditionBuilder.add_condition(ConditionProtocol::Udp());
+
+		for (const auto port : m_udpPorts)
+		{
+			conditionBuilder.add_condition(ConditionPort::Local(port));
+		}
+
+		if (!objectInstaller.addFilter(filterBuilder, conditionBuilder))
+		{
+			return false;
+		}
+	}
+
+	//
+	// #2 Permit inbound UDP traffic received on the ports of the other VPN.
+	//
+
+	filterBuilder
+		.key(ipv4
+			? MullvadGuids::Filter_Baseline_PermitVpnCoexistence_Ports_Inbound_Ipv4()
+			: MullvadGuids::Filter_Baseline_PermitVpnCoexistence_Ports_Inbound_Ipv6())
+		.name(ipv4
+			? L"Permit inbound UDP traffic of another VPN (IPv4)"
+			: L"Permit inbound UDP traffic of another VPN (IPv6)")
+		.layer(acceptLayer);
+
+	wfp::ConditionBuilder conditionBuilder(acceptLayer);
+
+	conditionBuilder.add_condition(ConditionProtocol::Udp());
+
+	for (const auto port : m_udpPorts)
+	{
+		conditionBuilder.add_condition(ConditionPort::Local(port));
+	}
+
+	return objectInstaller.addFilter(filterBuilder, conditionBuilder);
+}
+
+}
@@ -0,0 +1,41 @@
+#pragma once
+
+#include <winfw/rules/ifirewallrule.h>
+#include <libwfp/ipnetwork.h>
+#include <cstdint>
+#include <vector>
+
+namespace rules::baseline
+{
+
+class PermitVpnCoexistence : public IFirewallRule
+{
+public:
+
+	PermitVpnCoexistence
+	(
+		const std::vector<wfp::IpNetwork> &ipv4Networks,
+		const std::vector<wfp::IpNetwork> &ipv6Networks,
+		const std::vector<uint16_t> &udpPorts
+	);
+	~PermitVpnCoexistence() = default;
+
+	bool apply(IObjectInstaller &objectInstaller) override;
+
+private:
+
+	bool applyNetworks
+	(
+		IObjectInstaller &objectInstaller,
+		const std::vector<wfp::IpNetwork> &networks,
+		bool ipv4
+	) const;
+
+	bool applyPorts(IObjectInstaller &objectInstaller, bool ipv4) const;
+
+	std::vector<wfp::IpNetwork> m_ipv4Networks;
+	std::vector<wfp::IpNetwork> m_ipv6Networks;
+	std::vector<uint16_t> m_udpPorts;
+};
+
+}
@@ -15,12 +15,47 @@ using namespace wfp::conditions;
 namespace rules::baseline
 {
 
+bool PermitVpnTunnel::Endpoints::includes(wfp::IpAddress::Type type) const
+{
+	for (const auto &host : hosts)
+	{
+		if (host.type() == type)
+		{
+			return true;
+		}
+	}
+
+	return false;
+}
+
+void PermitVpnTunnel::Endpoints::addConditions(wfp::ConditionBuilder &conditionBuilder, wfp::IpAddress::Type type) const
+{
+	//
+	// Conditions on the same field are OR'ed, and conditions on different fields are AND'ed.
+	//
+
+	for (const auto &host : hosts)
+	{
+		if (host.type() == type)
+		{
+			conditionBuilder.add_condition(ConditionIp::Remote(host));
+		}
+	}
+
+	conditionBuilder.add_condition(ConditionPort::Remote(port));
+
+	if (protocol.has_value())
+	{
+		conditionBuilder.add_condition(CreateProtocolCondition(*protocol));
+	}
+}
+
 PermitVpnTunnel::PermitVpnTunnel(
 	const std::wstring &tunnelInterfaceAlias,
-	const std::optional<Endpoint> &onlyEndpoint
+	const std::optional<Endpoints> &onlyEndpoints
 )
 	: m_tunnelInterfaceAlias(tunnelInterfaceAlias)
-	, m_tunnelOnlyEndpoint(onlyEndpoint)
+	, m_tunnelOnlyEndpoints(onlyEndpoints)
 {
 }
 
@@ -28,8 +63,8 @@ bool PermitVpnTunnel::apply(IObjectInstaller &objectInstaller)
 {
 	wfp::FilterBuilder filterBuilder;
 
-	bool includeV4 = !m_tunnelOnlyEndpoint.has_value() || m_tunnelOnlyEndpoint->ip.type() == wfp::IpAddress::Ipv4;
-	bool includeV6 = !m_tunnelOnlyEndpoint.has_value() || m_tunnelOnlyEndpoint->ip.type() == wfp::IpAddress::Ipv6;
+	bool includeV4 = !m_tunnelOnlyEndpoints.has_value() || m_tunnelOnlyEndpoints->includes(wfp::IpAddress::Ipv4);
+	bool includeV6 = !m_tunnelOnlyEndpoints.has_value() || m_tunnelOnlyEndpoints->includes(wfp::IpAddress::Ipv6);
 
 	//
 	// #1 Permit outbound connections, IPv4.
@@ -51,11 +86,9 @@ bool PermitVpnTunnel::apply(IObjectInstaller &objectInstaller)
 
 		conditionBuilder.add_condition(ConditionInterface::Alias(m_tunnelInterfaceAlias));
 
-		if (m_tunnelOnlyEndpoint.has_value())
+		if (m_tunnelOnlyEndpoints.has_value())
 		{
-			conditionBuilder.add_condition(ConditionIp::Remote(m_tunnelOnlyEndpoint->ip));
-			conditionBuilder.add_condition(ConditionPort::Remote(m_tunnelOnlyEndpoint->port));
-			conditionBuilder.add_condition(CreateProtocolCondition(m_tunnelOnlyEndpoint->protocol));
+			m_tunnelOnlyEndpoints->addConditions(conditionBuilder, wfp::IpAddress::Ipv4);
 		}
 
 		if (!objectInstaller.addFilter(filterBuilder, conditionBuilder))
@@ -79,11 +112,9 @@ bool PermitVpnTunnel::apply(IObjectInstaller &objectInstaller)
 
 		conditionBuilder.add_condition(ConditionInterface::Alias(m_tunnelInterfaceAlias));
 
-		if (m_tunnelOnlyEndpoint.has_value())
+		if (m_tunnelOnlyEndpoints.has_value())
 		{
-			conditionBuilder.add_condition(ConditionIp::Remote(m_tunnelOnlyEndpoint->ip));
-			conditionBuilder.add_condition(ConditionPort::Remote(m_tunnelOnlyEndpoint->port));
-			conditionBuilder.add_condition(CreateProtocolCondition(m_tunnelOnlyEndpoint->protocol));
+			m_tunnelOnlyEndpoints->addConditions(conditionBuilder, wfp::IpAddress::Ipv6);
 		}
 
 		return objectInstaller.addFilter(filterBuilder, conditionBuilder);
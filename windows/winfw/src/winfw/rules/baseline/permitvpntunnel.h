@@ -3,8 +3,10 @@
 #include <winfw/rules/ifirewallrule.h>
 #include <winfw/winfw.h>
 #include <libwfp/ipaddress.h>
+#include <libwfp/conditionbuilder.h>
 #include <string>
 #include <optional>
+#include <vector>
 
 namespace rules::baseline
 {
@@ -13,15 +15,22 @@ class PermitVpnTunnel : public IFirewallRule
 {
 public:
 
-	struct Endpoint {
-		wfp::IpAddress ip;
+	//
+	// Traffic to the given port on any of the given hosts.
+	// Both TCP and UDP are matched if no protocol is specified.
+	//
+	struct Endpoints {
+		std::vector<wfp::IpAddress> hosts;
 		uint16_t port;
-		WinFwProtocol protocol;
+		std::optional<WinFwProtocol> protocol;
+
+		bool includes(wfp::IpAddress::Type type) const;
+		void addConditions(wfp::ConditionBuilder &conditionBuilder, wfp::IpAddress::Type type) const;
 	};
 
 	PermitVpnTunnel(
 		const std::wstring &tunnelInterfaceAlias,
-		const std::optional<Endpoint> &onlyEndpoint
+		const std::optional<Endpoints> &onlyEndpoints
 	);
 	
 	bool apply(IObjectInstaller &objectInstaller) override;
@@ -29,7 +38,7 @@ public:
 private:
 
 	const std::wstring m_tunnelInterfaceAlias;
-	const std::optional<Endpoint> m_tunnelOnlyEndpoint;
+	const std::optional<Endpoints> m_tunnelOnlyEndpoints;
 };
 
 }
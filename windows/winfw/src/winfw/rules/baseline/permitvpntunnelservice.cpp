@@ -17,10 +17,10 @@ namespace rules::baseline
 
 PermitVpnTunnelService::PermitVpnTunnelService(
 	const std::wstring &tunnelInterfaceAlias,
-	const std::optional<PermitVpnTunnel::Endpoint> &onlyEndpoint
+	const std::optional<PermitVpnTunnel::Endpoints> &onlyEndpoints
 )
 	: m_tunnelInterfaceAlias(tunnelInterfaceAlias)
-	, m_tunnelOnlyEndpoint(onlyEndpoint)
+	, m_tunnelOnlyEndpoints(onlyEndpoints)
 {
 }
 
@@ -28,8 +28,8 @@ bool PermitVpnTunnelService::apply(IObjectInstaller &objectInstaller)
 {
 	wfp::FilterBuilder filterBuilder;
 
-	bool includeV4 = !m_tunnelOnlyEndpoint.has_value() || m_tunnelOnlyEndpoint->ip.type() == wfp::IpAddress::Ipv4;
-	bool includeV6 = !m_tunnelOnlyEndpoint.has_value() || m_tunnelOnlyEndpoint->ip.type() == wfp::IpAddress::Ipv6;
+	bool includeV4 = !m_tunnelOnlyEndpoints.has_value() || m_tunnelOnlyEndpoints->includes(wfp::IpAddress::Ipv4);
+	bool includeV6 = !m_tunnelOnlyEndpoints.has_value() || m_tunnelOnlyEndpoints->includes(wfp::IpAddress::Ipv6);
 
 	//
 	// #1 Permit inbound connections, IPv4.
@@ -51,11 +51,9 @@ bool PermitVpnTunnelService::apply(IObjectInstaller &objectInstaller)
 	{
 		conditionBuilder.add_condition(ConditionInterface::Alias(m_tunnelInterfaceAlias));
 
-		if (m_tunnelOnlyEndpoint.has_value())
+		if (m_tunnelOnlyEndpoints.has_value())
 		{
-			conditionBuilder.add_condition(ConditionIp::Remote(m_tunnelOnlyEndpoint->ip));
-			conditionBuilder.add_condition(ConditionPort::Remote(m_tunnelOnlyEndpoint->port));
-			conditionBuilder.add_condition(CreateProtocolCondition(m_tunnelOnlyEndpoint->protocol));
+			m_tunnelOnlyEndpoints->addConditions(conditionBuilder, wfp::IpAddress::Ipv4);
 		}
 
 		if (!objectInstaller.addFilter(filterBuilder, conditionBuilder))
@@ -78,11 +76,9 @@ bool PermitVpnTunnelService::apply(IObjectInstaller &objectInstaller)
 		conditionBuilder.reset(FWPM_LAYER_ALE_AUTH_RECV_ACCEPT_V6);
 		conditionBuilder.add_condition(ConditionInterface::Alias(m_tunnelInterfaceAlias));
 
-		if (m_tunnelOnlyEndpoint.has_value())
+		if (m_tunnelOnlyEndpoints.has_value())
 		{
-			conditionBuilder.add_condition(ConditionIp::Remote(m_tunnelOnlyEndpoint->ip));
-			conditionBuilder.add_condition(ConditionPort::Remote(m_tunnelOnlyEndpoint->port));
-			conditionBuilder.add_condition(CreateProtocolCondition(m_tunnelOnlyEndpoint->protocol));
+			m_tunnelOnlyEndpoints->addConditions(conditionBuilder, wfp::IpAddress::Ipv6);
 		}
 
 		return objectInstaller.addFilter(filterBuilder, conditionBuilder);
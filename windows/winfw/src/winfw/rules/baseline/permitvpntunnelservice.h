@@ -16,7 +16,7 @@ public:
 
 	PermitVpnTunnelService(
 		const std::wstring &tunnelInterfaceAlias,
-		const std::optional<PermitVpnTunnel::Endpoint> &onlyEndpoint
+		const std::optional<PermitVpnTunnel::Endpoints> &onlyEndpoints
 	);
 
 	bool apply(IObjectInstaller &objectInstaller) override;
@@ -24,7 +24,7 @@ public:
 private:
 
 	const std::wstring m_tunnelInterfaceAlias;
-	const std::optional<PermitVpnTunnel::Endpoint> m_tunnelOnlyEndpoint;
+	const std::optional<PermitVpnTunnel::Endpoints> m_tunnelOnlyEndpoints;
 };
 
 }
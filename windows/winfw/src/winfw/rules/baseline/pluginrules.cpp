@@ -0,0 +1,154 @@
+#include "stdafx.h"
+#include "pluginrules.h"
+#include <winfw/mullvadguids.h>
+#include <libwfp/filterbuilder.h>
+#include <libwfp/conditions/conditionip.h>
+#include <libwfp/conditions/conditionport.h>
+#include <libwfp/conditions/conditionprotocol.h>
+#include <libcommon/error.h>
+#include <algorithm>
+
+using namespace wfp::conditions;
+
+namespace rules::baseline
+{
+
+PluginRules::PluginRules(const std::vector<Rule> &rules)
+{
+	for (const auto &rule : rules)
+	{
+		auto group = std::find_if(m_groups.begin(), m_groups.end(), [&rule](const Group &group)
+		{
+			return group.permit == rule.permit
+				&& group.ipv4 == rule.ipv4
+				&& group.protocol == rule.protocol
+				&& group.port == rule.port;
+		});
+
+		if (m_groups.end() == group)
+		{
+			m_groups.push_back(Group{ rule.permit, rule.ipv4, rule.protocol, rule.port, { rule.network } });
+		}
+		else
+		{
+			group->networks.push_back(rule.network);
+		}
+	}
+}
+
+bool PluginRules::apply(IObjectInstaller &objectInstaller)
+{
+	//
+	// Each group requires one outbound and one inbound filter.
+	//
+
+	if (2 * m_groups.size() > MullvadGuids::NumPluginRuleFilters)
+	{
+		THROW_ERROR("Too many distinct firewall plugin rules");
+	}
+
+	for (size_t i = 0; i < m_groups.size(); i++)
+	{
+		if (!applyGroup(objectInstaller, m_groups[i], 2 * i))
+		{
+			return false;
+		}
+	}
+
+	return true;
+}
+
+bool PluginRules::applyGroup(IObjectInstaller &objectInstaller, const Group &group, size_t filterIndex) const
+{
+	const auto connectLayer = group.ipv4 ? FWPM_LAYER_ALE_AUTH_CONNECT_V4 : FWPM_LAYER_ALE_AUTH_CONNECT_V6;
+	const auto acceptLayer = group.ipv4 ? FWPM_LAYER_ALE_AUTH_RECV_ACCEPT_V4 : FWPM_LAYER_ALE_AUTH_RECV_ACCEPT_V6;
+
+	wfp::FilterBuilder filterBuilder;
+
+	//
+	// #1 Permit or block outbound connections to the networks of the group.
+	//
+	// Block filters use a higher weight than the filters that permit traffic, so that they
+	// take precedence within the baseline sublayer.
+	//
+
+	filterBuilder
+		.key(MullvadGuids::Filter_Baseline_PluginRule(filterIndex))
+		.name(group.permit
+			? L"Permit outbound connections allowed by a firewall plugin"
+			: L"Block outbound connections blocked by a firewall plugin")
+		.description(L"This filter is part of a rule that applies the rules of signed firewall policy plugins")
+		.provider(MullvadGuids::Provider())
+		.layer(connectLayer)
+		.sublayer(MullvadGuids::SublayerBaseline())
+		.weight(group.permit
+			? wfp::FilterBuilder::WeightClass::Medium
+			: wfp::FilterBuilder::WeightClass::Max);
+
+	if (group.permit)
+	{
+		filterBuilder.permit();
+	}
+	else
+	{
+		filterBuilder.block();
+	}
+
+	{
+		wfp::ConditionBuilder conditionBuilder(connectLayer);
+
+		AddConditions(conditionBuilder, group);
+
+		if (!objectInstaller.addFilter(filterBuilder, conditionBuilder))
+		{
+			return false;
+		}
+	}
+
+	//
+	// #2 Permit or block inbound connections from the networks of the group.
+	//
+
+	filterBuilder
+		.key(MullvadGuids::Filter_Baseline_PluginRule(filterIndex + 1))
+		.name(group.permit
+			? L"Permit inbound connections allowed by a firewall plugin"
+			: L"Block inbound connections blocked by a firewall plugin")
+		.layer(acceptLayer);
+
+	wfp::ConditionBuilder conditionBuilder(acceptLayer);
+
+	AddConditions(conditionBuilder, group);
+
+	return objectInstaller.addFilter(filterBuilder, conditionBuilder);
+}
+
+//static
+void PluginRules::AddConditions(wfp::ConditionBuilder &conditionBuilder, const Group &group)
+{
+	for (const auto &network : group.networks)
+	{
+		conditionBuilder.add_condition(ConditionIp::Remote(network));
+	}
+
+	if (!group.protocol.has_value())
+	{
+		return;
+	}
+
+	if (WinFwProtocol::Tcp == group.protocol.value())
+	{
+		conditionBuilder.add_condition(ConditionProtocol::Tcp());
+	}
+	else
+	{
+		conditionBuilder.add_condition(ConditionProtocol::Udp());
+	}
+
+	if (0 != group.port)
+	{
+		conditionBuilder.add_condition(ConditionPort::Remote(group.port));
+	}
+}
+
+}
@@ -0,0 +1,56 @@
+#pragma once
+
+#include <winfw/rules/ifirewallrule.h>
+#include <winfw/winfw.h>
+#include <libwfp/conditionbuilder.h>
+#include <libwfp/ipnetwork.h>
+#include <cstdint>
+#include <optional>
+#include <vector>
+
+namespace rules::baseline
+{
+
+class PluginRules : public IFirewallRule
+{
+public:
+
+	struct Rule
+	{
+		bool permit;
+		bool ipv4;
+		wfp::IpNetwork network;
+		std::optional<WinFwProtocol> protocol;
+
+		// Zero matches any port.
+		uint16_t port;
+	};
+
+	PluginRules(const std::vector<Rule> &rules);
+	~PluginRules() = default;
+
+	bool apply(IObjectInstaller &objectInstaller) override;
+
+private:
+
+	//
+	// Rules that only differ in the remote network are grouped, so that each group can be
+	// expressed as a single filter in each layer.
+	//
+	struct Group
+	{
+		bool permit;
+		bool ipv4;
+		std::optional<WinFwProtocol> protocol;
+		uint16_t port;
+		std::vector<wfp::IpNetwork> networks;
+	};
+
+	bool applyGroup(IObjectInstaller &objectInstaller, const Group &group, size_t filterIndex) const;
+
+	static void AddConditions(wfp::ConditionBuilder &conditionBuilder, const Group &group);
+
+	std::vector<Group> m_groups;
+};
+
+}
@@ -1,6 +1,8 @@
 #include "stdafx.h"
 #include "shared.h"
+#include <libwfp/conditions/conditionport.h>
 #include <libcommon/error.h>
+#include <sstream>
 
 using namespace wfp::conditions;
 
@@ -52,4 +54,53 @@ std::unique_ptr<wfp::conditions::ConditionProtocol> CreateProtocolCondition(WinF
 	};
 }
 
+ConditionRemotePortRange::ConditionRemotePortRange(uint16_t start, uint16_t end)
+	: m_start(start)
+	, m_end(end)
+{
+	if (start > end)
+	{
+		THROW_ERROR("Invalid port range");
+	}
+
+	m_range.valueLow.type = FWP_UINT16;
+	m_range.valueLow.uint16 = start;
+	m_range.valueHigh.type = FWP_UINT16;
+	m_range.valueHigh.uint16 = end;
+
+	m_condition.fieldKey = FWPM_CONDITION_IP_REMOTE_PORT;
+	m_condition.matchType = FWP_MATCH_RANGE;
+	m_condition.conditionValue.type = FWP_RANGE_TYPE;
+	m_condition.conditionValue.rangeValue = &m_range;
+}
+
+std::wstring ConditionRemotePortRange::toString() const
+{
+	std::wstringstream ss;
+
+	ss << L"remote port in range " << m_start << L"-" << m_end;
+
+	return ss.str();
+}
+
+const GUID &ConditionRemotePortRange::identifier() const
+{
+	return FWPM_CONDITION_IP_REMOTE_PORT;
+}
+
+const FWPM_FILTER_CONDITION0 &ConditionRemotePortRange::condition() const
+{
+	return m_condition;
+}
+
+std::unique_ptr<wfp::conditions::IFilterCondition> CreateRemotePortCondition(uint16_t start, uint16_t end)
+{
+	if (start == end)
+	{
+		return ConditionPort::Remote(start);
+	}
+
+	return std::make_unique<ConditionRemotePortRange>(start, end);
+}
+
 }
@@ -4,6 +4,7 @@
 #include <memory>
 #include <winfw/winfw.h>
 #include <libwfp/conditions/conditionprotocol.h>
+#include <libwfp/conditions/ifiltercondition.h>
 #include <libwfp/ipaddress.h>
 
 namespace rules
@@ -15,4 +16,28 @@ void SplitAddresses(const IpSet &in, IpSet &outIpv4, IpSet &outIpv6);
 
 std::unique_ptr<wfp::conditions::ConditionProtocol> CreateProtocolCondition(WinFwProtocol protocol);
 
+//
+// Matches remote ports from `start` to `end`, inclusive.
+//
+class ConditionRemotePortRange : public wfp::conditions::IFilterCondition
+{
+public:
+
+	ConditionRemotePortRange(uint16_t start, uint16_t end);
+
+	std::wstring toString() const override;
+	const GUID &identifier() const override;
+	const FWPM_FILTER_CONDITION0 &condition() const override;
+
+private:
+
+	uint16_t m_start;
+	uint16_t m_end;
+
+	FWP_RANGE0 m_range;
+	FWPM_FILTER_CONDITION0 m_condition;
+};
+
+std::unique_ptr<wfp::conditions::IFilterCondition> CreateRemotePortCondition(uint16_t start, uint16_t end);
+
 }
@@ -303,7 +303,8 @@ WinFw_ApplyPolicyConnected(
 	const wchar_t *v4Gateway,
 	const wchar_t *v6Gateway,
 	const wchar_t * const *dnsServers,
-	size_t numDnsServers
+	size_t numDnsServers,
+	const WinFwBlockedTunnelProtocols *blockedProtocols
 )
 {
 	if (nullptr == g_fwContext)
@@ -343,6 +344,11 @@ WinFw_ApplyPolicyConnected(
 			THROW_ERROR("Invalid argument: dnsServers");
 		}
 
+		if (nullptr == blockedProtocols)
+		{
+			THROW_ERROR("Invalid argument: blockedProtocols");
+		}
+
 		std::vector<wfp::IpAddress> tunnelDnsServers;
 		std::vector<wfp::IpAddress> nonTunnelDnsServers;
 
@@ -413,7 +419,8 @@ WinFw_ApplyPolicyConnected(
 			relayClient,
 			tunnelInterfaceAlias,
 			tunnelDnsServers,
-			nonTunnelDnsServers
+			nonTunnelDnsServers,
+			*blockedProtocols
 		) ? WINFW_POLICY_STATUS_SUCCESS : WINFW_POLICY_STATUS_GENERAL_FAILURE;
 	}
 	catch (common::error::WindowsException &err)
@@ -440,7 +447,9 @@ WINFW_POLICY_STATUS
 WINFW_API
 WinFw_ApplyPolicyBlocked(
 	const WinFwSettings *settings,
-	const WinFwAllowedEndpoint *allowedEndpoint
+	const WinFwAllowedEndpoint *allowedEndpoint,
+	const WinFwNetwork *recoveryNetworks,
+	size_t numRecoveryNetworks
 )
 {
 	if (nullptr == g_fwContext)
@@ -455,7 +464,22 @@ WinFw_ApplyPolicyBlocked(
 			THROW_ERROR("Invalid argument: settings");
 		}
 
-		return g_fwContext->applyPolicyBlocked(*settings, MakeOptional(allowedEndpoint))
+		if (nullptr == recoveryNetworks && 0 != numRecoveryNetworks)
+		{
+			THROW_ERROR("Invalid argument: recoveryNetworks");
+		}
+
+		std::vector<wfp::IpNetwork> ipv4RecoveryNetworks;
+		std::vector<wfp::IpNetwork> ipv6RecoveryNetworks;
+
+		for (size_t i = 0; i < numRecoveryNetworks; i++)
+		{
+			const auto address = wfp::IpAddress(recoveryNetworks[i].address);
+			auto &networks = address.type() == wfp::IpAddress::Type::Ipv4 ? ipv4RecoveryNetworks : ipv6RecoveryNetworks;
+			networks.emplace_back(address, recoveryNetworks[i].prefix);
+		}
+
+		return g_fwContext->applyPolicyBlocked(*settings, MakeOptional(allowedEndpoint), ipv4RecoveryNetworks, ipv6RecoveryNetworks)
 			? WINFW_POLICY_STATUS_SUCCESS
 			: WINFW_POLICY_STATUS_GENERAL_FAILURE;
 	}
@@ -19,6 +19,43 @@
 // Structures
 ///////////////////////////////////////////////////////////////////////////////
 
+typedef struct tag_WinFwNetwork
+{
+	const wchar_t *address;
+	uint8_t prefix;
+}
+WinFwNetwork;
+
+enum WinFwProtocol : uint8_t
+{
+	Tcp = 0,
+	Udp = 1,
+};
+
+enum WinFwPluginAction : uint8_t
+{
+	PluginPermit = 0,
+	PluginBlock = 1,
+};
+
+//
+// Rule from a signed firewall policy plugin. It matches traffic to and from the network,
+// regardless of the policy.
+//
+typedef struct tag_WinFwPluginRule
+{
+	WinFwPluginAction action;
+	WinFwNetwork network;
+
+	// Whether only traffic of the given protocol is matched.
+	bool matchProtocol;
+	WinFwProtocol protocol;
+
+	// Remote port to match, if matching a protocol. Zero matches any port.
+	uint16_t port;
+}
+WinFwPluginRule;
+
 typedef struct tag_WinFwSettings
 {
 	// Permit outbound DHCP requests and inbound DHCP responses on all interfaces.
@@ -26,15 +63,22 @@ typedef struct tag_WinFwSettings
 
 	// Permit all traffic to and from private address ranges.
 	bool permitLan;
+
+	// Permit all traffic to and from networks of another VPN running alongside this one.
+	const WinFwNetwork *coexistenceNetworks;
+	size_t numCoexistenceNetworks;
+
+	// Permit UDP traffic sent from and received on these local ports, which are used by
+	// another VPN running alongside this one.
+	const uint16_t *coexistenceUdpPorts;
+	size_t numCoexistenceUdpPorts;
+
+	// Rules from firewall policy plugins. Block rules take precedence over permit rules.
+	const WinFwPluginRule *pluginRules;
+	size_t numPluginRules;
 }
 WinFwSettings;
 
-enum WinFwProtocol : uint8_t
-{
-	Tcp = 0,
-	Udp = 1,
-};
-
 typedef struct tag_WinFwEndpoint
 {
 	const wchar_t *ip;
@@ -43,15 +87,31 @@ typedef struct tag_WinFwEndpoint
 }
 WinFwEndpoint;
 
+typedef struct tag_WinFwEndpointRange
+{
+	const wchar_t *ip;
+
+	// Inclusive range of remote ports.
+	uint16_t portStart;
+	uint16_t portEnd;
+
+	// Whether only traffic of the given protocol is matched. Otherwise, both TCP and UDP
+	// are matched.
+	bool matchProtocol;
+	WinFwProtocol protocol;
+}
+WinFwEndpointRange;
+
 typedef struct tag_WinFwAllowedEndpoint
 {
 	uint32_t numClients;
 
-	// A list of paths that are allowed to reach the given endpoint,
+	// A list of paths that are allowed to reach the given endpoints,
 	// even when traffic would otherwise be blocked.
 	const wchar_t **clients;
 
-	WinFwEndpoint endpoint;
+	uint32_t numEndpoints;
+	const WinFwEndpointRange *endpoints;
 }
 WinFwAllowedEndpoint;
 
@@ -59,16 +119,43 @@ enum WinFwAllowedTunnelTrafficType : uint8_t
 {
 	None,
 	All,
-	Only
+	Only,
+	// DNS requests to the given addresses, over both TCP and UDP.
+	Dns,
+	// Traffic to the given port and protocol on any of the given addresses.
+	Endpoints
 };
 
 typedef struct tag_WinFwAllowedTunnelTraffic
 {
 	WinFwAllowedTunnelTrafficType type;
+
+	// Only used by the "Only" type.
 	WinFwEndpoint *endpoint;
+
+	// Only used by the "Dns" and "Endpoints" types.
+	const wchar_t * const *addresses;
+	size_t numAddresses;
+
+	// Only used by the "Endpoints" type.
+	uint16_t port;
+	WinFwProtocol protocol;
 }
 WinFwAllowedTunnelTraffic;
 
+typedef struct tag_WinFwBlockedTunnelProtocols
+{
+	// Block all outbound IPv6 traffic on the tunnel interface.
+	bool ipv6;
+
+	// Block all outbound UDP on the tunnel interface, including DNS.
+	bool udp;
+
+	// Block outbound SMB (TCP ports 139 and 445) on the tunnel interface.
+	bool smb;
+}
+WinFwBlockedTunnelProtocols;
+
 ///////////////////////////////////////////////////////////////////////////////
 // Functions
 ///////////////////////////////////////////////////////////////////////////////
@@ -153,7 +240,7 @@ enum WINFW_POLICY_STATUS
 // Apply restrictions in the firewall that block all traffic, except:
 // - What is specified by settings
 // - Communication with the relay server
-// - Specified in-tunnel traffic, except DNS.
+// - Specified in-tunnel traffic, except DNS unless the DNS type is used.
 //
 extern "C"
 WINFW_LINKAGE
@@ -174,7 +261,7 @@ WinFw_ApplyPolicyConnecting(
 // Apply restrictions in the firewall that block all traffic, except:
 // - What is specified by settings
 // - Communication with the relay server
-// - Non-DNS traffic inside the VPN tunnel
+// - Non-DNS traffic inside the VPN tunnel, unless blocked by blockedProtocols
 // - DNS requests inside the VPN tunnel to any specified remote DNS server
 // - DNS requests outside the VPN tunnel to any specified local DNS servers
 //
@@ -184,6 +271,8 @@ WinFw_ApplyPolicyConnecting(
 //   Friendly name of VPN tunnel interface
 // dnsServers:
 //   Array of string-encoded IP addresses of DNS servers to use
+// blockedProtocols:
+//   Protocols to block inside the VPN tunnel
 //
 extern "C"
 WINFW_LINKAGE
@@ -197,7 +286,8 @@ WinFw_ApplyPolicyConnected(
 	const wchar_t *v4Gateway,
 	const wchar_t *v6Gateway,
 	const wchar_t * const *dnsServers,
-	size_t numDnsServers
+	size_t numDnsServers,
+	const WinFwBlockedTunnelProtocols *blockedProtocols
 );
 
 //
@@ -205,6 +295,8 @@ WinFw_ApplyPolicyConnected(
 //
 // Apply restrictions in the firewall that block all traffic, except:
 // - What is specified by settings
+// - Communication with the allowed endpoint, if any
+// - Communication with the recovery networks, if any
 //
 extern "C"
 WINFW_LINKAGE
@@ -212,7 +304,9 @@ WINFW_POLICY_STATUS
 WINFW_API
 WinFw_ApplyPolicyBlocked(
 	const WinFwSettings *settings,
-	const WinFwAllowedEndpoint *allowedEndpoint
+	const WinFwAllowedEndpoint *allowedEndpoint,
+	const WinFwNetwork *recoveryNetworks,
+	size_t numRecoveryNetworks
 );
 
 //
@@ -0,0 +1,179 @@
+#include "stdafx.h"
+#include <winnet/routing/helpers.h>
+#include <CppUnitTest.h>
+#include <vector>
+
+using namespace Microsoft::VisualStudio::CppUnitTestFramework;
+using namespace winnet::routing;
+
+namespace
+{
+
+NET_LUID MakeLuid(uint32_t ifType, uint64_t index)
+{
+	NET_LUID luid = { 0 };
+	luid.Info.IfType = ifType;
+	luid.Info.NetLuidIndex = index;
+
+	return luid;
+}
+
+//
+// Creates an IPv4 default route. `gateway` is in host byte order.
+//
+MIB_IPFORWARD_ROW2 MakeDefaultRoute(NET_LUID luid, uint32_t gateway)
+{
+	MIB_IPFORWARD_ROW2 route = { 0 };
+	route.InterfaceLuid = luid;
+	route.DestinationPrefix.Prefix.si_family = AF_INET;
+	route.NextHop.si_family = AF_INET;
+
+	auto &bytes = route.NextHop.Ipv4.sin_addr.S_un.S_un_b;
+	bytes.s_b1 = static_cast<UCHAR>(gateway >> 24);
+	bytes.s_b2 = static_cast<UCHAR>(gateway >> 16);
+	bytes.s_b3 = static_cast<UCHAR>(gateway >> 8);
+	bytes.s_b4 = static_cast<UCHAR>(gateway);
+
+	return route;
+}
+
+}
+
+TEST_CLASS(DefaultRouteInterfaceTests)
+{
+public:
+
+	TEST_METHOD(ownedInterfaceIsExcludedByLuid)
+	{
+		// A renamed Wintun adapter is recognized by neither its type nor its description.
+		const auto tunnel = MakeLuid(IF_TYPE_PROP_VIRTUAL, 1);
+		const auto ethernet = MakeLuid(IF_TYPE_ETHERNET_CSMACD, 2);
+
+		Assert::IsFalse(IsTunnelInterface(tunnel, L"My Adapter"));
+
+		const std::vector<NET_LUID> excluded{ tunnel };
+
+		Assert::IsTrue(IsExcludedInterface(tunnel, excluded));
+		Assert::IsFalse(IsExcludedInterface(ethernet, excluded));
+		Assert::IsFalse(IsExcludedInterface(tunnel, {}));
+	}
+
+	TEST_METHOD(otherTunnelsAreRecognizedByTypeOrDescription)
+	{
+		Assert::IsTrue(IsTunnelInterface(MakeLuid(IF_TYPE_SOFTWARE_LOOPBACK, 1), nullptr));
+		Assert::IsTrue(IsTunnelInterface(MakeLuid(IF_TYPE_TUNNEL, 1), L"Teredo"));
+		Assert::IsTrue(IsTunnelInterface(MakeLuid(IF_TYPE_PROP_VIRTUAL, 1), L"WireGuard Tunnel"));
+		Assert::IsTrue(IsTunnelInterface(MakeLuid(IF_TYPE_PROP_VIRTUAL, 1), L"Wintun Userspace Tunnel"));
+
+		Assert::IsFalse(IsTunnelInterface(MakeLuid(IF_TYPE_ETHERNET_CSMACD, 1), L"Intel(R) Ethernet Connection"));
+		Assert::IsFalse(IsTunnelInterface(MakeLuid(IF_TYPE_PROP_VIRTUAL, 1), nullptr));
+	}
+};
+
+TEST_CLASS(BestDefaultRouteTests)
+{
+public:
+
+	TEST_METHOD(lowestMetricWins)
+	{
+		const auto low = MakeDefaultRoute(MakeLuid(IF_TYPE_ETHERNET_CSMACD, 2), 0xC0A80101);
+		const auto high = MakeDefaultRoute(MakeLuid(IF_TYPE_ETHERNET_CSMACD, 1), 0xC0A80201);
+
+		DefaultRouteRationale rationale = {};
+
+		// The preferred interface is ignored when the metrics differ.
+		const auto best = SelectBestDefaultRoute
+		(
+			{ AnnotatedRoute{ &high, true, 50 }, AnnotatedRoute{ &low, true, 25 } },
+			high.InterfaceLuid,
+			&rationale
+		);
+
+		Assert::IsTrue(best.has_value());
+		Assert::IsTrue(best->sameInterface(low.InterfaceLuid));
+		Assert::IsTrue(25 == rationale.effectiveMetric);
+		Assert::IsTrue(1 == rationale.numEqualCost);
+		Assert::IsFalse(rationale.onPreferredInterface);
+	}
+
+	TEST_METHOD(tieKeepsPreferredInterface)
+	{
+		const auto first = MakeDefaultRoute(MakeLuid(IF_TYPE_ETHERNET_CSMACD, 1), 0xC0A80101);
+		const auto second = MakeDefaultRoute(MakeLuid(IF_TYPE_IEEE80211, 2), 0xC0A80201);
+
+		DefaultRouteRationale rationale = {};
+
+		const auto best = SelectBestDefaultRoute
+		(
+			{ AnnotatedRoute{ &first, true, 25 }, AnnotatedRoute{ &second, true, 25 } },
+			second.InterfaceLuid,
+			&rationale
+		);
+
+		Assert::IsTrue(best.has_value());
+		Assert::IsTrue(best->sameInterface(second.InterfaceLuid));
+		Assert::IsTrue(2 == rationale.numEqualCost);
+		Assert::IsTrue(rationale.onPreferredInterface);
+	}
+
+	TEST_METHOD(tieWithoutPreferenceChoosesLowestLuid)
+	{
+		const auto first = MakeDefaultRoute(MakeLuid(IF_TYPE_ETHERNET_CSMACD, 1), 0xC0A80101);
+		const auto second = MakeDefaultRoute(MakeLuid(IF_TYPE_ETHERNET_CSMACD, 2), 0xC0A80201);
+
+		// The order of the input must not affect the result.
+		for (const auto &routes : std::vector<std::vector<AnnotatedRoute>>
+		{
+			{ AnnotatedRoute{ &first, true, 25 }, AnnotatedRoute{ &second, true, 25 } },
+			{ AnnotatedRoute{ &second, true, 25 }, AnnotatedRoute{ &first, true, 25 } }
+		})
+		{
+			DefaultRouteRationale rationale = {};
+
+			const auto best = SelectBestDefaultRoute(routes, std::nullopt, &rationale);
+
+			Assert::IsTrue(best.has_value());
+			Assert::IsTrue(best->sameInterface(first.InterfaceLuid));
+			Assert::IsTrue(2 == rationale.numEqualCost);
+			Assert::IsFalse(rationale.onPreferredInterface);
+		}
+	}
+
+	TEST_METHOD(tieOnSameInterfaceChoosesLowestGateway)
+	{
+		const auto luid = MakeLuid(IF_TYPE_ETHERNET_CSMACD, 1);
+		const auto lower = MakeDefaultRoute(luid, 0x0A000001);
+		const auto higher = MakeDefaultRoute(luid, 0x0A000002);
+
+		const auto best = SelectBestDefaultRoute
+		(
+			{ AnnotatedRoute{ &higher, true, 25 }, AnnotatedRoute{ &lower, true, 25 } },
+			luid
+		);
+
+		Assert::IsTrue(best.has_value());
+		Assert::IsTrue(*best == InterfaceAndGateway{ luid, lower.NextHop });
+	}
+
+	TEST_METHOD(inactiveRoutesAreIgnored)
+	{
+		const auto inactive = MakeDefaultRoute(MakeLuid(IF_TYPE_ETHERNET_CSMACD, 1), 0xC0A80101);
+		const auto active = MakeDefaultRoute(MakeLuid(IF_TYPE_ETHERNET_CSMACD, 2), 0xC0A80201);
+
+		DefaultRouteRationale rationale = {};
+
+		const auto best = SelectBestDefaultRoute
+		(
+			{ AnnotatedRoute{ &inactive, false, 5 }, AnnotatedRoute{ &active, true, 25 }, AnnotatedRoute{ &inactive, false, 25 } },
+			inactive.InterfaceLuid,
+			&rationale
+		);
+
+		Assert::IsTrue(best.has_value());
+		Assert::IsTrue(best->sameInterface(active.InterfaceLuid));
+		Assert::IsTrue(1 == rationale.numEqualCost);
+
+		Assert::IsFalse(SelectBestDefaultRoute({ AnnotatedRoute{ &inactive, false, 5 } }).has_value());
+		Assert::IsFalse(SelectBestDefaultRoute({}).has_value());
+	}
+};
@@ -0,0 +1,248 @@
+#include "stdafx.h"
+#include <winnet/routing/routerecords.h>
+#include <winnet/routing/helpers.h>
+#include <CppUnitTest.h>
+#include <random>
+#include <vector>
+
+using namespace Microsoft::VisualStudio::CppUnitTestFramework;
+using namespace winnet::routing;
+
+namespace
+{
+
+Network MakeNetwork(uint32_t address, uint8_t prefixLength)
+{
+	Network network = { 0 };
+	network.Prefix.si_family = AF_INET;
+	network.Prefix.Ipv4.sin_addr.s_addr = address;
+	network.PrefixLength = prefixLength;
+
+	return network;
+}
+
+NodeAddress MakeAddress(uint32_t address)
+{
+	NodeAddress nodeAddress = { 0 };
+	nodeAddress.si_family = AF_INET;
+	nodeAddress.Ipv4.sin_addr.s_addr = address;
+
+	return nodeAddress;
+}
+
+NET_LUID MakeLuid(uint64_t value)
+{
+	NET_LUID luid;
+	luid.Value = value;
+
+	return luid;
+}
+
+RouteRecord MakeRecord(uint64_t luid, uint32_t destination, uint32_t gateway, const wchar_t *device)
+{
+	const auto network = MakeNetwork(destination, 32);
+
+	return RouteRecord
+	{
+		Route(network, Node(std::wstring(device), std::nullopt)),
+		RouteKey(MakeLuid(luid), network, MakeAddress(gateway))
+	};
+}
+
+//
+// Records are compared by key and specification, regardless of order.
+//
+bool SameRecords(const RouteRecords &records, const std::vector<RouteRecord> &expected)
+{
+	if (records.size() != expected.size())
+	{
+		return false;
+	}
+
+	for (const auto &record : expected)
+	{
+		const auto found = std::find_if(records.begin(), records.end(), [&record](const auto &candidate)
+		{
+			return candidate.key == record.key && candidate.route == record.route;
+		});
+
+		if (records.end() == found)
+		{
+			return false;
+		}
+	}
+
+	return true;
+}
+
+std::vector<RouteRecord> Snapshot(const RouteRecords &records)
+{
+	return std::vector<RouteRecord>(records.begin(), records.end());
+}
+
+}
+
+TEST_CLASS(RouteKeyTests)
+{
+public:
+
+	TEST_METHOD(onLinkNextHopIsNormalized)
+	{
+		const auto network = MakeNetwork(0x01020304, 32);
+
+		NodeAddress unspecified = { 0 };
+
+		const RouteKey withoutFamily(MakeLuid(1), network, unspecified);
+		const RouteKey withFamily(MakeLuid(1), network, MakeAddress(0));
+
+		Assert::IsTrue(withoutFamily == withFamily, L"On-link next hops should compare equal");
+	}
+
+	TEST_METHOD(allComponentsAreCompared)
+	{
+		const RouteKey key(MakeLuid(1), MakeNetwork(0x01020304, 32), MakeAddress(0x0101a8c0));
+
+		Assert::IsTrue(key != RouteKey(MakeLuid(2), MakeNetwork(0x01020304, 32), MakeAddress(0x0101a8c0)));
+		Assert::IsTrue(key != RouteKey(MakeLuid(1), MakeNetwork(0x01020305, 32), MakeAddress(0x0101a8c0)));
+		Assert::IsTrue(key != RouteKey(MakeLuid(1), MakeNetwork(0x01020304, 31), MakeAddress(0x0101a8c0)));
+		Assert::IsTrue(key != RouteKey(MakeLuid(1), MakeNetwork(0x01020304, 32), MakeAddress(0x0201a8c0)));
+	}
+
+	TEST_METHOD(interfaceComparisonIgnoresGateway)
+	{
+		const InterfaceAndGateway first{ MakeLuid(1), MakeAddress(0x0101a8c0) };
+		const InterfaceAndGateway second{ MakeLuid(1), MakeAddress(0x0201a8c0) };
+
+		Assert::IsTrue(first.sameInterface(second));
+		Assert::IsTrue(first != second);
+	}
+
+	TEST_METHOD(equalityIsAnEquivalenceRelation)
+	{
+		std::mt19937 rng(1);
+		std::uniform_int_distribution<uint32_t> small(0, 2);
+
+		std::vector<RouteKey> keys;
+
+		for (int i = 0; i < 50; ++i)
+		{
+			keys.emplace_back(
+				MakeLuid(small(rng)),
+				MakeNetwork(small(rng), static_cast<uint8_t>(30 + small(rng))),
+				MakeAddress(small(rng))
+			);
+		}
+
+		for (const auto &a : keys)
+		{
+			Assert::IsTrue(a == a, L"Reflexivity");
+
+			for (const auto &b : keys)
+			{
+				Assert::AreEqual(a == b, b == a, L"Symmetry");
+				Assert::AreEqual(a == b, !(a != b), L"Consistency with inequality");
+
+				for (const auto &c : keys)
+				{
+					if (a == b && b == c)
+					{
+						Assert::IsTrue(a == c, L"Transitivity");
+					}
+				}
+			}
+		}
+	}
+};
+
+TEST_CLASS(RouteRecordsTests)
+{
+public:
+
+	TEST_METHOD(insertWithSameKeyReplacesRecord)
+	{
+		RouteRecords records;
+
+		records.insert(MakeRecord(1, 0x01020304, 0x0101a8c0, L"first"));
+		const auto event = records.insert(MakeRecord(1, 0x01020304, 0x0101a8c0, L"second"));
+
+		Assert::AreEqual(size_t(1), records.size(), L"Keys should be unique");
+		Assert::IsTrue(RouteRecords::EventType::UPDATE == event.type);
+		Assert::IsTrue(event.previous.has_value());
+
+		records.revert(event);
+
+		Assert::IsTrue(SameRecords(records, { MakeRecord(1, 0x01020304, 0x0101a8c0, L"first") }));
+	}
+
+	TEST_METHOD(findBySpecAndKey)
+	{
+		RouteRecords records;
+
+		const auto record = MakeRecord(1, 0x01020304, 0x0101a8c0, L"first");
+		records.insert(record);
+
+		Assert::IsTrue(records.end() != records.find(record.key));
+		Assert::IsTrue(records.end() != records.findBySpec(record.route));
+		Assert::IsTrue(records.end() == records.find(MakeRecord(2, 0x01020304, 0x0101a8c0, L"first").key));
+	}
+
+	//
+	// Applies random batches of changes and reverts them in the reverse order,
+	// like `RouteManager` does when a batch fails. The records must be unique by key
+	// after every change, and be restored exactly after every rollback.
+	//
+	TEST_METHOD(randomRollbacksRestoreRecords)
+	{
+		std::mt19937 rng(1);
+		std::uniform_int_distribution<uint32_t> small(0, 3);
+		std::uniform_int_distribution<int> operation(0, 2);
+		std::uniform_int_distribution<int> batchSize(1, 8);
+
+		const wchar_t *devices[] = { L"a", L"b", L"c", L"d" };
+
+		RouteRecords records;
+
+		for (int round = 0; round < 200; ++round)
+		{
+			const auto before = Snapshot(records);
+			std::vector<RouteRecords::Event> eventLog;
+
+			for (int i = batchSize(rng); i > 0; --i)
+			{
+				if (0 != operation(rng) || records.empty())
+				{
+					eventLog.emplace_back(records.insert(
+						MakeRecord(small(rng), small(rng), small(rng), devices[small(rng)])));
+				}
+				else
+				{
+					auto it = records.begin();
+					std::advance(it, std::uniform_int_distribution<size_t>(0, records.size() - 1)(rng));
+					eventLog.emplace_back(records.erase(it));
+				}
+
+				for (auto a = records.begin(); a != records.end(); ++a)
+				{
+					for (auto b = std::next(a); b != records.end(); ++b)
+					{
+						Assert::IsTrue(a->key != b->key, L"Keys should be unique");
+					}
+				}
+			}
+
+			//
+			// Keep every other batch.
+			//
+
+			if (0 == round % 2)
+			{
+				for (auto it = eventLog.rbegin(); it != eventLog.rend(); ++it)
+				{
+					records.revert(*it);
+				}
+
+				Assert::IsTrue(SameRecords(records, before), L"Rollback should restore the records");
+			}
+		}
+	}
+};
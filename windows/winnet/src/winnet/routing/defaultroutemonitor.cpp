@@ -1,4 +1,5 @@
 #include "stdafx.h"
+#include <sstream>
 #include <libcommon/error.h>
 #include "defaultroutemonitor.h"
 #include "helpers.h"
@@ -24,6 +25,8 @@ DefaultRouteMonitor::DefaultRouteMonitor
 	, m_callback(callback)
 	, m_logSink(logSink)
 	, m_refreshCurrentRoute(false)
+	, m_activeCallbacks(0)
+	, m_shutdown(false)
 	, m_evaluateRoutesGuard(std::make_unique<common::BurstGuard>(
 		std::bind(&DefaultRouteMonitor::evaluateRoutes, this),
 		POINT_TWO_SECOND_BURST,
@@ -69,6 +72,26 @@ DefaultRouteMonitor::DefaultRouteMonitor
 
 DefaultRouteMonitor::~DefaultRouteMonitor()
 {
+	shutdown();
+}
+
+void DefaultRouteMonitor::shutdown(std::chrono::milliseconds timeout)
+{
+	{
+		std::scoped_lock<std::mutex> lock(m_activityLock);
+
+		if (m_shutdown)
+		{
+			return;
+		}
+
+		//
+		// Callbacks that start from now on return immediately.
+		//
+
+		m_shutdown = true;
+	}
+
 	//
 	// Cancel notifications to stop triggering the BurstGuard.
 	//
@@ -77,6 +100,25 @@ DefaultRouteMonitor::~DefaultRouteMonitor()
 	CancelMibChangeNotify2(m_interfaceNotificationHandle);
 	CancelMibChangeNotify2(m_routeNotificationHandle);
 
+	//
+	// Wait for callbacks that were already executing, e.g. a route evaluation
+	// that is busy notifying the owner.
+	//
+
+	{
+		std::unique_lock<std::mutex> lock(m_activityLock);
+
+		const auto completed = m_activityDone.wait_for(lock, timeout, [this]()
+		{
+			return 0 == m_activeCallbacks;
+		});
+
+		if (false == completed)
+		{
+			m_logSink->error("Timed out waiting for default route callbacks to complete");
+		}
+	}
+
 	//
 	// Controlled destruction of BurstGuard to prevent it from calling here
 	// after other member variables have been destructed.
@@ -85,6 +127,43 @@ DefaultRouteMonitor::~DefaultRouteMonitor()
 	m_evaluateRoutesGuard.reset();
 }
 
+bool DefaultRouteMonitor::beginActivity()
+{
+	std::scoped_lock<std::mutex> lock(m_activityLock);
+
+	if (m_shutdown)
+	{
+		return false;
+	}
+
+	++m_activeCallbacks;
+
+	return true;
+}
+
+void DefaultRouteMonitor::endActivity()
+{
+	{
+		std::scoped_lock<std::mutex> lock(m_activityLock);
+		--m_activeCallbacks;
+	}
+
+	m_activityDone.notify_all();
+}
+
+void DefaultRouteMonitor::handleChange(const NET_LUID &luid, const NET_IFINDEX &index)
+{
+	if (false == beginActivity())
+	{
+		return;
+	}
+
+	updateRefreshFlag(luid, index);
+	m_evaluateRoutesGuard->trigger();
+
+	endActivity();
+}
+
 //static
 void NETIOAPI_API_ DefaultRouteMonitor::RouteChangeCallback
 (
@@ -104,8 +183,7 @@ void NETIOAPI_API_ DefaultRouteMonitor::RouteChangeCallback
 	}
 
 	const auto monitor = reinterpret_cast<DefaultRouteMonitor*>(context);
-	monitor->updateRefreshFlag(row->InterfaceLuid, row->InterfaceIndex);
-	monitor->m_evaluateRoutesGuard->trigger();
+	monitor->handleChange(row->InterfaceLuid, row->InterfaceIndex);
 }
 
 //static
@@ -117,8 +195,7 @@ void NETIOAPI_API_ DefaultRouteMonitor::InterfaceChangeCallback
 )
 {
 	const auto monitor = reinterpret_cast<DefaultRouteMonitor*>(context);
-	monitor->updateRefreshFlag(row->InterfaceLuid, row->InterfaceIndex);
-	monitor->m_evaluateRoutesGuard->trigger();
+	monitor->handleChange(row->InterfaceLuid, row->InterfaceIndex);
 }
 
 //static
@@ -130,8 +207,7 @@ void NETIOAPI_API_ DefaultRouteMonitor::AddressChangeCallback
 )
 {
 	const auto monitor = reinterpret_cast<DefaultRouteMonitor*>(context);
-	monitor->updateRefreshFlag(row->InterfaceLuid, row->InterfaceIndex);
-	monitor->m_evaluateRoutesGuard->trigger();
+	monitor->handleChange(row->InterfaceLuid, row->InterfaceIndex);
 }
 
 void DefaultRouteMonitor::updateRefreshFlag(const NET_LUID &luid, const NET_IFINDEX &index)
@@ -143,7 +219,7 @@ void DefaultRouteMonitor::updateRefreshFlag(const NET_LUID &luid, const NET_IFIN
 		return;
 	}
 
-	if (luid.Value == m_bestRoute->iface.Value)
+	if (m_bestRoute->sameInterface(luid))
 	{
 		m_refreshCurrentRoute = true;
 		return;
@@ -163,21 +239,30 @@ void DefaultRouteMonitor::updateRefreshFlag(const NET_LUID &luid, const NET_IFIN
 
 void DefaultRouteMonitor::evaluateRoutes()
 {
-	std::scoped_lock<std::mutex> lock(m_evaluationLock);
-
-	try
-	{
-		evaluateRoutesInner();
-	}
-	catch (const std::exception &ex)
+	if (false == beginActivity())
 	{
-		const auto msg = std::string("Failure while evaluating route table: ").append(ex.what());
-		m_logSink->error(msg.c_str());
+		return;
 	}
-	catch (...)
+
 	{
-		m_logSink->error("Unspecified failure while evaluating route table");
+		std::scoped_lock<std::mutex> lock(m_evaluationLock);
+
+		try
+		{
+			evaluateRoutesInner();
+		}
+		catch (const std::exception &ex)
+		{
+			const auto msg = std::string("Failure while evaluating route table: ").append(ex.what());
+			m_logSink->error(msg.c_str());
+		}
+		catch (...)
+		{
+			m_logSink->error("Unspecified failure while evaluating route table");
+		}
 	}
+
+	endActivity();
 }
 
 void DefaultRouteMonitor::evaluateRoutesInner()
@@ -187,14 +272,40 @@ void DefaultRouteMonitor::evaluateRoutesInner()
 	bool refreshCurrent = m_refreshCurrentRoute;
 	m_refreshCurrentRoute = false;
 
+	//
+	// Prefer the interface that is already in use if other routes have the same
+	// metric. Otherwise, the best route could flap between the interfaces.
+	//
+
+	std::optional<NET_LUID> currentLuid;
+
+	if (m_bestRoute.has_value())
+	{
+		currentLuid = m_bestRoute->iface;
+	}
+
+	DefaultRouteRationale rationale = {};
+
 	try
 	{
-		currentBestRoute = GetBestDefaultRoute(m_family);
+		currentBestRoute = GetBestDefaultRoute(m_family, {}, currentLuid, &rationale);
 	}
 	catch (...)
 	{
 	}
 
+	//
+	// Only explain the choice when it changes. Otherwise, machines with several
+	// equal-cost default routes would log this on every evaluation.
+	//
+
+	if (currentBestRoute.has_value()
+		&& rationale.numEqualCost > 1
+		&& m_bestRoute != currentBestRoute)
+	{
+		logTieBreak(currentBestRoute.value(), rationale);
+	}
+
 	//
 	// If there was no default route previously.
 	//
@@ -204,7 +315,7 @@ void DefaultRouteMonitor::evaluateRoutesInner()
 		if (currentBestRoute.has_value())
 		{
 			m_bestRoute = currentBestRoute;
-			m_callback(EventType::Updated, m_bestRoute);
+			m_callback(EventType::Updated, std::nullopt, m_bestRoute);
 		}
 
 		return;
@@ -217,8 +328,9 @@ void DefaultRouteMonitor::evaluateRoutesInner()
 
 	if (false == currentBestRoute.has_value())
 	{
+		const auto previousRoute = m_bestRoute;
 		m_bestRoute.reset();
-		m_callback(EventType::Removed, std::nullopt);
+		m_callback(EventType::Removed, previousRoute, std::nullopt);
 
 		return;
 	}
@@ -229,8 +341,9 @@ void DefaultRouteMonitor::evaluateRoutesInner()
 
 	if (m_bestRoute.value() != currentBestRoute.value())
 	{
+		const auto previousRoute = m_bestRoute;
 		m_bestRoute = currentBestRoute;
-		m_callback(EventType::Updated, m_bestRoute);
+		m_callback(EventType::Updated, previousRoute, m_bestRoute);
 
 		return;
 	}
@@ -241,8 +354,29 @@ void DefaultRouteMonitor::evaluateRoutesInner()
 
 	if (refreshCurrent)
 	{
-		m_callback(EventType::UpdatedDetails, m_bestRoute);
+		m_callback(EventType::UpdatedDetails, m_bestRoute, m_bestRoute);
 	}
 }
 
+void DefaultRouteMonitor::logTieBreak(const InterfaceAndGateway &route, const DefaultRouteRationale &rationale)
+{
+	std::stringstream ss;
+
+	ss << rationale.numEqualCost << " default routes have the lowest metric ("
+		<< rationale.effectiveMetric << "). ";
+
+	if (rationale.onPreferredInterface)
+	{
+		ss << "Keeping the route on the current interface";
+	}
+	else
+	{
+		ss << "Choosing the route on the interface with the lowest LUID";
+	}
+
+	ss << " (LUID 0x" << std::hex << route.iface.Value << ")";
+
+	m_logSink->info(ss.str().c_str());
+}
+
 }
@@ -2,6 +2,8 @@
 
 #include <ifdef.h>
 #include <ws2def.h>
+#include <chrono>
+#include <condition_variable>
 #include <functional>
 #include <optional>
 #include <memory>
@@ -9,6 +11,7 @@
 #include <libcommon/logging/ilogsink.h>
 #include <libcommon/burstguard.h>
 #include "types.h"
+#include "helpers.h"
 
 namespace winnet::routing
 {
@@ -34,6 +37,10 @@ public:
 	(
 		EventType eventType,
 
+		// The best default route before the event, if there was one.
+		// For `UpdatedDetails` events, this is the same as `route`.
+		const std::optional<InterfaceAndGateway> &previousRoute,
+
 		// For update events, data associated with the new best default route.
 		const std::optional<InterfaceAndGateway> &route
 	)>;
@@ -46,6 +53,13 @@ public:
 	DefaultRouteMonitor &operator=(const DefaultRouteMonitor &) = delete;
 	DefaultRouteMonitor &operator=(DefaultRouteMonitor &&) = delete;
 
+	//
+	// Stops listening for notifications and waits for callbacks that are already
+	// executing to complete, for at most `timeout`. No callbacks are invoked after
+	// this returns. Calling it more than once has no effect.
+	//
+	void shutdown(std::chrono::milliseconds timeout = std::chrono::seconds(5));
+
 private:
 
 	ADDRESS_FAMILY m_family;
@@ -65,6 +79,18 @@ private:
 
 	std::mutex m_evaluationLock;
 
+	// Tracks notification callbacks and route evaluations that are executing,
+	// so that shutting down can wait for them.
+	std::mutex m_activityLock;
+	std::condition_variable m_activityDone;
+	size_t m_activeCallbacks;
+	bool m_shutdown;
+
+	bool beginActivity();
+	void endActivity();
+
+	void handleChange(const NET_LUID &luid, const NET_IFINDEX &index);
+
 	static void NETIOAPI_API_ RouteChangeCallback(void *context, MIB_IPFORWARD_ROW2 *row, MIB_NOTIFICATION_TYPE notificationType);
 	static void NETIOAPI_API_ InterfaceChangeCallback(void *context, MIB_IPINTERFACE_ROW *row, MIB_NOTIFICATION_TYPE notificationType);
 	static void NETIOAPI_API_ AddressChangeCallback(void *context, MIB_UNICASTIPADDRESS_ROW *row, MIB_NOTIFICATION_TYPE notificationType);
@@ -73,6 +99,8 @@ private:
 
 	void evaluateRoutes();
 	void evaluateRoutesInner();
+
+	void logTieBreak(const InterfaceAndGateway &route, const DefaultRouteRationale &rationale);
 };
 
 }
@@ -3,6 +3,7 @@
 #include <ws2def.h>
 #include <in6addr.h>
 #include <numeric>
+#include <algorithm>
 #include <libcommon/error.h>
 #include <libcommon/memory.h>
 
@@ -16,21 +17,13 @@ const wchar_t *TUNNEL_INTERFACE_DESCS[] = {
 	L"Tunnel"
 };
 
-bool IsRouteOnPhysicalInterface(const MIB_IPFORWARD_ROW2 &route)
+bool IsRouteOnPhysicalInterface(const MIB_IPFORWARD_ROW2 &route, const std::vector<NET_LUID> &excludedLuids)
 {
-	switch (route.InterfaceLuid.Info.IfType)
+	if (winnet::routing::IsExcludedInterface(route.InterfaceLuid, excludedLuids))
 	{
-		case IF_TYPE_SOFTWARE_LOOPBACK:
-		case IF_TYPE_TUNNEL:
-		{
-			return false;
-		}
+		return false;
 	}
 
-	// OpenVPN uses interface type IF_TYPE_PROP_VIRTUAL,
-	// but tethering etc. may rely on virtual adapters too,
-	// so we have to filter out the TAP adapter specifically.
-
 	MIB_IF_ROW2 row = { 0 };
 	row.InterfaceLuid = route.InterfaceLuid;
 
@@ -39,15 +32,35 @@ bool IsRouteOnPhysicalInterface(const MIB_IPFORWARD_ROW2 &route)
 		THROW_ERROR("Cannot obtain interface information for the given route");
 	}
 
-	for (size_t i = 0; i < ARRAYSIZE(TUNNEL_INTERFACE_DESCS); i++)
+	return false == winnet::routing::IsTunnelInterface(route.InterfaceLuid, row.Description);
+}
+
+//
+// Orders gateways by address family and address. Used to make the order of
+// routes on the same interface deterministic.
+//
+bool GatewayLessThan(const SOCKADDR_INET &lhs, const SOCKADDR_INET &rhs)
+{
+	if (lhs.si_family != rhs.si_family)
 	{
-		if (nullptr != wcsstr(row.Description, TUNNEL_INTERFACE_DESCS[i]))
+		return lhs.si_family < rhs.si_family;
+	}
+
+	switch (lhs.si_family)
+	{
+		case AF_INET:
+		{
+			return ntohl(lhs.Ipv4.sin_addr.s_addr) < ntohl(rhs.Ipv4.sin_addr.s_addr);
+		}
+		case AF_INET6:
+		{
+			return 0 > memcmp(&lhs.Ipv6.sin6_addr, &rhs.Ipv6.sin6_addr, sizeof(IN6_ADDR));
+		}
+		default:
 		{
 			return false;
 		}
 	}
-
-	return true;
 }
 
 } // anonymous namespace
@@ -55,6 +68,50 @@ bool IsRouteOnPhysicalInterface(const MIB_IPFORWARD_ROW2 &route)
 namespace winnet::routing
 {
 
+bool IsExcludedInterface(NET_LUID luid, const std::vector<NET_LUID> &excludedLuids)
+{
+	for (const auto &excluded : excludedLuids)
+	{
+		if (excluded.Value == luid.Value)
+		{
+			return true;
+		}
+	}
+
+	return false;
+}
+
+bool IsTunnelInterface(NET_LUID luid, const wchar_t *description)
+{
+	switch (luid.Info.IfType)
+	{
+		case IF_TYPE_SOFTWARE_LOOPBACK:
+		case IF_TYPE_TUNNEL:
+		{
+			return true;
+		}
+	}
+
+	// OpenVPN uses interface type IF_TYPE_PROP_VIRTUAL,
+	// but tethering etc. may rely on virtual adapters too,
+	// so other tunnel adapters can only be recognized by their description.
+
+	if (nullptr == description)
+	{
+		return false;
+	}
+
+	for (size_t i = 0; i < ARRAYSIZE(TUNNEL_INTERFACE_DESCS); i++)
+	{
+		if (nullptr != wcsstr(description, TUNNEL_INTERFACE_DESCS[i]))
+		{
+			return true;
+		}
+	}
+
+	return false;
+}
+
 bool EqualAddress(const Network &lhs, const Network &rhs)
 {
 	if (lhs.PrefixLength != rhs.PrefixLength)
@@ -170,7 +227,13 @@ bool RouteHasGateway(const MIB_IPFORWARD_ROW2 &route)
 	};
 }
 
-std::optional<InterfaceAndGateway> GetBestDefaultRoute(ADDRESS_FAMILY family)
+std::optional<InterfaceAndGateway> GetBestDefaultRoute
+(
+	ADDRESS_FAMILY family,
+	const std::vector<NET_LUID> &excludedLuids,
+	const std::optional<NET_LUID> &preferredLuid,
+	DefaultRouteRationale *rationale
+)
 {
 	PMIB_IPFORWARD_TABLE2 table;
 
@@ -194,6 +257,8 @@ std::optional<InterfaceAndGateway> GetBestDefaultRoute(ADDRESS_FAMILY family)
 	//
 	// Enumerate routes looking for: route 0/0
 	// The WireGuard interface route has no gateway.
+	// Interfaces owned by the caller are excluded by LUID. Other tunnel
+	// interfaces are excluded based on their type and description.
 	//
 
 	for (ULONG i = 0; i < table->NumEntries; ++i)
@@ -202,43 +267,89 @@ std::optional<InterfaceAndGateway> GetBestDefaultRoute(ADDRESS_FAMILY family)
 
 		if (0 == candidate.DestinationPrefix.PrefixLength
 			&& RouteHasGateway(candidate)
-			&& IsRouteOnPhysicalInterface(candidate))
+			&& IsRouteOnPhysicalInterface(candidate, excludedLuids))
 		{
 			candidates.emplace_back(&candidate);
 		}
 	}
 
-	auto annotated = AnnotateRoutes(candidates);
+	return SelectBestDefaultRoute(AnnotateRoutes(candidates), preferredLuid, rationale);
+}
 
+std::optional<InterfaceAndGateway> SelectBestDefaultRoute
+(
+	std::vector<AnnotatedRoute> annotated,
+	const std::optional<NET_LUID> &preferredLuid,
+	DefaultRouteRationale *rationale
+)
+{
 	if (annotated.empty())
 	{
 		return std::nullopt;
 	}
 
+	const auto isPreferred = [&preferredLuid](const AnnotatedRoute &route)
+	{
+		return preferredLuid.has_value()
+			&& preferredLuid->Value == route.route->InterfaceLuid.Value;
+	};
+
 	//
 	// Sort on (active, effectiveMetric) ascending by metric.
+	// Routes with equal metrics are ordered by (preferred interface, LUID, gateway),
+	// so that the same route is chosen every time.
 	//
 
-	std::sort(annotated.begin(), annotated.end(), [](const AnnotatedRoute &lhs, const AnnotatedRoute &rhs)
+	std::sort(annotated.begin(), annotated.end(), [&isPreferred](const AnnotatedRoute &lhs, const AnnotatedRoute &rhs)
 	{
-		if (lhs.active == rhs.active)
+		if (lhs.active != rhs.active)
+		{
+			return lhs.active;
+		}
+
+		if (lhs.effectiveMetric != rhs.effectiveMetric)
 		{
 			return lhs.effectiveMetric < rhs.effectiveMetric;
 		}
 
-		return lhs.active && false == rhs.active;
+		const auto lhsPreferred = isPreferred(lhs);
+		const auto rhsPreferred = isPreferred(rhs);
+
+		if (lhsPreferred != rhsPreferred)
+		{
+			return lhsPreferred;
+		}
+
+		if (lhs.route->InterfaceLuid.Value != rhs.route->InterfaceLuid.Value)
+		{
+			return lhs.route->InterfaceLuid.Value < rhs.route->InterfaceLuid.Value;
+		}
+
+		return GatewayLessThan(lhs.route->NextHop, rhs.route->NextHop);
 	});
 
 	//
 	// Ensure the top rated route is active.
 	//
 
-	if (false == annotated[0].active)
+	const auto &best = annotated[0];
+
+	if (false == best.active)
 	{
 		return std::nullopt;
 	}
 
-	return std::make_optional(InterfaceAndGateway { annotated[0].route->InterfaceLuid, annotated[0].route->NextHop });
+	if (nullptr != rationale)
+	{
+		rationale->effectiveMetric = best.effectiveMetric;
+		rationale->numEqualCost = std::count_if(annotated.begin(), annotated.end(), [&best](const AnnotatedRoute &route)
+		{
+			return route.active && route.effectiveMetric == best.effectiveMetric;
+		});
+		rationale->onPreferredInterface = isPreferred(best);
+	}
+
+	return std::make_optional(InterfaceAndGateway { best.route->InterfaceLuid, best.route->NextHop });
 }
 
 bool AdapterInterfaceEnabled(const IP_ADAPTER_ADDRESSES *adapter, ADDRESS_FAMILY family)
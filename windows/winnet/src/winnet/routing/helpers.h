@@ -30,7 +30,63 @@ std::vector<AnnotatedRoute> AnnotateRoutes(const std::vector<const MIB_IPFORWARD
 
 bool RouteHasGateway(const MIB_IPFORWARD_ROW2 &route);
 
-std::optional<InterfaceAndGateway> GetBestDefaultRoute(ADDRESS_FAMILY family);
+//
+// Returns whether the interface is one of `excludedLuids`, i.e. an interface
+// that is owned by the caller, such as the tunnel interface.
+//
+bool IsExcludedInterface(NET_LUID luid, const std::vector<NET_LUID> &excludedLuids);
+
+//
+// Returns whether the interface is a loopback or tunnel interface, based on
+// its type and description. This is a heuristic for recognizing interfaces
+// that are not owned by the caller.
+//
+bool IsTunnelInterface(NET_LUID luid, const wchar_t *description);
+
+//
+// Describes how the best default route was chosen, for logging.
+//
+struct DefaultRouteRationale
+{
+	// Effective metric of the chosen route.
+	uint32_t effectiveMetric;
+
+	// Number of active routes with the same effective metric as the chosen route,
+	// including the chosen route.
+	size_t numEqualCost;
+
+	// Whether the chosen route is on the preferred interface. If not, and there
+	// are other routes with the same metric, the route on the interface with the
+	// lowest LUID was chosen.
+	bool onPreferredInterface;
+};
+
+//
+// Returns the active default route with the lowest effective metric.
+//
+// Ties between routes with the same metric are broken deterministically, so that
+// the result does not flap between interfaces: a route on `preferredLuid`, which
+// should be the interface that is currently in use, is preferred. Otherwise, the
+// route on the interface with the lowest LUID is chosen.
+//
+std::optional<InterfaceAndGateway> GetBestDefaultRoute
+(
+	ADDRESS_FAMILY family,
+	const std::vector<NET_LUID> &excludedLuids = {},
+	const std::optional<NET_LUID> &preferredLuid = std::nullopt,
+	DefaultRouteRationale *rationale = nullptr
+);
+
+//
+// Returns the best of the given routes, using the same ordering as
+// GetBestDefaultRoute(). Returns std::nullopt if none of the routes is active.
+//
+std::optional<InterfaceAndGateway> SelectBestDefaultRoute
+(
+	std::vector<AnnotatedRoute> annotated,
+	const std::optional<NET_LUID> &preferredLuid = std::nullopt,
+	DefaultRouteRationale *rationale = nullptr
+);
 
 bool AdapterInterfaceEnabled(const IP_ADAPTER_ADDRESSES *adapter, ADDRESS_FAMILY family);
 
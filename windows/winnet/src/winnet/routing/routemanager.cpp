@@ -193,12 +193,12 @@ RouteManager::RouteManager(std::shared_ptr<common::logging::ILogSink> logSink)
 	: m_logSink(logSink)
 	, m_routeMonitorV4(std::make_unique<DefaultRouteMonitor>(
 		static_cast<ADDRESS_FAMILY>(AF_INET),
-		std::bind(&RouteManager::defaultRouteChanged, this, static_cast<ADDRESS_FAMILY>(AF_INET), _1, _2),
+		std::bind(&RouteManager::defaultRouteChanged, this, static_cast<ADDRESS_FAMILY>(AF_INET), _1, _2, _3),
 		logSink
 	))
 	, m_routeMonitorV6(std::make_unique<DefaultRouteMonitor>(
 		static_cast<ADDRESS_FAMILY>(AF_INET6),
-		std::bind(&RouteManager::defaultRouteChanged, this, static_cast<ADDRESS_FAMILY>(AF_INET6), _1, _2),
+		std::bind(&RouteManager::defaultRouteChanged, this, static_cast<ADDRESS_FAMILY>(AF_INET6), _1, _2, _3),
 		logSink
 	))
 {
@@ -207,9 +207,14 @@ RouteManager::RouteManager(std::shared_ptr<common::logging::ILogSink> logSink)
 RouteManager::~RouteManager()
 {
 	//
-	// Stop callbacks that are triggered by events in Windows from coming in.
+	// Stop callbacks that are triggered by events in Windows from coming in,
+	// and wait for those in progress to complete, before releasing any state
+	// that they use.
 	//
 
+	m_routeMonitorV4->shutdown();
+	m_routeMonitorV6->shutdown();
+
 	m_routeMonitorV4.reset();
 	m_routeMonitorV6.reset();
 
@@ -220,25 +225,26 @@ void RouteManager::addRoutes(const std::vector<Route> &routes)
 {
 	AutoLockType lock(m_routesLock);
 
-	std::vector<EventEntry> eventLog;
+	std::vector<RouteRecords::Event> eventLog;
 
 	for (const auto &route : routes)
 	{
 		try
 		{
-			RouteRecord newRecord{ route, addIntoRoutingTable(route) };
+			//
+			// A route that was applied before may resolve to a different node now,
+			// e.g. if the default route has changed. Its old registration is replaced.
+			//
 
-			eventLog.emplace_back(EventEntry{ EventType::ADD_ROUTE, newRecord });
+			const auto staleRecord = m_routes.findBySpec(route);
+			const auto key = addIntoRoutingTable(route);
 
-			auto existingRecord = findRouteRecord(newRecord.registeredRoute);
+			eventLog.emplace_back(m_routes.insert(RouteRecord{ route, key }));
 
-			if (m_routes.end() == existingRecord)
-			{
-				m_routes.emplace_back(std::move(newRecord));
-			}
-			else
+			if (m_routes.end() != staleRecord && key != staleRecord->key)
 			{
-				*existingRecord = std::move(newRecord);
+				deleteFromRoutingTable(staleRecord->key);
+				eventLog.emplace_back(m_routes.erase(staleRecord));
 			}
 		}
 		catch (const error::RouteManagerError&)
@@ -258,13 +264,13 @@ void RouteManager::deleteRoutes(const std::vector<Route> &routes)
 {
 	AutoLockType lock(m_routesLock);
 
-	std::vector<EventEntry> eventLog;
+	std::vector<RouteRecords::Event> eventLog;
 
 	for (const auto &route : routes)
 	{
 		try
 		{
-			const auto record = findRouteRecordFromSpec(route);
+			const auto record = m_routes.findBySpec(route);
 
 			if (m_routes.end() == record)
 			{
@@ -276,10 +282,9 @@ void RouteManager::deleteRoutes(const std::vector<Route> &routes)
 				continue;
 			}
 
-			deleteFromRoutingTable(record->registeredRoute);
+			deleteFromRoutingTable(record->key);
 
-			eventLog.emplace_back(EventEntry{ EventType::DELETE_ROUTE, *record });
-			m_routes.erase(record);
+			eventLog.emplace_back(m_routes.erase(record));
 		}
 		catch (...)
 		{
@@ -300,14 +305,14 @@ void RouteManager::deleteAppliedRoutes()
 	{
 		try
 		{
-			deleteFromRoutingTable(record.registeredRoute);
+			deleteFromRoutingTable(record.key);
 		}
 		catch (const std::exception & ex)
 		{
 			std::wstringstream ss;
 
 			ss << L"Failed to delete route while clearing applied routes, Route: "
-				<< FormatRegisteredRoute(record.registeredRoute);
+				<< FormatRouteKey(record.key);
 
 			m_logSink->error(common::string::ToAnsi(ss.str()).c_str());
 			m_logSink->error(ex.what());
@@ -317,6 +322,16 @@ void RouteManager::deleteAppliedRoutes()
 	m_routes.clear();
 }
 
+void RouteManager::deleteUnownedRoutes(const std::vector<Route> &routes)
+{
+	for (const auto &route : routes)
+	{
+		const auto node = ResolveNode(route.network().Prefix.si_family, route.node());
+
+		deleteFromRoutingTable(RouteKey(node.iface, route.network(), node.gateway));
+	}
+}
+
 RouteManager::CallbackHandle RouteManager::registerDefaultRouteChangedCallback(DefaultRouteChangedCallback callback)
 {
 	AutoRecursiveLockType lock(m_defaultRouteCallbacksLock);
@@ -342,23 +357,7 @@ void RouteManager::unregisterDefaultRouteChangedCallback(CallbackHandle handle)
 	}
 }
 
-std::list<RouteManager::RouteRecord>::iterator RouteManager::findRouteRecord(const RegisteredRoute &route)
-{
-	return std::find_if(m_routes.begin(), m_routes.end(), [&route](const auto &record)
-	{
-		return route == record.registeredRoute;
-	});
-}
-
-std::list<RouteManager::RouteRecord>::iterator RouteManager::findRouteRecordFromSpec(const Route &route)
-{
-	return std::find_if(m_routes.begin(), m_routes.end(), [&route](const auto &record)
-	{
-		return route == record.route;
-	});
-}
-
-RouteManager::RegisteredRoute RouteManager::addIntoRoutingTable(const Route &route)
+RouteKey RouteManager::addIntoRoutingTable(const Route &route)
 {
 	const auto node = ResolveNode(route.network().Prefix.si_family, route.node());
 
@@ -396,18 +395,18 @@ RouteManager::RegisteredRoute RouteManager::addIntoRoutingTable(const Route &rou
 		THROW_WINDOWS_ERROR(status, "Register route in routing table");
 	}
 
-	return RegisteredRoute { route.network(), node.iface, node.gateway };
+	return RouteKey(node.iface, route.network(), node.gateway);
 }
 
-void RouteManager::restoreIntoRoutingTable(const RegisteredRoute &route)
+void RouteManager::restoreIntoRoutingTable(const RouteKey &route)
 {
 	MIB_IPFORWARD_ROW2 spec;
 
 	InitializeIpForwardEntry(&spec);
 
-	spec.InterfaceLuid = route.luid;
-	spec.DestinationPrefix = route.network;
-	spec.NextHop = route.nextHop;
+	spec.InterfaceLuid = route.iface();
+	spec.DestinationPrefix = route.network();
+	spec.NextHop = route.nextHop();
 	spec.Metric = 0;
 	spec.Protocol = MIB_IPPROTO_NETMGMT;
 	spec.Origin = NlroManual;
@@ -420,13 +419,13 @@ void RouteManager::restoreIntoRoutingTable(const RegisteredRoute &route)
 	}
 }
 
-void RouteManager::deleteFromRoutingTable(const RegisteredRoute &route)
+void RouteManager::deleteFromRoutingTable(const RouteKey &route)
 {
 	MIB_IPFORWARD_ROW2 r = { 0};
 
-	r.InterfaceLuid = route.luid;
-	r.DestinationPrefix = route.network;
-	r.NextHop = route.nextHop;
+	r.InterfaceLuid = route.iface();
+	r.DestinationPrefix = route.network();
+	r.NextHop = route.nextHop();
 
 	auto status = DeleteIpForwardEntry2(&r);
 
@@ -435,7 +434,7 @@ void RouteManager::deleteFromRoutingTable(const RegisteredRoute &route)
 		status = NO_ERROR;
 
 		const auto err = std::wstring(L"Attempting to delete route which was not present in routing table, " \
-			"ignoring and proceeding. Route: ").append(FormatRegisteredRoute(route));
+			"ignoring and proceeding. Route: ").append(FormatRouteKey(route));
 
 		m_logSink->warning(common::string::ToAnsi(err).c_str());
 	}
@@ -446,7 +445,7 @@ void RouteManager::deleteFromRoutingTable(const RegisteredRoute &route)
 	}
 }
 
-void RouteManager::undoEvents(const std::vector<EventEntry> &eventLog)
+void RouteManager::undoEvents(const std::vector<RouteRecords::Event> &eventLog)
 {
 	//
 	// Rewind state by processing events in the reverse order.
@@ -458,32 +457,32 @@ void RouteManager::undoEvents(const std::vector<EventEntry> &eventLog)
 		{
 			switch (it->type)
 			{
-				case EventType::ADD_ROUTE:
+				case RouteRecords::EventType::ADD:
 				{
-					const auto record = findRouteRecord(it->record.registeredRoute);
-
-					if (m_routes.end() == record)
-					{
-						THROW_ERROR("Internal state inconsistency in route manager");
-					}
-
-					deleteFromRoutingTable(record->registeredRoute);
-					m_routes.erase(record);
-
+					deleteFromRoutingTable(it->record.key);
 					break;
 				}
-				case EventType::DELETE_ROUTE:
+				case RouteRecords::EventType::UPDATE:
 				{
-					restoreIntoRoutingTable(it->record.registeredRoute);
-					m_routes.emplace_back(it->record);
+					//
+					// The route is still registered under the same key.
+					// Only the specification it was derived from has changed.
+					//
 
 					break;
 				}
+				case RouteRecords::EventType::REMOVE:
+				{
+					restoreIntoRoutingTable(it->record.key);
+					break;
+				}
 				default:
 				{
 					THROW_ERROR("Missing case handler in switch clause");
 				}
 			}
+
+			m_routes.revert(*it);
 		}
 		catch (const std::exception &ex)
 		{
@@ -494,40 +493,40 @@ void RouteManager::undoEvents(const std::vector<EventEntry> &eventLog)
 }
 
 // static
-std::wstring RouteManager::FormatRegisteredRoute(const RegisteredRoute &route)
+std::wstring RouteManager::FormatRouteKey(const RouteKey &route)
 {
 	using namespace common::string;
 
 	std::wstringstream ss;
 
-	if (AF_INET == route.network.Prefix.si_family)
+	if (AF_INET == route.network().Prefix.si_family)
 	{
 		std::wstring gateway(L"\"On-link\"");
 
-		if (0 != route.nextHop.Ipv4.sin_addr.s_addr)
+		if (0 != route.nextHop().Ipv4.sin_addr.s_addr)
 		{
-			gateway = FormatIpv4<AddressOrder::NetworkByteOrder>(route.nextHop.Ipv4.sin_addr.s_addr);
+			gateway = FormatIpv4<AddressOrder::NetworkByteOrder>(route.nextHop().Ipv4.sin_addr.s_addr);
 		}
 
-		ss << FormatIpv4<AddressOrder::NetworkByteOrder>(route.network.Prefix.Ipv4.sin_addr.s_addr, route.network.PrefixLength)
+		ss << FormatIpv4<AddressOrder::NetworkByteOrder>(route.network().Prefix.Ipv4.sin_addr.s_addr, route.network().PrefixLength)
 			<< L" with gateway " << gateway
-			<< L" on interface with LUID 0x" << std::hex << route.luid.Value;
+			<< L" on interface with LUID 0x" << std::hex << route.iface().Value;
 	}
-	else if (AF_INET6 == route.network.Prefix.si_family)
+	else if (AF_INET6 == route.network().Prefix.si_family)
 	{
 		std::wstring gateway(L"\"On-link\"");
 
-		const uint8_t *begin = &route.nextHop.Ipv6.sin6_addr.u.Byte[0];
+		const uint8_t *begin = &route.nextHop().Ipv6.sin6_addr.u.Byte[0];
 		const uint8_t *end = begin + 16;
 
 		if (0 != std::accumulate(begin, end, 0))
 		{
-			gateway = FormatIpv6(route.nextHop.Ipv6.sin6_addr.u.Byte);
+			gateway = FormatIpv6(route.nextHop().Ipv6.sin6_addr.u.Byte);
 		}
 
-		ss << FormatIpv6(route.network.Prefix.Ipv6.sin6_addr.u.Byte, route.network.PrefixLength)
+		ss << FormatIpv6(route.network().Prefix.Ipv6.sin6_addr.u.Byte, route.network().PrefixLength)
 			<< L" with gateway " << gateway
-			<< L" on interface with LUID 0x" << std::hex << route.luid.Value;
+			<< L" on interface with LUID 0x" << std::hex << route.iface().Value;
 	}
 	else
 	{
@@ -538,6 +537,7 @@ std::wstring RouteManager::FormatRegisteredRoute(const RegisteredRoute &route)
 }
 
 void RouteManager::defaultRouteChanged(ADDRESS_FAMILY family, DefaultRouteMonitor::EventType eventType,
+	const std::optional<InterfaceAndGateway> &previousRoute,
 	const std::optional<InterfaceAndGateway> &route)
 {
 	//
@@ -550,7 +550,7 @@ void RouteManager::defaultRouteChanged(ADDRESS_FAMILY family, DefaultRouteMonito
 	{
 		try
 		{
-			callback(eventType, family, route);
+			callback(eventType, family, previousRoute, route);
 		}
 		catch (const std::exception &ex)
 		{
@@ -580,11 +580,9 @@ void RouteManager::defaultRouteChanged(ADDRESS_FAMILY family, DefaultRouteMonito
 
 	AutoLockType routesLock(m_routesLock);
 
-	using RecordIterator = std::list<RouteRecord>::iterator;
+	std::list<RouteRecords::iterator> affectedRoutes;
 
-	std::list<RecordIterator> affectedRoutes;
-
-	for (RecordIterator it = m_routes.begin(); it != m_routes.end(); ++it)
+	for (auto it = m_routes.begin(); it != m_routes.end(); ++it)
 	{
 		if (false == it->route.node().has_value()
 			&& family == it->route.network().Prefix.si_family)
@@ -613,7 +611,7 @@ void RouteManager::defaultRouteChanged(ADDRESS_FAMILY family, DefaultRouteMonito
 
 		try
 		{
-			deleteFromRoutingTable(it->registeredRoute);
+			deleteFromRoutingTable(it->key);
 		}
 		catch (const std::exception &ex)
 		{
@@ -625,12 +623,28 @@ void RouteManager::defaultRouteChanged(ADDRESS_FAMILY family, DefaultRouteMonito
 			continue;
 		}
 
-		it->registeredRoute.luid = route.value().iface;
-		it->registeredRoute.nextHop = route.value().gateway;
+		const RouteKey newKey(route.value().iface, it->key.network(), route.value().gateway);
+
+		//
+		// Keys must remain unique. If another record already registered the same route,
+		// the route is in the routing table and this record is redundant.
+		//
+
+		const auto duplicate = m_routes.find(newKey);
+
+		if (m_routes.end() != duplicate && it != duplicate)
+		{
+			m_logSink->info("Refreshed route is already registered by another route. Dropping it");
+			m_routes.erase(it);
+
+			continue;
+		}
+
+		it->key = newKey;
 
 		try
 		{
-			restoreIntoRoutingTable(it->registeredRoute);
+			restoreIntoRoutingTable(it->key);
 		}
 		catch (const std::exception &ex)
 		{
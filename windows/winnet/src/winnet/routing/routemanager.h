@@ -14,6 +14,7 @@
 #include <libcommon/logging/ilogsink.h>
 #include "defaultroutemonitor.h"
 #include "helpers.h"
+#include "routerecords.h"
 
 namespace winnet::routing
 {
@@ -79,6 +80,10 @@ public:
 	void deleteRoutes(const std::vector<Route> &routes);
 	void deleteAppliedRoutes();
 
+	// Deletes routes that are not owned by this instance, e.g. routes left behind by a
+	// previous instance.
+	void deleteUnownedRoutes(const std::vector<Route> &routes);
+
 	using DefaultRouteChangedEventType = DefaultRouteMonitor::EventType;
 
 	using DefaultRouteChangedCallback = std::function<void
@@ -86,6 +91,9 @@ public:
 		DefaultRouteChangedEventType eventType,
 		ADDRESS_FAMILY family,
 
+		// The best default route before the event, if there was one.
+		const std::optional<InterfaceAndGateway> &previousRoute,
+
 		// For update events, data associated with the new best default route.
 		const std::optional<InterfaceAndGateway> &route
 	)>;
@@ -102,74 +110,22 @@ private:
 	std::unique_ptr<DefaultRouteMonitor> m_routeMonitorV4;
 	std::unique_ptr<DefaultRouteMonitor> m_routeMonitorV6;
 
-	// These are the exact details derived from the route specification (`Route`).
-	// They are used when registering and deleting a route in the system.
-	struct RegisteredRoute
-	{
-		Network network;
-		NET_LUID luid;
-		NodeAddress nextHop;
-
-		bool operator==(const RegisteredRoute &rhs) const
-		{
-			return luid.Value == rhs.luid.Value
-				&& EqualAddress(nextHop, rhs.nextHop)
-				&& EqualAddress(network, rhs.network);
-		}
-	};
-
-	struct RouteRecord
-	{
-		Route route;
-		RegisteredRoute registeredRoute;
-	};
-
-	std::list<RouteRecord> m_routes;
+	RouteRecords m_routes;
 	std::mutex m_routesLock;
 
 	std::list<DefaultRouteChangedCallback> m_defaultRouteCallbacks;
 	std::recursive_mutex m_defaultRouteCallbacksLock;
 
-	//
-	// Find record based on route registration data.
-	//
-	// Note: Searching the records and matching on route specification is
-	// unreliable because of the node attribute on the route. Different node
-	// specifications can resolve to the same physical node.
-	//
-	// (node = exit node = interface)
-	//
-	std::list<RouteRecord>::iterator findRouteRecord(const RegisteredRoute &route);
-
-	//
-	// Find record based on route specification.
-	//
-	// Note: Only ever use this to find the registration data for a route
-	// that was successfully registered previously.
-	//
-	std::list<RouteRecord>::iterator findRouteRecordFromSpec(const Route &route);
-
-	RegisteredRoute addIntoRoutingTable(const Route &route);
-	void restoreIntoRoutingTable(const RegisteredRoute &route);
-	void deleteFromRoutingTable(const RegisteredRoute &route);
-
-	enum class EventType
-	{
-		ADD_ROUTE,
-		DELETE_ROUTE,
-	};
-
-	struct EventEntry
-	{
-		EventType type;
-		RouteRecord record;
-	};
+	RouteKey addIntoRoutingTable(const Route &route);
+	void restoreIntoRoutingTable(const RouteKey &route);
+	void deleteFromRoutingTable(const RouteKey &route);
 
-	void undoEvents(const std::vector<EventEntry> &eventLog);
+	void undoEvents(const std::vector<RouteRecords::Event> &eventLog);
 
-	static std::wstring FormatRegisteredRoute(const RegisteredRoute &route);
+	static std::wstring FormatRouteKey(const RouteKey &route);
 
 	void defaultRouteChanged(ADDRESS_FAMILY family, DefaultRouteMonitor::EventType eventType,
+		const std::optional<InterfaceAndGateway> &previousRoute,
 		const std::optional<InterfaceAndGateway> &route);
 };
 
@@ -0,0 +1,98 @@
+#include "stdafx.h"
+#include "routerecords.h"
+#include <libcommon/error.h>
+#include <algorithm>
+#include <utility>
+
+namespace winnet::routing
+{
+
+RouteRecords::Event RouteRecords::insert(RouteRecord record)
+{
+	auto existing = find(record.key);
+
+	if (m_records.end() == existing)
+	{
+		m_records.emplace_back(record);
+
+		return Event{ EventType::ADD, std::move(record), std::nullopt };
+	}
+
+	auto previous = std::exchange(*existing, record);
+
+	return Event{ EventType::UPDATE, std::move(record), std::move(previous) };
+}
+
+RouteRecords::Event RouteRecords::erase(iterator it)
+{
+	auto record = *it;
+	m_records.erase(it);
+
+	return Event{ EventType::REMOVE, std::move(record), std::nullopt };
+}
+
+void RouteRecords::revert(const Event &event)
+{
+	switch (event.type)
+	{
+		case EventType::ADD:
+		{
+			const auto it = find(event.record.key);
+
+			if (m_records.end() == it)
+			{
+				THROW_ERROR("Internal state inconsistency in route records");
+			}
+
+			m_records.erase(it);
+
+			break;
+		}
+		case EventType::UPDATE:
+		{
+			const auto it = find(event.record.key);
+
+			if (m_records.end() == it || false == event.previous.has_value())
+			{
+				THROW_ERROR("Internal state inconsistency in route records");
+			}
+
+			*it = event.previous.value();
+
+			break;
+		}
+		case EventType::REMOVE:
+		{
+			if (m_records.end() != find(event.record.key))
+			{
+				THROW_ERROR("Internal state inconsistency in route records");
+			}
+
+			m_records.emplace_back(event.record);
+
+			break;
+		}
+		default:
+		{
+			THROW_ERROR("Missing case handler in switch clause");
+		}
+	}
+}
+
+RouteRecords::iterator RouteRecords::find(const RouteKey &key)
+{
+	return std::find_if(m_records.begin(), m_records.end(), [&key](const auto &record)
+	{
+		return key == record.key;
+	});
+}
+
+RouteRecords::iterator RouteRecords::findBySpec(const Route &route)
+{
+	return std::find_if(m_records.begin(), m_records.end(), [&route](const auto &record)
+	{
+		return route == record.route;
+	});
+}
+
+}
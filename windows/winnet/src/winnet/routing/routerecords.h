@@ -0,0 +1,134 @@
+#pragma once
+
+#include "types.h"
+#include <list>
+#include <optional>
+
+namespace winnet::routing
+{
+
+struct RouteRecord
+{
+	// The route as it was specified.
+	Route route;
+
+	// The route as it was registered in the routing table.
+	RouteKey key;
+};
+
+//
+// Bookkeeping of the routes that have been registered in the routing table.
+//
+// Records are unique by key. Every change returns an event that can be passed to
+// `revert()` to undo the change. Reverting events in the reverse order in which
+// they were returned restores the exact set of records from before the changes.
+//
+// This class does not touch the routing table.
+//
+class RouteRecords
+{
+public:
+
+	using iterator = std::list<RouteRecord>::iterator;
+	using const_iterator = std::list<RouteRecord>::const_iterator;
+
+	enum class EventType
+	{
+		// A record with a new key was added.
+		ADD,
+
+		// A record replaced an existing record with the same key.
+		UPDATE,
+
+		// A record was removed.
+		REMOVE,
+	};
+
+	struct Event
+	{
+		EventType type;
+
+		// The record that was added, the new version of an updated record,
+		// or the record that was removed.
+		RouteRecord record;
+
+		// The replaced record, for update events.
+		std::optional<RouteRecord> previous;
+	};
+
+	//
+	// Adds the record, replacing any record with the same key.
+	//
+	Event insert(RouteRecord record);
+
+	//
+	// Removes the record at `it`, which must be a valid iterator.
+	//
+	Event erase(iterator it);
+
+	//
+	// Undoes the change that produced `event`.
+	// Throws if the records are not in the state that the event left them in.
+	//
+	void revert(const Event &event);
+
+	//
+	// Find record based on route registration data.
+	//
+	// Note: Searching the records and matching on route specification is
+	// unreliable because of the node attribute on the route. Different node
+	// specifications can resolve to the same physical node.
+	//
+	// (node = exit node = interface)
+	//
+	iterator find(const RouteKey &key);
+
+	//
+	// Find record based on route specification.
+	//
+	// Note: Only ever use this to find the registration data for a route
+	// that was successfully registered previously.
+	//
+	iterator findBySpec(const Route &route);
+
+	iterator begin()
+	{
+		return m_records.begin();
+	}
+
+	iterator end()
+	{
+		return m_records.end();
+	}
+
+	const_iterator begin() const
+	{
+		return m_records.begin();
+	}
+
+	const_iterator end() const
+	{
+		return m_records.end();
+	}
+
+	size_t size() const
+	{
+		return m_records.size();
+	}
+
+	bool empty() const
+	{
+		return m_records.empty();
+	}
+
+	void clear()
+	{
+		m_records.clear();
+	}
+
+private:
+
+	std::list<RouteRecord> m_records;
+};
+
+}
@@ -79,13 +79,58 @@ bool Route::operator==(const Route &rhs) const
 		&& EqualAddress(m_network, rhs.network());
 }
 
-bool InterfaceAndGateway::operator==(const InterfaceAndGateway &rhs)
+bool InterfaceAndGateway::operator==(const InterfaceAndGateway &rhs) const
 {
-	return iface.Value == rhs.iface.Value
+	return sameInterface(rhs)
 		&& EqualAddress(gateway, rhs.gateway);
 }
 
-bool InterfaceAndGateway::operator!=(const InterfaceAndGateway &rhs)
+bool InterfaceAndGateway::operator!=(const InterfaceAndGateway &rhs) const
+{
+	return !(*this == rhs);
+}
+
+bool InterfaceAndGateway::sameInterface(const InterfaceAndGateway &rhs) const
+{
+	return sameInterface(rhs.iface);
+}
+
+bool InterfaceAndGateway::sameInterface(const NET_LUID &rhs) const
+{
+	return iface.Value == rhs.Value;
+}
+
+RouteKey::RouteKey(const NET_LUID &iface, const Network &network, const NodeAddress &nextHop)
+	: m_iface(iface)
+	, m_network(network)
+	, m_nextHop(nextHop)
+{
+	const auto family = m_network.Prefix.si_family;
+
+	if (AF_INET != family && AF_INET6 != family)
+	{
+		THROW_ERROR("Invalid address family for route destination");
+	}
+
+	if (AF_UNSPEC == m_nextHop.si_family)
+	{
+		m_nextHop = NodeAddress{ 0 };
+		m_nextHop.si_family = family;
+	}
+	else if (family != m_nextHop.si_family)
+	{
+		THROW_ERROR("Address family of next hop does not match destination");
+	}
+}
+
+bool RouteKey::operator==(const RouteKey &rhs) const
+{
+	return m_iface.Value == rhs.m_iface.Value
+		&& EqualAddress(m_network, rhs.m_network)
+		&& EqualAddress(m_nextHop, rhs.m_nextHop);
+}
+
+bool RouteKey::operator!=(const RouteKey &rhs) const
 {
 	return !(*this == rhs);
 }
@@ -67,8 +67,57 @@ struct InterfaceAndGateway
 	NET_LUID iface;
 	NodeAddress gateway;
 
-	bool operator==(const InterfaceAndGateway &rhs);
-	bool operator!=(const InterfaceAndGateway &rhs);
+	//
+	// Compares both the interface and the gateway.
+	// Use `sameInterface()` where only the interface is of interest.
+	//
+	bool operator==(const InterfaceAndGateway &rhs) const;
+	bool operator!=(const InterfaceAndGateway &rhs) const;
+
+	bool sameInterface(const InterfaceAndGateway &rhs) const;
+	bool sameInterface(const NET_LUID &rhs) const;
+};
+
+//
+// Identifies a route in the routing table.
+//
+// Windows identifies a route by its interface, destination prefix and next hop.
+// All other properties may be adjusted by the system after the route is added,
+// so they are not part of the key.
+//
+// The next hop of an on-link route is the unspecified address of the same family
+// as the destination. Keys are normalized on construction so that on-link routes
+// compare equal regardless of whether the family of the next hop was set.
+//
+class RouteKey
+{
+public:
+
+	RouteKey(const NET_LUID &iface, const Network &network, const NodeAddress &nextHop);
+
+	const NET_LUID &iface() const
+	{
+		return m_iface;
+	}
+
+	const Network &network() const
+	{
+		return m_network;
+	}
+
+	const NodeAddress &nextHop() const
+	{
+		return m_nextHop;
+	}
+
+	bool operator==(const RouteKey &rhs) const;
+	bool operator!=(const RouteKey &rhs) const;
+
+private:
+
+	NET_LUID m_iface;
+	Network m_network;
+	NodeAddress m_nextHop;
 };
 
 }
@@ -11,6 +11,7 @@
 #include <memory>
 #include <optional>
 #include <mutex>
+#include <vector>
 
 using namespace winnet::routing;
 using AutoLockType = std::scoped_lock<std::mutex>;
@@ -22,6 +23,44 @@ std::mutex g_RouteManagerLock;
 RouteManager *g_RouteManager = nullptr;
 std::shared_ptr<shared::logging::LogSinkAdapter> g_RouteManagerLogSink;
 
+WINNET_DEFAULT_ROUTE ConvertDefaultRoute(const InterfaceAndGateway &route)
+{
+	WINNET_DEFAULT_ROUTE converted = { 0 };
+
+	const auto ips = winnet::ConvertNativeAddresses(&route.gateway, 1);
+	converted.gateway = ips[0];
+	converted.interfaceLuid = route.iface.Value;
+
+	return converted;
+}
+
+//
+// Best effort. Fields are left zeroed if details cannot be obtained.
+//
+void GetInterfaceDetails(ADDRESS_FAMILY family, const NET_LUID &luid, WINNET_INTERFACE_DETAILS *details)
+{
+	MIB_IF_ROW2 ifRow = { 0 };
+	ifRow.InterfaceLuid = luid;
+
+	if (NO_ERROR == GetIfEntry2(&ifRow))
+	{
+		details->index = ifRow.InterfaceIndex;
+		details->transmitLinkSpeed = ifRow.TransmitLinkSpeed;
+		details->receiveLinkSpeed = ifRow.ReceiveLinkSpeed;
+		wcsncpy_s(details->alias, ifRow.Alias, _TRUNCATE);
+	}
+
+	MIB_IPINTERFACE_ROW ipRow = { 0 };
+	InitializeIpInterfaceEntry(&ipRow);
+	ipRow.Family = family;
+	ipRow.InterfaceLuid = luid;
+
+	if (NO_ERROR == GetIpInterfaceEntry(&ipRow))
+	{
+		details->mtu = ipRow.NlMtu;
+	}
+}
+
 } //anonymous namespace
 
 extern "C"
@@ -30,6 +69,8 @@ WINNET_STATUS
 WINNET_API
 WinNet_GetBestDefaultRoute(
 	WINNET_ADDR_FAMILY family,
+	const uint64_t *excludedLuids,
+	uint32_t numExcludedLuids,
 	WINNET_DEFAULT_ROUTE *route,
 	MullvadLogSink logSink,
 	void *logSinkContext
@@ -42,6 +83,11 @@ WinNet_GetBestDefaultRoute(
 			THROW_ERROR("Invalid argument: route");
 		}
 
+		if (nullptr == excludedLuids && 0 != numExcludedLuids)
+		{
+			THROW_ERROR("Invalid argument: excludedLuids");
+		}
+
 		static const std::pair<WINNET_ADDR_FAMILY, ADDRESS_FAMILY> familyMap[] =
 		{
 			{ WINNET_ADDR_FAMILY_IPV4, static_cast<ADDRESS_FAMILY>(AF_INET) },
@@ -49,7 +95,14 @@ WinNet_GetBestDefaultRoute(
 		};
 		const auto win_family = common::ValueMapper::Map<>(family, familyMap);
 
-		const auto ifaceAndGateway = GetBestDefaultRoute(win_family);
+		std::vector<NET_LUID> excluded(numExcludedLuids);
+
+		for (uint32_t i = 0; i < numExcludedLuids; ++i)
+		{
+			excluded[i].Value = excludedLuids[i];
+		}
+
+		const auto ifaceAndGateway = GetBestDefaultRoute(win_family, excluded);
 
 		if (!ifaceAndGateway.has_value())
 		{
@@ -236,6 +289,43 @@ WinNet_DeleteAppliedRoutes()
 	}
 }
 
+extern "C"
+WINNET_LINKAGE
+bool
+WINNET_API
+WinNet_DeleteUnownedRoutes(
+	const WINNET_ROUTE *routes,
+	uint32_t numRoutes
+)
+{
+	AutoLockType lock(g_RouteManagerLock);
+
+	if (nullptr == g_RouteManager)
+	{
+		return false;
+	}
+
+	try
+	{
+		if (nullptr == routes)
+		{
+			THROW_ERROR("Invalid argument: routes");
+		}
+
+		g_RouteManager->deleteUnownedRoutes(winnet::ConvertRoutes(routes, numRoutes));
+		return true;
+	}
+	catch (const std::exception &err)
+	{
+		common::error::UnwindException(err, g_RouteManagerLogSink);
+		return false;
+	}
+	catch (...)
+	{
+		return false;
+	}
+}
+
 extern "C"
 WINNET_LINKAGE
 bool
@@ -277,7 +367,8 @@ WinNet_RegisterDefaultRouteChangedCallback(
 		}
 
 		auto forwarder = [callback, context](RouteManager::DefaultRouteChangedEventType eventType,
-			ADDRESS_FAMILY family, const std::optional<InterfaceAndGateway> &route)
+			ADDRESS_FAMILY family, const std::optional<InterfaceAndGateway> &previousRoute,
+			const std::optional<InterfaceAndGateway> &route)
 		{
 			//
 			// Translate the event type.
@@ -307,7 +398,13 @@ WinNet_RegisterDefaultRouteChangedCallback(
 
 			const auto translatedFamily = common::ValueMapper::Map<>(family, familyMap);
 
-			WINNET_DEFAULT_ROUTE defaultRoute = { 0 };
+			WINNET_DEFAULT_ROUTE_CHANGE change = { 0 };
+
+			if (previousRoute.has_value())
+			{
+				change.hasPreviousRoute = true;
+				change.previousRoute = ConvertDefaultRoute(previousRoute.value());
+			}
 
 			//
 			// Determine which LUID and gateway to forward.
@@ -318,9 +415,8 @@ WinNet_RegisterDefaultRouteChangedCallback(
 				case RouteManager::DefaultRouteChangedEventType::Updated:
 				case RouteManager::DefaultRouteChangedEventType::UpdatedDetails:
 				{
-					const auto ips = winnet::ConvertNativeAddresses(&route.value().gateway, 1);
-					defaultRoute.gateway = ips[0];
-					defaultRoute.interfaceLuid = route.value().iface.Value;
+					change.route = ConvertDefaultRoute(route.value());
+					GetInterfaceDetails(family, route.value().iface, &change.interfaceDetails);
 					break;
 				}
 			}
@@ -329,7 +425,7 @@ WinNet_RegisterDefaultRouteChangedCallback(
 			// Forward to client.
 			//
 
-			callback(translatedEventType, translatedFamily, defaultRoute, context);
+			callback(translatedEventType, translatedFamily, &change, context);
 		};
 
 		*registrationHandle = g_RouteManager->registerDefaultRouteChangedCallback(forwarder);
@@ -109,6 +109,15 @@ WINNET_API
 WinNet_DeleteAppliedRoutes(
 );
 
+extern "C"
+WINNET_LINKAGE
+bool
+WINNET_API
+WinNet_DeleteUnownedRoutes(
+	const WINNET_ROUTE *routes,
+	uint32_t numRoutes
+);
+
 typedef struct tag_WINNET_DEFAULT_ROUTE
 {
 	uint64_t interfaceLuid;
@@ -129,6 +138,10 @@ WINNET_STATUS
 WINNET_API
 WinNet_GetBestDefaultRoute(
 	WINNET_ADDR_FAMILY family,
+	// Interfaces that are never considered, such as the tunnel interface.
+	// May be null if `numExcludedLuids` is zero.
+	const uint64_t *excludedLuids,
+	uint32_t numExcludedLuids,
 	WINNET_DEFAULT_ROUTE *route,
 	MullvadLogSink logSink,
 	void *logSinkContext
@@ -147,6 +160,39 @@ enum WINNET_DEFAULT_ROUTE_CHANGED_EVENT_TYPE
 	WINNET_DEFAULT_ROUTE_CHANGED_EVENT_TYPE_REMOVED = 2,
 };
 
+typedef struct tag_WINNET_INTERFACE_DETAILS
+{
+	// Interface index. Zero if the details could not be obtained.
+	uint32_t index;
+
+	// IP layer MTU for the address family of the event.
+	uint32_t mtu;
+
+	// Link speeds in bits per second.
+	uint64_t transmitLinkSpeed;
+	uint64_t receiveLinkSpeed;
+
+	// Null-terminated interface alias. The size is `IF_MAX_STRING_SIZE + 1`.
+	wchar_t alias[257];
+}
+WINNET_INTERFACE_DETAILS;
+
+typedef struct tag_WINNET_DEFAULT_ROUTE_CHANGE
+{
+	// Indicates whether `previousRoute` is set.
+	bool hasPreviousRoute;
+
+	// The best default route before the event.
+	WINNET_DEFAULT_ROUTE previousRoute;
+
+	// For update events, the new best default route.
+	WINNET_DEFAULT_ROUTE route;
+
+	// For update events, details of the interface associated with `route`.
+	WINNET_INTERFACE_DETAILS interfaceDetails;
+}
+WINNET_DEFAULT_ROUTE_CHANGE;
+
 typedef void (WINNET_API *WinNetDefaultRouteChangedCallback)
 (
 	WINNET_DEFAULT_ROUTE_CHANGED_EVENT_TYPE eventType,
@@ -154,8 +200,8 @@ typedef void (WINNET_API *WinNetDefaultRouteChangedCallback)
 	// Indicates which IP family the event relates to.
 	WINNET_ADDR_FAMILY family,
 
-	// For update events, indicates the interface associated with the new best default route.
-	WINNET_DEFAULT_ROUTE route,
+	// The previous and, for update events, the new best default route.
+	const WINNET_DEFAULT_ROUTE_CHANGE *change,
 
 	void *context
 );
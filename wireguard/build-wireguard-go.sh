@@ -39,6 +39,13 @@ function win_gather_export_symbols {
    grep -Eo "\/\/export \w+" libwg.go libwg_windows.go | cut -d' ' -f2
 }
 
+function win_machine_type {
+    case "$1" in
+        "aarch64-pc-windows-msvc") echo "ARM64";;
+        *) echo "X64";;
+    esac
+}
+
 function win_create_lib_file {
     echo "LIBRARY libwg" > exports.def
     echo "EXPORTS" >> exports.def
@@ -51,17 +58,23 @@ function win_create_lib_file {
     "$lib_path" \
         "/def:exports.def" \
         "/out:libwg.lib" \
-        "/machine:X64"
+        "/machine:$(win_machine_type "$1")"
 
 }
 
 function build_windows {
-    echo "Building wireguard-go for Windows"
+    echo "Building wireguard-go for $1"
+
+    if [[ "$1" == "aarch64-pc-windows-msvc" ]]; then
+        export CGO_ENABLED=1
+        export GOARCH=arm64
+    fi
+
     pushd libwg
         go build -v -o libwg.dll -buildmode c-shared
-        win_create_lib_file
+        win_create_lib_file "$1"
 
-        target_dir=../../build/lib/x86_64-pc-windows-msvc/
+        target_dir=../../build/lib/$1/
         mkdir -p $target_dir
         mv libwg.dll libwg.lib $target_dir
     popd
@@ -142,7 +155,7 @@ function build_wireguard_go {
     local platform="$(uname -s)";
     case  "$platform" in
         Linux*|Darwin*) build_unix ${1:-$(unix_target_triple)};;
-        MINGW*|MSYS_NT*) build_windows;;
+        MINGW*|MSYS_NT*) build_windows ${1:-x86_64-pc-windows-msvc};;
     esac
 }
 
@@ -0,0 +1,227 @@
+// +build darwin linux
+// +build !android
+
+/* SPDX-License-Identifier: Apache-2.0
+ *
+ * Copyright (C) 2017-2022 WireGuard LLC. All Rights Reserved.
+ * Copyright (C) 2022 Mullvad VPN AB. All Rights Reserved.
+ */
+
+package interfacebind
+
+import (
+	"context"
+	"errors"
+	"net"
+	"net/netip"
+	"strconv"
+	"sync"
+	"syscall"
+
+	"golang.zx2c4.com/wireguard/conn"
+)
+
+var (
+	errBindAlreadyOpen   = errors.New("bind is already open")
+	errWrongEndpointType = errors.New("endpoint type does not correspond with bind type")
+)
+
+// Bind is a conn.Bind whose sockets can only send and receive through a single interface,
+// regardless of the routing table. It is based on conn.StdNetBind.
+type Bind struct {
+	mu             sync.Mutex
+	interfaceIndex uint32
+	mark           uint32
+	ipv4           *net.UDPConn
+	ipv6           *net.UDPConn
+}
+
+// NewBind returns a bind whose sockets are bound to the interface with the given index.
+func NewBind(interfaceIndex uint32) *Bind {
+	return &Bind{interfaceIndex: interfaceIndex}
+}
+
+type endpoint netip.AddrPort
+
+var (
+	_ conn.Bind     = (*Bind)(nil)
+	_ conn.Endpoint = endpoint{}
+)
+
+func (*Bind) ParseEndpoint(s string) (conn.Endpoint, error) {
+	addrPort, err := netip.ParseAddrPort(s)
+	return endpoint(addrPort), err
+}
+
+func (endpoint) ClearSrc() {}
+
+func (e endpoint) DstIP() netip.Addr {
+	return netip.AddrPort(e).Addr()
+}
+
+func (e endpoint) SrcIP() netip.Addr {
+	return netip.Addr{}
+}
+
+func (e endpoint) DstToBytes() []byte {
+	b, _ := netip.AddrPort(e).MarshalBinary()
+	return b
+}
+
+func (e endpoint) DstToString() string {
+	return netip.AddrPort(e).String()
+}
+
+func (e endpoint) SrcToString() string {
+	return ""
+}
+
+func (bind *Bind) listenNet(network string, port int) (*net.UDPConn, int, error) {
+	config := net.ListenConfig{
+		Control: func(network, address string, rawConn syscall.RawConn) error {
+			var sockErr error
+			err := rawConn.Control(func(fd uintptr) {
+				sockErr = bindToInterface(int(fd), network, bind.interfaceIndex)
+				if sockErr == nil && bind.mark != 0 {
+					sockErr = setMark(int(fd), bind.mark)
+				}
+			})
+			if err != nil {
+				return err
+			}
+			return sockErr
+		},
+	}
+	packetConn, err := config.ListenPacket(context.Background(), network, ":"+strconv.Itoa(port))
+	if err != nil {
+		return nil, 0, err
+	}
+	udpConn := packetConn.(*net.UDPConn)
+	return udpConn, udpConn.LocalAddr().(*net.UDPAddr).Port, nil
+}
+
+func (bind *Bind) Open(uport uint16) ([]conn.ReceiveFunc, uint16, error) {
+	bind.mu.Lock()
+	defer bind.mu.Unlock()
+
+	var err error
+	var tries int
+
+	if bind.ipv4 != nil || bind.ipv6 != nil {
+		return nil, 0, errBindAlreadyOpen
+	}
+
+	// Attempt to open ipv4 and ipv6 listeners on the same port.
+	// If uport is 0, we can retry on failure.
+again:
+	port := int(uport)
+	var ipv4, ipv6 *net.UDPConn
+
+	ipv4, port, err = bind.listenNet("udp4", port)
+	if err != nil && !errors.Is(err, syscall.EAFNOSUPPORT) {
+		return nil, 0, err
+	}
+
+	// Listen on the same port as we're using for ipv4.
+	ipv6, port, err = bind.listenNet("udp6", port)
+	if uport == 0 && errors.Is(err, syscall.EADDRINUSE) && tries < 100 {
+		ipv4.Close()
+		tries++
+		goto again
+	}
+	if err != nil && !errors.Is(err, syscall.EAFNOSUPPORT) {
+		if ipv4 != nil {
+			ipv4.Close()
+		}
+		return nil, 0, err
+	}
+	var fns []conn.ReceiveFunc
+	if ipv4 != nil {
+		fns = append(fns, makeReceiveFunc(ipv4))
+		bind.ipv4 = ipv4
+	}
+	if ipv6 != nil {
+		fns = append(fns, makeReceiveFunc(ipv6))
+		bind.ipv6 = ipv6
+	}
+	if len(fns) == 0 {
+		return nil, 0, syscall.EAFNOSUPPORT
+	}
+	return fns, uint16(port), nil
+}
+
+func (bind *Bind) Close() error {
+	bind.mu.Lock()
+	defer bind.mu.Unlock()
+
+	var err1, err2 error
+	if bind.ipv4 != nil {
+		err1 = bind.ipv4.Close()
+		bind.ipv4 = nil
+	}
+	if bind.ipv6 != nil {
+		err2 = bind.ipv6.Close()
+		bind.ipv6 = nil
+	}
+	if err1 != nil {
+		return err1
+	}
+	return err2
+}
+
+// SetMark sets the firewall mark of the sockets, so that the policy routing rules let the
+// traffic bypass the tunnel.
+func (bind *Bind) SetMark(mark uint32) error {
+	bind.mu.Lock()
+	defer bind.mu.Unlock()
+
+	bind.mark = mark
+	for _, udpConn := range []*net.UDPConn{bind.ipv4, bind.ipv6} {
+		if udpConn == nil {
+			continue
+		}
+		rawConn, err := udpConn.SyscallConn()
+		if err != nil {
+			return err
+		}
+		var sockErr error
+		err = rawConn.Control(func(fd uintptr) {
+			sockErr = setMark(int(fd), mark)
+		})
+		if err != nil {
+			return err
+		}
+		if sockErr != nil {
+			return sockErr
+		}
+	}
+	return nil
+}
+
+func makeReceiveFunc(udpConn *net.UDPConn) conn.ReceiveFunc {
+	return func(buff []byte) (int, conn.Endpoint, error) {
+		n, addrPort, err := udpConn.ReadFromUDPAddrPort(buff)
+		return n, endpoint(addrPort), err
+	}
+}
+
+func (bind *Bind) Send(buff []byte, ep conn.Endpoint) error {
+	nend, ok := ep.(endpoint)
+	if !ok {
+		return errWrongEndpointType
+	}
+	addrPort := netip.AddrPort(nend)
+
+	bind.mu.Lock()
+	udpConn := bind.ipv4
+	if addrPort.Addr().Is6() {
+		udpConn = bind.ipv6
+	}
+	bind.mu.Unlock()
+
+	if udpConn == nil {
+		return syscall.EAFNOSUPPORT
+	}
+	_, err := udpConn.WriteToUDPAddrPort(buff, addrPort)
+	return err
+}
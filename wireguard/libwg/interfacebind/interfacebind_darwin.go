@@ -0,0 +1,22 @@
+/* SPDX-License-Identifier: Apache-2.0
+ *
+ * Copyright (C) 2022 Mullvad VPN AB. All Rights Reserved.
+ */
+
+package interfacebind
+
+import (
+	"golang.org/x/sys/unix"
+)
+
+func bindToInterface(fd int, network string, interfaceIndex uint32) error {
+	if network == "udp6" {
+		return unix.SetsockoptInt(fd, unix.IPPROTO_IPV6, unix.IPV6_BOUND_IF, int(interfaceIndex))
+	}
+	return unix.SetsockoptInt(fd, unix.IPPROTO_IP, unix.IP_BOUND_IF, int(interfaceIndex))
+}
+
+// Firewall marks do not exist on macOS, where the route to the relay bypasses the tunnel.
+func setMark(fd int, mark uint32) error {
+	return nil
+}
@@ -0,0 +1,20 @@
+// +build !android
+
+/* SPDX-License-Identifier: Apache-2.0
+ *
+ * Copyright (C) 2022 Mullvad VPN AB. All Rights Reserved.
+ */
+
+package interfacebind
+
+import (
+	"golang.org/x/sys/unix"
+)
+
+func bindToInterface(fd int, network string, interfaceIndex uint32) error {
+	return unix.SetsockoptInt(fd, unix.SOL_SOCKET, unix.SO_BINDTOIFINDEX, int(interfaceIndex))
+}
+
+func setMark(fd int, mark uint32) error {
+	return unix.SetsockoptInt(fd, unix.SOL_SOCKET, unix.SO_MARK, int(mark))
+}
@@ -37,6 +37,9 @@ func wgTurnOff(tunnelHandle int32) {
 		if err != nil {
 			return
 		}
+		if tunnel.Shaper != nil {
+			tunnel.Shaper.Close()
+		}
 		tunnel.Device.Close()
 	}
 	// Calling twice convinces the GC to release NOW.
@@ -78,6 +81,42 @@ func wgSetConfig(tunnelHandle int32, cSettings *C.char) int32 {
 		tunnel.Logger.Errorf("%s\n", setError)
 		return ERROR_GENERAL_FAILURE
 	}
+	if tunnel.Shaper != nil {
+		if err := tunnel.Shaper.UpdatePeers(tunnel.Device); err != nil {
+			tunnel.Logger.Errorf("Failed to update peers for dummy traffic: %s\n", err)
+		}
+	}
+	return 0
+}
+
+//export wgSetTrafficShaping
+func wgSetTrafficShaping(tunnelHandle int32, padding bool, dummyTraffic bool) int32 {
+	tunnel, err := tunnels.Get(tunnelHandle)
+	if err != nil {
+		return ERROR_GENERAL_FAILURE
+	}
+	if tunnel.Shaper == nil {
+		return ERROR_GENERAL_FAILURE
+	}
+	if err := tunnel.Shaper.Set(tunnel.Device, padding, dummyTraffic); err != nil {
+		tunnel.Logger.Errorf("Failed to set up traffic shaping: %s\n", err)
+		return ERROR_GENERAL_FAILURE
+	}
+	return 0
+}
+
+//export wgGetTrafficShapingStats
+func wgGetTrafficShapingStats(tunnelHandle int32, paddedPackets *uint64, injectedPackets *uint64) int32 {
+	tunnel, err := tunnels.Get(tunnelHandle)
+	if err != nil || tunnel.Shaper == nil {
+		return ERROR_GENERAL_FAILURE
+	}
+	padded, injected, ok := tunnel.Shaper.Stats()
+	if !ok {
+		return ERROR_GENERAL_FAILURE
+	}
+	*paddedPackets = padded
+	*injectedPackets = injected
 	return 0
 }
 
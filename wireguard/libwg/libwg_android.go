@@ -19,6 +19,7 @@ import (
 	"golang.zx2c4.com/wireguard/tun"
 
 	"github.com/mullvad/mullvadvpn-app/wireguard/libwg/logging"
+	"github.com/mullvad/mullvadvpn-app/wireguard/libwg/trafficshaping"
 	"github.com/mullvad/mullvadvpn-app/wireguard/libwg/tunnelcontainer"
 )
 
@@ -47,7 +48,14 @@ func wgTurnOn(cSettings *C.char, fd int, logSink LogSink, logContext LogContext)
 		return ERROR_GENERAL_FAILURE
 	}
 
-	device := device.NewDevice(tunDevice, conn.NewStdNetBind(), logger)
+	mtu, err := tunDevice.MTU()
+	if err != nil {
+		logger.Errorf("%s\n", err)
+		tunDevice.Close()
+		return ERROR_GENERAL_FAILURE
+	}
+	shaper := trafficshaping.NewShaper(tunDevice, mtu)
+	device := device.NewDevice(shaper.TUN(), conn.NewStdNetBind(), logger)
 
 	setErr := device.IpcSetOperation(bufio.NewReader(strings.NewReader(settings)))
 	if setErr != nil {
@@ -62,6 +70,7 @@ func wgTurnOn(cSettings *C.char, fd int, logSink LogSink, logContext LogContext)
 	context := tunnelcontainer.Context{
 		Device: device,
 		Logger: logger,
+		Shaper: shaper,
 	}
 
 	handle, err := tunnels.Insert(context)
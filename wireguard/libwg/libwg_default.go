@@ -21,7 +21,9 @@ import (
 	"golang.zx2c4.com/wireguard/device"
 	"golang.zx2c4.com/wireguard/tun"
 
+	"github.com/mullvad/mullvadvpn-app/wireguard/libwg/interfacebind"
 	"github.com/mullvad/mullvadvpn-app/wireguard/libwg/logging"
+	"github.com/mullvad/mullvadvpn-app/wireguard/libwg/trafficshaping"
 	"github.com/mullvad/mullvadvpn-app/wireguard/libwg/tunnelcontainer"
 )
 
@@ -30,8 +32,11 @@ import (
 type LogSink = unsafe.Pointer
 type LogContext = unsafe.Pointer
 
+// If bindInterface is not zero, the tunnel sockets only send and receive through the interface
+// with that index.
+//
 //export wgTurnOn
-func wgTurnOn(mtu int, cSettings *C.char, fd int, logSink LogSink, logContext LogContext) int32 {
+func wgTurnOn(mtu int, cSettings *C.char, fd int, bindInterface uint32, logSink LogSink, logContext LogContext) int32 {
 
 	logger := logging.NewLogger(logSink, logContext)
 
@@ -51,7 +56,15 @@ func wgTurnOn(mtu int, cSettings *C.char, fd int, logSink LogSink, logContext Lo
 		return ERROR_GENERAL_FAILURE
 	}
 
-	device := device.NewDevice(tunDevice, conn.NewDefaultBind(), logger)
+	shaper := trafficshaping.NewShaper(tunDevice, mtu)
+	var bind conn.Bind
+	if bindInterface != 0 {
+		logger.Verbosef("Binding tunnel sockets to interface %d\n", bindInterface)
+		bind = interfacebind.NewBind(bindInterface)
+	} else {
+		bind = conn.NewDefaultBind()
+	}
+	device := device.NewDevice(shaper.TUN(), bind, logger)
 
 	setErr := device.IpcSetOperation(bufio.NewReader(strings.NewReader(settings)))
 	if setErr != nil {
@@ -65,6 +78,7 @@ func wgTurnOn(mtu int, cSettings *C.char, fd int, logSink LogSink, logContext Lo
 	context := tunnelcontainer.Context{
 		Device: device,
 		Logger: logger,
+		Shaper: shaper,
 	}
 
 	handle, err := tunnels.Insert(context)
@@ -76,3 +90,18 @@ func wgTurnOn(mtu int, cSettings *C.char, fd int, logSink LogSink, logContext Lo
 
 	return handle
 }
+
+//export wgRebindTunnelSockets
+func wgRebindTunnelSockets(tunnelHandle int32) int32 {
+	tunnel, err := tunnels.Get(tunnelHandle)
+	if err != nil {
+		return ERROR_GENERAL_FAILURE
+	}
+	// Closing and reopening the sockets makes the kernel pick the route and source address of
+	// the current default route. The peer roams to the new address once it receives a packet.
+	if err := tunnel.Device.BindUpdate(); err != nil {
+		tunnel.Logger.Errorf("Failed to rebind tunnel sockets: %s\n", err)
+		return ERROR_GENERAL_FAILURE
+	}
+	return 0
+}
@@ -21,6 +21,7 @@ import (
 	"golang.zx2c4.com/wireguard/tun"
 
 	"github.com/mullvad/mullvadvpn-app/wireguard/libwg/logging"
+	"github.com/mullvad/mullvadvpn-app/wireguard/libwg/trafficshaping"
 	"github.com/mullvad/mullvadvpn-app/wireguard/libwg/tunnelcontainer"
 )
 
@@ -75,7 +76,8 @@ func wgTurnOn(cIfaceName *C.char, mtu int, cSettings *C.char, cIfaceNameOut **C.
 		logger.Verbosef("Failed to create adapter with specific name\n")
 	}
 
-	device := device.NewDevice(wintun, conn.NewDefaultBind(), logger)
+	shaper := trafficshaping.NewShaper(wintun, mtu)
+	device := device.NewDevice(shaper.TUN(), conn.NewDefaultBind(), logger)
 
 	setError := device.IpcSetOperation(bufio.NewReader(strings.NewReader(settings)))
 	if setError != nil {
@@ -90,6 +92,7 @@ func wgTurnOn(cIfaceName *C.char, mtu int, cSettings *C.char, cIfaceNameOut **C.
 	context := tunnelcontainer.Context{
 		Device: device,
 		Logger: logger,
+		Shaper: shaper,
 	}
 
 	handle, err := tunnels.Insert(context)
@@ -0,0 +1,218 @@
+/* SPDX-License-Identifier: Apache-2.0
+ *
+ * Copyright (C) 2022 Mullvad VPN AB. All Rights Reserved.
+ */
+
+package trafficshaping
+
+import (
+	"bufio"
+	"bytes"
+	"encoding/hex"
+	"math/rand"
+	"strings"
+	"sync"
+	"sync/atomic"
+	"time"
+
+	"golang.zx2c4.com/wireguard/device"
+	"golang.zx2c4.com/wireguard/tun"
+)
+
+// Bounds of the random time between dummy packets.
+const (
+	minDummyInterval = 50 * time.Millisecond
+	maxDummyInterval = 500 * time.Millisecond
+)
+
+// Shaper pads the packets that are sent through a tunnel, and sends dummy packets to its peers,
+// to make it harder to analyze the traffic.
+type Shaper struct {
+	// Accessed atomically. Must be first in the struct to be aligned on 32-bit platforms.
+	paddedPackets   uint64
+	injectedPackets uint64
+	padding         uint32
+
+	tun *paddingTUN
+
+	// Holds the []device.NoisePublicKey of the peers that dummy traffic is sent to.
+	peers atomic.Value
+
+	mutex     sync.Mutex
+	enabled   bool
+	stopDummy chan struct{}
+	dummyDone sync.WaitGroup
+}
+
+// paddingTUN wraps a tunnel device and pads the packets that are read from it to the MTU. The
+// receiving end uses the length in the IP header, so it ignores the padding.
+type paddingTUN struct {
+	tun.Device
+	shaper *Shaper
+	mtu    int
+}
+
+// NewShaper returns a shaper for tunnelDevice, which must be replaced by Shaper.TUN when
+// creating the WireGuard device. Nothing is shaped until Shaper.Set is called.
+func NewShaper(tunnelDevice tun.Device, mtu int) *Shaper {
+	shaper := &Shaper{}
+	shaper.tun = &paddingTUN{
+		Device: tunnelDevice,
+		shaper: shaper,
+		mtu:    mtu,
+	}
+	return shaper
+}
+
+// TUN returns the tunnel device that pads packets.
+func (shaper *Shaper) TUN() tun.Device {
+	return shaper.tun
+}
+
+// Set enables or disables padding and dummy traffic for the peers of dev.
+func (shaper *Shaper) Set(dev *device.Device, padding bool, dummyTraffic bool) error {
+	var peers []device.NoisePublicKey
+	if dummyTraffic {
+		var err error
+		peers, err = peerKeys(dev)
+		if err != nil {
+			return err
+		}
+	}
+
+	shaper.mutex.Lock()
+	defer shaper.mutex.Unlock()
+
+	shaper.stopDummyTraffic()
+	if padding {
+		atomic.StoreUint32(&shaper.padding, 1)
+	} else {
+		atomic.StoreUint32(&shaper.padding, 0)
+	}
+	shaper.enabled = padding || dummyTraffic
+	shaper.peers.Store(peers)
+	if dummyTraffic {
+		shaper.stopDummy = make(chan struct{})
+		shaper.dummyDone.Add(1)
+		go shaper.sendDummyTraffic(dev, shaper.stopDummy)
+	}
+	return nil
+}
+
+// UpdatePeers makes dummy traffic go to the current peers of dev. It must be called when the
+// peers of the device change.
+func (shaper *Shaper) UpdatePeers(dev *device.Device) error {
+	shaper.mutex.Lock()
+	defer shaper.mutex.Unlock()
+
+	if shaper.stopDummy == nil {
+		return nil
+	}
+	peers, err := peerKeys(dev)
+	if err != nil {
+		return err
+	}
+	shaper.peers.Store(peers)
+	return nil
+}
+
+// Stats returns the number of padded and dummy packets. ok is false if shaping is disabled.
+func (shaper *Shaper) Stats() (paddedPackets uint64, injectedPackets uint64, ok bool) {
+	shaper.mutex.Lock()
+	enabled := shaper.enabled
+	shaper.mutex.Unlock()
+
+	return atomic.LoadUint64(&shaper.paddedPackets), atomic.LoadUint64(&shaper.injectedPackets), enabled
+}
+
+// Close stops sending dummy traffic. It must be called before the device is closed.
+func (shaper *Shaper) Close() {
+	shaper.mutex.Lock()
+	defer shaper.mutex.Unlock()
+
+	shaper.stopDummyTraffic()
+}
+
+func (shaper *Shaper) stopDummyTraffic() {
+	if shaper.stopDummy != nil {
+		close(shaper.stopDummy)
+		shaper.stopDummy = nil
+		shaper.dummyDone.Wait()
+	}
+}
+
+// sendDummyTraffic sends keepalive packets to the peers at random intervals until stop is
+// closed. Keepalives are dropped by the peer after being decrypted, like any other packet.
+func (shaper *Shaper) sendDummyTraffic(dev *device.Device, stop chan struct{}) {
+	defer shaper.dummyDone.Done()
+
+	random := rand.New(rand.NewSource(time.Now().UnixNano()))
+	for {
+		interval := minDummyInterval + time.Duration(random.Int63n(int64(maxDummyInterval-minDummyInterval)))
+		select {
+		case <-stop:
+			return
+		case <-time.After(interval):
+		}
+
+		for _, key := range shaper.peers.Load().([]device.NoisePublicKey) {
+			if peer := dev.LookupPeer(key); peer != nil {
+				peer.SendKeepalive()
+				atomic.AddUint64(&shaper.injectedPackets, 1)
+			}
+		}
+	}
+}
+
+func (t *paddingTUN) Read(buf []byte, offset int) (int, error) {
+	n, err := t.Device.Read(buf, offset)
+	if err != nil || n == 0 || atomic.LoadUint32(&t.shaper.padding) == 0 {
+		return n, err
+	}
+
+	size := t.mtu
+	if available := len(buf) - offset; size > available {
+		size = available
+	}
+	if size <= n {
+		return n, nil
+	}
+	zero(buf[offset+n : offset+size])
+	atomic.AddUint64(&t.shaper.paddedPackets, 1)
+	return size, nil
+}
+
+// peerKeys returns the public keys of the peers of dev.
+func peerKeys(dev *device.Device) ([]device.NoisePublicKey, error) {
+	config := new(bytes.Buffer)
+	writer := bufio.NewWriter(config)
+	if err := dev.IpcGetOperation(writer); err != nil {
+		return nil, err
+	}
+	writer.Flush()
+	// The configuration includes the private key, so don't leave it in memory.
+	defer zero(config.Bytes())
+
+	var keys []device.NoisePublicKey
+	scanner := bufio.NewScanner(config)
+	for scanner.Scan() {
+		value := strings.TrimPrefix(scanner.Text(), "public_key=")
+		if value == scanner.Text() {
+			continue
+		}
+		decoded, err := hex.DecodeString(value)
+		if err != nil || len(decoded) != device.NoisePublicKeySize {
+			continue
+		}
+		var key device.NoisePublicKey
+		copy(key[:], decoded)
+		keys = append(keys, key)
+	}
+	return keys, nil
+}
+
+func zero(buf []byte) {
+	for i := range buf {
+		buf[i] = 0
+	}
+}
@@ -12,12 +12,15 @@ import (
 	"errors"
 
 	"golang.zx2c4.com/wireguard/device"
+
+	"github.com/mullvad/mullvadvpn-app/wireguard/libwg/trafficshaping"
 )
 
 type Context struct {
 	Device *device.Device
 	Uapi   net.Listener
 	Logger *device.Logger
+	Shaper *trafficshaping.Shaper
 }
 
 type Container struct {